@@ -1,16 +1,11 @@
-use argon2::{
-    password_hash::{PasswordHasher, SaltString},
-    Argon2, Params, Version,
-};
 use clap::{Parser, Subcommand};
 use blockchain_utils::init_logger;
+use chrono::Utc;
 use dialoguer::Input;
-use otc_models::ApiKey;
-use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use otc_models::{ApiKey, ApiKeyScope};
 use snafu::prelude::*;
 use std::fs;
 use std::path::PathBuf;
-use uuid::Uuid;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -52,6 +47,11 @@ enum Command {
         /// Market maker name (if not provided, will prompt interactively)
         #[arg(long)]
         market_maker: Option<String>,
+
+        /// What the key may be used for, rfq, otc or lock (repeatable, defaults to
+        /// all of them)
+        #[arg(long = "scope")]
+        scopes: Vec<ApiKeyScope>,
     },
     /// List all API keys
     List {
@@ -59,33 +59,36 @@ enum Command {
         #[arg(long, default_value = "bin/otc-server/prod_whitelisted_market_makers.json")]
         input: PathBuf,
     },
+    /// Revoke a market maker's API key, the entry is kept and marked revoked
+    Revoke {
+        /// Path to the API keys JSON file
+        #[arg(long, default_value = "bin/otc-server/prod_whitelisted_market_makers.json")]
+        file: PathBuf,
+
+        /// Market maker whose key to revoke
+        #[arg(long)]
+        market_maker: String,
+    },
+    /// Replace a market maker's API key, keeping its key ID (also reinstates a revoked key)
+    Rotate {
+        /// Path to the API keys JSON file
+        #[arg(long, default_value = "bin/otc-server/prod_whitelisted_market_makers.json")]
+        file: PathBuf,
+
+        /// Market maker whose key to rotate
+        #[arg(long)]
+        market_maker: String,
+    },
 }
 
-fn generate_api_key() -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect()
+fn password_hash_error(error: otc_auth::AuthError) -> Error {
+    Error::PasswordHash {
+        message: error.to_string(),
+    }
 }
 
 fn hash_api_key(api_key: &str) -> Result<String> {
-    // OWASP recommended settings: m=19456 (19 MiB), t=2, p=1
-    let params = Params::new(19456, 2, 1, None)
-        .map_err(|e| Error::PasswordHash {
-            message: e.to_string(),
-        })?;
-
-    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params);
-    let salt = SaltString::generate(&mut OsRng);
-
-    let hash = argon2
-        .hash_password(api_key.as_bytes(), &salt)
-        .map_err(|e| Error::PasswordHash {
-            message: e.to_string(),
-        })?;
-
-    Ok(hash.to_string())
+    otc_auth::hash_api_key(api_key).map_err(password_hash_error)
 }
 
 fn load_api_keys(path: &PathBuf) -> Result<Vec<ApiKey>> {
@@ -108,7 +111,11 @@ fn save_api_keys(path: &PathBuf, api_keys: &[ApiKey]) -> Result<()> {
     Ok(())
 }
 
-fn generate_command(output: PathBuf, market_maker: Option<String>) -> Result<()> {
+fn generate_command(
+    output: PathBuf,
+    market_maker: Option<String>,
+    scopes: Vec<ApiKeyScope>,
+) -> Result<()> {
     // Get market maker name either from args or prompt
     let market_maker = match market_maker {
         Some(name) => name,
@@ -137,16 +144,16 @@ fn generate_command(output: PathBuf, market_maker: Option<String>) -> Result<()>
         });
     }
 
+    // No --scope means every scope
+    let scopes: Vec<ApiKeyScope> = ApiKeyScope::ALL
+        .into_iter()
+        .filter(|scope| scopes.is_empty() || scopes.contains(scope))
+        .collect();
+
     // Generate new API key
-    let id = Uuid::new_v4();
-    let api_key = generate_api_key();
-    let hash = hash_api_key(&api_key)?;
-
-    let new_key = ApiKey {
-        id,
-        market_maker: market_maker.clone(),
-        hash,
-    };
+    let (new_key, api_key) =
+        otc_auth::generate_api_key(&market_maker, scopes.clone()).map_err(password_hash_error)?;
+    let id = new_key.id;
 
     // Add to list and save
     api_keys.push(new_key.clone());
@@ -156,6 +163,7 @@ fn generate_command(output: PathBuf, market_maker: Option<String>) -> Result<()>
     println!("\n📋 API Key Details:");
     println!("Market Maker: {market_maker}");
     println!("Key ID: {id}");
+    println!("Scopes: {}", format_scopes(&scopes));
     println!("\n🔑 API Key (save this, it won't be shown again):");
     println!("{api_key}");
     println!("\n📁 Saved to: {}", output.display());
@@ -172,13 +180,99 @@ fn list_command(input: PathBuf) -> Result<()> {
     }
 
     println!("API Keys in {}:", input.display());
-    println!("{:<40} {:<30}", "ID", "Market Maker");
-    println!("{}", "-".repeat(70));
+    println!(
+        "{:<40} {:<30} {:<10} {:<30}",
+        "ID", "Market Maker", "Scopes", "Status"
+    );
+    println!("{}", "-".repeat(110));
 
     for key in api_keys {
-        println!("{:<40} {:<30}", key.id, key.market_maker);
+        let status = match key.revoked_at {
+            Some(revoked_at) => format!("revoked {}", revoked_at.to_rfc3339()),
+            None => "active".to_string(),
+        };
+        println!(
+            "{:<40} {:<30} {:<10} {:<30}",
+            key.id,
+            key.market_maker,
+            format_scopes(&key.scopes),
+            status
+        );
+    }
+
+    Ok(())
+}
+
+fn format_scopes(scopes: &[ApiKeyScope]) -> String {
+    scopes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn find_api_key<'a>(api_keys: &'a mut [ApiKey], market_maker: &str) -> Result<&'a mut ApiKey> {
+    api_keys
+        .iter_mut()
+        .find(|k| k.market_maker == market_maker)
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("No API key found for market maker '{market_maker}'"),
+        })
+}
+
+/// Mark a market maker's key revoked so servers reject it
+fn revoke_api_key(api_keys: &mut [ApiKey], market_maker: &str) -> Result<ApiKey> {
+    let key = find_api_key(api_keys, market_maker)?;
+    if let Some(revoked_at) = key.revoked_at {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "API key for market maker '{market_maker}' was already revoked at {revoked_at}"
+            ),
+        });
     }
 
+    key.revoked_at = Some(Utc::now());
+    Ok(key.clone())
+}
+
+/// Give a market maker a fresh secret under the same key ID, returns the new secret
+fn rotate_api_key(api_keys: &mut [ApiKey], market_maker: &str) -> Result<(ApiKey, String)> {
+    let key = find_api_key(api_keys, market_maker)?;
+
+    let api_key = otc_auth::generate_api_key_secret();
+    key.hash = hash_api_key(&api_key)?;
+    key.revoked_at = None;
+    Ok((key.clone(), api_key))
+}
+
+fn revoke_command(file: PathBuf, market_maker: &str) -> Result<()> {
+    let mut api_keys = load_api_keys(&file)?;
+    let revoked = revoke_api_key(&mut api_keys, market_maker)?;
+    save_api_keys(&file, &api_keys)?;
+
+    println!("\n🚫 API key revoked");
+    println!("Market Maker: {}", revoked.market_maker);
+    println!("Key ID: {}", revoked.id);
+    println!("\nThe OTC and RFQ servers pick up the change within seconds.");
+    println!("📁 Saved to: {}", file.display());
+
+    Ok(())
+}
+
+fn rotate_command(file: PathBuf, market_maker: &str) -> Result<()> {
+    let mut api_keys = load_api_keys(&file)?;
+    let (rotated, api_key) = rotate_api_key(&mut api_keys, market_maker)?;
+    save_api_keys(&file, &api_keys)?;
+
+    println!("\n✅ API key rotated successfully!");
+    println!("\n📋 API Key Details:");
+    println!("Market Maker: {}", rotated.market_maker);
+    println!("Key ID: {} (unchanged)", rotated.id);
+    println!("\n🔑 New API Key (save this, it won't be shown again):");
+    println!("{api_key}");
+    println!("\nThe OTC and RFQ servers pick up the change within seconds.");
+    println!("📁 Saved to: {}", file.display());
+
     Ok(())
 }
 
@@ -188,7 +282,87 @@ fn main() -> Result<()> {
     init_logger(&args.log_level).expect("Logger should initialize");
 
     match args.command {
-        Command::Generate { output, market_maker } => generate_command(output, market_maker),
+        Command::Generate {
+            output,
+            market_maker,
+            scopes,
+        } => generate_command(output, market_maker, scopes),
         Command::List { input } => list_command(input),
+        Command::Revoke { file, market_maker } => revoke_command(file, &market_maker),
+        Command::Rotate { file, market_maker } => rotate_command(file, &market_maker),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+    use tempfile::tempdir;
+
+    fn api_key_for(market_maker: &str, api_key: &str) -> ApiKey {
+        ApiKey {
+            id: Uuid::new_v4(),
+            market_maker: market_maker.to_string(),
+            hash: hash_api_key(api_key).unwrap(),
+            revoked_at: None,
+            scopes: ApiKeyScope::ALL.to_vec(),
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_revoke_marks_key_revoked() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("whitelist.json");
+        let original = vec![
+            api_key_for("mm_a", "secret_a"),
+            api_key_for("mm_b", "secret_b"),
+        ];
+        save_api_keys(&file, &original).unwrap();
+
+        revoke_command(file.clone(), "mm_a").unwrap();
+
+        let api_keys = load_api_keys(&file).unwrap();
+        assert_eq!(api_keys.len(), 2);
+        assert!(api_keys[0].is_revoked());
+        assert_eq!(api_keys[0].id, original[0].id);
+        assert!(!api_keys[1].is_revoked());
+
+        // Revoking twice or revoking an unknown market maker is an error
+        assert!(revoke_command(file.clone(), "mm_a").is_err());
+        assert!(revoke_command(file, "mm_unknown").is_err());
+    }
+
+    #[test]
+    fn test_rotate_replaces_secret_and_keeps_id() {
+        let mut api_keys = vec![api_key_for("mm_a", "secret_a")];
+        let id = api_keys[0].id;
+        revoke_api_key(&mut api_keys, "mm_a").unwrap();
+
+        let (rotated, new_secret) = rotate_api_key(&mut api_keys, "mm_a").unwrap();
+
+        assert_eq!(rotated.id, id);
+        assert!(!rotated.is_revoked());
+        assert!(rotated.verify(&new_secret));
+        assert!(!rotated.verify("secret_a"));
+        assert!(rotate_api_key(&mut api_keys, "mm_unknown").is_err());
+    }
+
+    #[test]
+    fn test_generate_with_scope() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("whitelist.json");
+
+        generate_command(
+            file.clone(),
+            Some("mm_rfq".to_string()),
+            vec![ApiKeyScope::Rfq],
+        )
+        .unwrap();
+        generate_command(file.clone(), Some("mm_full".to_string()), Vec::new()).unwrap();
+
+        let api_keys = load_api_keys(&file).unwrap();
+        assert_eq!(api_keys[0].scopes, vec![ApiKeyScope::Rfq]);
+        assert!(!api_keys[0].has_scope(ApiKeyScope::Otc));
+        assert_eq!(api_keys[1].scopes, ApiKeyScope::ALL);
+    }
+}
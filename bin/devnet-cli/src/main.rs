@@ -1,7 +1,10 @@
+mod otc_stack;
+
 use clap::{Parser, Subcommand};
 use blockchain_utils::{handle_background_thread_result, init_logger};
 use devnet::evm_devnet::ForkConfig;
 use devnet::{RiftDevnet, RiftDevnetCache};
+use otc_stack::OtcStack;
 use snafu::{ResultExt, Whatever};
 use tracing::info;
 use tokio::signal;
@@ -24,6 +27,14 @@ struct Cli {
     /// Block number to fork from, if unset and `fork_url` is set, will use the latest block (used when no subcommand provided)
     #[arg(short = 'b', long, global = true)]
     fork_block_number: Option<u64>,
+
+    /// Also run the OTC server, RFQ server and a funded market maker against the devnet (server mode only)
+    #[arg(long, global = true)]
+    with_otc_stack: bool,
+
+    /// Postgres server the OTC stack creates its databases on, if unset an embedded one is started (needs `initdb` and `postgres` on the PATH)
+    #[arg(long, global = true, requires = "with_otc_stack")]
+    database_url: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -53,7 +64,8 @@ async fn main() -> Result<(), Whatever> {
             } else {
                 None
             };
-            run_server(cli.fund_address, fork_config).await
+            let otc_stack_database_url = cli.with_otc_stack.then_some(cli.database_url);
+            run_server(cli.fund_address, fork_config, otc_stack_database_url).await
         }
         Some(Commands::Cache) => run_cache().await,
     }
@@ -62,6 +74,7 @@ async fn main() -> Result<(), Whatever> {
 async fn run_server(
     fund_address: Vec<String>,
     fork_config: Option<ForkConfig>,
+    otc_stack_database_url: Option<Option<String>>,
 ) -> Result<(), Whatever> {
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -89,6 +102,14 @@ async fn run_server(
         "[Devnet Server] Devnet built in {:?}",
         build_start.elapsed()
     );
+    let otc_stack = match otc_stack_database_url {
+        Some(database_url) => {
+            let otc_stack = OtcStack::launch(&mut devnet, database_url).await?;
+            otc_stack.print_summary();
+            Some(otc_stack)
+        }
+        None => None,
+    };
     info!(
         "[Devnet Server] Total startup time: {:?}",
         server_start.elapsed()
@@ -104,6 +125,7 @@ async fn run_server(
     }
 
     drop(devnet);
+    drop(otc_stack);
     Ok(())
 }
 
@@ -0,0 +1,300 @@
+//! The OTC server, RFQ server and a market maker, run in-process next to the
+//! devnet so the whole product can be demoed from one command
+
+use std::{fmt::Display, path::Path, process::Stdio, time::Duration};
+
+use alloy::{hex, primitives::U256, signers::local::PrivateKeySigner};
+use clap::Parser;
+use common::Shutdown;
+use devnet::{DevnetError, MultichainAccount, RiftDevnet};
+use market_maker::MarketMakerArgs;
+use otc_models::ApiKeyScope;
+use otc_server::OtcServerArgs;
+use rfq_server::RfqServerArgs;
+use snafu::{ensure_whatever, ResultExt, Whatever};
+use sqlx::{Connection, PgConnection};
+use tempfile::TempDir;
+use tokio::{
+    net::TcpListener,
+    process::{Child, Command},
+};
+use tracing::info;
+use url::Url;
+use uuid::Uuid;
+
+/// Where the market maker expects the servers by default
+pub const OTC_SERVER_PORT: u16 = 3000;
+pub const RFQ_SERVER_PORT: u16 = 3001;
+const EMBEDDED_POSTGRES_PORT: u16 = 50104;
+const EMBEDDED_POSTGRES_USER: &str = "postgres";
+const EMBEDDED_POSTGRES_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The account interactive mode funds with ether and bitcoin for a market maker
+const MARKET_MAKER_ACCOUNT_SALT: u32 = 152;
+const MARKET_MAKER_CBBTC: u64 = 10_000_000_000; // 100 cbBTC
+
+/// A running stack, its services live in the devnet's join set. Dropping it
+/// removes the databases' and wallet's files
+pub struct OtcStack {
+    pub otc_server_url: String,
+    pub rfq_server_url: String,
+    pub database_url: String,
+    pub market_maker_id: String,
+    pub api_key_id: Uuid,
+    pub api_key: String,
+    _postgres: Option<EmbeddedPostgres>,
+    _dir: TempDir,
+}
+
+impl OtcStack {
+    /// Start the servers and a market maker against `devnet`, storing their
+    /// data in `database_url` or, without one, in an embedded Postgres
+    pub async fn launch(
+        devnet: &mut RiftDevnet,
+        database_url: Option<String>,
+    ) -> Result<Self, Whatever> {
+        let dir = tempfile::tempdir().whatever_context("Failed to create the stack's directory")?;
+        let (postgres, database_url) = match database_url {
+            Some(database_url) => (None, database_url),
+            None => {
+                let postgres = EmbeddedPostgres::start(&dir.path().join("postgres")).await?;
+                let database_url = postgres.database_url.clone();
+                (Some(postgres), database_url)
+            }
+        };
+        let otc_database_url = create_database(&database_url, "otc_server").await?;
+        let market_maker_database_url = create_database(&database_url, "market_maker").await?;
+
+        let market_maker_id = Uuid::new_v4().to_string();
+        let (api_key, api_key_secret) =
+            otc_auth::generate_api_key(&market_maker_id, ApiKeyScope::ALL.to_vec())
+                .whatever_context("Failed to generate the market maker's API key")?;
+        let whitelist_file = path_arg(&dir.path().join("whitelisted_market_makers.json"));
+        std::fs::write(
+            &whitelist_file,
+            serde_json::to_string_pretty(&[&api_key])
+                .whatever_context("Failed to serialize the whitelist")?,
+        )
+        .whatever_context("Failed to write the whitelist")?;
+        let quote_signing_key = hex::encode(PrivateKeySigner::random().to_bytes());
+
+        let otc_args = OtcServerArgs::try_parse_from([
+            "otc-server",
+            "--port",
+            &OTC_SERVER_PORT.to_string(),
+            "--database-url",
+            &otc_database_url,
+            "--whitelist-file",
+            &whitelist_file,
+            "--settings-file",
+            &path_arg(&dir.path().join("otc_server_settings.toml")),
+            "--ethereum-mainnet-rpc-url",
+            &devnet.ethereum.anvil.endpoint(),
+            "--ethereum-mainnet-chain-id",
+            &devnet.ethereum.anvil.chain_id().to_string(),
+            "--ethereum-mainnet-ws-url",
+            &devnet.ethereum.anvil.ws_endpoint(),
+            "--bitcoin-rpc-url",
+            &devnet.bitcoin.rpc_url_with_cookie,
+            "--bitcoin-rpc-auth",
+            &path_arg(&devnet.bitcoin.cookie),
+            "--esplora-http-server-url",
+            &esplora_url(devnet)?,
+            "--bitcoin-network",
+            "regtest",
+            "--quote-signing-key",
+            &quote_signing_key,
+        ])
+        .whatever_context("Invalid OTC server arguments")?;
+        let rfq_args = RfqServerArgs::try_parse_from([
+            "rfq-server",
+            "--port",
+            &RFQ_SERVER_PORT.to_string(),
+            "--whitelist-file",
+            &whitelist_file,
+            "--quote-signing-key",
+            &quote_signing_key,
+        ])
+        .whatever_context("Invalid RFQ server arguments")?;
+
+        // Bound before anything connects, so the market maker's first attempt
+        // waits for the servers instead of being refused
+        let otc_listener = bind(OTC_SERVER_PORT).await?;
+        let rfq_listener = bind(RFQ_SERVER_PORT).await?;
+        // ctrl_c tears the devnet down and the servers with it, they never
+        // drain on their own
+        devnet.join_set.spawn(async move {
+            otc_server::server::run_server_until(otc_args, otc_listener, Shutdown::new())
+                .await
+                .map_err(|e| service_exited("otc-server", e))
+        });
+        devnet.join_set.spawn(async move {
+            rfq_server::server::run_server_until(rfq_args, rfq_listener, Shutdown::new())
+                .await
+                .map_err(|e| service_exited("rfq-server", e))
+        });
+
+        let account = MultichainAccount::new(MARKET_MAKER_ACCOUNT_SALT);
+        devnet
+            .ethereum
+            .mint_cbbtc(account.ethereum_address, U256::from(MARKET_MAKER_CBBTC))
+            .await
+            .map_err(DevnetError::from)
+            .whatever_context("Failed to mint cbBTC for the market maker")?;
+        let market_maker_args = MarketMakerArgs::try_parse_from([
+            "market-maker",
+            "--market-maker-id",
+            &market_maker_id,
+            "--api-key-id",
+            &api_key.id.to_string(),
+            "--api-key",
+            &api_key_secret,
+            "--otc-ws-url",
+            &format!("ws://127.0.0.1:{OTC_SERVER_PORT}/ws/mm"),
+            "--rfq-ws-url",
+            &format!("ws://127.0.0.1:{RFQ_SERVER_PORT}/ws/mm"),
+            "--bitcoin-wallet-db-file",
+            &path_arg(&dir.path().join("market_maker_wallet.sqlite")),
+            "--bitcoin-wallet-descriptor",
+            &format!("wpkh({})", account.bitcoin_wallet.private_key),
+            "--bitcoin-wallet-network",
+            "regtest",
+            "--bitcoin-wallet-esplora-url",
+            &esplora_url(devnet)?,
+            "--ethereum-wallet-private-key",
+            &hex::encode(account.secret_bytes),
+            "--ethereum-rpc-ws-url",
+            &devnet.ethereum.anvil.ws_endpoint(),
+            "--ethereum-chain-id",
+            &devnet.ethereum.anvil.chain_id().to_string(),
+            "--database-url",
+            &market_maker_database_url,
+            "--auto-accept",
+            "--simulation-mode",
+        ])
+        .whatever_context("Invalid market maker arguments")?;
+        devnet.join_set.spawn(async move {
+            market_maker::run_market_maker(market_maker_args)
+                .await
+                .map_err(|e| service_exited("market-maker", e))
+        });
+        info!("[Devnet Server] OTC stack launched");
+
+        Ok(Self {
+            otc_server_url: format!("http://127.0.0.1:{OTC_SERVER_PORT}"),
+            rfq_server_url: format!("http://127.0.0.1:{RFQ_SERVER_PORT}"),
+            database_url,
+            market_maker_id,
+            api_key_id: api_key.id,
+            api_key: api_key_secret,
+            _postgres: postgres,
+            _dir: dir,
+        })
+    }
+
+    pub fn print_summary(&self) {
+        println!("---OTC STACK---");
+        println!("OTC Server URL:             {}", self.otc_server_url);
+        println!("RFQ Server URL:             {}", self.rfq_server_url);
+        println!("Postgres URL:               {}", self.database_url);
+        println!("Market Maker ID:            {}", self.market_maker_id);
+        println!("Market Maker API Key ID:    {}", self.api_key_id);
+        println!("Market Maker API Key:       {}", self.api_key);
+        println!("---OTC STACK---");
+    }
+}
+
+/// A throwaway Postgres cluster, killed when dropped. Needs `initdb` and
+/// `postgres` on the PATH
+struct EmbeddedPostgres {
+    _server: Child,
+    database_url: String,
+}
+
+impl EmbeddedPostgres {
+    async fn start(data_dir: &Path) -> Result<Self, Whatever> {
+        let status = Command::new("initdb")
+            .arg("--pgdata")
+            .arg(data_dir)
+            .args(["--username", EMBEDDED_POSTGRES_USER, "--auth", "trust"])
+            .stdout(Stdio::null())
+            .status()
+            .await
+            .whatever_context("Failed to run initdb, is Postgres installed?")?;
+        ensure_whatever!(status.success(), "initdb failed with {status}");
+
+        let server = Command::new("postgres")
+            .arg("-D")
+            .arg(data_dir)
+            .args(["-p", &EMBEDDED_POSTGRES_PORT.to_string()])
+            .args(["-c", "listen_addresses=127.0.0.1"])
+            .arg("-k")
+            .arg(data_dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .whatever_context("Failed to start postgres")?;
+        let database_url = format!(
+            "postgres://{EMBEDDED_POSTGRES_USER}@127.0.0.1:{EMBEDDED_POSTGRES_PORT}/postgres"
+        );
+
+        let started = tokio::time::Instant::now();
+        while PgConnection::connect(&database_url).await.is_err() {
+            ensure_whatever!(
+                started.elapsed() < EMBEDDED_POSTGRES_STARTUP_TIMEOUT,
+                "Postgres didn't accept connections within {EMBEDDED_POSTGRES_STARTUP_TIMEOUT:?}"
+            );
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        info!("[Devnet Server] Embedded Postgres listening at {database_url}");
+
+        Ok(Self {
+            _server: server,
+            database_url,
+        })
+    }
+}
+
+/// Create a fresh database for `service` on the server at `database_url`,
+/// returning its URL
+async fn create_database(database_url: &str, service: &str) -> Result<String, Whatever> {
+    let mut url = Url::parse(database_url).whatever_context("Invalid database URL")?;
+    url.set_path("postgres");
+    let mut admin = PgConnection::connect(url.as_str())
+        .await
+        .whatever_context("Failed to connect to Postgres")?;
+
+    let database = format!("devnet_{service}_{}", Uuid::new_v4().simple());
+    sqlx::query(&format!("CREATE DATABASE {database}"))
+        .execute(&mut admin)
+        .await
+        .with_whatever_context(|_| format!("Failed to create database {database}"))?;
+
+    url.set_path(&database);
+    Ok(url.to_string())
+}
+
+async fn bind(port: u16) -> Result<TcpListener, Whatever> {
+    TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_whatever_context(|_| format!("Failed to bind port {port}, is it already in use?"))
+}
+
+fn esplora_url(devnet: &RiftDevnet) -> Result<String, Whatever> {
+    match &devnet.bitcoin.esplora_url {
+        Some(url) => Ok(url.to_string()),
+        None => snafu::whatever!("The OTC stack needs the devnet's esplora"),
+    }
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+fn service_exited(service: &str, error: impl Display) -> DevnetError {
+    DevnetError::ServiceExited {
+        service: service.to_string(),
+        message: error.to_string(),
+    }
+}
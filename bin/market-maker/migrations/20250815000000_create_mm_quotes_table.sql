@@ -29,14 +29,121 @@ CREATE TABLE IF NOT EXISTS mm_quotes (
     
     -- Metadata for tracking
     sent_to_rfq BOOLEAN NOT NULL DEFAULT FALSE,
-    sent_to_otc BOOLEAN NOT NULL DEFAULT FALSE
+    sent_to_otc BOOLEAN NOT NULL DEFAULT FALSE,
+
+    -- Set once a swap references the quote, such quotes are never pruned
+    accepted_at TIMESTAMPTZ,
+    filled_at TIMESTAMPTZ,
+
+    -- Protocol fee paid alongside the fill, in the to currency
+    protocol_fee TEXT, -- U256 stored as string
+    -- Latest payout transaction for the quote, a retried fill replaces it
+    fill_tx_hash TEXT,
+    -- Account nonce of that payout, on chains that have one
+    fill_nonce BIGINT,
+    -- Swap the quote was claimed to pay out, set before the first payout
+    fill_swap_id UUID,
+
+    -- Set when the OTC server reports the swap for the quote won't settle
+    failed_at TIMESTAMPTZ,
+    failure_reason TEXT,
+
+    -- Latest status the OTC server reported for the swap filling the quote
+    swap_id UUID,
+    swap_status JSONB,
+    swap_user_deposit_confirmations BIGINT,
+    swap_mm_deposit_confirmations BIGINT,
+    swap_status_updated_at TIMESTAMPTZ
 );
 
 -- Create indexes for efficient queries
 CREATE INDEX idx_mm_quotes_market_maker ON mm_quotes(market_maker_id);
 CREATE INDEX idx_mm_quotes_expires_at ON mm_quotes(expires_at);
 CREATE INDEX idx_mm_quotes_created_at ON mm_quotes(created_at DESC);
+CREATE INDEX idx_mm_quotes_swap_id ON mm_quotes(swap_id) WHERE swap_id IS NOT NULL;
+
+-- A swap is paid out by one quote only
+CREATE UNIQUE INDEX idx_mm_quotes_fill_swap_id ON mm_quotes(fill_swap_id)
+WHERE fill_swap_id IS NOT NULL;
+
+-- Index for the retention task, which only ever deletes unreferenced quotes
+CREATE INDEX idx_mm_quotes_unreferenced_created_at ON mm_quotes(created_at)
+WHERE accepted_at IS NULL AND filled_at IS NULL;
 
 -- Index for finding unsent quotes
 CREATE INDEX idx_mm_quotes_unsent ON mm_quotes(sent_to_rfq, sent_to_otc)
-WHERE sent_to_rfq = FALSE OR sent_to_otc = FALSE;
\ No newline at end of file
+WHERE sent_to_rfq = FALSE OR sent_to_otc = FALSE;
+-- Funds set aside from trading in the payout wallets, per currency and bucket.
+-- Whatever a wallet holds beyond these is the trading bucket
+CREATE TABLE IF NOT EXISTS mm_wallet_buckets (
+    chain VARCHAR(50) NOT NULL,
+    token JSONB NOT NULL,
+    decimals SMALLINT NOT NULL,
+    bucket VARCHAR(50) NOT NULL,
+    amount TEXT NOT NULL, -- U256 stored as string
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (chain, token, bucket)
+);
+
+-- Each payout wallet's balance at the last bucket reconciliation, what moves
+-- out of the trading bucket are checked against
+CREATE TABLE IF NOT EXISTS mm_wallet_balances (
+    chain VARCHAR(50) NOT NULL,
+    token JSONB NOT NULL,
+    decimals SMALLINT NOT NULL,
+    amount TEXT NOT NULL, -- U256 stored as string
+    observed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (chain, token)
+);
+
+-- Every move between buckets, for auditing the bucket balances
+CREATE TABLE IF NOT EXISTS mm_wallet_bucket_transfers (
+    id UUID PRIMARY KEY,
+    chain VARCHAR(50) NOT NULL,
+    token JSONB NOT NULL,
+    decimals SMALLINT NOT NULL,
+    from_bucket VARCHAR(50) NOT NULL,
+    to_bucket VARCHAR(50) NOT NULL,
+    amount TEXT NOT NULL, -- U256 stored as string
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Every quote request answered and how, turned down ones included, so a
+-- missing quote can be explained after the fact
+CREATE TABLE IF NOT EXISTS mm_quote_attempts (
+    id UUID PRIMARY KEY,
+    request_id UUID NOT NULL,
+    mode VARCHAR(20) NOT NULL,
+
+    -- Requested currencies, the amount is on the send side for exact input
+    from_chain VARCHAR(50) NOT NULL,
+    from_token JSONB NOT NULL,
+    from_decimals SMALLINT NOT NULL,
+    to_chain VARCHAR(50) NOT NULL,
+    to_token JSONB NOT NULL,
+    to_decimals SMALLINT NOT NULL,
+    amount TEXT NOT NULL, -- U256 stored as string
+
+    -- quoted, maker_unavailable or invalid_request, a quote later becomes won
+    -- or lost once the RFQ server reports the outcome
+    outcome VARCHAR(20) NOT NULL,
+    -- Why the request was turned down
+    message TEXT,
+
+    -- The quote offered
+    quote_id UUID,
+    quoted_from_amount TEXT, -- U256 stored as string
+    quoted_to_amount TEXT, -- U256 stored as string
+
+    -- What the network fee was priced from
+    sats_per_vbyte DOUBLE PRECISION,
+    eth_per_btc DOUBLE PRECISION,
+
+    -- Time spent answering, the whole batch for batched requests
+    duration_ms BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_mm_quote_attempts_created_at ON mm_quote_attempts(created_at DESC);
+CREATE INDEX idx_mm_quote_attempts_quote_id ON mm_quote_attempts(quote_id)
+WHERE quote_id IS NOT NULL;
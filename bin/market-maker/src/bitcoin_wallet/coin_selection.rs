@@ -0,0 +1,239 @@
+use std::{cmp::Reverse, fmt, str::FromStr, time::Duration};
+
+use bdk_wallet::{bitcoin::OutPoint, LocalOutput};
+
+/// Default cap on the inputs of a fill transaction
+pub const DEFAULT_MAX_FILL_INPUTS: usize = 20;
+
+/// vbytes of a fill besides its inputs: the payout, protocol fee and change
+/// P2WPKH outputs, the 16 byte nonce OP_RETURN and the transaction overhead
+const FILL_BASE_VBYTES: u64 = 131;
+
+/// vbytes of each P2WPKH input
+pub const P2WPKH_INPUT_VBYTES: u64 = 68;
+
+/// How a fill picks the UTXOs it spends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoinSelectionStrategy {
+    /// Fewest inputs, spending the largest UTXOs first
+    LargestFirst,
+    /// BDK's branch and bound, avoiding change where it can
+    #[default]
+    BranchAndBound,
+    /// Spend the oldest UTXOs first, consolidating the wallet as it fills
+    OldestFirst,
+}
+
+impl fmt::Display for CoinSelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let strategy = match self {
+            Self::LargestFirst => "largest-first",
+            Self::BranchAndBound => "branch-and-bound",
+            Self::OldestFirst => "oldest-first",
+        };
+        f.write_str(strategy)
+    }
+}
+
+impl FromStr for CoinSelectionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "largest-first" => Ok(Self::LargestFirst),
+            "branch-and-bound" => Ok(Self::BranchAndBound),
+            "oldest-first" => Ok(Self::OldestFirst),
+            _ => Err(format!(
+                "invalid coin selection strategy {s:?}, expected largest-first, branch-and-bound or oldest-first"
+            )),
+        }
+    }
+}
+
+/// When spare small UTXOs are swept into one
+#[derive(Debug, Clone, Copy)]
+pub struct ConsolidationConfig {
+    /// Sweep only while the fee rate for confirmation within a day is at most this
+    pub max_fee_rate_sat_per_vb: u64,
+    /// UTXOs below this many sats are swept
+    pub small_utxo_sats: u64,
+    /// Sweep once at least this many small UTXOs are spendable
+    pub min_utxos: usize,
+    pub check_interval: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CoinSelectionConfig {
+    pub strategy: CoinSelectionStrategy,
+    /// Most inputs a fill transaction may spend
+    pub max_inputs: usize,
+    pub consolidation: Option<ConsolidationConfig>,
+}
+
+impl Default for CoinSelectionConfig {
+    fn default() -> Self {
+        Self {
+            strategy: CoinSelectionStrategy::default(),
+            max_inputs: DEFAULT_MAX_FILL_INPUTS,
+            consolidation: None,
+        }
+    }
+}
+
+impl CoinSelectionConfig {
+    /// The UTXOs a fill may choose from: `required` first, then the rest in the
+    /// order the strategy prefers, at most `max_inputs` in total. Branch and bound
+    /// chooses among the largest.
+    #[must_use]
+    pub fn candidates(
+        &self,
+        mut spendable: Vec<LocalOutput>,
+        required: &[OutPoint],
+    ) -> Vec<LocalOutput> {
+        match self.strategy {
+            CoinSelectionStrategy::LargestFirst | CoinSelectionStrategy::BranchAndBound => {
+                spendable.sort_by_key(|utxo| Reverse(utxo.txout.value));
+            }
+            CoinSelectionStrategy::OldestFirst => {
+                // Unconfirmed outputs are the newest
+                spendable.sort_by_key(|utxo| {
+                    utxo.chain_position
+                        .confirmation_height_upper_bound()
+                        .unwrap_or(u32::MAX)
+                });
+            }
+        }
+        spendable.sort_by_key(|utxo| !required.contains(&utxo.outpoint));
+        spendable.truncate(self.max_inputs.max(1));
+        spendable
+    }
+
+    /// Inputs a fill of `amount_sats` is expected to spend, taking candidates in
+    /// order until they cover it and its fee at `sat_per_vb`
+    #[must_use]
+    pub fn expected_inputs(
+        &self,
+        spendable: Vec<LocalOutput>,
+        amount_sats: u64,
+        sat_per_vb: f64,
+    ) -> usize {
+        let mut total = 0u64;
+        let mut inputs = 0;
+        for utxo in self.candidates(spendable, &[]) {
+            let fee = (fill_vbytes(inputs) as f64 * sat_per_vb).ceil() as u64;
+            if inputs > 0 && total >= amount_sats + fee {
+                break;
+            }
+            total += utxo.txout.value.to_sat();
+            inputs += 1;
+        }
+        inputs.max(1)
+    }
+}
+
+/// vbytes of a fill spending `inputs` P2WPKH inputs
+#[must_use]
+pub fn fill_vbytes(inputs: usize) -> u64 {
+    FILL_BASE_VBYTES + P2WPKH_INPUT_VBYTES * inputs as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_wallet::{
+        bitcoin::{hashes::Hash, Amount, ScriptBuf, TxOut, Txid},
+        chain::{BlockId, ChainPosition, ConfirmationBlockTime},
+        KeychainKind,
+    };
+
+    fn utxo(vout: u32, sats: u64, height: Option<u32>) -> LocalOutput {
+        let chain_position = match height {
+            Some(height) => ChainPosition::Confirmed {
+                anchor: ConfirmationBlockTime {
+                    block_id: BlockId {
+                        height,
+                        hash: Hash::all_zeros(),
+                    },
+                    confirmation_time: 0,
+                },
+                transitively: None,
+            },
+            None => ChainPosition::Unconfirmed {
+                first_seen: None,
+                last_seen: None,
+            },
+        };
+        LocalOutput {
+            outpoint: OutPoint::new(Txid::all_zeros(), vout),
+            txout: TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey: ScriptBuf::new(),
+            },
+            keychain: KeychainKind::External,
+            is_spent: false,
+            derivation_index: 0,
+            chain_position,
+        }
+    }
+
+    fn vouts(utxos: &[LocalOutput]) -> Vec<u32> {
+        utxos.iter().map(|utxo| utxo.outpoint.vout).collect()
+    }
+
+    #[test]
+    fn test_candidates_follow_the_strategy_and_cap() {
+        let spendable = vec![
+            utxo(0, 10_000, Some(5)),
+            utxo(1, 50_000, None),
+            utxo(2, 30_000, Some(1)),
+            utxo(3, 20_000, Some(3)),
+        ];
+        let config = |strategy| CoinSelectionConfig {
+            strategy,
+            max_inputs: 3,
+            consolidation: None,
+        };
+
+        let largest = config(CoinSelectionStrategy::LargestFirst);
+        assert_eq!(
+            vouts(&largest.candidates(spendable.clone(), &[])),
+            [1, 2, 3]
+        );
+        let oldest = config(CoinSelectionStrategy::OldestFirst);
+        assert_eq!(vouts(&oldest.candidates(spendable.clone(), &[])), [2, 3, 0]);
+
+        // Required inputs are kept even if the strategy wouldn't pick them
+        let required = [OutPoint::new(Txid::all_zeros(), 0)];
+        assert_eq!(
+            vouts(&largest.candidates(spendable.clone(), &required)),
+            [0, 1, 2]
+        );
+
+        assert_eq!(largest.expected_inputs(spendable.clone(), 40_000, 1.0), 1);
+        assert_eq!(largest.expected_inputs(spendable.clone(), 70_000, 1.0), 2);
+        assert_eq!(oldest.expected_inputs(spendable.clone(), 40_000, 1.0), 2);
+        // The fee can take another input
+        assert_eq!(largest.expected_inputs(spendable.clone(), 50_000, 0.0), 1);
+        assert_eq!(largest.expected_inputs(spendable.clone(), 50_000, 1.0), 2);
+        // Past the cap the fill can't be paid, the estimate stops at it
+        assert_eq!(largest.expected_inputs(spendable, 1_000_000, 1.0), 3);
+    }
+
+    #[test]
+    fn test_fill_vbytes_matches_a_single_input_fill() {
+        assert_eq!(fill_vbytes(1), 199);
+        assert_eq!(fill_vbytes(3) - fill_vbytes(2), P2WPKH_INPUT_VBYTES);
+    }
+
+    #[test]
+    fn test_strategy_round_trips_through_its_name() {
+        for strategy in [
+            CoinSelectionStrategy::LargestFirst,
+            CoinSelectionStrategy::BranchAndBound,
+            CoinSelectionStrategy::OldestFirst,
+        ] {
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+        }
+        assert!("smallest-first".parse::<CoinSelectionStrategy>().is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt;
+
+use bdk_wallet::{
+    bitcoin::{
+        hashes::{sha256, Hash},
+        secp256k1::Secp256k1,
+        Network,
+    },
+    miniscript::{descriptor::DescriptorPublicKey, Descriptor},
+    rusqlite::{Connection, OptionalExtension},
+    CreateParams, KeychainKind, LoadParams, LoadWithPersistError, PersistedWallet, WalletPersister,
+};
+use snafu::{ensure, OptionExt, ResultExt};
+use tracing::warn;
+
+use super::{
+    BitcoinWalletError, DescriptorMismatchSnafu, InvalidDescriptorSnafu,
+    MissingChangeDescriptorSnafu, WalletIdentitySnafu,
+};
+
+/// Load the wallet in `conn`, or create it if the database is new.
+///
+/// Refuses a database created for another descriptor or network, `db_file` is
+/// only used to say which one. New wallets send change to `change_descriptor`
+/// when one is given. A wallet keeps the keychains it was created with, so a
+/// single descriptor database still loads, change going to external addresses.
+pub(super) fn open_persisted_wallet(
+    conn: &mut Connection,
+    db_file: &str,
+    external_descriptor: &str,
+    change_descriptor: Option<&str>,
+    network: Network,
+) -> Result<PersistedWallet<Connection>, BitcoinWalletError> {
+    let identity = WalletIdentity::new(external_descriptor, network)?;
+    let stored = WalletIdentity::load(conn)?;
+    if let Some(stored) = &stored {
+        ensure!(
+            *stored == identity,
+            DescriptorMismatchSnafu {
+                db_file,
+                expected: identity.clone(),
+                found: stored.clone(),
+            }
+        );
+    }
+
+    let stored_changeset =
+        WalletPersister::initialize(conn).map_err(|e| BitcoinWalletError::LoadWallet {
+            source: Box::new(LoadWithPersistError::Persist(e)),
+        })?;
+
+    // Try to load existing wallet
+    let mut load_params = LoadParams::new()
+        .descriptor(
+            KeychainKind::External,
+            Some(external_descriptor.to_string()),
+        )
+        .extract_keys()
+        .check_network(network);
+    if stored_changeset.change_descriptor.is_some() {
+        let change_descriptor =
+            change_descriptor.context(MissingChangeDescriptorSnafu { db_file })?;
+        load_params =
+            load_params.descriptor(KeychainKind::Internal, Some(change_descriptor.to_string()));
+    } else if change_descriptor.is_some() && stored_changeset.descriptor.is_some() {
+        warn!(
+            "Wallet database {} has no change keychain, change keeps going to external addresses",
+            db_file
+        );
+    }
+
+    let wallet_opt =
+        PersistedWallet::load(conn, load_params).map_err(|e| BitcoinWalletError::LoadWallet {
+            source: Box::new(e),
+        })?;
+
+    let wallet = match wallet_opt {
+        Some(wallet) => wallet,
+        None => {
+            // Create new wallet
+            let create_params = match change_descriptor {
+                Some(change_descriptor) => CreateParams::new(
+                    external_descriptor.to_string(),
+                    change_descriptor.to_string(),
+                ),
+                None => CreateParams::new_single(external_descriptor.to_string()),
+            }
+            .network(network);
+
+            PersistedWallet::create(conn, create_params).map_err(|e| {
+                BitcoinWalletError::CreateWallet {
+                    source: Box::new(e),
+                }
+            })?
+        }
+    };
+
+    // Databases from before the identity was stored were just checked by BDK itself
+    if stored.is_none() {
+        identity.store(conn)?;
+    }
+    Ok(wallet)
+}
+
+/// Check the wallet in `conn` was created for `external_descriptor` on
+/// `network` without writing to it. The stored identity, `None` if the
+/// database has none yet
+pub(super) fn check_persisted_wallet(
+    conn: &Connection,
+    db_file: &str,
+    external_descriptor: &str,
+    network: Network,
+) -> Result<Option<WalletIdentity>, BitcoinWalletError> {
+    let identity = WalletIdentity::new(external_descriptor, network)?;
+    let Some(stored) = WalletIdentity::load_read_only(conn)? else {
+        return Ok(None);
+    };
+    ensure!(
+        stored == identity,
+        DescriptorMismatchSnafu {
+            db_file,
+            expected: identity,
+            found: stored,
+        }
+    );
+    Ok(Some(stored))
+}
+
+/// Which descriptor and network a wallet database was created for.
+///
+/// The fingerprint hashes the public descriptor, so it can be stored and logged
+/// without exposing the keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletIdentity {
+    pub descriptor_fingerprint: String,
+    pub network: String,
+}
+
+impl WalletIdentity {
+    pub fn new(descriptor: &str, network: Network) -> Result<Self, BitcoinWalletError> {
+        let (public_descriptor, _) =
+            Descriptor::<DescriptorPublicKey>::parse_descriptor(&Secp256k1::new(), descriptor)
+                .context(InvalidDescriptorSnafu)?;
+        let hash = sha256::Hash::hash(public_descriptor.to_string().as_bytes());
+        Ok(Self {
+            descriptor_fingerprint: hash.to_string(),
+            network: network.to_string(),
+        })
+    }
+
+    /// Database file name unique to this descriptor and network
+    #[must_use]
+    pub fn db_file_name(&self) -> String {
+        format!(
+            "bitcoin_wallet_{}_{}.db",
+            self.network,
+            &self.descriptor_fingerprint[..16]
+        )
+    }
+
+    /// The identity stored in `conn`, None if the database predates it or is new
+    pub(super) fn load(conn: &Connection) -> Result<Option<Self>, BitcoinWalletError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS mm_wallet_identity (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                descriptor_fingerprint TEXT NOT NULL,
+                network TEXT NOT NULL
+            )",
+            [],
+        )
+        .context(WalletIdentitySnafu)?;
+        Self::read(conn)
+    }
+
+    /// [`Self::load`] without creating the table, for a read-only connection
+    pub(super) fn load_read_only(conn: &Connection) -> Result<Option<Self>, BitcoinWalletError> {
+        let has_table = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'mm_wallet_identity'",
+                [],
+                |_| Ok(()),
+            )
+            .optional()
+            .context(WalletIdentitySnafu)?
+            .is_some();
+        if !has_table {
+            return Ok(None);
+        }
+        Self::read(conn)
+    }
+
+    fn read(conn: &Connection) -> Result<Option<Self>, BitcoinWalletError> {
+        conn.query_row(
+            "SELECT descriptor_fingerprint, network FROM mm_wallet_identity WHERE id = 0",
+            [],
+            |row| {
+                Ok(Self {
+                    descriptor_fingerprint: row.get(0)?,
+                    network: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+        .context(WalletIdentitySnafu)
+    }
+
+    pub(super) fn store(&self, conn: &Connection) -> Result<(), BitcoinWalletError> {
+        conn.execute(
+            "INSERT INTO mm_wallet_identity (id, descriptor_fingerprint, network) VALUES (0, ?1, ?2)",
+            [&self.descriptor_fingerprint, &self.network],
+        )
+        .context(WalletIdentitySnafu)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for WalletIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "descriptor {} on {}",
+            &self.descriptor_fingerprint[..16],
+            self.network
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Private keys 1 and 2
+    const DESCRIPTOR: &str = "wpkh(cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA)";
+    const OTHER_DESCRIPTOR: &str = "wpkh(cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87K7XCyj5v)";
+    // Private key 3
+    const CHANGE_DESCRIPTOR: &str = "wpkh(cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87KcLPVfXz)";
+
+    fn open(
+        conn: &mut Connection,
+        descriptor: &str,
+        network: Network,
+    ) -> Result<PersistedWallet<Connection>, BitcoinWalletError> {
+        open_persisted_wallet(conn, "wallet.db", descriptor, None, network)
+    }
+
+    fn open_with_change(
+        conn: &mut Connection,
+        change_descriptor: Option<&str>,
+    ) -> Result<PersistedWallet<Connection>, BitcoinWalletError> {
+        open_persisted_wallet(
+            conn,
+            "wallet.db",
+            DESCRIPTOR,
+            change_descriptor,
+            Network::Regtest,
+        )
+    }
+
+    #[test]
+    fn test_reopens_the_wallet_it_created() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut wallet = open(&mut conn, DESCRIPTOR, Network::Regtest).unwrap();
+        let address = wallet.reveal_next_address(KeychainKind::External).address;
+        wallet.persist(&mut conn).unwrap();
+        drop(wallet);
+
+        let wallet = open(&mut conn, DESCRIPTOR, Network::Regtest).unwrap();
+        assert_eq!(
+            wallet.peek_address(KeychainKind::External, 0).address,
+            address
+        );
+        assert_eq!(
+            WalletIdentity::load(&conn).unwrap(),
+            Some(WalletIdentity::new(DESCRIPTOR, Network::Regtest).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_rejects_another_descriptor() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        open(&mut conn, DESCRIPTOR, Network::Regtest).unwrap();
+
+        let err = open(&mut conn, OTHER_DESCRIPTOR, Network::Regtest).unwrap_err();
+        let BitcoinWalletError::DescriptorMismatch {
+            expected, found, ..
+        } = err
+        else {
+            panic!("expected a descriptor mismatch, got {err}");
+        };
+        assert_eq!(
+            found,
+            WalletIdentity::new(DESCRIPTOR, Network::Regtest).unwrap()
+        );
+        assert_eq!(
+            expected,
+            WalletIdentity::new(OTHER_DESCRIPTOR, Network::Regtest).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_only_check_leaves_the_database_untouched() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(
+            check_persisted_wallet(&conn, "wallet.db", DESCRIPTOR, Network::Regtest).unwrap(),
+            None
+        );
+        assert_eq!(WalletIdentity::load_read_only(&conn).unwrap(), None);
+        let tables: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tables, 0);
+
+        let mut conn = conn;
+        open(&mut conn, DESCRIPTOR, Network::Regtest).unwrap();
+        assert_eq!(
+            check_persisted_wallet(&conn, "wallet.db", DESCRIPTOR, Network::Regtest).unwrap(),
+            Some(WalletIdentity::new(DESCRIPTOR, Network::Regtest).unwrap())
+        );
+        assert!(matches!(
+            check_persisted_wallet(&conn, "wallet.db", OTHER_DESCRIPTOR, Network::Regtest),
+            Err(BitcoinWalletError::DescriptorMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_another_network() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        open(&mut conn, DESCRIPTOR, Network::Regtest).unwrap();
+
+        let err = open(&mut conn, DESCRIPTOR, Network::Testnet).unwrap_err();
+        assert!(
+            matches!(err, BitcoinWalletError::DescriptorMismatch { .. }),
+            "expected a descriptor mismatch, got {err}"
+        );
+    }
+
+    #[test]
+    fn test_identity_hides_the_keys() {
+        let identity = WalletIdentity::new(DESCRIPTOR, Network::Regtest).unwrap();
+        assert!(!identity.to_string().contains("cMahea"));
+        assert_ne!(
+            identity.db_file_name(),
+            WalletIdentity::new(OTHER_DESCRIPTOR, Network::Regtest)
+                .unwrap()
+                .db_file_name()
+        );
+        assert_ne!(
+            identity.db_file_name(),
+            WalletIdentity::new(DESCRIPTOR, Network::Signet)
+                .unwrap()
+                .db_file_name()
+        );
+    }
+
+    #[test]
+    fn test_change_goes_to_the_internal_keychain() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut wallet = open_with_change(&mut conn, Some(CHANGE_DESCRIPTOR)).unwrap();
+        let change = wallet.reveal_next_address(KeychainKind::Internal);
+        assert_eq!(change.keychain, KeychainKind::Internal);
+        assert_ne!(
+            change.address,
+            wallet.peek_address(KeychainKind::External, 0).address
+        );
+        wallet.persist(&mut conn).unwrap();
+        drop(wallet);
+
+        let wallet = open_with_change(&mut conn, Some(CHANGE_DESCRIPTOR)).unwrap();
+        assert_eq!(
+            wallet.derivation_of_spk(change.address.script_pubkey()),
+            Some((KeychainKind::Internal, 0))
+        );
+
+        // Its keys are needed to spend change, so the wallet can't open without it
+        let err = open_with_change(&mut conn, None).unwrap_err();
+        assert!(
+            matches!(err, BitcoinWalletError::MissingChangeDescriptor { .. }),
+            "expected a missing change descriptor, got {err}"
+        );
+    }
+
+    #[test]
+    fn test_single_descriptor_wallet_still_loads_with_a_change_descriptor() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut wallet = open_with_change(&mut conn, None).unwrap();
+        let address = wallet.reveal_next_address(KeychainKind::External).address;
+        wallet.persist(&mut conn).unwrap();
+        drop(wallet);
+
+        let mut wallet = open_with_change(&mut conn, Some(CHANGE_DESCRIPTOR)).unwrap();
+        assert_eq!(
+            wallet.peek_address(KeychainKind::External, 0).address,
+            address
+        );
+        // Without a change keychain BDK keeps drawing change from the external one
+        assert_eq!(
+            wallet.reveal_next_address(KeychainKind::Internal).keychain,
+            KeychainKind::External
+        );
+    }
+}
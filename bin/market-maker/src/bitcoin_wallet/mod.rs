@@ -1,28 +1,55 @@
+pub mod coin_selection;
+pub mod identity;
+pub mod signer;
+pub mod sync;
 pub mod transaction_broadcaster;
+pub mod utxos;
 
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 
+use alloy::primitives::U256;
 use async_trait::async_trait;
-use bdk_esplora::{esplora_client, EsploraAsyncExt};
-use bdk_wallet::rusqlite::Connection;
+use bdk_esplora::esplora_client;
+use bdk_wallet::rusqlite::{Connection, OpenFlags};
 use bdk_wallet::{
-    bitcoin::{self, Network},
+    bitcoin::{self, Network, OutPoint},
     error::CreateTxError,
-    signer::SignerError,
-    CreateParams, KeychainKind, LoadParams, LoadWithPersistError, PersistedWallet,
+    KeychainKind, LoadWithPersistError, PersistedWallet,
 };
+use chrono::{DateTime, Utc};
 use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_models::{ChainType, Currency, Lot, TokenIdentifier};
+use otc_models::{ChainType, Currency, Lot, TokenIdentifier, TxHash};
 use snafu::{ResultExt, Snafu};
 use tokio::sync::Mutex;
 use tokio::task::JoinSet;
-use tracing::info;
+use tokio::time::{timeout, Instant};
+use tracing::{info, warn};
 
-use crate::wallet::{self, Wallet as WalletTrait, WalletError};
+use crate::wallet::{
+    self, with_reservations, FailedPayment, FillPreparation, PaymentStatus, PreparedFeeRate,
+    TransactionResult, Wallet as WalletTrait, WalletError,
+};
+
+use coin_selection::fill_vbytes;
+pub use coin_selection::{
+    CoinSelectionConfig, CoinSelectionStrategy, ConsolidationConfig, DEFAULT_MAX_FILL_INPUTS,
+};
+use identity::{check_persisted_wallet, open_persisted_wallet};
+pub use identity::WalletIdentity;
+use signer::{has_private_keys, DescriptorSigner, ExternalSigner, SignPsbtError, Signer};
+pub use signer::{ExternalSignerConfig, SignerConfig, DEFAULT_EXTERNAL_SIGNER_TIMEOUT};
+pub use sync::BitcoinWalletSyncConfig;
+use sync::WalletSyncer;
+pub use utxos::DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH;
+use utxos::classify_utxos;
 
 const STOP_GAP: usize = 50;
 const PARALLEL_REQUESTS: usize = 5;
 const BALANCE_BUFFER_PERCENT: u64 = 25; // 25% buffer
+/// Confirmation target of the fee rate we prefetch for payouts
+const FEE_TARGET_BLOCKS: u16 = 2;
 
 #[derive(Debug, Snafu)]
 pub enum BitcoinWalletError {
@@ -34,6 +61,32 @@ pub enum BitcoinWalletError {
         source: Box<LoadWithPersistError<bdk_wallet::rusqlite::Error>>,
     },
 
+    #[snafu(display("Invalid wallet descriptor: {}", source))]
+    InvalidDescriptor {
+        source: bdk_wallet::miniscript::Error,
+    },
+
+    #[snafu(display("Failed to read or store the wallet identity: {}", source))]
+    WalletIdentity { source: bdk_wallet::rusqlite::Error },
+
+    #[snafu(display(
+        "Wallet database {} was created for {}, not {}. Give each descriptor and network its own database file, or move this one aside to start a fresh wallet",
+        db_file,
+        found,
+        expected
+    ))]
+    DescriptorMismatch {
+        db_file: String,
+        expected: WalletIdentity,
+        found: WalletIdentity,
+    },
+
+    #[snafu(display(
+        "Wallet database {} sends change to an internal keychain, its change descriptor is needed to open it",
+        db_file
+    ))]
+    MissingChangeDescriptor { db_file: String },
+
     #[snafu(display("Failed to create wallet: {}", source))]
     CreateWallet {
         source: Box<bdk_wallet::CreateWithPersistError<bdk_wallet::rusqlite::Error>>,
@@ -58,8 +111,23 @@ pub enum BitcoinWalletError {
     #[snafu(display("Failed to build transaction: {}", source))]
     BuildTransaction { source: CreateTxError },
 
+    #[snafu(display("Failed to add UTXOs to transaction: {}", source))]
+    AddUtxo {
+        source: bdk_wallet::tx_builder::AddUtxoError,
+    },
+
     #[snafu(display("Failed to sign transaction: {}", source))]
-    SignTransaction { source: SignerError },
+    SignTransaction { source: SignPsbtError },
+
+    #[snafu(display(
+        "Wallet descriptor holds private keys, a watch-only wallet with an external signer takes a public descriptor"
+    ))]
+    PrivateKeysInWatchOnlyWallet,
+
+    #[snafu(display(
+        "Wallet descriptor holds no private keys, configure an external signer to run watch-only"
+    ))]
+    MissingPrivateKeys,
 
     #[snafu(display("Failed to extract transaction: {}", source))]
     ExtractTransaction {
@@ -83,52 +151,61 @@ pub enum BitcoinWalletError {
     InsufficientBalance,
 }
 
+/// What [`BitcoinWallet::check_database`] found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletDatabaseCheck {
+    /// No database yet, starting the market maker creates it
+    Missing,
+    /// Created for the configured descriptor and network
+    Matches(WalletIdentity),
+    /// Predates the stored identity, its descriptor is checked on start
+    Unidentified,
+}
+
 pub struct BitcoinWallet {
     pub tx_broadcaster: transaction_broadcaster::BitcoinTransactionBroadcaster,
     wallet: Arc<Mutex<PersistedWallet<Connection>>>,
-    connection: Arc<Mutex<Connection>>,
+    syncer: Arc<WalletSyncer>,
     esplora_client: Arc<esplora_client::AsyncClient>,
+    sync_config: BitcoinWalletSyncConfig,
+    max_unconfirmed_chain_depth: usize,
+    coin_selection: CoinSelectionConfig,
 }
 
 impl BitcoinWallet {
+    /// Open the wallet in `db_file`. With an external signer the descriptors must be
+    /// public ones, the wallet then only watches and hands its PSBTs off for signing
     pub async fn new(
         db_file: &str,
         external_descriptor: &str,
+        change_descriptor: Option<&str>,
         network: Network,
         esplora_url: &str,
+        sync_config: BitcoinWalletSyncConfig,
+        max_unconfirmed_chain_depth: usize,
+        coin_selection: CoinSelectionConfig,
+        signer: SignerConfig,
         join_set: &mut JoinSet<crate::Result<()>>,
     ) -> Result<Self, BitcoinWalletError> {
-        let mut conn = Connection::open(db_file).context(OpenDatabaseSnafu)?;
-
-        // Try to load existing wallet
-        let load_params = LoadParams::new()
-            .descriptor(
-                KeychainKind::External,
-                Some(external_descriptor.to_string()),
-            )
-            .extract_keys()
-            .check_network(network);
-
-        let wallet_opt = PersistedWallet::load(&mut conn, load_params).map_err(|e| {
-            BitcoinWalletError::LoadWallet {
-                source: Box::new(e),
-            }
-        })?;
+        let holds_keys = has_private_keys(external_descriptor)?
+            || change_descriptor
+                .map(has_private_keys)
+                .transpose()?
+                .unwrap_or(false);
+        match (&signer, holds_keys) {
+            (SignerConfig::Descriptor, false) => return MissingPrivateKeysSnafu.fail(),
+            (SignerConfig::External(_), true) => return PrivateKeysInWatchOnlyWalletSnafu.fail(),
+            _ => {}
+        }
 
-        let wallet = match wallet_opt {
-            Some(wallet) => wallet,
-            None => {
-                // Create new wallet
-                let create_params =
-                    CreateParams::new_single(external_descriptor.to_string()).network(network);
-
-                PersistedWallet::create(&mut conn, create_params).map_err(|e| {
-                    BitcoinWalletError::CreateWallet {
-                        source: Box::new(e),
-                    }
-                })?
-            }
-        };
+        let mut conn = Connection::open(db_file).context(OpenDatabaseSnafu)?;
+        let wallet = open_persisted_wallet(
+            &mut conn,
+            db_file,
+            external_descriptor,
+            change_descriptor,
+            network,
+        )?;
 
         let esplora_client = esplora_client::Builder::new(esplora_url)
             .build_async()
@@ -145,67 +222,236 @@ impl BitcoinWallet {
             info!("Bitcoin wallet initialized with address: {}", address);
         }
 
+        let syncer = Arc::new(WalletSyncer::new(
+            wallet.clone(),
+            connection,
+            esplora_client.clone(),
+        ));
+
+        join_set.spawn({
+            let syncer = syncer.clone();
+            async move {
+                syncer.run_background_sync(sync_config.sync_interval).await;
+                Ok(())
+            }
+        });
+
+        let signer: Arc<dyn Signer> = match signer {
+            SignerConfig::Descriptor => Arc::new(DescriptorSigner::new(wallet.clone())),
+            SignerConfig::External(config) => {
+                info!("Bitcoin wallet is watch-only, signing with {}", config.url);
+                Arc::new(ExternalSigner::new(config, network)?)
+            }
+        };
+
         let tx_broadcaster = transaction_broadcaster::BitcoinTransactionBroadcaster::new(
             wallet.clone(),
-            connection.clone(),
+            signer,
+            syncer.clone(),
             esplora_client.clone(),
             network,
+            max_unconfirmed_chain_depth,
+            coin_selection,
             join_set,
         );
 
         Ok(Self {
             tx_broadcaster,
             wallet,
-            connection,
+            syncer,
             esplora_client,
+            sync_config,
+            max_unconfirmed_chain_depth,
+            coin_selection,
         })
     }
 
+    /// Check the database in `db_file` belongs to `external_descriptor` on
+    /// `network` without creating or writing to it
+    pub fn check_database(
+        db_file: &str,
+        external_descriptor: &str,
+        network: Network,
+    ) -> Result<WalletDatabaseCheck, BitcoinWalletError> {
+        if !Path::new(db_file).exists() {
+            WalletIdentity::new(external_descriptor, network)?;
+            return Ok(WalletDatabaseCheck::Missing);
+        }
+        let conn = Connection::open_with_flags(db_file, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .context(OpenDatabaseSnafu)?;
+        Ok(
+            match check_persisted_wallet(&conn, db_file, external_descriptor, network)? {
+                Some(identity) => WalletDatabaseCheck::Matches(identity),
+                None => WalletDatabaseCheck::Unidentified,
+            },
+        )
+    }
+
+    /// Open the wallet in `dir`, in a database file named after the descriptor and
+    /// network so different wallets never share one
+    pub async fn open_or_create_in_dir(
+        dir: &Path,
+        external_descriptor: &str,
+        change_descriptor: Option<&str>,
+        network: Network,
+        esplora_url: &str,
+        sync_config: BitcoinWalletSyncConfig,
+        max_unconfirmed_chain_depth: usize,
+        coin_selection: CoinSelectionConfig,
+        signer: SignerConfig,
+        join_set: &mut JoinSet<crate::Result<()>>,
+    ) -> Result<Self, BitcoinWalletError> {
+        let identity = WalletIdentity::new(external_descriptor, network)?;
+        let db_file = dir.join(identity.db_file_name());
+        Self::new(
+            &db_file.to_string_lossy(),
+            external_descriptor,
+            change_descriptor,
+            network,
+            esplora_url,
+            sync_config,
+            max_unconfirmed_chain_depth,
+            coin_selection,
+            signer,
+            join_set,
+        )
+        .await
+    }
+
+    /// Incrementally sync the wallet against esplora right now
+    pub async fn sync_now(&self) -> Result<(), BitcoinWalletError> {
+        self.syncer.sync().await
+    }
+
+    /// A receive address never handed out before, persisted so a restart doesn't reuse it
+    pub async fn reveal_next_address(&self) -> Result<bitcoin::Address, BitcoinWalletError> {
+        self.syncer.reveal_next_address().await
+    }
+
+    /// When the wallet state was last refreshed from the chain
+    pub async fn last_synced_at(&self) -> Option<DateTime<Utc>> {
+        self.syncer.last_synced_at().await
+    }
+
     async fn check_balance(&self, lot: &Lot) -> Result<bool, BitcoinWalletError> {
-        // First sync the wallet to get the latest balance
-        let mut wallet = self.wallet.lock().await;
-        
-        // Do a full scan to get the latest balance from the blockchain
-        let request = wallet.start_full_scan().build();
-        let update = self.esplora_client
-            .full_scan(request, 10, 5)
-            .await
-            .map_err(|e| BitcoinWalletError::SyncWallet { source: e })?;
-        
-        wallet.apply_update(update)
-            .map_err(|_| BitcoinWalletError::ApplyUpdate)?;
-        
-        // Persist the updated wallet state
-        let mut conn = self.connection.lock().await;
-        wallet.persist(&mut conn)
-            .map_err(|e| BitcoinWalletError::PersistWallet { source: e })?;
-        drop(conn);
-        
-        let balance = wallet.balance();
+        // Refresh before committing to a fill, but don't let a slow esplora stall quoting
+        match timeout(self.sync_config.fill_sync_timeout, self.sync_now()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("On-demand wallet sync failed: {}", e),
+            Err(_) => warn!(
+                "On-demand wallet sync timed out after {:?}",
+                self.sync_config.fill_sync_timeout
+            ),
+        }
+
+        if self.syncer.is_stale(self.sync_config.max_staleness).await {
+            warn!(
+                "Refusing fill, wallet state is stale (last synced at {:?})",
+                self.last_synced_at().await
+            );
+            return Ok(false);
+        }
+
+        // Same view of spendable funds the broadcaster uses when building the payout,
+        // limited to the inputs one fill may spend
+        let spendable: bitcoin::Amount = self
+            .coin_selection
+            .candidates(
+                classify_utxos(&*self.wallet.lock().await, self.max_unconfirmed_chain_depth)
+                    .spendable,
+                &[],
+            )
+            .iter()
+            .map(|utxo| utxo.txout.value)
+            .sum();
         info!("Bitcoin lot is valid: {:?}", lot);
 
         let amount_sats = lot.amount.to::<u64>();
         let required_balance = balance_with_buffer(amount_sats);
         
         info!(
-            "Bitcoin balance check: wallet_balance={} sats, required={} sats, required_with_buffer={} sats",
-            balance.total().to_sat(),
+            "Bitcoin balance check: spendable_balance={} sats, required={} sats, required_with_buffer={} sats",
+            spendable.to_sat(),
             amount_sats,
             required_balance
         );
 
-        Ok(balance.total().to_sat() > required_balance)
+        Ok(spendable.to_sat() > required_balance)
     }
-}
 
-#[async_trait]
-impl WalletTrait for BitcoinWallet {
-    async fn create_payment(
+    /// Fee rate for confirmation within `FEE_TARGET_BLOCKS`, `None` if esplora has no estimate
+    async fn fetch_fee_rate(&self) -> Option<PreparedFeeRate> {
+        match self.esplora_client.get_fee_estimates().await {
+            Ok(estimates) => fee_rate_for_target(&estimates, FEE_TARGET_BLOCKS)
+                .map(|sat_per_vb| PreparedFeeRate::Bitcoin { sat_per_vb }),
+            Err(e) => {
+                warn!("Failed to fetch fee estimates: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Spendable UTXOs covering `lot` with the balance buffer, in the order of the
+    /// coin selection strategy and skipping any that `pending` preparations already
+    /// set aside. Empty if they don't cover it within the input cap, the payout then
+    /// selects inputs itself.
+    async fn select_utxos(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> wallet::Result<Vec<OutPoint>> {
+        let amount_sats = u64::try_from(lot.amount)
+            .map_err(|_| WalletError::UnsupportedLot { lot: lot.clone() })?;
+        let reserved: HashSet<OutPoint> = pending
+            .iter()
+            .flat_map(|preparation| preparation.utxos.iter().copied())
+            .collect();
+        let spendable =
+            classify_utxos(&*self.wallet.lock().await, self.max_unconfirmed_chain_depth).spendable;
+        let candidates = self.coin_selection.candidates(
+            spendable
+                .into_iter()
+                .filter(|utxo| !reserved.contains(&utxo.outpoint))
+                .collect(),
+            &[],
+        );
+
+        let target = balance_with_buffer(amount_sats);
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for utxo in candidates {
+            if total >= target {
+                break;
+            }
+            total += utxo.txout.value.to_sat();
+            selected.push(utxo.outpoint);
+        }
+
+        if total >= target {
+            Ok(selected)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// vbytes a fill of `amount_sats` at `sat_per_vb` is expected to take, from the
+    /// inputs the coin selection strategy would spend on it now
+    pub async fn estimate_fill_vbytes(&self, amount_sats: u64, sat_per_vb: f64) -> u64 {
+        let spendable =
+            classify_utxos(&*self.wallet.lock().await, self.max_unconfirmed_chain_depth).spendable;
+        fill_vbytes(
+            self.coin_selection
+                .expected_inputs(spendable, amount_sats, sat_per_vb),
+        )
+    }
+
+    async fn queue_payment(
         &self,
         lot: &Lot,
         to_address: &str,
         mm_payment_validation: Option<MarketMakerPaymentValidation>,
-    ) -> wallet::Result<String> {
+        preparation: Option<FillPreparation>,
+    ) -> wallet::Result<TransactionResult> {
         ensure_valid_lot(lot)?;
 
         info!(
@@ -215,25 +461,27 @@ impl WalletTrait for BitcoinWallet {
 
         // Send transaction request to the broadcaster
         self.tx_broadcaster
-            .broadcast_transaction(lot.clone(), to_address.to_string(), mm_payment_validation)
+            .broadcast_transaction(
+                lot.clone(),
+                to_address.to_string(),
+                mm_payment_validation,
+                preparation,
+            )
+            .await
+            .map_err(|e| payment_error(lot, e))
+    }
+}
+
+#[async_trait]
+impl WalletTrait for BitcoinWallet {
+    async fn create_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        self.queue_payment(lot, to_address, mm_payment_validation, None)
             .await
-            .map_err(|e| match e {
-                transaction_broadcaster::TransactionBroadcasterError::InvalidCurrency => {
-                    WalletError::UnsupportedLot { lot: lot.clone() }
-                }
-                transaction_broadcaster::TransactionBroadcasterError::InsufficientBalance => {
-                    WalletError::InsufficientBalance {
-                        required: lot.amount.to_string(),
-                        available: "unknown".to_string(),
-                    }
-                }
-                transaction_broadcaster::TransactionBroadcasterError::ParseAddress { reason } => {
-                    WalletError::ParseAddressFailed { context: reason }
-                }
-                _ => WalletError::TransactionCreationFailed {
-                    reason: e.to_string(),
-                },
-            })
     }
 
     async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
@@ -247,6 +495,144 @@ impl WalletTrait for BitcoinWallet {
                 reason: e.to_string(),
             })
     }
+
+    async fn prepare_fill(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> wallet::Result<FillPreparation> {
+        ensure_valid_lot(lot)?;
+        let start = Instant::now();
+
+        let reserved = with_reservations(lot, pending);
+        if !self.can_fill(&reserved).await? {
+            return Err(WalletError::InsufficientBalance {
+                required: reserved.amount.to_string(),
+                available: "unknown".to_string(),
+            });
+        }
+
+        let fee_rate = self.fetch_fee_rate().await;
+        let utxos = self.select_utxos(lot, pending).await?;
+
+        Ok(FillPreparation {
+            lot: lot.clone(),
+            fee_rate,
+            utxos,
+            lookup_duration: start.elapsed(),
+        })
+    }
+
+    async fn create_prepared_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        preparation: &FillPreparation,
+    ) -> wallet::Result<TransactionResult> {
+        self.queue_payment(
+            lot,
+            to_address,
+            mm_payment_validation,
+            Some(preparation.clone()),
+        )
+        .await
+    }
+
+    async fn balance(&self, currency: &Currency) -> wallet::Result<U256> {
+        ensure_valid_lot(&Lot {
+            currency: currency.clone(),
+            amount: U256::ZERO,
+        })?;
+        let total = self.wallet.lock().await.balance().total();
+        Ok(U256::from(total.to_sat()))
+    }
+
+    async fn payment_status(&self, tx_hash: &TxHash) -> wallet::Result<PaymentStatus> {
+        let lookup_failed = |reason: String| WalletError::PaymentLookupFailed { reason };
+        let txid = parse_txid(tx_hash)?;
+        if self
+            .esplora_client
+            .get_tx(&txid)
+            .await
+            .map_err(|e| lookup_failed(e.to_string()))?
+            .is_none()
+        {
+            return Ok(PaymentStatus::NotFound);
+        }
+
+        let status = self
+            .esplora_client
+            .get_tx_status(&txid)
+            .await
+            .map_err(|e| lookup_failed(e.to_string()))?;
+        Ok(if status.confirmed {
+            PaymentStatus::Confirmed
+        } else {
+            PaymentStatus::Pending
+        })
+    }
+
+    /// Bitcoin payments don't revert, only one the chain lost is replaced. The
+    /// replacement spends its inputs and copies its outputs, the nonce and fee
+    /// ones included
+    async fn replace_payment(
+        &self,
+        failed: &FailedPayment,
+        lot: &Lot,
+        to_address: &str,
+        _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        ensure_valid_lot(lot)?;
+        if failed.status != PaymentStatus::NotFound {
+            return Err(WalletError::TransactionCreationFailed {
+                reason: format!(
+                    "payment {} is {:?}, only a lost one can be replaced",
+                    failed.tx_hash, failed.status
+                ),
+            });
+        }
+
+        let txid = parse_txid(&failed.tx_hash)?;
+        info!("Queueing replacement of Bitcoin transaction {}", txid);
+        self.tx_broadcaster
+            .broadcast_replacement(txid, lot.clone(), to_address.to_string())
+            .await
+            .map_err(|e| payment_error(lot, e))
+    }
+}
+
+fn parse_txid(tx_hash: &TxHash) -> Result<bitcoin::Txid, WalletError> {
+    tx_hash
+        .digits()
+        .parse()
+        .map_err(|e| WalletError::PaymentLookupFailed {
+            reason: format!("{tx_hash} is not a Bitcoin txid: {e}"),
+        })
+}
+
+/// What a failed payment of `lot` is reported as
+fn payment_error(
+    lot: &Lot,
+    e: transaction_broadcaster::TransactionBroadcasterError,
+) -> WalletError {
+    match e {
+        transaction_broadcaster::TransactionBroadcasterError::InvalidCurrency => {
+            WalletError::UnsupportedLot { lot: lot.clone() }
+        }
+        transaction_broadcaster::TransactionBroadcasterError::InsufficientBalance => {
+            WalletError::InsufficientBalance {
+                required: lot.amount.to_string(),
+                available: "unknown".to_string(),
+            }
+        }
+        transaction_broadcaster::TransactionBroadcasterError::ParseAddress { reason } => {
+            WalletError::ParseAddressFailed { context: reason }
+        }
+        _ => WalletError::TransactionCreationFailed {
+            reason: e.to_string(),
+        },
+    }
 }
 
 fn ensure_valid_lot(lot: &Lot) -> Result<(), WalletError> {
@@ -265,6 +651,15 @@ fn ensure_valid_lot(lot: &Lot) -> Result<(), WalletError> {
     Ok(())
 }
 
+/// Cheapest estimated rate that still confirms within `target_blocks`, in whole sat/vB
+fn fee_rate_for_target(estimates: &HashMap<u16, f64>, target_blocks: u16) -> Option<u64> {
+    estimates
+        .iter()
+        .filter(|(blocks, rate)| **blocks <= target_blocks && rate.is_finite())
+        .max_by_key(|(blocks, _)| **blocks)
+        .map(|(_, rate)| (rate.ceil() as u64).max(1))
+}
+
 fn balance_with_buffer(balance_sats: u64) -> u64 {
     balance_sats + (balance_sats * BALANCE_BUFFER_PERCENT) / 100
 }
@@ -0,0 +1,388 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bdk_wallet::{
+    bitcoin::{psbt::PsbtParseError, secp256k1::Secp256k1, Address, Network, Psbt, ScriptBuf},
+    miniscript::{descriptor::DescriptorPublicKey, Descriptor},
+    rusqlite::Connection,
+    signer::SignOptions,
+    PersistedWallet,
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt, Snafu};
+use tokio::sync::Mutex;
+use url::Url;
+
+use super::{BitcoinWalletError, InvalidDescriptorSnafu, ParseAddressSnafu};
+
+/// How long the external signer gets to answer unless configured otherwise
+pub const DEFAULT_EXTERNAL_SIGNER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Snafu)]
+pub enum SignPsbtError {
+    #[snafu(display("Descriptor signing failed: {}", source))]
+    Descriptor {
+        source: bdk_wallet::signer::SignerError,
+    },
+
+    #[snafu(display("Refusing to have a PSBT paying {} signed", output))]
+    UnexpectedOutput { output: String },
+
+    #[snafu(display("External signer request failed: {}", source))]
+    Request { source: reqwest::Error },
+
+    #[snafu(display("External signer didn't answer within {:?}", timeout))]
+    Timeout { timeout: Duration },
+
+    #[snafu(display("External signer returned an invalid PSBT: {}", source))]
+    DecodePsbt { source: PsbtParseError },
+
+    #[snafu(display("External signer returned a different transaction than it was sent"))]
+    TransactionChanged,
+
+    #[snafu(display("Signed PSBT is missing signatures"))]
+    Incomplete,
+}
+
+/// A PSBT built by the broadcaster on its way to be signed
+#[derive(Debug, Clone)]
+pub struct PsbtHandoff {
+    pub psbt: Psbt,
+    /// Every output the broadcaster meant to create, change included
+    pub payees: Vec<ScriptBuf>,
+}
+
+/// Signs the wallet's inputs of PSBTs handed off by the transaction broadcaster
+#[async_trait]
+pub trait Signer: Send + Sync {
+    async fn sign(&self, handoff: PsbtHandoff) -> Result<Psbt, SignPsbtError>;
+}
+
+/// Who signs the wallet's transactions
+#[derive(Debug, Clone, Default)]
+pub enum SignerConfig {
+    /// The private keys in the wallet descriptor, in this process
+    #[default]
+    Descriptor,
+    /// A watch-only wallet whose PSBTs an external service signs
+    External(ExternalSignerConfig),
+}
+
+#[derive(Debug, Clone)]
+pub struct ExternalSignerConfig {
+    pub url: Url,
+    pub timeout: Duration,
+    /// Addresses besides a payment's own recipients the signer may be asked to pay
+    pub allowed_addresses: Vec<String>,
+}
+
+/// Signs with the keys of the wallet descriptor
+pub struct DescriptorSigner {
+    wallet: Arc<Mutex<PersistedWallet<Connection>>>,
+}
+
+impl DescriptorSigner {
+    pub fn new(wallet: Arc<Mutex<PersistedWallet<Connection>>>) -> Self {
+        Self { wallet }
+    }
+}
+
+#[async_trait]
+impl Signer for DescriptorSigner {
+    async fn sign(&self, handoff: PsbtHandoff) -> Result<Psbt, SignPsbtError> {
+        let mut psbt = handoff.psbt;
+        self.wallet
+            .lock()
+            .await
+            .sign(&mut psbt, SignOptions::default())
+            .context(DescriptorSnafu)?;
+        Ok(psbt)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignPsbtRequest {
+    /// Base64 encoded PSBT
+    pub psbt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignPsbtResponse {
+    /// Base64 encoded PSBT
+    pub psbt: String,
+}
+
+/// POSTs PSBTs to a signing service, keeping the keys off this host.
+///
+/// Only PSBTs paying the payment's own outputs, OP_RETURNs and the allowed
+/// addresses are sent, and the service must return the same transaction.
+pub struct ExternalSigner {
+    client: reqwest::Client,
+    url: Url,
+    timeout: Duration,
+    network: Network,
+    allowed_scripts: HashSet<ScriptBuf>,
+}
+
+impl ExternalSigner {
+    pub fn new(config: ExternalSignerConfig, network: Network) -> Result<Self, BitcoinWalletError> {
+        let allowed_scripts = config
+            .allowed_addresses
+            .iter()
+            .map(|address| {
+                Address::from_str(address)
+                    .context(ParseAddressSnafu)?
+                    .require_network(network)
+                    .map(|address| address.script_pubkey())
+                    .map_err(|_| BitcoinWalletError::InvalidAddress {
+                        address: address.clone(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: config.url,
+            timeout: config.timeout,
+            network,
+            allowed_scripts,
+        })
+    }
+
+    fn check_outputs(&self, handoff: &PsbtHandoff) -> Result<(), SignPsbtError> {
+        for output in &handoff.psbt.unsigned_tx.output {
+            let script = &output.script_pubkey;
+            ensure!(
+                script.is_op_return()
+                    || handoff.payees.contains(script)
+                    || self.allowed_scripts.contains(script),
+                UnexpectedOutputSnafu {
+                    output: Address::from_script(script, self.network)
+                        .map(|address| address.to_string())
+                        .unwrap_or_else(|_| script.to_hex_string()),
+                }
+            );
+        }
+        Ok(())
+    }
+
+    async fn request_signature(&self, psbt: &Psbt) -> Result<SignPsbtResponse, reqwest::Error> {
+        self.client
+            .post(self.url.clone())
+            .json(&SignPsbtRequest {
+                psbt: psbt.to_string(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+#[async_trait]
+impl Signer for ExternalSigner {
+    async fn sign(&self, handoff: PsbtHandoff) -> Result<Psbt, SignPsbtError> {
+        self.check_outputs(&handoff)?;
+
+        let response = tokio::time::timeout(self.timeout, self.request_signature(&handoff.psbt))
+            .await
+            .map_err(|_| SignPsbtError::Timeout {
+                timeout: self.timeout,
+            })?
+            .context(RequestSnafu)?;
+        let signed = Psbt::from_str(&response.psbt).context(DecodePsbtSnafu)?;
+        ensure!(
+            signed.unsigned_tx == handoff.psbt.unsigned_tx,
+            TransactionChangedSnafu
+        );
+        Ok(signed)
+    }
+}
+
+/// Whether the descriptor carries private keys the wallet could sign with
+pub fn has_private_keys(descriptor: &str) -> Result<bool, BitcoinWalletError> {
+    let (_, keys) =
+        Descriptor::<DescriptorPublicKey>::parse_descriptor(&Secp256k1::new(), descriptor)
+            .context(InvalidDescriptorSnafu)?;
+    Ok(!keys.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_wallet::bitcoin::{
+        absolute, hashes::Hash, transaction, Amount, OutPoint, Transaction, TxIn, TxOut,
+        WScriptHash, Witness,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn script(seed: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wsh(&WScriptHash::hash(&[seed]))
+    }
+
+    fn address(script: &ScriptBuf) -> String {
+        Address::from_script(script, Network::Regtest)
+            .unwrap()
+            .to_string()
+    }
+
+    fn unsigned_psbt(outputs: &[ScriptBuf]) -> Psbt {
+        Psbt::from_unsigned_tx(Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                ..Default::default()
+            }],
+            output: outputs
+                .iter()
+                .map(|script_pubkey| TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: script_pubkey.clone(),
+                })
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    /// Body of the HTTP request on `socket`
+    async fn read_body(socket: &mut TcpStream) -> Vec<u8> {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = socket.read(&mut buf).await.unwrap();
+            assert!(read > 0, "Connection closed mid request");
+            request.extend_from_slice(&buf[..read]);
+            let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+            let length: usize = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .map_or(0, |length| length.trim().parse().unwrap());
+            let body = end + 4;
+            if request.len() >= body + length {
+                return request[body..body + length].to_vec();
+            }
+        }
+    }
+
+    /// Signing service stand-in answering each PSBT with `sign(psbt)`, counting requests
+    async fn mock_signer(sign: fn(Psbt) -> Psbt) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/sign", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    let request: SignPsbtRequest =
+                        serde_json::from_slice(&read_body(&mut socket).await).unwrap();
+                    let signed = sign(Psbt::from_str(&request.psbt).unwrap());
+                    let body = serde_json::to_string(&SignPsbtResponse {
+                        psbt: signed.to_string(),
+                    })
+                    .unwrap();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+        (url, requests)
+    }
+
+    fn add_witness(mut psbt: Psbt) -> Psbt {
+        psbt.inputs[0].final_script_witness = Some(Witness::from_slice(&[[1u8; 64]]));
+        psbt
+    }
+
+    fn external_signer(url: Url, allowed_addresses: Vec<String>) -> ExternalSigner {
+        ExternalSigner::new(
+            ExternalSignerConfig {
+                url,
+                timeout: Duration::from_secs(5),
+                allowed_addresses,
+            },
+            Network::Regtest,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_external_signer_returns_the_signed_psbt() {
+        let (url, requests) = mock_signer(add_witness).await;
+        let (recipient, change, cold_storage) = (script(1), script(2), script(3));
+        let signer = external_signer(url, vec![address(&cold_storage)]);
+
+        let psbt = unsigned_psbt(&[recipient.clone(), change.clone(), cold_storage]);
+        let signed = signer
+            .sign(PsbtHandoff {
+                psbt: psbt.clone(),
+                payees: vec![recipient, change],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(signed.unsigned_tx, psbt.unsigned_tx);
+        assert!(signed.inputs[0].final_script_witness.is_some());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_psbt_paying_an_unexpected_address_is_refused() {
+        let (url, requests) = mock_signer(add_witness).await;
+        let (recipient, change, attacker) = (script(1), script(2), script(4));
+        let signer = external_signer(url, vec![]);
+
+        let error = signer
+            .sign(PsbtHandoff {
+                psbt: unsigned_psbt(&[recipient.clone(), change.clone(), attacker.clone()]),
+                payees: vec![recipient, change],
+            })
+            .await
+            .unwrap_err();
+
+        match error {
+            SignPsbtError::UnexpectedOutput { output } => assert_eq!(output, address(&attacker)),
+            other => panic!("Expected an unexpected output, got {other:?}"),
+        }
+        assert_eq!(
+            requests.load(Ordering::SeqCst),
+            0,
+            "The PSBT must not reach the signer"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signer_changing_the_transaction_is_rejected() {
+        let (url, _) = mock_signer(|mut psbt| {
+            psbt.unsigned_tx.output[0].value = Amount::from_sat(1);
+            add_witness(psbt)
+        })
+        .await;
+        let recipient = script(1);
+        let signer = external_signer(url, vec![]);
+
+        let error = signer
+            .sign(PsbtHandoff {
+                psbt: unsigned_psbt(&[recipient.clone()]),
+                payees: vec![recipient],
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, SignPsbtError::TransactionChanged),
+            "{error:?}"
+        );
+    }
+}
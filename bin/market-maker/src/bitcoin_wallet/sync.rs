@@ -0,0 +1,164 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use bdk_esplora::{esplora_client, EsploraAsyncExt};
+use bdk_wallet::{
+    bitcoin::{Address, Transaction},
+    rusqlite::Connection,
+    KeychainKind, PersistedWallet, Update,
+};
+use chrono::{DateTime, Utc};
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::{self, Instant};
+use tracing::{debug, error};
+
+use super::{BitcoinWalletError, PARALLEL_REQUESTS, STOP_GAP};
+
+/// Sync behaviour of the bitcoin wallet
+#[derive(Debug, Clone, Copy)]
+pub struct BitcoinWalletSyncConfig {
+    /// How often the background task runs a full scan of the wallet
+    pub sync_interval: Duration,
+    /// How long the fill path waits on an on-demand sync before falling back to cached state
+    pub fill_sync_timeout: Duration,
+    /// Cached wallet state older than this is not trusted to back a fill
+    pub max_staleness: Duration,
+}
+
+impl Default for BitcoinWalletSyncConfig {
+    fn default() -> Self {
+        Self {
+            sync_interval: Duration::from_secs(30),
+            fill_sync_timeout: Duration::from_secs(3),
+            max_staleness: Duration::from_secs(120),
+        }
+    }
+}
+
+/// Keeps the persisted wallet in step with the chain and tracks when that last happened
+pub struct WalletSyncer {
+    wallet: Arc<Mutex<PersistedWallet<Connection>>>,
+    connection: Arc<Mutex<Connection>>,
+    esplora_client: Arc<esplora_client::AsyncClient>,
+    last_synced_at: RwLock<Option<DateTime<Utc>>>,
+}
+
+impl WalletSyncer {
+    pub fn new(
+        wallet: Arc<Mutex<PersistedWallet<Connection>>>,
+        connection: Arc<Mutex<Connection>>,
+        esplora_client: Arc<esplora_client::AsyncClient>,
+    ) -> Self {
+        Self {
+            wallet,
+            connection,
+            esplora_client,
+            last_synced_at: RwLock::new(None),
+        }
+    }
+
+    /// Incremental sync of the already revealed script pubkeys.
+    ///
+    /// Much cheaper than a full scan, which makes it suitable for the fill path.
+    pub async fn sync(&self) -> Result<(), BitcoinWalletError> {
+        let start = Instant::now();
+        // Only hold the wallet lock while building the request, not during network IO
+        let request = self
+            .wallet
+            .lock()
+            .await
+            .start_sync_with_revealed_spks()
+            .build();
+        let update = self
+            .esplora_client
+            .sync(request, PARALLEL_REQUESTS)
+            .await
+            .map_err(|e| BitcoinWalletError::SyncWallet { source: e })?;
+        self.apply(update).await?;
+        debug!("Incremental wallet sync completed in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    /// Full scan up to the stop gap, discovering any newly used addresses
+    pub async fn full_scan(&self) -> Result<(), BitcoinWalletError> {
+        let start = Instant::now();
+        let request = self.wallet.lock().await.start_full_scan().build();
+        let update = self
+            .esplora_client
+            .full_scan(request, STOP_GAP, PARALLEL_REQUESTS)
+            .await
+            .map_err(|e| BitcoinWalletError::SyncWallet { source: e })?;
+        self.apply(update).await?;
+        debug!("Full wallet scan completed in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    /// Insert a transaction we just broadcast as unconfirmed.
+    ///
+    /// Its inputs are marked spent and its change becomes available immediately,
+    /// without waiting for esplora to index it, so the next payout chains onto it.
+    pub async fn record_broadcast(&self, tx: Transaction) -> Result<(), BitcoinWalletError> {
+        let mut wallet = self.wallet.lock().await;
+        wallet.apply_unconfirmed_txs([(tx, Utc::now().timestamp() as u64)]);
+
+        let mut conn = self.connection.lock().await;
+        wallet
+            .persist(&mut conn)
+            .map_err(|e| BitcoinWalletError::PersistWallet { source: e })?;
+        Ok(())
+    }
+
+    /// Reveal the next external address and persist it before it is handed out
+    pub async fn reveal_next_address(&self) -> Result<Address, BitcoinWalletError> {
+        let mut wallet = self.wallet.lock().await;
+        let address = wallet.reveal_next_address(KeychainKind::External).address;
+
+        let mut conn = self.connection.lock().await;
+        wallet
+            .persist(&mut conn)
+            .map_err(|e| BitcoinWalletError::PersistWallet { source: e })?;
+        Ok(address)
+    }
+
+    pub async fn last_synced_at(&self) -> Option<DateTime<Utc>> {
+        *self.last_synced_at.read().await
+    }
+
+    /// Whether the cached wallet state is older than `max_staleness` (or was never synced)
+    pub async fn is_stale(&self, max_staleness: Duration) -> bool {
+        match self.last_synced_at().await {
+            Some(last_synced_at) => Utc::now()
+                .signed_duration_since(last_synced_at)
+                .to_std()
+                .is_ok_and(|age| age > max_staleness),
+            None => true,
+        }
+    }
+
+    pub async fn run_background_sync(&self, sync_interval: Duration) {
+        let mut interval = time::interval(sync_interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.full_scan().await {
+                error!("Background wallet sync failed: {}", e);
+            }
+        }
+    }
+
+    async fn apply(&self, update: impl Into<Update>) -> Result<(), BitcoinWalletError> {
+        let mut wallet = self.wallet.lock().await;
+        wallet
+            .apply_update(update)
+            .map_err(|_| BitcoinWalletError::ApplyUpdate)?;
+
+        let mut conn = self.connection.lock().await;
+        wallet
+            .persist(&mut conn)
+            .map_err(|e| BitcoinWalletError::PersistWallet { source: e })?;
+
+        *self.last_synced_at.write().await = Some(Utc::now());
+        Ok(())
+    }
+}
@@ -1,23 +1,34 @@
 use std::sync::Arc;
-use std::time::Duration;
 
-use bdk_esplora::{esplora_client, EsploraAsyncExt};
+use alloy::primitives::U256;
+use bdk_esplora::esplora_client;
 use bdk_wallet::{
-    bitcoin::{self, Address, Amount, ScriptBuf},
+    bitcoin::{self, Address, Amount, FeeRate, OutPoint, Psbt, ScriptBuf},
+    coin_selection::{CoinSelectionAlgorithm, LargestFirstCoinSelection, OldestFirstCoinSelection},
+    error::CreateTxError,
     signer::SignOptions,
-    KeychainKind, PersistedWallet,
+    KeychainKind, PersistedWallet, TxBuilder,
 };
-use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_models::{ChainType, Lot};
+use otc_chains::{bitcoin::mm_nonce_script, traits::MarketMakerPaymentValidation};
+use otc_models::{ChainType, FillUsage, Lot, TxHash};
 use snafu::Snafu;
-use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio::task::JoinSet;
 use tokio::time::Instant;
-use tracing::{error, info};
-
-use super::{BitcoinWalletError, PARALLEL_REQUESTS, STOP_GAP};
-
-const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+use tracing::{debug, error, info, warn};
+
+use super::{
+    coin_selection::{
+        fill_vbytes, CoinSelectionConfig, CoinSelectionStrategy, ConsolidationConfig,
+        P2WPKH_INPUT_VBYTES,
+    },
+    fee_rate_for_target,
+    signer::{PsbtHandoff, SignPsbtError, Signer},
+    sync::WalletSyncer,
+    utxos::classify_utxos,
+    BitcoinWalletError,
+};
+use crate::wallet::{FillPreparation, PreparedFeeRate, TransactionResult};
 
 #[derive(Debug, Snafu)]
 pub enum TransactionBroadcasterError {
@@ -42,51 +53,142 @@ pub enum TransactionBroadcasterError {
     #[snafu(display("Insufficient balance"))]
     InsufficientBalance,
 
+    #[snafu(display("Paying this needs more than {} inputs", max_inputs))]
+    TooManyInputs { max_inputs: usize },
+
     #[snafu(display("Failed to parse address: {}", reason))]
     ParseAddress { reason: String },
+
+    #[snafu(display("Can't replace transaction {}: {}", txid, reason))]
+    ReplaceTransaction { txid: bitcoin::Txid, reason: String },
 }
 
 pub type Result<T, E = TransactionBroadcasterError> = std::result::Result<T, E>;
 
+/// Confirmation target of the fee rate consolidations are checked against
+const CONSOLIDATION_TARGET_BLOCKS: u16 = 144;
+
+/// Most UTXOs swept by one consolidation, keeping it well under standard size
+const MAX_CONSOLIDATION_INPUTS: usize = 200;
+
 pub struct TransactionRequest {
     pub lot: Lot,
     pub to_address: String,
     pub mm_payment_validation: Option<MarketMakerPaymentValidation>,
-    pub response_tx: oneshot::Sender<Result<String>>,
+    pub preparation: Option<FillPreparation>,
+    pub response_tx: oneshot::Sender<Result<TransactionResult>>,
+}
+
+/// Sweep `utxos` back into the wallet at `sat_per_vb`
+struct ConsolidationRequest {
+    utxos: Vec<OutPoint>,
+    sat_per_vb: u64,
+    response_tx: oneshot::Sender<Result<TransactionResult>>,
+}
+
+/// Pay again what transaction `txid` paid, which has to pay `lot` to `to_address`
+struct ReplacementRequest {
+    txid: bitcoin::Txid,
+    lot: Lot,
+    to_address: String,
+    response_tx: oneshot::Sender<Result<TransactionResult>>,
+}
+
+/// Fills and consolidations share one queue so they never pick the same inputs
+enum BroadcastRequest {
+    Payment(TransactionRequest),
+    Consolidation(ConsolidationRequest),
+    Replacement(ReplacementRequest),
 }
 
 pub struct BitcoinTransactionBroadcaster {
-    request_tx: mpsc::UnboundedSender<TransactionRequest>,
+    request_tx: mpsc::UnboundedSender<BroadcastRequest>,
 }
 
 impl BitcoinTransactionBroadcaster {
     pub fn new(
         wallet: Arc<Mutex<PersistedWallet<bdk_wallet::rusqlite::Connection>>>,
-        connection: Arc<Mutex<bdk_wallet::rusqlite::Connection>>,
+        signer: Arc<dyn Signer>,
+        syncer: Arc<WalletSyncer>,
         esplora_client: Arc<esplora_client::AsyncClient>,
         network: bitcoin::Network,
+        max_unconfirmed_chain_depth: usize,
+        coin_selection: CoinSelectionConfig,
         join_set: &mut JoinSet<crate::Result<()>>,
     ) -> Self {
-        let (request_tx, mut request_rx) = mpsc::unbounded_channel::<TransactionRequest>();
-        let last_sync = Arc::new(RwLock::new(Instant::now() - SYNC_INTERVAL));
+        let (request_tx, mut request_rx) = mpsc::unbounded_channel::<BroadcastRequest>();
+
+        if let Some(consolidation) = coin_selection.consolidation {
+            join_set.spawn({
+                let wallet = wallet.clone();
+                let esplora_client = esplora_client.clone();
+                let request_tx = request_tx.clone();
+                async move {
+                    run_consolidation(
+                        &wallet,
+                        &esplora_client,
+                        &request_tx,
+                        max_unconfirmed_chain_depth,
+                        consolidation,
+                    )
+                    .await;
+                    Ok(())
+                }
+            });
+        }
 
         join_set.spawn(async move {
             info!("Bitcoin transaction broadcaster started");
 
             while let Some(request) = request_rx.recv().await {
-                let result = process_transaction(
-                    &wallet,
-                    &connection,
-                    &esplora_client,
-                    network,
-                    &last_sync,
-                    request.lot,
-                    request.to_address,
-                    request.mm_payment_validation,
-                )
-                .await;
-
-                if let Err(e) = request.response_tx.send(result) {
+                let (result, response_tx) = match request {
+                    BroadcastRequest::Payment(request) => (
+                        process_transaction(
+                            &wallet,
+                            signer.as_ref(),
+                            &syncer,
+                            &esplora_client,
+                            network,
+                            max_unconfirmed_chain_depth,
+                            &coin_selection,
+                            request.lot,
+                            request.to_address,
+                            request.mm_payment_validation,
+                            request.preparation,
+                        )
+                        .await,
+                        request.response_tx,
+                    ),
+                    BroadcastRequest::Consolidation(request) => (
+                        process_consolidation(
+                            &wallet,
+                            signer.as_ref(),
+                            &syncer,
+                            &esplora_client,
+                            max_unconfirmed_chain_depth,
+                            &request.utxos,
+                            request.sat_per_vb,
+                        )
+                        .await,
+                        request.response_tx,
+                    ),
+                    BroadcastRequest::Replacement(request) => (
+                        process_replacement(
+                            &wallet,
+                            signer.as_ref(),
+                            &syncer,
+                            &esplora_client,
+                            network,
+                            request.txid,
+                            &request.lot,
+                            &request.to_address,
+                        )
+                        .await,
+                        request.response_tx,
+                    ),
+                };
+
+                if let Err(e) = response_tx.send(result) {
                     error!("Failed to send transaction response: {:?}", e);
                 }
             }
@@ -103,18 +205,46 @@ impl BitcoinTransactionBroadcaster {
         lot: Lot,
         to_address: String,
         mm_payment_validation: Option<MarketMakerPaymentValidation>,
-    ) -> Result<String> {
+        preparation: Option<FillPreparation>,
+    ) -> Result<TransactionResult> {
         let (response_tx, response_rx) = oneshot::channel();
 
         let request = TransactionRequest {
             lot,
             to_address,
             mm_payment_validation,
+            preparation,
             response_tx,
         };
 
         self.request_tx
-            .send(request)
+            .send(BroadcastRequest::Payment(request))
+            .map_err(|_| TransactionBroadcasterError::BroadcasterStopped)?;
+
+        response_rx
+            .await
+            .map_err(|_| TransactionBroadcasterError::BroadcasterStopped)?
+    }
+
+    /// Pay `lot` to `to_address` again in place of `txid`, spending the same
+    /// inputs so that only one of the two can confirm
+    pub async fn broadcast_replacement(
+        &self,
+        txid: bitcoin::Txid,
+        lot: Lot,
+        to_address: String,
+    ) -> Result<TransactionResult> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        let request = ReplacementRequest {
+            txid,
+            lot,
+            to_address,
+            response_tx,
+        };
+
+        self.request_tx
+            .send(BroadcastRequest::Replacement(request))
             .map_err(|_| TransactionBroadcasterError::BroadcasterStopped)?;
 
         response_rx
@@ -125,14 +255,17 @@ impl BitcoinTransactionBroadcaster {
 
 async fn process_transaction(
     wallet: &Arc<Mutex<PersistedWallet<bdk_wallet::rusqlite::Connection>>>,
-    connection: &Arc<Mutex<bdk_wallet::rusqlite::Connection>>,
+    signer: &dyn Signer,
+    syncer: &WalletSyncer,
     esplora_client: &Arc<esplora_client::AsyncClient>,
     network: bitcoin::Network,
-    last_sync: &Arc<RwLock<Instant>>,
+    max_unconfirmed_chain_depth: usize,
+    coin_selection: &CoinSelectionConfig,
     lot: Lot,
     to_address: String,
     mm_payment_validation: Option<MarketMakerPaymentValidation>,
-) -> Result<String> {
+    preparation: Option<FillPreparation>,
+) -> Result<TransactionResult> {
     let start_time = Instant::now();
 
     info!(
@@ -153,149 +286,546 @@ async fn process_transaction(
             ),
         })?;
 
-    sync_wallet(wallet, connection, esplora_client, last_sync).await?;
+    // Inputs set aside when the quote was selected are still ours to spend as long
+    // as nothing spent them since, in which case the sync can be skipped
+    let prepared_utxos = preparation
+        .as_ref()
+        .map(|preparation| preparation.utxos.as_slice())
+        .unwrap_or_default();
+    let prepared_utxos_spendable = !prepared_utxos.is_empty()
+        && all_spendable(
+            &classify_utxos(&*wallet.lock().await, max_unconfirmed_chain_depth).spendable,
+            prepared_utxos,
+        );
+    if prepared_utxos_spendable {
+        info!("Prepared inputs are unspent, skipping wallet sync");
+    } else {
+        syncer
+            .sync()
+            .await
+            .map_err(|e| TransactionBroadcasterError::SyncWallet { source: e })?;
+    }
+    let required_utxos: &[OutPoint] = if prepared_utxos_spendable {
+        prepared_utxos
+    } else {
+        &[]
+    };
 
     // Lock wallet for transaction creation
     let mut wallet_guard = wallet.lock().await;
 
-    // Check balance
-    let balance = wallet_guard.balance();
+    // Own unconfirmed change is spendable, foreign unconfirmed receipts are not
+    let utxos = classify_utxos(&wallet_guard, max_unconfirmed_chain_depth);
     let amount_sats = lot.amount.to::<u64>();
     let amount = Amount::from_sat(amount_sats);
-    info!("balance: {:?}", balance);
+    info!(
+        "spendable: {} ({} utxos, {} excluded)",
+        utxos.total(),
+        utxos.spendable.len(),
+        utxos.unspendable.len()
+    );
 
-    if balance.total() < amount {
+    if utxos.total() < amount {
         return Err(TransactionBroadcasterError::InsufficientBalance);
     }
 
-    // Build transaction
-    let mut tx_builder = wallet_guard.build_tx();
-    tx_builder.add_recipient(address.script_pubkey(), amount);
+    // Only the candidates of the configured strategy may be spent, which caps the
+    // inputs of the fill
+    let candidates = coin_selection.candidates(utxos.spendable.clone(), required_utxos);
+    let candidates_total: Amount = candidates.iter().map(|utxo| utxo.txout.value).sum();
+    if candidates_total < amount {
+        return Err(TransactionBroadcasterError::TooManyInputs {
+            max_inputs: coin_selection.max_inputs,
+        });
+    }
+    let mut unspendable = utxos.unspendable;
+    unspendable.extend(
+        utxos
+            .spendable
+            .iter()
+            .map(|utxo| utxo.outpoint)
+            .filter(|outpoint| !candidates.iter().any(|utxo| utxo.outpoint == *outpoint)),
+    );
 
+    let mut recipients = vec![(address.script_pubkey(), amount)];
     // Add OP_RETURN output with nonce if provided
     if let Some(mm_payment_validation) = mm_payment_validation {
         let nonce = mm_payment_validation.embedded_nonce;
-        let op_return_script = create_op_return_script(&nonce);
-        tx_builder.add_recipient(op_return_script, Amount::ZERO);
+        recipients.push((mm_nonce_script(&nonce), Amount::ZERO));
         // Now handle fees
         let fee_amount = mm_payment_validation.fee_amount;
         let fee_address =
             Address::from_str(&otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Bitcoin])
                 .unwrap()
                 .assume_checked();
-        tx_builder.add_recipient(
+        recipients.push((
             fee_address.script_pubkey(),
             Amount::from_sat(fee_amount.to::<u64>()),
-        );
+        ));
     }
 
-    // Create and sign the transaction
+    let fee_rate = match preparation.as_ref().and_then(|p| p.fee_rate) {
+        Some(PreparedFeeRate::Bitcoin { sat_per_vb }) => FeeRate::from_sat_per_vb(sat_per_vb),
+        _ => None,
+    };
+
+    // Build the transaction, it is signed once the wallet is unlocked
     let build_start = Instant::now();
-    let mut psbt =
-        tx_builder
-            .finish()
-            .map_err(|e| TransactionBroadcasterError::BuildTransaction {
-                source: BitcoinWalletError::BuildTransaction { source: e },
-            })?;
+    let change = wallet_guard
+        .next_unused_address(KeychainKind::Internal)
+        .script_pubkey();
+    let payees = recipients
+        .iter()
+        .map(|(script_pubkey, _)| script_pubkey.clone())
+        .chain([change.clone()])
+        .collect();
+    let fill = FillSpec {
+        unspendable,
+        required_utxos,
+        recipients,
+        change,
+        fee_rate,
+    };
+    let psbt = match coin_selection.strategy {
+        CoinSelectionStrategy::LargestFirst => fill.build(
+            wallet_guard
+                .build_tx()
+                .coin_selection(LargestFirstCoinSelection),
+        ),
+        CoinSelectionStrategy::OldestFirst => fill.build(
+            wallet_guard
+                .build_tx()
+                .coin_selection(OldestFirstCoinSelection),
+        ),
+        CoinSelectionStrategy::BranchAndBound => fill.build(wallet_guard.build_tx()),
+    }
+    .map_err(|e| TransactionBroadcasterError::BuildTransaction {
+        source: BitcoinWalletError::BuildTransaction { source: e },
+    })?;
     info!("Transaction built in {:?}", build_start.elapsed());
 
+    let inputs = psbt.unsigned_tx.input.len();
+    info!(
+        "Fill spends {} inputs (~{} vB), fee {:?}",
+        inputs,
+        fill_vbytes(inputs),
+        psbt.fee().ok()
+    );
+
+    drop(wallet_guard);
+
+    let result = sign_and_broadcast(
+        wallet,
+        signer,
+        syncer,
+        esplora_client,
+        PsbtHandoff { psbt, payees },
+    )
+    .await?;
+
+    let total_duration = start_time.elapsed();
+    info!(
+        "Bitcoin transaction created and broadcast successfully: {} (total time: {:?})",
+        result.tx_hash, total_duration
+    );
+
+    Ok(result)
+}
+
+/// Everything about a fill transaction besides its coin selection algorithm
+struct FillSpec<'a> {
+    unspendable: Vec<OutPoint>,
+    required_utxos: &'a [OutPoint],
+    recipients: Vec<(ScriptBuf, Amount)>,
+    /// Where the change goes, an internal keychain address unless the wallet has none
+    change: ScriptBuf,
+    fee_rate: Option<FeeRate>,
+}
+
+impl FillSpec<'_> {
+    fn build<Cs: CoinSelectionAlgorithm>(
+        self,
+        mut tx_builder: TxBuilder<'_, Cs>,
+    ) -> std::result::Result<Psbt, CreateTxError> {
+        tx_builder.unspendable(self.unspendable);
+        // More inputs are still added if the prepared ones fall short
+        if let Err(e) = tx_builder.add_utxos(self.required_utxos) {
+            warn!("Failed to use prepared inputs: {}", e);
+        }
+        for (script_pubkey, amount) in self.recipients {
+            tx_builder.add_recipient(script_pubkey, amount);
+        }
+        tx_builder.drain_to(self.change);
+        if let Some(fee_rate) = self.fee_rate {
+            tx_builder.fee_rate(fee_rate);
+        }
+        tx_builder.finish()
+    }
+}
+
+/// Sweep `outpoints` that are still spendable into one output of our own
+async fn process_consolidation(
+    wallet: &Arc<Mutex<PersistedWallet<bdk_wallet::rusqlite::Connection>>>,
+    signer: &dyn Signer,
+    syncer: &WalletSyncer,
+    esplora_client: &Arc<esplora_client::AsyncClient>,
+    max_unconfirmed_chain_depth: usize,
+    outpoints: &[OutPoint],
+    sat_per_vb: u64,
+) -> Result<TransactionResult> {
+    syncer
+        .sync()
+        .await
+        .map_err(|e| TransactionBroadcasterError::SyncWallet { source: e })?;
+
+    // A fresh address, the first unused one may be where the wallet is being funded
+    let destination = syncer
+        .reveal_next_address()
+        .await
+        .map_err(|e| TransactionBroadcasterError::BuildTransaction { source: e })?
+        .script_pubkey();
+
+    let mut wallet_guard = wallet.lock().await;
+    let spendable = classify_utxos(&wallet_guard, max_unconfirmed_chain_depth).spendable;
+    let outpoints: Vec<OutPoint> = outpoints
+        .iter()
+        .copied()
+        .filter(|outpoint| spendable.iter().any(|utxo| utxo.outpoint == *outpoint))
+        .collect();
+    if outpoints.len() < 2 {
+        return Err(TransactionBroadcasterError::InsufficientBalance);
+    }
+
+    let mut tx_builder = wallet_guard.build_tx();
+    tx_builder.add_utxos(&outpoints).map_err(|e| {
+        TransactionBroadcasterError::BuildTransaction {
+            source: BitcoinWalletError::AddUtxo { source: e },
+        }
+    })?;
+    tx_builder.manually_selected_only();
+    tx_builder.drain_to(destination.clone());
+    if let Some(fee_rate) = FeeRate::from_sat_per_vb(sat_per_vb) {
+        tx_builder.fee_rate(fee_rate);
+    }
+    let psbt = tx_builder
+        .finish()
+        .map_err(|e| TransactionBroadcasterError::BuildTransaction {
+            source: BitcoinWalletError::BuildTransaction { source: e },
+        })?;
+
+    info!(
+        "Consolidating {} UTXOs at {} sat/vB, fee {:?}",
+        outpoints.len(),
+        sat_per_vb,
+        psbt.fee().ok()
+    );
+    drop(wallet_guard);
+
+    sign_and_broadcast(
+        wallet,
+        signer,
+        syncer,
+        esplora_client,
+        PsbtHandoff {
+            psbt,
+            payees: vec![destination],
+        },
+    )
+    .await
+}
+
+/// Pay what `txid` paid again at a higher fee rate, from its own inputs. While
+/// the wallet still counts `txid` as unconfirmed it is bumped, once a sync found
+/// it gone its inputs are spendable again and are spent on the same outputs
+async fn process_replacement(
+    wallet: &Arc<Mutex<PersistedWallet<bdk_wallet::rusqlite::Connection>>>,
+    signer: &dyn Signer,
+    syncer: &WalletSyncer,
+    esplora_client: &Arc<esplora_client::AsyncClient>,
+    network: bitcoin::Network,
+    txid: bitcoin::Txid,
+    lot: &Lot,
+    to_address: &str,
+) -> Result<TransactionResult> {
+    let replace_failed =
+        |reason: String| TransactionBroadcasterError::ReplaceTransaction { txid, reason };
+    let address = Address::from_str(to_address)
+        .map_err(|e| TransactionBroadcasterError::ParseAddress {
+            reason: e.to_string(),
+        })?
+        .require_network(network)
+        .map_err(|_| TransactionBroadcasterError::ParseAddress {
+            reason: format!(
+                "Address {} is not valid for network {:?}",
+                to_address, network
+            ),
+        })?;
+    let amount = Amount::from_sat(
+        u64::try_from(lot.amount).map_err(|_| TransactionBroadcasterError::InvalidCurrency)?,
+    );
+
+    syncer
+        .sync()
+        .await
+        .map_err(|e| TransactionBroadcasterError::SyncWallet { source: e })?;
+    let estimate = match esplora_client.get_fee_estimates().await {
+        Ok(estimates) => fee_rate_for_target(&estimates, super::FEE_TARGET_BLOCKS),
+        Err(e) => {
+            warn!("Failed to fetch fee estimates: {}", e);
+            None
+        }
+    };
+
+    let mut wallet_guard = wallet.lock().await;
+    let original = wallet_guard
+        .tx_graph()
+        .get_tx(txid)
+        .ok_or_else(|| replace_failed("not a transaction of this wallet".to_string()))?;
+    if !original
+        .output
+        .iter()
+        .any(|output| output.script_pubkey == address.script_pubkey() && output.value == amount)
+    {
+        return Err(replace_failed(format!(
+            "it doesn't pay {amount} to {to_address}"
+        )));
+    }
+
+    // Above the original's rate, as a replacement has to pay more to relay
+    let original_rate = wallet_guard
+        .calculate_fee_rate(&original)
+        .map_err(|e| replace_failed(e.to_string()))?;
+    let sat_per_vb = estimate
+        .unwrap_or_default()
+        .max(original_rate.to_sat_per_vb_ceil() + 1);
+    let fee_rate = FeeRate::from_sat_per_vb(sat_per_vb)
+        .ok_or_else(|| replace_failed(format!("fee rate of {sat_per_vb} sat/vB overflows")))?;
+
+    let psbt = if wallet_guard.get_tx(txid).is_some() {
+        let mut tx_builder = wallet_guard
+            .build_fee_bump(txid)
+            .map_err(|e| replace_failed(e.to_string()))?;
+        tx_builder.fee_rate(fee_rate);
+        tx_builder.finish()
+    } else {
+        let outpoints: Vec<OutPoint> = original
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect();
+        // Everything but the original's change, the nonce and fee outputs included
+        let recipients: Vec<(ScriptBuf, Amount)> = original
+            .output
+            .iter()
+            .filter(|output| !wallet_guard.is_mine(output.script_pubkey.clone()))
+            .map(|output| (output.script_pubkey.clone(), output.value))
+            .collect();
+        let change = wallet_guard
+            .next_unused_address(KeychainKind::Internal)
+            .script_pubkey();
+        let mut tx_builder = wallet_guard.build_tx();
+        tx_builder
+            .add_utxos(&outpoints)
+            .map_err(|e| replace_failed(e.to_string()))?;
+        tx_builder.manually_selected_only();
+        for (script_pubkey, amount) in recipients {
+            tx_builder.add_recipient(script_pubkey, amount);
+        }
+        tx_builder.drain_to(change);
+        tx_builder.fee_rate(fee_rate);
+        tx_builder.finish()
+    }
+    .map_err(|e| TransactionBroadcasterError::BuildTransaction {
+        source: BitcoinWalletError::BuildTransaction { source: e },
+    })?;
+
+    info!(
+        "Replacing transaction {} at {} sat/vB, fee {:?}",
+        txid,
+        sat_per_vb,
+        psbt.fee().ok()
+    );
+    let payees = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|output| output.script_pubkey.clone())
+        .collect();
+    drop(wallet_guard);
+
+    sign_and_broadcast(
+        wallet,
+        signer,
+        syncer,
+        esplora_client,
+        PsbtHandoff { psbt, payees },
+    )
+    .await
+}
+
+/// Hand `handoff` to the signer and broadcast the transaction it signs. The wallet
+/// stays unlocked while the signer works, it may be a remote service.
+async fn sign_and_broadcast(
+    wallet: &Arc<Mutex<PersistedWallet<bdk_wallet::rusqlite::Connection>>>,
+    signer: &dyn Signer,
+    syncer: &WalletSyncer,
+    esplora_client: &Arc<esplora_client::AsyncClient>,
+    handoff: PsbtHandoff,
+) -> Result<TransactionResult> {
+    let unsigned_tx = handoff.psbt.unsigned_tx.clone();
+    let signed = signer.sign(handoff).await;
+
+    let mut wallet_guard = wallet.lock().await;
+    let mut psbt = match signed {
+        Ok(psbt) => psbt,
+        Err(e) => {
+            // Hand the change address back to the next payout
+            wallet_guard.cancel_tx(&unsigned_tx);
+            return Err(TransactionBroadcasterError::SignTransaction {
+                source: BitcoinWalletError::SignTransaction { source: e },
+            });
+        }
+    };
+
     let finalized = wallet_guard
-        .sign(&mut psbt, SignOptions::default())
+        .finalize_psbt(&mut psbt, SignOptions::default())
         .map_err(|e| TransactionBroadcasterError::SignTransaction {
-            source: BitcoinWalletError::SignTransaction { source: e },
+            source: BitcoinWalletError::SignTransaction {
+                source: SignPsbtError::Descriptor { source: e },
+            },
         })?;
 
     if !finalized {
-        return Err(TransactionBroadcasterError::BuildTransaction {
-            source: BitcoinWalletError::BuildTransaction {
-                source: bdk_wallet::error::CreateTxError::NoRecipients,
+        wallet_guard.cancel_tx(&unsigned_tx);
+        return Err(TransactionBroadcasterError::SignTransaction {
+            source: BitcoinWalletError::SignTransaction {
+                source: SignPsbtError::Incomplete,
             },
         });
     }
 
+    let fee = psbt.fee().ok();
+
     // Extract transaction
     let tx = psbt
         .extract_tx()
         .map_err(|e| TransactionBroadcasterError::BuildTransaction {
             source: BitcoinWalletError::ExtractTransaction { source: e },
         })?;
+    let vsize = tx.vsize() as u64;
+    let usage = fee.map(|fee| FillUsage::Bitcoin {
+        sat_per_vb: fee.to_sat() as f64 / vsize as f64,
+        vsize,
+        inputs: tx.input.len() as u64,
+        outputs: tx.output.len() as u64,
+    });
 
     // Release wallet lock before broadcasting
     drop(wallet_guard);
 
     // Broadcast the transaction
     let broadcast_start = Instant::now();
-    esplora_client.broadcast(&tx).await.map_err(|e| {
+    if let Err(e) = esplora_client.broadcast(&tx).await {
         // If broadcast fails, cancel the transaction
-        let mut wallet_guard = wallet.blocking_lock();
-        wallet_guard.cancel_tx(&tx);
-        TransactionBroadcasterError::BroadcastTransaction {
+        wallet.lock().await.cancel_tx(&tx);
+        return Err(TransactionBroadcasterError::BroadcastTransaction {
             source: BitcoinWalletError::BroadcastTransaction { source: e },
-        }
-    })?;
+        });
+    }
     info!("Transaction broadcast in {:?}", broadcast_start.elapsed());
 
-    let txid = tx.compute_txid().to_string();
-    let total_duration = start_time.elapsed();
-    info!(
-        "Bitcoin transaction created and broadcast successfully: {} (total time: {:?})",
-        txid, total_duration
-    );
+    // Make the change spendable by the next payout before esplora catches up.
+    // The payment is already out, so a failure here must not be reported as a failed payment.
+    let txid = TxHash::parse(ChainType::Bitcoin, &tx.compute_txid().to_string())
+        .expect("txids display as 64 hex digits");
+    let raw = serde_json::Value::String(bitcoin::consensus::encode::serialize_hex(&tx));
+    if let Err(e) = syncer.record_broadcast(tx).await {
+        warn!("Failed to record broadcast transaction {}: {}", txid, e);
+    }
 
-    Ok(txid)
+    Ok(TransactionResult {
+        tx_hash: txid,
+        fee: fee.map(|fee| U256::from(fee.to_sat())),
+        confirmations: 0,
+        raw: Some(raw),
+        usage,
+        nonce: None,
+    })
 }
 
-async fn sync_wallet(
+/// Periodically sweep small confirmed UTXOs into one while fees are low
+async fn run_consolidation(
     wallet: &Arc<Mutex<PersistedWallet<bdk_wallet::rusqlite::Connection>>>,
-    connection: &Arc<Mutex<bdk_wallet::rusqlite::Connection>>,
-    esplora_client: &Arc<esplora_client::AsyncClient>,
-    last_sync: &Arc<RwLock<Instant>>,
-) -> Result<()> {
-    let sync_start = Instant::now();
-    info!("Starting wallet sync");
-
-    let mut wallet_guard = wallet.lock().await;
-    let address = wallet_guard.next_unused_address(KeychainKind::External);
-    info!("next unused address: {:?}", address);
-    let mut conn = connection.lock().await;
-    wallet_guard.persist(&mut conn).unwrap();
-    drop(conn);
-
-    let request = wallet_guard.start_full_scan().build();
-    let update = esplora_client
-        .full_scan(request, STOP_GAP, PARALLEL_REQUESTS)
-        .await
-        .map_err(|e| TransactionBroadcasterError::SyncWallet {
-            source: BitcoinWalletError::SyncWallet { source: e },
-        })?;
-
-    info!("update: {:?}", update);
-
-    wallet_guard
-        .apply_update(update)
-        .map_err(|_| TransactionBroadcasterError::SyncWallet {
-            source: BitcoinWalletError::ApplyUpdate,
-        })?;
-
-    let mut conn = connection.lock().await;
-    wallet_guard
-        .persist(&mut conn)
-        .map_err(|e| TransactionBroadcasterError::SyncWallet {
-            source: BitcoinWalletError::PersistWallet { source: e },
-        })?;
+    esplora_client: &esplora_client::AsyncClient,
+    request_tx: &mpsc::UnboundedSender<BroadcastRequest>,
+    max_unconfirmed_chain_depth: usize,
+    config: ConsolidationConfig,
+) {
+    let mut interval = tokio::time::interval(config.check_interval);
+    loop {
+        interval.tick().await;
+
+        let sat_per_vb = match esplora_client.get_fee_estimates().await {
+            // No estimate means an empty mempool
+            Ok(estimates) => {
+                fee_rate_for_target(&estimates, CONSOLIDATION_TARGET_BLOCKS).unwrap_or(1)
+            }
+            Err(e) => {
+                warn!("Failed to fetch fee estimates for consolidation: {}", e);
+                continue;
+            }
+        };
+        if sat_per_vb > config.max_fee_rate_sat_per_vb {
+            debug!(
+                "Skipping consolidation, fee rate {} sat/vB is above {}",
+                sat_per_vb, config.max_fee_rate_sat_per_vb
+            );
+            continue;
+        }
 
-    // Update last sync time
-    *last_sync.write().await = Instant::now();
+        // Inputs that cost more to spend than they hold aren't worth sweeping
+        let input_cost = P2WPKH_INPUT_VBYTES * sat_per_vb;
+        let utxos: Vec<OutPoint> =
+            classify_utxos(&*wallet.lock().await, max_unconfirmed_chain_depth)
+                .spendable
+                .into_iter()
+                .filter(|utxo| {
+                    let sats = utxo.txout.value.to_sat();
+                    utxo.chain_position.is_confirmed()
+                        && sats < config.small_utxo_sats
+                        && sats > input_cost
+                })
+                .map(|utxo| utxo.outpoint)
+                .take(MAX_CONSOLIDATION_INPUTS)
+                .collect();
+        if utxos.len() < config.min_utxos.max(2) {
+            continue;
+        }
 
-    info!("Wallet sync completed in {:?}", sync_start.elapsed());
-    Ok(())
+        let (response_tx, response_rx) = oneshot::channel();
+        let request = ConsolidationRequest {
+            utxos,
+            sat_per_vb,
+            response_tx,
+        };
+        if request_tx
+            .send(BroadcastRequest::Consolidation(request))
+            .is_err()
+        {
+            return;
+        }
+        match response_rx.await {
+            Ok(Ok(result)) => info!("Broadcast consolidation {}", result.tx_hash),
+            Ok(Err(e)) => warn!("Consolidation failed: {}", e),
+            Err(_) => return,
+        }
+    }
 }
 
-fn create_op_return_script(nonce: &[u8; 16]) -> ScriptBuf {
-    bitcoin::blockdata::script::Builder::new()
-        .push_opcode(bitcoin::opcodes::all::OP_RETURN)
-        .push_slice(nonce)
-        .into_script()
+fn all_spendable(spendable: &[bdk_wallet::LocalOutput], outpoints: &[OutPoint]) -> bool {
+    outpoints
+        .iter()
+        .all(|outpoint| spendable.iter().any(|utxo| utxo.outpoint == *outpoint))
 }
 
 use std::str::FromStr;
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use bdk_wallet::{
+    bitcoin::{Amount, OutPoint, Txid},
+    rusqlite::Connection,
+    LocalOutput, PersistedWallet,
+};
+
+/// Default cap on the chain of our own unconfirmed transactions.
+///
+/// Mempool policy allows 25 ancestors; staying far below that keeps our payouts
+/// relayable and leaves room for CPFP.
+pub const DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH: usize = 5;
+
+/// The wallet's UTXOs split into what a new payout may spend and what it must not
+#[derive(Debug, Default)]
+pub struct SpendableUtxos {
+    pub spendable: Vec<LocalOutput>,
+    /// Unconfirmed outputs from third parties, or own change too deep in an unconfirmed chain
+    pub unspendable: Vec<OutPoint>,
+}
+
+impl SpendableUtxos {
+    #[must_use]
+    pub fn total(&self) -> Amount {
+        self.spendable.iter().map(|utxo| utxo.txout.value).sum()
+    }
+}
+
+/// Classify the wallet's UTXOs for spending.
+///
+/// Confirmed outputs are always spendable. Unconfirmed outputs are only spendable
+/// if they are change from a transaction we created (every input was ours), and
+/// spending them wouldn't grow the unconfirmed chain past `max_unconfirmed_chain_depth`.
+/// Unconfirmed payments from anyone else can still be replaced or dropped, so they
+/// are never counted.
+pub fn classify_utxos(
+    wallet: &PersistedWallet<Connection>,
+    max_unconfirmed_chain_depth: usize,
+) -> SpendableUtxos {
+    let mut depths = HashMap::new();
+    let mut utxos = SpendableUtxos::default();
+
+    for utxo in wallet.list_unspent() {
+        if utxo.chain_position.is_confirmed() {
+            utxos.spendable.push(utxo);
+            continue;
+        }
+
+        let txid = utxo.outpoint.txid;
+        let own_change = is_own_transaction(wallet, txid);
+        let depth = unconfirmed_depth(wallet, txid, &mut depths);
+
+        // Spending this output creates a transaction one deeper than its parent
+        if own_change && depth < max_unconfirmed_chain_depth {
+            utxos.spendable.push(utxo);
+        } else {
+            utxos.unspendable.push(utxo.outpoint);
+        }
+    }
+
+    utxos
+}
+
+/// Whether every input of the transaction spends one of our own outputs
+fn is_own_transaction(wallet: &PersistedWallet<Connection>, txid: Txid) -> bool {
+    let Some(wallet_tx) = wallet.get_tx(txid) else {
+        return false;
+    };
+    let tx = &wallet_tx.tx_node.tx;
+
+    !tx.input.is_empty()
+        && tx.input.iter().all(|input| {
+            wallet
+                .tx_graph()
+                .get_txout(input.previous_output)
+                .is_some_and(|txout| wallet.is_mine(txout.script_pubkey.clone()))
+        })
+}
+
+/// Length of the unconfirmed chain ending at `txid` (0 once confirmed or unknown)
+fn unconfirmed_depth(
+    wallet: &PersistedWallet<Connection>,
+    txid: Txid,
+    depths: &mut HashMap<Txid, usize>,
+) -> usize {
+    if let Some(depth) = depths.get(&txid) {
+        return *depth;
+    }
+
+    let depth = match wallet.get_tx(txid) {
+        Some(wallet_tx) if !wallet_tx.chain_position.is_confirmed() => {
+            let parents: Vec<Txid> = wallet_tx
+                .tx_node
+                .tx
+                .input
+                .iter()
+                .map(|input| input.previous_output.txid)
+                .collect();
+            1 + parents
+                .into_iter()
+                .map(|parent| unconfirmed_depth(wallet, parent, depths))
+                .max()
+                .unwrap_or(0)
+        }
+        _ => 0,
+    };
+
+    depths.insert(txid, depth);
+    depth
+}
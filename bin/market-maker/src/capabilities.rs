@@ -0,0 +1,64 @@
+use otc_protocols::{
+    capabilities::{Capabilities, CAPABILITIES_PATH},
+    mm::{ensure_version_compatible, ProtocolError},
+};
+use snafu::prelude::*;
+use tracing::info;
+use url::Url;
+
+#[derive(Debug, Snafu)]
+pub enum CapabilitiesError {
+    #[snafu(display("URL parse error: {}", source))]
+    CapabilitiesUrl { source: url::ParseError },
+
+    #[snafu(display("Unsupported server URL scheme: {}", scheme))]
+    UnsupportedScheme { scheme: String },
+
+    #[snafu(display("Failed to fetch capabilities from {}: {}", url, source))]
+    Fetch { url: String, source: reqwest::Error },
+
+    #[snafu(display("Server speaks an incompatible MM protocol: {}", source))]
+    IncompatibleProtocol { source: ProtocolError },
+}
+
+/// Fetch the capability descriptor of the server behind a websocket URL and make
+/// sure we speak its MM protocol, logging the feature set we'll run with
+pub async fn negotiate(ws_url: &str) -> Result<Capabilities, CapabilitiesError> {
+    let url = capabilities_url(ws_url)?;
+
+    let capabilities: Capabilities = reqwest::get(url.as_str())
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context(FetchSnafu { url: url.as_str() })?
+        .json()
+        .await
+        .context(FetchSnafu { url: url.as_str() })?;
+
+    ensure_version_compatible(&capabilities.mm_protocol.version)
+        .context(IncompatibleProtocolSnafu)?;
+
+    info!("Negotiated capabilities with {}", capabilities.summary());
+    Ok(capabilities)
+}
+
+/// The capabilities endpoint lives on the same host as the websocket
+fn capabilities_url(ws_url: &str) -> Result<Url, CapabilitiesError> {
+    let mut url = Url::parse(ws_url).context(CapabilitiesUrlSnafu)?;
+    let scheme = match url.scheme() {
+        "ws" | "http" => "http",
+        "wss" | "https" => "https",
+        other => {
+            return UnsupportedSchemeSnafu {
+                scheme: other.to_string(),
+            }
+            .fail()
+        }
+    };
+    url.set_scheme(scheme)
+        .map_err(|()| CapabilitiesError::UnsupportedScheme {
+            scheme: scheme.to_string(),
+        })?;
+    url.set_path(CAPABILITIES_PATH);
+    url.set_query(None);
+    Ok(url)
+}
@@ -1,20 +1,318 @@
+use arc_swap::ArcSwap;
+use blockchain_utils::ProtocolFeeParams;
+use common::ReconnectOptions;
+use otc_models::Redacted;
+use otc_protocols::attestation::AttestationVerifier;
+use serde::Deserialize;
 use snafu::prelude::*;
+use std::{sync::Arc, time::Duration};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
 use uuid::Uuid;
 
+/// Longest wait between reconnect attempts to the OTC and RFQ servers
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Widest trade spread accepted without `--allow-extreme-spread`
+pub const MAX_TRADE_SPREAD_BPS: u64 = 1_000;
+
+/// Bounds of the fee safety multiplier. Below 1x fees are underquoted, past
+/// 10x it is far more likely a typo than a margin
+pub const MIN_FEE_SAFETY_MULTIPLIER: f64 = 1.0;
+pub const MAX_FEE_SAFETY_MULTIPLIER: f64 = 10.0;
+
 #[derive(Debug, Snafu)]
 pub enum ConfigError {
     #[snafu(display("Invalid URL: {}", url))]
     InvalidUrl { url: String },
+    #[snafu(display("At least one --rfq-ws-url is needed"))]
+    NoRfqServer,
     #[snafu(display("Invalid UUID: {}", uuid))]
     InvalidUuid { uuid: String, error: uuid::Error },
+    #[snafu(display(
+        "Protocol fee of {} bps (min {} sats) is below the {} bps (min {} sats) the OTC server requires",
+        configured.bps,
+        configured.min_sats,
+        required.bps,
+        required.min_sats
+    ))]
+    ProtocolFeeTooLow {
+        configured: ProtocolFeeParams,
+        required: ProtocolFeeParams,
+    },
+    #[snafu(display("Protocol fee of {} bps must be under 100%", bps))]
+    ProtocolFeeTooHigh { bps: u64 },
+    #[snafu(display(
+        "--expected-measurement and --attestation-root-of-trust must be set together"
+    ))]
+    IncompleteAttestation,
+    #[snafu(display("Simulation mode only runs on regtest, not {}", network))]
+    SimulationOutsideRegtest {
+        network: bdk_wallet::bitcoin::Network,
+    },
+    #[snafu(display("Simulated price of {} BTC/ETH must be positive", btc_per_eth))]
+    InvalidSimulatedPrice { btc_per_eth: f64 },
+    #[snafu(display("Trade spread of {} bps must be under 100%", bps))]
+    TradeSpreadTooHigh { bps: u64 },
+    #[snafu(display(
+        "Trade spread of {} bps is above {} bps, pass --allow-extreme-spread if that is intended",
+        bps,
+        MAX_TRADE_SPREAD_BPS
+    ))]
+    ExtremeTradeSpread { bps: u64 },
+    #[snafu(display(
+        "Fee safety multiplier {} must be from {} to {}",
+        multiplier,
+        MIN_FEE_SAFETY_MULTIPLIER,
+        MAX_FEE_SAFETY_MULTIPLIER
+    ))]
+    InvalidFeeSafetyMultiplier { multiplier: f64 },
+    #[snafu(display("Failed to read pricing file {}: {}", path, source))]
+    ReadPricingFile {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Invalid pricing file {}: {}", path, source))]
+    ParsePricingFile {
+        path: String,
+        source: serde_json::Error,
+    },
+}
+
+/// Fee params the MM pays on its fills. The OTC server rejects a payout whose fee
+/// is short of the protocol default, so only params at or above it are accepted
+pub fn validate_protocol_fee(
+    configured: ProtocolFeeParams,
+) -> Result<ProtocolFeeParams, ConfigError> {
+    let required = ProtocolFeeParams::DEFAULT;
+    ensure!(
+        configured.covers(&required),
+        ProtocolFeeTooLowSnafu {
+            configured,
+            required
+        }
+    );
+    ensure!(
+        configured.bps < 10_000,
+        ProtocolFeeTooHighSnafu {
+            bps: configured.bps
+        }
+    );
+    Ok(configured)
+}
+
+/// Spread and fee margin quotes are priced with, the two knobs operators may
+/// turn while the market maker runs
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfig {
+    pub trade_spread_bps: u64,
+    /// Applied to the network fee rates a quote pays for its fill
+    pub fee_safety_multiplier: f64,
+}
+
+/// `pricing` if it makes for sensible quotes. Spreads of
+/// [`MAX_TRADE_SPREAD_BPS`] and more need `allow_extreme_spread`, and never
+/// reach 100%
+pub fn validate_pricing(
+    pricing: PricingConfig,
+    allow_extreme_spread: bool,
+) -> Result<PricingConfig, ConfigError> {
+    let bps = pricing.trade_spread_bps;
+    ensure!(bps < 10_000, TradeSpreadTooHighSnafu { bps });
+    ensure!(
+        bps < MAX_TRADE_SPREAD_BPS || allow_extreme_spread,
+        ExtremeTradeSpreadSnafu { bps }
+    );
+    // Also turns away NaN and the infinities
+    let multiplier = pricing.fee_safety_multiplier;
+    ensure!(
+        (MIN_FEE_SAFETY_MULTIPLIER..=MAX_FEE_SAFETY_MULTIPLIER).contains(&multiplier),
+        InvalidFeeSafetyMultiplierSnafu { multiplier }
+    );
+    Ok(pricing)
+}
+
+/// Read and validate the JSON pricing file at `path`
+pub fn load_pricing_file(
+    path: &str,
+    allow_extreme_spread: bool,
+) -> Result<PricingConfig, ConfigError> {
+    let contents = std::fs::read_to_string(path).context(ReadPricingFileSnafu { path })?;
+    let pricing = serde_json::from_str(&contents).context(ParsePricingFileSnafu { path })?;
+    validate_pricing(pricing, allow_extreme_spread)
+}
+
+/// Swap in the pricing file at `path`. On error the current pricing stays
+pub fn reload_pricing(
+    pricing: &ArcSwap<PricingConfig>,
+    path: &str,
+    allow_extreme_spread: bool,
+) -> Result<PricingConfig, ConfigError> {
+    let reloaded = load_pricing_file(path, allow_extreme_spread)?;
+    let previous = pricing.swap(Arc::new(reloaded));
+    info!(
+        previous_trade_spread_bps = previous.trade_spread_bps,
+        previous_fee_safety_multiplier = previous.fee_safety_multiplier,
+        trade_spread_bps = reloaded.trade_spread_bps,
+        fee_safety_multiplier = reloaded.fee_safety_multiplier,
+        "Reloaded pricing from {}",
+        path
+    );
+    Ok(reloaded)
+}
+
+/// Reload the pricing file at `path` on every SIGHUP, so spreads can be widened
+/// without a restart
+pub async fn reload_pricing_on_sighup(
+    pricing: Arc<ArcSwap<PricingConfig>>,
+    path: String,
+    allow_extreme_spread: bool,
+) -> crate::Result<()> {
+    let mut hangups = signal(SignalKind::hangup()).context(crate::SignalHandlerSnafu)?;
+    while hangups.recv().await.is_some() {
+        if let Err(e) = reload_pricing(&pricing, &path, allow_extreme_spread) {
+            warn!("Kept the current pricing, failed to reload it: {}", e);
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub market_maker_id: Uuid,
     pub api_key_id: String,
-    pub api_key: String,
+    pub api_key: Redacted<String>,
     pub otc_ws_url: String,
+    /// Delay before the first reconnect, doubled per consecutive failure
     pub reconnect_interval_secs: u64,
-    pub max_reconnect_attempts: u32,
+    /// Consecutive failures before the client gives up, `None` retries forever
+    pub max_reconnect_attempts: Option<u32>,
+    /// Protocol fee paid alongside each fill
+    pub protocol_fee: ProtocolFeeParams,
+    /// Checks the OTC server's attestation on connect, `None` trusts it unverified
+    pub attestation: Option<AttestationVerifier>,
+    /// Destinations fills are refused for, compared ignoring case
+    pub blocked_destination_addresses: Vec<String>,
+}
+
+impl Config {
+    #[must_use]
+    pub fn reconnect_options(&self) -> ReconnectOptions {
+        ReconnectOptions {
+            initial_delay: Duration::from_secs(self.reconnect_interval_secs),
+            max_delay: MAX_RECONNECT_DELAY,
+            max_attempts: self.max_reconnect_attempts,
+            ..ReconnectOptions::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pricing(trade_spread_bps: u64, fee_safety_multiplier: f64) -> PricingConfig {
+        PricingConfig {
+            trade_spread_bps,
+            fee_safety_multiplier,
+        }
+    }
+
+    fn pricing_file(contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mm-pricing-{}.json", Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_extreme_spreads_need_to_be_allowed() {
+        assert!(validate_pricing(pricing(999, 1.5), false).is_ok());
+        assert!(matches!(
+            validate_pricing(pricing(1_000, 1.5), false),
+            Err(ConfigError::ExtremeTradeSpread { bps: 1_000 })
+        ));
+        assert!(validate_pricing(pricing(1_000, 1.5), true).is_ok());
+        assert!(validate_pricing(pricing(9_999, 1.5), true).is_ok());
+
+        for bps in [10_000, 25_000] {
+            for allow_extreme_spread in [false, true] {
+                assert!(matches!(
+                    validate_pricing(pricing(bps, 1.5), allow_extreme_spread),
+                    Err(ConfigError::TradeSpreadTooHigh { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fee_safety_multiplier_must_be_bounded_and_finite() {
+        for multiplier in [1.0, 1.5, 10.0] {
+            assert!(validate_pricing(pricing(0, multiplier), false).is_ok());
+        }
+        for multiplier in [
+            0.0,
+            -1.5,
+            0.99,
+            10.01,
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ] {
+            assert!(
+                matches!(
+                    validate_pricing(pricing(0, multiplier), false),
+                    Err(ConfigError::InvalidFeeSafetyMultiplier { .. })
+                ),
+                "{multiplier}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pricing_file_is_validated() {
+        let path = pricing_file(r#"{"trade_spread_bps": 25, "fee_safety_multiplier": 2.0}"#);
+        assert_eq!(load_pricing_file(&path, false).unwrap(), pricing(25, 2.0));
+
+        let path = pricing_file(r#"{"trade_spread_bps": 25, "fee_safety_multiplier": 0.5}"#);
+        assert!(matches!(
+            load_pricing_file(&path, false),
+            Err(ConfigError::InvalidFeeSafetyMultiplier { .. })
+        ));
+
+        for contents in [
+            r#"{"trade_spread_bps": 25}"#,
+            r#"{"trade_spread_bps": -25, "fee_safety_multiplier": 1.5}"#,
+            r#"{"trade_spread_bps": 25, "fee_safety_multiplier": 1.5, "spread": 30}"#,
+            "trade_spread_bps = 25",
+        ] {
+            assert!(
+                matches!(
+                    load_pricing_file(&pricing_file(contents), false),
+                    Err(ConfigError::ParsePricingFile { .. })
+                ),
+                "{contents}"
+            );
+        }
+
+        assert!(matches!(
+            load_pricing_file("/nonexistent/pricing.json", false),
+            Err(ConfigError::ReadPricingFile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_reload_keeps_the_current_pricing() {
+        let current = ArcSwap::from_pointee(pricing(10, 1.5));
+        let path = pricing_file(r#"{"trade_spread_bps": 2000, "fee_safety_multiplier": 1.5}"#);
+
+        assert!(reload_pricing(&current, &path, false).is_err());
+        assert_eq!(**current.load(), pricing(10, 1.5));
+
+        assert_eq!(
+            reload_pricing(&current, &path, true).unwrap(),
+            pricing(2_000, 1.5)
+        );
+        assert_eq!(**current.load(), pricing(2_000, 1.5));
+    }
 }
@@ -0,0 +1,224 @@
+//! Batches market maker fills of the same token into one Disperse call
+//!
+//! Fills wait up to `window` for others of the same token, then all of them are
+//! paid by a single transaction. Each fill resolves with the batch's tx hash and
+//! its share of the fee, or with the error that failed it.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{Address, U256},
+};
+use blockchain_utils::WebsocketWalletProvider;
+use otc_chains::traits::MarketMakerPaymentValidation;
+use tokio::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        oneshot,
+    },
+    task::JoinSet,
+    time::{sleep_until, Instant},
+};
+use tracing::{info, warn};
+
+use super::{
+    create_fill_batch_transaction,
+    transaction_broadcaster::{
+        EVMTransactionBroadcaster, PreflightCheck, TransactionExecutionResult,
+    },
+    transaction_result, FillPayout,
+};
+use crate::wallet::{self, PreparedFeeRate, TransactionResult, WalletError};
+
+/// When a batch of fills is sent
+#[derive(Debug, Clone, Copy)]
+pub struct FillBatchConfig {
+    /// Longest a fill waits for others to join its batch
+    pub window: Duration,
+    /// A batch this size is sent without waiting out the window
+    pub max_fills: usize,
+}
+
+struct Fill {
+    token_address: Address,
+    payout: FillPayout,
+    fee_rate: Option<PreparedFeeRate>,
+    result: oneshot::Sender<wallet::Result<TransactionResult>>,
+}
+
+/// Fills of one token waiting to be sent
+struct PendingBatch {
+    deadline: Instant,
+    fills: Vec<Fill>,
+}
+
+pub struct FillBatcher {
+    request_sender: Sender<Fill>,
+}
+
+impl FillBatcher {
+    pub fn new(
+        provider: Arc<WebsocketWalletProvider>,
+        tx_broadcaster: EVMTransactionBroadcaster,
+        config: FillBatchConfig,
+        join_set: &mut JoinSet<crate::Result<()>>,
+    ) -> Self {
+        let (request_sender, request_receiver) = channel(128);
+        join_set.spawn(async move {
+            Self::batch_queue(provider, tx_broadcaster, config, request_receiver).await
+        });
+        Self { request_sender }
+    }
+
+    /// Pay `amount` of the token to `recipient` in the next batch, returning the
+    /// batch's tx hash
+    pub async fn submit(
+        &self,
+        token_address: Address,
+        recipient: Address,
+        amount: U256,
+        payment_validation: MarketMakerPaymentValidation,
+        fee_rate: Option<PreparedFeeRate>,
+    ) -> wallet::Result<TransactionResult> {
+        let (result, rx) = oneshot::channel();
+        let fill = Fill {
+            token_address,
+            payout: FillPayout {
+                recipient,
+                amount,
+                payment_validation,
+            },
+            fee_rate,
+            result,
+        };
+        self.request_sender
+            .send(fill)
+            .await
+            .map_err(|_| WalletError::EnqueueFailed)?;
+        rx.await
+            .map_err(|e| WalletError::ReceiveResult { source: e })?
+    }
+
+    async fn batch_queue(
+        provider: Arc<WebsocketWalletProvider>,
+        tx_broadcaster: EVMTransactionBroadcaster,
+        config: FillBatchConfig,
+        mut request_receiver: Receiver<Fill>,
+    ) -> crate::Result<()> {
+        let tx_broadcaster = Arc::new(tx_broadcaster);
+        let mut pending: HashMap<Address, PendingBatch> = HashMap::new();
+        loop {
+            let next_deadline = pending.values().map(|batch| batch.deadline).min();
+            tokio::select! {
+                fill = request_receiver.recv() => {
+                    let Some(fill) = fill else {
+                        return Err(WalletError::ChannelClosed.into());
+                    };
+                    let token_address = fill.token_address;
+                    let batch = pending.entry(token_address).or_insert_with(|| PendingBatch {
+                        deadline: Instant::now() + config.window,
+                        fills: Vec::new(),
+                    });
+                    batch.fills.push(fill);
+                    if batch.fills.len() >= config.max_fills {
+                        let batch = pending.remove(&token_address).unwrap();
+                        Self::spawn_send(&provider, &tx_broadcaster, token_address, batch.fills);
+                    }
+                }
+                () = sleep_until(next_deadline.unwrap_or_else(Instant::now)),
+                    if next_deadline.is_some() =>
+                {
+                    let now = Instant::now();
+                    let due: Vec<Address> = pending
+                        .iter()
+                        .filter(|(_, batch)| batch.deadline <= now)
+                        .map(|(token_address, _)| *token_address)
+                        .collect();
+                    for token_address in due {
+                        let batch = pending.remove(&token_address).unwrap();
+                        Self::spawn_send(&provider, &tx_broadcaster, token_address, batch.fills);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send a batch without holding up the next one, the broadcaster orders the
+    /// transactions
+    fn spawn_send(
+        provider: &Arc<WebsocketWalletProvider>,
+        tx_broadcaster: &Arc<EVMTransactionBroadcaster>,
+        token_address: Address,
+        fills: Vec<Fill>,
+    ) {
+        let provider = provider.clone();
+        let tx_broadcaster = tx_broadcaster.clone();
+        tokio::spawn(async move {
+            let result = Self::send_batch(&provider, &tx_broadcaster, token_address, &fills).await;
+            for fill in fills {
+                let result = match &result {
+                    Ok(transaction) => Ok(transaction.clone()),
+                    Err(reason) => Err(WalletError::TransactionCreationFailed {
+                        reason: reason.clone(),
+                    }),
+                };
+                // The caller may have given up waiting, the payment stands either way
+                let _ = fill.result.send(result);
+            }
+        });
+    }
+
+    async fn send_batch(
+        provider: &Arc<WebsocketWalletProvider>,
+        tx_broadcaster: &EVMTransactionBroadcaster,
+        token_address: Address,
+        fills: &[Fill],
+    ) -> Result<TransactionResult, String> {
+        let payouts: Vec<FillPayout> = fills.iter().map(|fill| fill.payout.clone()).collect();
+        let mut transaction_request =
+            create_fill_batch_transaction(provider, token_address, &payouts);
+        // Pay the highest fee any fill was prepared with
+        let fee_rate = fills
+            .iter()
+            .filter_map(|fill| match fill.fee_rate {
+                Some(PreparedFeeRate::Evm {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                    ..
+                }) => Some((max_fee_per_gas, max_priority_fee_per_gas)),
+                _ => None,
+            })
+            .max();
+        if let Some((max_fee_per_gas, max_priority_fee_per_gas)) = fee_rate {
+            transaction_request.set_max_fee_per_gas(max_fee_per_gas);
+            transaction_request.set_max_priority_fee_per_gas(max_priority_fee_per_gas);
+        }
+
+        info!(
+            "Sending {} fills of token {} in one transaction",
+            fills.len(),
+            token_address
+        );
+        let broadcast_result = tx_broadcaster
+            .broadcast_transaction(transaction_request, PreflightCheck::Simulate)
+            .await
+            .map_err(|e| e.to_string())?;
+        match broadcast_result {
+            TransactionExecutionResult::Success(tx_receipt) => Ok(transaction_result(
+                &tx_receipt,
+                tx_broadcaster.confirmations(),
+                fills.len(),
+            )),
+            _ => {
+                warn!(
+                    "Batch of {} fills of token {} failed: {:?}",
+                    fills.len(),
+                    token_address,
+                    broadcast_result
+                );
+                Err(format!("{broadcast_result:?}"))
+            }
+        }
+    }
+}
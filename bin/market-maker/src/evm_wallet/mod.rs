@@ -1,36 +1,53 @@
+pub mod fill_batcher;
 pub mod transaction_broadcaster;
 
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
 use alloy::{
+    consensus::Transaction as _,
     network::TransactionBuilder,
-    primitives::{Address, U256},
+    primitives::{Address, B256, U256},
     providers::Provider,
-    rpc::types::TransactionRequest,
+    rpc::types::{TransactionReceipt, TransactionRequest},
 };
 use async_trait::async_trait;
 use blockchain_utils::{GenericERC20::GenericERC20Instance, WebsocketWalletProvider};
 use disperse_contract::Disperse::DisperseInstance;
 use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_models::{ChainType, Currency, Lot, TokenIdentifier};
-use tokio::task::JoinSet;
-use tracing::info;
+use otc_models::{
+    ChainNetwork, ChainType, Currency, FillUsage, Lot, SupportedCurrencies, TokenIdentifier, TxHash,
+};
+use tokio::{task::JoinSet, time::Instant};
+use tracing::{info, warn};
 
-use crate::wallet::{self, Wallet, WalletError};
+use crate::wallet::{
+    self, with_reservations, FailedPayment, FillPreparation, PaymentStatus, PreparedFeeRate,
+    TransactionResult, Wallet, WalletError,
+};
+use fill_batcher::{FillBatchConfig, FillBatcher};
 
 pub struct EVMWallet {
     pub tx_broadcaster: transaction_broadcaster::EVMTransactionBroadcaster,
     provider: Arc<WebsocketWalletProvider>,
+    supported_currencies: Arc<SupportedCurrencies>,
+    /// The network the provider is connected to, lots on any other are refused
+    network: ChainNetwork,
+    /// Sends fills of the same token together, `None` sends each on its own
+    fill_batcher: Option<FillBatcher>,
 }
 
 const BALANCE_BUFFER_PERCENT: u8 = 25; // 25% buffer
 const DISPERSE_CONTRACT_ADDRESS: &str = "0xd152f549545093347A162DCE210e7293f1452150";
+/// About one block, a prepared fee rate any older is re-estimated at payout
+const MAX_PREPARED_FEE_AGE: Duration = Duration::from_secs(12);
 
 impl EVMWallet {
     pub fn new(
         provider: Arc<WebsocketWalletProvider>,
         debug_rpc_url: String,
         confirmations: u64,
+        supported_currencies: Arc<SupportedCurrencies>,
+        fill_batching: Option<FillBatchConfig>,
         join_set: &mut JoinSet<crate::Result<()>>,
     ) -> Self {
         let tx_broadcaster = transaction_broadcaster::EVMTransactionBroadcaster::new(
@@ -39,11 +56,25 @@ impl EVMWallet {
             confirmations,
             join_set,
         );
+        let fill_batcher = fill_batching.map(|config| {
+            FillBatcher::new(provider.clone(), tx_broadcaster.clone(), config, join_set)
+        });
         Self {
             tx_broadcaster,
             provider,
+            supported_currencies,
+            network: ChainNetwork::primary(ChainType::Ethereum),
+            fill_batcher,
         }
     }
+
+    /// Pay out on the EVM network `chain_id` instead of the primary one
+    #[must_use]
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.network = ChainNetwork::evm(chain_id);
+        self
+    }
+
     pub async fn ensure_inf_approval_on_disperse(
         &self,
         token_address: &Address,
@@ -86,46 +117,134 @@ impl EVMWallet {
     }
 }
 
-#[async_trait]
-impl Wallet for EVMWallet {
-    async fn create_payment(
+impl EVMWallet {
+    /// Pay `lot` to `to_address`, at `nonce` in place of whatever was sent at it
+    /// if given, and record the nonce the payment used
+    async fn send_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        fee_rate: Option<PreparedFeeRate>,
+        nonce: Option<u64>,
+    ) -> wallet::Result<TransactionResult> {
+        // Without a fee rate the provider estimates one when it sends
+        let fee_rate = fee_rate.filter(|fee_rate| !is_stale(fee_rate));
+        let mut result = self
+            .broadcast_payment(lot, to_address, mm_payment_validation, fee_rate, nonce)
+            .await?;
+        result.nonce = match nonce {
+            Some(nonce) => Some(nonce),
+            None => self.payment_nonce(&result.tx_hash).await,
+        };
+        Ok(result)
+    }
+
+    /// Nonce of a payment we sent, `None` if it can't be looked up
+    async fn payment_nonce(&self, tx_hash: &TxHash) -> Option<u64> {
+        let hash = parse_tx_hash(tx_hash).ok()?;
+        match self.provider.get_transaction_by_hash(hash).await {
+            Ok(Some(transaction)) => Some(transaction.nonce()),
+            Ok(None) => {
+                warn!("Payment {} not found looking up its nonce", tx_hash);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to look up the nonce of payment {}: {}", tx_hash, e);
+                None
+            }
+        }
+    }
+
+    async fn broadcast_payment(
         &self,
         lot: &Lot,
         to_address: &str,
         mm_payment_validation: Option<MarketMakerPaymentValidation>,
-    ) -> wallet::Result<String> {
-        ensure_valid_lot(lot)?;
-        let transaction_request = create_evm_transfer_transaction(
+        fee_rate: Option<PreparedFeeRate>,
+        nonce: Option<u64>,
+    ) -> wallet::Result<TransactionResult> {
+        ensure_valid_lot(lot, self.network, &self.supported_currencies)?;
+        // A batch has its own nonce, a replacement is sent on its own
+        if let (Some(fill_batcher), Some(payment_validation), None) =
+            (&self.fill_batcher, &mm_payment_validation, nonce)
+        {
+            let TokenIdentifier::Address(token_address) = &lot.currency.token else {
+                return Err(WalletError::UnsupportedLot { lot: lot.clone() });
+            };
+            return fill_batcher
+                .submit(
+                    parse_address(token_address, "invalid token address")?,
+                    parse_address(to_address, "invalid to address")?,
+                    lot.amount,
+                    payment_validation.clone(),
+                    fee_rate,
+                )
+                .await;
+        }
+
+        let mut transaction_request = create_evm_transfer_transaction(
             &self.provider,
             lot,
             to_address,
             mm_payment_validation,
         )?;
+        if let Some(PreparedFeeRate::Evm {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            ..
+        }) = fee_rate
+        {
+            transaction_request.set_max_fee_per_gas(max_fee_per_gas);
+            transaction_request.set_max_priority_fee_per_gas(max_priority_fee_per_gas);
+        }
 
-        let broadcast_result = self
-            .tx_broadcaster
-            .broadcast_transaction(
-                transaction_request,
-                transaction_broadcaster::PreflightCheck::Simulate,
-            )
-            .await
-            .map_err(|e| WalletError::TransactionCreationFailed {
-                reason: e.to_string(),
-            })?;
+        let broadcast_result = match nonce {
+            Some(nonce) => {
+                transaction_request.set_nonce(nonce);
+                self.tx_broadcaster
+                    .broadcast_replacement(transaction_request)
+                    .await
+            }
+            None => {
+                self.tx_broadcaster
+                    .broadcast_transaction(
+                        transaction_request,
+                        transaction_broadcaster::PreflightCheck::Simulate,
+                    )
+                    .await
+            }
+        }
+        .map_err(|e| WalletError::TransactionCreationFailed {
+            reason: e.to_string(),
+        })?;
         // we need a method to get some erc20 calldata
         match broadcast_result {
-            transaction_broadcaster::TransactionExecutionResult::Success(tx_receipt) => {
-                Ok(tx_receipt.transaction_hash.to_string())
-            }
+            transaction_broadcaster::TransactionExecutionResult::Success(tx_receipt) => Ok(
+                transaction_result(&tx_receipt, self.tx_broadcaster.confirmations(), 1),
+            ),
             _ => Err(WalletError::TransactionCreationFailed {
                 reason: format!("{broadcast_result:?}"),
             }),
         }
     }
+}
+
+#[async_trait]
+impl Wallet for EVMWallet {
+    async fn create_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        self.send_payment(lot, to_address, mm_payment_validation, None, None)
+            .await
+    }
 
     async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
         // TODO: This check should also include a check that we can pay for gas
-        if ensure_valid_lot(lot).is_err() {
+        if ensure_valid_lot(lot, self.network, &self.supported_currencies).is_err() {
             return Ok(false);
         }
 
@@ -144,6 +263,166 @@ impl Wallet for EVMWallet {
         let required_balance = balance_with_buffer(lot.amount);
         Ok(balance > required_balance)
     }
+
+    async fn prepare_fill(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> wallet::Result<FillPreparation> {
+        ensure_valid_lot(lot, self.network, &self.supported_currencies)?;
+        let start = Instant::now();
+
+        let reserved = with_reservations(lot, pending);
+        if !self.can_fill(&reserved).await? {
+            return Err(WalletError::InsufficientBalance {
+                required: reserved.amount.to_string(),
+                available: "unknown".to_string(),
+            });
+        }
+
+        let fee_rate = match self.provider.estimate_eip1559_fees().await {
+            Ok(estimate) => Some(PreparedFeeRate::Evm {
+                max_fee_per_gas: estimate.max_fee_per_gas,
+                max_priority_fee_per_gas: estimate.max_priority_fee_per_gas,
+                estimated_at: Instant::now(),
+            }),
+            Err(e) => {
+                warn!("Failed to estimate fees: {}", e);
+                None
+            }
+        };
+
+        Ok(FillPreparation {
+            lot: lot.clone(),
+            fee_rate,
+            utxos: Vec::new(),
+            lookup_duration: start.elapsed(),
+        })
+    }
+
+    async fn create_prepared_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        preparation: &FillPreparation,
+    ) -> wallet::Result<TransactionResult> {
+        self.send_payment(
+            lot,
+            to_address,
+            mm_payment_validation,
+            preparation.fee_rate,
+            None,
+        )
+        .await
+    }
+
+    async fn balance(&self, currency: &Currency) -> wallet::Result<U256> {
+        match &currency.token {
+            TokenIdentifier::Native => self
+                .provider
+                .get_balance(self.tx_broadcaster.sender)
+                .await
+                .map_err(|e| WalletError::BalanceCheckFailed {
+                    reason: e.to_string(),
+                }),
+            TokenIdentifier::Address(address) => {
+                let token_address =
+                    address
+                        .parse::<Address>()
+                        .map_err(|e| WalletError::ParseAddressFailed {
+                            context: e.to_string(),
+                        })?;
+                get_erc20_balance(&self.provider, &token_address, &self.tx_broadcaster.sender).await
+            }
+        }
+    }
+
+    async fn payment_status(&self, tx_hash: &TxHash) -> wallet::Result<PaymentStatus> {
+        let lookup_failed = |reason: String| WalletError::PaymentLookupFailed { reason };
+        let hash = parse_tx_hash(tx_hash)?;
+        if let Some(receipt) = self
+            .provider
+            .get_transaction_receipt(hash)
+            .await
+            .map_err(|e| lookup_failed(e.to_string()))?
+        {
+            return Ok(if receipt.status() {
+                PaymentStatus::Confirmed
+            } else {
+                PaymentStatus::Reverted
+            });
+        }
+
+        let known = self
+            .provider
+            .get_transaction_by_hash(hash)
+            .await
+            .map_err(|e| lookup_failed(e.to_string()))?
+            .is_some();
+        Ok(if known {
+            PaymentStatus::Pending
+        } else {
+            PaymentStatus::NotFound
+        })
+    }
+
+    /// A reverted payment used up its nonce without paying, so it's paid again
+    /// as usual. One the chain lost is sent again at its nonce, which fails
+    /// rather than pay twice if the lost one lands after all. That includes a
+    /// lost batch, each of its fills after the first is refused
+    async fn replace_payment(
+        &self,
+        failed: &FailedPayment,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        match failed.status {
+            PaymentStatus::Reverted => {
+                self.send_payment(lot, to_address, mm_payment_validation, None, None)
+                    .await
+            }
+            PaymentStatus::NotFound => {
+                let nonce = failed
+                    .nonce
+                    .ok_or_else(|| WalletError::TransactionCreationFailed {
+                        reason: format!(
+                            "nonce of payment {} is unknown, it can't be replaced safely",
+                            failed.tx_hash
+                        ),
+                    })?;
+                info!("Replacing payment {} at nonce {}", failed.tx_hash, nonce);
+                self.send_payment(lot, to_address, mm_payment_validation, None, Some(nonce))
+                    .await
+            }
+            status => Err(WalletError::TransactionCreationFailed {
+                reason: format!("payment {} is {status:?}, not failed", failed.tx_hash),
+            }),
+        }
+    }
+}
+
+/// What a successful `receipt` says about one of the `fills` it paid, which
+/// share its fee evenly
+fn transaction_result(
+    receipt: &TransactionReceipt,
+    confirmations: u64,
+    fills: usize,
+) -> TransactionResult {
+    let fee = U256::from(receipt.gas_used) * U256::from(receipt.effective_gas_price);
+    let fills = fills.max(1);
+    TransactionResult {
+        tx_hash: receipt.transaction_hash.into(),
+        fee: Some(fee / U256::from(fills)),
+        confirmations,
+        raw: serde_json::to_value(receipt).ok(),
+        usage: Some(FillUsage::Ethereum {
+            effective_gas_price: U256::from(receipt.effective_gas_price),
+            gas_used: receipt.gas_used / fills as u64,
+        }),
+        nonce: None,
+    }
 }
 
 async fn get_erc20_balance(
@@ -171,67 +450,111 @@ fn create_evm_transfer_transaction(
     match &lot.currency.token {
         TokenIdentifier::Native => unimplemented!(),
         TokenIdentifier::Address(address) => {
-            let token_address =
-                address
-                    .parse::<Address>()
-                    .map_err(|_| WalletError::ParseAddressFailed {
-                        context: "invalid token address".to_string(),
-                    })?;
-            let to_address =
-                to_address
-                    .parse::<Address>()
-                    .map_err(|_| WalletError::ParseAddressFailed {
-                        context: "invalid to address".to_string(),
-                    })?;
+            let token_address = parse_address(address, "invalid token address")?;
+            let to_address = parse_address(to_address, "invalid to address")?;
 
-            let fee_address =
-                Address::from_str(&otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Ethereum])
-                    .unwrap();
-
-            let token_contract = DisperseInstance::new(
-                Address::from_str(DISPERSE_CONTRACT_ADDRESS).unwrap(),
-                provider,
-            );
-            let recipients = match mm_payment_validation.is_some() {
-                true => vec![to_address, fee_address],
-                false => vec![to_address],
-            };
-            let amounts = match mm_payment_validation.is_some() {
-                true => {
-                    let fee_amount = mm_payment_validation.as_ref().unwrap().fee_amount;
-                    vec![lot.amount, fee_amount]
+            let transaction_request = match mm_payment_validation {
+                Some(payment_validation) => create_fill_batch_transaction(
+                    provider,
+                    token_address,
+                    &[FillPayout {
+                        recipient: to_address,
+                        amount: lot.amount,
+                        payment_validation,
+                    }],
+                ),
+                None => {
+                    let token_contract = DisperseInstance::new(
+                        Address::from_str(DISPERSE_CONTRACT_ADDRESS).unwrap(),
+                        provider,
+                    );
+                    token_contract
+                        .disperseTokenSimple(token_address, vec![to_address], vec![lot.amount])
+                        .into_transaction_request()
                 }
-                false => vec![lot.amount],
             };
-            let transfer = token_contract.disperseTokenSimple(token_address, recipients, amounts);
-            let mut transaction_request = transfer.into_transaction_request();
-
-            // Add nonce to the end of calldata if provided
-            if let Some(mm_payment_validation) = &mm_payment_validation {
-                let nonce = mm_payment_validation.embedded_nonce;
-                // Audit: Consider how this could be problematic if done with arbitrary addresses (not whitelisted)
-                let mut calldata_with_nonce = transaction_request
-                    .input
-                    .input()
-                    .to_owned()
-                    .unwrap()
-                    .to_vec();
-                calldata_with_nonce.extend_from_slice(&nonce);
-                transaction_request.set_input(calldata_with_nonce);
-                transaction_request.set_input_and_data();
-            }
-            info!("transaction_request: {:?}", transaction_request);
+            // The calldata carries the payment's nonce, so only the transfer is logged
+            info!(
+                "Built transfer of {} {} to {}",
+                lot.amount, token_address, to_address
+            );
             Ok(transaction_request)
         }
     }
 }
 
-fn ensure_valid_lot(lot: &Lot) -> Result<(), WalletError> {
-    if !matches!(lot.currency.chain, ChainType::Ethereum)
-        || !otc_models::SUPPORTED_TOKENS_BY_CHAIN
-            .get(&lot.currency.chain)
-            .unwrap()
-            .contains(&lot.currency.token)
+/// One swap's payout in a Disperse call
+#[derive(Debug, Clone)]
+struct FillPayout {
+    recipient: Address,
+    amount: U256,
+    payment_validation: MarketMakerPaymentValidation,
+}
+
+/// Disperse call paying each recipient followed by its protocol fee, with the
+/// swaps' nonces appended to the calldata in the same order. The otc-server
+/// attributes each payout to its swap by that order.
+fn create_fill_batch_transaction(
+    provider: &Arc<WebsocketWalletProvider>,
+    token_address: Address,
+    payouts: &[FillPayout],
+) -> TransactionRequest {
+    let fee_address =
+        Address::from_str(&otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Ethereum]).unwrap();
+
+    let mut recipients = Vec::with_capacity(payouts.len() * 2);
+    let mut amounts = Vec::with_capacity(payouts.len() * 2);
+    for payout in payouts {
+        recipients.extend([payout.recipient, fee_address]);
+        amounts.extend([payout.amount, payout.payment_validation.fee_amount]);
+    }
+
+    let token_contract = DisperseInstance::new(
+        Address::from_str(DISPERSE_CONTRACT_ADDRESS).unwrap(),
+        provider,
+    );
+    let transfer = token_contract.disperseTokenSimple(token_address, recipients, amounts);
+    let mut transaction_request = transfer.into_transaction_request();
+
+    // Audit: Consider how this could be problematic if done with arbitrary addresses (not whitelisted)
+    let mut calldata_with_nonces = transaction_request
+        .input
+        .input()
+        .to_owned()
+        .unwrap()
+        .to_vec();
+    for payout in payouts {
+        calldata_with_nonces.extend_from_slice(&payout.payment_validation.embedded_nonce);
+    }
+    transaction_request.set_input(calldata_with_nonces);
+    transaction_request.set_input_and_data();
+    transaction_request
+}
+
+fn parse_tx_hash(tx_hash: &TxHash) -> Result<B256, WalletError> {
+    tx_hash
+        .as_str()
+        .parse()
+        .map_err(|e| WalletError::PaymentLookupFailed {
+            reason: format!("{tx_hash} is not an EVM transaction hash: {e}"),
+        })
+}
+
+fn parse_address(address: &str, context: &str) -> Result<Address, WalletError> {
+    address
+        .parse::<Address>()
+        .map_err(|_| WalletError::ParseAddressFailed {
+            context: context.to_string(),
+        })
+}
+
+fn ensure_valid_lot(
+    lot: &Lot,
+    network: ChainNetwork,
+    supported_currencies: &SupportedCurrencies,
+) -> Result<(), WalletError> {
+    if lot.currency.network() != network
+        || !supported_currencies.is_supported(network, &lot.currency.token)
     {
         return Err(WalletError::UnsupportedLot { lot: lot.clone() });
     }
@@ -239,6 +562,14 @@ fn ensure_valid_lot(lot: &Lot) -> Result<(), WalletError> {
     Ok(())
 }
 
+/// Whether a fee rate was estimated more than `MAX_PREPARED_FEE_AGE` ago
+fn is_stale(fee_rate: &PreparedFeeRate) -> bool {
+    match fee_rate {
+        PreparedFeeRate::Evm { estimated_at, .. } => estimated_at.elapsed() > MAX_PREPARED_FEE_AGE,
+        PreparedFeeRate::Bitcoin { .. } => false,
+    }
+}
+
 fn balance_with_buffer(balance: U256) -> U256 {
     balance + (balance * U256::from(BALANCE_BUFFER_PERCENT)) / U256::from(100_u8)
 }
@@ -72,6 +72,8 @@ struct Request {
     transaction_request: AlloyTransactionRequest,
     preflight_check: PreflightCheck,
     confirmations: u64,
+    /// The request's nonce must not change, it replaces a transaction at that nonce
+    pinned_nonce: bool,
     // the tx part of a oneshot channel
     tx: oneshot::Sender<TransactionExecutionResult>,
 }
@@ -82,7 +84,7 @@ pub struct TransactionStatusUpdate {
     pub result: TransactionExecutionResult,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EVMTransactionBroadcaster {
     request_sender: Sender<Request>,
     status_broadcaster: broadcast::Sender<TransactionStatusUpdate>,
@@ -147,6 +149,12 @@ impl EVMTransactionBroadcaster {
         }
     }
 
+    /// Confirmations a transaction has when its broadcast resolves
+    #[must_use]
+    pub fn confirmations(&self) -> u64 {
+        self.confirmations
+    }
+
     pub fn subscribe_to_status_updates(&self) -> broadcast::Receiver<TransactionStatusUpdate> {
         self.status_broadcaster.subscribe()
     }
@@ -158,12 +166,34 @@ impl EVMTransactionBroadcaster {
         &self,
         transaction_request: AlloyTransactionRequest,
         preflight_check: PreflightCheck,
+    ) -> crate::wallet::Result<TransactionExecutionResult> {
+        self.enqueue(transaction_request, preflight_check, false)
+            .await
+    }
+
+    /// Broadcast `transaction_request` at the nonce it sets, in place of whatever
+    /// was sent at that nonce. Fails rather than moving to another nonce once
+    /// that one is used, as the transaction it replaces may have landed
+    pub async fn broadcast_replacement(
+        &self,
+        transaction_request: AlloyTransactionRequest,
+    ) -> crate::wallet::Result<TransactionExecutionResult> {
+        self.enqueue(transaction_request, PreflightCheck::Simulate, true)
+            .await
+    }
+
+    async fn enqueue(
+        &self,
+        transaction_request: AlloyTransactionRequest,
+        preflight_check: PreflightCheck,
+        pinned_nonce: bool,
     ) -> crate::wallet::Result<TransactionExecutionResult> {
         let (tx, rx) = oneshot::channel();
         let request = Request {
             transaction_request,
             preflight_check,
             confirmations: self.confirmations,
+            pinned_nonce,
             tx,
         };
 
@@ -293,6 +323,17 @@ impl EVMTransactionBroadcaster {
                         }
                     }
                     Err(e) => {
+                        let nonce_too_low = matches!(
+                            &e,
+                            RpcError::ErrorResp(error_payload)
+                                if error_payload.message.to_lowercase().contains("nonce too low")
+                        );
+                        if nonce_too_low && request.pinned_nonce {
+                            break TransactionExecutionResult::InvalidRequest(format!(
+                                "Nonce of the replacement is already used: {e}"
+                            ));
+                        }
+
                         // Check if this is a nonce error and we should retry
                         if Self::is_nonce_error(&e) && retry_count < MAX_RETRIES {
                             retry_count += 1;
@@ -309,14 +350,8 @@ impl EVMTransactionBroadcaster {
                             Self::bump_gas_prices(&mut request.transaction_request);
 
                             // For nonce too low errors, clear the nonce to let the provider refetch it
-                            if let RpcError::ErrorResp(error_payload) = &e {
-                                if error_payload
-                                    .message
-                                    .to_lowercase()
-                                    .contains("nonce too low")
-                                {
-                                    request.transaction_request.nonce = None;
-                                }
+                            if nonce_too_low {
+                                request.transaction_request.nonce = None;
                             }
 
                             continue;
@@ -0,0 +1,228 @@
+//! Why the market maker stopped, and the process exit code each reason maps to,
+//! so a supervisor can restart on some and page a human on others
+
+use crate::{
+    bitcoin_wallet::BitcoinWalletError, otc_client::ClientError, preflight::PreflightError,
+    rfq_client::RfqClientError, Error,
+};
+
+/// Terminal reason behind a [`crate::run_market_maker`] error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The OTC or RFQ server turned the API key away
+    AuthRejected,
+    /// Arguments or files the market maker can't run with
+    ConfigInvalid,
+    /// The Bitcoin wallet database can't be read or written
+    WalletCorrupt,
+    /// A server or node stayed unreachable, restarting may be enough
+    ConnectionExhausted,
+    /// A background task panicked
+    Panicked,
+    /// Anything else, like a failing database
+    Unexpected,
+}
+
+impl ShutdownReason {
+    /// Exit code of the process, after sysexits.h where one fits
+    #[must_use]
+    pub const fn exit_code(self) -> u8 {
+        match self {
+            Self::Unexpected => 1,
+            Self::WalletCorrupt => 65,       // EX_DATAERR
+            Self::ConnectionExhausted => 69, // EX_UNAVAILABLE
+            Self::Panicked => 70,            // EX_SOFTWARE
+            Self::AuthRejected => 77,        // EX_NOPERM
+            Self::ConfigInvalid => 78,       // EX_CONFIG
+        }
+    }
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::AuthRejected => "auth_rejected",
+            Self::ConfigInvalid => "config_invalid",
+            Self::WalletCorrupt => "wallet_corrupt",
+            Self::ConnectionExhausted => "connection_exhausted",
+            Self::Panicked => "panicked",
+            Self::Unexpected => "unexpected",
+        }
+    }
+}
+
+impl std::fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Classify the error the market maker stopped with
+#[must_use]
+pub fn shutdown_reason(error: &Error) -> ShutdownReason {
+    match error {
+        Error::Config { .. }
+        | Error::EsploraInitialization { .. }
+        | Error::SupportedCurrencies { .. }
+        | Error::DryRunFailed { .. }
+        | Error::DuplicateEvmNetwork { .. } => ShutdownReason::ConfigInvalid,
+        Error::Client { source } => connection_reason(
+            source.is_auth_rejection(),
+            matches!(source, ClientError::MaxReconnectAttempts { .. }),
+        ),
+        Error::RfqClient { source } => connection_reason(
+            source.is_auth_rejection(),
+            matches!(source, RfqClientError::MaxReconnectAttempts { .. }),
+        ),
+        Error::BitcoinWallet { source } => bitcoin_wallet_reason(source),
+        Error::Preflight { source } => preflight_reason(source),
+        Error::Provider { .. } | Error::PriceFeed { .. } => ShutdownReason::ConnectionExhausted,
+        Error::BackgroundTaskJoin { source } if source.is_panic() => ShutdownReason::Panicked,
+        Error::BackgroundTaskJoin { .. }
+        | Error::BackgroundTaskExited
+        | Error::GenericWallet { .. }
+        | Error::QuoteStorage { .. }
+        | Error::WalletBuckets { .. }
+        | Error::SignalHandler { .. } => ShutdownReason::Unexpected,
+    }
+}
+
+fn connection_reason(auth_rejected: bool, retries_exhausted: bool) -> ShutdownReason {
+    if auth_rejected {
+        ShutdownReason::AuthRejected
+    } else if retries_exhausted {
+        ShutdownReason::ConnectionExhausted
+    } else {
+        ShutdownReason::Unexpected
+    }
+}
+
+fn bitcoin_wallet_reason(error: &BitcoinWalletError) -> ShutdownReason {
+    match error {
+        BitcoinWalletError::OpenDatabase { .. }
+        | BitcoinWalletError::LoadWallet { .. }
+        | BitcoinWalletError::CreateWallet { .. }
+        | BitcoinWalletError::WalletIdentity { .. }
+        | BitcoinWalletError::PersistWallet { .. } => ShutdownReason::WalletCorrupt,
+        BitcoinWalletError::InvalidDescriptor { .. }
+        | BitcoinWalletError::DescriptorMismatch { .. }
+        | BitcoinWalletError::MissingChangeDescriptor { .. }
+        | BitcoinWalletError::PrivateKeysInWatchOnlyWallet
+        | BitcoinWalletError::MissingPrivateKeys
+        | BitcoinWalletError::BuildEsploraClient { .. } => ShutdownReason::ConfigInvalid,
+        _ => ShutdownReason::Unexpected,
+    }
+}
+
+fn preflight_reason(error: &PreflightError) -> ShutdownReason {
+    match error {
+        PreflightError::EsploraGenesis { .. }
+        | PreflightError::EthereumChainIdUnavailable { .. } => ShutdownReason::ConnectionExhausted,
+        PreflightError::EsploraClient { .. }
+        | PreflightError::EsploraNetworkMismatch { .. }
+        | PreflightError::EthereumRpcUrl { .. }
+        | PreflightError::EthereumRpcNotWebsocket { .. }
+        | PreflightError::EthereumChainIdMismatch { .. } => ShutdownReason::ConfigInvalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigError;
+    use std::collections::HashSet;
+    use tokio_tungstenite::tungstenite::{self, http};
+
+    fn http_error(status: u16) -> tungstenite::Error {
+        tungstenite::Error::Http(http::Response::builder().status(status).body(None).unwrap())
+    }
+
+    #[test]
+    fn test_rejected_api_key_is_told_apart_from_an_unreachable_server() {
+        let rejected = Error::RfqClient {
+            source: RfqClientError::WebSocketConnection {
+                source: http_error(401),
+            },
+        };
+        assert_eq!(shutdown_reason(&rejected), ShutdownReason::AuthRejected);
+
+        let forbidden_after_retries = Error::Client {
+            source: ClientError::MaxReconnectAttempts {
+                attempts: 5,
+                source: Box::new(ClientError::WebSocketConnection {
+                    source: http_error(403),
+                }),
+            },
+        };
+        assert_eq!(
+            shutdown_reason(&forbidden_after_retries),
+            ShutdownReason::AuthRejected
+        );
+
+        let unreachable = Error::Client {
+            source: ClientError::MaxReconnectAttempts {
+                attempts: 5,
+                source: Box::new(ClientError::WebSocketConnection {
+                    source: tungstenite::Error::ConnectionClosed,
+                }),
+            },
+        };
+        assert_eq!(
+            shutdown_reason(&unreachable),
+            ShutdownReason::ConnectionExhausted
+        );
+    }
+
+    #[test]
+    fn test_wallet_database_errors_are_corruption() {
+        let corrupt = Error::BitcoinWallet {
+            source: BitcoinWalletError::OpenDatabase {
+                source: bdk_wallet::rusqlite::Error::InvalidQuery,
+            },
+        };
+        assert_eq!(shutdown_reason(&corrupt), ShutdownReason::WalletCorrupt);
+
+        let misconfigured = Error::BitcoinWallet {
+            source: BitcoinWalletError::MissingChangeDescriptor {
+                db_file: "wallet.db".to_string(),
+            },
+        };
+        assert_eq!(
+            shutdown_reason(&misconfigured),
+            ShutdownReason::ConfigInvalid
+        );
+        assert_eq!(
+            shutdown_reason(&Error::Config {
+                source: ConfigError::IncompleteAttestation
+            }),
+            ShutdownReason::ConfigInvalid
+        );
+    }
+
+    #[tokio::test]
+    async fn test_panicked_task_is_panicked() {
+        let join_error = tokio::spawn(async { panic!("boom") }).await.unwrap_err();
+        assert_eq!(
+            shutdown_reason(&Error::BackgroundTaskJoin { source: join_error }),
+            ShutdownReason::Panicked
+        );
+        assert_eq!(
+            shutdown_reason(&Error::BackgroundTaskExited),
+            ShutdownReason::Unexpected
+        );
+    }
+
+    #[test]
+    fn test_every_reason_has_its_own_exit_code() {
+        let reasons = [
+            ShutdownReason::AuthRejected,
+            ShutdownReason::ConfigInvalid,
+            ShutdownReason::WalletCorrupt,
+            ShutdownReason::ConnectionExhausted,
+            ShutdownReason::Panicked,
+            ShutdownReason::Unexpected,
+        ];
+        let codes: HashSet<_> = reasons.iter().map(|reason| reason.exit_code()).collect();
+        assert_eq!(codes.len(), reasons.len());
+        assert!(!codes.contains(&0));
+    }
+}
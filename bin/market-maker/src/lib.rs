@@ -1,32 +1,72 @@
 pub mod bitcoin_wallet;
+mod capabilities;
 mod config;
 pub mod evm_wallet;
+pub mod exit;
 mod otc_client;
 mod otc_handler;
+pub mod preflight;
 pub mod price_oracle;
 pub mod quote_storage;
+pub mod quotes_cli;
+pub mod readiness;
 mod rfq_client;
 mod rfq_handler;
+pub mod rfq_placement;
+pub mod simulation;
+pub mod status;
 mod strategy;
 pub mod wallet;
-mod wrapped_bitcoin_quoter;
+pub mod wallet_buckets;
+pub mod wrapped_bitcoin_quoter;
 
-use std::{str::FromStr, sync::Arc};
+use std::{collections::HashSet, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
 
-use alloy::{primitives::Address, providers::Provider};
+use alloy::{
+    primitives::{Address, B256},
+    providers::Provider,
+};
+use arc_swap::ArcSwap;
 use bdk_wallet::bitcoin;
 use clap::Parser;
-use blockchain_utils::{create_websocket_wallet_provider, handle_background_thread_result};
-use config::Config;
-use otc_models::ChainType;
+use blockchain_utils::{
+    create_websocket_wallet_provider, ProtocolFeeParams, MIN_PROTOCOL_FEE_SATS, PROTOCOL_FEE_BPS,
+};
+use common::{check_clock_drift, Clock, EvmNetworkUrl, SystemClock};
+use config::{Config, PricingConfig};
+use otc_models::{
+    ChainNetwork, ChainType, Redacted, SupportedCurrencies, SupportedCurrenciesError,
+    SupportedCurrency, TokenIdentifier,
+};
+use otc_protocols::attestation::{AttestationVerifier, DEFAULT_ATTESTATION_MAX_AGE_SECONDS};
 use snafu::{prelude::*, ResultExt};
-use tokio::task::JoinSet;
-use tracing::info;
+use tokio::task::{JoinError, JoinSet};
+use tracing::{info, warn};
+use url::Url;
 use uuid::Uuid;
+use zeroize::Zeroize;
 
 use crate::{
-    bitcoin_wallet::BitcoinWallet, evm_wallet::EVMWallet, quote_storage::QuoteStorage,
-    wallet::WalletManager, wrapped_bitcoin_quoter::WrappedBitcoinQuoter,
+    bitcoin_wallet::{
+        BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, CoinSelectionStrategy,
+        ConsolidationConfig, ExternalSignerConfig, SignerConfig, DEFAULT_EXTERNAL_SIGNER_TIMEOUT,
+        DEFAULT_MAX_FILL_INPUTS, DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    },
+    evm_wallet::{fill_batcher::FillBatchConfig, EVMWallet},
+    quote_storage::{QuoteStorage, DEFAULT_QUOTE_RETENTION_HOURS},
+    readiness::{ReadinessCheck, ReadinessState},
+    rfq_placement::{RfqPlacement, DEFAULT_FAILOVER_AFTER},
+    simulation::{FixedFeeEstimator, DEFAULT_SIMULATED_BTC_PER_ETH},
+    status::StatusState,
+    strategy::{
+        AutoAcceptPolicy, StrictValidationPolicy, ValidationPolicy, DEFAULT_PRICE_TOLERANCE_BPS,
+    },
+    wallet::{Wallet, WalletManager},
+    wallet_buckets::{
+        WalletBuckets, DEFAULT_BUCKET_DRIFT_TOLERANCE_BPS,
+        DEFAULT_BUCKET_RECONCILIATION_INTERVAL_SECONDS,
+    },
+    wrapped_bitcoin_quoter::{ChainFeeEstimator, FeeEstimator, WrappedBitcoinQuoter},
 };
 
 #[derive(Debug, Snafu)]
@@ -37,6 +77,11 @@ pub enum Error {
     #[snafu(display("Client error: {}", source))]
     Client { source: otc_client::ClientError },
 
+    #[snafu(display("RFQ client error: {}", source))]
+    RfqClient {
+        source: rfq_client::RfqClientError,
+    },
+
     #[snafu(display("Bitcoin wallet error: {}", source))]
     BitcoinWallet {
         source: bitcoin_wallet::BitcoinWalletError,
@@ -51,15 +96,47 @@ pub enum Error {
     #[snafu(display("Esplora client error: {}", source))]
     EsploraInitialization { source: esplora_client::Error },
 
-    #[snafu(display("Background thread error: {}", source))]
-    BackgroundThread {
-        source: Box<dyn std::error::Error + Send + Sync>,
+    #[snafu(display("Price feed error: {}", source))]
+    PriceFeed {
+        source: price_oracle::PriceOracleError,
     },
 
+    #[snafu(display("Background task panicked or was cancelled: {}", source))]
+    BackgroundTaskJoin { source: JoinError },
+
+    #[snafu(display("Background task exited unexpectedly"))]
+    BackgroundTaskExited,
+
+    #[snafu(display("Supported currencies error: {}", source))]
+    SupportedCurrencies { source: SupportedCurrenciesError },
+
     #[snafu(display("Quote storage error: {}", source))]
     QuoteStorage {
         source: quote_storage::QuoteStorageError,
     },
+
+    #[snafu(display("Wallet bucket error: {}", source))]
+    WalletBuckets {
+        source: wallet_buckets::WalletBucketError,
+    },
+
+    #[snafu(display("Startup check failed: {}", source))]
+    Preflight { source: preflight::PreflightError },
+
+    #[snafu(display("Dry run found {} failing checks", failed))]
+    DryRunFailed { failed: usize },
+
+    #[snafu(display("EVM network {} is configured more than once", chain_id))]
+    DuplicateEvmNetwork { chain_id: u64 },
+
+    #[snafu(display("Failed to listen for signals: {}", source))]
+    SignalHandler { source: std::io::Error },
+
+    #[snafu(display("Status endpoint on {} failed: {}", addr, source))]
+    StatusServer {
+        addr: SocketAddr,
+        source: std::io::Error,
+    },
 }
 
 impl From<blockchain_utils::ProviderError> for Error {
@@ -96,23 +173,50 @@ pub struct MarketMakerArgs {
 
     /// API key for authentication
     #[arg(long, env = "MM_API_KEY")]
-    pub api_key: String,
+    pub api_key: Redacted<String>,
 
     /// OTC server WebSocket URL
     #[arg(long, env = "OTC_WS_URL", default_value = "ws://localhost:3000/ws/mm")]
     pub otc_ws_url: String,
 
-    /// RFQ server WebSocket URL
-    #[arg(long, env = "RFQ_WS_URL", default_value = "ws://localhost:3001/ws/mm")]
-    pub rfq_ws_url: String,
+    /// RFQ server WebSocket URLs. Given more than one, the market maker connects
+    /// to the fastest and keeps the others as failover targets
+    #[arg(
+        long = "rfq-ws-url",
+        env = "RFQ_WS_URL",
+        value_delimiter = ',',
+        default_value = "ws://localhost:3001/ws/mm"
+    )]
+    pub rfq_ws_urls: Vec<String>,
+
+    /// Seconds the active RFQ server may stay unreachable before the next
+    /// fastest one is promoted
+    #[arg(
+        long,
+        env = "RFQ_FAILOVER_AFTER_SECONDS",
+        default_value_t = DEFAULT_FAILOVER_AFTER.as_secs()
+    )]
+    pub rfq_failover_after_seconds: u64,
+
+    /// Address to serve the status endpoint on, `GET /status` reports whether
+    /// warm-up is done, the active RFQ server and the latencies measured to
+    /// each. Not served when unset
+    #[arg(long, env = "STATUS_ADDR")]
+    pub status_addr: Option<SocketAddr>,
 
     /// Bitcoin wallet database file
     #[arg(long, env = "BITCOIN_WALLET_DB_PATH")]
     pub bitcoin_wallet_db_file: String,
 
-    /// Bitcoin wallet descriptor (aka private key in descriptor format)
+    /// Bitcoin wallet descriptor (aka private key in descriptor format). With an
+    /// external signer, the public descriptor of the wallet instead
     #[arg(long, env = "BITCOIN_WALLET_DESCRIPTOR")]
-    pub bitcoin_wallet_descriptor: String,
+    pub bitcoin_wallet_descriptor: Redacted<String>,
+
+    /// Descriptor of the Bitcoin wallet's change addresses. Without one change goes
+    /// back to external addresses. Only new wallet databases pick it up
+    #[arg(long, env = "BITCOIN_WALLET_CHANGE_DESCRIPTOR")]
+    pub bitcoin_wallet_change_descriptor: Option<Redacted<String>>,
 
     /// Bitcoin wallet network
     #[arg(long, env = "BITCOIN_WALLET_NETWORK", default_value = "bitcoin")]
@@ -122,9 +226,101 @@ pub struct MarketMakerArgs {
     #[arg(long, env = "BITCOIN_WALLET_ESPLORA_URL")]
     pub bitcoin_wallet_esplora_url: String,
 
+    /// How often the Bitcoin wallet runs a full background sync, in seconds
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_SYNC_INTERVAL_SECONDS",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub bitcoin_wallet_sync_interval_seconds: u64,
+
+    /// Maximum age of the Bitcoin wallet state, in seconds, before fills are refused
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_MAX_SYNC_STALENESS_SECONDS",
+        default_value = "120"
+    )]
+    pub bitcoin_wallet_max_sync_staleness_seconds: u64,
+
+    /// Longest chain of our own unconfirmed transactions a payout may extend
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_MAX_UNCONFIRMED_CHAIN_DEPTH",
+        default_value_t = DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH
+    )]
+    pub bitcoin_wallet_max_unconfirmed_chain_depth: usize,
+
+    /// How Bitcoin fills pick their inputs: largest-first, branch-and-bound or oldest-first
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_COIN_SELECTION",
+        default_value_t = CoinSelectionStrategy::BranchAndBound
+    )]
+    pub bitcoin_wallet_coin_selection: CoinSelectionStrategy,
+
+    /// Most inputs a Bitcoin fill transaction may spend
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_MAX_FILL_INPUTS",
+        default_value_t = DEFAULT_MAX_FILL_INPUTS
+    )]
+    pub bitcoin_wallet_max_fill_inputs: usize,
+
+    /// Sweep small UTXOs into one while the day-ahead fee rate is at most this many
+    /// sat/vB (no consolidation if unset)
+    #[arg(long, env = "BITCOIN_WALLET_CONSOLIDATION_MAX_FEE_RATE")]
+    pub bitcoin_wallet_consolidation_max_fee_rate: Option<u64>,
+
+    /// UTXOs below this many sats are consolidated
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_CONSOLIDATION_SMALL_UTXO_SATS",
+        default_value = "100000"
+    )]
+    pub bitcoin_wallet_consolidation_small_utxo_sats: u64,
+
+    /// Consolidate once at least this many small UTXOs are spendable
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_CONSOLIDATION_MIN_UTXOS",
+        default_value = "10"
+    )]
+    pub bitcoin_wallet_consolidation_min_utxos: usize,
+
+    /// How often to check whether to consolidate, in seconds
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_CONSOLIDATION_INTERVAL_SECONDS",
+        default_value = "600",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub bitcoin_wallet_consolidation_interval_seconds: u64,
+
+    /// Run the Bitcoin wallet watch-only, POSTing its PSBTs to this signing service
+    #[arg(long, env = "BITCOIN_WALLET_EXTERNAL_SIGNER_URL")]
+    pub bitcoin_wallet_external_signer_url: Option<Url>,
+
+    /// How long the external signer gets to sign a PSBT, in seconds
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_EXTERNAL_SIGNER_TIMEOUT_SECONDS",
+        default_value_t = DEFAULT_EXTERNAL_SIGNER_TIMEOUT.as_secs()
+    )]
+    pub bitcoin_wallet_external_signer_timeout_seconds: u64,
+
+    /// Addresses the external signer may be asked to pay besides a payment's own
+    /// recipient, fee address and change
+    #[arg(
+        long,
+        env = "BITCOIN_WALLET_EXTERNAL_SIGNER_ALLOWED_ADDRESSES",
+        value_delimiter = ','
+    )]
+    pub bitcoin_wallet_external_signer_allowed_addresses: Vec<String>,
+
     /// Ethereum wallet private key
-    #[arg(long, env = "ETHEREUM_WALLET_PRIVATE_KEY", value_parser = parse_hex_string)]
-    pub ethereum_wallet_private_key: [u8; 32],
+    #[arg(long, env = "ETHEREUM_WALLET_PRIVATE_KEY", value_parser = parse_private_key)]
+    pub ethereum_wallet_private_key: Redacted<[u8; 32]>,
 
     /// Ethereum confirmations necessary for a transaction to be considered confirmed (for the wallet to be allowed to send a new transaction)
     #[arg(long, env = "ETHEREUM_CONFIRMATIONS", default_value = "1")]
@@ -134,10 +330,52 @@ pub struct MarketMakerArgs {
     #[arg(long, env = "ETHEREUM_RPC_WS_URL")]
     pub ethereum_rpc_ws_url: String,
 
+    /// Chain id the Ethereum RPC must serve, not checked if unset
+    #[arg(long, env = "ETHEREUM_CHAIN_ID")]
+    pub ethereum_chain_id: Option<u64>,
+
+    /// Comma separated websocket RPC URLs of further EVM networks to fill on, as
+    /// `<chain_id>=<url>`. Their tokens are listed in the supported currencies
+    /// file under the chain id
+    #[arg(long, env = "ETHEREUM_NETWORK_RPC_WS_URLS", value_delimiter = ',')]
+    pub ethereum_network_rpc_ws_urls: Vec<EvmNetworkUrl>,
+
+    /// Milliseconds an Ethereum fill waits for others of the same token to share
+    /// its transaction (each fill is sent on its own if unset)
+    #[arg(long, env = "ETHEREUM_FILL_BATCH_WINDOW_MS")]
+    pub ethereum_fill_batch_window_ms: Option<u64>,
+
+    /// Most Ethereum fills sent in one transaction
+    #[arg(long, env = "ETHEREUM_FILL_BATCH_MAX_FILLS", default_value = "10")]
+    pub ethereum_fill_batch_max_fills: usize,
+
     /// Trade spread in basis points
     #[arg(long, env = "TRADE_SPREAD_BPS", default_value = "0")]
     pub trade_spread_bps: u64,
 
+    /// Accept trade spreads of 1000 bps and more
+    #[arg(long, env = "ALLOW_EXTREME_SPREAD")]
+    pub allow_extreme_spread: bool,
+
+    /// JSON file with `trade_spread_bps` and `fee_safety_multiplier`, replacing
+    /// those arguments. Re-read on SIGHUP to change them without a restart
+    #[arg(long, env = "PRICING_FILE")]
+    pub pricing_file: Option<String>,
+
+    /// Protocol fee paid alongside each fill, in basis points of the payout. The OTC
+    /// server rejects fills paying less than the protocol default
+    #[arg(long, env = "PROTOCOL_FEE_BPS", default_value_t = PROTOCOL_FEE_BPS)]
+    pub protocol_fee_bps: u64,
+
+    /// Smallest protocol fee paid alongside a fill, in sats
+    #[arg(long, env = "MIN_PROTOCOL_FEE_SATS", default_value_t = MIN_PROTOCOL_FEE_SATS)]
+    pub min_protocol_fee_sats: u64,
+
+    /// Destination addresses we never pay out to, whatever swap the OTC server
+    /// asks us to fill, e.g. our own wallets
+    #[arg(long, env = "BLOCKED_DESTINATION_ADDRESSES", value_delimiter = ',')]
+    pub blocked_destination_addresses: Vec<String>,
+
     /// Fee safety multiplier, by default 1.5x
     #[arg(long, env = "FEE_SAFETY_MULTIPLIER", default_value = "1.5")]
     pub fee_safety_multiplier: f64,
@@ -149,125 +387,623 @@ pub struct MarketMakerArgs {
     /// Database URL for quote storage
     #[arg(long, env = "MM_DATABASE_URL")]
     pub database_url: String,
+
+    /// Hours to keep quotes that no swap references before deleting them
+    #[arg(long, env = "QUOTE_RETENTION_HOURS", default_value_t = DEFAULT_QUOTE_RETENTION_HOURS)]
+    pub quote_retention_hours: u32,
+
+    /// Seconds between comparisons of the wallet buckets with the on-chain balances
+    #[arg(
+        long,
+        env = "WALLET_BUCKET_RECONCILIATION_INTERVAL_SECONDS",
+        default_value_t = DEFAULT_BUCKET_RECONCILIATION_INTERVAL_SECONDS
+    )]
+    pub wallet_bucket_reconciliation_interval_seconds: u64,
+
+    /// How far the funds set aside from trading may exceed what a wallet holds
+    /// before it's reported, in basis points of the funds set aside
+    #[arg(
+        long,
+        env = "WALLET_BUCKET_DRIFT_TOLERANCE_BPS",
+        default_value_t = DEFAULT_BUCKET_DRIFT_TOLERANCE_BPS
+    )]
+    pub wallet_bucket_drift_tolerance_bps: u64,
+
+    /// Accept every quote we issued that hasn't expired when the OTC server asks,
+    /// without checking balance or price again
+    #[arg(long, env = "AUTO_ACCEPT")]
+    pub auto_accept: bool,
+
+    /// How much more a quote may pay out than a fresh quote would when the OTC
+    /// server asks to fill it, in basis points
+    #[arg(long, env = "QUOTE_PRICE_TOLERANCE_BPS", default_value_t = DEFAULT_PRICE_TOLERANCE_BPS)]
+    pub quote_price_tolerance_bps: u64,
+
+    /// TOML or JSON file listing the tokens to quote, defaults to BTC and cbBTC
+    #[arg(long, env = "SUPPORTED_CURRENCIES_FILE")]
+    pub supported_currencies_file: Option<String>,
+
+    /// Code measurement the OTC server must attest to before any of its requests
+    /// are processed. Unset, the server is trusted without verification
+    #[arg(
+        long,
+        env = "EXPECTED_MEASUREMENT",
+        requires = "attestation_root_of_trust"
+    )]
+    pub expected_measurement: Option<B256>,
+
+    /// Address of the key the OTC server's attestation must be signed by
+    #[arg(
+        long,
+        env = "ATTESTATION_ROOT_OF_TRUST",
+        requires = "expected_measurement"
+    )]
+    pub attestation_root_of_trust: Option<Address>,
+
+    /// Oldest the OTC server's attestation may be when the market maker connects
+    #[arg(
+        long,
+        env = "ATTESTATION_MAX_AGE_SECONDS",
+        default_value_t = DEFAULT_ATTESTATION_MAX_AGE_SECONDS
+    )]
+    pub attestation_max_age_seconds: u64,
+
+    /// Check the configuration against the database, wallet file, chains and the
+    /// OTC and RFQ servers without writing to any of them, print a report and
+    /// exit without quoting or filling
+    #[arg(long, env = "DRY_RUN")]
+    pub dry_run: bool,
+
+    /// Quote from a fixed BTC/ETH price and fixed network fees without a spread,
+    /// so a devnet runs without the price feed. Only allowed on regtest
+    #[arg(long, env = "SIMULATION_MODE")]
+    pub simulation_mode: bool,
+
+    /// BTC per ETH quoted from in simulation mode
+    #[arg(long, env = "SIMULATED_BTC_PER_ETH", default_value_t = DEFAULT_SIMULATED_BTC_PER_ETH)]
+    pub simulated_btc_per_eth: f64,
 }
 
-fn parse_hex_string(s: &str) -> std::result::Result<[u8; 32], String> {
-    let bytes = alloy::hex::decode(s).map_err(|e| e.to_string())?;
+fn parse_private_key(s: &str) -> std::result::Result<Redacted<[u8; 32]>, String> {
+    let mut bytes = alloy::hex::decode(s).map_err(|e| e.to_string())?;
     if bytes.len() != 32 {
-        return Err(format!("Expected 32 bytes, got {}", bytes.len()).into());
+        let len = bytes.len();
+        bytes.zeroize();
+        return Err(format!("Expected 32 bytes, got {len}"));
     }
-    Ok(bytes.try_into().unwrap())
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    bytes.zeroize();
+    Ok(Redacted::new(key))
 }
 
-pub async fn run_market_maker(args: MarketMakerArgs) -> Result<()> {
-    let mut join_set: JoinSet<Result<()>> = JoinSet::new();
-    let market_maker_id = Uuid::parse_str(&args.market_maker_id).map_err(|e| Error::Config {
+fn parse_market_maker_id(market_maker_id: &str) -> Result<Uuid> {
+    Uuid::parse_str(market_maker_id).map_err(|e| Error::Config {
         source: config::ConfigError::InvalidUuid {
-            uuid: args.market_maker_id,
+            uuid: market_maker_id.to_string(),
             error: e,
         },
-    })?;
+    })
+}
+
+fn protocol_fee_params(args: &MarketMakerArgs) -> ProtocolFeeParams {
+    ProtocolFeeParams {
+        bps: args.protocol_fee_bps,
+        min_sats: args.min_protocol_fee_sats,
+    }
+}
+
+fn attestation_verifier(args: &MarketMakerArgs) -> Result<Option<AttestationVerifier>> {
+    match (args.expected_measurement, args.attestation_root_of_trust) {
+        (Some(expected_measurement), Some(root_of_trust)) => Ok(Some(AttestationVerifier {
+            root_of_trust,
+            expected_measurement,
+            max_age: Duration::from_secs(args.attestation_max_age_seconds),
+        })),
+        (None, None) => {
+            warn!("No --expected-measurement set, the OTC server's attestation won't be verified");
+            Ok(None)
+        }
+        _ => Err(Error::Config {
+            source: config::ConfigError::IncompleteAttestation,
+        }),
+    }
+}
+
+/// Startup pricing, from the pricing file if there is one
+fn pricing_config(args: &MarketMakerArgs) -> Result<PricingConfig, config::ConfigError> {
+    match &args.pricing_file {
+        Some(path) => config::load_pricing_file(path, args.allow_extreme_spread),
+        None => config::validate_pricing(
+            PricingConfig {
+                trade_spread_bps: args.trade_spread_bps,
+                fee_safety_multiplier: args.fee_safety_multiplier,
+            },
+            args.allow_extreme_spread,
+        ),
+    }
+}
+
+fn load_supported_currencies(args: &MarketMakerArgs) -> Result<SupportedCurrencies> {
+    match &args.supported_currencies_file {
+        Some(path) => SupportedCurrencies::load(path).context(SupportedCurrenciesSnafu),
+        None => Ok(SupportedCurrencies::default()),
+    }
+}
+
+async fn open_bitcoin_wallet(
+    args: &MarketMakerArgs,
+    join_set: &mut JoinSet<Result<()>>,
+) -> Result<BitcoinWallet> {
+    BitcoinWallet::new(
+        &args.bitcoin_wallet_db_file,
+        args.bitcoin_wallet_descriptor.expose(),
+        args.bitcoin_wallet_change_descriptor
+            .as_ref()
+            .map(|descriptor| descriptor.expose().as_str()),
+        args.bitcoin_wallet_network,
+        &args.bitcoin_wallet_esplora_url,
+        BitcoinWalletSyncConfig {
+            sync_interval: Duration::from_secs(args.bitcoin_wallet_sync_interval_seconds),
+            max_staleness: Duration::from_secs(args.bitcoin_wallet_max_sync_staleness_seconds),
+            ..Default::default()
+        },
+        args.bitcoin_wallet_max_unconfirmed_chain_depth,
+        CoinSelectionConfig {
+            strategy: args.bitcoin_wallet_coin_selection,
+            max_inputs: args.bitcoin_wallet_max_fill_inputs.max(1),
+            consolidation: args.bitcoin_wallet_consolidation_max_fee_rate.map(
+                |max_fee_rate_sat_per_vb| ConsolidationConfig {
+                    max_fee_rate_sat_per_vb,
+                    small_utxo_sats: args.bitcoin_wallet_consolidation_small_utxo_sats,
+                    min_utxos: args.bitcoin_wallet_consolidation_min_utxos,
+                    check_interval: Duration::from_secs(
+                        args.bitcoin_wallet_consolidation_interval_seconds,
+                    ),
+                },
+            ),
+        },
+        match &args.bitcoin_wallet_external_signer_url {
+            Some(url) => SignerConfig::External(ExternalSignerConfig {
+                url: url.clone(),
+                timeout: Duration::from_secs(args.bitcoin_wallet_external_signer_timeout_seconds),
+                allowed_addresses: args
+                    .bitcoin_wallet_external_signer_allowed_addresses
+                    .clone(),
+            }),
+            None => SignerConfig::Descriptor,
+        },
+        join_set,
+    )
+    .await
+    .context(BitcoinWalletSnafu)
+}
+
+/// Connection settings shared by the OTC and RFQ clients
+fn client_config(
+    args: &MarketMakerArgs,
+    market_maker_id: Uuid,
+    protocol_fee: ProtocolFeeParams,
+    attestation: Option<AttestationVerifier>,
+) -> Config {
+    Config {
+        market_maker_id,
+        api_key_id: args.api_key_id.clone(),
+        api_key: args.api_key.clone(),
+        otc_ws_url: args.otc_ws_url.clone(),
+        reconnect_interval_secs: 5,
+        max_reconnect_attempts: Some(5),
+        protocol_fee,
+        attestation,
+        blocked_destination_addresses: args.blocked_destination_addresses.clone(),
+    }
+}
+
+/// Let the disperse contract move every token `wallet` pays out on `network`
+async fn ensure_disperse_approvals(
+    wallet: &EVMWallet,
+    supported_currencies: &SupportedCurrencies,
+    network: ChainNetwork,
+) {
+    // TODO: something better than adhoc approval?
+    for currency in supported_currencies.on_chain(network) {
+        let TokenIdentifier::Address(token_address) = &currency.token else {
+            continue;
+        };
+        let token_address =
+            Address::from_str(token_address).expect("Supported token address should be valid");
+        wallet
+            .ensure_inf_approval_on_disperse(&token_address)
+            .await
+            .expect("Failed to ensure inf approval on disperse contract");
+    }
+}
+
+pub async fn run_market_maker(args: MarketMakerArgs) -> Result<()> {
+    run_market_maker_with_wallet_layer(args, |_, wallet| wallet).await
+}
+
+/// Same as [`run_market_maker`], with each payout wallet passed through `layer`
+/// before it is registered, e.g. to instrument payments
+pub async fn run_market_maker_with_wallet_layer(
+    args: MarketMakerArgs,
+    layer: impl Fn(ChainType, Arc<dyn Wallet>) -> Arc<dyn Wallet>,
+) -> Result<()> {
+    let rfq_placement = Arc::new(
+        RfqPlacement::new(
+            args.rfq_ws_urls.clone(),
+            Duration::from_secs(args.rfq_failover_after_seconds),
+        )
+        .context(ConfigSnafu)?,
+    );
+    if args.dry_run {
+        return preflight::dry_run(&args).await;
+    }
+
+    let mut join_set: JoinSet<Result<()>> = JoinSet::new();
+    let market_maker_id = parse_market_maker_id(&args.market_maker_id)?;
+    // Quotes are turned down until the price feed and both wallets are up
+    let readiness = Arc::new(ReadinessState::new());
+
+    if let Some(addr) = args.status_addr {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .context(StatusServerSnafu { addr })?;
+        let state = StatusState {
+            readiness: readiness.clone(),
+            rfq_placement: rfq_placement.clone(),
+        };
+        join_set.spawn(async move {
+            status::serve(listener, state)
+                .await
+                .context(StatusServerSnafu { addr })
+        });
+    }
+
+    let protocol_fee =
+        config::validate_protocol_fee(protocol_fee_params(&args)).context(ConfigSnafu)?;
+    let pricing = pricing_config(&args).context(ConfigSnafu)?;
+    info!(
+        "Quoting with a {} bps spread and {}x fee safety multiplier",
+        pricing.trade_spread_bps,
+        pricing.fee_safety_multiplier
+    );
+    let pricing = Arc::new(ArcSwap::from_pointee(pricing));
+    if let Some(path) = &args.pricing_file {
+        join_set.spawn(config::reload_pricing_on_sighup(
+            pricing.clone(),
+            path.clone(),
+            args.allow_extreme_spread,
+        ));
+    }
+
+    if args.simulation_mode {
+        simulation::check_args(args.bitcoin_wallet_network, args.simulated_btc_per_eth)
+            .context(ConfigSnafu)?;
+        warn!(
+            "SIMULATION MODE: quoting from a fixed price of {} BTC/ETH and fixed fees without a spread, never run this with real funds",
+            args.simulated_btc_per_eth
+        );
+    }
 
     info!("Starting market maker with ID: {}", market_maker_id);
 
+    // A wallet pointed at the wrong chain fails here rather than on its first sync or fill
+    preflight::check_esplora_network(
+        &args.bitcoin_wallet_esplora_url,
+        args.bitcoin_wallet_network,
+    )
+    .await
+    .context(PreflightSnafu)?;
+    preflight::check_ethereum_rpc_url(&args.ethereum_rpc_ws_url).context(PreflightSnafu)?;
+    for network in &args.ethereum_network_rpc_ws_urls {
+        preflight::check_ethereum_rpc_url(&network.url).context(PreflightSnafu)?;
+    }
+
+    let supported_currencies = Arc::new(load_supported_currencies(&args)?);
+
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
     // Initialize quote storage
     let quote_storage = Arc::new(
-        QuoteStorage::new(&args.database_url, &mut join_set)
-            .await
-            .context(QuoteStorageSnafu)?,
+        QuoteStorage::new(
+            &args.database_url,
+            chrono::Duration::hours(i64::from(args.quote_retention_hours)),
+            clock.clone(),
+            &mut join_set,
+        )
+        .await
+        .context(QuoteStorageSnafu)?,
     );
 
+    match quote_storage.database_now().await {
+        Ok(database_now) => {
+            check_clock_drift(clock.as_ref(), database_now);
+        }
+        Err(e) => warn!("Failed to read the database clock: {}", e),
+    }
+
     let esplora_client = esplora_client::Builder::new(&args.bitcoin_wallet_esplora_url)
         .build_async()
         .context(EsploraInitializationSnafu)?;
 
+    // Quotes and fills only ever see the trading bucket of each wallet
+    let wallet_buckets = WalletBuckets::load(quote_storage.clone())
+        .await
+        .context(WalletBucketsSnafu)?
+        .with_currencies(
+            supported_currencies
+                .all()
+                .iter()
+                .map(SupportedCurrency::currency)
+                .collect(),
+        );
+
+    let bitcoin_wallet = Arc::new(open_bitcoin_wallet(&args, &mut join_set).await?);
     let mut wallet_manager = WalletManager::new();
     wallet_manager.register(
         ChainType::Bitcoin,
-        Arc::new(
-            BitcoinWallet::new(
-                &args.bitcoin_wallet_db_file,
-                &args.bitcoin_wallet_descriptor,
-                args.bitcoin_wallet_network,
-                &args.bitcoin_wallet_esplora_url,
-                &mut join_set,
-            )
-            .await
-            .context(BitcoinWalletSnafu)?,
+        layer(
+            ChainType::Bitcoin,
+            wallet_buckets.wrap(bitcoin_wallet.clone()),
         ),
     );
 
     let provider = Arc::new(
         create_websocket_wallet_provider(
             &args.ethereum_rpc_ws_url,
-            args.ethereum_wallet_private_key,
+            *args.ethereum_wallet_private_key.expose(),
         )
         .await?,
     );
+    let primary_chain_id = preflight::check_ethereum_chain_id(&*provider, args.ethereum_chain_id)
+        .await
+        .context(PreflightSnafu)?;
+    let fill_batching = args
+        .ethereum_fill_batch_window_ms
+        .map(|window_ms| FillBatchConfig {
+            window: Duration::from_millis(window_ms),
+            max_fills: args.ethereum_fill_batch_max_fills.max(1),
+        });
     let evm_wallet = Arc::new(EVMWallet::new(
         provider.clone(),
-        args.ethereum_rpc_ws_url,
+        args.ethereum_rpc_ws_url.clone(),
         args.ethereum_confirmations,
+        supported_currencies.clone(),
+        fill_batching,
         &mut join_set,
     ));
+    ensure_disperse_approvals(
+        &evm_wallet,
+        &supported_currencies,
+        ChainNetwork::primary(ChainType::Ethereum),
+    )
+    .await;
+    wallet_manager.register(
+        ChainType::Ethereum,
+        layer(ChainType::Ethereum, wallet_buckets.wrap(evm_wallet.clone())),
+    );
 
-    // TODO: something better than adhoc approval?
-    evm_wallet
-        .ensure_inf_approval_on_disperse(
-            &Address::from_str("0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf").unwrap(),
-        )
-        .await
-        .expect("Failed to ensure inf approval on disperse contract");
-
-    wallet_manager.register(ChainType::Ethereum, evm_wallet.clone());
-    let btc_eth_price_oracle = price_oracle::BitcoinEtherPriceOracle::new(&mut join_set);
+    // The same key pays out on every further network, each through its own node
+    let mut chain_ids = HashSet::from([primary_chain_id]);
+    let mut network_providers = Vec::new();
+    for network in &args.ethereum_network_rpc_ws_urls {
+        ensure!(
+            chain_ids.insert(network.chain_id),
+            DuplicateEvmNetworkSnafu {
+                chain_id: network.chain_id
+            }
+        );
+        let network_provider = Arc::new(
+            create_websocket_wallet_provider(
+                &network.url,
+                *args.ethereum_wallet_private_key.expose(),
+            )
+            .await?,
+        );
+        preflight::check_ethereum_chain_id(&*network_provider, Some(network.chain_id))
+            .await
+            .context(PreflightSnafu)?;
+        let network_wallet = Arc::new(
+            EVMWallet::new(
+                network_provider.clone(),
+                network.url.clone(),
+                args.ethereum_confirmations,
+                supported_currencies.clone(),
+                fill_batching,
+                &mut join_set,
+            )
+            .with_chain_id(network.chain_id),
+        );
+        let chain_network = ChainNetwork::evm(network.chain_id);
+        ensure_disperse_approvals(&network_wallet, &supported_currencies, chain_network).await;
+        wallet_manager.register_network(
+            chain_network,
+            layer(ChainType::Ethereum, wallet_buckets.wrap(network_wallet)),
+        );
+        info!("Filling on EVM network {}", network.chain_id);
+        network_providers.push((network.chain_id, network_provider));
+    }
+    let reconciliation_wallets = wallet_manager.clone();
+    let reconciliation_interval =
+        Duration::from_secs(args.wallet_bucket_reconciliation_interval_seconds.max(1));
+    let drift_tolerance_bps = args.wallet_bucket_drift_tolerance_bps;
+    join_set.spawn(async move {
+        wallet_buckets
+            .run_reconciliation(
+                reconciliation_wallets,
+                reconciliation_interval,
+                drift_tolerance_bps,
+            )
+            .await;
+        Ok(())
+    });
+    let btc_eth_price_oracle = if args.simulation_mode {
+        price_oracle::BitcoinEtherPriceOracle::fixed(args.simulated_btc_per_eth)
+    } else {
+        price_oracle::BitcoinEtherPriceOracle::new(&mut join_set)
+    };
+
+    let oracle = btc_eth_price_oracle.clone();
+    readiness.spawn_probe(ReadinessCheck::PriceAvailable, move || {
+        let oracle = oracle.clone();
+        async move { oracle.has_price().await }
+    });
+    let wallet = bitcoin_wallet.clone();
+    readiness.spawn_probe(ReadinessCheck::BitcoinWalletSynced, move || {
+        let wallet = wallet.clone();
+        async move { wallet.last_synced_at().await.is_some() }
+    });
+    let evm_provider = provider.clone();
+    readiness.spawn_probe(ReadinessCheck::EvmProviderConnected, move || {
+        let evm_provider = evm_provider.clone();
+        async move { evm_provider.get_block_number().await.is_ok() }
+    });
 
+    let fee_estimator: Arc<dyn FeeEstimator> = if args.simulation_mode {
+        Arc::new(FixedFeeEstimator)
+    } else {
+        Arc::new(network_providers.into_iter().fold(
+            ChainFeeEstimator::new(esplora_client, provider.clone().erased()),
+            |estimator, (chain_id, provider)| {
+                estimator.with_network_provider(chain_id, provider.erased())
+            },
+        ))
+    };
     let wrapped_bitcoin_quoter = WrappedBitcoinQuoter::new(
-        btc_eth_price_oracle,
-        esplora_client,
-        provider.clone().erased(),
-        args.trade_spread_bps,
-        args.fee_safety_multiplier,
+        btc_eth_price_oracle.clone(),
+        fee_estimator,
+        bitcoin_wallet,
+        pricing,
+        protocol_fee,
+        supported_currencies,
+        clock.clone(),
     );
+    let wrapped_bitcoin_quoter = Arc::new(if args.simulation_mode {
+        wrapped_bitcoin_quoter.in_simulation()
+    } else {
+        wrapped_bitcoin_quoter
+    });
 
-    let otc_fill_client = otc_client::OtcFillClient::new(
-        Config {
+    let validation_policy: Arc<dyn ValidationPolicy> = if args.auto_accept {
+        info!("Auto accepting quotes the OTC server asks to fill");
+        Arc::new(AutoAcceptPolicy)
+    } else {
+        Arc::new(StrictValidationPolicy::new(
             market_maker_id,
-            api_key_id: args.api_key_id.clone(),
-            api_key: args.api_key.clone(),
-            otc_ws_url: args.otc_ws_url.clone(),
-            reconnect_interval_secs: 5,
-            max_reconnect_attempts: 5,
-        },
+            wrapped_bitcoin_quoter.clone(),
+            wallet_manager.clone(),
+            args.quote_price_tolerance_bps,
+        ))
+    };
+
+    let attestation = attestation_verifier(&args)?;
+
+    let otc_fill_client = otc_client::OtcFillClient::new(
+        client_config(&args, market_maker_id, protocol_fee, attestation),
         wallet_manager.clone(),
         quote_storage.clone(),
+        args.bitcoin_wallet_network,
+        validation_policy,
+        btc_eth_price_oracle,
+        clock,
     );
-    join_set.spawn(async move { otc_fill_client.run().await.map_err(Error::from) });
+    // Registering with the OTC server only once ready keeps swaps from being
+    // validated against a wallet that hasn't seen its balance yet, so it's
+    // never told we're paused
+    let otc_readiness = readiness.clone();
+    join_set.spawn(async move {
+        otc_readiness
+            .once_ready(otc_fill_client.run())
+            .await
+            .map_err(Error::from)
+    });
 
     // Add RFQ client for handling quote requests
     let rfq_client = rfq_client::RfqClient::new(
-        Config {
-            market_maker_id,
-            api_key_id: args.api_key_id,
-            api_key: args.api_key,
-            otc_ws_url: args.otc_ws_url,
-            reconnect_interval_secs: 5,
-            max_reconnect_attempts: 5,
-        },
-        args.rfq_ws_url,
+        // The RFQ server holds no funds and runs outside the enclave
+        client_config(&args, market_maker_id, protocol_fee, None),
+        rfq_placement,
         wrapped_bitcoin_quoter,
         quote_storage,
         wallet_manager,
+        readiness,
     );
-    join_set.spawn(async move {
-        rfq_client.run().await.map_err(|e| Error::Client {
-            source: otc_client::ClientError::BackgroundThreadExited {
-                source: Box::new(e),
-            },
-        })
-    });
+    join_set.spawn(async move { rfq_client.run().await.context(RfqClientSnafu) });
+
+    // Every task runs for the life of the process, the first one to end takes
+    // the market maker down with its error
+    match join_set.join_next().await {
+        Some(Ok(Err(e))) => Err(e),
+        Some(Err(e)) => Err(Error::BackgroundTaskJoin { source: e }),
+        Some(Ok(Ok(()))) | None => BackgroundTaskExitedSnafu.fail(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const API_KEY: &str = "mm-api-key-for-debug-test";
+    const DESCRIPTOR: &str = "wpkh(cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy)";
+    const ETHEREUM_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    /// Every required arg, followed by `extra`
+    fn parse_args(extra: &[&str]) -> Result<MarketMakerArgs, clap::Error> {
+        let market_maker_id = Uuid::new_v4().to_string();
+        let api_key_id = Uuid::new_v4().to_string();
+        MarketMakerArgs::try_parse_from(
+            [
+                "market-maker",
+                "--market-maker-id",
+                &market_maker_id,
+                "--api-key-id",
+                &api_key_id,
+                "--api-key",
+                API_KEY,
+                "--bitcoin-wallet-db-file",
+                "wallet.db",
+                "--bitcoin-wallet-descriptor",
+                DESCRIPTOR,
+                "--bitcoin-wallet-change-descriptor",
+                DESCRIPTOR,
+                "--bitcoin-wallet-esplora-url",
+                "http://localhost:3002",
+                "--ethereum-wallet-private-key",
+                ETHEREUM_KEY,
+                "--ethereum-rpc-ws-url",
+                "ws://localhost:8545",
+                "--database-url",
+                "postgres://localhost/mm",
+            ]
+            .into_iter()
+            .chain(extra.iter().copied()),
+        )
+    }
 
-    handle_background_thread_result(join_set.join_next().await).context(BackgroundThreadSnafu)?;
+    #[test]
+    fn test_debug_output_has_no_key_material() {
+        let args = parse_args(&[]).unwrap();
+        assert_eq!(args.api_key.expose(), API_KEY);
+        let config = client_config(&args, Uuid::new_v4(), ProtocolFeeParams::DEFAULT, None);
+
+        let key_bytes = format!("{:?}", args.ethereum_wallet_private_key.expose());
+        for debug in [format!("{args:?}"), format!("{config:?}")] {
+            assert!(!debug.contains(API_KEY), "{debug}");
+            assert!(!debug.contains(DESCRIPTOR), "{debug}");
+            assert!(!debug.contains(ETHEREUM_KEY), "{debug}");
+            assert!(!debug.contains(&key_bytes), "{debug}");
+        }
+    }
 
-    Ok(())
+    #[test]
+    fn test_wallet_intervals_of_zero_are_refused() {
+        for arg in [
+            "--bitcoin-wallet-sync-interval-seconds",
+            "--bitcoin-wallet-consolidation-interval-seconds",
+        ] {
+            assert!(parse_args(&[arg, "0"]).is_err(), "{arg}");
+            assert!(parse_args(&[arg, "1"]).is_ok(), "{arg}");
+        }
+    }
 }
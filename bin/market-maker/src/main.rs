@@ -1,12 +1,40 @@
-use clap::Parser;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
 use blockchain_utils::init_logger;
-use market_maker::{run_market_maker, MarketMakerArgs};
+use market_maker::{exit::shutdown_reason, quotes_cli, run_market_maker, MarketMakerArgs};
+use tracing::error;
 
 #[tokio::main]
-async fn main() -> market_maker::Result<()> {
+async fn main() -> ExitCode {
+    if std::env::args()
+        .nth(1)
+        .is_some_and(|arg| quotes_cli::Tool::has_subcommand(&arg))
+    {
+        return match quotes_cli::run(quotes_cli::Tool::parse()).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
     let args = MarketMakerArgs::parse();
 
     init_logger(&args.log_level).expect("Logger should initialize");
 
-    run_market_maker(args).await
-}
\ No newline at end of file
+    match run_market_maker(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let reason = shutdown_reason(&e);
+            error!(
+                reason = reason.as_str(),
+                exit_code = reason.exit_code(),
+                error = %e,
+                "Market maker stopped"
+            );
+            ExitCode::from(reason.exit_code())
+        }
+    }
+}
@@ -1,17 +1,33 @@
+use crate::capabilities::{self, CapabilitiesError};
 use crate::otc_handler::OTCMessageHandler;
+use crate::price_oracle::BitcoinEtherPriceOracle;
 use crate::quote_storage::QuoteStorage;
+use crate::strategy::ValidationPolicy;
 use crate::{config::Config, wallet::WalletManager};
+use alloy::primitives::B256;
+use bdk_wallet::bitcoin;
+use common::{
+    is_auth_rejection, Clock, ConnectionEnd, ReconnectError, ReconnectingWsClient, WsStream,
+};
 use futures_util::{SinkExt, StreamExt};
-use otc_protocols::mm::{MMRequest, ProtocolMessage};
+use otc_protocols::{
+    attestation::{AttestationError, ATTESTATION_NONCE_HEADER},
+    mm::{Connected, MMRequest, ProtocolMessage, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER},
+};
+use serde::Deserialize;
 use snafu::prelude::*;
-use std::sync::Arc;
-use tokio::time::{sleep, Duration};
+use std::{sync::Arc, time::Duration};
+use tokio::time::timeout;
 use tokio_tungstenite::{
     connect_async_with_config,
-    tungstenite::{http, Message},
+    tungstenite::{http, protocol::frame::coding::CloseCode, Message},
 };
 use tracing::{error, info, warn};
 use url::Url;
+use zeroize::Zeroize;
+
+/// How long the server has to send its Connected message after the upgrade
+const CONNECTED_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Snafu)]
 pub enum ClientError {
@@ -31,19 +47,65 @@ pub enum ClientError {
     #[snafu(display("Message serialization error: {}", source))]
     Serialization { source: serde_json::Error },
 
-    #[snafu(display("Maximum reconnection attempts reached"))]
-    MaxReconnectAttempts,
-    #[snafu(display("Background thread exited: {}", source))]
-    BackgroundThreadExited {
-        source: Box<dyn std::error::Error + Send + Sync>,
+    #[snafu(display("Capability negotiation failed: {}", source))]
+    Capabilities { source: CapabilitiesError },
+
+    #[snafu(display("Expected a Connected message from the OTC server: {}", source))]
+    UnexpectedHandshake { source: serde_json::Error },
+
+    #[snafu(display("OTC server closed the connection before sending Connected"))]
+    HandshakeClosed,
+
+    #[snafu(display("OTC server sent no Connected message within {:?}", CONNECTED_TIMEOUT))]
+    ConnectedTimeout,
+
+    #[snafu(display("OTC server failed attestation, refusing to trust it: {}", source))]
+    Attestation { source: AttestationError },
+
+    #[snafu(display("Gave up after {} reconnection attempts: {}", attempts, source))]
+    MaxReconnectAttempts {
+        attempts: u32,
+        source: Box<ClientError>,
     },
 }
 
 type Result<T, E = ClientError> = std::result::Result<T, E>;
 
+/// Sequence numbers seen on one connection, the OTC server numbers what it
+/// sends from 1 with no gaps
+#[derive(Debug, Default)]
+struct SequenceCheck {
+    last: u64,
+}
+
+impl SequenceCheck {
+    /// Messages lost just before the one numbered `sequence`
+    fn missed_before(&mut self, sequence: u64) -> u64 {
+        // Servers that don't number their messages leave every one at 0
+        if sequence == 0 {
+            return 0;
+        }
+        let missed = sequence.saturating_sub(self.last + 1);
+        self.last = self.last.max(sequence);
+        missed
+    }
+}
+
+impl ClientError {
+    /// Whether the OTC server turned the API key away, retrying won't help
+    #[must_use]
+    pub fn is_auth_rejection(&self) -> bool {
+        match self {
+            Self::WebSocketConnection { source } => is_auth_rejection(source),
+            Self::MaxReconnectAttempts { source, .. } => source.is_auth_rejection(),
+            _ => false,
+        }
+    }
+}
+
 pub struct OtcFillClient {
-    config: Config,
     handler: OTCMessageHandler,
+    connection: ReconnectingWsClient<WsStream, ClientError>,
 }
 
 impl OtcFillClient {
@@ -51,96 +113,81 @@ impl OtcFillClient {
         config: Config,
         wallet_manager: WalletManager,
         quote_storage: Arc<QuoteStorage>,
+        bitcoin_network: bitcoin::Network,
+        validation_policy: Arc<dyn ValidationPolicy>,
+        price_oracle: BitcoinEtherPriceOracle,
+        clock: Arc<dyn Clock>,
     ) -> Self {
-        let handler = OTCMessageHandler::new(config.clone(), wallet_manager, quote_storage);
-        Self { config, handler }
+        let handler = OTCMessageHandler::new(
+            config.clone(),
+            wallet_manager,
+            quote_storage,
+            bitcoin_network,
+            validation_policy,
+            price_oracle,
+            clock,
+        );
+        let connection =
+            ReconnectingWsClient::new("OTC server", config.reconnect_options(), move || {
+                connect(config.clone())
+            })
+            .with_fatal_errors(ClientError::is_auth_rejection);
+        Self {
+            handler,
+            connection,
+        }
     }
 
     pub async fn run(&self) -> Result<()> {
-        let mut reconnect_attempts = 0;
-
-        loop {
-            match self.connect_and_run().await {
-                Ok(()) => {
-                    info!("WebSocket connection closed normally");
-                    reconnect_attempts = 0;
-                }
-                Err(e) => {
-                    error!("WebSocket error: {}", e);
-                    reconnect_attempts += 1;
-
-                    if reconnect_attempts >= self.config.max_reconnect_attempts {
-                        return Err(ClientError::MaxReconnectAttempts);
+        self.connection
+            .run(|ws_stream| self.handle_connection(ws_stream))
+            .await
+            .map_err(|e| match e {
+                ReconnectError::MaxAttempts { attempts, source } => {
+                    ClientError::MaxReconnectAttempts {
+                        attempts,
+                        source: Box::new(source),
                     }
-
-                    let delay = Duration::from_secs(
-                        self.config.reconnect_interval_secs * u64::from(reconnect_attempts),
-                    );
-                    warn!(
-                        "Reconnecting in {} seconds (attempt {}/{})",
-                        delay.as_secs(),
-                        reconnect_attempts,
-                        self.config.max_reconnect_attempts
-                    );
-                    sleep(delay).await;
                 }
-            }
-        }
+                ReconnectError::Fatal { source } => source,
+            })
     }
 
-    // TODO(tee): When TEE logic is implemented, we need a way to validate that we're connected to a valid TEE
-    async fn connect_and_run(&self) -> Result<()> {
-        let url = Url::parse(&self.config.otc_ws_url).context(UrlParseSnafu)?;
-        info!("Connecting to {}", url);
-
-        // Build request with authentication headers
-        let request = http::Request::builder()
-            .method("GET")
-            .uri(url.as_str())
-            .header("Host", url.host_str().unwrap_or("localhost"))
-            .header("Connection", "Upgrade")
-            .header("Upgrade", "websocket")
-            .header("Sec-WebSocket-Version", "13")
-            .header(
-                "Sec-WebSocket-Key",
-                tokio_tungstenite::tungstenite::handshake::client::generate_key(),
-            )
-            .header("X-API-Key-ID", &self.config.api_key_id)
-            .header("X-API-Key", &self.config.api_key)
-            .body(())
-            .map_err(|e| ClientError::WebSocketConnection {
-                source: tokio_tungstenite::tungstenite::Error::Http(
-                    http::Response::builder()
-                        .status(400)
-                        .body(Some(format!("Failed to build request: {e}").into_bytes()))
-                        .unwrap(),
-                ),
-            })?;
-
-        let (ws_stream, _) = connect_async_with_config(request, None, false)
-            .await
-            .context(WebSocketConnectionSnafu)?;
-
-        info!("WebSocket connected, authenticated via headers");
-
+    async fn handle_connection(&self, ws_stream: WsStream) -> Result<ConnectionEnd> {
         let (mut write, mut read) = ws_stream.split();
+        let mut sequence_check = SequenceCheck::default();
 
         // Handle messages
         while let Some(msg) = read.next().await {
             match msg {
-                Ok(Message::Text(text)) => {
-                    // First check if it's a Connected response
-                    if text.contains("Connected") {
-                        info!("Received Connected acknowledgment from server");
-                        continue;
-                    }
-
+                Ok(Message::Text(mut text)) => {
                     // TODO: Do we want to support concurrent messaging?
                     // Otherwise, try to parse as a protocol message
                     match serde_json::from_str::<ProtocolMessage<MMRequest>>(&text) {
-                        Ok(protocol_msg) => {
-                            if let Some(response) = self.handler.handle_request(&protocol_msg).await
+                        Ok(mut protocol_msg) => {
+                            let missed = sequence_check.missed_before(protocol_msg.sequence);
+                            if missed > 0 {
+                                error!(
+                                    "Missed {} messages from the OTC server before message {}",
+                                    missed, protocol_msg.sequence
+                                );
+                            }
+                            if let MMRequest::GoingAway { reason, .. } = &protocol_msg.payload {
+                                info!("OTC server is going away: {}", reason);
+                                return Ok(ConnectionEnd::GoingAway);
+                            }
+                            let response = self.handler.handle_request(&protocol_msg).await;
+                            // The user's deposit key has been checked, wipe it and
+                            // the frame it came in rather than leave it to the allocator
+                            if let MMRequest::SwapComplete {
+                                user_deposit_private_key,
+                                ..
+                            } = &mut protocol_msg.payload
                             {
+                                user_deposit_private_key.zeroize();
+                                text.zeroize();
+                            }
+                            if let Some(response) = response {
                                 let response_json =
                                     serde_json::to_string(&response).context(SerializationSnafu)?;
                                 write
@@ -154,8 +201,18 @@ impl OtcFillClient {
                         }
                     }
                 }
-                Ok(Message::Close(_)) => {
-                    info!("Server closed connection");
+                Ok(Message::Close(frame)) => {
+                    match &frame {
+                        // A revoked or rotated key, the reconnect that follows
+                        // is turned away and stops the market maker
+                        Some(frame) if frame.code == CloseCode::Policy => {
+                            warn!("Server closed connection: {}", frame.reason);
+                        }
+                        _ => info!("Server closed connection"),
+                    }
+                    if frame.is_some_and(|frame| frame.code == CloseCode::Away) {
+                        return Ok(ConnectionEnd::GoingAway);
+                    }
                     break;
                 }
                 Err(e) => {
@@ -166,6 +223,131 @@ impl OtcFillClient {
             }
         }
 
-        Ok(())
+        Ok(ConnectionEnd::Closed)
+    }
+}
+
+/// Connect, authenticate and verify the server's attestation once, then hang up
+pub(crate) async fn check_connection(config: Config) -> Result<()> {
+    let mut ws_stream = connect(config).await?;
+    let _ = ws_stream.close(None).await;
+    Ok(())
+}
+
+async fn connect(config: Config) -> Result<WsStream> {
+    // Renegotiated on every connect, the server may have been redeployed
+    capabilities::negotiate(&config.otc_ws_url)
+        .await
+        .context(CapabilitiesSnafu)?;
+
+    let url = Url::parse(&config.otc_ws_url).context(UrlParseSnafu)?;
+    info!("Connecting to {}", url);
+
+    // A fresh nonce per connection, so a recorded attestation can't be replayed
+    let mut nonce = [0u8; 32];
+    getrandom::getrandom(&mut nonce).expect("Failed to generate random nonce");
+    let attestation_nonce = B256::from(nonce);
+
+    // Build request with authentication headers
+    let request = http::Request::builder()
+        .method("GET")
+        .uri(url.as_str())
+        .header("Host", url.host_str().unwrap_or("localhost"))
+        .header("Connection", "Upgrade")
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Version", "13")
+        .header(
+            "Sec-WebSocket-Key",
+            tokio_tungstenite::tungstenite::handshake::client::generate_key(),
+        )
+        .header("X-API-Key-ID", &config.api_key_id)
+        .header("X-API-Key", config.api_key.expose())
+        .header(PROTOCOL_VERSION_HEADER, PROTOCOL_VERSION)
+        .header(ATTESTATION_NONCE_HEADER, attestation_nonce.to_string())
+        .body(())
+        .map_err(|e| ClientError::WebSocketConnection {
+            source: tokio_tungstenite::tungstenite::Error::Http(
+                http::Response::builder()
+                    .status(400)
+                    .body(Some(format!("Failed to build request: {e}").into_bytes()))
+                    .unwrap(),
+            ),
+        })?;
+
+    let (mut ws_stream, _) = connect_async_with_config(request, None, false)
+        .await
+        .context(WebSocketConnectionSnafu)?;
+
+    info!("WebSocket connected, authenticated via headers");
+
+    // Nothing the server sends is acted on until its attestation checks out
+    let connected = timeout(CONNECTED_TIMEOUT, read_connected(&mut ws_stream))
+        .await
+        .map_err(|_| ClientError::ConnectedTimeout)??;
+    match &config.attestation {
+        Some(verifier) => {
+            verifier
+                .verify(
+                    connected.attestation.as_ref(),
+                    &config.otc_ws_url,
+                    &attestation_nonce,
+                    chrono::Utc::now(),
+                )
+                .context(AttestationSnafu)?;
+            info!(
+                "Verified OTC server attestation of measurement {}",
+                verifier.expected_measurement
+            );
+        }
+        None => warn!("Trusting the OTC server without verifying its attestation"),
+    }
+
+    Ok(ws_stream)
+}
+
+/// The server's first frame, `{"Connected": Connected}`
+async fn read_connected(ws_stream: &mut WsStream) -> Result<Connected> {
+    #[derive(Deserialize)]
+    struct ConnectedFrame {
+        #[serde(rename = "Connected")]
+        connected: Connected,
+    }
+
+    loop {
+        match ws_stream.next().await {
+            Some(Ok(Message::Text(text))) => {
+                let frame: ConnectedFrame =
+                    serde_json::from_str(&text).context(UnexpectedHandshakeSnafu)?;
+                info!(
+                    "Connected to OTC server {} (session {})",
+                    frame.connected.server_version, frame.connected.session_id
+                );
+                return Ok(frame.connected);
+            }
+            Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+            Some(Ok(_)) | None => return HandshakeClosedSnafu.fail(),
+            Some(Err(source)) => return Err(ClientError::WebSocketConnection { source }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_gaps_are_counted() {
+        let mut check = SequenceCheck::default();
+        assert_eq!(check.missed_before(1), 0);
+        assert_eq!(check.missed_before(2), 0);
+        assert_eq!(check.missed_before(5), 2);
+        assert_eq!(check.missed_before(6), 0);
+        // Unnumbered messages are never counted as a gap
+        assert_eq!(check.missed_before(0), 0);
+        assert_eq!(check.missed_before(7), 0);
+
+        // A fresh connection numbers from 1 again
+        let mut check = SequenceCheck::default();
+        assert_eq!(check.missed_before(3), 2);
     }
 }
@@ -1,19 +1,42 @@
-use crate::quote_storage::QuoteStorage;
-use crate::strategy::ValidationStrategy;
-use crate::{config::Config, wallet::WalletManager};
+use crate::price_oracle::BitcoinEtherPriceOracle;
+use crate::quote_storage::{FillClaim, QuoteStorage, QuoteStorageError, SwapProgress};
+use crate::strategy::{check_not_expired, ValidationPolicy};
+use crate::{
+    config::Config,
+    wallet::{self, with_reservations, FailedPayment, TransactionResult, WalletManager},
+};
 use alloy::primitives::U256;
 use chrono::Utc;
+use bdk_wallet::bitcoin;
 use blockchain_utils::FeeCalcFromLot;
-use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_protocols::mm::{MMErrorCode, MMRequest, MMResponse, MMStatus, ProtocolMessage};
+use common::Clock;
+use dashmap::DashMap;
+use otc_chains::{deposit_key, traits::MarketMakerPaymentValidation};
+use otc_models::{
+    external_reference_for_log, ChainType, FillCost, Lot, Quote, SwapStatus, TokenIdentifier,
+    TxHash, CBBTC_ADDRESS,
+};
+use otc_protocols::mm::{
+    MMErrorCode, MMRequest, MMResponse, MMStatus, ProtocolMessage, QuoteRejection,
+    SwapFailureReason,
+};
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use tracing::{error, info, info_span, warn, Instrument};
+use uuid::Uuid;
 
 pub struct OTCMessageHandler {
     config: Config,
-    strategy: ValidationStrategy,
+    validation_policy: Arc<dyn ValidationPolicy>,
     wallet_manager: WalletManager,
     quote_storage: Arc<QuoteStorage>,
+    bitcoin_network: bitcoin::Network,
+    /// User deposit addresses from `UserDepositConfirmed`, checked against the
+    /// key released at settlement
+    deposit_addresses: DashMap<Uuid, (ChainType, String)>,
+    /// Converts ether fees into sats for the fill costs we report
+    price_oracle: BitcoinEtherPriceOracle,
+    /// Quotes we're asked to fill are checked for expiry against this clock
+    clock: Arc<dyn Clock>,
 }
 
 impl OTCMessageHandler {
@@ -21,19 +44,37 @@ impl OTCMessageHandler {
         config: Config,
         wallet_manager: WalletManager,
         quote_storage: Arc<QuoteStorage>,
+        bitcoin_network: bitcoin::Network,
+        validation_policy: Arc<dyn ValidationPolicy>,
+        price_oracle: BitcoinEtherPriceOracle,
+        clock: Arc<dyn Clock>,
     ) -> Self {
-        let strategy = ValidationStrategy::new();
         Self {
             config,
-            strategy,
+            validation_policy,
             wallet_manager,
             quote_storage,
+            bitcoin_network,
+            deposit_addresses: DashMap::new(),
+            price_oracle,
+            clock,
         }
     }
 
     pub async fn handle_request(
         &self,
         msg: &ProtocolMessage<MMRequest>,
+    ) -> Option<ProtocolMessage<MMResponse>> {
+        let span = info_span!(
+            "otc_request",
+            trace_id = msg.trace_id.as_deref().unwrap_or_default()
+        );
+        self.handle_payload(msg).instrument(span).await
+    }
+
+    async fn handle_payload(
+        &self,
+        msg: &ProtocolMessage<MMRequest>,
     ) -> Option<ProtocolMessage<MMResponse>> {
         match &msg.payload {
             MMRequest::ValidateQuote {
@@ -48,43 +89,21 @@ impl OTCMessageHandler {
                     quote_id, user_destination_address
                 );
 
-                // Verify the quote exists in our database
-                let quote_exists = match self.quote_storage.get_quote(*quote_id).await {
-                    Ok(quote) => {
-                        info!(
-                            "Found quote {} in database, hash: {:?}",
-                            quote_id,
-                            quote.hash()
-                        );
-                        // Verify the hash matches
-                        if quote.hash() != *quote_hash {
-                            warn!(
-                                "Quote {} hash mismatch! Expected: {:?}, Got: {:?}",
-                                quote_id,
-                                quote.hash(),
-                                quote_hash
-                            );
-                        }
-                        true
-                    }
-                    Err(e) => {
-                        error!("Failed to retrieve quote {} from database: {}", quote_id, e);
-                        false
-                    }
-                };
-
-                let (accepted, rejection_reason) = if quote_exists {
-                    self.strategy
-                        .validate_quote(quote_id, quote_hash, user_destination_address)
-                } else {
-                    (false, Some("Quote not found in database".to_string()))
-                };
+                let rejection_reason = self.validate_quote(*quote_id, quote_hash).await.err();
+                let accepted = rejection_reason.is_none();
 
                 info!(
                     "Quote {} validation result: accepted={}, reason={:?}",
-                    quote_id, accepted, &rejection_reason
+                    quote_id, accepted, rejection_reason
                 );
 
+                // Keep the quote past the retention period now that a swap references it
+                if accepted {
+                    if let Err(e) = self.quote_storage.mark_accepted(*quote_id).await {
+                        error!("Failed to mark quote {} as accepted: {}", quote_id, e);
+                    }
+                }
+
                 let response = MMResponse::QuoteValidated {
                     request_id: *request_id,
                     quote_id: *quote_id,
@@ -97,6 +116,7 @@ impl OTCMessageHandler {
                     version: msg.version.clone(),
                     sequence: msg.sequence + 1,
                     payload: response,
+                    trace_id: msg.trace_id.clone(),
                 })
             }
 
@@ -130,58 +150,149 @@ impl OTCMessageHandler {
                 user_destination_address,
                 mm_nonce,
                 expected_lot,
+                user_deposit_address,
+                user_deposit_chain,
+                external_reference,
                 ..
             } => {
                 info!(
                     message = "User deposit confirmed for swap {swap_id}: MM should send {expected_lot:?} to {user_destination_address}",
                     quote_id = quote_id.to_string(),
+                    external_reference = ?external_reference.as_deref().map(external_reference_for_log),
                 );
 
-                // TODO: We should have additional safety checks here to ensure the user's deposit is valid
-                // instead of trusting the TEE
-                let wallet = self.wallet_manager.get(expected_lot.currency.chain);
-                let response: MMResponse = {
-                    if let Some(wallet) = wallet {
-                        let tx_result = wallet
-                            .create_payment(
-                                expected_lot,
-                                user_destination_address,
-                                Some(MarketMakerPaymentValidation {
-                                    fee_amount: U256::from(expected_lot.compute_protocol_fee()),
-                                    embedded_nonce: *mm_nonce,
-                                }),
-                            )
-                            .await;
-
-                        match tx_result {
-                            Ok(txid) => MMResponse::DepositInitiated {
-                                request_id: *request_id,
-                                swap_id: *swap_id,
-                                tx_hash: txid,
-                                amount_sent: expected_lot.amount,
-                                timestamp: Utc::now(),
-                            },
-                            Err(e) => MMResponse::Error {
-                                request_id: *request_id,
-                                error_code: MMErrorCode::InternalError,
-                                message: e.to_string(),
-                                timestamp: Utc::now(),
-                            },
-                        }
-                    } else {
-                        MMResponse::Error {
+                // TODO: We still trust the TEE that the user's deposit is valid
+                let response = match self
+                    .verify_fill(
+                        *swap_id,
+                        *quote_id,
+                        user_destination_address,
+                        expected_lot,
+                        false,
+                    )
+                    .await
+                {
+                    Ok(()) => {
+                        self.deposit_addresses.insert(
+                            *swap_id,
+                            (*user_deposit_chain, user_deposit_address.clone()),
+                        );
+                        self.pay_out(
+                            *request_id,
+                            *swap_id,
+                            *quote_id,
+                            user_destination_address,
+                            *mm_nonce,
+                            expected_lot,
+                        )
+                        .await
+                    }
+                    Err(rejection) => refuse_fill(*request_id, *swap_id, rejection),
+                };
+
+                Some(ProtocolMessage {
+                    version: msg.version.clone(),
+                    sequence: msg.sequence + 1,
+                    payload: response,
+                    trace_id: msg.trace_id.clone(),
+                })
+            }
+
+            MMRequest::DepositRetryRequested {
+                request_id,
+                swap_id,
+                quote_id,
+                failed_tx_hash,
+                user_destination_address,
+                mm_nonce,
+                expected_lot,
+                attempt,
+                deadline,
+                ..
+            } => {
+                warn!(
+                    "OTC server asked us to retry deposit {} for swap {} (attempt {})",
+                    failed_tx_hash, swap_id, attempt
+                );
+                let error = |error_code, message: String| MMResponse::Error {
+                    request_id: *request_id,
+                    error_code,
+                    message,
+                    timestamp: Utc::now(),
+                };
+                let response = match (
+                    self.quote_storage.fill_tx_hash(*quote_id).await,
+                    self.quote_storage.failure_reason(*quote_id).await,
+                ) {
+                    // An earlier answer to this retry may have been lost, report
+                    // the payment it made rather than paying twice
+                    (Ok(Some(tx_hash)), _) if tx_hash != *failed_tx_hash => {
+                        info!(
+                            "Already paid swap {} again with {}, reporting it",
+                            swap_id, tx_hash
+                        );
+                        MMResponse::DepositInitiated {
                             request_id: *request_id,
-                            error_code: MMErrorCode::UnsupportedChain,
-                            message: "No wallet found for chain".to_string(),
+                            swap_id: *swap_id,
+                            tx_hash,
+                            amount_sent: expected_lot.amount,
+                            fee: None,
+                            fill_cost: None,
                             timestamp: Utc::now(),
                         }
                     }
+                    (Ok(_), Ok(Some(reason))) => error(
+                        MMErrorCode::InvalidRequest,
+                        format!("Swap for quote {quote_id} already failed: {reason}"),
+                    ),
+                    (Ok(_), Ok(None)) if self.clock.now() > *deadline => error(
+                        MMErrorCode::InvalidRequest,
+                        format!("Deposit retry for swap {swap_id} is past its deadline {deadline}"),
+                    ),
+                    (Ok(_), Ok(None)) => match self
+                        .verify_fill(
+                            *swap_id,
+                            *quote_id,
+                            user_destination_address,
+                            expected_lot,
+                            true,
+                        )
+                        .await
+                    {
+                        Ok(()) => match self
+                            .replace_payout(
+                                *swap_id,
+                                *quote_id,
+                                failed_tx_hash,
+                                user_destination_address,
+                                *mm_nonce,
+                                expected_lot,
+                            )
+                            .await
+                        {
+                            Ok((protocol_fee, tx_result)) => {
+                                self.report_payout(
+                                    *request_id,
+                                    *swap_id,
+                                    *quote_id,
+                                    protocol_fee,
+                                    expected_lot,
+                                    tx_result,
+                                )
+                                .await
+                            }
+                            Err(rejection) => refuse_fill(*request_id, *swap_id, rejection),
+                        },
+                        Err(rejection) => refuse_fill(*request_id, *swap_id, rejection),
+                    },
+                    (Err(e), _) | (_, Err(e)) => error(MMErrorCode::InternalError, e.to_string()),
                 };
 
                 Some(ProtocolMessage {
                     version: msg.version.clone(),
                     sequence: msg.sequence + 1,
                     payload: response,
+                    trace_id: msg.trace_id.clone(),
                 })
             }
 
@@ -199,20 +310,165 @@ impl OTCMessageHandler {
                 // TODO: Implement claiming logic
                 warn!("TODO: Implement claiming from user's wallet");
 
-                let response = MMResponse::SwapCompleteAck {
-                    request_id: *request_id,
-                    swap_id: *swap_id,
-                    timestamp: Utc::now(),
+                let response = match self.deposit_addresses.remove(swap_id) {
+                    Some((_, (deposit_chain, deposit_address))) => {
+                        let matches = deposit_chain == *chain
+                            && self.key_controls_address(
+                                *chain,
+                                user_deposit_private_key.expose(),
+                                &deposit_address,
+                                swap_id,
+                            );
+                        if !matches {
+                            error!(
+                                "Key released for swap {} does not control deposit address {}",
+                                swap_id, deposit_address
+                            );
+                        }
+                        MMResponse::VerifyDepositKey {
+                            request_id: *request_id,
+                            swap_id: *swap_id,
+                            deposit_address,
+                            chain: deposit_chain,
+                            matches,
+                            timestamp: Utc::now(),
+                        }
+                    }
+                    None => {
+                        warn!(
+                            "No deposit address recorded for swap {}, can't verify its key",
+                            swap_id
+                        );
+                        MMResponse::SwapCompleteAck {
+                            request_id: *request_id,
+                            swap_id: *swap_id,
+                            timestamp: Utc::now(),
+                        }
+                    }
                 };
 
                 Some(ProtocolMessage {
                     version: msg.version.clone(),
                     sequence: msg.sequence + 1,
                     payload: response,
+                    trace_id: msg.trace_id.clone(),
+                })
+            }
+
+            MMRequest::MMDepositRejected {
+                swap_id,
+                quote_id,
+                tx_hash,
+                error_code,
+                expected_amount,
+                received_amount,
+                ..
+            } => {
+                error!(
+                    "Deposit {} for swap {} (quote {}) was rejected with {:?}: sent {}, expected {}",
+                    tx_hash, swap_id, quote_id, error_code, received_amount, expected_amount
+                );
+                // The user is refunded, no key is released for the swap
+                self.deposit_addresses.remove(swap_id);
+
+                None
+            }
+
+            MMRequest::SwapFailed {
+                swap_id,
+                quote_id,
+                reason_code,
+                refund_tx_hash,
+                ..
+            } => {
+                warn!(
+                    "Swap {} (quote {}) failed with {}, refund tx: {:?}",
+                    swap_id, quote_id, reason_code, refund_tx_hash
+                );
+
+                // Frees the funds the preparation claimed for other fills
+                if self
+                    .quote_storage
+                    .take_fill_preparation(*quote_id)
+                    .is_some()
+                {
+                    info!("Released fill preparation for quote {}", quote_id);
+                }
+                if self.quote_storage.release_quote_lock(*quote_id).is_some() {
+                    info!("Released lock on quote {}", quote_id);
+                }
+                self.deposit_addresses.remove(swap_id);
+                if let Err(e) = self
+                    .quote_storage
+                    .mark_failed(*quote_id, &reason_code.to_string())
+                    .await
+                {
+                    error!("Failed to mark quote {} as failed: {}", quote_id, e);
+                }
+
+                None
+            }
+
+            MMRequest::CancelRequested {
+                request_id,
+                swap_id,
+                quote_id,
+                ..
+            } => {
+                let accepted = self.give_up_fill(*swap_id, *quote_id).await;
+                Some(ProtocolMessage {
+                    version: msg.version.clone(),
+                    sequence: msg.sequence + 1,
+                    payload: MMResponse::CancelAcknowledged {
+                        request_id: *request_id,
+                        swap_id: *swap_id,
+                        accepted,
+                        timestamp: Utc::now(),
+                    },
+                    trace_id: msg.trace_id.clone(),
                 })
             }
 
+            MMRequest::SwapStatusUpdate {
+                swap_id,
+                quote_id,
+                status,
+                user_deposit_confirmations,
+                mm_deposit_confirmations,
+                updated_at,
+                ..
+            } => {
+                info!(
+                    "Swap {} (quote {}) is now {:?}, user deposit confirmations: {:?}, our deposit confirmations: {:?}",
+                    swap_id, quote_id, status, user_deposit_confirmations, mm_deposit_confirmations
+                );
+
+                let progress = SwapProgress {
+                    swap_id: *swap_id,
+                    quote_id: *quote_id,
+                    status: *status,
+                    user_deposit_confirmations: *user_deposit_confirmations,
+                    mm_deposit_confirmations: *mm_deposit_confirmations,
+                    updated_at: *updated_at,
+                };
+                if let Err(e) = self.quote_storage.record_swap_progress(&progress).await {
+                    error!("Failed to record status of swap {}: {}", swap_id, e);
+                }
+                if matches!(
+                    status,
+                    SwapStatus::Failed | SwapStatus::RefundingUser | SwapStatus::RefundingMM
+                ) {
+                    self.deposit_addresses.remove(swap_id);
+                }
+
+                None
+            }
+
+            // The connection reconnects on its own, nothing to answer
+            MMRequest::GoingAway { .. } => None,
+
             MMRequest::Ping { request_id, .. } => {
+                // We only register with the OTC server once warmed up
                 let response = MMResponse::Pong {
                     request_id: *request_id,
                     status: MMStatus::Active,
@@ -224,8 +480,1382 @@ impl OTCMessageHandler {
                     version: msg.version.clone(),
                     sequence: msg.sequence + 1,
                     payload: response,
+                    trace_id: msg.trace_id.clone(),
                 })
             }
         }
     }
+
+    /// Agree to an operator cancelling a swap we haven't paid, failing its quote
+    /// so it's never paid afterwards. Refused once a payout went out
+    async fn give_up_fill(&self, swap_id: Uuid, quote_id: Uuid) -> bool {
+        match self.quote_storage.fill_tx_hash(quote_id).await {
+            Ok(None) => {}
+            Ok(Some(tx_hash)) => {
+                warn!(
+                    "Refusing to cancel swap {}, we already paid it with {}",
+                    swap_id, tx_hash
+                );
+                return false;
+            }
+            Err(e) => {
+                error!("Failed to look up the payout of quote {}: {}", quote_id, e);
+                return false;
+            }
+        }
+        if let Err(e) = self
+            .quote_storage
+            .mark_failed(quote_id, &SwapFailureReason::Cancelled.to_string())
+            .await
+        {
+            error!("Failed to mark quote {} as cancelled: {}", quote_id, e);
+            return false;
+        }
+
+        self.quote_storage.take_fill_preparation(quote_id);
+        self.quote_storage.release_quote_lock(quote_id);
+        self.deposit_addresses.remove(&swap_id);
+        info!("Gave up filling swap {} (quote {})", swap_id, quote_id);
+        true
+    }
+
+    /// Check a fill the OTC server asks for pays out the quote we issued, to a
+    /// destination we pay, for a swap no other payout was claimed for. A
+    /// `retry` may pay a swap its quote was already claimed for again, as may a
+    /// repeated request for a swap whose payout was never recorded
+    async fn verify_fill(
+        &self,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        user_destination_address: &str,
+        expected_lot: &Lot,
+        retry: bool,
+    ) -> Result<(), QuoteRejection> {
+        let quote = match self.quote_storage.get_quote(quote_id).await {
+            Ok(quote) => quote,
+            Err(QuoteStorageError::Database {
+                source: sqlx::Error::RowNotFound,
+            }) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::QuoteNotFound,
+                    format!("Quote {quote_id} is not one we issued"),
+                ));
+            }
+            Err(e) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InternalError,
+                    format!("Failed to retrieve quote {quote_id}: {e}"),
+                ));
+            }
+        };
+
+        match self.quote_storage.failure_reason(quote_id).await {
+            Ok(None) => {}
+            Ok(Some(reason)) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InvalidRequest,
+                    format!("Swap for quote {quote_id} already failed: {reason}"),
+                ));
+            }
+            Err(e) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InternalError,
+                    format!("Failed to look up quote {quote_id}: {e}"),
+                ));
+            }
+        }
+
+        if !same_lot(expected_lot, &quote.to) {
+            return Err(QuoteRejection::new(
+                MMErrorCode::QuoteMismatch,
+                format!(
+                    "Asked to send {:?}, quote {} pays {:?}",
+                    expected_lot, quote_id, quote.to
+                ),
+            ));
+        }
+
+        // Bech32 and hex addresses are case insensitive, a base58 one differing
+        // in case only is blocked too rather than let through
+        if self
+            .config
+            .blocked_destination_addresses
+            .iter()
+            .any(|blocked| blocked.eq_ignore_ascii_case(user_destination_address))
+        {
+            return Err(QuoteRejection::new(
+                MMErrorCode::InvalidRequest,
+                format!("Destination {user_destination_address} is blocked"),
+            ));
+        }
+
+        match self.quote_storage.claim_fill(quote_id, swap_id).await {
+            Ok(FillClaim::Claimed) => Ok(()),
+            Ok(FillClaim::AlreadyClaimed) if retry => Ok(()),
+            Ok(FillClaim::AlreadyClaimed) => {
+                match self.quote_storage.fill_tx_hash(quote_id).await {
+                    Ok(None) => Ok(()),
+                    Ok(Some(tx_hash)) => Err(QuoteRejection::new(
+                        MMErrorCode::InvalidRequest,
+                        format!("Swap {swap_id} was already filled with {tx_hash}"),
+                    )),
+                    Err(e) => Err(QuoteRejection::new(
+                        MMErrorCode::InternalError,
+                        format!("Failed to look up the payout of quote {quote_id}: {e}"),
+                    )),
+                }
+            }
+            Ok(FillClaim::Conflict) => Err(QuoteRejection::new(
+                MMErrorCode::InvalidRequest,
+                format!("Quote {quote_id} or swap {swap_id} belongs to another fill"),
+            )),
+            Err(e) => Err(QuoteRejection::new(
+                MMErrorCode::InternalError,
+                format!("Failed to claim quote {quote_id} for swap {swap_id}: {e}"),
+            )),
+        }
+    }
+
+    /// Pay the user their side of the swap and record the fill, answering
+    /// with the deposit or why it couldn't be made
+    async fn pay_out(
+        &self,
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        user_destination_address: &str,
+        mm_nonce: [u8; 16],
+        expected_lot: &Lot,
+    ) -> MMResponse {
+        let Some(wallet) = self.wallet_manager.for_currency(&expected_lot.currency) else {
+            return MMResponse::Error {
+                request_id,
+                error_code: MMErrorCode::UnsupportedChain,
+                message: "No wallet found for chain".to_string(),
+                timestamp: Utc::now(),
+            };
+        };
+
+        let (protocol_fee, mm_payment_validation) = self.payment_validation(expected_lot, mm_nonce);
+        // Paying out ends the lock, its funds go into this payment
+        let locked = self.quote_storage.release_quote_lock(quote_id);
+        // Reuse the lookups from when the quote was selected or locked, if fresh
+        let tx_result = match self
+            .quote_storage
+            .take_fill_preparation(quote_id)
+            .or(locked)
+        {
+            Some(preparation) => {
+                let result = wallet
+                    .create_prepared_payment(
+                        expected_lot,
+                        user_destination_address,
+                        mm_payment_validation,
+                        &preparation,
+                    )
+                    .await;
+                if result.is_ok() {
+                    info!(
+                        "Payout for quote {} reused its fill preparation, saved {} ms",
+                        quote_id,
+                        preparation.lookup_duration.as_millis()
+                    );
+                }
+                result
+            }
+            None => {
+                wallet
+                    .create_payment(
+                        expected_lot,
+                        user_destination_address,
+                        mm_payment_validation,
+                    )
+                    .await
+            }
+        };
+
+        self.report_payout(
+            request_id,
+            swap_id,
+            quote_id,
+            protocol_fee,
+            expected_lot,
+            tx_result,
+        )
+        .await
+    }
+
+    /// Pay the user again in place of `failed_tx_hash`, which the OTC server
+    /// says won't pay them, returning the protocol fee owed and the payment.
+    /// Our own chain lookup has to agree before anything is sent, and the
+    /// wallet builds the new payment to conflict with the failed one, so a late
+    /// confirmation of that one can't pay twice
+    async fn replace_payout(
+        &self,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        failed_tx_hash: &TxHash,
+        user_destination_address: &str,
+        mm_nonce: [u8; 16],
+        expected_lot: &Lot,
+    ) -> Result<(U256, wallet::Result<TransactionResult>), QuoteRejection> {
+        let Some(wallet) = self.wallet_manager.for_currency(&expected_lot.currency) else {
+            return Err(QuoteRejection::new(
+                MMErrorCode::UnsupportedChain,
+                "No wallet found for chain",
+            ));
+        };
+
+        let status = match wallet.payment_status(failed_tx_hash).await {
+            Ok(status) if status.failed() => status,
+            Ok(status) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InvalidRequest,
+                    format!("Deposit {failed_tx_hash} is {status:?} on chain"),
+                ));
+            }
+            Err(e) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InternalError,
+                    format!("Failed to look up deposit {failed_tx_hash}: {e}"),
+                ));
+            }
+        };
+        let nonce = self.quote_storage.fill_nonce(quote_id).await.map_err(|e| {
+            QuoteRejection::new(
+                MMErrorCode::InternalError,
+                format!("Failed to look up the nonce of deposit {failed_tx_hash}: {e}"),
+            )
+        })?;
+        info!(
+            "Deposit {} for swap {} is {:?} on chain, replacing it",
+            failed_tx_hash, swap_id, status
+        );
+
+        let failed = FailedPayment {
+            tx_hash: failed_tx_hash.clone(),
+            status,
+            nonce,
+        };
+        let (protocol_fee, mm_payment_validation) = self.payment_validation(expected_lot, mm_nonce);
+        let tx_result = wallet
+            .replace_payment(
+                &failed,
+                expected_lot,
+                user_destination_address,
+                mm_payment_validation,
+            )
+            .await;
+        Ok((protocol_fee, tx_result))
+    }
+
+    /// Protocol fee owed on a payout of `lot` and what the payment has to carry
+    /// for the OTC server to accept it
+    fn payment_validation(
+        &self,
+        lot: &Lot,
+        mm_nonce: [u8; 16],
+    ) -> (U256, Option<MarketMakerPaymentValidation>) {
+        let protocol_fee = U256::from(lot.compute_protocol_fee_with(&self.config.protocol_fee));
+        let mm_payment_validation = Some(MarketMakerPaymentValidation {
+            fee_amount: protocol_fee,
+            embedded_nonce: mm_nonce,
+            not_before: None,
+        });
+        (protocol_fee, mm_payment_validation)
+    }
+
+    /// Record a payout and answer with it, or with why it couldn't be made
+    async fn report_payout(
+        &self,
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        protocol_fee: U256,
+        expected_lot: &Lot,
+        tx_result: wallet::Result<TransactionResult>,
+    ) -> MMResponse {
+        match tx_result {
+            Ok(transaction) => {
+                if let Err(e) = self
+                    .quote_storage
+                    .mark_filled(
+                        quote_id,
+                        protocol_fee,
+                        &transaction.tx_hash,
+                        transaction.nonce,
+                    )
+                    .await
+                {
+                    error!("Failed to mark quote {} as filled: {}", quote_id, e);
+                }
+                let fill_cost = self.fill_cost(expected_lot, &transaction).await;
+                MMResponse::DepositInitiated {
+                    request_id,
+                    swap_id,
+                    tx_hash: transaction.tx_hash,
+                    amount_sent: expected_lot.amount,
+                    fee: transaction.fee,
+                    fill_cost,
+                    timestamp: Utc::now(),
+                }
+            }
+            // The quote stays claimed for the swap: the payout may have gone
+            // out before the error, and only this swap may try it again
+            Err(e) => MMResponse::Error {
+                request_id,
+                error_code: MMErrorCode::InternalError,
+                message: e.to_string(),
+                timestamp: Utc::now(),
+            },
+        }
+    }
+
+    /// Check a quote we're asked to fill is one we issued and still live, then
+    /// defer to the validation policy
+    async fn validate_quote(
+        &self,
+        quote_id: Uuid,
+        quote_hash: &[u8; 32],
+    ) -> Result<(), QuoteRejection> {
+        let quote = match self.quote_storage.get_quote(quote_id).await {
+            Ok(quote) => quote,
+            Err(QuoteStorageError::Database {
+                source: sqlx::Error::RowNotFound,
+            }) => {
+                warn!("Quote {} not found in database", quote_id);
+                return Err(QuoteRejection::new(
+                    MMErrorCode::QuoteNotFound,
+                    "Quote not found in database",
+                ));
+            }
+            Err(e) => {
+                error!("Failed to retrieve quote {} from database: {}", quote_id, e);
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InternalError,
+                    "Failed to retrieve quote",
+                ));
+            }
+        };
+        info!(
+            "Found quote {} in database, hash: {:?}",
+            quote_id,
+            quote.hash()
+        );
+
+        if quote.hash() != *quote_hash {
+            warn!(
+                "Quote {} hash mismatch! Expected: {:?}, Got: {:?}",
+                quote_id,
+                quote.hash(),
+                quote_hash
+            );
+            // A valid RFQ signature only shows some quote was issued, not that
+            // it's the one we stored, so any difference is refused
+            return Err(QuoteRejection::new(
+                MMErrorCode::QuoteMismatch,
+                "Quote hash does not match the issued quote",
+            ));
+        }
+
+        check_not_expired(&quote, self.clock.now())?;
+        self.check_locked_funds(&quote).await?;
+        self.validation_policy.validate(&quote).await
+    }
+
+    /// Funds held for locked quotes aren't ours to promise, so another quote is
+    /// only accepted if it fits next to them
+    async fn check_locked_funds(&self, quote: &Quote) -> Result<(), QuoteRejection> {
+        let chain = quote.to.currency.network();
+        let locked = self
+            .quote_storage
+            .locked_fill_preparations(chain, Some(quote.id));
+        if locked.is_empty() {
+            return Ok(());
+        }
+
+        let Some(wallet) = self.wallet_manager.get_network(chain) else {
+            return Err(QuoteRejection::new(
+                MMErrorCode::UnsupportedChain,
+                format!("No wallet configured for {chain}"),
+            ));
+        };
+        match wallet
+            .can_fill(&with_reservations(&quote.to, &locked))
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                info!(
+                    "Turning down quote {}, {} locked quotes hold the funds",
+                    quote.id,
+                    locked.len()
+                );
+                Err(QuoteRejection::new(
+                    MMErrorCode::InsufficientLiquidity,
+                    "Funds are held for locked quotes",
+                ))
+            }
+            Err(e) => {
+                error!("Failed to check balance for quote {}: {}", quote.id, e);
+                Err(QuoteRejection::new(
+                    MMErrorCode::InternalError,
+                    "Failed to check balance",
+                ))
+            }
+        }
+    }
+
+    /// What paying out `lot` in `transaction` cost. Ether fees are converted to
+    /// sats at the current price for cbBTC lots, kept in wei for ether lots and
+    /// left out for any other token
+    async fn fill_cost(&self, lot: &Lot, transaction: &TransactionResult) -> Option<FillCost> {
+        let usage = transaction.usage.clone()?;
+        let cbbtc = TokenIdentifier::Address(CBBTC_ADDRESS.to_string());
+        let fee_in_lot_currency = match (lot.currency.chain, transaction.fee) {
+            (_, None) => None,
+            (ChainType::Bitcoin, Some(fee_sats)) => u64::try_from(fee_sats).ok(),
+            (ChainType::Ethereum, Some(fee_wei))
+                if lot.currency.token == TokenIdentifier::Native =>
+            {
+                u64::try_from(fee_wei).ok()
+            }
+            (ChainType::Ethereum, Some(_)) if !lot.currency.token.is_same_token(&cbbtc) => None,
+            (ChainType::Ethereum, Some(fee_wei)) => match self.price_oracle.get_btc_per_eth().await
+            {
+                Ok(btc_per_eth) => wei_to_sats(fee_wei, btc_per_eth),
+                Err(e) => {
+                    warn!("No BTC/ETH price to report the fill's fee in sats: {}", e);
+                    None
+                }
+            },
+        };
+        Some(FillCost {
+            fee_in_lot_currency,
+            usage,
+        })
+    }
+
+    /// Whether `private_key` controls `address`, treating an unparseable key or
+    /// address as a mismatch
+    fn key_controls_address(
+        &self,
+        chain: ChainType,
+        private_key: &str,
+        address: &str,
+        swap_id: &Uuid,
+    ) -> bool {
+        deposit_key::private_key_controls_address(chain, private_key, address, self.bitcoin_network)
+            .unwrap_or_else(|e| {
+                error!(
+                    "Failed to check the deposit key for swap {}: {}",
+                    swap_id, e
+                );
+                false
+            })
+    }
+}
+
+/// Answer a fill we won't make with why
+fn refuse_fill(request_id: Uuid, swap_id: Uuid, rejection: QuoteRejection) -> MMResponse {
+    error!("Refusing to fill swap {}: {}", swap_id, rejection);
+    MMResponse::Error {
+        request_id,
+        error_code: rejection.code,
+        message: rejection.message,
+        timestamp: Utc::now(),
+    }
+}
+
+/// Whether both are the same amount of the same token on the same network
+fn same_lot(a: &Lot, b: &Lot) -> bool {
+    a.amount == b.amount
+        && a.currency.network() == b.currency.network()
+        && a.currency.token.is_same_token(&b.currency.token)
+        && a.currency.decimals == b.currency.decimals
+}
+
+/// `wei` of ether in sats of a bitcoin denominated lot
+fn wei_to_sats(wei: U256, btc_per_eth: f64) -> Option<u64> {
+    let wei = u128::try_from(wei).ok()?;
+    // 1e18 wei per ether, 1e8 sats per bitcoin
+    Some((wei as f64 * btc_per_eth / 1e10).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::AutoAcceptPolicy;
+    use crate::wallet::{FillPreparation, PaymentStatus, Wallet, WalletError};
+    use async_trait::async_trait;
+    use blockchain_utils::ProtocolFeeParams;
+    use chrono::SubsecRound;
+    use common::{ManualClock, SystemClock};
+    use otc_models::{Currency, FillUsage, Redacted};
+    use otc_protocols::mm::PROTOCOL_VERSION;
+    use sqlx::PgPool;
+    use std::time::Duration;
+    use tokio::task::JoinSet;
+
+    /// How long the OTC server waits for a validation response
+    const SERVER_VALIDATION_TIMEOUT: Duration = Duration::from_secs(5);
+    const BTC_PER_ETH: f64 = 0.0365;
+    const BLOCKED_DESTINATION: &str = "bcrt1qblocked";
+
+    struct RejectAllPolicy;
+
+    #[async_trait]
+    impl ValidationPolicy for RejectAllPolicy {
+        async fn validate(&self, _quote: &Quote) -> Result<(), QuoteRejection> {
+            Err(QuoteRejection::new(MMErrorCode::RateLimited, "Paused"))
+        }
+    }
+
+    /// Can fill anything up to `balance`
+    struct BalanceWallet {
+        balance: U256,
+    }
+
+    #[async_trait]
+    impl Wallet for BalanceWallet {
+        async fn create_payment(
+            &self,
+            _lot: &Lot,
+            _to_address: &str,
+            _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        ) -> wallet::Result<TransactionResult> {
+            unimplemented!("payouts aren't exercised here")
+        }
+
+        async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
+            Ok(lot.amount <= self.balance)
+        }
+    }
+
+    /// The txid of the `n`th payout a [`PayingWallet`] makes
+    fn payout(n: usize) -> TxHash {
+        TxHash::parse(ChainType::Bitcoin, &format!("{n:064x}")).unwrap()
+    }
+
+    /// Pays anything, numbering its payouts. Earlier payouts are reported as
+    /// `status` on chain, not found unless set
+    #[derive(Default)]
+    struct PayingWallet {
+        payments: std::sync::atomic::AtomicUsize,
+        status: std::sync::Mutex<Option<PaymentStatus>>,
+        replaced: std::sync::Mutex<Vec<FailedPayment>>,
+        /// Fail the next payment without sending anything
+        fail_next: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl Wallet for PayingWallet {
+        async fn create_payment(
+            &self,
+            _lot: &Lot,
+            _to_address: &str,
+            _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        ) -> wallet::Result<TransactionResult> {
+            if self
+                .fail_next
+                .swap(false, std::sync::atomic::Ordering::SeqCst)
+            {
+                return Err(WalletError::TransactionCreationFailed {
+                    reason: "broadcast failed".to_string(),
+                });
+            }
+            let payment = self
+                .payments
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                + 1;
+            Ok(TransactionResult {
+                tx_hash: payout(payment),
+                fee: None,
+                confirmations: 0,
+                raw: None,
+                usage: None,
+                nonce: Some(payment as u64),
+            })
+        }
+
+        async fn can_fill(&self, _lot: &Lot) -> wallet::Result<bool> {
+            Ok(true)
+        }
+
+        async fn payment_status(&self, _tx_hash: &TxHash) -> wallet::Result<PaymentStatus> {
+            Ok(self
+                .status
+                .lock()
+                .unwrap()
+                .unwrap_or(PaymentStatus::NotFound))
+        }
+
+        async fn replace_payment(
+            &self,
+            failed: &FailedPayment,
+            lot: &Lot,
+            to_address: &str,
+            mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        ) -> wallet::Result<TransactionResult> {
+            self.replaced.lock().unwrap().push(failed.clone());
+            self.create_payment(lot, to_address, mm_payment_validation)
+                .await
+        }
+    }
+
+    async fn handler(pool: PgPool, policy: Arc<dyn ValidationPolicy>) -> OTCMessageHandler {
+        handler_with(pool, policy, WalletManager::new(), Arc::new(SystemClock)).await
+    }
+
+    async fn handler_with(
+        pool: PgPool,
+        policy: Arc<dyn ValidationPolicy>,
+        wallet_manager: WalletManager,
+        clock: Arc<dyn Clock>,
+    ) -> OTCMessageHandler {
+        let quote_storage = QuoteStorage::from_pool(
+            pool,
+            chrono::Duration::hours(24),
+            clock.clone(),
+            &mut JoinSet::new(),
+        )
+        .await
+        .unwrap();
+        OTCMessageHandler::new(
+            Config {
+                market_maker_id: Uuid::new_v4(),
+                api_key_id: Uuid::new_v4().to_string(),
+                api_key: Redacted::new("key".to_string()),
+                otc_ws_url: "ws://localhost:3000/ws/mm".to_string(),
+                reconnect_interval_secs: 5,
+                max_reconnect_attempts: None,
+                protocol_fee: ProtocolFeeParams::DEFAULT,
+                attestation: None,
+                blocked_destination_addresses: vec![BLOCKED_DESTINATION.to_string()],
+            },
+            wallet_manager,
+            Arc::new(quote_storage),
+            bitcoin::Network::Regtest,
+            policy,
+            BitcoinEtherPriceOracle::fixed(BTC_PER_ETH),
+            clock,
+        )
+    }
+
+    fn quote(expires_in: chrono::Duration) -> Quote {
+        let currency = |chain| Currency {
+            chain,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        };
+        Quote {
+            id: Uuid::new_v4(),
+            market_maker_id: Uuid::new_v4(),
+            from: Lot {
+                currency: currency(ChainType::Ethereum),
+                amount: U256::from(100_000u64),
+            },
+            to: Lot {
+                currency: currency(ChainType::Bitcoin),
+                amount: U256::from(99_000u64),
+            },
+            expires_at: Utc::now() + expires_in,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// The OTC server asking for `lot` to be paid to `destination` for `swap_id`
+    fn deposit_confirmed(
+        swap_id: Uuid,
+        quote_id: Uuid,
+        destination: &str,
+        lot: &Lot,
+    ) -> ProtocolMessage<MMRequest> {
+        ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 0,
+            payload: MMRequest::UserDepositConfirmed {
+                request_id: Uuid::new_v4(),
+                swap_id,
+                quote_id,
+                user_destination_address: destination.to_string(),
+                mm_nonce: [0; 16],
+                expected_lot: lot.clone(),
+                user_deposit_address: "0xdeposit".to_string(),
+                user_deposit_chain: ChainType::Ethereum,
+                external_reference: None,
+                timestamp: Utc::now(),
+            },
+            trace_id: None,
+        }
+    }
+
+    /// The payout `handler` made for `request`, or the error code it refused with
+    async fn fill(
+        handler: &OTCMessageHandler,
+        request: &ProtocolMessage<MMRequest>,
+    ) -> Result<TxHash, MMErrorCode> {
+        match handler
+            .handle_request(request)
+            .await
+            .expect("fills are answered")
+            .payload
+        {
+            MMResponse::DepositInitiated { tx_hash, .. } => Ok(tx_hash),
+            MMResponse::Error { error_code, .. } => Err(error_code),
+            other => panic!("expected a deposit or an error, got {other:?}"),
+        }
+    }
+
+    async fn paying_handler(pool: PgPool) -> (OTCMessageHandler, Arc<PayingWallet>) {
+        let wallet = Arc::new(PayingWallet::default());
+        let mut wallet_manager = WalletManager::new();
+        wallet_manager.register(ChainType::Bitcoin, wallet.clone());
+        let handler = handler_with(
+            pool,
+            Arc::new(AutoAcceptPolicy),
+            wallet_manager,
+            Arc::new(SystemClock),
+        )
+        .await;
+        (handler, wallet)
+    }
+
+    /// Ask `handler` to validate `quote` like the OTC server would
+    async fn validate(
+        handler: &OTCMessageHandler,
+        quote: &Quote,
+    ) -> (bool, Option<QuoteRejection>) {
+        let request = ProtocolMessage {
+            version: "1.0.0".to_string(),
+            sequence: 0,
+            payload: MMRequest::ValidateQuote {
+                request_id: Uuid::new_v4(),
+                quote_id: quote.id,
+                quote_hash: quote.hash(),
+                user_destination_address: "bcrt1qtest".to_string(),
+                timestamp: Utc::now(),
+            },
+            trace_id: Some("trace-1".to_string()),
+        };
+        let response =
+            tokio::time::timeout(SERVER_VALIDATION_TIMEOUT, handler.handle_request(&request))
+                .await
+                .expect("validation should answer before the server gives up")
+                .expect("validation should be answered");
+        assert_eq!(response.trace_id, request.trace_id);
+        match response.payload {
+            MMResponse::QuoteValidated {
+                quote_id,
+                accepted,
+                rejection_reason,
+                ..
+            } => {
+                assert_eq!(quote_id, quote.id);
+                (accepted, rejection_reason)
+            }
+            other => panic!("expected a validation response, got {other:?}"),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_accepts_a_live_quote(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+
+        assert_eq!(validate(&handler, &quote).await, (true, None));
+        assert_eq!(handler.quote_storage.stats().await.unwrap().accepted, 1);
+    }
+
+    #[sqlx::test]
+    async fn test_rejects_an_expired_quote(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let quote = quote(chrono::Duration::seconds(-1));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+
+        let (accepted, rejection) = validate(&handler, &quote).await;
+        assert!(!accepted);
+        assert_eq!(rejection.unwrap().code, MMErrorCode::QuoteExpired);
+        assert_eq!(handler.quote_storage.stats().await.unwrap().accepted, 0);
+    }
+
+    #[sqlx::test]
+    async fn test_quote_expires_at_its_expiry(pool: PgPool) {
+        let clock = ManualClock::new(Utc::now().trunc_subsecs(0));
+        let handler = handler_with(
+            pool,
+            Arc::new(AutoAcceptPolicy),
+            WalletManager::new(),
+            Arc::new(clock.clone()),
+        )
+        .await;
+        let mut quote = quote(chrono::Duration::zero());
+        quote.expires_at = clock.now() + chrono::Duration::seconds(30);
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+
+        clock.advance(chrono::Duration::seconds(30));
+        let (accepted, rejection) = validate(&handler, &quote).await;
+        assert!(!accepted);
+        assert_eq!(rejection.unwrap().code, MMErrorCode::QuoteExpired);
+
+        clock.advance(-chrono::Duration::microseconds(1));
+        assert_eq!(validate(&handler, &quote).await, (true, None));
+    }
+
+    #[sqlx::test]
+    async fn test_rejects_an_unknown_quote(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+
+        let (accepted, rejection) = validate(&handler, &quote(chrono::Duration::minutes(5))).await;
+        assert!(!accepted);
+        assert_eq!(rejection.unwrap().code, MMErrorCode::QuoteNotFound);
+    }
+
+    #[sqlx::test]
+    async fn test_rejects_a_quote_that_differs_from_the_issued_one(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        let mut altered = quote.clone();
+        altered.from.amount = U256::from(1u64);
+
+        let (accepted, rejection) = validate(&handler, &altered).await;
+        assert!(!accepted);
+        assert_eq!(rejection.unwrap().code, MMErrorCode::QuoteMismatch);
+        assert_eq!(handler.quote_storage.stats().await.unwrap().accepted, 0);
+    }
+
+    #[sqlx::test]
+    async fn test_policy_rejection_reaches_the_server(pool: PgPool) {
+        let handler = handler(pool, Arc::new(RejectAllPolicy)).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+
+        let (accepted, rejection) = validate(&handler, &quote).await;
+        assert!(!accepted);
+        assert_eq!(
+            rejection,
+            Some(QuoteRejection::new(MMErrorCode::RateLimited, "Paused"))
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_locked_quotes_hold_their_funds(pool: PgPool) {
+        let mut wallet_manager = WalletManager::new();
+        wallet_manager.register(
+            ChainType::Bitcoin,
+            Arc::new(BalanceWallet {
+                balance: U256::from(150_000u64),
+            }),
+        );
+        let handler = handler_with(
+            pool,
+            Arc::new(AutoAcceptPolicy),
+            wallet_manager,
+            Arc::new(SystemClock),
+        )
+        .await;
+        let locked = quote(chrono::Duration::minutes(5));
+        let other = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&locked).await.unwrap();
+        handler.quote_storage.store_quote(&other).await.unwrap();
+        handler.quote_storage.lock_quote(
+            locked.id,
+            FillPreparation {
+                lot: locked.to.clone(),
+                fee_rate: None,
+                utxos: Vec::new(),
+                lookup_duration: Duration::ZERO,
+            },
+            Duration::from_secs(60),
+        );
+
+        // The lock holds 99_000 of the 150_000, too little is left for another quote
+        let (accepted, rejection) = validate(&handler, &other).await;
+        assert!(!accepted);
+        assert_eq!(rejection.unwrap().code, MMErrorCode::InsufficientLiquidity);
+        // The locked quote itself can use its funds
+        assert_eq!(validate(&handler, &locked).await, (true, None));
+
+        handler.quote_storage.release_quote_lock(locked.id);
+        assert_eq!(validate(&handler, &other).await, (true, None));
+    }
+
+    #[sqlx::test]
+    async fn test_swap_failure_releases_the_quote(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        handler.quote_storage.cache_fill_preparation(
+            quote.id,
+            FillPreparation {
+                lot: quote.to.clone(),
+                fee_rate: None,
+                utxos: Vec::new(),
+                lookup_duration: Duration::ZERO,
+            },
+            Duration::from_secs(60),
+        );
+
+        let request = ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 0,
+            payload: MMRequest::SwapFailed {
+                request_id: Uuid::new_v4(),
+                swap_id: Uuid::new_v4(),
+                quote_id: quote.id,
+                reason_code: SwapFailureReason::Cancelled,
+                refund_tx_hash: None,
+                timestamp: Utc::now(),
+            },
+            trace_id: None,
+        };
+        assert!(handler.handle_request(&request).await.is_none());
+
+        assert!(handler
+            .quote_storage
+            .pending_fill_preparations(ChainType::Bitcoin)
+            .is_empty());
+        assert_eq!(
+            handler
+                .quote_storage
+                .failure_reason(quote.id)
+                .await
+                .unwrap(),
+            Some("cancelled".to_string())
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_swap_status_updates_keep_the_latest_status(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        let swap_id = Uuid::new_v4();
+        let update = |status, user_confirmations, updated_at| ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 0,
+            payload: MMRequest::SwapStatusUpdate {
+                request_id: Uuid::new_v4(),
+                swap_id,
+                quote_id: quote.id,
+                status,
+                user_deposit_confirmations: Some(user_confirmations),
+                mm_deposit_confirmations: None,
+                user_deposit_detected_at: Some(Utc::now()),
+                mm_deposit_detected_at: None,
+                updated_at,
+                timestamp: Utc::now(),
+            },
+            trace_id: None,
+        };
+
+        let detected_at = Utc::now();
+        let confirmed_at = detected_at + chrono::Duration::seconds(30);
+        for request in [
+            update(SwapStatus::WaitingUserDepositConfirmed, 0, detected_at),
+            update(SwapStatus::WaitingMMDepositInitiated, 6, confirmed_at),
+            // Delivered late, the newer status stands
+            update(SwapStatus::WaitingUserDepositConfirmed, 1, detected_at),
+        ] {
+            assert!(handler.handle_request(&request).await.is_none());
+        }
+
+        let progress = handler
+            .quote_storage
+            .swap_progress(swap_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(progress.quote_id, quote.id);
+        assert_eq!(progress.status, SwapStatus::WaitingMMDepositInitiated);
+        assert_eq!(progress.user_deposit_confirmations, Some(6));
+        assert_eq!(progress.mm_deposit_confirmations, None);
+        assert!(handler
+            .quote_storage
+            .swap_progress(Uuid::new_v4())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_refunds_and_failures_forget_the_deposit_address(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let quote_id = Uuid::new_v4();
+        let message = |payload| ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 0,
+            payload,
+            trace_id: None,
+        };
+        let status_update = |swap_id, status| {
+            message(MMRequest::SwapStatusUpdate {
+                request_id: Uuid::new_v4(),
+                swap_id,
+                quote_id,
+                status,
+                user_deposit_confirmations: None,
+                mm_deposit_confirmations: None,
+                user_deposit_detected_at: None,
+                mm_deposit_detected_at: None,
+                updated_at: Utc::now(),
+                timestamp: Utc::now(),
+            })
+        };
+
+        let rejected = Uuid::new_v4();
+        let refunding = Uuid::new_v4();
+        let failed = Uuid::new_v4();
+        let waiting = Uuid::new_v4();
+        for swap_id in [rejected, refunding, failed, waiting] {
+            handler
+                .deposit_addresses
+                .insert(swap_id, (ChainType::Bitcoin, "bcrt1qdeposit".to_string()));
+        }
+
+        for request in [
+            message(MMRequest::MMDepositRejected {
+                request_id: Uuid::new_v4(),
+                swap_id: rejected,
+                quote_id,
+                tx_hash: payout(1),
+                error_code: MMErrorCode::InvalidAmount,
+                expected_amount: U256::from(1_000u64),
+                received_amount: U256::from(999u64),
+                timestamp: Utc::now(),
+            }),
+            status_update(refunding, SwapStatus::RefundingUser),
+            status_update(failed, SwapStatus::Failed),
+            status_update(waiting, SwapStatus::WaitingMMDepositConfirmed),
+        ] {
+            assert!(handler.handle_request(&request).await.is_none());
+        }
+
+        let remaining: Vec<_> = handler
+            .deposit_addresses
+            .iter()
+            .map(|entry| *entry.key())
+            .collect();
+        assert_eq!(remaining, vec![waiting]);
+    }
+
+    #[sqlx::test]
+    async fn test_deposit_retries_pay_once_per_failure(pool: PgPool) {
+        let wallet = Arc::new(PayingWallet::default());
+        let mut wallet_manager = WalletManager::new();
+        wallet_manager.register(ChainType::Bitcoin, wallet.clone());
+        let handler = handler_with(
+            pool,
+            Arc::new(AutoAcceptPolicy),
+            wallet_manager,
+            Arc::new(SystemClock),
+        )
+        .await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        let swap_id = Uuid::new_v4();
+        let request = |payload| ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 0,
+            payload,
+            trace_id: None,
+        };
+        let retry = |failed_tx_hash: TxHash, deadline| {
+            request(MMRequest::DepositRetryRequested {
+                request_id: Uuid::new_v4(),
+                swap_id,
+                quote_id: quote.id,
+                failed_tx_hash,
+                user_destination_address: "bcrt1qtest".to_string(),
+                mm_nonce: [0; 16],
+                expected_lot: quote.to.clone(),
+                attempt: 1,
+                deadline,
+                timestamp: Utc::now(),
+            })
+        };
+        let answer = |response: Option<ProtocolMessage<MMResponse>>| match response
+            .expect("deposit requests are answered")
+            .payload
+        {
+            MMResponse::DepositInitiated { tx_hash, .. } => Ok(tx_hash),
+            MMResponse::Error { error_code, .. } => Err(error_code),
+            other => panic!("expected a deposit or an error, got {other:?}"),
+        };
+
+        let confirmed = request(MMRequest::UserDepositConfirmed {
+            request_id: Uuid::new_v4(),
+            swap_id,
+            quote_id: quote.id,
+            user_destination_address: "bcrt1qtest".to_string(),
+            mm_nonce: [0; 16],
+            expected_lot: quote.to.clone(),
+            user_deposit_address: "0xdeposit".to_string(),
+            user_deposit_chain: ChainType::Ethereum,
+            external_reference: None,
+            timestamp: Utc::now(),
+        });
+        let first = answer(handler.handle_request(&confirmed).await);
+        assert_eq!(first, Ok(payout(1)));
+
+        let deadline = Utc::now() + chrono::Duration::minutes(2);
+        // A payout that may still confirm isn't paid again
+        for status in [PaymentStatus::Pending, PaymentStatus::Confirmed] {
+            *wallet.status.lock().unwrap() = Some(status);
+            let refused = answer(handler.handle_request(&retry(payout(1), deadline)).await);
+            assert_eq!(refused, Err(MMErrorCode::InvalidRequest));
+        }
+        *wallet.status.lock().unwrap() = None;
+        let retried = answer(handler.handle_request(&retry(payout(1), deadline)).await);
+        assert_eq!(retried, Ok(payout(2)));
+        // and the replacement is built against the payout it replaces
+        assert_eq!(
+            *wallet.replaced.lock().unwrap(),
+            vec![FailedPayment {
+                tx_hash: payout(1),
+                status: PaymentStatus::NotFound,
+                nonce: Some(1),
+            }]
+        );
+        // The answer got lost and the server asks again, the retry isn't paid twice
+        let repeated = answer(handler.handle_request(&retry(payout(1), deadline)).await);
+        assert_eq!(repeated, Ok(payout(2)));
+        assert_eq!(
+            handler
+                .quote_storage
+                .fill_tx_hash(quote.id)
+                .await
+                .unwrap(),
+            Some(payout(2))
+        );
+
+        let expired = answer(
+            handler
+                .handle_request(&retry(payout(2), Utc::now() - chrono::Duration::seconds(1)))
+                .await,
+        );
+        assert_eq!(expired, Err(MMErrorCode::InvalidRequest));
+        assert_eq!(wallet.payments.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[sqlx::test]
+    async fn test_refuses_fills_that_differ_from_the_quote(pool: PgPool) {
+        let (handler, wallet) = paying_handler(pool).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        let swap_id = Uuid::new_v4();
+        let tampered = |tamper: fn(&mut Lot)| {
+            let mut lot = quote.to.clone();
+            tamper(&mut lot);
+            deposit_confirmed(swap_id, quote.id, "bcrt1qtest", &lot)
+        };
+
+        for request in [
+            tampered(|lot| lot.amount *= U256::from(100u64)),
+            tampered(|lot| lot.currency.decimals = 6),
+            tampered(|lot| lot.currency.chain = ChainType::Ethereum),
+            tampered(|lot| {
+                lot.currency.token =
+                    TokenIdentifier::address("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap();
+            }),
+        ] {
+            assert_eq!(
+                fill(&handler, &request).await,
+                Err(MMErrorCode::QuoteMismatch)
+            );
+        }
+        let blocked = deposit_confirmed(swap_id, quote.id, BLOCKED_DESTINATION, &quote.to);
+        assert_eq!(
+            fill(&handler, &blocked).await,
+            Err(MMErrorCode::InvalidRequest)
+        );
+        let unknown = deposit_confirmed(swap_id, Uuid::new_v4(), "bcrt1qtest", &quote.to);
+        assert_eq!(
+            fill(&handler, &unknown).await,
+            Err(MMErrorCode::QuoteNotFound)
+        );
+        assert_eq!(wallet.payments.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        // Refusals claim nothing, the fill as quoted still goes through
+        let honest = deposit_confirmed(swap_id, quote.id, "bcrt1qtest", &quote.to);
+        assert_eq!(fill(&handler, &honest).await, Ok(payout(1)));
+    }
+
+    #[sqlx::test]
+    async fn test_refuses_to_fill_a_swap_twice(pool: PgPool) {
+        let (handler, wallet) = paying_handler(pool).await;
+        let other = quote(chrono::Duration::minutes(5));
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        handler.quote_storage.store_quote(&other).await.unwrap();
+        let swap_id = Uuid::new_v4();
+
+        let confirmed = deposit_confirmed(swap_id, quote.id, "bcrt1qtest", &quote.to);
+        assert_eq!(fill(&handler, &confirmed).await, Ok(payout(1)));
+
+        for request in [
+            // Delivered again, e.g. after a reorg rolled the swap back
+            deposit_confirmed(swap_id, quote.id, "bcrt1qtest", &quote.to),
+            // The swap id reused for another quote
+            deposit_confirmed(swap_id, other.id, "bcrt1qtest", &other.to),
+            // The quote reused for another swap
+            deposit_confirmed(Uuid::new_v4(), quote.id, "bcrt1qtest", &quote.to),
+        ] {
+            assert_eq!(
+                fill(&handler, &request).await,
+                Err(MMErrorCode::InvalidRequest)
+            );
+        }
+        assert_eq!(wallet.payments.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    /// Whether `handler` agreed to cancel `swap_id`
+    async fn cancel(handler: &OTCMessageHandler, swap_id: Uuid, quote_id: Uuid) -> bool {
+        let request = ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 0,
+            payload: MMRequest::CancelRequested {
+                request_id: Uuid::new_v4(),
+                swap_id,
+                quote_id,
+                timestamp: Utc::now(),
+            },
+            trace_id: None,
+        };
+        match handler
+            .handle_request(&request)
+            .await
+            .expect("cancel requests are answered")
+            .payload
+        {
+            MMResponse::CancelAcknowledged { accepted, .. } => accepted,
+            other => panic!("expected a cancel acknowledgment, got {other:?}"),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_cancel_is_accepted_only_before_paying(pool: PgPool) {
+        let (handler, wallet) = paying_handler(pool).await;
+        let paid = quote(chrono::Duration::minutes(5));
+        let unpaid = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&paid).await.unwrap();
+        handler.quote_storage.store_quote(&unpaid).await.unwrap();
+        let (paid_swap, unpaid_swap) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let confirmed = deposit_confirmed(paid_swap, paid.id, "bcrt1qtest", &paid.to);
+        assert_eq!(fill(&handler, &confirmed).await, Ok(payout(1)));
+        assert!(!cancel(&handler, paid_swap, paid.id).await);
+
+        assert!(cancel(&handler, unpaid_swap, unpaid.id).await);
+        // Once agreed, the swap is never paid
+        let confirmed = deposit_confirmed(unpaid_swap, unpaid.id, "bcrt1qtest", &unpaid.to);
+        assert_eq!(
+            fill(&handler, &confirmed).await,
+            Err(MMErrorCode::InvalidRequest)
+        );
+        assert_eq!(wallet.payments.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[sqlx::test]
+    async fn test_failed_payout_keeps_the_quote_bound_to_its_swap(pool: PgPool) {
+        let (handler, wallet) = paying_handler(pool).await;
+        let quote = quote(chrono::Duration::minutes(5));
+        handler.quote_storage.store_quote(&quote).await.unwrap();
+        let swap_id = Uuid::new_v4();
+
+        wallet
+            .fail_next
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        let confirmed = deposit_confirmed(swap_id, quote.id, "bcrt1qtest", &quote.to);
+        assert_eq!(
+            fill(&handler, &confirmed).await,
+            Err(MMErrorCode::InternalError)
+        );
+        // The payout may have gone out anyway, so no other swap gets the quote
+        let other_swap_id = Uuid::new_v4();
+        let refilled = deposit_confirmed(other_swap_id, quote.id, "bcrt1qtest", &quote.to);
+        assert_eq!(
+            fill(&handler, &refilled).await,
+            Err(MMErrorCode::InvalidRequest)
+        );
+
+        // while the swap it was claimed for may try again, until it's paid
+        assert_eq!(fill(&handler, &confirmed).await, Ok(payout(1)));
+        assert_eq!(
+            fill(&handler, &confirmed).await,
+            Err(MMErrorCode::InvalidRequest)
+        );
+        assert_eq!(wallet.payments.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[sqlx::test]
+    async fn test_fill_cost_is_reported_in_the_lot_currency(pool: PgPool) {
+        let handler = handler(pool, Arc::new(AutoAcceptPolicy)).await;
+        let lot = |chain, token, decimals| Lot {
+            currency: Currency {
+                chain,
+                token,
+                decimals,
+                chain_id: None,
+            },
+            amount: U256::from(1_000_000u64),
+        };
+        let bitcoin_lot = lot(ChainType::Bitcoin, TokenIdentifier::Native, 8);
+        let transaction = |fee: u64, usage| TransactionResult {
+            tx_hash: payout(1),
+            fee: Some(U256::from(fee)),
+            confirmations: 0,
+            raw: None,
+            usage: Some(usage),
+            nonce: None,
+        };
+
+        let bitcoin = FillUsage::Bitcoin {
+            sat_per_vb: 2.0,
+            vsize: 200,
+            inputs: 1,
+            outputs: 4,
+        };
+        let cost = handler
+            .fill_cost(&bitcoin_lot, &transaction(400, bitcoin.clone()))
+            .await
+            .unwrap();
+        assert_eq!(cost.fee_in_lot_currency, Some(400));
+        assert_eq!(cost.usage, bitcoin);
+
+        // 50k gas at 2 gwei is 1e14 wei, 0.0001 ETH
+        let ethereum = FillUsage::Ethereum {
+            effective_gas_price: U256::from(2_000_000_000u64),
+            gas_used: 50_000,
+        };
+        let ethereum_fill = transaction(100_000_000_000_000, ethereum.clone());
+        let cbbtc = TokenIdentifier::address(CBBTC_ADDRESS).unwrap();
+        let cost = handler
+            .fill_cost(&lot(ChainType::Ethereum, cbbtc, 8), &ethereum_fill)
+            .await
+            .unwrap();
+        assert_eq!(cost.fee_in_lot_currency, Some(365));
+        assert_eq!(cost.usage, ethereum);
+
+        // Ether lots are already in wei, other tokens have no price to convert with
+        let ether = lot(ChainType::Ethereum, TokenIdentifier::Native, 18);
+        let cost = handler.fill_cost(&ether, &ethereum_fill).await.unwrap();
+        assert_eq!(cost.fee_in_lot_currency, Some(100_000_000_000_000));
+        let usdt = TokenIdentifier::address("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap();
+        let cost = handler
+            .fill_cost(&lot(ChainType::Ethereum, usdt, 6), &ethereum_fill)
+            .await
+            .unwrap();
+        assert_eq!(cost.fee_in_lot_currency, None);
+
+        // Wallets that can't tell report no cost
+        let unknown = TransactionResult {
+            usage: None,
+            ..transaction(400, bitcoin)
+        };
+        assert!(handler.fill_cost(&bitcoin_lot, &unknown).await.is_none());
+    }
 }
@@ -0,0 +1,455 @@
+//! Startup checks that the configuration agrees with the chains and servers it
+//! points at. A mismatch fails at boot naming what's wrong, instead of surfacing
+//! minutes later as a failed sync or fill.
+
+use std::fmt;
+
+use alloy::{providers::Provider, transports::TransportError};
+use bdk_wallet::bitcoin::{constants::genesis_block, Network};
+use blockchain_utils::create_websocket_wallet_provider;
+use snafu::prelude::*;
+use url::Url;
+
+use crate::{
+    bitcoin_wallet::{BitcoinWallet, WalletDatabaseCheck},
+    config, otc_client,
+    quote_storage::QuoteStorage,
+    rfq_client, DryRunFailedSnafu, MarketMakerArgs,
+};
+
+/// Networks recognized by their genesis block when esplora serves the wrong one
+const KNOWN_NETWORKS: [Network; 4] = [
+    Network::Bitcoin,
+    Network::Testnet,
+    Network::Signet,
+    Network::Regtest,
+];
+
+#[derive(Debug, Snafu)]
+pub enum PreflightError {
+    #[snafu(display("Failed to create Esplora client for {}: {}", url, source))]
+    EsploraClient {
+        url: String,
+        source: esplora_client::Error,
+    },
+
+    #[snafu(display(
+        "Failed to fetch the genesis block from Esplora at {}: {}",
+        url,
+        source
+    ))]
+    EsploraGenesis {
+        url: String,
+        source: esplora_client::Error,
+    },
+
+    #[snafu(display(
+        "Esplora at {} serves {}, but the Bitcoin wallet network is {}",
+        url,
+        found,
+        expected
+    ))]
+    EsploraNetworkMismatch {
+        url: String,
+        expected: Network,
+        found: String,
+    },
+
+    #[snafu(display("Invalid Ethereum RPC URL {}: {}", url, source))]
+    EthereumRpcUrl {
+        url: String,
+        source: url::ParseError,
+    },
+
+    #[snafu(display(
+        "Ethereum RPC URL {} uses {}, a ws:// or wss:// URL is required",
+        url,
+        scheme
+    ))]
+    EthereumRpcNotWebsocket { url: String, scheme: String },
+
+    #[snafu(display("Failed to fetch the Ethereum chain id: {}", source))]
+    EthereumChainIdUnavailable { source: TransportError },
+
+    #[snafu(display(
+        "Ethereum RPC serves chain id {}, but --ethereum-chain-id is {}",
+        actual,
+        expected
+    ))]
+    EthereumChainIdMismatch { expected: u64, actual: u64 },
+}
+
+/// Make sure esplora serves the chain the wallet is configured for, by its genesis block
+pub async fn check_esplora_network(
+    esplora_url: &str,
+    network: Network,
+) -> Result<(), PreflightError> {
+    let client = esplora_client::Builder::new(esplora_url)
+        .build_async()
+        .context(EsploraClientSnafu { url: esplora_url })?;
+    let genesis_hash = client
+        .get_block_hash(0)
+        .await
+        .context(EsploraGenesisSnafu { url: esplora_url })?
+        .to_string();
+
+    let genesis_of = |network: Network| genesis_block(network).block_hash().to_string();
+    if genesis_hash == genesis_of(network) {
+        return Ok(());
+    }
+    let found = KNOWN_NETWORKS
+        .into_iter()
+        .find(|known| genesis_hash == genesis_of(*known))
+        .map_or_else(
+            || format!("an unknown chain with genesis block {genesis_hash}"),
+            |known| known.to_string(),
+        );
+    EsploraNetworkMismatchSnafu {
+        url: esplora_url,
+        expected: network,
+        found,
+    }
+    .fail()
+}
+
+/// The EVM wallet subscribes to blocks, which needs a websocket RPC
+pub fn check_ethereum_rpc_url(rpc_url: &str) -> Result<(), PreflightError> {
+    let url = Url::parse(rpc_url).context(EthereumRpcUrlSnafu { url: rpc_url })?;
+    ensure!(
+        matches!(url.scheme(), "ws" | "wss"),
+        EthereumRpcNotWebsocketSnafu {
+            url: rpc_url,
+            scheme: url.scheme(),
+        }
+    );
+    Ok(())
+}
+
+/// Chain id the provider serves, which must be `expected` when set
+pub async fn check_ethereum_chain_id(
+    provider: &impl Provider,
+    expected: Option<u64>,
+) -> Result<u64, PreflightError> {
+    let actual = provider
+        .get_chain_id()
+        .await
+        .context(EthereumChainIdUnavailableSnafu)?;
+    if let Some(expected) = expected {
+        ensure!(
+            actual == expected,
+            EthereumChainIdMismatchSnafu { expected, actual }
+        );
+    }
+    Ok(actual)
+}
+
+enum Outcome {
+    Passed(String),
+    Failed(String),
+    Skipped(&'static str),
+}
+
+/// What `--dry-run` found, one line per check
+#[derive(Default)]
+pub struct PreflightReport {
+    checks: Vec<(&'static str, Outcome)>,
+}
+
+impl PreflightReport {
+    /// Record `result` under `name`, describing a pass with `describe`
+    fn record<T, E: fmt::Display>(
+        &mut self,
+        name: &'static str,
+        result: Result<T, E>,
+        describe: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        let (outcome, value) = match result {
+            Ok(value) => (Outcome::Passed(describe(&value)), Some(value)),
+            Err(e) => (Outcome::Failed(e.to_string()), None),
+        };
+        self.checks.push((name, outcome));
+        value
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &'static str) {
+        self.checks.push((name, Outcome::Skipped(reason)));
+    }
+
+    #[must_use]
+    pub fn failed(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+            .count()
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Market maker dry run")?;
+        for (name, outcome) in &self.checks {
+            let (status, detail) = match outcome {
+                Outcome::Passed(detail) => ("ok", detail.as_str()),
+                Outcome::Failed(error) => ("FAIL", error.as_str()),
+                Outcome::Skipped(reason) => ("skip", *reason),
+            };
+            writeln!(f, "  {status:<5} {name:<22} {detail}")?;
+        }
+        write!(
+            f,
+            "{} of {} checks failed",
+            self.failed(),
+            self.checks.len()
+        )
+    }
+}
+
+/// Check everything the market maker needs against the chains and servers,
+/// without starting the quoting or fill loops or writing to the database or
+/// wallet file. Prints the report and fails if any check did
+pub async fn dry_run(args: &MarketMakerArgs) -> crate::Result<()> {
+    let mut report = PreflightReport::default();
+
+    let market_maker_id = report.record(
+        "market maker id",
+        crate::parse_market_maker_id(&args.market_maker_id),
+        ToString::to_string,
+    );
+    let protocol_fee = report.record(
+        "protocol fee",
+        config::validate_protocol_fee(crate::protocol_fee_params(args)),
+        |fee| format!("{} bps, at least {} sats", fee.bps, fee.min_sats),
+    );
+    report.record("pricing", crate::pricing_config(args), |pricing| {
+        format!(
+            "{} bps spread, {}x fee safety multiplier",
+            pricing.trade_spread_bps, pricing.fee_safety_multiplier
+        )
+    });
+    let attestation = report.record(
+        "attestation",
+        crate::attestation_verifier(args),
+        |verifier| match verifier {
+            Some(verifier) => format!("expecting measurement {}", verifier.expected_measurement),
+            None => "OTC server trusted without verification".to_string(),
+        },
+    );
+    if args.simulation_mode {
+        report.record(
+            "simulation mode",
+            crate::simulation::check_args(args.bitcoin_wallet_network, args.simulated_btc_per_eth),
+            |()| format!("quoting from {} BTC/ETH", args.simulated_btc_per_eth),
+        );
+    }
+    report.record(
+        "supported currencies",
+        crate::load_supported_currencies(args),
+        |currencies| format!("{} currencies", currencies.all().len()),
+    );
+    // Neither the database nor the wallet file is written to, a dry run leaves
+    // them as a real start would find them
+    report.record(
+        "quote database",
+        QuoteStorage::pending_migrations(&args.database_url).await,
+        |pending| match pending {
+            0 => "connected, schema up to date".to_string(),
+            pending => format!("connected, {pending} migrations to apply on start"),
+        },
+    );
+
+    report.record(
+        "bitcoin wallet",
+        BitcoinWallet::check_database(
+            &args.bitcoin_wallet_db_file,
+            args.bitcoin_wallet_descriptor.expose(),
+            args.bitcoin_wallet_network,
+        ),
+        |check| match check {
+            WalletDatabaseCheck::Missing => format!(
+                "{} doesn't exist yet, it is created on start",
+                args.bitcoin_wallet_db_file
+            ),
+            WalletDatabaseCheck::Matches(identity) => {
+                format!("{} holds {}", args.bitcoin_wallet_db_file, identity)
+            }
+            WalletDatabaseCheck::Unidentified => format!(
+                "opened {}, its descriptor is checked on start",
+                args.bitcoin_wallet_db_file
+            ),
+        },
+    );
+    report.record(
+        "esplora network",
+        check_esplora_network(
+            &args.bitcoin_wallet_esplora_url,
+            args.bitcoin_wallet_network,
+        )
+        .await,
+        |_| {
+            format!(
+                "{} at {}",
+                args.bitcoin_wallet_network, args.bitcoin_wallet_esplora_url
+            )
+        },
+    );
+
+    let provider = match report.record(
+        "ethereum rpc url",
+        check_ethereum_rpc_url(&args.ethereum_rpc_ws_url),
+        |_| args.ethereum_rpc_ws_url.clone(),
+    ) {
+        Some(()) => report.record(
+            "ethereum provider",
+            create_websocket_wallet_provider(
+                &args.ethereum_rpc_ws_url,
+                *args.ethereum_wallet_private_key.expose(),
+            )
+            .await,
+            |_| "connected".to_string(),
+        ),
+        None => {
+            report.skip("ethereum provider", "needs a websocket RPC URL");
+            None
+        }
+    };
+    match provider {
+        Some(provider) => {
+            report.record(
+                "ethereum chain id",
+                check_ethereum_chain_id(&provider, args.ethereum_chain_id).await,
+                |chain_id| match args.ethereum_chain_id {
+                    Some(_) => format!("{chain_id}"),
+                    None => format!("{chain_id}, unchecked without --ethereum-chain-id"),
+                },
+            );
+        }
+        None => report.skip("ethereum chain id", "needs the Ethereum provider"),
+    }
+
+    match (market_maker_id, protocol_fee, attestation) {
+        (Some(market_maker_id), Some(protocol_fee), Some(attestation)) => {
+            let otc_config = crate::client_config(args, market_maker_id, protocol_fee, attestation);
+            // The RFQ server runs outside the enclave and has no attestation
+            let rfq_config = crate::client_config(args, market_maker_id, protocol_fee, None);
+            report.record(
+                "otc server",
+                otc_client::check_connection(otc_config).await,
+                |_| format!("authenticated at {}", args.otc_ws_url),
+            );
+            for url in &args.rfq_ws_urls {
+                report.record(
+                    "rfq server",
+                    rfq_client::check_connection(rfq_config.clone(), url.clone())
+                        .await
+                        .map_err(|e| format!("{url}: {e}")),
+                    |_| format!("authenticated at {url}"),
+                );
+            }
+        }
+        _ => {
+            let reason = "needs a valid market maker id, protocol fee and attestation";
+            report.skip("otc server", reason);
+            report.skip("rfq server", reason);
+        }
+    }
+
+    println!("{report}");
+    let failed = report.failed();
+    ensure!(failed == 0, DryRunFailedSnafu { failed });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::{mock::Asserter, ProviderBuilder};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Esplora stand-in answering every request with `body`
+    async fn mock_esplora(body: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    const MAINNET_GENESIS: &str =
+        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+    #[tokio::test]
+    async fn test_regtest_wallet_against_mainnet_esplora_is_rejected() {
+        let esplora_url = mock_esplora(MAINNET_GENESIS).await;
+
+        match check_esplora_network(&esplora_url, Network::Regtest).await {
+            Err(PreflightError::EsploraNetworkMismatch {
+                expected, found, ..
+            }) => {
+                assert_eq!(expected, Network::Regtest);
+                assert_eq!(found, Network::Bitcoin.to_string());
+            }
+            other => panic!("Expected a network mismatch, got {other:?}"),
+        }
+        check_esplora_network(&esplora_url, Network::Bitcoin)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_genesis_is_reported_by_hash() {
+        let esplora_url =
+            mock_esplora("1111111111111111111111111111111111111111111111111111111111111111").await;
+
+        let error = check_esplora_network(&esplora_url, Network::Regtest)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown chain"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_chain_id_mismatch_is_rejected() {
+        let asserter = Asserter::new();
+        let provider = ProviderBuilder::new().connect_mocked_client(asserter.clone());
+
+        // Anvil's 1337 where mainnet was expected
+        asserter.push_success(&"0x539");
+        match check_ethereum_chain_id(&provider, Some(1)).await {
+            Err(PreflightError::EthereumChainIdMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), (1, 1337));
+            }
+            other => panic!("Expected a chain id mismatch, got {other:?}"),
+        }
+
+        asserter.push_success(&"0x539");
+        assert_eq!(
+            check_ethereum_chain_id(&provider, Some(1337))
+                .await
+                .unwrap(),
+            1337
+        );
+        asserter.push_success(&"0x539");
+        assert_eq!(
+            check_ethereum_chain_id(&provider, None).await.unwrap(),
+            1337
+        );
+    }
+
+    #[test]
+    fn test_ethereum_rpc_url_must_be_a_websocket() {
+        check_ethereum_rpc_url("ws://localhost:8545").unwrap();
+        check_ethereum_rpc_url("wss://mainnet.example.com").unwrap();
+        assert!(matches!(
+            check_ethereum_rpc_url("http://localhost:8545"),
+            Err(PreflightError::EthereumRpcNotWebsocket { scheme, .. }) if scheme == "http"
+        ));
+    }
+}
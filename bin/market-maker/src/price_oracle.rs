@@ -81,14 +81,22 @@ impl BitcoinEtherPriceOracle {
             oracle_clone
                 .run_price_feed()
                 .await
-                .map_err(|e| crate::Error::BackgroundThread {
-                    source: Box::new(e),
-                })
+                .map_err(|e| crate::Error::PriceFeed { source: e })
         });
 
         oracle
     }
 
+    /// An oracle that always reports `btc_per_eth`, without a price feed
+    #[must_use]
+    pub fn fixed(btc_per_eth: f64) -> Self {
+        Self {
+            inner: Arc::new(BitcoinEtherPriceOracleInner {
+                btc_per_eth: RwLock::new(Some(btc_per_eth)),
+            }),
+        }
+    }
+
     async fn wait_for_connection(&self) -> Result<()> {
         let start_time = Instant::now();
         let timeout = Duration::from_secs(5);
@@ -103,6 +111,11 @@ impl BitcoinEtherPriceOracle {
         }
     }
 
+    /// Whether the feed delivered a price yet
+    pub async fn has_price(&self) -> bool {
+        self.inner.btc_per_eth.read().await.is_some()
+    }
+
     pub async fn get_btc_per_eth(&self) -> Result<f64> {
         self.wait_for_connection().await?;
         self.inner
@@ -1,18 +1,41 @@
-use chrono::{DateTime, Utc};
-use otc_models::{ChainType, Currency, Lot, Quote, TokenIdentifier};
+use alloy::primitives::U256;
+use chrono::{DateTime, Duration, Utc};
+use common::{Clock, SystemClock};
+use dashmap::DashMap;
+use otc_models::{
+    ChainNetwork, Currency, InvalidTxHash, Lot, Quote, QuoteMode, QuoteRequest, SwapStatus,
+    TokenIdentifier, TxHash,
+};
+use otc_protocols::rfq::{QuoteWithFees, RFQResult};
 use snafu::prelude::*;
 use sqlx::{
     migrate::Migrator,
-    postgres::{PgPool, PgPoolOptions, PgRow},
-    Row,
+    postgres::{PgConnection, PgPool, PgPoolOptions, PgRow},
+    Connection, Row,
 };
-use std::sync::Arc;
+use std::{collections::HashSet, fmt, str::FromStr, sync::Arc};
 use tokio::{task::JoinSet, time};
 use tracing::{error, info};
 use uuid::Uuid;
 
+use crate::{
+    wallet::FillPreparation,
+    wallet_buckets::{BucketEarmarks, Earmarks, WalletBucket},
+    wrapped_bitcoin_quoter::PricingInputs,
+};
+
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
+/// How long unreferenced quotes are kept by default
+pub const DEFAULT_QUOTE_RETENTION_HOURS: u32 = 24;
+
+/// Unique index that keeps a swap from being paid out by two quotes
+const FILL_SWAP_ID_INDEX: &str = "idx_mm_quotes_fill_swap_id";
+
+/// How long a fill preparation is trusted. Fee rates and wallet state move, so a
+/// payout after this redoes the lookups.
+pub const FILL_PREPARATION_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
 #[derive(Debug, Snafu)]
 pub enum QuoteStorageError {
     #[snafu(display("Database error: {}", source))]
@@ -29,20 +52,265 @@ pub enum QuoteStorageError {
 
     #[snafu(display("Invalid U256 value: {}", value))]
     InvalidU256 { value: String },
+
+    #[snafu(display("Invalid swap status: {}", status))]
+    InvalidSwapStatus { status: serde_json::Value },
+
+    #[snafu(display("Invalid fill tx hash: {}", source))]
+    InvalidFillTxHash { source: InvalidTxHash },
+
+    #[snafu(display("Invalid quote attempt: {}", message))]
+    InvalidQuoteAttempt { message: String },
+
+    #[snafu(display("Invalid wallet bucket: {}", bucket))]
+    InvalidWalletBucket { bucket: String },
+
+    #[snafu(display("Can't move funds from the {} bucket to itself", bucket))]
+    SameWalletBucket { bucket: WalletBucket },
+
+    #[snafu(display("The {} bucket holds only {}", bucket, available))]
+    InsufficientBucketBalance {
+        bucket: WalletBucket,
+        available: String,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, QuoteStorageError>;
 
+/// Row counts of the quote table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuoteStorageStats {
+    pub total: u64,
+    /// Quotes that haven't expired yet
+    pub active: u64,
+    /// Quotes we agreed to fill when the OTC server asked
+    pub accepted: u64,
+    /// Quotes we've sent a payment for
+    pub filled: u64,
+}
+
+/// Latest status the OTC server reported for a swap filling one of our quotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapProgress {
+    pub swap_id: Uuid,
+    pub quote_id: Uuid,
+    pub status: SwapStatus,
+    pub user_deposit_confirmations: Option<u64>,
+    pub mm_deposit_confirmations: Option<u64>,
+    /// When the swap entered `status`, per the OTC server
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Outcome of claiming a quote's payout for a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillClaim {
+    /// The quote is now claimed for the swap
+    Claimed,
+    /// The quote was already claimed for the same swap, e.g. by a payout
+    /// being retried
+    AlreadyClaimed,
+    /// The quote was claimed for another swap or the swap by another quote
+    Conflict,
+}
+
+/// How we answered a quote request, and for a quote whether it won
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteAttemptOutcome {
+    Quoted,
+    MakerUnavailable,
+    InvalidRequest,
+    Won,
+    Lost,
+}
+
+impl QuoteAttemptOutcome {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QuoteAttemptOutcome::Quoted => "quoted",
+            QuoteAttemptOutcome::MakerUnavailable => "maker_unavailable",
+            QuoteAttemptOutcome::InvalidRequest => "invalid_request",
+            QuoteAttemptOutcome::Won => "won",
+            QuoteAttemptOutcome::Lost => "lost",
+        }
+    }
+}
+
+impl fmt::Display for QuoteAttemptOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for QuoteAttemptOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "quoted" => Ok(QuoteAttemptOutcome::Quoted),
+            "maker_unavailable" => Ok(QuoteAttemptOutcome::MakerUnavailable),
+            "invalid_request" => Ok(QuoteAttemptOutcome::InvalidRequest),
+            "won" => Ok(QuoteAttemptOutcome::Won),
+            "lost" => Ok(QuoteAttemptOutcome::Lost),
+            _ => Err(format!("unknown quote outcome: {s}")),
+        }
+    }
+}
+
+/// The quote offered for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OfferedQuote {
+    pub quote_id: Uuid,
+    pub from_amount: U256,
+    pub to_amount: U256,
+}
+
+/// A quote request we answered, and how
+#[derive(Debug, Clone)]
+pub struct QuoteAttempt {
+    pub id: Uuid,
+    pub request_id: Uuid,
+    pub request: QuoteRequest,
+    pub outcome: QuoteAttemptOutcome,
+    /// Why the request was turned down
+    pub message: Option<String>,
+    pub quote: Option<OfferedQuote>,
+    pub inputs: PricingInputs,
+    /// Time spent answering, the whole batch for batched requests
+    pub duration: std::time::Duration,
+    pub created_at: DateTime<Utc>,
+}
+
+impl QuoteAttempt {
+    /// The attempt of answering `request` with `result`
+    #[must_use]
+    pub fn new(
+        request_id: Uuid,
+        request: &QuoteRequest,
+        result: &RFQResult<QuoteWithFees>,
+        inputs: PricingInputs,
+        duration: std::time::Duration,
+        created_at: DateTime<Utc>,
+    ) -> Self {
+        let (outcome, message, quote) = match result {
+            RFQResult::Success(quote) => (
+                QuoteAttemptOutcome::Quoted,
+                None,
+                Some(OfferedQuote {
+                    quote_id: quote.quote.id,
+                    from_amount: quote.quote.from.amount,
+                    to_amount: quote.quote.to.amount,
+                }),
+            ),
+            RFQResult::MakerUnavailable(message) => (
+                QuoteAttemptOutcome::MakerUnavailable,
+                Some(message.clone()),
+                None,
+            ),
+            RFQResult::InvalidRequest(message) => (
+                QuoteAttemptOutcome::InvalidRequest,
+                Some(message.clone()),
+                None,
+            ),
+        };
+        Self {
+            id: Uuid::new_v4(),
+            request_id,
+            request: request.clone(),
+            outcome,
+            message,
+            quote,
+            inputs,
+            duration,
+            created_at,
+        }
+    }
+}
+
+/// Which quote attempts to list, every one when unset
+#[derive(Debug, Clone, Default)]
+pub struct QuoteAttemptFilter {
+    /// Only attempts made at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only requests from the first network to the second
+    pub pair: Option<(ChainNetwork, ChainNetwork)>,
+    pub outcome: Option<QuoteAttemptOutcome>,
+}
+
+struct CachedFillPreparation {
+    preparation: FillPreparation,
+    expires_at: time::Instant,
+}
+
 #[derive(Clone)]
 pub struct QuoteStorage {
     pool: PgPool,
+    /// Unreferenced quotes older than this are deleted by the cleanup task
+    retention: Duration,
+    /// Fill preparations of selected quotes, kept in memory until the payout
+    fill_preparations: Arc<DashMap<Uuid, CachedFillPreparation>>,
+    /// Funds held for quotes a user locked through the RFQ server
+    quote_locks: Arc<DashMap<Uuid, CachedFillPreparation>>,
+    /// The bucket ledger as last written or loaded here
+    earmarks: Earmarks,
+    /// What the cleanup task measures retention against
+    clock: Arc<dyn Clock>,
 }
 
 impl QuoteStorage {
     pub async fn new(
         database_url: &str,
+        retention: Duration,
+        clock: Arc<dyn Clock>,
         join_set: &mut JoinSet<crate::Result<()>>,
     ) -> Result<Self> {
+        let pool = Self::connect(database_url).await?;
+        Self::from_pool(pool, retention, clock, join_set).await
+    }
+
+    /// Storage without the retention task, for tools reading it alongside a
+    /// running market maker
+    pub async fn open(database_url: &str) -> Result<Self> {
+        let pool = Self::connect(database_url).await?;
+        Ok(Self {
+            pool,
+            retention: Duration::hours(i64::from(DEFAULT_QUOTE_RETENTION_HOURS)),
+            fill_preparations: Arc::new(DashMap::new()),
+            quote_locks: Arc::new(DashMap::new()),
+            earmarks: Earmarks::default(),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Connect to `database_url` without migrating it, returning how many
+    /// migrations starting the market maker would apply
+    pub async fn pending_migrations(database_url: &str) -> Result<usize> {
+        let mut conn = PgConnection::connect(database_url)
+            .await
+            .context(DatabaseSnafu)?;
+        let migrated: bool =
+            sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                .fetch_one(&mut conn)
+                .await
+                .context(DatabaseSnafu)?;
+        let applied: HashSet<i64> = if migrated {
+            sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&mut conn)
+                .await
+                .context(DatabaseSnafu)?
+                .into_iter()
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let _ = conn.close().await;
+        Ok(MIGRATOR
+            .iter()
+            .filter(|migration| !applied.contains(&migration.version))
+            .count())
+    }
+
+    async fn connect(database_url: &str) -> Result<PgPool> {
         info!("Connecting to market maker database...");
 
         let pool = PgPoolOptions::new()
@@ -58,14 +326,23 @@ impl QuoteStorage {
         MIGRATOR.run(&pool).await.context(MigrationSnafu)?;
         info!("Market maker database initialization complete");
 
-        Self::from_pool(pool, join_set).await
+        Ok(pool)
     }
 
     pub async fn from_pool(
         pool: PgPool,
+        retention: Duration,
+        clock: Arc<dyn Clock>,
         join_set: &mut JoinSet<crate::Result<()>>,
     ) -> Result<Self> {
-        let storage = Self { pool };
+        let storage = Self {
+            pool,
+            retention,
+            fill_preparations: Arc::new(DashMap::new()),
+            quote_locks: Arc::new(DashMap::new()),
+            earmarks: Earmarks::default(),
+            clock,
+        };
 
         let cleanup_storage = storage.clone();
         join_set.spawn(async move {
@@ -150,7 +427,12 @@ impl QuoteStorage {
         self.deserialize_quote(&row)
     }
 
-    pub async fn get_active_quotes(&self, market_maker_id: Uuid) -> Result<Vec<Quote>> {
+    /// Quotes of `market_maker_id` that haven't expired at `now`
+    pub async fn get_active_quotes(
+        &self,
+        market_maker_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Quote>> {
         let rows = sqlx::query(
             r#"
             SELECT 
@@ -168,11 +450,12 @@ impl QuoteStorage {
                 created_at
             FROM mm_quotes
             WHERE market_maker_id = $1 
-            AND expires_at > NOW()
+            AND expires_at > $2
             ORDER BY created_at DESC
             "#,
         )
         .bind(market_maker_id)
+        .bind(now)
         .fetch_all(&self.pool)
         .await
         .context(DatabaseSnafu)?;
@@ -217,13 +500,413 @@ impl QuoteStorage {
         Ok(())
     }
 
-    pub async fn delete_expired_quotes(&self) -> Result<u64> {
+    /// Record that we accepted the quote when we locked it or the OTC server asked to validate it
+    pub async fn mark_accepted(&self, id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE mm_quotes
+            SET accepted_at = COALESCE(accepted_at, NOW())
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// Record the payout for a quote along with the protocol fee it paid. The
+    /// fee leaves the wallet as an output of the payout itself, so it isn't
+    /// set aside in the protocol fees bucket
+    pub async fn mark_filled(
+        &self,
+        id: Uuid,
+        protocol_fee: U256,
+        tx_hash: &TxHash,
+        nonce: Option<u64>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE mm_quotes
+            SET filled_at = COALESCE(filled_at, NOW()),
+                protocol_fee = COALESCE(protocol_fee, $2),
+                fill_tx_hash = $3,
+                fill_nonce = $4
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(protocol_fee.to_string())
+        .bind(tx_hash.as_str())
+        .bind(nonce.map(|nonce| nonce as i64))
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// The latest payout transaction for a quote, `None` if it was never filled
+    pub async fn fill_tx_hash(&self, id: Uuid) -> Result<Option<TxHash>> {
+        let tx_hash = sqlx::query_scalar::<_, Option<String>>(
+            r#"
+            SELECT fill_tx_hash
+            FROM mm_quotes
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(DatabaseSnafu)?
+        .flatten();
+
+        tx_hash
+            .map(|tx_hash| tx_hash.parse().context(InvalidFillTxHashSnafu))
+            .transpose()
+    }
+
+    /// Account nonce of the latest payout for a quote, `None` if it was never
+    /// filled or its chain has no nonces
+    pub async fn fill_nonce(&self, id: Uuid) -> Result<Option<u64>> {
+        let nonce = sqlx::query_scalar::<_, Option<i64>>(
+            r#"
+            SELECT fill_nonce
+            FROM mm_quotes
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(DatabaseSnafu)?
+        .flatten();
+
+        Ok(nonce.map(|nonce| nonce as u64))
+    }
+
+    /// Claim the payout of a quote for `swap_id` before paying it. A quote pays
+    /// out for one swap and a swap is paid by one quote, whatever the OTC
+    /// server asks
+    pub async fn claim_fill(&self, id: Uuid, swap_id: Uuid) -> Result<FillClaim> {
+        let claimed = match sqlx::query(
+            r#"
+            UPDATE mm_quotes
+            SET fill_swap_id = $2
+            WHERE id = $1
+            AND fill_swap_id IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(swap_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(result) => result.rows_affected() > 0,
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some(FILL_SWAP_ID_INDEX) => {
+                return Ok(FillClaim::Conflict);
+            }
+            Err(source) => return Err(QuoteStorageError::Database { source }),
+        };
+        if claimed {
+            return Ok(FillClaim::Claimed);
+        }
+
+        let claimed_for = sqlx::query_scalar::<_, Option<Uuid>>(
+            r#"
+            SELECT fill_swap_id
+            FROM mm_quotes
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(DatabaseSnafu)?
+        .flatten();
+
+        Ok(if claimed_for == Some(swap_id) {
+            FillClaim::AlreadyClaimed
+        } else {
+            FillClaim::Conflict
+        })
+    }
+
+    /// Record that the OTC server gave up on the swap for a quote. The first
+    /// reported reason is kept
+    pub async fn mark_failed(&self, id: Uuid, reason: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE mm_quotes
+            SET failed_at = COALESCE(failed_at, NOW()),
+                failure_reason = COALESCE(failure_reason, $2)
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(reason)
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// Why the swap for a quote failed, `None` if it hasn't
+    pub async fn failure_reason(&self, id: Uuid) -> Result<Option<String>> {
+        let reason = sqlx::query_scalar::<_, Option<String>>(
+            r#"
+            SELECT failure_reason
+            FROM mm_quotes
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(DatabaseSnafu)?
+        .flatten();
+
+        Ok(reason)
+    }
+
+    /// Record a status update for the swap filling a quote. Updates can arrive
+    /// out of order, one older than the stored status is ignored
+    pub async fn record_swap_progress(&self, progress: &SwapProgress) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE mm_quotes
+            SET swap_id = $2,
+                swap_status = $3,
+                swap_user_deposit_confirmations = $4,
+                swap_mm_deposit_confirmations = $5,
+                swap_status_updated_at = $6
+            WHERE id = $1
+            AND (swap_status_updated_at IS NULL OR swap_status_updated_at <= $6)
+            "#,
+        )
+        .bind(progress.quote_id)
+        .bind(progress.swap_id)
+        .bind(serde_json::json!(progress.status))
+        .bind(progress.user_deposit_confirmations.map(|c| c as i64))
+        .bind(progress.mm_deposit_confirmations.map(|c| c as i64))
+        .bind(progress.updated_at)
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// Latest reported status of a swap, `None` if we never heard about it
+    pub async fn swap_progress(&self, swap_id: Uuid) -> Result<Option<SwapProgress>> {
+        let Some(row) = sqlx::query(
+            r#"
+            SELECT
+                id,
+                swap_id,
+                swap_status,
+                swap_user_deposit_confirmations,
+                swap_mm_deposit_confirmations,
+                swap_status_updated_at
+            FROM mm_quotes
+            WHERE swap_id = $1
+            "#,
+        )
+        .bind(swap_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(DatabaseSnafu)?
+        else {
+            return Ok(None);
+        };
+
+        let status: serde_json::Value = row.get("swap_status");
+        let confirmations = |column: &str| -> Option<u64> {
+            row.get::<Option<i64>, _>(column).map(i64::unsigned_abs)
+        };
+        Ok(Some(SwapProgress {
+            swap_id: row.get("swap_id"),
+            quote_id: row.get("id"),
+            status: serde_json::from_value(status.clone())
+                .map_err(|_| QuoteStorageError::InvalidSwapStatus { status })?,
+            user_deposit_confirmations: confirmations("swap_user_deposit_confirmations"),
+            mm_deposit_confirmations: confirmations("swap_mm_deposit_confirmations"),
+            updated_at: row.get("swap_status_updated_at"),
+        }))
+    }
+
+    pub async fn store_quote_attempt(&self, attempt: &QuoteAttempt) -> Result<()> {
+        let (from_chain, from_token, from_decimals) =
+            self.serialize_currency(&attempt.request.from)?;
+        let (to_chain, to_token, to_decimals) = self.serialize_currency(&attempt.request.to)?;
+        let mode = match attempt.request.mode {
+            QuoteMode::ExactInput => "exact_input",
+            QuoteMode::ExactOutput => "exact_output",
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO mm_quote_attempts (
+                id,
+                request_id,
+                mode,
+                from_chain,
+                from_token,
+                from_decimals,
+                to_chain,
+                to_token,
+                to_decimals,
+                amount,
+                outcome,
+                message,
+                quote_id,
+                quoted_from_amount,
+                quoted_to_amount,
+                sats_per_vbyte,
+                eth_per_btc,
+                duration_ms,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        )
+        .bind(attempt.id)
+        .bind(attempt.request_id)
+        .bind(mode)
+        .bind(from_chain)
+        .bind(from_token)
+        .bind(from_decimals)
+        .bind(to_chain)
+        .bind(to_token)
+        .bind(to_decimals)
+        .bind(attempt.request.amount.to_string())
+        .bind(attempt.outcome.as_str())
+        .bind(attempt.message.as_deref())
+        .bind(attempt.quote.map(|quote| quote.quote_id))
+        .bind(attempt.quote.map(|quote| quote.from_amount.to_string()))
+        .bind(attempt.quote.map(|quote| quote.to_amount.to_string()))
+        .bind(attempt.inputs.sats_per_vbyte)
+        .bind(attempt.inputs.eth_per_btc)
+        .bind(i64::try_from(attempt.duration.as_millis()).unwrap_or(i64::MAX))
+        .bind(attempt.created_at)
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// Record whether the quote `quote_id` won, once the RFQ server reports it
+    pub async fn record_quote_outcome(&self, quote_id: Uuid, won: bool) -> Result<()> {
+        let outcome = if won {
+            QuoteAttemptOutcome::Won
+        } else {
+            QuoteAttemptOutcome::Lost
+        };
+        sqlx::query(
+            r#"
+            UPDATE mm_quote_attempts
+            SET outcome = $2
+            WHERE quote_id = $1
+            "#,
+        )
+        .bind(quote_id)
+        .bind(outcome.as_str())
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// Quote attempts matching `filter`, newest first, at most `limit` of them
+    pub async fn quote_attempts(
+        &self,
+        filter: &QuoteAttemptFilter,
+        limit: u32,
+    ) -> Result<Vec<QuoteAttempt>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id,
+                request_id,
+                mode,
+                from_chain,
+                from_token,
+                from_decimals,
+                to_chain,
+                to_token,
+                to_decimals,
+                amount,
+                outcome,
+                message,
+                quote_id,
+                quoted_from_amount,
+                quoted_to_amount,
+                sats_per_vbyte,
+                eth_per_btc,
+                duration_ms,
+                created_at
+            FROM mm_quote_attempts
+            WHERE ($1::TIMESTAMPTZ IS NULL OR created_at >= $1)
+            AND ($2::TEXT IS NULL OR from_chain = $2)
+            AND ($3::TEXT IS NULL OR to_chain = $3)
+            AND ($4::TEXT IS NULL OR outcome = $4)
+            ORDER BY created_at DESC
+            LIMIT $5
+            "#,
+        )
+        .bind(filter.since)
+        .bind(filter.pair.map(|(from, _)| from.to_string()))
+        .bind(filter.pair.map(|(_, to)| to.to_string()))
+        .bind(filter.outcome.map(QuoteAttemptOutcome::as_str))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        rows.iter()
+            .map(|row| self.deserialize_quote_attempt(row))
+            .collect()
+    }
+
+    /// Delete quote attempts made before `cutoff`
+    pub async fn delete_quote_attempts_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM mm_quote_attempts
+            WHERE created_at < $1
+            "#,
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete quotes expired at `now` and created before `cutoff` that no swap references
+    pub async fn delete_unreferenced_quotes(
+        &self,
+        now: DateTime<Utc>,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64> {
         let result = sqlx::query(
             r#"
             DELETE FROM mm_quotes
-            WHERE expires_at < NOW()
+            WHERE created_at < $1
+            AND expires_at <= $2
+            AND accepted_at IS NULL
+            AND filled_at IS NULL
             "#,
         )
+        .bind(cutoff)
+        .bind(now)
         .execute(&self.pool)
         .await
         .context(DatabaseSnafu)?;
@@ -231,30 +914,436 @@ impl QuoteStorage {
         Ok(result.rows_affected())
     }
 
+    /// The database's `NOW()`, to compare the host clock against
+    pub async fn database_now(&self) -> Result<DateTime<Utc>> {
+        sqlx::query_scalar("SELECT NOW()")
+            .fetch_one(&self.pool)
+            .await
+            .context(DatabaseSnafu)
+    }
+
+    pub async fn stats(&self) -> Result<QuoteStorageStats> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                COUNT(*) AS total,
+                COUNT(*) FILTER (WHERE expires_at > NOW()) AS active,
+                COUNT(accepted_at) AS accepted,
+                COUNT(filled_at) AS filled
+            FROM mm_quotes
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        let count = |column: &str| -> u64 { row.get::<i64, _>(column).unsigned_abs() };
+        Ok(QuoteStorageStats {
+            total: count("total"),
+            active: count("active"),
+            accepted: count("accepted"),
+            filled: count("filled"),
+        })
+    }
+
+    /// Protocol fees paid on our fills so far, one lot per payout currency
+    pub async fn protocol_fee_totals(&self) -> Result<Vec<Lot>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT to_chain, to_token, to_decimals, protocol_fee
+            FROM mm_quotes
+            WHERE protocol_fee IS NOT NULL
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        let mut totals: Vec<Lot> = Vec::new();
+        for row in rows {
+            let currency = self.deserialize_currency(
+                &row.get::<String, _>("to_chain"),
+                row.get("to_token"),
+                row.get("to_decimals"),
+            )?;
+            let fee: String = row.get("protocol_fee");
+            let fee = U256::from_str_radix(&fee, 10)
+                .map_err(|_| QuoteStorageError::InvalidU256 { value: fee.clone() })?;
+
+            match totals.iter_mut().find(|total| {
+                total.currency.network() == currency.network()
+                    && total.currency.token == currency.token
+            }) {
+                Some(total) => total.amount = total.amount.saturating_add(fee),
+                None => totals.push(Lot {
+                    currency,
+                    amount: fee,
+                }),
+            }
+        }
+
+        Ok(totals)
+    }
+
+    /// What each currency has set aside from trading, currencies with nothing
+    /// ever set aside left out
+    pub async fn bucket_earmarks(&self) -> Result<Vec<(Currency, BucketEarmarks)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT chain, token, decimals, bucket, amount
+            FROM mm_wallet_buckets
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        let mut earmarks: Vec<(Currency, BucketEarmarks)> = Vec::new();
+        for row in rows {
+            let currency = self.deserialize_currency(
+                &row.get::<String, _>("chain"),
+                row.get("token"),
+                row.get("decimals"),
+            )?;
+            let bucket = parse_bucket(row.get("bucket"))?;
+            let amount = parse_u256(row.get("amount"))?;
+
+            let index = match earmarks.iter().position(|(known, _)| {
+                known.network() == currency.network() && known.token == currency.token
+            }) {
+                Some(index) => index,
+                None => {
+                    earmarks.push((currency, BucketEarmarks::default()));
+                    earmarks.len() - 1
+                }
+            };
+            if let Some(slot) = earmarks[index].1.get_mut(bucket) {
+                *slot = amount;
+            }
+        }
+
+        Ok(earmarks)
+    }
+
+    /// Move `amount` of `currency` between buckets and log the move, returning
+    /// what the currency has set aside afterwards. Only the buckets stored here
+    /// are checked for funds, the trading bucket is whatever the wallet holds
+    /// beyond them
+    pub async fn transfer_between_buckets(
+        &self,
+        currency: &Currency,
+        from: WalletBucket,
+        to: WalletBucket,
+        amount: U256,
+    ) -> Result<BucketEarmarks> {
+        ensure!(from != to, SameWalletBucketSnafu { bucket: from });
+
+        let mut tx = self.pool.begin().await.context(DatabaseSnafu)?;
+        let earmarks = self
+            .move_between_buckets(&mut tx, currency, from, to, amount)
+            .await?;
+        tx.commit().await.context(DatabaseSnafu)?;
+        self.earmarks.set(currency, earmarks);
+        Ok(earmarks)
+    }
+
+    /// [`Self::transfer_between_buckets`] within `tx`
+    async fn move_between_buckets(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        currency: &Currency,
+        from: WalletBucket,
+        to: WalletBucket,
+        amount: U256,
+    ) -> Result<BucketEarmarks> {
+        let (chain, token, decimals) = self.serialize_currency(currency)?;
+
+        // Rows exist before they're locked, so concurrent transfers queue on them
+        for bucket in WalletBucket::EARMARKED {
+            sqlx::query(
+                r#"
+                INSERT INTO mm_wallet_buckets (chain, token, decimals, bucket, amount)
+                VALUES ($1, $2, $3, $4, '0')
+                ON CONFLICT (chain, token, bucket) DO NOTHING
+                "#,
+            )
+            .bind(&chain)
+            .bind(&token)
+            .bind(decimals)
+            .bind(bucket.as_str())
+            .execute(&mut **tx)
+            .await
+            .context(DatabaseSnafu)?;
+        }
+
+        let rows = sqlx::query(
+            r#"
+            SELECT bucket, amount
+            FROM mm_wallet_buckets
+            WHERE chain = $1 AND token = $2
+            FOR UPDATE
+            "#,
+        )
+        .bind(&chain)
+        .bind(&token)
+        .fetch_all(&mut **tx)
+        .await
+        .context(DatabaseSnafu)?;
+
+        let mut earmarks = BucketEarmarks::default();
+        for row in rows {
+            if let Some(slot) = earmarks.get_mut(parse_bucket(row.get("bucket"))?) {
+                *slot = parse_u256(row.get("amount"))?;
+            }
+        }
+        if let Some(slot) = earmarks.get_mut(from) {
+            *slot = slot
+                .checked_sub(amount)
+                .context(InsufficientBucketBalanceSnafu {
+                    bucket: from,
+                    available: slot.to_string(),
+                })?;
+        }
+        if let Some(slot) = earmarks.get_mut(to) {
+            *slot = slot.saturating_add(amount);
+        }
+
+        for bucket in WalletBucket::EARMARKED {
+            let amount = earmarks.get(bucket).unwrap_or_default();
+            sqlx::query(
+                r#"
+                UPDATE mm_wallet_buckets
+                SET amount = $4, decimals = $3, updated_at = NOW()
+                WHERE chain = $1 AND token = $2 AND bucket = $5
+                "#,
+            )
+            .bind(&chain)
+            .bind(&token)
+            .bind(decimals)
+            .bind(amount.to_string())
+            .bind(bucket.as_str())
+            .execute(&mut **tx)
+            .await
+            .context(DatabaseSnafu)?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO mm_wallet_bucket_transfers (
+                id, chain, token, decimals, from_bucket, to_bucket, amount
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(&chain)
+        .bind(&token)
+        .bind(decimals)
+        .bind(from.as_str())
+        .bind(to.as_str())
+        .bind(amount.to_string())
+        .execute(&mut **tx)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(earmarks)
+    }
+
+    /// The bucket ledger shared with the bucketed wallets, kept current with
+    /// every move made through this storage
+    #[must_use]
+    pub fn earmarks(&self) -> &Earmarks {
+        &self.earmarks
+    }
+
+    /// Record what the wallet holds of `currency`, as seen at `observed_at`
+    pub async fn record_wallet_balance(
+        &self,
+        currency: &Currency,
+        amount: U256,
+        observed_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let (chain, token, decimals) = self.serialize_currency(currency)?;
+        sqlx::query(
+            r#"
+            INSERT INTO mm_wallet_balances (chain, token, decimals, amount, observed_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (chain, token) DO UPDATE
+            SET decimals = EXCLUDED.decimals,
+                amount = EXCLUDED.amount,
+                observed_at = EXCLUDED.observed_at
+            "#,
+        )
+        .bind(&chain)
+        .bind(&token)
+        .bind(decimals)
+        .bind(amount.to_string())
+        .bind(observed_at)
+        .execute(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        Ok(())
+    }
+
+    /// The last recorded balance of `currency` and when it was seen, `None`
+    /// if it was never recorded
+    pub async fn wallet_balance(
+        &self,
+        currency: &Currency,
+    ) -> Result<Option<(U256, DateTime<Utc>)>> {
+        let (chain, token, _) = self.serialize_currency(currency)?;
+        let row = sqlx::query(
+            r#"
+            SELECT amount, observed_at
+            FROM mm_wallet_balances
+            WHERE chain = $1 AND token = $2
+            "#,
+        )
+        .bind(&chain)
+        .bind(&token)
+        .fetch_optional(&self.pool)
+        .await
+        .context(DatabaseSnafu)?;
+
+        row.map(|row| Ok((parse_u256(row.get("amount"))?, row.get("observed_at"))))
+            .transpose()
+    }
+
+    /// Cache the preparation for a selected quote until it's taken or `ttl` passes
+    pub fn cache_fill_preparation(
+        &self,
+        quote_id: Uuid,
+        preparation: FillPreparation,
+        ttl: std::time::Duration,
+    ) {
+        self.fill_preparations.insert(
+            quote_id,
+            CachedFillPreparation {
+                preparation,
+                expires_at: time::Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Remove and return the preparation for a quote, `None` if there is none or it expired
+    pub fn take_fill_preparation(&self, quote_id: Uuid) -> Option<FillPreparation> {
+        let (_, cached) = self.fill_preparations.remove(&quote_id)?;
+        (cached.expires_at > time::Instant::now()).then_some(cached.preparation)
+    }
+
+    /// Unexpired preparations paying out on `network`, i.e. funds other fills already claim
+    pub fn pending_fill_preparations(
+        &self,
+        network: impl Into<ChainNetwork>,
+    ) -> Vec<FillPreparation> {
+        let network = network.into();
+        let now = time::Instant::now();
+        self.fill_preparations
+            .iter()
+            .filter(|cached| {
+                cached.expires_at > now && cached.preparation.lot.currency.network() == network
+            })
+            .map(|cached| cached.preparation.clone())
+            .collect()
+    }
+
+    /// Drop expired preparations and quote locks, returning how many were dropped
+    pub fn expire_fill_preparations(&self) -> usize {
+        let now = time::Instant::now();
+        let before = self.fill_preparations.len() + self.quote_locks.len();
+        self.fill_preparations
+            .retain(|_, cached| cached.expires_at > now);
+        self.quote_locks.retain(|_, cached| cached.expires_at > now);
+        before.saturating_sub(self.fill_preparations.len() + self.quote_locks.len())
+    }
+
+    /// Hold the funds `preparation` claims for a locked quote until it's
+    /// released or `ttl` passes
+    pub fn lock_quote(
+        &self,
+        quote_id: Uuid,
+        preparation: FillPreparation,
+        ttl: std::time::Duration,
+    ) {
+        self.quote_locks.insert(
+            quote_id,
+            CachedFillPreparation {
+                preparation,
+                expires_at: time::Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Release the lock on a quote, returning its preparation if it hadn't expired
+    pub fn release_quote_lock(&self, quote_id: Uuid) -> Option<FillPreparation> {
+        let (_, cached) = self.quote_locks.remove(&quote_id)?;
+        (cached.expires_at > time::Instant::now()).then_some(cached.preparation)
+    }
+
+    /// Funds held by unexpired locks paying out on `network`, leaving out `except`'s own lock
+    pub fn locked_fill_preparations(
+        &self,
+        network: impl Into<ChainNetwork>,
+        except: Option<Uuid>,
+    ) -> Vec<FillPreparation> {
+        let network = network.into();
+        let now = time::Instant::now();
+        self.quote_locks
+            .iter()
+            .filter(|cached| {
+                Some(*cached.key()) != except
+                    && cached.expires_at > now
+                    && cached.preparation.lot.currency.network() == network
+            })
+            .map(|cached| cached.preparation.clone())
+            .collect()
+    }
+
     async fn run_cleanup_task(&self) {
         let mut interval = time::interval(time::Duration::from_secs(600)); // 10 minutes
 
         loop {
             interval.tick().await;
 
-            match self.delete_expired_quotes().await {
+            let now = self.clock.now();
+            match self
+                .delete_unreferenced_quotes(now, now - self.retention)
+                .await
+            {
                 Ok(count) => {
                     if count > 0 {
-                        info!("Deleted {} expired quotes", count);
+                        info!("Deleted {} quotes past retention", count);
                     }
                 }
                 Err(e) => {
-                    error!("Failed to delete expired quotes: {}", e);
+                    error!("Failed to delete quotes past retention: {}", e);
                 }
             }
+            match self
+                .delete_quote_attempts_before(now - self.retention)
+                .await
+            {
+                Ok(count) => {
+                    if count > 0 {
+                        info!("Deleted {} quote attempts past retention", count);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to delete quote attempts past retention: {}", e);
+                }
+            }
+
+            let expired = self.expire_fill_preparations();
+            if expired > 0 {
+                info!("Dropped {} expired fill preparations", expired);
+            }
         }
     }
 
+    /// The chain column holds the currency's network, e.g. `ethereum` or `ethereum:8453`
     fn serialize_currency(&self, currency: &Currency) -> Result<(String, serde_json::Value, i16)> {
-        let chain = match currency.chain {
-            ChainType::Bitcoin => "bitcoin".to_string(),
-            ChainType::Ethereum => "ethereum".to_string(),
-        };
+        let chain = currency.network().to_string();
 
         let token = match &currency.token {
             TokenIdentifier::Native => serde_json::json!({"type": "Native"}),
@@ -315,26 +1404,80 @@ impl QuoteStorage {
         })
     }
 
+    fn deserialize_quote_attempt(&self, row: &PgRow) -> Result<QuoteAttempt> {
+        let invalid = |message: String| QuoteStorageError::InvalidQuoteAttempt { message };
+        let mode = match row.get::<String, _>("mode").as_str() {
+            "exact_input" => QuoteMode::ExactInput,
+            "exact_output" => QuoteMode::ExactOutput,
+            mode => return Err(invalid(format!("unknown mode {mode}"))),
+        };
+        let request = QuoteRequest {
+            mode,
+            from: self.deserialize_currency(
+                &row.get::<String, _>("from_chain"),
+                row.get("from_token"),
+                row.get("from_decimals"),
+            )?,
+            to: self.deserialize_currency(
+                &row.get::<String, _>("to_chain"),
+                row.get("to_token"),
+                row.get("to_decimals"),
+            )?,
+            amount: parse_u256(row.get("amount"))?,
+        };
+
+        let quote = match (
+            row.get::<Option<Uuid>, _>("quote_id"),
+            row.get::<Option<String>, _>("quoted_from_amount"),
+            row.get::<Option<String>, _>("quoted_to_amount"),
+        ) {
+            (Some(quote_id), Some(from_amount), Some(to_amount)) => Some(OfferedQuote {
+                quote_id,
+                from_amount: parse_u256(from_amount)?,
+                to_amount: parse_u256(to_amount)?,
+            }),
+            _ => None,
+        };
+
+        Ok(QuoteAttempt {
+            id: row.get("id"),
+            request_id: row.get("request_id"),
+            request,
+            outcome: row.get::<String, _>("outcome").parse().map_err(invalid)?,
+            message: row.get("message"),
+            quote,
+            inputs: PricingInputs {
+                sats_per_vbyte: row.get("sats_per_vbyte"),
+                eth_per_btc: row.get("eth_per_btc"),
+            },
+            duration: std::time::Duration::from_millis(
+                row.get::<i64, _>("duration_ms").unsigned_abs(),
+            ),
+            created_at: row.get("created_at"),
+        })
+    }
+
     fn deserialize_currency(
         &self,
         chain: &str,
         token: serde_json::Value,
         decimals: i16,
     ) -> Result<Currency> {
-        let chain_type = match chain {
-            "bitcoin" => ChainType::Bitcoin,
-            "ethereum" => ChainType::Ethereum,
-            _ => {
-                return InvalidChainTypeSnafu { chain }.fail();
-            }
-        };
+        let network: ChainNetwork =
+            chain
+                .parse()
+                .map_err(|_| QuoteStorageError::InvalidChainType {
+                    chain: chain.to_string(),
+                })?;
 
         let token_identifier = if let Some(token_type) = token.get("type") {
             match token_type.as_str() {
                 Some("Native") => TokenIdentifier::Native,
                 Some("Address") => {
                     if let Some(addr) = token.get("data").and_then(|v| v.as_str()) {
-                        TokenIdentifier::Address(addr.to_string())
+                        // Rows from before addresses were checksummed read back canonical
+                        TokenIdentifier::address(addr)
+                            .map_err(|_| InvalidTokenIdentifierSnafu.build())?
                     } else {
                         return InvalidTokenIdentifierSnafu.fail();
                     }
@@ -346,9 +1489,20 @@ impl QuoteStorage {
         };
 
         Ok(Currency {
-            chain: chain_type,
+            chain: network.chain,
             token: token_identifier,
             decimals: decimals as u8,
+            chain_id: network.chain_id,
         })
     }
 }
+
+fn parse_bucket(bucket: String) -> Result<WalletBucket> {
+    bucket
+        .parse()
+        .map_err(|_| QuoteStorageError::InvalidWalletBucket { bucket })
+}
+
+fn parse_u256(value: String) -> Result<U256> {
+    U256::from_str_radix(&value, 10).map_err(|_| QuoteStorageError::InvalidU256 { value })
+}
@@ -0,0 +1,464 @@
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, Parser};
+use otc_models::{ChainNetwork, Lot, QuoteMode, SupportedCurrencies, SupportedCurrenciesError};
+use snafu::prelude::*;
+use std::sync::Arc;
+
+use crate::{
+    quote_storage::{
+        QuoteAttempt, QuoteAttemptFilter, QuoteAttemptOutcome, QuoteStorage, QuoteStorageError,
+    },
+    wallet_buckets::{WalletBucket, WalletBucketError, WalletBuckets},
+};
+
+#[derive(Debug, Snafu)]
+pub enum ToolError {
+    #[snafu(display("{}", source))]
+    Storage { source: QuoteStorageError },
+
+    #[snafu(display("{}", source))]
+    Buckets { source: WalletBucketError },
+
+    #[snafu(display("Supported currencies error: {}", source))]
+    SupportedCurrencies { source: SupportedCurrenciesError },
+
+    #[snafu(display("{} isn't a currency the market maker quotes on {}", symbol, network))]
+    UnknownCurrency {
+        network: ChainNetwork,
+        symbol: String,
+    },
+
+    #[snafu(display("Invalid amount {:?} of {}", amount, symbol))]
+    InvalidAmount { amount: String, symbol: String },
+}
+
+/// Commands for inspecting and operating a market maker, run instead of the
+/// market maker itself when named as the first argument
+#[derive(Parser, Debug)]
+#[command(name = "market-maker")]
+pub enum Tool {
+    /// List recent quote requests and how they were answered
+    Quotes(QuotesArgs),
+    /// Move funds between the wallet buckets of a currency. A running market
+    /// maker picks the move up when it next reconciles its buckets
+    MoveFunds(MoveFundsArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct QuotesArgs {
+    /// Database URL of the market maker's quote storage
+    #[arg(long, env = "MM_DATABASE_URL")]
+    pub database_url: String,
+
+    /// Only requests made since then, an RFC 3339 time or an age like 30m, 2h or 1d
+    #[arg(long, value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only requests for this pair, as from/to networks, e.g. bitcoin/ethereum
+    #[arg(long, value_parser = parse_pair)]
+    pub pair: Option<(ChainNetwork, ChainNetwork)>,
+
+    /// Only requests with this outcome: quoted, maker_unavailable,
+    /// invalid_request, won or lost
+    #[arg(long)]
+    pub outcome: Option<QuoteAttemptOutcome>,
+
+    /// Most requests to list, newest first
+    #[arg(long, default_value = "50")]
+    pub limit: u32,
+}
+
+impl QuotesArgs {
+    #[must_use]
+    pub fn filter(&self) -> QuoteAttemptFilter {
+        QuoteAttemptFilter {
+            since: self.since,
+            pair: self.pair,
+            outcome: self.outcome,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct MoveFundsArgs {
+    /// Database URL of the market maker's quote storage
+    #[arg(long, env = "MM_DATABASE_URL")]
+    pub database_url: String,
+
+    /// TOML or JSON file listing the tokens the market maker quotes, defaults
+    /// to BTC and cbBTC
+    #[arg(long, env = "SUPPORTED_CURRENCIES_FILE")]
+    pub supported_currencies_file: Option<String>,
+
+    /// Network of the currency, e.g. bitcoin, ethereum or ethereum:8453
+    #[arg(long)]
+    pub network: ChainNetwork,
+
+    /// Symbol of the currency on that network, e.g. BTC or cbBTC
+    #[arg(long)]
+    pub symbol: String,
+
+    /// Bucket to move the funds out of: trading, protocol_fees or reserved
+    #[arg(long)]
+    pub from: WalletBucket,
+
+    /// Bucket to move the funds into
+    #[arg(long)]
+    pub to: WalletBucket,
+
+    /// Amount in whole tokens, e.g. 0.5
+    #[arg(long)]
+    pub amount: String,
+}
+
+impl MoveFundsArgs {
+    /// The currency named and the amount of it to move
+    pub fn lot(&self, supported: &SupportedCurrencies) -> Result<Lot, ToolError> {
+        let currency = supported
+            .on_chain(self.network)
+            .find(|currency| currency.symbol.eq_ignore_ascii_case(&self.symbol))
+            .context(UnknownCurrencySnafu {
+                network: self.network,
+                symbol: self.symbol.clone(),
+            })?
+            .currency();
+        let amount = currency
+            .parse_amount(&self.amount)
+            .context(InvalidAmountSnafu {
+                amount: self.amount.clone(),
+                symbol: self.symbol.clone(),
+            })?;
+        Ok(Lot { currency, amount })
+    }
+}
+
+pub async fn run(tool: Tool) -> Result<(), ToolError> {
+    match tool {
+        Tool::Quotes(args) => {
+            let quote_storage = QuoteStorage::open(&args.database_url)
+                .await
+                .context(StorageSnafu)?;
+            let attempts = quote_storage
+                .quote_attempts(&args.filter(), args.limit)
+                .await
+                .context(StorageSnafu)?;
+            print!("{}", format_table(&attempts));
+            Ok(())
+        }
+        Tool::MoveFunds(args) => {
+            let supported = match &args.supported_currencies_file {
+                Some(path) => SupportedCurrencies::load(path).context(SupportedCurrenciesSnafu)?,
+                None => SupportedCurrencies::default(),
+            };
+            let lot = args.lot(&supported)?;
+            let quote_storage = QuoteStorage::open(&args.database_url)
+                .await
+                .context(StorageSnafu)?;
+            let earmarks = WalletBuckets::load(Arc::new(quote_storage))
+                .await
+                .context(BucketsSnafu)?
+                .transfer(&lot.currency, args.from, args.to, lot.amount)
+                .await
+                .context(BucketsSnafu)?;
+            println!(
+                "Moved {} {} from {} to {}, now {} in protocol_fees and {} in reserved",
+                args.amount,
+                args.symbol,
+                args.from,
+                args.to,
+                lot.currency.format_amount(earmarks.protocol_fees),
+                lot.currency.format_amount(earmarks.reserved)
+            );
+            Ok(())
+        }
+    }
+}
+
+fn parse_since(s: &str) -> Result<DateTime<Utc>, String> {
+    parse_since_at(s, Utc::now())
+}
+
+/// `s` as a time, either RFC 3339 or an age before `now`
+fn parse_since_at(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let invalid = || format!("invalid time {s:?}, expected RFC 3339 or an age like 30m, 2h or 1d");
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .filter(|&index| index > 0)
+        .ok_or_else(invalid)?;
+    let (count, unit) = s.split_at(unit_start);
+    let count: i64 = count.parse().map_err(|_| invalid())?;
+    let age = match unit {
+        "s" => Duration::try_seconds(count),
+        "m" => Duration::try_minutes(count),
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    Ok(now - age)
+}
+
+fn parse_pair(s: &str) -> Result<(ChainNetwork, ChainNetwork), String> {
+    let (from, to) = s
+        .split_once('/')
+        .ok_or_else(|| format!("invalid pair {s:?}, expected from/to, e.g. bitcoin/ethereum"))?;
+    Ok((from.parse()?, to.parse()?))
+}
+
+const TABLE_HEADER: [&str; 9] = [
+    "TIME", "OUTCOME", "PAIR", "MODE", "AMOUNT", "QUOTED", "SAT/VB", "ETH/BTC", "MS",
+];
+
+/// `attempts` as a table, one row per attempt, with why it was turned down
+/// after the columns
+#[must_use]
+pub fn format_table(attempts: &[QuoteAttempt]) -> String {
+    let rows: Vec<[String; 9]> = attempts.iter().map(table_row).collect();
+    let mut widths = TABLE_HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_line = |cells: &[String], message: Option<&str>| {
+        let mut line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        if let Some(message) = message {
+            line.push_str("  ");
+            line.push_str(message);
+        }
+        format!("{}\n", line.trim_end())
+    };
+
+    let mut table = format_line(&TABLE_HEADER.map(String::from), None);
+    for (attempt, row) in attempts.iter().zip(&rows) {
+        table.push_str(&format_line(row, attempt.message.as_deref()));
+    }
+    table
+}
+
+fn table_row(attempt: &QuoteAttempt) -> [String; 9] {
+    let request = &attempt.request;
+    let (mode, amount) = match request.mode {
+        QuoteMode::ExactInput => ("exact_input", request.from.format_amount(request.amount)),
+        QuoteMode::ExactOutput => ("exact_output", request.to.format_amount(request.amount)),
+    };
+    let quoted = attempt.quote.map_or_else(
+        || "-".to_string(),
+        |quote| {
+            format!(
+                "{} -> {}",
+                request.from.format_amount(quote.from_amount),
+                request.to.format_amount(quote.to_amount)
+            )
+        },
+    );
+    let input = |value: Option<f64>, precision: usize| {
+        value.map_or_else(|| "-".to_string(), |value| format!("{value:.precision$}"))
+    };
+    [
+        attempt.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        attempt.outcome.to_string(),
+        format!("{}/{}", request.from.network(), request.to.network()),
+        mode.to_string(),
+        amount,
+        quoted,
+        input(attempt.inputs.sats_per_vbyte, 1),
+        input(attempt.inputs.eth_per_btc, 4),
+        attempt.duration.as_millis().to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+    use otc_models::{ChainType, QuoteRequest, SupportedCurrencies};
+    use otc_protocols::rfq::RFQResult;
+    use uuid::Uuid;
+
+    use crate::wrapped_bitcoin_quoter::PricingInputs;
+
+    #[test]
+    fn since_accepts_ages_and_times() {
+        let now = DateTime::parse_from_rfc3339("2025-08-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            parse_since_at("30m", now).unwrap(),
+            now - Duration::minutes(30)
+        );
+        assert_eq!(parse_since_at("2h", now).unwrap(), now - Duration::hours(2));
+        assert_eq!(parse_since_at("1d", now).unwrap(), now - Duration::days(1));
+        assert_eq!(
+            parse_since_at("2025-08-14T08:30:00+02:00", now).unwrap(),
+            DateTime::parse_from_rfc3339("2025-08-14T06:30:00Z").unwrap()
+        );
+
+        for invalid in ["", "h", "2", "2w", "-2h", "yesterday"] {
+            assert!(parse_since_at(invalid, now).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn pair_is_from_and_to_networks() {
+        assert_eq!(
+            parse_pair("bitcoin/ethereum:8453").unwrap(),
+            (
+                ChainNetwork::primary(ChainType::Bitcoin),
+                ChainNetwork::evm(8453)
+            )
+        );
+        assert!(parse_pair("bitcoin").is_err());
+        assert!(parse_pair("bitcoin/dogecoin").is_err());
+    }
+
+    #[test]
+    fn quotes_command_parses_its_filters() {
+        let Ok(Tool::Quotes(args)) = Tool::try_parse_from([
+            "market-maker",
+            "quotes",
+            "--database-url",
+            "postgres://localhost/mm",
+            "--pair",
+            "ethereum/bitcoin",
+            "--outcome",
+            "maker_unavailable",
+            "--limit",
+            "10",
+        ]) else {
+            panic!("quotes should parse");
+        };
+
+        let filter = args.filter();
+        assert_eq!(filter.since, None);
+        assert_eq!(
+            filter.pair,
+            Some((ChainType::Ethereum.into(), ChainType::Bitcoin.into()))
+        );
+        assert_eq!(filter.outcome, Some(QuoteAttemptOutcome::MakerUnavailable));
+        assert_eq!(args.limit, 10);
+
+        assert!(Tool::try_parse_from([
+            "market-maker",
+            "quotes",
+            "--database-url",
+            "postgres://localhost/mm",
+            "--outcome",
+            "declined",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn move_funds_names_a_quoted_currency_in_whole_tokens() {
+        let parse = |args: &[&str]| {
+            let mut argv = vec![
+                "market-maker",
+                "move-funds",
+                "--database-url",
+                "postgres://localhost/mm",
+            ];
+            argv.extend_from_slice(args);
+            match Tool::try_parse_from(argv).unwrap() {
+                Tool::MoveFunds(args) => args,
+                tool => panic!("expected move-funds, got {tool:?}"),
+            }
+        };
+        let supported = SupportedCurrencies::default();
+
+        let args = parse(&[
+            "--network",
+            "bitcoin",
+            "--symbol",
+            "btc",
+            "--from",
+            "trading",
+            "--to",
+            "reserved",
+            "--amount",
+            "0.5",
+        ]);
+        assert_eq!(args.from, WalletBucket::Trading);
+        assert_eq!(args.to, WalletBucket::Reserved);
+        let lot = args.lot(&supported).unwrap();
+        assert_eq!(lot.currency.chain, ChainType::Bitcoin);
+        assert_eq!(lot.amount, U256::from(50_000_000u64));
+
+        let on_wrong_network = parse(&[
+            "--network",
+            "ethereum",
+            "--symbol",
+            "BTC",
+            "--from",
+            "trading",
+            "--to",
+            "reserved",
+            "--amount",
+            "1",
+        ]);
+        assert!(matches!(
+            on_wrong_network.lot(&supported),
+            Err(ToolError::UnknownCurrency { .. })
+        ));
+        let too_precise = parse(&[
+            "--network",
+            "bitcoin",
+            "--symbol",
+            "BTC",
+            "--from",
+            "reserved",
+            "--to",
+            "trading",
+            "--amount",
+            "0.000000001",
+        ]);
+        assert!(matches!(
+            too_precise.lot(&supported),
+            Err(ToolError::InvalidAmount { .. })
+        ));
+    }
+
+    #[test]
+    fn table_has_a_row_per_attempt_with_its_rejection() {
+        let supported = SupportedCurrencies::default();
+        let request = QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            from: supported.all()[0].currency(),
+            to: supported.all()[1].currency(),
+            amount: U256::from(150_000_000u64),
+        };
+        let attempt = QuoteAttempt::new(
+            Uuid::new_v4(),
+            &request,
+            &RFQResult::MakerUnavailable("Insufficient balance to fulfill quote".to_string()),
+            PricingInputs {
+                sats_per_vbyte: Some(12.5),
+                eth_per_btc: None,
+            },
+            std::time::Duration::from_millis(42),
+            Utc::now(),
+        );
+
+        let table = format_table(&[attempt]);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("TIME"));
+        assert!(lines[1].contains("maker_unavailable"));
+        assert!(lines[1].contains("exact_input"));
+        assert!(lines[1].contains("12.5"));
+        assert!(lines[1].contains("42"));
+        assert!(lines[1].ends_with("Insufficient balance to fulfill quote"));
+
+        assert_eq!(format_table(&[]).lines().count(), 1);
+    }
+}
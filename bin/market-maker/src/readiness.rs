@@ -0,0 +1,228 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use otc_protocols::rfq::RFQResult;
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::info;
+
+/// Why quote requests are turned down before the market maker is ready
+pub const WARMING_UP: &str = "warming up";
+
+/// How often a pending check is retried
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Something the market maker needs before it can quote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessCheck {
+    /// The BTC/ETH price oracle has a price
+    PriceAvailable,
+    /// The Bitcoin wallet finished a sync, so balances aren't read as zero
+    BitcoinWalletSynced,
+    /// The EVM provider answers requests
+    EvmProviderConnected,
+}
+
+impl ReadinessCheck {
+    pub const ALL: [ReadinessCheck; 3] = [
+        ReadinessCheck::PriceAvailable,
+        ReadinessCheck::BitcoinWalletSynced,
+        ReadinessCheck::EvmProviderConnected,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Readiness {
+    price_available: bool,
+    bitcoin_wallet_synced: bool,
+    evm_provider_connected: bool,
+}
+
+impl Readiness {
+    fn flag(&mut self, check: ReadinessCheck) -> &mut bool {
+        match check {
+            ReadinessCheck::PriceAvailable => &mut self.price_available,
+            ReadinessCheck::BitcoinWalletSynced => &mut self.bitcoin_wallet_synced,
+            ReadinessCheck::EvmProviderConnected => &mut self.evm_provider_connected,
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.price_available && self.bitcoin_wallet_synced && self.evm_provider_connected
+    }
+}
+
+/// Startup warm-up shared by the RFQ and OTC handlers. Checks only ever pass,
+/// once ready the market maker stays ready
+#[derive(Debug)]
+pub struct ReadinessState {
+    state: watch::Sender<Readiness>,
+}
+
+impl ReadinessState {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: watch::channel(Readiness::default()).0,
+        }
+    }
+
+    pub fn mark(&self, check: ReadinessCheck) {
+        self.state.send_if_modified(|readiness| {
+            let flag = readiness.flag(check);
+            let changed = !*flag;
+            *flag = true;
+            if changed {
+                info!("Readiness check passed: {:?}", check);
+                if readiness.is_ready() {
+                    info!("Market maker is ready to quote");
+                }
+            }
+            changed
+        });
+    }
+
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.state.borrow().is_ready()
+    }
+
+    /// Checks that haven't passed yet, none once ready
+    #[must_use]
+    pub fn pending_checks(&self) -> Vec<ReadinessCheck> {
+        let mut readiness = *self.state.borrow();
+        ReadinessCheck::ALL
+            .into_iter()
+            .filter(|check| !*readiness.flag(*check))
+            .collect()
+    }
+
+    /// Wait until every check has passed
+    pub async fn wait_ready(&self) {
+        let mut receiver = self.state.subscribe();
+        // The sender lives as long as `self`, so this can't fail
+        let _ = receiver.wait_for(Readiness::is_ready).await;
+    }
+
+    /// Run `task` once every check has passed
+    pub async fn once_ready<F: Future>(&self, task: F) -> F::Output {
+        self.wait_ready().await;
+        task.await
+    }
+
+    /// Answer to a quote request while warming up, `None` once quotes can be computed
+    #[must_use]
+    pub fn warm_up_result<T>(&self) -> Option<RFQResult<T>> {
+        (!self.is_ready()).then(|| RFQResult::MakerUnavailable(WARMING_UP.to_string()))
+    }
+
+    /// Retry `probe` in the background until it passes, then mark `check`
+    pub fn spawn_probe<F, Fut>(self: &Arc<Self>, check: ReadinessCheck, probe: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = bool> + Send,
+    {
+        let readiness = self.clone();
+        tokio::spawn(async move {
+            while !probe().await {
+                tokio::time::sleep(PROBE_INTERVAL).await;
+            }
+            readiness.mark(check);
+        });
+    }
+}
+
+impl Default for ReadinessState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Price oracle stand-in that has no price until `delay` has passed
+    fn slow_oracle(delay: Duration) -> Arc<AtomicBool> {
+        let has_price = Arc::new(AtomicBool::new(false));
+        let oracle = has_price.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            oracle.store(true, Ordering::SeqCst);
+        });
+        has_price
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_turned_down_until_a_slow_oracle_has_a_price() {
+        let readiness = Arc::new(ReadinessState::new());
+        readiness.mark(ReadinessCheck::BitcoinWalletSynced);
+        readiness.mark(ReadinessCheck::EvmProviderConnected);
+
+        let has_price = slow_oracle(Duration::from_millis(500));
+        readiness.spawn_probe(ReadinessCheck::PriceAvailable, move || {
+            let has_price = has_price.clone();
+            async move { has_price.load(Ordering::SeqCst) }
+        });
+
+        for _ in 0..3 {
+            match readiness.warm_up_result::<()>() {
+                Some(RFQResult::MakerUnavailable(reason)) => assert_eq!(reason, WARMING_UP),
+                other => panic!("Expected a warm-up response, got {other:?}"),
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        tokio::time::timeout(Duration::from_secs(5), readiness.wait_ready())
+            .await
+            .expect("the oracle's price should make the market maker ready");
+        assert!(readiness.is_ready());
+        assert!(readiness.warm_up_result::<()>().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tasks_held_until_ready_start_once_warmed_up() {
+        let readiness = Arc::new(ReadinessState::new());
+        let started = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn({
+            let readiness = readiness.clone();
+            let started = started.clone();
+            async move {
+                readiness
+                    .once_ready(async move { started.store(true, Ordering::SeqCst) })
+                    .await;
+            }
+        });
+
+        readiness.mark(ReadinessCheck::PriceAvailable);
+        readiness.mark(ReadinessCheck::BitcoinWalletSynced);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!started.load(Ordering::SeqCst));
+
+        readiness.mark(ReadinessCheck::EvmProviderConnected);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("the task should start once ready")
+            .unwrap();
+        assert!(started.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_every_check_is_needed() {
+        let readiness = ReadinessState::new();
+        readiness.mark(ReadinessCheck::PriceAvailable);
+        readiness.mark(ReadinessCheck::BitcoinWalletSynced);
+        assert!(!readiness.is_ready());
+        assert_eq!(
+            readiness.pending_checks(),
+            vec![ReadinessCheck::EvmProviderConnected]
+        );
+        readiness.mark(ReadinessCheck::EvmProviderConnected);
+        assert!(readiness.is_ready());
+        assert!(readiness.pending_checks().is_empty());
+        // Checks don't regress
+        readiness.mark(ReadinessCheck::PriceAvailable);
+        assert!(readiness.is_ready());
+    }
+}
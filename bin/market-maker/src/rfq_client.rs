@@ -1,17 +1,20 @@
+use crate::capabilities::{self, CapabilitiesError};
 use crate::quote_storage::QuoteStorage;
+use crate::readiness::ReadinessState;
 use crate::rfq_handler::RFQMessageHandler;
+use crate::rfq_placement::RfqPlacement;
 use crate::wallet::WalletManager;
 use crate::{config::Config, wrapped_bitcoin_quoter::WrappedBitcoinQuoter};
+use common::{is_auth_rejection, ConnectionEnd, ReconnectError, ReconnectingWsClient, WsStream};
 use futures_util::{SinkExt, StreamExt};
-use otc_protocols::rfq::{ProtocolMessage, RFQRequest};
+use otc_protocols::rfq::{ProtocolMessage, RFQRequest, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER};
 use snafu::prelude::*;
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
 use tokio_tungstenite::{
     connect_async_with_config,
-    tungstenite::{http, Message},
+    tungstenite::{http, protocol::frame::coding::CloseCode, Message},
 };
-use tracing::{error, info, warn};
+use tracing::{error, info};
 use url::Url;
 
 #[derive(Debug, Snafu)]
@@ -32,105 +35,93 @@ pub enum RfqClientError {
     #[snafu(display("Message serialization error: {}", source))]
     Serialization { source: serde_json::Error },
 
-    #[snafu(display("Maximum reconnection attempts reached"))]
-    MaxReconnectAttempts,
+    #[snafu(display("Capability negotiation failed: {}", source))]
+    Capabilities { source: CapabilitiesError },
+
+    #[snafu(display("Gave up after {} reconnection attempts: {}", attempts, source))]
+    MaxReconnectAttempts {
+        attempts: u32,
+        source: Box<RfqClientError>,
+    },
 }
 
 type Result<T, E = RfqClientError> = std::result::Result<T, E>;
 
+impl RfqClientError {
+    /// Whether the RFQ server turned the API key away, retrying won't help
+    #[must_use]
+    pub fn is_auth_rejection(&self) -> bool {
+        match self {
+            Self::WebSocketConnection { source } => is_auth_rejection(source),
+            Self::MaxReconnectAttempts { source, .. } => source.is_auth_rejection(),
+            _ => false,
+        }
+    }
+}
+
 pub struct RfqClient {
     config: Config,
     handler: RFQMessageHandler,
-    rfq_ws_url: String,
+    /// Which RFQ server the one connection goes to
+    placement: Arc<RfqPlacement>,
+    connection: ReconnectingWsClient<WsStream, RfqClientError>,
 }
 
 impl RfqClient {
     pub fn new(
         config: Config,
-        rfq_ws_url: String,
-        wrapped_bitcoin_quoter: WrappedBitcoinQuoter,
+        placement: Arc<RfqPlacement>,
+        wrapped_bitcoin_quoter: Arc<WrappedBitcoinQuoter>,
         quote_storage: Arc<QuoteStorage>,
         wallet_manager: WalletManager,
+        readiness: Arc<ReadinessState>,
     ) -> Self {
         let handler = RFQMessageHandler::new(
             config.market_maker_id.clone(),
             wrapped_bitcoin_quoter,
             quote_storage,
             wallet_manager,
+            readiness,
         );
+        let connection = ReconnectingWsClient::new("RFQ server", config.reconnect_options(), {
+            let config = config.clone();
+            let placement = placement.clone();
+            move || {
+                let config = config.clone();
+                let placement = placement.clone();
+                async move { placement.connect(|url| connect(config.clone(), url)).await }
+            }
+        })
+        .with_fatal_errors(RfqClientError::is_auth_rejection);
         Self {
             config,
             handler,
-            rfq_ws_url,
+            placement,
+            connection,
         }
     }
 
+    /// Pick the fastest RFQ server, then serve quote requests from it until
+    /// reconnecting gives up
     pub async fn run(&self) -> Result<()> {
-        let mut reconnect_attempts = 0;
-
-        loop {
-            match self.connect_and_run().await {
-                Ok(()) => {
-                    info!("RFQ WebSocket connection closed normally");
-                    reconnect_attempts = 0;
-                }
-                Err(e) => {
-                    error!("RFQ WebSocket error: {}", e);
-                    reconnect_attempts += 1;
-
-                    if reconnect_attempts >= self.config.max_reconnect_attempts {
-                        return Err(RfqClientError::MaxReconnectAttempts);
+        self.placement
+            .measure(|url| connect(self.config.clone(), url))
+            .await;
+        self.connection
+            .run(|ws_stream| self.handle_connection(ws_stream))
+            .await
+            .map_err(|e| match e {
+                ReconnectError::MaxAttempts { attempts, source } => {
+                    RfqClientError::MaxReconnectAttempts {
+                        attempts,
+                        source: Box::new(source),
                     }
-
-                    let delay = Duration::from_secs(
-                        self.config.reconnect_interval_secs * u64::from(reconnect_attempts),
-                    );
-                    warn!(
-                        "Reconnecting to RFQ server in {} seconds (attempt {}/{})",
-                        delay.as_secs(),
-                        reconnect_attempts,
-                        self.config.max_reconnect_attempts
-                    );
-                    sleep(delay).await;
                 }
-            }
-        }
+                ReconnectError::Fatal { source } => source,
+            })
     }
 
-    async fn connect_and_run(&self) -> Result<()> {
-        let url = Url::parse(&self.rfq_ws_url).context(UrlParseSnafu)?;
-        info!("Connecting to RFQ server at {}", url);
-
-        // Build request with authentication headers
-        let request = http::Request::builder()
-            .method("GET")
-            .uri(url.as_str())
-            .header("Host", url.host_str().unwrap_or("localhost"))
-            .header("Connection", "Upgrade")
-            .header("Upgrade", "websocket")
-            .header("Sec-WebSocket-Version", "13")
-            .header(
-                "Sec-WebSocket-Key",
-                tokio_tungstenite::tungstenite::handshake::client::generate_key(),
-            )
-            .header("X-API-Key-ID", &self.config.api_key_id)
-            .header("X-API-Key", &self.config.api_key)
-            .body(())
-            .map_err(|e| RfqClientError::WebSocketConnection {
-                source: tokio_tungstenite::tungstenite::Error::Http(
-                    http::Response::builder()
-                        .status(400)
-                        .body(Some(format!("Failed to build request: {e}").into_bytes()))
-                        .unwrap(),
-                ),
-            })?;
-
-        let (ws_stream, _) = connect_async_with_config(request, None, false)
-            .await
-            .context(WebSocketConnectionSnafu)?;
-
-        info!("RFQ WebSocket connected, authenticated via headers");
-
+    async fn handle_connection(&self, ws_stream: WsStream) -> Result<ConnectionEnd> {
         let (mut write, mut read) = ws_stream.split();
 
         // Handle messages
@@ -146,6 +137,10 @@ impl RfqClient {
                     // Otherwise, try to parse as a protocol message
                     match serde_json::from_str::<ProtocolMessage<RFQRequest>>(&text) {
                         Ok(protocol_msg) => {
+                            if let RFQRequest::GoingAway { reason, .. } = &protocol_msg.payload {
+                                info!("RFQ server is going away: {}", reason);
+                                return Ok(ConnectionEnd::GoingAway);
+                            }
                             if let Some(response) = self.handler.handle_request(&protocol_msg).await
                             {
                                 let response_json =
@@ -161,8 +156,11 @@ impl RfqClient {
                         }
                     }
                 }
-                Ok(Message::Close(_)) => {
+                Ok(Message::Close(frame)) => {
                     info!("RFQ server closed connection");
+                    if frame.is_some_and(|frame| frame.code == CloseCode::Away) {
+                        return Ok(ConnectionEnd::GoingAway);
+                    }
                     break;
                 }
                 Err(e) => {
@@ -173,6 +171,55 @@ impl RfqClient {
             }
         }
 
-        Ok(())
+        Ok(ConnectionEnd::Closed)
     }
 }
+
+/// Connect and authenticate once, then hang up
+pub(crate) async fn check_connection(config: Config, rfq_ws_url: String) -> Result<()> {
+    let mut ws_stream = connect(config, rfq_ws_url).await?;
+    let _ = ws_stream.close(None).await;
+    Ok(())
+}
+
+async fn connect(config: Config, rfq_ws_url: String) -> Result<WsStream> {
+    capabilities::negotiate(&rfq_ws_url)
+        .await
+        .context(CapabilitiesSnafu)?;
+
+    let url = Url::parse(&rfq_ws_url).context(UrlParseSnafu)?;
+    info!("Connecting to RFQ server at {}", url);
+
+    // Build request with authentication headers
+    let request = http::Request::builder()
+        .method("GET")
+        .uri(url.as_str())
+        .header("Host", url.host_str().unwrap_or("localhost"))
+        .header("Connection", "Upgrade")
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Version", "13")
+        .header(
+            "Sec-WebSocket-Key",
+            tokio_tungstenite::tungstenite::handshake::client::generate_key(),
+        )
+        .header("X-API-Key-ID", &config.api_key_id)
+        .header("X-API-Key", config.api_key.expose())
+        .header(PROTOCOL_VERSION_HEADER, PROTOCOL_VERSION)
+        .body(())
+        .map_err(|e| RfqClientError::WebSocketConnection {
+            source: tokio_tungstenite::tungstenite::Error::Http(
+                http::Response::builder()
+                    .status(400)
+                    .body(Some(format!("Failed to build request: {e}").into_bytes()))
+                    .unwrap(),
+            ),
+        })?;
+
+    let (ws_stream, _) = connect_async_with_config(request, None, false)
+        .await
+        .context(WebSocketConnectionSnafu)?;
+
+    info!("RFQ WebSocket connected, authenticated via headers");
+
+    Ok(ws_stream)
+}
@@ -1,39 +1,57 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use otc_models::{Currency, Lot, Quote};
-use otc_protocols::rfq::{ProtocolMessage, RFQRequest, RFQResponse, RFQResult};
-use std::sync::Arc;
-use tracing::{debug, error, info, warn};
+use otc_protocols::rfq::{
+    BatchedQuoteResponse, ProtocolMessage, QuoteWithFees, RFQErrorCode, RFQRequest, RFQResponse,
+    RFQResult,
+};
+use std::{sync::Arc, time::Instant};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
-use crate::quote_storage::QuoteStorage;
-use crate::wallet::WalletManager;
-use crate::wrapped_bitcoin_quoter::WrappedBitcoinQuoter;
+use crate::quote_storage::{QuoteAttempt, QuoteStorage, FILL_PREPARATION_TTL};
+use crate::readiness::ReadinessState;
+use crate::wallet::{WalletError, WalletManager};
+use crate::wrapped_bitcoin_quoter::{PricingInputs, WrappedBitcoinQuoter};
 
 pub struct RFQMessageHandler {
     market_maker_id: Uuid,
-    wrapped_bitcoin_quoter: WrappedBitcoinQuoter,
+    wrapped_bitcoin_quoter: Arc<WrappedBitcoinQuoter>,
     quote_storage: Arc<QuoteStorage>,
     wallet_manager: WalletManager,
+    readiness: Arc<ReadinessState>,
 }
 
 impl RFQMessageHandler {
     pub fn new(
         market_maker_id: Uuid,
-        wrapped_bitcoin_quoter: WrappedBitcoinQuoter,
+        wrapped_bitcoin_quoter: Arc<WrappedBitcoinQuoter>,
         quote_storage: Arc<QuoteStorage>,
         wallet_manager: WalletManager,
+        readiness: Arc<ReadinessState>,
     ) -> Self {
         Self {
             market_maker_id,
             wrapped_bitcoin_quoter,
             quote_storage,
             wallet_manager,
+            readiness,
         }
     }
 
     pub async fn handle_request(
         &self,
         msg: &ProtocolMessage<RFQRequest>,
+    ) -> Option<ProtocolMessage<RFQResponse>> {
+        let span = info_span!(
+            "rfq_request",
+            trace_id = msg.trace_id.as_deref().unwrap_or_default()
+        );
+        self.handle_payload(msg).instrument(span).await
+    }
+
+    async fn handle_payload(
+        &self,
+        msg: &ProtocolMessage<RFQRequest>,
     ) -> Option<ProtocolMessage<RFQResponse>> {
         match &msg.payload {
             RFQRequest::QuoteRequested {
@@ -46,66 +64,40 @@ impl RFQMessageHandler {
                     request_id, request.mode, request.from.chain, request.amount, request.to.chain
                 );
 
-                let quote = self
-                    .wrapped_bitcoin_quoter
-                    .compute_quote(self.market_maker_id, request)
-                    .await;
-                if quote.is_err() {
-                    tracing::error!("Failed to compute quote: {:?}", quote.err());
-                    return None;
-                }
-                let mut rfq_result = quote.unwrap();
-
-                // Check if we have sufficient balance to fulfill the quote
-                if let RFQResult::Success(ref quote_with_fees) = rfq_result {
-                    let wallet = self.wallet_manager.get(quote_with_fees.quote.to.currency.chain);
-                    
-                    let can_fill = if let Some(wallet) = wallet {
-                        match wallet.can_fill(&quote_with_fees.quote.to).await {
-                            Ok(can_fill) => can_fill,
-                            Err(e) => {
-                                warn!("Failed to check wallet balance: {}", e);
-                                false
-                            }
-                        }
-                    } else {
-                        warn!("No wallet configured for chain {:?}", quote_with_fees.quote.to.currency.chain);
-                        false
-                    };
-                    
-                    if !can_fill {
-                        info!(
-                            "Insufficient balance to fulfill quote {}: need {} on {:?}",
-                            quote_with_fees.quote.id,
-                            quote_with_fees.quote.to.amount,
-                            quote_with_fees.quote.to.currency.chain
-                        );
-                        rfq_result = RFQResult::MakerUnavailable(
-                            "Insufficient balance to fulfill quote".to_string()
-                        );
-                    }
-                }
-
-                let quote = match &rfq_result {
-                    RFQResult::Success(quote) => Some(quote.quote.clone()),
-                    RFQResult::MakerUnavailable(_) => None,
-                    RFQResult::InvalidRequest(_) => None,
-                };
-
-                if let Some(quote) = quote {
+                let started = Instant::now();
+                let created_at = Utc::now();
+                let (rfq_result, inputs) = if let Some(warm_up) = self.readiness.warm_up_result() {
                     info!(
-                        "Generated quote: id={}, from_chain={:?}, from_amount={}, to_chain={:?}, to_amount={}",
-                        quote.id, quote.from.currency.chain, quote.from.amount, quote.to.currency.chain , quote.to.amount
+                        "Still warming up, turning down quote request {}",
+                        request_id
                     );
-                    if let Err(e) = self.quote_storage.store_quote(&quote).await {
-                        error!("Failed to store quote {}: {}", quote.id, e);
-                    } else {
-                        info!("Stored quote {} in database", quote.id);
-                        if let Err(e) = self.quote_storage.mark_sent_to_rfq(quote.id).await {
-                            error!("Failed to mark quote {} as sent to RFQ: {}", quote.id, e);
+                    (warm_up, PricingInputs::default())
+                } else {
+                    match self
+                        .wrapped_bitcoin_quoter
+                        .price_quote(self.market_maker_id, request)
+                        .await
+                    {
+                        Ok(priced) => (self.offer_quote(priced.result).await, priced.inputs),
+                        // Turned down rather than left unanswered, so the RFQ server
+                        // doesn't wait out its timeout on us
+                        Err(e) => {
+                            error!("Failed to compute quote: {:?}", e);
+                            (
+                                RFQResult::MakerUnavailable(e.to_string()),
+                                PricingInputs::default(),
+                            )
                         }
                     }
-                }
+                };
+                self.record_attempts(vec![QuoteAttempt::new(
+                    *request_id,
+                    request,
+                    &rfq_result,
+                    inputs,
+                    started.elapsed(),
+                    created_at,
+                )]);
 
                 let response = RFQResponse::QuoteResponse {
                     request_id: *request_id,
@@ -117,6 +109,67 @@ impl RFQMessageHandler {
                     version: msg.version.clone(),
                     sequence: msg.sequence,
                     payload: response,
+                    trace_id: msg.trace_id.clone(),
+                })
+            }
+            RFQRequest::QuoteBatchRequested {
+                requests,
+                timestamp: _,
+            } => {
+                info!("Received RFQ quote batch of {} requests", requests.len());
+
+                let started = Instant::now();
+                let created_at = Utc::now();
+                let quotes = if let Some(warm_up) = self.readiness.warm_up_result() {
+                    info!("Still warming up, turning down the quote batch");
+                    vec![(warm_up, PricingInputs::default()); requests.len()]
+                } else {
+                    let quote_requests: Vec<_> =
+                        requests.iter().map(|item| item.request.clone()).collect();
+                    match self
+                        .wrapped_bitcoin_quoter
+                        .price_quote_batch(self.market_maker_id, &quote_requests)
+                        .await
+                    {
+                        Ok(priced) => priced
+                            .into_iter()
+                            .map(|priced| (priced.result, priced.inputs))
+                            .collect(),
+                        Err(e) => {
+                            error!("Failed to compute quote batch: {:?}", e);
+                            let failure = RFQResult::MakerUnavailable(e.to_string());
+                            vec![(failure, PricingInputs::default()); requests.len()]
+                        }
+                    }
+                };
+
+                let mut responses = Vec::with_capacity(requests.len());
+                let mut attempts = Vec::with_capacity(requests.len());
+                for (item, (quote, inputs)) in requests.iter().zip(quotes) {
+                    let quote = self.offer_quote(quote).await;
+                    attempts.push(QuoteAttempt::new(
+                        item.request_id,
+                        &item.request,
+                        &quote,
+                        inputs,
+                        started.elapsed(),
+                        created_at,
+                    ));
+                    responses.push(BatchedQuoteResponse {
+                        request_id: item.request_id,
+                        quote,
+                    });
+                }
+                self.record_attempts(attempts);
+
+                Some(ProtocolMessage {
+                    version: msg.version.clone(),
+                    sequence: msg.sequence,
+                    payload: RFQResponse::QuoteBatchResponse {
+                        responses,
+                        timestamp: Utc::now(),
+                    },
+                    trace_id: msg.trace_id.clone(),
                 })
             }
             RFQRequest::QuoteSelected {
@@ -132,8 +185,77 @@ impl RFQMessageHandler {
                 if let Err(e) = self.quote_storage.mark_sent_to_otc(*quote_id).await {
                     error!("Failed to mark quote {} as sent to OTC: {}", quote_id, e);
                 }
+
+                // The user still has to deposit, get the payout ready in the meantime.
+                // If we can't fill anymore, say so now so the quote can be retracted.
+                let Err((error_code, message)) = self.prepare_fill(*quote_id).await else {
+                    return None;
+                };
+                warn!("Can't fill selected quote {}: {}", quote_id, message);
+
+                Some(ProtocolMessage {
+                    version: msg.version.clone(),
+                    sequence: msg.sequence,
+                    payload: RFQResponse::Error {
+                        request_id: *request_id,
+                        error_code,
+                        message: format!("Quote {quote_id} can no longer be filled: {message}"),
+                        timestamp: Utc::now(),
+                    },
+                    trace_id: msg.trace_id.clone(),
+                })
+            }
+            RFQRequest::QuoteOutcome {
+                outcome,
+                timestamp: _,
+            } => {
+                info!(
+                    quote_id = %outcome.quote_id,
+                    request_id = %outcome.request_id,
+                    won = outcome.won,
+                    mode = ?outcome.mode,
+                    winning_amount = %outcome.winning_amount,
+                    "Quote outcome"
+                );
+                let quote_storage = self.quote_storage.clone();
+                let (quote_id, won) = (outcome.quote_id, outcome.won);
+                tokio::spawn(async move {
+                    if let Err(e) = quote_storage.record_quote_outcome(quote_id, won).await {
+                        warn!("Failed to record the outcome of quote {}: {}", quote_id, e);
+                    }
+                });
                 None
             }
+            RFQRequest::QuoteLockRequested {
+                request_id,
+                quote_id,
+                lock_expires_at,
+                timestamp: _,
+            } => {
+                info!(
+                    "Asked to lock quote {} until {}, request ID: {}",
+                    quote_id, lock_expires_at, request_id
+                );
+                let rejection_reason = self.lock_quote(*quote_id, *lock_expires_at).await.err();
+                if let Some(reason) = &rejection_reason {
+                    warn!("Declined to lock quote {}: {}", quote_id, reason);
+                }
+
+                Some(ProtocolMessage {
+                    version: msg.version.clone(),
+                    sequence: msg.sequence,
+                    payload: RFQResponse::QuoteLockResponse {
+                        request_id: *request_id,
+                        quote_id: *quote_id,
+                        accepted: rejection_reason.is_none(),
+                        rejection_reason,
+                        timestamp: Utc::now(),
+                    },
+                    trace_id: msg.trace_id.clone(),
+                })
+            }
+            // The connection reconnects on its own, nothing to answer
+            RFQRequest::GoingAway { .. } => None,
             RFQRequest::Ping {
                 request_id,
                 timestamp: _,
@@ -147,8 +269,391 @@ impl RFQMessageHandler {
                     version: msg.version.clone(),
                     sequence: msg.sequence,
                     payload: response,
+                    trace_id: msg.trace_id.clone(),
                 })
             }
         }
     }
+
+    /// Store `attempts` in the background, so answering quotes never waits on
+    /// the database
+    fn record_attempts(&self, attempts: Vec<QuoteAttempt>) {
+        let quote_storage = self.quote_storage.clone();
+        tokio::spawn(async move {
+            for attempt in attempts {
+                if let Err(e) = quote_storage.store_quote_attempt(&attempt).await {
+                    warn!(
+                        "Failed to record quote attempt for request {}: {}",
+                        attempt.request_id, e
+                    );
+                }
+            }
+        });
+    }
+
+    /// Turn `rfq_result` down if our balance can't fill it, and store it
+    /// otherwise so it can be found once selected
+    async fn offer_quote(
+        &self,
+        mut rfq_result: RFQResult<QuoteWithFees>,
+    ) -> RFQResult<QuoteWithFees> {
+        // Check if we have sufficient balance to fulfill the quote
+        if let RFQResult::Success(ref quote_with_fees) = rfq_result {
+            let wallet = self
+                .wallet_manager
+                .for_currency(&quote_with_fees.quote.to.currency);
+
+            let can_fill = if let Some(wallet) = wallet {
+                match wallet.can_fill(&quote_with_fees.quote.to).await {
+                    Ok(can_fill) => can_fill,
+                    Err(e) => {
+                        warn!("Failed to check wallet balance: {}", e);
+                        false
+                    }
+                }
+            } else {
+                warn!(
+                    "No wallet configured for {}",
+                    quote_with_fees.quote.to.currency.network()
+                );
+                false
+            };
+
+            if !can_fill {
+                info!(
+                    "Insufficient balance to fulfill quote {}: need {} on {:?}",
+                    quote_with_fees.quote.id,
+                    quote_with_fees.quote.to.amount,
+                    quote_with_fees.quote.to.currency.chain
+                );
+                rfq_result = RFQResult::MakerUnavailable(
+                    "Insufficient balance to fulfill quote".to_string(),
+                );
+            }
+        }
+
+        let quote = match &rfq_result {
+            RFQResult::Success(quote) => Some(quote.quote.clone()),
+            RFQResult::MakerUnavailable(_) => None,
+            RFQResult::InvalidRequest(_) => None,
+        };
+
+        if let Some(quote) = quote {
+            info!(
+                "Generated quote: id={}, from_chain={:?}, from_amount={}, to_chain={:?}, to_amount={}",
+                quote.id, quote.from.currency.chain, quote.from.amount, quote.to.currency.chain , quote.to.amount
+            );
+            if let Err(e) = self.quote_storage.store_quote(&quote).await {
+                error!("Failed to store quote {}: {}", quote.id, e);
+            } else {
+                info!("Stored quote {} in database", quote.id);
+                if let Err(e) = self.quote_storage.mark_sent_to_rfq(quote.id).await {
+                    error!("Failed to mark quote {} as sent to RFQ: {}", quote.id, e);
+                }
+            }
+        }
+
+        rfq_result
+    }
+
+    /// Check a selected quote can still be filled and cache the lookups its payout
+    /// will need, counting funds other selected or locked quotes already claim
+    async fn prepare_fill(&self, quote_id: Uuid) -> Result<(), (RFQErrorCode, String)> {
+        let quote = self
+            .quote_storage
+            .get_quote(quote_id)
+            .await
+            .map_err(|e| (RFQErrorCode::InternalError, e.to_string()))?;
+        let chain = quote.to.currency.network();
+        let wallet = self.wallet_manager.get_network(chain).ok_or_else(|| {
+            (
+                RFQErrorCode::PairNotSupported,
+                format!("No wallet configured for {chain}"),
+            )
+        })?;
+
+        let mut pending = self.quote_storage.pending_fill_preparations(chain);
+        pending.extend(
+            self.quote_storage
+                .locked_fill_preparations(chain, Some(quote_id)),
+        );
+        let preparation = wallet
+            .prepare_fill(&quote.to, &pending)
+            .await
+            .map_err(|e| {
+                let error_code = match e {
+                    WalletError::InsufficientBalance { .. } => RFQErrorCode::InsufficientLiquidity,
+                    _ => RFQErrorCode::InternalError,
+                };
+                (error_code, e.to_string())
+            })?;
+
+        info!(
+            "Prepared fill for quote {} in {} ms",
+            quote_id,
+            preparation.lookup_duration.as_millis()
+        );
+        self.quote_storage
+            .cache_fill_preparation(quote_id, preparation, FILL_PREPARATION_TTL);
+        Ok(())
+    }
+
+    /// Hold the funds for a quote until `expires_at`, on top of what other locks
+    /// already hold. Refuses when the quote is unknown, expired or can't be covered
+    async fn lock_quote(&self, quote_id: Uuid, expires_at: DateTime<Utc>) -> Result<(), String> {
+        if let Some(RFQResult::MakerUnavailable(reason)) = self.readiness.warm_up_result::<()>() {
+            return Err(reason);
+        }
+
+        let quote = self
+            .quote_storage
+            .get_quote(quote_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let ttl = (expires_at.min(quote.expires_at) - Utc::now())
+            .to_std()
+            .map_err(|_| format!("Quote {quote_id} has expired"))?;
+        let chain = quote.to.currency.network();
+        let wallet = self
+            .wallet_manager
+            .get_network(chain)
+            .ok_or_else(|| format!("No wallet configured for {chain}"))?;
+
+        let locked = self
+            .quote_storage
+            .locked_fill_preparations(chain, Some(quote_id));
+        let preparation = wallet
+            .prepare_fill(&quote.to, &locked)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.quote_storage.lock_quote(quote_id, preparation, ttl);
+
+        if let Err(e) = self.quote_storage.mark_accepted(quote_id).await {
+            error!("Failed to mark quote {} as accepted: {}", quote_id, e);
+        }
+        info!("Locked quote {} for {} s", quote_id, ttl.as_secs());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin_wallet::{
+        BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, SignerConfig,
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    };
+    use crate::config::PricingConfig;
+    use crate::price_oracle::BitcoinEtherPriceOracle;
+    use crate::readiness::{ReadinessCheck, WARMING_UP};
+    use crate::rfq_placement::{RfqPlacement, DEFAULT_FAILOVER_AFTER};
+    use crate::simulation::{FixedFeeEstimator, DEFAULT_SIMULATED_BTC_PER_ETH};
+    use crate::status::StatusState;
+    use crate::wallet::{self, TransactionResult, Wallet};
+    use crate::wrapped_bitcoin_quoter::{FeeEstimator, GasPrices, WrappedBitcoinQuoterError};
+    use alloy::primitives::U256;
+    use arc_swap::ArcSwap;
+    use async_trait::async_trait;
+    use bdk_wallet::bitcoin::Network;
+    use blockchain_utils::ProtocolFeeParams;
+    use common::SystemClock;
+    use otc_chains::traits::MarketMakerPaymentValidation;
+    use otc_models::{
+        ChainNetwork, ChainType, QuoteMode, QuoteRequest, SupportedCurrencies, TokenIdentifier,
+        CBBTC_ADDRESS,
+    };
+    use otc_protocols::rfq::PROTOCOL_VERSION;
+    use sqlx::PgPool;
+    use tokio::task::JoinSet;
+
+    const DESCRIPTOR: &str = "wpkh(cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA)";
+
+    /// Payout wallet with funds for any quote, quoting never pays out
+    struct FundedWallet;
+
+    #[async_trait]
+    impl Wallet for FundedWallet {
+        async fn create_payment(
+            &self,
+            _lot: &Lot,
+            _to_address: &str,
+            _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        ) -> wallet::Result<TransactionResult> {
+            unreachable!("quoting never pays out")
+        }
+
+        async fn can_fill(&self, _lot: &Lot) -> wallet::Result<bool> {
+            Ok(true)
+        }
+    }
+
+    fn currency(chain: ChainType, token: TokenIdentifier) -> Currency {
+        Currency {
+            chain,
+            token,
+            decimals: 8,
+            chain_id: None,
+        }
+    }
+
+    fn bitcoin() -> Currency {
+        currency(ChainType::Bitcoin, TokenIdentifier::Native)
+    }
+
+    fn cbbtc() -> Currency {
+        currency(
+            ChainType::Ethereum,
+            TokenIdentifier::Address(CBBTC_ADDRESS.to_string()),
+        )
+    }
+
+    fn quote_request(from: Currency, to: Currency) -> ProtocolMessage<RFQRequest> {
+        ProtocolMessage {
+            version: PROTOCOL_VERSION.to_string(),
+            sequence: 1,
+            payload: RFQRequest::QuoteRequested {
+                request_id: Uuid::new_v4(),
+                request: QuoteRequest {
+                    mode: QuoteMode::ExactInput,
+                    from,
+                    to,
+                    amount: U256::from(1_000_000u64),
+                },
+                timestamp: Utc::now(),
+            },
+            trace_id: None,
+        }
+    }
+
+    async fn quote(
+        handler: &RFQMessageHandler,
+        from: Currency,
+        to: Currency,
+    ) -> RFQResult<QuoteWithFees> {
+        match handler.handle_request(&quote_request(from, to)).await {
+            Some(ProtocolMessage {
+                payload: RFQResponse::QuoteResponse { quote, .. },
+                ..
+            }) => quote,
+            other => panic!("Expected a quote response, got {other:?}"),
+        }
+    }
+
+    /// Bitcoin fee estimates from an esplora that can't be reached
+    struct UnreachableEsplora;
+
+    #[async_trait]
+    impl FeeEstimator for UnreachableEsplora {
+        async fn bitcoin_sats_per_vbyte(&self) -> Result<f64, WrappedBitcoinQuoterError> {
+            let client = esplora_client::Builder::new("http://127.0.0.1:1").build_async()?;
+            client.get_fee_estimates().await?;
+            unreachable!("nothing listens on the esplora port")
+        }
+
+        async fn ethereum_gas_prices(&self, network: ChainNetwork) -> Result<GasPrices, String> {
+            FixedFeeEstimator.ethereum_gas_prices(network).await
+        }
+    }
+
+    /// Handler quoting with fees from `fee_estimator`, still warming up
+    async fn handler(
+        pool: PgPool,
+        fee_estimator: Arc<dyn FeeEstimator>,
+        join_set: &mut JoinSet<crate::Result<()>>,
+    ) -> (RFQMessageHandler, Arc<ReadinessState>) {
+        // Never synced, the esplora URL isn't reached while quoting
+        let bitcoin_wallet = BitcoinWallet::new(
+            ":memory:",
+            DESCRIPTOR,
+            None,
+            Network::Regtest,
+            "http://127.0.0.1:1",
+            BitcoinWalletSyncConfig::default(),
+            DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+            CoinSelectionConfig::default(),
+            SignerConfig::Descriptor,
+            join_set,
+        )
+        .await
+        .unwrap();
+        let quoter = WrappedBitcoinQuoter::new(
+            BitcoinEtherPriceOracle::fixed(DEFAULT_SIMULATED_BTC_PER_ETH),
+            fee_estimator,
+            Arc::new(bitcoin_wallet),
+            Arc::new(ArcSwap::from_pointee(PricingConfig {
+                trade_spread_bps: 13,
+                fee_safety_multiplier: 1.0,
+            })),
+            ProtocolFeeParams::DEFAULT,
+            Arc::new(SupportedCurrencies::default()),
+            Arc::new(SystemClock),
+        );
+        let quote_storage = QuoteStorage::from_pool(
+            pool,
+            chrono::Duration::hours(24),
+            Arc::new(SystemClock),
+            join_set,
+        )
+        .await
+        .unwrap();
+        let mut wallet_manager = WalletManager::new();
+        wallet_manager.register(ChainType::Ethereum, Arc::new(FundedWallet));
+        let readiness = Arc::new(ReadinessState::new());
+        let handler = RFQMessageHandler::new(
+            Uuid::new_v4(),
+            Arc::new(quoter),
+            Arc::new(quote_storage),
+            wallet_manager,
+            readiness.clone(),
+        );
+        (handler, readiness)
+    }
+
+    #[sqlx::test]
+    async fn test_quotes_are_served_once_the_status_reports_ready(pool: PgPool) {
+        let mut join_set = JoinSet::new();
+        let (handler, readiness) = handler(pool, Arc::new(FixedFeeEstimator), &mut join_set).await;
+        let status = StatusState {
+            readiness: readiness.clone(),
+            rfq_placement: Arc::new(
+                RfqPlacement::new(vec!["ws://rfq".to_string()], DEFAULT_FAILOVER_AFTER).unwrap(),
+            ),
+        };
+
+        assert!(!status.status().ready);
+        assert_eq!(status.status().pending_checks, ReadinessCheck::ALL);
+        match quote(&handler, bitcoin(), cbbtc()).await {
+            RFQResult::MakerUnavailable(reason) => assert_eq!(reason, WARMING_UP),
+            other => panic!("Expected a warm-up response, got {other:?}"),
+        }
+
+        for check in ReadinessCheck::ALL {
+            readiness.mark(check);
+        }
+        assert!(status.status().ready);
+        assert!(status.status().pending_checks.is_empty());
+        match quote(&handler, bitcoin(), cbbtc()).await {
+            RFQResult::Success(quote) => {
+                assert_eq!(quote.quote.from.amount, U256::from(1_000_000u64));
+                assert!(quote.quote.to.amount > U256::ZERO);
+            }
+            other => panic!("Expected a quote once warmed up, got {other:?}"),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_quotes_that_fail_to_price_are_turned_down(pool: PgPool) {
+        let mut join_set = JoinSet::new();
+        let (handler, readiness) = handler(pool, Arc::new(UnreachableEsplora), &mut join_set).await;
+        for check in ReadinessCheck::ALL {
+            readiness.mark(check);
+        }
+
+        match quote(&handler, cbbtc(), bitcoin()).await {
+            RFQResult::MakerUnavailable(reason) => {
+                assert!(reason.starts_with("Failed to get fee rate from esplora"));
+            }
+            other => panic!("Expected the maker to be unavailable, got {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,373 @@
+//! Which of several RFQ servers the market maker connects to
+//!
+//! At startup every configured server is measured by how long it takes to
+//! connect and answer a ping, and the fastest becomes the active one. The
+//! rest are kept in latency order as failover targets: once the active server
+//! has been unreachable for `failover_after`, the next one is promoted. Only
+//! one server is connected at a time, quotes aren't deduplicated across
+//! connections.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use common::WsStream;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+use crate::config::ConfigError;
+
+/// How long a server gets to connect and answer the ping before it's ranked
+/// unreachable
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the active server may stay unreachable by default before the next
+/// one is promoted
+pub const DEFAULT_FAILOVER_AFTER: Duration = Duration::from_secs(30);
+
+/// An RFQ server and how fast it answered at startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RfqEndpoint {
+    url: String,
+    /// Time to connect and get the first pong, `None` if it wasn't measured or
+    /// couldn't be reached
+    latency: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Fastest first, unreachable ones last in the order they were given
+    endpoints: Vec<RfqEndpoint>,
+    active: usize,
+    /// When connecting to the active server started failing
+    failing_since: Option<Instant>,
+}
+
+/// An RFQ server as reported on the status endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RfqEndpointStatus {
+    pub url: String,
+    /// Milliseconds to connect and get the first pong at startup, `None` if it
+    /// wasn't measured or couldn't be reached
+    pub latency_ms: Option<u64>,
+}
+
+/// Which RFQ server is active and how each one answered at startup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RfqPlacementStatus {
+    pub active_url: String,
+    /// Fastest first, in the order they are failed over to
+    pub endpoints: Vec<RfqEndpointStatus>,
+}
+
+#[derive(Debug)]
+pub struct RfqPlacement {
+    state: Mutex<State>,
+    failover_after: Duration,
+}
+
+impl RfqPlacement {
+    /// Servers in the order given, the first active until [`Self::measure`]
+    /// ranks them. Fails if there are none or one is blank
+    pub fn new(urls: Vec<String>, failover_after: Duration) -> Result<Self, ConfigError> {
+        if urls.is_empty() {
+            return Err(ConfigError::NoRfqServer);
+        }
+        if let Some(url) = urls.iter().find(|url| url.trim().is_empty()) {
+            return Err(ConfigError::InvalidUrl { url: url.clone() });
+        }
+        let endpoints = urls
+            .into_iter()
+            .map(|url| RfqEndpoint { url, latency: None })
+            .collect();
+        Ok(Self {
+            state: Mutex::new(State {
+                endpoints,
+                active: 0,
+                failing_since: None,
+            }),
+            failover_after,
+        })
+    }
+
+    /// Probe every server with `connect` and make the fastest one active. A
+    /// single server is left unmeasured, there is nothing to choose
+    pub async fn measure<F, Fut, E>(&self, connect: F)
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<WsStream, E>>,
+        E: fmt::Display,
+    {
+        let urls: Vec<String> = self
+            .lock()
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.url.clone())
+            .collect();
+        if urls.len() == 1 {
+            info!("Only one RFQ server configured, connecting to {}", urls[0]);
+            return;
+        }
+
+        let latencies =
+            futures_util::future::join_all(urls.iter().map(|url| probe(url, &connect))).await;
+        let mut endpoints: Vec<RfqEndpoint> = urls
+            .into_iter()
+            .zip(latencies)
+            .map(|(url, latency)| RfqEndpoint { url, latency })
+            .collect();
+        // Stable, so unreachable servers keep the order they were given in
+        endpoints.sort_by_key(|endpoint| endpoint.latency.unwrap_or(Duration::MAX));
+
+        for endpoint in &endpoints {
+            match endpoint.latency {
+                Some(latency) => info!("RFQ server {} answered in {:?}", endpoint.url, latency),
+                None => warn!("RFQ server {} is unreachable", endpoint.url),
+            }
+        }
+        match endpoints[0].latency {
+            Some(latency) => info!(
+                "Using RFQ server {}, the fastest of {} at {:?}, the others are failover targets",
+                endpoints[0].url,
+                endpoints.len(),
+                latency
+            ),
+            None => warn!("No RFQ server answered, trying {} first", endpoints[0].url),
+        }
+
+        let mut state = self.lock();
+        state.endpoints = endpoints;
+        state.active = 0;
+        state.failing_since = None;
+    }
+
+    /// Connect to the active server with `connect`. If it has been failing for
+    /// `failover_after`, the next server is promoted and tried instead
+    pub async fn connect<F, Fut, S, E>(&self, connect: F) -> Result<S, E>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<S, E>>,
+    {
+        let error = match connect(self.active_url()).await {
+            Ok(connection) => {
+                self.lock().failing_since = None;
+                return Ok(connection);
+            }
+            Err(e) => e,
+        };
+        let Some(promoted) = self.record_failure() else {
+            return Err(error);
+        };
+        match connect(promoted).await {
+            Ok(connection) => {
+                self.lock().failing_since = None;
+                Ok(connection)
+            }
+            Err(e) => {
+                self.record_failure();
+                Err(e)
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn active_url(&self) -> String {
+        let state = self.lock();
+        state.endpoints[state.active].url.clone()
+    }
+
+    /// The active server and the measured latency of each
+    #[must_use]
+    pub fn status(&self) -> RfqPlacementStatus {
+        let state = self.lock();
+        RfqPlacementStatus {
+            active_url: state.endpoints[state.active].url.clone(),
+            endpoints: state
+                .endpoints
+                .iter()
+                .map(|endpoint| RfqEndpointStatus {
+                    url: endpoint.url.clone(),
+                    latency_ms: endpoint
+                        .latency
+                        .map(|latency| u64::try_from(latency.as_millis()).unwrap_or(u64::MAX)),
+                })
+                .collect(),
+        }
+    }
+
+    /// Note a failed connect to the active server, promoting the next one and
+    /// returning its URL once the active one has failed for `failover_after`
+    fn record_failure(&self) -> Option<String> {
+        let mut state = self.lock();
+        let now = Instant::now();
+        let failing_since = *state.failing_since.get_or_insert(now);
+        if state.endpoints.len() < 2 || now.duration_since(failing_since) < self.failover_after {
+            return None;
+        }
+
+        let unreachable = state.endpoints[state.active].url.clone();
+        state.active = (state.active + 1) % state.endpoints.len();
+        state.failing_since = None;
+        let promoted = state.endpoints[state.active].url.clone();
+        warn!(
+            "RFQ server {} unreachable for {:?}, failing over to {}",
+            unreachable,
+            now.duration_since(failing_since),
+            promoted
+        );
+        Some(promoted)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("RFQ placement mutex poisoned")
+    }
+}
+
+/// Time to connect to `url` and get the first pong back, `None` if that fails
+/// or takes longer than `PROBE_TIMEOUT`
+async fn probe<F, Fut, E>(url: &str, connect: &F) -> Option<Duration>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<WsStream, E>>,
+    E: fmt::Display,
+{
+    let started = Instant::now();
+    let answered = timeout(PROBE_TIMEOUT, async {
+        let mut ws = connect(url.to_string()).await.map_err(|e| e.to_string())?;
+        ws.send(Message::Ping(Vec::new()))
+            .await
+            .map_err(|e| e.to_string())?;
+        // The server may greet us before it answers the ping
+        while let Some(message) = ws.next().await {
+            if let Message::Pong(_) = message.map_err(|e| e.to_string())? {
+                let latency = started.elapsed();
+                let _ = ws.close(None).await;
+                return Ok(latency);
+            }
+        }
+        Err("closed before answering the ping".to_string())
+    })
+    .await;
+
+    match answered {
+        Ok(Ok(latency)) => Some(latency),
+        Ok(Err(e)) => {
+            warn!("Failed to probe RFQ server {}: {}", url, e);
+            None
+        }
+        Err(_) => {
+            warn!(
+                "RFQ server {} didn't answer within {:?}",
+                url, PROBE_TIMEOUT
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{accept_async, connect_async, tungstenite};
+
+    /// WebSocket server that waits `delay` before each handshake and answers
+    /// pings until the client hangs up
+    async fn spawn_server(delay: Duration) -> (String, tokio::task::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let Ok(mut ws) = accept_async(stream).await else {
+                        return;
+                    };
+                    // Reading answers pings
+                    while let Some(Ok(_)) = ws.next().await {}
+                });
+            }
+        });
+        (url, server)
+    }
+
+    async fn connect(url: String) -> Result<WsStream, tungstenite::Error> {
+        connect_async(url).await.map(|(ws, _)| ws)
+    }
+
+    #[tokio::test]
+    async fn test_fastest_server_is_chosen() {
+        let (slow, _slow_server) = spawn_server(Duration::from_millis(300)).await;
+        let (fast, _fast_server) = spawn_server(Duration::ZERO).await;
+        // Nothing listens on a port whose listener was dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let unreachable = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let placement = RfqPlacement::new(
+            vec![unreachable.clone(), slow.clone(), fast.clone()],
+            DEFAULT_FAILOVER_AFTER,
+        )
+        .unwrap();
+        placement.measure(connect).await;
+
+        assert_eq!(placement.active_url(), fast);
+        let status = placement.status();
+        assert_eq!(status.active_url, fast);
+        let order: Vec<_> = status.endpoints.iter().map(|e| e.url.clone()).collect();
+        assert_eq!(order, [fast, slow, unreachable]);
+        let latencies: Vec<_> = status.endpoints.iter().map(|e| e.latency_ms).collect();
+        assert!(latencies[0].unwrap() < latencies[1].unwrap());
+        assert!(latencies[1].unwrap() >= 300);
+        assert_eq!(latencies[2], None);
+    }
+
+    #[test]
+    fn test_no_or_blank_servers_are_a_config_error() {
+        assert!(matches!(
+            RfqPlacement::new(Vec::new(), DEFAULT_FAILOVER_AFTER),
+            Err(ConfigError::NoRfqServer)
+        ));
+        assert!(matches!(
+            RfqPlacement::new(
+                vec!["ws://a".to_string(), " ".to_string()],
+                DEFAULT_FAILOVER_AFTER
+            ),
+            Err(ConfigError::InvalidUrl { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_next_server_is_promoted_once_the_active_one_stays_down() {
+        let (slow, _slow_server) = spawn_server(Duration::from_millis(100)).await;
+        let (fast, fast_server) = spawn_server(Duration::ZERO).await;
+        let failover_after = Duration::from_millis(200);
+        let placement =
+            RfqPlacement::new(vec![slow.clone(), fast.clone()], failover_after).unwrap();
+        placement.measure(connect).await;
+        assert_eq!(placement.active_url(), fast);
+        assert!(placement.connect(connect).await.is_ok());
+
+        // Stopping the accept loop closes its listener
+        fast_server.abort();
+        let _ = fast_server.await;
+        assert!(placement.connect(connect).await.is_err());
+        // A short outage doesn't move the market maker
+        assert!(placement.connect(connect).await.is_err());
+        assert_eq!(placement.active_url(), fast);
+
+        tokio::time::sleep(failover_after).await;
+        assert!(placement.connect(connect).await.is_ok());
+        assert_eq!(placement.active_url(), slow);
+        // The failed server stays in the list as a target to fail back to
+        let status = placement.status();
+        assert_eq!(status.active_url, slow);
+        assert_eq!(status.endpoints.len(), 2);
+    }
+}
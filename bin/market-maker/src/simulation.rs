@@ -0,0 +1,74 @@
+//! Simulation mode: quoting from a fixed BTC/ETH price and fixed network fees,
+//! so the whole flow runs on a devnet without the price feed or live fee
+//! estimates. Regtest only, the quotes have nothing to do with the market
+
+use async_trait::async_trait;
+use bdk_wallet::bitcoin;
+use otc_models::ChainNetwork;
+
+use crate::{
+    config::ConfigError,
+    wrapped_bitcoin_quoter::{FeeEstimator, GasPrices, WrappedBitcoinQuoterError},
+};
+
+/// BTC per ETH quoted from unless `--simulated-btc-per-eth` says otherwise
+pub const DEFAULT_SIMULATED_BTC_PER_ETH: f64 = 0.035;
+
+const SIMULATED_SATS_PER_VBYTE: f64 = 2.0;
+const SIMULATED_GAS_PRICES: GasPrices = GasPrices {
+    base_fee_gwei: 1.0,
+    max_priority_fee_gwei: 0.1,
+};
+
+/// The same fees on every chain, whatever the chains are doing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedFeeEstimator;
+
+#[async_trait]
+impl FeeEstimator for FixedFeeEstimator {
+    async fn bitcoin_sats_per_vbyte(&self) -> Result<f64, WrappedBitcoinQuoterError> {
+        Ok(SIMULATED_SATS_PER_VBYTE)
+    }
+
+    async fn ethereum_gas_prices(&self, _network: ChainNetwork) -> Result<GasPrices, String> {
+        Ok(SIMULATED_GAS_PRICES)
+    }
+}
+
+/// Refuse to simulate anywhere real funds could be quoted, or from a price
+/// that can't be quoted from
+pub fn check_args(network: bitcoin::Network, btc_per_eth: f64) -> Result<(), ConfigError> {
+    if network != bitcoin::Network::Regtest {
+        return Err(ConfigError::SimulationOutsideRegtest { network });
+    }
+    if !(btc_per_eth.is_finite() && btc_per_eth > 0.0) {
+        return Err(ConfigError::InvalidSimulatedPrice { btc_per_eth });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulation_is_regtest_only() {
+        assert!(check_args(bitcoin::Network::Regtest, DEFAULT_SIMULATED_BTC_PER_ETH).is_ok());
+        for network in [
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Testnet,
+            bitcoin::Network::Signet,
+        ] {
+            assert!(matches!(
+                check_args(network, DEFAULT_SIMULATED_BTC_PER_ETH),
+                Err(ConfigError::SimulationOutsideRegtest { .. })
+            ));
+        }
+        for btc_per_eth in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                check_args(bitcoin::Network::Regtest, btc_per_eth),
+                Err(ConfigError::InvalidSimulatedPrice { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Read-only HTTP status endpoint, served when `--status-addr` is set
+
+use std::sync::Arc;
+
+use axum::{extract::State, routing::get, Json, Router};
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::readiness::{ReadinessCheck, ReadinessState};
+use crate::rfq_placement::{RfqPlacement, RfqPlacementStatus};
+
+/// What `GET /status` reports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MarketMakerStatus {
+    /// Whether quotes are priced, rather than turned down as warming up
+    pub ready: bool,
+    /// Warm-up checks that haven't passed yet
+    pub pending_checks: Vec<ReadinessCheck>,
+    pub rfq: RfqPlacementStatus,
+}
+
+#[derive(Clone)]
+pub struct StatusState {
+    pub readiness: Arc<ReadinessState>,
+    pub rfq_placement: Arc<RfqPlacement>,
+}
+
+impl StatusState {
+    #[must_use]
+    pub fn status(&self) -> MarketMakerStatus {
+        MarketMakerStatus {
+            ready: self.readiness.is_ready(),
+            pending_checks: self.readiness.pending_checks(),
+            rfq: self.rfq_placement.status(),
+        }
+    }
+}
+
+pub fn router(state: StatusState) -> Router {
+    Router::new()
+        .route("/status", get(get_status))
+        .with_state(state)
+}
+
+async fn get_status(State(state): State<StatusState>) -> Json<MarketMakerStatus> {
+    Json(state.status())
+}
+
+/// Serve the status endpoint on `listener` until the process exits
+pub async fn serve(listener: TcpListener, state: StatusState) -> std::io::Result<()> {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Serving status on http://{}/status", addr);
+    }
+    axum::serve(listener, router(state)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rfq_placement::DEFAULT_FAILOVER_AFTER;
+
+    #[tokio::test]
+    async fn test_status_reports_warm_up_and_the_active_rfq_server() {
+        let placement = Arc::new(
+            RfqPlacement::new(
+                vec!["ws://first".to_string(), "ws://second".to_string()],
+                DEFAULT_FAILOVER_AFTER,
+            )
+            .unwrap(),
+        );
+        let readiness = Arc::new(ReadinessState::new());
+        readiness.mark(ReadinessCheck::PriceAvailable);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            listener,
+            StatusState {
+                readiness: readiness.clone(),
+                rfq_placement: placement,
+            },
+        ));
+        let get_status = || async {
+            reqwest::get(format!("http://{addr}/status"))
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        };
+
+        let status = get_status().await;
+        assert_eq!(status["ready"], false);
+        assert_eq!(
+            status["pending_checks"],
+            serde_json::json!(["bitcoin_wallet_synced", "evm_provider_connected"])
+        );
+        assert_eq!(
+            status["rfq"],
+            serde_json::json!({
+                "active_url": "ws://first",
+                "endpoints": [
+                    { "url": "ws://first", "latency_ms": null },
+                    { "url": "ws://second", "latency_ms": null },
+                ],
+            })
+        );
+
+        readiness.mark(ReadinessCheck::BitcoinWalletSynced);
+        readiness.mark(ReadinessCheck::EvmProviderConnected);
+        let status = get_status().await;
+        assert_eq!(status["ready"], true);
+        assert_eq!(status["pending_checks"], serde_json::json!([]));
+    }
+}
@@ -1,33 +1,237 @@
-use tracing::info;
+use alloy::primitives::U256;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use otc_models::{Quote, QuoteMode, QuoteRequest};
+use otc_protocols::{
+    mm::{MMErrorCode, QuoteRejection},
+    rfq::{QuoteWithFees, RFQResult},
+};
+use std::sync::Arc;
+use tracing::{info, warn};
 use uuid::Uuid;
 
-/// Strategy for validating quotes
-pub struct ValidationStrategy {}
-
-impl ValidationStrategy {
-    pub fn new() -> Self {
-        Self {}
-    }
-
-    /// Validate whether to accept a quote
-    /// Returns (accepted, `rejection_reason`)
-    pub fn validate_quote(
-        &self,
-        quote_id: &Uuid,
-        quote_hash: &[u8; 32],
-        user_destination_address: &str,
-    ) -> (bool, Option<String>) {
-        // TODO: Implement real validation logic
-        // This could include:
-        // - Check current inventory levels
-        // - Verify pricing is still valid
-        // - Check risk limits
-        // - Verify liquidity availability
-        // - Check quote against market-maker specific database (claude this is where you will add your logic to read from the quote database)
-
-        info!("Validating quote {} with custom logic", quote_id);
-
-        // For MVP, we'll accept all quotes
-        (true, None)
+use crate::{wallet::WalletManager, wrapped_bitcoin_quoter::WrappedBitcoinQuoter};
+
+/// Default for how much more a quote may pay out than a fresh quote would, in basis points
+pub const DEFAULT_PRICE_TOLERANCE_BPS: u64 = 50;
+
+/// Decides whether to fill a quote the OTC server asks us to validate.
+///
+/// Only called for quotes we issued, with the hash checked and not yet
+/// expired, so a policy only adds the checks on top of that.
+#[async_trait]
+pub trait ValidationPolicy: Send + Sync {
+    async fn validate(&self, quote: &Quote) -> Result<(), QuoteRejection>;
+}
+
+/// Accept every quote we issued that hasn't expired
+pub struct AutoAcceptPolicy;
+
+#[async_trait]
+impl ValidationPolicy for AutoAcceptPolicy {
+    async fn validate(&self, _quote: &Quote) -> Result<(), QuoteRejection> {
+        Ok(())
+    }
+}
+
+/// Accept a quote only if we can still pay it out and pricing it now wouldn't
+/// pay out much less
+pub struct StrictValidationPolicy {
+    market_maker_id: Uuid,
+    quoter: Arc<WrappedBitcoinQuoter>,
+    wallet_manager: WalletManager,
+    price_tolerance_bps: u64,
+}
+
+impl StrictValidationPolicy {
+    #[must_use]
+    pub fn new(
+        market_maker_id: Uuid,
+        quoter: Arc<WrappedBitcoinQuoter>,
+        wallet_manager: WalletManager,
+        price_tolerance_bps: u64,
+    ) -> Self {
+        Self {
+            market_maker_id,
+            quoter,
+            wallet_manager,
+            price_tolerance_bps,
+        }
+    }
+}
+
+#[async_trait]
+impl ValidationPolicy for StrictValidationPolicy {
+    async fn validate(&self, quote: &Quote) -> Result<(), QuoteRejection> {
+        let chain = quote.to.currency.network();
+        let wallet = self.wallet_manager.get_network(chain).ok_or_else(|| {
+            QuoteRejection::new(
+                MMErrorCode::UnsupportedChain,
+                format!("No wallet configured for {chain}"),
+            )
+        })?;
+        match wallet.can_fill(&quote.to).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InsufficientLiquidity,
+                    "Insufficient balance to fill the quote",
+                ))
+            }
+            Err(e) => {
+                warn!("Failed to check balance for quote {}: {}", quote.id, e);
+                return Err(QuoteRejection::new(
+                    MMErrorCode::InternalError,
+                    "Failed to check balance",
+                ));
+            }
+        }
+
+        // Price the same input again, fees and the BTC/ETH price may have moved
+        let request = QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            from: quote.from.currency.clone(),
+            to: quote.to.currency.clone(),
+            amount: quote.from.amount,
+        };
+        let current = self
+            .quoter
+            .compute_quote(self.market_maker_id, &request)
+            .await
+            .map_err(|e| {
+                warn!("Failed to reprice quote {}: {}", quote.id, e);
+                QuoteRejection::new(MMErrorCode::InternalError, "Failed to reprice the quote")
+            })?;
+        check_price(quote, &current, self.price_tolerance_bps)
+    }
+}
+
+/// Reject a quote we couldn't fill by now, whatever the policy
+pub fn check_not_expired(quote: &Quote, now: DateTime<Utc>) -> Result<(), QuoteRejection> {
+    if quote.is_expired(now) {
+        return Err(QuoteRejection::new(
+            MMErrorCode::QuoteExpired,
+            format!("Quote expired at {}", quote.expires_at),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject `quote` if it pays out more than `tolerance_bps` above `current`, a
+/// fresh quote for the same input
+pub fn check_price(
+    quote: &Quote,
+    current: &RFQResult<QuoteWithFees>,
+    tolerance_bps: u64,
+) -> Result<(), QuoteRejection> {
+    let current = match current {
+        RFQResult::Success(current) => current,
+        RFQResult::MakerUnavailable(e) => {
+            return Err(QuoteRejection::new(
+                MMErrorCode::InternalError,
+                format!("Failed to reprice the quote: {e}"),
+            ))
+        }
+        RFQResult::InvalidRequest(e) => {
+            return Err(QuoteRejection::new(
+                MMErrorCode::PriceMoved,
+                format!("The quote can't be priced anymore: {e}"),
+            ))
+        }
+    };
+    let quoted = quote.to.amount;
+    let overpaid = quoted.saturating_sub(current.quote.to.amount);
+    if overpaid * U256::from(10_000u64) > quoted * U256::from(tolerance_bps) {
+        info!(
+            "Quote {} pays out {} but would pay out {} now",
+            quote.id, quoted, current.quote.to.amount
+        );
+        return Err(QuoteRejection::new(
+            MMErrorCode::PriceMoved,
+            format!(
+                "Quote pays out {quoted}, more than {tolerance_bps} bps above the current {}",
+                current.quote.to.amount
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otc_models::{ChainType, Currency, Lot, TokenIdentifier};
+    use otc_protocols::rfq::FeeSchedule;
+
+    fn quote(to_sats: u64, expires_at: DateTime<Utc>) -> Quote {
+        let bitcoin = Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        };
+        Quote {
+            id: Uuid::new_v4(),
+            market_maker_id: Uuid::new_v4(),
+            from: Lot {
+                currency: Currency {
+                    chain: ChainType::Ethereum,
+                    token: TokenIdentifier::Address(
+                        "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf".to_string(),
+                    ),
+                    decimals: 8,
+                    chain_id: None,
+                },
+                amount: U256::from(1_000_000u64),
+            },
+            to: Lot {
+                currency: bitcoin,
+                amount: U256::from(to_sats),
+            },
+            expires_at,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn current(to_sats: u64) -> RFQResult<QuoteWithFees> {
+        RFQResult::Success(QuoteWithFees {
+            quote: quote(to_sats, Utc::now()),
+            fees: FeeSchedule {
+                network_fee_sats: 0,
+                liquidity_fee_sats: 0,
+                protocol_fee_sats: 0,
+            },
+            signature: None,
+        })
+    }
+
+    #[test]
+    fn test_expiry_boundary() {
+        let now = Utc::now();
+        let second = chrono::Duration::seconds(1);
+        assert!(check_not_expired(&quote(1, now + second), now).is_ok());
+        for expires_at in [now, now - second] {
+            let rejection = check_not_expired(&quote(1, expires_at), now).unwrap_err();
+            assert_eq!(rejection.code, MMErrorCode::QuoteExpired);
+        }
+    }
+
+    #[test]
+    fn test_price_tolerance() {
+        let quote = quote(1_000_000, Utc::now());
+        // 50 bps more than now is the limit
+        assert!(check_price(&quote, &current(995_000), 50).is_ok());
+        let rejection = check_price(&quote, &current(994_999), 50).unwrap_err();
+        assert_eq!(rejection.code, MMErrorCode::PriceMoved);
+        // Paying out less than now is always fine
+        assert!(check_price(&quote, &current(1_100_000), 0).is_ok());
+
+        let rejection = check_price(
+            &quote,
+            &RFQResult::InvalidRequest("Amount out too low net of fees".to_string()),
+            50,
+        )
+        .unwrap_err();
+        assert_eq!(rejection.code, MMErrorCode::PriceMoved);
     }
 }
@@ -1,10 +1,12 @@
+use alloy::primitives::U256;
 use async_trait::async_trait;
+use bdk_wallet::bitcoin::OutPoint;
 use dashmap::DashMap;
 use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_models::{ChainType, Currency, Lot};
+use otc_models::{ChainNetwork, ChainType, Currency, FillUsage, Lot, TxHash};
 use snafu::Snafu;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::oneshot;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::oneshot, time::Instant};
 
 #[derive(Debug, Snafu)]
 pub enum WalletError {
@@ -48,10 +50,104 @@ pub enum WalletError {
 
     #[snafu(display("Failed to receive transaction result: {}", source))]
     ReceiveResult { source: oneshot::error::RecvError },
+
+    #[snafu(display("Payment lookup failed: {}", reason))]
+    PaymentLookupFailed { reason: String },
 }
 
 pub type Result<T, E = WalletError> = std::result::Result<T, E>;
 
+/// Fee rate looked up ahead of a payout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreparedFeeRate {
+    Bitcoin {
+        sat_per_vb: u64,
+    },
+    Evm {
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+        /// Base fees move every block, so the estimate goes stale quickly
+        estimated_at: Instant,
+    },
+}
+
+/// Lookups done when our quote is selected, so the payout doesn't repeat them
+/// once the user's deposit confirms
+#[derive(Debug, Clone)]
+pub struct FillPreparation {
+    /// What we expect to pay out
+    pub lot: Lot,
+    pub fee_rate: Option<PreparedFeeRate>,
+    /// Bitcoin inputs set aside for the payout
+    pub utxos: Vec<OutPoint>,
+    /// How long the lookups took, i.e. what a payout reusing them saves
+    pub lookup_duration: Duration,
+}
+
+/// What a wallet knows about a payment it just broadcast
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionResult {
+    pub tx_hash: TxHash,
+    /// Fee paid in the chain's native currency, when the wallet could tell
+    pub fee: Option<U256>,
+    /// Confirmations already seen, 0 for a payment that was only broadcast
+    pub confirmations: u64,
+    /// Chain specific details, e.g. the raw transaction or receipt
+    pub raw: Option<serde_json::Value>,
+    /// Fee rate achieved and block space used, when the wallet could tell
+    pub usage: Option<FillUsage>,
+    /// Account nonce the payment used, on chains that have one
+    pub nonce: Option<u64>,
+}
+
+/// Where a payment we broadcast stands on chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentStatus {
+    /// Known to the chain but not in a block yet
+    Pending,
+    Confirmed,
+    /// Mined but failed, so it paid nothing
+    Reverted,
+    /// Neither mined nor known to the chain's mempool
+    NotFound,
+}
+
+impl PaymentStatus {
+    /// Whether the payment can no longer pay out as it is
+    #[must_use]
+    pub fn failed(self) -> bool {
+        matches!(self, Self::Reverted | Self::NotFound)
+    }
+}
+
+/// A payout that failed on chain and has to be made again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedPayment {
+    pub tx_hash: TxHash,
+    pub status: PaymentStatus,
+    /// Account nonce the payment used, when it was recorded
+    pub nonce: Option<u64>,
+}
+
+/// `lot` plus everything `pending` preparations already claim of the same token
+#[must_use]
+pub fn with_reservations(lot: &Lot, pending: &[FillPreparation]) -> Lot {
+    let reserved = pending
+        .iter()
+        .filter(|preparation| {
+            preparation.lot.currency.network() == lot.currency.network()
+                && preparation.lot.currency.token.is_same_token(&lot.currency.token)
+        })
+        .fold(lot.amount, |total, preparation| {
+            total.saturating_add(preparation.lot.amount)
+        });
+
+    Lot {
+        currency: lot.currency.clone(),
+        amount: reserved,
+    }
+}
+
 #[async_trait]
 pub trait Wallet: Send + Sync {
     /// Create a transaction for the given currency to the specified address
@@ -61,15 +157,81 @@ pub trait Wallet: Send + Sync {
         lot: &Lot,
         to_address: &str,
         mm_payment_validation: Option<MarketMakerPaymentValidation>,
-    ) -> Result<String>;
+    ) -> Result<TransactionResult>;
 
     /// Check if the wallet can fill the specified amount of currency
     async fn can_fill(&self, lot: &Lot) -> Result<bool>;
+
+    /// Check the lot can still be filled on top of the `pending` preparations and
+    /// do whatever lookups the payout can reuse
+    async fn prepare_fill(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> Result<FillPreparation> {
+        let start = Instant::now();
+        let reserved = with_reservations(lot, pending);
+        if !self.can_fill(&reserved).await? {
+            return Err(WalletError::InsufficientBalance {
+                required: reserved.amount.to_string(),
+                available: "unknown".to_string(),
+            });
+        }
+
+        Ok(FillPreparation {
+            lot: lot.clone(),
+            fee_rate: None,
+            utxos: Vec::new(),
+            lookup_duration: start.elapsed(),
+        })
+    }
+
+    /// Same as `create_payment`, reusing what `prepare_fill` looked up where possible
+    async fn create_prepared_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        _preparation: &FillPreparation,
+    ) -> Result<TransactionResult> {
+        self.create_payment(lot, to_address, mm_payment_validation)
+            .await
+    }
+
+    /// Everything the wallet holds of `currency` on chain
+    async fn balance(&self, currency: &Currency) -> Result<U256> {
+        Err(WalletError::BalanceCheckFailed {
+            reason: format!("wallet doesn't report its balance of {currency:?}"),
+        })
+    }
+
+    /// Look up a payment this wallet broadcast
+    async fn payment_status(&self, tx_hash: &TxHash) -> Result<PaymentStatus> {
+        Err(WalletError::PaymentLookupFailed {
+            reason: format!("wallet can't look up payment {tx_hash}"),
+        })
+    }
+
+    /// Pay `lot` again in place of `failed`, built so that at most one of the
+    /// two can ever confirm
+    async fn replace_payment(
+        &self,
+        failed: &FailedPayment,
+        _lot: &Lot,
+        _to_address: &str,
+        _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> Result<TransactionResult> {
+        Err(WalletError::TransactionCreationFailed {
+            reason: format!("wallet can't replace payment {}", failed.tx_hash),
+        })
+    }
 }
 
+/// Payout wallets by network. Each chain type has a primary network, EVM chains
+/// may also have wallets on others, keyed by chain id
 #[derive(Clone)]
 pub struct WalletManager {
-    wallets: HashMap<ChainType, Arc<dyn Wallet>>,
+    wallets: HashMap<ChainNetwork, Arc<dyn Wallet>>,
 }
 
 impl WalletManager {
@@ -80,31 +242,57 @@ impl WalletManager {
         }
     }
 
-    /// Register a wallet implementation for a specific chain type
+    /// Register a wallet implementation for the primary network of a chain type
     pub fn register(&mut self, chain_type: ChainType, wallet: Arc<dyn Wallet>) {
-        self.wallets.insert(chain_type, wallet);
+        self.register_network(ChainNetwork::primary(chain_type), wallet);
+    }
+
+    /// Register a wallet implementation for a specific network
+    pub fn register_network(&mut self, network: ChainNetwork, wallet: Arc<dyn Wallet>) {
+        self.wallets.insert(network, wallet);
     }
 
-    /// Remove a wallet implementation for a specific chain type
+    /// Remove the wallet implementation of a chain type's primary network
     pub fn remove(&mut self, chain_type: ChainType) -> Option<Arc<dyn Wallet>> {
-        self.wallets.remove(&chain_type)
+        self.wallets.remove(&ChainNetwork::primary(chain_type))
     }
 
-    /// Get a wallet implementation for a specific chain type
+    /// Get the wallet implementation of a chain type's primary network
     pub fn get(&self, chain_type: ChainType) -> Option<Arc<dyn Wallet>> {
-        self.wallets.get(&chain_type).cloned()
+        self.get_network(ChainNetwork::primary(chain_type))
     }
 
-    /// Check if a wallet is registered for a specific chain type
+    /// Get the wallet implementation for a specific network
+    pub fn get_network(&self, network: ChainNetwork) -> Option<Arc<dyn Wallet>> {
+        self.wallets.get(&network).cloned()
+    }
+
+    /// Get the wallet paying out in `currency`
+    pub fn for_currency(&self, currency: &Currency) -> Option<Arc<dyn Wallet>> {
+        self.get_network(currency.network())
+    }
+
+    /// Check if a wallet is registered for a chain type's primary network
     #[must_use]
     pub fn is_registered(&self, chain_type: ChainType) -> bool {
-        self.wallets.contains_key(&chain_type)
+        self.wallets
+            .contains_key(&ChainNetwork::primary(chain_type))
     }
 
-    /// Get all registered chain types
+    /// Get all chain types with a wallet on their primary network
     #[must_use]
     pub fn registered_chains(&self) -> Vec<ChainType> {
-        self.wallets.keys().cloned().collect()
+        self.wallets
+            .keys()
+            .filter(|network| network.chain_id.is_none())
+            .map(|network| network.chain)
+            .collect()
+    }
+
+    /// Get every network with a registered wallet, primary ones included
+    #[must_use]
+    pub fn networks(&self) -> Vec<ChainNetwork> {
+        self.wallets.keys().copied().collect()
     }
 }
 
@@ -117,9 +305,10 @@ impl Default for WalletManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloy::primitives::U256;
     use otc_models::TokenIdentifier;
 
+    const MOCK_TXID: &str = "7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730";
+
     struct MockWallet {
         can_fill_response: bool,
     }
@@ -131,8 +320,15 @@ mod tests {
             _lot: &Lot,
             _to_address: &str,
             _mm_payment_validation: Option<MarketMakerPaymentValidation>,
-        ) -> Result<String> {
-            Ok("mock_txid_123".to_string())
+        ) -> Result<TransactionResult> {
+            Ok(TransactionResult {
+                tx_hash: TxHash::parse(ChainType::Bitcoin, MOCK_TXID).unwrap(),
+                fee: Some(U256::from(250)),
+                confirmations: 0,
+                raw: None,
+                usage: None,
+                nonce: None,
+            })
         }
 
         async fn can_fill(&self, _lot: &Lot) -> Result<bool> {
@@ -159,6 +355,7 @@ mod tests {
                 chain: ChainType::Bitcoin,
                 token: TokenIdentifier::Native,
                 decimals: 8,
+                chain_id: None,
             },
             amount: U256::from(100000),
         };
@@ -167,8 +364,9 @@ mod tests {
         let can_fill = wallet.can_fill(&lot).await.unwrap();
         assert!(can_fill);
 
-        let txid = wallet.create_payment(&lot, "bc1q...", None).await.unwrap();
-        assert_eq!(txid, "mock_txid_123");
+        let payment = wallet.create_payment(&lot, "bc1q...", None).await.unwrap();
+        assert_eq!(payment.tx_hash.as_str(), MOCK_TXID);
+        assert_eq!(payment.fee, Some(U256::from(250)));
 
         // Remove wallet
         let removed = manager.remove(ChainType::Bitcoin);
@@ -176,6 +374,38 @@ mod tests {
         assert!(!manager.is_registered(ChainType::Bitcoin));
     }
 
+    #[test]
+    fn test_reservations_only_count_the_same_token() {
+        let lot = |token: TokenIdentifier, amount: u64| Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(amount),
+        };
+        let cbbtc = TokenIdentifier::Address("0xcbb7c0000ab88b473b1f5afd9ef808440eed33bf".into());
+        let preparation = |lot: Lot| FillPreparation {
+            lot,
+            fee_rate: None,
+            utxos: Vec::new(),
+            lookup_duration: Duration::ZERO,
+        };
+        let pending = [
+            preparation(lot(cbbtc.clone(), 100)),
+            preparation(lot(cbbtc.clone(), 50)),
+            preparation(lot(TokenIdentifier::Native, 1_000)),
+        ];
+
+        let reserved = with_reservations(&lot(cbbtc, 10), &pending);
+        assert_eq!(reserved.amount, U256::from(160));
+        assert_eq!(
+            with_reservations(&lot(TokenIdentifier::Native, 10), &[]).amount,
+            U256::from(10)
+        );
+    }
+
     #[test]
     fn test_registered_chains() {
         let mut manager = WalletManager::new();
@@ -191,4 +421,38 @@ mod tests {
         assert!(chains.contains(&ChainType::Bitcoin));
         assert!(chains.contains(&ChainType::Ethereum));
     }
+
+    #[tokio::test]
+    async fn test_currencies_route_to_their_network() {
+        let mut manager = WalletManager::new();
+        manager.register(
+            ChainType::Ethereum,
+            Arc::new(MockWallet {
+                can_fill_response: true,
+            }),
+        );
+        manager.register_network(
+            ChainNetwork::evm(8453),
+            Arc::new(MockWallet {
+                can_fill_response: false,
+            }),
+        );
+        let lot = |chain_id| Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Native,
+                decimals: 18,
+                chain_id,
+            },
+            amount: U256::from(1),
+        };
+
+        let mainnet = manager.for_currency(&lot(None).currency).unwrap();
+        assert!(mainnet.can_fill(&lot(None)).await.unwrap());
+        let base = manager.for_currency(&lot(Some(8453)).currency).unwrap();
+        assert!(!base.can_fill(&lot(Some(8453))).await.unwrap());
+        assert!(manager.for_currency(&lot(Some(10)).currency).is_none());
+        assert_eq!(manager.registered_chains(), vec![ChainType::Ethereum]);
+        assert_eq!(manager.networks().len(), 2);
+    }
 }
@@ -0,0 +1,654 @@
+//! Named buckets over each payout wallet, so funds set aside for the protocol
+//! or held back by the operator are never quoted against. On chain each
+//! currency stays in one wallet: the buckets other than trading are a ledger
+//! kept in quote storage, and trading is whatever the wallet holds beyond them,
+//! so external deposits land in trading.
+
+use alloy::primitives::U256;
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use otc_chains::traits::MarketMakerPaymentValidation;
+use otc_models::{ChainNetwork, Currency, Lot, TokenIdentifier, TxHash};
+use snafu::prelude::*;
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::{
+    quote_storage::{QuoteStorage, QuoteStorageError},
+    wallet::{
+        self, FailedPayment, FillPreparation, PaymentStatus, TransactionResult, Wallet,
+        WalletError, WalletManager,
+    },
+};
+
+/// How often bucket totals are compared with the on-chain balances by default
+pub const DEFAULT_BUCKET_RECONCILIATION_INTERVAL_SECONDS: u64 = 300;
+
+/// How far the funds set aside may exceed a wallet's balance before it's
+/// reported, in basis points of the funds set aside
+pub const DEFAULT_BUCKET_DRIFT_TOLERANCE_BPS: u64 = 10;
+
+#[derive(Debug, Snafu)]
+pub enum WalletBucketError {
+    #[snafu(display("Wallet bucket storage failed: {}", source))]
+    Storage { source: QuoteStorageError },
+
+    #[snafu(display("Failed to read the wallet balance: {}", source))]
+    Balance { source: WalletError },
+
+    #[snafu(display("Wallet not registered for {}", network))]
+    BucketWalletNotRegistered { network: ChainNetwork },
+
+    #[snafu(display("The trading bucket holds only {}, {} requested", available, required))]
+    InsufficientTradingBalance { required: String, available: String },
+
+    #[snafu(display(
+        "No balance of {:?} recorded yet, the market maker records it when it reconciles",
+        currency
+    ))]
+    BalanceNotRecorded { currency: Currency },
+}
+
+pub type Result<T, E = WalletBucketError> = std::result::Result<T, E>;
+
+/// A named share of a payout wallet's funds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WalletBucket {
+    /// What quotes and fills may use
+    Trading,
+    /// Set aside for protocol fees
+    ProtocolFees,
+    /// Held back by the operator
+    Reserved,
+}
+
+impl WalletBucket {
+    /// Buckets kept in the ledger, i.e. all but trading
+    pub const EARMARKED: [WalletBucket; 2] = [WalletBucket::ProtocolFees, WalletBucket::Reserved];
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WalletBucket::Trading => "trading",
+            WalletBucket::ProtocolFees => "protocol_fees",
+            WalletBucket::Reserved => "reserved",
+        }
+    }
+}
+
+impl fmt::Display for WalletBucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WalletBucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "trading" => Ok(WalletBucket::Trading),
+            "protocol_fees" => Ok(WalletBucket::ProtocolFees),
+            "reserved" => Ok(WalletBucket::Reserved),
+            _ => Err(format!("unknown wallet bucket: {s}")),
+        }
+    }
+}
+
+/// What one currency has set aside from trading
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BucketEarmarks {
+    pub protocol_fees: U256,
+    pub reserved: U256,
+}
+
+impl BucketEarmarks {
+    #[must_use]
+    pub fn total(&self) -> U256 {
+        self.protocol_fees.saturating_add(self.reserved)
+    }
+
+    /// The amount in `bucket`, `None` for trading which isn't kept in the ledger
+    #[must_use]
+    pub fn get(&self, bucket: WalletBucket) -> Option<U256> {
+        match bucket {
+            WalletBucket::Trading => None,
+            WalletBucket::ProtocolFees => Some(self.protocol_fees),
+            WalletBucket::Reserved => Some(self.reserved),
+        }
+    }
+
+    pub fn get_mut(&mut self, bucket: WalletBucket) -> Option<&mut U256> {
+        match bucket {
+            WalletBucket::Trading => None,
+            WalletBucket::ProtocolFees => Some(&mut self.protocol_fees),
+            WalletBucket::Reserved => Some(&mut self.reserved),
+        }
+    }
+}
+
+/// How a currency's on-chain balance splits across the buckets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketBalances {
+    pub trading: U256,
+    pub protocol_fees: U256,
+    pub reserved: U256,
+}
+
+/// A currency's buckets checked against what its wallet holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconciliation {
+    pub on_chain: U256,
+    pub balances: BucketBalances,
+    /// Funds set aside beyond what the wallet holds, zero when the ledger is covered
+    pub shortfall: U256,
+}
+
+impl Reconciliation {
+    #[must_use]
+    pub fn new(on_chain: U256, earmarks: &BucketEarmarks) -> Self {
+        Self {
+            on_chain,
+            balances: BucketBalances {
+                trading: on_chain.saturating_sub(earmarks.total()),
+                protocol_fees: earmarks.protocol_fees,
+                reserved: earmarks.reserved,
+            },
+            shortfall: earmarks.total().saturating_sub(on_chain),
+        }
+    }
+
+    /// Whether the shortfall is more than `tolerance_bps` of the funds set aside
+    #[must_use]
+    pub fn drifted(&self, tolerance_bps: u64) -> bool {
+        let earmarked = self
+            .balances
+            .protocol_fees
+            .saturating_add(self.balances.reserved);
+        self.shortfall.saturating_mul(U256::from(10_000u64))
+            > earmarked.saturating_mul(U256::from(tolerance_bps))
+    }
+}
+
+/// The ledger's amounts by currency, shared by the bucketed wallets so quoting
+/// doesn't wait on the database
+#[derive(Debug, Clone, Default)]
+pub struct Earmarks {
+    by_currency: Arc<DashMap<(ChainNetwork, TokenIdentifier), (Currency, BucketEarmarks)>>,
+}
+
+impl Earmarks {
+    #[must_use]
+    pub fn get(&self, currency: &Currency) -> BucketEarmarks {
+        self.by_currency
+            .get(&(currency.network(), currency.token.clone()))
+            .map(|entry| entry.1)
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, currency: &Currency, earmarks: BucketEarmarks) {
+        self.by_currency.insert(
+            (currency.network(), currency.token.clone()),
+            (currency.clone(), earmarks),
+        );
+    }
+
+    /// Every currency with funds set aside
+    #[must_use]
+    pub fn currencies(&self) -> Vec<(Currency, BucketEarmarks)> {
+        self.by_currency
+            .iter()
+            .filter(|entry| entry.1.total() > U256::ZERO)
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+}
+
+/// The bucket ledger, written through to quote storage
+#[derive(Clone)]
+pub struct WalletBuckets {
+    storage: Arc<QuoteStorage>,
+    /// Reconciled alongside the currencies with funds set aside, so moves out
+    /// of their trading buckets have a balance to be checked against
+    currencies: Vec<Currency>,
+}
+
+impl WalletBuckets {
+    /// Load the ledger from `storage`
+    pub async fn load(storage: Arc<QuoteStorage>) -> Result<Self> {
+        let buckets = Self {
+            storage,
+            currencies: Vec::new(),
+        };
+        buckets.reload().await?;
+        Ok(buckets)
+    }
+
+    /// Reconcile `currencies` too, whether or not they have funds set aside
+    #[must_use]
+    pub fn with_currencies(mut self, currencies: Vec<Currency>) -> Self {
+        self.currencies = currencies;
+        self
+    }
+
+    #[must_use]
+    pub fn earmarks(&self) -> &Earmarks {
+        self.storage.earmarks()
+    }
+
+    /// `wallet` limited to its trading bucket
+    #[must_use]
+    pub fn wrap(&self, wallet: Arc<dyn Wallet>) -> Arc<dyn Wallet> {
+        Arc::new(BucketedWallet::new(wallet, self.earmarks().clone()))
+    }
+
+    /// Pick up moves made outside this process, e.g. from the command line
+    pub async fn reload(&self) -> Result<()> {
+        for (currency, amounts) in self.storage.bucket_earmarks().await.context(StorageSnafu)? {
+            self.earmarks().set(&currency, amounts);
+        }
+        Ok(())
+    }
+
+    /// Move `amount` of `currency` between buckets. Moving out of trading needs
+    /// the wallet to have held it beyond what's already set aside when it was
+    /// last reconciled
+    pub async fn transfer(
+        &self,
+        currency: &Currency,
+        from: WalletBucket,
+        to: WalletBucket,
+        amount: U256,
+    ) -> Result<BucketEarmarks> {
+        if from == WalletBucket::Trading {
+            let (on_chain, _) = self
+                .storage
+                .wallet_balance(currency)
+                .await
+                .context(StorageSnafu)?
+                .context(BalanceNotRecordedSnafu {
+                    currency: currency.clone(),
+                })?;
+            let available = Reconciliation::new(on_chain, &self.earmarks().get(currency))
+                .balances
+                .trading;
+            ensure!(
+                amount <= available,
+                InsufficientTradingBalanceSnafu {
+                    required: amount.to_string(),
+                    available: available.to_string(),
+                }
+            );
+        }
+
+        let earmarks = self
+            .storage
+            .transfer_between_buckets(currency, from, to, amount)
+            .await
+            .context(StorageSnafu)?;
+        info!(
+            "Moved {} of {:?} from the {} bucket to the {} bucket",
+            amount, currency, from, to
+        );
+        Ok(earmarks)
+    }
+
+    /// Compare the buckets of every reconciled currency against the wallet
+    /// balance, recording the balance and logging an error for those off by
+    /// more than `tolerance_bps`
+    pub async fn reconcile(
+        &self,
+        wallet_manager: &WalletManager,
+        tolerance_bps: u64,
+    ) -> Vec<(Currency, Reconciliation)> {
+        if let Err(e) = self.reload().await {
+            warn!("Reconciling wallet buckets without reloading them: {}", e);
+        }
+
+        let mut currencies = self.currencies.clone();
+        for (currency, _) in self.earmarks().currencies() {
+            if !currencies
+                .iter()
+                .any(|known| known.network() == currency.network() && known.token == currency.token)
+            {
+                currencies.push(currency);
+            }
+        }
+
+        let mut reconciliations = Vec::new();
+        for currency in currencies {
+            let on_chain = match self.on_chain_balance(wallet_manager, &currency).await {
+                Ok(on_chain) => on_chain,
+                Err(e) => {
+                    warn!("Skipping bucket reconciliation of {:?}: {}", currency, e);
+                    continue;
+                }
+            };
+            if let Err(e) = self
+                .storage
+                .record_wallet_balance(&currency, on_chain, Utc::now())
+                .await
+            {
+                warn!("Failed to record the balance of {:?}: {}", currency, e);
+            }
+
+            let earmarks = self.earmarks().get(&currency);
+            let reconciliation = Reconciliation::new(on_chain, &earmarks);
+            if reconciliation.drifted(tolerance_bps) {
+                error!(
+                    "Wallet buckets of {:?} drifted from the chain: {} set aside, {} held, {} short",
+                    currency,
+                    earmarks.total(),
+                    on_chain,
+                    reconciliation.shortfall
+                );
+            }
+            reconciliations.push((currency, reconciliation));
+        }
+        reconciliations
+    }
+
+    /// Reconcile every `interval`, forever
+    pub async fn run_reconciliation(
+        self,
+        wallet_manager: WalletManager,
+        interval: Duration,
+        tolerance_bps: u64,
+    ) {
+        let mut interval = time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.reconcile(&wallet_manager, tolerance_bps).await;
+        }
+    }
+
+    async fn on_chain_balance(
+        &self,
+        wallet_manager: &WalletManager,
+        currency: &Currency,
+    ) -> Result<U256> {
+        let network = currency.network();
+        let wallet = wallet_manager
+            .get_network(network)
+            .context(BucketWalletNotRegisteredSnafu { network })?;
+        wallet.balance(currency).await.context(BalanceSnafu)
+    }
+}
+
+/// A payout wallet that only quotes and fills against its trading bucket
+pub struct BucketedWallet {
+    inner: Arc<dyn Wallet>,
+    earmarks: Earmarks,
+}
+
+impl BucketedWallet {
+    #[must_use]
+    pub fn new(inner: Arc<dyn Wallet>, earmarks: Earmarks) -> Self {
+        Self { inner, earmarks }
+    }
+
+    /// `lot` plus what its currency has set aside
+    fn with_earmarks(&self, lot: &Lot) -> Lot {
+        Lot {
+            currency: lot.currency.clone(),
+            amount: lot
+                .amount
+                .saturating_add(self.earmarks.get(&lot.currency).total()),
+        }
+    }
+
+    /// Refuse a payment of `lot` that would dip into the funds set aside
+    async fn ensure_trading_covers(&self, lot: &Lot) -> wallet::Result<()> {
+        let required = self.with_earmarks(lot);
+        if !self.inner.can_fill(&required).await? {
+            return Err(WalletError::InsufficientBalance {
+                required: required.amount.to_string(),
+                available: "unknown".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Wallet for BucketedWallet {
+    async fn create_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        self.ensure_trading_covers(lot).await?;
+        self.inner
+            .create_payment(lot, to_address, mm_payment_validation)
+            .await
+    }
+
+    async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
+        self.inner.can_fill(&self.with_earmarks(lot)).await
+    }
+
+    async fn prepare_fill(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> wallet::Result<FillPreparation> {
+        // The funds set aside count as one more pending fill, claiming no inputs
+        let mut claimed = pending.to_vec();
+        claimed.push(FillPreparation {
+            lot: Lot {
+                currency: lot.currency.clone(),
+                amount: self.earmarks.get(&lot.currency).total(),
+            },
+            fee_rate: None,
+            utxos: Vec::new(),
+            lookup_duration: Duration::ZERO,
+        });
+        self.inner.prepare_fill(lot, &claimed).await
+    }
+
+    async fn create_prepared_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        preparation: &FillPreparation,
+    ) -> wallet::Result<TransactionResult> {
+        self.ensure_trading_covers(lot).await?;
+        self.inner
+            .create_prepared_payment(lot, to_address, mm_payment_validation, preparation)
+            .await
+    }
+
+    async fn balance(&self, currency: &Currency) -> wallet::Result<U256> {
+        self.inner.balance(currency).await
+    }
+
+    async fn payment_status(&self, tx_hash: &TxHash) -> wallet::Result<PaymentStatus> {
+        self.inner.payment_status(tx_hash).await
+    }
+
+    async fn replace_payment(
+        &self,
+        failed: &FailedPayment,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        self.inner
+            .replace_payment(failed, lot, to_address, mm_payment_validation)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::B256;
+    use otc_models::ChainType;
+
+    /// Holds `balance` of every currency, fills what it holds
+    struct FixedBalanceWallet {
+        balance: U256,
+    }
+
+    #[async_trait]
+    impl Wallet for FixedBalanceWallet {
+        async fn create_payment(
+            &self,
+            _lot: &Lot,
+            _to_address: &str,
+            _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        ) -> wallet::Result<TransactionResult> {
+            Ok(TransactionResult {
+                tx_hash: B256::repeat_byte(0x11).into(),
+                fee: None,
+                confirmations: 0,
+                raw: None,
+                usage: None,
+                nonce: None,
+            })
+        }
+
+        async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
+            Ok(lot.amount <= self.balance)
+        }
+
+        async fn balance(&self, _currency: &Currency) -> wallet::Result<U256> {
+            Ok(self.balance)
+        }
+    }
+
+    fn btc() -> Currency {
+        Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        }
+    }
+
+    fn lot(amount: u64) -> Lot {
+        Lot {
+            currency: btc(),
+            amount: U256::from(amount),
+        }
+    }
+
+    fn earmarks(protocol_fees: u64, reserved: u64) -> BucketEarmarks {
+        BucketEarmarks {
+            protocol_fees: U256::from(protocol_fees),
+            reserved: U256::from(reserved),
+        }
+    }
+
+    #[test]
+    fn test_trading_is_what_the_wallet_holds_beyond_the_earmarks() {
+        let reconciliation = Reconciliation::new(U256::from(1_000u64), &earmarks(150, 50));
+        assert_eq!(
+            reconciliation.balances,
+            BucketBalances {
+                trading: U256::from(800u64),
+                protocol_fees: U256::from(150u64),
+                reserved: U256::from(50u64),
+            }
+        );
+        assert_eq!(reconciliation.shortfall, U256::ZERO);
+
+        // A deposit from outside only grows trading
+        let reconciliation = Reconciliation::new(U256::from(1_500u64), &earmarks(150, 50));
+        assert_eq!(reconciliation.balances.trading, U256::from(1_300u64));
+        assert_eq!(reconciliation.balances.protocol_fees, U256::from(150u64));
+
+        let mut amounts = earmarks(150, 50);
+        *amounts.get_mut(WalletBucket::Reserved).unwrap() += U256::from(25u64);
+        assert_eq!(amounts.total(), U256::from(225u64));
+        assert_eq!(amounts.get(WalletBucket::Trading), None);
+        assert!(amounts.get_mut(WalletBucket::Trading).is_none());
+
+        for bucket in [
+            WalletBucket::Trading,
+            WalletBucket::ProtocolFees,
+            WalletBucket::Reserved,
+        ] {
+            assert_eq!(bucket.as_str().parse::<WalletBucket>(), Ok(bucket));
+        }
+    }
+
+    #[test]
+    fn test_drift_is_a_shortfall_beyond_the_tolerance() {
+        // 10 bps of the 10_000 set aside is 10
+        let set_aside = earmarks(6_000, 4_000);
+        let within = Reconciliation::new(U256::from(9_990u64), &set_aside);
+        assert_eq!(within.shortfall, U256::from(10u64));
+        assert_eq!(within.balances.trading, U256::ZERO);
+        assert!(!within.drifted(10));
+
+        let beyond = Reconciliation::new(U256::from(9_989u64), &set_aside);
+        assert!(beyond.drifted(10));
+        assert!(!beyond.drifted(11));
+
+        // Nothing set aside can't drift, however empty the wallet
+        assert!(!Reconciliation::new(U256::ZERO, &BucketEarmarks::default()).drifted(0));
+    }
+
+    #[tokio::test]
+    async fn test_fills_only_draw_on_the_trading_bucket() {
+        let set_aside = Earmarks::default();
+        let wallet = BucketedWallet::new(
+            Arc::new(FixedBalanceWallet {
+                balance: U256::from(1_000u64),
+            }),
+            set_aside.clone(),
+        );
+
+        assert!(wallet.can_fill(&lot(1_000)).await.unwrap());
+
+        set_aside.set(&btc(), earmarks(300, 100));
+        assert!(wallet.can_fill(&lot(600)).await.unwrap());
+        assert!(!wallet.can_fill(&lot(601)).await.unwrap());
+
+        let pending = [FillPreparation {
+            lot: lot(500),
+            fee_rate: None,
+            utxos: Vec::new(),
+            lookup_duration: Duration::ZERO,
+        }];
+        let preparation = wallet.prepare_fill(&lot(100), &pending).await.unwrap();
+        assert_eq!(preparation.lot.amount, U256::from(100u64));
+        assert!(matches!(
+            wallet.prepare_fill(&lot(101), &pending).await,
+            Err(WalletError::InsufficientBalance { .. })
+        ));
+
+        // Nor can a payment, prepared or not, dip into the funds set aside
+        assert!(wallet.create_payment(&lot(600), "", None).await.is_ok());
+        assert!(matches!(
+            wallet.create_payment(&lot(601), "", None).await,
+            Err(WalletError::InsufficientBalance { .. })
+        ));
+        assert!(matches!(
+            wallet
+                .create_prepared_payment(&lot(601), "", None, &preparation)
+                .await,
+            Err(WalletError::InsufficientBalance { .. })
+        ));
+
+        // Other currencies are unaffected
+        let cbbtc = Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Address(
+                    "0xcbb7c0000ab88b473b1f5afd9ef808440eed33bf".to_string(),
+                ),
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(1_000u64),
+        };
+        assert!(wallet.can_fill(&cbbtc).await.unwrap());
+        assert_eq!(wallet.balance(&btc()).await.unwrap(), U256::from(1_000u64));
+    }
+}
@@ -1,15 +1,21 @@
-use std::time::Duration;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::{
-    bitcoin_wallet::BitcoinWallet, evm_wallet::EVMWallet, price_oracle::BitcoinEtherPriceOracle,
+    bitcoin_wallet::BitcoinWallet, config::PricingConfig, evm_wallet::EVMWallet,
+    price_oracle::BitcoinEtherPriceOracle,
 };
 use alloy::eips::BlockNumberOrTag;
 use alloy::providers::DynProvider;
 use alloy::{primitives::U256, providers::Provider};
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
 use bdk_wallet::bitcoin::policy::DUST_RELAY_TX_FEE;
-use chrono::Utc;
-use blockchain_utils::{compute_protocol_fee_sats, inverse_compute_protocol_fee};
-use otc_models::{constants, ChainType, Lot, Quote, QuoteMode, QuoteRequest};
+use blockchain_utils::ProtocolFeeParams;
+use common::Clock;
+use otc_chains::dust::max_dust_threshold;
+use otc_models::{
+    ChainNetwork, ChainType, Lot, Quote, QuoteMode, QuoteRequest, SupportedCurrencies,
+};
 use otc_protocols::rfq::{FeeSchedule, QuoteWithFees, RFQResult};
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
@@ -32,32 +38,161 @@ impl From<esplora_client::Error> for WrappedBitcoinQuoterError {
 
 type Result<T, E = WrappedBitcoinQuoterError> = std::result::Result<T, E>;
 
-/// Note this is greater than the
-pub const MIN_PROTOCOL_FEE_SATS: u64 = 300;
+/// Gas prices of an EVM network, in gwei
+#[derive(Debug, Clone, Copy)]
+pub struct GasPrices {
+    pub base_fee_gwei: f64,
+    pub max_priority_fee_gwei: f64,
+}
+
+/// Where the network fee of a fill is estimated from, before the safety
+/// multiplier is applied
+#[async_trait]
+pub trait FeeEstimator: Send + Sync {
+    /// Sats per vbyte for a bitcoin fill to confirm in the next block
+    async fn bitcoin_sats_per_vbyte(&self) -> Result<f64>;
+
+    /// Gas prices of `network`, or why they couldn't be fetched
+    async fn ethereum_gas_prices(&self, network: ChainNetwork) -> Result<GasPrices, String>;
+}
+
+/// Fee estimates of the live chains, from esplora and each EVM network's node
+pub struct ChainFeeEstimator {
+    esplora_client: esplora_client::AsyncClient,
+    /// Gas prices of each EVM network the market maker fills on
+    eth_providers: HashMap<ChainNetwork, DynProvider>,
+}
+
+impl ChainFeeEstimator {
+    pub fn new(esplora_client: esplora_client::AsyncClient, eth_provider: DynProvider) -> Self {
+        Self {
+            esplora_client,
+            eth_providers: HashMap::from([(
+                ChainNetwork::primary(ChainType::Ethereum),
+                eth_provider,
+            )]),
+        }
+    }
+
+    /// Price fills on the EVM network `chain_id` from `provider`'s gas prices
+    #[must_use]
+    pub fn with_network_provider(mut self, chain_id: u64, provider: DynProvider) -> Self {
+        self.eth_providers
+            .insert(ChainNetwork::evm(chain_id), provider);
+        self
+    }
+}
+
+#[async_trait]
+impl FeeEstimator for ChainFeeEstimator {
+    async fn bitcoin_sats_per_vbyte(&self) -> Result<f64> {
+        //TODO: put updating this fee rate behind a RwLock that we cache so it's not fetched on every quote
+        let sats_per_vbyte_by_confirmations = self.esplora_client.get_fee_estimates().await?;
+        Ok(*sats_per_vbyte_by_confirmations.get(&1).unwrap_or(&1.5))
+    }
+
+    async fn ethereum_gas_prices(&self, network: ChainNetwork) -> Result<GasPrices, String> {
+        let Some(provider) = self.eth_providers.get(&network) else {
+            return Err(format!("No provider for {network}"));
+        };
+        //TODO: put updating this fee rate behind a RwLock that we cache so it's not fetched on every quote
+        let fee_history = match provider
+            .get_fee_history(10u64, BlockNumberOrTag::Latest, &[25.0, 50.0, 75.0])
+            .await
+        {
+            Ok(history) => history,
+            Err(e) => {
+                warn!("Failed to get fee history: {:?}", e);
+                return Err("Failed to get fee history".to_string());
+            }
+        };
+
+        let base_fee_wei: u128 = fee_history.next_block_base_fee().unwrap_or(0u128);
+
+        let mid_priority_wei: u128 = fee_history
+            .reward
+            .as_ref()
+            .and_then(|rewards| rewards.last())
+            .and_then(|percentiles| percentiles.get(1)) // 50th percentile
+            .copied()
+            .unwrap_or(1_500_000_000u128); // default 1.5 gwei
+
+        Ok(GasPrices {
+            base_fee_gwei: (base_fee_wei as f64) / 1e9f64,
+            max_priority_fee_gwei: (mid_priority_wei as f64) / 1e9f64,
+        })
+    }
+}
+
+/// Fee and price inputs the network fee of a quote was computed from
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PricingInputs {
+    /// Sats per vbyte with the safety multiplier applied, for fills on bitcoin
+    pub sats_per_vbyte: Option<f64>,
+    /// ETH per BTC the gas of an EVM fill was converted at
+    pub eth_per_btc: Option<f64>,
+}
+
+/// A quote result along with what it was priced from, empty inputs when the
+/// request was turned down before any fee was looked up
+#[derive(Debug, Clone)]
+pub struct PricedQuote {
+    pub result: RFQResult<QuoteWithFees>,
+    pub inputs: PricingInputs,
+}
 
 pub struct WrappedBitcoinQuoter {
     btc_eth_price_oracle: BitcoinEtherPriceOracle,
-    esplora_client: esplora_client::AsyncClient,
-    eth_provider: DynProvider,
-    trade_spread_bps: u64,
-    fee_safety_multiplier: f64,
+    fee_estimator: Arc<dyn FeeEstimator>,
+    bitcoin_wallet: Arc<BitcoinWallet>,
+    /// Swapped on reload, see [`crate::config::reload_pricing_on_sighup`]
+    pricing: Arc<ArcSwap<PricingConfig>>,
+    protocol_fee: ProtocolFeeParams,
+    supported_currencies: Arc<SupportedCurrencies>,
+    /// Quotes are created and expire by this clock
+    clock: Arc<dyn Clock>,
+    /// Every quote is logged as simulated, see [`Self::in_simulation`]
+    simulation: bool,
 }
 
 impl WrappedBitcoinQuoter {
     pub fn new(
         btc_eth_price_oracle: BitcoinEtherPriceOracle,
-        esplora_client: esplora_client::AsyncClient,
-        eth_provider: DynProvider,
-        trade_spread_bps: u64,
-        fee_safety_multiplier: f64,
+        fee_estimator: Arc<dyn FeeEstimator>,
+        bitcoin_wallet: Arc<BitcoinWallet>,
+        pricing: Arc<ArcSwap<PricingConfig>>,
+        protocol_fee: ProtocolFeeParams,
+        supported_currencies: Arc<SupportedCurrencies>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         Self {
             btc_eth_price_oracle,
-            esplora_client,
-            eth_provider,
-            trade_spread_bps,
-            fee_safety_multiplier,
+            fee_estimator,
+            bitcoin_wallet,
+            pricing,
+            protocol_fee,
+            supported_currencies,
+            clock,
+            simulation: false,
+        }
+    }
+
+    /// Quote without a spread and warn on every quote, for simulation mode where
+    /// the price and fees are made up
+    #[must_use]
+    pub fn in_simulation(mut self) -> Self {
+        self.simulation = true;
+        self
+    }
+
+    /// Pricing of the next quote or batch, read once so a reload never splits
+    /// one between old and new values
+    fn pricing(&self) -> PricingConfig {
+        let mut pricing = **self.pricing.load();
+        if self.simulation {
+            pricing.trade_spread_bps = 0;
         }
+        pricing
     }
 
     /// Compute a quote for the given amount and quote mode.
@@ -68,83 +203,208 @@ impl WrappedBitcoinQuoter {
         market_maker_id: Uuid,
         quote_request: &QuoteRequest,
     ) -> Result<RFQResult<QuoteWithFees>> {
-        if let Some(error_message) = is_fillable_request(quote_request) {
+        Ok(self
+            .price_quote(market_maker_id, quote_request)
+            .await?
+            .result)
+    }
+
+    /// Like [`Self::compute_quote`], along with the fee and price inputs used
+    pub async fn price_quote(
+        &self,
+        market_maker_id: Uuid,
+        quote_request: &QuoteRequest,
+    ) -> Result<PricedQuote> {
+        let fillable = match self.check_request(quote_request) {
+            Ok(fillable) => fillable,
+            Err(rejection) => {
+                return Ok(PricedQuote {
+                    result: rejection,
+                    inputs: PricingInputs::default(),
+                })
+            }
+        };
+        let pricing = self.pricing();
+        let fee_rates = self
+            .fetch_fee_rates(&[quote_request.to.network()], &pricing)
+            .await?;
+        Ok(PricedQuote {
+            result: self
+                .quote_with_fee_rates(
+                    market_maker_id,
+                    quote_request,
+                    fillable,
+                    &fee_rates,
+                    &pricing,
+                )
+                .await,
+            inputs: fee_rates.inputs(quote_request.to.network()),
+        })
+    }
+
+    /// Price every one of `quote_requests`, in order, fetching fee rates and the
+    /// BTC/ETH price once for all of them
+    pub async fn price_quote_batch(
+        &self,
+        market_maker_id: Uuid,
+        quote_requests: &[QuoteRequest],
+    ) -> Result<Vec<PricedQuote>> {
+        let checked: Vec<_> = quote_requests
+            .iter()
+            .map(|quote_request| self.check_request(quote_request))
+            .collect();
+        let fill_networks: Vec<ChainNetwork> = quote_requests
+            .iter()
+            .zip(&checked)
+            .filter(|(_, checked)| checked.is_ok())
+            .map(|(quote_request, _)| quote_request.to.network())
+            .collect();
+        let pricing = self.pricing();
+        let fee_rates = self.fetch_fee_rates(&fill_networks, &pricing).await?;
+
+        let mut quotes = Vec::with_capacity(quote_requests.len());
+        for (quote_request, checked) in quote_requests.iter().zip(checked) {
+            quotes.push(match checked {
+                Ok(fillable) => PricedQuote {
+                    result: self
+                        .quote_with_fee_rates(
+                            market_maker_id,
+                            quote_request,
+                            fillable,
+                            &fee_rates,
+                            &pricing,
+                        )
+                        .await,
+                    inputs: fee_rates.inputs(quote_request.to.network()),
+                },
+                Err(rejection) => PricedQuote {
+                    result: rejection,
+                    inputs: PricingInputs::default(),
+                },
+            });
+        }
+        Ok(quotes)
+    }
+
+    /// The request in sats, or why it can't be quoted
+    fn check_request(
+        &self,
+        quote_request: &QuoteRequest,
+    ) -> Result<FillableRequest, RFQResult<QuoteWithFees>> {
+        is_fillable_request(quote_request, &self.supported_currencies).map_err(|error_message| {
             info!("Unfillable quote request: {:?}", quote_request);
-            return Ok(RFQResult::InvalidRequest(error_message));
+            RFQResult::InvalidRequest(error_message)
+        })
+    }
+
+    /// Fetch what the network fee of filling on each of `fill_networks` is priced from
+    async fn fetch_fee_rates(
+        &self,
+        fill_networks: &[ChainNetwork],
+        pricing: &PricingConfig,
+    ) -> Result<FeeRates> {
+        let mut fee_rates = FeeRates::default();
+        if fill_networks.contains(&ChainNetwork::primary(ChainType::Bitcoin)) {
+            let sats_per_vbyte = self.fee_estimator.bitcoin_sats_per_vbyte().await?;
+            fee_rates.bitcoin_sats_per_vbyte = Some(sats_per_vbyte * pricing.fee_safety_multiplier);
         }
-        if quote_request.amount > U256::from(u64::MAX) {
-            return Ok(RFQResult::InvalidRequest("Amount too large".to_string()));
+        for network in fill_networks {
+            if network.chain != ChainType::Ethereum || fee_rates.ethereum.contains_key(network) {
+                continue;
+            }
+            let rates = self
+                .fetch_ethereum_fee_rates(*network, pricing.fee_safety_multiplier)
+                .await;
+            fee_rates.ethereum.insert(*network, rates);
         }
-        let amount = quote_request.amount.to::<u64>();
-        let send_fees_in_sats = {
-            match quote_request.to.chain {
-                ChainType::Bitcoin => {
-                    //TODO: put updating this fee rate behind a RwLock that we cache so it's not fetched on every quote
-                    let sats_per_vbyte_by_confirmations =
-                        self.esplora_client.get_fee_estimates().await?;
-                    let sats_per_vbyte = sats_per_vbyte_by_confirmations.get(&1).unwrap_or(&1.5);
-                    let sats_per_vbyte = sats_per_vbyte * self.fee_safety_multiplier;
-
-                    calculate_fees_in_sats_to_send_btc(sats_per_vbyte)
-                }
-                ChainType::Ethereum => {
-                    //TODO: put updating this fee rate behind a RwLock that we cache so it's not fetched on every quote
-                    let fee_history = match self
-                        .eth_provider
-                        .get_fee_history(10u64, BlockNumberOrTag::Latest, &[25.0, 50.0, 75.0])
-                        .await
-                    {
-                        Ok(history) => history,
-                        Err(_) => {
-                            warn!("Failed to get fee history: {:?}", quote_request);
-                            return Ok(RFQResult::MakerUnavailable(
-                                "Failed to get fee history".to_string(),
-                            ));
-                        }
-                    };
-
-                    let base_fee_wei: u128 = fee_history.next_block_base_fee().unwrap_or(0u128);
-                    let base_fee_gwei: f64 = (base_fee_wei as f64) / 1e9f64;
-
-                    let mid_priority_wei: u128 = fee_history
-                        .reward
-                        .as_ref()
-                        .and_then(|rewards| rewards.last())
-                        .and_then(|percentiles| percentiles.get(1)) // 50th percentile
-                        .copied()
-                        .unwrap_or(1_500_000_000u128); // default 1.5 gwei
-                    let mut max_priority_fee_gwei: f64 = (mid_priority_wei as f64) / 1e9f64;
-
-                    max_priority_fee_gwei *= self.fee_safety_multiplier;
-
-                    let eth_per_btc_price = match self.btc_eth_price_oracle.get_eth_per_btc().await
-                    {
-                        Ok(p) => p,
-                        Err(e) => {
-                            warn!("Failed to get BTC/ETH price: {:?}", e);
-                            return Ok(RFQResult::MakerUnavailable(
-                                "Failed to get BTC/ETH price".to_string(),
-                            ));
-                        }
-                    };
-
-                    calculate_fees_in_sats_to_send_cbbtc_on_eth(
-                        base_fee_gwei,
-                        max_priority_fee_gwei,
-                        eth_per_btc_price,
+        Ok(fee_rates)
+    }
+
+    /// Gas prices of `network` and the BTC/ETH price, or why they couldn't be
+    /// fetched
+    async fn fetch_ethereum_fee_rates(
+        &self,
+        network: ChainNetwork,
+        fee_safety_multiplier: f64,
+    ) -> Result<EthereumFeeRates, String> {
+        let gas_prices = self.fee_estimator.ethereum_gas_prices(network).await?;
+
+        let eth_per_btc_price = match self.btc_eth_price_oracle.get_eth_per_btc().await {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Failed to get BTC/ETH price: {:?}", e);
+                return Err("Failed to get BTC/ETH price".to_string());
+            }
+        };
+
+        Ok(EthereumFeeRates {
+            base_fee_gwei: gas_prices.base_fee_gwei,
+            max_priority_fee_gwei: gas_prices.max_priority_fee_gwei * fee_safety_multiplier,
+            eth_per_btc_price,
+        })
+    }
+
+    /// Quote a request that passed `check_request`
+    async fn quote_with_fee_rates(
+        &self,
+        market_maker_id: Uuid,
+        quote_request: &QuoteRequest,
+        fillable: FillableRequest,
+        fee_rates: &FeeRates,
+        pricing: &PricingConfig,
+    ) -> RFQResult<QuoteWithFees> {
+        let amount = fillable.amount_sats;
+        let send_fees_in_sats = match quote_request.to.chain {
+            ChainType::Bitcoin => {
+                let Some(sats_per_vbyte) = fee_rates.bitcoin_sats_per_vbyte else {
+                    return RFQResult::MakerUnavailable("Bitcoin fee rate unavailable".to_string());
+                };
+                // Sized by the inputs the wallet would actually spend on this fill
+                let vbytes = self
+                    .bitcoin_wallet
+                    .estimate_fill_vbytes(amount, sats_per_vbyte)
+                    .await;
+
+                calculate_fees_in_sats_to_send_btc(sats_per_vbyte, vbytes)
+            }
+            ChainType::Ethereum => match fee_rates.ethereum.get(&quote_request.to.network()) {
+                Some(Ok(rates)) => match calculate_fees_in_sats_to_send_cbbtc_on_eth(
+                    rates.base_fee_gwei,
+                    rates.max_priority_fee_gwei,
+                    rates.eth_per_btc_price,
+                ) {
+                    Ok(fee) => fee,
+                    Err(reason) => return RFQResult::MakerUnavailable(reason),
+                },
+                Some(Err(reason)) => return RFQResult::MakerUnavailable(reason.clone()),
+                None => {
+                    return RFQResult::MakerUnavailable(
+                        "Ethereum fee rates unavailable".to_string(),
                     )
                 }
-            }
+            },
         };
 
         let quote_id = Uuid::new_v4();
+        let now = self.clock.now();
+        if self.simulation {
+            warn!(
+                "SIMULATION MODE: quote {} is priced from a fixed BTC/ETH price and fixed fees, not the market",
+                quote_id
+            );
+        }
         match quote_request.mode {
             QuoteMode::ExactInput => {
-                let quote_result =
-                    quote_exact_input(amount, send_fees_in_sats, self.trade_spread_bps);
+                let quote_result = quote_exact_input(
+                    amount,
+                    send_fees_in_sats,
+                    max_dust_threshold(quote_request.to.chain),
+                    pricing.trade_spread_bps,
+                    &self.protocol_fee,
+                );
 
                 match quote_result {
-                    RFQResult::Success((rx_btc, fees)) => Ok(RFQResult::Success(QuoteWithFees {
+                    RFQResult::Success((rx_btc, fees)) => RFQResult::Success(QuoteWithFees {
                         quote: Quote {
                             id: quote_id,
                             market_maker_id,
@@ -154,83 +414,184 @@ impl WrappedBitcoinQuoter {
                             },
                             to: Lot {
                                 currency: quote_request.to.clone(),
-                                amount: U256::from(rx_btc),
+                                amount: from_sats(rx_btc, fillable.to_decimals, false),
                             },
-                            expires_at: Utc::now() + QUOTE_EXPIRATION_TIME,
-                            created_at: Utc::now(),
+                            expires_at: now + QUOTE_EXPIRATION_TIME,
+                            created_at: now,
                         },
                         fees,
-                    })),
-                    RFQResult::MakerUnavailable(error) => Ok(RFQResult::MakerUnavailable(error)),
-                    RFQResult::InvalidRequest(error) => Ok(RFQResult::InvalidRequest(error)),
+                        signature: None,
+                    }),
+                    RFQResult::MakerUnavailable(error) => RFQResult::MakerUnavailable(error),
+                    RFQResult::InvalidRequest(error) => RFQResult::InvalidRequest(error),
                 }
             }
             QuoteMode::ExactOutput => {
-                let quote_result =
-                    quote_exact_output(amount, send_fees_in_sats, self.trade_spread_bps);
+                let quote_result = quote_exact_output(
+                    amount,
+                    send_fees_in_sats,
+                    max_dust_threshold(quote_request.to.chain),
+                    max_dust_threshold(quote_request.from.chain),
+                    pricing.trade_spread_bps,
+                    &self.protocol_fee,
+                );
                 match quote_result {
-                    RFQResult::Success((tx_btc, fees)) => Ok(RFQResult::Success(QuoteWithFees {
+                    RFQResult::Success((tx_btc, fees)) => RFQResult::Success(QuoteWithFees {
                         quote: Quote {
                             id: quote_id,
                             market_maker_id,
                             from: Lot {
                                 currency: quote_request.from.clone(),
-                                amount: U256::from(tx_btc),
+                                amount: from_sats(tx_btc, fillable.from_decimals, true),
                             },
                             to: Lot {
                                 currency: quote_request.to.clone(),
                                 amount: quote_request.amount,
                             },
-                            expires_at: Utc::now() + QUOTE_EXPIRATION_TIME,
-                            created_at: Utc::now(),
+                            expires_at: now + QUOTE_EXPIRATION_TIME,
+                            created_at: now,
                         },
                         fees,
-                    })),
-                    RFQResult::MakerUnavailable(error) => Ok(RFQResult::MakerUnavailable(error)),
-                    RFQResult::InvalidRequest(error) => Ok(RFQResult::InvalidRequest(error)),
+                        signature: None,
+                    }),
+                    RFQResult::MakerUnavailable(error) => RFQResult::MakerUnavailable(error),
+                    RFQResult::InvalidRequest(error) => RFQResult::InvalidRequest(error),
                 }
             }
         }
     }
 }
 
-fn is_fillable_request(quote_request: &QuoteRequest) -> Option<String> {
+/// What the network fee of a fill is priced from, per chain the quotes fill on
+#[derive(Debug, Default)]
+struct FeeRates {
+    /// Sats per vbyte with the safety multiplier applied, when filling on bitcoin
+    bitcoin_sats_per_vbyte: Option<f64>,
+    /// Per EVM network filled on, or why they couldn't be fetched
+    ethereum: HashMap<ChainNetwork, Result<EthereumFeeRates, String>>,
+}
+
+impl FeeRates {
+    /// What a fill on `network` is priced from
+    fn inputs(&self, network: ChainNetwork) -> PricingInputs {
+        match network.chain {
+            ChainType::Bitcoin => PricingInputs {
+                sats_per_vbyte: self.bitcoin_sats_per_vbyte,
+                eth_per_btc: None,
+            },
+            ChainType::Ethereum => PricingInputs {
+                sats_per_vbyte: None,
+                eth_per_btc: self
+                    .ethereum
+                    .get(&network)
+                    .and_then(|rates| rates.as_ref().ok())
+                    .map(|rates| rates.eth_per_btc_price),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EthereumFeeRates {
+    base_fee_gwei: f64,
+    max_priority_fee_gwei: f64,
+    eth_per_btc_price: f64,
+}
+
+/// Only bitcoin-denominated tokens are quoted, priced 1:1 in sats whatever
+/// decimals each is configured with
+const SATS_DECIMALS: u8 = 8;
+
+/// A request that can be quoted, with the decimals each leg is configured with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FillableRequest {
+    /// The requested amount in sats
+    amount_sats: u64,
+    from_decimals: u8,
+    to_decimals: u8,
+}
+
+fn is_fillable_request(
+    quote_request: &QuoteRequest,
+    supported_currencies: &SupportedCurrencies,
+) -> Result<FillableRequest, String> {
     if quote_request.from.chain == quote_request.to.chain {
         info!("Invalid chain selection: {:?}", quote_request);
-        return Some("From and to chains cannot be the same".to_string());
-    }
-    match constants::SUPPORTED_TOKENS_BY_CHAIN.get(&quote_request.from.chain) {
-        Some(supported_tokens) => {
-            if !supported_tokens.contains(&quote_request.from.token) {
-                return Some("Invalid send token".to_string());
-            }
-        }
-        None => {
-            return Some("Invalid send chain".to_string());
-        }
+        return Err("From and to chains cannot be the same".to_string());
     }
+    let from_decimals = supported_currencies
+        .check_currency(&quote_request.from)
+        .map_err(|e| format!("Invalid send token: {e}"))?
+        .decimals;
+    let to_decimals = supported_currencies
+        .check_currency(&quote_request.to)
+        .map_err(|e| format!("Invalid receive token: {e}"))?
+        .decimals;
+
+    // The requested amount is on the send side for exact input, the receive side otherwise
+    let (currency, decimals) = match quote_request.mode {
+        QuoteMode::ExactInput => (quote_request.from.clone(), from_decimals),
+        QuoteMode::ExactOutput => (quote_request.to.clone(), to_decimals),
+    };
+    let requested = Lot {
+        currency,
+        amount: quote_request.amount,
+    };
+    supported_currencies
+        .check_lot(&requested)
+        .map_err(|e| e.to_string())?;
+
+    Ok(FillableRequest {
+        amount_sats: to_sats(quote_request.amount, decimals)?,
+        from_decimals,
+        to_decimals,
+    })
+}
 
-    match constants::SUPPORTED_TOKENS_BY_CHAIN.get(&quote_request.to.chain) {
-        Some(supported_tokens) => {
-            if !supported_tokens.contains(&quote_request.to.token) {
-                return Some("Invalid receive token".to_string());
+/// `amount` of a token with `decimals` in sats, refused unless it's a whole
+/// number of sats that fits a `u64`
+fn to_sats(amount: U256, decimals: u8) -> Result<u64, String> {
+    let sats = match decimals.checked_sub(SATS_DECIMALS) {
+        Some(extra) => {
+            let per_sat = U256::from(10u64).pow(U256::from(extra));
+            if !(amount % per_sat).is_zero() {
+                return Err("Amount must be a whole number of sats".to_string());
             }
+            amount / per_sat
         }
+        None => amount
+            .checked_mul(U256::from(10u64).pow(U256::from(SATS_DECIMALS - decimals)))
+            .ok_or_else(|| "Amount too large".to_string())?,
+    };
+    u64::try_from(sats).map_err(|_| "Amount too large".to_string())
+}
+
+/// `sats` in units of a token with `decimals`. A token coarser than a sat
+/// rounds down, or up when `round_up`
+fn from_sats(sats: u64, decimals: u8, round_up: bool) -> U256 {
+    let sats = U256::from(sats);
+    match decimals.checked_sub(SATS_DECIMALS) {
+        Some(extra) => sats * U256::from(10u64).pow(U256::from(extra)),
         None => {
-            return Some("Invalid receive chain".to_string());
+            let sats_per_unit = U256::from(10u64).pow(U256::from(SATS_DECIMALS - decimals));
+            let units = sats / sats_per_unit;
+            if round_up && !(sats % sats_per_unit).is_zero() {
+                units + U256::from(1u64)
+            } else {
+                units
+            }
         }
     }
-
-    None
 }
 
-/// P2PKH is the MOST expensive address to send BTC to, dust limit wise, so we use this as our minimum
-const MIN_DUST_SATS: u64 = 546;
-
+/// `received_dust_sats` is the dust threshold of the chain the user receives
+/// on. The destination isn't known at quote time so it's the costliest one's
 fn quote_exact_input(
     sent_sats: u64,
     fee_sats: u64,
+    received_dust_sats: u64,
     trade_spread_bps: u64,
+    protocol_fee_params: &ProtocolFeeParams,
 ) -> RFQResult<(u64, FeeSchedule)> {
     const BPS_DENOM: u64 = 10_000;
 
@@ -248,10 +609,11 @@ fn quote_exact_input(
 
     let rx_after_network_fee = rx_before_fees.saturating_sub(network_fee);
 
-    let protocol_fee = compute_protocol_fee_sats(rx_after_network_fee);
+    let protocol_fee = protocol_fee_params.compute_fee_sats(rx_after_network_fee);
     let final_rx = rx_after_network_fee.saturating_sub(protocol_fee);
 
-    if final_rx <= MIN_DUST_SATS {
+    // Never quote a payout of nothing, even where there's no dust limit
+    if final_rx < received_dust_sats.max(1) {
         return RFQResult::InvalidRequest("Amount out too low net of fees".to_string());
     }
 
@@ -265,14 +627,18 @@ fn quote_exact_input(
     ))
 }
 
+/// The dust thresholds are those of the chains the user receives and sends on
 fn quote_exact_output(
     received_sats: u64,
     network_fee_sats: u64,
+    received_dust_sats: u64,
+    sent_dust_sats: u64,
     trade_spread_bps: u64,
+    protocol_fee_params: &ProtocolFeeParams,
 ) -> RFQResult<(u64, FeeSchedule)> {
     const BPS_DENOM: u64 = 10_000;
 
-    if received_sats < MIN_DUST_SATS {
+    if received_sats < received_dust_sats.max(1) {
         return RFQResult::InvalidRequest("Amount out too low".to_string());
     }
 
@@ -281,7 +647,7 @@ fn quote_exact_output(
         return RFQResult::MakerUnavailable("Profit spread is >= 100%".to_string());
     }
 
-    let rx_after_protocol_fee = inverse_compute_protocol_fee(received_sats);
+    let rx_after_protocol_fee = protocol_fee_params.inverse_compute_fee(received_sats);
     let protocol_fee = rx_after_protocol_fee - received_sats;
 
     let rx_after_fees = rx_after_protocol_fee.saturating_add(network_fee_sats);
@@ -292,7 +658,7 @@ fn quote_exact_output(
 
     let liquidity_fee = tx - rx_after_fees;
 
-    if tx < MIN_DUST_SATS {
+    if tx < sent_dust_sats {
         return RFQResult::InvalidRequest("Amount out too low net of fees".to_string());
     }
 
@@ -306,29 +672,33 @@ fn quote_exact_output(
     ))
 }
 
-// TODO(gpt-ignore): This should be computed by the wallet
-fn calculate_fees_in_sats_to_send_btc(sats_per_vbyte: f64) -> u64 {
-    let vbytes = 199.0; // 3 p2wpkh outputs, 1 op return w/ 16 bytes, 1 p2wpkh input (napkin math)
-    let fee = sats_per_vbyte * vbytes;
+fn calculate_fees_in_sats_to_send_btc(sats_per_vbyte: f64, vbytes: u64) -> u64 {
+    let fee = sats_per_vbyte * vbytes as f64;
     fee.ceil() as u64
 }
 
 // TODO: This should be computed by the wallet?
+/// Refused when the BTC/ETH price rounds to nothing or the fee doesn't fit a `u64`
 fn calculate_fees_in_sats_to_send_cbbtc_on_eth(
     base_fee_gwei: f64,
     max_priority_fee_gwei: f64,
     eth_per_btc_price: f64,
-) -> u64 {
+) -> Result<u64, String> {
     // This is the gas cost to use disperse.app on ethereum mainnet w/ 2 addresses as recipients reference: https://etherscan.io/tx/0x22d7b1141273fb60ded7a910da4eb4492fd349abe927b6d1961afa7759d25644
     let transfer_gas_limit = 98_722f64;
     let gas_cost_gwei = transfer_gas_limit * (max_priority_fee_gwei + base_fee_gwei);
     let gas_cost_wei = U256::from(gas_cost_gwei.ceil() as u64) * U256::from(1e9);
     let wei_per_sat = U256::from((eth_per_btc_price * 1e10).round() as u128);
-    (gas_cost_wei / wei_per_sat).to::<u64>()
+    let fee_sats = gas_cost_wei
+        .checked_div(wei_per_sat)
+        .ok_or_else(|| "BTC/ETH price too low to quote".to_string())?;
+    u64::try_from(fee_sats).map_err(|_| "Ethereum network fee too large".to_string())
 }
 
 mod tests {
     use super::*;
+    use otc_chains::dust::MAX_BITCOIN_DUST_SATS;
+    use otc_models::{Currency, TokenIdentifier};
 
     const BASE_FEE_GWEI: f64 = 0.5;
     const MAX_PRIORITY_FEE_GWEI: f64 = 0.01;
@@ -341,9 +711,18 @@ mod tests {
         let user_input_sats = [1500, 2000, 10000, 30001, 1001001];
         for user_input_sats in user_input_sats {
             println!("user_input_sats: {user_input_sats}");
-            let fee_sats_to_send_btc = calculate_fees_in_sats_to_send_btc(SATS_PER_VBYTE);
+            let fee_sats_to_send_btc = calculate_fees_in_sats_to_send_btc(
+                SATS_PER_VBYTE,
+                crate::bitcoin_wallet::coin_selection::fill_vbytes(1),
+            );
             println!("fee_sats_to_send_btc: {fee_sats_to_send_btc}");
-            let output = quote_exact_input(user_input_sats, fee_sats_to_send_btc, TRADE_SPREAD_BPS);
+            let output = quote_exact_input(
+                user_input_sats,
+                fee_sats_to_send_btc,
+                MAX_BITCOIN_DUST_SATS,
+                TRADE_SPREAD_BPS,
+                &ProtocolFeeParams::DEFAULT,
+            );
             println!("output: {output:?}");
             let output = match output {
                 RFQResult::Success((rx_btc, fees)) => (rx_btc, fees),
@@ -352,7 +731,14 @@ mod tests {
                 }
             };
             assert_eq!(output.1.network_fee_sats, fee_sats_to_send_btc);
-            let input = quote_exact_output(output.0, output.1.network_fee_sats, TRADE_SPREAD_BPS);
+            let input = quote_exact_output(
+                output.0,
+                output.1.network_fee_sats,
+                MAX_BITCOIN_DUST_SATS,
+                0,
+                TRADE_SPREAD_BPS,
+                &ProtocolFeeParams::DEFAULT,
+            );
             println!("input: {input:?}");
             let input = match input {
                 RFQResult::Success((tx_btc, fees)) => (tx_btc, fees),
@@ -368,4 +754,213 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_ethereum_fees_out_of_range_are_refused() {
+        assert!(calculate_fees_in_sats_to_send_cbbtc_on_eth(
+            BASE_FEE_GWEI,
+            MAX_PRIORITY_FEE_GWEI,
+            ETH_PER_BTC
+        )
+        .is_ok());
+        // Rounds to 0 wei per sat
+        assert!(calculate_fees_in_sats_to_send_cbbtc_on_eth(
+            BASE_FEE_GWEI,
+            MAX_PRIORITY_FEE_GWEI,
+            1e-12
+        )
+        .is_err());
+        // Over 2^64 sats of gas
+        assert!(calculate_fees_in_sats_to_send_cbbtc_on_eth(1e12, 0.0, 1e-9).is_err());
+    }
+
+    #[test]
+    fn test_only_bitcoin_payouts_are_held_to_the_dust_limit() {
+        // 1000 sats in leaves 501 out after the network fee, with no spread or protocol fee
+        let quote = |received_dust_sats| {
+            quote_exact_input(
+                1000,
+                499,
+                received_dust_sats,
+                0,
+                &ProtocolFeeParams {
+                    bps: 0,
+                    min_sats: 0,
+                },
+            )
+        };
+        assert!(matches!(
+            quote(max_dust_threshold(ChainType::Bitcoin)),
+            RFQResult::InvalidRequest(_)
+        ));
+        assert!(matches!(
+            quote(max_dust_threshold(ChainType::Ethereum)),
+            RFQResult::Success((501, _))
+        ));
+    }
+
+    #[test]
+    fn test_legs_are_priced_in_sats_at_their_configured_decimals() {
+        let supported = SupportedCurrencies::from_toml(
+            r#"
+            [[currencies]]
+            chain = "bitcoin"
+            symbol = "BTC"
+            decimals = 8
+            min_amount = "10000"
+            max_amount = "10000000000"
+
+            [[currencies]]
+            chain = "ethereum"
+            address = "0x18084fbA666a33d37592fA2633fD49a74DD93a88"
+            symbol = "tBTC"
+            decimals = 18
+            min_amount = "100000000000000"
+            max_amount = "100000000000000000000"
+            "#,
+        )
+        .unwrap();
+        let btc = supported.all()[0].currency();
+        let tbtc = supported.all()[1].currency();
+        // 0.01 tBTC, or 1_000_000 sats
+        let one_hundredth_tbtc = U256::from(10u64).pow(U256::from(16u64));
+
+        let exact_input = QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            from: tbtc.clone(),
+            to: btc.clone(),
+            amount: one_hundredth_tbtc,
+        };
+        assert_eq!(
+            is_fillable_request(&exact_input, &supported),
+            Ok(FillableRequest {
+                amount_sats: 1_000_000,
+                from_decimals: 18,
+                to_decimals: 8,
+            })
+        );
+
+        let exact_output = QuoteRequest {
+            mode: QuoteMode::ExactOutput,
+            from: btc,
+            to: tbtc,
+            amount: one_hundredth_tbtc,
+        };
+        assert_eq!(
+            is_fillable_request(&exact_output, &supported).map(|fillable| fillable.amount_sats),
+            Ok(1_000_000)
+        );
+
+        // Less than a sat can't be priced
+        let dust = QuoteRequest {
+            amount: one_hundredth_tbtc + U256::from(1u64),
+            ..exact_input
+        };
+        assert!(is_fillable_request(&dust, &supported).is_err());
+
+        assert_eq!(
+            from_sats(1_000_000, 18, false),
+            U256::from(10u64).pow(U256::from(16u64))
+        );
+        assert_eq!(from_sats(1_000_000, 8, true), U256::from(1_000_000u64));
+        assert_eq!(from_sats(150, 6, false), U256::from(1u64));
+        assert_eq!(from_sats(150, 6, true), U256::from(2u64));
+        assert_eq!(to_sats(U256::from(2u64), 6), Ok(200));
+    }
+
+    /// Quoter at `pricing` that prices from fixed fees and a fixed BTC/ETH
+    /// price, with a wallet that is never synced
+    async fn quoter(pricing: Arc<ArcSwap<PricingConfig>>) -> WrappedBitcoinQuoter {
+        let bitcoin_wallet = BitcoinWallet::new(
+            ":memory:",
+            "wpkh(cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA)",
+            None,
+            bdk_wallet::bitcoin::Network::Regtest,
+            "http://127.0.0.1:1",
+            crate::bitcoin_wallet::BitcoinWalletSyncConfig::default(),
+            crate::bitcoin_wallet::DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+            crate::bitcoin_wallet::CoinSelectionConfig::default(),
+            crate::bitcoin_wallet::SignerConfig::Descriptor,
+            &mut tokio::task::JoinSet::new(),
+        )
+        .await
+        .unwrap();
+        WrappedBitcoinQuoter::new(
+            BitcoinEtherPriceOracle::fixed(crate::simulation::DEFAULT_SIMULATED_BTC_PER_ETH),
+            Arc::new(crate::simulation::FixedFeeEstimator),
+            Arc::new(bitcoin_wallet),
+            pricing,
+            ProtocolFeeParams::DEFAULT,
+            Arc::new(SupportedCurrencies::default()),
+            Arc::new(common::SystemClock),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_next_quote_uses_the_reloaded_spread() {
+        let path = std::env::temp_dir().join(format!("mm-pricing-{}.json", Uuid::new_v4()));
+        let path = path.to_string_lossy().to_string();
+        let write_spread = |bps: u64| {
+            std::fs::write(
+                &path,
+                format!(r#"{{"trade_spread_bps": {bps}, "fee_safety_multiplier": 1.5}}"#),
+            )
+            .unwrap();
+        };
+        let request = QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            from: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            to: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Address(otc_models::CBBTC_ADDRESS.to_string()),
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(1_000_000u64),
+        };
+
+        write_spread(TRADE_SPREAD_BPS);
+        let pricing = Arc::new(ArcSwap::from_pointee(
+            crate::config::load_pricing_file(&path, false).unwrap(),
+        ));
+        let quoter = quoter(pricing.clone()).await;
+        let next_quote = || async {
+            match quoter
+                .compute_quote(Uuid::new_v4(), &request)
+                .await
+                .unwrap()
+            {
+                RFQResult::Success(quote) => quote,
+                other => panic!("Failed to quote: {other:?}"),
+            }
+        };
+        let before = next_quote().await;
+
+        write_spread(200);
+        crate::config::reload_pricing(&pricing, &path, false).unwrap();
+        let after = next_quote().await;
+
+        assert!(
+            after.quote.to.amount < before.quote.to.amount,
+            "{} should be under {}",
+            after.quote.to.amount,
+            before.quote.to.amount
+        );
+        let RFQResult::Success((rx_btc, fees)) = quote_exact_input(
+            1_000_000,
+            after.fees.network_fee_sats,
+            max_dust_threshold(ChainType::Ethereum),
+            200,
+            &ProtocolFeeParams::DEFAULT,
+        ) else {
+            panic!("Failed to quote at 200 bps");
+        };
+        assert_eq!(after.quote.to.amount, U256::from(rx_btc));
+        assert_eq!(after.fees.liquidity_fee_sats, fees.liquidity_fee_sats);
+    }
 }
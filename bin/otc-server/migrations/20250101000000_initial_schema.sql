@@ -10,27 +10,34 @@ CREATE TYPE swap_status AS ENUM (
     'waiting_user_deposit_confirmed',
     'waiting_mm_deposit_initiated',
     'waiting_mm_deposit_confirmed',
+    'mm_deposit_amount_mismatch',
     'settled',
+    'manual_review',
     'refunding_user',
     'refunding_mm',
     'failed'
 );
 
+-- Length caps on user-supplied columns mirror otc_models::validation
+
 -- Create quotes table
 CREATE TABLE quotes (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
     
     -- From currency details (what user sends)
     from_chain VARCHAR(50) NOT NULL,
-    from_token JSONB NOT NULL,
-    from_amount TEXT NOT NULL, -- U256 stored as string
+    from_token JSONB NOT NULL CHECK (octet_length(from_token::text) <= 256),
+    from_amount VARCHAR(78) NOT NULL, -- U256 stored as string
     from_decimals SMALLINT NOT NULL,
+    -- Set for an EVM network other than the chain's primary one
+    from_chain_id BIGINT,
     
     -- To currency details (what user receives)
     to_chain VARCHAR(50) NOT NULL,
-    to_token JSONB NOT NULL,
-    to_amount TEXT NOT NULL, -- U256 stored as string
+    to_token JSONB NOT NULL CHECK (octet_length(to_token::text) <= 256),
+    to_amount VARCHAR(78) NOT NULL, -- U256 stored as string
     to_decimals SMALLINT NOT NULL,
+    to_chain_id BIGINT,
     
     market_maker_id UUID NOT NULL,
     expires_at TIMESTAMPTZ NOT NULL,
@@ -45,53 +52,200 @@ CREATE TABLE swaps (
     
     -- Salt and nonce columns for deterministic wallet generation
     user_deposit_salt BYTEA NOT NULL,
-    user_deposit_address VARCHAR(255) NOT NULL,
+    user_deposit_address VARCHAR(90) NOT NULL,
+    master_key_version INTEGER NOT NULL CHECK (master_key_version > 0),
     mm_nonce BYTEA NOT NULL,
     
     -- User addresses
-    user_destination_address VARCHAR(255) NOT NULL,
-    user_evm_account_address VARCHAR(255) NOT NULL,
+    user_destination_address VARCHAR(90) NOT NULL,
+    user_evm_account_address VARCHAR(42) NOT NULL,
+    -- Where the user deposit is refunded, set by the user until it's confirmed
+    user_refund_address VARCHAR(90),
     
     -- Core status using enum
     status swap_status NOT NULL DEFAULT 'waiting_user_deposit_initiated',
     
+    -- Deposit finality resolved at creation (baseline or active override)
+    user_required_confirmations INTEGER NOT NULL CHECK (user_required_confirmations > 0),
+    mm_required_confirmations INTEGER NOT NULL CHECK (mm_required_confirmations > 0),
+    
     -- Deposit tracking (JSONB for rich data)
-    user_deposit_status JSONB,
-    mm_deposit_status JSONB,
+    user_deposit_status JSONB CHECK (octet_length(user_deposit_status::text) <= 2048),
+    mm_deposit_status JSONB CHECK (octet_length(mm_deposit_status::text) <= 2048),
     
     -- Settlement tracking
-    settlement_status JSONB,
+    settlement_status JSONB CHECK (octet_length(settlement_status::text) <= 2048),
     
     -- Failure tracking
-    failure_reason TEXT,
+    failure_reason VARCHAR(256),
     failure_at TIMESTAMPTZ,
     
     -- MM coordination
     mm_notified_at TIMESTAMPTZ,
+    mm_deposit_detected_at TIMESTAMPTZ,
     mm_private_key_sent_at TIMESTAMPTZ,
     
+    -- What the MM reported for its deposit, kept to reconcile against the chain
+    mm_claimed_tx_hash VARCHAR(128),
+    mm_claimed_fee VARCHAR(78), -- U256 stored as string, in the chain's native currency
+    mm_claimed_fill_cost JSONB CHECK (octet_length(mm_claimed_fill_cost::text) <= 1024),
+    mm_claimed_at TIMESTAMPTZ,
+    
+    -- Times the MM was asked to pay again after its deposit failed on chain
+    mm_deposit_retries INTEGER NOT NULL DEFAULT 0 CHECK (mm_deposit_retries >= 0),
+    mm_deposit_retry_requested_at TIMESTAMPTZ,
+    
+    -- Estimated network fee of refunding the user and what is left to send back
+    user_refund_fee VARCHAR(78), -- U256 stored as string, in the chain's native currency
+    user_refund_amount VARCHAR(78), -- U256 stored as string
+    
+    -- Protocol fee charged on the MM's payout, fixed when the swap is created
+    protocol_fee_bps INTEGER NOT NULL CHECK (protocol_fee_bps >= 0),
+    protocol_fee VARCHAR(78), -- U256 stored as string, NULL past amounts the fee math takes
+    
+    -- Correlation id of the quote request, shared with the RFQ server and MM logs
+    trace_id VARCHAR(128),
+    
+    -- The user's own reference for the swap, opaque to the server
+    external_reference VARCHAR(128),
+    
+    -- Bumped on every write, updates only apply on top of the version they read
+    version BIGINT NOT NULL DEFAULT 0,
+    
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
+-- Append-only log of swap status changes, written in the same transaction as the swap row
+CREATE TABLE swap_events (
+    id BIGSERIAL PRIMARY KEY,
+    swap_id UUID NOT NULL REFERENCES swaps(id),
+    from_status swap_status,
+    to_status swap_status NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Set when the change isn't the usual step, e.g. a reorg rolling the swap back
+    note VARCHAR(256)
+);
+
+-- Operator issued confirmation overrides; every row is kept as the audit trail
+-- and the most recent unexpired row per chain is the active override
+CREATE TABLE confirmation_overrides (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    chain VARCHAR(50) NOT NULL,
+    
+    -- Exactly one of multiplier / absolute is set, as requested by the operator
+    multiplier DOUBLE PRECISION,
+    absolute_confirmations INTEGER,
+    CHECK ((multiplier IS NULL) <> (absolute_confirmations IS NULL)),
+    
+    -- Snapshot of the resolution at the time the override was issued
+    baseline_confirmations INTEGER NOT NULL,
+    required_confirmations INTEGER NOT NULL CHECK (required_confirmations >= baseline_confirmations),
+    
+    reason VARCHAR(256) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Idempotency-Key header values seen on POST /api/v1/swaps. A row is claimed
+-- before the swap is created (response still NULL) and completed with the
+-- response replayed to retries with the same key.
+CREATE TABLE swap_idempotency_keys (
+    idempotency_key VARCHAR(255) PRIMARY KEY,
+    request_hash BYTEA NOT NULL,
+    swap_id UUID REFERENCES swaps(id),
+    response JSONB,
+    CHECK ((swap_id IS NULL) = (response IS NULL)),
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Every time a market maker was asked to validate a quote and how it answered,
+-- feeding the per market maker statistics
+CREATE TABLE mm_quote_validations (
+    id BIGSERIAL PRIMARY KEY,
+    market_maker_id UUID NOT NULL,
+    quote_id UUID NOT NULL,
+    outcome VARCHAR(16) NOT NULL CHECK (outcome IN ('accepted', 'rejected', 'unanswered')),
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Mismatches between terminal swaps and the chains seen by the settlement
+-- reconciliation job. Nothing is corrected automatically: a finding stays open
+-- until a later run no longer sees it, and rows are kept as the audit trail
+CREATE TABLE reconciliation_findings (
+    id BIGSERIAL PRIMARY KEY,
+    swap_id UUID NOT NULL REFERENCES swaps(id),
+    swap_status swap_status NOT NULL,
+    kind VARCHAR(32) NOT NULL CHECK (kind IN ('tx_missing', 'amount_differs', 'unexpected_balance')),
+    leg VARCHAR(16) NOT NULL CHECK (leg IN ('user_deposit', 'mm_deposit')),
+    chain VARCHAR(50) NOT NULL,
+    tx_hash VARCHAR(128),
+    detail VARCHAR(512) NOT NULL,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
 -- Create indexes for efficient queries
 CREATE INDEX idx_quotes_market_maker ON quotes(market_maker_id);
 CREATE INDEX idx_quotes_expires_at ON quotes(expires_at);
+CREATE INDEX idx_quotes_created_at ON quotes(created_at);
+
+CREATE INDEX idx_confirmation_overrides_chain ON confirmation_overrides(chain, created_at DESC);
 
 CREATE INDEX idx_swaps_quote_id ON swaps(quote_id);
 CREATE INDEX idx_swaps_market_maker ON swaps(market_maker_id);
 CREATE INDEX idx_swaps_status ON swaps(status);
+CREATE INDEX idx_swaps_created_at ON swaps(created_at, id);
+
+-- Indexes for user lookups, matched case-insensitively and tx hashes without 0x.
+-- The deposit address one is unique, funds sent to an address shared by two
+-- swaps can't be attributed
+CREATE UNIQUE INDEX idx_swaps_user_deposit_address ON swaps(lower(user_deposit_address));
+-- The MM nonce tags the fill, a nonce shared by two swaps would let one
+-- payment settle both
+CREATE UNIQUE INDEX idx_swaps_mm_nonce ON swaps(mm_nonce);
+CREATE INDEX idx_swaps_user_deposit_tx_hash
+ON swaps(regexp_replace(lower(user_deposit_status->>'tx_hash'), '^0x', ''));
+CREATE INDEX idx_swaps_mm_deposit_tx_hash
+ON swaps(regexp_replace(lower(mm_deposit_status->>'tx_hash'), '^0x', ''));
+-- A batched fill pays several swaps in one transaction, each to its own
+-- destination. Two swaps claiming the same payment would both settle
+CREATE UNIQUE INDEX idx_swaps_mm_fill
+ON swaps(
+    regexp_replace(lower(mm_deposit_status->>'tx_hash'), '^0x', ''),
+    lower(user_destination_address)
+)
+WHERE mm_deposit_status IS NOT NULL;
+CREATE INDEX idx_swaps_external_reference ON swaps(external_reference)
+WHERE external_reference IS NOT NULL;
+
+CREATE INDEX idx_swap_events_swap ON swap_events(swap_id, id);
+
+CREATE INDEX idx_mm_quote_validations_market_maker
+ON mm_quote_validations(market_maker_id, requested_at);
+
+CREATE INDEX idx_swap_idempotency_keys_expires_at ON swap_idempotency_keys(expires_at);
+
+-- One open finding per swap, kind and deposit
+CREATE UNIQUE INDEX idx_reconciliation_findings_open
+ON reconciliation_findings(swap_id, kind, leg) WHERE resolved_at IS NULL;
 
 -- Indexes for monitoring active swaps
 CREATE INDEX idx_swaps_active ON swaps(status) 
-WHERE status NOT IN ('settled', 'failed');
+WHERE status NOT IN ('settled', 'manual_review', 'failed');
 
 CREATE INDEX idx_swaps_failure ON swaps(failure_at)
 WHERE failure_at IS NOT NULL;
 
+-- Terminal swaps sampled by the settlement reconciliation job
+CREATE INDEX idx_swaps_terminal ON swaps(updated_at)
+WHERE status IN ('settled', 'failed');
+
 -- Combined index for market maker queries
 CREATE INDEX idx_swaps_market_maker_active ON swaps(market_maker_id, status)
-WHERE status NOT IN ('settled', 'failed');
+WHERE status NOT IN ('settled', 'manual_review', 'failed');
 
 -- Create update trigger for updated_at
 CREATE OR REPLACE FUNCTION update_updated_at_column()
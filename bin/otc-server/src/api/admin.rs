@@ -0,0 +1,243 @@
+use crate::db::{
+    FillCosts, FillLatency, MarketMakerStats, ReconciliationFinding, SwapCounts, ValidationCounts,
+};
+use chrono::{DateTime, Duration, Utc};
+use otc_models::{
+    apply, sanitize_text, ChainType, ConfirmationRule, FieldError, SwapStatus, Validate,
+    MAX_REASON_LEN,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Request for POST /admin/chains/:chain/confirmation-override
+///
+/// Exactly one of `multiplier` and `absolute` must be set.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct SetConfirmationOverrideRequest {
+    /// Scale the chain's baseline confirmations (rounded up)
+    #[serde(default)]
+    pub multiplier: Option<f64>,
+
+    /// Require exactly this many confirmations
+    #[serde(default)]
+    pub absolute: Option<u64>,
+
+    /// When the override stops applying to new swaps
+    pub expires_at: DateTime<Utc>,
+
+    /// Operator justification, kept in the audit trail
+    pub reason: String,
+}
+
+impl SetConfirmationOverrideRequest {
+    /// The rule described by the request, only meaningful once validated
+    #[must_use]
+    pub fn rule(&self) -> Option<ConfirmationRule> {
+        match (self.multiplier, self.absolute) {
+            (Some(multiplier), None) => Some(ConfirmationRule::Multiplier(multiplier)),
+            (None, Some(absolute)) => Some(ConfirmationRule::Absolute(absolute)),
+            _ => None,
+        }
+    }
+}
+
+impl Validate for SetConfirmationOverrideRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let result = sanitize_text("reason", &self.reason, MAX_REASON_LEN);
+        apply(&mut errors, &mut self.reason, result);
+
+        if self.rule().is_none() {
+            errors.push(FieldError::new(
+                "multiplier",
+                "exactly one of multiplier or absolute must be set",
+            ));
+        }
+        if let Some(multiplier) = self.multiplier {
+            if !multiplier.is_finite() {
+                errors.push(FieldError::new("multiplier", "must be a finite number"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Request for POST /admin/swaps/:id/cancel
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CancelSwapRequest {
+    /// Operator justification, kept in the audit trail
+    pub reason: String,
+}
+
+impl Validate for CancelSwapRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let result = sanitize_text("reason", &self.reason, MAX_REASON_LEN);
+        apply(&mut errors, &mut self.reason, result);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Query for GET /admin/swaps
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminSwapsQuery {
+    /// Matched exactly against the reference given when the swap was created
+    pub external_reference: String,
+}
+
+/// Response for GET and POST /admin/master-keys
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MasterKeysResponse {
+    /// Version new swaps derive their deposit wallets with
+    pub current_version: u32,
+    pub keys: Vec<MasterKeyInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MasterKeyInfo {
+    pub version: u32,
+
+    /// Swaps not yet settled or failed, or failed holding the user's deposit, that
+    /// still derive their wallet from this key.
+    /// A retired key can only be removed once this reaches zero.
+    pub active_swaps: u64,
+}
+
+/// Response for GET /admin/reconciliation/findings
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReconciliationFindingsResponse {
+    /// Open mismatches between terminal swaps and the chains, newest first
+    pub findings: Vec<ReconciliationFinding>,
+}
+
+/// Response for GET /admin/v1/summary, assembled at `generated_at` and served
+/// as is for a few seconds after
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AdminSummaryResponse {
+    pub generated_at: DateTime<Utc>,
+    /// Every status in lifecycle order, with zero counts included
+    pub swaps_by_status: Vec<SwapStatusCount>,
+    /// The earliest created swap in each active status that has any
+    pub oldest_active_swaps: Vec<OldestActiveSwap>,
+    pub connected_market_makers: usize,
+    /// Messages queued for market makers and not yet written to their sockets
+    pub mm_notification_backlog: usize,
+    /// Chains monitoring has passed over so far
+    pub chains: Vec<ChainMonitoringHealth>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SwapStatusCount {
+    pub status: SwapStatus,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct OldestActiveSwap {
+    pub status: SwapStatus,
+    pub swap_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    /// Whole seconds from `created_at` to the summary's `generated_at`
+    pub age_seconds: u64,
+}
+
+/// How monitoring of a chain keeps up, as of its latest pass
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ChainMonitoringHealth {
+    pub chain: ChainType,
+    /// The latest pass succeeded and the next one isn't overdue
+    pub healthy: bool,
+    pub interval_seconds: u64,
+    pub last_pass_at: DateTime<Utc>,
+    pub last_pass_duration_ms: u64,
+    /// Swaps the latest pass checked, `None` if it failed
+    pub last_pass_swaps: Option<usize>,
+    /// Why the latest pass failed
+    pub last_pass_error: Option<String>,
+}
+
+/// Lookback of a market maker statistics window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum StatsWindow {
+    #[serde(rename = "24h")]
+    Day,
+    #[serde(rename = "7d")]
+    Week,
+    #[serde(rename = "30d")]
+    Month,
+}
+
+impl StatsWindow {
+    pub const ALL: [Self; 3] = [Self::Day, Self::Week, Self::Month];
+
+    #[must_use]
+    pub fn duration(self) -> Duration {
+        match self {
+            Self::Day => Duration::hours(24),
+            Self::Week => Duration::days(7),
+            Self::Month => Duration::days(30),
+        }
+    }
+}
+
+/// Query for GET /api/v1/market-makers/:id/stats, every window when unset
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketMakerStatsQuery {
+    pub window: Option<StatsWindow>,
+}
+
+/// Response for GET /api/v1/market-makers/:id/stats
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MarketMakerStatsResponse {
+    pub market_maker_id: Uuid,
+    pub windows: Vec<MarketMakerWindowStats>,
+}
+
+/// What a market maker did with the quotes and swaps of one window. How often
+/// it answers RFQs and how often its quotes win aren't part of it: the OTC
+/// server only sees the quotes swaps are created from, never the RFQ traffic
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MarketMakerWindowStats {
+    pub window: StatsWindow,
+    pub since: DateTime<Utc>,
+    pub validations: ValidationCounts,
+    /// Share of validation requests the MM accepted, `None` without requests
+    pub acceptance_rate: Option<f64>,
+    pub swaps: SwapCounts,
+    /// Share of fills that ended with a refund, `None` without fills
+    pub refund_rate: Option<f64>,
+    pub fill_latency: FillLatency,
+    pub fill_costs: FillCosts,
+}
+
+impl MarketMakerWindowStats {
+    #[must_use]
+    pub fn new(window: StatsWindow, since: DateTime<Utc>, stats: MarketMakerStats) -> Self {
+        let rate = |part: u64, whole: u64| (whole > 0).then(|| part as f64 / whole as f64);
+        Self {
+            window,
+            since,
+            acceptance_rate: rate(stats.validations.accepted, stats.validations.requested),
+            validations: stats.validations,
+            refund_rate: rate(stats.swaps.refunded_fills, stats.swaps.fills),
+            swaps: stats.swaps,
+            fill_latency: stats.fill_latency,
+            fill_costs: stats.fill_costs,
+        }
+    }
+}
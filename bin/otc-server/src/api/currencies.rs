@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use otc_models::{ChainType, SupportedCurrency};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Response for GET /api/v1/currencies
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CurrenciesResponse {
+    pub chains: Vec<ChainCurrencyResponse>,
+}
+
+/// Tokens and deposit finality a new swap on this chain would get right now
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChainCurrencyResponse {
+    pub chain: ChainType,
+
+    /// Confirmations a deposit made now needs
+    pub required_confirmations: u64,
+
+    /// Confirmations required when no override is active
+    pub baseline_confirmations: u64,
+
+    /// Rough time for a deposit to reach `required_confirmations`
+    pub estimated_confirmation_seconds: u64,
+
+    /// Set while an operator override raises the requirement above the baseline
+    pub confirmation_override_expires_at: Option<DateTime<Utc>>,
+
+    /// Tokens accepted on this chain, on any of its networks, with their deposit bounds
+    pub tokens: Vec<SupportedCurrency>,
+}
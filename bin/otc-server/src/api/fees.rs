@@ -0,0 +1,224 @@
+use alloy::primitives::U256;
+use chrono::{DateTime, Utc};
+use otc_chains::NetworkFees;
+use otc_models::ChainType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::services::fee_telemetry::ChainFees;
+
+/// Virtual size of a one input, two output P2WPKH transaction
+pub const STANDARD_TX_VBYTES: u64 = 141;
+
+/// Rate assumed when the fee backend returns no estimates, Bitcoin Core's
+/// minimum relay fee
+const FALLBACK_FEE_RATE_SAT_PER_VB: f64 = 1.0;
+
+/// Response for GET /api/v1/chains/{chain}/fees
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChainFeesResponse {
+    pub chain: ChainType,
+
+    /// When the fees were fetched from the chain backend. Responses are
+    /// cached for a few seconds, so this can be slightly in the past
+    pub fetched_at: DateTime<Utc>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitcoin: Option<BitcoinFeesResponse>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ethereum: Option<EthereumFeesResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BitcoinFeesResponse {
+    /// Fee rate in sat/vB expected to confirm within each number of blocks
+    pub fee_estimates: BTreeMap<u16, f64>,
+
+    /// Median of `fee_estimates`
+    pub median_fee_rate_sat_per_vb: f64,
+
+    /// Blocks a transaction paying the median rate is expected to wait
+    pub estimated_confirmation_blocks: u16,
+
+    /// `estimated_confirmation_blocks` in seconds at the usual block time
+    pub estimated_confirmation_seconds: u64,
+
+    /// Fee of a standard one input, two output transaction at the median rate
+    pub standard_tx_fee_sats: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EthereumFeesResponse {
+    /// Base fee of the next block, in wei per gas
+    #[schema(value_type = otc_models::U256Schema)]
+    pub next_base_fee_wei: U256,
+
+    /// Suggested priority fees in wei per gas, from the 25th, 50th and 75th
+    /// percentile tips of recent blocks
+    #[schema(value_type = otc_models::U256Schema)]
+    pub priority_fee_low_wei: U256,
+    #[schema(value_type = otc_models::U256Schema)]
+    pub priority_fee_medium_wei: U256,
+    #[schema(value_type = otc_models::U256Schema)]
+    pub priority_fee_high_wei: U256,
+}
+
+impl From<&ChainFees> for ChainFeesResponse {
+    fn from(fees: &ChainFees) -> Self {
+        let (bitcoin, ethereum) = match &fees.fees {
+            NetworkFees::Bitcoin { fee_estimates } => (
+                Some(bitcoin_fees(fee_estimates, fees.estimated_block_time)),
+                None,
+            ),
+            NetworkFees::Ethereum {
+                next_base_fee_wei,
+                priority_fees_wei,
+            } => (
+                None,
+                Some(EthereumFeesResponse {
+                    next_base_fee_wei: U256::from(*next_base_fee_wei),
+                    priority_fee_low_wei: U256::from(priority_fees_wei.low),
+                    priority_fee_medium_wei: U256::from(priority_fees_wei.medium),
+                    priority_fee_high_wei: U256::from(priority_fees_wei.high),
+                }),
+            ),
+        };
+        Self {
+            chain: fees.chain,
+            fetched_at: fees.fetched_at,
+            bitcoin,
+            ethereum,
+        }
+    }
+}
+
+fn bitcoin_fees(
+    fee_estimates: &BTreeMap<u16, f64>,
+    block_time: std::time::Duration,
+) -> BitcoinFeesResponse {
+    let median_fee_rate_sat_per_vb = median_rate(fee_estimates);
+    // Estimates fall as the target grows, so the first target the median
+    // already pays for is how long the median waits
+    let estimated_confirmation_blocks = fee_estimates
+        .iter()
+        .find(|(_, &rate)| rate <= median_fee_rate_sat_per_vb)
+        .map_or(1, |(&blocks, _)| blocks);
+    BitcoinFeesResponse {
+        fee_estimates: fee_estimates.clone(),
+        median_fee_rate_sat_per_vb,
+        estimated_confirmation_blocks,
+        estimated_confirmation_seconds: block_time.as_secs()
+            * u64::from(estimated_confirmation_blocks),
+        standard_tx_fee_sats: (median_fee_rate_sat_per_vb * STANDARD_TX_VBYTES as f64).ceil()
+            as u64,
+    }
+}
+
+fn median_rate(fee_estimates: &BTreeMap<u16, f64>) -> f64 {
+    let mut rates: Vec<f64> = fee_estimates.values().copied().collect();
+    if rates.is_empty() {
+        return FALLBACK_FEE_RATE_SAT_PER_VB;
+    }
+    rates.sort_by(f64::total_cmp);
+    let middle = rates.len() / 2;
+    if rates.len() % 2 == 0 {
+        (rates[middle - 1] + rates[middle]) / 2.0
+    } else {
+        rates[middle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otc_chains::PriorityFees;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn chain_fees(chain: ChainType, fees: NetworkFees, block_time: Duration) -> ChainFees {
+        ChainFees {
+            chain,
+            fees,
+            estimated_block_time: block_time,
+            fetched_at: DateTime::parse_from_rfc3339("2025-08-15T12:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        }
+    }
+
+    #[test]
+    fn bitcoin_response_hints_at_the_median_rate() {
+        let fees = chain_fees(
+            ChainType::Bitcoin,
+            NetworkFees::Bitcoin {
+                fee_estimates: BTreeMap::from([(1, 20.0), (3, 12.0), (6, 8.0), (144, 1.5)]),
+            },
+            Duration::from_secs(600),
+        );
+
+        let response = serde_json::to_value(ChainFeesResponse::from(&fees)).unwrap();
+        assert_eq!(
+            response,
+            json!({
+                "chain": "bitcoin",
+                "fetched_at": "2025-08-15T12:00:00Z",
+                "bitcoin": {
+                    "fee_estimates": {"1": 20.0, "3": 12.0, "6": 8.0, "144": 1.5},
+                    "median_fee_rate_sat_per_vb": 10.0,
+                    "estimated_confirmation_blocks": 6,
+                    "estimated_confirmation_seconds": 3600,
+                    "standard_tx_fee_sats": 1410,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn bitcoin_response_without_estimates_assumes_the_minimum_rate() {
+        let fees = chain_fees(
+            ChainType::Bitcoin,
+            NetworkFees::Bitcoin {
+                fee_estimates: BTreeMap::new(),
+            },
+            Duration::from_secs(600),
+        );
+
+        let bitcoin = ChainFeesResponse::from(&fees).bitcoin.unwrap();
+        assert_eq!(bitcoin.median_fee_rate_sat_per_vb, 1.0);
+        assert_eq!(bitcoin.estimated_confirmation_blocks, 1);
+        assert_eq!(bitcoin.standard_tx_fee_sats, 141);
+    }
+
+    #[test]
+    fn ethereum_response_has_base_and_priority_fees() {
+        let fees = chain_fees(
+            ChainType::Ethereum,
+            NetworkFees::Ethereum {
+                next_base_fee_wei: 12_000_000_000,
+                priority_fees_wei: PriorityFees {
+                    low: 1_000_000,
+                    medium: 1_500_000_000,
+                    high: 3_000_000_000,
+                },
+            },
+            Duration::from_secs(12),
+        );
+
+        let response = serde_json::to_value(ChainFeesResponse::from(&fees)).unwrap();
+        assert_eq!(
+            response,
+            json!({
+                "chain": "ethereum",
+                "fetched_at": "2025-08-15T12:00:00Z",
+                "ethereum": {
+                    "next_base_fee_wei": "0x2cb417800",
+                    "priority_fee_low_wei": "0xf4240",
+                    "priority_fee_medium_wei": "0x59682f00",
+                    "priority_fee_high_wei": "0xb2d05e00",
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use otc_models::{transition_graph, SwapStatus};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Response for GET /api/v1/meta/swap-states
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SwapStatesResponse {
+    /// Every swap status, in lifecycle order
+    pub states: Vec<SwapStateResponse>,
+}
+
+/// A status of the swap state machine and where a swap can go from it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SwapStateResponse {
+    pub status: SwapStatus,
+
+    pub description: String,
+
+    /// Statuses a swap in this one may move to, empty for a final status
+    pub allowed_transitions: Vec<SwapStatus>,
+}
+
+impl SwapStatesResponse {
+    #[must_use]
+    pub fn from_state_machine() -> Self {
+        let states = transition_graph()
+            .into_iter()
+            .map(|(status, allowed_transitions)| SwapStateResponse {
+                status,
+                description: status.description().to_string(),
+                allowed_transitions: allowed_transitions.to_vec(),
+            })
+            .collect();
+        Self { states }
+    }
+}
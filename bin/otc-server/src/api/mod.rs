@@ -1,3 +1,22 @@
+pub mod admin;
+pub mod currencies;
+pub mod fees;
+pub mod meta;
+pub mod reports;
 pub mod swaps;
 
-pub use swaps::{CreateSwapRequest, CreateSwapResponse, SwapResponse};
\ No newline at end of file
+pub use admin::{
+    AdminSummaryResponse, AdminSwapsQuery, CancelSwapRequest, ChainMonitoringHealth,
+    MarketMakerStatsQuery, MarketMakerStatsResponse, MarketMakerWindowStats, MasterKeyInfo,
+    MasterKeysResponse, OldestActiveSwap, ReconciliationFindingsResponse,
+    SetConfirmationOverrideRequest, StatsWindow, SwapStatusCount,
+};
+pub use currencies::{ChainCurrencyResponse, CurrenciesResponse};
+pub use fees::{BitcoinFeesResponse, ChainFeesResponse, EthereumFeesResponse};
+pub use meta::{SwapStateResponse, SwapStatesResponse};
+pub use otc_api_types::{ReceiptDeposit, ReceiptFees, ReceiptTransition, SwapReceipt};
+pub use reports::SwapReportQuery;
+pub use swaps::{
+    CreateSwapQuery, CreateSwapRequest, CreateSwapResponse, SwapLookup, SwapLookupQuery,
+    SwapResponse,
+};
\ No newline at end of file
@@ -0,0 +1,248 @@
+use crate::db::SwapReportEntry;
+use alloy::primitives::U256;
+use chrono::{DateTime, SecondsFormat, Utc};
+use otc_models::{Lot, SwapStatus, TokenIdentifier};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+/// Columns of GET /api/v1/reports/swaps.csv, in order
+pub const SWAP_REPORT_COLUMNS: [&str; 20] = [
+    "swap_id",
+    "status",
+    "created_at",
+    "settled_at",
+    "from_chain",
+    "from_chain_id",
+    "from_token",
+    "from_amount",
+    "to_chain",
+    "to_chain_id",
+    "to_token",
+    "to_amount",
+    "market_maker_id",
+    "protocol_fee_bps",
+    "protocol_fee",
+    "mm_network_fee",
+    "user_deposit_tx_hash",
+    "mm_deposit_tx_hash",
+    "settlement_tx_hash",
+    "external_reference",
+];
+
+/// Query for GET /api/v1/reports/swaps.csv
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SwapReportQuery {
+    /// Swaps created at or after this time
+    pub from: DateTime<Utc>,
+
+    /// Swaps created before this time
+    pub to: DateTime<Utc>,
+
+    /// Only swaps currently in this status, every status when unset
+    pub status: Option<SwapStatus>,
+
+    /// Only swaps tagged with exactly this reference when set
+    pub external_reference: Option<String>,
+}
+
+/// The CSV header line
+#[must_use]
+pub fn swap_report_header() -> String {
+    csv_line(
+        SWAP_REPORT_COLUMNS
+            .iter()
+            .map(|column| (*column).to_string()),
+    )
+}
+
+/// One CSV line for `entry`. Amounts and fees are decimals in their lot's
+/// units at full precision, fees are in the market maker's deposit currency
+#[must_use]
+pub fn swap_report_row(entry: &SwapReportEntry) -> String {
+    let swap = &entry.swap;
+    let to_currency = &swap.quote.to.currency;
+    let [from_chain, from_chain_id, from_token, from_amount] = lot_columns(&swap.quote.from);
+    let [to_chain, to_chain_id, to_token, to_amount] = lot_columns(&swap.quote.to);
+
+    csv_line([
+        swap.id.to_string(),
+        status_name(swap.status),
+        timestamp(swap.created_at),
+        entry.settled_at.map(timestamp).unwrap_or_default(),
+        from_chain,
+        from_chain_id,
+        from_token,
+        from_amount,
+        to_chain,
+        to_chain_id,
+        to_token,
+        to_amount,
+        swap.market_maker_id.to_string(),
+        entry.protocol_fee_bps.to_string(),
+        entry
+            .protocol_fee
+            .map(|fee| to_currency.format_amount(fee))
+            .unwrap_or_default(),
+        entry
+            .mm_fill_cost
+            .as_ref()
+            .and_then(|cost| cost.fee_in_lot_currency)
+            .map(|fee| to_currency.format_amount(U256::from(fee)))
+            .unwrap_or_default(),
+        swap.user_deposit_status
+            .as_ref()
+            .map(|deposit| deposit.tx_hash.to_string())
+            .unwrap_or_default(),
+        swap.mm_deposit_status
+            .as_ref()
+            .map(|deposit| deposit.tx_hash.to_string())
+            .unwrap_or_default(),
+        swap.settlement_status
+            .as_ref()
+            .map(|settlement| settlement.tx_hash.to_string())
+            .unwrap_or_default(),
+        swap.external_reference.clone().unwrap_or_default(),
+    ])
+}
+
+fn lot_columns(lot: &Lot) -> [String; 4] {
+    let token = match &lot.currency.token {
+        TokenIdentifier::Native => "native".to_string(),
+        TokenIdentifier::Address(address) => address.clone(),
+    };
+    [
+        lot.currency.chain.to_string(),
+        lot.currency
+            .chain_id
+            .map(|chain_id| chain_id.to_string())
+            .unwrap_or_default(),
+        token,
+        lot.currency.format_amount(lot.amount),
+    ]
+}
+
+fn status_name(status: SwapStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Join `fields` into a CRLF terminated line, quoting the ones that need it
+fn csv_line(fields: impl IntoIterator<Item = String>) -> String {
+    let mut line = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otc_models::{ChainType, Currency, SwapBuilder, TxHash, UserDepositStatus};
+
+    const USER_TX: &str = "7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730";
+
+    fn entry() -> SwapReportEntry {
+        let now = Utc::now();
+        let native = |chain, decimals| Currency {
+            chain,
+            token: TokenIdentifier::Native,
+            decimals,
+            chain_id: None,
+        };
+        let mut swap = SwapBuilder::new()
+            .with_lots(
+                Lot {
+                    currency: native(ChainType::Bitcoin, 8),
+                    amount: U256::from(150_000_000u64),
+                },
+                Lot {
+                    currency: Currency {
+                        chain_id: Some(1),
+                        ..native(ChainType::Ethereum, 18)
+                    },
+                    // More than a u128 holds
+                    amount: U256::from(u128::MAX) * U256::from(10u64) + U256::from(1u64),
+                },
+            )
+            .with_status(SwapStatus::Settled)
+            .with_created_at(now)
+            .build();
+        swap.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: TxHash::parse(ChainType::Bitcoin, USER_TX).unwrap(),
+            amount: U256::from(150_000_000u64),
+            detected_at: now,
+            confirmations: 2,
+            last_checked: now,
+        });
+        swap.external_reference = Some("INV-42".to_string());
+        SwapReportEntry {
+            swap,
+            settled_at: Some(now),
+            mm_fill_cost: None,
+            // Charged under an older rate than today's
+            protocol_fee_bps: 25,
+            protocol_fee: None,
+        }
+    }
+
+    #[test]
+    fn test_header_names_every_column() {
+        assert_eq!(
+            swap_report_header(),
+            format!("{}\r\n", SWAP_REPORT_COLUMNS.join(","))
+        );
+        assert!(swap_report_header().starts_with("swap_id,status,created_at,settled_at,"));
+    }
+
+    #[test]
+    fn test_row_formats_amounts_at_full_precision() {
+        let entry = entry();
+        let row = swap_report_row(&entry);
+        let fields: Vec<_> = row.trim_end().split(',').collect();
+        assert_eq!(fields.len(), SWAP_REPORT_COLUMNS.len());
+
+        let field = |name: &str| {
+            let index = SWAP_REPORT_COLUMNS.iter().position(|c| *c == name).unwrap();
+            fields[index]
+        };
+        assert_eq!(field("swap_id"), entry.swap.id.to_string());
+        assert_eq!(field("status"), "settled");
+        assert_eq!(field("from_token"), "native");
+        assert_eq!(field("from_amount"), "1.5");
+        // 3402823669209384634633746074317682114551 wei
+        assert_eq!(
+            field("to_amount"),
+            "3402823669209384634633.746074317682114551"
+        );
+        assert_eq!(field("to_chain_id"), "1");
+        assert_eq!(field("protocol_fee_bps"), "25");
+        assert_eq!(field("protocol_fee"), "");
+        assert_eq!(field("user_deposit_tx_hash"), USER_TX);
+        assert_eq!(field("mm_deposit_tx_hash"), "");
+        assert_eq!(field("external_reference"), "INV-42");
+    }
+
+    #[test]
+    fn test_fields_with_separators_are_quoted() {
+        assert_eq!(
+            csv_line(["a,b".to_string(), "say \"hi\"".to_string()]),
+            "\"a,b\",\"say \"\"hi\"\"\"\r\n"
+        );
+    }
+}
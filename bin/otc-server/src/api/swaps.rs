@@ -1,76 +1,33 @@
-use alloy::primitives::{Address, U256};
-use chrono::{DateTime, Utc};
-use otc_models::Quote;
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
-
-/// Request to create a new swap from a quote
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct CreateSwapRequest {
-    /// The quote ID to create a swap from
-    pub quote: Quote,
-
-    /// User's destination address for receiving funds
-    pub user_destination_address: String,
-
-    /// User's EVM account that is authorized to control the swap
-    pub user_evm_account_address: Address,
+use qrcode::{render::svg, QrCode};
+
+pub use otc_api_types::{
+    refund_address_message, CreateSwapQuery, CreateSwapRequest, CreateSwapResponse,
+    DepositInfoResponse, DepositProgress, SetRefundAddressRequest, SettlementProgress, SwapLookup,
+    SwapLookupQuery, SwapResponse,
+};
+
+/// Render `data` as a standalone SVG QR code
+pub fn render_qr_svg(data: &str) -> Result<String, qrcode::types::QrError> {
+    let code = QrCode::new(data.as_bytes())?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .quiet_zone(true)
+        .build())
 }
 
-/// Response after successfully creating a swap
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CreateSwapResponse {
-    /// The newly created swap ID
-    pub swap_id: Uuid,
-
-    /// Deposit address for the user to send funds to
-    pub deposit_address: String,
-
-    /// Chain type for the deposit (Bitcoin/Ethereum)
-    pub deposit_chain: String,
-
-    /// Expected amount to deposit (matches quote.from.amount)
-    pub expected_amount: U256,
-
-    /// Number of decimals for the amount
-    pub decimals: u8,
-
-    /// Token type (Native or token address)
-    pub token: String,
-
-    /// When the swap expires (based on quote expiry)
-    pub expires_at: DateTime<Utc>,
-
-    /// Current swap status
-    pub status: String,
-}
-
-/// Response for GET /swaps/:id
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SwapResponse {
-    pub id: Uuid,
-    pub quote_id: Uuid,
-    pub status: String,
-    pub created_at: DateTime<Utc>,
-    pub updated_at: DateTime<Utc>,
-
-    /// User's deposit information
-    pub user_deposit: DepositInfoResponse,
-
-    /// Market maker's deposit information  
-    pub mm_deposit: DepositInfoResponse,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DepositInfoResponse {
-    pub address: String,
-    pub chain: String,
-    pub expected_amount: U256,
-    pub decimals: u8,
-    pub token: String,
+    #[test]
+    fn test_qr_svg_is_well_formed() {
+        let uri = "bitcoin:bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080?amount=0.1";
+        let svg = render_qr_svg(uri).unwrap();
 
-    /// Actual deposit info if detected
-    pub deposit_tx: Option<String>,
-    pub deposit_amount: Option<U256>,
-    pub deposit_detected_at: Option<DateTime<Utc>>,
+        let document = roxmltree::Document::parse(&svg).unwrap();
+        let root = document.root_element();
+        assert_eq!(root.tag_name().name(), "svg");
+        assert!(root.descendants().any(|node| node.has_tag_name("path")));
+    }
 }
@@ -0,0 +1,15 @@
+use clap::Parser;
+use otc_server::recovery::{run_recovery, RecoverArgs, RecoveryResult};
+
+#[tokio::main]
+async fn main() -> RecoveryResult<()> {
+    let args = RecoverArgs::parse();
+
+    // Stdout carries the report
+    tracing_subscriber::fmt()
+        .with_env_filter(args.log_level.as_str())
+        .with_writer(std::io::stderr)
+        .init();
+
+    run_recovery(args).await
+}
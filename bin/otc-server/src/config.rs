@@ -1,77 +1,261 @@
 use config::{Config, File};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
-use snafu::{ResultExt, Snafu};
-use std::path::Path;
-use zeroize::Zeroize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 #[derive(Debug, Snafu)]
 pub enum SettingsError {
     #[snafu(display("Failed to load config: {}", source))]
     Load { source: config::ConfigError },
-    
+
     #[snafu(display("Failed to create config file: {}", source))]
     Create { source: std::io::Error },
+
+    #[snafu(display("Failed to serialize config: {}", source))]
+    Serialize { source: toml::ser::Error },
+
+    #[snafu(display("Failed to write config file: {}", source))]
+    Persist { source: std::io::Error },
+
+    #[snafu(display("Invalid master keys: {}", message))]
+    InvalidMasterKeys { message: String },
+
+    #[snafu(display("Unknown master key version {}", version))]
+    UnknownMasterKeyVersion { version: u32 },
+
+    #[snafu(display("Master key version {} is current and can't be removed", version))]
+    CurrentMasterKey { version: u32 },
+
+    #[snafu(display("Master keys set through OTC_MASTER_KEY can't be rotated at runtime"))]
+    ReadOnlyMasterKeys,
 }
 
 type Result<T> = std::result::Result<T, SettingsError>;
 
-#[derive(Debug, Deserialize, Clone)]
+/// Versioned master keys that every user deposit wallet is derived from
+///
+/// New swaps use the current version and record it, so adding a version never
+/// moves the deposit address of a swap that's already in flight. Retired
+/// versions stay loaded until no active swap references them.
+#[derive(Debug)]
 pub struct Settings {
-    pub master_key: SecretString,
+    keyring: RwLock<Keyring>,
+    /// Where key changes are written, `None` when the key came from OTC_MASTER_KEY
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+struct Keyring {
+    current_version: u32,
+    keys: BTreeMap<u32, SecretString>,
 }
 
-#[derive(Serialize)]
-struct DefaultSettings {
-    master_key: String,
+/// On-disk layout of the config file
+#[derive(Serialize, Deserialize)]
+struct SettingsFile {
+    current_master_key_version: u32,
+    master_keys: Vec<VersionedMasterKey>,
+}
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+struct VersionedMasterKey {
+    version: u32,
+    key: String,
 }
 
 impl Settings {
-    pub fn load() -> Result<Self> {
-        let config_path = "otc-server.toml";
-        
+    pub fn load(config_path: impl AsRef<Path>) -> Result<Self> {
+        let config_path = config_path.as_ref();
+
         // Check for environment variable first (useful for tests)
         if let Ok(master_key) = std::env::var("OTC_MASTER_KEY") {
             return Ok(Settings {
-                master_key: SecretString::from(master_key),
+                keyring: RwLock::new(Keyring {
+                    current_version: 1,
+                    keys: BTreeMap::from([(1, SecretString::from(master_key))]),
+                }),
+                path: None,
             });
         }
-        
+
         // Create default config if it doesn't exist
-        if !Path::new(config_path).exists() {
+        if !config_path.exists() {
             Self::create_default_config(config_path)?;
         }
-        
-        let settings = Config::builder()
-            .add_source(File::with_name(config_path))
+
+        let file: SettingsFile = Config::builder()
+            .add_source(File::from(config_path))
             .build()
+            .context(LoadSnafu)?
+            .try_deserialize()
             .context(LoadSnafu)?;
-        
-        settings.try_deserialize().context(LoadSnafu)
+
+        Ok(Settings {
+            keyring: RwLock::new(Keyring::from_file(&file)?),
+            path: Some(config_path.to_path_buf()),
+        })
+    }
+
+    fn create_default_config(path: &Path) -> Result<()> {
+        let default = SettingsFile {
+            current_master_key_version: 1,
+            master_keys: vec![VersionedMasterKey {
+                version: 1,
+                key: generate_master_key(),
+            }],
+        };
+
+        let mut toml = toml::to_string_pretty(&default).context(SerializeSnafu)?;
+        let written = fs::write(path, &toml).context(CreateSnafu);
+        toml.zeroize();
+        written?;
+
+        tracing::info!("Created default config file at {}", path.display());
+        Ok(())
+    }
+
+    /// Version new swaps derive their deposit wallets with
+    #[must_use]
+    pub fn current_master_key_version(&self) -> u32 {
+        self.keyring.read().unwrap().current_version
+    }
+
+    /// Every loaded version, oldest first
+    #[must_use]
+    pub fn master_key_versions(&self) -> Vec<u32> {
+        self.keyring.read().unwrap().keys.keys().copied().collect()
     }
-    
-    fn create_default_config(path: &str) -> Result<()> {
-        use std::fs;
-        
-        // Generate a random 64-byte master key
-        let mut key_bytes = [0u8; 64];
-        getrandom::getrandom(&mut key_bytes).expect("Failed to generate random bytes");
-        let master_key = alloy::hex::encode(key_bytes);
-        key_bytes.zeroize();
-        
-        let default = DefaultSettings { master_key };
-        
-        let toml = toml::to_string_pretty(&default)
-            .expect("Failed to serialize default config");
-        
-        fs::write(path, toml).context(CreateSnafu)?;
-        
-        tracing::info!("Created default config file at {}", path);
+
+    pub fn master_key_bytes(&self, version: u32) -> Result<Vec<u8>> {
+        let keyring = self.keyring.read().unwrap();
+        let key = keyring
+            .keys
+            .get(&version)
+            .context(UnknownMasterKeyVersionSnafu { version })?;
+        Ok(alloy::hex::decode(key.expose_secret()).expect("Master keys are validated on load"))
+    }
+
+    /// Generate a new master key, persist it and make it current. Returns its version.
+    pub fn add_master_key(&self) -> Result<u32> {
+        let mut keyring = self.keyring.write().unwrap();
+
+        let mut updated = keyring.clone();
+        let version = updated.keys.keys().next_back().copied().unwrap_or(0) + 1;
+        updated
+            .keys
+            .insert(version, SecretString::from(generate_master_key()));
+        updated.current_version = version;
+
+        self.persist(&updated)?;
+        *keyring = updated;
+
+        tracing::info!("Master key version {} is now current", version);
+        Ok(version)
+    }
+
+    /// Forget a retired master key. Callers must make sure no active swap still uses it.
+    pub fn remove_master_key(&self, version: u32) -> Result<()> {
+        let mut keyring = self.keyring.write().unwrap();
+        ensure!(
+            keyring.current_version != version,
+            CurrentMasterKeySnafu { version }
+        );
+        ensure!(
+            keyring.keys.contains_key(&version),
+            UnknownMasterKeyVersionSnafu { version }
+        );
+
+        let mut updated = keyring.clone();
+        updated.keys.remove(&version);
+
+        self.persist(&updated)?;
+        *keyring = updated;
+
+        tracing::info!("Removed retired master key version {}", version);
         Ok(())
     }
-    
-    #[must_use] pub fn master_key_bytes(&self) -> Vec<u8> {
-        alloy::hex::decode(self.master_key.expose_secret())
-            .expect("Master key should be valid hex")
+
+    /// Write the keyring to the config file, replacing it atomically
+    fn persist(&self, keyring: &Keyring) -> Result<()> {
+        let path = self.path.as_ref().context(ReadOnlyMasterKeysSnafu)?;
+
+        let mut toml = toml::to_string_pretty(&keyring.to_file()).context(SerializeSnafu)?;
+        let tmp_path = path.with_extension("tmp");
+        let written = fs::write(&tmp_path, &toml)
+            .and_then(|()| fs::rename(&tmp_path, path))
+            .context(PersistSnafu);
+        toml.zeroize();
+        written
+    }
+}
+
+impl Keyring {
+    fn from_file(file: &SettingsFile) -> Result<Self> {
+        let mut keys = BTreeMap::new();
+        for entry in &file.master_keys {
+            ensure!(
+                entry.version > 0,
+                InvalidMasterKeysSnafu {
+                    message: "versions start at 1"
+                }
+            );
+            ensure!(
+                alloy::hex::decode(&entry.key).is_ok(),
+                InvalidMasterKeysSnafu {
+                    message: format!("version {} is not valid hex", entry.version)
+                }
+            );
+            ensure!(
+                keys.insert(entry.version, SecretString::from(entry.key.clone()))
+                    .is_none(),
+                InvalidMasterKeysSnafu {
+                    message: format!("version {} is listed twice", entry.version)
+                }
+            );
+        }
+        ensure!(
+            keys.contains_key(&file.current_master_key_version),
+            InvalidMasterKeysSnafu {
+                message: format!(
+                    "current version {} has no key",
+                    file.current_master_key_version
+                )
+            }
+        );
+
+        Ok(Self {
+            current_version: file.current_master_key_version,
+            keys,
+        })
+    }
+
+    fn to_file(&self) -> SettingsFile {
+        SettingsFile {
+            current_master_key_version: self.current_version,
+            master_keys: self
+                .keys
+                .iter()
+                .map(|(version, key)| VersionedMasterKey {
+                    version: *version,
+                    key: key.expose_secret().to_string(),
+                })
+                .collect(),
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Random 64-byte master key, hex encoded
+fn generate_master_key() -> String {
+    let mut key_bytes = [0u8; 64];
+    getrandom::getrandom(&mut key_bytes).expect("Failed to generate random bytes");
+    let master_key = alloy::hex::encode(key_bytes);
+    key_bytes.zeroize();
+    master_key
+}
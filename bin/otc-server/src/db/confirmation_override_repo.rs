@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use otc_models::{ChainType, ConfirmationOverride};
+use sqlx::postgres::PgPool;
+
+use super::conversions::{chain_type_to_db, confirmation_rule_to_db};
+use super::row_mappers::FromRow;
+use crate::error::OtcServerResult;
+
+#[derive(Clone)]
+pub struct ConfirmationOverrideRepository {
+    pool: PgPool,
+}
+
+impl ConfirmationOverrideRepository {
+    #[must_use]
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Record a new override. Rows are never updated or deleted so the table
+    /// doubles as the audit trail of operator changes.
+    pub async fn create(
+        &self,
+        confirmation_override: &ConfirmationOverride,
+    ) -> OtcServerResult<()> {
+        let (multiplier, absolute_confirmations) =
+            confirmation_rule_to_db(&confirmation_override.rule);
+
+        sqlx::query(
+            r"
+            INSERT INTO confirmation_overrides (
+                id, chain,
+                multiplier, absolute_confirmations,
+                baseline_confirmations, required_confirmations,
+                reason, expires_at, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ",
+        )
+        .bind(confirmation_override.id)
+        .bind(chain_type_to_db(&confirmation_override.chain))
+        .bind(multiplier)
+        .bind(absolute_confirmations)
+        .bind(confirmation_override.baseline_confirmations as i32)
+        .bind(confirmation_override.required_confirmations as i32)
+        .bind(&confirmation_override.reason)
+        .bind(confirmation_override.expires_at)
+        .bind(confirmation_override.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The most recently issued override for the chain, if it hasn't expired yet
+    pub async fn get_active(
+        &self,
+        chain: ChainType,
+        now: DateTime<Utc>,
+    ) -> OtcServerResult<Option<ConfirmationOverride>> {
+        let row = sqlx::query(
+            r"
+            SELECT
+                id, chain,
+                multiplier, absolute_confirmations,
+                baseline_confirmations, required_confirmations,
+                reason, expires_at, created_at
+            FROM confirmation_overrides
+            WHERE chain = $1
+            ORDER BY created_at DESC
+            LIMIT 1
+            ",
+        )
+        .bind(chain_type_to_db(&chain))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        // A newer override always supersedes older ones, even once it has expired
+        match row {
+            Some(row) => {
+                let confirmation_override = ConfirmationOverride::from_row(&row)?;
+                Ok(confirmation_override
+                    .is_active_at(now)
+                    .then_some(confirmation_override))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Full override history for a chain, newest first
+    pub async fn get_history(
+        &self,
+        chain: ChainType,
+    ) -> OtcServerResult<Vec<ConfirmationOverride>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                id, chain,
+                multiplier, absolute_confirmations,
+                baseline_confirmations, required_confirmations,
+                reason, expires_at, created_at
+            FROM confirmation_overrides
+            WHERE chain = $1
+            ORDER BY created_at DESC
+            ",
+        )
+        .bind(chain_type_to_db(&chain))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(ConfirmationOverride::from_row).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use chrono::Duration;
+    use otc_models::ConfirmationRule;
+    use uuid::Uuid;
+
+    fn build_override(
+        chain: ChainType,
+        rule: ConfirmationRule,
+        expires_at: DateTime<Utc>,
+    ) -> ConfirmationOverride {
+        ConfirmationOverride {
+            id: Uuid::new_v4(),
+            chain,
+            rule,
+            baseline_confirmations: 2,
+            required_confirmations: rule.resolve(2),
+            reason: "fee spike".to_string(),
+            expires_at,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_active_override_round_trip(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let repo = db.confirmation_overrides();
+        let now = Utc::now();
+
+        assert!(repo
+            .get_active(ChainType::Bitcoin, now)
+            .await
+            .unwrap()
+            .is_none());
+
+        let original = build_override(
+            ChainType::Bitcoin,
+            ConfirmationRule::Multiplier(2.5),
+            now + Duration::hours(1),
+        );
+        repo.create(&original).await.unwrap();
+
+        let active = repo
+            .get_active(ChainType::Bitcoin, now)
+            .await
+            .unwrap()
+            .expect("override should be active");
+        assert_eq!(active.id, original.id);
+        assert_eq!(active.rule, ConfirmationRule::Multiplier(2.5));
+        assert_eq!(active.required_confirmations, 5);
+        assert_eq!(active.reason, "fee spike");
+
+        // Scoped to the chain it was issued for
+        assert!(repo
+            .get_active(ChainType::Ethereum, now)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Expires without any cleanup
+        assert!(repo
+            .get_active(ChainType::Bitcoin, now + Duration::hours(2))
+            .await
+            .unwrap()
+            .is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_newer_override_supersedes_and_history_is_kept(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let repo = db.confirmation_overrides();
+        let now = Utc::now();
+
+        let first = build_override(
+            ChainType::Bitcoin,
+            ConfirmationRule::Absolute(10),
+            now + Duration::hours(1),
+        );
+        repo.create(&first).await.unwrap();
+
+        // The newest override wins even after it has lapsed
+        let mut second = build_override(
+            ChainType::Bitcoin,
+            ConfirmationRule::Absolute(6),
+            now - Duration::seconds(1),
+        );
+        second.created_at = first.created_at + Duration::seconds(1);
+        repo.create(&second).await.unwrap();
+
+        assert!(repo
+            .get_active(ChainType::Bitcoin, now)
+            .await
+            .unwrap()
+            .is_none());
+
+        let history = repo.get_history(ChainType::Bitcoin).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].id, second.id);
+        assert_eq!(history[1].id, first.id);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use alloy::primitives::U256;
-use otc_models::{ChainType, TokenIdentifier, Currency, Lot, UserDepositStatus, MMDepositStatus, SettlementStatus};
+use otc_models::{ChainType, ConfirmationRule, TokenIdentifier, Currency, FillCost, Lot, UserDepositStatus, MMDepositStatus, SettlementStatus, TxHash};
 use serde_json;
 use crate::error::{OtcServerError, OtcServerResult};
 
@@ -42,35 +42,69 @@ pub fn u256_from_db(s: &str) -> OtcServerResult<U256> {
     })
 }
 
-pub fn lot_to_db(lot: &Lot) -> OtcServerResult<(String, serde_json::Value, String, u8)> {
+/// Chain, chain id, token, amount and decimals columns of `lot`
+pub fn lot_to_db(lot: &Lot) -> OtcServerResult<(String, Option<i64>, serde_json::Value, String, u8)> {
     let chain = chain_type_to_db(&lot.currency.chain).to_string();
+    let chain_id = chain_id_to_db(lot.currency.chain_id)?;
     let token = token_identifier_to_json(&lot.currency.token)?;
     let amount = u256_to_db(&lot.amount);
     let decimals = lot.currency.decimals;
-    Ok((chain, token, amount, decimals))
+    Ok((chain, chain_id, token, amount, decimals))
 }
 
-pub fn lot_from_db(chain: String, token: serde_json::Value, amount: String, decimals: u8) -> OtcServerResult<Lot> {
+pub fn lot_from_db(chain: String, chain_id: Option<i64>, token: serde_json::Value, amount: String, decimals: u8) -> OtcServerResult<Lot> {
     Ok(Lot {
         currency: Currency {
             chain: chain_type_from_db(&chain)?,
             token: token_identifier_from_json(token)?,
             decimals,
+            chain_id: chain_id_from_db(chain_id)?,
         },
         amount: u256_from_db(&amount)?,
     })
 }
 
+pub fn chain_id_to_db(chain_id: Option<u64>) -> OtcServerResult<Option<i64>> {
+    chain_id
+        .map(|id| {
+            i64::try_from(id).map_err(|_| OtcServerError::InvalidData {
+                message: format!("Chain id {id} doesn't fit the database"),
+            })
+        })
+        .transpose()
+}
+
+fn chain_id_from_db(chain_id: Option<i64>) -> OtcServerResult<Option<u64>> {
+    chain_id
+        .map(|id| {
+            u64::try_from(id).map_err(|_| OtcServerError::InvalidData {
+                message: format!("Invalid chain id: {id}"),
+            })
+        })
+        .transpose()
+}
+
+/// A tx hash read back from the database, in whichever form it was written
+pub fn tx_hash_from_db(s: &str) -> OtcServerResult<TxHash> {
+    s.parse().map_err(|e: otc_models::InvalidTxHash| OtcServerError::InvalidData {
+        message: e.to_string(),
+    })
+}
+
 pub fn user_deposit_status_to_json(status: &UserDepositStatus) -> OtcServerResult<serde_json::Value> {
     serde_json::to_value(status).map_err(|e| OtcServerError::InvalidData {
         message: format!("Failed to serialize user deposit status: {e}"),
     })
 }
 
-pub fn user_deposit_status_from_json(value: serde_json::Value) -> OtcServerResult<UserDepositStatus> {
-    serde_json::from_value(value).map_err(|e| OtcServerError::InvalidData {
+/// Rows written before hashes were canonicalized may hold either form, the
+/// hash is read back in the form of `chain`, the one the deposit was made on
+pub fn user_deposit_status_from_json(value: serde_json::Value, chain: ChainType) -> OtcServerResult<UserDepositStatus> {
+    let mut status: UserDepositStatus = serde_json::from_value(value).map_err(|e| OtcServerError::InvalidData {
         message: format!("Failed to deserialize user deposit status: {e}"),
-    })
+    })?;
+    status.tx_hash = status.tx_hash.for_chain(chain);
+    Ok(status)
 }
 
 pub fn mm_deposit_status_to_json(status: &MMDepositStatus) -> OtcServerResult<serde_json::Value> {
@@ -79,9 +113,24 @@ pub fn mm_deposit_status_to_json(status: &MMDepositStatus) -> OtcServerResult<se
     })
 }
 
-pub fn mm_deposit_status_from_json(value: serde_json::Value) -> OtcServerResult<MMDepositStatus> {
-    serde_json::from_value(value).map_err(|e| OtcServerError::InvalidData {
+/// Like [`user_deposit_status_from_json`], `chain` is the one the MM paid on
+pub fn mm_deposit_status_from_json(value: serde_json::Value, chain: ChainType) -> OtcServerResult<MMDepositStatus> {
+    let mut status: MMDepositStatus = serde_json::from_value(value).map_err(|e| OtcServerError::InvalidData {
         message: format!("Failed to deserialize MM deposit status: {e}"),
+    })?;
+    status.tx_hash = status.tx_hash.for_chain(chain);
+    Ok(status)
+}
+
+pub fn fill_cost_to_json(cost: &FillCost) -> OtcServerResult<serde_json::Value> {
+    serde_json::to_value(cost).map_err(|e| OtcServerError::InvalidData {
+        message: format!("Failed to serialize fill cost: {e}"),
+    })
+}
+
+pub fn fill_cost_from_json(value: serde_json::Value) -> OtcServerResult<FillCost> {
+    serde_json::from_value(value).map_err(|e| OtcServerError::InvalidData {
+        message: format!("Failed to deserialize fill cost: {e}"),
     })
 }
 
@@ -119,18 +168,80 @@ mod tests {
                 chain: ChainType::Bitcoin,
                 token: TokenIdentifier::Native,
                 decimals: 8,
+                chain_id: None,
             },
             amount: U256::from(100),
         };
-        let (chain, token, amount, decimals) = lot_to_db(&lot).unwrap();
+        let (chain, chain_id, token, amount, decimals) = lot_to_db(&lot).unwrap();
         assert_eq!(chain, "bitcoin");
+        assert_eq!(chain_id, None);
         assert_eq!(token, serde_json::json!({ "type": "Native" }));
         assert_eq!(amount, "100");
         assert_eq!(decimals, 8);
 
-        let lot2 = lot_from_db(chain, token, amount, decimals).unwrap();
+        let lot2 = lot_from_db(chain, chain_id, token, amount, decimals).unwrap();
         assert_eq!(lot2.currency.chain, lot.currency.chain);
         assert_eq!(lot2.currency.token, lot.currency.token);
         assert_eq!(lot2.amount, lot.amount); 
     }
-}
\ No newline at end of file
+
+    const TX_HASH: &str = "88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63";
+
+    #[test]
+    fn test_statuses_missing_progress_fields_are_refused() {
+        let deposit = serde_json::json!({
+            "tx_hash": TX_HASH,
+            "amount": "0x2710",
+            "detected_at": "2025-01-01T00:00:00Z",
+        });
+        assert!(user_deposit_status_from_json(deposit.clone(), ChainType::Bitcoin).is_err());
+        assert!(mm_deposit_status_from_json(deposit, ChainType::Ethereum).is_err());
+
+        let settlement = settlement_status_from_json(serde_json::json!({
+            "tx_hash": TX_HASH,
+            "broadcast_at": "2025-01-01T00:00:00Z",
+        }));
+        assert!(settlement.is_err());
+    }
+
+    #[test]
+    fn test_stored_tx_hashes_load_in_their_chains_form() {
+        // Hashes were stored as the chain clients reported them, EVM ones
+        // without 0x from the chains crate and with it from the MM
+        for stored in [TX_HASH.to_string(), format!("0x{}", TX_HASH.to_uppercase())] {
+            let deposit = serde_json::json!({
+                "tx_hash": stored,
+                "amount": "0x2710",
+                "detected_at": "2025-01-01T00:00:00Z",
+                "confirmations": 1,
+                "last_checked": "2025-01-01T00:00:00Z",
+            });
+
+            let user_deposit = user_deposit_status_from_json(deposit.clone(), ChainType::Bitcoin).unwrap();
+            assert_eq!(user_deposit.tx_hash.as_str(), TX_HASH);
+            let mm_deposit = mm_deposit_status_from_json(deposit, ChainType::Ethereum).unwrap();
+            assert_eq!(mm_deposit.tx_hash.as_str(), format!("0x{TX_HASH}"));
+
+            let json = mm_deposit_status_to_json(&mm_deposit).unwrap();
+            assert_eq!(json["tx_hash"], format!("0x{TX_HASH}"));
+        }
+    }
+}
+
+/// Split a confirmation rule into its `(multiplier, absolute_confirmations)` columns
+#[must_use] pub fn confirmation_rule_to_db(rule: &ConfirmationRule) -> (Option<f64>, Option<i32>) {
+    match rule {
+        ConfirmationRule::Multiplier(multiplier) => (Some(*multiplier), None),
+        ConfirmationRule::Absolute(confirmations) => (None, Some(*confirmations as i32)),
+    }
+}
+
+pub fn confirmation_rule_from_db(multiplier: Option<f64>, absolute_confirmations: Option<i32>) -> OtcServerResult<ConfirmationRule> {
+    match (multiplier, absolute_confirmations) {
+        (Some(multiplier), None) => Ok(ConfirmationRule::Multiplier(multiplier)),
+        (None, Some(confirmations)) => Ok(ConfirmationRule::Absolute(confirmations as u64)),
+        _ => Err(OtcServerError::InvalidData {
+            message: "confirmation override must have exactly one of multiplier or absolute_confirmations".to_string(),
+        }),
+    }
+}
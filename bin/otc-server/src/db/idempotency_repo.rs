@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{postgres::PgPool, Row};
+use uuid::Uuid;
+
+use crate::error::OtcServerResult;
+
+/// A key some earlier request already holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyRecord {
+    pub request_hash: Vec<u8>,
+    /// `None` while the request that claimed the key is still creating its swap
+    pub response: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyClaim {
+    /// The caller owns the key and must `complete` or `release` it
+    Claimed,
+    Existing(IdempotencyRecord),
+}
+
+#[derive(Clone)]
+pub struct IdempotencyRepository {
+    pool: PgPool,
+}
+
+impl IdempotencyRepository {
+    #[must_use]
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Atomically claim a key, or return whoever holds it. Keys that expired by
+    /// `now` but haven't been cleaned up yet are reclaimed.
+    pub async fn claim(
+        &self,
+        key: &str,
+        request_hash: &[u8],
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> OtcServerResult<IdempotencyClaim> {
+        loop {
+            let claimed = sqlx::query(
+                r"
+                INSERT INTO swap_idempotency_keys (idempotency_key, request_hash, expires_at, created_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (idempotency_key) DO UPDATE
+                SET request_hash = EXCLUDED.request_hash,
+                    swap_id = NULL,
+                    response = NULL,
+                    expires_at = EXCLUDED.expires_at,
+                    created_at = EXCLUDED.created_at
+                WHERE swap_idempotency_keys.expires_at <= $4
+                RETURNING idempotency_key
+                ",
+            )
+            .bind(key)
+            .bind(request_hash)
+            .bind(expires_at)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await?;
+            if claimed.is_some() {
+                return Ok(IdempotencyClaim::Claimed);
+            }
+
+            let existing = sqlx::query(
+                r"
+                SELECT request_hash, response
+                FROM swap_idempotency_keys
+                WHERE idempotency_key = $1
+                ",
+            )
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+            // The holder released the key between the two queries, try again
+            if let Some(row) = existing {
+                return Ok(IdempotencyClaim::Existing(IdempotencyRecord {
+                    request_hash: row.try_get("request_hash")?,
+                    response: row.try_get("response")?,
+                }));
+            }
+        }
+    }
+
+    /// Record the swap a claimed key produced, so retries get the same response
+    pub async fn complete(
+        &self,
+        key: &str,
+        swap_id: Uuid,
+        response: &Value,
+    ) -> OtcServerResult<()> {
+        sqlx::query(
+            r"
+            UPDATE swap_idempotency_keys
+            SET swap_id = $2, response = $3
+            WHERE idempotency_key = $1
+            ",
+        )
+        .bind(key)
+        .bind(swap_id)
+        .bind(response)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Give up a claimed key whose request failed, so the client can retry with it
+    pub async fn release(&self, key: &str) -> OtcServerResult<()> {
+        sqlx::query(
+            r"
+            DELETE FROM swap_idempotency_keys
+            WHERE idempotency_key = $1 AND swap_id IS NULL
+            ",
+        )
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove keys that expired before `now`, returning how many were removed
+    pub async fn delete_expired(&self, now: DateTime<Utc>) -> OtcServerResult<u64> {
+        let result = sqlx::query(
+            r"
+            DELETE FROM swap_idempotency_keys
+            WHERE expires_at <= $1
+            ",
+        )
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use chrono::Duration;
+
+    #[sqlx::test]
+    async fn test_claim_is_exclusive_until_released_or_expired(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let repo = db.idempotency_keys();
+        let now = Utc::now();
+        let expires_at = now + Duration::hours(24);
+
+        assert_eq!(
+            repo.claim("key-1", b"hash-a", now, expires_at)
+                .await
+                .unwrap(),
+            IdempotencyClaim::Claimed
+        );
+        // Still pending, a second claim sees the holder
+        assert_eq!(
+            repo.claim("key-1", b"hash-b", now, expires_at)
+                .await
+                .unwrap(),
+            IdempotencyClaim::Existing(IdempotencyRecord {
+                request_hash: b"hash-a".to_vec(),
+                response: None,
+            })
+        );
+
+        // A released key can be claimed again
+        repo.release("key-1").await.unwrap();
+        assert_eq!(
+            repo.claim("key-1", b"hash-b", now, expires_at)
+                .await
+                .unwrap(),
+            IdempotencyClaim::Claimed
+        );
+
+        // An expired key is reclaimed even before cleanup runs
+        let expired = now - Duration::seconds(1);
+        assert_eq!(
+            repo.claim("key-2", b"hash-a", now, expired).await.unwrap(),
+            IdempotencyClaim::Claimed
+        );
+        assert_eq!(
+            repo.claim("key-2", b"hash-c", now, expires_at)
+                .await
+                .unwrap(),
+            IdempotencyClaim::Claimed
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_delete_expired_keeps_live_keys(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let repo = db.idempotency_keys();
+        let now = Utc::now();
+
+        repo.claim("live", b"hash", now, now + Duration::hours(1))
+            .await
+            .unwrap();
+        repo.claim("expired", b"hash", now, now - Duration::hours(1))
+            .await
+            .unwrap();
+
+        assert_eq!(repo.delete_expired(now).await.unwrap(), 1);
+        assert!(matches!(
+            repo.claim("live", b"hash", now, now + Duration::hours(1))
+                .await
+                .unwrap(),
+            IdempotencyClaim::Existing(_)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+use sqlx::Row;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::OtcServerResult;
+
+/// How a market maker answered when asked to validate a quote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationOutcome {
+    Accepted,
+    Rejected,
+    /// Timed out, or the request never reached the MM
+    Unanswered,
+}
+
+impl ValidationOutcome {
+    fn as_db(self) -> &'static str {
+        match self {
+            Self::Accepted => "accepted",
+            Self::Rejected => "rejected",
+            Self::Unanswered => "unanswered",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ValidationCounts {
+    pub requested: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub unanswered: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SwapCounts {
+    pub total: u64,
+    pub settled: u64,
+    pub failed: u64,
+    /// Swaps the MM paid into
+    pub fills: u64,
+    /// Fills that still ended with a refund
+    pub refunded_fills: u64,
+}
+
+/// Time from asking the MM to pay to detecting its deposit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FillLatency {
+    pub samples: u64,
+    pub average_seconds: Option<f64>,
+    pub max_seconds: Option<f64>,
+}
+
+/// Network fees the MM reported paying for its fills, in the paid lot's
+/// smallest unit like the quotes' `network_fee_sats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FillCosts {
+    pub samples: u64,
+    pub average_fee: Option<f64>,
+    pub max_fee: Option<f64>,
+}
+
+/// What a market maker did with the quotes and swaps created since some time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MarketMakerStats {
+    pub validations: ValidationCounts,
+    pub swaps: SwapCounts,
+    pub fill_latency: FillLatency,
+    pub fill_costs: FillCosts,
+}
+
+/// Computes market maker statistics on demand from swaps and recorded validations
+#[derive(Clone)]
+pub struct MarketMakerStatsRepository {
+    pool: PgPool,
+}
+
+impl MarketMakerStatsRepository {
+    #[must_use]
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn record_validation(
+        &self,
+        market_maker_id: Uuid,
+        quote_id: Uuid,
+        outcome: ValidationOutcome,
+    ) -> OtcServerResult<()> {
+        sqlx::query(
+            r"
+            INSERT INTO mm_quote_validations (market_maker_id, quote_id, outcome)
+            VALUES ($1, $2, $3)
+            ",
+        )
+        .bind(market_maker_id)
+        .bind(quote_id)
+        .bind(outcome.as_db())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn stats(
+        &self,
+        market_maker_id: Uuid,
+        since: DateTime<Utc>,
+    ) -> OtcServerResult<MarketMakerStats> {
+        let count = |row: &sqlx::postgres::PgRow, column: &str| -> OtcServerResult<u64> {
+            Ok(row.try_get::<i64, _>(column)?.unsigned_abs())
+        };
+
+        let row = sqlx::query(
+            r"
+            SELECT
+                COUNT(*) AS requested,
+                COUNT(*) FILTER (WHERE outcome = 'accepted') AS accepted,
+                COUNT(*) FILTER (WHERE outcome = 'rejected') AS rejected,
+                COUNT(*) FILTER (WHERE outcome = 'unanswered') AS unanswered
+            FROM mm_quote_validations
+            WHERE market_maker_id = $1 AND requested_at >= $2
+            ",
+        )
+        .bind(market_maker_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        let validations = ValidationCounts {
+            requested: count(&row, "requested")?,
+            accepted: count(&row, "accepted")?,
+            rejected: count(&row, "rejected")?,
+            unanswered: count(&row, "unanswered")?,
+        };
+
+        let row = sqlx::query(
+            r"
+            SELECT
+                COUNT(*) AS total,
+                COUNT(*) FILTER (WHERE status = 'settled') AS settled,
+                COUNT(*) FILTER (WHERE status = 'failed') AS failed,
+                COUNT(mm_deposit_detected_at) AS fills,
+                COUNT(*) FILTER (
+                    WHERE mm_deposit_detected_at IS NOT NULL
+                    AND EXISTS (
+                        SELECT 1 FROM swap_events e
+                        WHERE e.swap_id = s.id
+                        AND e.to_status IN ('refunding_user', 'refunding_mm')
+                    )
+                ) AS refunded_fills,
+                COUNT(*) FILTER (WHERE mm_deposit_detected_at >= mm_notified_at) AS latency_samples,
+                CAST(
+                    AVG(EXTRACT(EPOCH FROM mm_deposit_detected_at - mm_notified_at))
+                    FILTER (WHERE mm_deposit_detected_at >= mm_notified_at)
+                    AS DOUBLE PRECISION
+                ) AS latency_average,
+                CAST(
+                    MAX(EXTRACT(EPOCH FROM mm_deposit_detected_at - mm_notified_at))
+                    FILTER (WHERE mm_deposit_detected_at >= mm_notified_at)
+                    AS DOUBLE PRECISION
+                ) AS latency_max,
+                COUNT(mm_claimed_fill_cost->>'fee_in_lot_currency') AS fill_cost_samples,
+                CAST(
+                    AVG((mm_claimed_fill_cost->>'fee_in_lot_currency')::NUMERIC)
+                    AS DOUBLE PRECISION
+                ) AS fill_cost_average,
+                CAST(
+                    MAX((mm_claimed_fill_cost->>'fee_in_lot_currency')::NUMERIC)
+                    AS DOUBLE PRECISION
+                ) AS fill_cost_max
+            FROM swaps s
+            WHERE market_maker_id = $1 AND created_at >= $2
+            ",
+        )
+        .bind(market_maker_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        let swaps = SwapCounts {
+            total: count(&row, "total")?,
+            settled: count(&row, "settled")?,
+            failed: count(&row, "failed")?,
+            fills: count(&row, "fills")?,
+            refunded_fills: count(&row, "refunded_fills")?,
+        };
+        let fill_latency = FillLatency {
+            samples: count(&row, "latency_samples")?,
+            average_seconds: row.try_get("latency_average")?,
+            max_seconds: row.try_get("latency_max")?,
+        };
+        let fill_costs = FillCosts {
+            samples: count(&row, "fill_cost_samples")?,
+            average_fee: row.try_get("fill_cost_average")?,
+            max_fee: row.try_get("fill_cost_max")?,
+        };
+
+        Ok(MarketMakerStats {
+            validations,
+            swaps,
+            fill_latency,
+            fill_costs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use alloy::primitives::U256;
+    use chrono::Duration;
+    use otc_models::{FillCost, FillUsage, Swap, SwapBuilder, SwapStatus};
+
+    /// A swap of `market_maker_id` created at `created_at`, whose MM was asked to
+    /// pay at `notified_at` and paid `latency` later
+    fn swap(
+        market_maker_id: Uuid,
+        status: SwapStatus,
+        created_at: DateTime<Utc>,
+        latency: Option<Duration>,
+    ) -> Swap {
+        let notified_at = created_at + Duration::minutes(10);
+        let mut swap = SwapBuilder::new()
+            .with_market_maker_id(market_maker_id)
+            .with_status(status)
+            .with_created_at(created_at)
+            .build();
+        swap.mm_notified_at = latency.map(|_| notified_at);
+        swap.mm_deposit_detected_at = latency.map(|latency| notified_at + latency);
+        swap
+    }
+
+    #[sqlx::test]
+    async fn test_stats_from_seeded_swaps(pool: PgPool) {
+        let db = Database::from_pool(pool).await.unwrap();
+        let mm = Uuid::new_v4();
+        let now = Utc::now();
+        let hour_ago = now - Duration::hours(1);
+
+        let seeded = [
+            swap(
+                mm,
+                SwapStatus::Settled,
+                hour_ago,
+                Some(Duration::seconds(30)),
+            ),
+            swap(
+                mm,
+                SwapStatus::Settled,
+                hour_ago,
+                Some(Duration::seconds(90)),
+            ),
+            // Underpaid, the user is being refunded
+            swap(
+                mm,
+                SwapStatus::RefundingUser,
+                hour_ago,
+                Some(Duration::seconds(60)),
+            ),
+            swap(mm, SwapStatus::Failed, hour_ago, None),
+            // Outside a week, inside a month
+            swap(
+                mm,
+                SwapStatus::Settled,
+                now - Duration::days(10),
+                Some(Duration::seconds(600)),
+            ),
+            // Another market maker's
+            swap(
+                Uuid::new_v4(),
+                SwapStatus::Settled,
+                hour_ago,
+                Some(Duration::seconds(5)),
+            ),
+        ];
+        for seeded in &seeded {
+            db.swaps().create(seeded).await.unwrap();
+        }
+
+        // Two of the fills reported their cost in sats, one only its usage
+        for (filled, fee_in_lot_currency) in seeded.iter().zip([Some(400), Some(1_000), None]) {
+            db.swaps()
+                .record_mm_claimed_deposit(
+                    filled.id,
+                    &"11".repeat(32).parse().unwrap(),
+                    None,
+                    Some(&FillCost {
+                        fee_in_lot_currency,
+                        usage: FillUsage::Ethereum {
+                            effective_gas_price: U256::from(1_000_000_000u64),
+                            gas_used: 50_000,
+                        },
+                    }),
+                )
+                .await
+                .unwrap();
+        }
+
+        let stats = db.market_maker_stats();
+        for outcome in [
+            ValidationOutcome::Accepted,
+            ValidationOutcome::Accepted,
+            ValidationOutcome::Accepted,
+            ValidationOutcome::Rejected,
+            ValidationOutcome::Unanswered,
+        ] {
+            stats
+                .record_validation(mm, Uuid::new_v4(), outcome)
+                .await
+                .unwrap();
+        }
+
+        let week = stats.stats(mm, now - Duration::days(7)).await.unwrap();
+        assert_eq!(
+            week.validations,
+            ValidationCounts {
+                requested: 5,
+                accepted: 3,
+                rejected: 1,
+                unanswered: 1,
+            }
+        );
+        assert_eq!(
+            week.swaps,
+            SwapCounts {
+                total: 4,
+                settled: 2,
+                failed: 1,
+                fills: 3,
+                refunded_fills: 1,
+            }
+        );
+        assert_eq!(week.fill_latency.samples, 3);
+        assert!((week.fill_latency.average_seconds.unwrap() - 60.0).abs() < 1e-6);
+        assert!((week.fill_latency.max_seconds.unwrap() - 90.0).abs() < 1e-6);
+        assert_eq!(week.fill_costs.samples, 2);
+        assert!((week.fill_costs.average_fee.unwrap() - 700.0).abs() < 1e-6);
+        assert!((week.fill_costs.max_fee.unwrap() - 1_000.0).abs() < 1e-6);
+
+        let month = stats.stats(mm, now - Duration::days(30)).await.unwrap();
+        assert_eq!(month.swaps.total, 5);
+        assert_eq!(month.fill_latency.samples, 4);
+        assert!((month.fill_latency.max_seconds.unwrap() - 600.0).abs() < 1e-6);
+
+        let unknown = stats
+            .stats(Uuid::new_v4(), now - Duration::days(30))
+            .await
+            .unwrap();
+        assert_eq!(unknown, MarketMakerStats::default());
+    }
+}
@@ -1,24 +1,99 @@
+pub mod confirmation_override_repo;
 pub mod conversions;
+pub mod idempotency_repo;
+pub mod market_maker_stats_repo;
 pub mod quote_repo;
+pub mod reconciliation_finding_repo;
 pub mod row_mappers;
+pub mod swap_event_repo;
 pub mod swap_repo;
 
-pub use swap_repo::SwapRepository;
+pub use confirmation_override_repo::ConfirmationOverrideRepository;
+pub use idempotency_repo::{IdempotencyClaim, IdempotencyRecord, IdempotencyRepository};
+pub use market_maker_stats_repo::{
+    FillCosts, FillLatency, MarketMakerStats, MarketMakerStatsRepository, SwapCounts, ValidationCounts,
+    ValidationOutcome,
+};
+pub use reconciliation_finding_repo::{
+    DepositLeg, Discrepancy, FindingKind, ReconciliationFinding, ReconciliationFindingRepository,
+};
+pub use swap_event_repo::SwapEventRepository;
+pub use swap_repo::{
+    MMClaimedDeposit, MMDepositAttempts, OldestSwap, SwapReportEntry, SwapRepository,
+};
 
-use crate::{db::quote_repo::QuoteRepository, error::OtcServerResult};
+use crate::{
+    db::quote_repo::QuoteRepository,
+    error::{OtcServerError, OtcServerResult},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::{
     migrate::Migrator,
     postgres::{PgPool, PgPoolOptions},
 };
-use std::time::Duration;
-use tracing::info;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
 
 // Embeds all migration files from ./migrations at compile time
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
+/// Attempts a query gets when the database is unreachable, see [`with_retry`]
+const RETRY_ATTEMPTS: u32 = 4;
+const RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const RETRY_MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Run `query` until it succeeds, fails for a reason other than the database
+/// being unreachable, or runs out of attempts, backing off between them
+///
+/// Covers a connection dropped by a database restart or a pool exhausted
+/// while it reconnects, so the hot paths ride out a failover instead of
+/// failing the request or monitoring pass.
+pub(crate) async fn with_retry<T, E, F, Fut>(mut query: F) -> OtcServerResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<OtcServerError>,
+{
+    let mut backoff = RETRY_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match query().await.map_err(Into::into) {
+            Err(e) if e.is_transient() && attempt < RETRY_ATTEMPTS => {
+                warn!(
+                    "Database unavailable (attempt {}/{}), retrying in {:?}: {}",
+                    attempt, RETRY_ATTEMPTS, backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(RETRY_MAX_BACKOFF);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Connection pool usage, reported by /health and /metrics
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PoolStats {
+    /// Open connections, idle or in use
+    pub size: u32,
+    pub idle: usize,
+    pub max_size: u32,
+    /// How long the latest health check waited for a connection
+    pub acquire_wait_seconds: Option<f64>,
+}
+
 #[derive(Clone)]
 pub struct Database {
     pool: PgPool,
+    /// Microseconds the latest [`Database::ping`] waited for a connection,
+    /// `u64::MAX` until one has
+    acquire_wait_micros: Arc<AtomicU64>,
 }
 
 impl Database {
@@ -42,16 +117,159 @@ impl Database {
         info!("Running database migrations...");
         MIGRATOR.run(&pool).await?;
         info!("Database initialization complete");
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            acquire_wait_micros: Arc::new(AtomicU64::new(u64::MAX)),
+        })
+    }
+
+    /// Check the database is reachable, recording how long getting a
+    /// connection took
+    pub async fn ping(&self) -> OtcServerResult<()> {
+        let started = Instant::now();
+        let mut conn = self.pool.acquire().await?;
+        let waited = u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX - 1);
+        self.acquire_wait_micros.store(waited, Ordering::Relaxed);
+
+        sqlx::query("SELECT 1").execute(&mut *conn).await?;
+        Ok(())
+    }
+
+    /// The database's `NOW()`, to compare the host clock against
+    pub async fn now(&self) -> OtcServerResult<DateTime<Utc>> {
+        Ok(sqlx::query_scalar("SELECT NOW()")
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    #[must_use]
+    pub fn pool_stats(&self) -> PoolStats {
+        let waited = self.acquire_wait_micros.load(Ordering::Relaxed);
+        PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            max_size: self.pool.options().get_max_connections(),
+            acquire_wait_seconds: (waited != u64::MAX)
+                .then(|| Duration::from_micros(waited).as_secs_f64()),
+        }
     }
 
     #[must_use]
     pub fn swaps(&self) -> SwapRepository {
-        SwapRepository::new(self.pool.clone(), self.quotes())
+        SwapRepository::new(self.pool.clone())
     }
 
     #[must_use]
     pub fn quotes(&self) -> QuoteRepository {
         QuoteRepository::new(self.pool.clone())
     }
+
+    #[must_use]
+    pub fn swap_events(&self) -> SwapEventRepository {
+        SwapEventRepository::new(self.pool.clone())
+    }
+
+    #[must_use]
+    pub fn confirmation_overrides(&self) -> ConfirmationOverrideRepository {
+        ConfirmationOverrideRepository::new(self.pool.clone())
+    }
+
+    #[must_use]
+    pub fn idempotency_keys(&self) -> IdempotencyRepository {
+        IdempotencyRepository::new(self.pool.clone())
+    }
+
+    #[must_use]
+    pub fn market_maker_stats(&self) -> MarketMakerStatsRepository {
+        MarketMakerStatsRepository::new(self.pool.clone())
+    }
+
+    #[must_use]
+    pub fn reconciliation_findings(&self) -> ReconciliationFindingRepository {
+        ReconciliationFindingRepository::new(self.pool.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgConnectOptions;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::watch;
+
+    /// TCP proxy in front of the test database that can drop every connection
+    /// and refuse new ones, standing in for a database restart
+    struct FlakyProxy {
+        port: u16,
+        up: watch::Sender<bool>,
+    }
+
+    impl FlakyProxy {
+        async fn start(upstream: (String, u16)) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (up, _) = watch::channel(true);
+            let watcher = up.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (mut client, _) = listener.accept().await.unwrap();
+                    let mut up = watcher.subscribe();
+                    if !*up.borrow() {
+                        continue;
+                    }
+                    let upstream = upstream.clone();
+                    tokio::spawn(async move {
+                        let mut server = TcpStream::connect(upstream).await.unwrap();
+                        tokio::select! {
+                            _ = tokio::io::copy_bidirectional(&mut client, &mut server) => {}
+                            _ = up.wait_for(|up| !*up) => {}
+                        }
+                    });
+                }
+            });
+            Self { port, up }
+        }
+
+        fn set_up(&self, up: bool) {
+            self.up.send_replace(up);
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_queries_recover_after_database_restart(
+        _: PgPoolOptions,
+        connect_options: PgConnectOptions,
+    ) {
+        let proxy = FlakyProxy::start((
+            connect_options.get_host().to_string(),
+            connect_options.get_port(),
+        ))
+        .await;
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(500))
+            .connect_with(connect_options.host("127.0.0.1").port(proxy.port))
+            .await
+            .unwrap();
+        let db = Database::from_pool(pool).await.unwrap();
+
+        db.ping().await.unwrap();
+        let stats = db.pool_stats();
+        assert!(stats.size >= 1 && stats.acquire_wait_seconds.is_some());
+
+        // Down for longer than the retries last
+        proxy.set_up(false);
+        let err = db.swaps().get_active().await.unwrap_err();
+        assert!(err.is_transient(), "{err}");
+        assert!(db.ping().await.is_err());
+
+        // Back before they run out
+        let query = tokio::spawn({
+            let db = db.clone();
+            async move { db.swaps().get_active().await }
+        });
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        proxy.set_up(true);
+        assert!(query.await.unwrap().unwrap().is_empty());
+        db.ping().await.unwrap();
+    }
 }
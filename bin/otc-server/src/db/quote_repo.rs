@@ -1,11 +1,11 @@
 use chrono::{DateTime, Utc};
-use otc_models::Quote;
-use sqlx::postgres::PgPool;
+use otc_models::{Currency, Quote};
+use sqlx::postgres::{PgExecutor, PgPool};
 use uuid::Uuid;
 
 use crate::error::OtcServerResult;
 
-use super::conversions::lot_to_db;
+use super::conversions::{chain_id_to_db, chain_type_to_db, lot_to_db, token_identifier_to_json};
 use super::row_mappers::FromRow;
 
 #[derive(Clone)]
@@ -19,35 +19,47 @@ impl QuoteRepository {
     }
 
     pub async fn create(&self, quote: &Quote) -> OtcServerResult<()> {
-        let (from_chain, from_token, from_amount, from_decimals) = lot_to_db(&quote.from)?;
-        let (to_chain, to_token, to_amount, to_decimals) = lot_to_db(&quote.to)?;
+        Self::insert(&self.pool, quote).await
+    }
+
+    /// Insert `quote` through `executor`, so a swap's quote can be written in
+    /// the swap's own transaction
+    pub async fn insert<'e, E>(executor: E, quote: &Quote) -> OtcServerResult<()>
+    where
+        E: PgExecutor<'e>,
+    {
+        let (from_chain, from_chain_id, from_token, from_amount, from_decimals) =
+            lot_to_db(&quote.from)?;
+        let (to_chain, to_chain_id, to_token, to_amount, to_decimals) = lot_to_db(&quote.to)?;
 
         sqlx::query(
             r#"
             INSERT INTO quotes (
                 id, 
-                from_chain, from_token, from_amount, from_decimals,
-                to_chain, to_token, to_amount, to_decimals,
+                from_chain, from_chain_id, from_token, from_amount, from_decimals,
+                to_chain, to_chain_id, to_token, to_amount, to_decimals,
                 market_maker_id, 
                 expires_at, 
                 created_at
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             "#,
         )
         .bind(quote.id)
         .bind(from_chain)
+        .bind(from_chain_id)
         .bind(from_token)
         .bind(from_amount)
         .bind(from_decimals as i16)
         .bind(to_chain)
+        .bind(to_chain_id)
         .bind(to_token)
         .bind(to_amount)
         .bind(to_decimals as i16)
         .bind(quote.market_maker_id)
         .bind(quote.expires_at)
         .bind(quote.created_at)
-        .execute(&self.pool)
+        .execute(executor)
         .await?;
 
         Ok(())
@@ -58,8 +70,8 @@ impl QuoteRepository {
             r#"
             SELECT 
                 id,
-                from_chain, from_token, from_amount, from_decimals,
-                to_chain, to_token, to_amount, to_decimals,
+                from_chain, from_chain_id, from_token, from_amount, from_decimals,
+                to_chain, to_chain_id, to_token, to_amount, to_decimals,
                 market_maker_id,
                 expires_at,
                 created_at
@@ -74,26 +86,29 @@ impl QuoteRepository {
         Quote::from_row(&row)
     }
 
+    /// Quotes of `market_maker_id` that haven't expired at `now`
     pub async fn get_active_by_market_maker(
         &self,
         market_maker_id: Uuid,
+        now: DateTime<Utc>,
     ) -> OtcServerResult<Vec<Quote>> {
         let rows = sqlx::query(
             r#"
             SELECT 
                 id,
-                from_chain, from_token, from_amount, from_decimals,
-                to_chain, to_token, to_amount, to_decimals,
+                from_chain, from_chain_id, from_token, from_amount, from_decimals,
+                to_chain, to_chain_id, to_token, to_amount, to_decimals,
                 market_maker_id,
                 expires_at,
                 created_at
             FROM quotes
             WHERE market_maker_id = $1 
-            AND expires_at > NOW()
+            AND expires_at > $2
             ORDER BY created_at DESC
             "#,
         )
         .bind(market_maker_id)
+        .bind(now)
         .fetch_all(&self.pool)
         .await?;
 
@@ -105,22 +120,71 @@ impl QuoteRepository {
         Ok(quotes)
     }
 
-    pub async fn get_expired(&self, limit: i64) -> OtcServerResult<Vec<Quote>> {
+    /// Quotes for the `from` to `to` pair issued after `issued_after`, newest
+    /// first. Token addresses match in any case, older rows may not be checksummed
+    pub async fn get_recent_for_pair(
+        &self,
+        from: &Currency,
+        to: &Currency,
+        issued_after: DateTime<Utc>,
+        limit: i64,
+    ) -> OtcServerResult<Vec<Quote>> {
         let rows = sqlx::query(
             r#"
             SELECT 
                 id,
-                from_chain, from_token, from_amount, from_decimals,
-                to_chain, to_token, to_amount, to_decimals,
+                from_chain, from_chain_id, from_token, from_amount, from_decimals,
+                to_chain, to_chain_id, to_token, to_amount, to_decimals,
                 market_maker_id,
                 expires_at,
                 created_at
             FROM quotes
-            WHERE expires_at <= NOW()
+            WHERE from_chain = $1 AND from_chain_id IS NOT DISTINCT FROM $2
+            AND lower(from_token::text) = lower($3::text)
+            AND to_chain = $4 AND to_chain_id IS NOT DISTINCT FROM $5
+            AND lower(to_token::text) = lower($6::text)
+            AND created_at > $7
+            ORDER BY created_at DESC
+            LIMIT $8
+            "#,
+        )
+        .bind(chain_type_to_db(&from.chain))
+        .bind(chain_id_to_db(from.chain_id)?)
+        .bind(token_identifier_to_json(&from.token)?)
+        .bind(chain_type_to_db(&to.chain))
+        .bind(chain_id_to_db(to.chain_id)?)
+        .bind(token_identifier_to_json(&to.token)?)
+        .bind(issued_after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut quotes = Vec::new();
+        for row in rows {
+            quotes.push(Quote::from_row(&row)?);
+        }
+
+        Ok(quotes)
+    }
+
+    /// Up to `limit` quotes expired by `now`, longest expired first
+    pub async fn get_expired(&self, now: DateTime<Utc>, limit: i64) -> OtcServerResult<Vec<Quote>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT 
+                id,
+                from_chain, from_chain_id, from_token, from_amount, from_decimals,
+                to_chain, to_chain_id, to_token, to_amount, to_decimals,
+                market_maker_id,
+                expires_at,
+                created_at
+            FROM quotes
+            WHERE expires_at <= $1
             ORDER BY expires_at ASC
-            LIMIT $1
+            LIMIT $2
             "#,
         )
+        .bind(now)
         .bind(limit)
         .fetch_all(&self.pool)
         .await?;
@@ -154,7 +218,7 @@ mod tests {
     use crate::db::Database;
     use alloy::primitives::U256;
     use chrono::{Duration, Utc};
-    use otc_models::{ChainType, Currency, Lot, Quote, TokenIdentifier};
+    use otc_models::{ChainType, Currency, Lot, Quote, TokenIdentifier, CBBTC_ADDRESS};
     use uuid::Uuid;
 
     #[sqlx::test]
@@ -171,6 +235,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(1000000u64), // 0.01 BTC in sats
             },
@@ -179,6 +244,7 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(500000000000000000u64), // 0.5 ETH in wei
             },
@@ -243,6 +309,7 @@ mod tests {
                         "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
                     ), // USDC
                     decimals: 6,
+                    chain_id: None,
                 },
                 amount: U256::from(1000000000u64), // 1000 USDC (6 decimals)
             },
@@ -253,6 +320,7 @@ mod tests {
                         "0x6B175474E89094C44Da98b954EedeAC495271d0F".to_string(),
                     ), // DAI
                     decimals: 18,
+                    chain_id: Some(8453),
                 },
                 amount: U256::from(1000000000000000000000u128), // 1000 DAI (18 decimals)
             },
@@ -265,6 +333,10 @@ mod tests {
         quote_repo.create(&original_quote).await.unwrap();
         let retrieved_quote = quote_repo.get(original_quote.id).await.unwrap();
 
+        // Validate the networks are preserved
+        assert_eq!(retrieved_quote.from.currency.chain_id, None);
+        assert_eq!(retrieved_quote.to.currency.chain_id, Some(8453));
+
         // Validate token addresses are preserved
         match (&retrieved_quote.from.currency.token, &original_quote.from.currency.token) {
             (TokenIdentifier::Address(retrieved), TokenIdentifier::Address(original)) => {
@@ -283,6 +355,62 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn test_lowercase_token_rows_match_checksummed_pairs(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let quote_repo = db.quotes();
+
+        // A row written before addresses were checksummed
+        let bitcoin = Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        };
+        let cbbtc = |address: String| Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(address),
+            decimals: 8,
+            chain_id: None,
+        };
+        let quote = Quote {
+            id: Uuid::new_v4(),
+            from: Lot {
+                currency: bitcoin.clone(),
+                amount: U256::from(1_000_000u64),
+            },
+            to: Lot {
+                currency: cbbtc(CBBTC_ADDRESS.to_lowercase()),
+                amount: U256::from(999_000u64),
+            },
+            market_maker_id: Uuid::new_v4(),
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+        };
+        quote_repo.create(&quote).await.unwrap();
+
+        let recent = quote_repo
+            .get_recent_for_pair(
+                &bitcoin,
+                &cbbtc(CBBTC_ADDRESS.to_string()),
+                Utc::now() - Duration::minutes(1),
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].id, quote.id);
+        // Read back in the canonical form
+        assert_eq!(
+            recent[0].to.currency.token,
+            TokenIdentifier::Address(CBBTC_ADDRESS.to_string())
+        );
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn test_quote_large_u256_values(pool: sqlx::PgPool) -> sqlx::Result<()> {
         // Database will auto-initialize with schema
@@ -303,6 +431,7 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: large_amount,
             },
@@ -311,6 +440,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(21000000u64) * U256::from(100000000u64), // 21M BTC in sats
             },
@@ -337,6 +467,7 @@ mod tests {
         let quote_repo = db.quotes();
 
         let mm_identifier = Uuid::new_v4();
+        let now = Utc::now();
 
         // Create multiple quotes - some expired, some active
         let expired_quote = Quote {
@@ -346,6 +477,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(100000u64),
             },
@@ -354,12 +486,13 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(1000000000000000000u64),
             },
             market_maker_id: mm_identifier,
-            expires_at: Utc::now() - Duration::hours(1), // Already expired
-            created_at: Utc::now() - Duration::hours(2),
+            expires_at: now - Duration::hours(1), // Already expired
+            created_at: now - Duration::hours(2),
         };
 
         let active_quote1 = Quote {
@@ -369,6 +502,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(200000u64),
             },
@@ -377,12 +511,13 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(2000000000000000000u64),
             },
             market_maker_id: mm_identifier,
-            expires_at: Utc::now() + Duration::minutes(30),
-            created_at: Utc::now(),
+            expires_at: now + Duration::minutes(30),
+            created_at: now,
         };
 
         let active_quote2 = Quote {
@@ -392,6 +527,7 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(3000000000000000000u64),
             },
@@ -400,12 +536,13 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(300000u64),
             },
             market_maker_id: mm_identifier,
-            expires_at: Utc::now() + Duration::hours(1),
-            created_at: Utc::now(),
+            expires_at: now + Duration::hours(1),
+            created_at: now,
         };
 
         // Store all quotes
@@ -415,7 +552,7 @@ mod tests {
 
         // Get active quotes
         let active_quotes = quote_repo
-            .get_active_by_market_maker(mm_identifier)
+            .get_active_by_market_maker(mm_identifier, now)
             .await
             .unwrap();
 
@@ -427,6 +564,14 @@ mod tests {
         assert!(active_ids.contains(&active_quote2.id));
         assert!(!active_ids.contains(&expired_quote.id));
 
+        // A quote stops being active the moment it expires
+        let active_quotes = quote_repo
+            .get_active_by_market_maker(mm_identifier, active_quote1.expires_at)
+            .await
+            .unwrap();
+        let active_ids: Vec<Uuid> = active_quotes.iter().map(|q| q.id).collect();
+        assert_eq!(active_ids, vec![active_quote2.id]);
+
         Ok(())
     }
 }
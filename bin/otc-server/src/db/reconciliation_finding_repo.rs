@@ -0,0 +1,221 @@
+use chrono::{DateTime, Utc};
+use otc_models::{ChainType, SwapStatus};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::Row;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::conversions::{chain_type_from_db, chain_type_to_db};
+use crate::error::{OtcServerError, OtcServerResult};
+
+/// How a terminal swap's record disagrees with the chain
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// The recorded deposit tx isn't in a block
+    TxMissing,
+    /// The deposit moved a different amount than recorded, or less than quoted
+    AmountDiffers,
+    /// Funds sit at the deposit address of a swap that should have none
+    UnexpectedBalance,
+}
+
+/// Which deposit of the swap a finding is about
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositLeg {
+    UserDeposit,
+    MmDeposit,
+}
+
+impl FindingKind {
+    fn as_db(self) -> &'static str {
+        match self {
+            Self::TxMissing => "tx_missing",
+            Self::AmountDiffers => "amount_differs",
+            Self::UnexpectedBalance => "unexpected_balance",
+        }
+    }
+
+    fn from_db(s: &str) -> OtcServerResult<Self> {
+        match s {
+            "tx_missing" => Ok(Self::TxMissing),
+            "amount_differs" => Ok(Self::AmountDiffers),
+            "unexpected_balance" => Ok(Self::UnexpectedBalance),
+            _ => Err(OtcServerError::InvalidData {
+                message: format!("Unknown reconciliation finding kind: {s}"),
+            }),
+        }
+    }
+}
+
+impl DepositLeg {
+    fn as_db(self) -> &'static str {
+        match self {
+            Self::UserDeposit => "user_deposit",
+            Self::MmDeposit => "mm_deposit",
+        }
+    }
+
+    fn from_db(s: &str) -> OtcServerResult<Self> {
+        match s {
+            "user_deposit" => Ok(Self::UserDeposit),
+            "mm_deposit" => Ok(Self::MmDeposit),
+            _ => Err(OtcServerError::InvalidData {
+                message: format!("Unknown deposit leg: {s}"),
+            }),
+        }
+    }
+}
+
+/// A mismatch the reconciliation job saw, before it's recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    pub kind: FindingKind,
+    pub leg: DepositLeg,
+    pub chain: ChainType,
+    pub tx_hash: Option<String>,
+    pub detail: String,
+}
+
+/// A recorded mismatch, open until a later run no longer sees it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ReconciliationFinding {
+    pub id: i64,
+    pub swap_id: Uuid,
+    /// Status of the swap when the mismatch was last seen
+    pub swap_status: SwapStatus,
+    pub kind: FindingKind,
+    pub leg: DepositLeg,
+    pub chain: ChainType,
+    pub tx_hash: Option<String>,
+    pub detail: String,
+    pub detected_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl ReconciliationFinding {
+    fn from_row(row: &PgRow) -> OtcServerResult<Self> {
+        let kind: String = row.try_get("kind")?;
+        let leg: String = row.try_get("leg")?;
+        let chain: String = row.try_get("chain")?;
+        Ok(Self {
+            id: row.try_get("id")?,
+            swap_id: row.try_get("swap_id")?,
+            swap_status: row.try_get("swap_status")?,
+            kind: FindingKind::from_db(&kind)?,
+            leg: DepositLeg::from_db(&leg)?,
+            chain: chain_type_from_db(&chain)?,
+            tx_hash: row.try_get("tx_hash")?,
+            detail: row.try_get("detail")?,
+            detected_at: row.try_get("detected_at")?,
+            last_seen_at: row.try_get("last_seen_at")?,
+            resolved_at: row.try_get("resolved_at")?,
+        })
+    }
+}
+
+/// Findings of the settlement reconciliation job. Rows are never deleted, so
+/// the table is the audit trail of every mismatch seen
+#[derive(Clone)]
+pub struct ReconciliationFindingRepository {
+    pool: PgPool,
+}
+
+impl ReconciliationFindingRepository {
+    #[must_use]
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Record what a run saw for one swap: new mismatches are opened, ones
+    /// still open are marked seen again and open ones no longer seen are
+    /// resolved. Returns the mismatches that weren't open before
+    pub async fn record_check(
+        &self,
+        swap_id: Uuid,
+        swap_status: SwapStatus,
+        discrepancies: &[Discrepancy],
+        now: DateTime<Utc>,
+    ) -> OtcServerResult<Vec<Discrepancy>> {
+        let mut tx = self.pool.begin().await?;
+        let mut opened = Vec::new();
+        for discrepancy in discrepancies {
+            let inserted: bool = sqlx::query_scalar(
+                r"
+                INSERT INTO reconciliation_findings (
+                    swap_id, swap_status, kind, leg, chain, tx_hash, detail,
+                    detected_at, last_seen_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+                ON CONFLICT (swap_id, kind, leg) WHERE resolved_at IS NULL
+                DO UPDATE SET
+                    swap_status = EXCLUDED.swap_status,
+                    tx_hash = EXCLUDED.tx_hash,
+                    detail = EXCLUDED.detail,
+                    last_seen_at = EXCLUDED.last_seen_at
+                RETURNING (xmax = 0)
+                ",
+            )
+            .bind(swap_id)
+            .bind(swap_status)
+            .bind(discrepancy.kind.as_db())
+            .bind(discrepancy.leg.as_db())
+            .bind(chain_type_to_db(&discrepancy.chain))
+            .bind(&discrepancy.tx_hash)
+            .bind(&discrepancy.detail)
+            .bind(now)
+            .fetch_one(&mut *tx)
+            .await?;
+            if inserted {
+                opened.push(discrepancy.clone());
+            }
+        }
+
+        let seen: Vec<String> = discrepancies
+            .iter()
+            .map(|d| format!("{}:{}", d.kind.as_db(), d.leg.as_db()))
+            .collect();
+        sqlx::query(
+            r"
+            UPDATE reconciliation_findings
+            SET resolved_at = $3
+            WHERE swap_id = $1
+              AND resolved_at IS NULL
+              AND kind || ':' || leg <> ALL($2)
+            ",
+        )
+        .bind(swap_id)
+        .bind(&seen)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(opened)
+    }
+
+    /// Findings not resolved yet, most recently detected first
+    pub async fn get_open(&self) -> OtcServerResult<Vec<ReconciliationFinding>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                id, swap_id, swap_status, kind, leg, chain, tx_hash, detail,
+                detected_at, last_seen_at, resolved_at
+            FROM reconciliation_findings
+            WHERE resolved_at IS NULL
+            ORDER BY detected_at DESC, id DESC
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(ReconciliationFinding::from_row).collect()
+    }
+}
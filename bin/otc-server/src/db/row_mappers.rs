@@ -2,14 +2,14 @@ use std::str::FromStr;
 
 use alloy::primitives::Address;
 use chrono::{DateTime, Utc};
-use otc_models::{Quote, Swap, SwapStatus};
+use otc_models::{ConfirmationOverride, Quote, Swap, SwapEvent, SwapStatus};
 use sqlx::postgres::PgRow;
 use sqlx::Row;
 use uuid::Uuid;
 
 use super::conversions::{
-    lot_from_db, mm_deposit_status_from_json, settlement_status_from_json,
-    user_deposit_status_from_json,
+    chain_type_from_db, confirmation_rule_from_db, lot_from_db, mm_deposit_status_from_json,
+    settlement_status_from_json, user_deposit_status_from_json,
 };
 use crate::error::{OtcServerError, OtcServerResult};
 
@@ -21,10 +21,12 @@ impl<'r> FromRow<'r> for Quote {
     fn from_row(row: &'r PgRow) -> OtcServerResult<Self> {
         let id: Uuid = row.try_get("id")?;
         let from_chain: String = row.try_get("from_chain")?;
+        let from_chain_id: Option<i64> = row.try_get("from_chain_id")?;
         let from_token: serde_json::Value = row.try_get("from_token")?;
         let from_amount: String = row.try_get("from_amount")?;
         let from_decimals: i16 = row.try_get("from_decimals")?;
         let to_chain: String = row.try_get("to_chain")?;
+        let to_chain_id: Option<i64> = row.try_get("to_chain_id")?;
         let to_token: serde_json::Value = row.try_get("to_token")?;
         let to_amount: String = row.try_get("to_amount")?;
         let to_decimals: i16 = row.try_get("to_decimals")?;
@@ -32,8 +34,20 @@ impl<'r> FromRow<'r> for Quote {
         let expires_at: DateTime<Utc> = row.try_get("expires_at")?;
         let created_at: DateTime<Utc> = row.try_get("created_at")?;
 
-        let from = lot_from_db(from_chain, from_token, from_amount, from_decimals as u8)?;
-        let to = lot_from_db(to_chain, to_token, to_amount, to_decimals as u8)?;
+        let from = lot_from_db(
+            from_chain,
+            from_chain_id,
+            from_token,
+            from_amount,
+            from_decimals as u8,
+        )?;
+        let to = lot_from_db(
+            to_chain,
+            to_chain_id,
+            to_token,
+            to_amount,
+            to_decimals as u8,
+        )?;
 
         Ok(Quote {
             id,
@@ -76,10 +90,12 @@ impl<'r> FromRow<'r> for Swap {
         // Get the embedded quote fields
         let quote_id: Uuid = row.try_get("quote_id")?;
         let from_chain: String = row.try_get("from_chain")?;
+        let from_chain_id: Option<i64> = row.try_get("from_chain_id")?;
         let from_token: serde_json::Value = row.try_get("from_token")?;
         let from_amount: String = row.try_get("from_amount")?;
         let from_decimals: i16 = row.try_get("from_decimals")?;
         let to_chain: String = row.try_get("to_chain")?;
+        let to_chain_id: Option<i64> = row.try_get("to_chain_id")?;
         let to_token: serde_json::Value = row.try_get("to_token")?;
         let to_amount: String = row.try_get("to_amount")?;
         let to_decimals: i16 = row.try_get("to_decimals")?;
@@ -87,8 +103,20 @@ impl<'r> FromRow<'r> for Swap {
         let expires_at: DateTime<Utc> = row.try_get("expires_at")?;
         let quote_created_at: DateTime<Utc> = row.try_get("quote_created_at")?;
 
-        let from = lot_from_db(from_chain, from_token, from_amount, from_decimals as u8)?;
-        let to = lot_from_db(to_chain, to_token, to_amount, to_decimals as u8)?;
+        let from = lot_from_db(
+            from_chain,
+            from_chain_id,
+            from_token,
+            from_amount,
+            from_decimals as u8,
+        )?;
+        let to = lot_from_db(
+            to_chain,
+            to_chain_id,
+            to_token,
+            to_amount,
+            to_decimals as u8,
+        )?;
 
         let quote = Quote {
             id: quote_id,
@@ -100,19 +128,25 @@ impl<'r> FromRow<'r> for Swap {
         };
 
         let user_deposit_address: String = row.try_get("user_deposit_address")?;
+        let master_key_version: i32 = row.try_get("master_key_version")?;
         let user_destination_address: String = row.try_get("user_destination_address")?;
         let status: SwapStatus = row.try_get("status")?;
+        let user_required_confirmations: i32 = row.try_get("user_required_confirmations")?;
+        let mm_required_confirmations: i32 = row.try_get("mm_required_confirmations")?;
 
         // Handle JSONB fields
         let user_deposit_json: Option<serde_json::Value> = row.try_get("user_deposit_status")?;
         let user_deposit_status = match user_deposit_json {
-            Some(json) => Some(user_deposit_status_from_json(json)?),
+            Some(json) => Some(user_deposit_status_from_json(
+                json,
+                quote.from.currency.chain,
+            )?),
             None => None,
         };
 
         let mm_deposit_json: Option<serde_json::Value> = row.try_get("mm_deposit_status")?;
         let mm_deposit_status = match mm_deposit_json {
-            Some(json) => Some(mm_deposit_status_from_json(json)?),
+            Some(json) => Some(mm_deposit_status_from_json(json, quote.to.currency.chain)?),
             None => None,
         };
 
@@ -125,8 +159,13 @@ impl<'r> FromRow<'r> for Swap {
         let failure_reason: Option<String> = row.try_get("failure_reason")?;
         let failure_at: Option<DateTime<Utc>> = row.try_get("failure_at")?;
         let mm_notified_at: Option<DateTime<Utc>> = row.try_get("mm_notified_at")?;
+        let mm_deposit_detected_at: Option<DateTime<Utc>> =
+            row.try_get("mm_deposit_detected_at")?;
         let mm_private_key_sent_at: Option<DateTime<Utc>> =
             row.try_get("mm_private_key_sent_at")?;
+        let user_refund_address: Option<String> = row.try_get("user_refund_address")?;
+        let trace_id: Option<String> = row.try_get("trace_id")?;
+        let external_reference: Option<String> = row.try_get("external_reference")?;
         let created_at: DateTime<Utc> = row.try_get("created_at")?;
         let updated_at: DateTime<Utc> = row.try_get("updated_at")?;
 
@@ -139,19 +178,69 @@ impl<'r> FromRow<'r> for Swap {
             quote,
             user_deposit_salt,
             user_deposit_address,
+            master_key_version: master_key_version as u32,
             mm_nonce,
             user_destination_address,
             user_evm_account_address,
+            user_refund_address,
             status,
+            user_required_confirmations: user_required_confirmations as u64,
+            mm_required_confirmations: mm_required_confirmations as u64,
             user_deposit_status,
             mm_deposit_status,
             settlement_status,
             failure_reason,
             failure_at,
             mm_notified_at,
+            mm_deposit_detected_at,
             mm_private_key_sent_at,
+            trace_id,
+            external_reference,
             created_at,
             updated_at,
         })
     }
 }
+
+impl<'r> FromRow<'r> for ConfirmationOverride {
+    fn from_row(row: &'r PgRow) -> OtcServerResult<Self> {
+        let id: Uuid = row.try_get("id")?;
+        let chain: String = row.try_get("chain")?;
+        let multiplier: Option<f64> = row.try_get("multiplier")?;
+        let absolute_confirmations: Option<i32> = row.try_get("absolute_confirmations")?;
+        let baseline_confirmations: i32 = row.try_get("baseline_confirmations")?;
+        let required_confirmations: i32 = row.try_get("required_confirmations")?;
+        let reason: String = row.try_get("reason")?;
+        let expires_at: DateTime<Utc> = row.try_get("expires_at")?;
+        let created_at: DateTime<Utc> = row.try_get("created_at")?;
+
+        Ok(ConfirmationOverride {
+            id,
+            chain: chain_type_from_db(&chain)?,
+            rule: confirmation_rule_from_db(multiplier, absolute_confirmations)?,
+            baseline_confirmations: baseline_confirmations as u64,
+            required_confirmations: required_confirmations as u64,
+            reason,
+            expires_at,
+            created_at,
+        })
+    }
+}
+
+impl<'r> FromRow<'r> for SwapEvent {
+    fn from_row(row: &'r PgRow) -> OtcServerResult<Self> {
+        let swap_id: Uuid = row.try_get("swap_id")?;
+        let from_status: Option<SwapStatus> = row.try_get("from_status")?;
+        let to_status: SwapStatus = row.try_get("to_status")?;
+        let occurred_at: DateTime<Utc> = row.try_get("occurred_at")?;
+        let note: Option<String> = row.try_get("note")?;
+
+        Ok(SwapEvent {
+            swap_id,
+            from_status,
+            to_status,
+            occurred_at,
+            note,
+        })
+    }
+}
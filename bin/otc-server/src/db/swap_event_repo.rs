@@ -0,0 +1,63 @@
+use otc_models::{SwapEvent, SwapStatus};
+use sqlx::postgres::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use super::row_mappers::FromRow;
+use crate::error::OtcServerResult;
+
+#[derive(Clone)]
+pub struct SwapEventRepository {
+    pool: PgPool,
+}
+
+impl SwapEventRepository {
+    #[must_use]
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Record a status change. Takes an executor so the event is written in the
+    /// same transaction as the swap row it describes.
+    pub async fn record<'e, E>(
+        executor: E,
+        swap_id: Uuid,
+        from_status: Option<SwapStatus>,
+        to_status: SwapStatus,
+        note: Option<&str>,
+    ) -> OtcServerResult<()>
+    where
+        E: PgExecutor<'e>,
+    {
+        sqlx::query(
+            r"
+            INSERT INTO swap_events (swap_id, from_status, to_status, note)
+            VALUES ($1, $2, $3, $4)
+            ",
+        )
+        .bind(swap_id)
+        .bind(from_status)
+        .bind(to_status)
+        .bind(note)
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every recorded status change for a swap, oldest first
+    pub async fn get_for_swap(&self, swap_id: Uuid) -> OtcServerResult<Vec<SwapEvent>> {
+        let rows = sqlx::query(
+            r"
+            SELECT swap_id, from_status, to_status, occurred_at, note
+            FROM swap_events
+            WHERE swap_id = $1
+            ORDER BY id ASC
+            ",
+        )
+        .bind(swap_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(SwapEvent::from_row).collect()
+    }
+}
@@ -1,24 +1,95 @@
-use otc_models::{MMDepositStatus, SettlementStatus, Swap, SwapStatus, UserDepositStatus};
-use sqlx::postgres::PgPool;
+use alloy::primitives::U256;
+use blockchain_utils::{FeeCalcFromLot, PROTOCOL_FEE_BPS};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, TryStreamExt};
+use otc_models::{
+    FillCost, Lot, MMDepositStatus, SettlementStatus, Swap, SwapStatus, TransitionResult, TxHash,
+    UserDepositStatus,
+};
+use sqlx::postgres::{PgPool, Postgres};
+use sqlx::{Row, Transaction};
+use std::collections::BTreeMap;
+use tracing::debug;
 use uuid::Uuid;
 
 use super::conversions::{
-    mm_deposit_status_to_json, settlement_status_to_json, user_deposit_status_to_json,
+    fill_cost_from_json, fill_cost_to_json, mm_deposit_status_to_json, settlement_status_to_json,
+    tx_hash_from_db, u256_from_db, u256_to_db, user_deposit_status_to_json,
 };
 use super::row_mappers::FromRow;
-use crate::db::quote_repo::QuoteRepository;
+use super::swap_event_repo::SwapEventRepository;
+use crate::db::{quote_repo::QuoteRepository, with_retry};
 use crate::error::{OtcServerError, OtcServerResult};
 
+/// Times a state transition is re-read and re-applied after losing a race with
+/// another writer before giving up with a conflict
+const TRANSITION_ATTEMPTS: u32 = 5;
+
+/// Unique index that keeps two swaps from sharing a deposit address
+const DEPOSIT_ADDRESS_INDEX: &str = "idx_swaps_user_deposit_address";
+
+/// Unique index that keeps two swaps from sharing an MM nonce
+const MM_NONCE_INDEX: &str = "idx_swaps_mm_nonce";
+
+/// Unique index that keeps one MM payment from settling two swaps
+const MM_FILL_INDEX: &str = "idx_swaps_mm_fill";
+
+/// What the MM reported for its deposit, kept apart from what the chain showed
+#[derive(Debug, Clone, PartialEq)]
+pub struct MMClaimedDeposit {
+    pub tx_hash: TxHash,
+    /// In the chain's native currency
+    pub fee: Option<U256>,
+    pub fill_cost: Option<FillCost>,
+}
+
+/// The MM's latest deposit claim and how often it was asked to pay again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MMDepositAttempts {
+    pub claimed_tx_hash: Option<TxHash>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub retries: u32,
+    pub retry_requested_at: Option<DateTime<Utc>>,
+}
+
+/// A swap with what the accounting export reports next to it
+#[derive(Debug, Clone)]
+pub struct SwapReportEntry {
+    pub swap: Swap,
+    /// When the swap first moved to settled, `None` if it never has
+    pub settled_at: Option<DateTime<Utc>>,
+    pub mm_fill_cost: Option<FillCost>,
+    /// Protocol fee rate the swap was created under
+    pub protocol_fee_bps: u64,
+    /// Protocol fee charged on the MM's payout, `None` past the amounts the
+    /// fee math can take
+    pub protocol_fee: Option<U256>,
+}
+
+/// The longest standing swap in a status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OldestSwap {
+    pub status: SwapStatus,
+    pub swap_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Protocol fee owed on `lot`, `None` past the amounts the sats based fee
+/// math can take without overflowing
+fn protocol_fee(lot: &Lot) -> Option<U256> {
+    u64::try_from(lot.amount).ok()?.checked_mul(10_000)?;
+    Some(U256::from(lot.compute_protocol_fee()))
+}
+
 #[derive(Clone)]
 pub struct SwapRepository {
     pool: PgPool,
-    quote_repo: QuoteRepository,
 }
 
 impl SwapRepository {
     #[must_use]
-    pub fn new(pool: PgPool, quote_repo: QuoteRepository) -> Self {
-        Self { pool, quote_repo }
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
     }
 
     pub async fn create(&self, swap: &Swap) -> OtcServerResult<()> {
@@ -35,23 +106,23 @@ impl SwapRepository {
             None => None,
         };
 
-        self.quote_repo.create(&swap.quote).await?;
-
+        let mut tx = self.pool.begin().await?;
+        QuoteRepository::insert(&mut *tx, &swap.quote).await?;
         sqlx::query(
             r"
             INSERT INTO swaps (
                 id, quote_id, market_maker_id,
-                user_deposit_salt, user_deposit_address, mm_nonce,
-                user_destination_address, user_evm_account_address,
-                status,
+                user_deposit_salt, user_deposit_address, master_key_version, mm_nonce,
+                user_destination_address, user_evm_account_address, user_refund_address,
+                status, user_required_confirmations, mm_required_confirmations,
                 user_deposit_status, mm_deposit_status, settlement_status,
                 failure_reason, failure_at,
-                mm_notified_at, mm_private_key_sent_at,
-                created_at, updated_at
+                mm_notified_at, mm_deposit_detected_at, mm_private_key_sent_at, trace_id,
+                external_reference, created_at, updated_at, protocol_fee_bps, protocol_fee
             )
             VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8,
-                $9, $10, $11, $12, $13, $14, $15, $16, $17, $18
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14,
+                $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27
             )
             ",
         )
@@ -60,40 +131,75 @@ impl SwapRepository {
         .bind(swap.market_maker_id)
         .bind(&swap.user_deposit_salt[..])
         .bind(&swap.user_deposit_address)
+        .bind(swap.master_key_version as i32)
         .bind(&swap.mm_nonce[..])
         .bind(&swap.user_destination_address)
         .bind(swap.user_evm_account_address.to_string())
+        .bind(&swap.user_refund_address)
         .bind(swap.status)
+        .bind(swap.user_required_confirmations as i32)
+        .bind(swap.mm_required_confirmations as i32)
         .bind(user_deposit_json)
         .bind(mm_deposit_json)
         .bind(settlement_json)
         .bind(&swap.failure_reason)
         .bind(swap.failure_at)
         .bind(swap.mm_notified_at)
+        .bind(swap.mm_deposit_detected_at)
         .bind(swap.mm_private_key_sent_at)
+        .bind(&swap.trace_id)
+        .bind(&swap.external_reference)
         .bind(swap.created_at)
         .bind(swap.updated_at)
-        .execute(&self.pool)
-        .await?;
+        .bind(PROTOCOL_FEE_BPS as i32)
+        .bind(protocol_fee(&swap.quote.to).map(|fee| u256_to_db(&fee)))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.constraint() == Some(DEPOSIT_ADDRESS_INDEX) => {
+                OtcServerError::DepositAddressInUse {
+                    address: swap.user_deposit_address.clone(),
+                }
+            }
+            sqlx::Error::Database(db_err) if db_err.constraint() == Some(MM_NONCE_INDEX) => {
+                OtcServerError::MmNonceInUse {
+                    nonce: alloy::hex::encode(swap.mm_nonce),
+                }
+            }
+            e => e.into(),
+        })?;
+
+        SwapEventRepository::record(&mut *tx, swap.id, None, swap.status, None).await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
     pub async fn get(&self, id: Uuid) -> OtcServerResult<Swap> {
+        Ok(self.get_versioned(id).await?.0)
+    }
+
+    /// The swap along with the version its row is at, for [`Self::update`]
+    pub async fn get_versioned(&self, id: Uuid) -> OtcServerResult<(Swap, i64)> {
+        with_retry(|| self.try_get_versioned(id)).await
+    }
+
+    async fn try_get_versioned(&self, id: Uuid) -> OtcServerResult<(Swap, i64)> {
         let row = sqlx::query(
             r"
             SELECT 
                 s.id, s.quote_id, s.market_maker_id,
-                s.user_deposit_salt, s.user_deposit_address, s.mm_nonce,
-                s.user_destination_address, s.user_evm_account_address,
-                s.status,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
                 s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
                 s.failure_reason, s.failure_at,
-                s.mm_notified_at, s.mm_private_key_sent_at,
-                s.created_at, s.updated_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at, s.version,
                 -- Quote fields
-                q.id as quote_id, q.from_chain, q.from_token, q.from_amount, q.from_decimals,
-                q.to_chain, q.to_token, q.to_amount, q.to_decimals,
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
                 q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
             FROM swaps s
             JOIN quotes q ON s.quote_id = q.id
@@ -104,25 +210,47 @@ impl SwapRepository {
         .fetch_one(&self.pool)
         .await?;
 
-        Swap::from_row(&row)
+        Ok((Swap::from_row(&row)?, row.try_get("version")?))
     }
 
     pub async fn update_status(&self, id: Uuid, status: SwapStatus) -> OtcServerResult<()> {
+        let mut tx = self.pool.begin().await?;
+        let previous_status = Self::lock_status(&mut tx, id).await?;
+
         sqlx::query(
             r"
             UPDATE swaps
-            SET status = $2, updated_at = NOW()
+            SET status = $2, updated_at = NOW(), version = version + 1
             WHERE id = $1
             ",
         )
         .bind(id)
         .bind(status)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        if previous_status != status {
+            SwapEventRepository::record(&mut *tx, id, Some(previous_status), status, None).await?;
+        }
+        tx.commit().await?;
+
         Ok(())
     }
 
+    /// Current status of a swap, locking the row until the transaction ends so
+    /// concurrent transitions are recorded in the order they are applied
+    async fn lock_status(
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> OtcServerResult<SwapStatus> {
+        let status = sqlx::query_scalar("SELECT status FROM swaps WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await?;
+
+        Ok(status)
+    }
+
     pub async fn update_user_deposit(
         &self,
         id: Uuid,
@@ -135,7 +263,8 @@ impl SwapRepository {
             UPDATE swaps
             SET 
                 user_deposit_status = $2,
-                updated_at = NOW()
+                updated_at = NOW(),
+                version = version + 1
             WHERE id = $1
             ",
         )
@@ -159,7 +288,8 @@ impl SwapRepository {
             UPDATE swaps
             SET 
                 mm_deposit_status = $2,
-                updated_at = NOW()
+                updated_at = NOW(),
+                version = version + 1
             WHERE id = $1
             ",
         )
@@ -183,7 +313,8 @@ impl SwapRepository {
             UPDATE swaps
             SET 
                 settlement_status = $2,
-                updated_at = NOW()
+                updated_at = NOW(),
+                version = version + 1
             WHERE id = $1
             ",
         )
@@ -195,25 +326,179 @@ impl SwapRepository {
         Ok(())
     }
 
+    /// Record the tx hash, fee and fill cost the MM reported for its deposit,
+    /// for reconciling against what the chain shows
+    pub async fn record_mm_claimed_deposit(
+        &self,
+        id: Uuid,
+        tx_hash: &TxHash,
+        fee: Option<U256>,
+        fill_cost: Option<&FillCost>,
+    ) -> OtcServerResult<()> {
+        let fill_cost_json = fill_cost.map(fill_cost_to_json).transpose()?;
+        sqlx::query(
+            r"
+            UPDATE swaps
+            SET
+                mm_claimed_tx_hash = $2,
+                mm_claimed_fee = $3,
+                mm_claimed_fill_cost = $4,
+                mm_claimed_at = NOW(),
+                updated_at = NOW(),
+                version = version + 1
+            WHERE id = $1
+            ",
+        )
+        .bind(id)
+        .bind(tx_hash.as_str())
+        .bind(fee.as_ref().map(u256_to_db))
+        .bind(fill_cost_json)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// What the MM reported for its deposit, if it has
+    pub async fn mm_claimed_deposit(&self, id: Uuid) -> OtcServerResult<Option<MMClaimedDeposit>> {
+        let row: (Option<String>, Option<String>, Option<serde_json::Value>) = sqlx::query_as(
+            "SELECT mm_claimed_tx_hash, mm_claimed_fee, mm_claimed_fill_cost FROM swaps WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        match row {
+            (Some(tx_hash), fee, fill_cost) => Ok(Some(MMClaimedDeposit {
+                tx_hash: tx_hash_from_db(&tx_hash)?,
+                fee: fee.as_deref().map(u256_from_db).transpose()?,
+                fill_cost: fill_cost.map(fill_cost_from_json).transpose()?,
+            })),
+            (None, _, _) => Ok(None),
+        }
+    }
+
+    /// The MM's latest deposit claim next to the retries it was asked for
+    pub async fn mm_deposit_attempts(&self, id: Uuid) -> OtcServerResult<MMDepositAttempts> {
+        let (claimed_tx_hash, claimed_at, retries, retry_requested_at): (
+            Option<String>,
+            Option<DateTime<Utc>>,
+            i32,
+            Option<DateTime<Utc>>,
+        ) = sqlx::query_as(
+            r"
+            SELECT mm_claimed_tx_hash, mm_claimed_at, mm_deposit_retries, mm_deposit_retry_requested_at
+            FROM swaps
+            WHERE id = $1
+            ",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(MMDepositAttempts {
+            claimed_tx_hash: claimed_tx_hash
+                .as_deref()
+                .map(tx_hash_from_db)
+                .transpose()?,
+            claimed_at,
+            retries: retries as u32,
+            retry_requested_at,
+        })
+    }
+
+    /// Count another request for the MM to pay again, returning the retries so far
+    pub async fn record_mm_deposit_retry(
+        &self,
+        id: Uuid,
+        requested_at: DateTime<Utc>,
+    ) -> OtcServerResult<u32> {
+        let retries: i32 = sqlx::query_scalar(
+            r"
+            UPDATE swaps
+            SET
+                mm_deposit_retries = mm_deposit_retries + 1,
+                mm_deposit_retry_requested_at = $2,
+                updated_at = NOW(),
+                version = version + 1
+            WHERE id = $1
+            RETURNING mm_deposit_retries
+            ",
+        )
+        .bind(id)
+        .bind(requested_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(retries as u32)
+    }
+
+    /// Record what refunding the user costs in network fees and what that
+    /// leaves them
+    pub async fn record_user_refund_cost(
+        &self,
+        id: Uuid,
+        amount: U256,
+        fee: U256,
+    ) -> OtcServerResult<()> {
+        sqlx::query(
+            r"
+            UPDATE swaps
+            SET
+                user_refund_amount = $2,
+                user_refund_fee = $3,
+                updated_at = NOW(),
+                version = version + 1
+            WHERE id = $1
+            ",
+        )
+        .bind(id)
+        .bind(u256_to_db(&amount))
+        .bind(u256_to_db(&fee))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The amount refunded to the user and the fee it costs, once costed
+    pub async fn user_refund_cost(&self, id: Uuid) -> OtcServerResult<Option<(U256, U256)>> {
+        let row: (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT user_refund_amount, user_refund_fee FROM swaps WHERE id = $1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+
+        match row {
+            (Some(amount), Some(fee)) => Ok(Some((u256_from_db(&amount)?, u256_from_db(&fee)?))),
+            _ => Ok(None),
+        }
+    }
+
     pub async fn get_active_swaps(&self) -> OtcServerResult<Vec<Swap>> {
+        with_retry(|| self.try_get_active_swaps()).await
+    }
+
+    async fn try_get_active_swaps(&self) -> OtcServerResult<Vec<Swap>> {
         let rows = sqlx::query(
             r"
             SELECT 
                 s.id, s.quote_id, s.market_maker_id,
-                s.user_deposit_salt, s.user_deposit_address, s.mm_nonce,
-                s.user_destination_address, s.user_evm_account_address,
-                s.status,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
                 s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
                 s.failure_reason, s.failure_at,
-                s.mm_notified_at, s.mm_private_key_sent_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
                 s.created_at, s.updated_at,
                 -- Quote fields
-                q.id as quote_id, q.from_chain, q.from_token, q.from_amount, q.from_decimals,
-                q.to_chain, q.to_token, q.to_amount, q.to_decimals,
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
                 q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
             FROM swaps s
             JOIN quotes q ON s.quote_id = q.id
-            WHERE s.status NOT IN ('settled', 'failed')
+            WHERE s.status NOT IN ('settled', 'manual_review', 'failed')
             ORDER BY s.created_at DESC
             ",
         )
@@ -228,8 +513,45 @@ impl SwapRepository {
         Ok(swaps)
     }
 
-    /// Update entire swap record
-    pub async fn update(&self, swap: &Swap) -> OtcServerResult<()> {
+    /// Update entire swap record read at `version`, recording an event if the
+    /// status changed. Fails with a conflict if the swap was written since
+    pub async fn update(&self, swap: &Swap, version: i64) -> OtcServerResult<()> {
+        if self.update_with_note(swap, version, None).await? {
+            Ok(())
+        } else {
+            Err(OtcServerError::Conflict {
+                message: format!("Swap {} was updated concurrently", swap.id),
+            })
+        }
+    }
+
+    /// Like [`Self::update`], attaching `note` to the event for the status change.
+    /// `false` if the swap is no longer at `version`
+    async fn update_with_note(
+        &self,
+        swap: &Swap,
+        version: i64,
+        note: Option<&str>,
+    ) -> OtcServerResult<bool> {
+        // Only the statements are retried. A commit that fails may still have
+        // landed, and running the update again would then miss the version
+        let Some(tx) = with_retry(|| self.stage_update_with_note(swap, version, note)).await?
+        else {
+            return Ok(false);
+        };
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// The uncommitted update and its event, `None` if the swap is no longer
+    /// at `version`
+    async fn stage_update_with_note(
+        &self,
+        swap: &Swap,
+        version: i64,
+        note: Option<&str>,
+    ) -> OtcServerResult<Option<Transaction<'static, Postgres>>> {
         let user_deposit_json = swap
             .user_deposit_status
             .as_ref()
@@ -246,7 +568,10 @@ impl SwapRepository {
             .map(settlement_status_to_json)
             .transpose()?;
 
-        sqlx::query(
+        let mut tx = self.pool.begin().await?;
+        let previous_status = Self::lock_status(&mut tx, swap.id).await?;
+
+        let result = sqlx::query(
             r"
             UPDATE swaps
             SET 
@@ -257,9 +582,12 @@ impl SwapRepository {
                 failure_reason = $6,
                 failure_at = $7,
                 mm_notified_at = $8,
-                mm_private_key_sent_at = $9,
-                updated_at = $10
-            WHERE id = $1
+                mm_deposit_detected_at = $9,
+                mm_private_key_sent_at = $10,
+                updated_at = $11,
+                user_refund_address = $12,
+                version = version + 1
+            WHERE id = $1 AND version = $13
             ",
         )
         .bind(swap.id)
@@ -270,12 +598,41 @@ impl SwapRepository {
         .bind(&swap.failure_reason)
         .bind(swap.failure_at)
         .bind(swap.mm_notified_at)
+        .bind(swap.mm_deposit_detected_at)
         .bind(swap.mm_private_key_sent_at)
         .bind(swap.updated_at)
-        .execute(&self.pool)
-        .await?;
+        .bind(&swap.user_refund_address)
+        .bind(version)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.constraint() == Some(MM_FILL_INDEX) => {
+                OtcServerError::MmFillClaimed {
+                    tx_hash: swap
+                        .mm_deposit_status
+                        .as_ref()
+                        .map(|deposit| deposit.tx_hash.to_string())
+                        .unwrap_or_default(),
+                }
+            }
+            e => e.into(),
+        })?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
 
-        Ok(())
+        if previous_status != swap.status {
+            SwapEventRepository::record(
+                &mut *tx,
+                swap.id,
+                Some(previous_status),
+                swap.status,
+                note,
+            )
+            .await?;
+        }
+
+        Ok(Some(tx))
     }
 
     pub async fn get_swaps_by_market_maker(&self, mm_id: Uuid) -> OtcServerResult<Vec<Swap>> {
@@ -283,16 +640,17 @@ impl SwapRepository {
             r"
             SELECT 
                 s.id, s.quote_id, s.market_maker_id,
-                s.user_deposit_salt, s.user_deposit_address, s.mm_nonce,
-                s.user_destination_address, s.user_evm_account_address,
-                s.status,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
                 s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
                 s.failure_reason, s.failure_at,
-                s.mm_notified_at, s.mm_private_key_sent_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
                 s.created_at, s.updated_at,
                 -- Quote fields
-                q.id as quote_id, q.from_chain, q.from_token, q.from_amount, q.from_decimals,
-                q.to_chain, q.to_token, q.to_amount, q.to_decimals,
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
                 q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
             FROM swaps s
             JOIN quotes q ON s.quote_id = q.id
@@ -312,166 +670,623 @@ impl SwapRepository {
         Ok(swaps)
     }
 
-    /// Alias for `get_active_swaps` for consistency with monitoring service
-    pub async fn get_active(&self) -> OtcServerResult<Vec<Swap>> {
-        self.get_active_swaps().await
+    /// Swaps whose user deposit address is `address`, ignoring case
+    pub async fn find_by_deposit_address(&self, address: &str) -> OtcServerResult<Vec<Swap>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                s.id, s.quote_id, s.market_maker_id,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
+                s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
+                s.failure_reason, s.failure_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at,
+                -- Quote fields
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
+                q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
+            FROM swaps s
+            JOIN quotes q ON s.quote_id = q.id
+            WHERE lower(s.user_deposit_address) = lower($1)
+            ORDER BY s.created_at DESC
+            ",
+        )
+        .bind(address.trim())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut swaps = Vec::new();
+        for row in rows {
+            swaps.push(Swap::from_row(&row)?);
+        }
+
+        Ok(swaps)
     }
 
-    /// Update swap when user deposit is detected
-    pub async fn user_deposit_detected(
-        &self,
-        swap_id: Uuid,
-        deposit_status: UserDepositStatus,
-    ) -> OtcServerResult<()> {
-        // First get the swap
-        let mut swap = self.get(swap_id).await?;
-
-        // Apply the state transition
-        swap.user_deposit_detected(
-            deposit_status.tx_hash.clone(),
-            deposit_status.amount,
-            deposit_status.confirmations,
+    /// Swaps whose user or MM deposit was made in `tx_hash`. Rows written before
+    /// hashes were canonicalized hold either form, so only the digits compare
+    pub async fn find_by_tx_hash(&self, tx_hash: &TxHash) -> OtcServerResult<Vec<Swap>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                s.id, s.quote_id, s.market_maker_id,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
+                s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
+                s.failure_reason, s.failure_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at,
+                -- Quote fields
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
+                q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
+            FROM swaps s
+            JOIN quotes q ON s.quote_id = q.id
+            WHERE regexp_replace(lower(s.user_deposit_status->>'tx_hash'), '^0x', '') = $1
+               OR regexp_replace(lower(s.mm_deposit_status->>'tx_hash'), '^0x', '') = $1
+            ORDER BY s.created_at DESC
+            ",
         )
-        .map_err(|e| OtcServerError::InvalidState {
-            message: format!("State transition failed: {e}"),
-        })?;
+        .bind(tx_hash.digits())
+        .fetch_all(&self.pool)
+        .await?;
 
-        // Update the database
-        self.update(&swap).await?;
-        Ok(())
+        let mut swaps = Vec::new();
+        for row in rows {
+            swaps.push(Swap::from_row(&row)?);
+        }
+
+        Ok(swaps)
     }
 
-    /// Update swap when MM deposit is detected
-    pub async fn mm_deposit_detected(
-        &self,
-        swap_id: Uuid,
-        deposit_status: MMDepositStatus,
-    ) -> OtcServerResult<()> {
-        // First get the swap
-        let mut swap = self.get(swap_id).await?;
-
-        // Apply the state transition
-        swap.mm_deposit_detected(
-            deposit_status.tx_hash.clone(),
-            deposit_status.amount,
-            deposit_status.confirmations,
+    /// Swaps the user tagged with `reference`, newest first. References are
+    /// opaque, so only an exact match counts
+    pub async fn find_by_external_reference(&self, reference: &str) -> OtcServerResult<Vec<Swap>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                s.id, s.quote_id, s.market_maker_id,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
+                s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
+                s.failure_reason, s.failure_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at,
+                -- Quote fields
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
+                q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
+            FROM swaps s
+            JOIN quotes q ON s.quote_id = q.id
+            WHERE s.external_reference = $1
+            ORDER BY s.created_at DESC
+            ",
         )
-        .map_err(|e| OtcServerError::InvalidState {
-            message: format!("State transition failed: {e}"),
-        })?;
+        .bind(reference.trim())
+        .fetch_all(&self.pool)
+        .await?;
 
-        // Update the database
-        self.update(&swap).await?;
-        Ok(())
-    }
+        let mut swaps = Vec::new();
+        for row in rows {
+            swaps.push(Swap::from_row(&row)?);
+        }
 
-    /// Update user deposit confirmations
-    pub async fn update_user_confirmations(
-        &self,
-        swap_id: Uuid,
-        confirmations: u32,
-    ) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.update_confirmations(Some(confirmations as u64), None)
-            .map_err(|e| OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            })?;
-        self.update(&swap).await?;
-        Ok(())
+        Ok(swaps)
     }
 
-    /// Update MM deposit confirmations
-    pub async fn update_mm_confirmations(
+    /// Swaps in any of `statuses`, or all when empty, created in `[since, until)`,
+    /// oldest first
+    pub async fn find_for_recovery(
         &self,
-        swap_id: Uuid,
-        confirmations: u32,
-    ) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.update_confirmations(None, Some(confirmations as u64))
-            .map_err(|e| OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            })?;
-        self.update(&swap).await?;
-        Ok(())
-    }
+        statuses: &[SwapStatus],
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> OtcServerResult<Vec<Swap>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                s.id, s.quote_id, s.market_maker_id,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
+                s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
+                s.failure_reason, s.failure_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at,
+                -- Quote fields
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
+                q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
+            FROM swaps s
+            JOIN quotes q ON s.quote_id = q.id
+            WHERE ($1::swap_status[] IS NULL OR s.status = ANY($1))
+              AND ($2::timestamptz IS NULL OR s.created_at >= $2)
+              AND ($3::timestamptz IS NULL OR s.created_at < $3)
+            ORDER BY s.created_at ASC
+            ",
+        )
+        .bind((!statuses.is_empty()).then_some(statuses))
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
 
-    /// Update swap when user deposit is confirmed
-    pub async fn user_deposit_confirmed(&self, swap_id: Uuid) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.user_deposit_confirmed()
-            .map_err(|e| OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            })?;
-        self.update(&swap).await?;
-        Ok(())
+        let mut swaps = Vec::new();
+        for row in rows {
+            swaps.push(Swap::from_row(&row)?);
+        }
+
+        Ok(swaps)
     }
 
-    /// Update swap when MM deposit is confirmed
-    pub async fn mm_deposit_confirmed(&self, swap_id: Uuid) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.mm_deposit_confirmed()
-            .map_err(|e| OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            })?;
-        self.update(&swap).await?;
-        Ok(())
+    /// Swaps created in `[from, to)` in any of `statuses`, or all when empty,
+    /// and tagged with `external_reference` when set, oldest first. Pages of
+    /// `page_size` are only read as the stream is polled, so a long range is
+    /// never held in memory at once
+    pub fn stream_range(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        statuses: Vec<SwapStatus>,
+        external_reference: Option<String>,
+        page_size: usize,
+    ) -> impl Stream<Item = OtcServerResult<SwapReportEntry>> + Send + 'static {
+        let pool = self.pool.clone();
+        let page_size = page_size.max(1);
+        stream::try_unfold((None, false), move |(after, exhausted)| {
+            let pool = pool.clone();
+            let statuses = statuses.clone();
+            let external_reference = external_reference.clone();
+            async move {
+                if exhausted {
+                    return Ok::<_, OtcServerError>(None);
+                }
+                let page = Self::range_page(
+                    &pool,
+                    from,
+                    to,
+                    &statuses,
+                    external_reference.as_deref(),
+                    after,
+                    page_size,
+                )
+                .await?;
+                let next = page
+                    .last()
+                    .map(|entry| (entry.swap.created_at, entry.swap.id));
+                let exhausted = page.len() < page_size;
+                Ok(Some((
+                    stream::iter(page.into_iter().map(Ok)),
+                    (next, exhausted),
+                )))
+            }
+        })
+        .try_flatten()
     }
 
-    /// Mark private key as sent to MM
-    pub async fn mark_private_key_sent(&self, swap_id: Uuid) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.mark_private_key_sent()
-            .map_err(|e| OtcServerError::InvalidState {
+    /// The page of [`Self::stream_range`] following the `(created_at, id)` cursor
+    async fn range_page(
+        pool: &PgPool,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        statuses: &[SwapStatus],
+        external_reference: Option<&str>,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        page_size: usize,
+    ) -> OtcServerResult<Vec<SwapReportEntry>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                s.id, s.quote_id, s.market_maker_id,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
+                s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
+                s.failure_reason, s.failure_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at, s.mm_claimed_fill_cost,
+                s.protocol_fee_bps, s.protocol_fee,
+                (
+                    SELECT MIN(e.occurred_at) FROM swap_events e
+                    WHERE e.swap_id = s.id AND e.to_status = 'settled'
+                ) AS settled_at,
+                -- Quote fields
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
+                q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
+            FROM swaps s
+            JOIN quotes q ON s.quote_id = q.id
+            WHERE s.created_at >= $1
+              AND s.created_at < $2
+              AND ($3::swap_status[] IS NULL OR s.status = ANY($3))
+              AND ($4::timestamptz IS NULL OR (s.created_at, s.id) > ($4, $5::uuid))
+              AND ($7::text IS NULL OR s.external_reference = $7)
+            ORDER BY s.created_at ASC, s.id ASC
+            LIMIT $6
+            ",
+        )
+        .bind(from)
+        .bind(to)
+        .bind((!statuses.is_empty()).then_some(statuses))
+        .bind(after.map(|(created_at, _)| created_at))
+        .bind(after.map(|(_, id)| id))
+        .bind(i64::try_from(page_size).unwrap_or(i64::MAX))
+        .bind(external_reference.map(str::trim))
+        .fetch_all(pool)
+        .await?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            let fill_cost: Option<serde_json::Value> = row.try_get("mm_claimed_fill_cost")?;
+            let protocol_fee_bps: i32 = row.try_get("protocol_fee_bps")?;
+            let protocol_fee: Option<String> = row.try_get("protocol_fee")?;
+            entries.push(SwapReportEntry {
+                swap: Swap::from_row(&row)?,
+                settled_at: row.try_get("settled_at")?,
+                mm_fill_cost: fill_cost.map(fill_cost_from_json).transpose()?,
+                protocol_fee_bps: protocol_fee_bps as u64,
+                protocol_fee: protocol_fee.as_deref().map(u256_from_db).transpose()?,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Up to `limit` settled or failed swaps last updated at or after `since`, in
+    /// random order so repeated runs cover more than the same few
+    pub async fn sample_terminal(
+        &self,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> OtcServerResult<Vec<Swap>> {
+        let rows = sqlx::query(
+            r"
+            SELECT
+                s.id, s.quote_id, s.market_maker_id,
+                s.user_deposit_salt, s.user_deposit_address, s.master_key_version, s.mm_nonce,
+                s.user_destination_address, s.user_evm_account_address, s.user_refund_address,
+                s.status, s.user_required_confirmations, s.mm_required_confirmations,
+                s.user_deposit_status, s.mm_deposit_status, s.settlement_status,
+                s.failure_reason, s.failure_at,
+                s.mm_notified_at, s.mm_deposit_detected_at, s.mm_private_key_sent_at, s.trace_id,
+                s.external_reference,
+                s.created_at, s.updated_at,
+                -- Quote fields
+                q.id as quote_id, q.from_chain, q.from_chain_id, q.from_token, q.from_amount, q.from_decimals,
+                q.to_chain, q.to_chain_id, q.to_token, q.to_amount, q.to_decimals,
+                q.market_maker_id as quote_market_maker_id, q.expires_at, q.created_at as quote_created_at
+            FROM swaps s
+            JOIN quotes q ON s.quote_id = q.id
+            WHERE s.status IN ('settled', 'failed')
+              AND s.updated_at >= $1
+            ORDER BY random()
+            LIMIT $2
+            ",
+        )
+        .bind(since)
+        .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut swaps = Vec::new();
+        for row in rows {
+            swaps.push(Swap::from_row(&row)?);
+        }
+
+        Ok(swaps)
+    }
+
+    /// Alias for `get_active_swaps` for consistency with monitoring service
+    pub async fn get_active(&self) -> OtcServerResult<Vec<Swap>> {
+        self.get_active_swaps().await
+    }
+
+    /// Number of swaps per master key version that may still need their deposit
+    /// key, versions without any are left out. Besides active swaps that is every
+    /// failed swap the user paid into, since no refund of it is ever recorded
+    pub async fn count_active_by_master_key_version(&self) -> OtcServerResult<BTreeMap<u32, u64>> {
+        let rows: Vec<(i32, i64)> = sqlx::query_as(
+            r"
+            SELECT master_key_version, COUNT(*)
+            FROM swaps
+            WHERE status NOT IN ('settled', 'manual_review', 'failed')
+                OR (status = 'failed' AND user_deposit_status IS NOT NULL)
+            GROUP BY master_key_version
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(version, count)| (version as u32, count as u64))
+            .collect())
+    }
+
+    /// Number of swaps in each status in lifecycle order, statuses without any
+    /// are left out
+    pub async fn count_by_status(&self) -> OtcServerResult<Vec<(SwapStatus, u64)>> {
+        let rows: Vec<(SwapStatus, i64)> = sqlx::query_as(
+            r"
+            SELECT status, COUNT(*)
+            FROM swaps
+            GROUP BY status
+            ORDER BY status
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(status, count)| (status, count as u64))
+            .collect())
+    }
+
+    /// The earliest created swap in each active status in lifecycle order,
+    /// statuses without any are left out
+    pub async fn oldest_in_status(&self) -> OtcServerResult<Vec<OldestSwap>> {
+        let rows: Vec<(SwapStatus, Uuid, DateTime<Utc>)> = sqlx::query_as(
+            r"
+            SELECT DISTINCT ON (status) status, id, created_at
+            FROM swaps
+            WHERE status NOT IN ('settled', 'manual_review', 'failed')
+            ORDER BY status, created_at, id
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(status, swap_id, created_at)| OldestSwap {
+                status,
+                swap_id,
+                created_at,
+            })
+            .collect())
+    }
+
+    /// Deposit addresses held by more than one swap, with how many hold each.
+    /// The unique index keeps new ones out, this finds any from before it
+    pub async fn duplicate_deposit_addresses(&self) -> OtcServerResult<Vec<(String, u64)>> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            r"
+            SELECT lower(user_deposit_address), COUNT(*)
+            FROM swaps
+            GROUP BY lower(user_deposit_address)
+            HAVING COUNT(*) > 1
+            ORDER BY 1
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(address, count)| (address, count as u64))
+            .collect())
+    }
+
+    /// Apply `transition` to the stored swap and write it back, returning the
+    /// updated swap. A write that lost a race with another one is retried on
+    /// a fresh read, so neither side's change is lost
+    async fn transition(
+        &self,
+        swap_id: Uuid,
+        note: Option<&str>,
+        transition: impl Fn(&mut Swap) -> TransitionResult,
+    ) -> OtcServerResult<Swap> {
+        for attempt in 1..=TRANSITION_ATTEMPTS {
+            let (mut swap, version) = self.get_versioned(swap_id).await?;
+            transition(&mut swap).map_err(|e| OtcServerError::InvalidState {
                 message: format!("State transition failed: {e}"),
             })?;
-        self.update(&swap).await?;
+            if self.update_with_note(&swap, version, note).await? {
+                return Ok(swap);
+            }
+            debug!(
+                "Swap {} was updated concurrently (attempt {}/{}), retrying",
+                swap_id, attempt, TRANSITION_ATTEMPTS
+            );
+        }
+        Err(OtcServerError::Conflict {
+            message: format!(
+                "Swap {swap_id} kept being updated concurrently, gave up after {TRANSITION_ATTEMPTS} attempts"
+            ),
+        })
+    }
+
+    /// Update swap when user deposit is detected
+    pub async fn user_deposit_detected(
+        &self,
+        swap_id: Uuid,
+        deposit_status: UserDepositStatus,
+    ) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.user_deposit_detected(
+                deposit_status.tx_hash.clone(),
+                deposit_status.amount,
+                deposit_status.confirmations,
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Roll a swap back to waiting for the user deposit after a reorg dropped
+    /// it, `note` says why on the recorded event
+    pub async fn user_deposit_reorged(&self, swap_id: Uuid, note: &str) -> OtcServerResult<Swap> {
+        self.transition(swap_id, Some(note), Swap::user_deposit_reorged)
+            .await
+    }
+
+    /// Update swap when MM deposit is detected
+    pub async fn mm_deposit_detected(
+        &self,
+        swap_id: Uuid,
+        deposit_status: MMDepositStatus,
+    ) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.mm_deposit_detected(
+                deposit_status.tx_hash.clone(),
+                deposit_status.amount,
+                deposit_status.confirmations,
+            )
+        })
+        .await?;
         Ok(())
     }
 
+    /// Roll a swap back to waiting for the MM deposit after a reorg dropped it,
+    /// `note` says why on the recorded event
+    pub async fn mm_deposit_reorged(&self, swap_id: Uuid, note: &str) -> OtcServerResult<Swap> {
+        self.transition(swap_id, Some(note), Swap::mm_deposit_reorged)
+            .await
+    }
+
+    /// Update swap when the MM deposit pays less than the quote
+    pub async fn mm_deposit_amount_mismatch(
+        &self,
+        swap_id: Uuid,
+        deposit_status: MMDepositStatus,
+    ) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.mm_deposit_amount_mismatch(
+                deposit_status.tx_hash.clone(),
+                deposit_status.amount,
+                deposit_status.confirmations,
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Update user deposit confirmations
+    pub async fn update_user_confirmations(
+        &self,
+        swap_id: Uuid,
+        confirmations: u32,
+    ) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.update_confirmations(Some(confirmations as u64), None)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Update MM deposit confirmations
+    pub async fn update_mm_confirmations(
+        &self,
+        swap_id: Uuid,
+        confirmations: u32,
+    ) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.update_confirmations(None, Some(confirmations as u64))
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Update swap when user deposit is confirmed, the MM being asked to pay
+    /// at `now`
+    pub async fn user_deposit_confirmed(
+        &self,
+        swap_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.user_deposit_confirmed()
+                // The MM is asked to pay as soon as this is recorded
+                .and_then(|()| swap.mark_mm_notified(now))
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Update swap when MM deposit is confirmed
+    pub async fn mm_deposit_confirmed(&self, swap_id: Uuid) -> OtcServerResult<()> {
+        self.transition(swap_id, None, Swap::mm_deposit_confirmed)
+            .await?;
+        Ok(())
+    }
+
+    /// Mark private key as sent to MM
+    pub async fn mark_private_key_sent(&self, swap_id: Uuid) -> OtcServerResult<()> {
+        self.transition(swap_id, None, Swap::mark_private_key_sent)
+            .await?;
+        Ok(())
+    }
+
+    /// Hold a settled swap for manual review
+    pub async fn flag_for_manual_review(&self, swap_id: Uuid, reason: &str) -> OtcServerResult<()> {
+        self.transition(swap_id, None, |swap| {
+            swap.flag_for_manual_review(reason.to_string())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Set where the user deposit is refunded, returning the updated swap
+    pub async fn set_user_refund_address(
+        &self,
+        swap_id: Uuid,
+        address: &str,
+    ) -> OtcServerResult<Swap> {
+        self.transition(swap_id, None, |swap| {
+            swap.set_user_refund_address(address.to_string())
+        })
+        .await
+    }
+
     /// Mark swap as failed
     pub async fn mark_failed(&self, swap_id: Uuid, reason: &str) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.mark_failed(reason.to_string())
-            .map_err(|e| OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            })?;
-        self.update(&swap).await?;
+        self.transition(swap_id, None, |swap| swap.mark_failed(reason.to_string()))
+            .await?;
         Ok(())
     }
 
     /// Initiate user refund
     pub async fn initiate_user_refund(&self, swap_id: Uuid, reason: &str) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.initiate_user_refund(reason.to_string()).map_err(|e| {
-            OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            }
-        })?;
-        self.update(&swap).await?;
+        self.transition(swap_id, None, |swap| {
+            swap.initiate_user_refund(reason.to_string())
+        })
+        .await?;
         Ok(())
     }
 
     /// Initiate refund to MM
     pub async fn initiate_mm_refund(&self, swap_id: Uuid, reason: &str) -> OtcServerResult<()> {
-        let mut swap = self.get(swap_id).await?;
-        swap.initiate_mm_refund(reason.to_string())
-            .map_err(|e| OtcServerError::InvalidState {
-                message: format!("State transition failed: {e}"),
-            })?;
-        self.update(&swap).await?;
+        self.transition(swap_id, None, |swap| {
+            swap.initiate_mm_refund(reason.to_string())
+        })
+        .await?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{MMClaimedDeposit, OldestSwap};
+    use crate::api::reports;
     use crate::db::conversions::chain_type_to_db;
     use crate::db::Database;
+    use crate::error::OtcServerError;
     use alloy::primitives::U256;
-    use chrono::{Duration, Utc};
+    use chrono::{Duration, SubsecRound, Utc};
+    use futures_util::TryStreamExt;
     use otc_models::{
-        ChainType, Currency, Lot, MMDepositStatus, Quote, SettlementStatus, Swap, SwapStatus,
-        TokenIdentifier, UserDepositStatus,
+        ChainType, Currency, FillCost, FillUsage, Lot, MMDepositStatus, Quote, SettlementStatus,
+        Swap, SwapBuilder, SwapStatus, TokenIdentifier, UserDepositStatus,
     };
     use serde_json;
     use uuid::Uuid;
@@ -490,6 +1305,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(1000000u64), // 0.01 BTC
             },
@@ -498,6 +1314,7 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(500000000000000000u64), // 0.5 ETH
             },
@@ -519,19 +1336,26 @@ mod tests {
             quote: quote.clone(),
             user_deposit_salt: user_salt,
             user_deposit_address: "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh".to_string(),
+            master_key_version: 2,
             mm_nonce,
             user_destination_address: "0x1234567890123456789012345678901234567890".to_string(),
             user_evm_account_address: "0x1234567890123456789012345678901234567890"
                 .parse()
                 .unwrap(),
+            user_refund_address: Some("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_string()),
             status: SwapStatus::WaitingUserDepositInitiated,
+            user_required_confirmations: 2,
+            mm_required_confirmations: 4,
             user_deposit_status: None,
             mm_deposit_status: None,
             settlement_status: None,
             failure_reason: None,
             failure_at: None,
             mm_notified_at: None,
+            mm_deposit_detected_at: None,
             mm_private_key_sent_at: None,
+            trace_id: Some("trace-1".to_string()),
+            external_reference: Some("INV-2024/0042".to_string()),
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -557,7 +1381,15 @@ mod tests {
             retrieved_swap.user_deposit_address,
             original_swap.user_deposit_address
         );
+        assert_eq!(
+            retrieved_swap.master_key_version,
+            original_swap.master_key_version
+        );
         assert_eq!(retrieved_swap.mm_nonce, original_swap.mm_nonce);
+        assert_eq!(
+            retrieved_swap.get_required_confirmations(),
+            original_swap.get_required_confirmations()
+        );
         assert_eq!(
             retrieved_swap.user_destination_address,
             original_swap.user_destination_address
@@ -566,7 +1398,30 @@ mod tests {
             retrieved_swap.user_evm_account_address,
             original_swap.user_evm_account_address
         );
+        assert_eq!(
+            retrieved_swap.user_refund_address,
+            original_swap.user_refund_address
+        );
         assert_eq!(retrieved_swap.status, original_swap.status);
+        assert_eq!(retrieved_swap.trace_id, original_swap.trace_id);
+        assert_eq!(
+            retrieved_swap.external_reference,
+            original_swap.external_reference
+        );
+
+        let tagged = swap_repo
+            .find_by_external_reference("INV-2024/0042")
+            .await
+            .unwrap();
+        assert_eq!(
+            tagged.iter().map(|swap| swap.id).collect::<Vec<_>>(),
+            vec![original_swap.id]
+        );
+        assert!(swap_repo
+            .find_by_external_reference("inv-2024/0042")
+            .await
+            .unwrap()
+            .is_empty());
 
         Ok(())
     }
@@ -585,6 +1440,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(2000000u64),
             },
@@ -593,6 +1449,7 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(1000000000000000000u64),
             },
@@ -615,15 +1472,20 @@ mod tests {
             quote: quote.clone(),
             user_deposit_salt: user_salt,
             user_deposit_address: "bc1qnahvmnz8vgsdmrr68l5mfr8v8q9fxqz3n5d9u0".to_string(),
+            master_key_version: 1,
             mm_nonce,
             user_destination_address: "0x9876543210987654321098765432109876543210".to_string(),
             user_evm_account_address: "0x1234567890123456789012345678901234567890"
                 .parse()
                 .unwrap(),
+            user_refund_address: None,
             status: SwapStatus::WaitingMMDepositConfirmed,
+            user_required_confirmations: 2,
+            mm_required_confirmations: 4,
             user_deposit_status: Some(UserDepositStatus {
                 tx_hash: "7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730"
-                    .to_string(),
+                    .parse()
+                    .unwrap(),
                 amount: U256::from(2000000u64),
                 detected_at: now,
                 confirmations: 6,
@@ -631,7 +1493,8 @@ mod tests {
             }),
             mm_deposit_status: Some(MMDepositStatus {
                 tx_hash: "0x88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63"
-                    .to_string(),
+                    .parse()
+                    .unwrap(),
                 amount: U256::from(1000000000000000000u64),
                 detected_at: now + Duration::minutes(5),
                 confirmations: 12,
@@ -641,7 +1504,10 @@ mod tests {
             failure_reason: None,
             failure_at: None,
             mm_notified_at: None,
+            mm_deposit_detected_at: None,
             mm_private_key_sent_at: None,
+            trace_id: None,
+            external_reference: None,
             created_at: now,
             updated_at: now + Duration::minutes(5),
         };
@@ -692,6 +1558,7 @@ mod tests {
                     chain: ChainType::Bitcoin,
                     token: TokenIdentifier::Native,
                     decimals: 8,
+                    chain_id: None,
                 },
                 amount: U256::from(1000000u64),
             },
@@ -700,6 +1567,7 @@ mod tests {
                     chain: ChainType::Ethereum,
                     token: TokenIdentifier::Native,
                     decimals: 18,
+                    chain_id: None,
                 },
                 amount: U256::from(500000000000000000u64),
             },
@@ -721,19 +1589,26 @@ mod tests {
             quote: quote.clone(),
             user_deposit_salt: user_salt,
             user_deposit_address: "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh".to_string(),
+            master_key_version: 1,
             mm_nonce,
             user_destination_address: "0xabcdef1234567890abcdef1234567890abcdef12".to_string(),
             user_evm_account_address: "0x1234567890123456789012345678901234567890"
                 .parse()
                 .unwrap(),
+            user_refund_address: None,
             status: SwapStatus::WaitingUserDepositInitiated,
+            user_required_confirmations: 2,
+            mm_required_confirmations: 4,
             user_deposit_status: None,
             mm_deposit_status: None,
             settlement_status: None,
             failure_reason: None,
             failure_at: None,
             mm_notified_at: None,
+            mm_deposit_detected_at: None,
             mm_private_key_sent_at: None,
+            trace_id: None,
+            external_reference: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -752,7 +1627,9 @@ mod tests {
         // Update user deposit
         let deposit_amount = U256::from(1000000u64);
         let user_deposit = UserDepositStatus {
-            tx_hash: "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890".to_string(),
+            tx_hash: "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890"
+                .parse()
+                .unwrap(),
             amount: deposit_amount,
             detected_at: Utc::now(),
             confirmations: 0,
@@ -771,7 +1648,8 @@ mod tests {
         // Update settlement status
         let settlement_status = SettlementStatus {
             tx_hash: "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
-                .to_string(),
+                .parse()
+                .unwrap(),
             broadcast_at: Utc::now(),
             confirmations: 0,
             completed_at: None,
@@ -785,10 +1663,722 @@ mod tests {
         let updated = swap_repo.get(swap.id).await.unwrap();
         assert!(updated.settlement_status.is_some());
         assert_eq!(
-            updated.settlement_status.unwrap().tx_hash,
+            updated.settlement_status.unwrap().tx_hash.as_str(),
             "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
         );
 
+        // Transitions through the helpers are recorded too
+        swap_repo
+            .mark_failed(swap.id, "Failed waiting for MM deposit")
+            .await
+            .unwrap();
+
+        // Only status changes produce events, in the order they were applied
+        let events = db.swap_events().get_for_swap(swap.id).await.unwrap();
+        let transitions: Vec<_> = events
+            .iter()
+            .map(|e| (e.from_status, e.to_status))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (None, SwapStatus::WaitingUserDepositInitiated),
+                (
+                    Some(SwapStatus::WaitingUserDepositInitiated),
+                    SwapStatus::WaitingUserDepositConfirmed
+                ),
+                (
+                    Some(SwapStatus::WaitingUserDepositConfirmed),
+                    SwapStatus::Failed
+                ),
+            ]
+        );
+        assert!(events
+            .windows(2)
+            .all(|w| w[0].occurred_at <= w[1].occurred_at));
+        assert!(events.iter().all(|e| e.note.is_none()));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_set_refund_address_until_deposit_confirmed(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+
+        let swap = SwapBuilder::new().build();
+        swap_repo.create(&swap).await.unwrap();
+
+        // Set after creation, and changed again while the deposit confirms
+        let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        swap_repo
+            .set_user_refund_address(swap.id, address)
+            .await
+            .unwrap();
+        swap_repo
+            .update_status(swap.id, SwapStatus::WaitingUserDepositConfirmed)
+            .await
+            .unwrap();
+        let updated = swap_repo
+            .set_user_refund_address(swap.id, "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh")
+            .await
+            .unwrap();
+        assert_eq!(
+            swap_repo.get(swap.id).await.unwrap().user_refund_address,
+            updated.user_refund_address
+        );
+
+        // Once the MM is asked to pay the address is locked
+        swap_repo
+            .update_status(swap.id, SwapStatus::WaitingMMDepositInitiated)
+            .await
+            .unwrap();
+        assert!(matches!(
+            swap_repo.set_user_refund_address(swap.id, address).await,
+            Err(OtcServerError::InvalidState { .. })
+        ));
+        assert_eq!(
+            swap_repo.get(swap.id).await.unwrap().user_refund_address,
+            updated.user_refund_address
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_find_swaps_by_deposit_address_and_tx_hash(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+
+        let now = Utc::now();
+        let native = |chain, decimals, amount: u64| Lot {
+            currency: Currency {
+                chain,
+                token: TokenIdentifier::Native,
+                decimals,
+                chain_id: None,
+            },
+            amount: U256::from(amount),
+        };
+        let mut swap = SwapBuilder::new()
+            .with_lots(
+                native(ChainType::Ethereum, 18, 500000000000000000),
+                native(ChainType::Bitcoin, 8, 1000000),
+            )
+            .with_status(SwapStatus::WaitingMMDepositConfirmed)
+            .with_deposit([1u8; 32], "0xAbCdEf1234567890aBcDeF1234567890AbCdEf12")
+            .with_destination("bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh")
+            .build();
+        swap.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: "0xAB865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730"
+                .parse()
+                .unwrap(),
+            amount: U256::from(500000000000000000u64),
+            detected_at: now,
+            confirmations: 6,
+            last_checked: now,
+        });
+        swap.mm_deposit_status = Some(MMDepositStatus {
+            tx_hash: "88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63"
+                .parse()
+                .unwrap(),
+            amount: U256::from(1000000u64),
+            detected_at: now,
+            confirmations: 1,
+            last_checked: now,
+        });
+        swap_repo.create(&swap).await.unwrap();
+
+        let found_ids = |swaps: Vec<Swap>| swaps.iter().map(|s| s.id).collect::<Vec<_>>();
+
+        // Addresses match regardless of case
+        for address in [
+            "0xAbCdEf1234567890aBcDeF1234567890AbCdEf12",
+            "0xabcdef1234567890abcdef1234567890abcdef12",
+        ] {
+            let found = swap_repo.find_by_deposit_address(address).await.unwrap();
+            assert_eq!(found_ids(found), vec![swap.id]);
+        }
+
+        // Either deposit's hash matches, with or without 0x and in any case
+        for tx_hash in [
+            "ab865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730",
+            "0xAB865E959B2466918C9863AFCA942D0FB89D7C9AC0C99BAFC3749504DED97730",
+            "0x88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63",
+        ] {
+            let found = swap_repo
+                .find_by_tx_hash(&tx_hash.parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(found_ids(found), vec![swap.id]);
+        }
+
+        // A row written before hashes were canonicalized still loads and
+        // matches, in the form of the chain the deposit was made on
+        sqlx::query(
+            r#"
+            UPDATE swaps
+            SET mm_deposit_status = jsonb_set(
+                mm_deposit_status,
+                '{tx_hash}',
+                '"0x88DF016429689C079F3B2F6AD39FA052532C56B6A39DF8E3C84C03B8346CFC63"'
+            )
+            WHERE id = $1
+            "#,
+        )
+        .bind(swap.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let found = swap_repo
+            .find_by_tx_hash(
+                &"88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63"
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(found_ids(found), vec![swap.id]);
+        let stored = swap_repo.get(swap.id).await.unwrap();
+        assert_eq!(
+            stored.mm_deposit_status.unwrap().tx_hash.as_str(),
+            "88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63"
+        );
+        assert_eq!(
+            stored.user_deposit_status.unwrap().tx_hash.as_str(),
+            "0xab865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730"
+        );
+
+        // No matches is an empty list, not an error
+        assert!(swap_repo
+            .find_by_deposit_address("bc1qnahvmnz8vgsdmrr68l5mfr8v8q9fxqz3n5d9u0")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(swap_repo
+            .find_by_tx_hash(
+                &"7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730"
+                    .parse()
+                    .unwrap()
+            )
+            .await
+            .unwrap()
+            .is_empty());
+
+        // What the MM claimed is kept apart from what the chain showed
+        assert_eq!(swap_repo.mm_claimed_deposit(swap.id).await.unwrap(), None);
+        let claimed = MMClaimedDeposit {
+            tx_hash: "88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63"
+                .parse()
+                .unwrap(),
+            fee: Some(U256::from(2_100u64)),
+            fill_cost: Some(FillCost {
+                fee_in_lot_currency: Some(2_100),
+                usage: FillUsage::Bitcoin {
+                    sat_per_vb: 10.0,
+                    vsize: 210,
+                    inputs: 1,
+                    outputs: 4,
+                },
+            }),
+        };
+        swap_repo
+            .record_mm_claimed_deposit(
+                swap.id,
+                &claimed.tx_hash,
+                claimed.fee,
+                claimed.fill_cost.as_ref(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            swap_repo.mm_claimed_deposit(swap.id).await.unwrap(),
+            Some(claimed.clone())
+        );
+
+        let attempts = swap_repo.mm_deposit_attempts(swap.id).await.unwrap();
+        assert_eq!(attempts.claimed_tx_hash, Some(claimed.tx_hash));
+        assert!(attempts.claimed_at.is_some());
+        assert_eq!(attempts.retries, 0);
+        let requested_at = chrono::Utc::now();
+        assert_eq!(
+            swap_repo
+                .record_mm_deposit_retry(swap.id, requested_at)
+                .await
+                .unwrap(),
+            1
+        );
+        let attempts = swap_repo.mm_deposit_attempts(swap.id).await.unwrap();
+        assert_eq!(attempts.retries, 1);
+        assert_eq!(
+            attempts.retry_requested_at.map(|at| at.timestamp_micros()),
+            Some(requested_at.timestamp_micros())
+        );
+
+        assert_eq!(swap_repo.user_refund_cost(swap.id).await.unwrap(), None);
+        swap_repo
+            .record_user_refund_cost(swap.id, U256::from(997_900u64), U256::from(2_100u64))
+            .await
+            .unwrap();
+        assert_eq!(
+            swap_repo.user_refund_cost(swap.id).await.unwrap(),
+            Some((U256::from(997_900u64), U256::from(2_100u64)))
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_find_for_recovery_by_status_and_date(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+
+        let now = Utc::now();
+        let swap = |status, age: Duration| {
+            SwapBuilder::new()
+                .with_status(status)
+                .with_created_at(now - age)
+                .build()
+        };
+
+        let old_failed = swap(SwapStatus::Failed, Duration::days(10));
+        let failed = swap(SwapStatus::Failed, Duration::hours(2));
+        let settled = swap(SwapStatus::Settled, Duration::hours(1));
+        let active = swap(
+            SwapStatus::WaitingUserDepositInitiated,
+            Duration::minutes(5),
+        );
+        for seeded in [&active, &settled, &failed, &old_failed] {
+            swap_repo.create(seeded).await.unwrap();
+        }
+
+        let found_ids = |swaps: Vec<Swap>| swaps.iter().map(|s| s.id).collect::<Vec<_>>();
+
+        // Oldest first, every status when none is given
+        let all = swap_repo.find_for_recovery(&[], None, None).await.unwrap();
+        assert_eq!(
+            found_ids(all),
+            vec![old_failed.id, failed.id, settled.id, active.id]
+        );
+
+        let ended = swap_repo
+            .find_for_recovery(&[SwapStatus::Failed, SwapStatus::Settled], None, None)
+            .await
+            .unwrap();
+        assert_eq!(found_ids(ended), vec![old_failed.id, failed.id, settled.id]);
+
+        // Since is inclusive, until exclusive
+        let last_week_failed = swap_repo
+            .find_for_recovery(
+                &[SwapStatus::Failed],
+                Some(now - Duration::days(7)),
+                Some(settled.created_at),
+            )
+            .await
+            .unwrap();
+        assert_eq!(found_ids(last_week_failed), vec![failed.id]);
+
+        let before = swap_repo
+            .find_for_recovery(&[], None, Some(failed.created_at))
+            .await
+            .unwrap();
+        assert_eq!(found_ids(before), vec![old_failed.id]);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_counts_and_oldest_swap_by_status(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let now = Utc::now().trunc_subsecs(6);
+        let swap = |status, age: Duration| {
+            SwapBuilder::new()
+                .with_status(status)
+                .with_created_at(now - age)
+                .build()
+        };
+
+        let newer_waiting = swap(
+            SwapStatus::WaitingUserDepositInitiated,
+            Duration::minutes(5),
+        );
+        let older_waiting = swap(SwapStatus::WaitingUserDepositInitiated, Duration::hours(3));
+        let confirming = swap(SwapStatus::WaitingMMDepositConfirmed, Duration::minutes(30));
+        let settled = swap(SwapStatus::Settled, Duration::days(2));
+        let failed = swap(SwapStatus::Failed, Duration::days(1));
+        for seeded in [
+            &newer_waiting,
+            &older_waiting,
+            &confirming,
+            &settled,
+            &failed,
+        ] {
+            db.swaps().create(seeded).await.unwrap();
+        }
+
+        assert_eq!(
+            db.swaps().count_by_status().await.unwrap(),
+            vec![
+                (SwapStatus::WaitingUserDepositInitiated, 2),
+                (SwapStatus::WaitingMMDepositConfirmed, 1),
+                (SwapStatus::Settled, 1),
+                (SwapStatus::Failed, 1),
+            ]
+        );
+
+        // Settled and failed swaps are done, however old
+        assert_eq!(
+            db.swaps().oldest_in_status().await.unwrap(),
+            vec![
+                OldestSwap {
+                    status: SwapStatus::WaitingUserDepositInitiated,
+                    swap_id: older_waiting.id,
+                    created_at: older_waiting.created_at,
+                },
+                OldestSwap {
+                    status: SwapStatus::WaitingMMDepositConfirmed,
+                    swap_id: confirming.id,
+                    created_at: confirming.created_at,
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_failed_swaps_holding_a_deposit_keep_their_master_key(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let now = Utc::now();
+        let swap = |status| SwapBuilder::new().with_status(status).build();
+
+        let mut paid_failed = swap(SwapStatus::Failed);
+        paid_failed.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: "7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730"
+                .parse()
+                .unwrap(),
+            amount: paid_failed.quote.from.amount,
+            detected_at: now,
+            confirmations: 6,
+            last_checked: now,
+        });
+        for seeded in [
+            &swap(SwapStatus::WaitingUserDepositInitiated),
+            &swap(SwapStatus::Settled),
+            &swap(SwapStatus::Failed),
+            &paid_failed,
+        ] {
+            db.swaps().create(seeded).await.unwrap();
+        }
+
+        let counts = db
+            .swaps()
+            .count_active_by_master_key_version()
+            .await
+            .unwrap();
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![(1, 2)]);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_stream_range_pages_through_the_range(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+
+        // Whole seconds, as the database keeps no more than microseconds
+        let start = Utc::now().trunc_subsecs(0) - Duration::hours(1);
+        let mut seeded = Vec::new();
+        for minutes in 0..5 {
+            let mut swap = SwapBuilder::new().build();
+            swap.created_at = start + Duration::minutes(minutes);
+            if minutes % 2 == 0 {
+                swap.status = SwapStatus::Settled;
+            }
+            if minutes == 3 {
+                swap.external_reference = Some("INV-42".to_string());
+            }
+            swap_repo.create(&swap).await.unwrap();
+            seeded.push(swap);
+        }
+        // Outside the range
+        let mut later = SwapBuilder::new().build();
+        later.created_at = start + Duration::minutes(30);
+        swap_repo.create(&later).await.unwrap();
+
+        let collect = |from, to, statuses| {
+            swap_repo
+                .stream_range(from, to, statuses, None, 2)
+                .try_collect::<Vec<_>>()
+        };
+        let end = start + Duration::minutes(10);
+
+        // Pages smaller than the range still return every swap, oldest first
+        let all = collect(start, end, vec![]).await.unwrap();
+        assert_eq!(
+            all.iter().map(|entry| entry.swap.id).collect::<Vec<_>>(),
+            seeded.iter().map(|swap| swap.id).collect::<Vec<_>>()
+        );
+        // Each carries the fee rate recorded when it was created
+        assert!(all
+            .iter()
+            .all(|entry| entry.protocol_fee_bps == blockchain_utils::PROTOCOL_FEE_BPS));
+
+        let settled = collect(start, end, vec![SwapStatus::Settled])
+            .await
+            .unwrap();
+        assert_eq!(settled.len(), 3);
+        assert!(settled.iter().all(|entry| entry.settled_at.is_some()));
+        assert!(all
+            .iter()
+            .filter(|entry| entry.swap.status != SwapStatus::Settled)
+            .all(|entry| entry.settled_at.is_none()));
+
+        // From is inclusive, to exclusive
+        let middle = collect(
+            start + Duration::minutes(1),
+            start + Duration::minutes(3),
+            vec![],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            middle.iter().map(|entry| entry.swap.id).collect::<Vec<_>>(),
+            vec![seeded[1].id, seeded[2].id]
+        );
+
+        let tagged = swap_repo
+            .stream_range(start, end, vec![], Some("INV-42".to_string()), 2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            tagged.iter().map(|entry| entry.swap.id).collect::<Vec<_>>(),
+            vec![seeded[3].id]
+        );
+
+        // One CSV line per swap after the header
+        let csv = std::iter::once(reports::swap_report_header())
+            .chain(all.iter().map(reports::swap_report_row))
+            .collect::<String>();
+        assert_eq!(csv.lines().count(), 1 + seeded.len());
+        assert!(csv.starts_with("swap_id,"));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_concurrent_transitions_are_not_lost(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+        let refund_address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        let deposit = UserDepositStatus {
+            tx_hash: "1f2e3d4c5b6a79881f2e3d4c5b6a79881f2e3d4c5b6a79881f2e3d4c5b6a7988"
+                .parse()
+                .unwrap(),
+            amount: U256::from(1000000u64),
+            detected_at: Utc::now(),
+            confirmations: 0,
+            last_checked: Utc::now(),
+        };
+
+        // Each round races a monitoring pass against the user setting a refund
+        // address, both rewriting the whole row from their own read
+        for _ in 0..10 {
+            let swap = SwapBuilder::new().build();
+            swap_repo.create(&swap).await.unwrap();
+
+            let detect = tokio::spawn({
+                let swap_repo = swap_repo.clone();
+                let deposit = deposit.clone();
+                async move { swap_repo.user_deposit_detected(swap.id, deposit).await }
+            });
+            let set_refund_address = tokio::spawn({
+                let swap_repo = swap_repo.clone();
+                async move {
+                    swap_repo
+                        .set_user_refund_address(swap.id, refund_address)
+                        .await
+                }
+            });
+            detect.await.unwrap().unwrap();
+            set_refund_address.await.unwrap().unwrap();
+
+            let (stored, version) = swap_repo.get_versioned(swap.id).await.unwrap();
+            assert_eq!(stored.status, SwapStatus::WaitingUserDepositConfirmed);
+            assert_eq!(stored.user_deposit_status.unwrap().tx_hash, deposit.tx_hash);
+            assert_eq!(stored.user_refund_address.as_deref(), Some(refund_address));
+            assert_eq!(version, 2);
+        }
+
+        // A write based on an outdated read is turned away
+        let swap = SwapBuilder::new().build();
+        swap_repo.create(&swap).await.unwrap();
+        let (mut stale, version) = swap_repo.get_versioned(swap.id).await.unwrap();
+        swap_repo
+            .set_user_refund_address(swap.id, refund_address)
+            .await
+            .unwrap();
+        stale.mark_failed("Abandoned".to_string()).unwrap();
+        assert!(matches!(
+            swap_repo.update(&stale, version).await,
+            Err(OtcServerError::Conflict { .. })
+        ));
+        let stored = swap_repo.get(swap.id).await.unwrap();
+        assert_eq!(stored.status, SwapStatus::WaitingUserDepositInitiated);
+        assert_eq!(stored.user_refund_address.as_deref(), Some(refund_address));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_deposit_address_is_held_by_one_swap(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+
+        let first = SwapBuilder::new().build();
+        swap_repo.create(&first).await.unwrap();
+
+        // The same address in another case is the same address
+        let mut second = SwapBuilder::new().build();
+        second.user_deposit_address = first.user_deposit_address.to_uppercase();
+        assert!(matches!(
+            swap_repo.create(&second).await,
+            Err(OtcServerError::DepositAddressInUse { address })
+                if address == second.user_deposit_address
+        ));
+        // Its quote went with it, so a retry can insert it again
+        assert!(matches!(
+            db.quotes().get(second.quote.id).await,
+            Err(OtcServerError::NotFound)
+        ));
+        assert!(matches!(
+            swap_repo.get(second.id).await,
+            Err(OtcServerError::NotFound)
+        ));
+
+        second.user_deposit_address = SwapBuilder::new().build().user_deposit_address;
+        swap_repo.create(&second).await.unwrap();
+        assert!(swap_repo
+            .duplicate_deposit_addresses()
+            .await
+            .unwrap()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_mm_nonce_is_held_by_one_swap(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+
+        let first = SwapBuilder::new().build();
+        swap_repo.create(&first).await.unwrap();
+
+        let mut second = SwapBuilder::new().build();
+        second.mm_nonce = first.mm_nonce;
+        assert!(matches!(
+            swap_repo.create(&second).await,
+            Err(OtcServerError::MmNonceInUse { nonce })
+                if nonce == alloy::hex::encode(first.mm_nonce)
+        ));
+        assert!(matches!(
+            swap_repo.get(second.id).await,
+            Err(OtcServerError::NotFound)
+        ));
+
+        second.mm_nonce = SwapBuilder::new().build().mm_nonce;
+        swap_repo.create(&second).await.unwrap();
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_mm_fill_settles_one_swap_per_destination(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+        let destination = "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh";
+        let swap = |destination: &str| {
+            SwapBuilder::new()
+                .with_status(SwapStatus::WaitingMMDepositInitiated)
+                .with_destination(destination)
+                .build()
+        };
+        let first = swap(destination);
+        let second = swap(&destination.to_uppercase());
+        let third = swap("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq");
+        for swap in [&first, &second, &third] {
+            swap_repo.create(swap).await.unwrap();
+        }
+        let deposit = |tx_hash: &str| {
+            let now = Utc::now();
+            MMDepositStatus {
+                tx_hash: tx_hash.parse().unwrap(),
+                amount: U256::from(1_000_000u64),
+                detected_at: now,
+                confirmations: 0,
+                last_checked: now,
+            }
+        };
+        let tx_hash = "88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63";
+
+        swap_repo
+            .mm_deposit_detected(first.id, deposit(tx_hash))
+            .await
+            .unwrap();
+        // The same payment in another form, to the same destination in another case
+        let same_payment = deposit(&format!("0x{}", tx_hash.to_uppercase()));
+        assert!(matches!(
+            swap_repo.mm_deposit_detected(second.id, same_payment).await,
+            Err(OtcServerError::MmFillClaimed { .. })
+        ));
+        assert_eq!(
+            swap_repo.get(second.id).await.unwrap().status,
+            SwapStatus::WaitingMMDepositInitiated
+        );
+        // A batched fill's payment to another destination settles its own swap
+        swap_repo
+            .mm_deposit_detected(third.id, deposit(tx_hash))
+            .await
+            .unwrap();
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_duplicate_deposit_addresses_from_before_the_index_are_found(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool.clone()).await.unwrap();
+        let swap_repo = db.swaps();
+        sqlx::query("DROP INDEX idx_swaps_user_deposit_address")
+            .execute(&pool)
+            .await?;
+
+        let shared = SwapBuilder::new().build();
+        swap_repo.create(&shared).await.unwrap();
+        for address in [
+            shared.user_deposit_address.clone(),
+            shared.user_deposit_address.to_uppercase(),
+        ] {
+            let mut swap = SwapBuilder::new().build();
+            swap.user_deposit_address = address;
+            swap_repo.create(&swap).await.unwrap();
+        }
+        swap_repo.create(&SwapBuilder::new().build()).await.unwrap();
+
+        assert_eq!(
+            swap_repo.duplicate_deposit_addresses().await.unwrap(),
+            vec![(shared.user_deposit_address.to_lowercase(), 3)]
+        );
+
         Ok(())
     }
 }
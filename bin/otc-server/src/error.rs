@@ -3,13 +3,18 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use otc_api_types::{ApiErrorCode, ApiErrorDetail, ApiErrorResponse};
+use otc_models::FieldError;
 use snafu::Snafu;
 
 #[derive(Debug, Snafu)]
 pub enum OtcServerError {
     #[snafu(display("Database query failed: {}", source))]
     DatabaseQuery { source: sqlx::Error },
+
+    /// The database couldn't be reached, the query may succeed if retried
+    #[snafu(display("Database unavailable: {}", source))]
+    DatabaseUnavailable { source: sqlx::Error },
     
     #[snafu(display("Record not found"))]
     NotFound,
@@ -61,15 +66,88 @@ pub enum OtcServerError {
     
     #[snafu(display("Conflict: {}", message))]
     Conflict { message: String },
+
+    /// The swap's deposit address was derived for another swap already
+    #[snafu(display("Deposit address {} is already used by another swap", address))]
+    DepositAddressInUse { address: String },
+
+    /// The swap's MM nonce was drawn for another swap already
+    #[snafu(display("MM nonce {} is already used by another swap", nonce))]
+    MmNonceInUse { nonce: String },
+
+    /// The MM payment to the swap's destination already settles another swap
+    #[snafu(display("MM deposit {} already settles another swap", tx_hash))]
+    MmFillClaimed { tx_hash: String },
     
     #[snafu(display("Timeout: {}", message))]
     Timeout { message: String },
+
+    #[snafu(display("Rate limited: {}", message))]
+    RateLimited { message: String },
+
+    #[snafu(display("Quote signature invalid: {}", message))]
+    QuoteSignatureInvalid { message: String },
+
+    #[snafu(display("Quote price is stale: {}", message))]
+    QuoteStalePrice { message: String },
+
+    #[snafu(display("Quote is about to expire: {}", message))]
+    QuoteExpiring { message: String },
+
+    #[snafu(display("Unsupported token: {}", message))]
+    UnsupportedToken { message: String },
+
+    #[snafu(display("Decimals mismatch: {}", message))]
+    DecimalsMismatch { message: String },
+
+    #[snafu(display("Output below dust: {}", message))]
+    OutputBelowDust { message: String },
+
+    #[snafu(display("Idempotency key reused: {}", message))]
+    IdempotencyKeyReused { message: String },
+
+    #[snafu(display("Invalid request fields: {}", join_field_errors(errors)))]
+    FieldValidation { errors: Vec<FieldError> },
+}
+
+fn join_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl OtcServerError {
+    /// Whether the error came from losing the database rather than from the
+    /// query itself, so retrying once it's back can succeed
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        matches!(self, OtcServerError::DatabaseUnavailable { .. })
+    }
+}
+
+/// Connection failures, pool exhaustion and the server shutting down or
+/// restarting, as opposed to errors the same query would hit again
+fn is_transient_sqlx_error(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        // Class 08 is connection exceptions, 57P01-57P03 are admin or crash
+        // shutdown and the server still starting up
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            code.starts_with("08") || matches!(&*code, "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
 }
 
 impl From<sqlx::Error> for OtcServerError {
     fn from(err: sqlx::Error) -> Self {
         match err {
             sqlx::Error::RowNotFound => OtcServerError::NotFound,
+            _ if is_transient_sqlx_error(&err) => {
+                OtcServerError::DatabaseUnavailable { source: err }
+            }
             _ => OtcServerError::DatabaseQuery { source: err },
         }
     }
@@ -87,23 +165,52 @@ impl IntoResponse for OtcServerError {
             OtcServerError::NotFound => (StatusCode::NOT_FOUND, "Resource not found"),
             OtcServerError::Validation { .. } => (StatusCode::BAD_REQUEST, "Validation error"),
             OtcServerError::BadRequest { .. } => (StatusCode::BAD_REQUEST, "Bad request"),
+            OtcServerError::QuoteSignatureInvalid { .. } => (StatusCode::BAD_REQUEST, "Quote signature invalid"),
+            OtcServerError::UnsupportedToken { .. } => (StatusCode::BAD_REQUEST, "Unsupported token"),
+            OtcServerError::DecimalsMismatch { .. } => (StatusCode::BAD_REQUEST, "Decimals mismatch"),
+            OtcServerError::OutputBelowDust { .. } => (StatusCode::BAD_REQUEST, "Output below dust"),
+            OtcServerError::QuoteExpiring { .. } => (StatusCode::BAD_REQUEST, "Quote is about to expire"),
+            OtcServerError::FieldValidation { .. } => (StatusCode::BAD_REQUEST, "Validation error"),
             OtcServerError::Authentication { .. } => (StatusCode::UNAUTHORIZED, "Authentication failed"),
             OtcServerError::Authorization { .. } => (StatusCode::FORBIDDEN, "Authorization failed"),
             OtcServerError::Conflict { .. } => (StatusCode::CONFLICT, "Resource conflict"),
+            OtcServerError::QuoteStalePrice { .. } => (StatusCode::CONFLICT, "Quote price is stale"),
+            OtcServerError::IdempotencyKeyReused { .. } => (StatusCode::UNPROCESSABLE_ENTITY, "Idempotency key reused"),
             OtcServerError::Timeout { .. } => (StatusCode::REQUEST_TIMEOUT, "Request timeout"),
+            OtcServerError::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
             OtcServerError::ServiceUnavailable { .. } => (StatusCode::SERVICE_UNAVAILABLE, "Service unavailable"),
+            OtcServerError::DatabaseUnavailable { .. } => (StatusCode::SERVICE_UNAVAILABLE, "Service unavailable"),
             OtcServerError::WebSocket { .. } => (StatusCode::BAD_GATEWAY, "WebSocket error"),
             OtcServerError::MarketMaker { .. } => (StatusCode::BAD_GATEWAY, "Market maker error"),
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
         };
 
-        let body = Json(json!({
-            "error": {
-                "code": status.as_u16(),
-                "message": error_message,
-                "details": self.to_string(),
-            }
-        }));
+        // Errors clients are expected to branch on get their own code
+        let code = match &self {
+            OtcServerError::QuoteSignatureInvalid { .. } => ApiErrorCode::QuoteSignatureInvalid,
+            OtcServerError::FieldValidation { .. } => ApiErrorCode::ValidationFailed,
+            OtcServerError::UnsupportedToken { .. } => ApiErrorCode::UnsupportedToken,
+            OtcServerError::DecimalsMismatch { .. } => ApiErrorCode::DecimalsMismatch,
+            OtcServerError::OutputBelowDust { .. } => ApiErrorCode::OutputBelowDust,
+            OtcServerError::QuoteStalePrice { .. } => ApiErrorCode::QuoteStalePrice,
+            OtcServerError::QuoteExpiring { .. } => ApiErrorCode::QuoteExpiring,
+            OtcServerError::IdempotencyKeyReused { .. } => ApiErrorCode::IdempotencyKeyReused,
+            _ => ApiErrorCode::for_status(status.as_u16()),
+        };
+
+        let details = self.to_string();
+        let fields = match self {
+            OtcServerError::FieldValidation { errors } => Some(errors),
+            _ => None,
+        };
+        let body = Json(ApiErrorResponse {
+            error: ApiErrorDetail {
+                code,
+                message: error_message.to_string(),
+                details,
+                fields,
+            },
+        });
 
         (status, body).into_response()
     }
@@ -1,13 +1,19 @@
-use std::{fs, net::IpAddr, path::PathBuf, time::Duration};
+use std::{fmt, fs, net::IpAddr, path::PathBuf, str::FromStr, time::Duration};
 
+use alloy::primitives::B256;
+use axum::http::HeaderName;
 use bitcoincore_rpc_async::Auth;
 use clap::Parser;
+use common::EvmNetworkUrl;
+use otc_protocols::capabilities::QuoteSigningMode;
+use services::swap_monitoring::ChainMonitorInterval;
 use snafu::{prelude::*, Whatever};
 
 pub mod api;
 pub mod config;
 pub mod db;
 pub mod error;
+pub mod recovery;
 pub mod server;
 pub mod services;
 
@@ -33,6 +39,30 @@ pub enum Error {
     #[snafu(display("Database initialization failed: {}", source))]
     DatabaseInit { source: error::OtcServerError },
 
+    #[snafu(display("Invalid quote signing key: {}", source))]
+    QuoteSigningKey {
+        source: otc_protocols::rfq::QuoteSignatureError,
+    },
+
+    #[snafu(display("A quote signing key is required when quote signatures are {}", mode))]
+    MissingQuoteSigningKey { mode: QuoteSigningMode },
+
+    #[snafu(display("Attestation failed: {}", source))]
+    Attestation {
+        source: otc_protocols::attestation::AttestationError,
+    },
+
+    #[snafu(display(
+        "--mock-attestation-measurement is required with --mock-attestation-signing-key"
+    ))]
+    MissingAttestationMeasurement,
+
+    #[snafu(display("--{} is required in {} mode", arg, mode))]
+    MissingChainArg { arg: &'static str, mode: ServerMode },
+
+    #[snafu(display("EVM network {} is configured more than once", chain_id))]
+    DuplicateEvmNetwork { chain_id: u64 },
+
     #[snafu(display("Generic error: {}", source))]
     Generic { source: Whatever },
 }
@@ -45,7 +75,41 @@ impl From<Whatever> for Error {
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-#[derive(Parser, Debug)]
+/// What an OTC server instance runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServerMode {
+    /// Chain monitoring, market maker connections and the whole API
+    #[default]
+    Full,
+    /// Read only API replica against the database of a full node
+    ApiOnly,
+}
+
+impl fmt::Display for ServerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            Self::Full => "full",
+            Self::ApiOnly => "api-only",
+        };
+        f.write_str(mode)
+    }
+}
+
+impl FromStr for ServerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "api-only" => Ok(Self::ApiOnly),
+            _ => Err(format!(
+                "invalid server mode {s:?}, expected full or api-only"
+            )),
+        }
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
 #[command(name = "otc-server")]
 #[command(about = "TEE-OTC server for cross-chain swaps")]
 pub struct OtcServerArgs {
@@ -69,34 +133,70 @@ pub struct OtcServerArgs {
     #[arg(long, env = "RUST_LOG", default_value = "info")]
     pub log_level: String,
 
-    /// Ethereum Mainnet RPC URL
+    /// full, or api-only to serve the read endpoints without monitoring chains or
+    /// accepting market makers
+    #[arg(long, env = "OTC_SERVER_MODE", default_value_t = ServerMode::Full)]
+    pub mode: ServerMode,
+
+    /// Ethereum Mainnet RPC URL, required in full mode
     #[arg(long, env = "EVM_RPC_URL")]
-    pub ethereum_mainnet_rpc_url: String,
+    pub ethereum_mainnet_rpc_url: Option<String>,
 
-    /// Ethereum Mainnet Token Indexer URL
+    /// Ethereum Mainnet Token Indexer URL. Without it, deposits are found by scanning Transfer logs over RPC
     #[arg(long, env = "EVM_TOKEN_INDEXER_URL")]
-    pub ethereum_mainnet_token_indexer_url: String,
+    pub ethereum_mainnet_token_indexer_url: Option<String>,
 
     /// Ethereum Mainnet Chain ID
     #[arg(long, env = "EVM_CHAIN_ID", default_value = "1")]
     pub ethereum_mainnet_chain_id: u64,
 
-    /// Bitcoin RPC URL
+    /// Ethereum Mainnet websocket RPC URL. With it, token deposits are pushed
+    /// as they land instead of waiting for the next monitoring pass
+    #[arg(long, env = "EVM_WS_URL")]
+    pub ethereum_mainnet_ws_url: Option<String>,
+
+    /// Comma separated RPC URLs of further EVM networks, as `<chain_id>=<url>`.
+    /// Their tokens are listed in the supported currencies file under the chain id
+    #[arg(long, env = "EVM_NETWORK_RPC_URLS", value_delimiter = ',')]
+    pub evm_network_rpc_urls: Vec<EvmNetworkUrl>,
+
+    /// Token Indexer URLs of the further EVM networks, as `<chain_id>=<url>`
+    #[arg(long, env = "EVM_NETWORK_TOKEN_INDEXER_URLS", value_delimiter = ',')]
+    pub evm_network_token_indexer_urls: Vec<EvmNetworkUrl>,
+
+    /// Websocket RPC URLs of the further EVM networks, as `<chain_id>=<url>`
+    #[arg(long, env = "EVM_NETWORK_WS_URLS", value_delimiter = ',')]
+    pub evm_network_ws_urls: Vec<EvmNetworkUrl>,
+
+    /// Judge Ethereum deposits by confirmations alone instead of the safe and
+    /// finalized block tags, for chains whose tags don't track finality
+    #[arg(long, env = "EVM_IGNORE_FINALITY_TAGS")]
+    pub ethereum_ignore_finality_tags: bool,
+
+    /// Bitcoin Core RPC URL. Without it, Bitcoin data comes from the esplora server alone
     #[arg(long, env = "BITCOIN_RPC_URL")]
-    pub bitcoin_rpc_url: String,
+    pub bitcoin_rpc_url: Option<String>,
 
     /// Bitcoin RPC Auth
     #[arg(long, env = "BITCOIN_RPC_AUTH", default_value = "none", value_parser = parse_auth)]
     pub bitcoin_rpc_auth: Auth,
 
-    /// Electrum HTTP Server URL
+    /// Electrum HTTP Server URL, required in full mode
     #[arg(long, env = "ELECTRUM_HTTP_SERVER_URL")]
-    pub esplora_http_server_url: String,
+    pub esplora_http_server_url: Option<String>,
 
     /// Bitcoin Network
     #[arg(long, env = "BITCOIN_NETWORK", default_value = "bitcoin")]
     pub bitcoin_network: bitcoin::Network,
 
+    /// Blocks within which Bitcoin transfers the server costs or sends should confirm
+    #[arg(
+        long,
+        env = "BITCOIN_FEE_TARGET_BLOCKS",
+        default_value_t = otc_chains::bitcoin::DEFAULT_FEE_TARGET_BLOCKS
+    )]
+    pub bitcoin_fee_target_blocks: u16,
+
     /// API keys file
     #[arg(
         long,
@@ -105,13 +205,132 @@ pub struct OtcServerArgs {
     )]
     pub whitelist_file: String,
 
-    /// Chain monitor interval in seconds
-    #[arg(long, env = "CHAIN_MONITOR_INTERVAL", default_value = "10")]
-    pub chain_monitor_interval_seconds: u64,
+    /// Seconds between checks of the API keys file for changes. Market makers
+    /// whose key was removed, revoked or rotated are disconnected
+    #[arg(long, env = "WHITELIST_RELOAD_INTERVAL_SECONDS", default_value = "5")]
+    pub whitelist_reload_interval_seconds: u64,
+
+    /// Config file holding the versioned master keys, created on first start (ignored if OTC_MASTER_KEY is set)
+    #[arg(long, env = "OTC_SETTINGS_FILE", default_value = "otc-server.toml")]
+    pub settings_file: String,
+
+    /// TOML or JSON file listing the tokens swaps may use, defaults to BTC and cbBTC
+    #[arg(long, env = "SUPPORTED_CURRENCIES_FILE")]
+    pub supported_currencies_file: Option<String>,
+
+    /// Seconds between checks of the swaps waiting on a chain, comma separated as
+    /// `<seconds>` for every chain or `<chain>=<seconds>` for one. Chains without a
+    /// value are checked ten times per block, at most every 10 seconds
+    #[arg(long, env = "CHAIN_MONITOR_INTERVAL", value_delimiter = ',')]
+    pub chain_monitor_interval_seconds: Vec<ChainMonitorInterval>,
+
+    /// Most swaps the chain monitor checks at once
+    #[arg(long, env = "SWAP_MONITOR_CONCURRENCY", default_value = "16")]
+    pub swap_monitor_concurrency: usize,
+
+    /// Seconds an MM deposit that failed on chain, or a request to retry it, is
+    /// given before the MM is asked to pay again
+    #[arg(long, env = "MM_DEPOSIT_RETRY_GRACE_SECONDS", default_value = "120")]
+    pub mm_deposit_retry_grace_seconds: u64,
+
+    /// Times an MM is asked to retry a failed deposit before the user is refunded
+    #[arg(long, env = "MM_DEPOSIT_MAX_RETRIES", default_value = "2")]
+    pub mm_deposit_max_retries: u32,
+
+    /// Seconds between checks of recently settled and failed swaps against the
+    /// chains, 0 turns them off
+    #[arg(long, env = "RECONCILIATION_INTERVAL_SECONDS", default_value = "3600")]
+    pub reconciliation_interval_seconds: u64,
+
+    /// Days back a swap may have turned settled or failed and still be checked
+    #[arg(
+        long,
+        env = "RECONCILIATION_LOOKBACK_DAYS",
+        default_value = "7",
+        value_parser = clap::value_parser!(u64).range(1..=3650)
+    )]
+    pub reconciliation_lookback_days: u64,
+
+    /// Most swaps each reconciliation run checks, picked at random
+    #[arg(long, env = "RECONCILIATION_SAMPLE_SIZE", default_value = "200")]
+    pub reconciliation_sample_size: usize,
+
+    /// Swap lookups each client IP may make per minute
+    #[arg(long, env = "SWAP_LOOKUP_RATE_LIMIT_PER_MINUTE", default_value = "10")]
+    pub swap_lookup_rate_limit_per_minute: u32,
+
+    /// Header a trusted reverse proxy puts the client address in (e.g.
+    /// "X-Forwarded-For"), rate limits use the peer address when unset
+    #[arg(long, env = "TRUSTED_PROXY_HEADER")]
+    pub trusted_proxy_header: Option<HeaderName>,
+
+    /// Comma separated CORS domains to allow (supports wildcards like "*.example.com")
+    #[arg(long = "corsdomain", env = "CORS_DOMAIN", value_delimiter = ',')]
+    pub cors_domains: Vec<String>,
+
+    /// Hex encoded key used to verify quote signatures (must match the RFQ server's key)
+    #[arg(long, env = "QUOTE_SIGNING_KEY")]
+    pub quote_signing_key: Option<String>,
+
+    /// Whether swaps need a valid RFQ quote signature: required, optional or off
+    #[arg(long, env = "QUOTE_SIGNATURE_MODE", default_value_t = QuoteSigningMode::Required)]
+    pub quote_signature_mode: QuoteSigningMode,
+
+    /// How much worse for the user than recent quotes of a similar size, in basis
+    /// points, a quote's price may be. Unset, quote prices aren't checked. Only
+    /// quotes swaps were created from count, with fewer than 3 in the last 10
+    /// minutes the check is skipped
+    #[arg(long, env = "QUOTE_PRICE_MAX_DEVIATION_BPS")]
+    pub quote_price_max_deviation_bps: Option<u64>,
+
+    /// Seconds of validity a quote must still have once the market maker's
+    /// validation window is deducted for a swap to be created from it
+    #[arg(long, env = "MIN_QUOTE_VALIDITY_SECONDS", default_value = "20")]
+    pub min_quote_validity_seconds: u64,
+
+    /// Key required in the X-Admin-API-Key header for /admin routes (admin API is disabled if unset)
+    #[arg(long, env = "ADMIN_API_KEY")]
+    pub admin_api_key: Option<String>,
+
+    /// Hex encoded key the devnet attestation provider signs with. Anyone holding
+    /// it can impersonate the enclave, never set it in production
+    #[arg(long, env = "MOCK_ATTESTATION_SIGNING_KEY")]
+    pub mock_attestation_signing_key: Option<B256>,
+
+    /// Code measurement the devnet attestation provider attests to
+    #[arg(long, env = "MOCK_ATTESTATION_MEASUREMENT")]
+    pub mock_attestation_measurement: Option<B256>,
+
+    /// Market maker websocket URL the attestation is bound to, defaults to
+    /// ws://<host>:<port>/ws/mm
+    #[arg(long, env = "ATTESTATION_ENDPOINT")]
+    pub attestation_endpoint: Option<String>,
+
+    /// Largest message, in bytes, a market maker may send over its websocket
+    #[arg(long, env = "MM_MAX_MESSAGE_BYTES", default_value = "1048576")]
+    pub mm_max_message_bytes: usize,
+
+    /// Malformed messages in a row that get a market maker disconnected
+    #[arg(long, env = "MM_MAX_MALFORMED_MESSAGES", default_value = "5")]
+    pub mm_max_malformed_messages: u32,
+
+    /// Seconds a run of malformed market maker messages is remembered
+    #[arg(long, env = "MM_MALFORMED_WINDOW_SECONDS", default_value = "60")]
+    pub mm_malformed_window_seconds: u64,
+
+    /// Messages that may wait on a market maker's socket before it's disconnected
+    /// as a slow consumer
+    #[arg(
+        long,
+        env = "MM_SEND_QUEUE_CAPACITY",
+        default_value_t = common::DEFAULT_MM_SEND_QUEUE_CAPACITY
+    )]
+    pub mm_send_queue_capacity: usize,
 
-    /// CORS domain to allow (supports wildcards like "*.example.com")
-    #[arg(long = "corsdomain", env = "CORS_DOMAIN")]
-    pub cors_domain: Option<String>,
+    /// Seconds a SIGTERM waits for the monitoring pass, open requests and market
+    /// maker connections to wind down before the server exits anyway
+    #[arg(long, env = "SHUTDOWN_DRAIN_TIMEOUT_SECONDS", default_value = "30")]
+    pub shutdown_drain_timeout_seconds: u64,
 }
 
 fn parse_auth(s: &str) -> Result<Auth, String> {
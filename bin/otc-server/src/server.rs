@@ -1,30 +1,92 @@
 use crate::{
-    api::swaps::{CreateSwapRequest, CreateSwapResponse, SwapResponse},
-    config::Settings,
-    db::Database,
-    services::{MMRegistry, SwapManager, SwapMonitoringService},
-    OtcServerArgs, Result,
+    api::{
+        reports::{swap_report_header, swap_report_row},
+        swaps::{
+            render_qr_svg, CreateSwapQuery, CreateSwapRequest, CreateSwapResponse,
+            SetRefundAddressRequest, SwapLookup, SwapLookupQuery, SwapResponse,
+        },
+        AdminSummaryResponse, AdminSwapsQuery, CancelSwapRequest, ChainCurrencyResponse,
+        ChainFeesResponse, CurrenciesResponse, MarketMakerStatsQuery, MarketMakerStatsResponse,
+        MarketMakerWindowStats, MasterKeyInfo, MasterKeysResponse, ReconciliationFindingsResponse,
+        SetConfirmationOverrideRequest, StatsWindow, SwapReceipt, SwapReportQuery,
+        SwapStatesResponse,
+    },
+    config::{Settings, SettingsError},
+    db::{Database, PoolStats},
+    services::{
+        confirmation_policy::ConfirmationPolicyError,
+        mm_registry::StatusUpdateCounts,
+        swap_monitoring::{resolve_monitor_intervals, MMDepositRetryPolicy, MonitoringResult},
+        AdminSummary, ConfirmationPolicy, FeeTelemetry, FeeTelemetryError, MMRegistry,
+        QuotePriceCheck, SettlementReconciliationService, SwapManager, SwapMonitoringService,
+        DEFAULT_FEE_CACHE_TTL, DEFAULT_SUMMARY_CACHE_TTL,
+    },
+    OtcServerArgs, Result, ServerMode,
 };
+use alloy::primitives::B256;
 use axum::{
+    body::Body,
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Extension, Path, Query, State,
     },
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
-    routing::{get, post, Router},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, Router},
     Json,
 };
-use futures_util::{SinkExt, StreamExt};
-use otc_auth::ApiKeyStore;
+use common::{
+    api_docs_router, build_cors_layer, check_clock_drift, describe_websocket, evm_network_url,
+    trace_id_middleware, Clock, MmSocketCounters, MmSocketCounts, MmSocketGuard, MmSocketLimits,
+    RateLimiter, RejectsInvalidJson, Shutdown, SystemClock, TraceId, ValidatedJson,
+    MAX_REQUEST_BODY_BYTES,
+};
+use futures_util::{stream, Sink, SinkExt, StreamExt, TryStreamExt};
+use otc_api_types::{
+    ApiErrorCode, ApiErrorResponse, ConnectedMarketMakersQuery, ConnectedMarketMakersResponse,
+    IDEMPOTENCY_KEY_HEADER,
+};
+use otc_auth::{reload::reload_api_keys, ApiKeyStore, AuthError, ValidatedApiKey};
 use otc_chains::{bitcoin::BitcoinChain, ethereum::EthereumChain, ChainRegistry};
-use otc_protocols::mm::{Connected, MMRequest, MMResponse, ProtocolMessage};
+use otc_models::{
+    ApiKeyScope, ChainNetwork, ChainType, ConfirmationOverride, FieldError, SupportedCurrencies,
+    MAX_REASON_LEN,
+};
+use otc_protocols::{
+    attestation::{
+        AttestationDocument, AttestationProvider, AttestationQuery, AttestationResult,
+        MockAttestationProvider, ATTESTATION_NONCE_HEADER, ATTESTATION_PATH,
+    },
+    capabilities::{
+        AmountLimitsSource, Capabilities, Features, Limits, ProtocolVersions, QuoteSigningMode,
+        ServerKind, API_VERSIONS, CAPABILITIES_PATH,
+    },
+    mm::{
+        ensure_version_compatible, is_version_at_least, Connected, MMRequest, MMResponse,
+        ProtocolMessage, GOING_AWAY_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+    },
+    rfq::QuoteSigner,
+};
 use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::{sync::mpsc, time::Duration};
-use tower_http::cors::{AllowOrigin, CorsLayer};
-use tracing::{error, info};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    future::IntoFuture,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex as StdMutex},
+};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot, watch, Notify},
+    task::{JoinHandle, JoinSet},
+    time::{self, Duration},
+};
+use tracing::{error, info, info_span, warn, Instrument};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -32,39 +94,656 @@ pub struct AppState {
     pub db: Database,
     pub swap_manager: Arc<SwapManager>,
     pub mm_registry: Arc<MMRegistry>,
-    pub api_key_store: Arc<otc_auth::ApiKeyStore>,
+    /// Replaced whenever the API keys file changes
+    pub api_key_store: watch::Receiver<Arc<otc_auth::ApiKeyStore>>,
+    pub confirmation_policy: Arc<ConfirmationPolicy>,
+    pub admin_api_key: Option<Arc<str>>,
+    pub capabilities: Arc<Capabilities>,
+    pub settings: Arc<Settings>,
+    pub supported_currencies: Arc<SupportedCurrencies>,
+    pub fee_telemetry: Arc<FeeTelemetry>,
+    pub admin_summary: Arc<AdminSummary>,
+    /// Swap lookups are enumerable, so they're limited per client IP
+    pub swap_lookup_rate_limiter: Arc<RateLimiter>,
+    /// Attests this server to market makers on connect, `None` when not
+    /// running in a TEE
+    pub attester: Option<Arc<Attester>>,
+    pub mm_socket_limits: MmSocketLimits,
+    pub mm_socket_counters: Arc<MmSocketCounters>,
+}
+
+impl RejectsInvalidJson for AppState {
+    type Rejection = crate::error::OtcServerError;
+
+    fn malformed_json(message: String) -> Self::Rejection {
+        crate::error::OtcServerError::BadRequest { message }
+    }
+
+    fn invalid_fields(errors: Vec<FieldError>) -> Self::Rejection {
+        crate::error::OtcServerError::FieldValidation { errors }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Longest accepted Idempotency-Key header (matches the column width)
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// How often expired idempotency keys are deleted
+const IDEMPOTENCY_KEY_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Why market makers are disconnected during a shutdown
+const GOING_AWAY_REASON: &str = "server shutting down";
+
+/// Why a market maker is disconnected once its API key stops being accepted
+const API_KEY_INVALIDATED_REASON: &str = "API key no longer valid";
+
+/// How long a slow consumer's close frame may take to go out before the socket
+/// is just dropped
+const SLOW_CONSUMER_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Swaps read from the database per query while exporting a report
+const SWAP_REPORT_PAGE_SIZE: usize = 500;
+
+#[derive(Serialize, Deserialize, ToSchema)]
 struct Status {
     status: String,
     version: String,
 }
 
-pub async fn run_server(args: OtcServerArgs) -> Result<()> {
-    info!("Starting OTC server...");
+#[derive(Serialize, Deserialize, ToSchema)]
+struct Health {
+    database: PoolStats,
+}
 
-    let addr = SocketAddr::from((args.host, args.port));
+/// OpenAPI document of every route a full node serves
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "OTC server"),
+    paths(
+        status_handler,
+        health_handler,
+        metrics_handler,
+        websocket_handler,
+        mm_websocket_handler,
+        create_swap,
+        lookup_swaps,
+        get_swap,
+        get_swap_receipt,
+        set_refund_address,
+        get_currencies,
+        get_chain_fees,
+        get_swap_states,
+        get_capabilities,
+        get_attestation,
+        get_connected_market_makers,
+        get_market_maker_stats,
+        set_confirmation_override,
+        list_swaps_by_reference,
+        cancel_swap,
+        get_master_keys,
+        rotate_master_key,
+        remove_master_key,
+        get_reconciliation_findings,
+        get_admin_summary,
+        export_swaps_csv,
+    ),
+    components(schemas(
+        ApiErrorCode,
+        Connected,
+        ProtocolMessage<MMRequest>,
+        ProtocolMessage<MMResponse>
+    )),
+    modifiers(&ApiDocSecurity)
+)]
+struct ApiDoc;
 
-    // Load configuration
-    let settings = Arc::new(Settings::load().map_err(|e| crate::Error::DatabaseInit {
-        source: crate::error::OtcServerError::InvalidData {
-            message: format!("Failed to load settings: {e}"),
-        },
-    })?);
-    let db = Database::connect(&args.database_url)
+/// Header credentials of the admin routes and the market maker websocket, and
+/// the messages exchanged over that websocket
+struct ApiDocSecurity;
+
+impl Modify for ApiDocSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for (name, header) in [
+            ("admin_api_key", "x-admin-api-key"),
+            ("mm_api_key_id", "x-api-key-id"),
+            ("mm_api_key", "x-api-key"),
+        ] {
+            components.add_security_scheme(
+                name,
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(header))),
+            );
+        }
+        describe_websocket(
+            openapi,
+            "/ws/mm",
+            "ProtocolMessage_MMResponse",
+            "ProtocolMessage_MMRequest",
+        );
+    }
+}
+
+pub async fn run_server(args: OtcServerArgs) -> Result<()> {
+    let listener = TcpListener::bind(SocketAddr::from((args.host, args.port)))
         .await
-        .context(crate::DatabaseInitSnafu)?;
+        .context(crate::ServerBindSnafu)?;
+    run_server_with_listener(args, listener).await
+}
+
+/// Run the server on a listener bound by the caller, whose address is served
+/// instead of `args.host` and `args.port`. Drains and returns on SIGTERM or ctrl-c
+pub async fn run_server_with_listener(args: OtcServerArgs, listener: TcpListener) -> Result<()> {
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
+    run_server_until(args, listener, shutdown).await
+}
+
+/// Run the server on `listener` until `shutdown` is triggered. New connections
+/// are then refused, open requests and the monitoring pass in progress finish,
+/// and market makers are told to reconnect elsewhere, all within
+/// `args.shutdown_drain_timeout_seconds`
+pub async fn run_server_until(
+    mut args: OtcServerArgs,
+    listener: TcpListener,
+    shutdown: Shutdown,
+) -> Result<()> {
+    // The attestation names the address actually served
+    let addr = listener.local_addr().context(crate::ServerBindSnafu)?;
+    args.host = addr.ip();
+    args.port = addr.port();
+
+    let server = OtcServer::build(args).await?.with_shutdown(shutdown);
+    let mut background = JoinSet::new();
+    server.spawn_background(&mut background);
+    let result = server.serve(listener).await;
+    background.shutdown().await;
+    result
+}
+
+/// An OTC server connected to its database and, on a full node, its chains,
+/// but not running anything yet. [`run_server_until`] spawns its background
+/// tasks and serves it; embedders and tests can drive each part themselves,
+/// e.g. call the router without a socket or step monitoring with
+/// [`Self::tick_once`]
+pub struct OtcServer {
+    args: OtcServerArgs,
+    state: AppState,
+    router: Router,
+    shutdown: Shutdown,
+    clock: Arc<dyn Clock>,
+    chain_registry: Arc<ChainRegistry>,
+    api_key_updates: watch::Sender<Arc<ApiKeyStore>>,
+    /// `None` on an API-only replica
+    monitoring: Option<Arc<SwapMonitoringService>>,
+    /// Awaited while draining, so the last pass's notifications go out before
+    /// market makers are disconnected
+    monitoring_task: StdMutex<Option<JoinHandle<()>>>,
+}
+
+impl OtcServer {
+    /// Load the configuration and connect to the database and chains. The
+    /// attestation names `args.host` and `args.port` unless
+    /// `args.attestation_endpoint` is set
+    pub async fn build(args: OtcServerArgs) -> Result<Self> {
+        info!("Starting OTC server...");
+
+        let addr = SocketAddr::from((args.host, args.port));
+
+        // Load configuration
+        let settings =
+            Settings::load(&args.settings_file).map_err(|e| crate::Error::DatabaseInit {
+                source: crate::error::OtcServerError::InvalidData {
+                    message: format!("Failed to load settings: {e}"),
+                },
+            })?;
+        let settings = Arc::new(settings);
+        let supported_currencies = Arc::new(match &args.supported_currencies_file {
+            Some(path) => {
+                SupportedCurrencies::load(path).map_err(|e| crate::Error::DatabaseInit {
+                    source: crate::error::OtcServerError::InvalidData {
+                        message: format!("Failed to load supported currencies: {e}"),
+                    },
+                })?
+            }
+            None => SupportedCurrencies::default(),
+        });
+        let db = Database::connect(&args.database_url)
+            .await
+            .context(crate::DatabaseInitSnafu)?;
+
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        match db.now().await {
+            Ok(database_now) => {
+                check_clock_drift(clock.as_ref(), database_now);
+            }
+            Err(e) => warn!("Failed to read the database clock: {}", e),
+        }
+        match db.swaps().duplicate_deposit_addresses().await {
+            Ok(duplicates) => {
+                for (address, swaps) in duplicates {
+                    error!(
+                        "Deposit address {} is shared by {} swaps, deposits to it can't be told apart",
+                        address, swaps
+                    );
+                }
+            }
+            Err(e) => warn!("Failed to audit deposit addresses: {}", e),
+        }
+
+        let chain_registry = Arc::new(match args.mode {
+            ServerMode::Full => {
+                info!("Initializing chain registry...");
+                connect_chains(&args, &supported_currencies, &clock).await?
+            }
+            // Replicas read swaps from the database alone
+            ServerMode::ApiOnly => ChainRegistry::new(),
+        });
+
+        info!("Initializing services...");
+
+        // Initialize API key store, reloaded by the background tasks
+        let (api_key_updates, api_key_store) = watch::channel(Arc::new(
+            ApiKeyStore::new(PathBuf::from(&args.whitelist_file)).await?,
+        ));
+
+        // Initialize MM registry with 5-second validation timeout
+        let mm_registry = Arc::new(MMRegistry::new(Duration::from_secs(5)));
+
+        // Initialize quote signature verification, replicas don't create swaps
+        let quote_signer = match (args.mode, args.quote_signature_mode) {
+            (ServerMode::ApiOnly, _) | (_, QuoteSigningMode::Off) => None,
+            (ServerMode::Full, mode) => {
+                let key = args
+                    .quote_signing_key
+                    .as_deref()
+                    .context(crate::MissingQuoteSigningKeySnafu { mode })?;
+                Some(Arc::new(
+                    QuoteSigner::from_hex(key).context(crate::QuoteSigningKeySnafu)?,
+                ))
+            }
+        };
+        info!("Quote signatures are {}", args.quote_signature_mode);
+
+        let attester = attester(&args, addr)?.map(Arc::new);
+
+        let capabilities = Arc::new(build_capabilities(
+            args.mode,
+            args.quote_signature_mode,
+            args.admin_api_key.is_some(),
+            &chain_registry,
+        ));
+
+        let confirmation_policy = Arc::new(ConfirmationPolicy::new(
+            db.clone(),
+            chain_registry.clone(),
+        ));
+
+        // Monitoring and cleanup run on the full node only, replicas share its database
+        let monitoring = (args.mode == ServerMode::Full).then(|| {
+            Arc::new(
+                SwapMonitoringService::new(
+                    db.clone(),
+                    settings.clone(),
+                    chain_registry.clone(),
+                    mm_registry.clone(),
+                    resolve_monitor_intervals(
+                        &args.chain_monitor_interval_seconds,
+                        &chain_registry,
+                    ),
+                    args.swap_monitor_concurrency,
+                    clock.clone(),
+                )
+                .with_mm_deposit_retry(MMDepositRetryPolicy {
+                    grace: Duration::from_secs(args.mm_deposit_retry_grace_seconds),
+                    max_retries: args.mm_deposit_max_retries,
+                }),
+            )
+        });
+        if monitoring.is_none() {
+            info!("Running as an API-only replica");
+        }
+
+        let mut swap_manager = SwapManager::new(
+            db.clone(),
+            settings.clone(),
+            chain_registry.clone(),
+            mm_registry.clone(),
+            quote_signer,
+            args.quote_signature_mode,
+            confirmation_policy.clone(),
+            supported_currencies.clone(),
+            args.quote_price_max_deviation_bps
+                .map(|max_deviation_bps| QuotePriceCheck { max_deviation_bps }),
+            Duration::from_secs(args.min_quote_validity_seconds),
+            args.bitcoin_network,
+            clock.clone(),
+        )
+        .with_mm_deposit_grace(Duration::from_secs(args.mm_deposit_retry_grace_seconds));
+        if let Some(monitoring) = &monitoring {
+            swap_manager = swap_manager.with_transfer_watch(monitoring.transfer_watch_requests());
+        }
+
+        let admin_summary = AdminSummary::new(
+            db.clone(),
+            mm_registry.clone(),
+            monitoring
+                .as_ref()
+                .map(|monitoring| monitoring.monitoring_passes())
+                .unwrap_or_default(),
+            DEFAULT_SUMMARY_CACHE_TTL,
+            clock.clone(),
+        );
+
+        let state = AppState {
+            db,
+            swap_manager: Arc::new(swap_manager),
+            mm_registry,
+            api_key_store,
+            confirmation_policy,
+            admin_api_key: args.admin_api_key.as_deref().map(Arc::from),
+            capabilities,
+            settings,
+            supported_currencies,
+            fee_telemetry: Arc::new(FeeTelemetry::new(
+                chain_registry.clone(),
+                DEFAULT_FEE_CACHE_TTL,
+                clock.clone(),
+            )),
+            admin_summary: Arc::new(admin_summary),
+            swap_lookup_rate_limiter: Arc::new(
+                RateLimiter::per_minute(args.swap_lookup_rate_limit_per_minute)
+                    .with_trusted_proxy_header(args.trusted_proxy_header.clone()),
+            ),
+            attester,
+            mm_socket_limits: MmSocketLimits {
+                max_message_bytes: args.mm_max_message_bytes,
+                max_malformed_messages: args.mm_max_malformed_messages,
+                malformed_window: Duration::from_secs(args.mm_malformed_window_seconds),
+                send_queue_capacity: args.mm_send_queue_capacity.max(1),
+            },
+            mm_socket_counters: Arc::new(MmSocketCounters::default()),
+        };
+        let router = build_router(args.mode, &args.cors_domains, state.clone());
+
+        Ok(Self {
+            args,
+            state,
+            router,
+            shutdown: Shutdown::new(),
+            clock,
+            chain_registry,
+            api_key_updates,
+            monitoring,
+            monitoring_task: StdMutex::new(None),
+        })
+    }
+
+    /// Stop the background tasks and drain the server when `shutdown` is
+    /// triggered instead of on a shutdown of its own
+    #[must_use]
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// What stops the background tasks and drains the server once triggered
+    #[must_use]
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    #[must_use]
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Every route this server serves with its state applied. Handlers that
+    /// read the client's address need a `ConnectInfo<SocketAddr>` extension,
+    /// which `serve` provides
+    #[must_use]
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
+
+    /// Spawn API key reloading, rate limiter sweeping and, on a full node,
+    /// swap monitoring, settlement reconciliation and idempotency key cleanup,
+    /// all stopping once the shutdown is triggered or `join_set` is dropped.
+    /// Monitoring is held by the server instead of `join_set` so `serve` can
+    /// wait for its last pass
+    pub fn spawn_background(&self, join_set: &mut JoinSet<()>) {
+        if self.args.whitelist_reload_interval_seconds > 0 {
+            join_set.spawn(reload_api_keys(
+                PathBuf::from(&self.args.whitelist_file),
+                Duration::from_secs(self.args.whitelist_reload_interval_seconds),
+                self.api_key_updates.clone(),
+                self.shutdown.clone(),
+            ));
+        }
+        join_set.spawn(
+            self.state
+                .swap_lookup_rate_limiter
+                .clone()
+                .sweep_until(self.shutdown.clone()),
+        );
+
+        let Some(monitoring) = &self.monitoring else {
+            return;
+        };
+        info!("Starting swap monitoring service...");
+        let task = tokio::spawn(monitoring.clone().run(self.shutdown.clone()));
+        if let Some(previous) = self.monitoring_task_guard().replace(task) {
+            previous.abort();
+        }
+
+        if self.args.reconciliation_interval_seconds > 0 {
+            let reconciliation_service = Arc::new(SettlementReconciliationService::new(
+                self.state.db.clone(),
+                self.chain_registry.clone(),
+                self.args
+                    .reconciliation_lookback_days
+                    .checked_mul(24 * 60 * 60)
+                    .map_or(Duration::MAX, Duration::from_secs),
+                self.args.reconciliation_sample_size,
+                self.clock.clone(),
+            ));
+            info!("Starting settlement reconciliation service...");
+            join_set.spawn(reconciliation_service.run(
+                Duration::from_secs(self.args.reconciliation_interval_seconds),
+                self.shutdown.clone(),
+            ));
+        }
+
+        join_set.spawn(delete_expired_idempotency_keys(
+            self.state.db.clone(),
+            self.clock.clone(),
+            self.shutdown.clone(),
+        ));
+    }
+
+    /// Run one monitoring pass over every chain now, returning how many swaps
+    /// were checked. A replica monitors nothing
+    pub async fn tick_once(&self) -> MonitoringResult<usize> {
+        match &self.monitoring {
+            Some(monitoring) => monitoring.tick_once().await,
+            None => Ok(0),
+        }
+    }
+
+    /// Serve on `listener` until the shutdown is triggered, then drain
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        let addr = listener.local_addr().context(crate::ServerBindSnafu)?;
+        info!("Listening on {}", addr);
+
+        let monitoring = self.monitoring_task_guard().take();
+        let Self {
+            args,
+            state,
+            router,
+            shutdown,
+            ..
+        } = self;
+        let mm_registry = state.mm_registry;
+
+        let drain_timeout = Duration::from_secs(args.shutdown_drain_timeout_seconds);
+        let serve = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+        let drained = async {
+            // Returns once the listener is closed and open requests are answered,
+            // which may still need market makers to validate quotes
+            let served = serve.into_future().await;
+            shutdown.trigger();
+            if let Some(monitoring) = monitoring {
+                if let Err(e) = monitoring.await {
+                    error!("Swap monitoring service failed: {}", e);
+                }
+            }
+            // Notifications from the last monitoring pass are queued ahead of this
+            mm_registry.going_away(GOING_AWAY_REASON).await;
+            mm_registry.wait_until_disconnected().await;
+            served.context(crate::ServerStartSnafu)
+        };
+        tokio::pin!(drained);
+
+        tokio::select! {
+            result = &mut drained => return result,
+            () = shutdown.triggered() => {}
+        }
+        info!("Shutting down, draining for at most {:?}", drain_timeout);
+        match time::timeout(drain_timeout, drained).await {
+            Ok(result) => result?,
+            Err(_) => warn!(
+                "Drain did not finish within {:?}, exiting anyway",
+                drain_timeout
+            ),
+        }
+        info!("OTC server stopped");
+
+        Ok(())
+    }
+
+    fn monitoring_task_guard(&self) -> std::sync::MutexGuard<'_, Option<JoinHandle<()>>> {
+        self.monitoring_task
+            .lock()
+            .expect("monitoring task mutex poisoned")
+    }
+}
+
+/// The routes `mode` serves, with `state` applied
+fn build_router(mode: ServerMode, cors_domains: &[String], state: AppState) -> Router {
+    // Replicas serve the read endpoints that work from the database alone
+    let mut router = Router::new()
+        // Health checks
+        .route("/status", get(status_handler))
+        .route("/health", get(health_handler))
+        .route("/metrics", get(metrics_handler))
+        // API endpoints
+        .route("/api/v1/swaps/lookup", get(lookup_swaps))
+        .route("/api/v1/swaps/:id", get(get_swap))
+        .route("/api/v1/swaps/:id/receipt", get(get_swap_receipt))
+        .route("/api/v1/meta/swap-states", get(get_swap_states))
+        .route(CAPABILITIES_PATH, get(get_capabilities));
+    router = match mode {
+        ServerMode::Full => router
+            // WebSocket endpoints
+            .route("/ws", get(websocket_handler))
+            .route("/ws/mm", get(mm_websocket_handler))
+            .route(ATTESTATION_PATH, get(get_attestation))
+            .route("/api/v1/swaps", post(create_swap))
+            .route(
+                "/api/v1/swaps/:id/refund-address",
+                patch(set_refund_address),
+            )
+            .route("/api/v1/currencies", get(get_currencies))
+            .route("/api/v1/chains/:chain/fees", get(get_chain_fees))
+            .route(
+                "/api/v1/market-makers/connected",
+                get(get_connected_market_makers),
+            )
+            .route(
+                "/api/v1/market-makers/:id/stats",
+                get(get_market_maker_stats),
+            )
+            // Admin endpoints
+            .route("/admin/v1/summary", get(get_admin_summary))
+            .route(
+                "/admin/chains/:chain/confirmation-override",
+                post(set_confirmation_override),
+            )
+            .route(
+                "/admin/master-keys",
+                get(get_master_keys).post(rotate_master_key),
+            )
+            .route("/admin/master-keys/:version", delete(remove_master_key))
+            .route("/admin/swaps", get(list_swaps_by_reference))
+            .route("/admin/swaps/:id/cancel", post(cancel_swap))
+            .route(
+                "/admin/reconciliation/findings",
+                get(get_reconciliation_findings),
+            )
+            .route("/api/v1/reports/swaps.csv", get(export_swaps_csv)),
+        ServerMode::ApiOnly => router.route("/api/v1/swaps", post(create_swap_unavailable)),
+    };
+    router = router.merge(api_docs_router(ApiDoc::openapi()));
+
+    let mut app = router
+        .layer(DefaultBodyLimit::max(MAX_REQUEST_BODY_BYTES))
+        .layer(middleware::from_fn(trace_id_middleware))
+        .with_state(state);
+
+    if !cors_domains.is_empty() {
+        app = app.layer(build_cors_layer(cors_domains));
+        info!("CORS enabled for domains: {}", cors_domains.join(", "));
+    }
+    app
+}
+
+/// Delete expired idempotency keys every `IDEMPOTENCY_KEY_CLEANUP_INTERVAL`
+/// until `shutdown` is triggered
+async fn delete_expired_idempotency_keys(db: Database, clock: Arc<dyn Clock>, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(IDEMPOTENCY_KEY_CLEANUP_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            () = shutdown.triggered() => return,
+        }
+        match db.idempotency_keys().delete_expired(clock.now()).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Deleted {} expired idempotency keys", deleted),
+            Err(e) => error!("Failed to delete expired idempotency keys: {}", e),
+        }
+    }
+}
+
+/// Connect to every chain the server settles on, a full node needs all of them
+async fn connect_chains(
+    args: &OtcServerArgs,
+    supported_currencies: &SupportedCurrencies,
+    clock: &Arc<dyn Clock>,
+) -> Result<ChainRegistry> {
+    let required = |value: &Option<String>, arg: &'static str| {
+        value.clone().context(crate::MissingChainArgSnafu {
+            arg,
+            mode: args.mode,
+        })
+    };
+    let esplora_http_server_url =
+        required(&args.esplora_http_server_url, "esplora-http-server-url")?;
+    let ethereum_mainnet_rpc_url =
+        required(&args.ethereum_mainnet_rpc_url, "ethereum-mainnet-rpc-url")?;
 
-    info!("Initializing chain registry...");
     let mut chain_registry = ChainRegistry::new();
 
-    // Initialize Bitcoin chain
-    let bitcoin_chain = BitcoinChain::new(
-        &args.bitcoin_rpc_url,
-        args.bitcoin_rpc_auth,
-        &args.esplora_http_server_url,
-        args.bitcoin_network,
+    // Without a Core node every Bitcoin lookup and broadcast goes through esplora
+    let bitcoin_data_source = otc_chains::bitcoin::connect_data_source(
+        &esplora_http_server_url,
+        args.bitcoin_rpc_url
+            .as_deref()
+            .map(|url| (url, args.bitcoin_rpc_auth.clone())),
     )
     .await
     .map_err(|e| crate::Error::DatabaseInit {
@@ -72,13 +751,15 @@ pub async fn run_server(args: OtcServerArgs) -> Result<()> {
             message: format!("Failed to initialize Bitcoin chain: {e}"),
         },
     })?;
+    let bitcoin_chain = BitcoinChain::new(bitcoin_data_source, args.bitcoin_network)
+        .with_fee_target_blocks(args.bitcoin_fee_target_blocks);
     chain_registry.register(otc_models::ChainType::Bitcoin, Arc::new(bitcoin_chain));
 
-    // Initialize Ethereum chain
     let ethereum_chain = EthereumChain::new(
-        &args.ethereum_mainnet_rpc_url,
-        &args.ethereum_mainnet_token_indexer_url,
+        &ethereum_mainnet_rpc_url,
+        args.ethereum_mainnet_token_indexer_url.as_deref(),
         args.ethereum_mainnet_chain_id,
+        supported_currencies,
     )
     .await
     .map_err(|e| crate::Error::DatabaseInit {
@@ -86,127 +767,331 @@ pub async fn run_server(args: OtcServerArgs) -> Result<()> {
             message: format!("Failed to initialize Ethereum chain: {e}"),
         },
     })?;
+    let ethereum_chain = ethereum_chain
+        .with_finality_tags(!args.ethereum_ignore_finality_tags)
+        .with_subscriptions(args.ethereum_mainnet_ws_url.clone())
+        .with_clock(clock.clone());
     chain_registry.register(otc_models::ChainType::Ethereum, Arc::new(ethereum_chain));
 
-    let chain_registry = Arc::new(chain_registry);
+    let mut chain_ids = HashSet::from([args.ethereum_mainnet_chain_id]);
+    for network in &args.evm_network_rpc_urls {
+        ensure!(
+            chain_ids.insert(network.chain_id),
+            crate::DuplicateEvmNetworkSnafu {
+                chain_id: network.chain_id
+            }
+        );
+        let token_indexer_url =
+            evm_network_url(&args.evm_network_token_indexer_urls, network.chain_id);
+        let chain = EthereumChain::for_network(
+            &network.url,
+            token_indexer_url,
+            network.chain_id,
+            supported_currencies,
+        )
+        .await
+        .map_err(|e| crate::Error::DatabaseInit {
+            source: crate::error::OtcServerError::InvalidData {
+                message: format!("Failed to initialize EVM network {}: {e}", network.chain_id),
+            },
+        })?;
+        let chain = chain
+            .with_finality_tags(!args.ethereum_ignore_finality_tags)
+            .with_subscriptions(
+                evm_network_url(&args.evm_network_ws_urls, network.chain_id).map(str::to_string),
+            )
+            .with_clock(clock.clone());
+        info!("Registered EVM network {}", network.chain_id);
+        chain_registry.register_network(ChainNetwork::evm(network.chain_id), Arc::new(chain));
+    }
 
-    info!("Initializing services...");
+    Ok(chain_registry)
+}
 
-    // Initialize API key store
-    let api_key_store = Arc::new(ApiKeyStore::new(args.whitelist_file.into()).await?);
+/// Signs attestations of this server for the endpoint it serves
+pub struct Attester {
+    provider: Box<dyn AttestationProvider>,
+    endpoint: String,
+}
 
-    // Initialize MM registry with 5-second validation timeout
-    let mm_registry = Arc::new(MMRegistry::new(Duration::from_secs(5)));
+impl Attester {
+    /// A fresh attestation signed over the requester's `nonce`
+    pub fn attest(&self, nonce: Option<B256>) -> AttestationResult<AttestationDocument> {
+        self.provider.attest(&self.endpoint, None, nonce)
+    }
+}
 
-    let swap_manager = Arc::new(SwapManager::new(
-        db.clone(),
-        settings.clone(),
-        chain_registry.clone(),
-        mm_registry.clone(),
-    ));
+/// Attester of this server, only a full node runs in the enclave. The mock
+/// provider is the only one so far, without its key nothing is attested.
+fn attester(args: &OtcServerArgs, addr: SocketAddr) -> Result<Option<Attester>> {
+    let (ServerMode::Full, Some(signing_key)) = (args.mode, &args.mock_attestation_signing_key)
+    else {
+        warn!("No attestation provider configured, market makers can't verify this server");
+        return Ok(None);
+    };
+    let measurement = args
+        .mock_attestation_measurement
+        .context(crate::MissingAttestationMeasurementSnafu)?;
+    let provider =
+        MockAttestationProvider::new(signing_key, measurement).context(crate::AttestationSnafu)?;
+    warn!(
+        "Attesting with the mock provider (root of trust {}), anyone holding its key can impersonate this server",
+        provider.root_of_trust()
+    );
 
-    // Start the swap monitoring service
-    let swap_monitoring_service = Arc::new(SwapMonitoringService::new(
-        db.clone(),
-        settings.clone(),
-        chain_registry.clone(),
-        mm_registry.clone(),
-        args.chain_monitor_interval_seconds,
-    ));
+    let endpoint = args
+        .attestation_endpoint
+        .clone()
+        .unwrap_or_else(|| format!("ws://{addr}/ws/mm"));
+    // Signing once up front surfaces a broken provider at startup
+    let document = provider
+        .attest(&endpoint, None, None)
+        .context(crate::AttestationSnafu)?;
+    info!(
+        "Attesting measurement {} for {}",
+        document.statement.measurement, endpoint
+    );
+    Ok(Some(Attester {
+        provider: Box::new(provider),
+        endpoint,
+    }))
+}
 
-    info!("Starting swap monitoring service...");
-    tokio::spawn({
-        let monitoring_service = swap_monitoring_service.clone();
-        async move {
-            monitoring_service.run().await;
-        }
-    });
+/// Describe what this deployment supports, derived from the config it was started with
+fn build_capabilities(
+    mode: ServerMode,
+    quote_signing: QuoteSigningMode,
+    admin_api: bool,
+    chain_registry: &ChainRegistry,
+) -> Capabilities {
+    let mut chains = chain_registry.supported_chains();
+    chains.sort();
+    let full = mode == ServerMode::Full;
 
-    let state = AppState {
-        db,
-        swap_manager,
-        mm_registry,
-        api_key_store,
-    };
+    Capabilities {
+        server: ServerKind::Otc,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        api_versions: API_VERSIONS.iter().map(ToString::to_string).collect(),
+        mm_protocol: ProtocolVersions::default(),
+        features: Features {
+            quote_signing,
+            encrypted_key_handoff: false,
+            partial_quotes: false,
+            webhooks: false,
+            announcements: false,
+            admin_api: full && admin_api,
+        },
+        limits: Limits {
+            max_request_body_bytes: MAX_REQUEST_BODY_BYTES,
+            max_metadata_chars: Some(MAX_REASON_LEN),
+            rate_limit_per_minute: None,
+            quote_timeout_ms: None,
+            amount_limits_source: AmountLimitsSource::SupportedCurrencies,
+            quote_batch: None,
+        },
+        // A replica doesn't connect to chains, so it can't tell which are served
+        chains: full.then_some(chains),
+    }
+}
 
-    let mut app = Router::new()
-        // Health check
-        .route("/status", get(status_handler))
-        // WebSocket endpoints
-        .route("/ws", get(websocket_handler))
-        .route("/ws/mm", get(mm_websocket_handler))
-        // API endpoints
-        .route("/api/v1/swaps", post(create_swap))
-        .route("/api/v1/swaps/:id", get(get_swap))
-        .route(
-            "/api/v1/market-makers/connected",
-            get(get_connected_market_makers),
-        )
-        .with_state(state);
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "health",
+    responses((status = 200, description = "Server is up", body = Status))
+)]
+async fn status_handler() -> impl IntoResponse {
+    Json(Status {
+        status: "online".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
 
-    // Add CORS layer if cors_domain is specified
-    if let Some(ref cors_domain_pattern) = args.cors_domain {
-        let cors_domain = cors_domain_pattern.clone();
-        let cors = if cors_domain == "*" {
-            // Allow all origins
-            CorsLayer::new()
-                .allow_origin(tower_http::cors::Any)
-                .allow_methods(tower_http::cors::Any)
-                .allow_headers(tower_http::cors::Any)
-        } else {
-            // Handle specific patterns
-            CorsLayer::new()
-                .allow_origin(AllowOrigin::predicate(move |origin, _request_parts| {
-                    let origin_str = origin.to_str().unwrap_or("");
-
-                    // Support wildcard patterns
-                    if cors_domain.contains('*') {
-                        let pattern = cors_domain.replace("*", "");
-                        if cors_domain.starts_with('*') {
-                            origin_str.ends_with(&pattern)
-                        } else if cors_domain.ends_with('*') {
-                            origin_str.starts_with(&pattern[..pattern.len() - 1])
-                        } else {
-                            // Handle middle wildcards like "*.example.*"
-                            let parts: Vec<&str> = cors_domain.split('*').collect();
-                            parts.iter().all(|part| origin_str.contains(part))
-                        }
-                    } else {
-                        origin_str == cors_domain
-                    }
-                }))
-                .allow_methods(tower_http::cors::Any)
-                .allow_headers(tower_http::cors::Any)
-        };
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "Database is reachable", body = Health),
+        (status = 503, description = "Database is unreachable", body = ApiErrorResponse)
+    )
+)]
+async fn health_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Health>, crate::error::OtcServerError> {
+    state.db.ping().await.map_err(|e| {
+        error!("Health check failed: {}", e);
+        database_unavailable()
+    })?;
+    Ok(Json(Health {
+        database: state.db.pool_stats(),
+    }))
+}
 
-        app = app.layer(cors);
-        info!("CORS enabled for domain: {}", cors_domain_pattern);
+fn database_unavailable() -> crate::error::OtcServerError {
+    crate::error::OtcServerError::ServiceUnavailable {
+        service: "database".to_string(),
     }
+}
 
-    info!("Listening on {}", addr);
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses((status = 200, description = "Database pool and MM notification metrics in the Prometheus text format", body = String, content_type = "text/plain"))
+)]
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let up = state.db.ping().await.is_ok();
+    let mut metrics = render_pool_metrics(up, &state.db.pool_stats());
+    metrics.push_str(&render_status_update_metrics(
+        &state.mm_registry.status_update_counts(),
+    ));
+    metrics.push_str(&render_mm_socket_metrics(
+        &state.mm_socket_counters.snapshot(),
+    ));
+    metrics.push_str(&render_send_queue_metrics(
+        &state.mm_registry.send_queue_depths(),
+    ));
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+}
 
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .context(crate::ServerBindSnafu)?;
+/// Prometheus exposition of the database pool's state
+fn render_pool_metrics(up: bool, stats: &PoolStats) -> String {
+    let mut gauges = vec![
+        (
+            "otc_db_up",
+            "Whether the database answered the scrape's ping",
+            u8::from(up).to_string(),
+        ),
+        (
+            "otc_db_pool_connections",
+            "Open database connections, idle or in use",
+            stats.size.to_string(),
+        ),
+        (
+            "otc_db_pool_idle_connections",
+            "Idle database connections",
+            stats.idle.to_string(),
+        ),
+        (
+            "otc_db_pool_max_connections",
+            "Most database connections the pool opens",
+            stats.max_size.to_string(),
+        ),
+    ];
+    if let Some(wait) = stats.acquire_wait_seconds {
+        gauges.push((
+            "otc_db_pool_acquire_wait_seconds",
+            "Time the latest ping waited for a database connection",
+            wait.to_string(),
+        ));
+    }
 
-    axum::serve(listener, app)
-        .await
-        .context(crate::ServerStartSnafu)?;
+    gauges
+        .into_iter()
+        .map(|(name, help, value)| {
+            format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n")
+        })
+        .collect()
+}
 
-    Ok(())
+/// Prometheus exposition of the swap status updates sent to market makers
+fn render_status_update_metrics(counts: &StatusUpdateCounts) -> String {
+    [
+        (
+            "otc_mm_status_updates_sent_total",
+            "Swap status updates handed to a market maker's connection",
+            counts.sent,
+        ),
+        (
+            "otc_mm_status_updates_dropped_total",
+            "Swap status updates not delivered, the market maker was offline or too old",
+            counts.dropped,
+        ),
+    ]
+    .into_iter()
+    .map(|(name, help, value)| {
+        format!("# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n")
+    })
+    .collect()
 }
 
-async fn status_handler() -> impl IntoResponse {
-    Json(Status {
-        status: "online".to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
+/// Prometheus exposition of the market maker messages refused by the socket limits
+fn render_mm_socket_metrics(counts: &MmSocketCounts) -> String {
+    [
+        (
+            "otc_mm_oversized_messages_total",
+            "Market maker messages over the size cap",
+            counts.oversized,
+        ),
+        (
+            "otc_mm_malformed_messages_total",
+            "Market maker messages that failed to parse",
+            counts.malformed,
+        ),
+        (
+            "otc_mm_slow_consumer_disconnects_total",
+            "Market makers disconnected for letting their outgoing queue fill up",
+            counts.slow_consumers,
+        ),
+        (
+            "otc_mm_socket_limit_disconnects_total",
+            "Market makers disconnected for oversized or malformed messages, or for not keeping up",
+            counts.disconnects,
+        ),
+    ]
+    .into_iter()
+    .map(|(name, help, value)| {
+        format!("# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n")
     })
+    .collect()
+}
+
+/// Prometheus exposition of the messages waiting on each market maker's socket
+fn render_send_queue_metrics(depths: &[(Uuid, usize)]) -> String {
+    let name = "otc_mm_send_queue_depth";
+    let mut metrics = format!(
+        "# HELP {name} Messages queued for a market maker and not yet written to its socket\n# TYPE {name} gauge\n"
+    );
+    for (market_maker_id, depth) in depths {
+        metrics.push_str(&format!(
+            "{name}{{market_maker_id=\"{market_maker_id}\"}} {depth}\n"
+        ));
+    }
+    metrics
 }
 
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "websocket",
+    responses((status = 101, description = "Upgraded to an echo websocket"))
+)]
 async fn websocket_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
     ws.on_upgrade(handle_socket)
 }
 
+#[utoipa::path(
+    get,
+    path = "/ws/mm",
+    tag = "websocket",
+    params(
+        ("x-protocol-version" = Option<String>, Header, description = "MM protocol version the client speaks"),
+        ("x-attestation-nonce" = Option<String>, Header, description = "Fresh 32 byte hex nonce the attestation in `Connected` is signed over")
+    ),
+    security(("mm_api_key_id" = [], "mm_api_key" = [])),
+    responses(
+        (status = 101, description = "Upgraded to the market maker websocket"),
+        (status = 400, description = "Malformed headers or incompatible protocol version"),
+        (status = 401, description = "Missing or invalid API key"),
+        (status = 403, description = "API key lacks the otc scope")
+    )
+)]
+/// Market maker connection. The server's first frame is `{"Connected": Connected}`,
+/// every later frame in either direction is a `ProtocolMessage` envelope.
 async fn mm_websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
@@ -242,11 +1127,64 @@ async fn mm_websocket_handler(
         }
     };
 
+    let protocol_version = match headers.get(PROTOCOL_VERSION_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(version) => version.to_string(),
+            Err(_) => {
+                return (StatusCode::BAD_REQUEST, "Invalid protocol version header")
+                    .into_response();
+            }
+        },
+        None => MIN_PROTOCOL_VERSION.to_string(),
+    };
+    if let Err(e) = ensure_version_compatible(&protocol_version) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    let attestation_nonce = match headers.get(ATTESTATION_NONCE_HEADER) {
+        Some(value) => match value
+            .to_str()
+            .ok()
+            .and_then(|nonce| nonce.parse::<B256>().ok())
+        {
+            Some(nonce) => Some(nonce),
+            None => {
+                return (StatusCode::BAD_REQUEST, "Invalid attestation nonce header")
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+
     // Validate the API key
-    match state.api_key_store.validate_by_id(&api_key_id, api_key) {
-        Ok(market_maker_id) => {
-            info!("Market maker {} authenticated via headers", market_maker_id);
-            ws.on_upgrade(move |socket| handle_mm_socket(socket, state, market_maker_id))
+    let api_keys = state.api_key_store.borrow().clone();
+    match api_keys
+        .validate_by_id(&api_key_id, api_key)
+        .and_then(|key| key.require_scope(ApiKeyScope::Otc))
+    {
+        Ok(key) => {
+            info!(
+                "Market maker {} authenticated via headers",
+                key.market_maker
+            );
+            state
+                .mm_socket_limits
+                .configure(ws)
+                .on_upgrade(move |socket| {
+                    handle_mm_socket(socket, state, key, protocol_version, attestation_nonce)
+                })
+        }
+        Err(e @ AuthError::Revoked { .. }) => {
+            warn!("Rejected connection with a revoked API key: {}", e);
+            (StatusCode::UNAUTHORIZED, "Invalid API key").into_response()
+        }
+        Err(e @ AuthError::MissingScope { .. }) => {
+            warn!("Rejected connection: {}", e);
+            (
+                StatusCode::FORBIDDEN,
+                "API key is not allowed to use the otc API",
+            )
+                .into_response()
         }
         Err(e) => {
             error!("API key validation failed: {}", e);
@@ -284,17 +1222,55 @@ async fn handle_socket(mut socket: WebSocket) {
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/swaps",
+    tag = "swaps",
+    params(
+        CreateSwapQuery,
+        ("idempotency-key" = Option<String>, Header, description = "Replays the first response for a repeated request")
+    ),
+    request_body = CreateSwapRequest,
+    responses(
+        (status = 200, description = "Swap created", body = CreateSwapResponse),
+        (status = 400, description = "Invalid request, quote or quote lock, or a payout that would be dust", body = ApiErrorResponse),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+        (status = 408, description = "Market maker didn't validate the quote in time", body = ApiErrorResponse),
+        (status = 409, description = "Quote rejected or stale", body = ApiErrorResponse),
+        (status = 422, description = "Idempotency key reused with a different request", body = ApiErrorResponse),
+        (status = 503, description = "Market maker offline, or this is an API-only replica", body = ApiErrorResponse)
+    )
+)]
 async fn create_swap(
     State(state): State<AppState>,
-    Json(request): Json<CreateSwapRequest>,
+    headers: HeaderMap,
+    Query(query): Query<CreateSwapQuery>,
+    Extension(TraceId(request_trace_id)): Extension<TraceId>,
+    ValidatedJson(request): ValidatedJson<CreateSwapRequest>,
 ) -> Result<Json<CreateSwapResponse>, crate::error::OtcServerError> {
-    state
-        .swap_manager
-        .create_swap(request)
-        .await
-        .map(Json)
+    // Keep the id from quote time so the swap's logs line up with the quote's
+    let trace_id = request.trace_id.clone().unwrap_or(request_trace_id);
+    let span = info_span!("create_swap", trace_id = %trace_id);
+    let result = match idempotency_key(&headers)? {
+        Some(key) => {
+            state
+                .swap_manager
+                .create_swap_idempotent(key, request, &trace_id)
+                .instrument(span)
+                .await
+        }
+        None => {
+            state
+                .swap_manager
+                .create_swap(request, &trace_id)
+                .instrument(span)
+                .await
+        }
+    };
+    let mut response = result
         // TODO: Impl a cleaner way to map these errors
         .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
             crate::services::swap_manager::SwapError::QuoteNotFound { .. } => {
                 crate::error::OtcServerError::NotFound
             }
@@ -303,6 +1279,46 @@ async fn create_swap(
                     message: "Quote has expired".to_string(),
                 }
             }
+            crate::services::swap_manager::SwapError::QuoteExpiring { .. } => {
+                crate::error::OtcServerError::QuoteExpiring {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::QuoteSignatureInvalid { .. } => {
+                crate::error::OtcServerError::QuoteSignatureInvalid {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::QuoteLockInvalid { .. } => {
+                crate::error::OtcServerError::BadRequest {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::QuoteStalePrice { .. } => {
+                crate::error::OtcServerError::QuoteStalePrice {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::UnsupportedToken {
+                source: source @ otc_models::UnsupportedCurrency::DecimalsMismatch { .. },
+            } => crate::error::OtcServerError::DecimalsMismatch {
+                message: source.to_string(),
+            },
+            crate::services::swap_manager::SwapError::UnsupportedToken { source } => {
+                crate::error::OtcServerError::UnsupportedToken {
+                    message: source.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::InvalidDestinationAddress { .. } => {
+                crate::error::OtcServerError::BadRequest {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::OutputBelowDust { .. } => {
+                crate::error::OtcServerError::OutputBelowDust {
+                    message: e.to_string(),
+                }
+            }
             crate::services::swap_manager::SwapError::MarketMakerRejected => {
                 crate::error::OtcServerError::Conflict {
                     message: "Market maker rejected the quote".to_string(),
@@ -333,24 +1349,125 @@ async fn create_swap(
                     message: e.to_string(),
                 }
             }
+            crate::services::swap_manager::SwapError::MasterKeyUnavailable { .. } => {
+                crate::error::OtcServerError::Internal {
+                    message: e.to_string(),
+                }
+            }
             crate::services::swap_manager::SwapError::InvalidEvmAccountAddress { .. } => {
                 crate::error::OtcServerError::BadRequest {
                     message: e.to_string(),
                 }
             }
-        })
-}
-
-async fn get_swap(
-    State(state): State<AppState>,
-    Path(swap_id): Path<Uuid>,
-) -> Result<Json<SwapResponse>, crate::error::OtcServerError> {
-    state
+            crate::services::swap_manager::SwapError::ConfirmationPolicy { .. } => {
+                crate::error::OtcServerError::Internal {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::SwapNotSettled { .. } => {
+                crate::error::OtcServerError::Internal {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::SwapNotCancellable { .. }
+            | crate::services::swap_manager::SwapError::MarketMakerMayStillPay { .. } => {
+                crate::error::OtcServerError::Conflict {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::IdempotencyKeyReused => {
+                crate::error::OtcServerError::IdempotencyKeyReused {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::IdempotentRequestInProgress => {
+                crate::error::OtcServerError::Conflict {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::IdempotencySerialization { .. } => {
+                crate::error::OtcServerError::Internal {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::InvalidRefundAddress { .. } => {
+                crate::error::OtcServerError::BadRequest {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::RefundAddressLocked { .. } => {
+                crate::error::OtcServerError::Conflict {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::RefundAddressSignatureInvalid => {
+                crate::error::OtcServerError::Authentication {
+                    message: e.to_string(),
+                }
+            }
+        })?;
+
+    if query.include_qr {
+        let qr_svg = render_qr_svg(&response.payment_uri).map_err(|e| {
+            crate::error::OtcServerError::Internal {
+                message: format!("Failed to render QR code: {e}"),
+            }
+        })?;
+        response.qr_svg = Some(qr_svg);
+    }
+
+    Ok(Json(response))
+}
+
+/// Swaps are created on the full node, replicas only serve reads
+async fn create_swap_unavailable() -> crate::error::OtcServerError {
+    crate::error::OtcServerError::ServiceUnavailable {
+        service: "swap creation on an API-only replica".to_string(),
+    }
+}
+
+/// The optional Idempotency-Key header, 1 to `MAX_IDEMPOTENCY_KEY_LEN` visible ASCII characters
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, crate::error::OtcServerError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .filter(|key| {
+            !key.is_empty()
+                && key.len() <= MAX_IDEMPOTENCY_KEY_LEN
+                && key.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .ok_or_else(|| crate::error::OtcServerError::BadRequest {
+            message: format!(
+                "Idempotency-Key must be 1 to {MAX_IDEMPOTENCY_KEY_LEN} visible ASCII characters"
+            ),
+        })?;
+    Ok(Some(key))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/swaps/{id}",
+    tag = "swaps",
+    params(("id" = Uuid, Path, description = "Swap id")),
+    responses(
+        (status = 200, description = "The swap", body = SwapResponse),
+        (status = 404, description = "Swap not found", body = ApiErrorResponse)
+    )
+)]
+async fn get_swap(
+    State(state): State<AppState>,
+    Path(swap_id): Path<Uuid>,
+) -> Result<Json<SwapResponse>, crate::error::OtcServerError> {
+    state
         .swap_manager
         .get_swap(swap_id)
         .await
         .map(Json)
         .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
             crate::services::swap_manager::SwapError::QuoteNotFound { .. } => {
                 crate::error::OtcServerError::NotFound
             }
@@ -375,19 +1492,692 @@ async fn get_swap(
         })
 }
 
-#[derive(Serialize)]
-struct ConnectedMarketMakersResponse {
-    market_makers: Vec<Uuid>,
+#[utoipa::path(
+    get,
+    path = "/api/v1/swaps/lookup",
+    tag = "swaps",
+    params(SwapLookupQuery),
+    responses(
+        (status = 200, description = "Matching swaps", body = Vec<SwapResponse>),
+        (status = 400, description = "Not exactly one lookup key", body = ApiErrorResponse),
+        (status = 429, description = "Too many lookups from this address", body = ApiErrorResponse)
+    )
+)]
+async fn lookup_swaps(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<SwapLookupQuery>,
+) -> Result<Json<Vec<SwapResponse>>, crate::error::OtcServerError> {
+    if !state.swap_lookup_rate_limiter.check(&headers, client) {
+        return Err(crate::error::OtcServerError::RateLimited {
+            message: "Too many swap lookups, try again in a minute".to_string(),
+        });
+    }
+    let lookup = SwapLookup::try_from(query).map_err(|message| {
+        crate::error::OtcServerError::BadRequest {
+            message: message.to_string(),
+        }
+    })?;
+
+    state
+        .swap_manager
+        .lookup_swaps(&lookup)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
+            _ => crate::error::OtcServerError::Internal {
+                message: e.to_string(),
+            },
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/swaps/{id}/receipt",
+    tag = "swaps",
+    params(("id" = Uuid, Path, description = "Swap id")),
+    responses(
+        (status = 200, description = "Receipt of the settled swap", body = SwapReceipt),
+        (status = 404, description = "Swap not found", body = ApiErrorResponse),
+        (status = 409, description = "Swap not settled yet", body = ApiErrorResponse)
+    )
+)]
+async fn get_swap_receipt(
+    State(state): State<AppState>,
+    Path(swap_id): Path<Uuid>,
+) -> Result<Json<SwapReceipt>, crate::error::OtcServerError> {
+    state
+        .swap_manager
+        .get_swap_receipt(swap_id)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
+            crate::services::swap_manager::SwapError::Database {
+                source: crate::error::OtcServerError::NotFound,
+            } => crate::error::OtcServerError::NotFound,
+            crate::services::swap_manager::SwapError::SwapNotSettled { .. } => {
+                crate::error::OtcServerError::Conflict {
+                    message: e.to_string(),
+                }
+            }
+            _ => crate::error::OtcServerError::Internal {
+                message: e.to_string(),
+            },
+        })
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/swaps/{id}/refund-address",
+    tag = "swaps",
+    params(("id" = Uuid, Path, description = "Swap id")),
+    request_body = SetRefundAddressRequest,
+    responses(
+        (status = 200, description = "Refund address set", body = SwapResponse),
+        (status = 400, description = "Address isn't valid on the deposit chain", body = ApiErrorResponse),
+        (status = 401, description = "Not signed by the swap's user EVM account", body = ApiErrorResponse),
+        (status = 404, description = "Swap not found", body = ApiErrorResponse),
+        (status = 409, description = "Refund address can no longer change", body = ApiErrorResponse)
+    )
+)]
+async fn set_refund_address(
+    State(state): State<AppState>,
+    Path(swap_id): Path<Uuid>,
+    ValidatedJson(request): ValidatedJson<SetRefundAddressRequest>,
+) -> Result<Json<SwapResponse>, crate::error::OtcServerError> {
+    state
+        .swap_manager
+        .set_refund_address(swap_id, request)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
+            crate::services::swap_manager::SwapError::Database {
+                source: crate::error::OtcServerError::NotFound,
+            } => crate::error::OtcServerError::NotFound,
+            crate::services::swap_manager::SwapError::InvalidRefundAddress { .. } => {
+                crate::error::OtcServerError::BadRequest {
+                    message: e.to_string(),
+                }
+            }
+            crate::services::swap_manager::SwapError::RefundAddressSignatureInvalid => {
+                crate::error::OtcServerError::Authentication {
+                    message: e.to_string(),
+                }
+            }
+            // The swap can move on between the check and the write
+            crate::services::swap_manager::SwapError::RefundAddressLocked { .. }
+            | crate::services::swap_manager::SwapError::Database {
+                source: crate::error::OtcServerError::InvalidState { .. },
+            } => crate::error::OtcServerError::Conflict {
+                message: e.to_string(),
+            },
+            _ => crate::error::OtcServerError::Internal {
+                message: e.to_string(),
+            },
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/currencies",
+    tag = "swaps",
+    responses((status = 200, description = "Supported tokens and confirmation requirements", body = CurrenciesResponse))
+)]
+async fn get_currencies(
+    State(state): State<AppState>,
+) -> Result<Json<CurrenciesResponse>, crate::error::OtcServerError> {
+    let resolved = state
+        .confirmation_policy
+        .resolve_all()
+        .await
+        .map_err(confirmation_policy_error)?;
+
+    let chains = resolved
+        .into_iter()
+        .map(|r| ChainCurrencyResponse {
+            chain: r.chain,
+            required_confirmations: r.required,
+            baseline_confirmations: r.baseline,
+            estimated_confirmation_seconds: r.estimated_confirmation_time().as_secs(),
+            confirmation_override_expires_at: r.active_override.as_ref().map(|o| o.expires_at),
+            // Every network of the chain, a token's chain id tells them apart
+            tokens: state
+                .supported_currencies
+                .all()
+                .iter()
+                .filter(|currency| currency.chain == r.chain)
+                .cloned()
+                .collect(),
+        })
+        .collect();
+
+    Ok(Json(CurrenciesResponse { chains }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/chains/{chain}/fees",
+    tag = "swaps",
+    params(("chain" = ChainType, Path, description = "Chain to report fees for")),
+    responses(
+        (status = 200, description = "Current network fees, cached for a few seconds", body = ChainFeesResponse),
+        (status = 404, description = "Chain not supported", body = ApiErrorResponse),
+        (status = 503, description = "Chain fee backend unavailable", body = ApiErrorResponse)
+    )
+)]
+async fn get_chain_fees(
+    State(state): State<AppState>,
+    Path(chain): Path<ChainType>,
+) -> Result<Json<ChainFeesResponse>, crate::error::OtcServerError> {
+    let fees = state.fee_telemetry.fees(chain).await.map_err(|e| match e {
+        FeeTelemetryError::ChainNotSupported { .. } => crate::error::OtcServerError::NotFound,
+        FeeTelemetryError::Fetch { .. } => {
+            warn!("{}", e);
+            crate::error::OtcServerError::ServiceUnavailable {
+                service: format!("{chain:?} fee estimates"),
+            }
+        }
+    })?;
+    Ok(Json(ChainFeesResponse::from(&fees)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/meta/swap-states",
+    tag = "swaps",
+    responses((status = 200, description = "Every swap status with a description and the statuses it may move to", body = SwapStatesResponse))
+)]
+async fn get_swap_states() -> Json<SwapStatesResponse> {
+    Json(SwapStatesResponse::from_state_machine())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/capabilities",
+    tag = "health",
+    responses((status = 200, description = "Versions, features and limits of this server", body = Capabilities))
+)]
+async fn get_capabilities(State(state): State<AppState>) -> Json<Capabilities> {
+    Json(state.capabilities.as_ref().clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/attestation",
+    tag = "health",
+    params(AttestationQuery),
+    responses(
+        (status = 200, description = "Signed statement of the code this server runs", body = AttestationDocument),
+        (status = 404, description = "Server is not running in a TEE", body = ApiErrorResponse),
+        (status = 500, description = "Attestation could not be signed", body = ApiErrorResponse)
+    )
+)]
+async fn get_attestation(
+    State(state): State<AppState>,
+    Query(query): Query<AttestationQuery>,
+) -> Result<Json<AttestationDocument>, crate::error::OtcServerError> {
+    let attester = state
+        .attester
+        .as_ref()
+        .ok_or(crate::error::OtcServerError::NotFound)?;
+    attester
+        .attest(query.nonce)
+        .map(Json)
+        .map_err(|e| crate::error::OtcServerError::Internal {
+            message: format!("failed to attest: {e}"),
+        })
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/chains/{chain}/confirmation-override",
+    tag = "admin",
+    params(("chain" = ChainType, Path, description = "Chain to override")),
+    request_body = SetConfirmationOverrideRequest,
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Override in effect", body = ConfirmationOverride),
+        (status = 400, description = "Invalid override", body = ApiErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse)
+    )
+)]
+async fn set_confirmation_override(
+    State(state): State<AppState>,
+    Path(chain): Path<ChainType>,
+    headers: HeaderMap,
+    ValidatedJson(request): ValidatedJson<SetConfirmationOverrideRequest>,
+) -> Result<Json<ConfirmationOverride>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+
+    let rule = request
+        .rule()
+        .ok_or_else(|| crate::error::OtcServerError::BadRequest {
+            message: "exactly one of multiplier or absolute must be set".to_string(),
+        })?;
+
+    state
+        .confirmation_policy
+        .set_override(chain, rule, request.expires_at, request.reason)
+        .await
+        .map(Json)
+        .map_err(confirmation_policy_error)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/swaps/{id}/cancel",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Swap id")),
+    request_body = CancelSwapRequest,
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Swap cancelled", body = SwapResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse),
+        (status = 404, description = "Swap not found", body = ApiErrorResponse),
+        (status = 409, description = "Swap can no longer be cancelled, or not yet without the market maker's agreement", body = ApiErrorResponse)
+    )
+)]
+async fn cancel_swap(
+    State(state): State<AppState>,
+    Path(swap_id): Path<Uuid>,
+    headers: HeaderMap,
+    ValidatedJson(request): ValidatedJson<CancelSwapRequest>,
+) -> Result<Json<SwapResponse>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+
+    state
+        .swap_manager
+        .cancel_swap(swap_id, &request.reason)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
+            crate::services::swap_manager::SwapError::Database {
+                source: crate::error::OtcServerError::NotFound,
+            } => crate::error::OtcServerError::NotFound,
+            crate::services::swap_manager::SwapError::SwapNotCancellable { .. }
+            | crate::services::swap_manager::SwapError::MarketMakerMayStillPay { .. } => {
+                crate::error::OtcServerError::Conflict {
+                    message: e.to_string(),
+                }
+            }
+            _ => crate::error::OtcServerError::Internal {
+                message: e.to_string(),
+            },
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/master-keys",
+    tag = "admin",
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Master key versions in use", body = MasterKeysResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse)
+    )
+)]
+async fn get_master_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<MasterKeysResponse>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+    master_keys_response(&state).await.map(Json)
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/master-keys",
+    tag = "admin",
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "New master key added and made current", body = MasterKeysResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse),
+        (status = 409, description = "Master keys are read-only", body = ApiErrorResponse)
+    )
+)]
+async fn rotate_master_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<MasterKeysResponse>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+    state.settings.add_master_key().map_err(settings_error)?;
+    master_keys_response(&state).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/master-keys/{version}",
+    tag = "admin",
+    params(("version" = u32, Path, description = "Master key version")),
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 204, description = "Master key removed"),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse),
+        (status = 404, description = "Unknown master key version", body = ApiErrorResponse),
+        (status = 409, description = "Key is current or still used by active swaps", body = ApiErrorResponse)
+    )
+)]
+/// Drop a retired master key, refused while any swap may still need a key derived from it
+async fn remove_master_key(
+    State(state): State<AppState>,
+    Path(version): Path<u32>,
+    headers: HeaderMap,
+) -> Result<StatusCode, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+
+    let active_swaps = state
+        .db
+        .swaps()
+        .count_active_by_master_key_version()
+        .await?
+        .get(&version)
+        .copied()
+        .unwrap_or(0);
+    if active_swaps > 0 {
+        return Err(crate::error::OtcServerError::Conflict {
+            message: format!("{active_swaps} swaps may still need master key version {version}"),
+        });
+    }
+
+    state
+        .settings
+        .remove_master_key(version)
+        .map_err(settings_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/swaps",
+    tag = "admin",
+    params(AdminSwapsQuery),
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Swaps tagged with the reference, newest first", body = Vec<SwapResponse>),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse)
+    )
+)]
+/// Swaps by the reference their user gave. References are easy to guess, so
+/// unlike the public lookup this needs the admin key
+async fn list_swaps_by_reference(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminSwapsQuery>,
+) -> Result<Json<Vec<SwapResponse>>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+    state
+        .swap_manager
+        .swaps_by_external_reference(&query.external_reference)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            e if e.is_transient() => database_unavailable(),
+            _ => crate::error::OtcServerError::Internal {
+                message: e.to_string(),
+            },
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/reconciliation/findings",
+    tag = "admin",
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Open reconciliation findings", body = ReconciliationFindingsResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse)
+    )
+)]
+/// Mismatches the settlement reconciliation job found and still sees
+async fn get_reconciliation_findings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ReconciliationFindingsResponse>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+    let findings = state.db.reconciliation_findings().get_open().await?;
+    Ok(Json(ReconciliationFindingsResponse { findings }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/v1/summary",
+    tag = "admin",
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Swaps by status, connected market makers and how monitoring keeps up, cached for a few seconds", body = AdminSummaryResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse),
+        (status = 503, description = "Database is unreachable", body = ApiErrorResponse)
+    )
+)]
+/// One document summarizing the server, for dashboards and alert scripts
+async fn get_admin_summary(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<AdminSummaryResponse>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+    state.admin_summary.summary().await.map(Json).map_err(|e| {
+        if e.is_transient() {
+            database_unavailable()
+        } else {
+            e
+        }
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/reports/swaps.csv",
+    tag = "admin",
+    params(SwapReportQuery),
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "One line per swap created in the range, oldest first", body = String, content_type = "text/csv"),
+        (status = 400, description = "Empty or inverted range", body = ApiErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse)
+    )
+)]
+/// Swaps for accounting, streamed as they are read so a range of any size
+/// fits in memory
+async fn export_swaps_csv(
+    State(state): State<AppState>,
+    Query(query): Query<SwapReportQuery>,
+    headers: HeaderMap,
+) -> Result<Response, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+    if query.from >= query.to {
+        return Err(crate::error::OtcServerError::BadRequest {
+            message: "from must be before to".to_string(),
+        });
+    }
+
+    let rows = state
+        .db
+        .swaps()
+        .stream_range(
+            query.from,
+            query.to,
+            query.status.into_iter().collect(),
+            query.external_reference,
+            SWAP_REPORT_PAGE_SIZE,
+        )
+        .map_ok(|entry| swap_report_row(&entry))
+        // The status line is already sent, all that's left is cutting the body short
+        .inspect_err(|e| error!("Swap report failed mid-stream: {e}"));
+    let header_line = Ok::<_, crate::error::OtcServerError>(swap_report_header());
+    let body = stream::once(async move { header_line }).chain(rows);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"swaps.csv\"",
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+async fn master_keys_response(
+    state: &AppState,
+) -> Result<MasterKeysResponse, crate::error::OtcServerError> {
+    let active_swaps = state
+        .db
+        .swaps()
+        .count_active_by_master_key_version()
+        .await?;
+    Ok(MasterKeysResponse {
+        current_version: state.settings.current_master_key_version(),
+        keys: state
+            .settings
+            .master_key_versions()
+            .into_iter()
+            .map(|version| MasterKeyInfo {
+                version,
+                active_swaps: active_swaps.get(&version).copied().unwrap_or(0),
+            })
+            .collect(),
+    })
+}
+
+fn settings_error(e: SettingsError) -> crate::error::OtcServerError {
+    match e {
+        SettingsError::UnknownMasterKeyVersion { .. } => crate::error::OtcServerError::NotFound,
+        SettingsError::CurrentMasterKey { .. } | SettingsError::ReadOnlyMasterKeys => {
+            crate::error::OtcServerError::Conflict {
+                message: e.to_string(),
+            }
+        }
+        _ => crate::error::OtcServerError::Internal {
+            message: e.to_string(),
+        },
+    }
+}
+
+fn authorize_admin(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(), crate::error::OtcServerError> {
+    let expected = state.admin_api_key.as_deref().ok_or_else(|| {
+        crate::error::OtcServerError::Authorization {
+            message: "Admin API is disabled".to_string(),
+        }
+    })?;
+
+    let provided = headers
+        .get("x-admin-api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| crate::error::OtcServerError::Authentication {
+            message: "Missing X-Admin-API-Key header".to_string(),
+        })?;
+
+    // Compare without short-circuiting so timing doesn't reveal the matching prefix
+    let matches = expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+    if !matches {
+        return Err(crate::error::OtcServerError::Authentication {
+            message: "Invalid admin API key".to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn confirmation_policy_error(e: ConfirmationPolicyError) -> crate::error::OtcServerError {
+    match e {
+        ConfirmationPolicyError::Database { .. } => crate::error::OtcServerError::Internal {
+            message: e.to_string(),
+        },
+        _ => crate::error::OtcServerError::BadRequest {
+            message: e.to_string(),
+        },
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/market-makers/connected",
+    tag = "market-makers",
+    params(ConnectedMarketMakersQuery),
+    responses((status = 200, description = "Connected market makers, as ids or with their connection metadata", body = ConnectedMarketMakersResponse))
+)]
 async fn get_connected_market_makers(
     State(state): State<AppState>,
+    Query(query): Query<ConnectedMarketMakersQuery>,
 ) -> Json<ConnectedMarketMakersResponse> {
-    let market_makers = state.mm_registry.get_connected_market_makers();
-    Json(ConnectedMarketMakersResponse { market_makers })
+    let market_makers = state.mm_registry.connected_market_maker_details();
+    Json(query.respond(market_makers))
 }
 
-async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: String) {
+#[utoipa::path(
+    get,
+    path = "/api/v1/market-makers/{id}/stats",
+    tag = "market-makers",
+    params(("id" = Uuid, Path, description = "Market maker id"), MarketMakerStatsQuery),
+    security(("admin_api_key" = [])),
+    responses(
+        (status = 200, description = "Statistics per window", body = MarketMakerStatsResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ApiErrorResponse),
+        (status = 403, description = "Admin API disabled", body = ApiErrorResponse)
+    )
+)]
+/// Admin only, the stats feed whitelisting decisions. RFQ response and quote
+/// win rates aren't reported, the OTC server never sees the RFQ traffic
+async fn get_market_maker_stats(
+    State(state): State<AppState>,
+    Path(market_maker_id): Path<Uuid>,
+    Query(query): Query<MarketMakerStatsQuery>,
+    headers: HeaderMap,
+) -> Result<Json<MarketMakerStatsResponse>, crate::error::OtcServerError> {
+    authorize_admin(&state, &headers)?;
+
+    let windows = query
+        .window
+        .map_or_else(|| StatsWindow::ALL.to_vec(), |window| vec![window]);
+    let now = chrono::Utc::now();
+    let mut stats = Vec::with_capacity(windows.len());
+    for window in windows {
+        let since = now - window.duration();
+        let window_stats = state
+            .db
+            .market_maker_stats()
+            .stats(market_maker_id, since)
+            .await?;
+        stats.push(MarketMakerWindowStats::new(window, since, window_stats));
+    }
+
+    Ok(Json(MarketMakerStatsResponse {
+        market_maker_id,
+        windows: stats,
+    }))
+}
+
+async fn handle_mm_socket(
+    socket: WebSocket,
+    state: AppState,
+    key: ValidatedApiKey,
+    protocol_version: String,
+    attestation_nonce: Option<B256>,
+) {
+    let market_maker_id = key.market_maker.clone();
     info!(
         "Market maker {} WebSocket connection established",
         market_maker_id
@@ -402,36 +2192,44 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
         }
     };
 
-    // Channel for sending messages to the MM
-    let (tx, mut rx) = mpsc::channel::<ProtocolMessage<MMRequest>>(100);
+    // Messages for the MM wait here for the socket's one writer. Registry
+    // sends never block on it, an MM that lets it fill up is dropped
+    let (tx, rx) =
+        mpsc::channel::<ProtocolMessage<MMRequest>>(state.mm_socket_limits.send_queue_capacity);
 
     // Split the socket for bidirectional communication
-    let (sender, mut receiver) = socket.split();
-
-    // Register the MM immediately (already authenticated via headers)
-    state.mm_registry.register(
-        mm_uuid,
-        tx.clone(),
-        "1.0.0".to_string(), // Default protocol version
-    );
+    let (mut sender, mut receiver) = socket.split();
 
-    let mm_id = market_maker_id;
+    // Signed over the MM's nonce, so the document can't be replayed to it later
+    let attestation = match state
+        .attester
+        .as_ref()
+        .map(|attester| attester.attest(attestation_nonce))
+    {
+        Some(Ok(document)) => Some(document),
+        Some(Err(e)) => {
+            error!(
+                "Failed to attest to market maker {}: {}",
+                market_maker_id, e
+            );
+            None
+        }
+        None => None,
+    };
 
     // Send Connected response
     let connected_response = Connected {
         session_id: Uuid::new_v4(),
         server_version: env!("CARGO_PKG_VERSION").to_string(),
         timestamp: chrono::Utc::now(),
+        attestation,
     };
 
     let response = serde_json::json!({
         "Connected": connected_response
     });
 
-    let (sender_tx, mut sender_rx) = mpsc::channel::<Message>(100);
-
-    // Send initial connected response
-    if sender_tx
+    if sender
         .send(Message::Text(response.to_string()))
         .await
         .is_err()
@@ -440,73 +2238,171 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
         return;
     }
 
-    // Spawn task to handle outgoing messages from the registry
-    let mm_id_clone = mm_id.clone();
-    let sender_tx_clone = sender_tx.clone();
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if let Ok(json) = serde_json::to_string(&msg) {
-                if sender_tx_clone.send(Message::Text(json)).await.is_err() {
-                    error!("Failed to send message to market maker {}", mm_id_clone);
-                    break;
-                }
-            }
+    // Register the MM immediately (already authenticated via headers)
+    let speaks_going_away = is_version_at_least(&protocol_version, GOING_AWAY_VERSION);
+    let slow_consumer = state.mm_registry.register(mm_uuid, tx, protocol_version);
+
+    let mm_id = market_maker_id;
+
+    let (close_tx, close_rx) = oneshot::channel();
+    let mut close_tx = Some(close_tx);
+    let mut writer = tokio::spawn(write_mm_socket(
+        sender,
+        rx,
+        close_rx,
+        slow_consumer,
+        speaks_going_away,
+        mm_id.clone(),
+        state.mm_socket_counters.clone(),
+    ));
+    let mut close = |frame| {
+        if let Some(close_tx) = close_tx.take() {
+            let _ = close_tx.send(frame);
         }
-    });
+    };
 
-    // Spawn task to forward messages to the socket
-    let mm_id_clone = mm_id.clone();
-    let mut sender = sender;
-    tokio::spawn(async move {
-        while let Some(msg) = sender_rx.recv().await {
-            if sender.send(msg).await.is_err() {
-                error!(
-                    "Failed to send message to market maker {} socket",
-                    mm_id_clone
+    // Handle incoming messages
+    let mut guard = MmSocketGuard::new(state.mm_socket_limits, &state.mm_socket_counters, &mm_id);
+    let mut api_keys = state.api_key_store.clone();
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            // The writer only stops once the socket is closing
+            _ = &mut writer => break,
+            // The session lasts only as long as the key that opened it
+            Ok(()) = api_keys.changed() => {
+                if api_keys.borrow_and_update().still_accepts(&key) {
+                    continue;
+                }
+                warn!(
+                    "Disconnecting market maker {}, API key {} was removed, revoked or rotated",
+                    mm_id, key.id
                 );
+                close(CloseFrame {
+                    code: close_code::POLICY,
+                    reason: Cow::Borrowed(API_KEY_INVALIDATED_REASON),
+                });
                 break;
             }
-        }
-    });
-
-    // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
+        };
         match msg {
             Ok(Message::Text(text)) => {
+                if let Some(frame) = guard.check_size(text.len()) {
+                    close(frame);
+                    break;
+                }
                 match serde_json::from_str::<ProtocolMessage<MMResponse>>(&text) {
                     Ok(msg) => {
-                        match &msg.payload {
-                            MMResponse::QuoteValidated {
-                                quote_id, accepted, ..
-                            } => {
-                                info!(
-                                    "Market maker {} validated quote {}: accepted={}",
-                                    mm_id, quote_id, accepted
-                                );
-                                state
-                                    .mm_registry
-                                    .handle_validation_response(&mm_uuid, quote_id, *accepted);
-                            }
-                            MMResponse::Pong { .. } => {
-                                // Handle pong for keepalive
-                            }
-                            MMResponse::DepositInitiated { .. } => {
-                                // Handle deposit notification - will be implemented when needed
-                            }
-                            MMResponse::SwapCompleteAck { .. } => {
-                                // Handle swap complete acknowledgment
-                            }
-                            MMResponse::Error { .. } => {
-                                // Handle error response
-                                error!("Received error response from market maker {}", mm_id);
+                        guard.well_formed();
+                        let span = info_span!(
+                            "mm_message",
+                            trace_id = msg.trace_id.as_deref().unwrap_or_default()
+                        );
+                        async {
+                            match &msg.payload {
+                                MMResponse::QuoteValidated {
+                                    quote_id,
+                                    accepted,
+                                    rejection_reason,
+                                    ..
+                                } => {
+                                    info!(
+                                        "Market maker {} validated quote {}: accepted={}, reason={:?}",
+                                        mm_id, quote_id, accepted, rejection_reason
+                                    );
+                                    state
+                                        .mm_registry
+                                        .handle_validation_response(&mm_uuid, quote_id, *accepted);
+                                }
+                                MMResponse::Pong { .. } => {
+                                    state.mm_registry.record_pong(mm_uuid);
+                                }
+                                MMResponse::DepositInitiated {
+                                    swap_id,
+                                    tx_hash,
+                                    fee,
+                                    fill_cost,
+                                    ..
+                                } => {
+                                    if let Err(e) = state
+                                        .swap_manager
+                                        .handle_deposit_initiated(
+                                            mm_uuid,
+                                            *swap_id,
+                                            tx_hash,
+                                            *fee,
+                                            fill_cost.as_ref(),
+                                        )
+                                        .await
+                                    {
+                                        error!(
+                                            "Failed to record deposit reported for swap {}: {}",
+                                            swap_id, e
+                                        );
+                                    }
+                                }
+                                MMResponse::CancelAcknowledged {
+                                    swap_id, accepted, ..
+                                } => {
+                                    state.mm_registry.handle_cancel_acknowledged(
+                                        &mm_uuid, swap_id, *accepted,
+                                    );
+                                }
+                                MMResponse::SwapCompleteAck { .. } => {
+                                    // Handle swap complete acknowledgment
+                                }
+                                MMResponse::VerifyDepositKey {
+                                    swap_id,
+                                    deposit_address,
+                                    matches,
+                                    ..
+                                } => {
+                                    if let Err(e) = state
+                                        .swap_manager
+                                        .handle_deposit_key_verification(
+                                            mm_uuid,
+                                            *swap_id,
+                                            deposit_address,
+                                            *matches,
+                                        )
+                                        .await
+                                    {
+                                        error!(
+                                            "Failed to record deposit key check for swap {}: {}",
+                                            swap_id, e
+                                        );
+                                    }
+                                }
+                                MMResponse::Error { .. } => {
+                                    // Handle error response
+                                    error!("Received error response from market maker {}", mm_id);
+                                }
                             }
                         }
+                        .instrument(span)
+                        .await;
                     }
                     Err(e) => {
-                        error!("Failed to parse MM message: {}", e);
+                        if let Some(frame) = guard.malformed(&text, &e) {
+                            close(frame);
+                            break;
+                        }
                     }
                 }
             }
+            Ok(Message::Binary(data)) => {
+                // Market makers only speak JSON text frames
+                let frame = guard.check_size(data.len()).or_else(|| {
+                    guard.malformed(&String::from_utf8_lossy(&data), &"unexpected binary frame")
+                });
+                if let Some(frame) = frame {
+                    close(frame);
+                    break;
+                }
+            }
             Ok(Message::Close(_)) => {
                 info!("Market maker {} disconnected", mm_id);
                 break;
@@ -523,3 +2419,286 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
     state.mm_registry.unregister(mm_uuid);
     info!("Market maker {} unregistered", mm_id);
 }
+
+/// The one task writing to an MM's socket. Messages go out in queue order,
+/// numbered from 1 so the MM can tell if it missed any. Stops after sending a
+/// close: one the reader asked for, the close following `GoingAway`, or a slow
+/// consumer's when the queue overflows
+async fn write_mm_socket<S>(
+    mut sink: S,
+    mut queue: mpsc::Receiver<ProtocolMessage<MMRequest>>,
+    mut close: oneshot::Receiver<CloseFrame<'static>>,
+    slow_consumer: Arc<Notify>,
+    speaks_going_away: bool,
+    mm_id: String,
+    counters: Arc<MmSocketCounters>,
+) where
+    S: Sink<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    let mut sequence = 0u64;
+    loop {
+        let mut msg = tokio::select! {
+            biased;
+            () = slow_consumer.notified() => break,
+            frame = &mut close => {
+                if let Ok(frame) = frame {
+                    let _ = sink.send(Message::Close(Some(frame))).await;
+                }
+                return;
+            }
+            msg = queue.recv() => match msg {
+                Some(msg) => msg,
+                None => return,
+            },
+        };
+
+        let going_away = matches!(msg.payload, MMRequest::GoingAway { .. });
+        // MMs too old for GoingAway only get the close
+        if !going_away || speaks_going_away {
+            sequence += 1;
+            msg.sequence = sequence;
+            if let Ok(json) = serde_json::to_string(&msg) {
+                // An MM that stops reading stalls the send, the queue behind it
+                // overflowing is what ends the wait
+                let sent = tokio::select! {
+                    biased;
+                    () = slow_consumer.notified() => break,
+                    sent = sink.send(Message::Text(json)) => sent,
+                };
+                if let Err(e) = sent {
+                    error!("Failed to send message to market maker {}: {}", mm_id, e);
+                    return;
+                }
+            }
+        }
+        if going_away {
+            let close = CloseFrame {
+                code: close_code::AWAY,
+                reason: Cow::Borrowed(GOING_AWAY_REASON),
+            };
+            let _ = sink.send(Message::Close(Some(close))).await;
+            return;
+        }
+    }
+
+    let close = counters.slow_consumer(&mm_id);
+    let _ = time::timeout(
+        SLOW_CONSUMER_CLOSE_TIMEOUT,
+        sink.send(Message::Close(Some(close))),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_covers_every_route() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        oas3::from_json(document.to_string()).expect("spec is valid OpenAPI 3.1");
+        assert_eq!(common::dangling_refs(&document), Vec::<String>::new());
+
+        for (method, path) in [
+            ("get", "/status"),
+            ("get", "/health"),
+            ("get", "/metrics"),
+            ("get", "/ws"),
+            ("get", "/ws/mm"),
+            ("post", "/api/v1/swaps"),
+            ("get", "/api/v1/swaps/lookup"),
+            ("get", "/api/v1/swaps/{id}"),
+            ("get", "/api/v1/swaps/{id}/receipt"),
+            ("patch", "/api/v1/swaps/{id}/refund-address"),
+            ("get", "/api/v1/currencies"),
+            ("get", "/api/v1/chains/{chain}/fees"),
+            ("get", "/api/v1/meta/swap-states"),
+            ("get", CAPABILITIES_PATH),
+            ("get", ATTESTATION_PATH),
+            ("get", "/api/v1/market-makers/connected"),
+            ("get", "/api/v1/market-makers/{id}/stats"),
+            ("post", "/admin/chains/{chain}/confirmation-override"),
+            ("get", "/admin/swaps"),
+            ("post", "/admin/swaps/{id}/cancel"),
+            ("get", "/admin/master-keys"),
+            ("post", "/admin/master-keys"),
+            ("delete", "/admin/master-keys/{version}"),
+            ("get", "/admin/reconciliation/findings"),
+            ("get", "/admin/v1/summary"),
+            ("get", "/api/v1/reports/swaps.csv"),
+        ] {
+            assert!(
+                document["paths"][path][method].is_object(),
+                "{method} {path} is not documented"
+            );
+        }
+
+        let admin = &document["paths"]["/admin/master-keys"]["get"];
+        assert_eq!(admin["security"][0]["admin_api_key"], serde_json::json!([]));
+        let websocket = &document["paths"]["/ws/mm"]["get"]["x-websocket"];
+        assert_eq!(
+            websocket["client_messages"]["$ref"],
+            "#/components/schemas/ProtocolMessage_MMResponse"
+        );
+
+        // Amounts are hex strings and tagged enums keep their tag
+        let schemas = &document["components"]["schemas"];
+        assert_eq!(schemas["U256"]["type"], "string");
+        assert!(schemas["MMRequest"]["oneOf"][0]["properties"]["type"].is_object());
+    }
+
+    #[test]
+    fn test_pool_metrics_rendering() {
+        let stats = PoolStats {
+            size: 3,
+            idle: 2,
+            max_size: 10,
+            acquire_wait_seconds: None,
+        };
+        let metrics = render_pool_metrics(false, &stats);
+        assert!(metrics.contains("# TYPE otc_db_up gauge\notc_db_up 0\n"));
+        assert!(metrics.contains("otc_db_pool_idle_connections 2\n"));
+        // No wait is reported before the first ping
+        assert!(!metrics.contains("otc_db_pool_acquire_wait_seconds"));
+
+        let metrics = render_pool_metrics(
+            true,
+            &PoolStats {
+                acquire_wait_seconds: Some(0.25),
+                ..stats
+            },
+        );
+        assert!(metrics.contains("otc_db_up 1\n"));
+        assert!(metrics.contains("otc_db_pool_acquire_wait_seconds 0.25\n"));
+    }
+
+    #[test]
+    fn test_status_update_metrics_rendering() {
+        let metrics = render_status_update_metrics(&StatusUpdateCounts {
+            sent: 7,
+            dropped: 2,
+        });
+        assert!(metrics.contains(
+            "# TYPE otc_mm_status_updates_sent_total counter\notc_mm_status_updates_sent_total 7\n"
+        ));
+        assert!(metrics.contains("otc_mm_status_updates_dropped_total 2\n"));
+    }
+
+    #[test]
+    fn test_mm_socket_metrics_rendering() {
+        let metrics = render_mm_socket_metrics(&MmSocketCounts {
+            oversized: 1,
+            malformed: 4,
+            slow_consumers: 1,
+            disconnects: 2,
+        });
+        assert!(metrics.contains(
+            "# TYPE otc_mm_oversized_messages_total counter\notc_mm_oversized_messages_total 1\n"
+        ));
+        assert!(metrics.contains("otc_mm_malformed_messages_total 4\n"));
+        assert!(metrics.contains("otc_mm_slow_consumer_disconnects_total 1\n"));
+        assert!(metrics.contains("otc_mm_socket_limit_disconnects_total 2\n"));
+
+        let mm = Uuid::from_u128(1);
+        let metrics = render_send_queue_metrics(&[(mm, 3)]);
+        assert!(metrics.contains("# TYPE otc_mm_send_queue_depth gauge\n"));
+        assert!(metrics.contains(&format!(
+            "otc_mm_send_queue_depth{{market_maker_id=\"{mm}\"}} 3\n"
+        )));
+    }
+
+    /// A socket whose reader takes one message at a time off `tx`
+    fn socket_sink(
+        tx: mpsc::Sender<Message>,
+    ) -> impl Sink<Message, Error = mpsc::error::SendError<Message>> + Unpin {
+        Box::pin(futures_util::sink::unfold(
+            tx,
+            |tx, msg: Message| async move {
+                let sent = tx.send(msg).await;
+                sent.map(|()| tx)
+            },
+        ))
+    }
+
+    fn sequence(msg: &Message) -> u64 {
+        let Message::Text(text) = msg else {
+            panic!("expected a text frame, got {msg:?}");
+        };
+        serde_json::from_str::<ProtocolMessage<MMRequest>>(text)
+            .unwrap()
+            .sequence
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_is_closed_while_others_keep_receiving() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let counters = Arc::new(MmSocketCounters::default());
+        let (slow_mm, fast_mm) = (Uuid::from_u128(1), Uuid::from_u128(2));
+
+        let mut sockets = Vec::new();
+        let mut closes = Vec::new();
+        for mm in [slow_mm, fast_mm] {
+            let (tx, rx) = mpsc::channel(4);
+            let slow_consumer = registry.register(mm, tx, GOING_AWAY_VERSION.to_string());
+            let (socket_tx, socket_rx) = mpsc::channel(1);
+            let (close_tx, close_rx) = oneshot::channel();
+            closes.push(close_tx);
+            tokio::spawn(write_mm_socket(
+                socket_sink(socket_tx),
+                rx,
+                close_rx,
+                slow_consumer,
+                true,
+                mm.to_string(),
+                counters.clone(),
+            ));
+            sockets.push(socket_rx);
+        }
+        let mut fast_socket = sockets.pop().unwrap();
+        let mut slow_socket = sockets.pop().unwrap();
+        let fast_reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(msg) = fast_socket.recv().await {
+                received.push(sequence(&msg));
+            }
+            received
+        });
+
+        // The slow MM reads nothing while these go out
+        for _ in 0..20 {
+            for mm in [slow_mm, fast_mm] {
+                registry
+                    .notify_swap_complete(
+                        &mm,
+                        &Uuid::new_v4(),
+                        "key",
+                        ChainType::Bitcoin,
+                        "tx",
+                        None,
+                    )
+                    .await;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // What was already on its way arrives in order, then the close
+        assert_eq!(sequence(&slow_socket.recv().await.unwrap()), 1);
+        assert_eq!(sequence(&slow_socket.recv().await.unwrap()), 2);
+        let close = time::timeout(Duration::from_secs(1), slow_socket.recv())
+            .await
+            .unwrap();
+        let Some(Message::Close(Some(close))) = close else {
+            panic!("expected a close frame, got {close:?}");
+        };
+        assert_eq!(close.code, common::SLOW_CONSUMER_CLOSE_CODE);
+        assert_eq!(counters.snapshot().slow_consumers, 1);
+
+        registry.unregister(fast_mm);
+        let received = time::timeout(Duration::from_secs(1), fast_reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, (1..=20).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,324 @@
+use crate::api::{AdminSummaryResponse, ChainMonitoringHealth, OldestActiveSwap, SwapStatusCount};
+use crate::db::Database;
+use crate::error::OtcServerResult;
+use crate::services::{
+    mm_registry::MMRegistry,
+    swap_monitoring::{MonitoringPass, MonitoringPasses},
+};
+use chrono::{DateTime, Utc};
+use common::Clock;
+use otc_models::{ChainType, SwapStatus};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How long an assembled summary is served before it's assembled again
+pub const DEFAULT_SUMMARY_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Intervals a chain may go past its latest monitoring pass before the next
+/// one counts as overdue
+const OVERDUE_PASS_INTERVALS: u32 = 3;
+
+/// What operators check first: where swaps stand, who is connected and whether
+/// monitoring keeps up. Cached so that however often it's polled, the database
+/// is asked at most once per TTL
+pub struct AdminSummary {
+    db: Database,
+    mm_registry: Arc<MMRegistry>,
+    monitoring_passes: MonitoringPasses,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    /// Locked while assembling, so requests missing the cache together wait
+    /// for one summary rather than each querying
+    cache: Mutex<Option<AdminSummaryResponse>>,
+}
+
+impl AdminSummary {
+    #[must_use]
+    pub fn new(
+        db: Database,
+        mm_registry: Arc<MMRegistry>,
+        monitoring_passes: MonitoringPasses,
+        ttl: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            db,
+            mm_registry,
+            monitoring_passes,
+            ttl,
+            clock,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// The summary, assembled at most [`Self::new`]'s `ttl` ago. Failures
+    /// aren't cached, the next request tries again
+    pub async fn summary(&self) -> OtcServerResult<AdminSummaryResponse> {
+        let mut cached = self.cache.lock().await;
+        let now = self.clock.now();
+        if let Some(summary) = cached.as_ref() {
+            // A clock set back makes the age negative, which counts as stale
+            let fresh = (now - summary.generated_at)
+                .to_std()
+                .is_ok_and(|age| age < self.ttl);
+            if fresh {
+                return Ok(summary.clone());
+            }
+        }
+
+        let summary = self.assemble(now).await?;
+        *cached = Some(summary.clone());
+        Ok(summary)
+    }
+
+    async fn assemble(&self, now: DateTime<Utc>) -> OtcServerResult<AdminSummaryResponse> {
+        let counts = self.db.swaps().count_by_status().await?;
+        let swaps_by_status = SwapStatus::ALL
+            .into_iter()
+            .map(|status| SwapStatusCount {
+                status,
+                count: counts
+                    .iter()
+                    .find(|(counted, _)| *counted == status)
+                    .map_or(0, |(_, count)| *count),
+            })
+            .collect();
+
+        let oldest_active_swaps = self
+            .db
+            .swaps()
+            .oldest_in_status()
+            .await?
+            .into_iter()
+            .map(|oldest| OldestActiveSwap {
+                status: oldest.status,
+                swap_id: oldest.swap_id,
+                created_at: oldest.created_at,
+                age_seconds: u64::try_from((now - oldest.created_at).num_seconds()).unwrap_or(0),
+            })
+            .collect();
+
+        let mut chains: Vec<ChainMonitoringHealth> = self
+            .monitoring_passes
+            .latest()
+            .into_iter()
+            .map(|(chain, pass)| chain_health(chain, pass, now))
+            .collect();
+        chains.sort_by_key(|health| health.chain);
+
+        Ok(AdminSummaryResponse {
+            generated_at: now,
+            swaps_by_status,
+            oldest_active_swaps,
+            connected_market_makers: self.mm_registry.get_connected_market_makers().len(),
+            mm_notification_backlog: self
+                .mm_registry
+                .send_queue_depths()
+                .into_iter()
+                .map(|(_, depth)| depth)
+                .sum(),
+            chains,
+        })
+    }
+}
+
+/// A chain is healthy while its passes succeed and keep coming
+fn chain_health(
+    chain: ChainType,
+    pass: MonitoringPass,
+    now: DateTime<Utc>,
+) -> ChainMonitoringHealth {
+    let overdue_after = pass.interval * OVERDUE_PASS_INTERVALS;
+    let overdue = (now - pass.finished_at)
+        .to_std()
+        .is_ok_and(|since| since > overdue_after);
+    let (last_pass_swaps, last_pass_error) = match pass.result {
+        Ok(swaps) => (Some(swaps), None),
+        Err(e) => (None, Some(e)),
+    };
+    ChainMonitoringHealth {
+        chain,
+        healthy: last_pass_error.is_none() && !overdue,
+        interval_seconds: pass.interval.as_secs(),
+        last_pass_at: pass.finished_at,
+        last_pass_duration_ms: u64::try_from(pass.duration.as_millis()).unwrap_or(u64::MAX),
+        last_pass_swaps,
+        last_pass_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::SubsecRound;
+    use common::ManualClock;
+    use otc_models::{Swap, SwapBuilder};
+
+    /// Bitcoin to Ethereum swap in `status`, created `age` before `now`
+    fn swap(status: SwapStatus, now: DateTime<Utc>, age: chrono::Duration) -> Swap {
+        SwapBuilder::new()
+            .with_status(status)
+            .with_created_at(now - age)
+            .build()
+    }
+
+    fn count(summary: &AdminSummaryResponse, status: SwapStatus) -> u64 {
+        summary
+            .swaps_by_status
+            .iter()
+            .find(|counted| counted.status == status)
+            .unwrap()
+            .count
+    }
+
+    #[sqlx::test]
+    async fn test_summarizes_swaps_by_status(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let now = Utc::now().trunc_subsecs(6);
+        let clock = ManualClock::new(now);
+        let hours = chrono::Duration::hours;
+        let waiting = SwapStatus::WaitingUserDepositInitiated;
+        let oldest_waiting = swap(waiting, now, hours(5));
+        let refunding = swap(SwapStatus::RefundingUser, now, hours(30));
+        for seeded in [
+            swap(waiting, now, hours(1)),
+            oldest_waiting.clone(),
+            refunding.clone(),
+            swap(SwapStatus::Settled, now, hours(48)),
+            swap(SwapStatus::Settled, now, hours(2)),
+            swap(SwapStatus::Failed, now, hours(3)),
+        ] {
+            db.swaps().create(&seeded).await.unwrap();
+        }
+        let summary = AdminSummary::new(
+            db.clone(),
+            Arc::new(MMRegistry::new(Duration::from_secs(5))),
+            MonitoringPasses::default(),
+            Duration::from_secs(5),
+            Arc::new(clock.clone()),
+        );
+
+        let first = summary.summary().await.unwrap();
+        assert_eq!(first.generated_at, now);
+        assert_eq!(first.swaps_by_status.len(), SwapStatus::ALL.len());
+        assert_eq!(count(&first, waiting), 2);
+        assert_eq!(count(&first, SwapStatus::RefundingUser), 1);
+        assert_eq!(count(&first, SwapStatus::Settled), 2);
+        assert_eq!(count(&first, SwapStatus::Failed), 1);
+        assert_eq!(count(&first, SwapStatus::WaitingMMDepositInitiated), 0);
+        assert_eq!(
+            first.oldest_active_swaps,
+            vec![
+                OldestActiveSwap {
+                    status: waiting,
+                    swap_id: oldest_waiting.id,
+                    created_at: oldest_waiting.created_at,
+                    age_seconds: 5 * 60 * 60,
+                },
+                OldestActiveSwap {
+                    status: SwapStatus::RefundingUser,
+                    swap_id: refunding.id,
+                    created_at: refunding.created_at,
+                    age_seconds: 30 * 60 * 60,
+                },
+            ]
+        );
+        assert_eq!(first.connected_market_makers, 0);
+        assert_eq!(first.mm_notification_backlog, 0);
+        assert!(first.chains.is_empty());
+
+        // Served from the cache until the TTL is up
+        db.swaps()
+            .create(&swap(waiting, now, hours(8)))
+            .await
+            .unwrap();
+        clock.advance(chrono::Duration::seconds(4));
+        assert_eq!(summary.summary().await.unwrap(), first);
+
+        clock.advance(chrono::Duration::seconds(1));
+        let refreshed = summary.summary().await.unwrap();
+        assert_eq!(count(&refreshed, waiting), 3);
+        assert_eq!(
+            refreshed.oldest_active_swaps[0].age_seconds,
+            8 * 60 * 60 + 5
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_reports_chains_by_their_latest_monitoring_pass(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let now = Utc::now().trunc_subsecs(6);
+        let passes = MonitoringPasses::default();
+        let interval = Duration::from_secs(30);
+        passes.record(
+            ChainType::Ethereum,
+            MonitoringPass {
+                finished_at: now - chrono::Duration::seconds(10),
+                duration: Duration::from_millis(1_250),
+                interval,
+                result: Ok(4),
+            },
+        );
+        passes.record(
+            ChainType::Bitcoin,
+            MonitoringPass {
+                finished_at: now - chrono::Duration::seconds(20),
+                duration: Duration::from_millis(300),
+                interval,
+                result: Err("Database is unreachable".to_string()),
+            },
+        );
+        let summary = AdminSummary::new(
+            db,
+            Arc::new(MMRegistry::new(Duration::from_secs(5))),
+            passes.clone(),
+            Duration::ZERO,
+            Arc::new(ManualClock::new(now)),
+        );
+
+        let chains = summary.summary().await.unwrap().chains;
+        assert_eq!(
+            chains,
+            vec![
+                ChainMonitoringHealth {
+                    chain: ChainType::Bitcoin,
+                    healthy: false,
+                    interval_seconds: 30,
+                    last_pass_at: now - chrono::Duration::seconds(20),
+                    last_pass_duration_ms: 300,
+                    last_pass_swaps: None,
+                    last_pass_error: Some("Database is unreachable".to_string()),
+                },
+                ChainMonitoringHealth {
+                    chain: ChainType::Ethereum,
+                    healthy: true,
+                    interval_seconds: 30,
+                    last_pass_at: now - chrono::Duration::seconds(10),
+                    last_pass_duration_ms: 1_250,
+                    last_pass_swaps: Some(4),
+                    last_pass_error: None,
+                },
+            ]
+        );
+
+        // A chain whose passes stopped coming is unhealthy however the last went
+        passes.record(
+            ChainType::Ethereum,
+            MonitoringPass {
+                finished_at: now - chrono::Duration::seconds(91),
+                duration: Duration::from_millis(1_250),
+                interval,
+                result: Ok(4),
+            },
+        );
+        let chains = summary.summary().await.unwrap().chains;
+        assert!(!chains[1].healthy);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use crate::db::Database;
+use crate::error::OtcServerError;
+use chrono::{DateTime, Utc};
+use otc_chains::ChainRegistry;
+use otc_models::{ChainType, ConfirmationOverride, ConfirmationRule};
+use snafu::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+/// Sanity cap on overrides, well beyond any reasonable finality requirement
+pub const MAX_REQUIRED_CONFIRMATIONS: u64 = 1_000;
+
+#[derive(Debug, Snafu)]
+pub enum ConfirmationPolicyError {
+    #[snafu(display("Chain not supported: {:?}", chain))]
+    ChainNotSupported { chain: ChainType },
+
+    #[snafu(display(
+        "Override of {} confirmations is below the configured baseline of {}",
+        required,
+        baseline
+    ))]
+    BelowBaseline { required: u64, baseline: u64 },
+
+    #[snafu(display(
+        "Override of {} confirmations exceeds the maximum of {}",
+        required,
+        MAX_REQUIRED_CONFIRMATIONS
+    ))]
+    AboveMaximum { required: u64 },
+
+    #[snafu(display("Override multiplier must be a finite number, got {}", multiplier))]
+    InvalidMultiplier { multiplier: f64 },
+
+    #[snafu(display("Override expiry {} is not in the future", expires_at))]
+    AlreadyExpired { expires_at: DateTime<Utc> },
+
+    #[snafu(display("Database error: {}", source))]
+    Database { source: OtcServerError },
+}
+
+pub type ConfirmationPolicyResult<T> = Result<T, ConfirmationPolicyError>;
+
+/// Confirmation requirement for new swaps on a chain, as of the time it was resolved
+#[derive(Debug, Clone)]
+pub struct ResolvedConfirmations {
+    pub chain: ChainType,
+    pub baseline: u64,
+    pub required: u64,
+    pub estimated_block_time: Duration,
+    pub active_override: Option<ConfirmationOverride>,
+}
+
+impl ResolvedConfirmations {
+    /// Rough time until a deposit made now reaches the required confirmations
+    #[must_use]
+    pub fn estimated_confirmation_time(&self) -> Duration {
+        self.estimated_block_time * self.required as u32
+    }
+}
+
+/// Resolves how many confirmations a deposit needs on each chain.
+///
+/// The baseline comes from the chain adapter. Operators can temporarily raise it
+/// with an override, which only affects swaps created while it is active.
+pub struct ConfirmationPolicy {
+    db: Database,
+    chain_registry: Arc<ChainRegistry>,
+}
+
+impl ConfirmationPolicy {
+    #[must_use]
+    pub fn new(db: Database, chain_registry: Arc<ChainRegistry>) -> Self {
+        Self { db, chain_registry }
+    }
+
+    pub async fn resolve(
+        &self,
+        chain: ChainType,
+    ) -> ConfirmationPolicyResult<ResolvedConfirmations> {
+        let chain_ops = self
+            .chain_registry
+            .get(&chain)
+            .context(ChainNotSupportedSnafu { chain })?;
+        let baseline = u64::from(chain_ops.minimum_block_confirmations());
+
+        let active_override = self
+            .db
+            .confirmation_overrides()
+            .get_active(chain, Utc::now())
+            .await
+            .context(DatabaseSnafu)?;
+
+        // The baseline may have been raised since the override was issued, never go below it
+        let required = active_override
+            .as_ref()
+            .map_or(baseline, |o| o.required_confirmations.max(baseline));
+
+        Ok(ResolvedConfirmations {
+            chain,
+            baseline,
+            required,
+            estimated_block_time: chain_ops.estimated_block_time(),
+            active_override,
+        })
+    }
+
+    /// Resolve every chain in the registry
+    pub async fn resolve_all(&self) -> ConfirmationPolicyResult<Vec<ResolvedConfirmations>> {
+        let mut chains = self.chain_registry.supported_chains();
+        chains.sort_by_key(|chain| format!("{chain:?}"));
+
+        let mut resolved = Vec::with_capacity(chains.len());
+        for chain in chains {
+            resolved.push(self.resolve(chain).await?);
+        }
+        Ok(resolved)
+    }
+
+    /// Activate an override for new swaps on `chain`, superseding any existing one
+    pub async fn set_override(
+        &self,
+        chain: ChainType,
+        rule: ConfirmationRule,
+        expires_at: DateTime<Utc>,
+        reason: String,
+    ) -> ConfirmationPolicyResult<ConfirmationOverride> {
+        let chain_ops = self
+            .chain_registry
+            .get(&chain)
+            .context(ChainNotSupportedSnafu { chain })?;
+        let baseline = u64::from(chain_ops.minimum_block_confirmations());
+
+        if let ConfirmationRule::Multiplier(multiplier) = rule {
+            ensure!(
+                multiplier.is_finite(),
+                InvalidMultiplierSnafu { multiplier }
+            );
+        }
+        let required = rule.resolve(baseline);
+        ensure!(
+            required >= baseline,
+            BelowBaselineSnafu { required, baseline }
+        );
+        ensure!(
+            required <= MAX_REQUIRED_CONFIRMATIONS,
+            AboveMaximumSnafu { required }
+        );
+
+        let now = Utc::now();
+        ensure!(expires_at > now, AlreadyExpiredSnafu { expires_at });
+
+        let confirmation_override = ConfirmationOverride {
+            id: Uuid::new_v4(),
+            chain,
+            rule,
+            baseline_confirmations: baseline,
+            required_confirmations: required,
+            reason,
+            expires_at,
+            created_at: now,
+        };
+
+        self.db
+            .confirmation_overrides()
+            .create(&confirmation_override)
+            .await
+            .context(DatabaseSnafu)?;
+
+        info!(
+            chain = ?chain,
+            baseline,
+            required,
+            expires_at = %expires_at,
+            reason = %confirmation_override.reason,
+            "Confirmation override activated"
+        );
+
+        Ok(confirmation_override)
+    }
+}
@@ -0,0 +1,22 @@
+//! Salts user deposit addresses are derived from. The address is a function of
+//! the master key and the salt alone, so a repeated salt hands two swaps the
+//! same address
+
+use std::fmt::Debug;
+
+pub trait DepositSaltSource: Debug + Send + Sync {
+    /// A salt no earlier swap has used
+    fn next_salt(&self) -> [u8; 32];
+}
+
+/// Salts from the operating system's random number generator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandomSalts;
+
+impl DepositSaltSource for OsRandomSalts {
+    fn next_salt(&self) -> [u8; 32] {
+        let mut salt = [0u8; 32];
+        getrandom::getrandom(&mut salt).expect("Failed to generate random salt");
+        salt
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use common::Clock;
+use otc_chains::{ChainRegistry, NetworkFees};
+use otc_models::ChainType;
+use snafu::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How long fetched fees are served before the chain backend is asked again
+pub const DEFAULT_FEE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Snafu)]
+pub enum FeeTelemetryError {
+    #[snafu(display("Chain not supported: {:?}", chain))]
+    ChainNotSupported { chain: ChainType },
+
+    #[snafu(display("Failed to fetch {:?} fees: {}", chain, message))]
+    Fetch { chain: ChainType, message: String },
+}
+
+/// Network fees of a chain as of `fetched_at`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainFees {
+    pub chain: ChainType,
+    pub fees: NetworkFees,
+    pub estimated_block_time: Duration,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Outcome of the last fetch. Failures are kept too, so a struggling backend
+/// isn't asked again on every request
+struct CachedFees {
+    fetched_at: DateTime<Utc>,
+    result: Result<ChainFees, FeeTelemetryError>,
+}
+
+/// Network fees clients show before they send, cached so that however often
+/// they're requested each chain backend is asked at most once per TTL
+pub struct FeeTelemetry {
+    chain_registry: Arc<ChainRegistry>,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    /// Locked across a fetch, so requests missing the cache together wait for
+    /// one fetch rather than making their own
+    cache: HashMap<ChainType, Mutex<Option<CachedFees>>>,
+}
+
+impl FeeTelemetry {
+    #[must_use]
+    pub fn new(chain_registry: Arc<ChainRegistry>, ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        let cache = chain_registry
+            .supported_chains()
+            .into_iter()
+            .map(|chain| (chain, Mutex::new(None)))
+            .collect();
+        Self {
+            chain_registry,
+            ttl,
+            clock,
+            cache,
+        }
+    }
+
+    /// Fees of `chain`, fetched at most [`Self::new`]'s `ttl` ago
+    pub async fn fees(&self, chain: ChainType) -> Result<ChainFees, FeeTelemetryError> {
+        let (Some(cache), Some(operations)) =
+            (self.cache.get(&chain), self.chain_registry.get(&chain))
+        else {
+            return ChainNotSupportedSnafu { chain }.fail();
+        };
+
+        let mut cached = cache.lock().await;
+        let now = self.clock.now();
+        if let Some(cached) = cached.as_ref() {
+            // A clock set back makes the age negative, which counts as stale
+            let fresh = (now - cached.fetched_at)
+                .to_std()
+                .is_ok_and(|age| age < self.ttl);
+            if fresh {
+                return cached.result.clone();
+            }
+        }
+
+        let result = match operations.network_fees().await {
+            Ok(fees) => Ok(ChainFees {
+                chain,
+                fees,
+                estimated_block_time: operations.estimated_block_time(),
+                fetched_at: now,
+            }),
+            Err(e) => FetchSnafu {
+                chain,
+                message: e.to_string(),
+            }
+            .fail(),
+        };
+        *cached = Some(CachedFees {
+            fetched_at: now,
+            result: result.clone(),
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::ManualClock;
+    use otc_chains::test_utils::StubChain;
+    use std::collections::BTreeMap;
+
+    /// Bitcoin fees quoting `rate` sat/vB for the next block
+    fn fees(rate: f64) -> NetworkFees {
+        NetworkFees::Bitcoin {
+            fee_estimates: BTreeMap::from([(1, rate)]),
+        }
+    }
+
+    /// Chain whose fee backend quotes `rate` and takes a while to answer, so
+    /// concurrent requests pile up behind one another
+    fn chain(rate: f64) -> Arc<StubChain> {
+        Arc::new(
+            StubChain::new()
+                .with_network_fees(fees(rate))
+                .with_delay(Duration::from_millis(20))
+                .with_block_time(Duration::from_secs(600)),
+        )
+    }
+
+    fn telemetry(chain: Arc<StubChain>, clock: &ManualClock) -> FeeTelemetry {
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, chain);
+        FeeTelemetry::new(
+            Arc::new(chain_registry),
+            DEFAULT_FEE_CACHE_TTL,
+            Arc::new(clock.clone()),
+        )
+    }
+
+    fn fetched_rate(fees: &ChainFees) -> f64 {
+        match &fees.fees {
+            NetworkFees::Bitcoin { fee_estimates } => fee_estimates[&1],
+            NetworkFees::Ethereum { .. } => panic!("expected Bitcoin fees"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fees_are_fetched_once_per_ttl() {
+        let clock = ManualClock::new(Utc::now());
+        let chain = chain(1.0);
+        let telemetry = telemetry(chain.clone(), &clock);
+
+        let first = telemetry.fees(ChainType::Bitcoin).await.unwrap();
+        assert_eq!(first.fetched_at, clock.now());
+        assert_eq!(first.estimated_block_time, Duration::from_secs(600));
+
+        chain.set_network_fees(Some(fees(2.0)));
+        clock.advance(chrono::Duration::seconds(9));
+        let cached = telemetry.fees(ChainType::Bitcoin).await.unwrap();
+        assert_eq!(cached, first);
+        assert_eq!(chain.fee_requests(), 1);
+
+        clock.advance(chrono::Duration::seconds(1));
+        let refreshed = telemetry.fees(ChainType::Bitcoin).await.unwrap();
+        assert_eq!(fetched_rate(&refreshed), 2.0);
+        assert_eq!(refreshed.fetched_at, clock.now());
+        assert_eq!(chain.fee_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_share_one_fetch() {
+        let clock = ManualClock::new(Utc::now());
+        let chain = chain(1.0);
+        let telemetry = telemetry(chain.clone(), &clock);
+
+        let fetched =
+            futures_util::future::join_all((0..10).map(|_| telemetry.fees(ChainType::Bitcoin)))
+                .await;
+        assert!(fetched
+            .iter()
+            .all(|fees| fetched_rate(fees.as_ref().unwrap()) == 1.0));
+        assert_eq!(chain.fee_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_cached_too() {
+        let clock = ManualClock::new(Utc::now());
+        let chain = chain(1.0);
+        chain.set_network_fees(None);
+        let telemetry = telemetry(chain.clone(), &clock);
+
+        for _ in 0..3 {
+            assert!(matches!(
+                telemetry.fees(ChainType::Bitcoin).await,
+                Err(FeeTelemetryError::Fetch {
+                    chain: ChainType::Bitcoin,
+                    ..
+                })
+            ));
+        }
+        assert_eq!(chain.fee_requests(), 1);
+
+        chain.set_network_fees(Some(fees(1.0)));
+        clock.advance(chrono::Duration::seconds(10));
+        assert!(telemetry.fees(ChainType::Bitcoin).await.is_ok());
+        assert_eq!(chain.fee_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unregistered_chains_are_not_supported() {
+        let clock = ManualClock::new(Utc::now());
+        let telemetry = telemetry(chain(1.0), &clock);
+        assert!(matches!(
+            telemetry.fees(ChainType::Ethereum).await,
+            Err(FeeTelemetryError::ChainNotSupported {
+                chain: ChainType::Ethereum
+            })
+        ));
+    }
+}
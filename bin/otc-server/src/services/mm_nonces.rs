@@ -0,0 +1,22 @@
+//! Nonces market makers tag their fills with. A fill is matched to its swap by
+//! the nonce alone, so a repeated nonce lets one payment settle two swaps
+
+use std::fmt::Debug;
+
+pub trait MmNonceSource: Debug + Send + Sync {
+    /// A nonce no earlier swap has used
+    fn next_nonce(&self) -> [u8; 16];
+}
+
+/// Nonces from the operating system's random number generator, 128 bits so a
+/// collision with an existing one is out of reach
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandomNonces;
+
+impl MmNonceSource for OsRandomNonces {
+    fn next_nonce(&self) -> [u8; 16] {
+        let mut nonce = [0u8; 16];
+        getrandom::getrandom(&mut nonce).expect("Failed to generate random nonce");
+        nonce
+    }
+}
@@ -1,10 +1,18 @@
-use dashmap::DashMap;
-use otc_protocols::mm::{MMRequest, ProtocolMessage};
-use otc_models::{ChainType, Lot};
+use alloy::primitives::U256;
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use otc_protocols::mm::{
+    is_version_at_least, MMErrorCode, MMRequest, ProtocolMessage, SwapFailureReason,
+    CANCEL_REQUEST_VERSION, DEPOSIT_RETRY_VERSION, SWAP_FAILED_VERSION,
+    SWAP_STATUS_UPDATE_VERSION,
+};
+use otc_api_types::ConnectedMarketMaker;
+use otc_models::{ChainType, Lot, Redacted, Swap, TxHash};
 use snafu::Snafu;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot};
-use tokio::time::Duration;
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::time::{self, Duration, Instant};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
@@ -18,7 +26,7 @@ pub enum MMRegistryError {
 
     #[snafu(display("Failed to send message to market maker: {}", source))]
     MessageSendError {
-        source: mpsc::error::SendError<ProtocolMessage<MMRequest>>,
+        source: mpsc::error::TrySendError<ProtocolMessage<MMRequest>>,
     },
 
     #[snafu(display("Invalid quote ID: {}", quote_id))]
@@ -26,6 +34,13 @@ pub enum MMRegistryError {
 
     #[snafu(display("Failed to receive validation response: {}", source))]
     ResponseReceiveError { source: oneshot::error::RecvError },
+
+    #[snafu(display(
+        "Market maker '{}' already has {} validations in flight",
+        market_maker_id,
+        MAX_PENDING_VALIDATIONS_PER_MM
+    ))]
+    TooManyPendingValidations { market_maker_id: String },
 }
 
 type Result<T, E = MMRegistryError> = std::result::Result<T, E>;
@@ -34,13 +49,160 @@ pub struct MarketMakerConnection {
     pub id: Uuid,
     pub sender: mpsc::Sender<ProtocolMessage<MMRequest>>,
     pub protocol_version: String,
+    pub connected_at: DateTime<Utc>,
+    pub last_pong_at: Option<DateTime<Utc>>,
+    /// Woken when the send queue overflows, the socket's writer then drops the MM
+    slow_consumer: Arc<Notify>,
+}
+
+impl MarketMakerConnection {
+    /// Queue a message for the socket's writer without waiting. A full queue
+    /// means the MM isn't keeping up, so it's flagged as a slow consumer
+    /// rather than buffered for
+    pub fn enqueue(
+        &self,
+        request: ProtocolMessage<MMRequest>,
+    ) -> Result<(), mpsc::error::TrySendError<ProtocolMessage<MMRequest>>> {
+        let result = self.sender.try_send(request);
+        if let Err(mpsc::error::TrySendError::Full(_)) = &result {
+            warn!(
+                market_maker_id = %self.id,
+                capacity = self.sender.max_capacity(),
+                "Market maker send queue is full"
+            );
+            self.slow_consumer.notify_one();
+        }
+        result
+    }
+
+    /// Messages waiting for the socket's writer
+    #[must_use]
+    pub fn queue_depth(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+}
+
+/// Most validations one market maker can have in flight. Further requests fail
+/// right away instead of piling up behind an MM that isn't answering
+pub const MAX_PENDING_VALIDATIONS_PER_MM: usize = 64;
+
+/// How often a shutting down server checks whether its MMs have hung up
+const DISCONNECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct PendingValidation {
+    market_maker_id: Uuid,
+    /// Everyone waiting on the quote, all answered by the MM's one response
+    waiters: Vec<oneshot::Sender<Result<bool>>>,
+    expires_at: Instant,
+}
+
+/// Validation requests waiting on the MM's answer, keyed by quote id
+#[derive(Default)]
+struct PendingValidations {
+    entries: DashMap<Uuid, PendingValidation>,
+}
+
+impl PendingValidations {
+    /// Wait on the validation of `quote_id`. `Ok(true)` when no validation of the
+    /// quote was in flight and the caller must send the request, `Ok(false)` when
+    /// joining one. Hands `waiter` back if the MM is at its cap.
+    fn add(
+        &self,
+        market_maker_id: Uuid,
+        quote_id: Uuid,
+        waiter: oneshot::Sender<Result<bool>>,
+        expires_at: Instant,
+    ) -> std::result::Result<bool, oneshot::Sender<Result<bool>>> {
+        // Counted before taking the entry, iterating while holding a shard lock deadlocks
+        let in_flight = self.in_flight(market_maker_id);
+        match self.entries.entry(quote_id) {
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().waiters.push(waiter);
+                Ok(false)
+            }
+            Entry::Vacant(_) if in_flight >= MAX_PENDING_VALIDATIONS_PER_MM => Err(waiter),
+            Entry::Vacant(entry) => {
+                entry.insert(PendingValidation {
+                    market_maker_id,
+                    waiters: vec![waiter],
+                    expires_at,
+                });
+                Ok(true)
+            }
+        }
+    }
+
+    /// Answer everyone waiting on `quote_id`, if `market_maker_id` is the MM asked.
+    /// Returns whether anyone was waiting
+    fn resolve(
+        &self,
+        market_maker_id: Uuid,
+        quote_id: Uuid,
+        result: impl Fn() -> Result<bool>,
+    ) -> bool {
+        let Some((_, pending)) = self.entries.remove_if(&quote_id, |_, pending| {
+            pending.market_maker_id == market_maker_id
+        }) else {
+            return false;
+        };
+        for waiter in pending.waiters {
+            let _ = waiter.send(result());
+        }
+        true
+    }
+
+    /// Time out validations the MM never answered, returning how many were dropped
+    fn sweep_expired(&self) -> usize {
+        let now = Instant::now();
+        let before = self.entries.len();
+        self.entries.retain(|_, pending| {
+            if pending.expires_at > now {
+                return true;
+            }
+            for waiter in pending.waiters.drain(..) {
+                let _ = waiter.send(Err(MMRegistryError::ValidationTimeout {
+                    market_maker_id: pending.market_maker_id.to_string(),
+                }));
+            }
+            false
+        });
+        before.saturating_sub(self.entries.len())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn in_flight(&self, market_maker_id: Uuid) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.market_maker_id == market_maker_id)
+            .count()
+    }
+}
+
+/// How many swap status updates reached their MM's connection, and how many
+/// didn't because the MM was offline, too old or its connection was gone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatusUpdateCounts {
+    pub sent: u64,
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct StatusUpdateCounters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
 }
 
 #[derive(Clone)]
 pub struct MMRegistry {
     connections: Arc<DashMap<Uuid, MarketMakerConnection>>,
-    pending_validations: Arc<DashMap<Uuid, oneshot::Sender<Result<bool>>>>,
+    pending_validations: Arc<PendingValidations>,
+    /// Cancel requests waiting on the MM's answer, by swap
+    pending_cancels: Arc<DashMap<Uuid, (Uuid, oneshot::Sender<bool>)>>,
     validation_timeout: Duration,
+    status_updates: Arc<StatusUpdateCounters>,
 }
 
 impl MMRegistry {
@@ -48,30 +210,39 @@ impl MMRegistry {
     pub fn new(validation_timeout: Duration) -> Self {
         Self {
             connections: Arc::new(DashMap::new()),
-            pending_validations: Arc::new(DashMap::new()),
+            pending_validations: Arc::new(PendingValidations::default()),
+            pending_cancels: Arc::new(DashMap::new()),
             validation_timeout,
+            status_updates: Arc::new(StatusUpdateCounters::default()),
         }
     }
 
+    /// Returns the notification the connection's writer waits on to learn the
+    /// MM fell behind
     pub fn register(
         &self,
         market_maker_id: Uuid,
         sender: mpsc::Sender<ProtocolMessage<MMRequest>>,
         protocol_version: String,
-    ) {
+    ) -> Arc<Notify> {
         info!(
             market_maker_id = %market_maker_id,
             protocol_version = %protocol_version,
             "Registering market maker connection"
         );
 
+        let slow_consumer = Arc::new(Notify::new());
         let connection = MarketMakerConnection {
             id: market_maker_id,
             sender,
             protocol_version,
+            connected_at: Utc::now(),
+            last_pong_at: None,
+            slow_consumer: slow_consumer.clone(),
         };
 
         self.connections.insert(market_maker_id, connection);
+        slow_consumer
     }
 
     pub fn unregister(&self, market_maker_id: Uuid) {
@@ -90,7 +261,8 @@ impl MMRegistry {
         swap_id: &Uuid,
         quote_id: &Uuid,
         user_deposit_address: &str,
-        user_tx_hash: &str,
+        user_tx_hash: &TxHash,
+        trace_id: Option<&str>,
     ) {
         if let Some(conn) = self.connections.get(market_maker_id) {
             let request = ProtocolMessage {
@@ -100,12 +272,13 @@ impl MMRegistry {
                     request_id: Uuid::new_v4(),
                     swap_id: *swap_id,
                     quote_id: *quote_id,
-                    user_tx_hash: user_tx_hash.to_string(),
+                    user_tx_hash: user_tx_hash.clone(),
                     deposit_address: user_deposit_address.to_string(),
                     timestamp: chrono::Utc::now(),
                 },
+                trace_id: trace_id.map(str::to_string),
             };
-            if let Err(e) = conn.sender.send(request).await {
+            if let Err(e) = conn.enqueue(request) {
                 error!(market_maker_id = %market_maker_id, error = %e, "Failed to send user deposit notification");
             }
         }
@@ -119,6 +292,10 @@ impl MMRegistry {
         user_destination_address: &str,
         mm_nonce: [u8; 16],
         expected_lot: &Lot,
+        user_deposit_address: &str,
+        user_deposit_chain: ChainType,
+        external_reference: Option<&str>,
+        trace_id: Option<&str>,
     ) {
         if let Some(conn) = self.connections.get(market_maker_id) {
             let request = ProtocolMessage {
@@ -131,8 +308,12 @@ impl MMRegistry {
                     user_destination_address: user_destination_address.to_string(),
                     mm_nonce,
                     expected_lot: expected_lot.clone(),
+                    user_deposit_address: user_deposit_address.to_string(),
+                    user_deposit_chain,
+                    external_reference: external_reference.map(str::to_string),
                     timestamp: chrono::Utc::now(),
                 },
+                trace_id: trace_id.map(str::to_string),
             };
 
             info!(
@@ -142,7 +323,7 @@ impl MMRegistry {
                 "Notifying MM that user deposit is confirmed - MM should send payment with nonce"
             );
 
-            if let Err(e) = conn.sender.send(request).await {
+            if let Err(e) = conn.enqueue(request) {
                 error!(market_maker_id = %market_maker_id, error = %e, "Failed to send user deposit confirmed notification");
             }
         } else {
@@ -160,6 +341,7 @@ impl MMRegistry {
         user_deposit_private_key: &str,
         chain: ChainType,
         mm_tx_hash: &str,
+        trace_id: Option<&str>,
     ) {
         if let Some(conn) = self.connections.get(market_maker_id) {
             let request = ProtocolMessage {
@@ -168,24 +350,306 @@ impl MMRegistry {
                 payload: MMRequest::SwapComplete {
                     request_id: Uuid::new_v4(),
                     swap_id: *swap_id,
-                    user_deposit_private_key: user_deposit_private_key.to_string(),
+                    user_deposit_private_key: Redacted::new(user_deposit_private_key.to_string()),
                     chain,
                     user_withdrawal_tx: mm_tx_hash.to_string(),
                     timestamp: chrono::Utc::now(),
                 },
+                trace_id: trace_id.map(str::to_string),
             };
-            if let Err(e) = conn.sender.send(request).await {
+            if let Err(e) = conn.enqueue(request) {
                 error!(market_maker_id = %market_maker_id, error = %e, "Failed to send swap complete notification");
             }
         }
     }
 
+    pub async fn notify_mm_deposit_rejected(
+        &self,
+        market_maker_id: &Uuid,
+        swap_id: &Uuid,
+        quote_id: &Uuid,
+        mm_tx_hash: &TxHash,
+        expected_amount: U256,
+        received_amount: U256,
+        trace_id: Option<&str>,
+    ) {
+        if let Some(conn) = self.connections.get(market_maker_id) {
+            let request = ProtocolMessage {
+                version: conn.protocol_version.clone(),
+                sequence: 0,
+                payload: MMRequest::MMDepositRejected {
+                    request_id: Uuid::new_v4(),
+                    swap_id: *swap_id,
+                    quote_id: *quote_id,
+                    tx_hash: mm_tx_hash.clone(),
+                    error_code: MMErrorCode::InvalidAmount,
+                    expected_amount,
+                    received_amount,
+                    timestamp: chrono::Utc::now(),
+                },
+                trace_id: trace_id.map(str::to_string),
+            };
+            if let Err(e) = conn.enqueue(request) {
+                error!(market_maker_id = %market_maker_id, error = %e, "Failed to send MM deposit rejection");
+            }
+        } else {
+            warn!(
+                market_maker_id = %market_maker_id,
+                "Cannot notify MM - not connected"
+            );
+        }
+    }
+
+    /// Ask the MM to pay again after the deposit it reported failed on chain.
+    /// MMs on a protocol version from before `DepositRetryRequested` are skipped,
+    /// their attempts run out without a retry
+    pub async fn notify_deposit_retry_requested(
+        &self,
+        swap: &Swap,
+        failed_tx_hash: &TxHash,
+        attempt: u32,
+        deadline: DateTime<Utc>,
+    ) {
+        let Some(conn) = self.connections.get(&swap.market_maker_id) else {
+            warn!(
+                market_maker_id = %swap.market_maker_id,
+                swap_id = %swap.id,
+                "Cannot ask MM to retry its deposit - not connected"
+            );
+            return;
+        };
+        if !is_version_at_least(&conn.protocol_version, DEPOSIT_RETRY_VERSION) {
+            debug!(
+                market_maker_id = %swap.market_maker_id,
+                protocol_version = %conn.protocol_version,
+                "MM predates deposit retries, not asking for one"
+            );
+            return;
+        }
+
+        let request = ProtocolMessage {
+            version: conn.protocol_version.clone(),
+            sequence: 0,
+            payload: MMRequest::DepositRetryRequested {
+                request_id: Uuid::new_v4(),
+                swap_id: swap.id,
+                quote_id: swap.quote.id,
+                failed_tx_hash: failed_tx_hash.clone(),
+                user_destination_address: swap.user_destination_address.clone(),
+                mm_nonce: swap.mm_nonce,
+                expected_lot: swap.quote.to.clone(),
+                attempt,
+                deadline,
+                timestamp: chrono::Utc::now(),
+            },
+            trace_id: swap.trace_id.clone(),
+        };
+        if let Err(e) = conn.enqueue(request) {
+            error!(market_maker_id = %swap.market_maker_id, error = %e, "Failed to send deposit retry request");
+        }
+    }
+
+    /// Ask the MM whether `swap` can be cancelled and wait as long as a quote
+    /// validation for its answer. False when it refuses, doesn't answer, isn't
+    /// connected or predates `CancelRequested`
+    pub async fn request_cancel(&self, swap: &Swap) -> bool {
+        let response = {
+            let Some(conn) = self.connections.get(&swap.market_maker_id) else {
+                warn!(
+                    market_maker_id = %swap.market_maker_id,
+                    swap_id = %swap.id,
+                    "Cannot ask MM to agree to a cancel - not connected"
+                );
+                return false;
+            };
+            if !is_version_at_least(&conn.protocol_version, CANCEL_REQUEST_VERSION) {
+                debug!(
+                    market_maker_id = %swap.market_maker_id,
+                    protocol_version = %conn.protocol_version,
+                    "MM predates cancel requests, not asking for one"
+                );
+                return false;
+            }
+
+            let (response_tx, response_rx) = oneshot::channel();
+            self.pending_cancels
+                .insert(swap.id, (swap.market_maker_id, response_tx));
+            let request = ProtocolMessage {
+                version: conn.protocol_version.clone(),
+                sequence: 0,
+                payload: MMRequest::CancelRequested {
+                    request_id: Uuid::new_v4(),
+                    swap_id: swap.id,
+                    quote_id: swap.quote.id,
+                    timestamp: chrono::Utc::now(),
+                },
+                trace_id: swap.trace_id.clone(),
+            };
+            if let Err(e) = conn.enqueue(request) {
+                error!(market_maker_id = %swap.market_maker_id, error = %e, "Failed to send cancel request");
+                self.pending_cancels.remove(&swap.id);
+                return false;
+            }
+            response_rx
+        };
+
+        match time::timeout(self.validation_timeout, response).await {
+            Ok(Ok(accepted)) => accepted,
+            _ => {
+                warn!(
+                    market_maker_id = %swap.market_maker_id,
+                    swap_id = %swap.id,
+                    "MM didn't answer the cancel request"
+                );
+                self.pending_cancels.remove(&swap.id);
+                false
+            }
+        }
+    }
+
+    pub fn handle_cancel_acknowledged(
+        &self,
+        market_maker_id: &Uuid,
+        swap_id: &Uuid,
+        accepted: bool,
+    ) {
+        match self
+            .pending_cancels
+            .remove_if(swap_id, |_, (requested_from, _)| {
+                requested_from == market_maker_id
+            }) {
+            Some((_, (_, response_tx))) => {
+                let _ = response_tx.send(accepted);
+            }
+            None => warn!(
+                market_maker_id = %market_maker_id,
+                swap_id = %swap_id,
+                "Received cancel acknowledgment for a swap it wasn't asked about"
+            ),
+        }
+    }
+
+    /// Tell the MM a swap won't settle. MMs on a protocol version from before
+    /// `SwapFailed` are skipped
+    pub async fn notify_swap_failed(
+        &self,
+        market_maker_id: &Uuid,
+        swap_id: &Uuid,
+        quote_id: &Uuid,
+        reason_code: SwapFailureReason,
+        refund_tx_hash: Option<&str>,
+        trace_id: Option<&str>,
+    ) {
+        if let Some(conn) = self.connections.get(market_maker_id) {
+            if !is_version_at_least(&conn.protocol_version, SWAP_FAILED_VERSION) {
+                debug!(
+                    market_maker_id = %market_maker_id,
+                    protocol_version = %conn.protocol_version,
+                    "MM predates swap failure notifications, not sending one"
+                );
+                return;
+            }
+            let request = ProtocolMessage {
+                version: conn.protocol_version.clone(),
+                sequence: 0,
+                payload: MMRequest::SwapFailed {
+                    request_id: Uuid::new_v4(),
+                    swap_id: *swap_id,
+                    quote_id: *quote_id,
+                    reason_code,
+                    refund_tx_hash: refund_tx_hash.map(str::to_string),
+                    timestamp: chrono::Utc::now(),
+                },
+                trace_id: trace_id.map(str::to_string),
+            };
+            if let Err(e) = conn.enqueue(request) {
+                error!(market_maker_id = %market_maker_id, error = %e, "Failed to send swap failure");
+            }
+        } else {
+            warn!(
+                market_maker_id = %market_maker_id,
+                "Cannot notify MM - not connected"
+            );
+        }
+    }
+
+    /// Tell the swap's MM where the swap stands after a status change. Best
+    /// effort: updates that can't be delivered are counted and dropped, MMs on
+    /// a protocol version from before `SwapStatusUpdate` never get one
+    pub async fn notify_swap_status(&self, swap: &Swap) {
+        let Some(conn) = self.connections.get(&swap.market_maker_id) else {
+            debug!(
+                market_maker_id = %swap.market_maker_id,
+                swap_id = %swap.id,
+                "MM not connected, dropping swap status update"
+            );
+            self.record_status_update_dropped();
+            return;
+        };
+        if !is_version_at_least(&conn.protocol_version, SWAP_STATUS_UPDATE_VERSION) {
+            self.record_status_update_dropped();
+            return;
+        }
+
+        let request = ProtocolMessage {
+            version: conn.protocol_version.clone(),
+            sequence: 0,
+            payload: MMRequest::SwapStatusUpdate {
+                request_id: Uuid::new_v4(),
+                swap_id: swap.id,
+                quote_id: swap.quote.id,
+                status: swap.status,
+                user_deposit_confirmations: swap
+                    .user_deposit_status
+                    .as_ref()
+                    .map(|deposit| deposit.confirmations),
+                mm_deposit_confirmations: swap
+                    .mm_deposit_status
+                    .as_ref()
+                    .map(|deposit| deposit.confirmations),
+                user_deposit_detected_at: swap
+                    .user_deposit_status
+                    .as_ref()
+                    .map(|deposit| deposit.detected_at),
+                mm_deposit_detected_at: swap
+                    .mm_deposit_status
+                    .as_ref()
+                    .map(|deposit| deposit.detected_at),
+                updated_at: swap.updated_at,
+                timestamp: chrono::Utc::now(),
+            },
+            trace_id: swap.trace_id.clone(),
+        };
+        match conn.enqueue(request) {
+            Ok(()) => {
+                self.status_updates.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                warn!(market_maker_id = %swap.market_maker_id, error = %e, "Failed to send swap status update");
+                self.record_status_update_dropped();
+            }
+        }
+    }
+
+    pub fn record_status_update_dropped(&self) {
+        self.status_updates.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn status_update_counts(&self) -> StatusUpdateCounts {
+        StatusUpdateCounts {
+            sent: self.status_updates.sent.load(Ordering::Relaxed),
+            dropped: self.status_updates.dropped.load(Ordering::Relaxed),
+        }
+    }
+
     pub async fn validate_quote(
         &self,
         market_maker_id: &Uuid,
         quote_id: &Uuid,
         quote_hash: &[u8; 32],
         user_destination_address: &str,
+        trace_id: &str,
         response_tx: oneshot::Sender<Result<bool>>,
     ) {
         debug!(
@@ -194,6 +658,8 @@ impl MMRegistry {
             "Validating quote with market maker"
         );
 
+        self.pending_validations.sweep_expired();
+
         let mm_connection = if let Some(conn) = self.connections.get(&market_maker_id) {
             conn
         } else {
@@ -207,6 +673,32 @@ impl MMRegistry {
             return;
         };
 
+        match self.pending_validations.add(
+            *market_maker_id,
+            *quote_id,
+            response_tx,
+            Instant::now() + self.validation_timeout,
+        ) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(
+                    quote_id = %quote_id,
+                    "Quote is already being validated, waiting on the same response"
+                );
+                return;
+            }
+            Err(response_tx) => {
+                warn!(
+                    market_maker_id = %market_maker_id,
+                    "Too many validations in flight, rejecting"
+                );
+                let _ = response_tx.send(Err(MMRegistryError::TooManyPendingValidations {
+                    market_maker_id: market_maker_id.to_string(),
+                }));
+                return;
+            }
+        }
+
         let request = ProtocolMessage {
             version: mm_connection.protocol_version.clone(),
             sequence: 0, // TODO: Implement sequence tracking
@@ -217,25 +709,30 @@ impl MMRegistry {
                 user_destination_address: user_destination_address.to_string(),
                 timestamp: chrono::Utc::now(),
             },
+            trace_id: Some(trace_id.to_string()),
         };
 
-        // Store the response channel before sending the request
-        self.pending_validations
-            .insert(quote_id.clone(), response_tx);
-
         // Send the validation request
-        if let Err(e) = mm_connection.sender.send(request).await {
+        if let Err(e) = mm_connection.enqueue(request) {
             error!(
                 market_maker_id = %market_maker_id,
                 error = %e,
                 "Failed to send validation request"
             );
-            // Remove the pending validation since we failed to send
-            if let Some((_, tx)) = self.pending_validations.remove(&quote_id) {
-                let _ = tx.send(Err(MMRegistryError::MessageSendError { source: e }));
-            }
+            self.pending_validations
+                .resolve(*market_maker_id, *quote_id, || {
+                    Err(MMRegistryError::MessageSendError { source: e.clone() })
+                });
             return;
         }
+
+        // Don't leave the entry behind if the MM never answers
+        let pending_validations = self.pending_validations.clone();
+        let validation_timeout = self.validation_timeout;
+        tokio::spawn(async move {
+            time::sleep(validation_timeout).await;
+            pending_validations.sweep_expired();
+        });
     }
 
     pub fn handle_validation_response(
@@ -251,10 +748,10 @@ impl MMRegistry {
             "Handling validation response"
         );
 
-        // Find the pending validation for this quote
-        if let Some((_, tx)) = self.pending_validations.remove(quote_id) {
-            let _ = tx.send(Ok(accepted));
-        } else {
+        if !self
+            .pending_validations
+            .resolve(*market_maker_id, *quote_id, || Ok(accepted))
+        {
             warn!(
                 quote_id = %quote_id,
                 "Received validation response for unknown quote"
@@ -274,11 +771,90 @@ impl MMRegistry {
             .map(|entry| entry.key().clone())
             .collect()
     }
+
+    /// Messages waiting on each connected MM's socket
+    #[must_use]
+    pub fn send_queue_depths(&self) -> Vec<(Uuid, usize)> {
+        self.connections
+            .iter()
+            .map(|conn| (conn.id, conn.queue_depth()))
+            .collect()
+    }
+
+    /// Note that the MM answered a ping
+    pub fn record_pong(&self, market_maker_id: Uuid) {
+        if let Some(mut conn) = self.connections.get_mut(&market_maker_id) {
+            conn.last_pong_at = Some(Utc::now());
+        }
+    }
+
+    /// Connection metadata of every connected MM
+    #[must_use]
+    pub fn connected_market_maker_details(&self) -> Vec<ConnectedMarketMaker> {
+        let now = Utc::now();
+        // Collected first, counting validations takes the pending map's locks too
+        let connections: Vec<_> = self
+            .connections
+            .iter()
+            .map(|conn| {
+                (
+                    conn.id,
+                    conn.protocol_version.clone(),
+                    conn.connected_at,
+                    conn.last_pong_at,
+                )
+            })
+            .collect();
+        connections
+            .into_iter()
+            .map(
+                |(market_maker_id, protocol_version, connected_at, last_pong_at)| {
+                    ConnectedMarketMaker {
+                        market_maker_id,
+                        protocol_version,
+                        connected_at,
+                        uptime_seconds: (now - connected_at).to_std().unwrap_or_default().as_secs(),
+                        in_flight_requests: self.pending_validations.in_flight(market_maker_id),
+                        last_pong_at,
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Tell every connected MM the server is shutting down. Queued behind the
+    /// notifications already waiting on each connection, so those go out first.
+    /// The connection closes after `GoingAway`, MMs predating it only see the close
+    pub async fn going_away(&self, reason: &str) {
+        for conn in self.connections.iter() {
+            let request = ProtocolMessage {
+                version: conn.protocol_version.clone(),
+                sequence: 0,
+                payload: MMRequest::GoingAway {
+                    request_id: Uuid::new_v4(),
+                    reason: reason.to_string(),
+                    timestamp: chrono::Utc::now(),
+                },
+                trace_id: None,
+            };
+            if let Err(e) = conn.enqueue(request) {
+                warn!(market_maker_id = %conn.id, error = %e, "Failed to tell market maker the server is going away");
+            }
+        }
+    }
+
+    /// Wait until every MM connection has closed
+    pub async fn wait_until_disconnected(&self) {
+        while !self.connections.is_empty() {
+            time::sleep(DISCONNECT_POLL_INTERVAL).await;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use otc_models::SwapBuilder;
 
     #[tokio::test]
     async fn test_register_unregister() {
@@ -297,6 +873,36 @@ mod tests {
         assert_eq!(registry.get_connection_count(), 0);
     }
 
+    #[tokio::test]
+    async fn test_connection_details_follow_pongs_and_validations() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (tx, _rx) = mpsc::channel(10);
+        let mm_id = Uuid::new_v4();
+        registry.register(mm_id, tx, "1.0.0".to_string());
+
+        let details = registry.connected_market_maker_details();
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].protocol_version, "1.0.0");
+        assert_eq!(details[0].in_flight_requests, 0);
+        assert!(details[0].last_pong_at.is_none());
+
+        let (waiter, _response) = oneshot::channel();
+        registry
+            .pending_validations
+            .add(
+                mm_id,
+                Uuid::new_v4(),
+                waiter,
+                Instant::now() + Duration::from_secs(5),
+            )
+            .unwrap();
+        registry.record_pong(mm_id);
+
+        let details = registry.connected_market_maker_details();
+        assert_eq!(details[0].in_flight_requests, 1);
+        assert!(details[0].last_pong_at.unwrap() >= details[0].connected_at);
+    }
+
     #[tokio::test]
     async fn test_validate_quote_not_connected() {
         let registry = MMRegistry::new(Duration::from_secs(5));
@@ -309,6 +915,7 @@ mod tests {
                 &Uuid::new_v4(),
                 &[0u8; 32],
                 "0x123",
+                "trace-1",
                 response_tx,
             )
             .await;
@@ -319,4 +926,298 @@ mod tests {
             Err(MMRegistryError::MarketMakerNotConnected { .. })
         ));
     }
+
+    #[tokio::test]
+    async fn swap_failed_only_reaches_mms_that_understand_it() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (old_tx, mut old_rx) = mpsc::channel(10);
+        let (new_tx, mut new_rx) = mpsc::channel(10);
+        let old_mm = Uuid::new_v4();
+        let new_mm = Uuid::new_v4();
+        registry.register(old_mm, old_tx, "1.0.0".to_string());
+        registry.register(new_mm, new_tx, SWAP_FAILED_VERSION.to_string());
+
+        for mm in [old_mm, new_mm] {
+            registry
+                .notify_swap_failed(
+                    &mm,
+                    &Uuid::new_v4(),
+                    &Uuid::new_v4(),
+                    SwapFailureReason::Cancelled,
+                    None,
+                    None,
+                )
+                .await;
+        }
+
+        assert!(old_rx.try_recv().is_err());
+        assert!(matches!(
+            new_rx.try_recv().unwrap().payload,
+            MMRequest::SwapFailed {
+                reason_code: SwapFailureReason::Cancelled,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn deposit_retry_only_reaches_mms_that_understand_it() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (old_tx, mut old_rx) = mpsc::channel(10);
+        let (new_tx, mut new_rx) = mpsc::channel(10);
+        let old_mm = Uuid::new_v4();
+        let new_mm = Uuid::new_v4();
+        registry.register(old_mm, old_tx, SWAP_FAILED_VERSION.to_string());
+        registry.register(new_mm, new_tx, DEPOSIT_RETRY_VERSION.to_string());
+
+        let deadline = chrono::Utc::now();
+        let failed = TxHash::parse(ChainType::Ethereum, &"de".repeat(32)).unwrap();
+        for mm in [old_mm, new_mm] {
+            registry
+                .notify_deposit_retry_requested(&swap(mm), &failed, 1, deadline)
+                .await;
+        }
+
+        assert!(old_rx.try_recv().is_err());
+        match new_rx.try_recv().unwrap().payload {
+            MMRequest::DepositRetryRequested {
+                failed_tx_hash,
+                attempt,
+                deadline: sent_deadline,
+                ..
+            } => {
+                assert_eq!(failed_tx_hash, failed);
+                assert_eq!(attempt, 1);
+                assert_eq!(sent_deadline, deadline);
+            }
+            other => panic!("expected a deposit retry request, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_needs_an_answer_from_an_mm_that_understands_it() {
+        let registry = MMRegistry::new(Duration::from_millis(200));
+        let (old_tx, mut old_rx) = mpsc::channel(10);
+        let (new_tx, mut new_rx) = mpsc::channel(10);
+        let old_mm = Uuid::new_v4();
+        let new_mm = Uuid::new_v4();
+        registry.register(old_mm, old_tx, DEPOSIT_RETRY_VERSION.to_string());
+        registry.register(new_mm, new_tx, CANCEL_REQUEST_VERSION.to_string());
+
+        assert!(!registry.request_cancel(&swap(old_mm)).await);
+        assert!(old_rx.try_recv().is_err());
+
+        for accepted in [true, false] {
+            let swap = swap(new_mm);
+            let answer = async {
+                let request = new_rx.recv().await.unwrap();
+                let MMRequest::CancelRequested { swap_id, .. } = request.payload else {
+                    panic!("expected a cancel request, got {:?}", request.payload);
+                };
+                // Only the MM that was asked can answer
+                registry.handle_cancel_acknowledged(&old_mm, &swap_id, true);
+                registry.handle_cancel_acknowledged(&new_mm, &swap_id, accepted);
+            };
+            let (agreed, ()) = tokio::join!(registry.request_cancel(&swap), answer);
+            assert_eq!(agreed, accepted);
+        }
+
+        // Silence is a refusal
+        assert!(!registry.request_cancel(&swap(new_mm)).await);
+        assert!(registry.pending_cancels.is_empty());
+    }
+
+    /// A swap of `market_maker_id` whose user deposit was just seen
+    fn swap(market_maker_id: Uuid) -> Swap {
+        let now = chrono::Utc::now();
+        let mut swap = SwapBuilder::new()
+            .with_market_maker_id(market_maker_id)
+            .with_status(otc_models::SwapStatus::WaitingUserDepositConfirmed)
+            .build();
+        swap.user_deposit_status = Some(otc_models::UserDepositStatus {
+            tx_hash: TxHash::parse(ChainType::Bitcoin, &"ab".repeat(32)).unwrap(),
+            amount: swap.quote.from.amount,
+            detected_at: now,
+            confirmations: 1,
+            last_checked: now,
+        });
+        swap
+    }
+
+    #[tokio::test]
+    async fn status_updates_are_counted_and_skip_old_mms() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (old_tx, mut old_rx) = mpsc::channel(10);
+        let (new_tx, mut new_rx) = mpsc::channel(10);
+        let old_mm = Uuid::new_v4();
+        let new_mm = Uuid::new_v4();
+        registry.register(old_mm, old_tx, SWAP_FAILED_VERSION.to_string());
+        registry.register(new_mm, new_tx, SWAP_STATUS_UPDATE_VERSION.to_string());
+
+        for mm in [old_mm, new_mm, Uuid::new_v4()] {
+            registry.notify_swap_status(&swap(mm)).await;
+        }
+
+        assert!(old_rx.try_recv().is_err());
+        assert!(matches!(
+            new_rx.try_recv().unwrap().payload,
+            MMRequest::SwapStatusUpdate {
+                status: otc_models::SwapStatus::WaitingUserDepositConfirmed,
+                user_deposit_confirmations: Some(1),
+                mm_deposit_confirmations: None,
+                ..
+            }
+        ));
+        assert_eq!(
+            registry.status_update_counts(),
+            StatusUpdateCounts {
+                sent: 1,
+                dropped: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn going_away_follows_queued_notifications() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (tx, mut rx) = mpsc::channel(10);
+        let mm_id = Uuid::new_v4();
+        registry.register(mm_id, tx, SWAP_STATUS_UPDATE_VERSION.to_string());
+
+        registry.notify_swap_status(&swap(mm_id)).await;
+        registry.going_away("restarting").await;
+
+        assert!(matches!(
+            rx.try_recv().unwrap().payload,
+            MMRequest::SwapStatusUpdate { .. }
+        ));
+        assert!(matches!(
+            rx.try_recv().unwrap().payload,
+            MMRequest::GoingAway { reason, .. } if reason == "restarting"
+        ));
+
+        let waiting = tokio::spawn({
+            let registry = registry.clone();
+            async move { registry.wait_until_disconnected().await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiting.is_finished());
+        registry.unregister(mm_id);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_full_send_queue_flags_a_slow_consumer() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (tx, mut rx) = mpsc::channel(2);
+        let mm_id = Uuid::new_v4();
+        let slow_consumer = registry.register(mm_id, tx, SWAP_STATUS_UPDATE_VERSION.to_string());
+
+        registry.notify_swap_status(&swap(mm_id)).await;
+        registry.notify_swap_status(&swap(mm_id)).await;
+        assert_eq!(registry.send_queue_depths(), vec![(mm_id, 2)]);
+        assert_eq!(registry.status_update_counts().dropped, 0);
+
+        // The third doesn't wait for room, it's dropped and the writer is told
+        registry.notify_swap_status(&swap(mm_id)).await;
+        assert_eq!(registry.status_update_counts().dropped, 1);
+        tokio::time::timeout(Duration::from_secs(1), slow_consumer.notified())
+            .await
+            .unwrap();
+
+        rx.try_recv().unwrap();
+        assert_eq!(registry.send_queue_depths(), vec![(mm_id, 1)]);
+    }
+
+    /// Ask `registry` to validate `quote_id` with `mm_id`, returning the answer channel
+    async fn request_validation(
+        registry: &MMRegistry,
+        mm_id: Uuid,
+        quote_id: Uuid,
+    ) -> oneshot::Receiver<Result<bool>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        registry
+            .validate_quote(
+                &mm_id,
+                &quote_id,
+                &[0u8; 32],
+                "0x123",
+                "trace-1",
+                response_tx,
+            )
+            .await;
+        response_rx
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_validation_is_cleaned_up() {
+        let registry = MMRegistry::new(Duration::from_millis(50));
+        let (tx, _rx) = mpsc::channel(10);
+        let mm_id = Uuid::new_v4();
+        registry.register(mm_id, tx, "1.0.0".to_string());
+
+        let response_rx = request_validation(&registry, mm_id, Uuid::new_v4()).await;
+        assert_eq!(registry.pending_validations.len(), 1);
+
+        assert!(matches!(
+            response_rx.await.unwrap(),
+            Err(MMRegistryError::ValidationTimeout { .. })
+        ));
+        assert_eq!(registry.pending_validations.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_validations_share_one_request() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (tx, mut rx) = mpsc::channel(10);
+        let mm_id = Uuid::new_v4();
+        let quote_id = Uuid::new_v4();
+        registry.register(mm_id, tx, "1.0.0".to_string());
+
+        let first = request_validation(&registry, mm_id, quote_id).await;
+        let second = request_validation(&registry, mm_id, quote_id).await;
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_err(), "the MM should be asked once");
+
+        // Another MM can't answer for the quote
+        registry.handle_validation_response(&Uuid::new_v4(), &quote_id, false);
+        registry.handle_validation_response(&mm_id, &quote_id, true);
+
+        assert!(matches!(first.await.unwrap(), Ok(true)));
+        assert!(matches!(second.await.unwrap(), Ok(true)));
+        assert_eq!(registry.pending_validations.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_validations_are_capped_per_mm() {
+        let registry = MMRegistry::new(Duration::from_secs(5));
+        let (tx, _rx) = mpsc::channel(MAX_PENDING_VALIDATIONS_PER_MM + 1);
+        let (other_tx, _other_rx) = mpsc::channel(1);
+        let mm_id = Uuid::new_v4();
+        let other_mm_id = Uuid::new_v4();
+        registry.register(mm_id, tx, "1.0.0".to_string());
+        registry.register(other_mm_id, other_tx, "1.0.0".to_string());
+
+        let mut waiting = Vec::new();
+        for _ in 0..MAX_PENDING_VALIDATIONS_PER_MM {
+            waiting.push(request_validation(&registry, mm_id, Uuid::new_v4()).await);
+        }
+
+        let rejected = request_validation(&registry, mm_id, Uuid::new_v4()).await;
+        assert!(matches!(
+            rejected.await.unwrap(),
+            Err(MMRegistryError::TooManyPendingValidations { .. })
+        ));
+
+        // The cap is per market maker
+        let mut other = request_validation(&registry, other_mm_id, Uuid::new_v4()).await;
+        assert!(other.try_recv().is_err());
+        assert_eq!(
+            registry.pending_validations.len(),
+            MAX_PENDING_VALIDATIONS_PER_MM + 1
+        );
+    }
 }
@@ -1,7 +1,21 @@
+pub mod admin_summary;
+pub mod confirmation_policy;
+pub mod deposit_salts;
+pub mod fee_telemetry;
+pub mod mm_nonces;
 pub mod mm_registry;
+pub mod quote_price_check;
+pub mod settlement_reconciliation;
 pub mod swap_manager;
 pub mod swap_monitoring;
 
+pub use admin_summary::{AdminSummary, DEFAULT_SUMMARY_CACHE_TTL};
+pub use confirmation_policy::ConfirmationPolicy;
+pub use deposit_salts::{DepositSaltSource, OsRandomSalts};
+pub use fee_telemetry::{ChainFees, FeeTelemetry, FeeTelemetryError, DEFAULT_FEE_CACHE_TTL};
+pub use mm_nonces::{MmNonceSource, OsRandomNonces};
 pub use mm_registry::MMRegistry;
+pub use quote_price_check::QuotePriceCheck;
+pub use settlement_reconciliation::SettlementReconciliationService;
 pub use swap_manager::SwapManager;
 pub use swap_monitoring::SwapMonitoringService;
\ No newline at end of file
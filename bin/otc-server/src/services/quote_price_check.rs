@@ -0,0 +1,279 @@
+//! Sanity check of a quote's price when a swap is created from it
+//!
+//! A user can hold a quote until it nearly expires while the market moves. The
+//! reference price is the median rate of quotes for the same pair and of a
+//! similar size issued after it, so a quote is only compared against prices
+//! the market makers offered since. Only a move against the user is rejected,
+//! a quote that became a better deal than the market is always honored. The
+//! check is opt-in, see `OtcServerArgs::quote_price_max_deviation_bps`.
+//!
+//! The reference quotes are the ones swaps were created from, the OTC server
+//! never sees the rest of what the RFQ server quotes. Until
+//! [`MIN_REFERENCE_QUOTES`] of a similar size were swapped within
+//! [`REFERENCE_WINDOW`] a quote isn't checked, so on a quiet pair the check
+//! rarely runs. Each skipped check is logged.
+
+use chrono::Duration;
+use otc_models::{Lot, Quote};
+
+/// Only quotes issued this recently serve as a reference
+pub const REFERENCE_WINDOW: Duration = Duration::minutes(10);
+
+/// Fewer reference quotes than this say too little about the market, the check is skipped
+pub const MIN_REFERENCE_QUOTES: usize = 3;
+
+/// Most recent quotes the reference is taken from
+pub const MAX_REFERENCE_QUOTES: i64 = 50;
+
+/// How many times larger or smaller than the quote a reference quote may be.
+/// Fixed network fees weigh far more on the rate of a small quote than of a
+/// large one, so only quotes of a similar size are comparable
+pub const SIZE_BUCKET_FACTOR: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuotePriceCheck {
+    /// How much worse than the reference a quote's rate may be, in basis points
+    pub max_deviation_bps: u64,
+}
+
+/// What the check made of a quote's price
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuotePriceVerdict {
+    /// Within the allowed deviation of the reference, or better for the user
+    Fresh,
+    /// Worse for the user than the reference by more than allowed
+    Stale { shortfall_bps: f64 },
+    /// Too few reference quotes of a similar size to judge the price by
+    Unchecked { reference_quotes: usize },
+}
+
+impl QuotePriceCheck {
+    /// Compare `quote` with the median rate of the quotes in `reference` of a
+    /// similar size
+    #[must_use]
+    pub fn verdict(&self, quote: &Quote, reference: &[Quote]) -> QuotePriceVerdict {
+        let rates: Vec<f64> = reference
+            .iter()
+            .filter(|other| other.id != quote.id && same_size_bucket(&quote.from, &other.from))
+            .filter_map(|other| implied_rate(&other.from, &other.to))
+            .collect();
+        let reference_quotes = rates.len();
+        let (Some(quoted_rate), Some(reference_rate)) = (
+            implied_rate(&quote.from, &quote.to),
+            median(rates).filter(|_| reference_quotes >= MIN_REFERENCE_QUOTES),
+        ) else {
+            return QuotePriceVerdict::Unchecked { reference_quotes };
+        };
+        let shortfall_bps = user_shortfall_bps(quoted_rate, reference_rate);
+        if shortfall_bps > self.max_deviation_bps as f64 {
+            QuotePriceVerdict::Stale { shortfall_bps }
+        } else {
+            QuotePriceVerdict::Fresh
+        }
+    }
+}
+
+/// Whole units of `to` received per whole unit of `from`
+#[must_use]
+pub fn implied_rate(from: &Lot, to: &Lot) -> Option<f64> {
+    let from = whole_units(from);
+    let to = whole_units(to);
+    (from > 0.0 && from.is_finite() && to.is_finite()).then(|| to / from)
+}
+
+/// How much less the user gets at `quoted_rate` than at `reference_rate`, in
+/// basis points, negative when the quote is the better deal
+#[must_use]
+pub fn user_shortfall_bps(quoted_rate: f64, reference_rate: f64) -> f64 {
+    (reference_rate - quoted_rate) / reference_rate * 10_000.0
+}
+
+/// Whether `other` is within [`SIZE_BUCKET_FACTOR`] of `lot`'s size
+fn same_size_bucket(lot: &Lot, other: &Lot) -> bool {
+    let size = whole_units(lot);
+    let other = whole_units(other);
+    other >= size / SIZE_BUCKET_FACTOR && other <= size * SIZE_BUCKET_FACTOR
+}
+
+fn whole_units(lot: &Lot) -> f64 {
+    let amount: f64 = lot.amount.to_string().parse().unwrap_or(f64::NAN);
+    amount / 10f64.powi(i32::from(lot.currency.decimals))
+}
+
+fn median(mut rates: Vec<f64>) -> Option<f64> {
+    rates.sort_by(f64::total_cmp);
+    let mid = rates.len() / 2;
+    match rates.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((rates[mid - 1] + rates[mid]) / 2.0),
+        _ => Some(rates[mid]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+    use chrono::Utc;
+    use otc_models::{ChainType, Currency, TokenIdentifier};
+    use uuid::Uuid;
+
+    const CHECK: QuotePriceCheck = QuotePriceCheck {
+        max_deviation_bps: 200,
+    };
+
+    fn btc(sats: u64) -> Lot {
+        Lot {
+            currency: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(sats),
+        }
+    }
+
+    fn cbbtc(sats: u64) -> Lot {
+        Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Address(
+                    "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf".to_string(),
+                ),
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(sats),
+        }
+    }
+
+    /// 1 BTC quoted for `to_sats` cbBTC sats
+    fn quote(to_sats: u64) -> Quote {
+        sized_quote(100_000_000, to_sats)
+    }
+
+    fn sized_quote(from_sats: u64, to_sats: u64) -> Quote {
+        Quote {
+            id: Uuid::new_v4(),
+            from: btc(from_sats),
+            to: cbbtc(to_sats),
+            market_maker_id: Uuid::new_v4(),
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn stale_shortfall_bps(quote: &Quote, reference: &[Quote]) -> Option<f64> {
+        match CHECK.verdict(quote, reference) {
+            QuotePriceVerdict::Stale { shortfall_bps } => Some(shortfall_bps),
+            _ => None,
+        }
+    }
+
+    fn reference() -> Vec<Quote> {
+        vec![quote(99_000_000), quote(99_700_000), quote(99_800_000)]
+    }
+
+    #[test]
+    fn test_implied_rate_applies_decimals() {
+        let eth = Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Native,
+                decimals: 18,
+                chain_id: None,
+            },
+            amount: U256::from(15u64) * U256::from(10u64).pow(U256::from(18u64)),
+        };
+        let rate = implied_rate(&btc(50_000_000), &eth).unwrap();
+        assert!((rate - 30.0).abs() < 1e-9);
+        assert_eq!(implied_rate(&btc(0), &eth), None);
+    }
+
+    #[test]
+    fn test_shortfall_is_signed_by_who_loses() {
+        assert!((user_shortfall_bps(0.97, 1.0) - 300.0).abs() < 1e-6);
+        assert!((user_shortfall_bps(1.03, 1.0) + 300.0).abs() < 1e-6);
+        assert_eq!(user_shortfall_bps(1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_rejects_only_quotes_worse_for_the_user() {
+        // The median reference rate is 0.997
+        let worse = quote(97_000_000);
+        let shortfall = stale_shortfall_bps(&worse, &reference()).unwrap();
+        assert!((shortfall - 270.8).abs() < 0.1);
+
+        // Within the allowed deviation
+        assert_eq!(
+            CHECK.verdict(&quote(98_000_000), &reference()),
+            QuotePriceVerdict::Fresh
+        );
+
+        // The market moved against the market maker, the user keeps the better deal
+        assert_eq!(
+            CHECK.verdict(&quote(110_000_000), &reference()),
+            QuotePriceVerdict::Fresh
+        );
+    }
+
+    #[test]
+    fn test_skips_without_enough_reference_quotes() {
+        let worse = quote(90_000_000);
+        let mut reference = reference();
+        reference.pop();
+        assert_eq!(
+            CHECK.verdict(&worse, &reference),
+            QuotePriceVerdict::Unchecked {
+                reference_quotes: 2
+            }
+        );
+
+        // The quote itself doesn't count towards its reference
+        reference.push(worse.clone());
+        assert_eq!(
+            CHECK.verdict(&worse, &reference),
+            QuotePriceVerdict::Unchecked {
+                reference_quotes: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_compares_only_quotes_of_a_similar_size() {
+        // Fixed fees make a 0.01 BTC quote's rate 5% worse than a 1 BTC quote's,
+        // so the 1 BTC quotes are no reference for it
+        let small = sized_quote(1_000_000, 940_000);
+        assert_eq!(
+            CHECK.verdict(&small, &reference()),
+            QuotePriceVerdict::Unchecked {
+                reference_quotes: 0
+            }
+        );
+
+        let mut reference = reference();
+        reference.extend([
+            sized_quote(600_000, 570_000),
+            sized_quote(1_500_000, 1_425_000),
+            sized_quote(1_900_000, 1_805_000),
+        ]);
+        // Against its own size it's 105 bps worse, within the allowed deviation
+        assert_eq!(CHECK.verdict(&small, &reference), QuotePriceVerdict::Fresh);
+        let worse = sized_quote(1_000_000, 900_000);
+        let shortfall = stale_shortfall_bps(&worse, &reference).unwrap();
+        assert!((shortfall - 526.3).abs() < 0.1);
+
+        assert!(same_size_bucket(&btc(100), &btc(200)));
+        assert!(same_size_bucket(&btc(100), &btc(50)));
+        assert!(!same_size_bucket(&btc(100), &btc(201)));
+        assert!(!same_size_bucket(&btc(100), &btc(49)));
+    }
+
+    #[test]
+    fn test_median_resists_an_outlier() {
+        assert_eq!(median(vec![1.0, 100.0, 2.0]), Some(2.0));
+        assert_eq!(median(vec![4.0, 1.0, 3.0, 2.0]), Some(2.5));
+        assert_eq!(median(Vec::new()), None);
+    }
+}
@@ -0,0 +1,498 @@
+//! Audit of settled and failed swaps against the chains
+//!
+//! A missed notification or a bug in a state transition can leave a swap
+//! settled while its MM deposit was reorged out, or failed while the user's
+//! funds did arrive. Every so often a sample of recently terminal swaps is
+//! checked again: their recorded deposit txs must still be in a block and move
+//! what was recorded, and a failed swap's deposit address must be empty.
+//! Mismatches are only recorded and logged, nothing is corrected.
+
+use crate::db::{Database, DepositLeg, Discrepancy, FindingKind};
+use crate::error::OtcServerError;
+use alloy::primitives::U256;
+use blockchain_utils::FeeCalcFromLot;
+use common::{Clock, Shutdown};
+use otc_chains::traits::{ChainOperations, MarketMakerPaymentValidation};
+use otc_chains::ChainRegistry;
+use otc_models::{Currency, Lot, Swap, SwapStatus, TxHash, TxStatus};
+use snafu::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{error, info, warn};
+
+#[derive(Debug, Snafu)]
+pub enum ReconciliationError {
+    #[snafu(display("Database error: {}", source))]
+    Database { source: OtcServerError },
+
+    #[snafu(display("Chain operation error: {}", source))]
+    ChainOperation { source: otc_chains::Error },
+}
+
+pub type ReconciliationResult<T> = Result<T, ReconciliationError>;
+
+/// What one reconciliation run did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReconciliationRun {
+    pub checked: usize,
+    /// Swaps that couldn't be checked, a chain or the database failed
+    pub skipped: usize,
+    /// Findings opened by this run
+    pub opened: usize,
+}
+
+pub struct SettlementReconciliationService {
+    db: Database,
+    chain_registry: Arc<ChainRegistry>,
+    /// Only swaps that turned terminal this recently are sampled
+    lookback: chrono::Duration,
+    /// Most swaps checked per run
+    sample_size: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl SettlementReconciliationService {
+    #[must_use]
+    pub fn new(
+        db: Database,
+        chain_registry: Arc<ChainRegistry>,
+        lookback: Duration,
+        sample_size: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            db,
+            chain_registry,
+            lookback: chrono::Duration::from_std(lookback).unwrap_or(chrono::Duration::MAX),
+            sample_size,
+            clock,
+        }
+    }
+
+    /// Reconcile every `interval` until `shutdown` is triggered
+    pub async fn run(self: Arc<Self>, interval: Duration, shutdown: Shutdown) {
+        let mut ticker = time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick is immediate, a restart shouldn't trigger a run
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                () = shutdown.triggered() => return,
+            }
+            match self.reconcile().await {
+                Ok(run) => info!(
+                    "Reconciled {} terminal swaps ({} skipped), {} new findings",
+                    run.checked, run.skipped, run.opened
+                ),
+                Err(e) => error!("Settlement reconciliation failed: {}", e),
+            }
+        }
+    }
+
+    /// Check a sample of recently terminal swaps against the chains, recording
+    /// what disagrees
+    pub async fn reconcile(&self) -> ReconciliationResult<ReconciliationRun> {
+        let now = self.clock.now();
+        let swaps = self
+            .db
+            .swaps()
+            .sample_terminal(
+                now.checked_sub_signed(self.lookback)
+                    .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC),
+                self.sample_size,
+            )
+            .await
+            .context(DatabaseSnafu)?;
+
+        let mut run = ReconciliationRun::default();
+        for swap in swaps {
+            let discrepancies = match self.check_swap(&swap).await {
+                Ok(discrepancies) => discrepancies,
+                Err(e) => {
+                    warn!("Could not reconcile swap {}: {}", swap.id, e);
+                    run.skipped += 1;
+                    continue;
+                }
+            };
+            let opened = self
+                .db
+                .reconciliation_findings()
+                .record_check(swap.id, swap.status, &discrepancies, now)
+                .await
+                .context(DatabaseSnafu)?;
+            for discrepancy in &opened {
+                error!(
+                    swap_id = %swap.id,
+                    kind = ?discrepancy.kind,
+                    leg = ?discrepancy.leg,
+                    chain = %discrepancy.chain,
+                    "ALERT: {:?} swap disagrees with the chain: {}",
+                    swap.status,
+                    discrepancy.detail
+                );
+            }
+            run.checked += 1;
+            run.opened += opened.len();
+        }
+
+        Ok(run)
+    }
+
+    /// Everything about `swap` that disagrees with the chains
+    async fn check_swap(&self, swap: &Swap) -> ReconciliationResult<Vec<Discrepancy>> {
+        let quote = &swap.quote;
+        let mut discrepancies = Vec::new();
+
+        let user_chain = self.chain(&quote.from.currency)?;
+        match &swap.user_deposit_status {
+            Some(deposit) => {
+                let recorded = RecordedDeposit {
+                    leg: DepositLeg::UserDeposit,
+                    tx_hash: &deposit.tx_hash,
+                    amount: deposit.amount,
+                    address: &swap.user_deposit_address,
+                    lot: &quote.from,
+                    validation: None,
+                };
+                discrepancies.extend(recorded.check(user_chain.as_ref()).await?);
+            }
+            None if swap.status == SwapStatus::Settled => {
+                discrepancies.push(unrecorded(DepositLeg::UserDeposit, &quote.from));
+            }
+            None => {}
+        }
+
+        match swap.status {
+            SwapStatus::Settled => match &swap.mm_deposit_status {
+                Some(deposit) => {
+                    let recorded = RecordedDeposit {
+                        leg: DepositLeg::MmDeposit,
+                        tx_hash: &deposit.tx_hash,
+                        amount: deposit.amount,
+                        address: &swap.user_destination_address,
+                        lot: &quote.to,
+                        validation: Some(MarketMakerPaymentValidation {
+                            fee_amount: U256::from(quote.to.compute_protocol_fee()),
+                            embedded_nonce: swap.mm_nonce,
+                            not_before: swap.mm_notified_at,
+                        }),
+                    };
+                    let mm_chain = self.chain(&quote.to.currency)?;
+                    discrepancies.extend(recorded.check(mm_chain.as_ref()).await?);
+                }
+                None => discrepancies.push(unrecorded(DepositLeg::MmDeposit, &quote.to)),
+            },
+            // Refunds don't end in Failed, so whatever is at the address was
+            // never dealt with
+            SwapStatus::Failed => {
+                let balance = user_chain
+                    .get_balance(&swap.user_deposit_address, &quote.from.currency.token)
+                    .await
+                    .context(ChainOperationSnafu)?;
+                if balance > U256::ZERO {
+                    let recorded = match &swap.user_deposit_status {
+                        Some(deposit) => format!("a deposit of {} was recorded", deposit.amount),
+                        None => "no deposit was recorded".to_string(),
+                    };
+                    discrepancies.push(Discrepancy {
+                        kind: FindingKind::UnexpectedBalance,
+                        leg: DepositLeg::UserDeposit,
+                        chain: quote.from.currency.chain,
+                        tx_hash: None,
+                        detail: format!(
+                            "deposit address {} holds {}, {}",
+                            swap.user_deposit_address, balance, recorded
+                        ),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        Ok(discrepancies)
+    }
+
+    fn chain(&self, currency: &Currency) -> ReconciliationResult<Arc<dyn ChainOperations>> {
+        self.chain_registry
+            .for_currency(currency)
+            .ok_or(ReconciliationError::ChainOperation {
+                source: otc_chains::Error::ChainNotSupported {
+                    chain: currency.network().to_string(),
+                },
+            })
+    }
+}
+
+/// A settled swap without a record of one of its deposits
+fn unrecorded(leg: DepositLeg, lot: &Lot) -> Discrepancy {
+    Discrepancy {
+        kind: FindingKind::TxMissing,
+        leg,
+        chain: lot.currency.chain,
+        tx_hash: None,
+        detail: "settled without a recorded deposit".to_string(),
+    }
+}
+
+/// A deposit as the swap recorded it
+struct RecordedDeposit<'a> {
+    leg: DepositLeg,
+    tx_hash: &'a TxHash,
+    amount: U256,
+    /// Where the deposit was paid to
+    address: &'a str,
+    /// What the quote expected it to pay
+    lot: &'a Lot,
+    validation: Option<MarketMakerPaymentValidation>,
+}
+
+impl RecordedDeposit<'_> {
+    async fn check(
+        &self,
+        chain_ops: &dyn ChainOperations,
+    ) -> ReconciliationResult<Vec<Discrepancy>> {
+        let discrepancy = |kind, detail| Discrepancy {
+            kind,
+            leg: self.leg,
+            chain: self.lot.currency.chain,
+            tx_hash: Some(self.tx_hash.to_string()),
+            detail,
+        };
+        let mut discrepancies = Vec::new();
+
+        let status = chain_ops
+            .get_tx_status(self.tx_hash.as_str())
+            .await
+            .context(ChainOperationSnafu)?;
+        if !matches!(status, TxStatus::Included { .. }) {
+            discrepancies.push(discrepancy(
+                FindingKind::TxMissing,
+                format!("deposit tx {} is {:?}", self.tx_hash, status),
+            ));
+            return Ok(discrepancies);
+        }
+
+        if self.amount < self.lot.amount {
+            discrepancies.push(discrepancy(
+                FindingKind::AmountDiffers,
+                format!(
+                    "recorded deposit of {} is less than the quoted {}",
+                    self.amount, self.lot.amount
+                ),
+            ));
+        } else if let Some(transfer) = chain_ops
+            .search_for_transfer(self.address, self.lot, self.validation.clone(), None)
+            .await
+            .context(ChainOperationSnafu)?
+        {
+            // The search only reports one transfer, a different one says nothing
+            // about the recorded tx
+            if transfer.tx_hash == *self.tx_hash && transfer.amount != self.amount {
+                discrepancies.push(discrepancy(
+                    FindingKind::AmountDiffers,
+                    format!(
+                        "deposit tx {} moved {} on chain, {} was recorded",
+                        self.tx_hash, transfer.amount, self.amount
+                    ),
+                ));
+            }
+        }
+
+        Ok(discrepancies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::hex;
+    use chrono::Utc;
+    use common::SystemClock;
+    use otc_chains::test_utils::StubChain;
+    use otc_models::{
+        ChainType, Finality, MMDepositStatus, SwapBuilder, TokenIdentifier, TransferInfo,
+        UserDepositStatus,
+    };
+
+    fn lot(chain: ChainType, amount: u64) -> Lot {
+        Lot {
+            currency: Currency {
+                chain,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(amount),
+        }
+    }
+
+    fn terminal_swap(salt: [u8; 32], status: SwapStatus) -> Swap {
+        let now = Utc::now();
+        let mut swap = SwapBuilder::new()
+            .with_lots(
+                lot(ChainType::Bitcoin, 1_000_000),
+                lot(ChainType::Ethereum, 990_000),
+            )
+            .with_status(status)
+            .with_deposit(salt, hex::encode(salt))
+            .with_destination("0x1234567890123456789012345678901234567890")
+            .with_confirmations(1, 1)
+            .build();
+        if status == SwapStatus::Settled {
+            swap.user_deposit_status = Some(UserDepositStatus {
+                tx_hash: TxHash::parse(ChainType::Bitcoin, &hex::encode(salt)).unwrap(),
+                amount: swap.quote.from.amount,
+                detected_at: now,
+                confirmations: 1,
+                last_checked: now,
+            });
+            swap.mm_deposit_status = Some(MMDepositStatus {
+                tx_hash: TxHash::parse(ChainType::Ethereum, &hex::encode(salt.map(|b| !b)))
+                    .unwrap(),
+                amount: swap.quote.to.amount,
+                detected_at: now,
+                confirmations: 1,
+                last_checked: now,
+            });
+        }
+        swap
+    }
+
+    fn service(
+        db: &Database,
+        bitcoin: StubChain,
+        ethereum: StubChain,
+    ) -> SettlementReconciliationService {
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, Arc::new(bitcoin));
+        chain_registry.register(ChainType::Ethereum, Arc::new(ethereum));
+        SettlementReconciliationService::new(
+            db.clone(),
+            Arc::new(chain_registry),
+            Duration::from_secs(7 * 24 * 60 * 60),
+            100,
+            Arc::new(SystemClock),
+        )
+    }
+
+    #[sqlx::test]
+    async fn test_inconsistent_swaps_produce_findings(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let consistent = terminal_swap([1; 32], SwapStatus::Settled);
+        // Its MM deposit was reorged out after the swap settled
+        let reorged = terminal_swap([2; 32], SwapStatus::Settled);
+        // Funds arrived after the swap gave up waiting for them
+        let failed = terminal_swap([3; 32], SwapStatus::Failed);
+        for swap in [&consistent, &reorged, &failed] {
+            db.swaps().create(swap).await.unwrap();
+        }
+
+        let deposit_tx = |swap: &Swap| swap.user_deposit_status.as_ref().unwrap().tx_hash.clone();
+        let mm_tx = |swap: &Swap| swap.mm_deposit_status.as_ref().unwrap().tx_hash.clone();
+        let included = TxStatus::Included {
+            confirmations: 10,
+            finality: Finality::Unknown,
+        };
+        let bitcoin = || {
+            StubChain::new()
+                .with_tx_status(deposit_tx(&consistent).as_str(), included)
+                .with_tx_status(deposit_tx(&reorged).as_str(), included)
+                .with_balance(failed.user_deposit_address.clone(), U256::from(5_000))
+        };
+        let ethereum = StubChain::new().with_tx_status(mm_tx(&consistent).as_str(), included);
+
+        let run = service(&db, bitcoin(), ethereum).reconcile().await.unwrap();
+        assert_eq!(
+            run,
+            ReconciliationRun {
+                checked: 3,
+                skipped: 0,
+                opened: 2
+            }
+        );
+
+        let findings = db.reconciliation_findings().get_open().await.unwrap();
+        let mut seen: Vec<_> = findings
+            .iter()
+            .map(|f| (f.swap_id, f.kind, f.leg, f.chain))
+            .collect();
+        seen.sort();
+        let mut expected = vec![
+            (
+                reorged.id,
+                FindingKind::TxMissing,
+                DepositLeg::MmDeposit,
+                ChainType::Ethereum,
+            ),
+            (
+                failed.id,
+                FindingKind::UnexpectedBalance,
+                DepositLeg::UserDeposit,
+                ChainType::Bitcoin,
+            ),
+        ];
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        // Seen again, still the same open findings
+        let ethereum = StubChain::new().with_tx_status(mm_tx(&consistent).as_str(), included);
+        let run = service(&db, bitcoin(), ethereum).reconcile().await.unwrap();
+        assert_eq!(run.opened, 0);
+        assert_eq!(
+            db.reconciliation_findings().get_open().await.unwrap().len(),
+            2
+        );
+
+        // The MM deposit is back in a block but moved less than recorded
+        let ethereum = StubChain::new()
+            .with_tx_status(mm_tx(&consistent).as_str(), included)
+            .with_tx_status(mm_tx(&reorged).as_str(), included)
+            .with_transfer(
+                reorged.user_destination_address.clone(),
+                TransferInfo {
+                    tx_hash: mm_tx(&reorged),
+                    amount: U256::from(1),
+                    detected_at: Utc::now(),
+                    confirmations: 10,
+                },
+            );
+        let run = service(&db, bitcoin(), ethereum).reconcile().await.unwrap();
+        assert_eq!(run.opened, 1);
+        let findings = db.reconciliation_findings().get_open().await.unwrap();
+        let reorged_findings: Vec<_> = findings
+            .iter()
+            .filter(|f| f.swap_id == reorged.id)
+            .map(|f| f.kind)
+            .collect();
+        assert_eq!(reorged_findings, vec![FindingKind::AmountDiffers]);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_lookback_past_the_earliest_time_checks_everything(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let swap = terminal_swap([4; 32], SwapStatus::Failed);
+        db.swaps().create(&swap).await.unwrap();
+
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, Arc::new(StubChain::new()));
+        chain_registry.register(ChainType::Ethereum, Arc::new(StubChain::new()));
+        let service = SettlementReconciliationService::new(
+            db.clone(),
+            Arc::new(chain_registry),
+            Duration::MAX,
+            100,
+            Arc::new(SystemClock),
+        );
+
+        let run = service.reconcile().await.unwrap();
+        assert_eq!(run.checked, 1);
+
+        Ok(())
+    }
+}
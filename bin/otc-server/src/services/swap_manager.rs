@@ -1,23 +1,53 @@
-use crate::api::swaps::{CreateSwapRequest, CreateSwapResponse, DepositInfoResponse, SwapResponse};
-use crate::config::Settings;
-use crate::db::Database;
+use crate::api::swaps::{
+    refund_address_message, CreateSwapRequest, CreateSwapResponse, DepositInfoResponse,
+    DepositProgress, SetRefundAddressRequest, SettlementProgress, SwapLookup, SwapResponse,
+};
+use crate::api::{ReceiptDeposit, ReceiptFees, ReceiptTransition, SwapReceipt};
+use crate::config::{Settings, SettingsError};
+use crate::db::{Database, IdempotencyClaim, MMDepositAttempts, ValidationOutcome};
 use crate::error::OtcServerError;
-use crate::services::MMRegistry;
+use crate::services::confirmation_policy::ConfirmationPolicyError;
+use crate::services::quote_price_check::{
+    QuotePriceVerdict, MAX_REFERENCE_QUOTES, MIN_REFERENCE_QUOTES, REFERENCE_WINDOW,
+};
+use crate::services::swap_monitoring::{refund_user, MMDepositRetryPolicy, TransferWatchRequests};
+use crate::services::{
+    ConfirmationPolicy, DepositSaltSource, MMRegistry, MmNonceSource, OsRandomNonces,
+    OsRandomSalts, QuotePriceCheck,
+};
 use alloy::hex::FromHexError;
-use alloy::primitives::Address;
-use chrono::Utc;
-use otc_chains::ChainRegistry;
-use otc_models::{Swap, SwapStatus, TokenIdentifier};
+use alloy::primitives::{keccak256, Address, Signature, U256};
+use blockchain_utils::{FeeCalcFromLot, PROTOCOL_FEE_BPS};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use common::Clock;
+use otc_api_types::QuoteLock;
+use otc_chains::{dust, ChainRegistry};
+use otc_models::{
+    external_reference_for_log, seconds_until, FillCost, Quote, SupportedCurrencies, Swap,
+    SwapStatus, TokenIdentifier, TxHash, UnsupportedCurrency,
+};
+use otc_protocols::{
+    capabilities::QuoteSigningMode,
+    mm::SwapFailureReason,
+    rfq::{QuoteSignatureError, QuoteSigner},
+};
 use snafu::prelude::*;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::oneshot;
 use tokio::time::{timeout, Duration};
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 const MARKET_MAKER_VALIDATION_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// How long an Idempotency-Key keeps replaying the swap it created
+pub const IDEMPOTENCY_KEY_TTL: ChronoDuration = ChronoDuration::hours(24);
+
+/// Fresh salts and nonces tried when a derived deposit address or an MM nonce
+/// turns out to be taken before the swap is rejected
+const SWAP_INSERT_ATTEMPTS: u32 = 3;
+
 #[derive(Debug, Snafu)]
 pub enum SwapError {
     #[snafu(display("Quote not found: {}", quote_id))]
@@ -26,6 +56,50 @@ pub enum SwapError {
     #[snafu(display("Quote has expired"))]
     QuoteExpired,
 
+    #[snafu(display(
+        "Quote expires in {}s, swaps need {}s of validity left after market maker validation",
+        expires_in_seconds,
+        min_validity_seconds
+    ))]
+    QuoteExpiring {
+        expires_in_seconds: u64,
+        min_validity_seconds: u64,
+    },
+
+    #[snafu(display("Quote signature invalid: {}", source))]
+    QuoteSignatureInvalid { source: QuoteSignatureError },
+
+    #[snafu(display("Quote lock invalid: {}", source))]
+    QuoteLockInvalid { source: QuoteSignatureError },
+
+    #[snafu(display(
+        "Quote price is stale: {:.0} bps worse than recent quotes (at most {} allowed)",
+        shortfall_bps,
+        max_deviation_bps
+    ))]
+    QuoteStalePrice {
+        shortfall_bps: f64,
+        max_deviation_bps: u64,
+    },
+
+    #[snafu(display("Unsupported currency: {}", source))]
+    UnsupportedToken { source: UnsupportedCurrency },
+
+    #[snafu(display("Invalid destination address: {}", source))]
+    InvalidDestinationAddress { source: otc_chains::Error },
+
+    #[snafu(display(
+        "Payout of {} is below the dust threshold of {} for destination {}",
+        amount,
+        threshold,
+        address
+    ))]
+    OutputBelowDust {
+        amount: U256,
+        threshold: u64,
+        address: String,
+    },
+
     #[snafu(display("Market maker rejected the quote"))]
     MarketMakerRejected,
 
@@ -44,8 +118,59 @@ pub enum SwapError {
     #[snafu(display("Failed to derive wallet: {}", source))]
     WalletDerivation { source: otc_chains::Error },
 
+    #[snafu(display("Master key unavailable: {}", source))]
+    MasterKeyUnavailable { source: SettingsError },
+
     #[snafu(display("Invalid EVM account address: {}", source))]
     InvalidEvmAccountAddress { source: FromHexError },
+
+    #[snafu(display("Failed to resolve required confirmations: {}", source))]
+    ConfirmationPolicy { source: ConfirmationPolicyError },
+
+    #[snafu(display("Swap {} is not settled (status: {:?})", swap_id, status))]
+    SwapNotSettled { swap_id: Uuid, status: SwapStatus },
+
+    #[snafu(display(
+        "Swap {} can't be cancelled once the market maker has paid (status: {:?})",
+        swap_id,
+        status
+    ))]
+    SwapNotCancellable { swap_id: Uuid, status: SwapStatus },
+
+    #[snafu(display(
+        "Swap {} is waiting on the market maker's deposit, it can be cancelled once the market maker agrees or after {}",
+        swap_id,
+        deadline
+    ))]
+    MarketMakerMayStillPay {
+        swap_id: Uuid,
+        deadline: DateTime<Utc>,
+    },
+
+    #[snafu(display("Idempotency key was already used with a different request"))]
+    IdempotencyKeyReused,
+
+    #[snafu(display("A request with this idempotency key is still being processed"))]
+    IdempotentRequestInProgress,
+
+    #[snafu(display("Failed to serialize idempotent swap request or response: {}", source))]
+    IdempotencySerialization { source: serde_json::Error },
+
+    #[snafu(display("Invalid refund address {} on {:?}", address, chain))]
+    InvalidRefundAddress {
+        address: String,
+        chain: otc_models::ChainType,
+    },
+
+    #[snafu(display(
+        "Refund address of swap {} can't change anymore (status: {:?})",
+        swap_id,
+        status
+    ))]
+    RefundAddressLocked { swap_id: Uuid, status: SwapStatus },
+
+    #[snafu(display("Refund address must be signed by the swap's user EVM account"))]
+    RefundAddressSignatureInvalid,
 }
 
 impl From<OtcServerError> for SwapError {
@@ -59,6 +184,14 @@ impl From<OtcServerError> for SwapError {
     }
 }
 
+impl SwapError {
+    /// Whether the database was unreachable, see [`OtcServerError::is_transient`]
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SwapError::Database { source } if source.is_transient())
+    }
+}
+
 pub type SwapResult<T> = Result<T, SwapError>;
 
 /// Manages the swap lifecycle from creation to settlement
@@ -67,6 +200,26 @@ pub struct SwapManager {
     settings: Arc<Settings>,
     chain_registry: Arc<ChainRegistry>,
     mm_registry: Arc<MMRegistry>,
+    /// `None` when quote signatures are off
+    quote_signer: Option<Arc<QuoteSigner>>,
+    quote_signing_mode: QuoteSigningMode,
+    confirmation_policy: Arc<ConfirmationPolicy>,
+    supported_currencies: Arc<SupportedCurrencies>,
+    /// `None` when the price check is skipped
+    quote_price_check: Option<QuotePriceCheck>,
+    /// Validity a quote must have left after the market maker validated it
+    min_quote_validity: Duration,
+    /// Network bitcoin destination addresses must be on
+    bitcoin_network: bitcoin::Network,
+    /// What quote, lock and idempotency key expiries are checked against
+    clock: Arc<dyn Clock>,
+    /// `None` where swaps aren't monitored, their deposits are left to whoever does
+    transfer_watch: Option<TransferWatchRequests>,
+    /// How long the MM has to pay after it was last asked to, before an
+    /// operator may cancel without its agreement
+    mm_deposit_grace: Duration,
+    deposit_salts: Arc<dyn DepositSaltSource>,
+    mm_nonces: Arc<dyn MmNonceSource>,
 }
 
 impl SwapManager {
@@ -76,29 +229,105 @@ impl SwapManager {
         settings: Arc<Settings>,
         chain_registry: Arc<ChainRegistry>,
         mm_registry: Arc<MMRegistry>,
+        quote_signer: Option<Arc<QuoteSigner>>,
+        quote_signing_mode: QuoteSigningMode,
+        confirmation_policy: Arc<ConfirmationPolicy>,
+        supported_currencies: Arc<SupportedCurrencies>,
+        quote_price_check: Option<QuotePriceCheck>,
+        min_quote_validity: Duration,
+        bitcoin_network: bitcoin::Network,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         Self {
             db,
             settings,
             chain_registry,
             mm_registry,
+            quote_signer,
+            quote_signing_mode,
+            confirmation_policy,
+            supported_currencies,
+            quote_price_check,
+            min_quote_validity,
+            bitcoin_network,
+            clock,
+            transfer_watch: None,
+            mm_deposit_grace: MMDepositRetryPolicy::default().grace,
+            deposit_salts: Arc::new(OsRandomSalts),
+            mm_nonces: Arc::new(OsRandomNonces),
         }
     }
 
+    /// Have the deposits of new swaps subscribed to through `requests`
+    #[must_use]
+    pub fn with_transfer_watch(mut self, requests: TransferWatchRequests) -> Self {
+        self.transfer_watch = Some(requests);
+        self
+    }
+
+    /// Give the MM `grace` to pay before a cancel no longer needs its agreement
+    #[must_use]
+    pub fn with_mm_deposit_grace(mut self, grace: Duration) -> Self {
+        self.mm_deposit_grace = grace;
+        self
+    }
+
+    /// Draw user deposit salts from `salts` instead of the OS random number generator
+    #[must_use]
+    pub fn with_deposit_salts(mut self, salts: Arc<dyn DepositSaltSource>) -> Self {
+        self.deposit_salts = salts;
+        self
+    }
+
+    /// Draw MM nonces from `nonces` instead of the OS random number generator
+    #[must_use]
+    pub fn with_mm_nonces(mut self, nonces: Arc<dyn MmNonceSource>) -> Self {
+        self.mm_nonces = nonces;
+        self
+    }
+
     /// Create a new swap from a quote
     ///
     /// This will:
-    /// 1. Validate the quote hasn't expired
+    /// 0. Verify the quote was signed by the RFQ server and not modified (per the signing mode)
+    /// 1. Validate the quote hasn't expired, only uses configured tokens, its
+    ///    price isn't worse for the user than recent quotes and its payout
+    ///    isn't dust at the user's destination
     /// 2. Validate the market maker matches
-    /// 3. Ask the market maker if they'll fill the quote (TODO)
+    /// 3. Ask the market maker if they'll fill the quote, unless it locked the quote already
     /// 4. Generate salts for deterministic wallet derivation
-    /// 5. Create the swap record in the database
-    /// 6. Return the deposit details to the user
-    pub async fn create_swap(&self, request: CreateSwapRequest) -> SwapResult<CreateSwapResponse> {
+    /// 5. Resolve the confirmations each deposit needs (baseline or active override)
+    /// 6. Create the swap record in the database, with a fresh salt or nonce
+    ///    if its deposit address or MM nonce is somehow taken
+    /// 7. Return the deposit details to the user
+    ///
+    /// `trace_id` is stored on the swap and sent along with every message about it
+    pub async fn create_swap(
+        &self,
+        request: CreateSwapRequest,
+        trace_id: &str,
+    ) -> SwapResult<CreateSwapResponse> {
         let quote = request.quote;
-        // 1. Check if quote has expired
-        if quote.expires_at < Utc::now() {
-            return Err(SwapError::QuoteExpired);
+        // 0. Verify the quote signature before trusting any of its fields
+        self.verify_quote_signature(&quote, request.quote_signature.as_deref())?;
+
+        // 1. Check the quote won't expire before the swap is created
+        check_quote_validity(&quote, self.clock.now(), self.min_quote_validity)?;
+        // The deposit is bounded by the configured limits, the payout only has to be a known token
+        self.supported_currencies
+            .check_lot(&quote.from)
+            .context(UnsupportedTokenSnafu)?;
+        self.supported_currencies
+            .check_currency(&quote.to.currency)
+            .context(UnsupportedTokenSnafu)?;
+        self.check_quote_price(&quote).await?;
+        check_payout_above_dust(
+            &quote,
+            &request.user_destination_address,
+            self.bitcoin_network,
+        )?;
+        if let Some(refund_address) = &request.user_refund_address {
+            self.check_refund_address(&quote, refund_address)?;
         }
 
         // 2. Ask market maker if they'll fill this quote
@@ -118,101 +347,122 @@ impl SwapManager {
             });
         }
 
-        // 3. Send validation request with timeout
-        let (response_tx, response_rx) = oneshot::channel();
-        self.mm_registry
-            .validate_quote(
-                &quote.market_maker_id,
-                &quote.id,
-                &quote.hash(),
-                &request.user_destination_address,
-                response_tx,
-            )
-            .await;
-
-        // Wait for response with timeout
-        let validation_result = match timeout(MARKET_MAKER_VALIDATION_TIMEOUT, response_rx).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(_)) => {
-                warn!("Failed to receive validation response from market maker");
-                return Err(SwapError::MarketMakerValidationTimeout);
-            }
-            Err(_) => {
-                warn!("Market maker validation timed out after 5 seconds");
-                return Err(SwapError::MarketMakerValidationTimeout);
-            }
-        };
-
-        // Handle the validation result
-        match validation_result {
-            Ok(accepted) => {
-                if !accepted {
-                    info!("Market maker rejected quote {}", quote.id);
-                    return Err(SwapError::MarketMakerRejected);
-                }
-                info!("Market maker accepted quote {}", quote.id);
-            }
-            Err(e) => {
-                warn!("Market maker validation error: {:?}", e);
-                return Err(SwapError::MarketMakerValidationTimeout);
-            }
+        // 3. A lock the market maker granted through the RFQ server already
+        // commits it to the quote, otherwise ask it now
+        if self.check_quote_lock(&quote, request.quote_lock.as_ref())? {
+            info!(
+                "Quote {} is locked by market maker {}, skipping validation",
+                quote.id, quote.market_maker_id
+            );
+        } else {
+            self.validate_with_market_maker(&quote, &request.user_destination_address, trace_id)
+                .await?;
         }
 
-        // 5. Generate random salts for wallet derivation
+        // 5. Salts and MM nonces are drawn when the swap is stored
         let swap_id = Uuid::new_v4();
-        let mut user_deposit_salt = [0u8; 32];
-        let mut mm_nonce = [0u8; 16]; // 128 bits of collision resistance against an existing tx w/ a given output address && amount
-        getrandom::getrandom(&mut user_deposit_salt).expect("Failed to generate random salt");
-        getrandom::getrandom(&mut mm_nonce).expect("Failed to generate random nonce");
         // 7. Derive user deposit address for response
-        let user_chain = self.chain_registry.get(&quote.from.currency.chain).ok_or(
-            SwapError::ChainNotSupported {
+        let user_chain = self
+            .chain_registry
+            .for_currency(&quote.from.currency)
+            .ok_or(SwapError::ChainNotSupported {
                 chain: quote.from.currency.chain,
-            },
-        )?;
+            })?;
+
+        // Pin the master key version so a later rotation never moves this deposit address
+        let master_key_version = self.settings.current_master_key_version();
+        let master_key = self
+            .settings
+            .master_key_bytes(master_key_version)
+            .context(MasterKeyUnavailableSnafu)?;
 
-        let user_deposit_address = &user_chain
-            .derive_wallet(&self.settings.master_key_bytes(), &user_deposit_salt)
-            .map_err(|e| SwapError::WalletDerivation { source: e })?
-            .address;
+        // Pin the confirmation requirements now so later overrides never change this swap
+        let user_confirmations = self
+            .confirmation_policy
+            .resolve(quote.from.currency.chain)
+            .await
+            .context(ConfirmationPolicySnafu)?;
+        let mm_confirmations = self
+            .confirmation_policy
+            .resolve(quote.to.currency.chain)
+            .await
+            .context(ConfirmationPolicySnafu)?;
 
         // 6. Create swap record
-        let now = Utc::now();
-        let swap = Swap {
+        let now = self.clock.now();
+        let mut swap = Swap {
             id: swap_id,
             quote: quote.clone(),
             market_maker_id: quote.market_maker_id,
-            user_deposit_salt,
-            user_deposit_address: user_deposit_address.clone(),
-            mm_nonce,
+            // Drawn below, as is the MM nonce
+            user_deposit_salt: [0u8; 32],
+            user_deposit_address: String::new(),
+            master_key_version,
+            mm_nonce: [0u8; 16],
             user_destination_address: request.user_destination_address,
             user_evm_account_address: request.user_evm_account_address,
+            user_refund_address: request.user_refund_address,
             status: SwapStatus::WaitingUserDepositInitiated,
+            user_required_confirmations: user_confirmations.required,
+            mm_required_confirmations: mm_confirmations.required,
             user_deposit_status: None,
             mm_deposit_status: None,
             settlement_status: None,
             failure_reason: None,
             failure_at: None,
             mm_notified_at: None,
+            mm_deposit_detected_at: None,
             mm_private_key_sent_at: None,
+            trace_id: Some(trace_id.to_string()),
+            external_reference: request.external_reference,
             created_at: now,
             updated_at: now,
         };
 
         // Save swap to database
-        self.db.swaps().create(&swap).await.context(DatabaseSnafu)?;
+        insert_with_unused_salt_and_nonce(
+            &self.db,
+            self.deposit_salts.as_ref(),
+            self.mm_nonces.as_ref(),
+            &mut swap,
+            |salt| {
+                user_chain
+                    .derive_wallet(&master_key, salt)
+                    .map(|wallet| wallet.address)
+                    .map_err(|e| SwapError::WalletDerivation { source: e })
+            },
+        )
+        .await?;
 
-        info!("Created swap {} for quote {}", swap_id, quote.id);
+        // The nonce is what ties the MM's fill to this swap, logged so a
+        // disputed fill can be traced back
+        info!(
+            "Created swap {} for quote {} with MM nonce {}",
+            swap_id,
+            quote.id,
+            alloy::hex::encode(swap.mm_nonce)
+        );
+        if let Some(reference) = &swap.external_reference {
+            info!(
+                "Swap {} has external reference {}",
+                swap_id,
+                external_reference_for_log(reference)
+            );
+        }
+        if let Some(transfer_watch) = &self.transfer_watch {
+            transfer_watch.watch(swap_id);
+        }
 
         // 7. Derive user deposit address for response
-        let user_chain = self.chain_registry.get(&quote.from.currency.chain).ok_or(
-            SwapError::ChainNotSupported {
+        let user_chain = self
+            .chain_registry
+            .for_currency(&quote.from.currency)
+            .ok_or(SwapError::ChainNotSupported {
                 chain: quote.from.currency.chain,
-            },
-        )?;
+            })?;
 
         let user_wallet = user_chain
-            .derive_wallet(&self.settings.master_key_bytes(), &user_deposit_salt)
+            .derive_wallet(&master_key, &swap.user_deposit_salt)
             .map_err(|e| SwapError::WalletDerivation { source: e })?;
 
         // 8. Return response
@@ -226,63 +476,1089 @@ impl SwapManager {
                 TokenIdentifier::Native => "Native".to_string(),
                 TokenIdentifier::Address(addr) => addr.clone(),
             },
+            required_confirmations: user_confirmations.required,
+            estimated_confirmation_seconds: user_confirmations
+                .estimated_confirmation_time()
+                .as_secs(),
             expires_at: quote.expires_at,
+            expires_in_seconds: quote.expires_in_seconds(self.clock.now()),
             status: "waiting_user_deposit".to_string(),
+            payment_uri: user_chain.payment_uri(&user_wallet.address, &quote.from),
+            qr_svg: None,
         })
     }
 
+    /// Reject a refund address that isn't valid on the chain the user deposits on
+    fn check_refund_address(&self, quote: &Quote, address: &str) -> SwapResult<()> {
+        let chain = quote.from.currency.chain;
+        let operations = self
+            .chain_registry
+            .for_currency(&quote.from.currency)
+            .context(ChainNotSupportedSnafu { chain })?;
+        ensure!(
+            operations.validate_address(address),
+            InvalidRefundAddressSnafu { address, chain }
+        );
+        Ok(())
+    }
+
+    /// Reject `quote` if the market moved against the user since it was issued
+    async fn check_quote_price(&self, quote: &Quote) -> SwapResult<()> {
+        let Some(check) = self.quote_price_check else {
+            return Ok(());
+        };
+        let issued_after = quote.created_at.max(self.clock.now() - REFERENCE_WINDOW);
+        let reference = self
+            .db
+            .quotes()
+            .get_recent_for_pair(
+                &quote.from.currency,
+                &quote.to.currency,
+                issued_after,
+                MAX_REFERENCE_QUOTES,
+            )
+            .await
+            .context(DatabaseSnafu)?;
+
+        match check.verdict(quote, &reference) {
+            QuotePriceVerdict::Fresh => Ok(()),
+            QuotePriceVerdict::Stale { shortfall_bps } => {
+                warn!(
+                    "Rejecting quote {}: {:.0} bps worse for the user than recent quotes",
+                    quote.id, shortfall_bps
+                );
+                Err(SwapError::QuoteStalePrice {
+                    shortfall_bps,
+                    max_deviation_bps: check.max_deviation_bps,
+                })
+            }
+            QuotePriceVerdict::Unchecked { reference_quotes } => {
+                info!(
+                    "Not checking the price of quote {}: {} swapped quotes of a similar size in the last {} minutes, {} needed",
+                    quote.id,
+                    reference_quotes,
+                    REFERENCE_WINDOW.num_minutes(),
+                    MIN_REFERENCE_QUOTES
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Create a swap at most once per idempotency key
+    ///
+    /// The first request claims the key and creates the swap. Retries with an
+    /// identical body get the original response back, retries with a different
+    /// body are rejected, and retries that arrive while the first request is
+    /// still running are told to try again later. A failed request releases
+    /// the key so it can be retried. If the swap was created but its response
+    /// can't be stored, the key is released too and the swap still returned,
+    /// rather than leave the key pending until it expires.
+    pub async fn create_swap_idempotent(
+        &self,
+        idempotency_key: &str,
+        request: CreateSwapRequest,
+        trace_id: &str,
+    ) -> SwapResult<CreateSwapResponse> {
+        let request_hash =
+            keccak256(serde_json::to_vec(&request).context(IdempotencySerializationSnafu)?);
+        let idempotency_keys = self.db.idempotency_keys();
+        let now = self.clock.now();
+
+        match idempotency_keys
+            .claim(
+                idempotency_key,
+                request_hash.as_slice(),
+                now,
+                now + IDEMPOTENCY_KEY_TTL,
+            )
+            .await
+            .context(DatabaseSnafu)?
+        {
+            IdempotencyClaim::Claimed => {}
+            IdempotencyClaim::Existing(record) => {
+                ensure!(
+                    record.request_hash == request_hash.as_slice(),
+                    IdempotencyKeyReusedSnafu
+                );
+                let response = record.response.context(IdempotentRequestInProgressSnafu)?;
+                info!(
+                    "Replaying swap response for idempotency key {}",
+                    idempotency_key
+                );
+                let mut response: CreateSwapResponse =
+                    serde_json::from_value(response).context(IdempotencySerializationSnafu)?;
+                response.expires_in_seconds = seconds_until(response.expires_at, self.clock.now());
+                return Ok(response);
+            }
+        }
+
+        let response = match self.create_swap(request, trace_id).await {
+            Ok(response) => response,
+            Err(e) => {
+                if let Err(release_error) = idempotency_keys.release(idempotency_key).await {
+                    warn!(
+                        "Failed to release idempotency key {}: {}",
+                        idempotency_key, release_error
+                    );
+                }
+                return Err(e);
+            }
+        };
+
+        let completed = match serde_json::to_value(&response) {
+            Ok(stored) => idempotency_keys
+                .complete(idempotency_key, response.swap_id, &stored)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = completed {
+            error!(
+                "Failed to store the response of swap {} under idempotency key {}: {}",
+                response.swap_id, idempotency_key, e
+            );
+            if let Err(release_error) = idempotency_keys.release(idempotency_key).await {
+                warn!(
+                    "Failed to release idempotency key {}: {}",
+                    idempotency_key, release_error
+                );
+            }
+        }
+        Ok(response)
+    }
+
+    /// Whether `lock` is an unexpired lock the RFQ server signed for `quote`.
+    /// An expired lock falls back to validating with the market maker, one that
+    /// doesn't verify is rejected
+    fn check_quote_lock(&self, quote: &Quote, lock: Option<&QuoteLock>) -> SwapResult<bool> {
+        let (Some(lock), Some(quote_signer)) = (lock, self.quote_signer.as_ref()) else {
+            return Ok(false);
+        };
+        quote_signer
+            .verify_lock(quote, lock.expires_at, &lock.token)
+            .inspect_err(|e| warn!("Rejecting lock on quote {}: {}", quote.id, e))
+            .context(QuoteLockInvalidSnafu)?;
+        Ok(lock.expires_at > self.clock.now())
+    }
+
+    /// Ask the market maker whether it'll fill `quote`, recording its answer
+    async fn validate_with_market_maker(
+        &self,
+        quote: &Quote,
+        user_destination_address: &str,
+        trace_id: &str,
+    ) -> SwapResult<()> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.mm_registry
+            .validate_quote(
+                &quote.market_maker_id,
+                &quote.id,
+                &quote.hash(),
+                user_destination_address,
+                trace_id,
+                response_tx,
+            )
+            .await;
+
+        // Wait for response with timeout
+        let validation_result = match timeout(MARKET_MAKER_VALIDATION_TIMEOUT, response_rx).await {
+            Ok(Ok(result)) => Some(result),
+            Ok(Err(_)) => {
+                warn!("Failed to receive validation response from market maker");
+                None
+            }
+            Err(_) => {
+                warn!("Market maker validation timed out after 5 seconds");
+                None
+            }
+        };
+
+        let outcome = match &validation_result {
+            Some(Ok(true)) => ValidationOutcome::Accepted,
+            Some(Ok(false)) => ValidationOutcome::Rejected,
+            Some(Err(_)) | None => ValidationOutcome::Unanswered,
+        };
+        if let Err(e) = self
+            .db
+            .market_maker_stats()
+            .record_validation(quote.market_maker_id, quote.id, outcome)
+            .await
+        {
+            error!("Failed to record validation of quote {}: {}", quote.id, e);
+        }
+
+        // Handle the validation result
+        match validation_result {
+            Some(Ok(true)) => {
+                info!("Market maker accepted quote {}", quote.id);
+                Ok(())
+            }
+            Some(Ok(false)) => {
+                info!("Market maker rejected quote {}", quote.id);
+                Err(SwapError::MarketMakerRejected)
+            }
+            Some(Err(e)) => {
+                warn!("Market maker validation error: {:?}", e);
+                Err(SwapError::MarketMakerValidationTimeout)
+            }
+            None => Err(SwapError::MarketMakerValidationTimeout),
+        }
+    }
+
+    /// Off skips the check, optional only checks signatures that were sent
+    fn verify_quote_signature(&self, quote: &Quote, signature: Option<&str>) -> SwapResult<()> {
+        let Some(quote_signer) = self.quote_signer.as_ref() else {
+            return Ok(());
+        };
+        let signature = match signature {
+            Some(signature) => signature,
+            None if self.quote_signing_mode == QuoteSigningMode::Optional => return Ok(()),
+            None => {
+                return Err(SwapError::QuoteSignatureInvalid {
+                    source: QuoteSignatureError::MissingSignature,
+                })
+            }
+        };
+        quote_signer
+            .verify(quote, signature)
+            .inspect_err(|e| warn!("Rejecting quote {}: {}", quote.id, e))
+            .context(QuoteSignatureInvalidSnafu)
+    }
+
     /// Get swap details by ID with derived wallet addresses
     pub async fn get_swap(&self, swap_id: Uuid) -> SwapResult<SwapResponse> {
         // Get swap from database
         let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        Ok(swap_response(&swap))
+    }
 
-        // Derive wallet addresses
-        let master_key = self.settings.master_key_bytes();
+    /// Swaps matching a deposit address or tx hash, newest first. No match is
+    /// an empty list.
+    pub async fn lookup_swaps(&self, lookup: &SwapLookup) -> SwapResult<Vec<SwapResponse>> {
+        let swaps = match lookup {
+            SwapLookup::DepositAddress(address) => {
+                self.db.swaps().find_by_deposit_address(address).await
+            }
+            SwapLookup::TxHash(tx_hash) => self.db.swaps().find_by_tx_hash(tx_hash).await,
+        }
+        .context(DatabaseSnafu)?;
+        Ok(swaps.iter().map(swap_response).collect())
+    }
 
-        let user_chain = self
-            .chain_registry
-            .get(&swap.quote.from.currency.chain)
-            .ok_or(SwapError::ChainNotSupported {
-                chain: swap.quote.from.currency.chain,
-            })?;
+    /// Swaps tagged with `reference`, newest first. References are guessable,
+    /// so this is only served to the operator
+    pub async fn swaps_by_external_reference(
+        &self,
+        reference: &str,
+    ) -> SwapResult<Vec<SwapResponse>> {
+        let swaps = self
+            .db
+            .swaps()
+            .find_by_external_reference(reference)
+            .await
+            .context(DatabaseSnafu)?;
+        Ok(swaps.iter().map(swap_response).collect())
+    }
 
-        let user_wallet = user_chain
-            .derive_wallet(&master_key, &swap.user_deposit_salt)
-            .map_err(|e| SwapError::WalletDerivation { source: e })?;
+    /// Assemble the receipt of a settled swap
+    pub async fn get_swap_receipt(&self, swap_id: Uuid) -> SwapResult<SwapReceipt> {
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        ensure!(
+            swap.status == SwapStatus::Settled,
+            SwapNotSettledSnafu {
+                swap_id,
+                status: swap.status
+            }
+        );
 
-        // Build response
-        Ok(SwapResponse {
-            id: swap.id,
+        let events = self
+            .db
+            .swap_events()
+            .get_for_swap(swap_id)
+            .await
+            .context(DatabaseSnafu)?;
+        let settled_at = events
+            .iter()
+            .rev()
+            .find(|e| e.to_status == SwapStatus::Settled)
+            .map(|e| e.occurred_at)
+            .ok_or_else(|| missing_record(swap_id, "settlement event"))?;
+
+        let user_deposit = swap
+            .user_deposit_status
+            .as_ref()
+            .ok_or_else(|| missing_record(swap_id, "user deposit"))?;
+        let mm_deposit = swap
+            .mm_deposit_status
+            .as_ref()
+            .ok_or_else(|| missing_record(swap_id, "market maker deposit"))?;
+        let mm_claimed = self
+            .db
+            .swaps()
+            .mm_claimed_deposit(swap_id)
+            .await
+            .context(DatabaseSnafu)?;
+
+        Ok(SwapReceipt {
+            swap_id: swap.id,
             quote_id: swap.quote.id,
-            status: format!("{:?}", swap.status),
-            created_at: swap.created_at,
-            updated_at: swap.updated_at,
-            user_deposit: DepositInfoResponse {
-                address: user_wallet.address.clone(),
-                chain: format!("{:?}", swap.quote.from.currency.chain),
-                expected_amount: swap.quote.from.amount,
+            market_maker_id: swap.market_maker_id,
+            status: swap.status,
+            user_deposit_address: swap.user_deposit_address.clone(),
+            user_destination_address: swap.user_destination_address.clone(),
+            user_deposit: ReceiptDeposit {
+                chain: swap.quote.from.currency.chain,
+                token: swap.quote.from.currency.token.clone(),
                 decimals: swap.quote.from.currency.decimals,
-                token: match &swap.quote.from.currency.token {
-                    TokenIdentifier::Native => "Native".to_string(),
-                    TokenIdentifier::Address(addr) => addr.clone(),
-                },
-                deposit_tx: swap.user_deposit_status.as_ref().map(|d| d.tx_hash.clone()),
-                deposit_amount: swap.user_deposit_status.as_ref().map(|d| d.amount),
-                deposit_detected_at: swap.user_deposit_status.as_ref().map(|d| d.detected_at),
+                expected_amount: swap.quote.from.amount,
+                tx_hash: user_deposit.tx_hash.to_string(),
+                amount: user_deposit.amount,
+                detected_at: user_deposit.detected_at,
+                confirmations: user_deposit.confirmations,
+                required_confirmations: swap.user_required_confirmations,
             },
-            mm_deposit: DepositInfoResponse {
-                address: swap.user_destination_address.clone(),
-                chain: format!("{:?}", swap.quote.to.currency.chain),
-                expected_amount: swap.quote.to.amount,
+            mm_deposit: ReceiptDeposit {
+                chain: swap.quote.to.currency.chain,
+                token: swap.quote.to.currency.token.clone(),
                 decimals: swap.quote.to.currency.decimals,
-                token: match &swap.quote.to.currency.token {
-                    TokenIdentifier::Native => "Native".to_string(),
-                    TokenIdentifier::Address(addr) => addr.clone(),
-                },
-                deposit_tx: swap.mm_deposit_status.as_ref().map(|d| d.tx_hash.clone()),
-                deposit_amount: swap.mm_deposit_status.as_ref().map(|d| d.amount),
-                deposit_detected_at: swap.mm_deposit_status.as_ref().map(|d| d.detected_at),
+                expected_amount: swap.quote.to.amount,
+                tx_hash: mm_deposit.tx_hash.to_string(),
+                amount: mm_deposit.amount,
+                detected_at: mm_deposit.detected_at,
+                confirmations: mm_deposit.confirmations,
+                required_confirmations: swap.mm_required_confirmations,
             },
+            fees: ReceiptFees {
+                protocol_fee_bps: PROTOCOL_FEE_BPS,
+                protocol_fee: U256::from(swap.quote.to.compute_protocol_fee()),
+                mm_fill_cost: mm_claimed.and_then(|claimed| claimed.fill_cost),
+            },
+            transitions: events
+                .into_iter()
+                .map(|e| ReceiptTransition {
+                    from_status: e.from_status,
+                    to_status: e.to_status,
+                    occurred_at: e.occurred_at,
+                })
+                .collect(),
+            created_at: swap.created_at,
+            quote_expires_at: swap.quote.expires_at,
+            settled_at,
+            mm_private_key_sent_at: swap.mm_private_key_sent_at,
+            external_reference: swap.external_reference,
         })
     }
+
+    /// Set where the user deposit is refunded if the swap fails
+    ///
+    /// Allowed until the market maker is asked to pay, or while a refund waits
+    /// for an address. The request must be signed by the swap's user EVM
+    /// account over [`refund_address_message`].
+    pub async fn set_refund_address(
+        &self,
+        swap_id: Uuid,
+        request: SetRefundAddressRequest,
+    ) -> SwapResult<SwapResponse> {
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        let address = request.user_refund_address.trim();
+        self.check_refund_address(&swap.quote, address)?;
+
+        let signer = Signature::from_str(&request.signature)
+            .and_then(|signature| {
+                signature.recover_address_from_msg(refund_address_message(swap_id, address))
+            })
+            .map_err(|_| SwapError::RefundAddressSignatureInvalid)?;
+        if signer != swap.user_evm_account_address {
+            warn!(
+                "Rejecting refund address of swap {}: signed by {} instead of {}",
+                swap_id, signer, swap.user_evm_account_address
+            );
+            return Err(SwapError::RefundAddressSignatureInvalid);
+        }
+
+        // Check against the loaded swap so a late request gets a clear error
+        // rather than a generic invalid state
+        let mut check = swap.clone();
+        if check.set_user_refund_address(address.to_string()).is_err() {
+            return RefundAddressLockedSnafu {
+                swap_id,
+                status: swap.status,
+            }
+            .fail();
+        }
+
+        let swap = self
+            .db
+            .swaps()
+            .set_user_refund_address(swap_id, address)
+            .await
+            .context(DatabaseSnafu)?;
+        info!("Set refund address of swap {} to {}", swap_id, address);
+        Ok(swap_response(&swap))
+    }
+
+    /// Cancel a swap the market maker hasn't paid into yet: one without a user
+    /// deposit just fails, one with a deposit refunds the user. The MM is told
+    /// either way so it can release the quote. Once the MM was asked to pay,
+    /// it has to agree to the cancel unless its deadline has passed, so the
+    /// user isn't both refunded and paid.
+    pub async fn cancel_swap(&self, swap_id: Uuid, reason: &str) -> SwapResult<SwapResponse> {
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        let reason = format!("Cancelled by operator: {reason}");
+        match swap.status {
+            SwapStatus::WaitingUserDepositInitiated => {
+                self.db
+                    .swaps()
+                    .mark_failed(swap_id, &reason)
+                    .await
+                    .context(DatabaseSnafu)?;
+            }
+            SwapStatus::WaitingUserDepositConfirmed | SwapStatus::WaitingMMDepositInitiated => {
+                if swap.status == SwapStatus::WaitingMMDepositInitiated {
+                    let attempts = self
+                        .db
+                        .swaps()
+                        .mm_deposit_attempts(swap_id)
+                        .await
+                        .context(DatabaseSnafu)?;
+                    let deadline = mm_deposit_deadline(&swap, &attempts, self.mm_deposit_grace);
+                    if self.clock.now() < deadline && !self.mm_registry.request_cancel(&swap).await
+                    {
+                        return MarketMakerMayStillPaySnafu { swap_id, deadline }.fail();
+                    }
+                }
+                self.db
+                    .swaps()
+                    .initiate_user_refund(swap_id, &reason)
+                    .await
+                    .context(DatabaseSnafu)?;
+
+                refund_user(&self.db, &self.chain_registry, &swap).await;
+            }
+            status => return SwapNotCancellableSnafu { swap_id, status }.fail(),
+        }
+        warn!("Swap {} cancelled: {}", swap_id, reason);
+
+        self.mm_registry
+            .notify_swap_failed(
+                &swap.market_maker_id,
+                &swap_id,
+                &swap.quote.id,
+                SwapFailureReason::Cancelled,
+                None,
+                swap.trace_id.as_deref(),
+            )
+            .await;
+
+        self.get_swap(swap_id).await
+    }
+
+    /// Keep what the MM says it paid, the deposit itself is still only trusted
+    /// once the chain shows it
+    pub async fn handle_deposit_initiated(
+        &self,
+        market_maker_id: Uuid,
+        swap_id: Uuid,
+        tx_hash: &TxHash,
+        fee: Option<U256>,
+        fill_cost: Option<&FillCost>,
+    ) -> SwapResult<()> {
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        if swap.market_maker_id != market_maker_id {
+            warn!(
+                "Market maker {} reported a deposit for swap {} it isn't part of",
+                market_maker_id, swap_id
+            );
+            return Ok(());
+        }
+
+        // Kept in the form the chain's own hashes take, whichever the MM sent
+        let tx_hash = tx_hash.clone().for_chain(swap.quote.to.currency.chain);
+        info!(
+            "Market maker reported deposit {} for swap {} with fee {:?} ({:?})",
+            tx_hash, swap_id, fee, fill_cost
+        );
+        self.db
+            .swaps()
+            .record_mm_claimed_deposit(swap_id, &tx_hash, fee, fill_cost)
+            .await
+            .context(DatabaseSnafu)?;
+        Ok(())
+    }
+
+    /// Record a market maker's check of the deposit key released at settlement.
+    /// A key that doesn't control the deposit address holds the swap for manual
+    /// review
+    pub async fn handle_deposit_key_verification(
+        &self,
+        market_maker_id: Uuid,
+        swap_id: Uuid,
+        deposit_address: &str,
+        matches: bool,
+    ) -> SwapResult<()> {
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        if swap.market_maker_id != market_maker_id {
+            warn!(
+                "Market maker {} reported a deposit key check for swap {} it isn't part of",
+                market_maker_id, swap_id
+            );
+            return Ok(());
+        }
+
+        if matches && deposit_address == swap.user_deposit_address {
+            info!("Market maker verified the deposit key for swap {}", swap_id);
+            return Ok(());
+        }
+
+        let reason = format!(
+            "Market maker reported the released key does not control deposit address {deposit_address}"
+        );
+        error!(
+            "ALERT: swap {} needs manual review: {} (expected address {})",
+            swap_id, reason, swap.user_deposit_address
+        );
+        self.db
+            .swaps()
+            .flag_for_manual_review(swap_id, &reason)
+            .await
+            .context(DatabaseSnafu)?;
+        Ok(())
+    }
+}
+
+/// Read only view of a swap, built from the stored row alone so it needs no
+/// chain connection or master key
+fn swap_response(swap: &Swap) -> SwapResponse {
+    let (user_required_confirmations, mm_required_confirmations) =
+        swap.get_required_confirmations();
+    let user_decimals = swap.quote.from.currency.decimals;
+    let mm_decimals = swap.quote.to.currency.decimals;
+    SwapResponse {
+        id: swap.id,
+        quote_id: swap.quote.id,
+        status: format!("{:?}", swap.status),
+        created_at: swap.created_at,
+        updated_at: swap.updated_at,
+        user_deposit: DepositInfoResponse {
+            address: swap.user_deposit_address.clone(),
+            chain: format!("{:?}", swap.quote.from.currency.chain),
+            expected_amount: swap.quote.from.amount,
+            decimals: user_decimals,
+            token: match &swap.quote.from.currency.token {
+                TokenIdentifier::Native => "Native".to_string(),
+                TokenIdentifier::Address(addr) => addr.clone(),
+            },
+            required_confirmations: user_required_confirmations,
+            deposit_tx: swap
+                .user_deposit_status
+                .as_ref()
+                .map(|d| d.tx_hash.to_string()),
+            deposit_amount: swap.user_deposit_status.as_ref().map(|d| d.amount),
+            deposit_detected_at: swap.user_deposit_status.as_ref().map(|d| d.detected_at),
+            detected: swap.user_deposit_status.as_ref().map(|d| DepositProgress {
+                tx_hash: d.tx_hash.to_string(),
+                amount: d.amount.to_string(),
+                decimals: user_decimals,
+                confirmations: d.confirmations,
+                required_confirmations: user_required_confirmations,
+                detected_at: d.detected_at,
+            }),
+        },
+        mm_deposit: DepositInfoResponse {
+            address: swap.user_destination_address.clone(),
+            chain: format!("{:?}", swap.quote.to.currency.chain),
+            expected_amount: swap.quote.to.amount,
+            decimals: mm_decimals,
+            token: match &swap.quote.to.currency.token {
+                TokenIdentifier::Native => "Native".to_string(),
+                TokenIdentifier::Address(addr) => addr.clone(),
+            },
+            required_confirmations: mm_required_confirmations,
+            deposit_tx: swap
+                .mm_deposit_status
+                .as_ref()
+                .map(|d| d.tx_hash.to_string()),
+            deposit_amount: swap.mm_deposit_status.as_ref().map(|d| d.amount),
+            deposit_detected_at: swap.mm_deposit_status.as_ref().map(|d| d.detected_at),
+            detected: swap.mm_deposit_status.as_ref().map(|d| DepositProgress {
+                tx_hash: d.tx_hash.to_string(),
+                amount: d.amount.to_string(),
+                decimals: mm_decimals,
+                confirmations: d.confirmations,
+                required_confirmations: mm_required_confirmations,
+                detected_at: d.detected_at,
+            }),
+        },
+        user_refund_address: swap.user_refund_address.clone(),
+        refund_address_required: swap.awaiting_refund_address(),
+        settlement: swap
+            .settlement_status
+            .as_ref()
+            .map(|settlement| SettlementProgress {
+                tx_hash: settlement.tx_hash.to_string(),
+                broadcast_at: settlement.broadcast_at,
+                confirmations: settlement.confirmations,
+                completed_at: settlement.completed_at,
+                fee: settlement.fee.map(|fee| fee.to_string()),
+            }),
+        external_reference: swap.external_reference.clone(),
+    }
+}
+
+/// A settled swap missing data that settlement requires
+fn missing_record(swap_id: Uuid, what: &str) -> SwapError {
+    SwapError::Database {
+        source: OtcServerError::InvalidState {
+            message: format!("Settled swap {swap_id} has no {what}"),
+        },
+    }
+}
+
+/// Reject a quote that expired, or that has less than `min_validity` left once
+/// the market maker validation, which can take up to
+/// `MARKET_MAKER_VALIDATION_TIMEOUT`, is done
+fn check_quote_validity(
+    quote: &Quote,
+    now: DateTime<Utc>,
+    min_validity: Duration,
+) -> SwapResult<()> {
+    if quote.is_expired(now) {
+        return Err(SwapError::QuoteExpired);
+    }
+    let remaining = (quote.expires_at - now).to_std().unwrap_or_default();
+    if remaining.saturating_sub(MARKET_MAKER_VALIDATION_TIMEOUT) < min_validity {
+        return Err(SwapError::QuoteExpiring {
+            expires_in_seconds: quote.expires_in_seconds(now),
+            min_validity_seconds: min_validity.as_secs(),
+        });
+    }
+    Ok(())
+}
+
+/// Reject a quote whose payout would be dust at `destination`. The payout
+/// is already net of the market maker's network fee and the protocol fee is
+/// paid on top, so it's exactly what the user's output carries
+fn check_payout_above_dust(
+    quote: &Quote,
+    destination: &str,
+    bitcoin_network: bitcoin::Network,
+) -> SwapResult<()> {
+    let threshold = dust::dust_threshold(quote.to.currency.chain, destination, bitcoin_network)
+        .context(InvalidDestinationAddressSnafu)?;
+    ensure!(
+        quote.to.amount >= U256::from(threshold),
+        OutputBelowDustSnafu {
+            amount: quote.to.amount,
+            threshold,
+            address: destination,
+        }
+    );
+    Ok(())
+}
+
+/// When the MM's time to pay runs out: `grace` after it was last asked to,
+/// whether by the first notification, its own claim or a retry request
+fn mm_deposit_deadline(
+    swap: &Swap,
+    attempts: &MMDepositAttempts,
+    grace: Duration,
+) -> DateTime<Utc> {
+    let asked_at = [
+        swap.mm_notified_at,
+        attempts.claimed_at,
+        attempts.retry_requested_at,
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or(swap.updated_at);
+    let grace = ChronoDuration::from_std(grace).unwrap_or(ChronoDuration::MAX);
+    asked_at
+        .checked_add_signed(grace)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+/// Store `swap` under a fresh salt from `salts` and MM nonce from `nonces`,
+/// drawing again while the address the salt derives to or the nonce belongs to
+/// an earlier swap. Only a repeated draw can get there, so after
+/// [`SWAP_INSERT_ATTEMPTS`] tries the source is broken and the error is passed on
+async fn insert_with_unused_salt_and_nonce(
+    db: &Database,
+    salts: &dyn DepositSaltSource,
+    nonces: &dyn MmNonceSource,
+    swap: &mut Swap,
+    derive_address: impl Fn(&[u8; 32]) -> SwapResult<String>,
+) -> SwapResult<()> {
+    swap.user_deposit_salt = salts.next_salt();
+    swap.user_deposit_address = derive_address(&swap.user_deposit_salt)?;
+    swap.mm_nonce = nonces.next_nonce();
+    let mut attempt = 1;
+    loop {
+        match db.swaps().create(swap).await {
+            Ok(()) => return Ok(()),
+            Err(OtcServerError::DepositAddressInUse { address })
+                if attempt < SWAP_INSERT_ATTEMPTS =>
+            {
+                error!(
+                    "Deposit address {} derived for swap {} is already taken, retrying with a fresh salt (attempt {}/{})",
+                    address, swap.id, attempt, SWAP_INSERT_ATTEMPTS
+                );
+                swap.user_deposit_salt = salts.next_salt();
+                swap.user_deposit_address = derive_address(&swap.user_deposit_salt)?;
+            }
+            Err(OtcServerError::MmNonceInUse { nonce }) if attempt < SWAP_INSERT_ATTEMPTS => {
+                error!(
+                    "MM nonce {} drawn for swap {} is already taken, retrying with a fresh nonce (attempt {}/{})",
+                    nonce, swap.id, attempt, SWAP_INSERT_ATTEMPTS
+                );
+                swap.mm_nonce = nonces.next_nonce();
+            }
+            Err(e) => return Err(SwapError::Database { source: e }),
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::ManualClock;
+    use otc_models::{ChainType, Currency, Lot, SwapBuilder, UserDepositStatus};
+
+    fn quote_expiring_in(seconds: i64, now: DateTime<Utc>) -> Quote {
+        let lot = Lot {
+            currency: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(100_000u64),
+        };
+        Quote {
+            id: Uuid::new_v4(),
+            market_maker_id: Uuid::new_v4(),
+            from: lot.clone(),
+            to: lot,
+            expires_at: now + ChronoDuration::seconds(seconds),
+            created_at: now,
+        }
+    }
+
+    #[test]
+    fn test_near_expiry_quote_is_rejected_as_expiring() {
+        let now = Utc::now();
+        let min_validity = Duration::from_secs(20);
+
+        assert!(matches!(
+            check_quote_validity(&quote_expiring_in(3, now), now, min_validity),
+            Err(SwapError::QuoteExpiring {
+                expires_in_seconds: 3,
+                min_validity_seconds: 20,
+            })
+        ));
+        // The validation timeout comes out of the remaining validity
+        assert!(matches!(
+            check_quote_validity(&quote_expiring_in(24, now), now, min_validity),
+            Err(SwapError::QuoteExpiring { .. })
+        ));
+        assert!(check_quote_validity(&quote_expiring_in(25, now), now, min_validity).is_ok());
+        assert!(matches!(
+            check_quote_validity(&quote_expiring_in(-1, now), now, min_validity),
+            Err(SwapError::QuoteExpired)
+        ));
+    }
+
+    #[test]
+    fn test_minimum_validity_window_is_configurable() {
+        let now = Utc::now();
+        let quote = quote_expiring_in(60, now);
+
+        assert!(check_quote_validity(&quote, now, Duration::from_secs(20)).is_ok());
+        assert!(matches!(
+            check_quote_validity(&quote, now, Duration::from_secs(120)),
+            Err(SwapError::QuoteExpiring {
+                expires_in_seconds: 60,
+                min_validity_seconds: 120,
+            })
+        ));
+        // Without a minimum only expired quotes are turned away
+        assert!(check_quote_validity(&quote_expiring_in(3, now), now, Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_quote_expires_at_its_expiry() {
+        let clock = ManualClock::new(Utc::now());
+        let quote = quote_expiring_in(30, clock.now());
+
+        clock.advance(ChronoDuration::seconds(29));
+        assert!(check_quote_validity(&quote, clock.now(), Duration::ZERO).is_ok());
+        clock.advance(ChronoDuration::seconds(1));
+        assert!(matches!(
+            check_quote_validity(&quote, clock.now(), Duration::ZERO),
+            Err(SwapError::QuoteExpired)
+        ));
+    }
+
+    fn quote_paying(chain: ChainType, amount: u64) -> Quote {
+        let mut quote = quote_expiring_in(60, Utc::now());
+        quote.to.currency.chain = chain;
+        quote.to.amount = U256::from(amount);
+        quote
+    }
+
+    #[test]
+    fn test_payout_must_clear_the_destinations_dust_threshold() {
+        use bitcoin::secp256k1::{Secp256k1, SecretKey};
+        use bitcoin::{CompressedPublicKey, Network, PrivateKey};
+
+        let secp = Secp256k1::new();
+        let mut bytes = [0u8; 32];
+        bytes[31] = 1;
+        let private_key = PrivateKey::new(SecretKey::from_slice(&bytes).unwrap(), Network::Regtest);
+        let key = CompressedPublicKey::from_private_key(&secp, &private_key).unwrap();
+        let destinations = [
+            (bitcoin::Address::p2pkh(key, Network::Regtest), 546),
+            (bitcoin::Address::p2wpkh(&key, Network::Regtest), 294),
+            (
+                bitcoin::Address::p2tr(&secp, key.0.into(), None, Network::Regtest),
+                330,
+            ),
+        ];
+
+        for (address, threshold) in destinations {
+            let address = address.to_string();
+            let check = |amount| {
+                check_payout_above_dust(
+                    &quote_paying(ChainType::Bitcoin, amount),
+                    &address,
+                    Network::Regtest,
+                )
+            };
+            assert!(check(threshold).is_ok());
+            assert!(matches!(
+                check(threshold - 1),
+                Err(SwapError::OutputBelowDust { threshold: t, .. }) if t == threshold
+            ));
+        }
+
+        // cbBTC has no dust limit
+        assert!(check_payout_above_dust(
+            &quote_paying(ChainType::Ethereum, 1),
+            "0x742d35Cc6634C0532925a3b844Bc454e4438f44e",
+            Network::Regtest,
+        )
+        .is_ok());
+        // A destination on another network can't be priced
+        assert!(matches!(
+            check_payout_above_dust(
+                &quote_paying(ChainType::Bitcoin, 100_000),
+                &bitcoin::Address::p2wpkh(&key, Network::Bitcoin).to_string(),
+                Network::Regtest,
+            ),
+            Err(SwapError::InvalidDestinationAddress { .. })
+        ));
+    }
+
+    fn waiting_swap(now: DateTime<Utc>) -> Swap {
+        SwapBuilder::new()
+            .with_created_at(now)
+            .with_quote(quote_expiring_in(60, now))
+            .with_deposit([0; 32], "bcrt1qdeposit")
+            .with_destination("bcrt1qdestination")
+            .with_confirmations(3, 6)
+            .build()
+    }
+
+    #[test]
+    fn test_swap_response_reports_confirmation_progress() {
+        let now = Utc::now();
+        let mut swap = waiting_swap(now);
+
+        let response = swap_response(&swap);
+        assert!(response.user_deposit.detected.is_none());
+        assert!(response.mm_deposit.detected.is_none());
+        assert!(response.settlement.is_none());
+
+        // More than a JS number holds exactly
+        let amount = U256::from(u64::MAX) * U256::from(1_000u64);
+        swap.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: TxHash::parse(ChainType::Bitcoin, &"ab".repeat(32)).unwrap(),
+            amount,
+            detected_at: now,
+            confirmations: 2,
+            last_checked: now,
+        });
+        let response = swap_response(&swap);
+        let detected = response.user_deposit.detected.unwrap();
+        assert_eq!(
+            (detected.confirmations, detected.required_confirmations),
+            (2, swap.get_required_confirmations().0)
+        );
+        assert_eq!(detected.amount, amount.to_string());
+        assert_eq!(detected.decimals, 8);
+
+        let json = serde_json::to_value(swap_response(&swap)).unwrap();
+        assert_eq!(
+            json["user_deposit"]["detected"]["amount"],
+            serde_json::Value::String("18446744073709551615000".to_string())
+        );
+        assert_eq!(
+            json["user_deposit"]["detected"]["required_confirmations"],
+            3
+        );
+    }
+
+    #[test]
+    fn test_mm_deposit_deadline_counts_from_the_latest_ask() {
+        let now = Utc::now();
+        let grace = Duration::from_secs(600);
+        let mut swap = waiting_swap(now);
+        let mut attempts = MMDepositAttempts {
+            claimed_tx_hash: None,
+            claimed_at: None,
+            retries: 0,
+            retry_requested_at: None,
+        };
+
+        assert_eq!(
+            mm_deposit_deadline(&swap, &attempts, grace),
+            swap.updated_at + ChronoDuration::seconds(600)
+        );
+        swap.mm_notified_at = Some(now + ChronoDuration::seconds(10));
+        assert_eq!(
+            mm_deposit_deadline(&swap, &attempts, grace),
+            now + ChronoDuration::seconds(610)
+        );
+        attempts.retry_requested_at = Some(now + ChronoDuration::seconds(300));
+        attempts.claimed_at = Some(now + ChronoDuration::seconds(100));
+        assert_eq!(
+            mm_deposit_deadline(&swap, &attempts, grace),
+            now + ChronoDuration::seconds(900)
+        );
+        assert_eq!(
+            mm_deposit_deadline(&swap, &attempts, Duration::MAX),
+            DateTime::<Utc>::MAX_UTC
+        );
+    }
+
+    #[derive(Debug)]
+    struct ScriptedSalts(std::sync::Mutex<Vec<[u8; 32]>>);
+
+    impl DepositSaltSource for ScriptedSalts {
+        fn next_salt(&self) -> [u8; 32] {
+            self.0.lock().unwrap().remove(0)
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_taken_deposit_address_is_retried_with_a_fresh_salt(pool: sqlx::PgPool) {
+        let db = Database::from_pool(pool).await.unwrap();
+        let derive_address = |salt: &[u8; 32]| -> SwapResult<String> {
+            Ok(format!("bcrt1q{}", alloy::hex::encode(salt)))
+        };
+        let now = Utc::now();
+
+        let mut first = waiting_swap(now);
+        let salts = ScriptedSalts(std::sync::Mutex::new(vec![[1; 32]]));
+        insert_with_unused_salt_and_nonce(&db, &salts, &OsRandomNonces, &mut first, derive_address)
+            .await
+            .unwrap();
+
+        // A repeated salt costs an attempt, not the swap
+        let mut second = waiting_swap(now);
+        let salts = ScriptedSalts(std::sync::Mutex::new(vec![[1; 32], [2; 32]]));
+        insert_with_unused_salt_and_nonce(
+            &db,
+            &salts,
+            &OsRandomNonces,
+            &mut second,
+            derive_address,
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.user_deposit_salt, [2; 32]);
+        let stored = db.swaps().get(second.id).await.unwrap();
+        assert_eq!(stored.user_deposit_salt, [2; 32]);
+        assert_ne!(stored.user_deposit_address, first.user_deposit_address);
+
+        // A source that keeps repeating itself is given up on
+        let mut third = waiting_swap(now);
+        let repeats = vec![[1; 32]; SWAP_INSERT_ATTEMPTS as usize];
+        let salts = ScriptedSalts(std::sync::Mutex::new(repeats));
+        assert!(matches!(
+            insert_with_unused_salt_and_nonce(
+                &db,
+                &salts,
+                &OsRandomNonces,
+                &mut third,
+                derive_address
+            )
+            .await,
+            Err(SwapError::Database {
+                source: OtcServerError::DepositAddressInUse { .. }
+            })
+        ));
+        assert!(salts.0.lock().unwrap().is_empty());
+        assert!(db.swaps().get(third.id).await.is_err());
+    }
+
+    #[derive(Debug)]
+    struct ScriptedNonces(std::sync::Mutex<Vec<[u8; 16]>>);
+
+    impl MmNonceSource for ScriptedNonces {
+        fn next_nonce(&self) -> [u8; 16] {
+            self.0.lock().unwrap().remove(0)
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_taken_mm_nonce_is_retried_with_a_fresh_nonce(pool: sqlx::PgPool) {
+        let db = Database::from_pool(pool).await.unwrap();
+        let derive_address = |salt: &[u8; 32]| -> SwapResult<String> {
+            Ok(format!("bcrt1q{}", alloy::hex::encode(salt)))
+        };
+        let now = Utc::now();
+
+        let mut first = waiting_swap(now);
+        let nonces = ScriptedNonces(std::sync::Mutex::new(vec![[1; 16]]));
+        insert_with_unused_salt_and_nonce(&db, &OsRandomSalts, &nonces, &mut first, derive_address)
+            .await
+            .unwrap();
+
+        // The deposit address is kept, only the nonce is drawn again
+        let mut second = waiting_swap(now);
+        let nonces = ScriptedNonces(std::sync::Mutex::new(vec![[1; 16], [2; 16]]));
+        insert_with_unused_salt_and_nonce(
+            &db,
+            &OsRandomSalts,
+            &nonces,
+            &mut second,
+            derive_address,
+        )
+        .await
+        .unwrap();
+        let stored = db.swaps().get(second.id).await.unwrap();
+        assert_eq!(stored.mm_nonce, [2; 16]);
+        assert_eq!(stored.user_deposit_address, second.user_deposit_address);
+
+        let mut third = waiting_swap(now);
+        let repeats = vec![[1; 16]; SWAP_INSERT_ATTEMPTS as usize];
+        let nonces = ScriptedNonces(std::sync::Mutex::new(repeats));
+        assert!(matches!(
+            insert_with_unused_salt_and_nonce(
+                &db,
+                &OsRandomSalts,
+                &nonces,
+                &mut third,
+                derive_address
+            )
+            .await,
+            Err(SwapError::Database {
+                source: OtcServerError::MmNonceInUse { .. }
+            })
+        ));
+        assert!(nonces.0.lock().unwrap().is_empty());
+    }
 }
@@ -1,17 +1,34 @@
 use crate::db::Database;
 use crate::error::OtcServerError;
-use crate::{config::Settings, services::mm_registry};
+use crate::{
+    config::{Settings, SettingsError},
+    services::mm_registry,
+};
 use alloy::primitives::U256;
-use chrono::Utc;
 use blockchain_utils::FeeCalcFromLot;
-use otc_chains::traits::MarketMakerPaymentValidation;
+use chrono::{DateTime, Utc};
+use common::{Clock, Shutdown};
+use futures_util::StreamExt;
+use otc_chains::traits::{ChainOperations, MarketMakerPaymentValidation};
 use otc_chains::ChainRegistry;
-use otc_models::{MMDepositStatus, Swap, SwapStatus, TxStatus, UserDepositStatus};
+use otc_models::{
+    ChainNetwork, ChainType, Currency, Finality, MMDepositStatus, Swap, SwapStatus,
+    TokenIdentifier, TxStatus, UserDepositStatus,
+};
+use otc_protocols::mm::SwapFailureReason;
 use snafu::prelude::*;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::time;
-use tracing::{error, info, warn};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{error, info, info_span, warn, Instrument};
+use uuid::Uuid;
 
 #[derive(Debug, Snafu)]
 pub enum MonitoringError {
@@ -21,23 +38,317 @@ pub enum MonitoringError {
     #[snafu(display("Chain operation error: {}", source))]
     ChainOperation { source: otc_chains::Error },
 
+    #[snafu(display("Master key unavailable: {}", source))]
+    MasterKeyUnavailable { source: SettingsError },
+
     #[snafu(display("Invalid state transition from {:?}", current_state))]
     InvalidTransition { current_state: SwapStatus },
+
+    #[snafu(display("Database is unreachable"))]
+    DatabaseUnavailable,
+}
+
+impl MonitoringError {
+    /// Whether the database was unreachable, so monitoring should pause until
+    /// it's back rather than move on to the next swap
+    #[must_use]
+    pub fn is_database_unavailable(&self) -> bool {
+        match self {
+            MonitoringError::Database { source } => source.is_transient(),
+            MonitoringError::DatabaseUnavailable => true,
+            _ => false,
+        }
+    }
 }
 
 pub type MonitoringResult<T> = Result<T, MonitoringError>;
 
+/// Chains without an interval of their own are checked this many times per block
+const CHECKS_PER_BLOCK: u32 = 10;
+
+/// Shortest interval derived from a chain's block time
+pub const MIN_CHAIN_MONITOR_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Wait before probing an unreachable database, doubled up to the max after
+/// every failed probe
+const DATABASE_PROBE_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DATABASE_PROBE_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Wait before subscribing again after a transfer subscription failed or
+/// dropped, doubled up to the max while it keeps failing
+const SUBSCRIPTION_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const SUBSCRIPTION_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often swaps waiting on a chain are checked, `<seconds>` for every chain or
+/// `<chain>=<seconds>` for one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainMonitorInterval {
+    /// `None` covers every chain without an entry of its own
+    pub chain: Option<ChainType>,
+    pub seconds: u64,
+}
+
+impl fmt::Display for ChainMonitorInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chain {
+            Some(chain) => write!(f, "{chain}={}", self.seconds),
+            None => write!(f, "{}", self.seconds),
+        }
+    }
+}
+
+impl FromStr for ChainMonitorInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chain, seconds) = match s.split_once('=') {
+            Some((chain, seconds)) => (Some(chain.trim().parse()?), seconds),
+            None => (None, s),
+        };
+        let seconds = seconds
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .ok_or_else(|| {
+                format!(
+                    "invalid chain monitor interval {s:?}, expected <seconds> or <chain>=<seconds>"
+                )
+            })?;
+        Ok(Self { chain, seconds })
+    }
+}
+
+/// Interval each registered chain is checked at: its own entry, else the entry
+/// for every chain, else a tenth of its block time but no less than
+/// [`MIN_CHAIN_MONITOR_INTERVAL`]
+#[must_use]
+pub fn resolve_monitor_intervals(
+    configured: &[ChainMonitorInterval],
+    chain_registry: &ChainRegistry,
+) -> HashMap<ChainType, Duration> {
+    let configured_for = |chain: Option<ChainType>| {
+        configured
+            .iter()
+            .rev()
+            .find(|interval| interval.chain == chain)
+            .map(|interval| Duration::from_secs(interval.seconds))
+    };
+    chain_registry
+        .supported_chains()
+        .into_iter()
+        .filter_map(|chain| {
+            let chain_ops = chain_registry.get(&chain)?;
+            let interval = configured_for(Some(chain))
+                .or_else(|| configured_for(None))
+                .unwrap_or_else(|| {
+                    (chain_ops.estimated_block_time() / CHECKS_PER_BLOCK)
+                        .max(MIN_CHAIN_MONITOR_INTERVAL)
+                });
+            Some((chain, interval))
+        })
+        .collect()
+}
+
+/// Chain the swap's next expected event happens on, which sets how often it's checked
+#[must_use]
+pub fn monitored_chain(swap: &Swap) -> ChainType {
+    monitored_currency(swap).chain
+}
+
+/// Address and currency of the deposit a swap waits for, `None` unless it waits
+/// for one to be sent
+fn awaited_deposit(swap: &Swap) -> Option<(&str, &Currency)> {
+    match swap.status {
+        SwapStatus::WaitingUserDepositInitiated => {
+            Some((&swap.user_deposit_address, &swap.quote.from.currency))
+        }
+        SwapStatus::WaitingMMDepositInitiated => {
+            Some((&swap.user_destination_address, &swap.quote.to.currency))
+        }
+        _ => None,
+    }
+}
+
+/// Currency of the deposit the swap's next expected event is about
+fn monitored_currency(swap: &Swap) -> &Currency {
+    match swap.status {
+        SwapStatus::WaitingMMDepositInitiated
+        | SwapStatus::WaitingMMDepositConfirmed
+        | SwapStatus::Settled => &swap.quote.to.currency,
+        _ => &swap.quote.from.currency,
+    }
+}
+
+/// Refund the user deposit of a swap that just moved to refunding, unless the
+/// user still has to set where it goes. What the refund will cost is recorded
+/// either way
+pub(crate) async fn refund_user(db: &Database, chain_registry: &ChainRegistry, swap: &Swap) {
+    if let Err(e) = record_user_refund_cost(db, chain_registry, swap).await {
+        warn!("Failed to cost the refund of swap {}: {}", swap.id, e);
+    }
+
+    match &swap.user_refund_address {
+        // TODO: Actually execute the refund
+        Some(address) => info!(
+            "TODO: Execute user refund for swap {} to {}",
+            swap.id, address
+        ),
+        None => warn!(
+            "Swap {} has no refund address, waiting for the user to set one",
+            swap.id
+        ),
+    }
+}
+
+/// Estimate the network fee of refunding the user's deposit and record it
+/// along with what the user gets back
+async fn record_user_refund_cost(
+    db: &Database,
+    chain_registry: &ChainRegistry,
+    swap: &Swap,
+) -> MonitoringResult<()> {
+    // Nothing arrived, nothing to refund
+    let Some(deposit) = &swap.user_deposit_status else {
+        return Ok(());
+    };
+    let currency = &swap.quote.from.currency;
+    let chain_ops =
+        chain_registry
+            .for_currency(currency)
+            .ok_or(MonitoringError::ChainOperation {
+                source: otc_chains::Error::ChainNotSupported {
+                    chain: currency.network().to_string(),
+                },
+            })?;
+
+    let fee = chain_ops
+        .estimate_transfer_fee(currency)
+        .await
+        .context(ChainOperationSnafu)?;
+    let amount = refund_amount(currency, deposit.amount, fee);
+    db.swaps()
+        .record_user_refund_cost(swap.id, amount, fee)
+        .await
+        .context(DatabaseSnafu)?;
+    info!(
+        "Refund of swap {} returns {} of the {} deposited, paying a {} fee",
+        swap.id, amount, deposit.amount, fee
+    );
+    Ok(())
+}
+
+/// What the user gets back of `deposited` when the refund costs `fee`. Native
+/// deposits pay the fee themselves, a token deposit's gas is paid in the
+/// chain's native currency so the tokens come back whole
+fn refund_amount(currency: &Currency, deposited: U256, fee: U256) -> U256 {
+    match currency.token {
+        TokenIdentifier::Native => deposited.saturating_sub(fee),
+        TokenIdentifier::Address(_) => deposited,
+    }
+}
+
+/// How long an MM deposit that failed on chain is given before the MM is asked
+/// to pay again, and how often it is asked before the user is refunded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MMDepositRetryPolicy {
+    /// Wait after the MM's latest claim or retry request before judging it
+    pub grace: Duration,
+    pub max_retries: u32,
+}
+
+impl Default for MMDepositRetryPolicy {
+    fn default() -> Self {
+        Self {
+            grace: Duration::from_secs(120),
+            max_retries: 2,
+        }
+    }
+}
+
+/// Asks the monitoring service to subscribe to transfers for a swap that just
+/// started waiting on a deposit, so it's seen before the next pass
+#[derive(Debug, Clone)]
+pub struct TransferWatchRequests(mpsc::UnboundedSender<Uuid>);
+
+impl TransferWatchRequests {
+    pub fn watch(&self, swap_id: Uuid) {
+        // Gone only once monitoring stopped, polling had ended with it
+        let _ = self.0.send(swap_id);
+    }
+}
+
+/// How the latest monitoring pass over a chain went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitoringPass {
+    pub finished_at: DateTime<Utc>,
+    pub duration: Duration,
+    /// How often the chain is meant to be checked
+    pub interval: Duration,
+    /// Swaps checked, or why the pass failed
+    pub result: Result<usize, String>,
+}
+
+/// The latest monitoring pass over each chain, for reporting on how
+/// monitoring keeps up
+#[derive(Debug, Clone, Default)]
+pub struct MonitoringPasses(Arc<StdMutex<HashMap<ChainType, MonitoringPass>>>);
+
+impl MonitoringPasses {
+    pub(crate) fn record(&self, chain: ChainType, pass: MonitoringPass) {
+        self.0
+            .lock()
+            .expect("monitoring passes mutex poisoned")
+            .insert(chain, pass);
+    }
+
+    /// Every chain passed over so far, none before the first pass finishes
+    #[must_use]
+    pub fn latest(&self) -> HashMap<ChainType, MonitoringPass> {
+        self.0
+            .lock()
+            .expect("monitoring passes mutex poisoned")
+            .clone()
+    }
+}
+
+/// A subscription pushing transfers to the deposit a swap waits on in `status`
+struct TransferWatcher {
+    chain: ChainType,
+    status: SwapStatus,
+    task: AbortHandle,
+}
+
 /// Background service that monitors all active swaps for:
 /// - Incoming deposits (user and MM)
 /// - Confirmation tracking
 /// - Timeouts and refund triggers
 /// - Settlement completion
+///
+/// Each chain is checked in its own loop, so a swap waiting on a slow chain
+/// isn't polled at the pace of a fast one. On chains that support it, deposits
+/// are also subscribed to and checked the moment one lands
 pub struct SwapMonitoringService {
     db: Database,
     settings: Arc<Settings>,
     chain_registry: Arc<ChainRegistry>,
     mm_registry: Arc<mm_registry::MMRegistry>,
-    chain_monitor_interval_seconds: u64,
+    /// How often swaps waiting on each chain are checked
+    intervals: HashMap<ChainType, Duration>,
+    /// Most swaps checked at once, shared by every chain's loop
+    concurrency: Arc<Semaphore>,
+    /// Held while waiting out a database outage, so the chains' loops share
+    /// one probe instead of each polling the database
+    database_probe: Mutex<()>,
+    /// Stamps when deposits were detected and checked
+    clock: Arc<dyn Clock>,
+    mm_deposit_retry: MMDepositRetryPolicy,
+    /// Transfer subscriptions of the swaps waiting on a deposit, by swap
+    watchers: StdMutex<HashMap<Uuid, TransferWatcher>>,
+    watch_requests: mpsc::UnboundedSender<Uuid>,
+    /// Taken by [`Self::run`]
+    watch_request_rx: Mutex<Option<mpsc::UnboundedReceiver<Uuid>>>,
+    passes: MonitoringPasses,
 }
 
 impl SwapMonitoringService {
@@ -47,55 +358,434 @@ impl SwapMonitoringService {
         settings: Arc<Settings>,
         chain_registry: Arc<ChainRegistry>,
         mm_registry: Arc<mm_registry::MMRegistry>,
-        chain_monitor_interval_seconds: u64,
+        intervals: HashMap<ChainType, Duration>,
+        concurrency: usize,
+        clock: Arc<dyn Clock>,
     ) -> Self {
+        let (watch_requests, watch_request_rx) = mpsc::unbounded_channel();
         Self {
             db,
             settings,
             chain_registry,
             mm_registry,
-            chain_monitor_interval_seconds,
+            intervals,
+            concurrency: Arc::new(Semaphore::new(concurrency.max(1))),
+            database_probe: Mutex::new(()),
+            clock,
+            mm_deposit_retry: MMDepositRetryPolicy::default(),
+            watchers: StdMutex::new(HashMap::new()),
+            watch_requests,
+            watch_request_rx: Mutex::new(Some(watch_request_rx)),
+            passes: MonitoringPasses::default(),
         }
     }
 
-    /// Start the monitoring service
-    pub async fn run(self: Arc<Self>) {
+    /// Handle for whoever creates swaps to have their deposits subscribed to
+    #[must_use]
+    pub fn transfer_watch_requests(&self) -> TransferWatchRequests {
+        TransferWatchRequests(self.watch_requests.clone())
+    }
+
+    /// Handle to how the latest pass over each chain went
+    #[must_use]
+    pub fn monitoring_passes(&self) -> MonitoringPasses {
+        self.passes.clone()
+    }
+
+    /// Retry MM deposits that fail on chain by `policy` instead of the default
+    #[must_use]
+    pub fn with_mm_deposit_retry(mut self, policy: MMDepositRetryPolicy) -> Self {
+        self.mm_deposit_retry = policy;
+        self
+    }
+
+    /// Start the monitoring service, returning once `shutdown` is triggered and
+    /// the swaps being checked at the time are done
+    pub async fn run(self: Arc<Self>, shutdown: Shutdown) {
         info!("Starting swap monitoring service");
 
-        // Check every 12 seconds
-        // interval is based on the shortest confirmation time of all chains
-        let chains = self.chain_registry.supported_chains();
-        let interval = Duration::from_secs(self.chain_monitor_interval_seconds);
-        info!(
-            "Starting swap monitoring service with interval: {:?}",
-            interval
+        let mut loops = JoinSet::new();
+        for (&chain, &interval) in &self.intervals {
+            info!(
+                "Monitoring swaps waiting on {} with interval: {:?}",
+                chain, interval
+            );
+            loops.spawn(self.clone().run_chain(chain, interval, shutdown.clone()));
+        }
+        if let Some(requests) = self.watch_request_rx.lock().await.take() {
+            loops.spawn(self.clone().run_watch_requests(requests, shutdown.clone()));
+        }
+
+        while let Some(result) = loops.join_next().await {
+            if let Err(e) = result {
+                error!("Swap monitoring loop panicked: {}", e);
+            }
+        }
+        for (_, watcher) in self.watchers_guard().drain() {
+            watcher.task.abort();
+        }
+        info!("Swap monitoring service stopped");
+    }
+
+    /// Run one pass over the swaps waiting on every monitored chain, returning
+    /// how many were checked. For callers that drive the service step by step
+    /// instead of running it
+    pub async fn tick_once(self: &Arc<Self>) -> MonitoringResult<usize> {
+        let mut chains: Vec<ChainType> = self.intervals.keys().copied().collect();
+        chains.sort();
+        let never = Shutdown::new();
+        let mut swap_count = 0;
+        for chain in chains {
+            swap_count += self.recorded_pass(chain, &never).await.0?;
+        }
+        Ok(swap_count)
+    }
+
+    /// Subscribe to the deposits of the swaps `requests` name
+    async fn run_watch_requests(
+        self: Arc<Self>,
+        mut requests: mpsc::UnboundedReceiver<Uuid>,
+        shutdown: Shutdown,
+    ) {
+        loop {
+            let swap_id = tokio::select! {
+                Some(swap_id) = requests.recv() => swap_id,
+                () = shutdown.triggered() => return,
+            };
+            match self.db.swaps().get(swap_id).await {
+                Ok(swap) => self.watch(&swap),
+                // The next pass over the swap's chain subscribes instead
+                Err(e) => warn!("Failed to load swap {} to watch: {}", swap_id, e),
+            }
+        }
+    }
+
+    fn watchers_guard(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, TransferWatcher>> {
+        self.watchers
+            .lock()
+            .expect("transfer watchers mutex poisoned")
+    }
+
+    /// Subscribe to transfers to the deposit `swap` waits on, when its chain
+    /// supports it and no subscription is running for it yet
+    fn watch(self: &Arc<Self>, swap: &Swap) {
+        let Some((address, currency)) = awaited_deposit(swap) else {
+            return;
+        };
+        let Some(chain_ops) = self
+            .chain_registry
+            .for_currency(currency)
+            .filter(|chain_ops| chain_ops.supports_subscriptions())
+        else {
+            return;
+        };
+
+        let mut watchers = self.watchers_guard();
+        match watchers.get(&swap.id) {
+            Some(watcher) if watcher.status == swap.status => return,
+            Some(watcher) => watcher.task.abort(),
+            None => {}
+        }
+        let task = tokio::spawn(self.clone().watch_transfers(
+            swap.id,
+            swap.status,
+            chain_ops,
+            address.to_string(),
+            currency.clone(),
+        ));
+        watchers.insert(
+            swap.id,
+            TransferWatcher {
+                chain: currency.chain,
+                status: swap.status,
+                task: task.abort_handle(),
+            },
         );
-        let mut interval = time::interval(interval);
+    }
+
+    /// Check `swap_id` whenever a transfer lands on the deposit it waits on in
+    /// `status`, until it moves on. A dropped subscription is made again, passes
+    /// keep polling meanwhile so only latency is lost
+    async fn watch_transfers(
+        self: Arc<Self>,
+        swap_id: Uuid,
+        status: SwapStatus,
+        chain_ops: Arc<dyn ChainOperations>,
+        address: String,
+        currency: Currency,
+    ) {
+        let mut backoff = SUBSCRIPTION_INITIAL_BACKOFF;
+        loop {
+            match chain_ops.subscribe_transfers(&address, &currency).await {
+                Ok(transfers) => {
+                    backoff = SUBSCRIPTION_INITIAL_BACKOFF;
+                    info!(
+                        "Subscribed to transfers to {} for swap {}",
+                        address, swap_id
+                    );
+                    // A transfer mined before the subscription took hold is
+                    // never pushed, so look once before waiting
+                    let mut checks = futures_util::stream::once(async { None })
+                        .chain(transfers.map(Some))
+                        .boxed();
+                    while let Some(transfer) = checks.next().await {
+                        if let Some(transfer) = transfer {
+                            info!(
+                                "Transfer {} of {} to swap {} pushed by subscription",
+                                transfer.tx_hash, transfer.amount, swap_id
+                            );
+                        }
+                        match self.check_watched_swap(swap_id, status).await {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                self.forget_watcher(swap_id);
+                                return;
+                            }
+                            Err(e) => error!(
+                                "Error checking swap {} on a pushed transfer: {}",
+                                swap_id, e
+                            ),
+                        }
+                    }
+                    warn!("Transfer subscription for swap {} dropped", swap_id);
+                }
+                // Left registered so the passes don't keep trying
+                Err(
+                    e @ (otc_chains::Error::SubscriptionsUnsupported
+                    | otc_chains::Error::UnsupportedToken { .. }),
+                ) => {
+                    info!("Polling for the deposit of swap {}: {}", swap_id, e);
+                    return;
+                }
+                Err(e) => warn!(
+                    "Failed to subscribe to transfers for swap {}: {}",
+                    swap_id, e
+                ),
+            }
+            time::sleep(backoff).await;
+            backoff = (backoff * 2).min(SUBSCRIPTION_MAX_BACKOFF);
+        }
+    }
+
+    /// Check a watched swap like a pass would, returning whether it still waits
+    /// in `status`
+    async fn check_watched_swap(
+        &self,
+        swap_id: Uuid,
+        status: SwapStatus,
+    ) -> MonitoringResult<bool> {
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        if swap.status != status {
+            return Ok(false);
+        }
+        let _permit = self
+            .concurrency
+            .acquire()
+            .await
+            .expect("monitoring semaphore is never closed");
+        self.monitor_swap(&swap).await?;
+        let swap = self.db.swaps().get(swap_id).await.context(DatabaseSnafu)?;
+        Ok(swap.status == status)
+    }
+
+    /// Drop the calling watcher's registration, unless it was already replaced
+    fn forget_watcher(&self, swap_id: Uuid) {
+        let mut watchers = self.watchers_guard();
+        if watchers
+            .get(&swap_id)
+            .is_some_and(|watcher| watcher.task.id() == tokio::task::id())
+        {
+            watchers.remove(&swap_id);
+        }
+    }
+
+    /// Subscribe to the deposits `swaps`, the active swaps waiting on `chain`,
+    /// wait for, stopping the subscriptions of swaps that moved on
+    fn reconcile_watchers(self: &Arc<Self>, chain: ChainType, swaps: &[Swap]) {
+        let waiting: HashMap<Uuid, SwapStatus> =
+            swaps.iter().map(|swap| (swap.id, swap.status)).collect();
+        self.watchers_guard().retain(|swap_id, watcher| {
+            let keep = watcher.chain != chain || waiting.get(swap_id) == Some(&watcher.status);
+            if !keep {
+                watcher.task.abort();
+            }
+            keep
+        });
+        for swap in swaps {
+            self.watch(swap);
+        }
+    }
+
+    /// Check the swaps waiting on `chain` every `period`
+    async fn run_chain(self: Arc<Self>, chain: ChainType, period: Duration, shutdown: Shutdown) {
+        let mut interval = time::interval(period);
+        // A pass that overruns the interval delays the next one instead of
+        // queueing a burst of catch-up passes
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.triggered() => return,
+            }
 
-            if let Err(e) = self.monitor_all_swaps().await {
-                error!("Error monitoring swaps: {}", e);
+            let (result, elapsed) = self.recorded_pass(chain, &shutdown).await;
+            match result {
+                Ok(swap_count) => {
+                    info!(
+                        "Monitored {} active swaps waiting on {} in {:?}",
+                        swap_count, chain, elapsed
+                    );
+                    if elapsed > period {
+                        warn!(
+                            "Monitoring pass for {} took {:?}, longer than the {:?} interval, skipping missed ticks",
+                            chain, elapsed, period
+                        );
+                    }
+                }
+                Err(e) if e.is_database_unavailable() => {
+                    warn!("Pausing monitoring of swaps waiting on {}: {}", chain, e);
+                    tokio::select! {
+                        () = self.wait_for_database() => {}
+                        () = shutdown.triggered() => return,
+                    }
+                    interval.reset();
+                }
+                Err(e) => error!("Error monitoring swaps waiting on {}: {}", chain, e),
             }
         }
     }
 
-    /// Monitor all active swaps
-    async fn monitor_all_swaps(&self) -> MonitoringResult<()> {
-        // Get all active swaps
-        let active_swaps = self.db.swaps().get_active().await.context(DatabaseSnafu)?;
+    /// Monitor the active swaps waiting on `chain` and record how the pass went,
+    /// returning its result and duration
+    async fn recorded_pass(
+        self: &Arc<Self>,
+        chain: ChainType,
+        shutdown: &Shutdown,
+    ) -> (MonitoringResult<usize>, Duration) {
+        let started = Instant::now();
+        let result = self.monitor_chain_swaps(chain, shutdown).await;
+        let duration = started.elapsed();
+        self.passes.record(
+            chain,
+            MonitoringPass {
+                finished_at: self.clock.now(),
+                duration,
+                interval: self.intervals.get(&chain).copied().unwrap_or_default(),
+                result: result.as_ref().copied().map_err(ToString::to_string),
+            },
+        );
+        (result, duration)
+    }
+
+    /// Wait until the database answers again, backing off between probes
+    async fn wait_for_database(&self) {
+        let _probe = self.database_probe.lock().await;
+        let mut backoff = DATABASE_PROBE_INITIAL_BACKOFF;
+        loop {
+            time::sleep(backoff).await;
+            match self.db.ping().await {
+                Ok(()) => {
+                    info!("Database is reachable again, resuming monitoring");
+                    return;
+                }
+                Err(e) => {
+                    backoff = (backoff * 2).min(DATABASE_PROBE_MAX_BACKOFF);
+                    warn!(
+                        "Database still unreachable, probing again in {:?}: {}",
+                        backoff, e
+                    );
+                }
+            }
+        }
+    }
 
-        info!("Monitoring {} active swaps", active_swaps.len());
+    /// Monitor the active swaps waiting on `chain`, at most `concurrency` at
+    /// once, returning how many were checked
+    ///
+    /// Stops starting checks once one finds the database unreachable, the
+    /// rest would only fail the same way, or once `shutdown` is triggered.
+    /// Checks already started are always finished, so no swap is left halfway
+    /// through a transition.
+    async fn monitor_chain_swaps(
+        self: &Arc<Self>,
+        chain: ChainType,
+        shutdown: &Shutdown,
+    ) -> MonitoringResult<usize> {
+        let active_swaps: Vec<Swap> = self
+            .db
+            .swaps()
+            .get_active()
+            .await
+            .context(DatabaseSnafu)?
+            .into_iter()
+            .filter(|swap| monitored_chain(swap) == chain)
+            .collect();
+        let swap_count = active_swaps.len();
+        self.reconcile_watchers(chain, &active_swaps);
 
-        // TODO: use a semaphore to limit the number of swaps we monitor in parallel (+ support for parallelization)
+        info!(
+            "Monitoring {} active swaps waiting on {}",
+            swap_count, chain
+        );
+        let networks: BTreeSet<ChainNetwork> = active_swaps
+            .iter()
+            .map(|swap| monitored_currency(swap).network())
+            .collect();
+        for network in networks {
+            if let Some(chain_ops) = self.chain_registry.get_network(&network) {
+                // Status checks fall back to fetching for themselves
+                if let Err(e) = chain_ops.begin_monitoring_pass().await {
+                    warn!("Failed to prepare the {} monitoring pass: {}", network, e);
+                }
+            }
+        }
+
+        let database_unavailable = Arc::new(AtomicBool::new(false));
+        let mut tasks = JoinSet::new();
         for swap in active_swaps {
-            if let Err(e) = self.monitor_swap(&swap).await {
-                error!("Error monitoring swap {}: {}", swap.id, e);
+            let permit = self
+                .concurrency
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("monitoring semaphore is never closed");
+            if database_unavailable.load(AtomicOrdering::Relaxed) || shutdown.is_triggered() {
+                break;
+            }
+            let service = self.clone();
+            let database_unavailable = database_unavailable.clone();
+            let span = info_span!(
+                "monitor_swap",
+                swap_id = %swap.id,
+                trace_id = swap.trace_id.as_deref().unwrap_or_default()
+            );
+            tasks.spawn(
+                async move {
+                    match service.monitor_swap(&swap).await {
+                        Err(e) if e.is_database_unavailable() => {
+                            database_unavailable.store(true, AtomicOrdering::Relaxed);
+                        }
+                        Err(e) => error!("Error monitoring swap {}: {}", swap.id, e),
+                        Ok(()) => {}
+                    }
+                    drop(permit);
+                }
+                .instrument(span),
+            );
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
+                error!("Swap monitoring task panicked: {}", e);
             }
         }
 
-        Ok(())
+        ensure!(
+            !database_unavailable.load(AtomicOrdering::Relaxed),
+            DatabaseUnavailableSnafu
+        );
+        Ok(swap_count)
     }
 
     /// Monitor a single swap based on its current state
@@ -140,17 +830,22 @@ impl SwapMonitoringService {
         let quote = &swap.quote;
 
         // Get the chain operations for the user's deposit chain (from = user sends)
-        let chain_ops = self.chain_registry.get(&quote.from.currency.chain).ok_or(
-            MonitoringError::ChainOperation {
+        let chain_ops = self
+            .chain_registry
+            .for_currency(&quote.from.currency)
+            .ok_or(MonitoringError::ChainOperation {
                 source: otc_chains::Error::ChainNotSupported {
-                    chain: format!("{:?}", quote.from.currency.chain),
+                    chain: quote.from.currency.network().to_string(),
                 },
-            },
-        )?;
+            })?;
 
         // Derive the user deposit address
+        let master_key = self
+            .settings
+            .master_key_bytes(swap.master_key_version)
+            .context(MasterKeyUnavailableSnafu)?;
         let user_wallet = chain_ops
-            .derive_wallet(&self.settings.master_key_bytes(), &swap.user_deposit_salt)
+            .derive_wallet(&master_key, &swap.user_deposit_salt)
             .context(ChainOperationSnafu)?;
 
         info!("User deposit wallet: {:?}", user_wallet.address);
@@ -170,12 +865,13 @@ impl SwapMonitoringService {
             );
 
             // Update swap state
+            let now = self.clock.now();
             let user_deposit_status = UserDepositStatus {
                 tx_hash: deposit.tx_hash.clone(),
                 amount: deposit.amount,
-                detected_at: Utc::now(),
+                detected_at: now,
                 confirmations: 0, // Initial detection
-                last_checked: Utc::now(),
+                last_checked: now,
             };
 
             self.db
@@ -183,25 +879,19 @@ impl SwapMonitoringService {
                 .user_deposit_detected(swap.id, user_deposit_status)
                 .await
                 .context(DatabaseSnafu)?;
+            self.publish_status_update(swap.id).await;
 
             // Notify MM about user deposit
-            let mm_registry = self.mm_registry.clone();
-            let market_maker_id = swap.market_maker_id;
-            let swap_id = swap.id;
-            let quote_id = swap.quote.id;
-            let user_deposit_address = swap.user_deposit_address.clone();
-            let tx_hash = deposit.tx_hash.clone();
-            tokio::spawn(async move {
-                let _ = mm_registry
-                    .notify_user_deposit(
-                        &market_maker_id,
-                        &swap_id,
-                        &quote_id,
-                        &user_deposit_address,
-                        &tx_hash,
-                    )
-                    .await;
-            });
+            self.mm_registry
+                .notify_user_deposit(
+                    &swap.market_maker_id,
+                    &swap.id,
+                    &swap.quote.id,
+                    &swap.user_deposit_address,
+                    &deposit.tx_hash,
+                    swap.trace_id.as_deref(),
+                )
+                .await;
         }
 
         Ok(())
@@ -218,26 +908,36 @@ impl SwapMonitoringService {
                 })?;
 
         // Get the chain operations for the user's deposit chain
-        let chain_ops = self.chain_registry.get(&quote.from.currency.chain).ok_or(
-            MonitoringError::ChainOperation {
+        let chain_ops = self
+            .chain_registry
+            .for_currency(&quote.from.currency)
+            .ok_or(MonitoringError::ChainOperation {
                 source: otc_chains::Error::ChainNotSupported {
-                    chain: format!("{:?}", quote.from.currency.chain),
+                    chain: quote.from.currency.network().to_string(),
                 },
-            },
-        )?;
+            })?;
 
         // Check confirmation status
         let tx_status = chain_ops
-            .get_tx_status(&user_deposit.tx_hash)
+            .get_tx_status(user_deposit.tx_hash.as_str())
             .await
             .context(ChainOperationSnafu)?;
 
         match tx_status {
-            TxStatus::Confirmed(confirmations) => {
+            TxStatus::Included {
+                confirmations,
+                finality,
+            } => {
                 info!(
-                    "User deposit for swap {} has {} confirmations",
-                    swap.id, confirmations
+                    "User deposit for swap {} has {} confirmations ({:?})",
+                    swap.id, confirmations, finality
                 );
+                if confirmations < user_deposit.confirmations {
+                    warn!(
+                        "User deposit tx {} for swap {} dropped from {} to {} confirmations, likely a reorg",
+                        user_deposit.tx_hash, swap.id, user_deposit.confirmations, confirmations
+                    );
+                }
 
                 // Update confirmations
                 self.db
@@ -248,7 +948,7 @@ impl SwapMonitoringService {
 
                 // Check if we have enough confirmations
                 let (required_user_confirmations, _) = swap.get_required_confirmations();
-                if confirmations >= required_user_confirmations {
+                if tx_status.satisfies(required_user_confirmations) {
                     info!(
                         "User deposit for swap {} has reached required confirmations",
                         swap.id
@@ -257,33 +957,52 @@ impl SwapMonitoringService {
                     // Transition to waiting for MM deposit
                     self.db
                         .swaps()
-                        .user_deposit_confirmed(swap.id)
+                        .user_deposit_confirmed(swap.id, self.clock.now())
                         .await
                         .context(DatabaseSnafu)?;
+                    self.publish_status_update(swap.id).await;
+                    let _ = self.watch_requests.send(swap.id);
 
                     // Notify MM to send their deposit
-                    let mm_registry = self.mm_registry.clone();
-                    let market_maker_id = swap.market_maker_id;
-                    let swap_id = swap.id;
-                    let quote_id = swap.quote.id;
-                    let user_destination_address = swap.user_destination_address.clone();
-                    let mm_nonce = swap.mm_nonce;
-                    let expected_currency = swap.quote.to.clone();
-
-                    tokio::spawn(async move {
-                        let _ = mm_registry
-                            .notify_user_deposit_confirmed(
-                                &market_maker_id,
-                                &swap_id,
-                                &quote_id,
-                                &user_destination_address,
-                                mm_nonce,
-                                &expected_currency,
-                            )
-                            .await;
-                    });
+                    self.mm_registry
+                        .notify_user_deposit_confirmed(
+                            &swap.market_maker_id,
+                            &swap.id,
+                            &swap.quote.id,
+                            &swap.user_destination_address,
+                            swap.mm_nonce,
+                            &swap.quote.to,
+                            &swap.user_deposit_address,
+                            swap.quote.from.currency.chain,
+                            swap.external_reference.as_deref(),
+                            swap.trace_id.as_deref(),
+                        )
+                        .await;
                 }
             }
+            // Seen in a block before, so a reorg took it out
+            TxStatus::NotFound | TxStatus::Pending if user_deposit.confirmations > 0 => {
+                let note = format!(
+                    "Reorg dropped user deposit {} after {} confirmations",
+                    user_deposit.tx_hash, user_deposit.confirmations
+                );
+                warn!("Rolling back swap {}: {}", swap.id, note);
+                let swap = self
+                    .db
+                    .swaps()
+                    .user_deposit_reorged(swap.id, &note)
+                    .await
+                    .context(DatabaseSnafu)?;
+                self.publish_status_update(swap.id).await;
+                // The deposit may be back in the mempool or replaced by another one
+                self.check_user_deposit(&swap).await?;
+            }
+            TxStatus::Pending => {
+                info!(
+                    "User deposit tx {} for swap {} is still pending",
+                    user_deposit.tx_hash, swap.id
+                );
+            }
             TxStatus::NotFound => {
                 warn!(
                     "User deposit tx {} for swap {} not found on chain",
@@ -301,10 +1020,10 @@ impl SwapMonitoringService {
         let quote = &swap.quote;
 
         // Get the chain operations for the MM's deposit chain (to = MM sends)
-        let chain_ops = self.chain_registry.get(&quote.to.currency.chain).ok_or(
+        let chain_ops = self.chain_registry.for_currency(&quote.to.currency).ok_or(
             MonitoringError::ChainOperation {
                 source: otc_chains::Error::ChainNotSupported {
-                    chain: format!("{:?}", quote.to.currency.chain),
+                    chain: quote.to.currency.network().to_string(),
                 },
             },
         )?;
@@ -317,6 +1036,7 @@ impl SwapMonitoringService {
                 Some(MarketMakerPaymentValidation {
                     fee_amount: U256::from(quote.to.compute_protocol_fee()),
                     embedded_nonce: swap.mm_nonce,
+                    not_before: swap.mm_notified_at,
                 }),
                 None,
             )
@@ -329,22 +1049,241 @@ impl SwapMonitoringService {
                 swap.id, deposit.tx_hash, quote.to.currency.chain
             );
 
-            // Update swap state
+            let now = self.clock.now();
             let mm_deposit_status = MMDepositStatus {
                 tx_hash: deposit.tx_hash.clone(),
                 amount: deposit.amount,
-                detected_at: Utc::now(),
+                detected_at: now,
                 confirmations: deposit.confirmations,
-                last_checked: Utc::now(),
+                last_checked: now,
             };
 
-            self.db
+            // The user's deposit key is only released for the quoted amount or more
+            match deposit.amount.cmp(&quote.to.amount) {
+                Ordering::Less => {
+                    return self.reject_mm_deposit(swap, mm_deposit_status).await;
+                }
+                Ordering::Greater => {
+                    warn!(
+                        "MM deposit {} for swap {} overpaid: sent {}, expected {}",
+                        deposit.tx_hash, swap.id, deposit.amount, quote.to.amount
+                    );
+                }
+                Ordering::Equal => {}
+            }
+
+            // Update swap state. One payment can't settle two swaps, whatever
+            // tags it carries, so the write is refused if another swap has it
+            match self
+                .db
                 .swaps()
                 .mm_deposit_detected(swap.id, mm_deposit_status)
                 .await
+            {
+                Err(OtcServerError::MmFillClaimed { tx_hash }) => {
+                    warn!(
+                        "Ignoring MM deposit {} for swap {}, it already settles another swap",
+                        tx_hash, swap.id
+                    );
+                    return Ok(());
+                }
+                result => result.context(DatabaseSnafu)?,
+            }
+            self.publish_status_update(swap.id).await;
+
+            // The search already counted the deposit's confirmations, so one that has
+            // enough settles now instead of waiting on a status check next pass. Chains
+            // with finality still need the check, a count alone doesn't settle there
+            let (_, required_mm_confirmations) = swap.get_required_confirmations();
+            if deposit.confirmations >= required_mm_confirmations {
+                let tx_status = if chain_ops.reports_finality() {
+                    chain_ops
+                        .get_tx_status(deposit.tx_hash.as_str())
+                        .await
+                        .context(ChainOperationSnafu)?
+                } else {
+                    TxStatus::Included {
+                        confirmations: deposit.confirmations,
+                        finality: Finality::Unknown,
+                    }
+                };
+                if matches!(tx_status, TxStatus::Included { .. }) {
+                    self.record_mm_confirmations(swap, deposit.tx_hash.as_str(), tx_status)
+                        .await?;
+                }
+            }
+        } else {
+            self.check_failed_mm_deposit(swap, chain_ops.as_ref())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The MM claimed a deposit the search can't find. Once the grace period
+    /// is up and the claim is neither found nor pending - reverted, dropped or
+    /// never answered - ask the MM to pay again, refunding the user when it has
+    /// used up its retries
+    async fn check_failed_mm_deposit(
+        &self,
+        swap: &Swap,
+        chain_ops: &dyn ChainOperations,
+    ) -> MonitoringResult<()> {
+        let attempts = self
+            .db
+            .swaps()
+            .mm_deposit_attempts(swap.id)
+            .await
+            .context(DatabaseSnafu)?;
+        let (Some(tx_hash), Some(claimed_at)) = (attempts.claimed_tx_hash, attempts.claimed_at)
+        else {
+            return Ok(());
+        };
+
+        let now = self.clock.now();
+        let grace = self.mm_deposit_grace();
+        let last_attempt_at = attempts
+            .retry_requested_at
+            .map_or(claimed_at, |requested_at| requested_at.max(claimed_at));
+        if now - last_attempt_at < grace {
+            return Ok(());
+        }
+
+        // A claim from before the last retry request is the one that already
+        // failed, the MM never answered the request
+        let answered_retry = attempts
+            .retry_requested_at
+            .is_none_or(|requested_at| claimed_at > requested_at);
+        if answered_retry {
+            let tx_status = chain_ops
+                .get_tx_status(tx_hash.as_str())
+                .await
+                .context(ChainOperationSnafu)?;
+            if matches!(tx_status, TxStatus::Pending) {
+                info!(
+                    "MM deposit tx {} for swap {} is still pending",
+                    tx_hash, swap.id
+                );
+                return Ok(());
+            }
+            // Included without paying the swap means it reverted or paid
+            // someone else, not found means it was dropped
+            warn!(
+                "MM deposit tx {} for swap {} failed on chain ({:?})",
+                tx_hash, swap.id, tx_status
+            );
+        }
+
+        if attempts.retries >= self.mm_deposit_retry.max_retries {
+            let reason = format!(
+                "MM deposit {tx_hash} failed after {} retries",
+                attempts.retries
+            );
+            warn!("Refunding swap {}: {}", swap.id, reason);
+            self.db
+                .swaps()
+                .initiate_user_refund(swap.id, &reason)
+                .await
                 .context(DatabaseSnafu)?;
+            self.publish_status_update(swap.id).await;
+
+            refund_user(&self.db, &self.chain_registry, swap).await;
+
+            self.mm_registry
+                .notify_swap_failed(
+                    &swap.market_maker_id,
+                    &swap.id,
+                    &swap.quote.id,
+                    SwapFailureReason::MMDepositTimeout,
+                    None,
+                    swap.trace_id.as_deref(),
+                )
+                .await;
+            return Ok(());
+        }
+
+        let attempt = self
+            .db
+            .swaps()
+            .record_mm_deposit_retry(swap.id, now)
+            .await
+            .context(DatabaseSnafu)?;
+        info!(
+            "Asking MM to retry deposit {} for swap {} (attempt {} of {})",
+            tx_hash, swap.id, attempt, self.mm_deposit_retry.max_retries
+        );
+        self.mm_registry
+            .notify_deposit_retry_requested(swap, &tx_hash, attempt, now + grace)
+            .await;
+
+        Ok(())
+    }
+
+    /// Fail a swap whose MM deposit pays less than the quote and refund the user
+    async fn reject_mm_deposit(
+        &self,
+        swap: &Swap,
+        mm_deposit_status: MMDepositStatus,
+    ) -> MonitoringResult<()> {
+        let expected_amount = swap.quote.to.amount;
+        let received_amount = mm_deposit_status.amount;
+        let tx_hash = mm_deposit_status.tx_hash.clone();
+        error!(
+            "MM deposit {} for swap {} underpaid: sent {}, expected {}",
+            tx_hash, swap.id, received_amount, expected_amount
+        );
+
+        match self
+            .db
+            .swaps()
+            .mm_deposit_amount_mismatch(swap.id, mm_deposit_status)
+            .await
+        {
+            Err(OtcServerError::MmFillClaimed { tx_hash }) => {
+                warn!(
+                    "Ignoring underpaid MM deposit {} for swap {}, it already settles another swap",
+                    tx_hash, swap.id
+                );
+                return Ok(());
+            }
+            result => result.context(DatabaseSnafu)?,
         }
 
+        self.mm_registry
+            .notify_mm_deposit_rejected(
+                &swap.market_maker_id,
+                &swap.id,
+                &swap.quote.id,
+                &tx_hash,
+                expected_amount,
+                received_amount,
+                swap.trace_id.as_deref(),
+            )
+            .await;
+
+        self.db
+            .swaps()
+            .initiate_user_refund(
+                swap.id,
+                &format!("MM deposit {tx_hash} sent {received_amount}, expected {expected_amount}"),
+            )
+            .await
+            .context(DatabaseSnafu)?;
+        self.publish_status_update(swap.id).await;
+
+        refund_user(&self.db, &self.chain_registry, swap).await;
+
+        self.mm_registry
+            .notify_swap_failed(
+                &swap.market_maker_id,
+                &swap.id,
+                &swap.quote.id,
+                SwapFailureReason::AmountMismatch,
+                None,
+                swap.trace_id.as_deref(),
+            )
+            .await;
+
         Ok(())
     }
 
@@ -359,87 +1298,70 @@ impl SwapMonitoringService {
                 })?;
 
         // Get the chain operations for the MM's deposit chain
-        let chain_ops = self.chain_registry.get(&quote.to.currency.chain).ok_or(
+        let chain_ops = self.chain_registry.for_currency(&quote.to.currency).ok_or(
             MonitoringError::ChainOperation {
                 source: otc_chains::Error::ChainNotSupported {
-                    chain: format!("{:?}", quote.to.currency.chain),
+                    chain: quote.to.currency.network().to_string(),
                 },
             },
         )?;
 
         // Check confirmation status
         let tx_status = chain_ops
-            .get_tx_status(&mm_deposit.tx_hash)
+            .get_tx_status(mm_deposit.tx_hash.as_str())
             .await
             .context(ChainOperationSnafu)?;
 
         match tx_status {
-            TxStatus::Confirmed(confirmations) => {
+            TxStatus::Included { confirmations, .. } => {
+                if confirmations < mm_deposit.confirmations {
+                    warn!(
+                        "MM deposit tx {} for swap {} dropped from {} to {} confirmations, likely a reorg",
+                        mm_deposit.tx_hash, swap.id, mm_deposit.confirmations, confirmations
+                    );
+                }
+                self.record_mm_confirmations(swap, mm_deposit.tx_hash.as_str(), tx_status)
+                    .await?;
+            }
+            // Seen in a block before, so a reorg took it out
+            TxStatus::NotFound | TxStatus::Pending if mm_deposit.confirmations > 0 => {
+                let note = format!(
+                    "Reorg dropped MM deposit {} after {} confirmations",
+                    mm_deposit.tx_hash, mm_deposit.confirmations
+                );
+                warn!("Rolling back swap {}: {}", swap.id, note);
+                let swap = self
+                    .db
+                    .swaps()
+                    .mm_deposit_reorged(swap.id, &note)
+                    .await
+                    .context(DatabaseSnafu)?;
+                self.publish_status_update(swap.id).await;
+                self.check_mm_deposit(&swap).await?;
+            }
+            TxStatus::Pending => {
                 info!(
-                    "MM deposit for swap {} has {} confirmations",
-                    swap.id, confirmations
+                    "MM deposit tx {} for swap {} is still pending",
+                    mm_deposit.tx_hash, swap.id
                 );
-
-                // Update confirmations
-                self.db
+            }
+            // Never made it into a block and has since left the mempool
+            TxStatus::NotFound
+                if self.clock.now() - mm_deposit.detected_at >= self.mm_deposit_grace() =>
+            {
+                let note = format!(
+                    "MM deposit {} dropped from the mempool before confirming",
+                    mm_deposit.tx_hash
+                );
+                warn!("Rolling back swap {}: {}", swap.id, note);
+                let swap = self
+                    .db
                     .swaps()
-                    .update_mm_confirmations(swap.id, confirmations as u32)
+                    .mm_deposit_reorged(swap.id, &note)
                     .await
                     .context(DatabaseSnafu)?;
-
-                // Check if we have enough confirmations
-                let (_, required_mm_confirmations) = swap.get_required_confirmations();
-                if confirmations >= required_mm_confirmations {
-                    info!(
-                        "MM deposit for swap {} has reached required confirmations",
-                        swap.id
-                    );
-
-                    // Transition to settled state
-                    self.db
-                        .swaps()
-                        .mm_deposit_confirmed(swap.id)
-                        .await
-                        .context(DatabaseSnafu)?;
-
-                    // Send private key to MM
-                    let chain_ops = self.chain_registry.get(&quote.from.currency.chain).ok_or(
-                        MonitoringError::ChainOperation {
-                            source: otc_chains::Error::ChainNotSupported {
-                                chain: format!("{:?}", quote.from.currency.chain),
-                            },
-                        },
-                    )?;
-
-                    let user_wallet = chain_ops
-                        .derive_wallet(&self.settings.master_key_bytes(), &swap.user_deposit_salt)
-                        .context(ChainOperationSnafu)?;
-
-                    let mm_registry = self.mm_registry.clone();
-                    let market_maker_id = swap.market_maker_id;
-                    let swap_id = swap.id;
-                    let private_key = user_wallet.private_key().to_string();
-                    let mm_tx_hash = mm_deposit.tx_hash.clone();
-                    let chain = quote.from.currency.chain;
-                    tokio::spawn(async move {
-                        let _ = mm_registry
-                            .notify_swap_complete(
-                                &market_maker_id,
-                                &swap_id,
-                                &private_key,
-                                chain,
-                                &mm_tx_hash,
-                            )
-                            .await;
-                    });
-
-                    // Mark private key as sent
-                    self.db
-                        .swaps()
-                        .mark_private_key_sent(swap.id)
-                        .await
-                        .context(DatabaseSnafu)?;
-                }
+                self.publish_status_update(swap.id).await;
+                self.check_mm_deposit(&swap).await?;
             }
             TxStatus::NotFound => {
                 warn!(
@@ -452,11 +1374,123 @@ impl SwapMonitoringService {
         Ok(())
     }
 
+    /// Record the MM deposit's confirmations, settling the swap once it has enough
+    async fn record_mm_confirmations(
+        &self,
+        swap: &Swap,
+        mm_tx_hash: &str,
+        tx_status: TxStatus,
+    ) -> MonitoringResult<()> {
+        let quote = &swap.quote;
+        let confirmations = tx_status.confirmations();
+        info!(
+            "MM deposit for swap {} has {} confirmations ({:?})",
+            swap.id, confirmations, tx_status
+        );
+
+        // Update confirmations
+        self.db
+            .swaps()
+            .update_mm_confirmations(swap.id, confirmations as u32)
+            .await
+            .context(DatabaseSnafu)?;
+
+        // Check if we have enough confirmations
+        let (_, required_mm_confirmations) = swap.get_required_confirmations();
+        if tx_status.satisfies(required_mm_confirmations) {
+            info!(
+                "MM deposit for swap {} has reached required confirmations",
+                swap.id
+            );
+
+            // Transition to settled state
+            self.db
+                .swaps()
+                .mm_deposit_confirmed(swap.id)
+                .await
+                .context(DatabaseSnafu)?;
+            self.publish_status_update(swap.id).await;
+
+            // Send private key to MM
+            let chain_ops = self
+                .chain_registry
+                .for_currency(&quote.from.currency)
+                .ok_or(MonitoringError::ChainOperation {
+                    source: otc_chains::Error::ChainNotSupported {
+                        chain: quote.from.currency.network().to_string(),
+                    },
+                })?;
+
+            let master_key = self
+                .settings
+                .master_key_bytes(swap.master_key_version)
+                .context(MasterKeyUnavailableSnafu)?;
+            let user_wallet = chain_ops
+                .derive_wallet(&master_key, &swap.user_deposit_salt)
+                .context(ChainOperationSnafu)?;
+
+            // Run the same check the MM will, so a bad key is never released
+            let key_controls_deposit = chain_ops
+                .private_key_controls_address(user_wallet.private_key(), &swap.user_deposit_address)
+                .context(ChainOperationSnafu)?;
+            if !key_controls_deposit {
+                let reason = format!(
+                    "Derived deposit key does not control deposit address {}",
+                    swap.user_deposit_address
+                );
+                error!("ALERT: swap {} needs manual review: {}", swap.id, reason);
+                self.db
+                    .swaps()
+                    .flag_for_manual_review(swap.id, &reason)
+                    .await
+                    .context(DatabaseSnafu)?;
+                self.publish_status_update(swap.id).await;
+                return Ok(());
+            }
+
+            self.mm_registry
+                .notify_swap_complete(
+                    &swap.market_maker_id,
+                    &swap.id,
+                    user_wallet.private_key(),
+                    quote.from.currency.chain,
+                    mm_tx_hash,
+                    swap.trace_id.as_deref(),
+                )
+                .await;
+
+            // Mark private key as sent
+            self.db
+                .swaps()
+                .mark_private_key_sent(swap.id)
+                .await
+                .context(DatabaseSnafu)?;
+        }
+
+        Ok(())
+    }
+
+    fn mm_deposit_grace(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.mm_deposit_retry.grace).unwrap_or(chrono::Duration::MAX)
+    }
+
+    /// Send the swap's MM where the swap stands after a status change, once per
+    /// change rather than every pass. Best effort, a failed send is only counted
+    async fn publish_status_update(&self, swap_id: Uuid) {
+        match self.db.swaps().get(swap_id).await {
+            Ok(swap) => self.mm_registry.notify_swap_status(&swap).await,
+            Err(e) => {
+                warn!("Not sending status update for swap {}: {}", swap_id, e);
+                self.mm_registry.record_status_update_dropped();
+            }
+        }
+    }
+
     /// Handle swap timeout
     async fn handle_failure(&self, swap: &Swap) -> MonitoringResult<()> {
         warn!("Swap {} has timed out in state {:?}", swap.id, swap.status);
 
-        match swap.status {
+        let reason = match swap.status {
             SwapStatus::WaitingUserDepositInitiated => {
                 // No deposits yet, just mark as failed
                 self.db
@@ -464,6 +1498,7 @@ impl SwapMonitoringService {
                     .mark_failed(swap.id, "Failed waiting for user deposit")
                     .await
                     .context(DatabaseSnafu)?;
+                SwapFailureReason::UserDepositTimeout
             }
             SwapStatus::WaitingUserDepositConfirmed => {
                 // User deposited but MM didn't, refund user
@@ -473,8 +1508,8 @@ impl SwapMonitoringService {
                     .await
                     .context(DatabaseSnafu)?;
 
-                // TODO: Actually execute the refund
-                info!("TODO: Execute user refund for swap {}", swap.id);
+                refund_user(&self.db, &self.chain_registry, swap).await;
+                SwapFailureReason::UserDepositTimeout
             }
             SwapStatus::WaitingMMDepositConfirmed | SwapStatus::Settled => {
                 // MM deposited, refund MM
@@ -486,12 +1521,749 @@ impl SwapMonitoringService {
 
                 // TODO: Actually execute the refund
                 info!("TODO: Execute MM refund for swap {}", swap.id);
+                SwapFailureReason::MMDepositTimeout
             }
             _ => {
                 // Other states don't need timeout handling
+                return Ok(());
+            }
+        };
+
+        self.publish_status_update(swap.id).await;
+
+        // No refund has been broadcast yet, see the TODOs above
+        self.mm_registry
+            .notify_swap_failed(
+                &swap.market_maker_id,
+                &swap.id,
+                &swap.quote.id,
+                reason,
+                None,
+                swap.trace_id.as_deref(),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::hex;
+    use chrono::{Duration as ChronoDuration, SubsecRound, Utc};
+    use common::{ManualClock, SystemClock};
+    use otc_chains::test_utils::StubChain;
+    use otc_models::{SwapBuilder, TransferInfo, TxHash};
+    use std::collections::HashSet;
+
+    /// A Bitcoin tx hash repeating `byte`, as user deposits are made in
+    fn user_tx(byte: u8) -> TxHash {
+        TxHash::parse(ChainType::Bitcoin, &format!("{byte:02x}").repeat(32)).unwrap()
+    }
+
+    /// An Ethereum tx hash repeating `byte`, as MM deposits are made in
+    fn mm_tx(byte: u8) -> TxHash {
+        TxHash::parse(ChainType::Ethereum, &format!("{byte:02x}").repeat(32)).unwrap()
+    }
+
+    fn waiting_swap(salt: [u8; 32]) -> Swap {
+        SwapBuilder::new()
+            .with_deposit(salt, hex::encode(salt))
+            .with_confirmations(1, 1)
+            .build()
+    }
+
+    /// Runs one monitoring pass with `concurrency`, returning its duration and
+    /// the deposit addresses it searched
+    async fn timed_pass(
+        db: &Database,
+        concurrency: usize,
+        shutdown: &Shutdown,
+    ) -> (Duration, HashSet<String>) {
+        let chain = Arc::new(StubChain::new().with_delay(Duration::from_millis(100)));
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, chain.clone());
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let service = Arc::new(SwapMonitoringService::new(
+            db.clone(),
+            Arc::new(Settings::load(&settings_path).unwrap()),
+            Arc::new(chain_registry),
+            Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+            HashMap::new(),
+            concurrency,
+            Arc::new(SystemClock),
+        ));
+
+        let started = Instant::now();
+        let swap_count = service
+            .monitor_chain_swaps(ChainType::Bitcoin, shutdown)
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+        let _ = std::fs::remove_file(settings_path);
+
+        let searched = chain.searched();
+        assert_eq!(swap_count, searched.len());
+        (elapsed, searched)
+    }
+
+    #[sqlx::test]
+    async fn test_monitoring_checks_swaps_concurrently(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+
+        let mut addresses = HashSet::new();
+        for i in 0..32u8 {
+            let swap = waiting_swap([i; 32]);
+            addresses.insert(swap.user_deposit_address.clone());
+            db.swaps().create(&swap).await.unwrap();
+        }
+
+        let (sequential, searched) = timed_pass(&db, 1, &Shutdown::new()).await;
+        assert_eq!(searched, addresses);
+
+        let (concurrent, searched) = timed_pass(&db, 16, &Shutdown::new()).await;
+        assert_eq!(searched, addresses);
+
+        // 32 searches of 100ms take at least 3.2s one at a time, ~200ms 16 at a time
+        assert!(sequential >= Duration::from_millis(3_200));
+        assert!(
+            concurrent < sequential / 4,
+            "concurrent pass took {concurrent:?}, sequential {sequential:?}"
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_shutdown_finishes_started_checks_only(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        for i in 0..16u8 {
+            db.swaps().create(&waiting_swap([i; 32])).await.unwrap();
+        }
+
+        let shutdown = Shutdown::new();
+        tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                time::sleep(Duration::from_millis(150)).await;
+                shutdown.trigger();
+            }
+        });
+        let (elapsed, searched) = timed_pass(&db, 1, &shutdown).await;
+
+        // Checks of 100ms one at a time, the one running at 150ms is finished
+        assert_eq!(searched.len(), 2, "searched {searched:?}");
+        assert!(elapsed < Duration::from_secs(1), "pass took {elapsed:?}");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_tick_once_runs_one_pass_over_the_monitored_chains(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let mut addresses = HashSet::new();
+        for i in 0..3u8 {
+            let swap = waiting_swap([i; 32]);
+            addresses.insert(swap.user_deposit_address.clone());
+            db.swaps().create(&swap).await.unwrap();
+        }
+        let chain = Arc::new(StubChain::new());
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, chain.clone());
+        let chain_registry = Arc::new(chain_registry);
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let service = |intervals| {
+            Arc::new(SwapMonitoringService::new(
+                db.clone(),
+                Arc::new(Settings::load(&settings_path).unwrap()),
+                chain_registry.clone(),
+                Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+                intervals,
+                4,
+                Arc::new(SystemClock),
+            ))
+        };
+
+        // The interval is never waited on, tick_once runs the pass immediately
+        let monitored = service(HashMap::from([(
+            ChainType::Bitcoin,
+            Duration::from_secs(3600),
+        )]));
+        assert_eq!(monitored.tick_once().await.unwrap(), 3);
+        assert_eq!(chain.searched(), addresses);
+        let passes = monitored.monitoring_passes().latest();
+        let pass = &passes[&ChainType::Bitcoin];
+        assert_eq!(pass.result, Ok(3));
+        assert_eq!(pass.interval, Duration::from_secs(3600));
+
+        let unmonitored = service(HashMap::new());
+        assert_eq!(unmonitored.tick_once().await.unwrap(), 0);
+        assert!(unmonitored.monitoring_passes().latest().is_empty());
+        let _ = std::fs::remove_file(settings_path);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_confirmed_mm_deposit_settles_without_status_check(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let mut swap = waiting_swap([7; 32]);
+        swap.status = SwapStatus::WaitingMMDepositInitiated;
+        swap.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: user_tx(0xa1),
+            amount: swap.quote.from.amount,
+            detected_at: Utc::now(),
+            confirmations: 1,
+            last_checked: Utc::now(),
+        });
+        db.swaps().create(&swap).await.unwrap();
+
+        let chain = Arc::new(StubChain::new().with_any_transfer(TransferInfo {
+            tx_hash: mm_tx(0xb1),
+            amount: swap.quote.to.amount,
+            detected_at: Utc::now(),
+            confirmations: swap.mm_required_confirmations,
+        }));
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, chain.clone());
+        chain_registry.register(ChainType::Ethereum, chain.clone());
+        // Detection is stamped with the service's clock, not the host's
+        let clock = ManualClock::new(Utc::now().trunc_subsecs(0) - ChronoDuration::minutes(5));
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let service = SwapMonitoringService::new(
+            db.clone(),
+            Arc::new(Settings::load(&settings_path).unwrap()),
+            Arc::new(chain_registry),
+            Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+            HashMap::new(),
+            1,
+            Arc::new(clock.clone()),
+        );
+
+        service.monitor_swap(&swap).await.unwrap();
+        let _ = std::fs::remove_file(settings_path);
+
+        let swap = db.swaps().get(swap.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::Settled);
+        let mm_deposit_status = swap.mm_deposit_status.unwrap();
+        assert_eq!(mm_deposit_status.tx_hash, mm_tx(0xb1));
+        assert_eq!(mm_deposit_status.detected_at, clock.now());
+        assert!(swap.mm_private_key_sent_at.is_some());
+        // The search counted the confirmations
+        assert_eq!(chain.status_checks(), 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_one_mm_deposit_settles_one_swap_per_destination(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let mut swaps = Vec::new();
+        for salt in [[8; 32], [9; 32]] {
+            let mut swap = waiting_swap(salt);
+            swap.status = SwapStatus::WaitingMMDepositInitiated;
+            swap.user_deposit_status = Some(UserDepositStatus {
+                tx_hash: user_tx(salt[0]),
+                amount: swap.quote.from.amount,
+                detected_at: Utc::now(),
+                confirmations: 1,
+                last_checked: Utc::now(),
+            });
+            db.swaps().create(&swap).await.unwrap();
+            swaps.push(swap);
+        }
+        assert_eq!(
+            swaps[0].user_destination_address,
+            swaps[1].user_destination_address
+        );
+
+        // A chain that matches the one tagged payment to both swaps
+        let chain = Arc::new(StubChain::new().with_any_transfer(TransferInfo {
+            tx_hash: mm_tx(0xb2),
+            amount: swaps[0].quote.to.amount,
+            detected_at: Utc::now(),
+            confirmations: swaps[0].mm_required_confirmations,
+        }));
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, chain.clone());
+        chain_registry.register(ChainType::Ethereum, chain);
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let service = SwapMonitoringService::new(
+            db.clone(),
+            Arc::new(Settings::load(&settings_path).unwrap()),
+            Arc::new(chain_registry),
+            Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+            HashMap::new(),
+            1,
+            Arc::new(SystemClock),
+        );
+
+        for swap in &swaps {
+            service.monitor_swap(swap).await.unwrap();
+        }
+        let _ = std::fs::remove_file(settings_path);
+
+        let settled = db.swaps().get(swaps[0].id).await.unwrap();
+        assert_eq!(settled.status, SwapStatus::Settled);
+        let other = db.swaps().get(swaps[1].id).await.unwrap();
+        assert_eq!(other.status, SwapStatus::WaitingMMDepositInitiated);
+        assert!(other.mm_deposit_status.is_none());
+        assert!(other.mm_private_key_sent_at.is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_reorged_deposits_roll_swaps_back(pool: sqlx::PgPool) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let deposit = |tx_hash, amount, confirmations| UserDepositStatus {
+            tx_hash,
+            amount,
+            detected_at: Utc::now(),
+            confirmations,
+            last_checked: Utc::now(),
+        };
+
+        let mut user_reorged = waiting_swap([1; 32]);
+        user_reorged.status = SwapStatus::WaitingUserDepositConfirmed;
+        user_reorged.user_required_confirmations = 2;
+        user_reorged.user_deposit_status =
+            Some(deposit(user_tx(0xa1), user_reorged.quote.from.amount, 1));
+
+        // Never mined, so still in the mempool rather than reorged out
+        let mut user_pending = waiting_swap([2; 32]);
+        user_pending.status = SwapStatus::WaitingUserDepositConfirmed;
+        user_pending.user_deposit_status =
+            Some(deposit(user_tx(0xa2), user_pending.quote.from.amount, 0));
+
+        let mut mm_reorged = waiting_swap([3; 32]);
+        mm_reorged.status = SwapStatus::WaitingMMDepositConfirmed;
+        mm_reorged.mm_required_confirmations = 2;
+        mm_reorged.user_deposit_status =
+            Some(deposit(user_tx(0xa1), mm_reorged.quote.from.amount, 2));
+        mm_reorged.mm_deposit_status = Some(MMDepositStatus {
+            tx_hash: mm_tx(0xb1),
+            amount: mm_reorged.quote.to.amount,
+            detected_at: Utc::now(),
+            confirmations: 1,
+            last_checked: Utc::now(),
+        });
+
+        for swap in [&user_reorged, &user_pending, &mm_reorged] {
+            db.swaps().create(swap).await.unwrap();
+        }
+
+        // The user deposit made it into another block, the MM deposit is gone
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(
+            ChainType::Bitcoin,
+            Arc::new(StubChain::new().with_any_transfer(TransferInfo {
+                tx_hash: user_tx(0xa1),
+                amount: user_reorged.quote.from.amount,
+                detected_at: Utc::now(),
+                confirmations: 0,
+            })),
+        );
+        chain_registry.register(ChainType::Ethereum, Arc::new(StubChain::new()));
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let service = SwapMonitoringService::new(
+            db.clone(),
+            Arc::new(Settings::load(&settings_path).unwrap()),
+            Arc::new(chain_registry),
+            Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+            HashMap::new(),
+            1,
+            Arc::new(SystemClock),
+        );
+
+        for swap in [&user_reorged, &user_pending, &mm_reorged] {
+            service.monitor_swap(swap).await.unwrap();
+        }
+        let _ = std::fs::remove_file(settings_path);
+
+        // Rolled back, then found again by the search that follows
+        let swap = db.swaps().get(user_reorged.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingUserDepositConfirmed);
+        assert_eq!(swap.user_deposit_status.unwrap().confirmations, 0);
+        let events = db.swap_events().get_for_swap(swap.id).await.unwrap();
+        let reorg_note = format!(
+            "Reorg dropped user deposit {} after 1 confirmations",
+            user_tx(0xa1)
+        );
+        let transitions: Vec<_> = events
+            .iter()
+            .map(|e| (e.from_status, e.to_status, e.note.as_deref()))
+            .collect();
+        assert_eq!(
+            transitions[1..],
+            [
+                (
+                    Some(SwapStatus::WaitingUserDepositConfirmed),
+                    SwapStatus::WaitingUserDepositInitiated,
+                    Some(reorg_note.as_str()),
+                ),
+                (
+                    Some(SwapStatus::WaitingUserDepositInitiated),
+                    SwapStatus::WaitingUserDepositConfirmed,
+                    None,
+                ),
+            ]
+        );
+
+        let swap = db.swaps().get(user_pending.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingUserDepositConfirmed);
+        assert_eq!(swap.user_deposit_status.unwrap().tx_hash, user_tx(0xa2));
+
+        let swap = db.swaps().get(mm_reorged.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingMMDepositInitiated);
+        assert!(swap.mm_deposit_status.is_none());
+        assert!(swap.mm_private_key_sent_at.is_none());
+        let events = db.swap_events().get_for_swap(swap.id).await.unwrap();
+        assert_eq!(
+            events.last().unwrap().note,
+            Some(format!(
+                "Reorg dropped MM deposit {} after 1 confirmations",
+                mm_tx(0xb1)
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_failed_mm_deposits_are_retried_then_refunded(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let mut swap = waiting_swap([5; 32]);
+        swap.status = SwapStatus::WaitingMMDepositInitiated;
+        swap.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: user_tx(0xa1),
+            amount: swap.quote.from.amount,
+            detected_at: Utc::now(),
+            confirmations: 1,
+            last_checked: Utc::now(),
+        });
+        db.swaps().create(&swap).await.unwrap();
+        db.swaps()
+            .record_mm_claimed_deposit(swap.id, &mm_tx(0xb1), None, None)
+            .await
+            .unwrap();
+
+        let mm_registry = Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5)));
+        let (mm_tx, mut mm_rx) = tokio::sync::mpsc::channel(10);
+        mm_registry.register(
+            swap.market_maker_id,
+            mm_tx,
+            otc_protocols::mm::DEPOSIT_RETRY_VERSION.to_string(),
+        );
+        let clock = ManualClock::new(Utc::now().trunc_subsecs(0));
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let settings = Arc::new(Settings::load(&settings_path).unwrap());
+        let _ = std::fs::remove_file(&settings_path);
+        let service_with = |status| {
+            let mut chain_registry = ChainRegistry::new();
+            chain_registry.register(
+                ChainType::Ethereum,
+                Arc::new(
+                    StubChain::new()
+                        .with_default_tx_status(status)
+                        .with_finality(),
+                ),
+            );
+            SwapMonitoringService::new(
+                db.clone(),
+                settings.clone(),
+                Arc::new(chain_registry),
+                mm_registry.clone(),
+                HashMap::new(),
+                1,
+                Arc::new(clock.clone()),
+            )
+            .with_mm_deposit_retry(MMDepositRetryPolicy {
+                grace: Duration::from_secs(60),
+                max_retries: 2,
+            })
+        };
+        let dropped = || service_with(TxStatus::NotFound);
+        let swap_id = swap.id;
+        let pass = |service: SwapMonitoringService| {
+            let db = db.clone();
+            async move {
+                let swap = db.swaps().get(swap_id).await.unwrap();
+                service.monitor_swap(&swap).await.unwrap();
+                db.swaps().mm_deposit_attempts(swap_id).await.unwrap()
             }
+        };
+
+        // Inside the grace period nothing is judged yet
+        assert_eq!(pass(dropped()).await.retries, 0);
+
+        // A claim still in the mempool is left alone
+        clock.advance(ChronoDuration::minutes(2));
+        assert_eq!(pass(service_with(TxStatus::Pending)).await.retries, 0);
+
+        let attempts = pass(dropped()).await;
+        assert_eq!(attempts.retries, 1);
+        assert_eq!(attempts.retry_requested_at, Some(clock.now()));
+        match mm_rx.try_recv().unwrap().payload {
+            otc_protocols::mm::MMRequest::DepositRetryRequested {
+                failed_tx_hash,
+                attempt,
+                deadline,
+                ..
+            } => {
+                assert_eq!(failed_tx_hash, mm_tx(0xb1));
+                assert_eq!(attempt, 1);
+                assert_eq!(deadline, clock.now() + ChronoDuration::minutes(1));
+            }
+            other => panic!("expected a deposit retry request, got {other:?}"),
         }
 
+        // The MM never answers, each grace period costs it a retry
+        assert_eq!(pass(dropped()).await.retries, 1);
+        clock.advance(ChronoDuration::minutes(2));
+        assert_eq!(pass(dropped()).await.retries, 2);
+        clock.advance(ChronoDuration::minutes(2));
+        assert_eq!(pass(dropped()).await.retries, 2);
+
+        let swap = db.swaps().get(swap.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::RefundingUser);
+        assert_eq!(
+            swap.failure_reason,
+            Some(format!("MM deposit {} failed after 2 retries", mm_tx(0xb1)))
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_user_deposit_confirmation_respects_finality(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+        let mut swap = waiting_swap([4; 32]);
+        swap.status = SwapStatus::WaitingUserDepositConfirmed;
+        swap.user_required_confirmations = 6;
+        swap.user_deposit_status = Some(UserDepositStatus {
+            tx_hash: user_tx(0xa1),
+            amount: swap.quote.from.amount,
+            detected_at: Utc::now(),
+            confirmations: 1,
+            last_checked: Utc::now(),
+        });
+        db.swaps().create(&swap).await.unwrap();
+
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let settings = Arc::new(Settings::load(&settings_path).unwrap());
+        let _ = std::fs::remove_file(&settings_path);
+        let service_with = |status| {
+            let mut chain_registry = ChainRegistry::new();
+            chain_registry.register(
+                ChainType::Bitcoin,
+                Arc::new(
+                    StubChain::new()
+                        .with_default_tx_status(status)
+                        .with_finality(),
+                ),
+            );
+            SwapMonitoringService::new(
+                db.clone(),
+                settings.clone(),
+                Arc::new(chain_registry),
+                Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+                HashMap::new(),
+                1,
+                Arc::new(SystemClock),
+            )
+        };
+
+        // Well past the required count but ahead of the safe block
+        service_with(TxStatus::Included {
+            confirmations: 100,
+            finality: Finality::Unsafe,
+        })
+        .monitor_swap(&swap)
+        .await
+        .unwrap();
+        let swap = db.swaps().get(swap.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingUserDepositConfirmed);
+        assert_eq!(
+            swap.user_deposit_status.as_ref().unwrap().confirmations,
+            100
+        );
+
+        // Finalized settles it whatever the count
+        service_with(TxStatus::Included {
+            confirmations: 2,
+            finality: Finality::Finalized,
+        })
+        .monitor_swap(&swap)
+        .await
+        .unwrap();
+        let swap = db.swaps().get(swap.id).await.unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingMMDepositInitiated);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refund_amount_deducts_the_fee_from_native_deposits() {
+        let currency = |token| Currency {
+            chain: ChainType::Ethereum,
+            token,
+            decimals: 8,
+            chain_id: None,
+        };
+        let native = currency(TokenIdentifier::Native);
+        let token = currency(TokenIdentifier::Address(
+            "0x1234567890123456789012345678901234567890".to_string(),
+        ));
+
+        assert_eq!(
+            refund_amount(&native, U256::from(100_000), U256::from(1_500)),
+            U256::from(98_500)
+        );
+        // A fee above the deposit leaves nothing to send back
+        assert_eq!(
+            refund_amount(&native, U256::from(1_000), U256::from(1_500)),
+            U256::ZERO
+        );
+        assert_eq!(
+            refund_amount(&token, U256::from(100_000), U256::from(1_500)),
+            U256::from(100_000)
+        );
+    }
+
+    #[test]
+    fn test_chain_monitor_interval_parsing() {
+        assert_eq!(
+            "bitcoin=30".parse::<ChainMonitorInterval>().unwrap(),
+            ChainMonitorInterval {
+                chain: Some(ChainType::Bitcoin),
+                seconds: 30,
+            }
+        );
+        assert_eq!(
+            "5".parse::<ChainMonitorInterval>().unwrap(),
+            ChainMonitorInterval {
+                chain: None,
+                seconds: 5,
+            }
+        );
+        for interval in ["ethereum=3", "7"] {
+            let parsed: ChainMonitorInterval = interval.parse().unwrap();
+            assert_eq!(parsed.to_string(), interval);
+        }
+        for invalid in ["solana=3", "bitcoin=", "0", "ethereum=soon"] {
+            assert!(
+                invalid.parse::<ChainMonitorInterval>().is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_monitor_intervals_fall_back_to_block_time() {
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(
+            ChainType::Bitcoin,
+            Arc::new(StubChain::new().with_block_time(Duration::from_secs(600))),
+        );
+        chain_registry.register(
+            ChainType::Ethereum,
+            Arc::new(StubChain::new().with_block_time(Duration::from_secs(12))),
+        );
+        let parse = |intervals: &[&str]| -> Vec<ChainMonitorInterval> {
+            intervals.iter().map(|i| i.parse().unwrap()).collect()
+        };
+
+        let intervals = resolve_monitor_intervals(&[], &chain_registry);
+        assert_eq!(intervals[&ChainType::Bitcoin], Duration::from_secs(60));
+        assert_eq!(intervals[&ChainType::Ethereum], MIN_CHAIN_MONITOR_INTERVAL);
+
+        let intervals = resolve_monitor_intervals(&parse(&["ethereum=3"]), &chain_registry);
+        assert_eq!(intervals[&ChainType::Bitcoin], Duration::from_secs(60));
+        assert_eq!(intervals[&ChainType::Ethereum], Duration::from_secs(3));
+
+        // A chain's own entry wins over the one for every chain
+        let intervals = resolve_monitor_intervals(&parse(&["bitcoin=30", "5"]), &chain_registry);
+        assert_eq!(intervals[&ChainType::Bitcoin], Duration::from_secs(30));
+        assert_eq!(intervals[&ChainType::Ethereum], Duration::from_secs(5));
+    }
+
+    #[sqlx::test]
+    async fn test_each_chain_is_checked_at_its_own_interval(
+        pool: sqlx::PgPool,
+    ) -> sqlx::Result<()> {
+        let db = Database::from_pool(pool).await.unwrap();
+
+        // One swap waiting on a bitcoin deposit, one on an ethereum deposit
+        let bitcoin_swap = waiting_swap([1; 32]);
+        let mut ethereum_swap = waiting_swap([2; 32]);
+        std::mem::swap(&mut ethereum_swap.quote.from, &mut ethereum_swap.quote.to);
+        assert_eq!(monitored_chain(&bitcoin_swap), ChainType::Bitcoin);
+        assert_eq!(monitored_chain(&ethereum_swap), ChainType::Ethereum);
+        for swap in [&bitcoin_swap, &ethereum_swap] {
+            db.swaps().create(swap).await.unwrap();
+        }
+
+        let bitcoin = Arc::new(StubChain::new().with_block_time(Duration::from_secs(600)));
+        let ethereum = Arc::new(StubChain::new().with_block_time(Duration::from_secs(12)));
+        let mut chain_registry = ChainRegistry::new();
+        chain_registry.register(ChainType::Bitcoin, bitcoin.clone());
+        chain_registry.register(ChainType::Ethereum, ethereum.clone());
+        let settings_path =
+            std::env::temp_dir().join(format!("otc-monitoring-test-{}.toml", Uuid::new_v4()));
+        let service = Arc::new(SwapMonitoringService::new(
+            db.clone(),
+            Arc::new(Settings::load(&settings_path).unwrap()),
+            Arc::new(chain_registry),
+            Arc::new(mm_registry::MMRegistry::new(Duration::from_secs(5))),
+            HashMap::from([
+                (ChainType::Bitcoin, Duration::from_millis(500)),
+                (ChainType::Ethereum, Duration::from_millis(100)),
+            ]),
+            16,
+            Arc::new(SystemClock),
+        ));
+
+        let shutdown = Shutdown::new();
+        let monitoring = tokio::spawn(service.run(shutdown.clone()));
+        time::sleep(Duration::from_millis(1_050)).await;
+        shutdown.trigger();
+        time::timeout(Duration::from_secs(1), monitoring)
+            .await
+            .expect("monitoring should stop once shut down")
+            .unwrap();
+        let _ = std::fs::remove_file(settings_path);
+
+        // Bitcoin ticks at 0, 500 and 1000ms, ethereum every 100ms
+        let bitcoin_searches = bitcoin.searches();
+        let ethereum_searches = ethereum.searches();
+        assert!(
+            (1..=3).contains(&bitcoin_searches),
+            "bitcoin searched {bitcoin_searches} times"
+        );
+        assert!(
+            (6..=11).contains(&ethereum_searches),
+            "ethereum searched {ethereum_searches} times"
+        );
+
         Ok(())
     }
 }
@@ -3,7 +3,8 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde::Serialize;
+use otc_api_types::{ApiErrorCode, RfqErrorResponse};
+use otc_models::FieldError;
 use snafu::Snafu;
 
 #[derive(Debug, Snafu)]
@@ -21,31 +22,69 @@ pub enum RfqServerError {
     #[snafu(display("Request timeout: {}", message))]
     Timeout { message: String },
 
+    #[snafu(display("Not found: {}", message))]
+    NotFound { message: String },
+
+    #[snafu(display("Conflict: {}", message))]
+    Conflict { message: String },
+
     #[snafu(display("No quotes available"))]
     NoQuotesAvailable,
+
+    #[snafu(display("Rate limited: {}", message))]
+    RateLimited { message: String },
+
+    #[snafu(display("Invalid request fields"))]
+    Validation { errors: Vec<FieldError> },
+
+    #[snafu(display("Decimals mismatch: {}", message))]
+    DecimalsMismatch { message: String },
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
+impl RfqServerError {
+    #[must_use]
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RfqServerError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            RfqServerError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            RfqServerError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            RfqServerError::Timeout { .. } => StatusCode::REQUEST_TIMEOUT,
+            RfqServerError::NotFound { .. } => StatusCode::NOT_FOUND,
+            RfqServerError::Conflict { .. } => StatusCode::CONFLICT,
+            RfqServerError::NoQuotesAvailable => StatusCode::NOT_FOUND,
+            RfqServerError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            RfqServerError::Validation { .. } => StatusCode::BAD_REQUEST,
+            RfqServerError::DecimalsMismatch { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// The code shared with the OTC server, or the generic one of the status
+    #[must_use]
+    pub fn code(&self) -> ApiErrorCode {
+        match self {
+            RfqServerError::Validation { .. } => ApiErrorCode::ValidationFailed,
+            RfqServerError::DecimalsMismatch { .. } => ApiErrorCode::DecimalsMismatch,
+            _ => ApiErrorCode::for_status(self.status().as_u16()),
+        }
+    }
 }
 
 impl IntoResponse for RfqServerError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match &self {
-            RfqServerError::BadRequest { .. } => (StatusCode::BAD_REQUEST, self.to_string()),
-            RfqServerError::Internal { .. } => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            RfqServerError::ServiceUnavailable { .. } => {
-                (StatusCode::SERVICE_UNAVAILABLE, self.to_string())
-            }
-            RfqServerError::Timeout { .. } => (StatusCode::REQUEST_TIMEOUT, self.to_string()),
-            RfqServerError::NoQuotesAvailable => (StatusCode::NOT_FOUND, self.to_string()),
+        let status = self.status();
+        let code = self.code();
+        let error = self.to_string();
+        let fields = match self {
+            RfqServerError::Validation { errors } => Some(errors),
+            _ => None,
         };
 
-        let body = Json(ErrorResponse {
-            error: error_message,
+        let body = Json(RfqErrorResponse {
+            error,
+            code,
+            fields,
         });
 
         (status, body).into_response()
     }
-}
\ No newline at end of file
+}
@@ -1,10 +1,14 @@
+use axum::http::HeaderName;
 use clap::Parser;
 use snafu::prelude::*;
 use std::net::IpAddr;
+use std::path::PathBuf;
 
 pub mod error;
 pub mod mm_registry;
 pub mod quote_aggregator;
+pub mod quote_lock;
+pub mod quote_outcomes;
 pub mod server;
 
 #[derive(Debug, Snafu)]
@@ -22,10 +26,29 @@ pub enum Error {
 
     #[snafu(display("Failed to load API keys: {}", source))]
     ApiKeyLoad { source: snafu::Whatever },
+
+    #[snafu(display("Invalid quote signing key: {}", source))]
+    QuoteSigningKey {
+        source: otc_protocols::rfq::QuoteSignatureError,
+    },
+
+    #[snafu(display("Failed to open quote history {}: {}", path.display(), source))]
+    QuoteHistory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to load supported currencies: {}", source))]
+    SupportedCurrencies {
+        source: otc_models::SupportedCurrenciesError,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Longest any of the `*_seconds` quote and lock durations may be set to, a year
+pub const MAX_DURATION_SECONDS: u64 = 365 * 24 * 60 * 60;
+
 #[derive(Parser, Debug)]
 #[command(name = "rfq-server")]
 #[command(about = "RFQ server for collecting and aggregating market maker quotes")]
@@ -50,11 +73,127 @@ pub struct RfqServerArgs {
     )]
     pub whitelist_file: String,
 
-    /// Quote request timeout in milliseconds
+    /// Seconds between checks of the API keys file for changes, 0 to never
+    /// reload. Sockets and quote outcome reads of a key that was removed,
+    /// revoked or rotated are refused from then on
+    #[arg(long, env = "WHITELIST_RELOAD_INTERVAL_SECONDS", default_value = "5")]
+    pub whitelist_reload_interval_seconds: u64,
+
+    /// Quote request timeout in milliseconds, requests return earlier once
+    /// every market maker answered
     #[arg(long, env = "QUOTE_TIMEOUT_MILLISECONDS", default_value = "500")]
     pub quote_timeout_milliseconds: u64,
 
-    /// CORS domain to allow (supports wildcards like "*.example.com")
-    #[arg(long = "corsdomain", env = "CORS_DOMAIN")]
-    pub cors_domain: Option<String>,
+    /// Extra milliseconds slower market makers get when a quote already arrived
+    #[arg(
+        long,
+        env = "QUOTE_TIMEOUT_EXTENSION_MILLISECONDS",
+        default_value = "200"
+    )]
+    pub quote_timeout_extension_milliseconds: u64,
+
+    /// Longest a quote request waits for market makers, extension included
+    #[arg(long, env = "MAX_QUOTE_TIMEOUT_MILLISECONDS", default_value = "1000")]
+    pub max_quote_timeout_milliseconds: u64,
+
+    /// Quotes expiring further out than this many seconds are rejected
+    #[arg(
+        long,
+        env = "MAX_QUOTE_LIFETIME_SECONDS",
+        default_value = "600",
+        value_parser = clap::value_parser!(u64).range(..=MAX_DURATION_SECONDS)
+    )]
+    pub max_quote_lifetime_seconds: u64,
+
+    /// Quotes created more than this many seconds before or after server time are rejected
+    #[arg(
+        long,
+        env = "MAX_QUOTE_CLOCK_SKEW_SECONDS",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(..=MAX_DURATION_SECONDS)
+    )]
+    pub max_quote_clock_skew_seconds: u64,
+
+    /// Most quote requests one POST /api/v1/quotes/request-batch may hold
+    #[arg(long, env = "MAX_QUOTE_BATCH_SIZE", default_value = "10")]
+    pub max_quote_batch_size: usize,
+
+    /// Quote batches a client may request per minute
+    #[arg(long, env = "QUOTE_BATCH_RATE_LIMIT_PER_MINUTE", default_value = "60")]
+    pub quote_batch_rate_limit_per_minute: u32,
+
+    /// Header a trusted reverse proxy puts the client address in (e.g.
+    /// "X-Forwarded-For"), rate limits use the peer address when unset
+    #[arg(long, env = "TRUSTED_PROXY_HEADER")]
+    pub trusted_proxy_header: Option<HeaderName>,
+
+    /// Comma separated CORS domains to allow (supports wildcards like "*.example.com")
+    #[arg(long = "corsdomain", env = "CORS_DOMAIN", value_delimiter = ',')]
+    pub cors_domains: Vec<String>,
+
+    /// Hex encoded key used to sign quotes (must match the OTC server's key)
+    #[arg(long, env = "QUOTE_SIGNING_KEY")]
+    pub quote_signing_key: String,
+
+    /// Longest a market maker holds the funds for a locked quote, in seconds
+    #[arg(
+        long,
+        env = "QUOTE_LOCK_TTL_SECONDS",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(..=MAX_DURATION_SECONDS)
+    )]
+    pub quote_lock_ttl_seconds: u64,
+
+    /// Unexpired quote locks one market maker may be asked to hold at once
+    #[arg(long, env = "MAX_QUOTE_LOCKS_PER_MARKET_MAKER", default_value = "5")]
+    pub max_quote_locks_per_market_maker: usize,
+
+    /// Quote locks a client may request per minute
+    #[arg(long, env = "QUOTE_LOCK_RATE_LIMIT_PER_MINUTE", default_value = "30")]
+    pub quote_lock_rate_limit_per_minute: u32,
+
+    /// Seconds the outcome of a quoted request stays available to the market
+    /// makers that quoted it
+    #[arg(
+        long,
+        env = "QUOTE_OUTCOME_RETENTION_SECONDS",
+        default_value = "86400",
+        value_parser = clap::value_parser!(u64).range(..=MAX_DURATION_SECONDS)
+    )]
+    pub quote_outcome_retention_seconds: u64,
+
+    /// Quote outcomes kept per market maker, the oldest are dropped first
+    #[arg(
+        long,
+        env = "MAX_QUOTE_OUTCOMES_PER_MARKET_MAKER",
+        default_value = "10000"
+    )]
+    pub max_quote_outcomes_per_market_maker: usize,
+
+    /// File the quote history is kept in, so outcomes survive a restart. Held in
+    /// memory only when unset
+    #[arg(long, env = "QUOTE_HISTORY_FILE")]
+    pub quote_history_file: Option<PathBuf>,
+
+    /// Largest message, in bytes, a market maker may send over its websocket
+    #[arg(long, env = "MM_MAX_MESSAGE_BYTES", default_value = "1048576")]
+    pub mm_max_message_bytes: usize,
+
+    /// Malformed messages in a row that get a market maker disconnected
+    #[arg(long, env = "MM_MAX_MALFORMED_MESSAGES", default_value = "5")]
+    pub mm_max_malformed_messages: u32,
+
+    /// Seconds a run of malformed market maker messages is remembered
+    #[arg(long, env = "MM_MALFORMED_WINDOW_SECONDS", default_value = "60")]
+    pub mm_malformed_window_seconds: u64,
+
+    /// Seconds a SIGTERM waits for open quote requests and market maker
+    /// connections to wind down before the server exits anyway
+    #[arg(long, env = "SHUTDOWN_DRAIN_TIMEOUT_SECONDS", default_value = "10")]
+    pub shutdown_drain_timeout_seconds: u64,
+
+    /// TOML or JSON file listing the tokens and their decimals, defaults to BTC and
+    /// cbBTC. Requests claiming other decimals for a listed token are rejected
+    #[arg(long, env = "SUPPORTED_CURRENCIES_FILE")]
+    pub supported_currencies_file: Option<String>,
 }
@@ -1,8 +1,17 @@
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use otc_api_types::ConnectedMarketMaker;
 use otc_models::QuoteRequest;
-use otc_protocols::rfq::{ProtocolMessage, RFQRequest, RFQResponse};
+use otc_protocols::{
+    mm::is_version_at_least,
+    rfq::{
+        BatchedQuoteRequest, BatchedQuoteResponse, ProtocolMessage, QuoteOutcome, RFQRequest,
+        RFQResponse, QUOTE_BATCH_VERSION, QUOTE_LOCK_VERSION, QUOTE_OUTCOME_VERSION,
+    },
+};
+use rand::seq::SliceRandom;
 use snafu::Snafu;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::sync::mpsc;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
@@ -12,6 +21,9 @@ pub enum MMRegistryError {
     #[snafu(display("Market maker '{}' not connected", market_maker_id))]
     MarketMakerNotConnected { market_maker_id: String },
 
+    #[snafu(display("Market maker '{}' does not support quote locks", market_maker_id))]
+    QuoteLocksNotSupported { market_maker_id: String },
+
     #[snafu(display("Failed to send message to market maker: {}", source))]
     MessageSendError {
         source: mpsc::error::SendError<ProtocolMessage<RFQRequest>>,
@@ -20,16 +32,35 @@ pub enum MMRegistryError {
 
 type Result<T, E = MMRegistryError> = std::result::Result<T, E>;
 
+/// How often a shutting down server checks whether its market makers have hung up
+const DISCONNECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct MarketMakerConnection {
     pub id: Uuid,
     pub sender: mpsc::Sender<ProtocolMessage<RFQRequest>>,
     pub protocol_version: String,
+    pub connected_at: DateTime<Utc>,
+    pub last_pong_at: Option<DateTime<Utc>>,
+}
+
+/// What a broadcast needs of a connection, copied out so no map guard is held
+/// while sending
+struct BroadcastTarget {
+    market_maker_id: Uuid,
+    sender: mpsc::Sender<ProtocolMessage<RFQRequest>>,
+    protocol_version: String,
+}
+
+/// Where a market maker's answer to a request goes
+struct PendingRequest {
+    market_maker_id: Uuid,
+    response_tx: mpsc::Sender<RFQResponse>,
 }
 
 #[derive(Clone)]
 pub struct RfqMMRegistry {
     connections: Arc<DashMap<Uuid, MarketMakerConnection>>,
-    pending_requests: Arc<DashMap<Uuid, mpsc::Sender<RFQResponse>>>,
+    pending_requests: Arc<DashMap<Uuid, PendingRequest>>,
 }
 
 impl RfqMMRegistry {
@@ -57,6 +88,8 @@ impl RfqMMRegistry {
             id: market_maker_id,
             sender,
             protocol_version,
+            connected_at: Utc::now(),
+            last_pong_at: None,
         };
 
         self.connections.insert(market_maker_id, connection);
@@ -72,24 +105,46 @@ impl RfqMMRegistry {
         self.connections.contains_key(&market_maker_id)
     }
 
+    /// Connected market makers in a fresh random order, so none is always
+    /// asked, and so able to answer, first
+    fn broadcast_targets(&self) -> Vec<BroadcastTarget> {
+        let mut targets: Vec<BroadcastTarget> = self
+            .connections
+            .iter()
+            .map(|entry| BroadcastTarget {
+                market_maker_id: *entry.key(),
+                sender: entry.sender.clone(),
+                protocol_version: entry.protocol_version.clone(),
+            })
+            .collect();
+        targets.shuffle(&mut rand::thread_rng());
+        targets
+    }
+
     /// Broadcast a quote request to all connected market makers
     pub async fn broadcast_quote_request(
         &self,
         request_id: &Uuid,
         request: &QuoteRequest,
+        trace_id: &str,
     ) -> Vec<(Uuid, mpsc::Receiver<RFQResponse>)> {
         let mut receivers = Vec::new();
 
-        for entry in self.connections.iter() {
-            let mm_id = *entry.key();
-            let connection = entry.value();
+        for connection in self.broadcast_targets() {
+            let mm_id = connection.market_maker_id;
 
             // Create a channel for this MM's response
             let (response_tx, response_rx) = mpsc::channel::<RFQResponse>(1);
 
             // Store the response channel for this MM and request
             let mm_request_id = Uuid::new_v4(); // Unique ID for this MM's request
-            self.pending_requests.insert(mm_request_id, response_tx);
+            self.pending_requests.insert(
+                mm_request_id,
+                PendingRequest {
+                    market_maker_id: mm_id,
+                    response_tx,
+                },
+            );
 
             let request = ProtocolMessage {
                 version: connection.protocol_version.clone(),
@@ -99,6 +154,7 @@ impl RfqMMRegistry {
                     request: request.clone(),
                     timestamp: chrono::Utc::now(),
                 },
+                trace_id: Some(trace_id.to_string()),
             };
 
             // Send the request
@@ -124,12 +180,105 @@ impl RfqMMRegistry {
         receivers
     }
 
+    /// Broadcast several quote requests to all connected market makers, as one
+    /// batch to those speaking `QUOTE_BATCH_VERSION` and one by one to the rest.
+    /// Returns the response channels of each request, in request order
+    pub async fn broadcast_quote_batch(
+        &self,
+        batch_id: &Uuid,
+        requests: &[QuoteRequest],
+        trace_id: &str,
+    ) -> Vec<Vec<(Uuid, mpsc::Receiver<RFQResponse>)>> {
+        let mut receivers: Vec<Vec<_>> = requests.iter().map(|_| Vec::new()).collect();
+
+        for connection in self.broadcast_targets() {
+            let mm_id = connection.market_maker_id;
+
+            // Every request gets its own id per MM, as for single requests
+            let mut batch = Vec::with_capacity(requests.len());
+            let mut response_rxs = Vec::with_capacity(requests.len());
+            for request in requests {
+                let (response_tx, response_rx) = mpsc::channel::<RFQResponse>(1);
+                let mm_request_id = Uuid::new_v4();
+                self.pending_requests.insert(
+                    mm_request_id,
+                    PendingRequest {
+                        market_maker_id: mm_id,
+                        response_tx,
+                    },
+                );
+                batch.push(BatchedQuoteRequest {
+                    request_id: mm_request_id,
+                    request: request.clone(),
+                });
+                response_rxs.push(response_rx);
+            }
+
+            let request_ids: Vec<Uuid> = batch.iter().map(|item| item.request_id).collect();
+            let payloads = if is_version_at_least(&connection.protocol_version, QUOTE_BATCH_VERSION)
+            {
+                vec![RFQRequest::QuoteBatchRequested {
+                    requests: batch,
+                    timestamp: chrono::Utc::now(),
+                }]
+            } else {
+                batch
+                    .into_iter()
+                    .map(|item| RFQRequest::QuoteRequested {
+                        request_id: item.request_id,
+                        request: item.request,
+                        timestamp: chrono::Utc::now(),
+                    })
+                    .collect()
+            };
+
+            let mut sent = true;
+            for payload in payloads {
+                let request = ProtocolMessage {
+                    version: connection.protocol_version.clone(),
+                    sequence: 0,
+                    payload,
+                    trace_id: Some(trace_id.to_string()),
+                };
+                if let Err(e) = connection.sender.send(request).await {
+                    warn!(
+                        market_maker_id = %mm_id,
+                        error = %e,
+                        "Failed to send quote batch to market maker"
+                    );
+                    sent = false;
+                    break;
+                }
+            }
+            if !sent {
+                for request_id in &request_ids {
+                    self.pending_requests.remove(request_id);
+                }
+                continue;
+            }
+
+            for (index, response_rx) in response_rxs.into_iter().enumerate() {
+                receivers[index].push((mm_id, response_rx));
+            }
+        }
+
+        debug!(
+            batch_id = %batch_id,
+            requests = requests.len(),
+            market_makers_count = receivers.first().map_or(0, Vec::len),
+            "Broadcasted quote batch to market makers"
+        );
+
+        receivers
+    }
+
     /// Notify a market maker that their quote was selected
     pub async fn notify_quote_selected(
         &self,
         market_maker_id: Uuid,
         request_id: Uuid,
         quote_id: Uuid,
+        trace_id: &str,
     ) -> Result<()> {
         let connection = self.connections.get(&market_maker_id).ok_or_else(|| {
             MMRegistryError::MarketMakerNotConnected {
@@ -145,6 +294,7 @@ impl RfqMMRegistry {
                 quote_id,
                 timestamp: chrono::Utc::now(),
             },
+            trace_id: Some(trace_id.to_string()),
         };
 
         connection
@@ -162,20 +312,186 @@ impl RfqMMRegistry {
         Ok(())
     }
 
+    /// Send each market maker the outcome of its quote, skipping those gone or
+    /// too old for `QuoteOutcome`. Outcomes can be fetched again later, so one
+    /// whose queue is full doesn't hold up the request
+    pub fn notify_quote_outcomes(&self, outcomes: Vec<(Uuid, QuoteOutcome)>, trace_id: &str) {
+        for (market_maker_id, outcome) in outcomes {
+            let Some((version, sender)) =
+                self.connections.get(&market_maker_id).map(|connection| {
+                    (
+                        connection.protocol_version.clone(),
+                        connection.sender.clone(),
+                    )
+                })
+            else {
+                continue;
+            };
+            if !is_version_at_least(&version, QUOTE_OUTCOME_VERSION) {
+                continue;
+            }
+            let quote_id = outcome.quote_id;
+            let notification = ProtocolMessage {
+                version,
+                sequence: 0,
+                payload: RFQRequest::QuoteOutcome {
+                    outcome,
+                    timestamp: chrono::Utc::now(),
+                },
+                trace_id: Some(trace_id.to_string()),
+            };
+            if let Err(e) = sender.try_send(notification) {
+                warn!(
+                    market_maker_id = %market_maker_id,
+                    quote_id = %quote_id,
+                    error = %e,
+                    "Failed to send quote outcome to market maker"
+                );
+            }
+        }
+    }
+
+    /// Ask a market maker to hold the funds for one of its quotes until
+    /// `lock_expires_at`, its `QuoteLockResponse` arrives on the returned channel
+    pub async fn request_quote_lock(
+        &self,
+        market_maker_id: Uuid,
+        quote_id: Uuid,
+        lock_expires_at: DateTime<Utc>,
+        trace_id: &str,
+    ) -> Result<mpsc::Receiver<RFQResponse>> {
+        let (version, sender) = {
+            let connection = self.connections.get(&market_maker_id).ok_or_else(|| {
+                MMRegistryError::MarketMakerNotConnected {
+                    market_maker_id: market_maker_id.to_string(),
+                }
+            })?;
+            (
+                connection.protocol_version.clone(),
+                connection.sender.clone(),
+            )
+        };
+        if !is_version_at_least(&version, QUOTE_LOCK_VERSION) {
+            return Err(MMRegistryError::QuoteLocksNotSupported {
+                market_maker_id: market_maker_id.to_string(),
+            });
+        }
+
+        let (response_tx, response_rx) = mpsc::channel::<RFQResponse>(1);
+        let request_id = Uuid::new_v4();
+        self.pending_requests.insert(
+            request_id,
+            PendingRequest {
+                market_maker_id,
+                response_tx,
+            },
+        );
+
+        let request = ProtocolMessage {
+            version,
+            sequence: 0,
+            payload: RFQRequest::QuoteLockRequested {
+                request_id,
+                quote_id,
+                lock_expires_at,
+                timestamp: chrono::Utc::now(),
+            },
+            trace_id: Some(trace_id.to_string()),
+        };
+        if let Err(e) = sender.send(request).await {
+            self.pending_requests.remove(&request_id);
+            return Err(MMRegistryError::MessageSendError { source: e });
+        }
+
+        Ok(response_rx)
+    }
+
     #[must_use]
     pub fn get_connection_count(&self) -> usize {
         self.connections.len()
     }
 
+    /// Tell every connected market maker the server is shutting down, behind
+    /// whatever is already queued for it. The connection closes after
+    /// `GoingAway`, market makers predating it only see the close
+    pub async fn going_away(&self, reason: &str) {
+        let connections: Vec<_> = self
+            .connections
+            .iter()
+            .map(|conn| (conn.id, conn.protocol_version.clone(), conn.sender.clone()))
+            .collect();
+        for (market_maker_id, protocol_version, sender) in connections {
+            let request = ProtocolMessage {
+                version: protocol_version,
+                sequence: 0,
+                payload: RFQRequest::GoingAway {
+                    request_id: Uuid::new_v4(),
+                    reason: reason.to_string(),
+                    timestamp: chrono::Utc::now(),
+                },
+                trace_id: None,
+            };
+            if let Err(e) = sender.send(request).await {
+                warn!(
+                    market_maker_id = %market_maker_id,
+                    error = %e,
+                    "Failed to tell market maker the server is going away"
+                );
+            }
+        }
+    }
+
+    /// Wait until every market maker connection has closed
+    pub async fn wait_until_disconnected(&self) {
+        while !self.connections.is_empty() {
+            tokio::time::sleep(DISCONNECT_POLL_INTERVAL).await;
+        }
+    }
+
     #[must_use]
     pub fn get_connected_market_makers(&self) -> Vec<Uuid> {
         self.connections.iter().map(|entry| *entry.key()).collect()
     }
 
+    /// Note that a market maker answered a ping
+    pub fn record_pong(&self, market_maker_id: Uuid) {
+        if let Some(mut connection) = self.connections.get_mut(&market_maker_id) {
+            connection.last_pong_at = Some(Utc::now());
+        }
+    }
+
+    /// Connection metadata of every connected market maker. Requests whose
+    /// caller stopped waiting, like quotes past the aggregation deadline, aren't
+    /// in flight anymore even if the market maker never answered
+    #[must_use]
+    pub fn connected_market_maker_details(&self) -> Vec<ConnectedMarketMaker> {
+        let now = Utc::now();
+        let mut in_flight: HashMap<Uuid, usize> = HashMap::new();
+        for pending in self.pending_requests.iter() {
+            if !pending.response_tx.is_closed() {
+                *in_flight.entry(pending.market_maker_id).or_default() += 1;
+            }
+        }
+        self.connections
+            .iter()
+            .map(|connection| ConnectedMarketMaker {
+                market_maker_id: connection.id,
+                protocol_version: connection.protocol_version.clone(),
+                connected_at: connection.connected_at,
+                uptime_seconds: (now - connection.connected_at)
+                    .to_std()
+                    .unwrap_or_default()
+                    .as_secs(),
+                in_flight_requests: in_flight.get(&connection.id).copied().unwrap_or(0),
+                last_pong_at: connection.last_pong_at,
+            })
+            .collect()
+    }
+
     /// Handle incoming quote response from a market maker
     pub async fn handle_quote_response(&self, request_id: Uuid, response: RFQResponse) {
-        if let Some((_, sender)) = self.pending_requests.remove(&request_id) {
-            if let Err(e) = sender.send(response).await {
+        if let Some((_, pending)) = self.pending_requests.remove(&request_id) {
+            if let Err(e) = pending.response_tx.send(response).await {
                 warn!(
                     request_id = %request_id,
                     error = ?e,
@@ -189,6 +505,26 @@ impl RfqMMRegistry {
             );
         }
     }
+
+    /// Handle a market maker's answers to a quote batch, each routed like a
+    /// single quote response
+    pub async fn handle_quote_batch_response(
+        &self,
+        responses: Vec<BatchedQuoteResponse>,
+        timestamp: DateTime<Utc>,
+    ) {
+        for response in responses {
+            self.handle_quote_response(
+                response.request_id,
+                RFQResponse::QuoteResponse {
+                    request_id: response.request_id,
+                    quote: response.quote,
+                    timestamp,
+                },
+            )
+            .await;
+        }
+    }
 }
 
 impl Default for RfqMMRegistry {
@@ -217,4 +553,114 @@ mod tests {
         assert!(!registry.is_connected(mm_id));
         assert_eq!(registry.get_connection_count(), 0);
     }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_and_pongs_are_tracked() {
+        let registry = RfqMMRegistry::new();
+        let (tx, _rx) = mpsc::channel(10);
+        let mm_id = Uuid::new_v4();
+        registry.register(mm_id, tx, QUOTE_LOCK_VERSION.to_string());
+
+        let lock_response = registry
+            .request_quote_lock(mm_id, Uuid::new_v4(), Utc::now(), "trace")
+            .await
+            .unwrap();
+        let abandoned = registry
+            .request_quote_lock(mm_id, Uuid::new_v4(), Utc::now(), "trace")
+            .await
+            .unwrap();
+        drop(abandoned);
+        registry.record_pong(mm_id);
+
+        let details = registry.connected_market_maker_details();
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].protocol_version, QUOTE_LOCK_VERSION);
+        assert_eq!(details[0].in_flight_requests, 1);
+        assert!(details[0].last_pong_at.is_some());
+        drop(lock_response);
+    }
+
+    #[tokio::test]
+    async fn test_quote_outcomes_only_reach_market_makers_speaking_them() {
+        let registry = RfqMMRegistry::new();
+        let (legacy_tx, mut legacy_rx) = mpsc::channel(10);
+        let (current_tx, mut current_rx) = mpsc::channel(10);
+        let (legacy, current) = (Uuid::new_v4(), Uuid::new_v4());
+        registry.register(legacy, legacy_tx, QUOTE_LOCK_VERSION.to_string());
+        registry.register(current, current_tx, QUOTE_OUTCOME_VERSION.to_string());
+
+        let outcome = QuoteOutcome {
+            request_id: Uuid::new_v4(),
+            quote_id: Uuid::new_v4(),
+            won: false,
+            mode: otc_models::QuoteMode::ExactInput,
+            winning_amount: alloy::primitives::U256::from(100u64),
+            decided_at: Utc::now(),
+        };
+        registry.notify_quote_outcomes(
+            vec![
+                (legacy, outcome.clone()),
+                (current, outcome.clone()),
+                (Uuid::new_v4(), outcome.clone()),
+            ],
+            "trace",
+        );
+
+        let message = current_rx.try_recv().unwrap();
+        let RFQRequest::QuoteOutcome { outcome: sent, .. } = message.payload else {
+            panic!("expected a quote outcome, got {:?}", message.payload);
+        };
+        assert_eq!(sent, outcome);
+        assert!(legacy_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quote_batch_is_split_for_market_makers_without_batches() {
+        let registry = RfqMMRegistry::new();
+        let (legacy_tx, mut legacy_rx) = mpsc::channel(10);
+        let (batching_tx, mut batching_rx) = mpsc::channel(10);
+        registry.register(Uuid::new_v4(), legacy_tx, "1.0.0".to_string());
+        registry.register(Uuid::new_v4(), batching_tx, QUOTE_BATCH_VERSION.to_string());
+
+        let request = QuoteRequest {
+            mode: otc_models::QuoteMode::ExactInput,
+            from: otc_models::Currency {
+                chain: otc_models::ChainType::Bitcoin,
+                token: otc_models::TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            to: otc_models::Currency {
+                chain: otc_models::ChainType::Ethereum,
+                token: otc_models::TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: alloy::primitives::U256::from(100_000u64),
+        };
+        let receivers = registry
+            .broadcast_quote_batch(&Uuid::new_v4(), &[request.clone(), request], "trace")
+            .await;
+        assert_eq!(receivers.len(), 2);
+        assert!(receivers.iter().all(|receivers| receivers.len() == 2));
+
+        let mut legacy_ids = Vec::new();
+        while let Ok(message) = legacy_rx.try_recv() {
+            let RFQRequest::QuoteRequested { request_id, .. } = message.payload else {
+                panic!("expected a single quote request, got {:?}", message.payload);
+            };
+            legacy_ids.push(request_id);
+        }
+        assert_eq!(legacy_ids.len(), 2);
+
+        let message = batching_rx.try_recv().unwrap();
+        let RFQRequest::QuoteBatchRequested { requests, .. } = message.payload else {
+            panic!("expected a quote batch, got {:?}", message.payload);
+        };
+        assert_eq!(requests.len(), 2);
+        assert!(batching_rx.try_recv().is_err());
+        assert!(requests
+            .iter()
+            .all(|item| !legacy_ids.contains(&item.request_id)));
+    }
 }
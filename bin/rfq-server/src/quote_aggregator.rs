@@ -1,11 +1,22 @@
 use crate::mm_registry::RfqMMRegistry;
-use futures_util::future;
+use crate::quote_outcomes::{
+    QuoteOutcomeHistory, QuoteOutcomeRetention, RecordedTieBreak, TieBreakRule,
+};
+use alloy::primitives::U256;
+use chrono::{DateTime, Utc};
+use common::SystemClock;
+use dashmap::DashMap;
+use futures_util::{
+    future::join_all,
+    stream::{FuturesUnordered, StreamExt},
+};
 use otc_models::{Quote, QuoteMode, QuoteRequest};
-use otc_protocols::rfq::{QuoteWithFees, RFQResponse, RFQResult};
+use otc_protocols::rfq::{QuoteOutcome, QuoteSigner, QuoteWithFees, RFQResponse, RFQResult};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use snafu::Snafu;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
-use tokio::time::{timeout, Duration};
+use tokio::time::{sleep_until, Duration, Instant};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
@@ -21,11 +32,84 @@ pub enum QuoteAggregatorError {
     AggregationTimeout,
 }
 
+pub use otc_api_types::{QuoteRejectionReason, QuoteTieBreak, QuoteTiming, RejectedQuote};
+
 type Result<T, E = QuoteAggregatorError> = std::result::Result<T, E>;
 
+/// Bounds on the expiry and timestamp a market maker may put on its quotes
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteValidity {
+    /// Furthest from now a quote may expire
+    pub max_lifetime: chrono::Duration,
+    /// Furthest a quote's `created_at` may be from the server's clock, either way
+    pub max_clock_skew: chrono::Duration,
+}
+
+impl QuoteValidity {
+    /// Why `quote` can't be handed out at `now`, if it can't
+    pub fn check(&self, quote: &Quote, now: DateTime<Utc>) -> Result<(), QuoteRejectionReason> {
+        if quote.expires_at <= now {
+            return Err(QuoteRejectionReason::Expired);
+        }
+        if quote.expires_at > now + self.max_lifetime {
+            return Err(QuoteRejectionReason::LifetimeTooLong);
+        }
+        if (quote.created_at - now).abs() > self.max_clock_skew {
+            return Err(QuoteRejectionReason::ClockSkew);
+        }
+        Ok(())
+    }
+}
+
+/// How long to wait for market makers to quote
+///
+/// Collection ends as soon as every market maker answered. Otherwise it ends at
+/// `base`, unless a quote is already in hand and others are still pending: then
+/// slower market makers get `extension` more to beat it, never past `max`.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteTimeouts {
+    pub base: Duration,
+    pub extension: Duration,
+    pub max: Duration,
+}
+
+impl QuoteTimeouts {
+    /// Deadline once extended, the longest a request waits
+    #[must_use]
+    pub fn longest(&self) -> Duration {
+        (self.base + self.extension).min(self.max).max(self.base)
+    }
+}
+
 pub struct QuoteAggregator {
     mm_registry: Arc<RfqMMRegistry>,
-    timeout_duration: Duration,
+    quote_signer: Arc<QuoteSigner>,
+    timeouts: QuoteTimeouts,
+    validity: QuoteValidity,
+    /// Quotes rejected per market maker since startup
+    rejected_quote_counts: DashMap<Uuid, u64>,
+    /// Quotes handed out to clients, kept until they expire so they can be locked
+    issued_quotes: DashMap<Uuid, Quote>,
+    /// Picks the winner among quotes tied for the best price
+    tie_break_rng: Mutex<StdRng>,
+    /// Ties each market maker was part of since startup, and how many it won
+    tie_break_counts: DashMap<Uuid, TieBreakCount>,
+    /// How the requests each market maker quoted were decided
+    outcome_history: QuoteOutcomeHistory,
+}
+
+/// A successful quote and who sent it, under the request id it was sent
+struct QuotedBy {
+    market_maker_id: Uuid,
+    request_id: Uuid,
+    quote_id: Uuid,
+}
+
+/// How a market maker fared in ties for the best price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TieBreakCount {
+    pub tied: u64,
+    pub won: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -34,19 +118,105 @@ pub struct QuoteRequestResult {
     pub best_quote: Option<RFQResult<QuoteWithFees>>,
     pub total_quotes_received: usize,
     pub market_makers_contacted: usize,
+    pub rejected_quotes: Vec<RejectedQuote>,
+    pub timing: QuoteTiming,
+    pub tie_break: Option<QuoteTieBreak>,
+}
+
+/// Outcome of every request of a batch, in request order
+#[derive(Debug)]
+pub struct QuoteBatchRequestResult {
+    pub batch_id: Uuid,
+    pub results: Vec<Result<QuoteRequestResult>>,
 }
 
 impl QuoteAggregator {
     #[must_use]
-    pub fn new(mm_registry: Arc<RfqMMRegistry>, timeout_milliseconds: u64) -> Self {
+    pub fn new(
+        mm_registry: Arc<RfqMMRegistry>,
+        quote_signer: Arc<QuoteSigner>,
+        timeouts: QuoteTimeouts,
+        validity: QuoteValidity,
+    ) -> Self {
         Self {
             mm_registry,
-            timeout_duration: Duration::from_millis(timeout_milliseconds),
+            quote_signer,
+            timeouts,
+            validity,
+            rejected_quote_counts: DashMap::new(),
+            issued_quotes: DashMap::new(),
+            tie_break_rng: Mutex::new(StdRng::from_entropy()),
+            tie_break_counts: DashMap::new(),
+            outcome_history: QuoteOutcomeHistory::new(
+                QuoteOutcomeRetention::default(),
+                Arc::new(SystemClock),
+            ),
         }
     }
 
-    /// Request quotes from all connected market makers and return the best one
-    pub async fn request_quotes(&self, request: QuoteRequest) -> Result<QuoteRequestResult> {
+    /// Keep quote outcomes in `history` rather than in memory with the default
+    /// retention
+    #[must_use]
+    pub fn with_outcome_history(mut self, history: QuoteOutcomeHistory) -> Self {
+        self.outcome_history = history;
+        self
+    }
+
+    /// Break ties with an RNG seeded with `seed`, so the winners repeat
+    #[must_use]
+    pub fn with_tie_break_seed(mut self, seed: u64) -> Self {
+        self.tie_break_rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// A quote this server handed out that hasn't expired yet
+    #[must_use]
+    pub fn issued_quote(&self, quote_id: Uuid) -> Option<Quote> {
+        self.issued_quotes
+            .get(&quote_id)
+            .map(|quote| quote.clone())
+            .filter(|quote| quote.expires_at > Utc::now())
+    }
+
+    fn remember_issued(&self, quote: &Quote) {
+        let now = Utc::now();
+        self.issued_quotes.retain(|_, quote| quote.expires_at > now);
+        self.issued_quotes.insert(quote.id, quote.clone());
+    }
+
+    /// How many quotes from `market_maker_id` were rejected since startup
+    #[must_use]
+    pub fn rejected_quote_count(&self, market_maker_id: Uuid) -> u64 {
+        self.rejected_quote_counts
+            .get(&market_maker_id)
+            .map_or(0, |count| *count)
+    }
+
+    /// Ties `market_maker_id` was part of since startup, and how many it won
+    #[must_use]
+    pub fn tie_break_count(&self, market_maker_id: Uuid) -> TieBreakCount {
+        self.tie_break_counts
+            .get(&market_maker_id)
+            .map_or_else(TieBreakCount::default, |count| *count)
+    }
+
+    /// Outcomes of the requests `market_maker_id` quoted, decided after `since`
+    #[must_use]
+    pub fn quote_outcomes(
+        &self,
+        market_maker_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Vec<QuoteOutcome> {
+        self.outcome_history.since(market_maker_id, since)
+    }
+
+    /// Request quotes from all connected market makers and return the best one,
+    /// `trace_id` is passed along so their logs can be matched with ours
+    pub async fn request_quotes(
+        &self,
+        request: QuoteRequest,
+        trace_id: &str,
+    ) -> Result<QuoteRequestResult> {
         let request_id = Uuid::new_v4();
 
         info!(
@@ -61,63 +231,97 @@ impl QuoteAggregator {
         // Broadcast quote request to all connected MMs
         let receivers = self
             .mm_registry
-            .broadcast_quote_request(&request_id, &request)
+            .broadcast_quote_request(&request_id, &request, trace_id)
             .await;
 
         if receivers.is_empty() {
             return Err(QuoteAggregatorError::NoMarketMakersConnected);
         }
 
-        let market_makers_contacted = receivers.len();
-        let mut quotes = Vec::new();
+        self.select_quote(request_id, &request, receivers, trace_id)
+            .await
+    }
 
-        // Collect quotes with timeout
-        let collection_result = timeout(
-            self.timeout_duration,
-            self.collect_quotes(receivers, request_id),
-        )
-        .await;
-
-        match collection_result {
-            Ok(collected_quotes) => quotes = collected_quotes,
-            Err(_) => {
-                debug!(
-                    request_id = %request_id,
-                    "Quote collection timed out, proceeding with quotes received so far"
-                );
-            }
+    /// Request quotes for every one of `requests` from all connected market
+    /// makers in a single fan out
+    pub async fn request_quote_batch(
+        &self,
+        requests: &[QuoteRequest],
+        trace_id: &str,
+    ) -> Result<QuoteBatchRequestResult> {
+        let batch_id = Uuid::new_v4();
+
+        info!(
+            batch_id = %batch_id,
+            requests = requests.len(),
+            "Starting batch quote aggregation"
+        );
+
+        let receivers = self
+            .mm_registry
+            .broadcast_quote_batch(&batch_id, requests, trace_id)
+            .await;
+
+        if receivers.iter().all(Vec::is_empty) {
+            return Err(QuoteAggregatorError::NoMarketMakersConnected);
         }
 
+        // Collected side by side, so the batch takes as long as its slowest request
+        let selections = requests.iter().zip(receivers).map(|(request, receivers)| {
+            self.select_quote(Uuid::new_v4(), request, receivers, trace_id)
+        });
+        Ok(QuoteBatchRequestResult {
+            batch_id,
+            results: join_all(selections).await,
+        })
+    }
+
+    /// Collect the answers to `request` and pick the best of them, notifying
+    /// the market maker that gave it
+    async fn select_quote(
+        &self,
+        request_id: Uuid,
+        request: &QuoteRequest,
+        receivers: Vec<(Uuid, mpsc::Receiver<RFQResponse>)>,
+        trace_id: &str,
+    ) -> Result<QuoteRequestResult> {
+        let market_makers_contacted = receivers.len();
+        let (quotes, timing) = self.collect_quotes(receivers, request_id).await;
+
         if quotes.is_empty() {
             return Err(QuoteAggregatorError::NoQuotesReceived);
         }
 
         let total_quotes = quotes.len();
+        let quoted: Vec<QuotedBy> = quotes
+            .iter()
+            .filter_map(|(market_maker_id, request_id, quote)| match quote {
+                RFQResult::Success(quote) => Some(QuotedBy {
+                    market_maker_id: *market_maker_id,
+                    request_id: *request_id,
+                    quote_id: quote.quote.id,
+                }),
+                _ => None,
+            })
+            .collect();
+        let quotes = quotes
+            .into_iter()
+            .map(|(market_maker_id, _, quote)| (market_maker_id, quote))
+            .collect();
+        let (quotes, rejected_quotes) = self.drop_invalid_quotes(request_id, quotes, Utc::now());
 
         info!(
             request_id = %request_id,
             quotes_received = total_quotes,
+            quotes_rejected = rejected_quotes.len(),
             market_makers_contacted = market_makers_contacted,
+            market_makers_responded = timing.market_makers_responded,
+            elapsed_ms = timing.elapsed_ms,
+            deadline_extended = timing.deadline_extended,
             "Collected quotes from market makers"
         );
 
-        // Select the best quote (highest output amount)
-        let best_success_quote = match request.mode {
-            QuoteMode::ExactInput => quotes
-                .iter()
-                .filter_map(|q| match q {
-                    RFQResult::Success(quote) => Some(quote),
-                    _ => None,
-                })
-                .max_by_key(|q| q.quote.to.amount),
-            QuoteMode::ExactOutput => quotes
-                .iter()
-                .filter_map(|q| match q {
-                    RFQResult::Success(quote) => Some(quote),
-                    _ => None,
-                })
-                .max_by_key(|q| q.quote.from.amount),
-        };
+        let (best_success_quote, tie_break) = self.pick_best(request_id, request.mode, &quotes);
 
         // Relevant fail quote - prioritize InvalidRequest over MakerUnavailable
         let best_fail_quote: Option<RFQResult<QuoteWithFees>> =
@@ -139,6 +343,7 @@ impl QuoteAggregator {
                     best_quote.quote.market_maker_id,
                     request_id,
                     best_quote.quote.id,
+                    trace_id,
                 )
                 .await
             {
@@ -149,11 +354,27 @@ impl QuoteAggregator {
                     "Failed to notify market maker of quote selection"
                 );
             }
+            self.report_outcomes(
+                request.mode,
+                &quoted,
+                best_quote,
+                tie_break.as_ref(),
+                trace_id,
+            );
+
+            // Sign the winning quote so the OTC server can verify we issued it
+            let mut signed_quote = best_quote.clone();
+            signed_quote.signature = Some(self.quote_signer.sign(&signed_quote.quote));
+            self.remember_issued(&signed_quote.quote);
+
             Ok(QuoteRequestResult {
                 request_id,
-                best_quote: Some(RFQResult::Success(best_quote.clone())),
+                best_quote: Some(RFQResult::Success(signed_quote)),
                 total_quotes_received: total_quotes,
                 market_makers_contacted,
+                rejected_quotes,
+                timing,
+                tie_break,
             })
         } else {
             Ok(QuoteRequestResult {
@@ -161,82 +382,903 @@ impl QuoteAggregator {
                 best_quote: best_fail_quote,
                 total_quotes_received: total_quotes,
                 market_makers_contacted,
+                rejected_quotes,
+                timing,
+                tie_break: None,
+            })
+        }
+    }
+
+    /// Tell every market maker that quoted whether it won and at what price,
+    /// and keep the outcomes for those that miss the message, along with the
+    /// tie break that decided them
+    fn report_outcomes(
+        &self,
+        mode: QuoteMode,
+        quoted: &[QuotedBy],
+        winner: &QuoteWithFees,
+        tie_break: Option<&QuoteTieBreak>,
+        trace_id: &str,
+    ) {
+        let decided_at = Utc::now();
+        let winning_amount = price(mode, winner);
+        let tie_break = tie_break.map(|tie_break| RecordedTieBreak {
+            rule: TieBreakRule::UniformRandom,
+            winning_quote_id: winner.quote.id,
+            losing_quote_ids: quoted
+                .iter()
+                .filter(|quoted| {
+                    quoted.market_maker_id != tie_break.winner
+                        && tie_break
+                            .tied_market_makers
+                            .contains(&quoted.market_maker_id)
+                })
+                .map(|quoted| quoted.quote_id)
+                .collect(),
+        });
+        let outcomes: Vec<(Uuid, QuoteOutcome)> = quoted
+            .iter()
+            .map(|quoted| {
+                let outcome = QuoteOutcome {
+                    request_id: quoted.request_id,
+                    quote_id: quoted.quote_id,
+                    won: quoted.quote_id == winner.quote.id,
+                    mode,
+                    winning_amount,
+                    decided_at,
+                };
+                self.outcome_history.record(
+                    quoted.market_maker_id,
+                    outcome.clone(),
+                    tie_break.clone(),
+                );
+                (quoted.market_maker_id, outcome)
+            })
+            .collect();
+        self.mm_registry.notify_quote_outcomes(outcomes, trace_id);
+    }
+
+    /// The best successful quote, see [`price`]. Quotes tied for it are picked
+    /// from uniformly at random, so the market maker that answers first doesn't
+    /// win every tie
+    fn pick_best<'a>(
+        &self,
+        request_id: Uuid,
+        mode: QuoteMode,
+        quotes: &'a [RFQResult<QuoteWithFees>],
+    ) -> (Option<&'a QuoteWithFees>, Option<QuoteTieBreak>) {
+        let price = |quote: &QuoteWithFees| price(mode, quote);
+        let successes: Vec<&QuoteWithFees> = quotes
+            .iter()
+            .filter_map(|q| match q {
+                RFQResult::Success(quote) => Some(quote),
+                _ => None,
             })
+            .collect();
+        let Some(best_price) = successes.iter().map(|quote| price(quote)).max() else {
+            return (None, None);
+        };
+        // In id order rather than arrival order, so a seeded RNG picks the same winners
+        let mut tied: Vec<&QuoteWithFees> = successes
+            .into_iter()
+            .filter(|quote| price(quote) == best_price)
+            .collect();
+        tied.sort_by_key(|quote| quote.quote.market_maker_id);
+        if let [only] = tied[..] {
+            return (Some(only), None);
+        }
+        let best = *tied
+            .choose(&mut *self.tie_break_rng.lock().expect("tie break rng poisoned"))
+            .expect("the best price came from one of the quotes");
+
+        let tied_market_makers: Vec<Uuid> = tied
+            .iter()
+            .map(|quote| quote.quote.market_maker_id)
+            .collect();
+        let winner = best.quote.market_maker_id;
+        for market_maker_id in &tied_market_makers {
+            let mut count = self.tie_break_counts.entry(*market_maker_id).or_default();
+            count.tied += 1;
+            if *market_maker_id == winner {
+                count.won += 1;
+            }
+        }
+        info!(
+            request_id = %request_id,
+            tied_market_makers = ?tied_market_makers,
+            winner = %winner,
+            "Broke a tie for the best quote"
+        );
+        (
+            Some(best),
+            Some(QuoteTieBreak {
+                tied_market_makers,
+                winner,
+            }),
+        )
+    }
+
+    /// Split off the successful quotes whose expiry or timestamp is out of
+    /// bounds at `now`, counting them against the market maker that sent them
+    fn drop_invalid_quotes(
+        &self,
+        request_id: Uuid,
+        quotes: Vec<(Uuid, RFQResult<QuoteWithFees>)>,
+        now: DateTime<Utc>,
+    ) -> (Vec<RFQResult<QuoteWithFees>>, Vec<RejectedQuote>) {
+        let mut valid = Vec::with_capacity(quotes.len());
+        let mut rejected = Vec::new();
+        for (mm_id, result) in quotes {
+            let RFQResult::Success(quote) = &result else {
+                valid.push(result);
+                continue;
+            };
+            match self.validity.check(&quote.quote, now) {
+                Ok(()) => valid.push(result),
+                Err(reason) => {
+                    let mut count = self.rejected_quote_counts.entry(mm_id).or_insert(0);
+                    *count += 1;
+                    warn!(
+                        request_id = %request_id,
+                        market_maker_id = %mm_id,
+                        quote_id = %quote.quote.id,
+                        created_at = %quote.quote.created_at,
+                        expires_at = %quote.quote.expires_at,
+                        %reason,
+                        rejected_quotes_total = *count,
+                        "Rejected quote from market maker"
+                    );
+                    rejected.push(RejectedQuote {
+                        market_maker_id: mm_id,
+                        quote_id: quote.quote.id,
+                        reason,
+                    });
+                }
+            }
         }
+        (valid, rejected)
     }
 
-    /// Collect quotes from market makers
+    /// Collect quotes from market makers, with the market maker each came from
+    /// and the request id it answered, until all of them answered or the
+    /// deadline passed
     async fn collect_quotes(
         &self,
         receivers: Vec<(Uuid, mpsc::Receiver<RFQResponse>)>,
-        _request_id: Uuid,
-    ) -> Vec<RFQResult<QuoteWithFees>> {
+        request_id: Uuid,
+    ) -> (Vec<(Uuid, Uuid, RFQResult<QuoteWithFees>)>, QuoteTiming) {
+        let started = Instant::now();
+        let mut pending: FuturesUnordered<_> = receivers
+            .into_iter()
+            .map(|(mm_id, mut rx)| async move { (mm_id, rx.recv().await) })
+            .collect();
+
         let mut quotes = Vec::new();
+        let mut market_makers_responded = 0;
+        let mut deadline_extended = false;
+        let mut deadline = self.timeouts.base;
+        let sleep = sleep_until(started + deadline);
+        tokio::pin!(sleep);
 
-        // Convert receivers into futures
-        let mut futures = Vec::new();
-        for (mm_id, mut rx) in receivers {
-            let future = async move {
-                match rx.recv().await {
-                    Some(response) => match response {
-                        RFQResponse::QuoteResponse { quote, .. } => {
-                            // We don't check request_id since each MM gets a unique ID
-                            Some((mm_id, quote))
+        loop {
+            tokio::select! {
+                next = pending.next() => {
+                    // Everyone answered, no reason to wait any longer
+                    let Some((mm_id, response)) = next else {
+                        break;
+                    };
+                    match response {
+                        Some(RFQResponse::QuoteResponse {
+                            request_id: mm_request_id,
+                            quote,
+                            ..
+                        }) => {
+                            // Each MM gets its own request id, the registry routed it here by it
+                            market_makers_responded += 1;
+                            quotes.push((mm_id, mm_request_id, quote));
                         }
-                        _ => None,
-                    },
-                    None => {
-                        warn!(
-                            market_maker_id = %mm_id,
-                            "Market maker channel closed without response"
+                        Some(RFQResponse::Error {
+                            error_code,
+                            message,
+                            ..
+                        }) => {
+                            market_makers_responded += 1;
+                            warn!(
+                                request_id = %request_id,
+                                market_maker_id = %mm_id,
+                                ?error_code,
+                                %message,
+                                "Market maker answered with an error"
+                            );
+                        }
+                        // Batch responses are split up by the registry
+                        Some(
+                            RFQResponse::Pong { .. }
+                            | RFQResponse::QuoteBatchResponse { .. }
+                            | RFQResponse::QuoteLockResponse { .. },
+                        ) => {}
+                        None => {
+                            warn!(
+                                market_maker_id = %mm_id,
+                                "Market maker channel closed without response"
+                            );
+                        }
+                    }
+                }
+                () = &mut sleep => {
+                    let has_quote = quotes
+                        .iter()
+                        .any(|(_, _, quote)| matches!(quote, RFQResult::Success(_)));
+                    let extended = self.timeouts.longest();
+                    if deadline_extended || !has_quote || extended <= deadline {
+                        debug!(
+                            request_id = %request_id,
+                            pending = pending.len(),
+                            "Quote collection timed out, proceeding with quotes received so far"
                         );
-                        None
+                        break;
                     }
+                    debug!(
+                        request_id = %request_id,
+                        pending = pending.len(),
+                        extended_ms = extended.as_millis(),
+                        "Extending quote collection for slower market makers"
+                    );
+                    deadline_extended = true;
+                    deadline = extended;
+                    sleep.as_mut().reset(started + deadline);
                 }
-            };
-            futures.push(future);
+            }
         }
 
-        // Wait for all futures to complete
-        let results = future::join_all(futures).await;
-
         // TODO: We should be validating that the returned market maker id is the same as the one we sent the request to
-        for (_, quote) in results.into_iter().flatten() {
-            quotes.push(quote);
-        }
+        let timing = QuoteTiming {
+            elapsed_ms: millis(started.elapsed()),
+            deadline_ms: millis(deadline),
+            market_makers_responded,
+            deadline_extended,
+        };
+        (quotes, timing)
+    }
+}
 
-        quotes
+/// What a quote is ranked by: the output amount for exact input, the input
+/// amount for exact output
+fn price(mode: QuoteMode, quote: &QuoteWithFees) -> U256 {
+    match mode {
+        QuoteMode::ExactInput => quote.quote.to.amount,
+        QuoteMode::ExactOutput => quote.quote.from.amount,
     }
 }
 
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloy::primitives::U256;
-    use otc_models::Currency;
-    use otc_models::{ChainType, QuoteMode, TokenIdentifier};
+    use otc_models::{ChainType, Currency, Lot, QuoteMode, TokenIdentifier};
+    use otc_protocols::rfq::{
+        BatchedQuoteResponse, FeeSchedule, RFQErrorCode, RFQRequest, QUOTE_BATCH_VERSION,
+    };
 
-    #[tokio::test]
-    async fn test_no_market_makers() {
-        let registry = Arc::new(RfqMMRegistry::new());
-        let aggregator = QuoteAggregator::new(registry, 5);
+    const VALIDITY: QuoteValidity = QuoteValidity {
+        max_lifetime: chrono::Duration::minutes(10),
+        max_clock_skew: chrono::Duration::seconds(30),
+    };
+
+    const TRACE_ID: &str = "trace-1";
 
-        let request = QuoteRequest {
+    const TIMEOUTS: QuoteTimeouts = QuoteTimeouts {
+        base: Duration::from_secs(1),
+        extension: Duration::ZERO,
+        max: Duration::from_secs(1),
+    };
+
+    fn aggregator(registry: Arc<RfqMMRegistry>) -> QuoteAggregator {
+        aggregator_with_timeouts(registry, TIMEOUTS)
+    }
+
+    fn aggregator_with_timeouts(
+        registry: Arc<RfqMMRegistry>,
+        timeouts: QuoteTimeouts,
+    ) -> QuoteAggregator {
+        let signer = Arc::new(QuoteSigner::new(&[1u8; 32]).unwrap());
+        QuoteAggregator::new(registry, signer, timeouts, VALIDITY)
+    }
+
+    fn request() -> QuoteRequest {
+        QuoteRequest {
             mode: QuoteMode::ExactInput,
             from: Currency {
                 chain: ChainType::Bitcoin,
                 token: TokenIdentifier::Native,
                 decimals: 8,
+                chain_id: None,
             },
             to: Currency {
                 chain: ChainType::Ethereum,
                 token: TokenIdentifier::Native,
                 decimals: 18,
+                chain_id: None,
             },
             amount: U256::from(100_000u64),
+        }
+    }
+
+    fn quote(
+        market_maker_id: Uuid,
+        to_amount: u64,
+        created_at: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> QuoteWithFees {
+        let request = request();
+        QuoteWithFees {
+            quote: Quote {
+                id: Uuid::new_v4(),
+                market_maker_id,
+                from: Lot {
+                    currency: request.from,
+                    amount: request.amount,
+                },
+                to: Lot {
+                    currency: request.to,
+                    amount: U256::from(to_amount),
+                },
+                expires_at,
+                created_at,
+            },
+            fees: FeeSchedule {
+                network_fee_sats: 0,
+                liquidity_fee_sats: 0,
+                protocol_fee_sats: 0,
+            },
+            signature: None,
+        }
+    }
+
+    /// How a simulated market maker answers quote requests
+    enum Answer {
+        Quote(QuoteWithFees),
+        Error,
+        Silent,
+    }
+
+    /// Connect a market maker that answers every quote request traced with
+    /// `TRACE_ID` with `quote`
+    fn connect_market_maker(registry: &Arc<RfqMMRegistry>, quote: QuoteWithFees) {
+        let market_maker_id = quote.quote.market_maker_id;
+        connect_simulated_market_maker(
+            registry,
+            market_maker_id,
+            Duration::ZERO,
+            Answer::Quote(quote),
+        );
+    }
+
+    /// Connect a market maker that gives `answer` to every quote request traced
+    /// with `TRACE_ID`, `delay` after receiving it
+    fn connect_simulated_market_maker(
+        registry: &Arc<RfqMMRegistry>,
+        market_maker_id: Uuid,
+        delay: Duration,
+        answer: Answer,
+    ) {
+        let (tx, mut rx) = mpsc::channel(10);
+        registry.register(market_maker_id, tx, "1.0.0".to_string());
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if message.trace_id.as_deref() != Some(TRACE_ID) {
+                    continue;
+                }
+                let RFQRequest::QuoteRequested { request_id, .. } = message.payload else {
+                    continue;
+                };
+                let response = match &answer {
+                    Answer::Quote(quote) => RFQResponse::QuoteResponse {
+                        request_id,
+                        quote: RFQResult::Success(quote.clone()),
+                        timestamp: Utc::now(),
+                    },
+                    Answer::Error => RFQResponse::Error {
+                        request_id,
+                        error_code: RFQErrorCode::InsufficientLiquidity,
+                        message: "out of inventory".to_string(),
+                        timestamp: Utc::now(),
+                    },
+                    Answer::Silent => continue,
+                };
+                let registry = registry.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    registry.handle_quote_response(request_id, response).await;
+                });
+            }
+        });
+    }
+
+    /// Connect a market maker speaking `QUOTE_BATCH_VERSION` that answers every
+    /// quote batch traced with `TRACE_ID` with `quote` for each request
+    fn connect_batching_market_maker(registry: &Arc<RfqMMRegistry>, quote: QuoteWithFees) {
+        let (tx, mut rx) = mpsc::channel(10);
+        registry.register(
+            quote.quote.market_maker_id,
+            tx,
+            QUOTE_BATCH_VERSION.to_string(),
+        );
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if message.trace_id.as_deref() != Some(TRACE_ID) {
+                    continue;
+                }
+                let RFQRequest::QuoteBatchRequested { requests, .. } = message.payload else {
+                    continue;
+                };
+                let responses = requests
+                    .into_iter()
+                    .map(|item| BatchedQuoteResponse {
+                        request_id: item.request_id,
+                        quote: RFQResult::Success(quote.clone()),
+                    })
+                    .collect();
+                registry
+                    .handle_quote_batch_response(responses, Utc::now())
+                    .await;
+            }
+        });
+    }
+
+    fn valid_quote(to_amount: u64) -> QuoteWithFees {
+        let now = Utc::now();
+        quote(
+            Uuid::new_v4(),
+            to_amount,
+            now,
+            now + chrono::Duration::minutes(5),
+        )
+    }
+
+    /// The quote `result` picked, panicking when there is none
+    fn best_quote(result: &QuoteRequestResult) -> &QuoteWithFees {
+        match &result.best_quote {
+            Some(RFQResult::Success(best)) => best,
+            other => panic!("expected a quote, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validity_boundaries() {
+        let now = Utc::now();
+        let mm = Uuid::new_v4();
+        let check = |created_at, expires_at| {
+            VALIDITY.check(&quote(mm, 1, created_at, expires_at).quote, now)
+        };
+
+        let minute = chrono::Duration::minutes(1);
+        assert_eq!(check(now, now + minute), Ok(()));
+        assert_eq!(check(now, now), Err(QuoteRejectionReason::Expired));
+        assert_eq!(check(now, now - minute), Err(QuoteRejectionReason::Expired));
+
+        assert_eq!(check(now, now + VALIDITY.max_lifetime), Ok(()));
+        assert_eq!(
+            check(
+                now,
+                now + VALIDITY.max_lifetime + chrono::Duration::seconds(1)
+            ),
+            Err(QuoteRejectionReason::LifetimeTooLong)
+        );
+
+        let skew = VALIDITY.max_clock_skew;
+        let second = chrono::Duration::seconds(1);
+        assert_eq!(check(now - skew, now + minute), Ok(()));
+        assert_eq!(check(now + skew, now + minute), Ok(()));
+        assert_eq!(
+            check(now - skew - second, now + minute),
+            Err(QuoteRejectionReason::ClockSkew)
+        );
+        assert_eq!(
+            check(now + skew + second, now + minute),
+            Err(QuoteRejectionReason::ClockSkew)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_quotes_are_rejected_before_selection() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry.clone());
+        let now = Utc::now();
+
+        // The best prices come from the market makers whose quotes are invalid
+        let honest = quote(Uuid::new_v4(), 100, now, now + chrono::Duration::minutes(5));
+        let expired = quote(Uuid::new_v4(), 300, now, now - chrono::Duration::seconds(1));
+        let long_lived = quote(Uuid::new_v4(), 200, now, now + chrono::Duration::hours(24));
+        for quote in [&honest, &expired, &long_lived] {
+            connect_market_maker(&registry, quote.clone());
+        }
+
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        let Some(RFQResult::Success(best)) = result.best_quote else {
+            panic!("expected a quote, got {:?}", result.best_quote);
+        };
+        assert_eq!(best.quote.id, honest.quote.id);
+        assert_eq!(result.total_quotes_received, 3);
+
+        let mut rejected: Vec<_> = result
+            .rejected_quotes
+            .iter()
+            .map(|rejected| (rejected.quote_id, rejected.reason))
+            .collect();
+        rejected.sort_by_key(|(_, reason)| *reason as u8);
+        assert_eq!(
+            rejected,
+            [
+                (expired.quote.id, QuoteRejectionReason::Expired),
+                (long_lived.quote.id, QuoteRejectionReason::LifetimeTooLong),
+            ]
+        );
+
+        assert_eq!(
+            aggregator.rejected_quote_count(honest.quote.market_maker_id),
+            0
+        );
+        assert_eq!(
+            aggregator.rejected_quote_count(expired.quote.market_maker_id),
+            1
+        );
+        aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            aggregator.rejected_quote_count(expired.quote.market_maker_id),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skewed_quotes_surface_when_none_are_valid() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry.clone());
+        let now = Utc::now();
+
+        let skewed = quote(
+            Uuid::new_v4(),
+            100,
+            now + chrono::Duration::minutes(2),
+            now + chrono::Duration::minutes(5),
+        );
+        connect_market_maker(&registry, skewed.clone());
+
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert!(result.best_quote.is_none());
+        assert_eq!(result.rejected_quotes.len(), 1);
+        assert_eq!(
+            result.rejected_quotes[0].market_maker_id,
+            skewed.quote.market_maker_id
+        );
+        assert_eq!(
+            result.rejected_quotes[0].reason,
+            QuoteRejectionReason::ClockSkew
+        );
+    }
+
+    #[tokio::test]
+    async fn test_returns_once_every_market_maker_answered() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator_with_timeouts(
+            registry.clone(),
+            QuoteTimeouts {
+                base: Duration::from_secs(5),
+                extension: Duration::from_secs(5),
+                max: Duration::from_secs(10),
+            },
+        );
+
+        // An error is an answer too, nothing is left to wait for
+        let fast = valid_quote(100);
+        connect_market_maker(&registry, fast.clone());
+        connect_simulated_market_maker(
+            &registry,
+            Uuid::new_v4(),
+            Duration::from_millis(20),
+            Answer::Error,
+        );
+
+        let started = Instant::now();
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(best_quote(&result).quote.id, fast.quote.id);
+        assert_eq!(result.total_quotes_received, 1);
+        assert_eq!(result.market_makers_contacted, 2);
+        assert_eq!(result.timing.market_makers_responded, 2);
+        assert!(!result.timing.deadline_extended);
+        assert_eq!(result.timing.deadline_ms, 5_000);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_extends_for_slower_market_makers() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator_with_timeouts(
+            registry.clone(),
+            QuoteTimeouts {
+                base: Duration::from_millis(100),
+                extension: Duration::from_millis(400),
+                max: Duration::from_secs(1),
+            },
+        );
+
+        // The slow market maker misses the base timeout but has the better price
+        let fast = valid_quote(100);
+        let slow = valid_quote(200);
+        connect_market_maker(&registry, fast.clone());
+        connect_simulated_market_maker(
+            &registry,
+            slow.quote.market_maker_id,
+            Duration::from_millis(200),
+            Answer::Quote(slow.clone()),
+        );
+
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert_eq!(best_quote(&result).quote.id, slow.quote.id);
+        assert_eq!(result.timing.market_makers_responded, 2);
+        assert!(result.timing.deadline_extended);
+        assert_eq!(result.timing.deadline_ms, 500);
+        // Still returned as soon as the slow market maker answered
+        assert!(result.timing.elapsed_ms >= 200);
+        assert!(result.timing.elapsed_ms < 500);
+    }
+
+    #[tokio::test]
+    async fn test_silent_market_maker_is_cut_off_at_max() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator_with_timeouts(
+            registry.clone(),
+            QuoteTimeouts {
+                base: Duration::from_millis(100),
+                extension: Duration::from_millis(400),
+                max: Duration::from_millis(250),
+            },
+        );
+
+        let fast = valid_quote(100);
+        connect_market_maker(&registry, fast.clone());
+        connect_simulated_market_maker(&registry, Uuid::new_v4(), Duration::ZERO, Answer::Silent);
+
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert_eq!(best_quote(&result).quote.id, fast.quote.id);
+        assert_eq!(result.market_makers_contacted, 2);
+        assert_eq!(result.timing.market_makers_responded, 1);
+        assert!(result.timing.deadline_extended);
+        assert_eq!(result.timing.deadline_ms, 250);
+        assert!(result.timing.elapsed_ms >= 250);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_is_not_extended_without_a_quote() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator_with_timeouts(
+            registry.clone(),
+            QuoteTimeouts {
+                base: Duration::from_millis(100),
+                extension: Duration::from_millis(400),
+                max: Duration::from_secs(1),
+            },
+        );
+
+        let slow = valid_quote(100);
+        connect_simulated_market_maker(
+            &registry,
+            slow.quote.market_maker_id,
+            Duration::from_millis(300),
+            Answer::Quote(slow),
+        );
+
+        let started = Instant::now();
+        let result = aggregator.request_quotes(request(), TRACE_ID).await;
+        assert!(matches!(
+            result,
+            Err(QuoteAggregatorError::NoQuotesReceived)
+        ));
+        assert!(started.elapsed() < Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_quote_batch_reaches_market_makers_with_and_without_batches() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry.clone());
+
+        let legacy = valid_quote(100);
+        let batching = valid_quote(200);
+        connect_market_maker(&registry, legacy);
+        connect_batching_market_maker(&registry, batching.clone());
+
+        let started = Instant::now();
+        let results = aggregator
+            .request_quote_batch(&[request(), request(), request()], TRACE_ID)
+            .await
+            .unwrap()
+            .results;
+        // Both answered everything, the 1s timeout isn't waited out
+        assert!(started.elapsed() < TIMEOUTS.base);
+        assert_eq!(results.len(), 3);
+        for result in &results {
+            let result = result.as_ref().unwrap();
+            assert_eq!(result.market_makers_contacted, 2);
+            assert_eq!(result.timing.market_makers_responded, 2);
+            assert_eq!(best_quote(result).quote.id, batching.quote.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ties_are_spread_across_market_makers() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry.clone()).with_tie_break_seed(7);
+
+        let tied: Vec<QuoteWithFees> = (0..3).map(|_| valid_quote(200)).collect();
+        for quote in &tied {
+            connect_market_maker(&registry, quote.clone());
+        }
+        let outbid = valid_quote(100);
+        connect_market_maker(&registry, outbid.clone());
+        let mut tied_ids: Vec<Uuid> = tied.iter().map(|q| q.quote.market_maker_id).collect();
+        tied_ids.sort();
+
+        const ROUNDS: u64 = 60;
+        for _ in 0..ROUNDS {
+            let result = aggregator
+                .request_quotes(request(), TRACE_ID)
+                .await
+                .unwrap();
+            let tie_break = result.tie_break.clone().expect("three quotes tied");
+            assert_eq!(tie_break.tied_market_makers, tied_ids);
+            assert_eq!(tie_break.winner, best_quote(&result).quote.market_maker_id);
+        }
+
+        // The history says how every tie was broken and which quotes lost it
+        let history = aggregator
+            .outcome_history
+            .recorded_since(outbid.quote.market_maker_id, None);
+        assert_eq!(history.len() as u64, ROUNDS);
+        for recorded in &history {
+            let tie_break = recorded.tie_break.as_ref().expect("decided by a tie");
+            assert_eq!(tie_break.rule, TieBreakRule::UniformRandom);
+            assert_eq!(tie_break.losing_quote_ids.len(), 2);
+            let mut tied_quotes = tie_break.losing_quote_ids.clone();
+            tied_quotes.push(tie_break.winning_quote_id);
+            tied_quotes.sort();
+            let mut expected: Vec<Uuid> = tied.iter().map(|q| q.quote.id).collect();
+            expected.sort();
+            assert_eq!(tied_quotes, expected);
+        }
+
+        for market_maker_id in &tied_ids {
+            let count = aggregator.tie_break_count(*market_maker_id);
+            assert_eq!(count.tied, ROUNDS);
+            // A fair draw gives each about 20 of the 60
+            assert!(
+                (5..=35).contains(&count.won),
+                "{market_maker_id} won {} of {ROUNDS} ties",
+                count.won
+            );
+        }
+        let total_won: u64 = tied_ids
+            .iter()
+            .map(|id| aggregator.tie_break_count(*id).won)
+            .sum();
+        assert_eq!(total_won, ROUNDS);
+        assert_eq!(
+            aggregator.tie_break_count(outbid.quote.market_maker_id),
+            TieBreakCount::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seeded_tie_breaks_repeat() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        for _ in 0..3 {
+            connect_market_maker(&registry, valid_quote(200));
+        }
+
+        let mut winners = Vec::new();
+        for _ in 0..2 {
+            let aggregator = aggregator(registry.clone()).with_tie_break_seed(42);
+            let mut run = Vec::new();
+            for _ in 0..10 {
+                let result = aggregator
+                    .request_quotes(request(), TRACE_ID)
+                    .await
+                    .unwrap();
+                run.push(result.tie_break.unwrap().winner);
+            }
+            winners.push(run);
+        }
+        assert_eq!(winners[0], winners[1]);
+    }
+
+    #[tokio::test]
+    async fn test_no_tie_break_without_a_tie() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry.clone());
+        let best = valid_quote(200);
+        connect_market_maker(&registry, best.clone());
+        connect_market_maker(&registry, valid_quote(100));
+
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert_eq!(best_quote(&result).quote.id, best.quote.id);
+        assert_eq!(result.tie_break, None);
+        assert_eq!(
+            aggregator
+                .outcome_history
+                .recorded_since(best.quote.market_maker_id, None)[0]
+                .tie_break,
+            None
+        );
+        assert_eq!(
+            aggregator.tie_break_count(best.quote.market_maker_id),
+            TieBreakCount::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_every_quoting_market_maker_learns_the_outcome() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry.clone());
+        let winner = valid_quote(200);
+        let loser = valid_quote(100);
+        connect_market_maker(&registry, winner.clone());
+        connect_market_maker(&registry, loser.clone());
+
+        let result = aggregator
+            .request_quotes(request(), TRACE_ID)
+            .await
+            .unwrap();
+        assert_eq!(best_quote(&result).quote.id, winner.quote.id);
+
+        let outcome = |quote: &QuoteWithFees| {
+            let outcomes = aggregator.quote_outcomes(quote.quote.market_maker_id, None);
+            assert_eq!(outcomes.len(), 1);
+            outcomes[0].clone()
         };
+        let (won, lost) = (outcome(&winner), outcome(&loser));
+        assert!(won.won);
+        assert!(!lost.won);
+        assert_eq!(won.quote_id, winner.quote.id);
+        assert_eq!(lost.quote_id, loser.quote.id);
+        // Each knows the request by the id it was sent, and only the winning price
+        assert_ne!(won.request_id, lost.request_id);
+        assert_eq!(lost.winning_amount, U256::from(200u64));
+        assert!(aggregator
+            .quote_outcomes(loser.quote.market_maker_id, Some(lost.decided_at))
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_market_makers() {
+        let registry = Arc::new(RfqMMRegistry::new());
+        let aggregator = aggregator(registry);
 
-        let result = aggregator.request_quotes(request).await;
+        let result = aggregator.request_quotes(request(), TRACE_ID).await;
         assert!(matches!(
             result,
             Err(QuoteAggregatorError::NoMarketMakersConnected)
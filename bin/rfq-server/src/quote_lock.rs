@@ -0,0 +1,308 @@
+use crate::mm_registry::{MMRegistryError, RfqMMRegistry};
+use chrono::{DateTime, Utc};
+use otc_api_types::QuoteLock;
+use otc_models::Quote;
+use otc_protocols::rfq::{QuoteSigner, RFQResponse};
+use snafu::prelude::*;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Snafu)]
+pub enum QuoteLockError {
+    #[snafu(display("Quote {} was not issued by this server or has expired", quote_id))]
+    QuoteNotFound { quote_id: Uuid },
+
+    #[snafu(display("Quote {} is already locked", quote_id))]
+    AlreadyLocked { quote_id: Uuid },
+
+    #[snafu(display("Market maker {} already holds {} quote locks", market_maker_id, max))]
+    TooManyLocks { market_maker_id: Uuid, max: usize },
+
+    #[snafu(display("{}", source))]
+    MarketMaker { source: MMRegistryError },
+
+    #[snafu(display("Market maker declined to lock quote {}: {}", quote_id, reason))]
+    Rejected { quote_id: Uuid, reason: String },
+
+    #[snafu(display("Market maker didn't answer the lock request for quote {}", quote_id))]
+    Timeout { quote_id: Uuid },
+}
+
+type Result<T, E = QuoteLockError> = std::result::Result<T, E>;
+
+/// How long locks last and how many a market maker may be asked to hold
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteLockLimits {
+    /// Longest a lock is held, never past the quote's own expiry
+    pub ttl: chrono::Duration,
+    /// Unexpired locks per market maker, so no client can tie up all of its funds
+    pub max_per_market_maker: usize,
+    /// How long the market maker gets to answer
+    pub response_timeout: Duration,
+}
+
+struct HeldLock {
+    market_maker_id: Uuid,
+    expires_at: DateTime<Utc>,
+}
+
+/// Asks market makers to hold the funds for a selected quote and signs the
+/// locks they grant. Locks count from the request until they expire here,
+/// whether or not a swap is created in the meantime
+pub struct QuoteLocker {
+    mm_registry: Arc<RfqMMRegistry>,
+    quote_signer: Arc<QuoteSigner>,
+    limits: QuoteLockLimits,
+    locks: Mutex<HashMap<Uuid, HeldLock>>,
+}
+
+impl QuoteLocker {
+    #[must_use]
+    pub fn new(
+        mm_registry: Arc<RfqMMRegistry>,
+        quote_signer: Arc<QuoteSigner>,
+        limits: QuoteLockLimits,
+    ) -> Self {
+        Self {
+            mm_registry,
+            quote_signer,
+            limits,
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lock `quote`, which must be one this server issued
+    pub async fn lock(&self, quote: &Quote, trace_id: &str) -> Result<QuoteLock> {
+        let expires_at = self.reserve(quote, Utc::now())?;
+        if let Err(e) = self.ask_market_maker(quote, expires_at, trace_id).await {
+            self.release(quote.id);
+            return Err(e);
+        }
+
+        info!(
+            quote_id = %quote.id,
+            market_maker_id = %quote.market_maker_id,
+            %expires_at,
+            "Market maker locked quote"
+        );
+        Ok(QuoteLock {
+            quote_id: quote.id,
+            expires_at,
+            token: self.quote_signer.sign_lock(quote, expires_at),
+        })
+    }
+
+    /// Claim a lock slot for `quote`, returning when the lock will expire
+    fn reserve(&self, quote: &Quote, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let mut locks = self.locks.lock().expect("quote locks mutex poisoned");
+        locks.retain(|_, lock| lock.expires_at > now);
+
+        ensure!(
+            !locks.contains_key(&quote.id),
+            AlreadyLockedSnafu { quote_id: quote.id }
+        );
+        let held = locks
+            .values()
+            .filter(|lock| lock.market_maker_id == quote.market_maker_id)
+            .count();
+        ensure!(
+            held < self.limits.max_per_market_maker,
+            TooManyLocksSnafu {
+                market_maker_id: quote.market_maker_id,
+                max: self.limits.max_per_market_maker,
+            }
+        );
+
+        let expires_at = (now + self.limits.ttl).min(quote.expires_at);
+        locks.insert(
+            quote.id,
+            HeldLock {
+                market_maker_id: quote.market_maker_id,
+                expires_at,
+            },
+        );
+        Ok(expires_at)
+    }
+
+    fn release(&self, quote_id: Uuid) {
+        self.locks
+            .lock()
+            .expect("quote locks mutex poisoned")
+            .remove(&quote_id);
+    }
+
+    async fn ask_market_maker(
+        &self,
+        quote: &Quote,
+        expires_at: DateTime<Utc>,
+        trace_id: &str,
+    ) -> Result<()> {
+        let quote_id = quote.id;
+        let mut response_rx = self
+            .mm_registry
+            .request_quote_lock(quote.market_maker_id, quote_id, expires_at, trace_id)
+            .await
+            .context(MarketMakerSnafu)?;
+
+        let response = tokio::time::timeout(self.limits.response_timeout, response_rx.recv())
+            .await
+            .ok()
+            .flatten()
+            .context(TimeoutSnafu { quote_id })?;
+        match response {
+            RFQResponse::QuoteLockResponse { accepted: true, .. } => Ok(()),
+            RFQResponse::QuoteLockResponse {
+                rejection_reason, ..
+            } => RejectedSnafu {
+                quote_id,
+                reason: rejection_reason.unwrap_or_else(|| "no reason given".to_string()),
+            }
+            .fail(),
+            RFQResponse::Error { message, .. } => RejectedSnafu {
+                quote_id,
+                reason: message,
+            }
+            .fail(),
+            other => {
+                warn!(
+                    quote_id = %quote_id,
+                    response = ?other,
+                    "Unexpected answer to a quote lock request"
+                );
+                RejectedSnafu {
+                    quote_id,
+                    reason: "unexpected answer",
+                }
+                .fail()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+    use otc_models::{ChainType, Currency, Lot, TokenIdentifier};
+    use otc_protocols::rfq::{ProtocolMessage, RFQRequest, QUOTE_LOCK_VERSION};
+    use tokio::sync::mpsc;
+
+    fn locker(mm_registry: Arc<RfqMMRegistry>) -> QuoteLocker {
+        QuoteLocker::new(
+            mm_registry,
+            Arc::new(QuoteSigner::new(&[7u8; 32]).unwrap()),
+            QuoteLockLimits {
+                ttl: chrono::Duration::seconds(60),
+                max_per_market_maker: 2,
+                response_timeout: Duration::from_secs(1),
+            },
+        )
+    }
+
+    fn quote(market_maker_id: Uuid) -> Quote {
+        let lot = Lot {
+            currency: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(100_000u64),
+        };
+        Quote {
+            id: Uuid::new_v4(),
+            market_maker_id,
+            from: lot.clone(),
+            to: lot,
+            expires_at: Utc::now() + chrono::Duration::minutes(5),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_locks_are_limited_per_market_maker_until_they_expire() {
+        let locker = locker(Arc::new(RfqMMRegistry::new()));
+        let market_maker_id = Uuid::new_v4();
+        let now = Utc::now();
+
+        let first = quote(market_maker_id);
+        let expires_at = locker.reserve(&first, now).unwrap();
+        assert_eq!(expires_at, now + chrono::Duration::seconds(60));
+        assert!(matches!(
+            locker.reserve(&first, now),
+            Err(QuoteLockError::AlreadyLocked { .. })
+        ));
+        locker.reserve(&quote(market_maker_id), now).unwrap();
+        assert!(matches!(
+            locker.reserve(&quote(market_maker_id), now),
+            Err(QuoteLockError::TooManyLocks { max: 2, .. })
+        ));
+        // Other market makers have their own allowance
+        locker.reserve(&quote(Uuid::new_v4()), now).unwrap();
+
+        // Expired locks free their slot
+        locker.reserve(&quote(market_maker_id), expires_at).unwrap();
+
+        // A lock never outlives its quote
+        let mut short_lived = quote(Uuid::new_v4());
+        short_lived.expires_at = now + chrono::Duration::seconds(10);
+        assert_eq!(
+            locker.reserve(&short_lived, now).unwrap(),
+            short_lived.expires_at
+        );
+    }
+
+    #[tokio::test]
+    async fn test_declined_lock_frees_its_slot() {
+        let mm_registry = Arc::new(RfqMMRegistry::new());
+        let (tx, mut rx) = mpsc::channel::<ProtocolMessage<RFQRequest>>(10);
+        let market_maker_id = Uuid::new_v4();
+        mm_registry.register(market_maker_id, tx, QUOTE_LOCK_VERSION.to_string());
+        let locker = locker(mm_registry.clone());
+
+        // Declines the first request and accepts every later one
+        tokio::spawn(async move {
+            let mut accepted = false;
+            while let Some(message) = rx.recv().await {
+                let RFQRequest::QuoteLockRequested {
+                    request_id,
+                    quote_id,
+                    ..
+                } = message.payload
+                else {
+                    continue;
+                };
+                mm_registry
+                    .handle_quote_response(
+                        request_id,
+                        RFQResponse::QuoteLockResponse {
+                            request_id,
+                            quote_id,
+                            accepted,
+                            rejection_reason: (!accepted).then(|| "out of funds".to_string()),
+                            timestamp: Utc::now(),
+                        },
+                    )
+                    .await;
+                accepted = true;
+            }
+        });
+
+        let quote = quote(market_maker_id);
+        assert!(matches!(
+            locker.lock(&quote, "trace").await,
+            Err(QuoteLockError::Rejected { reason, .. }) if reason == "out of funds"
+        ));
+        let lock = locker.lock(&quote, "trace").await.unwrap();
+        assert_eq!(lock.quote_id, quote.id);
+        assert!(locker
+            .quote_signer
+            .verify_lock(&quote, lock.expires_at, &lock.token)
+            .is_ok());
+    }
+}
@@ -0,0 +1,298 @@
+use chrono::{DateTime, Utc};
+use common::Clock;
+use dashmap::DashMap;
+use otc_protocols::rfq::QuoteOutcome;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// How long outcomes are kept and how many per market maker
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteOutcomeRetention {
+    pub max_age: chrono::Duration,
+    pub max_per_market_maker: usize,
+}
+
+impl Default for QuoteOutcomeRetention {
+    fn default() -> Self {
+        Self {
+            max_age: chrono::Duration::hours(24),
+            max_per_market_maker: 10_000,
+        }
+    }
+}
+
+/// How a winner was picked among quotes tied for the best price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreakRule {
+    /// Uniformly at random
+    UniformRandom,
+}
+
+/// The tie a request was decided by, kept so fairness can be audited
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedTieBreak {
+    pub rule: TieBreakRule,
+    pub winning_quote_id: Uuid,
+    /// The other quotes tied for the best price
+    pub losing_quote_ids: Vec<Uuid>,
+}
+
+/// An outcome as kept in the history file, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedOutcome {
+    pub market_maker_id: Uuid,
+    pub outcome: QuoteOutcome,
+    /// Set when the request was decided by a tie break
+    pub tie_break: Option<RecordedTieBreak>,
+}
+
+/// Outcomes of the requests each market maker quoted, so one that was
+/// disconnected when they were decided can catch up.
+///
+/// Opened on a file, every outcome is appended to it and the ones still within
+/// the retention are read back on startup, when the file is rewritten without
+/// the rest. Replicas each keep their own file, so a market maker only finds
+/// the outcomes of the server that decided them
+pub struct QuoteOutcomeHistory {
+    retention: QuoteOutcomeRetention,
+    clock: Arc<dyn Clock>,
+    outcomes: DashMap<Uuid, VecDeque<RecordedOutcome>>,
+    file: Option<Mutex<File>>,
+}
+
+impl QuoteOutcomeHistory {
+    /// A history kept in memory only, forgotten on restart
+    #[must_use]
+    pub fn new(retention: QuoteOutcomeRetention, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            retention,
+            clock,
+            outcomes: DashMap::new(),
+            file: None,
+        }
+    }
+
+    /// A history persisted to `path`, starting from the outcomes it holds.
+    /// Lines that don't parse are skipped
+    pub fn open(
+        path: &Path,
+        retention: QuoteOutcomeRetention,
+        clock: Arc<dyn Clock>,
+    ) -> io::Result<Self> {
+        let history = Self::new(retention, clock);
+        match File::open(path) {
+            Ok(file) => {
+                for (number, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line?;
+                    match serde_json::from_str::<RecordedOutcome>(&line) {
+                        Ok(recorded) => history.remember(recorded),
+                        Err(e) => warn!(
+                            "Skipping line {} of quote history {}: {}",
+                            number + 1,
+                            path.display(),
+                            e
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        // Rewrite the file with only what was kept, so it doesn't grow forever
+        let compacted = path.with_extension("compacting");
+        {
+            let mut file = File::create(&compacted)?;
+            for outcomes in &history.outcomes {
+                for recorded in outcomes.iter() {
+                    writeln!(file, "{}", serde_json::to_string(recorded)?)?;
+                }
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&compacted, path)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+            ..history
+        })
+    }
+
+    /// Remember `outcome` for `market_maker_id` with the tie break that decided
+    /// it, dropping its oldest ones past the retention
+    pub fn record(
+        &self,
+        market_maker_id: Uuid,
+        outcome: QuoteOutcome,
+        tie_break: Option<RecordedTieBreak>,
+    ) {
+        let recorded = RecordedOutcome {
+            market_maker_id,
+            outcome,
+            tie_break,
+        };
+        if let Some(file) = &self.file {
+            let mut file = file.lock().expect("quote history file poisoned");
+            let written = serde_json::to_string(&recorded)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{line}"));
+            if let Err(e) = written {
+                error!(
+                    "Failed to persist the outcome of request {}: {}",
+                    recorded.outcome.request_id, e
+                );
+            }
+        }
+        self.remember(recorded);
+    }
+
+    fn remember(&self, recorded: RecordedOutcome) {
+        let mut outcomes = self.outcomes.entry(recorded.market_maker_id).or_default();
+        outcomes.push_back(recorded);
+        let oldest_kept = self.clock.now() - self.retention.max_age;
+        while outcomes.len() > self.retention.max_per_market_maker
+            || outcomes
+                .front()
+                .is_some_and(|recorded| recorded.outcome.decided_at < oldest_kept)
+        {
+            outcomes.pop_front();
+        }
+    }
+
+    /// Outcomes for `market_maker_id` decided after `since`, oldest first
+    #[must_use]
+    pub fn since(&self, market_maker_id: Uuid, since: Option<DateTime<Utc>>) -> Vec<QuoteOutcome> {
+        self.recorded_since(market_maker_id, since)
+            .into_iter()
+            .map(|recorded| recorded.outcome)
+            .collect()
+    }
+
+    /// [`Self::since`] with what else was recorded of each outcome
+    #[must_use]
+    pub fn recorded_since(
+        &self,
+        market_maker_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Vec<RecordedOutcome> {
+        let oldest_kept = self.clock.now() - self.retention.max_age;
+        self.outcomes
+            .get(&market_maker_id)
+            .map(|outcomes| {
+                outcomes
+                    .iter()
+                    .filter(|recorded| recorded.outcome.decided_at >= oldest_kept)
+                    .filter(|recorded| {
+                        since.map_or(true, |since| recorded.outcome.decided_at > since)
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+    use common::ManualClock;
+    use otc_models::QuoteMode;
+
+    fn outcome(decided_at: DateTime<Utc>) -> QuoteOutcome {
+        QuoteOutcome {
+            request_id: Uuid::new_v4(),
+            quote_id: Uuid::new_v4(),
+            won: false,
+            mode: QuoteMode::ExactInput,
+            winning_amount: U256::from(100u64),
+            decided_at,
+        }
+    }
+
+    fn retention() -> QuoteOutcomeRetention {
+        QuoteOutcomeRetention {
+            max_age: chrono::Duration::hours(1),
+            max_per_market_maker: 2,
+        }
+    }
+
+    #[test]
+    fn test_outcomes_are_kept_per_market_maker_within_the_retention() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let history = QuoteOutcomeHistory::new(retention(), clock.clone());
+        let market_maker = Uuid::new_v4();
+        let now = clock.now();
+
+        history.record(
+            market_maker,
+            outcome(now - chrono::Duration::hours(2)),
+            None,
+        );
+        assert!(history.since(market_maker, None).is_empty());
+
+        let outcomes: Vec<_> = (1..=3)
+            .map(|minutes| outcome(now - chrono::Duration::minutes(10 - minutes)))
+            .collect();
+        for outcome in &outcomes {
+            history.record(market_maker, outcome.clone(), None);
+        }
+        assert_eq!(history.since(market_maker, None), outcomes[1..]);
+        assert_eq!(
+            history.since(market_maker, Some(outcomes[1].decided_at)),
+            outcomes[2..]
+        );
+        assert!(history.since(Uuid::new_v4(), None).is_empty());
+
+        // Outcomes age out as the clock moves on
+        clock.advance(chrono::Duration::minutes(53));
+        assert_eq!(history.since(market_maker, None), outcomes[2..]);
+    }
+
+    #[test]
+    fn test_outcomes_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quote_history.jsonl");
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let market_maker = Uuid::new_v4();
+        let now = clock.now();
+
+        let history = QuoteOutcomeHistory::open(&path, retention(), clock.clone()).unwrap();
+        let old = outcome(now - chrono::Duration::minutes(50));
+        let recent = outcome(now - chrono::Duration::minutes(5));
+        let tie_break = RecordedTieBreak {
+            rule: TieBreakRule::UniformRandom,
+            winning_quote_id: Uuid::new_v4(),
+            losing_quote_ids: vec![recent.quote_id],
+        };
+        history.record(market_maker, old.clone(), None);
+        history.record(market_maker, recent.clone(), Some(tie_break.clone()));
+        drop(history);
+
+        let reopened = QuoteOutcomeHistory::open(&path, retention(), clock.clone()).unwrap();
+        assert_eq!(
+            reopened.since(market_maker, None),
+            vec![old, recent.clone()]
+        );
+        drop(reopened);
+
+        // Whatever aged out by the next start is dropped from the file too
+        clock.advance(chrono::Duration::minutes(20));
+        let reopened = QuoteOutcomeHistory::open(&path, retention(), clock).unwrap();
+        assert_eq!(reopened.since(market_maker, None), vec![recent]);
+        assert_eq!(
+            reopened.recorded_since(market_maker, None)[0].tie_break,
+            Some(tie_break)
+        );
+        let lines = fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 1);
+    }
+}
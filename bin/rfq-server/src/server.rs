@@ -1,80 +1,412 @@
 use crate::{
-    error::RfqServerError, mm_registry::RfqMMRegistry, quote_aggregator::QuoteAggregator, Result,
-    RfqServerArgs,
+    error::RfqServerError,
+    mm_registry::{MMRegistryError, RfqMMRegistry},
+    quote_aggregator::{
+        QuoteAggregator, QuoteAggregatorError, QuoteRequestResult, QuoteTimeouts, QuoteValidity,
+    },
+    quote_lock::{QuoteLockError, QuoteLockLimits, QuoteLocker},
+    quote_outcomes::{QuoteOutcomeHistory, QuoteOutcomeRetention},
+    Result, RfqServerArgs,
 };
 use alloy::primitives::U256;
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Extension, Path, Query, State,
     },
     http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use common::{
+    api_docs_router, build_cors_layer, describe_websocket, trace_id_middleware, Clock,
+    MmSocketCounters, MmSocketGuard, MmSocketLimits, RateLimiter, RejectsInvalidJson, Shutdown,
+    SystemClock, TraceId, ValidatedJson, MAX_REQUEST_BODY_BYTES,
+};
 use futures_util::{SinkExt, StreamExt};
-use otc_auth::ApiKeyStore;
-use otc_models::{Currency, Lot, Quote, QuoteRequest};
-use otc_protocols::rfq::{
-    Connected, ProtocolMessage, QuoteWithFees, RFQRequest, RFQResponse, RFQResult,
+use otc_api_types::{
+    ConnectedMarketMakersQuery, ConnectedMarketMakersResponse, QuoteBatchRequest,
+    QuoteBatchResponse, QuoteBatchResult, QuoteLock, QuoteOutcomesQuery, QuoteOutcomesResponse,
+    RfqErrorResponse,
+};
+use otc_auth::{reload::reload_api_keys, ApiKeyStore, AuthError, ValidatedApiKey};
+use otc_models::{
+    ApiKeyScope, Currency, FieldError, Lot, Quote, QuoteRequest, SupportedCurrencies,
+    UnsupportedCurrency,
+};
+use otc_protocols::{
+    capabilities::{
+        AmountLimitsSource, Capabilities, Features, Limits, ProtocolVersions, QuoteBatchLimits,
+        QuoteSigningMode, ServerKind, API_VERSIONS, CAPABILITIES_PATH,
+    },
+    mm::{is_version_at_least, is_version_compatible},
+    rfq::{
+        Connected, ProtocolMessage, QuoteSigner, QuoteWithFees, RFQRequest, RFQResponse, RFQResult,
+        GOING_AWAY_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+    },
 };
 use serde::{Deserialize, Serialize};
 use snafu::ResultExt;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::sync::mpsc;
-use tower_http::cors::{AllowOrigin, CorsLayer};
-use tracing::{error, info, warn};
+use std::{
+    borrow::Cow, future::IntoFuture, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration,
+};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch},
+};
+use tracing::{error, info, info_span, warn, Instrument};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
 use uuid::Uuid;
 
+pub use otc_api_types::QuoteResponse;
+
 #[derive(Clone)]
 pub struct AppState {
     pub mm_registry: Arc<RfqMMRegistry>,
-    pub api_key_store: Arc<ApiKeyStore>,
+    /// Replaced whenever the API keys file changes
+    pub api_key_store: watch::Receiver<Arc<ApiKeyStore>>,
     pub quote_aggregator: Arc<QuoteAggregator>,
+    pub quote_locker: Arc<QuoteLocker>,
+    pub capabilities: Arc<Capabilities>,
+    pub quote_batch_limits: QuoteBatchLimits,
+    pub quote_batch_rate_limiter: Arc<RateLimiter>,
+    pub quote_lock_rate_limiter: Arc<RateLimiter>,
+    pub mm_socket_limits: MmSocketLimits,
+    pub mm_socket_counters: Arc<MmSocketCounters>,
+    /// Decimals each listed token must be requested with
+    pub supported_currencies: Arc<SupportedCurrencies>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl RejectsInvalidJson for AppState {
+    type Rejection = RfqServerError;
+
+    fn malformed_json(message: String) -> Self::Rejection {
+        RfqServerError::BadRequest { message }
+    }
+
+    fn invalid_fields(errors: Vec<FieldError>) -> Self::Rejection {
+        RfqServerError::Validation { errors }
+    }
+}
+
+/// Why market makers are disconnected during a shutdown
+const GOING_AWAY_REASON: &str = "server shutting down";
+
+/// Why a market maker is disconnected once its API key stops being accepted
+const API_KEY_INVALIDATED_REASON: &str = "API key no longer valid";
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 struct Status {
     pub status: String,
     pub version: String,
     pub connected_market_makers: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct QuoteResponse {
-    pub request_id: Uuid,
-    pub quote: Option<RFQResult<QuoteWithFees>>,
-    pub total_quotes_received: usize,
-    pub market_makers_contacted: usize,
+/// OpenAPI document of every route the RFQ server serves
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "RFQ server"),
+    paths(
+        status_handler,
+        mm_websocket_handler,
+        request_quotes,
+        request_quote_batch,
+        lock_quote,
+        get_capabilities,
+        get_connected_market_makers,
+        get_quote_outcomes,
+    ),
+    components(schemas(
+        Connected,
+        ProtocolMessage<RFQRequest>,
+        ProtocolMessage<RFQResponse>
+    )),
+    modifiers(&ApiDocSecurity)
+)]
+struct ApiDoc;
+
+/// Header credentials of the market maker websocket and routes, and the
+/// messages exchanged over the websocket
+struct ApiDocSecurity;
+
+impl Modify for ApiDocSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for (name, header) in [
+            ("mm_api_key_id", "x-api-key-id"),
+            ("mm_api_key", "x-api-key"),
+        ] {
+            components.add_security_scheme(
+                name,
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(header))),
+            );
+        }
+        describe_websocket(
+            openapi,
+            "/ws/mm",
+            "ProtocolMessage_RFQResponse",
+            "ProtocolMessage_RFQRequest",
+        );
+    }
 }
 
 pub async fn run_server(args: RfqServerArgs) -> Result<()> {
-    info!("Starting RFQ server...");
-    let addr = SocketAddr::from((args.host, args.port));
-
-    // Initialize API key store
-    let api_key_store = Arc::new(
-        ApiKeyStore::new(args.whitelist_file.into())
-            .await
-            .map_err(|e| crate::Error::ApiKeyLoad { source: e })?,
-    );
+    let listener = TcpListener::bind(SocketAddr::from((args.host, args.port)))
+        .await
+        .context(crate::ServerBindSnafu)?;
+    run_server_with_listener(args, listener).await
+}
 
-    // Initialize MM registry
-    let mm_registry = Arc::new(RfqMMRegistry::new());
+/// Run the server on a listener bound by the caller, whose address is served
+/// instead of `args.host` and `args.port`. Drains and returns on SIGTERM or ctrl-c
+pub async fn run_server_with_listener(args: RfqServerArgs, listener: TcpListener) -> Result<()> {
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
+    run_server_until(args, listener, shutdown).await
+}
 
-    // Initialize quote aggregator
-    let quote_aggregator = Arc::new(QuoteAggregator::new(
-        mm_registry.clone(),
-        args.quote_timeout_milliseconds,
-    ));
+/// Run the server on `listener` until `shutdown` is triggered. New connections
+/// are then refused, open quote requests are answered and market makers are
+/// told to reconnect elsewhere, all within `args.shutdown_drain_timeout_seconds`
+pub async fn run_server_until(
+    args: RfqServerArgs,
+    listener: TcpListener,
+    shutdown: Shutdown,
+) -> Result<()> {
+    RfqServer::build(args)
+        .await?
+        .with_shutdown(shutdown)
+        .serve(listener)
+        .await
+}
 
-    let state = AppState {
-        mm_registry,
-        api_key_store,
-        quote_aggregator,
-    };
+/// An RFQ server with its API keys and signing key loaded, not serving yet.
+/// [`run_server_until`] serves it; embedders and tests can call the router
+/// without a socket
+pub struct RfqServer {
+    args: RfqServerArgs,
+    state: AppState,
+    router: Router,
+    shutdown: Shutdown,
+    api_key_updates: watch::Sender<Arc<ApiKeyStore>>,
+}
+
+impl RfqServer {
+    /// Load the API keys, quote signing key and supported currencies
+    pub async fn build(args: RfqServerArgs) -> Result<Self> {
+        info!("Starting RFQ server...");
+
+        // Initialize API key store
+        let (api_key_updates, api_key_store) = watch::channel(Arc::new(
+            ApiKeyStore::new(args.whitelist_file.clone().into())
+                .await
+                .map_err(|e| crate::Error::ApiKeyLoad { source: e })?,
+        ));
+
+        // Initialize quote signer
+        let quote_signer = Arc::new(
+            QuoteSigner::from_hex(&args.quote_signing_key).context(crate::QuoteSigningKeySnafu)?,
+        );
+
+        let supported_currencies = Arc::new(match &args.supported_currencies_file {
+            Some(path) => {
+                SupportedCurrencies::load(path).context(crate::SupportedCurrenciesSnafu)?
+            }
+            None => SupportedCurrencies::default(),
+        });
+
+        // Initialize MM registry
+        let mm_registry = Arc::new(RfqMMRegistry::new());
+
+        // Initialize quote aggregator
+        let quote_timeouts = QuoteTimeouts {
+            base: Duration::from_millis(args.quote_timeout_milliseconds),
+            extension: Duration::from_millis(args.quote_timeout_extension_milliseconds),
+            max: Duration::from_millis(args.max_quote_timeout_milliseconds),
+        };
+        let quote_locker = Arc::new(QuoteLocker::new(
+            mm_registry.clone(),
+            quote_signer.clone(),
+            QuoteLockLimits {
+                ttl: chrono_seconds(args.quote_lock_ttl_seconds),
+                max_per_market_maker: args.max_quote_locks_per_market_maker,
+                response_timeout: quote_timeouts.longest(),
+            },
+        ));
+        let outcome_retention = QuoteOutcomeRetention {
+            max_age: chrono_seconds(args.quote_outcome_retention_seconds),
+            max_per_market_maker: args.max_quote_outcomes_per_market_maker,
+        };
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let outcome_history = match &args.quote_history_file {
+            Some(path) => QuoteOutcomeHistory::open(path, outcome_retention, clock)
+                .context(crate::QuoteHistorySnafu { path })?,
+            None => QuoteOutcomeHistory::new(outcome_retention, clock),
+        };
+        let quote_aggregator = Arc::new(
+            QuoteAggregator::new(
+                mm_registry.clone(),
+                quote_signer,
+                quote_timeouts,
+                QuoteValidity {
+                    max_lifetime: chrono_seconds(args.max_quote_lifetime_seconds),
+                    max_clock_skew: chrono_seconds(args.max_quote_clock_skew_seconds),
+                },
+            )
+            .with_outcome_history(outcome_history),
+        );
+
+        let quote_batch_limits = QuoteBatchLimits {
+            max_requests: args.max_quote_batch_size,
+            rate_limit_per_minute: args.quote_batch_rate_limit_per_minute,
+        };
+        let state = AppState {
+            mm_registry,
+            api_key_store,
+            quote_aggregator,
+            quote_locker,
+            capabilities: Arc::new(build_capabilities(quote_timeouts, quote_batch_limits)),
+            quote_batch_limits,
+            quote_batch_rate_limiter: Arc::new(
+                RateLimiter::per_minute(args.quote_batch_rate_limit_per_minute)
+                    .with_trusted_proxy_header(args.trusted_proxy_header.clone()),
+            ),
+            quote_lock_rate_limiter: Arc::new(
+                RateLimiter::per_minute(args.quote_lock_rate_limit_per_minute)
+                    .with_trusted_proxy_header(args.trusted_proxy_header.clone()),
+            ),
+            mm_socket_limits: MmSocketLimits {
+                max_message_bytes: args.mm_max_message_bytes,
+                max_malformed_messages: args.mm_max_malformed_messages,
+                malformed_window: Duration::from_secs(args.mm_malformed_window_seconds),
+                ..MmSocketLimits::default()
+            },
+            mm_socket_counters: Arc::new(MmSocketCounters::default()),
+            supported_currencies,
+        };
+        let router = build_router(&args.cors_domains, state.clone());
+
+        Ok(Self {
+            args,
+            state,
+            router,
+            shutdown: Shutdown::new(),
+            api_key_updates,
+        })
+    }
+
+    /// Drain the server when `shutdown` is triggered instead of on a shutdown
+    /// of its own
+    #[must_use]
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// What drains the server once triggered
+    #[must_use]
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    #[must_use]
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Every route this server serves with its state applied. Handlers that
+    /// read the client's address need a `ConnectInfo<SocketAddr>` extension,
+    /// which `serve` provides
+    #[must_use]
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
 
+    /// Serve on `listener` until the shutdown is triggered, then drain
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        let addr = listener.local_addr().context(crate::ServerBindSnafu)?;
+        info!("Listening on {}", addr);
+
+        let Self {
+            args,
+            state,
+            router,
+            shutdown,
+            api_key_updates,
+        } = self;
+        let mm_registry = state.mm_registry;
+
+        if args.whitelist_reload_interval_seconds > 0 {
+            tokio::spawn(reload_api_keys(
+                PathBuf::from(&args.whitelist_file),
+                Duration::from_secs(args.whitelist_reload_interval_seconds),
+                api_key_updates,
+                shutdown.clone(),
+            ));
+        }
+        tokio::spawn(
+            state
+                .quote_batch_rate_limiter
+                .clone()
+                .sweep_until(shutdown.clone()),
+        );
+        tokio::spawn(
+            state
+                .quote_lock_rate_limiter
+                .clone()
+                .sweep_until(shutdown.clone()),
+        );
+
+        let drain_timeout = Duration::from_secs(args.shutdown_drain_timeout_seconds);
+        // Batches and locks are rate limited per client IP
+        let serve = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+        let drained = async {
+            // Open quote requests still wait on the market makers' answers
+            let served = serve.into_future().await;
+            mm_registry.going_away(GOING_AWAY_REASON).await;
+            mm_registry.wait_until_disconnected().await;
+            served.context(crate::ServerStartSnafu)
+        };
+        tokio::pin!(drained);
+
+        tokio::select! {
+            result = &mut drained => return result,
+            () = shutdown.triggered() => {}
+        }
+        info!("Shutting down, draining for at most {:?}", drain_timeout);
+        match tokio::time::timeout(drain_timeout, drained).await {
+            Ok(result) => result?,
+            Err(_) => warn!(
+                "Drain did not finish within {:?}, exiting anyway",
+                drain_timeout
+            ),
+        }
+        info!("RFQ server stopped");
+
+        Ok(())
+    }
+}
+
+/// `secs` as a chrono duration, the longest chrono holds when it is further out
+fn chrono_seconds(secs: u64) -> chrono::Duration {
+    i64::try_from(secs)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .unwrap_or(chrono::Duration::MAX)
+}
+
+/// Every route, with `state` applied
+fn build_router(cors_domains: &[String], state: AppState) -> Router {
     let mut app = Router::new()
         // Health check
         .route("/status", get(status_handler))
@@ -82,63 +414,63 @@ pub async fn run_server(args: RfqServerArgs) -> Result<()> {
         .route("/ws/mm", get(mm_websocket_handler))
         // API endpoints
         .route("/api/v1/quotes/request", post(request_quotes))
+        .route("/api/v1/quotes/request-batch", post(request_quote_batch))
+        .route("/api/v1/quotes/:id/lock", post(lock_quote))
         .route(
             "/api/v1/market-makers/connected",
             get(get_connected_market_makers),
         )
+        .route("/api/v1/mm/quote-outcomes", get(get_quote_outcomes))
+        .route(CAPABILITIES_PATH, get(get_capabilities))
+        .merge(api_docs_router(ApiDoc::openapi()))
+        .layer(DefaultBodyLimit::max(MAX_REQUEST_BODY_BYTES))
+        .layer(middleware::from_fn(trace_id_middleware))
         .with_state(state);
 
-    // Add CORS layer if cors_domain is specified
-    if let Some(ref cors_domain_pattern) = args.cors_domain {
-        let cors_domain = cors_domain_pattern.clone();
-        let cors = if cors_domain == "*" {
-            // Allow all origins
-            CorsLayer::new()
-                .allow_origin(tower_http::cors::Any)
-                .allow_methods(tower_http::cors::Any)
-                .allow_headers(tower_http::cors::Any)
-        } else {
-            // Handle specific patterns
-            CorsLayer::new()
-                .allow_origin(AllowOrigin::predicate(move |origin, _request_parts| {
-                    let origin_str = origin.to_str().unwrap_or("");
-
-                    // Support wildcard patterns
-                    if cors_domain.contains('*') {
-                        let pattern = cors_domain.replace("*", "");
-                        if cors_domain.starts_with('*') {
-                            origin_str.ends_with(&pattern)
-                        } else if cors_domain.ends_with('*') {
-                            origin_str.starts_with(&pattern[..pattern.len() - 1])
-                        } else {
-                            // Handle middle wildcards like "*.example.*"
-                            let parts: Vec<&str> = cors_domain.split('*').collect();
-                            parts.iter().all(|part| origin_str.contains(part))
-                        }
-                    } else {
-                        origin_str == cors_domain
-                    }
-                }))
-                .allow_methods(tower_http::cors::Any)
-                .allow_headers(tower_http::cors::Any)
-        };
-
-        app = app.layer(cors);
-        info!("CORS enabled for domain: {}", cors_domain_pattern);
+    if !cors_domains.is_empty() {
+        app = app.layer(build_cors_layer(cors_domains));
+        info!("CORS enabled for domains: {}", cors_domains.join(", "));
     }
+    app
+}
 
-    info!("Listening on {}", addr);
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .context(crate::ServerBindSnafu)?;
-
-    axum::serve(listener, app)
-        .await
-        .context(crate::ServerStartSnafu)?;
-
-    Ok(())
+/// Describe what this deployment supports, derived from the config it was started with
+fn build_capabilities(
+    quote_timeouts: QuoteTimeouts,
+    quote_batch_limits: QuoteBatchLimits,
+) -> Capabilities {
+    Capabilities {
+        server: ServerKind::Rfq,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        api_versions: API_VERSIONS.iter().map(ToString::to_string).collect(),
+        mm_protocol: ProtocolVersions::default(),
+        features: Features {
+            // Every quote handed out is signed, see QuoteAggregator
+            quote_signing: QuoteSigningMode::Required,
+            encrypted_key_handoff: false,
+            partial_quotes: false,
+            webhooks: false,
+            announcements: false,
+            admin_api: false,
+        },
+        limits: Limits {
+            max_request_body_bytes: MAX_REQUEST_BODY_BYTES,
+            max_metadata_chars: None,
+            rate_limit_per_minute: None,
+            quote_timeout_ms: Some(quote_timeouts.longest().as_millis() as u64),
+            amount_limits_source: AmountLimitsSource::MarketMaker,
+            quote_batch: Some(quote_batch_limits),
+        },
+        chains: None,
+    }
 }
 
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "health",
+    responses((status = 200, description = "Server is up", body = Status))
+)]
 async fn status_handler(State(state): State<AppState>) -> Json<Status> {
     Json(Status {
         status: "ok".to_string(),
@@ -147,26 +479,84 @@ async fn status_handler(State(state): State<AppState>) -> Json<Status> {
     })
 }
 
+#[utoipa::path(
+    get,
+    path = "/ws/mm",
+    tag = "websocket",
+    params(
+        ("x-protocol-version" = Option<String>, Header, description = "RFQ protocol version the client speaks")
+    ),
+    security(("mm_api_key_id" = [], "mm_api_key" = [])),
+    responses(
+        (status = 101, description = "Upgraded to the market maker websocket"),
+        (status = 400, description = "Malformed headers or incompatible protocol version"),
+        (status = 401, description = "Missing or invalid API key"),
+        (status = 403, description = "API key lacks the rfq scope")
+    )
+)]
+/// Market maker connection. The server's first frame is `{"Connected": Connected}`,
+/// every later frame in either direction is a `ProtocolMessage` envelope.
 async fn mm_websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
-    // Extract and validate authentication headers
+    let key = match authenticate(&state, &headers, ApiKeyScope::Rfq) {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let protocol_version = match headers.get(PROTOCOL_VERSION_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(version) => version.to_string(),
+            Err(_) => {
+                return (StatusCode::BAD_REQUEST, "Invalid protocol version header")
+                    .into_response();
+            }
+        },
+        None => MIN_PROTOCOL_VERSION.to_string(),
+    };
+    if !is_version_compatible(&protocol_version) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Incompatible RFQ protocol version {protocol_version}"),
+        )
+            .into_response();
+    }
+
+    info!(
+        "Market maker {} authenticated via headers",
+        key.market_maker
+    );
+    state
+        .mm_socket_limits
+        .configure(ws)
+        .on_upgrade(move |socket| handle_mm_socket(socket, state, key, protocol_version))
+}
+
+/// The API key in the `x-api-key-id` and `x-api-key` headers if it has
+/// `scope`, or the response refusing the request
+fn authenticate(
+    state: &AppState,
+    headers: &HeaderMap,
+    scope: ApiKeyScope,
+) -> Result<ValidatedApiKey, Response> {
     let api_key_id = match headers.get("x-api-key-id") {
         Some(value) => match value.to_str() {
             Ok(id_str) => match Uuid::parse_str(id_str) {
                 Ok(id) => id,
                 Err(_) => {
-                    return (StatusCode::BAD_REQUEST, "Invalid API key ID format").into_response();
+                    return Err(
+                        (StatusCode::BAD_REQUEST, "Invalid API key ID format").into_response()
+                    );
                 }
             },
             Err(_) => {
-                return (StatusCode::BAD_REQUEST, "Invalid API key ID header").into_response();
+                return Err((StatusCode::BAD_REQUEST, "Invalid API key ID header").into_response());
             }
         },
         None => {
-            return (StatusCode::UNAUTHORIZED, "Missing X-API-Key-ID header").into_response();
+            return Err((StatusCode::UNAUTHORIZED, "Missing X-API-Key-ID header").into_response());
         }
     };
 
@@ -174,28 +564,46 @@ async fn mm_websocket_handler(
         Some(value) => match value.to_str() {
             Ok(key) => key,
             Err(_) => {
-                return (StatusCode::BAD_REQUEST, "Invalid API key header").into_response();
+                return Err((StatusCode::BAD_REQUEST, "Invalid API key header").into_response());
             }
         },
         None => {
-            return (StatusCode::UNAUTHORIZED, "Missing X-API-Key header").into_response();
+            return Err((StatusCode::UNAUTHORIZED, "Missing X-API-Key header").into_response());
         }
     };
 
-    // Validate the API key
-    match state.api_key_store.validate_by_id(&api_key_id, api_key) {
-        Ok(market_maker_id) => {
-            info!("Market maker {} authenticated via headers", market_maker_id);
-            ws.on_upgrade(move |socket| handle_mm_socket(socket, state, market_maker_id))
+    let api_keys = state.api_key_store.borrow().clone();
+    match api_keys
+        .validate_by_id(&api_key_id, api_key)
+        .and_then(|key| key.require_scope(scope))
+    {
+        Ok(key) => Ok(key),
+        Err(e @ AuthError::Revoked { .. }) => {
+            warn!("Rejected connection with a revoked API key: {}", e);
+            Err((StatusCode::UNAUTHORIZED, "Invalid API key").into_response())
+        }
+        Err(e @ AuthError::MissingScope { .. }) => {
+            warn!("Rejected connection: {}", e);
+            Err((
+                StatusCode::FORBIDDEN,
+                format!("API key is not allowed to use the {scope} API"),
+            )
+                .into_response())
         }
         Err(e) => {
             error!("API key validation failed: {}", e);
-            (StatusCode::UNAUTHORIZED, "Invalid API key").into_response()
+            Err((StatusCode::UNAUTHORIZED, "Invalid API key").into_response())
         }
     }
 }
 
-async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: String) {
+async fn handle_mm_socket(
+    socket: WebSocket,
+    state: AppState,
+    key: ValidatedApiKey,
+    protocol_version: String,
+) {
+    let market_maker_id = key.market_maker.clone();
     info!(
         "RFQ Market maker {} WebSocket connection established",
         market_maker_id
@@ -217,11 +625,10 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
     let (sender, mut receiver) = socket.split();
 
     // Register the MM
-    state.mm_registry.register(
-        mm_uuid,
-        tx.clone(),
-        "1.0.0".to_string(), // Default protocol version
-    );
+    let speaks_going_away = is_version_at_least(&protocol_version, GOING_AWAY_VERSION);
+    state
+        .mm_registry
+        .register(mm_uuid, tx.clone(), protocol_version);
 
     let mm_id = market_maker_id;
 
@@ -253,12 +660,24 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
     let sender_tx_clone = sender_tx.clone();
     tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
-            if let Ok(json) = serde_json::to_string(&msg) {
-                if sender_tx_clone.send(Message::Text(json)).await.is_err() {
-                    error!("Failed to send message to market maker {}", mm_id_clone);
-                    break;
+            let going_away = matches!(msg.payload, RFQRequest::GoingAway { .. });
+            // Market makers too old for GoingAway only get the close
+            if !going_away || speaks_going_away {
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    if sender_tx_clone.send(Message::Text(json)).await.is_err() {
+                        error!("Failed to send message to market maker {}", mm_id_clone);
+                        break;
+                    }
                 }
             }
+            if going_away {
+                let close = CloseFrame {
+                    code: close_code::AWAY,
+                    reason: Cow::Borrowed(GOING_AWAY_REASON),
+                };
+                let _ = sender_tx_clone.send(Message::Close(Some(close))).await;
+                break;
+            }
         }
     });
 
@@ -267,6 +686,7 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
     let mut sender = sender;
     tokio::spawn(async move {
         while let Some(msg) = sender_rx.recv().await {
+            let closing = matches!(msg, Message::Close(_));
             if sender.send(msg).await.is_err() {
                 error!(
                     "Failed to send message to market maker {} socket",
@@ -274,41 +694,97 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
                 );
                 break;
             }
+            if closing {
+                break;
+            }
         }
     });
 
     // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
+    let mut guard = MmSocketGuard::new(state.mm_socket_limits, &state.mm_socket_counters, &mm_id);
+    let mut api_keys = state.api_key_store.clone();
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            // The session lasts only as long as the key that opened it
+            Ok(()) = api_keys.changed() => {
+                if api_keys.borrow_and_update().still_accepts(&key) {
+                    continue;
+                }
+                warn!(
+                    "Disconnecting market maker {}, API key {} was removed, revoked or rotated",
+                    mm_id, key.id
+                );
+                let close = CloseFrame {
+                    code: close_code::POLICY,
+                    reason: Cow::Borrowed(API_KEY_INVALIDATED_REASON),
+                };
+                let _ = sender_tx.send(Message::Close(Some(close))).await;
+                break;
+            }
+        };
         match msg {
             Ok(Message::Text(text)) => {
+                if let Some(close) = guard.check_size(text.len()) {
+                    let _ = sender_tx.send(Message::Close(Some(close))).await;
+                    break;
+                }
                 match serde_json::from_str::<ProtocolMessage<RFQResponse>>(&text) {
-                    Ok(msg) => match &msg.payload {
-                        RFQResponse::QuoteResponse { request_id, .. } => {
-                            // Route the response to the appropriate aggregator
-                            state
-                                .mm_registry
-                                .handle_quote_response(*request_id, msg.payload.clone())
-                                .await;
+                    Ok(msg) => {
+                        guard.well_formed();
+                        let span = info_span!(
+                            "mm_message",
+                            trace_id = msg.trace_id.as_deref().unwrap_or_default()
+                        );
+                        async {
+                            match &msg.payload {
+                                RFQResponse::QuoteResponse { request_id, .. }
+                                | RFQResponse::QuoteLockResponse { request_id, .. }
+                                | RFQResponse::Error { request_id, .. } => {
+                                    // Route the response to whoever waits for it,
+                                    // errors count as an answer too
+                                    state
+                                        .mm_registry
+                                        .handle_quote_response(*request_id, msg.payload.clone())
+                                        .await;
+                                }
+                                RFQResponse::QuoteBatchResponse {
+                                    responses,
+                                    timestamp,
+                                } => {
+                                    state
+                                        .mm_registry
+                                        .handle_quote_batch_response(responses.clone(), *timestamp)
+                                        .await;
+                                }
+                                RFQResponse::Pong { .. } => {
+                                    state.mm_registry.record_pong(mm_uuid);
+                                }
+                            }
                         }
-                        RFQResponse::Pong { .. } => {
-                            // Handle pong for keepalive
-                        }
-                        RFQResponse::Error {
-                            error_code,
-                            message,
-                            ..
-                        } => {
-                            warn!(
-                                "Received error from market maker {}: {:?} - {}",
-                                mm_id, error_code, message
-                            );
-                        }
-                    },
+                        .instrument(span)
+                        .await;
+                    }
                     Err(e) => {
-                        error!("Failed to parse RFQ message: {}", e);
+                        if let Some(close) = guard.malformed(&text, &e) {
+                            let _ = sender_tx.send(Message::Close(Some(close))).await;
+                            break;
+                        }
                     }
                 }
             }
+            Ok(Message::Binary(data)) => {
+                let close = guard.check_size(data.len()).or_else(|| {
+                    guard.malformed(&String::from_utf8_lossy(&data), &"unexpected binary frame")
+                });
+                if let Some(close) = close {
+                    let _ = sender_tx.send(Message::Close(Some(close))).await;
+                    break;
+                }
+            }
             Ok(Message::Close(_)) => {
                 info!("Market maker {} disconnected", mm_id);
                 break;
@@ -326,9 +802,23 @@ async fn handle_mm_socket(socket: WebSocket, state: AppState, market_maker_id: S
     info!("Market maker {} unregistered", mm_id);
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/quotes/request",
+    tag = "quotes",
+    request_body = QuoteRequest,
+    responses(
+        (status = 200, description = "Best quote from the connected market makers", body = QuoteResponse),
+        (status = 400, description = "Invalid quote request", body = RfqErrorResponse),
+        (status = 404, description = "No market maker quoted", body = RfqErrorResponse),
+        (status = 408, description = "Quote collection timed out", body = RfqErrorResponse),
+        (status = 503, description = "No market makers connected", body = RfqErrorResponse)
+    )
+)]
 async fn request_quotes(
     State(state): State<AppState>,
-    Json(request): Json<QuoteRequest>,
+    Extension(TraceId(trace_id)): Extension<TraceId>,
+    ValidatedJson(request): ValidatedJson<QuoteRequest>,
 ) -> Result<Json<QuoteResponse>, RfqServerError> {
     info!(
         from_chain = ?request.from.chain,
@@ -337,50 +827,385 @@ async fn request_quotes(
         quote_mode = ?request.mode,
         "Received quote request"
     );
+    check_decimals(&state.supported_currencies, &request)?;
 
-    match state.quote_aggregator.request_quotes(request).await {
+    match state
+        .quote_aggregator
+        .request_quotes(request, &trace_id)
+        .await
+    {
         Ok(result) => {
             info!(
                 request_id = %result.request_id,
                 "Quote aggregation successful"
             );
 
-            Ok(Json(QuoteResponse {
-                request_id: result.request_id,
-                quote: result.best_quote,
-                total_quotes_received: result.total_quotes_received,
-                market_makers_contacted: result.market_makers_contacted,
-            }))
+            Ok(Json(quote_response(result, trace_id)))
         }
         Err(e) => {
             error!("Quote aggregation failed: {}", e);
-            match e {
-                crate::quote_aggregator::QuoteAggregatorError::NoMarketMakersConnected => {
-                    Err(RfqServerError::ServiceUnavailable {
-                        service: "market_makers".to_string(),
-                    })
-                }
-                crate::quote_aggregator::QuoteAggregatorError::NoQuotesReceived => {
-                    Err(RfqServerError::NoQuotesAvailable)
-                }
-                crate::quote_aggregator::QuoteAggregatorError::AggregationTimeout => {
-                    Err(RfqServerError::Timeout {
-                        message: "Quote collection timeout".to_string(),
-                    })
+            Err(aggregation_error(e))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/quotes/request-batch",
+    tag = "quotes",
+    request_body = QuoteBatchRequest,
+    responses(
+        (status = 200, description = "Best quote for each request of the batch, in request order", body = QuoteBatchResponse),
+        (status = 400, description = "Invalid quote request or batch size", body = RfqErrorResponse),
+        (status = 429, description = "Too many batches from this client", body = RfqErrorResponse),
+        (status = 503, description = "No market makers connected", body = RfqErrorResponse)
+    )
+)]
+/// Several quote requests at once, fanned out to each market maker as a single
+/// message where it supports batches
+async fn request_quote_batch(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(TraceId(trace_id)): Extension<TraceId>,
+    ValidatedJson(request): ValidatedJson<QuoteBatchRequest>,
+) -> Result<Json<QuoteBatchResponse>, RfqServerError> {
+    if !state.quote_batch_rate_limiter.check(&headers, client) {
+        return Err(RfqServerError::RateLimited {
+            message: "Too many quote batches, try again in a minute".to_string(),
+        });
+    }
+    let max_requests = state.quote_batch_limits.max_requests;
+    if request.requests.is_empty() || request.requests.len() > max_requests {
+        return Err(RfqServerError::BadRequest {
+            message: format!("A batch holds between 1 and {max_requests} quote requests"),
+        });
+    }
+
+    info!(requests = request.requests.len(), "Received quote batch");
+    for (index, quote_request) in request.requests.iter().enumerate() {
+        check_decimals(&state.supported_currencies, quote_request).map_err(|e| {
+            RfqServerError::DecimalsMismatch {
+                message: format!("request {index}: {e}"),
+            }
+        })?;
+    }
+
+    let batch = state
+        .quote_aggregator
+        .request_quote_batch(&request.requests, &trace_id)
+        .await
+        .map_err(|e| {
+            error!("Quote batch aggregation failed: {}", e);
+            aggregation_error(e)
+        })?;
+
+    let results = batch
+        .results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| match result {
+            Ok(result) => QuoteBatchResult {
+                index,
+                response: Some(quote_response(result, trace_id.clone())),
+                error: None,
+            },
+            Err(e) => {
+                let error = aggregation_error(e);
+                QuoteBatchResult {
+                    index,
+                    response: None,
+                    error: Some(RfqErrorResponse {
+                        code: error.code(),
+                        error: error.to_string(),
+                        fields: None,
+                    }),
                 }
             }
+        })
+        .collect();
+
+    Ok(Json(QuoteBatchResponse {
+        batch_id: batch.batch_id,
+        trace_id,
+        results,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/quotes/{id}/lock",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Id of a quote this server returned")),
+    security(("mm_api_key_id" = [], "mm_api_key" = [])),
+    responses(
+        (status = 200, description = "The market maker holds the funds until the lock expires", body = QuoteLock),
+        (status = 400, description = "Malformed headers"),
+        (status = 401, description = "Missing or invalid API key"),
+        (status = 403, description = "API key lacks the lock scope"),
+        (status = 404, description = "Unknown or expired quote", body = RfqErrorResponse),
+        (status = 408, description = "Market maker didn't answer in time", body = RfqErrorResponse),
+        (status = 409, description = "Quote already locked, or the market maker declined", body = RfqErrorResponse),
+        (status = 429, description = "Too many locks from this client, or the market maker holds too many", body = RfqErrorResponse),
+        (status = 503, description = "Market maker not connected", body = RfqErrorResponse)
+    )
+)]
+/// Have the quote's market maker hold the funds for it, so inventory running
+/// low before the swap is created can't fail it. Pass the lock along with the
+/// swap request. Needs an API key with the lock scope
+async fn lock_quote(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(quote_id): Path<Uuid>,
+    Extension(TraceId(trace_id)): Extension<TraceId>,
+) -> Result<Json<QuoteLock>, Response> {
+    // Checked before the key so guessing keys is rate limited too
+    if !state.quote_lock_rate_limiter.check(&headers, client) {
+        return Err(RfqServerError::RateLimited {
+            message: "Too many quote locks, try again in a minute".to_string(),
+        }
+        .into_response());
+    }
+    let key = authenticate(&state, &headers, ApiKeyScope::Lock)?;
+
+    let quote = state
+        .quote_aggregator
+        .issued_quote(quote_id)
+        .ok_or_else(|| {
+            RfqServerError::NotFound {
+                message: format!("Quote {quote_id} was not issued here or has expired"),
+            }
+            .into_response()
+        })?;
+
+    info!(
+        quote_id = %quote_id,
+        requested_by = %key.market_maker,
+        "Received quote lock request"
+    );
+    let lock = state
+        .quote_locker
+        .lock(&quote, &trace_id)
+        .await
+        .map_err(|e| {
+            warn!("Failed to lock quote {}: {}", quote_id, e);
+            quote_lock_error(e).into_response()
+        })?;
+    Ok(Json(lock))
+}
+
+/// The API error a failed quote lock surfaces as
+fn quote_lock_error(error: QuoteLockError) -> RfqServerError {
+    let message = error.to_string();
+    match error {
+        QuoteLockError::QuoteNotFound { .. } => RfqServerError::NotFound { message },
+        QuoteLockError::AlreadyLocked { .. } | QuoteLockError::Rejected { .. } => {
+            RfqServerError::Conflict { message }
         }
+        QuoteLockError::TooManyLocks { .. } => RfqServerError::RateLimited { message },
+        QuoteLockError::MarketMaker {
+            source: MMRegistryError::QuoteLocksNotSupported { .. },
+        } => RfqServerError::Conflict { message },
+        QuoteLockError::MarketMaker { .. } => RfqServerError::ServiceUnavailable {
+            service: "market_maker".to_string(),
+        },
+        QuoteLockError::Timeout { .. } => RfqServerError::Timeout { message },
+    }
+}
+
+/// What a client gets back for an aggregated quote request
+fn quote_response(result: QuoteRequestResult, trace_id: String) -> QuoteResponse {
+    let expires_in_seconds = match &result.best_quote {
+        Some(RFQResult::Success(quote)) => Some(quote.quote.expires_in_seconds(chrono::Utc::now())),
+        _ => None,
+    };
+    QuoteResponse {
+        request_id: result.request_id,
+        trace_id,
+        quote: result.best_quote,
+        expires_in_seconds,
+        total_quotes_received: result.total_quotes_received,
+        market_makers_contacted: result.market_makers_contacted,
+        rejected_quotes: result.rejected_quotes,
+        timing: result.timing,
+        tie_break: result.tie_break,
+    }
+}
+
+/// Reject a request claiming other decimals for a token than the supported
+/// currencies list. Tokens missing from the list are left for the market makers
+/// to turn down
+fn check_decimals(
+    supported_currencies: &SupportedCurrencies,
+    request: &QuoteRequest,
+) -> Result<(), RfqServerError> {
+    for currency in [&request.from, &request.to] {
+        if let Err(e @ UnsupportedCurrency::DecimalsMismatch { .. }) =
+            supported_currencies.check_currency(currency)
+        {
+            return Err(RfqServerError::DecimalsMismatch {
+                message: e.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The API error a failed quote aggregation surfaces as
+fn aggregation_error(error: QuoteAggregatorError) -> RfqServerError {
+    match error {
+        QuoteAggregatorError::NoMarketMakersConnected => RfqServerError::ServiceUnavailable {
+            service: "market_makers".to_string(),
+        },
+        QuoteAggregatorError::NoQuotesReceived => RfqServerError::NoQuotesAvailable,
+        QuoteAggregatorError::AggregationTimeout => RfqServerError::Timeout {
+            message: "Quote collection timeout".to_string(),
+        },
     }
 }
 
-#[derive(Serialize)]
-struct ConnectedMarketMakersResponse {
-    market_makers: Vec<Uuid>,
+#[utoipa::path(
+    get,
+    path = "/api/v1/capabilities",
+    tag = "health",
+    responses((status = 200, description = "Versions, features and limits of this server", body = Capabilities))
+)]
+async fn get_capabilities(State(state): State<AppState>) -> Json<Capabilities> {
+    Json(state.capabilities.as_ref().clone())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/market-makers/connected",
+    tag = "market-makers",
+    params(ConnectedMarketMakersQuery),
+    responses((status = 200, description = "Connected market makers, as ids or with their connection metadata", body = ConnectedMarketMakersResponse))
+)]
 async fn get_connected_market_makers(
     State(state): State<AppState>,
+    Query(query): Query<ConnectedMarketMakersQuery>,
 ) -> Json<ConnectedMarketMakersResponse> {
-    let market_makers = state.mm_registry.get_connected_market_makers();
-    Json(ConnectedMarketMakersResponse { market_makers })
+    let market_makers = state.mm_registry.connected_market_maker_details();
+    Json(query.respond(market_makers))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/mm/quote-outcomes",
+    tag = "market-makers",
+    params(QuoteOutcomesQuery),
+    security(("mm_api_key_id" = [], "mm_api_key" = [])),
+    responses(
+        (status = 200, description = "Outcomes of the requests the market maker quoted, oldest first", body = QuoteOutcomesResponse),
+        (status = 400, description = "Malformed headers or query"),
+        (status = 401, description = "Missing or invalid API key"),
+        (status = 403, description = "API key lacks the rfq scope")
+    )
+)]
+/// How the requests the calling market maker quoted were decided, the same
+/// outcomes the websocket pushes, for catching up after a disconnect. Only
+/// outcomes this server decided are known
+async fn get_quote_outcomes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<QuoteOutcomesQuery>,
+) -> Result<Json<QuoteOutcomesResponse>, Response> {
+    let key = authenticate(&state, &headers, ApiKeyScope::Rfq)?;
+    let market_maker_id = Uuid::parse_str(&key.market_maker).map_err(|e| {
+        error!("Invalid market maker UUID {}: {}", key.market_maker, e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?;
+    Ok(Json(QuoteOutcomesResponse {
+        market_maker_id,
+        outcomes: state
+            .quote_aggregator
+            .quote_outcomes(market_maker_id, query.since),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_durations_past_a_year_are_refused() {
+        let parse = |ttl: &str| {
+            RfqServerArgs::try_parse_from([
+                "rfq-server",
+                "--quote-signing-key",
+                "00",
+                "--quote-lock-ttl-seconds",
+                ttl,
+            ])
+        };
+        let args = parse(&crate::MAX_DURATION_SECONDS.to_string()).unwrap();
+        assert_eq!(
+            chrono_seconds(args.quote_lock_ttl_seconds),
+            chrono::Duration::days(365)
+        );
+        assert!(parse(&u64::MAX.to_string()).is_err());
+        assert_eq!(chrono_seconds(u64::MAX), chrono::Duration::MAX);
+    }
+
+    #[test]
+    fn test_openapi_covers_every_route() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        oas3::from_json(document.to_string()).expect("spec is valid OpenAPI 3.1");
+        assert_eq!(common::dangling_refs(&document), Vec::<String>::new());
+
+        for (method, path) in [
+            ("get", "/status"),
+            ("get", "/ws/mm"),
+            ("post", "/api/v1/quotes/request"),
+            ("post", "/api/v1/quotes/request-batch"),
+            ("post", "/api/v1/quotes/{id}/lock"),
+            ("get", CAPABILITIES_PATH),
+            ("get", "/api/v1/market-makers/connected"),
+            ("get", "/api/v1/mm/quote-outcomes"),
+        ] {
+            assert!(
+                document["paths"][path][method].is_object(),
+                "{method} {path} is not documented"
+            );
+        }
+
+        let websocket = &document["paths"]["/ws/mm"]["get"]["x-websocket"];
+        assert_eq!(
+            websocket["server_messages"]["$ref"],
+            "#/components/schemas/ProtocolMessage_RFQRequest"
+        );
+        assert_eq!(document["components"]["schemas"]["U256"]["type"], "string");
+    }
+
+    #[test]
+    fn test_requests_with_the_wrong_decimals_are_rejected() {
+        let supported_currencies = SupportedCurrencies::default();
+        let bitcoin = supported_currencies.all()[0].currency();
+        let cbbtc = supported_currencies.all()[1].currency();
+        let request = |from: Currency| QuoteRequest {
+            mode: otc_models::QuoteMode::ExactInput,
+            from,
+            to: bitcoin.clone(),
+            amount: U256::from(100_000u64),
+        };
+
+        assert!(check_decimals(&supported_currencies, &request(cbbtc.clone())).is_ok());
+
+        let mut wrong_decimals = cbbtc.clone();
+        wrong_decimals.decimals = 18;
+        assert!(matches!(
+            check_decimals(&supported_currencies, &request(wrong_decimals)),
+            Err(RfqServerError::DecimalsMismatch { .. })
+        ));
+
+        // Unlisted tokens are up to the market makers
+        let mut unlisted = cbbtc;
+        unlisted.token = otc_models::TokenIdentifier::Address(
+            "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599".to_string(),
+        );
+        unlisted.decimals = 18;
+        assert!(check_decimals(&supported_currencies, &request(unlisted)).is_ok());
+    }
 }
@@ -4,41 +4,87 @@ use otc_models::Lot;
 pub const PROTOCOL_FEE_BPS: u64 = 10;
 pub const MIN_PROTOCOL_FEE_SATS: u64 = 300;
 
-pub fn compute_protocol_fee_sats(sats: u64) -> u64 {
-    let fee = sats.saturating_mul(PROTOCOL_FEE_BPS) / 10_000;
-    if fee < MIN_PROTOCOL_FEE_SATS {
-        MIN_PROTOCOL_FEE_SATS
-    } else {
-        fee
-    }
+/// Rate and floor of the protocol fee taken out of every payout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolFeeParams {
+    pub bps: u64,
+    pub min_sats: u64,
 }
 
-/// Given an amount, compute what the original amount was before the protocol fee was removed.
-pub fn inverse_compute_protocol_fee(g: u64) -> u64 {
-    let threshold = MIN_PROTOCOL_FEE_SATS
-        .saturating_mul(10_000)
-        .saturating_div(PROTOCOL_FEE_BPS);
+impl ProtocolFeeParams {
+    /// The fee the OTC server requires every fill to pay at least
+    pub const DEFAULT: Self = Self {
+        bps: PROTOCOL_FEE_BPS,
+        min_sats: MIN_PROTOCOL_FEE_SATS,
+    };
+
+    pub fn compute_fee_sats(&self, sats: u64) -> u64 {
+        let fee = sats.saturating_mul(self.bps) / 10_000;
+        if fee < self.min_sats {
+            self.min_sats
+        } else {
+            fee
+        }
+    }
+
+    /// Given an amount, compute what the original amount was before the protocol fee was removed.
+    pub fn inverse_compute_fee(&self, g: u64) -> u64 {
+        if self.bps == 0 {
+            return g.saturating_add(self.min_sats);
+        }
+        let threshold = self
+            .min_sats
+            .saturating_mul(10_000)
+            .saturating_div(self.bps);
 
-    let max_g_for_min_fee = threshold.saturating_sub(MIN_PROTOCOL_FEE_SATS);
+        let max_g_for_min_fee = threshold.saturating_sub(self.min_sats);
 
-    if g < max_g_for_min_fee {
-        g.saturating_add(MIN_PROTOCOL_FEE_SATS)
-    } else {
-        g.saturating_mul(10_000)
-            .saturating_div(10_000 - PROTOCOL_FEE_BPS)
+        if g < max_g_for_min_fee {
+            g.saturating_add(self.min_sats)
+        } else {
+            g.saturating_mul(10_000).saturating_div(10_000 - self.bps)
+        }
+    }
+
+    /// Whether every fee charged under these params is at least the one `other` would charge
+    pub fn covers(&self, other: &Self) -> bool {
+        self.bps >= other.bps && self.min_sats >= other.min_sats
+    }
+}
+
+impl Default for ProtocolFeeParams {
+    fn default() -> Self {
+        Self::DEFAULT
     }
 }
 
+pub fn compute_protocol_fee_sats(sats: u64) -> u64 {
+    ProtocolFeeParams::DEFAULT.compute_fee_sats(sats)
+}
+
+/// Given an amount, compute what the original amount was before the protocol fee was removed.
+pub fn inverse_compute_protocol_fee(g: u64) -> u64 {
+    ProtocolFeeParams::DEFAULT.inverse_compute_fee(g)
+}
+
 pub trait FeeCalcFromLot {
     fn compute_protocol_fee(&self) -> u64;
+
+    /// The protocol fee owed on top of this lot under the given params
+    fn compute_protocol_fee_with(&self, params: &ProtocolFeeParams) -> u64;
 }
 
 impl FeeCalcFromLot for Lot {
     fn compute_protocol_fee(&self) -> u64 {
-        inverse_compute_protocol_fee(self.amount.to::<u64>()) - self.amount.to::<u64>()
+        self.compute_protocol_fee_with(&ProtocolFeeParams::DEFAULT)
+    }
+
+    fn compute_protocol_fee_with(&self, params: &ProtocolFeeParams) -> u64 {
+        params.inverse_compute_fee(self.amount.to::<u64>()) - self.amount.to::<u64>()
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -60,4 +106,29 @@ mod tests {
             assert_eq!(amount_sats, amount_before_fee, "Fee computation is correct");
         }
     }
+
+    #[test]
+    fn test_custom_fee_params_inversion() {
+        let params = ProtocolFeeParams {
+            bps: 25,
+            min_sats: 1_000,
+        };
+        for amount_sats in [1_000, 5_000, 400_000, 10_000_000] {
+            let fee_sats = params.compute_fee_sats(amount_sats);
+            let amount_after_fee = amount_sats.saturating_sub(fee_sats);
+            assert_eq!(params.inverse_compute_fee(amount_after_fee), amount_sats);
+        }
+        assert!(params.covers(&ProtocolFeeParams::DEFAULT));
+        assert!(!ProtocolFeeParams::DEFAULT.covers(&params));
+    }
+
+    #[test]
+    fn test_zero_bps_charges_the_minimum() {
+        let params = ProtocolFeeParams {
+            bps: 0,
+            min_sats: 500,
+        };
+        assert_eq!(params.compute_fee_sats(10_000_000), 500);
+        assert_eq!(params.inverse_compute_fee(10_000_000), 10_000_500);
+    }
 }
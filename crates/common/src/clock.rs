@@ -0,0 +1,112 @@
+//! The time the servers decide expiries by
+//!
+//! Quote and lock expiries are compared against [`Clock::now`] rather than the
+//! database's `NOW()`, so the app and SQL layers can't disagree about whether a
+//! quote is still live. [`check_clock_drift`] flags hosts whose clock wandered
+//! off from the database's.
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use tracing::{info, warn};
+
+/// Largest difference from the database clock that goes without a warning
+pub const MAX_CLOCK_DRIFT: Duration = Duration::seconds(1);
+
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The host's wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, so tests can sit exactly on an expiry
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    #[must_use]
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("clock mutex poisoned") = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().expect("clock mutex poisoned") += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("clock mutex poisoned")
+    }
+}
+
+/// Compare `clock` with the database's `NOW()`, warning when they're more than
+/// [`MAX_CLOCK_DRIFT`] apart. Returns how far `clock` is ahead of the database
+pub fn check_clock_drift(clock: &dyn Clock, database_now: DateTime<Utc>) -> Duration {
+    let drift = clock.now() - database_now;
+    if drift.abs() > MAX_CLOCK_DRIFT {
+        warn!(
+            drift_ms = drift.num_milliseconds(),
+            "Clock differs from the database's, expiries are decided by this host's clock"
+        );
+    } else {
+        info!(
+            drift_ms = drift.num_milliseconds(),
+            "Clock agrees with the database"
+        );
+    }
+    drift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_only_moves_when_told() {
+        let start = Utc::now();
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::seconds(30));
+        assert_eq!(clock.now(), start + Duration::seconds(30));
+        // Clones share the time
+        clock.clone().set(start);
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn test_drift_is_measured_against_the_database() {
+        let database_now = Utc::now();
+        let clock = ManualClock::new(database_now + Duration::milliseconds(1_500));
+        assert_eq!(
+            check_clock_drift(&clock, database_now),
+            Duration::milliseconds(1_500)
+        );
+
+        clock.set(database_now - Duration::seconds(3));
+        assert_eq!(
+            check_clock_drift(&clock, database_now),
+            Duration::seconds(-3)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! CORS layer shared by the HTTP servers
+//!
+//! Each allowed domain is either an exact origin or a pattern where `*`
+//! matches any run of characters, e.g. `https://*.example.com`. A lone `*`
+//! allows every origin. The `null` origin sent by sandboxed frames and local
+//! files is only allowed when listed explicitly, wildcards never match it.
+
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+const NULL_ORIGIN: &str = "null";
+
+/// CORS layer allowing requests from any of `domains`
+#[must_use]
+pub fn build_cors_layer(domains: &[String]) -> CorsLayer {
+    let patterns: Vec<String> = domains
+        .iter()
+        .map(|domain| domain.trim().to_ascii_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .collect();
+
+    let allow_origin = if patterns.iter().any(|pattern| pattern == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::predicate(move |origin, _request_parts| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| origin_allowed(&patterns, origin))
+        })
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(Any)
+        .allow_headers(Any)
+}
+
+/// Whether `origin` matches any of the lowercase `patterns`
+fn origin_allowed(patterns: &[String], origin: &str) -> bool {
+    let origin = origin.to_ascii_lowercase();
+    if origin == NULL_ORIGIN {
+        return patterns.iter().any(|pattern| pattern == NULL_ORIGIN);
+    }
+    patterns
+        .iter()
+        .any(|pattern| matches_pattern(pattern, &origin))
+}
+
+/// Match `origin` against `pattern`, where each `*` matches any run of characters
+fn matches_pattern(pattern: &str, origin: &str) -> bool {
+    let mut parts: Vec<&str> = pattern.split('*').collect();
+    let Some(mut rest) = origin.strip_prefix(parts.remove(0)) else {
+        return false;
+    };
+    // No wildcard, the prefix has to be the whole origin
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(domains: &[&str], origin: &str) -> bool {
+        let patterns: Vec<String> = domains.iter().map(|d| d.to_ascii_lowercase()).collect();
+        origin_allowed(&patterns, origin)
+    }
+
+    #[test]
+    fn test_exact_origin() {
+        assert!(allowed(
+            &["https://app.example.com"],
+            "https://app.example.com"
+        ));
+        assert!(allowed(
+            &["https://app.example.com"],
+            "HTTPS://App.Example.com"
+        ));
+        assert!(!allowed(
+            &["https://app.example.com"],
+            "https://app.example.com.evil.com"
+        ));
+        assert!(!allowed(
+            &["https://app.example.com"],
+            "http://app.example.com"
+        ));
+    }
+
+    #[test]
+    fn test_leading_wildcard() {
+        let domains = ["*.example.com"];
+        assert!(allowed(&domains, "https://app.example.com"));
+        assert!(allowed(&domains, "http://a.b.example.com"));
+        assert!(!allowed(&domains, "https://example.com"));
+        assert!(!allowed(&domains, "https://app.example.com.evil.com"));
+        assert!(!allowed(&domains, "https://evilexample.com"));
+    }
+
+    #[test]
+    fn test_trailing_wildcard() {
+        let domains = ["https://app.example.*"];
+        assert!(allowed(&domains, "https://app.example.com"));
+        assert!(allowed(&domains, "https://app.example.org"));
+        // The character before the wildcard is part of the pattern
+        assert!(!allowed(&domains, "https://app.examplex"));
+        assert!(!allowed(&domains, "http://app.example.com"));
+    }
+
+    #[test]
+    fn test_middle_wildcard() {
+        let domains = ["https://*.example.com"];
+        assert!(allowed(&domains, "https://app.example.com"));
+        assert!(!allowed(&domains, "http://app.example.com"));
+        assert!(!allowed(&domains, "https://app.example.com.evil.com"));
+
+        // Parts must appear in order without overlapping
+        let domains = ["https://*.example.*"];
+        assert!(allowed(&domains, "https://app.example.io"));
+        assert!(!allowed(&domains, "https://example.io"));
+        assert!(!allowed(&["https://ab*ba.com"], "https://aba.com"));
+    }
+
+    #[test]
+    fn test_multiple_domains() {
+        let domains = ["https://app.example.com", "*.staging.example.com"];
+        assert!(allowed(&domains, "https://app.example.com"));
+        assert!(allowed(&domains, "https://pr-1.staging.example.com"));
+        assert!(!allowed(&domains, "https://other.example.com"));
+    }
+
+    #[test]
+    fn test_null_origin_only_when_listed() {
+        assert!(!allowed(&["*null*", "n*"], "null"));
+        assert!(allowed(&["https://app.example.com", "null"], "null"));
+    }
+}
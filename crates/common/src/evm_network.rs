@@ -0,0 +1,67 @@
+//! Endpoints of the EVM networks served next to Ethereum mainnet
+//!
+//! Each network is given on the command line as `<chain_id>=<url>`, e.g.
+//! `8453=https://base.example.com`, and its tokens are listed in the supported
+//! currencies file under the same chain id.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// An endpoint of the EVM network with chain id `chain_id`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmNetworkUrl {
+    pub chain_id: u64,
+    pub url: String,
+}
+
+impl fmt::Display for EvmNetworkUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.chain_id, self.url)
+    }
+}
+
+impl FromStr for EvmNetworkUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid EVM network {s:?}, expected <chain_id>=<url>");
+        let (chain_id, url) = s.split_once('=').ok_or_else(invalid)?;
+        let chain_id = chain_id.trim().parse().map_err(|_| invalid())?;
+        let url = url.trim();
+        if url.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            chain_id,
+            url: url.to_string(),
+        })
+    }
+}
+
+/// The URL given for `chain_id`, the last one if it's listed more than once
+#[must_use]
+pub fn evm_network_url(urls: &[EvmNetworkUrl], chain_id: u64) -> Option<&str> {
+    urls.iter()
+        .rev()
+        .find(|network| network.chain_id == chain_id)
+        .map(|network| network.url.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trips() {
+        let network: EvmNetworkUrl = "8453=wss://base.example.com/ws?key=a".parse().unwrap();
+        assert_eq!(network.chain_id, 8453);
+        assert_eq!(network.url, "wss://base.example.com/ws?key=a");
+        assert_eq!(network.to_string().parse::<EvmNetworkUrl>(), Ok(network));
+
+        assert!("https://base.example.com".parse::<EvmNetworkUrl>().is_err());
+        assert!("base=https://base.example.com"
+            .parse::<EvmNetworkUrl>()
+            .is_err());
+        assert!("8453=".parse::<EvmNetworkUrl>().is_err());
+    }
+}
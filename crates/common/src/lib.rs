@@ -0,0 +1,20 @@
+mod clock;
+mod cors;
+mod evm_network;
+mod mm_socket;
+mod openapi;
+mod rate_limiter;
+mod reconnect;
+mod shutdown;
+mod trace_id;
+mod validated_json;
+pub use clock::*;
+pub use cors::*;
+pub use evm_network::*;
+pub use mm_socket::*;
+pub use openapi::*;
+pub use rate_limiter::*;
+pub use reconnect::*;
+pub use shutdown::*;
+pub use trace_id::*;
+pub use validated_json::*;
@@ -0,0 +1,292 @@
+//! Limits on what an authenticated market maker can push through its websocket
+//!
+//! Oversized frames close the connection before they're parsed, and a run of
+//! messages that don't parse closes it too. Deeply nested JSON needs no guard of
+//! its own: serde_json gives up past 128 levels, so it counts as malformed.
+//! Going the other way, a market maker that lets its outgoing queue fill up is
+//! disconnected as a slow consumer.
+
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use axum::extract::ws::{close_code, CloseFrame, WebSocketUpgrade};
+use tracing::warn;
+
+/// Largest message a market maker may send
+pub const DEFAULT_MM_MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// Malformed messages in a row that get a market maker disconnected
+pub const DEFAULT_MM_MAX_MALFORMED_MESSAGES: u32 = 5;
+
+/// How long a run of malformed messages is remembered
+pub const DEFAULT_MM_MALFORMED_WINDOW: Duration = Duration::from_secs(60);
+
+/// Messages waiting to be written to a market maker before it's disconnected
+pub const DEFAULT_MM_SEND_QUEUE_CAPACITY: usize = 256;
+
+/// Close reason for a message over the size cap
+pub const MESSAGE_TOO_BIG_REASON: &str = "message too big";
+
+/// Close reason for too many malformed messages
+pub const TOO_MANY_MALFORMED_REASON: &str = "too many malformed messages";
+
+/// Close code for a market maker that doesn't read its messages fast enough,
+/// from the range left to applications
+pub const SLOW_CONSUMER_CLOSE_CODE: u16 = 4000;
+
+/// Close reason for a market maker that doesn't read its messages fast enough
+pub const SLOW_CONSUMER_REASON: &str = "slow consumer";
+
+/// Characters of an offending payload that make it into the logs
+const LOGGED_PAYLOAD_CHARS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmSocketLimits {
+    pub max_message_bytes: usize,
+    pub max_malformed_messages: u32,
+    pub malformed_window: Duration,
+    pub send_queue_capacity: usize,
+}
+
+impl Default for MmSocketLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: DEFAULT_MM_MAX_MESSAGE_BYTES,
+            max_malformed_messages: DEFAULT_MM_MAX_MALFORMED_MESSAGES,
+            malformed_window: DEFAULT_MM_MALFORMED_WINDOW,
+            send_queue_capacity: DEFAULT_MM_SEND_QUEUE_CAPACITY,
+        }
+    }
+}
+
+impl MmSocketLimits {
+    /// Bounds what the transport buffers for one message. Frames up to twice the
+    /// cap are still read so the market maker is told why it's disconnected,
+    /// anything bigger fails the connection outright.
+    #[must_use]
+    pub fn configure(&self, ws: WebSocketUpgrade) -> WebSocketUpgrade {
+        let transport_limit = self.max_message_bytes.saturating_mul(2);
+        ws.max_message_size(transport_limit)
+            .max_frame_size(transport_limit)
+    }
+}
+
+/// Messages refused, and market makers dropped for not keeping up, across
+/// every market maker connection
+#[derive(Debug, Default)]
+pub struct MmSocketCounters {
+    oversized: AtomicU64,
+    malformed: AtomicU64,
+    slow_consumers: AtomicU64,
+    disconnects: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MmSocketCounts {
+    pub oversized: u64,
+    pub malformed: u64,
+    pub slow_consumers: u64,
+    pub disconnects: u64,
+}
+
+impl MmSocketCounters {
+    #[must_use]
+    pub fn snapshot(&self) -> MmSocketCounts {
+        MmSocketCounts {
+            oversized: self.oversized.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            slow_consumers: self.slow_consumers.load(Ordering::Relaxed),
+            disconnects: self.disconnects.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counts a market maker disconnected for letting its outgoing queue fill up,
+    /// returning the close to send it
+    pub fn slow_consumer(&self, market_maker_id: &str) -> CloseFrame<'static> {
+        warn!(
+            market_maker_id,
+            "Disconnecting market maker that isn't reading its messages"
+        );
+        self.slow_consumers.fetch_add(1, Ordering::Relaxed);
+        self.disconnects.fetch_add(1, Ordering::Relaxed);
+        CloseFrame {
+            code: SLOW_CONSUMER_CLOSE_CODE,
+            reason: Cow::Borrowed(SLOW_CONSUMER_REASON),
+        }
+    }
+}
+
+/// One connection's view of the limits
+#[derive(Debug)]
+pub struct MmSocketGuard<'a> {
+    limits: MmSocketLimits,
+    counters: &'a MmSocketCounters,
+    market_maker_id: &'a str,
+    malformed_streak: u32,
+    streak_started_at: Option<Instant>,
+}
+
+impl<'a> MmSocketGuard<'a> {
+    #[must_use]
+    pub fn new(
+        limits: MmSocketLimits,
+        counters: &'a MmSocketCounters,
+        market_maker_id: &'a str,
+    ) -> Self {
+        Self {
+            limits,
+            counters,
+            market_maker_id,
+            malformed_streak: 0,
+            streak_started_at: None,
+        }
+    }
+
+    /// Checks a message's size before it's parsed, returning the close to send
+    /// when it's over the cap
+    pub fn check_size(&self, len: usize) -> Option<CloseFrame<'static>> {
+        if len <= self.limits.max_message_bytes {
+            return None;
+        }
+        warn!(
+            market_maker_id = self.market_maker_id,
+            len,
+            max = self.limits.max_message_bytes,
+            "Disconnecting market maker for an oversized message"
+        );
+        self.counters.oversized.fetch_add(1, Ordering::Relaxed);
+        self.counters.disconnects.fetch_add(1, Ordering::Relaxed);
+        Some(CloseFrame {
+            code: close_code::SIZE,
+            reason: Cow::Borrowed(MESSAGE_TOO_BIG_REASON),
+        })
+    }
+
+    /// Records a message that didn't parse, returning the close to send once the
+    /// market maker has sent too many in a row
+    pub fn malformed(&mut self, payload: &str, error: &dyn Display) -> Option<CloseFrame<'static>> {
+        self.malformed_at(payload, error, Instant::now())
+    }
+
+    /// Ends a run of malformed messages
+    pub fn well_formed(&mut self) {
+        self.malformed_streak = 0;
+        self.streak_started_at = None;
+    }
+
+    fn malformed_at(
+        &mut self,
+        payload: &str,
+        error: &dyn Display,
+        now: Instant,
+    ) -> Option<CloseFrame<'static>> {
+        let truncated: String = payload.chars().take(LOGGED_PAYLOAD_CHARS).collect();
+        warn!(
+            market_maker_id = self.market_maker_id,
+            payload = %truncated,
+            "Failed to parse market maker message: {error}"
+        );
+        self.counters.malformed.fetch_add(1, Ordering::Relaxed);
+
+        match self.streak_started_at {
+            Some(started) if now.duration_since(started) <= self.limits.malformed_window => {
+                self.malformed_streak += 1;
+            }
+            _ => {
+                self.malformed_streak = 1;
+                self.streak_started_at = Some(now);
+            }
+        }
+        if self.malformed_streak < self.limits.max_malformed_messages {
+            return None;
+        }
+
+        warn!(
+            market_maker_id = self.market_maker_id,
+            malformed = self.malformed_streak,
+            "Disconnecting market maker for malformed messages"
+        );
+        self.counters.disconnects.fetch_add(1, Ordering::Relaxed);
+        Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: Cow::Borrowed(TOO_MANY_MALFORMED_REASON),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> MmSocketLimits {
+        MmSocketLimits {
+            max_message_bytes: 16,
+            max_malformed_messages: 3,
+            malformed_window: Duration::from_secs(10),
+            send_queue_capacity: 4,
+        }
+    }
+
+    #[test]
+    fn test_oversized_messages_close_with_size() {
+        let counters = MmSocketCounters::default();
+        let guard = MmSocketGuard::new(limits(), &counters, "mm");
+
+        assert!(guard.check_size(16).is_none());
+        let close = guard.check_size(17).unwrap();
+        assert_eq!(close.code, close_code::SIZE);
+        assert_eq!(
+            counters.snapshot(),
+            MmSocketCounts {
+                oversized: 1,
+                malformed: 0,
+                slow_consumers: 0,
+                disconnects: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_malformed_streak_disconnects_within_window() {
+        let counters = MmSocketCounters::default();
+        let mut guard = MmSocketGuard::new(limits(), &counters, "mm");
+        let start = Instant::now();
+
+        assert!(guard.malformed_at("{", &"eof", start).is_none());
+        assert!(guard.malformed_at("{", &"eof", start).is_none());
+        // A good message ends the streak
+        guard.well_formed();
+        assert!(guard.malformed_at("{", &"eof", start).is_none());
+        assert!(guard.malformed_at("{", &"eof", start).is_none());
+        // So does the window running out
+        let later = start + Duration::from_secs(11);
+        assert!(guard.malformed_at("{", &"eof", later).is_none());
+        assert!(guard.malformed_at("{", &"eof", later).is_none());
+
+        let close = guard.malformed_at("{", &"eof", later).unwrap();
+        assert_eq!(close.code, close_code::POLICY);
+        assert_eq!(counters.snapshot().malformed, 7);
+        assert_eq!(counters.snapshot().disconnects, 1);
+    }
+
+    #[test]
+    fn test_slow_consumers_are_closed_with_their_own_code() {
+        let counters = MmSocketCounters::default();
+
+        let close = counters.slow_consumer("mm");
+        assert_eq!(close.code, SLOW_CONSUMER_CLOSE_CODE);
+        assert_eq!(close.reason, SLOW_CONSUMER_REASON);
+        assert_eq!(counters.snapshot().slow_consumers, 1);
+        assert_eq!(counters.snapshot().disconnects, 1);
+    }
+
+    #[test]
+    fn test_deeply_nested_json_is_malformed() {
+        let nested = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        assert!(serde_json::from_str::<serde_json::Value>(&nested).is_err());
+    }
+}
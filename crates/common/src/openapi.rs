@@ -0,0 +1,140 @@
+//! OpenAPI document serving shared by the HTTP servers
+//!
+//! Each server derives its document with utoipa and serves it at
+//! [`OPENAPI_PATH`]. Building with the `swagger-ui` feature also serves a
+//! browsable UI at [`SWAGGER_UI_PATH`]. OpenAPI has no way to describe
+//! websocket traffic, so [`describe_websocket`] attaches the message schemas
+//! to the upgrade route as an `x-websocket` extension instead.
+
+use axum::Router;
+use serde_json::{json, Value};
+use utoipa::openapi::extensions::ExtensionsBuilder;
+use utoipa::openapi::OpenApi;
+
+pub const OPENAPI_PATH: &str = "/api-docs/openapi.json";
+pub const SWAGGER_UI_PATH: &str = "/swagger-ui";
+
+const SCHEMA_REF_PREFIX: &str = "#/components/schemas/";
+
+/// Routes serving `spec`, and the Swagger UI for it when built with `swagger-ui`
+#[cfg(feature = "swagger-ui")]
+pub fn api_docs_router<S>(spec: OpenApi) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    utoipa_swagger_ui::SwaggerUi::new(SWAGGER_UI_PATH)
+        .url(OPENAPI_PATH, spec)
+        .into()
+}
+
+/// Routes serving `spec`, and the Swagger UI for it when built with `swagger-ui`
+#[cfg(not(feature = "swagger-ui"))]
+pub fn api_docs_router<S>(spec: OpenApi) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route(
+        OPENAPI_PATH,
+        axum::routing::get(move || {
+            let spec = spec.clone();
+            async move { axum::Json(spec) }
+        }),
+    )
+}
+
+/// Document the websocket upgrade at `path`, whose peers exchange the
+/// `client_messages` and `server_messages` component schemas
+///
+/// # Panics
+///
+/// If `path` has no documented GET operation
+pub fn describe_websocket(
+    spec: &mut OpenApi,
+    path: &str,
+    client_messages: &str,
+    server_messages: &str,
+) {
+    let operation = spec
+        .paths
+        .paths
+        .get_mut(path)
+        .and_then(|item| item.get.as_mut())
+        .unwrap_or_else(|| panic!("websocket route {path} is not documented"));
+
+    let extensions = ExtensionsBuilder::new()
+        .add(
+            "x-websocket",
+            json!({
+                "client_messages": { "$ref": format!("{SCHEMA_REF_PREFIX}{client_messages}") },
+                "server_messages": { "$ref": format!("{SCHEMA_REF_PREFIX}{server_messages}") },
+            }),
+        )
+        .build();
+    match operation.extensions.as_mut() {
+        Some(existing) => existing.merge(extensions),
+        None => operation.extensions = Some(extensions),
+    }
+}
+
+/// Schema references in a serialized document that point at no component
+#[must_use]
+pub fn dangling_refs(document: &Value) -> Vec<String> {
+    let components = &document["components"]["schemas"];
+    let mut refs = Vec::new();
+    collect_refs(document, &mut refs);
+    refs.retain(|reference| {
+        reference
+            .strip_prefix(SCHEMA_REF_PREFIX)
+            .map_or(true, |name| components.get(name).is_none())
+    });
+    refs.sort();
+    refs.dedup();
+    refs
+}
+
+fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    Value::String(reference) if key == "$ref" => refs.push(reference.clone()),
+                    _ => collect_refs(value, refs),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_refs(value, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dangling_refs() {
+        let document = json!({
+            "paths": {
+                "/a": { "get": { "responses": { "200": { "content": { "application/json": {
+                    "schema": { "$ref": "#/components/schemas/Known" }
+                } } } } } },
+                "/b": { "get": { "responses": { "200": { "content": { "application/json": {
+                    "schema": { "oneOf": [
+                        { "$ref": "#/components/schemas/Missing" },
+                        { "$ref": "#/components/schemas/Missing" }
+                    ] }
+                } } } } } }
+            },
+            "components": { "schemas": { "Known": { "type": "string" } } }
+        });
+
+        assert_eq!(
+            dangling_refs(&document),
+            vec!["#/components/schemas/Missing".to_string()]
+        );
+    }
+}
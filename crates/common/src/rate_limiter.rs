@@ -0,0 +1,154 @@
+use crate::Shutdown;
+use axum::http::{HeaderMap, HeaderName};
+use dashmap::DashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+/// Fixed-window request limit per client IP
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    clients: DashMap<IpAddr, Window>,
+    /// Header a trusted reverse proxy puts the client address in
+    trusted_proxy_header: Option<HeaderName>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            clients: DashMap::new(),
+            trusted_proxy_header: None,
+        }
+    }
+
+    #[must_use]
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Limit the client named in `header` instead of the peer, for a server
+    /// behind a reverse proxy that sets it. Only configure this when every
+    /// request goes through that proxy, clients can set the header themselves
+    #[must_use]
+    pub fn with_trusted_proxy_header(mut self, header: Option<HeaderName>) -> Self {
+        self.trusted_proxy_header = header;
+        self
+    }
+
+    /// Count a request from `peer`, false once its client is over the limit
+    /// for the current window
+    pub fn check(&self, headers: &HeaderMap, peer: SocketAddr) -> bool {
+        self.check_at(self.client_ip(headers, peer), Instant::now())
+    }
+
+    /// The last address in the trusted proxy header, the one the proxy
+    /// appended, falling back to the peer when it's missing or malformed
+    fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        self.trusted_proxy_header
+            .as_ref()
+            .and_then(|header| headers.get(header))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|client| client.trim().parse().ok())
+            .unwrap_or_else(|| peer.ip())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> bool {
+        let mut window = self.clients.entry(client).or_insert(Window {
+            started: now,
+            requests: 0,
+        });
+        if now.duration_since(window.started) >= self.window {
+            *window = Window {
+                started: now,
+                requests: 0,
+            };
+        }
+        window.requests = window.requests.saturating_add(1);
+        window.requests <= self.limit
+    }
+
+    /// Forget the clients whose window has ended
+    fn sweep_at(&self, now: Instant) {
+        self.clients
+            .retain(|_, window| now.duration_since(window.started) < self.window);
+    }
+
+    /// Sweep out ended windows once per window until `shutdown` is triggered,
+    /// so requests never pay for it
+    pub async fn sweep_until(self: Arc<Self>, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(self.window);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.triggered() => return,
+            }
+            self.sweep_at(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_applies_per_client_and_resets_each_window() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check_at(client, start));
+        assert!(limiter.check_at(client, start + Duration::from_secs(1)));
+        assert!(!limiter.check_at(client, start + Duration::from_secs(2)));
+        assert!(limiter.check_at(other, start + Duration::from_secs(2)));
+
+        assert!(limiter.check_at(client, start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_sweep_forgets_only_ended_windows() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        limiter.check_at("10.0.0.1".parse().unwrap(), start);
+        limiter.check_at("10.0.0.2".parse().unwrap(), start + Duration::from_secs(30));
+
+        limiter.sweep_at(start + Duration::from_secs(60));
+
+        assert_eq!(limiter.clients.len(), 1);
+        assert!(limiter
+            .clients
+            .contains_key(&"10.0.0.2".parse::<IpAddr>().unwrap()));
+    }
+
+    #[test]
+    fn test_client_ip_comes_from_the_trusted_proxy_header_only() {
+        let peer: SocketAddr = "192.168.0.1:4000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, 10.0.0.7".parse().unwrap());
+
+        let direct = RateLimiter::per_minute(1);
+        assert_eq!(direct.client_ip(&headers, peer), peer.ip());
+
+        let proxied = RateLimiter::per_minute(1)
+            .with_trusted_proxy_header(Some(HeaderName::from_static("x-forwarded-for")));
+        assert_eq!(
+            proxied.client_ip(&headers, peer),
+            "10.0.0.7".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(proxied.client_ip(&HeaderMap::new(), peer), peer.ip());
+
+        headers.insert("x-forwarded-for", "not an address".parse().unwrap());
+        assert_eq!(proxied.client_ip(&headers, peer), peer.ip());
+    }
+}
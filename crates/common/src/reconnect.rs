@@ -0,0 +1,451 @@
+//! Reconnect loop shared by the WebSocket clients
+//!
+//! [`ReconnectingWsClient`] owns how to connect, and hands every established
+//! connection to a handler until the handler returns. Failed connects and
+//! handler errors are retried with exponential backoff and jitter, a
+//! connection that closes normally is reopened after the initial delay and one
+//! the server announced it is going away is reopened right away. Errors marked
+//! fatal, like rejected credentials, end the loop on the spot.
+
+use std::{fmt, future::Future, pin::Pin, time::Duration};
+
+use rand::Rng;
+use snafu::Snafu;
+use tokio::{net::TcpStream, sync::watch, time::sleep};
+use tokio_tungstenite::{
+    tungstenite::{self, http::StatusCode},
+    MaybeTlsStream, WebSocketStream,
+};
+use tracing::{error, info, warn};
+
+/// Client side of a tungstenite WebSocket
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+type ConnectFuture<S, E> = Pin<Box<dyn Future<Output = Result<S, E>> + Send>>;
+type ConnectFn<S, E> = Box<dyn Fn() -> ConnectFuture<S, E> + Send + Sync>;
+type FatalFn<E> = Box<dyn Fn(&E) -> bool + Send + Sync>;
+
+#[derive(Debug, Snafu)]
+pub enum ReconnectError<E>
+where
+    E: std::error::Error + 'static,
+{
+    #[snafu(display("Gave up after {} failed attempts: {}", attempts, source))]
+    MaxAttempts { attempts: u32, source: E },
+
+    #[snafu(display("Not retrying: {}", source))]
+    Fatal { source: E },
+}
+
+/// Whether the server refused the WebSocket upgrade over the credentials sent
+#[must_use]
+pub fn is_auth_rejection(error: &tungstenite::Error) -> bool {
+    matches!(
+        error,
+        tungstenite::Error::Http(response)
+            if matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectOptions {
+    /// Delay before the first retry, and before reopening a normally closed connection
+    pub initial_delay: Duration,
+    /// Upper bound on the delay between attempts
+    pub max_delay: Duration,
+    /// Growth of the delay per consecutive failure
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomized away, between 0 and 1
+    pub jitter: f64,
+    /// Consecutive failures before giving up, `None` retries forever
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectOptions {
+    /// Delay before retry number `attempt` (starting at 1), before jitter
+    #[must_use]
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+
+    /// `backoff(attempt)` shortened by a random part of the jitter fraction
+    fn jittered_backoff(&self, attempt: u32) -> Duration {
+        let delay = self.backoff(attempt);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - rand::thread_rng().gen_range(0.0..=jitter))
+    }
+}
+
+/// How a connection handed to the handler ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionEnd {
+    /// The connection closed, reconnect after the initial delay
+    Closed,
+    /// The server is shutting down, reconnect without waiting so another
+    /// instance picks the client up
+    GoingAway,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Attempt number `attempt` (starting at 1) is in progress
+    Connecting {
+        attempt: u32,
+    },
+    Connected,
+    /// Waiting `delay` before the next attempt
+    Backoff {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// `max_attempts` was reached or an attempt failed fatally
+    GaveUp,
+}
+
+pub struct ReconnectingWsClient<S = WsStream, E = tokio_tungstenite::tungstenite::Error> {
+    name: String,
+    options: ReconnectOptions,
+    connect: ConnectFn<S, E>,
+    is_fatal: FatalFn<E>,
+    state: watch::Sender<ConnectionState>,
+}
+
+impl<S, E> ReconnectingWsClient<S, E>
+where
+    E: std::error::Error + 'static,
+{
+    /// `name` identifies the client in logs
+    pub fn new<F, Fut>(name: impl Into<String>, options: ReconnectOptions, connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<S, E>> + Send + 'static,
+    {
+        let (state, _) = watch::channel(ConnectionState::Connecting { attempt: 1 });
+        Self {
+            name: name.into(),
+            options,
+            connect: Box::new(move || -> ConnectFuture<S, E> { Box::pin(connect()) }),
+            is_fatal: Box::new(|_| false),
+            state,
+        }
+    }
+
+    /// Give up on the first error `is_fatal` matches instead of retrying it
+    #[must_use]
+    pub fn with_fatal_errors(
+        mut self,
+        is_fatal: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_fatal = Box::new(is_fatal);
+        self
+    }
+
+    /// Follow the connection state
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Connect and run `handler` on each connection, reconnecting whenever it
+    /// returns. Only returns once `max_attempts` consecutive attempts failed,
+    /// or one failed fatally.
+    pub async fn run<H, HFut>(&self, mut handler: H) -> Result<(), ReconnectError<E>>
+    where
+        H: FnMut(S) -> HFut,
+        HFut: Future<Output = Result<ConnectionEnd, E>>,
+    {
+        let mut failures = 0u32;
+
+        loop {
+            self.state.send_replace(ConnectionState::Connecting {
+                attempt: failures + 1,
+            });
+
+            let result = match (self.connect)().await {
+                Ok(connection) => {
+                    info!("{} connected", self.name);
+                    self.state.send_replace(ConnectionState::Connected);
+                    handler(connection).await
+                }
+                Err(e) => Err(e),
+            };
+
+            let delay = match result {
+                Ok(ConnectionEnd::Closed) => {
+                    info!("{} connection closed normally", self.name);
+                    failures = 0;
+                    self.options.initial_delay
+                }
+                Ok(ConnectionEnd::GoingAway) => {
+                    info!("{} is going away", self.name);
+                    failures = 0;
+                    Duration::ZERO
+                }
+                Err(e) if (self.is_fatal)(&e) => {
+                    error!("{} connection failed for good: {}", self.name, e);
+                    self.state.send_replace(ConnectionState::GaveUp);
+                    return Err(ReconnectError::Fatal { source: e });
+                }
+                Err(e) => {
+                    error!("{} connection failed: {}", self.name, e);
+                    failures += 1;
+                    if self.options.max_attempts.is_some_and(|max| failures >= max) {
+                        self.state.send_replace(ConnectionState::GaveUp);
+                        return Err(ReconnectError::MaxAttempts {
+                            attempts: failures,
+                            source: e,
+                        });
+                    }
+                    self.options.jittered_backoff(failures)
+                }
+            };
+
+            warn!(
+                "Reconnecting {} in {:?} (attempt {}{})",
+                self.name,
+                delay,
+                failures + 1,
+                MaxAttempts(self.options.max_attempts)
+            );
+            self.state.send_replace(ConnectionState::Backoff {
+                attempt: failures + 1,
+                delay,
+            });
+            sleep(delay).await;
+        }
+    }
+}
+
+/// Formats as `/max` when there is a limit
+struct MaxAttempts(Option<u32>);
+
+impl fmt::Display for MaxAttempts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(max) => write!(f, "/{max}"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+    use tokio::{net::TcpListener, time::Instant};
+    use tokio_tungstenite::{accept_async, connect_async};
+
+    fn test_options(max_attempts: Option<u32>) -> ReconnectOptions {
+        ReconnectOptions {
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(150),
+            multiplier: 2.0,
+            jitter: 0.0,
+            max_attempts,
+        }
+    }
+
+    /// WebSocket server that drops the first `failures` connections before the handshake
+    async fn spawn_flaky_server(failures: u32) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut accepted = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted += 1;
+                if accepted <= failures {
+                    drop(stream);
+                    continue;
+                }
+                tokio::spawn(async move {
+                    let mut ws = accept_async(stream).await.unwrap();
+                    ws.close(None).await.ok();
+                });
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_backoff_grows_up_to_the_max_delay() {
+        let options = test_options(None);
+        let delays: Vec<_> = (1..=4).map(|attempt| options.backoff(attempt)).collect();
+        assert_eq!(
+            delays,
+            [50, 100, 150, 150].map(Duration::from_millis).to_vec()
+        );
+
+        let jittered = ReconnectOptions {
+            jitter: 0.5,
+            ..options.clone()
+        };
+        for _ in 0..100 {
+            let delay = jittered.jittered_backoff(2);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flaky_server_is_retried_with_backoff() {
+        let url = spawn_flaky_server(3).await;
+        let connects = Arc::new(AtomicU32::new(0));
+        let client = ReconnectingWsClient::new("flaky", test_options(None), {
+            let connects = connects.clone();
+            move || {
+                let url = url.clone();
+                connects.fetch_add(1, Ordering::SeqCst);
+                async move { connect_async(url).await.map(|(ws, _)| ws) }
+            }
+        });
+        let mut state = client.subscribe();
+
+        let started = Instant::now();
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::channel::<Duration>(1);
+        let run = client.run(|mut ws| {
+            let done_tx = done_tx.clone();
+            async move {
+                while ws.next().await.is_some() {}
+                done_tx.send(started.elapsed()).await.unwrap();
+                Ok::<_, tungstenite::Error>(ConnectionEnd::Closed)
+            }
+        });
+
+        tokio::select! {
+            _ = run => panic!("Retrying forever should never return"),
+            elapsed = done_rx.recv() => {
+                // Three failures back off 50 + 100 + 150 ms before the fourth attempt
+                let elapsed = elapsed.unwrap();
+                assert!(elapsed >= Duration::from_millis(300), "connected after {elapsed:?}");
+                assert!(elapsed < Duration::from_secs(5), "connected after {elapsed:?}");
+            }
+        }
+        assert_eq!(connects.load(Ordering::SeqCst), 4);
+        assert_eq!(*state.borrow_and_update(), ConnectionState::Connected);
+    }
+
+    #[tokio::test]
+    async fn test_going_away_reconnects_without_waiting() {
+        let url = spawn_flaky_server(0).await;
+        let options = ReconnectOptions {
+            initial_delay: Duration::from_secs(30),
+            ..test_options(None)
+        };
+        let client = ReconnectingWsClient::new("draining", options, move || {
+            let url = url.clone();
+            async move { connect_async(url).await.map(|(ws, _)| ws) }
+        });
+
+        let handled = AtomicU32::new(0);
+        let run = client.run(|mut ws| {
+            handled.fetch_add(1, Ordering::SeqCst);
+            async move {
+                while ws.next().await.is_some() {}
+                Ok::<_, tungstenite::Error>(ConnectionEnd::GoingAway)
+            }
+        });
+
+        let started = Instant::now();
+        tokio::select! {
+            _ = run => panic!("Retrying forever should never return"),
+            () = async {
+                while handled.load(Ordering::SeqCst) < 3 {
+                    tokio::task::yield_now().await;
+                }
+            } => {}
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        // Nothing listens on a port whose listener was dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = ReconnectingWsClient::new("unreachable", test_options(Some(3)), move || {
+            let url = url.clone();
+            async move { connect_async(url).await.map(|(ws, _)| ws) }
+        });
+        let state = client.subscribe();
+
+        let started = Instant::now();
+        let result = client
+            .run(|_| async { Ok::<_, tungstenite::Error>(ConnectionEnd::Closed) })
+            .await;
+        assert!(matches!(
+            result,
+            Err(ReconnectError::MaxAttempts { attempts: 3, .. })
+        ));
+        // Backed off twice, 50 + 100 ms
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(*state.borrow(), ConnectionState::GaveUp);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_is_not_retried() {
+        // Answers every upgrade with 401
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let _ = tokio_tungstenite::accept_hdr_async(
+                        stream,
+                        |_: &tungstenite::handshake::server::Request,
+                         _: tungstenite::handshake::server::Response| {
+                            let mut response =
+                                tungstenite::handshake::server::ErrorResponse::new(None);
+                            *response.status_mut() = StatusCode::UNAUTHORIZED;
+                            Err(response)
+                        },
+                    )
+                    .await;
+                });
+            }
+        });
+
+        let connects = Arc::new(AtomicU32::new(0));
+        let client = ReconnectingWsClient::new("rejecting", test_options(None), {
+            let connects = connects.clone();
+            move || {
+                let url = url.clone();
+                connects.fetch_add(1, Ordering::SeqCst);
+                async move { connect_async(url).await.map(|(ws, _)| ws) }
+            }
+        })
+        .with_fatal_errors(is_auth_rejection);
+        let state = client.subscribe();
+
+        let result = client
+            .run(|_| async { Ok::<_, tungstenite::Error>(ConnectionEnd::Closed) })
+            .await;
+        match result {
+            Err(ReconnectError::Fatal { source }) => assert!(is_auth_rejection(&source)),
+            other => panic!("expected a fatal error, got {other:?}"),
+        }
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(*state.borrow(), ConnectionState::GaveUp);
+    }
+}
@@ -0,0 +1,108 @@
+//! Graceful shutdown shared by the servers
+//!
+//! A [`Shutdown`] is triggered once, by SIGTERM or ctrl-c or by whoever holds
+//! it, and every task draining on it sees the trigger.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tracing::{error, info};
+
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    triggered: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            triggered: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.triggered.send_replace(true);
+    }
+
+    #[must_use]
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Wait until the shutdown is triggered
+    pub async fn triggered(&self) {
+        let mut receiver = self.triggered.subscribe();
+        // The sender lives as long as `self`, so this can't fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Trigger the shutdown once the process gets SIGTERM or ctrl-c
+    pub fn trigger_on_signal(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            termination_signal().await;
+            shutdown.trigger();
+        });
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn termination_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => info!("Received ctrl-c, shutting down"),
+        () = terminate => info!("Received SIGTERM, shutting down"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_every_clone_sees_the_trigger() {
+        let shutdown = Shutdown::new();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+        assert!(!shutdown.is_triggered());
+
+        shutdown.clone().trigger();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("the waiter should see the trigger")
+            .unwrap();
+        assert!(shutdown.is_triggered());
+        // Waiting after the trigger returns right away
+        shutdown.triggered().await;
+    }
+}
@@ -0,0 +1,85 @@
+//! Correlation ids tying the log lines of one quote and the swap made from it
+//! together across the RFQ server, the OTC server and the market makers
+//!
+//! An HTTP request keeps the id the caller sent in `X-Request-Id` if it's
+//! valid and gets a fresh one otherwise. Every log line of the request carries
+//! it through a span and the response echoes it in the same header.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+pub use otc_api_types::{is_valid_trace_id, MAX_TRACE_ID_LEN, TRACE_ID_HEADER};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+/// The correlation id of the current HTTP request, see [`trace_id_middleware`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceId(pub String);
+
+#[must_use]
+pub fn new_trace_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// The caller's id from `headers` if it's valid, a fresh one otherwise
+#[must_use]
+pub fn trace_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(TRACE_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_trace_id(id))
+        .map_or_else(new_trace_id, ToString::to_string)
+}
+
+/// Give each request a [`TraceId`] extension and a span carrying it, and echo
+/// it in the response
+pub async fn trace_id_middleware(mut request: Request, next: Next) -> Response {
+    let trace_id = trace_id_from_headers(request.headers());
+    request.extensions_mut().insert(TraceId(trace_id.clone()));
+    let span = info_span!(
+        "request",
+        trace_id = %trace_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    let mut response = next.run(request).instrument(span).await;
+    // Valid ids are always valid header values
+    if let Ok(value) = HeaderValue::from_str(&trace_id) {
+        response.headers_mut().insert(TRACE_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(trace_id: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACE_ID_HEADER, HeaderValue::from_str(trace_id).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_keeps_a_valid_caller_id() {
+        for id in ["2b7c0d9e-5f0a-4c1e-9d3b-1a2b3c4d5e6f", "web:quote.42_a"] {
+            assert_eq!(trace_id_from_headers(&headers(id)), id);
+        }
+    }
+
+    #[test]
+    fn test_replaces_a_missing_or_invalid_id() {
+        let too_long = "a".repeat(MAX_TRACE_ID_LEN + 1);
+        for id in ["", "has space", "new\tline", "quote\"id", too_long.as_str()] {
+            let trace_id = trace_id_from_headers(&headers(id));
+            assert_ne!(trace_id, id);
+            assert!(Uuid::parse_str(&trace_id).is_ok());
+        }
+        assert!(Uuid::parse_str(&trace_id_from_headers(&HeaderMap::new())).is_ok());
+        assert!(is_valid_trace_id(&"a".repeat(MAX_TRACE_ID_LEN)));
+    }
+}
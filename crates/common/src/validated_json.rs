@@ -0,0 +1,49 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+    response::IntoResponse,
+    Json,
+};
+use otc_models::{sanitize_reason, FieldError, Validate};
+use serde::de::DeserializeOwned;
+
+/// Largest request body accepted on any route (axum's default, made explicit so
+/// it can be advertised)
+pub const MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Router state of a server taking [`ValidatedJson`] bodies, deciding how the
+/// server answers the ones it rejects
+pub trait RejectsInvalidJson {
+    type Rejection: IntoResponse;
+
+    /// The body isn't JSON of the expected shape, `message` is already sanitized
+    fn malformed_json(message: String) -> Self::Rejection;
+
+    fn invalid_fields(errors: Vec<FieldError>) -> Self::Rejection;
+}
+
+/// JSON body extractor that sanitizes the payload before the handler sees it.
+///
+/// Malformed JSON and invalid fields are both turned into structured 400s.
+#[derive(Debug, Clone)]
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: RejectsInvalidJson + Send + Sync,
+{
+    type Rejection = S::Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(mut value) = Json::<T>::from_request(req, state)
+            .await
+            // Deserialization errors can quote the offending input, so they get sanitized too
+            .map_err(|rejection| S::malformed_json(sanitize_reason(&rejection.body_text())))?;
+
+        value.validate().map_err(S::invalid_fields)?;
+
+        Ok(Self(value))
+    }
+}
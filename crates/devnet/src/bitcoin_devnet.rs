@@ -1,7 +1,12 @@
+use std::collections::HashSet;
 use std::sync::Arc;
-use std::{path::PathBuf, str::FromStr, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-use bitcoincore_rpc_async::bitcoin::Txid;
+use bitcoincore_rpc_async::bitcoin::{BlockHash, Txid};
 use bitcoincore_rpc_async::json::GetRawTransactionVerbose;
 use corepc_node::Conf;
 use log::info;
@@ -24,6 +29,33 @@ pub enum MiningMode {
     Interval(u64),
 }
 
+/// Fee delta that keeps a transaction out of every block regtest will mine
+const NEVER_MINE_FEE_DELTA_SATS: i64 = -100_000_000;
+
+/// How often the esplora waits re-check the index
+const ESPLORA_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long setup gives esplora to index the funding transactions
+const FUNDING_INDEX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long [`BitcoinDevnet::force_reorg`] gives esplora to follow the new chain
+const REORG_INDEX_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Chain tip and mempool recorded by [`BitcoinDevnet::checkpoint`]
+#[derive(Debug, Clone)]
+pub struct BitcoinCheckpoint {
+    pub height: u64,
+    pub block_hash: BlockHash,
+    mempool: HashSet<Txid>,
+}
+
+/// Tips either side of a reorg forced by [`BitcoinDevnet::force_reorg`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitcoinReorg {
+    pub old_tip: BlockHash,
+    pub new_tip: BlockHash,
+}
+
 /// Holds all Bitcoin-related devnet state.
 pub struct BitcoinDevnet {
     pub rpc_client: Arc<AsyncBitcoinClient>,
@@ -58,6 +90,7 @@ impl BitcoinDevnet {
         mining_mode: MiningMode,
         _join_set: &mut JoinSet<Result<()>>,
         devnet_cache: Option<Arc<RiftDevnetCache>>,
+        temp_root: Option<&Path>,
     ) -> Result<(Self, u32)> {
         info!("Instantiating Bitcoin Regtest...");
         let wallet_name = "alice";
@@ -69,10 +102,10 @@ impl BitcoinDevnet {
 
         let bitcoin_datadir = if let Some(devnet_cache) = devnet_cache.clone() {
             info!("[Bitcoin Setup] Using cached bitcoin datadir");
-            devnet_cache.create_bitcoin_datadir().await?
+            devnet_cache.create_bitcoin_datadir(temp_root).await?
         } else {
             info!("[Bitcoin Setup] Creating fresh bitcoin datadir");
-            get_new_temp_dir()?
+            get_new_temp_dir(temp_root)?
         };
         info!("[Bitcoin Setup] bitcoin_datadir: {bitcoin_datadir:?}");
 
@@ -217,6 +250,7 @@ impl BitcoinDevnet {
                 using_esplora,
                 fixed_esplora_url,
                 devnet_cache,
+                temp_root,
                 bitcoin_regtest.clone(),
             )
             .await
@@ -253,27 +287,11 @@ impl BitcoinDevnet {
         }
 
         // ensure esplora sees the txids
-        if let Some(esplora_client) = esplora_client.clone() {
-            for txid in txids {
-                let mut attempts = 0;
-                while attempts < 25 {
-                    let tx = esplora_client
-                        .get_tx_status(&txid)
-                        .await
-                        .map_err(|e| eyre::eyre!("Failed to get tx status: {}", e))?;
-                    if tx.confirmed {
-                        break;
-                    }
-                    attempts += 1;
-                    tokio::time::sleep(Duration::from_millis(250)).await;
-                    if attempts == 25 {
-                        return Err(eyre::eyre!(
-                            "Failed to confirm funding transaction on esplora {}",
-                            txid
-                        )
-                        .into());
-                    }
-                }
+        if let Some(esplora_client) = esplora_client.as_deref() {
+            wait_until_esplora_synced(esplora_client, &bitcoin_rpc_client, FUNDING_INDEX_TIMEOUT)
+                .await?;
+            for txid in &txids {
+                wait_until_tx_indexed(esplora_client, txid, FUNDING_INDEX_TIMEOUT).await?;
             }
         }
 
@@ -282,10 +300,13 @@ impl BitcoinDevnet {
         let mining_thread = if let MiningMode::Interval(interval) = mining_mode {
             Some(tokio::spawn(async move {
                 loop {
-                    bitcoin_rpc_client_clone
+                    // Fails while a restore is rolling the chain back, the next tick catches up
+                    if let Err(e) = bitcoin_rpc_client_clone
                         .generate_to_address(1, &alice_address_clone)
                         .await
-                        .unwrap();
+                    {
+                        log::warn!("Failed to auto-mine Bitcoin block: {e}");
+                    }
 
                     tokio::time::sleep(Duration::from_secs(interval)).await;
                 }
@@ -325,6 +346,7 @@ impl BitcoinDevnet {
         using_esplora: bool,
         fixed_esplora_url: bool,
         devnet_cache: Option<Arc<RiftDevnetCache>>,
+        temp_root: Option<&Path>,
         bitcoin_regtest: Arc<BitcoinRegtest>,
     ) -> Result<(
         Option<Arc<ElectrsD>>,
@@ -341,9 +363,9 @@ impl BitcoinDevnet {
         conf.args.push("*");
 
         let electrsd_datadir = if let Some(devnet_cache) = devnet_cache {
-            devnet_cache.create_electrsd_datadir().await?
+            devnet_cache.create_electrsd_datadir(temp_root).await?
         } else {
-            get_new_temp_dir()?
+            get_new_temp_dir(temp_root)?
         };
 
         conf.staticdir = Some(electrsd_datadir.path().to_path_buf());
@@ -445,6 +467,200 @@ impl BitcoinDevnet {
         Ok(())
     }
 
+    /// Records the chain tip and mempool so [`BitcoinDevnet::restore`] can roll back to them.
+    pub async fn checkpoint(&self) -> Result<BitcoinCheckpoint> {
+        let height = self
+            .rpc_client
+            .get_block_count()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get block count: {}", e))?;
+        let block_hash = self
+            .rpc_client
+            .get_block_hash(height)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get block hash: {}", e))?;
+        let mempool = self
+            .rpc_client
+            .get_raw_mempool()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get mempool: {}", e))?;
+
+        Ok(BitcoinCheckpoint {
+            height,
+            block_hash,
+            mempool: mempool.into_iter().collect(),
+        })
+    }
+
+    /// Rolls the chain back to `checkpoint` by invalidating the first block mined after it.
+    /// Transactions from the dropped blocks return to the mempool as in a real reorg, and
+    /// are deprioritised so later blocks never include them. Regtest only.
+    pub async fn restore(&self, checkpoint: &BitcoinCheckpoint) -> Result<()> {
+        let height = self
+            .rpc_client
+            .get_block_count()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get block count: {}", e))?;
+        if height < checkpoint.height {
+            return Err(eyre::eyre!(
+                "Chain at height {} is below the checkpoint at {}",
+                height,
+                checkpoint.height
+            )
+            .into());
+        }
+        let block_hash = self
+            .rpc_client
+            .get_block_hash(checkpoint.height)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get block hash: {}", e))?;
+        if block_hash != checkpoint.block_hash {
+            return Err(eyre::eyre!(
+                "Checkpointed block {} is no longer on the chain",
+                checkpoint.block_hash
+            )
+            .into());
+        }
+
+        if height > checkpoint.height {
+            let first_dropped = self
+                .rpc_client
+                .get_block_hash(checkpoint.height + 1)
+                .await
+                .map_err(|e| eyre::eyre!("Failed to get block hash: {}", e))?;
+            self.rpc_client
+                .invalidate_block(&first_dropped)
+                .await
+                .map_err(|e| eyre::eyre!("Failed to invalidate block: {}", e))?;
+        }
+
+        let mempool = self
+            .rpc_client
+            .get_raw_mempool()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get mempool: {}", e))?;
+        for txid in mempool
+            .into_iter()
+            .filter(|txid| !checkpoint.mempool.contains(txid))
+        {
+            self.rpc_client
+                .call::<bool>(
+                    "prioritisetransaction",
+                    &[
+                        serde_json::json!(txid.to_string()),
+                        serde_json::json!(0),
+                        serde_json::json!(NEVER_MINE_FEE_DELTA_SATS),
+                    ],
+                )
+                .await
+                .map_err(|e| eyre::eyre!("Failed to deprioritise {}: {}", txid, e))?;
+        }
+
+        info!(
+            "[Bitcoin] Restored chain to height {} from {}",
+            checkpoint.height, height
+        );
+        Ok(())
+    }
+
+    /// Orphans the top `n` blocks by invalidating the first of them, the tip's
+    /// `n - 1`th ancestor, and returns the new tip. Their transactions go back
+    /// to the mempool. Regtest only.
+    pub async fn invalidate_blocks(&self, n: u64) -> Result<BlockHash> {
+        if n == 0 {
+            return Err(eyre::eyre!("Can't invalidate zero blocks").into());
+        }
+        let height = self
+            .rpc_client
+            .get_block_count()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get block count: {}", e))?;
+        if n > height {
+            return Err(eyre::eyre!(
+                "Can't invalidate {} blocks of a chain at height {}",
+                n,
+                height
+            )
+            .into());
+        }
+        let first_dropped = self
+            .rpc_client
+            .get_block_hash(height - n + 1)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get block hash: {}", e))?;
+        self.rpc_client
+            .invalidate_block(&first_dropped)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to invalidate block: {}", e))?;
+        let tip = self
+            .rpc_client
+            .get_best_block_hash()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get best block hash: {}", e))?;
+        Ok(tip)
+    }
+
+    /// Mines `n + 1` empty blocks on the tip, enough to outweigh `n` blocks
+    /// orphaned by [`BitcoinDevnet::invalidate_blocks`], and returns the new tip.
+    /// The blocks leave the mempool alone, so orphaned transactions wait there
+    /// for the next [`BitcoinDevnet::mine_blocks`]
+    pub async fn mine_competing_chain(&self, n: u64) -> Result<BlockHash> {
+        // A fresh coinbase address keeps an empty block from hashing the same
+        // as the orphaned one at its height, which the node would reject
+        let address = self
+            .rpc_client
+            .get_new_address(None, None)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get new address: {}", e))?
+            .assume_checked();
+        let mut tip = None;
+        for _ in 0..=n {
+            let block: serde_json::Value = self
+                .rpc_client
+                .call(
+                    "generateblock",
+                    &[
+                        serde_json::json!(address.to_string()),
+                        serde_json::json!([]),
+                    ],
+                )
+                .await
+                .map_err(|e| eyre::eyre!("Failed to generate block: {}", e))?;
+            tip = block["hash"]
+                .as_str()
+                .map(BlockHash::from_str)
+                .transpose()
+                .map_err(|e| eyre::eyre!("Failed to parse block hash: {}", e))?;
+        }
+        let tip = tip.ok_or_else(|| eyre::eyre!("generateblock returned no hash"))?;
+        Ok(tip)
+    }
+
+    /// Replaces the top `depth` blocks with a longer chain and waits for esplora,
+    /// if running, to follow it. Only transactions confirmed in those `depth`
+    /// blocks drop out, they're back in the mempool unconfirmed; anything
+    /// confirmed deeper keeps its confirmations. Regtest only.
+    pub async fn force_reorg(&self, depth: u64) -> Result<BitcoinReorg> {
+        let old_tip = self
+            .rpc_client
+            .get_best_block_hash()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get best block hash: {}", e))?;
+        self.invalidate_blocks(depth).await?;
+        let new_tip = self.mine_competing_chain(depth).await?;
+        // The new chain is a block longer than the old one, so esplora at
+        // bitcoind's height has switched to it
+        if self.esplora_client.is_some() {
+            self.wait_for_esplora_sync(REORG_INDEX_TIMEOUT).await?;
+        }
+
+        info!(
+            "[Bitcoin] Reorged {} blocks, tip {} replaced by {}",
+            depth, old_tip, new_tip
+        );
+        Ok(BitcoinReorg { old_tip, new_tip })
+    }
+
     /// Convenience method for handing out some BTC to a given address.
     pub async fn deal_bitcoin(
         &self,
@@ -491,23 +707,73 @@ impl BitcoinDevnet {
         Ok(full_transaction)
     }
 
+    /// Waits until esplora's tip height matches bitcoind's
     pub async fn wait_for_esplora_sync(&self, timeout: Duration) -> Result<()> {
-        let start_time = Instant::now();
-        while start_time.elapsed() < timeout {
-            let height = self
-                .esplora_client
-                .as_ref()
-                .unwrap()
-                .get_height()
-                .await
-                .unwrap();
-            if height == self.rpc_client.get_block_count().await.unwrap() as u32 {
-                return Ok(());
-            }
+        let esplora_client = self
+            .esplora_client
+            .as_deref()
+            .ok_or(crate::DevnetError::EsploraDisabled)?;
+        wait_until_esplora_synced(esplora_client, &self.rpc_client, timeout).await
+    }
 
-            tokio::time::sleep(Duration::from_millis(50)).await;
+    /// Waits until esplora knows about `txid`, in the mempool or a block
+    pub async fn wait_for_tx_indexed(&self, txid: &Txid, timeout: Duration) -> Result<()> {
+        let esplora_client = self
+            .esplora_client
+            .as_deref()
+            .ok_or(crate::DevnetError::EsploraDisabled)?;
+        wait_until_tx_indexed(esplora_client, txid, timeout).await
+    }
+}
+
+async fn wait_until_esplora_synced(
+    esplora_client: &EsploraClient,
+    rpc_client: &AsyncBitcoinClient,
+    timeout: Duration,
+) -> Result<()> {
+    let start_time = Instant::now();
+    let mut esplora_height = None;
+    let mut bitcoind_height = None;
+    loop {
+        // Keep the last heights seen so a timeout can say how far apart they were
+        if let Ok(info) = rpc_client.get_blockchain_info().await {
+            bitcoind_height = Some(info.blocks);
+        }
+        if let Ok(height) = esplora_client.get_height().await {
+            esplora_height = Some(u64::from(height));
+        }
+        if esplora_height.is_some() && esplora_height == bitcoind_height {
+            return Ok(());
+        }
+        if start_time.elapsed() >= timeout {
+            return Err(crate::DevnetError::EsploraSyncTimeout {
+                timeout,
+                esplora_height,
+                bitcoind_height,
+            });
+        }
+
+        tokio::time::sleep(ESPLORA_POLL_INTERVAL).await;
+    }
+}
+
+async fn wait_until_tx_indexed(
+    esplora_client: &EsploraClient,
+    txid: &Txid,
+    timeout: Duration,
+) -> Result<()> {
+    let start_time = Instant::now();
+    loop {
+        if let Ok(Some(_)) = esplora_client.get_tx(txid).await {
+            return Ok(());
+        }
+        if start_time.elapsed() >= timeout {
+            return Err(crate::DevnetError::TxIndexTimeout {
+                txid: txid.to_string(),
+                timeout,
+            });
         }
 
-        Err(crate::DevnetError::EsploraSyncTimeout { timeout })
+        tokio::time::sleep(ESPLORA_POLL_INTERVAL).await;
     }
 }
@@ -1,4 +1,6 @@
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use blockchain_utils::{
     create_websocket_wallet_provider,
@@ -7,24 +9,47 @@ use blockchain_utils::{
 use disperse_contract::{Disperse::DisperseInstance, DISPERSE_DEPLOYED_BYTECODE};
 use eyre::{eyre, Result};
 use log::info;
-use tokio::time::Instant;
+use otc_models::{CBBTC_ADDRESS, CBBTC_DECIMALS};
+use tokio::{task::JoinSet, time::Instant};
 
 use alloy::{
     node_bindings::{Anvil, AnvilInstance},
     primitives::{Address, U256},
-    providers::{ext::AnvilApi, DynProvider, Provider},
+    providers::{ext::AnvilApi, DynProvider, Provider, ProviderBuilder},
     sol,
 };
 
-use crate::{get_new_temp_dir, token_indexerd::TokenIndexerInstance, RiftDevnetCache};
-
-const CBBTC_ADDRESS: &str = "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf";
+use crate::{
+    get_new_temp_dir,
+    token_indexerd::{TokenIndexerInstance, MAX_INDEXER_RESTARTS},
+    RiftDevnetCache,
+};
 
 const DISPERSE_ADDRESS: &str = "0xd152f549545093347A162DCE210e7293f1452150";
 
+/// Chain id of the devnet's main anvil
+pub const DEVNET_CHAIN_ID: u64 = 1337;
+
+/// How often [`EthDevnet::wait_for_indexer_sync`] re-checks the indexer
+const INDEXER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a fresh token indexer gets to start serving its API, pnpm and
+/// ponder's startup are slow on loaded CI machines
+const INDEXER_READY_TIMEOUT: Duration = Duration::from_secs(120);
+
 //solc 0.8.28; solc SimpleERC20.sol --via-ir --optimize --bin-runtime
 const CBBTC_BYTECODE: &str = "60806040526004361015610011575f80fd5b5f3560e01c806306fdde031461074a578063095ea7b3146106d157806318160ddd146106b457806318cb0ec0146101fc57806323b872dd146105d7578063313ce5671461021757806340c10f191461055357806353f927b11461026f57806370a0823114610237578063948442481461021757806395d89b41146101fc578063a9059cbb146101cb578063bba1964f146101075763dd62ed3e146100b3575f80fd5b34610103576040366003190112610103576100cc610803565b6100d4610819565b6001600160a01b039182165f908152600560209081526040808320949093168252928352819020549051908152f35b5f80fd5b34610103575f366003190112610103576040515f5f546101268161082f565b80845290600181169081156101a7575060011461015e575b61015a8361014e81850382610867565b604051918291826107d9565b0390f35b5f8080525f516020610a785f395f51905f52939250905b80821061018d5750909150810160200161014e61013e565b919260018160209254838588010152019101909291610175565b60ff191660208086019190915291151560051b8401909101915061014e905061013e565b34610103576040366003190112610103576101f16101e7610803565b60243590336109b5565b602060405160018152f35b34610103575f3660031901126101035761015a61014e610889565b34610103575f36600319011261010357602060ff60025416604051908152f35b34610103576020366003190112610103576001600160a01b03610258610803565b165f526004602052602060405f2054604051908152f35b346101035760603660031901126101035760043567ffffffffffffffff8111610103576102a090369060040161092d565b60243567ffffffffffffffff8111610103576102c090369060040161092d565b60443560ff811680910361010357825167ffffffffffffffff8111610461576102e95f5461082f565b601f81116104ec575b506020601f821160011461048057819293945f92610475575b50508160011b915f199060031b1c1916175f555b815167ffffffffffffffff81116104615761033b60015461082f565b601f81116103f9575b50602092601f821160011461038d57928192935f92610382575b50508160011b915f199060031b1c1916176001555b60ff1960025416176002555f80f35b01519050838061035e565b601f1982169360015f525f516020610a985f395f51905f52915f5b8681106103e157508360019596106103c9575b505050811b01600155610373565b01515f1960f88460031b161c191690558380806103bb565b919260206001819286850151815501940192016103a8565b60015f52601f820160051c5f516020610a985f395f51905f5201906020831061044c575b601f0160051c5f516020610a985f395f51905f5201905b8181106104415750610344565b5f8155600101610434565b5f516020610a985f395f51905f52915061041d565b634e487b7160e01b5f52604160045260245ffd5b01519050848061030b565b601f198216905f80525f516020610a785f395f51905f52915f5b8181106104d4575095836001959697106104bc575b505050811b015f5561031f565b01515f1960f88460031b161c191690558480806104af565b9192602060018192868b01518155019401920161049a565b5f8052601f820160051c5f516020610a785f395f51905f5201906020831061053e575b601f0160051c5f516020610a785f395f51905f5201905b81811061053357506102f2565b5f8155600101610526565b5f516020610a785f395f51905f52915061050f565b346101035760403660031901126101035761056c610803565b6001600160a01b03165f7fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef60206024356105a7851515610983565b6105b381600354610a6a565b60035584845260048252604084206105cc828254610a6a565b9055604051908152a3005b34610103576060366003190112610103576105f0610803565b6105f8610819565b6001600160a01b0382165f81815260056020908152604080832033845290915290205490926044359291838110610683576001810161063d575b506101f193506109b5565b83810390811161066f576101f1945f52600560205260405f2060018060a01b0333165f5260205260405f205584610632565b634e487b7160e01b5f52601160045260245ffd5b60405162461bcd60e51b8152602060048201526009602482015268616c6c6f77616e636560b81b6044820152606490fd5b34610103575f366003190112610103576020600354604051908152f35b34610103576040366003190112610103576106ea610803565b335f8181526005602090815260408083206001600160a01b03909516808452948252918290206024359081905591519182527f8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b92591a3602060405160018152f35b34610103575f366003190112610103576040515f5f546107698161082f565b80845290600181169081156101a757506001146107905761015a8361014e81850382610867565b5f8080525f516020610a785f395f51905f52939250905b8082106107bf5750909150810160200161014e61013e565b9192600181602092548385880101520191019092916107a7565b602060409281835280519182918282860152018484015e5f828201840152601f01601f1916010190565b600435906001600160a01b038216820361010357565b602435906001600160a01b038216820361010357565b90600182811c9216801561085d575b602083101461084957565b634e487b7160e01b5f52602260045260245ffd5b91607f169161083e565b90601f8019910116810190811067ffffffffffffffff82111761046157604052565b604051905f826001549161089c8361082f565b808352926001811690811561090e57506001146108c2575b6108c092500383610867565b565b5060015f90815290915f516020610a985f395f51905f525b8183106108f25750509060206108c0928201016108b4565b60209193508060019154838589010152019101909184926108da565b602092506108c094915060ff191682840152151560051b8201016108b4565b81601f820112156101035780359067ffffffffffffffff82116104615760405192610962601f8401601f191660200185610867565b8284526020838301011161010357815f926020809301838601378301015290565b1561098a57565b606460405162461bcd60e51b81526020600482015260046024820152630746f3d360e41b6044820152fd5b6001600160a01b0390911691906109cd831515610983565b6001600160a01b03165f81815260046020526040902054909190818110610a3b57817fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef92602092855f52600484520360405f2055845f526004825260405f20818154019055604051908152a3565b60405162461bcd60e51b815260206004820152600760248201526662616c616e636560c81b6044820152606490fd5b9190820180921161066f5756fe290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6a2646970667358221220046639b5c3b89eb5a8e808d3efb9abeda6d1f1c31c9dd41cdb37b5dd31a9673164736f6c634300081c0033";
 
+sol! {
+    /// Minting roles of the mainnet cbBTC token, a Circle FiatToken
+    #[sol(rpc)]
+    interface IFiatToken {
+        function masterMinter() external view returns (address);
+        function configureMinter(address minter, uint256 minterAllowedAmount) external returns (bool);
+        function mint(address to, uint256 amount) external returns (bool);
+    }
+}
+
 /// Holds all Ethereum-related devnet state.
 pub struct EthDevnet {
     pub anvil: Arc<AnvilInstance>,
@@ -38,6 +63,10 @@ pub struct EthDevnet {
     pub disperse_contract: DisperseInstance<DynProvider>,
 }
 
+/// Anvil state captured by [`EthDevnet::snapshot`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotId(pub U256);
+
 #[derive(Clone, Debug)]
 pub enum Mode {
     Fork(ForkConfig),
@@ -45,14 +74,26 @@ pub enum Mode {
 }
 
 impl EthDevnet {
-    /// Spawns Anvil, deploys the EVM contracts, returns `(Self, deployment_block_number)`.
+    /// Spawns Anvil as chain `chain_id` and deploys the EVM contracts. The token
+    /// indexer, when enabled, is supervised from `join_set` and serving before
+    /// this returns.
     pub async fn setup(
         deploy_mode: Mode,
+        chain_id: u64,
         devnet_cache: Option<Arc<RiftDevnetCache>>,
         token_indexer_database_url: Option<String>,
+        temp_root: Option<&Path>,
+        join_set: &mut JoinSet<crate::Result<()>>,
     ) -> Result<Self> {
-        let (anvil, anvil_datadir, anvil_dump_path) =
-            spawn_anvil(deploy_mode.clone(), devnet_cache.clone()).await?;
+        // Cached anvil state holds the mock cbBTC, which would shadow the forked token
+        let devnet_cache = devnet_cache.filter(|_| matches!(deploy_mode, Mode::Local));
+        let (anvil, anvil_datadir, anvil_dump_path) = spawn_anvil(
+            deploy_mode.clone(),
+            chain_id,
+            devnet_cache.clone(),
+            temp_root,
+        )
+        .await?;
         info!(
             "Anvil spawned at {}, chain_id={}",
             anvil.endpoint(),
@@ -71,7 +112,7 @@ impl EthDevnet {
         .erased();
 
         let (cbbtc_contract, disperse_contract) =
-            deploy_contracts(funded_provider.clone(), devnet_cache.clone()).await?;
+            deploy_contracts(funded_provider.clone(), &deploy_mode, devnet_cache.clone()).await?;
 
         let token_indexer = if let Some(database_url) = token_indexer_database_url {
             Some(
@@ -87,6 +128,12 @@ impl EthDevnet {
         } else {
             None
         };
+        if let Some(token_indexer) = &token_indexer {
+            join_set.spawn(token_indexer.supervise(MAX_INDEXER_RESTARTS));
+            token_indexer
+                .wait_until_ready(INDEXER_READY_TIMEOUT)
+                .await?;
+        }
 
         let devnet = EthDevnet {
             anvil: anvil.into(),
@@ -103,6 +150,40 @@ impl EthDevnet {
         Ok(devnet)
     }
 
+    /// Waits until the token indexer has processed every block anvil has mined
+    pub async fn wait_for_indexer_sync(&self, timeout: Duration) -> crate::Result<()> {
+        let token_indexer = self
+            .token_indexer
+            .as_ref()
+            .ok_or(crate::DevnetError::IndexerDisabled)?;
+        let chain_id = self.anvil.chain_id();
+        let start_time = Instant::now();
+        let mut indexed_block = None;
+        let mut anvil_block = None;
+        loop {
+            if let Ok(block) = self.funded_provider.get_block_number().await {
+                anvil_block = Some(block);
+            }
+            if let Some(block) = token_indexer.indexed_block(chain_id).await {
+                indexed_block = Some(block);
+            }
+            if let (Some(indexed), Some(anvil)) = (indexed_block, anvil_block) {
+                if indexed >= anvil {
+                    return Ok(());
+                }
+            }
+            if start_time.elapsed() >= timeout {
+                return Err(crate::DevnetError::IndexerSyncTimeout {
+                    timeout,
+                    indexed_block,
+                    anvil_block,
+                });
+            }
+
+            tokio::time::sleep(INDEXER_POLL_INTERVAL).await;
+        }
+    }
+
     /// Gives `amount_wei` of Ether to `address` (via `anvil_set_balance`).
     pub async fn fund_eth_address(&self, address: Address, amount_wei: U256) -> Result<()> {
         self.funded_provider
@@ -111,7 +192,27 @@ impl EthDevnet {
         Ok(())
     }
 
+    /// Captures the chain state (blocks, balances, code and storage) via `anvil_snapshot`.
+    pub async fn snapshot(&self) -> Result<SnapshotId> {
+        let id = self.funded_provider.anvil_snapshot().await?;
+        Ok(SnapshotId(id))
+    }
+
+    /// Returns the chain to `snapshot` via `anvil_revert`. Anvil drops the snapshot
+    /// (and any taken after it) once reverted, so take another to revert again.
+    pub async fn revert(&self, snapshot: SnapshotId) -> Result<()> {
+        let reverted = self.funded_provider.anvil_revert(snapshot.0).await?;
+        if !reverted {
+            return Err(eyre!("Anvil snapshot {} no longer exists", snapshot.0));
+        }
+        Ok(())
+    }
+
+    /// Mints cbBTC for `address`, through the real token's minter when forking
     pub async fn mint_cbbtc(&self, address: Address, amount: U256) -> Result<()> {
+        if matches!(self.deploy_mode, Mode::Fork(_)) {
+            return self.mint_cbbtc_forked(address, amount).await;
+        }
         self.cbbtc_contract
             .mint(address, amount)
             .send()
@@ -121,64 +222,64 @@ impl EthDevnet {
         Ok(())
     }
 
-    /*
-    /// Mints the mock token for `address`.
-    pub async fn mint_token(&self, address: Address, amount: U256) -> Result<()> {
-        let impersonate_provider = ProviderBuilder::new()
-            .connect_http(format!("http://localhost:{}", self.anvil.port()).parse()?);
-        if matches!(self.deploy_mode, Mode::Fork(_)) {
-            // 1. Get the master minter address
-            let master_minter = self.token_contract.masterMinter().call().await?;
-
-            // 2. Configure master minter with maximum minting allowance
-            let max_allowance = U256::MAX;
-            let configure_minter_calldata = self
-                .token_contract
-                .configureMinter(master_minter, max_allowance)
-                .calldata()
-                .clone();
-
-            let tx = TransactionRequest::default()
-                .with_from(master_minter)
-                .with_to(*self.token_contract.address())
-                .with_input(configure_minter_calldata.clone());
-
-            impersonate_provider
-                .anvil_impersonate_account(master_minter)
-                .await?;
+    /// Installs another mock ERC20 at `address`, for testing tokens besides cbBTC.
+    /// Mint with the returned instance.
+    pub async fn deploy_mock_token(
+        &self,
+        address: Address,
+        symbol: &str,
+        decimals: u8,
+    ) -> Result<GenericERC20Instance<DynProvider>> {
+        self.funded_provider
+            .anvil_set_code(address, CBBTC_BYTECODE.parse().unwrap())
+            .await?;
 
-            impersonate_provider
-                .send_transaction(tx)
-                .await?
-                .get_receipt()
-                .await?;
+        let token_contract = GenericERC20Instance::new(address, self.funded_provider.clone());
+        token_contract
+            .setConfig(symbol.to_string(), symbol.to_string(), decimals)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+        Ok(token_contract)
+    }
 
-            let mint_calldata = self.token_contract.mint(address, amount).calldata().clone();
+    /// Mints the mainnet cbBTC token on a fork. The token's master minter is
+    /// impersonated to grant itself a minting allowance, then mints to `address`.
+    pub async fn mint_cbbtc_forked(&self, address: Address, amount: U256) -> Result<()> {
+        // Anvil accepts unsigned transactions from impersonated accounts, so no wallet
+        let impersonating_provider = ProviderBuilder::new()
+            .connect_http(self.anvil.endpoint_url())
+            .erased();
+        let token = IFiatToken::new(*self.cbbtc_contract.address(), impersonating_provider);
+        let master_minter = token.masterMinter().call().await?;
+
+        self.fund_eth_address(master_minter, U256::from(10u64).pow(U256::from(18u64)))
+            .await?;
+        self.funded_provider
+            .anvil_impersonate_account(master_minter)
+            .await?;
 
-            let tx = TransactionRequest::default()
-                .with_from(master_minter)
-                .with_to(*self.token_contract.address())
-                .with_input(mint_calldata.clone());
+        token
+            .configureMinter(master_minter, amount)
+            .from(master_minter)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+        token
+            .mint(address, amount)
+            .from(master_minter)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
 
-            // 3. Mint tokens as master minter
-            impersonate_provider
-                .send_transaction(tx)
-                .await?
-                .get_receipt()
-                .await?;
-        } else {
-            // For local devnet, directly mint tokens
-            self.token_contract
-                .mint(address, amount)
-                .send()
-                .await?
-                .get_receipt()
-                .await?;
-        }
+        self.funded_provider
+            .anvil_stop_impersonating_account(master_minter)
+            .await?;
         Ok(())
     }
-
-    */
 }
 
 #[derive(Clone, Debug)]
@@ -189,6 +290,7 @@ pub struct ForkConfig {
 
 async fn deploy_contracts(
     provider: DynProvider,
+    deploy_mode: &Mode,
     devnet_cache: Option<Arc<RiftDevnetCache>>,
 ) -> Result<(
     GenericERC20Instance<DynProvider>,
@@ -202,22 +304,25 @@ async fn deploy_contracts(
         return Ok((cbbtc_contract, disperse_contract));
     }
 
-    provider
-        .anvil_set_code(
-            CBBTC_ADDRESS.parse().unwrap(),
-            CBBTC_BYTECODE.parse().unwrap(),
-        )
-        .await?;
-
     let cbbtc_contract =
         GenericERC20Instance::new(CBBTC_ADDRESS.parse().unwrap(), provider.clone());
 
-    cbbtc_contract
-        .setConfig("CBBTC".to_string(), "CBBTC".to_string(), 9)
-        .send()
-        .await?
-        .get_receipt()
-        .await?;
+    // A fork keeps the real token, with its pausing, blocklist and minter roles
+    if matches!(deploy_mode, Mode::Local) {
+        provider
+            .anvil_set_code(
+                CBBTC_ADDRESS.parse().unwrap(),
+                CBBTC_BYTECODE.parse().unwrap(),
+            )
+            .await?;
+
+        cbbtc_contract
+            .setConfig("CBBTC".to_string(), "CBBTC".to_string(), CBBTC_DECIMALS)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+    }
 
     provider
         .anvil_set_code(
@@ -234,7 +339,9 @@ async fn deploy_contracts(
 /// Spawns Anvil in a blocking task.
 async fn spawn_anvil(
     mode: Mode,
+    chain_id: u64,
     devnet_cache: Option<Arc<RiftDevnetCache>>,
+    temp_root: Option<&Path>,
 ) -> Result<(AnvilInstance, Option<tempfile::TempDir>, tempfile::TempDir)> {
     let spawn_start = Instant::now();
     // Create or load anvil datafile
@@ -244,7 +351,7 @@ async fn spawn_anvil(
             devnet_cache
                 .as_ref()
                 .unwrap()
-                .create_anvil_datadir()
+                .create_anvil_datadir(temp_root)
                 .await?,
         );
         info!(
@@ -259,15 +366,18 @@ async fn spawn_anvil(
     let anvil_datadir_pathbuf = anvil_datadir.as_ref().map(|dir| dir.path().to_path_buf());
 
     // get a directory for the --dump-state flag
-    let anvil_dump_path = get_new_temp_dir()?;
+    let anvil_dump_path = get_new_temp_dir(temp_root)?;
     let anvil_dump_pathbuf = anvil_dump_path.path().to_path_buf();
 
     let anvil_instance = tokio::task::spawn_blocking(move || {
         let mut anvil = Anvil::new()
             .arg("--host")
             .arg("0.0.0.0")
-            .chain_id(1337)
+            .chain_id(chain_id)
             .block_time(1)
+            // One-slot epochs keep the safe and finalized tags 1 and 2 blocks behind latest
+            .arg("--slots-in-an-epoch")
+            .arg("1")
             // .arg("--steps-tracing")
             .arg("--timestamp")
             .arg((chrono::Utc::now().timestamp() - 9 * 60 * 60).to_string()) // 9 hours ago? TODO: do we need to do this?
@@ -4,13 +4,13 @@ pub mod bitcoin_devnet;
 pub mod evm_devnet;
 pub mod token_indexerd;
 
-pub use bitcoin_devnet::BitcoinDevnet;
+pub use bitcoin_devnet::{BitcoinCheckpoint, BitcoinDevnet, BitcoinReorg};
 use blockchain_utils::P2WPKHBitcoinWallet;
-pub use evm_devnet::EthDevnet;
+pub use evm_devnet::{EthDevnet, SnapshotId, DEVNET_CHAIN_ID};
 
 use evm_devnet::ForkConfig;
 use log::info;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tempfile::NamedTempFile;
 use tokio::task::JoinSet;
@@ -56,8 +56,13 @@ const ESPLORA_DATADIR_NAME: &str = "esplora-datadir";
 const ANVIL_DATADIR_NAME: &str = "anvil-datadir";
 const ERROR_MESSAGE: &str = "Cache must be populated before utilizing it,";
 
-pub fn get_new_temp_dir() -> Result<tempfile::TempDir> {
-    Ok(tempfile::tempdir().unwrap())
+/// Fresh temporary directory, inside `root` when given
+pub fn get_new_temp_dir(root: Option<&Path>) -> Result<tempfile::TempDir> {
+    let temp_dir = match root {
+        Some(root) => tempfile::tempdir_in(root),
+        None => tempfile::tempdir(),
+    };
+    Ok(temp_dir.map_err(|e| eyre::eyre!("Failed to create temp dir: {}", e))?)
 }
 
 pub fn get_new_temp_file() -> Result<NamedTempFile> {
@@ -113,13 +118,14 @@ impl RiftDevnetCache {
         &self,
         dir_name: &str,
         operation_name: &str,
+        temp_root: Option<&Path>,
     ) -> Result<tempfile::TempDir> {
         if !self.populated {
             return Err(eyre::eyre!("{} {}", ERROR_MESSAGE, operation_name).into());
         }
 
         let cache_dir = self.cache_dir.join(dir_name);
-        let temp_dir = get_new_temp_dir()?;
+        let temp_dir = get_new_temp_dir(temp_root)?;
 
         // We need to copy the directory contents, not the directory itself
         let output = tokio::process::Command::new("cp")
@@ -142,9 +148,12 @@ impl RiftDevnetCache {
         Ok(temp_dir)
     }
 
-    pub async fn create_bitcoin_datadir(&self) -> Result<tempfile::TempDir> {
+    pub async fn create_bitcoin_datadir(
+        &self,
+        temp_root: Option<&Path>,
+    ) -> Result<tempfile::TempDir> {
         let temp_dir = self
-            .copy_cached_dir(BITCOIN_DATADIR_NAME, "bitcoin datadir")
+            .copy_cached_dir(BITCOIN_DATADIR_NAME, "bitcoin datadir", temp_root)
             .await?;
 
         // Remove the cached .cookie file as bitcoind will generate a new one
@@ -159,13 +168,19 @@ impl RiftDevnetCache {
         Ok(temp_dir)
     }
 
-    pub async fn create_electrsd_datadir(&self) -> Result<tempfile::TempDir> {
-        self.copy_cached_dir(ESPLORA_DATADIR_NAME, "electrsd datadir")
+    pub async fn create_electrsd_datadir(
+        &self,
+        temp_root: Option<&Path>,
+    ) -> Result<tempfile::TempDir> {
+        self.copy_cached_dir(ESPLORA_DATADIR_NAME, "electrsd datadir", temp_root)
             .await
     }
 
-    pub async fn create_anvil_datadir(&self) -> Result<tempfile::TempDir> {
-        self.copy_cached_dir(ANVIL_DATADIR_NAME, "anvil datadir")
+    pub async fn create_anvil_datadir(
+        &self,
+        temp_root: Option<&Path>,
+    ) -> Result<tempfile::TempDir> {
+        self.copy_cached_dir(ANVIL_DATADIR_NAME, "anvil datadir", temp_root)
             .await
     }
 
@@ -331,8 +346,50 @@ pub enum DevnetError {
     #[snafu(display("Failed to build devnet: {}", source))]
     Build { source: eyre::Report },
 
-    #[snafu(display("Timeout waiting for esplora to sync after {timeout:?}"))]
-    EsploraSyncTimeout { timeout: std::time::Duration },
+    #[snafu(display("Esplora isn't running on this devnet"))]
+    EsploraDisabled,
+
+    #[snafu(display(
+        "Timeout waiting for esplora to sync after {timeout:?}: esplora at {}, bitcoind at {}",
+        display_height(esplora_height),
+        display_height(bitcoind_height)
+    ))]
+    EsploraSyncTimeout {
+        timeout: std::time::Duration,
+        esplora_height: Option<u64>,
+        bitcoind_height: Option<u64>,
+    },
+
+    #[snafu(display("Timeout waiting for esplora to index {txid} after {timeout:?}"))]
+    TxIndexTimeout {
+        txid: String,
+        timeout: std::time::Duration,
+    },
+
+    #[snafu(display("The token indexer isn't running on this devnet"))]
+    IndexerDisabled,
+
+    #[snafu(display(
+        "Timeout waiting for the token indexer to sync after {timeout:?}: indexer at block {}, anvil at block {}",
+        display_height(indexed_block),
+        display_height(anvil_block)
+    ))]
+    IndexerSyncTimeout {
+        timeout: std::time::Duration,
+        indexed_block: Option<u64>,
+        anvil_block: Option<u64>,
+    },
+
+    #[snafu(display(
+        "Token indexer not ready after {timeout:?}, last stderr lines:\n{stderr_tail}"
+    ))]
+    IndexerNotReady {
+        timeout: std::time::Duration,
+        stderr_tail: String,
+    },
+
+    #[snafu(display("{service} exited: {message}"))]
+    ServiceExited { service: String, message: String },
 }
 
 impl From<eyre::Report> for DevnetError {
@@ -343,6 +400,11 @@ impl From<eyre::Report> for DevnetError {
 
 pub type Result<T, E = DevnetError> = std::result::Result<T, E>;
 
+/// Last height a sync wait saw, or "unknown" when it never got an answer
+fn display_height(height: &Option<u64>) -> String {
+    height.map_or_else(|| "unknown".to_string(), |height| height.to_string())
+}
+
 // ================== RiftDevnet ================== //
 
 /// The "combined" Devnet which holds:
@@ -355,6 +417,13 @@ pub struct RiftDevnet {
     pub join_set: JoinSet<Result<()>>,
 }
 
+/// State of both chains captured by [`RiftDevnet::checkpoint`]
+#[derive(Debug, Clone)]
+pub struct DevnetCheckpoint {
+    pub bitcoin: BitcoinCheckpoint,
+    pub ethereum: SnapshotId,
+}
+
 impl RiftDevnet {
     #[must_use]
     pub fn builder() -> RiftDevnetBuilder {
@@ -365,6 +434,22 @@ impl RiftDevnet {
     pub fn builder_for_cached() -> RiftDevnetBuilder {
         RiftDevnetBuilder::for_cached()
     }
+
+    /// Captures both chains so several test cases can share one devnet,
+    /// restoring it between them.
+    pub async fn checkpoint(&self) -> Result<DevnetCheckpoint> {
+        Ok(DevnetCheckpoint {
+            bitcoin: self.bitcoin.checkpoint().await?,
+            ethereum: self.ethereum.snapshot().await?,
+        })
+    }
+
+    /// Rolls both chains back to `checkpoint`, which is used up by anvil.
+    /// Take a new checkpoint to restore again.
+    pub async fn restore(&self, checkpoint: DevnetCheckpoint) -> Result<()> {
+        self.ethereum.revert(checkpoint.ethereum).await?;
+        self.bitcoin.restore(&checkpoint.bitcoin).await
+    }
 }
 
 /// A builder for configuring a `RiftDevnet` instantiation.
@@ -377,6 +462,7 @@ pub struct RiftDevnetBuilder {
     using_esplora: bool,
     token_indexer_database_url: Option<String>,
     bitcoin_mining_mode: crate::bitcoin_devnet::MiningMode,
+    temp_dir_root: Option<PathBuf>,
 }
 
 impl RiftDevnetBuilder {
@@ -397,6 +483,7 @@ impl RiftDevnetBuilder {
             using_esplora: true,
             token_indexer_database_url: None,
             bitcoin_mining_mode: crate::bitcoin_devnet::MiningMode::default(),
+            temp_dir_root: None,
         }
     }
 
@@ -438,6 +525,14 @@ impl RiftDevnetBuilder {
         self
     }
 
+    /// Create the chains' data directories under `root`, which must exist,
+    /// instead of the system temp dir.
+    #[must_use]
+    pub fn temp_dir_root(mut self, root: PathBuf) -> Self {
+        self.temp_dir_root = Some(root);
+        self
+    }
+
     /// Start a blockstream/electrs esplora REST API server for bitcoin data indexing.
     #[must_use]
     pub fn using_esplora(mut self, value: bool) -> Self {
@@ -487,6 +582,7 @@ impl RiftDevnetBuilder {
             self.bitcoin_mining_mode,
             &mut join_set,
             devnet_cache.clone(),
+            self.temp_dir_root.as_deref(),
         )
         .await
         .map_err(|e| eyre::eyre!("[devnet builder] Failed to setup Bitcoin devnet: {}", e))?;
@@ -514,8 +610,11 @@ impl RiftDevnetBuilder {
 
         let ethereum_devnet = crate::evm_devnet::EthDevnet::setup(
             deploy_mode,
+            DEVNET_CHAIN_ID,
             devnet_cache.clone(),
             self.token_indexer_database_url.clone(),
+            self.temp_dir_root.as_deref(),
+            &mut join_set,
         )
         .await
         .map_err(|e| eyre::eyre!("[devnet builder] Failed to setup Ethereum devnet: {}", e))?;
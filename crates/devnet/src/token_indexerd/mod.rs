@@ -1,22 +1,135 @@
+use evm_token_indexer_client::TokenIndexerClient;
+use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 use tokio::{
+    io::{AsyncBufReadExt, BufReader},
     net::TcpListener,
-    process::{Child, Command},
-    time::{sleep, timeout},
+    process::{Child, ChildStderr, Command},
+    time::{sleep, Instant},
 };
 use tracing::{info, warn};
 use uuid::Uuid;
 
+use crate::DevnetError;
+
 const HOST: &str = "127.0.0.1";
 
+/// Times the supervisor restarts a crashed indexer before giving up on it
+pub const MAX_INDEXER_RESTARTS: u32 = 3;
+
+/// How often the supervisor checks whether the indexer is still running
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often [`TokenIndexerInstance::wait_until_ready`] re-checks the indexer
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Lines of the indexer's stderr kept to explain a crash
+const STDERR_TAIL_LINES: usize = 50;
+
 pub struct TokenIndexerInstance {
-    // Handle to the spawned pnpm dev process
-    pub child: Child,
+    indexer: Arc<SupervisedIndexer>,
     pub api_server_url: String,
 }
 
+/// State shared between the instance and its supervisor task
+struct SupervisedIndexer {
+    command: IndexerCommand,
+    // Handle to the spawned pnpm dev process, replaced on every restart
+    process: Mutex<IndexerProcess>,
+    stderr_tail: Arc<StderrTail>,
+    restarts: AtomicU32,
+}
+
+struct IndexerProcess {
+    child: Child,
+    /// Set once the instance is dropped, so the supervisor doesn't bring the
+    /// killed process back
+    stopped: bool,
+}
+
+/// Everything `pnpm dev` is started with. Restarts reuse the port and schema,
+/// so the indexer picks up where it left off at the same URL
+struct IndexerCommand {
+    token_indexer_dir: PathBuf,
+    ponder_port: u16,
+    schema_uuid: Uuid,
+    rpc_url: String,
+    ws_url: String,
+    pipe_output: bool,
+    chain_id: u64,
+    database_url: String,
+}
+
+impl IndexerCommand {
+    fn spawn(&self, stderr_tail: &Arc<StderrTail>) -> std::io::Result<Child> {
+        let mut cmd = Command::new("pnpm");
+        cmd.args([
+            "dev",
+            "--disable-ui",
+            "--port",
+            self.ponder_port.to_string().as_str(),
+            "--schema",
+            self.schema_uuid.to_string().as_str(),
+        ])
+        .kill_on_drop(true)
+        .current_dir(&self.token_indexer_dir)
+        .env("DATABASE_URL", &self.database_url)
+        .env("PONDER_CHAIN_ID", self.chain_id.to_string())
+        .env("PONDER_RPC_URL_HTTP", &self.rpc_url)
+        .env("PONDER_WS_URL_HTTP", &self.ws_url)
+        .env("PONDER_DISABLE_CACHE", "true")
+        .env("PONDER_CONTRACT_START_BLOCK", "0")
+        .env("PONDER_LOG_LEVEL", "trace");
+
+        if self.pipe_output {
+            cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+        } else {
+            // Nothing reads stdout, a full pipe would stall ponder
+            cmd.stdout(Stdio::null()).stderr(Stdio::piped());
+        }
+
+        let mut child = cmd.spawn()?;
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(capture_stderr(stderr, stderr_tail.clone()));
+        }
+        Ok(child)
+    }
+}
+
+/// The last [`STDERR_TAIL_LINES`] lines the indexer wrote to stderr
+#[derive(Default)]
+struct StderrTail(Mutex<VecDeque<String>>);
+
+impl StderrTail {
+    fn push(&self, line: String) {
+        let mut lines = self.0.lock().unwrap();
+        if lines.len() == STDERR_TAIL_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn contents(&self) -> String {
+        let lines = self.0.lock().unwrap();
+        lines
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+async fn capture_stderr(stderr: ChildStderr, tail: Arc<StderrTail>) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tail.push(line);
+    }
+}
+
 impl TokenIndexerInstance {
     pub async fn new(
         rpc_url: &str,
@@ -41,87 +154,188 @@ impl TokenIndexerInstance {
             .expect("Should have a local address")
             .port();
 
-        // uuid for the schema
-        let schema_uuid = Uuid::new_v4();
-        let mut cmd = Command::new("pnpm");
-        cmd.args([
-            "dev",
-            "--disable-ui",
-            "--port",
-            ponder_port.to_string().as_str(),
-            "--schema",
-            schema_uuid.to_string().as_str(),
-        ])
-        .kill_on_drop(true)
-        .current_dir(token_indexer_dir)
-        .env("DATABASE_URL", database_url)
-        .env("PONDER_CHAIN_ID", chain_id.to_string())
-        .env("PONDER_RPC_URL_HTTP", rpc_url)
-        .env("PONDER_WS_URL_HTTP", ws_url)
-        .env("PONDER_DISABLE_CACHE", "true")
-        .env("PONDER_CONTRACT_START_BLOCK", "0")
-        .env("PONDER_LOG_LEVEL", "trace");
-
-        if pipe_output {
-            cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
-        } else {
-            cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
-        }
-
-        let child = cmd.spawn().expect("Failed to spawn token indexer process");
+        let command = IndexerCommand {
+            token_indexer_dir,
+            ponder_port,
+            // uuid for the schema
+            schema_uuid: Uuid::new_v4(),
+            rpc_url: rpc_url.to_string(),
+            ws_url: ws_url.to_string(),
+            pipe_output,
+            chain_id,
+            database_url,
+        };
+        let stderr_tail = Arc::new(StderrTail::default());
+        let child = command
+            .spawn(&stderr_tail)
+            .expect("Failed to spawn token indexer process");
 
         let api_server_url = format!("http://{HOST}:{ponder_port}");
         info!("Indexer API server URL: {api_server_url}");
 
         Ok(Self {
-            child,
+            indexer: Arc::new(SupervisedIndexer {
+                command,
+                process: Mutex::new(IndexerProcess {
+                    child,
+                    stopped: false,
+                }),
+                stderr_tail,
+                restarts: AtomicU32::new(0),
+            }),
             api_server_url,
         })
     }
 
+    /// Keeps the indexer running, restarting it with the same schema each time
+    /// it exits, up to `max_restarts` times. Resolves once the instance is
+    /// dropped, or with an error carrying the stderr tail once out of restarts
+    pub fn supervise(
+        &self,
+        max_restarts: u32,
+    ) -> impl std::future::Future<Output = crate::Result<()>> + Send + 'static {
+        let indexer = Arc::downgrade(&self.indexer);
+        async move {
+            loop {
+                sleep(SUPERVISE_INTERVAL).await;
+                if !restart_if_exited(&indexer, max_restarts)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Waits until the indexer answers the client's table counts query, which
+    /// it only does once its API is up over a migrated schema
+    pub async fn wait_until_ready(&self, timeout: Duration) -> crate::Result<()> {
+        let client = TokenIndexerClient::new(&self.api_server_url)
+            .map_err(|e| eyre::eyre!("Invalid token indexer URL: {e}"))?;
+        let start_time = Instant::now();
+        loop {
+            if client.get_table_counts().await.is_ok() {
+                return Ok(());
+            }
+            if start_time.elapsed() >= timeout {
+                return Err(DevnetError::IndexerNotReady {
+                    timeout,
+                    stderr_tail: self.stderr_tail(),
+                });
+            }
+
+            sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Latest block of `chain_id` the indexer has processed, per ponder's
+    /// `/status` endpoint. `None` until the indexer is up and has a block
+    pub async fn indexed_block(&self, chain_id: u64) -> Option<u64> {
+        let status: serde_json::Value = reqwest::get(format!("{}/status", self.api_server_url))
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+        status
+            .as_object()?
+            .values()
+            .find(|chain| chain["id"].as_u64() == Some(chain_id))?["block"]["number"]
+            .as_u64()
+    }
+
+    /// The last lines the indexer wrote to stderr, empty when its output is
+    /// piped to the terminal
+    #[must_use]
+    pub fn stderr_tail(&self) -> String {
+        self.indexer.stderr_tail.contents()
+    }
+
+    /// How often the supervisor has restarted the indexer
+    #[must_use]
+    pub fn restarts(&self) -> u32 {
+        self.indexer.restarts.load(Ordering::SeqCst)
+    }
+
     /// Check if the process is still running
-    pub fn is_running(&mut self) -> bool {
-        matches!(self.child.try_wait(), Ok(None))
+    pub fn is_running(&self) -> bool {
+        let mut process = self.indexer.process.lock().unwrap();
+        matches!(process.child.try_wait(), Ok(None))
     }
 
-    /// Kill the process
-    pub async fn kill(&mut self) -> std::io::Result<()> {
-        self.child.kill().await
+    /// Kill the process, as a crash would. The supervisor restarts it
+    pub fn kill(&self) {
+        let process = self.indexer.process.lock().unwrap();
+        if let Some(pid) = process.child.id() {
+            kill_process_tree(pid);
+        }
     }
+}
 
-    /// Wait for the process to finish
-    pub async fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
-        self.child.wait().await
+/// Restarts the indexer if it exited. `false` once the instance is gone and
+/// there is nothing left to supervise
+fn restart_if_exited(indexer: &Weak<SupervisedIndexer>, max_restarts: u32) -> crate::Result<bool> {
+    let Some(indexer) = indexer.upgrade() else {
+        return Ok(false);
+    };
+    let mut process = indexer.process.lock().unwrap();
+    if process.stopped {
+        return Ok(false);
     }
+    let exit = match process.child.try_wait() {
+        Ok(None) => return Ok(true),
+        Ok(Some(status)) => status.to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    let restarts = indexer.restarts.load(Ordering::SeqCst);
+    let stderr_tail = indexer.stderr_tail.contents();
+    if restarts >= max_restarts {
+        return Err(DevnetError::ServiceExited {
+            service: "token indexer".to_string(),
+            message: format!("{exit} after {restarts} restarts, stderr:\n{stderr_tail}"),
+        });
+    }
+    warn!(%exit, restarts, %stderr_tail, "Token indexer exited, restarting it");
+    process.child =
+        indexer
+            .command
+            .spawn(&indexer.stderr_tail)
+            .map_err(|e| DevnetError::ServiceExited {
+                service: "token indexer".to_string(),
+                message: format!("failed to restart: {e}"),
+            })?;
+    indexer.restarts.fetch_add(1, Ordering::SeqCst);
+    Ok(true)
 }
 
 impl Drop for TokenIndexerInstance {
     fn drop(&mut self) {
-        if let Some(pid) = self.child.id() {
-            self.kill_process_tree(pid);
+        let mut process = self.indexer.process.lock().unwrap();
+        process.stopped = true;
+        if let Some(pid) = process.child.id() {
+            kill_process_tree(pid);
         }
     }
 }
 
-impl TokenIndexerInstance {
-    fn kill_process_tree(&self, pid: u32) {
-        let mut pids = vec![pid];
-
-        // Get direct children
-        if let Ok(output) = std::process::Command::new("pgrep")
-            .arg("-P")
-            .arg(pid.to_string())
-            .output()
-        {
-            let children: Vec<u32> = String::from_utf8_lossy(&output.stdout)
-                .lines()
-                .filter_map(|line| line.trim().parse().ok())
-                .collect();
-            pids.extend(children);
-        }
+fn kill_process_tree(pid: u32) {
+    let mut pids = vec![pid];
 
-        // Kill all processes in one command
-        let pid_args: Vec<String> = pids.iter().map(|p| p.to_string()).collect();
-        let _ = std::process::Command::new("kill").args(&pid_args).output();
+    // Get direct children
+    if let Ok(output) = std::process::Command::new("pgrep")
+        .arg("-P")
+        .arg(pid.to_string())
+        .output()
+    {
+        let children: Vec<u32> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect();
+        pids.extend(children);
     }
+
+    // Kill all processes in one command
+    let pid_args: Vec<String> = pids.iter().map(|p| p.to_string()).collect();
+    let _ = std::process::Command::new("kill").args(&pid_args).output();
 }
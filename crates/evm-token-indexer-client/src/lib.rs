@@ -2,6 +2,7 @@ use alloy::primitives::{Address, B256, U256};
 use reqwest::{Client, Url};
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
+use tracing::warn;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -16,10 +17,19 @@ pub enum Error {
 
     #[snafu(display("Invalid base URL: {source}"))]
     InvalidUrl { source: url::ParseError },
+
+    #[snafu(display("Invalid block number {value:?}: {source}"))]
+    InvalidBlockNumber {
+        value: String,
+        source: std::num::ParseIntError,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Most pages fetched when collecting every transfer to an address
+const MAX_TRANSFER_PAGES: u32 = 20;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TableCounts {
@@ -47,6 +57,22 @@ pub struct TransferEvent {
     pub block_hash: B256,
 }
 
+impl TransferEvent {
+    pub fn block_number(&self) -> Result<u64> {
+        self.block_number.parse().context(InvalidBlockNumberSnafu {
+            value: self.block_number.clone(),
+        })
+    }
+}
+
+/// A transfer and its confirmations at the chain head it was searched against
+#[derive(Debug, Clone)]
+pub struct ConfirmedTransfer {
+    pub transfer: TransferEvent,
+    /// Blocks mined on top of the transfer's block, 0 while it's in the head block
+    pub confirmations: u64,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Pagination {
     pub page: u32,
@@ -110,11 +136,15 @@ impl TokenIndexerClient {
         Ok(response)
     }
 
+    /// One page of transfers to `address`, newest first. `min_block` and
+    /// `max_block` bound the blocks searched, both inclusive.
     pub async fn get_transfers_to(
         &self,
         address: Address,
         page: Option<u32>,
         min_amount: Option<U256>,
+        min_block: Option<u64>,
+        max_block: Option<u64>,
     ) -> Result<TransfersResponse> {
         let mut url = self.base_url
             .join(&format!("transfers/to/{:?}", address))
@@ -130,6 +160,14 @@ impl TokenIndexerClient {
             if let Some(amount) = min_amount {
                 query_pairs.append_pair("amount", &amount.to_string());
             }
+
+            if let Some(min_block) = min_block {
+                query_pairs.append_pair("min_block", &min_block.to_string());
+            }
+
+            if let Some(max_block) = max_block {
+                query_pairs.append_pair("max_block", &max_block.to_string());
+            }
         }
         
         let response = self.client
@@ -143,15 +181,179 @@ impl TokenIndexerClient {
         
         Ok(response)
     }
+
+    /// Every transfer to `address` from `min_block` up to `head_block`, newest
+    /// first, with its confirmations at `head_block`. Fetches at most
+    /// `MAX_TRANSFER_PAGES` pages.
+    pub async fn get_transfers_to_with_confirmations(
+        &self,
+        address: Address,
+        min_amount: Option<U256>,
+        min_block: Option<u64>,
+        head_block: u64,
+    ) -> Result<Vec<ConfirmedTransfer>> {
+        let mut transfers = Vec::new();
+        for page in 1..=MAX_TRANSFER_PAGES {
+            let response = self
+                .get_transfers_to(address, Some(page), min_amount, min_block, Some(head_block))
+                .await?;
+            for transfer in response.transfers {
+                let confirmations = head_block.saturating_sub(transfer.block_number()?);
+                transfers.push(ConfirmedTransfer {
+                    transfer,
+                    confirmations,
+                });
+            }
+            if page >= response.pagination.total_pages {
+                return Ok(transfers);
+            }
+        }
+
+        warn!(
+            "Transfers to {} span more than {} pages, only the newest were fetched",
+            address, MAX_TRANSFER_PAGES
+        );
+        Ok(transfers)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::{json, Value};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const RECIPIENT: Address = Address::repeat_byte(0x11);
+
+    fn transfer(block_number: &str) -> Value {
+        json!({
+            "id": format!("transfer-{block_number}"),
+            "amount": "1000",
+            "timestamp": 1_700_000_000u64,
+            "from": Address::repeat_byte(0x22),
+            "to": RECIPIENT,
+            "transactionHash": B256::repeat_byte(0x33),
+            "blockNumber": block_number,
+            "blockHash": B256::repeat_byte(0x44),
+        })
+    }
+
+    fn page(page: u32, total_pages: u32, transfers: Vec<Value>) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "transfers": transfers,
+            "pagination": {
+                "page": page,
+                "limit": 50,
+                "total": 0,
+                "totalPages": total_pages,
+            },
+        }))
+    }
+
+    fn transfers_path() -> String {
+        format!("/transfers/to/{RECIPIENT:?}")
+    }
 
     #[test]
     fn test_client_creation() {
         let client = TokenIndexerClient::new("http://localhost:3000");
         assert!(client.is_ok());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_get_transfers_to_bounds_the_blocks() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(transfers_path()))
+            .and(query_param("page", "2"))
+            .and(query_param("amount", "500"))
+            .and(query_param("min_block", "100"))
+            .and(query_param("max_block", "200"))
+            .respond_with(page(2, 2, vec![transfer("150")]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = TokenIndexerClient::new(server.uri()).unwrap();
+        let response = client
+            .get_transfers_to(
+                RECIPIENT,
+                Some(2),
+                Some(U256::from(500)),
+                Some(100),
+                Some(200),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.transfers.len(), 1);
+        assert_eq!(response.transfers[0].block_number().unwrap(), 150);
+        assert_eq!(response.pagination.total_pages, 2);
+    }
+
+    #[tokio::test]
+    async fn test_transfers_with_confirmations_fetch_every_page() {
+        let server = MockServer::start().await;
+        for (number, transfers) in [
+            (1, vec![transfer("110"), transfer("105")]),
+            (2, vec![transfer("100")]),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(transfers_path()))
+                .and(query_param("page", number.to_string()))
+                .and(query_param("min_block", "90"))
+                .and(query_param("max_block", "110"))
+                .respond_with(page(number, 2, transfers))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let client = TokenIndexerClient::new(server.uri()).unwrap();
+        let transfers = client
+            .get_transfers_to_with_confirmations(RECIPIENT, None, Some(90), 110)
+            .await
+            .unwrap();
+
+        let confirmations: Vec<u64> = transfers.iter().map(|t| t.confirmations).collect();
+        assert_eq!(confirmations, [0, 5, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_transfers_with_confirmations_stop_at_the_page_cap() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(transfers_path()))
+            .respond_with(page(1, MAX_TRANSFER_PAGES + 5, vec![transfer("100")]))
+            .expect(u64::from(MAX_TRANSFER_PAGES))
+            .mount(&server)
+            .await;
+
+        let client = TokenIndexerClient::new(server.uri()).unwrap();
+        let transfers = client
+            .get_transfers_to_with_confirmations(RECIPIENT, None, None, 100)
+            .await
+            .unwrap();
+
+        assert_eq!(transfers.len(), MAX_TRANSFER_PAGES as usize);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_block_number_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(transfers_path()))
+            .respond_with(page(1, 1, vec![transfer("not-a-block")]))
+            .mount(&server)
+            .await;
+
+        let client = TokenIndexerClient::new(server.uri()).unwrap();
+        let err = client
+            .get_transfers_to_with_confirmations(RECIPIENT, None, None, 100)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::InvalidBlockNumber { .. }));
+    }
+}
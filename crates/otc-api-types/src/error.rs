@@ -0,0 +1,111 @@
+use otc_models::FieldError;
+use serde::{Deserialize, Serialize};
+
+/// Stable codes of the errors the OTC and RFQ servers return. Errors clients
+/// are expected to branch on have their own, every other error carries the
+/// generic code of its HTTP status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ApiErrorCode {
+    QuoteSignatureInvalid,
+    ValidationFailed,
+    UnsupportedToken,
+    /// A currency's decimals differ from those the server lists for the token
+    DecimalsMismatch,
+    /// The payout would be dust at the user's destination, the details carry the threshold
+    OutputBelowDust,
+    QuoteStalePrice,
+    /// The quote has too little validity left to create a swap, request a new one
+    QuoteExpiring,
+    IdempotencyKeyReused,
+    RateLimited,
+    /// Any other 4xx without a code of its own
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    Timeout,
+    /// Any other 5xx without a code of its own
+    InternalError,
+    BadGateway,
+    ServiceUnavailable,
+}
+
+impl ApiErrorCode {
+    /// The generic code of an error answered with HTTP `status`
+    #[must_use]
+    pub fn for_status(status: u16) -> Self {
+        match status {
+            401 => Self::Unauthorized,
+            403 => Self::Forbidden,
+            404 => Self::NotFound,
+            408 => Self::Timeout,
+            409 => Self::Conflict,
+            429 => Self::RateLimited,
+            502 => Self::BadGateway,
+            503 => Self::ServiceUnavailable,
+            500..=599 => Self::InternalError,
+            _ => Self::BadRequest,
+        }
+    }
+}
+
+/// The `error` object of an OTC server error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ApiErrorDetail {
+    pub code: ApiErrorCode,
+    pub message: String,
+    pub details: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<FieldError>>,
+}
+
+/// Body of an OTC server error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ApiErrorResponse {
+    pub error: ApiErrorDetail,
+}
+
+/// Body of an RFQ server error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct RfqErrorResponse {
+    pub error: String,
+    pub code: ApiErrorCode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<FieldError>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_match_the_wire_format() {
+        assert_eq!(
+            serde_json::to_value(ApiErrorCode::IdempotencyKeyReused).unwrap(),
+            "IDEMPOTENCY_KEY_REUSED"
+        );
+        let detail: ApiErrorDetail = serde_json::from_str(
+            r#"{"code":"QUOTE_STALE_PRICE","message":"Quote price is stale","details":""}"#,
+        )
+        .unwrap();
+        assert_eq!(detail.code, ApiErrorCode::QuoteStalePrice);
+    }
+
+    #[test]
+    fn test_every_status_has_a_generic_code() {
+        assert_eq!(ApiErrorCode::for_status(404), ApiErrorCode::NotFound);
+        assert_eq!(ApiErrorCode::for_status(413), ApiErrorCode::BadRequest);
+        assert_eq!(
+            ApiErrorCode::for_status(503),
+            ApiErrorCode::ServiceUnavailable
+        );
+        assert_eq!(ApiErrorCode::for_status(500), ApiErrorCode::InternalError);
+        assert_eq!(ApiErrorCode::for_status(504), ApiErrorCode::InternalError);
+    }
+}
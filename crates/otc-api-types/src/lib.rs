@@ -0,0 +1,16 @@
+//! Request and response bodies of the OTC and RFQ server HTTP APIs, shared by
+//! the servers and their clients
+
+pub mod error;
+pub mod market_makers;
+pub mod quotes;
+pub mod receipts;
+pub mod swaps;
+pub mod trace_id;
+
+pub use error::*;
+pub use market_makers::*;
+pub use quotes::*;
+pub use receipts::*;
+pub use swaps::*;
+pub use trace_id::*;
@@ -0,0 +1,166 @@
+use chrono::{DateTime, Utc};
+use otc_protocols::rfq::QuoteOutcome;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Query parameters for GET /api/v1/market-makers/connected
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "utoipa",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct ConnectedMarketMakersQuery {
+    /// Return each market maker's connection metadata instead of just its id
+    #[serde(default)]
+    pub detail: bool,
+    /// Only market makers that connected with this protocol version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<String>,
+    /// Market makers to skip, in id order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    /// Most market makers to return, all of them when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// What a server knows about one market maker connection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ConnectedMarketMaker {
+    pub market_maker_id: Uuid,
+    pub protocol_version: String,
+    pub connected_at: DateTime<Utc>,
+    pub uptime_seconds: u64,
+    /// Requests sent to the market maker that it hasn't answered yet
+    pub in_flight_requests: usize,
+    /// When the market maker last answered a ping, `None` if it never has
+    pub last_pong_at: Option<DateTime<Utc>>,
+}
+
+/// Response for GET /api/v1/market-makers/connected. Plain ids unless the
+/// query asked for `detail`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(untagged)]
+pub enum ConnectedMarketMakersResponse {
+    Detailed {
+        market_makers: Vec<ConnectedMarketMaker>,
+        /// Market makers matching the filter, before `offset` and `limit`
+        total: usize,
+    },
+    Ids {
+        market_makers: Vec<Uuid>,
+        total: usize,
+    },
+}
+
+/// Query parameters for GET /api/v1/mm/quote-outcomes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "utoipa",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct QuoteOutcomesQuery {
+    /// Only outcomes decided after this time, every one still kept when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Response for GET /api/v1/mm/quote-outcomes, oldest outcome first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteOutcomesResponse {
+    pub market_maker_id: Uuid,
+    pub outcomes: Vec<QuoteOutcome>,
+}
+
+impl ConnectedMarketMakersQuery {
+    /// Filters and pages `market_makers` into the response shape the query asked for
+    #[must_use]
+    pub fn respond(
+        &self,
+        mut market_makers: Vec<ConnectedMarketMaker>,
+    ) -> ConnectedMarketMakersResponse {
+        if let Some(version) = &self.protocol_version {
+            market_makers.retain(|mm| &mm.protocol_version == version);
+        }
+        market_makers.sort_by_key(|mm| mm.market_maker_id);
+        let total = market_makers.len();
+        let page = market_makers
+            .into_iter()
+            .skip(self.offset.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX));
+
+        if self.detail {
+            ConnectedMarketMakersResponse::Detailed {
+                market_makers: page.collect(),
+                total,
+            }
+        } else {
+            ConnectedMarketMakersResponse::Ids {
+                market_makers: page.map(|mm| mm.market_maker_id).collect(),
+                total,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market_maker(id: u128, protocol_version: &str) -> ConnectedMarketMaker {
+        ConnectedMarketMaker {
+            market_maker_id: Uuid::from_u128(id),
+            protocol_version: protocol_version.to_string(),
+            connected_at: Utc::now(),
+            uptime_seconds: 0,
+            in_flight_requests: 0,
+            last_pong_at: None,
+        }
+    }
+
+    #[test]
+    fn test_plain_ids_unless_detail_is_asked_for() {
+        let market_makers = vec![market_maker(2, "1.0.0"), market_maker(1, "1.0.0")];
+
+        let plain = ConnectedMarketMakersQuery::default().respond(market_makers.clone());
+        let json = serde_json::to_value(&plain).unwrap();
+        assert_eq!(
+            json["market_makers"],
+            serde_json::json!([Uuid::from_u128(1), Uuid::from_u128(2)])
+        );
+
+        let query = ConnectedMarketMakersQuery {
+            detail: true,
+            ..Default::default()
+        };
+        let json = serde_json::to_value(query.respond(market_makers)).unwrap();
+        assert_eq!(json["market_makers"][0]["protocol_version"], "1.0.0");
+        assert_eq!(json["total"], 2);
+    }
+
+    #[test]
+    fn test_filter_then_page() {
+        let market_makers = (1..=5)
+            .map(|id| market_maker(id, if id == 3 { "0.9.0" } else { "1.0.0" }))
+            .collect();
+        let query = ConnectedMarketMakersQuery {
+            protocol_version: Some("1.0.0".to_string()),
+            offset: Some(1),
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            query.respond(market_makers),
+            ConnectedMarketMakersResponse::Ids {
+                market_makers: vec![Uuid::from_u128(2), Uuid::from_u128(4)],
+                total: 4,
+            }
+        );
+    }
+}
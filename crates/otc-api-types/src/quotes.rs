@@ -0,0 +1,151 @@
+use crate::RfqErrorResponse;
+use chrono::{DateTime, Utc};
+use otc_models::{FieldError, QuoteRequest, Validate};
+use otc_protocols::rfq::{QuoteWithFees, RFQResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+/// Response for POST /api/v1/quotes/request
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteResponse {
+    pub request_id: Uuid,
+    /// Correlation id of the request, to be passed along when creating a swap
+    pub trace_id: String,
+    pub quote: Option<RFQResult<QuoteWithFees>>,
+    /// Seconds the returned quote stays valid for, as of the response, for a countdown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in_seconds: Option<u64>,
+    pub total_quotes_received: usize,
+    pub market_makers_contacted: usize,
+    /// Quotes dropped for an out of bounds expiry or timestamp
+    #[serde(default)]
+    pub rejected_quotes: Vec<RejectedQuote>,
+    /// How long the server waited for market makers
+    #[serde(default)]
+    pub timing: QuoteTiming,
+    /// Set when several market makers offered the best price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tie_break: Option<QuoteTieBreak>,
+}
+
+/// Body of POST /api/v1/quotes/request-batch, e.g. both directions of a pair
+/// at several sizes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteBatchRequest {
+    pub requests: Vec<QuoteRequest>,
+}
+
+impl Validate for QuoteBatchRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        for (index, request) in self.requests.iter_mut().enumerate() {
+            if let Err(request_errors) = request.validate() {
+                errors.extend(request_errors.into_iter().map(|error| {
+                    FieldError::new(format!("requests[{index}].{}", error.field), error.message)
+                }));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Response for POST /api/v1/quotes/request-batch
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteBatchResponse {
+    pub batch_id: Uuid,
+    /// Correlation id of the request, to be passed along when creating a swap
+    pub trace_id: String,
+    /// One result per request of the batch, in request order
+    pub results: Vec<QuoteBatchResult>,
+}
+
+/// How one request of a batch went, either `response` or `error` is set
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteBatchResult {
+    /// Position of the request in the batch
+    pub index: usize,
+    /// What POST /api/v1/quotes/request would have returned for the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<QuoteResponse>,
+    /// Why no market maker quoted the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RfqErrorResponse>,
+}
+
+/// Response for POST /api/v1/quotes/:id/lock, the quote's market maker holds
+/// the funds for it until `expires_at`. Passed along when creating the swap
+/// so the market maker isn't asked to validate the quote again
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteLock {
+    pub quote_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    /// Hex encoded signature of the RFQ server over the quote and `expires_at`
+    pub token: String,
+}
+
+/// How quote collection went for one request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteTiming {
+    /// Time from fanning the request out to picking the best quote
+    pub elapsed_ms: u64,
+    /// Deadline in force when collection ended, measured from the fan out
+    pub deadline_ms: u64,
+    /// Market makers that answered, with a quote or an error, in time
+    pub market_makers_responded: usize,
+    /// The deadline was extended to wait for slower market makers
+    pub deadline_extended: bool,
+}
+
+/// Market makers whose quotes tied for the best price, one of them picked at
+/// random
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteTieBreak {
+    /// In id order, the winner included
+    pub tied_market_makers: Vec<Uuid>,
+    pub winner: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteRejectionReason {
+    /// `expires_at` had already passed when the quote arrived
+    Expired,
+    /// `expires_at` is further out than the maximum quote lifetime
+    LifetimeTooLong,
+    /// `created_at` is too far from the server's clock
+    ClockSkew,
+}
+
+impl fmt::Display for QuoteRejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::Expired => "quote already expired",
+            Self::LifetimeTooLong => "quote expires too far in the future",
+            Self::ClockSkew => "quote creation time is too far from server time",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// A quote a market maker returned that was dropped before selection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct RejectedQuote {
+    /// The market maker the quote came from, per its connection
+    pub market_maker_id: Uuid,
+    pub quote_id: Uuid,
+    pub reason: QuoteRejectionReason,
+}
@@ -0,0 +1,83 @@
+use alloy::primitives::U256;
+use chrono::{DateTime, Utc};
+use otc_models::{ChainType, FillCost, SwapStatus, TokenIdentifier};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Response for GET /swaps/:id/receipt
+///
+/// Everything here is read back from storage and serialized in field order, so
+/// the same settled swap always produces byte-identical JSON that can be archived
+/// or signed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SwapReceipt {
+    pub swap_id: Uuid,
+    pub quote_id: Uuid,
+    pub market_maker_id: Uuid,
+    pub status: SwapStatus,
+
+    /// Address the user deposited into
+    pub user_deposit_address: String,
+
+    /// Address the market maker paid out to
+    pub user_destination_address: String,
+
+    pub user_deposit: ReceiptDeposit,
+    pub mm_deposit: ReceiptDeposit,
+    pub fees: ReceiptFees,
+
+    /// Every status change, oldest first
+    pub transitions: Vec<ReceiptTransition>,
+
+    pub created_at: DateTime<Utc>,
+    pub quote_expires_at: DateTime<Utc>,
+    pub settled_at: DateTime<Utc>,
+
+    /// When the user deposit key was released to the market maker
+    pub mm_private_key_sent_at: Option<DateTime<Utc>>,
+
+    /// The reference the user gave when creating the swap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_reference: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ReceiptDeposit {
+    pub chain: ChainType,
+    pub token: TokenIdentifier,
+    pub decimals: u8,
+    #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+    pub expected_amount: U256,
+    pub tx_hash: String,
+    #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+    pub amount: U256,
+    pub detected_at: DateTime<Utc>,
+
+    /// Confirmations the deposit had when the swap settled
+    pub confirmations: u64,
+    pub required_confirmations: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ReceiptFees {
+    pub protocol_fee_bps: u64,
+
+    /// Protocol fee in the market maker's deposit currency
+    #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+    pub protocol_fee: U256,
+
+    /// Network fee of the market maker's deposit as the market maker reported
+    /// it, `None` for market makers that don't
+    pub mm_fill_cost: Option<FillCost>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ReceiptTransition {
+    pub from_status: Option<SwapStatus>,
+    pub to_status: SwapStatus,
+    pub occurred_at: DateTime<Utc>,
+}
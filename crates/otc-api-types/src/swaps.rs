@@ -0,0 +1,382 @@
+use crate::{is_valid_trace_id, QuoteLock, MAX_TRACE_ID_LEN};
+use alloy::primitives::{Address, U256};
+use chrono::{DateTime, Utc};
+use otc_models::{
+    apply, sanitize_address, sanitize_external_reference, sanitize_signature, FieldError, Quote,
+    TxHash, Validate,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Header that makes POST /api/v1/swaps replay the swap an earlier request
+/// with the same key created
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Request to create a new swap from a quote
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct CreateSwapRequest {
+    /// The quote ID to create a swap from
+    pub quote: Quote,
+
+    /// Signature issued by the RFQ server alongside the quote
+    #[serde(default)]
+    pub quote_signature: Option<String>,
+
+    /// Lock the quote's market maker granted through the RFQ server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_lock: Option<QuoteLock>,
+
+    /// User's destination address for receiving funds
+    pub user_destination_address: String,
+
+    /// User's EVM account that is authorized to control the swap
+    #[cfg_attr(feature = "utoipa", schema(value_type = String, pattern = "^0x[0-9a-fA-F]{40}$"))]
+    pub user_evm_account_address: Address,
+
+    /// Where the deposit is refunded if the swap fails, on the quote's `from`
+    /// chain. Can be set later with PATCH /api/v1/swaps/:id/refund-address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_refund_address: Option<String>,
+
+    /// Correlation id returned with the quote, the request's own id is used
+    /// when it's missing
+    #[serde(default)]
+    pub trace_id: Option<String>,
+
+    /// The user's own reference for the swap, e.g. an invoice number. Stored
+    /// and returned as is, swaps can be looked up by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "utoipa", schema(max_length = 128))]
+    pub external_reference: Option<String>,
+}
+
+impl Validate for CreateSwapRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = self.quote.validate().err().unwrap_or_default();
+
+        let result = sanitize_address(
+            "user_destination_address",
+            &self.user_destination_address,
+            self.quote.to.currency.chain,
+        );
+        apply(&mut errors, &mut self.user_destination_address, result);
+
+        if let Some(refund_address) = self.user_refund_address.as_mut() {
+            let result = sanitize_address(
+                "user_refund_address",
+                refund_address,
+                self.quote.from.currency.chain,
+            );
+            apply(&mut errors, refund_address, result);
+        }
+
+        if let Some(reference) = self.external_reference.as_mut() {
+            let result = sanitize_external_reference("external_reference", reference);
+            apply(&mut errors, reference, result);
+        }
+
+        if let Some(signature) = self.quote_signature.as_mut() {
+            let result = sanitize_signature("quote_signature", signature);
+            apply(&mut errors, signature, result);
+        }
+
+        if let Some(lock) = self.quote_lock.as_mut() {
+            if lock.quote_id != self.quote.id {
+                errors.push(FieldError::new(
+                    "quote_lock.quote_id",
+                    "must be the id of the quote",
+                ));
+            }
+            let result = sanitize_signature("quote_lock.token", &lock.token);
+            apply(&mut errors, &mut lock.token, result);
+        }
+
+        if let Some(trace_id) = &self.trace_id {
+            if !is_valid_trace_id(trace_id) {
+                errors.push(FieldError::new(
+                    "trace_id",
+                    format!(
+                        "must be 1 to {MAX_TRACE_ID_LEN} alphanumeric, '-', '_', '.' or ':' characters"
+                    ),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Request for PATCH /api/v1/swaps/:id/refund-address
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SetRefundAddressRequest {
+    /// Where the deposit is refunded, on the quote's `from` chain
+    pub user_refund_address: String,
+
+    /// EIP-191 signature of [`refund_address_message`] by the swap's user EVM account
+    pub signature: String,
+}
+
+impl Validate for SetRefundAddressRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        // The address is checked once the swap, and so its chain, is loaded
+        let mut errors = Vec::new();
+        let result = sanitize_signature("signature", &self.signature);
+        apply(&mut errors, &mut self.signature, result);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Message the user EVM account signs to set a swap's refund address
+#[must_use]
+pub fn refund_address_message(swap_id: Uuid, user_refund_address: &str) -> String {
+    format!("Set the refund address of swap {swap_id} to {user_refund_address}")
+}
+
+/// Response after successfully creating a swap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct CreateSwapResponse {
+    /// The newly created swap ID
+    pub swap_id: Uuid,
+
+    /// Deposit address for the user to send funds to
+    pub deposit_address: String,
+
+    /// Chain type for the deposit (Bitcoin/Ethereum)
+    pub deposit_chain: String,
+
+    /// Expected amount to deposit (matches quote.from.amount)
+    #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+    pub expected_amount: U256,
+
+    /// Number of decimals for the amount
+    pub decimals: u8,
+
+    /// Token type (Native or token address)
+    pub token: String,
+
+    /// Confirmations the deposit needs before the swap proceeds
+    pub required_confirmations: u64,
+
+    /// Rough time for the deposit to reach `required_confirmations`
+    pub estimated_confirmation_seconds: u64,
+
+    /// When the swap expires (based on quote expiry)
+    pub expires_at: DateTime<Utc>,
+
+    /// Seconds until `expires_at` as of the response, for a countdown
+    #[serde(default)]
+    pub expires_in_seconds: u64,
+
+    /// Current swap status
+    pub status: String,
+
+    /// BIP-21 or EIP-681 URI paying the expected amount to the deposit address
+    pub payment_uri: String,
+
+    /// SVG QR code of `payment_uri`, only rendered when asked for with `?include_qr=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qr_svg: Option<String>,
+}
+
+/// Query parameters for POST /api/v1/swaps
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "utoipa",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct CreateSwapQuery {
+    #[serde(default)]
+    pub include_qr: bool,
+}
+
+/// Query parameters for GET /api/v1/swaps/lookup, exactly one must be set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "utoipa",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct SwapLookupQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deposit_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+}
+
+/// What a swap lookup matches on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwapLookup {
+    DepositAddress(String),
+    /// Matches the user's or the MM's deposit, given in either chain's form
+    TxHash(TxHash),
+}
+
+impl TryFrom<SwapLookupQuery> for SwapLookup {
+    type Error = &'static str;
+
+    fn try_from(query: SwapLookupQuery) -> Result<Self, Self::Error> {
+        let non_empty = |value: Option<String>| value.filter(|v| !v.trim().is_empty());
+        match (non_empty(query.deposit_address), non_empty(query.tx_hash)) {
+            (Some(address), None) => Ok(Self::DepositAddress(address)),
+            (None, Some(tx_hash)) => tx_hash
+                .parse()
+                .map(Self::TxHash)
+                .map_err(|_| "tx_hash must be 64 hex characters, with or without 0x"),
+            _ => Err("Exactly one of deposit_address or tx_hash is required"),
+        }
+    }
+}
+
+impl From<SwapLookup> for SwapLookupQuery {
+    fn from(lookup: SwapLookup) -> Self {
+        match lookup {
+            SwapLookup::DepositAddress(address) => Self {
+                deposit_address: Some(address),
+                ..Self::default()
+            },
+            SwapLookup::TxHash(tx_hash) => Self {
+                tx_hash: Some(tx_hash.to_string()),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+/// Response for GET /swaps/:id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SwapResponse {
+    pub id: Uuid,
+    pub quote_id: Uuid,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+
+    /// User's deposit information
+    pub user_deposit: DepositInfoResponse,
+
+    /// Market maker's deposit information  
+    pub mm_deposit: DepositInfoResponse,
+
+    /// Where the user deposit is refunded, `None` until the user sets it
+    #[serde(default)]
+    pub user_refund_address: Option<String>,
+
+    /// A user refund is waiting for the user to set a refund address
+    #[serde(default)]
+    pub refund_address_required: bool,
+
+    /// Sweep of the user deposit to the market maker, once broadcast
+    #[serde(default)]
+    pub settlement: Option<SettlementProgress>,
+
+    /// The reference the user gave when creating the swap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_reference: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct DepositInfoResponse {
+    pub address: String,
+    pub chain: String,
+    #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+    pub expected_amount: U256,
+    pub decimals: u8,
+    pub token: String,
+
+    /// Confirmations required before this deposit is considered final
+    pub required_confirmations: u64,
+
+    /// Actual deposit info if detected
+    pub deposit_tx: Option<String>,
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<otc_models::U256Schema>))]
+    pub deposit_amount: Option<U256>,
+    pub deposit_detected_at: Option<DateTime<Utc>>,
+
+    /// Confirmation progress of the deposit, once detected
+    #[serde(default)]
+    pub detected: Option<DepositProgress>,
+}
+
+/// A deposit seen on chain and how far it is from final
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct DepositProgress {
+    pub tx_hash: String,
+    /// Amount deposited in the token's smallest unit, as a decimal string
+    pub amount: String,
+    /// Decimals of the token `amount` is counted in
+    pub decimals: u8,
+    pub confirmations: u64,
+    /// Same as the deposit's `required_confirmations`
+    pub required_confirmations: u64,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SettlementProgress {
+    pub tx_hash: String,
+    pub broadcast_at: DateTime<Utc>,
+    pub confirmations: u64,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Network fee in the smallest unit of the chain's native token, as a decimal string
+    pub fee: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TX_HASH: &str = "0x88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63";
+
+    #[test]
+    fn test_swap_lookup_needs_exactly_one_field() {
+        let query = |deposit_address: Option<&str>, tx_hash: Option<&str>| SwapLookupQuery {
+            deposit_address: deposit_address.map(str::to_string),
+            tx_hash: tx_hash.map(str::to_string),
+        };
+
+        assert_eq!(
+            SwapLookup::try_from(query(Some("bc1qaddress"), None)),
+            Ok(SwapLookup::DepositAddress("bc1qaddress".to_string()))
+        );
+        assert_eq!(
+            SwapLookup::try_from(query(Some(" "), Some(TX_HASH))),
+            Ok(SwapLookup::TxHash(TX_HASH.parse().unwrap()))
+        );
+        // Either form finds the same deposit, anything else isn't a hash
+        assert_eq!(
+            SwapLookup::try_from(query(None, Some(&TX_HASH[2..].to_uppercase()))),
+            Ok(SwapLookup::TxHash(TX_HASH.parse().unwrap()))
+        );
+        assert!(SwapLookup::try_from(query(None, Some("abcd"))).is_err());
+        assert!(SwapLookup::try_from(query(None, None)).is_err());
+        assert!(SwapLookup::try_from(query(Some("bc1qaddress"), Some(TX_HASH))).is_err());
+    }
+
+    #[test]
+    fn test_swap_lookup_round_trips_through_its_query() {
+        for lookup in [
+            SwapLookup::DepositAddress("bc1qaddress".to_string()),
+            SwapLookup::TxHash(TX_HASH.parse().unwrap()),
+        ] {
+            let query = SwapLookupQuery::from(lookup.clone());
+            assert_eq!(SwapLookup::try_from(query), Ok(lookup));
+        }
+    }
+}
@@ -0,0 +1,16 @@
+/// Header carrying a request's correlation id, echoed in every response
+pub const TRACE_ID_HEADER: &str = "x-request-id";
+
+/// Longest id accepted from a caller
+pub const MAX_TRACE_ID_LEN: usize = 128;
+
+/// Whether `id` is safe to log and pass along: 1 to `MAX_TRACE_ID_LEN`
+/// alphanumeric, `-`, `_`, `.` or `:` characters
+#[must_use]
+pub fn is_valid_trace_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_TRACE_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
@@ -1,4 +1,12 @@
-use otc_models::ApiKey;
+pub mod reload;
+
+use argon2::{
+    password_hash::{PasswordHasher, SaltString},
+    Argon2, Params, Version,
+};
+use chrono::{DateTime, Utc};
+use otc_models::{ApiKey, ApiKeyScope};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
 use snafu::{prelude::*, Whatever};
 use std::{collections::HashMap, path::PathBuf};
 use uuid::Uuid;
@@ -16,10 +24,62 @@ pub enum AuthError {
 
     #[snafu(display("Invalid API key for ID '{}'", id))]
     InvalidApiKeyForId { id: Uuid },
+
+    #[snafu(display(
+        "API key '{}' for market maker '{}' was revoked at {}",
+        id,
+        market_maker,
+        revoked_at
+    ))]
+    Revoked {
+        id: Uuid,
+        market_maker: String,
+        revoked_at: DateTime<Utc>,
+    },
+
+    #[snafu(display(
+        "API key '{}' for market maker '{}' lacks the {} scope",
+        id,
+        market_maker,
+        scope
+    ))]
+    MissingScope {
+        id: Uuid,
+        market_maker: String,
+        scope: ApiKeyScope,
+    },
+
+    #[snafu(display("Failed to hash API key: {}", message))]
+    HashFailed { message: String },
 }
 
 type Result<T, E = AuthError> = std::result::Result<T, E>;
 
+/// A key that passed validation, with what it may be used for
+#[derive(Debug, Clone)]
+pub struct ValidatedApiKey {
+    pub id: Uuid,
+    pub market_maker: String,
+    pub scopes: Vec<ApiKeyScope>,
+    /// Hash the secret was checked against, a rotation replaces it
+    hash: String,
+}
+
+impl ValidatedApiKey {
+    /// Fail with [`AuthError::MissingScope`] unless the key has `scope`
+    pub fn require_scope(self, scope: ApiKeyScope) -> Result<Self> {
+        ensure!(
+            self.scopes.contains(&scope),
+            MissingScopeSnafu {
+                id: self.id,
+                market_maker: self.market_maker,
+                scope,
+            }
+        );
+        Ok(self)
+    }
+}
+
 /// API key store that loads keys from a JSON file
 pub struct ApiKeyStore {
     keys: HashMap<String, ApiKey>,
@@ -34,9 +94,15 @@ impl ApiKeyStore {
                 "Failed to read whitelist file {}",
                 whitelist_file_path.display()
             ))?;
-        let api_keys: Vec<ApiKey> = serde_json::from_str(&api_keys_file).whatever_context(
-            format!("Invalid whitelist file {}", whitelist_file_path.display()),
-        )?;
+        Self::from_json(&api_keys_file).whatever_context(format!(
+            "Invalid whitelist file {}",
+            whitelist_file_path.display()
+        ))
+    }
+
+    /// Create an API key store from the contents of a whitelist file
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let api_keys: Vec<ApiKey> = serde_json::from_str(json)?;
 
         let mut keys = HashMap::new();
         let mut keys_by_id = HashMap::new();
@@ -55,6 +121,7 @@ impl ApiKeyStore {
             .keys
             .get(market_maker)
             .context(MarketMakerNotFoundSnafu { market_maker })?;
+        ensure_not_revoked(stored_key)?;
 
         if stored_key.verify(api_key) {
             Ok(())
@@ -71,15 +138,21 @@ impl ApiKeyStore {
         self.keys.contains_key(market_maker)
     }
 
-    /// Validate an API key by UUID and return the market maker name
-    pub fn validate_by_id(&self, id: &Uuid, api_key: &str) -> Result<String> {
+    /// Validate an API key by UUID and return the market maker name and the key's scopes
+    pub fn validate_by_id(&self, id: &Uuid, api_key: &str) -> Result<ValidatedApiKey> {
         let stored_key = self
             .keys_by_id
             .get(id)
             .context(ApiKeyIdNotFoundSnafu { id: *id })?;
+        ensure_not_revoked(stored_key)?;
 
         if stored_key.verify(api_key) {
-            Ok(stored_key.market_maker.clone())
+            Ok(ValidatedApiKey {
+                id: *id,
+                market_maker: stored_key.market_maker.clone(),
+                scopes: stored_key.scopes.clone(),
+                hash: stored_key.hash.clone(),
+            })
         } else {
             Err(AuthError::InvalidApiKeyForId { id: *id })
         }
@@ -90,6 +163,73 @@ impl ApiKeyStore {
     pub fn get_by_id(&self, id: &Uuid) -> Option<&ApiKey> {
         self.keys_by_id.get(id)
     }
+
+    /// Whether `key` would still pass validation with the secret it passed
+    /// with before, false once it's removed, revoked, rotated or loses a scope
+    #[must_use]
+    pub fn still_accepts(&self, key: &ValidatedApiKey) -> bool {
+        self.keys_by_id.get(&key.id).is_some_and(|stored| {
+            !stored.is_revoked()
+                && stored.hash == key.hash
+                && key.scopes.iter().all(|scope| stored.has_scope(*scope))
+        })
+    }
+}
+
+/// A new random API key secret
+#[must_use]
+pub fn generate_api_key_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// PHC string of `api_key`, what the whitelist stores in place of the secret
+pub fn hash_api_key(api_key: &str) -> Result<String> {
+    // OWASP recommended settings: m=19456 (19 MiB), t=2, p=1
+    let params = Params::new(19456, 2, 1, None).map_err(|e| AuthError::HashFailed {
+        message: e.to_string(),
+    })?;
+
+    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params);
+    let salt = SaltString::generate(&mut OsRng);
+
+    let hash = argon2
+        .hash_password(api_key.as_bytes(), &salt)
+        .map_err(|e| AuthError::HashFailed {
+            message: e.to_string(),
+        })?;
+
+    Ok(hash.to_string())
+}
+
+/// A new key for `market_maker` with `scopes`, and the secret it
+/// authenticates with, which is shown once and never stored
+pub fn generate_api_key(market_maker: &str, scopes: Vec<ApiKeyScope>) -> Result<(ApiKey, String)> {
+    let secret = generate_api_key_secret();
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        market_maker: market_maker.to_string(),
+        hash: hash_api_key(&secret)?,
+        revoked_at: None,
+        scopes,
+    };
+    Ok((api_key, secret))
+}
+
+/// Revoked keys are rejected before their hash is checked
+fn ensure_not_revoked(key: &ApiKey) -> Result<()> {
+    match key.revoked_at {
+        Some(revoked_at) => RevokedSnafu {
+            id: key.id,
+            market_maker: key.market_maker.clone(),
+            revoked_at,
+        }
+        .fail(),
+        None => Ok(()),
+    }
 }
 
 #[cfg(test)]
@@ -99,6 +239,24 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
 
+    #[tokio::test]
+    async fn test_generated_key_validates_with_its_secret() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("whitelist.json");
+
+        let (api_key, secret) = generate_api_key("new_mm", vec![ApiKeyScope::Rfq]).unwrap();
+        assert!(!api_key.hash.contains(&secret));
+        fs::write(&file_path, serde_json::to_string(&[&api_key]).unwrap()).unwrap();
+
+        let store = ApiKeyStore::new(file_path).await.unwrap();
+        let validated = store.validate_by_id(&api_key.id, &secret).unwrap();
+        assert_eq!(validated.market_maker, "new_mm");
+        assert_eq!(validated.scopes, vec![ApiKeyScope::Rfq]);
+        assert!(store
+            .validate_by_id(&api_key.id, &generate_api_key_secret())
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_api_key_store() {
         let dir = tempdir().unwrap();
@@ -108,6 +266,8 @@ mod tests {
             id: Uuid::new_v4(),
             market_maker: "test_mm".to_string(),
             hash: "$argon2id$v=19$m=19456,t=2,p=1$test_salt$test_hash".to_string(),
+            revoked_at: None,
+            scopes: ApiKeyScope::ALL.to_vec(),
         }];
 
         fs::write(&file_path, serde_json::to_string(&api_keys).unwrap()).unwrap();
@@ -116,4 +276,99 @@ mod tests {
         assert!(store.contains_market_maker("test_mm"));
         assert!(!store.contains_market_maker("unknown_mm"));
     }
+
+    #[tokio::test]
+    async fn test_api_key_store_rejects_revoked_key() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("whitelist.json");
+
+        let id = Uuid::new_v4();
+        let api_keys = vec![ApiKey {
+            id,
+            market_maker: "revoked_mm".to_string(),
+            hash: "$argon2id$v=19$m=19456,t=2,p=1$test_salt$test_hash".to_string(),
+            revoked_at: Some(Utc::now()),
+            scopes: ApiKeyScope::ALL.to_vec(),
+        }];
+
+        fs::write(&file_path, serde_json::to_string(&api_keys).unwrap()).unwrap();
+
+        let store = ApiKeyStore::new(file_path).await.unwrap();
+        assert!(matches!(
+            store.validate_by_id(&id, "any_key"),
+            Err(AuthError::Revoked { id: revoked_id, .. }) if revoked_id == id
+        ));
+        assert!(matches!(
+            store.validate("revoked_mm", "any_key"),
+            Err(AuthError::Revoked { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_whitelist_without_scopes_grants_every_scope() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("whitelist.json");
+        let id = Uuid::new_v4();
+        fs::write(
+            &file_path,
+            format!(
+                r#"[{{"id": "{id}", "market_maker": "old_mm", "hash": "$argon2id$v=19$m=19456,t=2,p=1$test_salt$test_hash"}}]"#
+            ),
+        )
+        .unwrap();
+
+        let store = ApiKeyStore::new(file_path).await.unwrap();
+        assert_eq!(store.get_by_id(&id).unwrap().scopes, ApiKeyScope::ALL);
+    }
+
+    #[test]
+    fn test_still_accepts_until_removed_revoked_or_rotated() {
+        let (api_key, secret) = generate_api_key("mm", ApiKeyScope::ALL.to_vec()).unwrap();
+        let store_with = |api_keys: &[&ApiKey]| {
+            ApiKeyStore::from_json(&serde_json::to_string(api_keys).unwrap()).unwrap()
+        };
+        let validated = store_with(&[&api_key])
+            .validate_by_id(&api_key.id, &secret)
+            .unwrap();
+
+        assert!(store_with(&[&api_key]).still_accepts(&validated));
+        assert!(!store_with(&[]).still_accepts(&validated));
+
+        let revoked = ApiKey {
+            revoked_at: Some(Utc::now()),
+            ..api_key.clone()
+        };
+        assert!(!store_with(&[&revoked]).still_accepts(&validated));
+
+        let rotated = ApiKey {
+            hash: hash_api_key(&generate_api_key_secret()).unwrap(),
+            ..api_key.clone()
+        };
+        assert!(!store_with(&[&rotated]).still_accepts(&validated));
+
+        let narrowed = ApiKey {
+            scopes: vec![ApiKeyScope::Rfq],
+            ..api_key
+        };
+        assert!(!store_with(&[&narrowed]).still_accepts(&validated));
+    }
+
+    #[test]
+    fn test_require_scope() {
+        let key = ValidatedApiKey {
+            id: Uuid::new_v4(),
+            market_maker: "rfq_only_mm".to_string(),
+            scopes: vec![ApiKeyScope::Rfq],
+            hash: String::new(),
+        };
+
+        assert!(key.clone().require_scope(ApiKeyScope::Rfq).is_ok());
+        assert!(matches!(
+            key.require_scope(ApiKeyScope::Otc),
+            Err(AuthError::MissingScope {
+                scope: ApiKeyScope::Otc,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,94 @@
+//! Picks up edits to the API keys file while a server runs, so a key removed,
+//! revoked or rotated with api-key-manager stops working without a restart
+
+use crate::ApiKeyStore;
+use common::Shutdown;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{info, warn};
+
+/// Re-reads `path` every `interval` and publishes the keys when its contents
+/// change. A file that fails to read or parse keeps the current keys, so a
+/// half-written edit can't lock every market maker out
+pub async fn reload_api_keys(
+    path: PathBuf,
+    interval: Duration,
+    api_keys: watch::Sender<Arc<ApiKeyStore>>,
+    shutdown: Shutdown,
+) {
+    let mut last_contents = tokio::fs::read_to_string(&path).await.ok();
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = shutdown.triggered() => return,
+        }
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Failed to read API keys file {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if last_contents.as_ref() == Some(&contents) {
+            continue;
+        }
+        match ApiKeyStore::from_json(&contents) {
+            Ok(store) => {
+                info!("Reloaded API keys from {}", path.display());
+                api_keys.send_replace(Arc::new(store));
+                last_contents = Some(contents);
+            }
+            Err(e) => warn!(
+                "Keeping the current API keys, {} is invalid: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otc_models::ApiKeyScope;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_publishes_edits_and_ignores_invalid_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("whitelist.json");
+        let (first, _) = crate::generate_api_key("mm_a", ApiKeyScope::ALL.to_vec()).unwrap();
+        let (second, _) = crate::generate_api_key("mm_b", ApiKeyScope::ALL.to_vec()).unwrap();
+        std::fs::write(&path, serde_json::to_string(&[&first]).unwrap()).unwrap();
+
+        let (sender, mut receiver) =
+            watch::channel(Arc::new(ApiKeyStore::new(path.clone()).await.unwrap()));
+        let shutdown = Shutdown::new();
+        let task = tokio::spawn(reload_api_keys(
+            path.clone(),
+            Duration::from_millis(20),
+            sender,
+            shutdown.clone(),
+        ));
+        receiver.mark_unchanged();
+
+        std::fs::write(&path, "[{\"id\": ").unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(!receiver.has_changed().unwrap());
+        assert!(receiver.borrow().get_by_id(&first.id).is_some());
+
+        std::fs::write(&path, serde_json::to_string(&[&second]).unwrap()).unwrap();
+        receiver.changed().await.unwrap();
+        assert!(receiver.borrow().get_by_id(&first.id).is_none());
+        assert!(receiver.borrow().get_by_id(&second.id).is_some());
+
+        shutdown.trigger();
+        task.await.unwrap();
+    }
+}
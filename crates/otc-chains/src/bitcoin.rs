@@ -1,49 +1,209 @@
-use crate::traits::MarketMakerPaymentValidation;
-use crate::{key_derivation, ChainOperations, Result};
-use alloy::hex;
+use crate::dust::MAX_BITCOIN_DUST_SATS;
+use crate::traits::{MarketMakerPaymentValidation, NetworkFees};
+use crate::{deposit_key, key_derivation, payment_uri, ChainOperations, Result};
 use alloy::primitives::U256;
 use async_trait::async_trait;
-use bitcoin::secp256k1::{Secp256k1, SecretKey};
-use bitcoin::{Address, Amount, CompressedPublicKey, Network, PrivateKey, Transaction};
-use bitcoincore_rpc_async::{Auth, Client, RpcApi};
-use otc_models::{ChainType, Lot, TransferInfo, TxStatus, Wallet};
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    ecdsa, Address, Amount, CompressedPublicKey, Network, OutPoint, PrivateKey, Script, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use esplora_client::UtxoStatus;
+use otc_models::{
+    ChainType, Currency, Finality, Lot, TokenIdentifier, TransferInfo, TxHash, TxStatus, Wallet,
+};
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, info};
 
+mod data_source;
+
+pub use data_source::{
+    connect_data_source, BitcoinDataSource, CoreRpcDataSource, EsploraDataSource,
+};
+
 const FEE_ADDRESS: &str = "bc1q2p8ms86h3namagp4y486udsv4syydhvqztg886";
 
+/// Transfers out of deposit wallets pay a fee rate for confirmation within
+/// this many blocks, unless configured otherwise
+pub const DEFAULT_FEE_TARGET_BLOCKS: u16 = 6;
+
+/// How far a block's timestamp can sit from when it was mined. Consensus
+/// allows two hours ahead of the network's clock, and behind it by about as
+/// much as it only has to beat the median of the previous eleven blocks
+const BLOCK_TIME_TOLERANCE: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Longest standard output script, a P2TR one. Refunds are costed with it as
+/// the refund address type isn't known up front
+const MAX_OUTPUT_SCRIPT_LEN: usize = 34;
+
+/// Virtual size of a transaction spending `inputs` P2WPKH inputs to a single
+/// output whose script is `script_len` bytes
+fn sweep_vbytes(inputs: usize, script_len: usize) -> u64 {
+    // Version, locktime, input and output counts and the segwit marker, rounded up
+    const OVERHEAD_VBYTES: u64 = 11;
+    // Outpoint, empty script_sig, sequence and the discounted signature and pubkey
+    const P2WPKH_INPUT_VBYTES: u64 = 68;
+    // Value and script length
+    const OUTPUT_OVERHEAD_VBYTES: u64 = 9;
+    OVERHEAD_VBYTES
+        + P2WPKH_INPUT_VBYTES * inputs as u64
+        + OUTPUT_OVERHEAD_VBYTES
+        + script_len as u64
+}
+
+/// Fee rate in sat/vB for confirmation within `target_blocks`, from esplora's
+/// estimates keyed by confirmation target
+fn fee_rate_for_target(estimates: &HashMap<u16, f64>, target_blocks: u16) -> u64 {
+    // No estimate means an empty mempool
+    estimates
+        .iter()
+        .filter(|(blocks, rate)| **blocks <= target_blocks && rate.is_finite())
+        .max_by_key(|(blocks, _)| **blocks)
+        .map_or(1, |(_, rate)| (rate.ceil() as u64).max(1))
+}
+
+/// Fee rate in sat/vB the data source suggests for confirmation within `target_blocks`
+async fn estimate_fee_rate(data_source: &dyn BitcoinDataSource, target_blocks: u16) -> Result<u64> {
+    let estimates = data_source.fee_estimates().await?;
+    Ok(fee_rate_for_target(&estimates, target_blocks))
+}
+
+/// Witnesses for every input of `tx`, each spending a P2WPKH output of
+/// `private_key` worth the matching entry of `amounts`
+fn p2wpkh_witnesses(
+    tx: &Transaction,
+    private_key: &PrivateKey,
+    amounts: &[u64],
+) -> Result<Vec<Witness>> {
+    let secp = Secp256k1::new();
+    let public_key = CompressedPublicKey::from_private_key(&secp, private_key).map_err(|_| {
+        crate::Error::KeyDerivation {
+            message: "Bitcoin deposit keys must be compressed".to_string(),
+        }
+    })?;
+    let script_pubkey = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash());
+    let mut sighashes = SighashCache::new(tx);
+
+    amounts
+        .iter()
+        .enumerate()
+        .map(|(index, amount)| {
+            let sighash = sighashes
+                .p2wpkh_signature_hash(
+                    index,
+                    &script_pubkey,
+                    Amount::from_sat(*amount),
+                    EcdsaSighashType::All,
+                )
+                .map_err(|e| crate::Error::Serialization {
+                    message: format!("Failed to compute sighash: {e}"),
+                })?;
+            let signature = secp.sign_ecdsa(
+                &Message::from_digest(sighash.to_byte_array()),
+                &private_key.inner,
+            );
+            Ok(Witness::p2wpkh(
+                &ecdsa::Signature::sighash_all(signature),
+                &public_key.0,
+            ))
+        })
+        .collect()
+}
+
+/// Bitcoin deposits are only ever made in BTC
+fn ensure_native(token: &TokenIdentifier) -> Result<()> {
+    match token {
+        TokenIdentifier::Native => Ok(()),
+        TokenIdentifier::Address(_) => Err(crate::Error::UnsupportedToken {
+            token: token.clone(),
+            network: ChainType::Bitcoin,
+        }),
+    }
+}
+
+/// OP_RETURN output tagging a market maker payment with its swap's nonce
+#[must_use]
+pub fn mm_nonce_script(nonce: &[u8; 16]) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_RETURN)
+        .push_slice(nonce)
+        .into_script()
+}
+
+/// Why `tx` is not the market maker payment described by `mm_payment`, if it isn't
+///
+/// A payment carries exactly one nonce sized OP_RETURN output, holding the
+/// swap's nonce, and pays at least the fee to `fee_script`. Plain payments to
+/// the same address never match.
+fn mm_payment_mismatch(
+    tx: &Transaction,
+    mm_payment: &MarketMakerPaymentValidation,
+    fee_script: &Script,
+) -> Option<&'static str> {
+    // OP_RETURN (0x6a) + OP_PUSHBYTES_16 (0x10)
+    let mut nonce_outputs = tx
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey.as_bytes().starts_with(&[0x6a, 0x10]));
+    let (Some(nonce_output), None) = (nonce_outputs.next(), nonce_outputs.next()) else {
+        return Some("not a single nonce OP_RETURN output");
+    };
+    if nonce_output.script_pubkey != mm_nonce_script(&mm_payment.embedded_nonce) {
+        return Some("embedded nonce does not match");
+    }
+
+    let fee = Amount::from_sat(mm_payment.fee_amount.to::<u64>());
+    if !tx
+        .output
+        .iter()
+        .any(|output| output.script_pubkey.as_script() == fee_script && output.value >= fee)
+    {
+        return Some("invalid fee amount or fee address");
+    }
+    None
+}
+
+/// Whether an output confirmed in a block timestamped well before the swap's
+/// market maker was notified, so its payment was made for something else
+fn mined_too_early(status: &UtxoStatus, mm_payment: &MarketMakerPaymentValidation) -> bool {
+    status
+        .block_time
+        .filter(|_| status.confirmed)
+        .and_then(|block_time| chrono::DateTime::from_timestamp(block_time as i64, 0))
+        .is_some_and(|block_time| mm_payment.mined_too_early(block_time, BLOCK_TIME_TOLERANCE))
+}
+
 pub struct BitcoinChain {
-    rpc_client: Client,
-    esplora_client: esplora_client::AsyncClient,
+    data_source: Arc<dyn BitcoinDataSource>,
     network: Network,
+    /// Confirmation target fees are estimated for
+    fee_target_blocks: u16,
 }
 
 impl BitcoinChain {
-    /// Auth (if necessary) should be embedded in the bitcoin_core_rpc_url
-    pub async fn new(
-        bitcoin_core_rpc_url: &str,
-        bitcoin_core_rpc_auth: Auth,
-        esplora_url: &str,
-        network: Network,
-    ) -> Result<Self> {
-        let rpc_client = Client::new(bitcoin_core_rpc_url.to_string(), bitcoin_core_rpc_auth)
-            .await
-            .map_err(|_| crate::Error::Rpc {
-                message: "Failed to create Bitcoin RPC client".to_string(),
-            })?;
-
-        let esplora_client = esplora_client::Builder::new(esplora_url)
-            .build_async()
-            .map_err(|_| crate::Error::Rpc {
-                message: "Failed to create Esplora client".to_string(),
-            })?;
-
-        Ok(Self {
-            rpc_client,
-            esplora_client,
+    #[must_use]
+    pub fn new(data_source: Arc<dyn BitcoinDataSource>, network: Network) -> Self {
+        Self {
+            data_source,
             network,
-        })
+            fee_target_blocks: DEFAULT_FEE_TARGET_BLOCKS,
+        }
+    }
+
+    /// Estimate fees for confirmation within `blocks` instead of `DEFAULT_FEE_TARGET_BLOCKS`
+    #[must_use]
+    pub fn with_fee_target_blocks(mut self, blocks: u16) -> Self {
+        self.fee_target_blocks = blocks.max(1);
+        self
     }
 }
 
@@ -57,13 +217,11 @@ impl ChainOperations for BitcoinChain {
         })?;
 
         // Generate a new private key
-        let secp = Secp256k1::new();
         let secret_key = bitcoin::secp256k1::SecretKey::from_slice(&salt).unwrap();
         let private_key = PrivateKey::new(secret_key, self.network);
 
         // Derive public key and address
-        let compressed_pk = CompressedPublicKey::from_private_key(&secp, &private_key).unwrap();
-        let address = Address::p2wpkh(&compressed_pk, self.network);
+        let address = deposit_key::bitcoin_address(&private_key, self.network)?;
 
         info!("Created new Bitcoin wallet: {}", address);
 
@@ -85,9 +243,7 @@ impl ChainOperations for BitcoinChain {
         let private_key = PrivateKey::new(secret_key, self.network);
 
         // Derive public key and address
-        let secp = Secp256k1::new();
-        let compressed_pk = CompressedPublicKey::from_private_key(&secp, &private_key).unwrap();
-        let address = Address::p2wpkh(&compressed_pk, self.network);
+        let address = deposit_key::bitcoin_address(&private_key, self.network)?;
 
         debug!("Derived Bitcoin wallet: {}", address);
 
@@ -95,12 +251,15 @@ impl ChainOperations for BitcoinChain {
     }
 
     async fn get_tx_status(&self, tx_hash: &str) -> Result<TxStatus> {
-        let tx = self
-            .rpc_client
-            .get_raw_transaction_verbose(&bitcoin::Txid::from_str(tx_hash).unwrap())
+        let confirmations = self
+            .data_source
+            .tx_confirmations(&bitcoin::Txid::from_str(tx_hash).unwrap())
             .await?;
-        if tx.confirmations.unwrap_or(0) > 0 {
-            Ok(TxStatus::Confirmed(tx.confirmations.unwrap_or(0)))
+        if confirmations > 0 {
+            Ok(TxStatus::Included {
+                confirmations,
+                finality: Finality::Unknown,
+            })
         } else {
             Ok(TxStatus::NotFound)
         }
@@ -139,6 +298,95 @@ impl ChainOperations for BitcoinChain {
         Ok(transfer_opt)
     }
 
+    async fn get_balance(&self, address: &str, token: &TokenIdentifier) -> Result<U256> {
+        ensure_native(token)?;
+        let address = Address::from_str(address)?.assume_checked();
+        let utxos = self.data_source.address_utxos(&address).await?;
+        Ok(U256::from(utxos.iter().map(|utxo| utxo.value).sum::<u64>()))
+    }
+
+    async fn sweep(
+        &self,
+        wallet: &Wallet,
+        token: &TokenIdentifier,
+        to_address: &str,
+    ) -> Result<String> {
+        ensure_native(token)?;
+        let private_key = PrivateKey::from_wif(wallet.private_key()).map_err(|_| {
+            crate::Error::KeyDerivation {
+                message: "Invalid WIF private key".to_string(),
+            }
+        })?;
+        let from = deposit_key::bitcoin_address(&private_key, self.network)?;
+        let to = Address::from_str(to_address)?
+            .require_network(self.network)
+            .map_err(|e| crate::Error::InvalidAddress {
+                address: to_address.to_string(),
+                network: ChainType::Bitcoin,
+                reason: e.to_string(),
+            })?;
+
+        let utxos = self.data_source.address_utxos(&from).await?;
+        let available: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+        let fee = sweep_vbytes(utxos.len(), to.script_pubkey().len())
+            * estimate_fee_rate(self.data_source.as_ref(), self.fee_target_blocks).await?;
+        if available < fee + MAX_BITCOIN_DUST_SATS {
+            return Err(crate::Error::InsufficientBalance {
+                required: U256::from(fee + MAX_BITCOIN_DUST_SATS),
+                available: U256::from(available),
+            });
+        }
+
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: utxos
+                .iter()
+                .map(|utxo| TxIn {
+                    previous_output: OutPoint::new(utxo.txid, utxo.vout),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(available - fee),
+                script_pubkey: to.script_pubkey(),
+            }],
+        };
+        let amounts: Vec<u64> = utxos.iter().map(|utxo| utxo.value).collect();
+        let witnesses = p2wpkh_witnesses(&tx, &private_key, &amounts)?;
+        for (input, witness) in tx.input.iter_mut().zip(witnesses) {
+            input.witness = witness;
+        }
+
+        self.data_source.broadcast(&tx).await?;
+        let txid = tx.compute_txid();
+        info!(
+            "Swept {} sats from {} to {} in {}",
+            available - fee,
+            from,
+            to,
+            txid
+        );
+        Ok(txid.to_string())
+    }
+
+    async fn estimate_transfer_fee(&self, currency: &Currency) -> Result<U256> {
+        ensure_native(&currency.token)?;
+        let fee_rate = estimate_fee_rate(self.data_source.as_ref(), self.fee_target_blocks).await?;
+        Ok(U256::from(
+            sweep_vbytes(1, MAX_OUTPUT_SCRIPT_LEN) * fee_rate,
+        ))
+    }
+
+    async fn network_fees(&self) -> Result<NetworkFees> {
+        let estimates = self.data_source.fee_estimates().await?;
+        Ok(NetworkFees::Bitcoin {
+            fee_estimates: estimates.into_iter().collect(),
+        })
+    }
+
     fn validate_address(&self, address: &str) -> bool {
         match Address::from_str(address) {
             Ok(addr) => addr.is_valid_for_network(self.network),
@@ -146,6 +394,14 @@ impl ChainOperations for BitcoinChain {
         }
     }
 
+    fn private_key_controls_address(&self, private_key: &str, address: &str) -> Result<bool> {
+        deposit_key::bitcoin_key_controls_address(private_key, address, self.network)
+    }
+
+    fn payment_uri(&self, address: &str, lot: &Lot) -> String {
+        payment_uri::bip21_uri(address, lot)
+    }
+
     fn minimum_block_confirmations(&self) -> u32 {
         2
     }
@@ -156,7 +412,7 @@ impl ChainOperations for BitcoinChain {
 }
 
 impl BitcoinChain {
-    // The output of this function can be trusted as we validate the transfer hint against the rpc client
+    // The output of this function can be trusted as far as the data source can
     async fn get_transfer_hint(
         &self,
         address: &str,
@@ -167,12 +423,23 @@ impl BitcoinChain {
 
         // Called a hint b/c the esplora client CANNOT be trusted to return non-fradulent data (b/c it not intended to run locally)
         // Note that if there are more than 50 utxos available to the address, this could ignore a valid transfer (TODO: how to handle this?)
-        let utxos = self.esplora_client.get_address_utxo(&address).await?;
+        let utxos = self.data_source.address_utxos(&address).await?;
         debug!("UTXOs: {:?}", utxos);
-        let current_block_height = self.rpc_client.get_block_count().await? as u32;
+        let current_block_height = self.data_source.block_height().await? as u32;
+        // MM payments are identified by their nonce, so underpaying ones are returned
+        // too and the caller compares the amount against the quote
+        let min_amount = if mm_payment.is_some() {
+            0
+        } else {
+            amount.to::<u64>()
+        };
+        let fee_script =
+            Address::from_str(&otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Bitcoin])?
+                .assume_checked()
+                .script_pubkey();
         let mut most_confirmed_transfer: Option<TransferInfo> = None;
         for utxo in utxos {
-            if utxo.value < amount.to::<u64>() {
+            if utxo.value < min_amount {
                 continue;
             }
             // TODO: the height of the utxo should be validated against the rpc client
@@ -189,78 +456,30 @@ impl BitcoinChain {
             // At this point, we either have a new candidate that's more confirmed than the current candidate
             // as let's finally validate that it's the correct transfer
             if let Some(mm_payment) = &mm_payment {
-                // we only need to do this check if the embedded nonce is a requirement
-                let embedded_nonce = mm_payment.embedded_nonce;
-                // TODO: Use rpc client instead of esplora so we dont have to implement validate logic twice
-                let tx_hex = self
-                    .rpc_client
-                    .get_raw_transaction_hex(&utxo.txid, None)
-                    .await;
-
-                if tx_hex.is_err() {
-                    info!(
-                        message = "Failed to get raw transaction, skipping",
-                        tx_hash = utxo.txid.to_string()
-                    );
-                    continue;
-                }
-                let tx_hex = tx_hex.unwrap();
-                let tx_bytes = hex::decode(&tx_hex);
-                if tx_bytes.is_err() {
-                    info!(
-                        message = "Failed to decode raw transaction, skipping",
-                        tx_hash = utxo.txid.to_string()
-                    );
-                    continue;
-                }
-                let tx_bytes = tx_bytes.unwrap();
-                let tx = bitcoin::consensus::deserialize::<Transaction>(&tx_bytes).unwrap();
-
-                // Each tx can only have one of the following prefixed script pubkeys
-                // [OP_RETURN (0x6a) + OP_PUSHBYTES_16 (0x10)]
-                if tx
-                    .output
-                    .iter()
-                    .filter(|output| output.script_pubkey.to_bytes().starts_with(&[0x6a, 0x10]))
-                    .count()
-                    != 1
-                {
-                    // Either not a mm payment OR invalid payment that has multiple OP_RETURN outputs
+                if mined_too_early(&utxo.status, mm_payment) {
                     info!(
-                        message = "Invalid mm payment, either not a mm payment or invalid payment that has multiple OP_RETURN outputs",
+                        message = "MM payment mined before the swap was notified, skipping",
                         tx_hash = utxo.txid.to_string()
                     );
                     continue;
                 }
+                // we only need to do this check if the embedded nonce is a requirement
+                let tx = match self.data_source.get_transaction(&utxo.txid).await {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        info!(
+                            message = "Failed to get transaction, skipping",
+                            tx_hash = utxo.txid.to_string(),
+                            error = %e
+                        );
+                        continue;
+                    }
+                };
 
-                let mut needle = vec![0x6a, 0x10];
-                needle.extend_from_slice(&embedded_nonce);
-
-                if !tx
-                    .output
-                    .iter()
-                    .any(|output| output.script_pubkey.to_bytes() == needle)
-                {
-                    // The embedded nonce is not in the OP_RETURN output
+                if let Some(reason) = mm_payment_mismatch(&tx, mm_payment, &fee_script) {
                     info!(
-                        message =
-                            "Invalid mm payment, embedded nonce is not in the OP_RETURN output",
-                        tx_hash = utxo.txid.to_string()
-                    );
-                    continue;
-                }
-                // finally validate fee
-                let fee = mm_payment.fee_amount;
-                let fee_address =
-                    Address::from_str(&otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Bitcoin])?
-                        .assume_checked();
-                if !tx.output.iter().any(|output| {
-                    output.script_pubkey == fee_address.script_pubkey()
-                        && output.value >= Amount::from_sat(fee.to::<u64>())
-                }) {
-                    // The fee is not in the OP_RETURN output
-                    info!(
-                        message = "Invalid mm payment, invalid fee amount or fee address",
+                        message = "Invalid mm payment, skipping",
+                        reason,
                         tx_hash = utxo.txid.to_string()
                     );
                     continue;
@@ -268,8 +487,12 @@ impl BitcoinChain {
             }
             // At this point, our new candidate is valid and the most confirmed transfer we've seen
             // so let's return it
+            let tx_hash = TxHash::parse(ChainType::Bitcoin, &utxo.txid.to_string())
+                .map_err(|e| crate::Error::Serialization {
+                    message: e.to_string(),
+                })?;
             most_confirmed_transfer = Some(TransferInfo {
-                tx_hash: utxo.txid.to_string(),
+                tx_hash,
                 amount: U256::from(utxo.value),
                 detected_at: chrono::Utc::now(),
                 confirmations: cur_utxo_confirmations as u64,
@@ -278,3 +501,183 @@ impl BitcoinChain {
         Ok(most_confirmed_transfer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONCE: [u8; 16] = [0xab; 16];
+
+    fn fee_script() -> ScriptBuf {
+        Address::from_str(&otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Bitcoin])
+            .unwrap()
+            .assume_checked()
+            .script_pubkey()
+    }
+
+    fn validation() -> MarketMakerPaymentValidation {
+        MarketMakerPaymentValidation {
+            fee_amount: U256::from(300),
+            embedded_nonce: NONCE,
+            not_before: None,
+        }
+    }
+
+    fn tx(outputs: Vec<(ScriptBuf, u64)>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, sats)| TxOut {
+                    value: Amount::from_sat(sats),
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    /// P2WPKH output paying the user
+    fn recipient() -> ScriptBuf {
+        ScriptBuf::from_bytes([&[0x00, 0x14][..], &[0u8; 20]].concat())
+    }
+
+    #[test]
+    fn test_tagged_payment_matches() {
+        let tx = tx(vec![
+            (recipient(), 10_000),
+            (mm_nonce_script(&NONCE), 0),
+            (fee_script(), 300),
+        ]);
+        assert_eq!(mm_payment_mismatch(&tx, &validation(), &fee_script()), None);
+    }
+
+    #[test]
+    fn test_untagged_or_mistagged_payment_is_rejected() {
+        let untagged = tx(vec![(recipient(), 10_000), (fee_script(), 300)]);
+        assert!(mm_payment_mismatch(&untagged, &validation(), &fee_script()).is_some());
+
+        let other_nonce = tx(vec![
+            (recipient(), 10_000),
+            (mm_nonce_script(&[0xcd; 16]), 0),
+            (fee_script(), 300),
+        ]);
+        assert!(mm_payment_mismatch(&other_nonce, &validation(), &fee_script()).is_some());
+
+        // A second nonce output makes the payment ambiguous
+        let doubly_tagged = tx(vec![
+            (recipient(), 10_000),
+            (mm_nonce_script(&NONCE), 0),
+            (mm_nonce_script(&[0xcd; 16]), 0),
+            (fee_script(), 300),
+        ]);
+        assert!(mm_payment_mismatch(&doubly_tagged, &validation(), &fee_script()).is_some());
+    }
+
+    #[test]
+    fn test_underpaid_fee_is_rejected() {
+        let tx = tx(vec![
+            (recipient(), 10_000),
+            (mm_nonce_script(&NONCE), 0),
+            (fee_script(), 299),
+        ]);
+        assert!(mm_payment_mismatch(&tx, &validation(), &fee_script()).is_some());
+    }
+
+    #[test]
+    fn test_payment_mined_well_before_notification_is_rejected() {
+        let notified_at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mm_payment = MarketMakerPaymentValidation {
+            not_before: Some(notified_at),
+            ..validation()
+        };
+        let mined_at = |offset: i64| UtxoStatus {
+            confirmed: true,
+            block_height: Some(100),
+            block_hash: None,
+            block_time: Some((notified_at.timestamp() + offset) as u64),
+        };
+
+        // Block timestamps lag by up to the tolerance
+        assert!(!mined_too_early(&mined_at(-60 * 60), &mm_payment));
+        assert!(mined_too_early(&mined_at(-3 * 60 * 60), &mm_payment));
+
+        let unconfirmed = UtxoStatus {
+            confirmed: false,
+            block_height: None,
+            block_hash: None,
+            block_time: None,
+        };
+        assert!(!mined_too_early(&unconfirmed, &mm_payment));
+        assert!(!mined_too_early(&mined_at(-3 * 60 * 60), &validation()));
+    }
+
+    /// Esplora stand-in answering its next requests with `bodies`, in order
+    async fn mock_esplora(bodies: &[&'static str]) -> EsploraDataSource {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let bodies = bodies.to_vec();
+        tokio::spawn(async move {
+            for body in bodies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        EsploraDataSource::new(&url).unwrap()
+    }
+
+    #[test]
+    fn test_fee_rate_uses_the_closest_target_within_reach() {
+        let estimates = HashMap::from([(1, 20.5), (3, 12.0), (6, 8.2), (144, 1.0)]);
+        assert_eq!(fee_rate_for_target(&estimates, 6), 9);
+        assert_eq!(fee_rate_for_target(&estimates, 5), 12);
+        assert_eq!(fee_rate_for_target(&estimates, 1), 21);
+        // An empty mempool has no estimates
+        assert_eq!(fee_rate_for_target(&HashMap::new(), 6), 1);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_fee_from_esplora_estimates() {
+        let esplora = mock_esplora(&[r#"{"1": 25.0, "2": 20.0, "6": 10.0, "144": 1.0}"#]).await;
+        let fee_rate = estimate_fee_rate(&esplora, DEFAULT_FEE_TARGET_BLOCKS)
+            .await
+            .unwrap();
+        assert_eq!(fee_rate, 10);
+
+        // One P2WPKH input paying a P2TR output
+        let vbytes = sweep_vbytes(1, MAX_OUTPUT_SCRIPT_LEN);
+        assert_eq!(vbytes, 122);
+        assert_eq!(vbytes * fee_rate, 1_220);
+    }
+
+    #[tokio::test]
+    async fn test_network_fees_are_esplora_estimates() {
+        let esplora = mock_esplora(&[r#"{"1": 20.5, "6": 8.2, "144": 1.0}"#]).await;
+        let chain = BitcoinChain::new(Arc::new(esplora), Network::Regtest);
+        assert_eq!(
+            chain.network_fees().await.unwrap(),
+            NetworkFees::Bitcoin {
+                fee_estimates: [(1, 20.5), (6, 8.2), (144, 1.0)].into_iter().collect(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_esplora_confirmations_count_the_including_block() {
+        let txid = bitcoin::Txid::from_byte_array([0x11; 32]);
+        let esplora = mock_esplora(&[r#"{"confirmed": true, "block_height": 100}"#, "102"]).await;
+        assert_eq!(esplora.tx_confirmations(&txid).await.unwrap(), 3);
+
+        let esplora = mock_esplora(&[r#"{"confirmed": false}"#]).await;
+        assert_eq!(esplora.tx_confirmations(&txid).await.unwrap(), 0);
+    }
+}
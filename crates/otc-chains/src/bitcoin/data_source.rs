@@ -0,0 +1,161 @@
+//! Where `BitcoinChain` reads chain state from and broadcasts through. Either
+//! a Bitcoin Core node backed by esplora, or esplora alone
+
+use crate::Result;
+use alloy::hex;
+use async_trait::async_trait;
+use bitcoin::{Address, Transaction, Txid};
+use bitcoincore_rpc_async::{Auth, Client, RpcApi};
+use esplora_client::Utxo;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[async_trait]
+pub trait BitcoinDataSource: Send + Sync {
+    /// Height of the chain tip
+    async fn block_height(&self) -> Result<u64>;
+
+    /// Confirmations of `txid`, zero while it is unconfirmed
+    async fn tx_confirmations(&self, txid: &Txid) -> Result<u64>;
+
+    async fn get_transaction(&self, txid: &Txid) -> Result<Transaction>;
+
+    /// Unspent outputs paying `address`, at most 50 of them
+    async fn address_utxos(&self, address: &Address) -> Result<Vec<Utxo>>;
+
+    /// Fee rates in sat/vB keyed by confirmation target in blocks
+    async fn fee_estimates(&self) -> Result<HashMap<u16, f64>>;
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<()>;
+}
+
+/// The Core RPC node when one is configured, esplora alone otherwise
+pub async fn connect_data_source(
+    esplora_url: &str,
+    core_rpc: Option<(&str, Auth)>,
+) -> Result<Arc<dyn BitcoinDataSource>> {
+    let esplora = EsploraDataSource::new(esplora_url)?;
+    Ok(match core_rpc {
+        Some((rpc_url, auth)) => Arc::new(CoreRpcDataSource::new(rpc_url, auth, esplora).await?),
+        None => Arc::new(esplora),
+    })
+}
+
+/// Everything from an esplora server, transactions are broadcast with POST /tx
+pub struct EsploraDataSource {
+    client: esplora_client::AsyncClient,
+}
+
+impl EsploraDataSource {
+    pub fn new(esplora_url: &str) -> Result<Self> {
+        let client = esplora_client::Builder::new(esplora_url)
+            .build_async()
+            .map_err(|_| crate::Error::Rpc {
+                message: "Failed to create Esplora client".to_string(),
+            })?;
+        Ok(Self::from_client(client))
+    }
+
+    #[must_use]
+    pub fn from_client(client: esplora_client::AsyncClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl BitcoinDataSource for EsploraDataSource {
+    async fn block_height(&self) -> Result<u64> {
+        Ok(u64::from(self.client.get_height().await?))
+    }
+
+    async fn tx_confirmations(&self, txid: &Txid) -> Result<u64> {
+        let status = self.client.get_tx_status(txid).await?;
+        let Some(block_height) = status.block_height.filter(|_| status.confirmed) else {
+            return Ok(0);
+        };
+        let tip_height = self.block_height().await?;
+        // Core counts the including block as the first confirmation
+        Ok((tip_height + 1).saturating_sub(u64::from(block_height)))
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        self.client
+            .get_tx(txid)
+            .await?
+            .ok_or_else(|| crate::Error::TransactionNotFound {
+                tx_hash: txid.to_string(),
+            })
+    }
+
+    async fn address_utxos(&self, address: &Address) -> Result<Vec<Utxo>> {
+        Ok(self.client.get_address_utxo(address).await?)
+    }
+
+    async fn fee_estimates(&self) -> Result<HashMap<u16, f64>> {
+        Ok(self.client.get_fee_estimates().await?)
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<()> {
+        Ok(self.client.broadcast(tx).await?)
+    }
+}
+
+/// Transactions and the tip from a Bitcoin Core node, address lookups, fee
+/// estimates and broadcasts from esplora
+pub struct CoreRpcDataSource {
+    rpc_client: Client,
+    esplora: EsploraDataSource,
+}
+
+impl CoreRpcDataSource {
+    /// Auth (if necessary) should be embedded in the bitcoin_core_rpc_url
+    pub async fn new(
+        bitcoin_core_rpc_url: &str,
+        bitcoin_core_rpc_auth: Auth,
+        esplora: EsploraDataSource,
+    ) -> Result<Self> {
+        let rpc_client = Client::new(bitcoin_core_rpc_url.to_string(), bitcoin_core_rpc_auth)
+            .await
+            .map_err(|_| crate::Error::Rpc {
+                message: "Failed to create Bitcoin RPC client".to_string(),
+            })?;
+        Ok(Self {
+            rpc_client,
+            esplora,
+        })
+    }
+}
+
+#[async_trait]
+impl BitcoinDataSource for CoreRpcDataSource {
+    async fn block_height(&self) -> Result<u64> {
+        Ok(self.rpc_client.get_block_count().await?)
+    }
+
+    async fn tx_confirmations(&self, txid: &Txid) -> Result<u64> {
+        let tx = self.rpc_client.get_raw_transaction_verbose(txid).await?;
+        Ok(tx.confirmations.unwrap_or(0))
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        let tx_hex = self.rpc_client.get_raw_transaction_hex(txid, None).await?;
+        let tx_bytes = hex::decode(&tx_hex).map_err(|e| crate::Error::Serialization {
+            message: format!("Invalid raw transaction hex for {txid}: {e}"),
+        })?;
+        bitcoin::consensus::deserialize(&tx_bytes).map_err(|e| crate::Error::Serialization {
+            message: format!("Invalid raw transaction {txid}: {e}"),
+        })
+    }
+
+    async fn address_utxos(&self, address: &Address) -> Result<Vec<Utxo>> {
+        self.esplora.address_utxos(address).await
+    }
+
+    async fn fee_estimates(&self) -> Result<HashMap<u16, f64>> {
+        self.esplora.fee_estimates().await
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<()> {
+        self.esplora.broadcast(tx).await
+    }
+}
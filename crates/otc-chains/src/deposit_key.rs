@@ -0,0 +1,202 @@
+//! Addresses controlled by deposit keys
+//!
+//! The server uses these when deriving deposit wallets, and market makers use them
+//! to check that the key released at settlement controls the deposit address.
+
+use crate::{Error, Result};
+use alloy::signers::local::PrivateKeySigner;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{CompressedPublicKey, Network, PrivateKey};
+use otc_models::ChainType;
+use std::str::FromStr;
+
+/// P2WPKH address of a Bitcoin key
+pub fn bitcoin_address(private_key: &PrivateKey, network: Network) -> Result<bitcoin::Address> {
+    let secp = Secp256k1::new();
+    let compressed_pk =
+        CompressedPublicKey::from_private_key(&secp, private_key).map_err(|_| {
+            Error::KeyDerivation {
+                message: "Bitcoin deposit keys must be compressed".to_string(),
+            }
+        })?;
+    Ok(bitcoin::Address::p2wpkh(&compressed_pk, network))
+}
+
+/// Checksummed address of an Ethereum key
+#[must_use]
+pub fn ethereum_address(signer: &PrivateKeySigner) -> String {
+    format!("{:?}", signer.address())
+}
+
+/// Address `private_key` controls on `chain`, formatted like the derived deposit
+/// wallets. Bitcoin keys are WIF, Ethereum keys hex.
+pub fn address_for_private_key(
+    chain: ChainType,
+    private_key: &str,
+    bitcoin_network: Network,
+) -> Result<String> {
+    match chain {
+        ChainType::Bitcoin => {
+            let private_key =
+                PrivateKey::from_wif(private_key).map_err(|_| Error::KeyDerivation {
+                    message: "Invalid WIF private key".to_string(),
+                })?;
+            Ok(bitcoin_address(&private_key, bitcoin_network)?.to_string())
+        }
+        ChainType::Ethereum => {
+            let signer =
+                PrivateKeySigner::from_str(private_key).map_err(|_| Error::KeyDerivation {
+                    message: "Invalid hex private key".to_string(),
+                })?;
+            Ok(ethereum_address(&signer))
+        }
+    }
+}
+
+/// Whether `private_key` controls `address` on `chain`
+pub fn private_key_controls_address(
+    chain: ChainType,
+    private_key: &str,
+    address: &str,
+    bitcoin_network: Network,
+) -> Result<bool> {
+    match chain {
+        ChainType::Bitcoin => bitcoin_key_controls_address(private_key, address, bitcoin_network),
+        ChainType::Ethereum => ethereum_key_controls_address(private_key, address),
+    }
+}
+
+/// Whether the WIF `private_key` controls the P2WPKH `address` on `network`
+pub fn bitcoin_key_controls_address(
+    private_key: &str,
+    address: &str,
+    network: Network,
+) -> Result<bool> {
+    let derived = address_for_private_key(ChainType::Bitcoin, private_key, network)?;
+    let address = bitcoin::Address::<NetworkUnchecked>::from_str(address)?;
+    Ok(address.is_valid_for_network(network) && address.assume_checked().to_string() == derived)
+}
+
+/// Whether the hex `private_key` controls `address`
+pub fn ethereum_key_controls_address(private_key: &str, address: &str) -> Result<bool> {
+    let signer = PrivateKeySigner::from_str(private_key).map_err(|_| Error::KeyDerivation {
+        message: "Invalid hex private key".to_string(),
+    })?;
+    let address =
+        alloy::primitives::Address::from_str(address).map_err(|_| Error::InvalidAddress {
+            address: address.to_string(),
+            network: ChainType::Ethereum,
+            reason: "Invalid hex address".to_string(),
+        })?;
+    Ok(signer.address() == address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::SecretKey;
+
+    /// WIF of the secret key `n`
+    fn bitcoin_wif(n: u8, network: Network) -> String {
+        let mut bytes = [0u8; 32];
+        bytes[31] = n;
+        PrivateKey::new(SecretKey::from_slice(&bytes).unwrap(), network).to_wif()
+    }
+
+    #[test]
+    fn test_bitcoin_key_controls_its_p2wpkh_address() {
+        let key = bitcoin_wif(1, Network::Regtest);
+        assert_eq!(
+            address_for_private_key(ChainType::Bitcoin, &key, Network::Regtest).unwrap(),
+            "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080"
+        );
+        assert_eq!(
+            address_for_private_key(
+                ChainType::Bitcoin,
+                &bitcoin_wif(1, Network::Bitcoin),
+                Network::Bitcoin
+            )
+            .unwrap(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+
+        let controls = |key: &str, address: &str| {
+            private_key_controls_address(ChainType::Bitcoin, key, address, Network::Regtest)
+                .unwrap()
+        };
+        assert!(controls(
+            &key,
+            "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080"
+        ));
+        assert!(controls(
+            &key,
+            "BCRT1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KYGT080"
+        ));
+        // Another key, or the same key's address on another network, don't match
+        assert!(!controls(
+            &bitcoin_wif(2, Network::Regtest),
+            "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080"
+        ));
+        assert!(!controls(
+            &key,
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        ));
+
+        assert!(
+            address_for_private_key(ChainType::Bitcoin, "not a key", Network::Regtest).is_err()
+        );
+    }
+
+    #[test]
+    fn test_ethereum_key_controls_its_address() {
+        // First anvil dev account
+        let key = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+        let address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+        assert_eq!(
+            address_for_private_key(ChainType::Ethereum, key, Network::Regtest).unwrap(),
+            address
+        );
+
+        let controls = |key: &str, address: &str| {
+            private_key_controls_address(ChainType::Ethereum, key, address, Network::Regtest)
+                .unwrap()
+        };
+        assert!(controls(key, address));
+        assert!(controls(key, &address.to_lowercase()));
+        assert!(!controls(key, "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"));
+
+        assert!(address_for_private_key(ChainType::Ethereum, "0x1234", Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn test_derived_deposit_keys_round_trip() {
+        let master_key = [7u8; 64];
+        let salt = [9u8; 32];
+
+        let bytes =
+            crate::key_derivation::derive_private_key(&master_key, &salt, b"bitcoin-wallet")
+                .unwrap();
+        let private_key = PrivateKey::new(SecretKey::from_slice(&bytes).unwrap(), Network::Regtest);
+        let address = bitcoin_address(&private_key, Network::Regtest).unwrap();
+        assert!(private_key_controls_address(
+            ChainType::Bitcoin,
+            &private_key.to_wif(),
+            &address.to_string(),
+            Network::Regtest
+        )
+        .unwrap());
+
+        let bytes =
+            crate::key_derivation::derive_private_key(&master_key, &salt, b"ethereum-wallet")
+                .unwrap();
+        let signer = PrivateKeySigner::from_bytes(&bytes.into()).unwrap();
+        assert!(private_key_controls_address(
+            ChainType::Ethereum,
+            &format!("0x{}", alloy::hex::encode(bytes)),
+            &ethereum_address(&signer),
+            Network::Regtest
+        )
+        .unwrap());
+    }
+}
@@ -0,0 +1,92 @@
+//! Smallest payments each chain relays, anything below is dust
+
+use crate::{Error, Result};
+use bitcoin::{Address, Network};
+use otc_models::ChainType;
+use std::str::FromStr;
+
+/// Dust threshold of a P2PKH output, the highest of the standard bitcoin
+/// destinations
+pub const MAX_BITCOIN_DUST_SATS: u64 = 546;
+
+/// Smallest amount, in the chain's smallest unit, a payment to `address` can
+/// carry. Bitcoin addresses must be on `network` and are priced by their
+/// script type, EVM chains have no dust limit
+pub fn dust_threshold(chain: ChainType, address: &str, network: Network) -> Result<u64> {
+    match chain {
+        ChainType::Bitcoin => {
+            let invalid = |reason: String| Error::InvalidAddress {
+                address: address.to_string(),
+                network: ChainType::Bitcoin,
+                reason,
+            };
+            let address = Address::from_str(address)
+                .map_err(|e| invalid(e.to_string()))?
+                .require_network(network)
+                .map_err(|e| invalid(e.to_string()))?;
+            Ok(address.script_pubkey().minimal_non_dust().to_sat())
+        }
+        ChainType::Ethereum => Ok(0),
+    }
+}
+
+/// Threshold of the costliest destination on `chain`, for when the
+/// destination isn't known yet
+#[must_use]
+pub const fn max_dust_threshold(chain: ChainType) -> u64 {
+    match chain {
+        ChainType::Bitcoin => MAX_BITCOIN_DUST_SATS,
+        ChainType::Ethereum => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::{CompressedPublicKey, PrivateKey};
+
+    fn public_key() -> CompressedPublicKey {
+        let mut bytes = [0u8; 32];
+        bytes[31] = 1;
+        let private_key = PrivateKey::new(SecretKey::from_slice(&bytes).unwrap(), Network::Regtest);
+        CompressedPublicKey::from_private_key(&Secp256k1::new(), &private_key).unwrap()
+    }
+
+    fn threshold(address: Address) -> u64 {
+        dust_threshold(ChainType::Bitcoin, &address.to_string(), Network::Regtest).unwrap()
+    }
+
+    #[test]
+    fn test_bitcoin_threshold_follows_the_script_type() {
+        let key = public_key();
+        let p2pkh = Address::p2pkh(key, Network::Regtest);
+        let p2wpkh = Address::p2wpkh(&key, Network::Regtest);
+        let p2tr = Address::p2tr(&Secp256k1::new(), key.0.into(), None, Network::Regtest);
+
+        assert_eq!(threshold(p2pkh), MAX_BITCOIN_DUST_SATS);
+        assert_eq!(threshold(p2wpkh), 294);
+        assert_eq!(threshold(p2tr), 330);
+        assert_eq!(
+            max_dust_threshold(ChainType::Bitcoin),
+            MAX_BITCOIN_DUST_SATS
+        );
+    }
+
+    #[test]
+    fn test_bitcoin_address_must_be_on_the_network() {
+        let mainnet = Address::p2wpkh(&public_key(), Network::Bitcoin).to_string();
+        assert!(dust_threshold(ChainType::Bitcoin, &mainnet, Network::Regtest).is_err());
+        assert!(dust_threshold(ChainType::Bitcoin, "not-an-address", Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn test_evm_has_no_dust() {
+        let address = "0x742d35Cc6634C0532925a3b844Bc454e4438f44e";
+        assert_eq!(
+            dust_threshold(ChainType::Ethereum, address, Network::Regtest).unwrap(),
+            0
+        );
+        assert_eq!(max_dust_threshold(ChainType::Ethereum), 0);
+    }
+}
@@ -1,5 +1,5 @@
 use alloy::primitives::U256;
-use otc_models::{ChainType, Lot};
+use otc_models::{ChainType, Lot, TokenIdentifier};
 use snafu::prelude::*;
 
 #[derive(Debug, Snafu)]
@@ -16,6 +16,9 @@ pub enum Error {
     #[snafu(display("Invalid lot for network {network:?}: {lot:?}"))]
     InvalidCurrency { lot: Lot, network: ChainType },
     
+    #[snafu(display("Token {token:?} is not supported on {network:?}"))]
+    UnsupportedToken { token: TokenIdentifier, network: ChainType },
+    
     #[snafu(display("Transaction not found: {tx_hash}"))]
     TransactionNotFound { tx_hash: String },
     
@@ -27,6 +30,12 @@ pub enum Error {
     
     #[snafu(display("Chain not supported: {chain}"))]
     ChainNotSupported { chain: String },
+
+    #[snafu(display("Transfer subscriptions are not supported"))]
+    SubscriptionsUnsupported,
+
+    #[snafu(display("Network fees are not available"))]
+    NetworkFeesUnsupported,
     
     #[snafu(display("Serialization error: {message}"))]
     Serialization { message: String },
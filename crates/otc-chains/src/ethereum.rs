@@ -1,53 +1,340 @@
-use crate::traits::MarketMakerPaymentValidation;
-use crate::{key_derivation, ChainOperations, Result};
-use alloy::primitives::{Address, Log, U256};
-use alloy::providers::{DynProvider, Provider, ProviderBuilder};
-use alloy::rpc::types::TransactionReceipt;
+use crate::traits::{MarketMakerPaymentValidation, NetworkFees, PriorityFees, TransferEvent};
+use crate::{deposit_key, key_derivation, payment_uri, ChainOperations, Result};
+use alloy::consensus::Transaction as _;
+use alloy::network::{EthereumWallet, TransactionBuilder};
+use alloy::primitives::{Address, Log, TxHash, U256, U64};
+use alloy::providers::{DynProvider, Provider, ProviderBuilder, WsConnect};
+use alloy::rpc::types::{BlockNumberOrTag, Filter, TransactionReceipt, TransactionRequest};
 use alloy::signers::local::PrivateKeySigner;
 use alloy::sol;
+use alloy::sol_types::SolEvent;
 use async_trait::async_trait;
-use blockchain_utils::inverse_compute_protocol_fee;
+use blockchain_utils::{inverse_compute_protocol_fee, GenericERC20::GenericERC20Instance};
+use common::{Clock, SystemClock};
 use evm_token_indexer_client::TokenIndexerClient;
-use otc_models::{ChainType, Lot, TokenIdentifier, TransferInfo, TxStatus, Wallet};
+use futures_util::stream::{BoxStream, StreamExt};
+use otc_models::{
+    ChainNetwork, ChainType, Currency, Finality, Lot, SupportedCurrencies, SupportedCurrency,
+    TokenIdentifier, TransferInfo, TxStatus, Wallet,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::time::Duration;
-use tracing::{debug, info};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 
 sol! {
     #[derive(Debug)]
     event Transfer(address indexed from, address indexed to, uint256 value);
 }
 
-const ALLOWED_TOKEN: &str = "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf";
+/// Block range per eth_getLogs request when scanning without the indexer, kept
+/// small enough for public RPC providers' range limits
+const LOG_SCAN_CHUNK_BLOCKS: u64 = 1_000;
+
+/// How far back log scanning looks when no starting block is given (~1.4 days of blocks)
+const LOG_SCAN_MAX_LOOKBACK_BLOCKS: u64 = 10_000;
+
+/// Gas a plain ETH transfer to an externally owned account uses
+const NATIVE_TRANSFER_GAS: u64 = 21_000;
+
+/// Gas limit budgeted for an ERC20 transfer, above what standard tokens use
+const ERC20_TRANSFER_GAS: u64 = 65_000;
+
+/// Blocks of fee history the priority fee is estimated from
+const FEE_HISTORY_BLOCKS: u64 = 10;
+
+/// Percentile of each block's priority fees sampled from the fee history
+const PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+
+/// Percentiles of each block's priority fees the suggested low, medium and high
+/// priority fees are averaged from
+const SUGGESTED_PRIORITY_FEE_PERCENTILES: [f64; 3] = [25.0, 50.0, 75.0];
+
+/// How long finality tags are reused outside a monitoring pass, about a block
+const FINALITY_TAGS_MAX_AGE: Duration = Duration::from_secs(12);
+
+/// Slack given when placing a block in time by its age against the head,
+/// which is read a moment before the block and blocks are a few seconds apart
+const BLOCK_TIME_TOLERANCE: Duration = Duration::from_secs(60);
+
+/// Block numbers behind the chain's `safe` and `finalized` tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FinalityTags {
+    safe: u64,
+    finalized: u64,
+}
+
+impl FinalityTags {
+    fn finality_of(&self, block: u64) -> Finality {
+        if block <= self.finalized {
+            Finality::Finalized
+        } else if block <= self.safe {
+            Finality::Safe
+        } else {
+            Finality::Unsafe
+        }
+    }
+}
+
+/// The only field of a block the finality tags are read for
+#[derive(Deserialize)]
+struct BlockNumber {
+    number: U64,
+}
+
+async fn fetch_finality_tags(provider: &DynProvider) -> Result<FinalityTags> {
+    let mut numbers = [0u64; 2];
+    for (tag, number) in ["safe", "finalized"].into_iter().zip(&mut numbers) {
+        let block: Option<BlockNumber> = provider
+            .raw_request("eth_getBlockByNumber".into(), (tag, false))
+            .await?;
+        *number = block
+            .ok_or_else(|| crate::Error::Rpc {
+                message: format!("Chain has no {tag} block"),
+            })?
+            .number
+            .to();
+    }
+    let [safe, finalized] = numbers;
+    Ok(FinalityTags { safe, finalized })
+}
+
+/// Status of a transaction in `block` with the chain at `latest`, judged by
+/// `tags` when the chain's finality is trusted
+fn included_status(block: u64, latest: u64, tags: Option<FinalityTags>) -> TxStatus {
+    TxStatus::Included {
+        confirmations: latest.saturating_sub(block),
+        finality: tags.map_or(Finality::Unknown, |tags| tags.finality_of(block)),
+    }
+}
+
+/// Cost of `gas_limit` gas at a max fee covering a doubled next base fee plus
+/// the highest sampled priority fee, so the transfer still lands if fees rise
+async fn estimate_fee(provider: &DynProvider, gas_limit: u64) -> Result<U256> {
+    let history = provider
+        .get_fee_history(
+            FEE_HISTORY_BLOCKS,
+            BlockNumberOrTag::Latest,
+            &[PRIORITY_FEE_PERCENTILE],
+        )
+        .await?;
+    let base_fee = history
+        .next_block_base_fee()
+        .ok_or_else(|| crate::Error::Rpc {
+            message: "Fee history has no base fee".to_string(),
+        })?;
+    let priority_fee = history
+        .reward
+        .iter()
+        .flatten()
+        .filter_map(|rewards| rewards.first().copied())
+        .max()
+        .unwrap_or_default();
+    Ok(U256::from(gas_limit) * (U256::from(base_fee) * U256::from(2) + U256::from(priority_fee)))
+}
+
+/// Next block's base fee and the priority fees recent blocks paid
+async fn fetch_network_fees(provider: &DynProvider) -> Result<NetworkFees> {
+    let history = provider
+        .get_fee_history(
+            FEE_HISTORY_BLOCKS,
+            BlockNumberOrTag::Latest,
+            &SUGGESTED_PRIORITY_FEE_PERCENTILES,
+        )
+        .await?;
+    let next_base_fee_wei = history
+        .next_block_base_fee()
+        .ok_or_else(|| crate::Error::Rpc {
+            message: "Fee history has no base fee".to_string(),
+        })?;
+    Ok(NetworkFees::Ethereum {
+        next_base_fee_wei,
+        priority_fees_wei: suggested_priority_fees(history.reward.as_deref().unwrap_or_default()),
+    })
+}
+
+/// Average of each of [`SUGGESTED_PRIORITY_FEE_PERCENTILES`] over the blocks
+/// of a fee history's rewards
+fn suggested_priority_fees(rewards: &[Vec<u128>]) -> PriorityFees {
+    let average = |percentile: usize| {
+        let fees: Vec<u128> = rewards
+            .iter()
+            .filter_map(|block| block.get(percentile).copied())
+            .collect();
+        match fees.len() {
+            0 => 0,
+            blocks => fees.iter().sum::<u128>() / blocks as u128,
+        }
+    };
+    PriorityFees {
+        low: average(0),
+        medium: average(1),
+        high: average(2),
+    }
+}
+
+fn parse_address(address: &str) -> Result<Address> {
+    Address::from_str(address).map_err(|e| crate::Error::InvalidAddress {
+        address: address.to_string(),
+        network: ChainType::Ethereum,
+        reason: e.to_string(),
+    })
+}
 
 pub struct EthereumChain {
     provider: DynProvider,
-    evm_indexer_client: TokenIndexerClient,
+    // Without an indexer, transfers are found by scanning Transfer logs over RPC
+    evm_indexer_client: Option<TokenIndexerClient>,
     chain_id: u64,
-    allowed_token: Address,
+    /// Decimals of each token deposits are accepted in
+    token_decimals: HashMap<Address, u8>,
+    /// Whether deposits are judged by the safe and finalized tags rather than
+    /// confirmations alone
+    use_finality_tags: bool,
+    /// Tags fetched for the current monitoring pass, and when
+    finality_tags: Mutex<Option<(Instant, FinalityTags)>>,
+    /// Websocket endpoint transfers are subscribed over, without one deposits
+    /// are only polled for
+    ws_url: Option<String>,
+    /// Connection every subscription shares, opened on first use and again
+    /// once it's found dead
+    ws_connection: tokio::sync::Mutex<Option<DynProvider>>,
+    /// What MM payments' mined times are worked out by, the clock their swaps
+    /// were notified by
+    clock: Arc<dyn Clock>,
 }
 
 impl EthereumChain {
-    pub async fn new(rpc_url: &str, evm_indexer_url: &str, chain_id: u64) -> Result<Self> {
+    /// The primary Ethereum network, accepting deposits in its configured tokens
+    pub async fn new(
+        rpc_url: &str,
+        evm_indexer_url: Option<&str>,
+        chain_id: u64,
+        supported_currencies: &SupportedCurrencies,
+    ) -> Result<Self> {
+        let currencies = supported_currencies.on_chain(ChainType::Ethereum);
+        Self::connect(rpc_url, evm_indexer_url, chain_id, currencies).await
+    }
+
+    /// An EVM network besides the primary one, accepting deposits in the tokens
+    /// configured under its chain id
+    pub async fn for_network(
+        rpc_url: &str,
+        evm_indexer_url: Option<&str>,
+        chain_id: u64,
+        supported_currencies: &SupportedCurrencies,
+    ) -> Result<Self> {
+        let currencies = supported_currencies.on_chain(ChainNetwork::evm(chain_id));
+        Self::connect(rpc_url, evm_indexer_url, chain_id, currencies).await
+    }
+
+    async fn connect<'a>(
+        rpc_url: &str,
+        evm_indexer_url: Option<&str>,
+        chain_id: u64,
+        currencies: impl Iterator<Item = &'a SupportedCurrency>,
+    ) -> Result<Self> {
         let provider = ProviderBuilder::new()
             .connect_http(rpc_url.parse().map_err(|_| crate::Error::Serialization {
                 message: "Invalid RPC URL".to_string(),
             })?)
             .erased();
 
-        let evm_indexer_client = TokenIndexerClient::new(evm_indexer_url)?;
-        let allowed_token =
-            Address::from_str(ALLOWED_TOKEN).map_err(|_| crate::Error::Serialization {
-                message: "Invalid allowed token address".to_string(),
+        let evm_indexer_client = evm_indexer_url.map(TokenIndexerClient::new).transpose()?;
+        if evm_indexer_client.is_none() {
+            warn!(
+                "No EVM token indexer configured, falling back to scanning Transfer logs over RPC"
+            );
+        }
+        let mut token_decimals = HashMap::new();
+        for currency in currencies {
+            let TokenIdentifier::Address(address) = &currency.token else {
+                continue;
+            };
+            let address = Address::from_str(address).map_err(|_| crate::Error::Serialization {
+                message: format!("Invalid {} token address", currency.symbol),
             })?;
+            token_decimals.insert(address, currency.decimals);
+        }
 
         Ok(Self {
             provider,
             evm_indexer_client,
             chain_id,
-            allowed_token,
+            token_decimals,
+            use_finality_tags: true,
+            finality_tags: Mutex::new(None),
+            ws_url: None,
+            ws_connection: tokio::sync::Mutex::new(None),
+            clock: Arc::new(SystemClock),
         })
     }
+
+    /// Judge deposits by confirmations alone, for chains without meaningful
+    /// safe and finalized tags
+    #[must_use]
+    pub fn with_finality_tags(mut self, enabled: bool) -> Self {
+        self.use_finality_tags = enabled;
+        self
+    }
+
+    /// Push transfers to subscribers over the websocket at `ws_url`
+    #[must_use]
+    pub fn with_subscriptions(mut self, ws_url: Option<String>) -> Self {
+        self.ws_url = ws_url;
+        self
+    }
+
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    async fn ws_provider(&self) -> Result<DynProvider> {
+        let ws_url = self
+            .ws_url
+            .as_deref()
+            .ok_or(crate::Error::SubscriptionsUnsupported)?;
+        let mut connection = self.ws_connection.lock().await;
+        if let Some(provider) = connection.as_ref() {
+            return Ok(provider.clone());
+        }
+        let provider = ProviderBuilder::new()
+            .connect_ws(WsConnect::new(ws_url))
+            .await?
+            .erased();
+        *connection = Some(provider.clone());
+        Ok(provider)
+    }
+
+    async fn refresh_finality_tags(&self) -> Result<FinalityTags> {
+        let tags = fetch_finality_tags(&self.provider).await?;
+        *self
+            .finality_tags
+            .lock()
+            .expect("finality tags mutex poisoned") = Some((Instant::now(), tags));
+        Ok(tags)
+    }
+
+    /// The pass's finality tags, fetched again once they're stale
+    async fn finality_tags(&self) -> Result<Option<FinalityTags>> {
+        if !self.use_finality_tags {
+            return Ok(None);
+        }
+        let cached = *self
+            .finality_tags
+            .lock()
+            .expect("finality tags mutex poisoned");
+        match cached {
+            Some((fetched_at, tags)) if fetched_at.elapsed() < FINALITY_TAGS_MAX_AGE => {
+                Ok(Some(tags))
+            }
+            _ => self.refresh_finality_tags().await.map(Some),
+        }
+    }
 }
 
 #[async_trait]
@@ -61,12 +348,12 @@ impl ChainOperations for EthereumChain {
 
         // Create a new random signer
         let signer = PrivateKeySigner::random();
-        let address = signer.address();
+        let address = deposit_key::ethereum_address(&signer);
         let private_key = alloy::primitives::hex::encode(signer.to_bytes());
 
         info!("Created new Ethereum wallet: {}", address);
 
-        let wallet = Wallet::new(format!("{address:?}"), format!("0x{private_key}"));
+        let wallet = Wallet::new(address, format!("0x{private_key}"));
         Ok((wallet, salt))
     }
 
@@ -82,7 +369,7 @@ impl ChainOperations for EthereumChain {
             }
         })?;
 
-        let address = format!("{:?}", signer.address());
+        let address = deposit_key::ethereum_address(&signer);
         let private_key = format!("0x{}", alloy::hex::encode(private_key_bytes));
 
         debug!("Derived Ethereum wallet: {}", address);
@@ -94,26 +381,91 @@ impl ChainOperations for EthereumChain {
         let tx_hash_parsed = tx_hash.parse().map_err(|_| crate::Error::Serialization {
             message: "Invalid transaction hash".to_string(),
         })?;
-        let tx = self
+        let receipt = self
             .provider
             .get_transaction_receipt(tx_hash_parsed)
             .await?;
 
-        if tx.is_some() {
-            let current_block_height = self.provider.get_block_number().await?;
-            Ok(TxStatus::Confirmed(
-                current_block_height - tx.unwrap().block_number.unwrap(),
-            ))
-        } else {
-            Ok(TxStatus::NotFound)
+        match receipt.and_then(|receipt| receipt.block_number) {
+            Some(block) => {
+                let latest = self.provider.get_block_number().await?;
+                Ok(included_status(block, latest, self.finality_tags().await?))
+            }
+            None => {
+                let pending = self
+                    .provider
+                    .get_transaction_by_hash(tx_hash_parsed)
+                    .await?;
+                Ok(if pending.is_some() {
+                    TxStatus::Pending
+                } else {
+                    TxStatus::NotFound
+                })
+            }
         }
     }
+
+    fn reports_finality(&self) -> bool {
+        self.use_finality_tags
+    }
+
+    async fn begin_monitoring_pass(&self) -> Result<()> {
+        if self.use_finality_tags {
+            self.refresh_finality_tags().await?;
+        }
+        Ok(())
+    }
+
+    fn supports_subscriptions(&self) -> bool {
+        self.ws_url.is_some()
+    }
+
+    async fn subscribe_transfers(
+        &self,
+        address: &str,
+        currency: &Currency,
+    ) -> Result<BoxStream<'static, TransferEvent>> {
+        // Ether transfers leave no log to subscribe to
+        let TokenIdentifier::Address(token) = &currency.token else {
+            return Err(crate::Error::UnsupportedToken {
+                token: currency.token.clone(),
+                network: ChainType::Ethereum,
+            });
+        };
+        let filter = Filter::new()
+            .address(parse_address(token)?)
+            .event_signature(Transfer::SIGNATURE_HASH)
+            .topic2(parse_address(address)?.into_word());
+
+        let subscription = match self.ws_provider().await?.subscribe_logs(&filter).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                *self.ws_connection.lock().await = None;
+                return Err(e.into());
+            }
+        };
+        Ok(subscription
+            .into_stream()
+            .filter_map(|log| async move {
+                // Logs of reorged blocks come again, flagged as removed
+                if log.removed {
+                    return None;
+                }
+                let transfer = log.log_decode::<Transfer>().ok()?;
+                Some(TransferEvent {
+                    tx_hash: log.transaction_hash?.into(),
+                    amount: transfer.inner.value,
+                    block_number: log.block_number,
+                })
+            })
+            .boxed())
+    }
     async fn search_for_transfer(
         &self,
         recipient_address: &str,
         lot: &Lot,
         mm_payment: Option<MarketMakerPaymentValidation>,
-        _from_block_height: Option<u64>,
+        from_block_height: Option<u64>,
     ) -> Result<Option<TransferInfo>> {
         let token_address = match &lot.currency.token {
             TokenIdentifier::Address(address) => address,
@@ -124,9 +476,20 @@ impl ChainOperations for EthereumChain {
                 message: "Invalid token address".to_string(),
             })?;
 
-        if token_address != self.allowed_token {
-            debug!("Token address {} is not allowed", token_address);
-            return Ok(None);
+        match self.token_decimals.get(&token_address) {
+            None => {
+                debug!("Token address {} is not allowed", token_address);
+                return Ok(None);
+            }
+            // The amount is in base units of the token, so a mismatch would misprice it
+            Some(decimals) if *decimals != lot.currency.decimals => {
+                warn!(
+                    "Token {} has {} decimals, lot expects {}",
+                    token_address, decimals, lot.currency.decimals
+                );
+                return Ok(None);
+            }
+            Some(_) => {}
         }
 
         let recipient_address =
@@ -135,7 +498,13 @@ impl ChainOperations for EthereumChain {
             })?;
 
         let transfer_hint = self
-            .get_transfer(&recipient_address, &lot.amount, mm_payment)
+            .get_transfer(
+                &token_address,
+                &recipient_address,
+                &lot.amount,
+                mm_payment,
+                from_block_height,
+            )
             .await?;
         if transfer_hint.is_none() {
             return Ok(None);
@@ -144,10 +513,132 @@ impl ChainOperations for EthereumChain {
         Ok(Some(transfer_hint.unwrap()))
     }
 
+    async fn get_balance(&self, address: &str, token: &TokenIdentifier) -> Result<U256> {
+        let address = parse_address(address)?;
+        match token {
+            TokenIdentifier::Native => Ok(self.provider.get_balance(address).await?),
+            TokenIdentifier::Address(token) => {
+                GenericERC20Instance::new(parse_address(token)?, self.provider.clone())
+                    .balanceOf(address)
+                    .call()
+                    .await
+                    .map_err(|e| crate::Error::Rpc {
+                        message: format!("Failed to get ERC20 balance: {e}"),
+                    })
+            }
+        }
+    }
+
+    async fn sweep(
+        &self,
+        wallet: &Wallet,
+        token: &TokenIdentifier,
+        to_address: &str,
+    ) -> Result<String> {
+        let signer = PrivateKeySigner::from_str(wallet.private_key()).map_err(|_| {
+            crate::Error::KeyDerivation {
+                message: "Invalid hex private key".to_string(),
+            }
+        })?;
+        let from = signer.address();
+        let to = parse_address(to_address)?;
+        let fees = self.provider.estimate_eip1559_fees().await?;
+        let eth_balance = self.provider.get_balance(from).await?;
+
+        let request = match token {
+            TokenIdentifier::Native => {
+                let gas_cost = U256::from(NATIVE_TRANSFER_GAS) * U256::from(fees.max_fee_per_gas);
+                if eth_balance <= gas_cost {
+                    return Err(crate::Error::InsufficientBalance {
+                        required: gas_cost,
+                        available: eth_balance,
+                    });
+                }
+                TransactionRequest::default()
+                    .with_from(from)
+                    .with_to(to)
+                    .with_value(eth_balance - gas_cost)
+                    .with_gas_limit(NATIVE_TRANSFER_GAS)
+            }
+            TokenIdentifier::Address(token) => {
+                let token = GenericERC20Instance::new(parse_address(token)?, self.provider.clone());
+                let amount = token
+                    .balanceOf(from)
+                    .call()
+                    .await
+                    .map_err(|e| crate::Error::Rpc {
+                        message: format!("Failed to get ERC20 balance: {e}"),
+                    })?;
+                if amount.is_zero() {
+                    return Err(crate::Error::InsufficientBalance {
+                        required: U256::from(1),
+                        available: amount,
+                    });
+                }
+                let request = token
+                    .transfer(to, amount)
+                    .from(from)
+                    .into_transaction_request();
+                // Deposit wallets pay their own gas, one holding no ETH must be funded first
+                let gas = self.provider.estimate_gas(request.clone()).await?;
+                let gas_cost = U256::from(gas) * U256::from(fees.max_fee_per_gas);
+                if eth_balance < gas_cost {
+                    return Err(crate::Error::InsufficientBalance {
+                        required: gas_cost,
+                        available: eth_balance,
+                    });
+                }
+                request.with_gas_limit(gas)
+            }
+        }
+        .with_max_fee_per_gas(fees.max_fee_per_gas)
+        .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+
+        let tx_hash = ProviderBuilder::new()
+            .wallet(EthereumWallet::from(signer))
+            .connect_provider(self.provider.clone())
+            .send_transaction(request)
+            .await?
+            .watch()
+            .await
+            .map_err(|e| crate::Error::Rpc {
+                message: format!("Sweep transaction failed: {e}"),
+            })?;
+        info!("Swept {:?} from {} to {} in {}", token, from, to, tx_hash);
+        Ok(tx_hash.to_string())
+    }
+
+    async fn estimate_transfer_fee(&self, currency: &Currency) -> Result<U256> {
+        let gas_limit = match currency.token {
+            TokenIdentifier::Native => NATIVE_TRANSFER_GAS,
+            TokenIdentifier::Address(_) => ERC20_TRANSFER_GAS,
+        };
+        estimate_fee(&self.provider, gas_limit).await
+    }
+
+    async fn network_fees(&self) -> Result<NetworkFees> {
+        fetch_network_fees(&self.provider).await
+    }
+
     fn validate_address(&self, address: &str) -> bool {
         Address::from_str(address).is_ok()
     }
 
+    fn private_key_controls_address(&self, private_key: &str, address: &str) -> Result<bool> {
+        deposit_key::ethereum_key_controls_address(private_key, address)
+    }
+
+    fn payment_uri(&self, address: &str, lot: &Lot) -> String {
+        match &lot.currency.token {
+            TokenIdentifier::Address(token) => {
+                payment_uri::eip681_erc20_transfer_uri(token, self.chain_id, address, lot.amount)
+            }
+            TokenIdentifier::Native => {
+                payment_uri::eip681_native_uri(address, self.chain_id, lot.amount)
+            }
+        }
+    }
+
     fn minimum_block_confirmations(&self) -> u32 {
         4 // Standard for Ethereum
     }
@@ -158,121 +649,154 @@ impl ChainOperations for EthereumChain {
 }
 
 impl EthereumChain {
-    // Note this function's response is safe to trust, b/c it will validate the responses from the untrusted evm_indexer_client
+    // Note this function's response is safe to trust, b/c every candidate is re-validated against the RPC node
     async fn get_transfer(
         &self,
+        token_address: &Address,
         recipient_address: &Address,
         amount: &U256,
         mm_payment: Option<MarketMakerPaymentValidation>,
+        from_block_height: Option<u64>,
     ) -> Result<Option<TransferInfo>> {
         info!(
             "Searching for transfer for address: {}, amount: {}, mm_payment: {:?}",
             recipient_address, amount, mm_payment
         );
 
-        // use the untrusted evm_indexer_client to get the transfer hint - this will only return 50 latest transfers (TODO: how to handle this?)
-        let transfers = self
-            .evm_indexer_client
-            .get_transfers_to(*recipient_address, None, Some(*amount))
-            .await?;
+        // MM payments are identified by their nonce, so underpaying ones are returned
+        // too and the caller compares the amount against the quote
+        let min_amount = if mm_payment.is_some() {
+            U256::ZERO
+        } else {
+            *amount
+        };
+
+        // Every candidate's confirmations are counted from this one head
+        let head_block = self.provider.get_block_number().await?;
+
+        let candidate_tx_hashes = match &self.evm_indexer_client {
+            Some(evm_indexer_client) => {
+                // use the untrusted evm_indexer_client to get the transfer hints, bounded to the blocks the RPC node has seen
+                let transfers = evm_indexer_client
+                    .get_transfers_to_with_confirmations(
+                        *recipient_address,
+                        Some(min_amount),
+                        from_block_height,
+                        head_block,
+                    )
+                    .await?;
+                debug!("Transfers from evm_indexer_client: {:?}", transfers);
+                let mut tx_hashes = Vec::new();
+                for transfer in transfers {
+                    if !tx_hashes.contains(&transfer.transfer.transaction_hash) {
+                        tx_hashes.push(transfer.transfer.transaction_hash);
+                    }
+                }
+                tx_hashes
+            }
+            None => {
+                self.scan_transfer_logs(
+                    token_address,
+                    recipient_address,
+                    &min_amount,
+                    from_block_height,
+                    head_block,
+                )
+                .await?
+            }
+        };
 
-        if transfers.transfers.is_empty() {
+        if candidate_tx_hashes.is_empty() {
             info!("No transfers found");
             return Ok(None);
         }
 
-        debug!("TransfersResponse from evm_indexer_client: {:?}", transfers);
-
         let mut transfer_hint: Option<TransferInfo> = None;
-        for transfer in transfers.transfers {
+        for transaction_hash in candidate_tx_hashes {
             let transaction_receipt = self
                 .provider
-                .get_transaction_receipt(transfer.transaction_hash)
+                .get_transaction_receipt(transaction_hash)
                 .await?;
 
             if transaction_receipt.is_none() {
-                debug!("Transaction receipt not found for transfer: {:?}", transfer);
+                debug!(
+                    "Transaction receipt not found for transfer: {:?}",
+                    transaction_hash
+                );
                 continue;
             }
             let transaction_receipt = transaction_receipt.unwrap();
             if !transaction_receipt.status() {
                 debug!(
                     "Transaction receipt not successful for transfer: {:?}",
-                    transfer
+                    transaction_hash
                 );
                 continue;
             }
 
+            if let (Some(mm_payment), Some(block_number)) =
+                (&mm_payment, transaction_receipt.block_number)
+            {
+                if mm_payment.not_before.is_some() {
+                    let mined_at = self.mined_at(block_number, head_block).await?;
+                    if mined_at.is_some_and(|mined_at| {
+                        mm_payment.mined_too_early(mined_at, BLOCK_TIME_TOLERANCE)
+                    }) {
+                        info!(
+                            "MM payment mined before the swap was notified: {:?}",
+                            transaction_hash
+                        );
+                        continue;
+                    }
+                }
+            }
+
             let intra_tx_transfers =
                 extract_all_transfers_from_transaction_receipt(&transaction_receipt);
 
-            // TODO: There's a security issue with handling more than 1 swap per tx, so for now we force there to be no more than 2 transfers per tx (1 for the swap, 1 for the fee)
-            if intra_tx_transfers.len() > 2 {
-                debug!("More than 2 transfers in transaction",);
-                for transfer_log in intra_tx_transfers {
-                    debug!("Transfer: {:?}", transfer_log);
+            let candidate_transfers = match &mm_payment {
+                Some(mm_payment) => {
+                    match self
+                        .mm_payment_transfer(transaction_hash, &intra_tx_transfers, mm_payment)
+                        .await?
+                    {
+                        Some(transfer_log) => vec![transfer_log],
+                        None => continue,
+                    }
                 }
-                continue;
-            }
-            for (index, transfer_log) in intra_tx_transfers.iter().enumerate() {
+                None => {
+                    // TODO: There's a security issue with handling more than 1 deposit per tx, so for now we force there to be no more than 2 transfers per tx
+                    if intra_tx_transfers.len() > 2 {
+                        debug!("More than 2 transfers in transaction",);
+                        for transfer_log in intra_tx_transfers {
+                            debug!("Transfer: {:?}", transfer_log);
+                        }
+                        continue;
+                    }
+                    intra_tx_transfers.iter().collect()
+                }
+            };
+
+            for transfer_log in candidate_transfers {
                 // validate the recipient
                 if transfer_log.to != *recipient_address {
                     debug!(
                         "Transfer recipient is not the expected address: {:?}",
-                        transfer
+                        transaction_hash
                     );
                     continue;
                 }
                 // validate the amount
-                if transfer_log.value < *amount {
-                    debug!("Transfer amount is less than expected: {:?}", transfer);
+                if transfer_log.value < min_amount {
+                    debug!(
+                        "Transfer amount is less than expected: {:?}",
+                        transaction_hash
+                    );
                     continue;
                 }
-                // validate the embedded nonce
-                if let Some(mm_payment) = &mm_payment {
-                    let embedded_nonce = mm_payment.embedded_nonce;
-                    let transaction = self
-                        .provider
-                        .get_raw_transaction_by_hash(transfer.transaction_hash)
-                        .await?;
-                    if transaction.is_none() {
-                        debug!("Transaction not found for transfer: {:?}", transfer);
-                        continue;
-                    }
-                    let transaction = transaction.unwrap();
-                    let tx_hex = alloy::hex::encode(transaction);
-                    let nonce_hex = alloy::hex::encode(embedded_nonce);
-                    if !tx_hex.contains(&nonce_hex) {
-                        debug!(
-                            "Transaction does not contain the expected nonce: {:?}",
-                            transfer
-                        );
-                        continue;
-                    }
-
-                    let fee_address = Address::from_str(
-                        &otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Ethereum],
-                    )
-                    .map_err(|_| crate::Error::Serialization {
-                        message: "Invalid fee address".to_string(),
-                    })?;
-
-                    // NOTE: This is only works b/c we force there to be 2 transfers IF it's a MM payment
-                    let fee_log_index = if index == 0 { 1 } else { 0 };
-                    let fee_log = intra_tx_transfers[fee_log_index].clone();
-                    if fee_log.to != fee_address {
-                        info!("Fee address is not the expected address");
-                        continue;
-                    }
-                    if fee_log.value < mm_payment.fee_amount {
-                        info!("Fee amount is less than expected");
-                        continue;
-                    }
-                }
-                // get the current block height
-                let current_block_height = self.provider.get_block_number().await?;
+                // The receipt can come from a block mined after the head was read
                 let confirmations =
-                    current_block_height - transaction_receipt.block_number.unwrap();
+                    head_block.saturating_sub(transaction_receipt.block_number.unwrap());
 
                 // only return the transfer if it has more confirmations than the previous transfer hint
                 if transfer_hint.is_some()
@@ -280,14 +804,14 @@ impl EthereumChain {
                 {
                     debug!(
                         "Transfer has more confirmations than the previous transfer hint: {:?}",
-                        transfer
+                        transaction_hash
                     );
                     continue;
                 }
 
                 transfer_hint = Some(TransferInfo {
-                    tx_hash: alloy::hex::encode(transfer.transaction_hash),
-                    detected_at: chrono::Utc::now(),
+                    tx_hash: transaction_hash.into(),
+                    detected_at: self.clock.now(),
                     confirmations,
                     amount: transfer_log.value,
                 });
@@ -296,6 +820,166 @@ impl EthereumChain {
 
         Ok(transfer_hint)
     }
+
+    /// When block `block_number` was mined by the server's clock. Worked out
+    /// from its age against `head_block` rather than its timestamp alone, as a
+    /// devnet's chain clock can be set hours away from the wall clock
+    async fn mined_at(
+        &self,
+        block_number: u64,
+        head_block: u64,
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let timestamp = |number: u64| async move {
+            Ok::<_, crate::Error>(
+                self.provider
+                    .get_block_by_number(BlockNumberOrTag::Number(number))
+                    .await?
+                    .map(|block| block.header.timestamp),
+            )
+        };
+        let (Some(block_time), Some(head_time)) =
+            (timestamp(block_number).await?, timestamp(head_block).await?)
+        else {
+            return Ok(None);
+        };
+        let age = chrono::Duration::seconds(head_time.saturating_sub(block_time) as i64);
+        Ok(Some(self.clock.now() - age))
+    }
+
+    /// The payout to the swap with `mm_payment`'s nonce in a market maker payment,
+    /// provided its protocol fee was paid alongside it
+    async fn mm_payment_transfer<'a>(
+        &self,
+        transaction_hash: TxHash,
+        intra_tx_transfers: &'a [Log<Transfer>],
+        mm_payment: &MarketMakerPaymentValidation,
+    ) -> Result<Option<&'a Log<Transfer>>> {
+        let Some(transaction) = self
+            .provider
+            .get_transaction_by_hash(transaction_hash)
+            .await?
+        else {
+            debug!("Transaction not found for transfer: {:?}", transaction_hash);
+            return Ok(None);
+        };
+        let Some(swap_index) = mm_payment_index(
+            transaction.input(),
+            intra_tx_transfers.len(),
+            &mm_payment.embedded_nonce,
+        ) else {
+            debug!(
+                "Transaction does not contain the expected nonce: {:?}",
+                transaction_hash
+            );
+            return Ok(None);
+        };
+
+        let fee_address = Address::from_str(
+            &otc_models::FEE_ADDRESSES_BY_CHAIN[&ChainType::Ethereum],
+        )
+        .map_err(|_| crate::Error::Serialization {
+            message: "Invalid fee address".to_string(),
+        })?;
+        let fee_log = &intra_tx_transfers[2 * swap_index + 1];
+        if fee_log.to != fee_address {
+            info!("Fee address is not the expected address");
+            return Ok(None);
+        }
+        if fee_log.value < mm_payment.fee_amount {
+            info!("Fee amount is less than expected");
+            return Ok(None);
+        }
+
+        Ok(Some(&intra_tx_transfers[2 * swap_index]))
+    }
+
+    /// Find transactions that moved at least `amount` of the token to the recipient by
+    /// scanning Transfer logs directly up to `latest_block`, newest blocks first. Only
+    /// used without an indexer.
+    async fn scan_transfer_logs(
+        &self,
+        token_address: &Address,
+        recipient_address: &Address,
+        amount: &U256,
+        from_block_height: Option<u64>,
+        latest_block: u64,
+    ) -> Result<Vec<TxHash>> {
+        let lookback_floor = latest_block.saturating_sub(LOG_SCAN_MAX_LOOKBACK_BLOCKS);
+        let first_block = from_block_height.map_or(lookback_floor, |b| b.max(lookback_floor));
+
+        let filter = Filter::new()
+            .address(*token_address)
+            .event_signature(Transfer::SIGNATURE_HASH)
+            .topic2(recipient_address.into_word());
+
+        let mut tx_hashes = Vec::new();
+        let mut chunk_end = latest_block;
+        while chunk_end >= first_block {
+            let chunk_start = chunk_end
+                .saturating_sub(LOG_SCAN_CHUNK_BLOCKS - 1)
+                .max(first_block);
+            let logs = self
+                .provider
+                .get_logs(&filter.clone().from_block(chunk_start).to_block(chunk_end))
+                .await?;
+
+            // Newest first within the chunk, matching the indexer's ordering
+            for log in logs.iter().rev() {
+                let Ok(transfer_log) = log.log_decode::<Transfer>() else {
+                    continue;
+                };
+                if transfer_log.inner.value < *amount {
+                    continue;
+                }
+                if let Some(tx_hash) = log.transaction_hash {
+                    if !tx_hashes.contains(&tx_hash) {
+                        tx_hashes.push(tx_hash);
+                    }
+                }
+            }
+
+            if chunk_start == 0 {
+                break;
+            }
+            chunk_end = chunk_start - 1;
+        }
+
+        debug!(
+            "Scanned blocks {}..={} for transfers to {}: {} candidates",
+            first_block,
+            latest_block,
+            recipient_address,
+            tx_hashes.len()
+        );
+        Ok(tx_hashes)
+    }
+}
+
+/// Position of the swap paid with `nonce` in a market maker payment. A payment
+/// makes a payout then a fee transfer for each swap it fills, and appends the
+/// swaps' nonces to its calldata in the same order, so one transaction can fill
+/// several swaps.
+fn mm_payment_index(input: &[u8], transfer_count: usize, nonce: &[u8; 16]) -> Option<usize> {
+    if transfer_count == 0 || transfer_count % 2 != 0 {
+        return None;
+    }
+    let nonces_len = transfer_count / 2 * nonce.len();
+    // The nonces follow at least a function selector
+    if input.len() < nonces_len + 4 {
+        return None;
+    }
+
+    let mut matches = input[input.len() - nonces_len..]
+        .chunks_exact(nonce.len())
+        .enumerate()
+        .filter(|(_, embedded_nonce)| *embedded_nonce == nonce)
+        .map(|(index, _)| index);
+    let index = matches.next()?;
+    // A nonce listed twice can't be tied to a single payout
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(index)
 }
 
 fn extract_all_transfers_from_transaction_receipt(
@@ -313,3 +997,217 @@ fn extract_all_transfers_from_transaction_receipt(
     }
     transfers
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::mock::Asserter;
+
+    /// Provider answering its next request with `response`
+    fn mock_provider(response: serde_json::Value) -> DynProvider {
+        let asserter = Asserter::new();
+        asserter.push_success(&response);
+        ProviderBuilder::new()
+            .connect_mocked_client(asserter)
+            .erased()
+    }
+
+    /// Fee history of two blocks at 1 and 2 gwei base fee, whose median
+    /// priority fees were 1 and 3 gwei, with a 3 gwei base fee next
+    fn fee_history() -> serde_json::Value {
+        serde_json::json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x3b9aca00", "0x77359400", "0xb2d05e00"],
+            "gasUsedRatio": [0.5, 0.9],
+            "reward": [["0x3b9aca00"], ["0xb2d05e00"]]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_native_transfer_fee_from_fee_history() {
+        let fee = estimate_fee(&mock_provider(fee_history()), NATIVE_TRANSFER_GAS)
+            .await
+            .unwrap();
+        // 21000 gas at 2 * 3 gwei + 3 gwei
+        assert_eq!(fee, U256::from(21_000u64 * 9_000_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_erc20_transfer_fee_from_fee_history() {
+        let fee = estimate_fee(&mock_provider(fee_history()), ERC20_TRANSFER_GAS)
+            .await
+            .unwrap();
+        assert_eq!(fee, U256::from(65_000u64 * 9_000_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_fee_history_without_rewards_pays_no_priority_fee() {
+        let mut history = fee_history();
+        history.as_object_mut().unwrap().remove("reward");
+        let fee = estimate_fee(&mock_provider(history), NATIVE_TRANSFER_GAS)
+            .await
+            .unwrap();
+        assert_eq!(fee, U256::from(21_000u64 * 6_000_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_network_fees_average_each_percentile() {
+        let mut history = fee_history();
+        history["reward"] = serde_json::json!([
+            ["0x3b9aca00", "0x77359400", "0xb2d05e00"],
+            ["0x0", "0x3b9aca00", "0x1dcd65000"]
+        ]);
+        let fees = fetch_network_fees(&mock_provider(history)).await.unwrap();
+        assert_eq!(
+            fees,
+            NetworkFees::Ethereum {
+                next_base_fee_wei: 3_000_000_000,
+                priority_fees_wei: PriorityFees {
+                    low: 500_000_000,
+                    medium: 1_500_000_000,
+                    high: 5_500_000_000,
+                },
+            }
+        );
+
+        // Without rewards there is nothing to suggest past the base fee
+        history.as_object_mut().unwrap().remove("reward");
+        let fees = fetch_network_fees(&mock_provider(history)).await.unwrap();
+        assert_eq!(
+            fees,
+            NetworkFees::Ethereum {
+                next_base_fee_wei: 3_000_000_000,
+                priority_fees_wei: PriorityFees {
+                    low: 0,
+                    medium: 0,
+                    high: 0,
+                },
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_finality_tags_from_block_tags() {
+        let asserter = Asserter::new();
+        asserter.push_success(&serde_json::json!({ "number": "0x20" }));
+        asserter.push_success(&serde_json::json!({ "number": "0x10" }));
+        let provider = ProviderBuilder::new()
+            .connect_mocked_client(asserter)
+            .erased();
+
+        let tags = fetch_finality_tags(&provider).await.unwrap();
+        assert_eq!(
+            tags,
+            FinalityTags {
+                safe: 0x20,
+                finalized: 0x10,
+            }
+        );
+        assert_eq!(tags.finality_of(0x10), Finality::Finalized);
+        assert_eq!(tags.finality_of(0x11), Finality::Safe);
+        assert_eq!(tags.finality_of(0x20), Finality::Safe);
+        assert_eq!(tags.finality_of(0x21), Finality::Unsafe);
+    }
+
+    #[tokio::test]
+    async fn test_chain_without_finality_tags_is_an_error() {
+        let provider = mock_provider(serde_json::Value::Null);
+        assert!(fetch_finality_tags(&provider).await.is_err());
+    }
+
+    #[test]
+    fn test_included_status_respects_finality() {
+        let tags = FinalityTags {
+            safe: 90,
+            finalized: 60,
+        };
+
+        // Deep in a non-finalizing chain, still not settled
+        let status = included_status(95, 200, Some(tags));
+        assert_eq!(status.confirmations(), 105);
+        assert!(!status.satisfies(4));
+        // Finalized with fewer confirmations than required
+        assert!(included_status(60, 61, Some(tags)).satisfies(4));
+        assert!(included_status(85, 89, Some(tags)).satisfies(4));
+        assert!(!included_status(88, 89, Some(tags)).satisfies(4));
+        // Without tags only confirmations count
+        assert!(included_status(95, 200, None).satisfies(4));
+    }
+
+    #[tokio::test]
+    async fn test_finality_tags_are_fetched_once_per_pass() {
+        let asserter = Asserter::new();
+        for number in ["0x20", "0x10"] {
+            asserter.push_success(&serde_json::json!({ "number": number }));
+        }
+        let chain = EthereumChain {
+            provider: ProviderBuilder::new()
+                .connect_mocked_client(asserter)
+                .erased(),
+            evm_indexer_client: None,
+            chain_id: 1,
+            token_decimals: HashMap::new(),
+            use_finality_tags: true,
+            finality_tags: Mutex::new(None),
+        };
+
+        chain.begin_monitoring_pass().await.unwrap();
+        // The mock has nothing left to answer with, so these come from the cache
+        for _ in 0..3 {
+            assert_eq!(
+                chain.finality_tags().await.unwrap(),
+                Some(FinalityTags {
+                    safe: 0x20,
+                    finalized: 0x10,
+                })
+            );
+        }
+
+        let chain = chain.with_finality_tags(false);
+        assert_eq!(chain.finality_tags().await.unwrap(), None);
+        assert!(!chain.reports_finality());
+    }
+
+    /// Calldata with a selector and arguments, followed by `nonces`
+    fn calldata_with_nonces(nonces: &[[u8; 16]]) -> Vec<u8> {
+        let mut input = vec![0xab; 4 + 32 * 6];
+        for nonce in nonces {
+            input.extend_from_slice(nonce);
+        }
+        input
+    }
+
+    #[test]
+    fn test_mm_payment_index_of_single_fill() {
+        let nonce = [1u8; 16];
+        let input = calldata_with_nonces(&[nonce]);
+        assert_eq!(mm_payment_index(&input, 2, &nonce), Some(0));
+        assert_eq!(mm_payment_index(&input, 2, &[2u8; 16]), None);
+        // A payout without its fee transfer isn't a market maker payment
+        assert_eq!(mm_payment_index(&input, 1, &nonce), None);
+        assert_eq!(mm_payment_index(&input, 0, &nonce), None);
+    }
+
+    #[test]
+    fn test_mm_payment_index_of_batched_fills() {
+        let nonces = [[1u8; 16], [2u8; 16], [3u8; 16]];
+        let input = calldata_with_nonces(&nonces);
+        for (index, nonce) in nonces.iter().enumerate() {
+            assert_eq!(mm_payment_index(&input, 6, nonce), Some(index));
+        }
+
+        // Only the trailing nonce per swap counts, a nonce elsewhere in the calldata doesn't
+        assert_eq!(mm_payment_index(&input, 2, &nonces[0]), None);
+        assert_eq!(mm_payment_index(&input, 2, &nonces[2]), Some(0));
+
+        // More swaps than the calldata has room for
+        assert_eq!(mm_payment_index(&[0u8; 20], 4, &[0u8; 16]), None);
+    }
+
+    #[test]
+    fn test_mm_payment_index_rejects_repeated_nonces() {
+        let nonce = [7u8; 16];
+        let input = calldata_with_nonces(&[nonce, nonce]);
+        assert_eq!(mm_payment_index(&input, 4, &nonce), None);
+    }
+}
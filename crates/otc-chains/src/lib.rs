@@ -1,6 +1,11 @@
+pub mod deposit_key;
+pub mod dust;
 pub mod error;
 pub mod key_derivation;
+pub mod payment_uri;
 pub mod registry;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub mod traits;
 
 // Chain implementations
@@ -9,4 +14,4 @@ pub mod ethereum;
 
 pub use error::{Error, Result};
 pub use registry::ChainRegistry;
-pub use traits::ChainOperations;
\ No newline at end of file
+pub use traits::{ChainOperations, NetworkFees, PriorityFees};
\ No newline at end of file
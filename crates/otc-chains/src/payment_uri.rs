@@ -0,0 +1,78 @@
+//! Payment request URIs wallets can open or scan to pay a deposit
+
+use alloy::primitives::U256;
+use otc_models::Lot;
+
+/// BIP-21 URI, the amount in BTC
+#[must_use]
+pub fn bip21_uri(address: &str, lot: &Lot) -> String {
+    format!(
+        "bitcoin:{address}?amount={}",
+        lot.currency.format_amount(lot.amount)
+    )
+}
+
+/// EIP-681 URI calling `transfer` on an ERC-20, the amount in base units
+#[must_use]
+pub fn eip681_erc20_transfer_uri(token: &str, chain_id: u64, to: &str, amount: U256) -> String {
+    format!("ethereum:{token}@{chain_id}/transfer?address={to}&uint256={amount}")
+}
+
+/// EIP-681 URI sending ether, the value in wei
+#[must_use]
+pub fn eip681_native_uri(to: &str, chain_id: u64, value: U256) -> String {
+    format!("ethereum:{to}@{chain_id}?value={value}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otc_models::{ChainType, Currency, TokenIdentifier};
+
+    fn btc(sats: u64) -> Lot {
+        Lot {
+            currency: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(sats),
+        }
+    }
+
+    #[test]
+    fn test_bip21_amount_uses_the_lot_decimals() {
+        let address = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+        assert_eq!(
+            bip21_uri(address, &btc(10_000_000)),
+            format!("bitcoin:{address}?amount=0.1")
+        );
+        assert_eq!(
+            bip21_uri(address, &btc(123_456_789)),
+            format!("bitcoin:{address}?amount=1.23456789")
+        );
+        assert_eq!(
+            bip21_uri(address, &btc(500_000_000)),
+            format!("bitcoin:{address}?amount=5")
+        );
+        assert_eq!(
+            bip21_uri(address, &btc(546)),
+            format!("bitcoin:{address}?amount=0.00000546")
+        );
+    }
+
+    #[test]
+    fn test_eip681_uris() {
+        let token = "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf";
+        let to = "0x1111111111111111111111111111111111111111";
+        assert_eq!(
+            eip681_erc20_transfer_uri(token, 1, to, U256::from(9_990_000u64)),
+            format!("ethereum:{token}@1/transfer?address={to}&uint256=9990000")
+        );
+        assert_eq!(
+            eip681_native_uri(to, 31337, U256::from(10u64).pow(U256::from(18u64))),
+            format!("ethereum:{to}@31337?value=1000000000000000000")
+        );
+    }
+}
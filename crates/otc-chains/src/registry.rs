@@ -1,10 +1,12 @@
-use otc_models::ChainType;
+use otc_models::{ChainNetwork, ChainType, Currency};
 use std::collections::HashMap;
 use std::sync::Arc;
 use crate::traits::ChainOperations;
 
+/// Chain adapters by network. Each chain type has a primary network, EVM chains
+/// may also have others registered under their chain id
 pub struct ChainRegistry {
-    chains: HashMap<ChainType, Arc<dyn ChainOperations>>,
+    chains: HashMap<ChainNetwork, Arc<dyn ChainOperations>>,
 }
 
 impl ChainRegistry {
@@ -13,16 +15,45 @@ impl ChainRegistry {
             chains: HashMap::new(),
         }
     }
-    
+
+    /// Register the primary network of `chain_type`
     pub fn register(&mut self, chain_type: ChainType, implementation: Arc<dyn ChainOperations>) {
-        self.chains.insert(chain_type, implementation);
+        self.register_network(ChainNetwork::primary(chain_type), implementation);
+    }
+
+    pub fn register_network(
+        &mut self,
+        network: ChainNetwork,
+        implementation: Arc<dyn ChainOperations>,
+    ) {
+        self.chains.insert(network, implementation);
     }
-    
+
+    /// The primary network of `chain_type`
     #[must_use] pub fn get(&self, chain_type: &ChainType) -> Option<Arc<dyn ChainOperations>> {
-        self.chains.get(chain_type).cloned()
+        self.get_network(&ChainNetwork::primary(*chain_type))
+    }
+
+    #[must_use] pub fn get_network(&self, network: &ChainNetwork) -> Option<Arc<dyn ChainOperations>> {
+        self.chains.get(network).cloned()
     }
-    
+
+    /// The network `currency` lives on
+    #[must_use] pub fn for_currency(&self, currency: &Currency) -> Option<Arc<dyn ChainOperations>> {
+        self.get_network(&currency.network())
+    }
+
+    /// Chain types with a registered primary network
     #[must_use] pub fn supported_chains(&self) -> Vec<ChainType> {
+        self.chains
+            .keys()
+            .filter(|network| network.chain_id.is_none())
+            .map(|network| network.chain)
+            .collect()
+    }
+
+    /// Every registered network, primary ones included
+    #[must_use] pub fn networks(&self) -> Vec<ChainNetwork> {
         self.chains.keys().copied().collect()
     }
 }
@@ -31,4 +62,4 @@ impl Default for ChainRegistry {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,232 @@
+use crate::traits::MarketMakerPaymentValidation;
+use crate::{ChainOperations, NetworkFees, Result};
+use alloy::hex;
+use alloy::primitives::U256;
+use async_trait::async_trait;
+use otc_models::{Currency, Lot, TokenIdentifier, TransferInfo, TxStatus, Wallet};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Chain for tests that answers from what it was configured with and counts
+/// what it was asked. Searches find nothing, transactions aren't found and
+/// balances are empty unless configured otherwise
+pub struct StubChain {
+    /// Transfer found at each address
+    transfers: HashMap<String, TransferInfo>,
+    /// Transfer found at addresses without one of their own
+    any_transfer: Option<TransferInfo>,
+    statuses: HashMap<String, TxStatus>,
+    default_status: TxStatus,
+    reports_finality: bool,
+    balances: HashMap<String, U256>,
+    /// Fees quoted, `None` for a fee backend that's down
+    network_fees: Mutex<Option<NetworkFees>>,
+    /// How long searches and fee requests take
+    delay: Duration,
+    block_time: Duration,
+    searched: Mutex<HashSet<String>>,
+    searches: AtomicUsize,
+    status_checks: AtomicUsize,
+    fee_requests: AtomicUsize,
+}
+
+impl Default for StubChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StubChain {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            transfers: HashMap::new(),
+            any_transfer: None,
+            statuses: HashMap::new(),
+            default_status: TxStatus::NotFound,
+            reports_finality: false,
+            balances: HashMap::new(),
+            network_fees: Mutex::new(None),
+            delay: Duration::ZERO,
+            block_time: Duration::from_secs(1),
+            searched: Mutex::new(HashSet::new()),
+            searches: AtomicUsize::new(0),
+            status_checks: AtomicUsize::new(0),
+            fee_requests: AtomicUsize::new(0),
+        }
+    }
+
+    /// Searches for transfers to `address` find `transfer`
+    #[must_use]
+    pub fn with_transfer(mut self, address: impl Into<String>, transfer: TransferInfo) -> Self {
+        self.transfers.insert(address.into(), transfer);
+        self
+    }
+
+    /// Searches for transfers to any other address find `transfer`
+    #[must_use]
+    pub fn with_any_transfer(mut self, transfer: TransferInfo) -> Self {
+        self.any_transfer = Some(transfer);
+        self
+    }
+
+    #[must_use]
+    pub fn with_tx_status(mut self, tx_hash: impl Into<String>, status: TxStatus) -> Self {
+        self.statuses.insert(tx_hash.into(), status);
+        self
+    }
+
+    /// Status of every transaction without one of its own
+    #[must_use]
+    pub fn with_default_tx_status(mut self, status: TxStatus) -> Self {
+        self.default_status = status;
+        self
+    }
+
+    #[must_use]
+    pub fn with_finality(mut self) -> Self {
+        self.reports_finality = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_balance(mut self, address: impl Into<String>, balance: U256) -> Self {
+        self.balances.insert(address.into(), balance);
+        self
+    }
+
+    #[must_use]
+    pub fn with_network_fees(self, fees: NetworkFees) -> Self {
+        self.set_network_fees(Some(fees));
+        self
+    }
+
+    #[must_use]
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    #[must_use]
+    pub fn with_block_time(mut self, block_time: Duration) -> Self {
+        self.block_time = block_time;
+        self
+    }
+
+    /// Fees quoted from now on, `None` to fail fee requests
+    pub fn set_network_fees(&self, fees: Option<NetworkFees>) {
+        *self.network_fees.lock().unwrap() = fees;
+    }
+
+    /// Addresses searched for transfers so far
+    pub fn searched(&self) -> HashSet<String> {
+        self.searched.lock().unwrap().clone()
+    }
+
+    pub fn searches(&self) -> usize {
+        self.searches.load(Ordering::SeqCst)
+    }
+
+    pub fn status_checks(&self) -> usize {
+        self.status_checks.load(Ordering::SeqCst)
+    }
+
+    pub fn fee_requests(&self) -> usize {
+        self.fee_requests.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl ChainOperations for StubChain {
+    fn create_wallet(&self) -> Result<(Wallet, [u8; 32])> {
+        unimplemented!()
+    }
+
+    fn derive_wallet(&self, _master_key: &[u8], salt: &[u8; 32]) -> Result<Wallet> {
+        Ok(Wallet::new(hex::encode(salt), String::new()))
+    }
+
+    async fn search_for_transfer(
+        &self,
+        recipient_address: &str,
+        _lot: &Lot,
+        _mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        _from_block_height: Option<u64>,
+    ) -> Result<Option<TransferInfo>> {
+        self.searches.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.searched
+            .lock()
+            .unwrap()
+            .insert(recipient_address.to_string());
+        Ok(self
+            .transfers
+            .get(recipient_address)
+            .or(self.any_transfer.as_ref())
+            .cloned())
+    }
+
+    async fn get_tx_status(&self, tx_hash: &str) -> Result<TxStatus> {
+        self.status_checks.fetch_add(1, Ordering::SeqCst);
+        Ok(self
+            .statuses
+            .get(tx_hash)
+            .copied()
+            .unwrap_or(self.default_status))
+    }
+
+    fn reports_finality(&self) -> bool {
+        self.reports_finality
+    }
+
+    async fn get_balance(&self, address: &str, _token: &TokenIdentifier) -> Result<U256> {
+        Ok(self.balances.get(address).copied().unwrap_or_default())
+    }
+
+    async fn sweep(
+        &self,
+        _wallet: &Wallet,
+        _token: &TokenIdentifier,
+        _to_address: &str,
+    ) -> Result<String> {
+        unimplemented!()
+    }
+
+    async fn estimate_transfer_fee(&self, _currency: &Currency) -> Result<U256> {
+        Ok(U256::ZERO)
+    }
+
+    async fn network_fees(&self) -> Result<NetworkFees> {
+        self.fee_requests.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.network_fees
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| crate::Error::Rpc {
+                message: "fee backend is down".to_string(),
+            })
+    }
+
+    fn validate_address(&self, _address: &str) -> bool {
+        true
+    }
+
+    fn private_key_controls_address(&self, _private_key: &str, _address: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn payment_uri(&self, address: &str, _lot: &Lot) -> String {
+        address.to_string()
+    }
+
+    fn minimum_block_confirmations(&self) -> u32 {
+        1
+    }
+
+    fn estimated_block_time(&self) -> Duration {
+        self.block_time
+    }
+}
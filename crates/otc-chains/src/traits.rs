@@ -1,13 +1,63 @@
 use crate::Result;
 use alloy::primitives::U256;
 use async_trait::async_trait;
-use otc_models::{Lot, TransferInfo, TxStatus, Wallet};
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use otc_models::{Currency, Lot, TokenIdentifier, TransferInfo, TxHash, TxStatus, Wallet};
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct MarketMakerPaymentValidation {
     pub fee_amount: U256,
     pub embedded_nonce: [u8; 16],
+    /// When the market maker was told to pay. A payment mined in a block
+    /// timestamped before it, less the chain's clock tolerance, was sent for
+    /// something else and is being replayed. Unconfirmed payments aren't checked
+    pub not_before: Option<DateTime<Utc>>,
+}
+
+impl MarketMakerPaymentValidation {
+    /// Whether a payment mined at `block_time` predates [`Self::not_before`]
+    /// by more than `tolerance`, the drift block timestamps are allowed
+    #[must_use]
+    pub fn mined_too_early(&self, block_time: DateTime<Utc>, tolerance: Duration) -> bool {
+        self.not_before.is_some_and(|not_before| {
+            chrono::Duration::from_std(tolerance)
+                .is_ok_and(|tolerance| block_time < not_before - tolerance)
+        })
+    }
+}
+
+/// A transfer a subscription saw land. Only a hint, like the indexer's, the
+/// transfer still has to be found by [`ChainOperations::search_for_transfer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferEvent {
+    pub tx_hash: TxHash,
+    pub amount: U256,
+    pub block_number: Option<u64>,
+}
+
+/// Priority fees recently paid on an EVM chain, in wei
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityFees {
+    pub low: u128,
+    pub medium: u128,
+    pub high: u128,
+}
+
+/// What getting a transaction confirmed costs on a chain right now
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkFees {
+    Bitcoin {
+        /// Fee rates in sat/vB keyed by confirmation target in blocks
+        fee_estimates: BTreeMap<u16, f64>,
+    },
+    Ethereum {
+        /// Base fee of the next block, in wei
+        next_base_fee_wei: u128,
+        priority_fees_wei: PriorityFees,
+    },
 }
 
 // implementors of this trait should be stateless
@@ -19,7 +69,8 @@ pub trait ChainOperations: Send + Sync {
     /// Derive a wallet deterministically from a master key and salt
     fn derive_wallet(&self, master_key: &[u8], salt: &[u8; 32]) -> Result<Wallet>;
 
-    /// Check for transfers to an address
+    /// Check for transfers to an address of at least the lot amount. A market maker
+    /// payment is matched by its nonce whatever its amount, callers must compare it
     async fn search_for_transfer(
         &self,
         recipient_address: &str,
@@ -33,6 +84,34 @@ pub trait ChainOperations: Send + Sync {
     /// Get the status of a transaction
     async fn get_tx_status(&self, tx_hash: &str) -> Result<TxStatus>;
 
+    /// Whether statuses carry a finality signal on top of confirmations. When they
+    /// don't, a transfer search's confirmation count is as good as a status check
+    fn reports_finality(&self) -> bool {
+        false
+    }
+
+    /// Called at the start of each monitoring pass over this chain, so what every
+    /// status check of the pass needs is fetched once
+    async fn begin_monitoring_pass(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether [`Self::subscribe_transfers`] can push transfers as they land,
+    /// sparing callers a poll
+    fn supports_subscriptions(&self) -> bool {
+        false
+    }
+
+    /// Transfers of `currency` to `address` as they land. The stream ends when
+    /// the subscription drops, callers resubscribe
+    async fn subscribe_transfers(
+        &self,
+        _address: &str,
+        _currency: &Currency,
+    ) -> Result<BoxStream<'static, TransferEvent>> {
+        Err(crate::Error::SubscriptionsUnsupported)
+    }
+
     /// Send funds from a wallet
     /// TODO: Reason about how refunds will work and create a type around this
     /*
@@ -44,9 +123,36 @@ pub trait ChainOperations: Send + Sync {
     ) -> Result<String>; // Returns tx hash
      */
 
+    /// Balance of `token` held by `address`, in the token's smallest unit
+    async fn get_balance(&self, address: &str, token: &TokenIdentifier) -> Result<U256>;
+
+    /// Send everything `wallet` holds of `token` to `to_address`, paying the network
+    /// fee from the wallet. Returns the tx hash.
+    async fn sweep(
+        &self,
+        wallet: &Wallet,
+        token: &TokenIdentifier,
+        to_address: &str,
+    ) -> Result<String>;
+
+    /// Network fee of transferring `currency` out of a deposit wallet, in the
+    /// chain's native currency's smallest unit
+    async fn estimate_transfer_fee(&self, currency: &Currency) -> Result<U256>;
+
+    /// Fees the network currently asks, for clients to show before they send
+    async fn network_fees(&self) -> Result<NetworkFees> {
+        Err(crate::Error::NetworkFeesUnsupported)
+    }
+
     /// Validate an address format
     fn validate_address(&self, address: &str) -> bool;
 
+    /// Whether a deposit wallet's private key controls `address`
+    fn private_key_controls_address(&self, private_key: &str, address: &str) -> Result<bool>;
+
+    /// URI a wallet can open to pay `lot` to `address`
+    fn payment_uri(&self, address: &str, lot: &Lot) -> String;
+
     /// Get minimum recommended confirmations
     fn minimum_block_confirmations(&self) -> u32;
 
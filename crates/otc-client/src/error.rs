@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use otc_api_types::{ApiErrorCode, ApiErrorResponse, RfqErrorResponse};
+use otc_models::{FieldError, SwapStatus};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use snafu::{ResultExt, Snafu};
+use uuid::Uuid;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("Failed to build HTTP client: {source}"))]
+    BuildClient { source: reqwest::Error },
+
+    #[snafu(display("Invalid base URL: {source}"))]
+    InvalidUrl { source: url::ParseError },
+
+    #[snafu(display("Failed to send request: {source}"))]
+    Request { source: reqwest::Error },
+
+    #[snafu(display("Failed to parse response: {source}"))]
+    ParseResponse { source: reqwest::Error },
+
+    /// The server answered with an error status
+    #[snafu(display("Server returned {status}: {message}"))]
+    Api {
+        status: StatusCode,
+        /// The stable code of the error, `None` for the plain text errors
+        /// raised before a handler runs
+        code: Option<ApiErrorCode>,
+        message: String,
+        /// The offending fields of a request that failed validation
+        fields: Vec<FieldError>,
+    },
+
+    #[snafu(display(
+        "Swap {swap_id} did not reach {expected:?} within {timeout:?}, last status {last_status}"
+    ))]
+    WaitTimeout {
+        swap_id: Uuid,
+        expected: SwapStatus,
+        timeout: Duration,
+        last_status: String,
+    },
+}
+
+impl Error {
+    /// The HTTP status the server answered with, if it answered
+    #[must_use]
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Api { status, .. } => Some(*status),
+            Self::Request { source } | Self::ParseResponse { source } => source.status(),
+            _ => None,
+        }
+    }
+
+    /// The stable code of a server error
+    #[must_use]
+    pub fn code(&self) -> Option<ApiErrorCode> {
+        match self {
+            Self::Api { code, .. } => *code,
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Parse a successful response as `T`, or turn an error response into
+/// [`Error::Api`]
+pub(crate) async fn parse_response<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let status = response.status();
+    if status.is_success() {
+        return response.json::<T>().await.context(ParseResponseSnafu);
+    }
+    let body = response.text().await.context(RequestSnafu)?;
+    Err(api_error(status, &body))
+}
+
+/// The error described by `body`, in either server's error format
+fn api_error(status: StatusCode, body: &str) -> Error {
+    if let Ok(ApiErrorResponse { error }) = serde_json::from_str(body) {
+        return Error::Api {
+            status,
+            code: Some(error.code),
+            message: error.details,
+            fields: error.fields.unwrap_or_default(),
+        };
+    }
+    if let Ok(RfqErrorResponse {
+        error,
+        code,
+        fields,
+    }) = serde_json::from_str(body)
+    {
+        return Error::Api {
+            status,
+            code: Some(code),
+            message: error,
+            fields: fields.unwrap_or_default(),
+        };
+    }
+    // Errors raised before a handler runs (auth, body limits) are plain text
+    Error::Api {
+        status,
+        code: None,
+        message: body.to_string(),
+        fields: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_both_error_formats() {
+        let otc = r#"{"error":{"code":"VALIDATION_FAILED","message":"Validation error","details":"Invalid request fields: trace_id: bad","fields":[{"field":"trace_id","message":"bad"}]}}"#;
+        let Error::Api {
+            code,
+            message,
+            fields,
+            ..
+        } = api_error(StatusCode::BAD_REQUEST, otc)
+        else {
+            panic!("Should be an API error");
+        };
+        assert_eq!(code, Some(ApiErrorCode::ValidationFailed));
+        assert_eq!(message, "Invalid request fields: trace_id: bad");
+        assert_eq!(fields, vec![FieldError::new("trace_id", "bad")]);
+
+        let otc_generic = r#"{"error":{"code":"NOT_FOUND","message":"Resource not found","details":"Resource not found"}}"#;
+        let error = api_error(StatusCode::NOT_FOUND, otc_generic);
+        assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(error.code(), Some(ApiErrorCode::NotFound));
+
+        let rfq = r#"{"error":"No quotes available","code":"NOT_FOUND"}"#;
+        let Error::Api { code, message, .. } = api_error(StatusCode::NOT_FOUND, rfq) else {
+            panic!("Should be an API error");
+        };
+        assert_eq!(code, Some(ApiErrorCode::NotFound));
+        assert_eq!(message, "No quotes available");
+
+        let rfq_with_code = r#"{"error":"Decimals mismatch: cbBTC has 8 decimals, got 18","code":"DECIMALS_MISMATCH"}"#;
+        let error = api_error(StatusCode::BAD_REQUEST, rfq_with_code);
+        assert_eq!(error.code(), Some(ApiErrorCode::DecimalsMismatch));
+
+        let Error::Api { message, .. } = api_error(StatusCode::UNAUTHORIZED, "Invalid API key")
+        else {
+            panic!("Should be an API error");
+        };
+        assert_eq!(message, "Invalid API key");
+    }
+}
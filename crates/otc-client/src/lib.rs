@@ -0,0 +1,11 @@
+//! Typed clients for the OTC server and RFQ server HTTP APIs
+
+mod error;
+mod otc;
+mod rfq;
+
+pub use error::*;
+pub use otc::OtcApiClient;
+pub use rfq::RfqApiClient;
+
+pub use otc_api_types as types;
@@ -0,0 +1,266 @@
+use std::time::{Duration, Instant};
+
+use otc_api_types::{
+    CreateSwapRequest, CreateSwapResponse, SetRefundAddressRequest, SwapLookup, SwapLookupQuery,
+    SwapReceipt, SwapResponse, IDEMPOTENCY_KEY_HEADER,
+};
+use otc_models::SwapStatus;
+use reqwest::{Client, Url};
+use snafu::ResultExt;
+use uuid::Uuid;
+
+use crate::{
+    error::{parse_response, BuildClientSnafu, InvalidUrlSnafu, RequestSnafu, WaitTimeoutSnafu},
+    Result,
+};
+
+/// How often [`OtcApiClient::wait_for_status`] polls the swap
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Client for the OTC server's public swap API
+#[derive(Debug, Clone)]
+pub struct OtcApiClient {
+    client: Client,
+    base_url: Url,
+}
+
+impl OtcApiClient {
+    pub fn new(base_url: impl AsRef<str>) -> Result<Self> {
+        let client = Client::builder().build().context(BuildClientSnafu)?;
+        let base_url = Url::parse(base_url.as_ref()).context(InvalidUrlSnafu)?;
+
+        Ok(Self { client, base_url })
+    }
+
+    fn url(&self, path: &str) -> Result<Url> {
+        self.base_url.join(path).context(InvalidUrlSnafu)
+    }
+
+    /// POST /api/v1/swaps
+    pub async fn create_swap(&self, request: &CreateSwapRequest) -> Result<CreateSwapResponse> {
+        let response = self
+            .client
+            .post(self.url("api/v1/swaps")?)
+            .json(request)
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// POST /api/v1/swaps with an `Idempotency-Key`, so retrying with the same
+    /// key and request returns the swap the first attempt created
+    pub async fn create_swap_idempotent(
+        &self,
+        idempotency_key: &str,
+        request: &CreateSwapRequest,
+    ) -> Result<CreateSwapResponse> {
+        let response = self
+            .client
+            .post(self.url("api/v1/swaps")?)
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+            .json(request)
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// GET /api/v1/swaps/:id
+    pub async fn get_swap(&self, swap_id: Uuid) -> Result<SwapResponse> {
+        let response = self
+            .client
+            .get(self.url(&format!("api/v1/swaps/{swap_id}"))?)
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// GET /api/v1/swaps/:id/receipt, only available once the swap settled
+    pub async fn get_swap_receipt(&self, swap_id: Uuid) -> Result<SwapReceipt> {
+        let response = self
+            .client
+            .get(self.url(&format!("api/v1/swaps/{swap_id}/receipt"))?)
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// PATCH /api/v1/swaps/:id/refund-address, signed by the swap's user EVM
+    /// account over [`otc_api_types::refund_address_message`]
+    pub async fn set_refund_address(
+        &self,
+        swap_id: Uuid,
+        request: &SetRefundAddressRequest,
+    ) -> Result<SwapResponse> {
+        let response = self
+            .client
+            .patch(self.url(&format!("api/v1/swaps/{swap_id}/refund-address"))?)
+            .json(request)
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// GET /api/v1/swaps/lookup, every matching swap, newest first
+    pub async fn list_swaps(&self, lookup: SwapLookup) -> Result<Vec<SwapResponse>> {
+        let response = self
+            .client
+            .get(self.url("api/v1/swaps/lookup")?)
+            .query(&SwapLookupQuery::from(lookup))
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// The newest swap matching `lookup`, if any
+    pub async fn lookup_swap(&self, lookup: SwapLookup) -> Result<Option<SwapResponse>> {
+        Ok(self.list_swaps(lookup).await?.into_iter().next())
+    }
+
+    /// Poll the swap until it reaches `status`, giving up after `timeout`
+    pub async fn wait_for_status(
+        &self,
+        swap_id: Uuid,
+        status: SwapStatus,
+        timeout: Duration,
+    ) -> Result<SwapResponse> {
+        // The API reports statuses by their variant name
+        let expected = format!("{status:?}");
+        let deadline = Instant::now() + timeout;
+        loop {
+            let swap = self.get_swap(swap_id).await?;
+            if swap.status == expected {
+                return Ok(swap);
+            }
+            if Instant::now() >= deadline {
+                return WaitTimeoutSnafu {
+                    swap_id,
+                    expected: status,
+                    timeout,
+                    last_status: swap.status,
+                }
+                .fail();
+            }
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use otc_api_types::ApiErrorCode;
+    use serde_json::{json, Value};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn swap(id: Uuid, status: &str) -> Value {
+        let deposit = json!({
+            "address": "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080",
+            "chain": "Bitcoin",
+            "expected_amount": "0x989680",
+            "decimals": 8,
+            "token": "Native",
+            "required_confirmations": 2,
+            "deposit_tx": null,
+            "deposit_amount": null,
+            "deposit_detected_at": null,
+        });
+        json!({
+            "id": id,
+            "quote_id": Uuid::new_v4(),
+            "status": status,
+            "created_at": "2025-01-01T00:00:00Z",
+            "updated_at": "2025-01-01T00:00:00Z",
+            "user_deposit": deposit,
+            "mm_deposit": deposit,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_lookup_swap_returns_the_newest_match() {
+        let server = MockServer::start().await;
+        let (newest, oldest) = (Uuid::new_v4(), Uuid::new_v4());
+        let tx_hash = "7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730";
+        Mock::given(method("GET"))
+            .and(path("/api/v1/swaps/lookup"))
+            .and(query_param("tx_hash", tx_hash))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                swap(newest, "Settled"),
+                swap(oldest, "RefundingUser"),
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = OtcApiClient::new(server.uri()).unwrap();
+        let found = client
+            .lookup_swap(SwapLookup::TxHash(tx_hash.parse().unwrap()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, newest);
+    }
+
+    #[tokio::test]
+    async fn test_error_responses_carry_their_code() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/swaps/lookup"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+                "error": {
+                    "code": "RATE_LIMITED",
+                    "message": "Too many requests",
+                    "details": "Rate limited: Too many swap lookups, try again in a minute",
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = OtcApiClient::new(server.uri()).unwrap();
+        let error = client
+            .list_swaps(SwapLookup::DepositAddress("bc1qaddress".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(error.code(), Some(ApiErrorCode::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_status_times_out_with_the_last_status() {
+        let server = MockServer::start().await;
+        let swap_id = Uuid::new_v4();
+        Mock::given(method("GET"))
+            .and(path(format!("/api/v1/swaps/{swap_id}")))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(swap(swap_id, "WaitingUserDepositInitiated")),
+            )
+            .mount(&server)
+            .await;
+
+        let client = OtcApiClient::new(server.uri()).unwrap();
+        let settled = client
+            .wait_for_status(swap_id, SwapStatus::Settled, Duration::ZERO)
+            .await;
+        assert!(matches!(
+            settled,
+            Err(Error::WaitTimeout { last_status, .. }) if last_status == "WaitingUserDepositInitiated"
+        ));
+
+        let waiting = client
+            .wait_for_status(
+                swap_id,
+                SwapStatus::WaitingUserDepositInitiated,
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+        assert_eq!(waiting.id, swap_id);
+    }
+}
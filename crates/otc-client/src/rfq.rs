@@ -0,0 +1,109 @@
+use otc_api_types::{
+    QuoteBatchRequest, QuoteBatchResponse, QuoteLock, QuoteResponse, TRACE_ID_HEADER,
+};
+use otc_models::QuoteRequest;
+use reqwest::{Client, Url};
+use snafu::ResultExt;
+use uuid::Uuid;
+
+use crate::{
+    error::{parse_response, BuildClientSnafu, InvalidUrlSnafu, RequestSnafu},
+    Result,
+};
+
+/// Client for the RFQ server's quote API
+#[derive(Debug, Clone)]
+pub struct RfqApiClient {
+    client: Client,
+    base_url: Url,
+    /// Id and secret of the API key quote locks are requested with
+    api_key: Option<(Uuid, String)>,
+}
+
+impl RfqApiClient {
+    pub fn new(base_url: impl AsRef<str>) -> Result<Self> {
+        let client = Client::builder().build().context(BuildClientSnafu)?;
+        let base_url = Url::parse(base_url.as_ref()).context(InvalidUrlSnafu)?;
+
+        Ok(Self {
+            client,
+            base_url,
+            api_key: None,
+        })
+    }
+
+    /// Lock quotes with the API key `id` and `secret`, which needs the lock scope
+    #[must_use]
+    pub fn with_api_key(mut self, id: Uuid, secret: impl Into<String>) -> Self {
+        self.api_key = Some((id, secret.into()));
+        self
+    }
+
+    /// POST /api/v1/quotes/request, under a fresh correlation id
+    pub async fn request_quote(&self, request: &QuoteRequest) -> Result<QuoteResponse> {
+        self.send_quote_request(request, None).await
+    }
+
+    /// POST /api/v1/quotes/request under the caller's correlation id, which the
+    /// response's `trace_id` carries back when the server accepts it
+    pub async fn request_quote_with_trace_id(
+        &self,
+        request: &QuoteRequest,
+        trace_id: &str,
+    ) -> Result<QuoteResponse> {
+        self.send_quote_request(request, Some(trace_id)).await
+    }
+
+    /// POST /api/v1/quotes/request-batch, quoting every one of `requests` at once
+    pub async fn request_quote_batch(
+        &self,
+        requests: Vec<QuoteRequest>,
+    ) -> Result<QuoteBatchResponse> {
+        let url = self
+            .base_url
+            .join("api/v1/quotes/request-batch")
+            .context(InvalidUrlSnafu)?;
+        let response = self
+            .client
+            .post(url)
+            .json(&QuoteBatchRequest { requests })
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    /// POST /api/v1/quotes/{id}/lock, asking the market maker behind a quote to
+    /// hold its funds. Pass the lock along when creating the swap
+    pub async fn lock_quote(&self, quote_id: Uuid) -> Result<QuoteLock> {
+        let url = self
+            .base_url
+            .join(&format!("api/v1/quotes/{quote_id}/lock"))
+            .context(InvalidUrlSnafu)?;
+        let mut builder = self.client.post(url);
+        if let Some((id, secret)) = &self.api_key {
+            builder = builder
+                .header("x-api-key-id", id.to_string())
+                .header("x-api-key", secret);
+        }
+        let response = builder.send().await.context(RequestSnafu)?;
+        parse_response(response).await
+    }
+
+    async fn send_quote_request(
+        &self,
+        request: &QuoteRequest,
+        trace_id: Option<&str>,
+    ) -> Result<QuoteResponse> {
+        let url = self
+            .base_url
+            .join("api/v1/quotes/request")
+            .context(InvalidUrlSnafu)?;
+        let mut builder = self.client.post(url).json(request);
+        if let Some(trace_id) = trace_id {
+            builder = builder.header(TRACE_ID_HEADER, trace_id);
+        }
+        let response = builder.send().await.context(RequestSnafu)?;
+        parse_response(response).await
+    }
+}
@@ -1,12 +1,66 @@
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 use uuid::Uuid;
 
+/// What a market maker may do with an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    /// Stream quotes to the rfq-server
+    Rfq,
+    /// Fill swaps through the otc-server, which hands over user deposit keys
+    Otc,
+    /// Have the rfq-server lock quotes before a swap is created
+    Lock,
+}
+
+impl ApiKeyScope {
+    pub const ALL: [Self; 3] = [Self::Rfq, Self::Otc, Self::Lock];
+}
+
+impl fmt::Display for ApiKeyScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scope = match self {
+            Self::Rfq => "rfq",
+            Self::Otc => "otc",
+            Self::Lock => "lock",
+        };
+        f.write_str(scope)
+    }
+}
+
+impl FromStr for ApiKeyScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rfq" => Ok(Self::Rfq),
+            "otc" => Ok(Self::Otc),
+            "lock" => Ok(Self::Lock),
+            _ => Err(format!(
+                "invalid API key scope {s:?}, expected rfq, otc or lock"
+            )),
+        }
+    }
+}
+
+fn all_scopes() -> Vec<ApiKeyScope> {
+    ApiKeyScope::ALL.to_vec()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKey {
     pub id: Uuid,
     pub market_maker: String,
     pub hash: String, // PHC format string from Argon2
+    /// Set once the key is revoked, a revoked key no longer authenticates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Keys written before scopes existed get every scope
+    #[serde(default = "all_scopes")]
+    pub scopes: Vec<ApiKeyScope>,
 }
 
 impl ApiKey {
@@ -20,4 +74,14 @@ impl ApiKey {
             false
         }
     }
-}
\ No newline at end of file
+
+    #[must_use]
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    #[must_use]
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
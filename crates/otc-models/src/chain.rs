@@ -1,14 +1,184 @@
 use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ChainType {
     Bitcoin,
     Ethereum,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+impl fmt::Display for ChainType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chain = match self {
+            Self::Bitcoin => "bitcoin",
+            Self::Ethereum => "ethereum",
+        };
+        f.write_str(chain)
+    }
+}
+
+impl FromStr for ChainType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bitcoin" => Ok(Self::Bitcoin),
+            "ethereum" => Ok(Self::Ethereum),
+            _ => Err(format!("invalid chain {s:?}, expected bitcoin or ethereum")),
+        }
+    }
+}
+
+/// A chain and which of its networks. EVM chains can run several networks
+/// side by side (mainnet, Base, Arbitrum...), told apart by chain id. `None` is
+/// the chain's primary network, the only one Bitcoin has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ChainNetwork {
+    pub chain: ChainType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+}
+
+impl ChainNetwork {
+    #[must_use]
+    pub const fn primary(chain: ChainType) -> Self {
+        Self {
+            chain,
+            chain_id: None,
+        }
+    }
+
+    /// An EVM network other than the primary Ethereum one
+    #[must_use]
+    pub const fn evm(chain_id: u64) -> Self {
+        Self {
+            chain: ChainType::Ethereum,
+            chain_id: Some(chain_id),
+        }
+    }
+}
+
+impl From<ChainType> for ChainNetwork {
+    fn from(chain: ChainType) -> Self {
+        Self::primary(chain)
+    }
+}
+
+impl fmt::Display for ChainNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chain_id {
+            Some(chain_id) => write!(f, "{}:{}", self.chain, chain_id),
+            None => write!(f, "{}", self.chain),
+        }
+    }
+}
+
+impl FromStr for ChainNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((chain, chain_id)) = s.split_once(':') else {
+            return Ok(Self::primary(s.parse()?));
+        };
+        let chain_id = chain_id
+            .parse()
+            .map_err(|_| format!("invalid chain id in {s:?}"))?;
+        match chain.parse()? {
+            ChainType::Ethereum => Ok(Self::evm(chain_id)),
+            ChainType::Bitcoin => Err(format!("{chain} has no networks besides its primary one")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TxStatus {
     NotFound,
-    Confirmed(u64), // 0 = in mempool, 1+ = confirmed blocks
-}
\ No newline at end of file
+    /// Broadcast but not in a block yet
+    Pending,
+    Included {
+        /// Blocks mined on top of the one holding the transaction
+        confirmations: u64,
+        finality: Finality,
+    },
+}
+
+/// How settled an included transaction is, on chains that report it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Finality {
+    /// The chain has no finality signal, only confirmations count
+    Unknown,
+    /// Ahead of the chain's safe block, no number of confirmations settles it
+    Unsafe,
+    /// At or behind the safe block, confirmations decide
+    Safe,
+    /// At or behind the finalized block, settled whatever its confirmations
+    Finalized,
+}
+
+impl TxStatus {
+    #[must_use]
+    pub fn confirmations(&self) -> u64 {
+        match self {
+            Self::NotFound | Self::Pending => 0,
+            Self::Included { confirmations, .. } => *confirmations,
+        }
+    }
+
+    /// Whether the transaction is settled enough for a deposit requiring
+    /// `required` confirmations
+    #[must_use]
+    pub fn satisfies(&self, required: u64) -> bool {
+        match self {
+            Self::NotFound | Self::Pending => false,
+            Self::Included {
+                finality: Finality::Finalized,
+                ..
+            } => true,
+            Self::Included {
+                finality: Finality::Unsafe,
+                ..
+            } => false,
+            Self::Included { confirmations, .. } => *confirmations >= required,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finality_overrides_confirmation_counts() {
+        let included = |confirmations, finality| TxStatus::Included {
+            confirmations,
+            finality,
+        };
+
+        assert!(included(6, Finality::Unknown).satisfies(6));
+        assert!(!included(5, Finality::Unknown).satisfies(6));
+        assert!(included(6, Finality::Safe).satisfies(6));
+        assert!(!included(5, Finality::Safe).satisfies(6));
+        // However many blocks pile on, an unsafe block can still be reorged out
+        assert!(!included(1_000, Finality::Unsafe).satisfies(6));
+        assert!(included(0, Finality::Finalized).satisfies(6));
+        assert!(!TxStatus::Pending.satisfies(0));
+        assert!(!TxStatus::NotFound.satisfies(0));
+    }
+
+    #[test]
+    fn test_network_parses_from_its_display() {
+        for network in [
+            ChainNetwork::primary(ChainType::Bitcoin),
+            ChainNetwork::primary(ChainType::Ethereum),
+            ChainNetwork::evm(8453),
+        ] {
+            assert_eq!(network.to_string().parse(), Ok(network));
+        }
+        assert_eq!(ChainNetwork::evm(8453).to_string(), "ethereum:8453");
+        assert!("bitcoin:1".parse::<ChainNetwork>().is_err());
+        assert!("ethereum:base".parse::<ChainNetwork>().is_err());
+    }
+}
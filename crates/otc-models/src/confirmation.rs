@@ -0,0 +1,70 @@
+use crate::ChainType;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How an override raises the configured confirmation baseline of a chain
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationRule {
+    /// Scale the baseline, rounding up
+    Multiplier(f64),
+    /// Require exactly this many confirmations
+    Absolute(u64),
+}
+
+impl ConfirmationRule {
+    #[must_use]
+    pub fn resolve(&self, baseline: u64) -> u64 {
+        match self {
+            ConfirmationRule::Multiplier(multiplier) => {
+                (baseline as f64 * multiplier).ceil() as u64
+            }
+            ConfirmationRule::Absolute(confirmations) => *confirmations,
+        }
+    }
+}
+
+/// An operator issued, time boxed increase of the confirmations required on a chain.
+///
+/// Only swaps created while the override is active are affected, since the
+/// resolved requirement is persisted on the swap at creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ConfirmationOverride {
+    pub id: Uuid,
+    pub chain: ChainType,
+    pub rule: ConfirmationRule,
+    /// Baseline at the time the override was issued
+    pub baseline_confirmations: u64,
+    /// Requirement new swaps on this chain get while the override is active
+    pub required_confirmations: u64,
+    pub reason: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ConfirmationOverride {
+    #[must_use]
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at > now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiplier_rounds_up() {
+        assert_eq!(ConfirmationRule::Multiplier(1.5).resolve(3), 5);
+        assert_eq!(ConfirmationRule::Multiplier(2.0).resolve(2), 4);
+        assert_eq!(ConfirmationRule::Multiplier(1.0).resolve(4), 4);
+    }
+
+    #[test]
+    fn test_absolute_ignores_baseline() {
+        assert_eq!(ConfirmationRule::Absolute(12).resolve(2), 12);
+    }
+}
@@ -0,0 +1,495 @@
+//! Registry of the currencies swaps can be quoted and settled in
+//!
+//! Loaded at startup by the OTC server and the market maker from a TOML or
+//! JSON file, so tokens can be added without a rebuild:
+//!
+//! ```toml
+//! [[currencies]]
+//! chain = "bitcoin"
+//! symbol = "BTC"
+//! decimals = 8
+//! min_amount = "10000"
+//! max_amount = "10000000000"
+//!
+//! [[currencies]]
+//! chain = "ethereum"
+//! address = "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf"
+//! symbol = "cbBTC"
+//! decimals = 8
+//! min_amount = "10000"
+//! max_amount = "10000000000"
+//! ```
+//!
+//! Entries without an `address` are the chain's native asset. Amounts are in
+//! base units and inclusive. An Ethereum entry with a `chain_id` lives on that
+//! EVM network instead of the primary one, which the OTC server and the market
+//! maker must both be connected to:
+//!
+//! ```toml
+//! [[currencies]]
+//! chain = "ethereum"
+//! chain_id = 8453
+//! address = "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf"
+//! symbol = "cbBTC.base"
+//! decimals = 8
+//! min_amount = "10000"
+//! max_amount = "10000000000"
+//! ```
+
+use std::path::Path;
+use std::str::FromStr;
+
+use alloy::primitives::U256;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt, Snafu};
+
+use crate::{ChainNetwork, ChainType, Currency, Lot, TokenIdentifier};
+
+/// cbBTC on Ethereum mainnet
+pub const CBBTC_ADDRESS: &str = "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf";
+
+/// cbBTC is denominated in satoshis, like BTC
+pub const CBBTC_DECIMALS: u8 = 8;
+
+#[derive(Debug, Snafu)]
+pub enum SupportedCurrenciesError {
+    #[snafu(display("Failed to read supported currencies file: {}", source))]
+    Read { source: std::io::Error },
+
+    #[snafu(display("Failed to parse supported currencies TOML: {}", source))]
+    ParseToml { source: toml::de::Error },
+
+    #[snafu(display("Failed to parse supported currencies JSON: {}", source))]
+    ParseJson { source: serde_json::Error },
+
+    #[snafu(display("Invalid supported currency {}: {}", symbol, message))]
+    InvalidCurrency { symbol: String, message: String },
+}
+
+/// Why a currency or lot was refused
+#[derive(Debug, Clone, PartialEq, Eq, Snafu)]
+pub enum UnsupportedCurrency {
+    #[snafu(display("Token {:?} on {} is not supported", token, network))]
+    UnsupportedToken {
+        network: ChainNetwork,
+        token: TokenIdentifier,
+    },
+
+    #[snafu(display("{} has {} decimals, got {}", symbol, expected, actual))]
+    DecimalsMismatch {
+        symbol: String,
+        expected: u8,
+        actual: u8,
+    },
+
+    #[snafu(display("{} amount {} is outside of [{}, {}]", symbol, amount, min, max))]
+    AmountOutOfRange {
+        symbol: String,
+        amount: U256,
+        min: U256,
+        max: U256,
+    },
+}
+
+/// A token swaps can be quoted and settled in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SupportedCurrency {
+    pub chain: ChainType,
+    /// EVM network of the token, the chain's primary network when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    pub token: TokenIdentifier,
+    pub decimals: u8,
+    pub symbol: String,
+    #[cfg_attr(feature = "utoipa", schema(value_type = crate::U256Schema))]
+    pub min_amount: U256,
+    #[cfg_attr(feature = "utoipa", schema(value_type = crate::U256Schema))]
+    pub max_amount: U256,
+}
+
+impl SupportedCurrency {
+    #[must_use]
+    pub fn currency(&self) -> Currency {
+        Currency {
+            chain: self.chain,
+            token: self.token.clone(),
+            decimals: self.decimals,
+            chain_id: self.chain_id,
+        }
+    }
+
+    #[must_use]
+    pub fn network(&self) -> ChainNetwork {
+        ChainNetwork {
+            chain: self.chain,
+            chain_id: self.chain_id,
+        }
+    }
+
+    fn matches(&self, network: ChainNetwork, token: &TokenIdentifier) -> bool {
+        self.network() == network && self.token.is_same_token(token)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupportedCurrencies {
+    currencies: Vec<SupportedCurrency>,
+}
+
+/// On-disk layout of the supported currencies file
+#[derive(Deserialize)]
+struct SupportedCurrenciesFile {
+    currencies: Vec<SupportedCurrencyEntry>,
+}
+
+#[derive(Deserialize)]
+struct SupportedCurrencyEntry {
+    chain: ChainType,
+    /// EVM network, the chain's primary network when unset
+    chain_id: Option<u64>,
+    /// Token contract, the native asset when unset
+    address: Option<String>,
+    symbol: String,
+    decimals: u8,
+    /// Decimal or `0x` hex string, in base units
+    min_amount: String,
+    max_amount: String,
+}
+
+impl SupportedCurrencies {
+    /// Load from a `.json` file, or TOML for any other extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SupportedCurrenciesError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).context(ReadSnafu)?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json(&contents)
+        } else {
+            Self::from_toml(&contents)
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, SupportedCurrenciesError> {
+        let file: SupportedCurrenciesFile = toml::from_str(contents).context(ParseTomlSnafu)?;
+        Self::from_entries(file.currencies)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, SupportedCurrenciesError> {
+        let file: SupportedCurrenciesFile =
+            serde_json::from_str(contents).context(ParseJsonSnafu)?;
+        Self::from_entries(file.currencies)
+    }
+
+    fn from_entries(
+        entries: Vec<SupportedCurrencyEntry>,
+    ) -> Result<Self, SupportedCurrenciesError> {
+        let mut currencies: Vec<SupportedCurrency> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let invalid = |message: String| InvalidCurrencySnafu {
+                symbol: entry.symbol.clone(),
+                message,
+            };
+            let parse_amount = |value: &str| {
+                U256::from_str(value.trim())
+                    .ok()
+                    .ok_or_else(|| invalid(format!("invalid amount {value:?}")).build())
+            };
+
+            let token = match &entry.address {
+                Some(address) => {
+                    ensure!(
+                        entry.chain != ChainType::Bitcoin,
+                        invalid("bitcoin has no token contracts".to_string())
+                    );
+                    TokenIdentifier::address(address).map_err(|e| invalid(e.reason).build())?
+                }
+                None => TokenIdentifier::Native,
+            };
+            ensure!(
+                entry.chain_id.is_none() || entry.chain != ChainType::Bitcoin,
+                invalid("bitcoin has no chain ids".to_string())
+            );
+            let network = ChainNetwork {
+                chain: entry.chain,
+                chain_id: entry.chain_id,
+            };
+            let min_amount = parse_amount(&entry.min_amount)?;
+            let max_amount = parse_amount(&entry.max_amount)?;
+            ensure!(
+                min_amount <= max_amount,
+                invalid("min_amount is above max_amount".to_string())
+            );
+            ensure!(
+                !currencies.iter().any(|c| c.matches(network, &token)),
+                invalid("listed twice".to_string())
+            );
+
+            currencies.push(SupportedCurrency {
+                chain: entry.chain,
+                chain_id: entry.chain_id,
+                token,
+                decimals: entry.decimals,
+                symbol: entry.symbol,
+                min_amount,
+                max_amount,
+            });
+        }
+
+        Ok(Self { currencies })
+    }
+
+    /// Every configured currency, in file order
+    #[must_use]
+    pub fn all(&self) -> &[SupportedCurrency] {
+        &self.currencies
+    }
+
+    /// Configured currencies on `network`, a bare chain type being its primary network
+    pub fn on_chain(
+        &self,
+        network: impl Into<ChainNetwork>,
+    ) -> impl Iterator<Item = &SupportedCurrency> {
+        let network = network.into();
+        self.currencies
+            .iter()
+            .filter(move |c| c.network() == network)
+    }
+
+    /// Every network with a configured currency, in file order
+    #[must_use]
+    pub fn networks(&self) -> Vec<ChainNetwork> {
+        let mut networks = Vec::new();
+        for currency in &self.currencies {
+            if !networks.contains(&currency.network()) {
+                networks.push(currency.network());
+            }
+        }
+        networks
+    }
+
+    #[must_use]
+    pub fn get(
+        &self,
+        network: impl Into<ChainNetwork>,
+        token: &TokenIdentifier,
+    ) -> Option<&SupportedCurrency> {
+        let network = network.into();
+        self.currencies.iter().find(|c| c.matches(network, token))
+    }
+
+    #[must_use]
+    pub fn is_supported(&self, network: impl Into<ChainNetwork>, token: &TokenIdentifier) -> bool {
+        self.get(network, token).is_some()
+    }
+
+    /// The configured entry for `currency`, which must also agree on decimals
+    pub fn check_currency(
+        &self,
+        currency: &Currency,
+    ) -> Result<&SupportedCurrency, UnsupportedCurrency> {
+        let supported = self
+            .get(currency.network(), &currency.token)
+            .ok_or_else(|| UnsupportedCurrency::UnsupportedToken {
+                network: currency.network(),
+                token: currency.token.clone(),
+            })?;
+        ensure!(
+            supported.decimals == currency.decimals,
+            DecimalsMismatchSnafu {
+                symbol: supported.symbol.clone(),
+                expected: supported.decimals,
+                actual: currency.decimals,
+            }
+        );
+        Ok(supported)
+    }
+
+    /// Like `check_currency`, also enforcing the configured amount bounds
+    pub fn check_lot(&self, lot: &Lot) -> Result<&SupportedCurrency, UnsupportedCurrency> {
+        let supported = self.check_currency(&lot.currency)?;
+        ensure!(
+            (supported.min_amount..=supported.max_amount).contains(&lot.amount),
+            AmountOutOfRangeSnafu {
+                symbol: supported.symbol.clone(),
+                amount: lot.amount,
+                min: supported.min_amount,
+                max: supported.max_amount,
+            }
+        );
+        Ok(supported)
+    }
+}
+
+impl Default for SupportedCurrencies {
+    /// Native BTC and cbBTC on Ethereum
+    fn default() -> Self {
+        let bitcoin_denominated = |chain, token, symbol: &str| SupportedCurrency {
+            chain,
+            chain_id: None,
+            token,
+            decimals: CBBTC_DECIMALS,
+            symbol: symbol.to_string(),
+            min_amount: U256::from(1_000u64),
+            max_amount: U256::from(100_000_000_000u64), // 1000 BTC
+        };
+
+        Self {
+            currencies: vec![
+                bitcoin_denominated(ChainType::Bitcoin, TokenIdentifier::Native, "BTC"),
+                bitcoin_denominated(
+                    ChainType::Ethereum,
+                    TokenIdentifier::Address(CBBTC_ADDRESS.to_string()),
+                    "cbBTC",
+                ),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [[currencies]]
+        chain = "bitcoin"
+        symbol = "BTC"
+        decimals = 8
+        min_amount = "10000"
+        max_amount = "10000000000"
+
+        [[currencies]]
+        chain = "ethereum"
+        address = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+        symbol = "USDC"
+        decimals = 6
+        min_amount = "1000000"
+        max_amount = "0xe8d4a51000"
+    "#;
+
+    fn usdc(amount: u64) -> Lot {
+        Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Address(
+                    "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+                ),
+                decimals: 6,
+                chain_id: None,
+            },
+            amount: U256::from(amount),
+        }
+    }
+
+    #[test]
+    fn test_lookup_and_bounds() {
+        let currencies = SupportedCurrencies::from_toml(CONFIG).unwrap();
+        assert_eq!(currencies.all().len(), 2);
+        assert_eq!(currencies.on_chain(ChainType::Ethereum).count(), 1);
+
+        // Addresses match regardless of checksum casing
+        let usdc_entry = currencies.check_lot(&usdc(5_000_000)).unwrap();
+        let lowercase = CONFIG.replace(
+            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        );
+        assert_eq!(
+            SupportedCurrencies::from_toml(&lowercase).unwrap().all()[1].token,
+            usdc_entry.token
+        );
+        assert_eq!(usdc_entry.symbol, "USDC");
+        assert_eq!(usdc_entry.max_amount, U256::from(1_000_000_000_000u64));
+
+        assert!(matches!(
+            currencies.check_lot(&usdc(1)),
+            Err(UnsupportedCurrency::AmountOutOfRange { .. })
+        ));
+        let mut wrong_decimals = usdc(5_000_000);
+        wrong_decimals.currency.decimals = 18;
+        assert!(matches!(
+            currencies.check_lot(&wrong_decimals),
+            Err(UnsupportedCurrency::DecimalsMismatch { expected: 6, .. })
+        ));
+        assert!(!currencies.is_supported(
+            ChainType::Ethereum,
+            &TokenIdentifier::Address("0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf".to_string())
+        ));
+    }
+
+    #[test]
+    fn test_invalid_files_are_rejected() {
+        let duplicate = format!(
+            "{CONFIG}\n[[currencies]]\nchain = \"bitcoin\"\nsymbol = \"BTC2\"\ndecimals = 8\nmin_amount = \"1\"\nmax_amount = \"2\"\n"
+        );
+        assert!(matches!(
+            SupportedCurrencies::from_toml(&duplicate),
+            Err(SupportedCurrenciesError::InvalidCurrency { .. })
+        ));
+
+        let inverted = CONFIG.replace("\"10000\"", "\"99999999999\"");
+        assert!(matches!(
+            SupportedCurrencies::from_toml(&inverted),
+            Err(SupportedCurrenciesError::InvalidCurrency { .. })
+        ));
+
+        let short_address = CONFIG.replace("eB48", "eB4");
+        assert!(matches!(
+            SupportedCurrencies::from_toml(&short_address),
+            Err(SupportedCurrenciesError::InvalidCurrency { .. })
+        ));
+
+        let json = r#"{"currencies": [{"chain": "bitcoin", "symbol": "BTC", "decimals": 8, "min_amount": "1", "max_amount": "2"}]}"#;
+        assert_eq!(SupportedCurrencies::from_json(json).unwrap().all().len(), 1);
+    }
+
+    #[test]
+    fn test_networks_keep_the_same_token_apart() {
+        let config = format!(
+            "{CONFIG}\n[[currencies]]\nchain = \"ethereum\"\nchain_id = 8453\naddress = \"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48\"\nsymbol = \"USDC.base\"\ndecimals = 6\nmin_amount = \"1\"\nmax_amount = \"2\"\n"
+        );
+        let currencies = SupportedCurrencies::from_toml(&config).unwrap();
+        assert_eq!(
+            currencies.networks(),
+            vec![
+                ChainNetwork::primary(ChainType::Bitcoin),
+                ChainNetwork::primary(ChainType::Ethereum),
+                ChainNetwork::evm(8453),
+            ]
+        );
+        assert_eq!(currencies.on_chain(ChainType::Ethereum).count(), 1);
+
+        assert_eq!(
+            currencies.check_lot(&usdc(5_000_000)).unwrap().symbol,
+            "USDC"
+        );
+        let mut on_base = usdc(1);
+        on_base.currency.chain_id = Some(8453);
+        assert_eq!(currencies.check_lot(&on_base).unwrap().symbol, "USDC.base");
+        on_base.currency.chain_id = Some(10);
+        assert!(matches!(
+            currencies.check_lot(&on_base),
+            Err(UnsupportedCurrency::UnsupportedToken { .. })
+        ));
+
+        let bitcoin_network = CONFIG.replacen(
+            "chain = \"bitcoin\"",
+            "chain = \"bitcoin\"\nchain_id = 1",
+            1,
+        );
+        assert!(matches!(
+            SupportedCurrencies::from_toml(&bitcoin_network),
+            Err(SupportedCurrenciesError::InvalidCurrency { .. })
+        ));
+    }
+
+    #[test]
+    fn test_default_matches_the_original_pairs() {
+        let currencies = SupportedCurrencies::default();
+        assert!(currencies.is_supported(ChainType::Bitcoin, &TokenIdentifier::Native));
+        assert!(currencies.is_supported(
+            ChainType::Ethereum,
+            &TokenIdentifier::Address("0xcbb7c0000ab88b473b1f5afd9ef808440eed33bf".to_string())
+        ));
+        assert!(!currencies.is_supported(ChainType::Ethereum, &TokenIdentifier::Native));
+    }
+}
@@ -1,17 +1,35 @@
 pub mod api_key;
 pub mod chain;
+pub mod confirmation;
 pub mod constants;
+pub mod currencies;
 pub mod quote;
+pub mod redacted;
+#[cfg(feature = "utoipa")]
+pub mod schema;
 pub mod status;
 pub mod swap;
 pub mod swap_transitions;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod tx_hash;
+pub mod validation;
 pub mod wallet;
 
 pub use api_key::*;
 pub use chain::*;
+pub use confirmation::*;
 pub use constants::*;
+pub use currencies::*;
 pub use quote::*;
+pub use redacted::*;
+#[cfg(feature = "utoipa")]
+pub use schema::*;
 pub use status::*;
 pub use swap::*;
 pub use swap_transitions::*;
+#[cfg(feature = "test-utils")]
+pub use test_utils::*;
+pub use tx_hash::*;
+pub use validation::*;
 pub use wallet::*;
@@ -1,30 +1,160 @@
-use crate::ChainType;
-use alloy::primitives::{keccak256, U256};
+use crate::{ChainNetwork, ChainType};
+use alloy::primitives::{keccak256, Address, U256};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use snafu::Snafu;
+use std::str::FromStr;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(tag = "type", content = "data")]
 pub enum TokenIdentifier {
     Native,
-    Address(String),
+    /// EVM token contract. Valid addresses are EIP-55 checksummed when
+    /// deserialized, so the same token always compares and hashes the same
+    Address(#[serde(deserialize_with = "deserialize_token_address")] String),
+}
+
+/// A token address that isn't a 20 byte hex EVM address
+#[derive(Debug, Clone, PartialEq, Eq, Snafu)]
+#[snafu(display("Invalid token address {address:?}: {reason}"))]
+pub struct InvalidTokenAddress {
+    pub address: String,
+    pub reason: String,
+}
+
+impl TokenIdentifier {
+    /// The token contract at `address`, given in any case and with or without
+    /// `0x`, kept in its EIP-55 checksummed form
+    pub fn address(address: &str) -> Result<Self, InvalidTokenAddress> {
+        canonical_token_address(address).map(Self::Address)
+    }
+
+    /// Whether both identify the same token. Addresses built without
+    /// [`TokenIdentifier::address`], e.g. read back from rows written before
+    /// canonicalization, may differ in case only
+    #[must_use]
+    pub fn is_same_token(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Native, Self::Native) => true,
+            (Self::Address(a), Self::Address(b)) => {
+                match (canonical_token_address(a), canonical_token_address(b)) {
+                    (Ok(a), Ok(b)) => a == b,
+                    _ => a.eq_ignore_ascii_case(b),
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// EIP-55 checksummed form of an EVM address given in any case, with or
+/// without `0x`
+pub fn canonical_token_address(address: &str) -> Result<String, InvalidTokenAddress> {
+    let invalid = |reason: &str| InvalidTokenAddressSnafu { address, reason }.build();
+    let trimmed = address.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    if digits.len() != 40 {
+        return Err(invalid("must be 40 hex characters"));
+    }
+    let parsed = Address::from_str(digits).map_err(|_| invalid("must be hex encoded"))?;
+    Ok(parsed.to_checksum(None))
+}
+
+/// Anything that isn't an address is kept as is, for request validation to
+/// reject with the offending field named
+fn deserialize_token_address<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let address = String::deserialize(deserializer)?;
+    Ok(canonical_token_address(&address).unwrap_or(address))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Currency {
     pub chain: ChainType,
     pub token: TokenIdentifier,
     pub decimals: u8,
+    /// EVM chain id of the network the token lives on. Unset is the chain's
+    /// primary network, Ethereum mainnet (chain id 1) in production, so
+    /// currencies from before networks existed keep their meaning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+}
+
+impl Currency {
+    #[must_use]
+    pub fn network(&self) -> ChainNetwork {
+        ChainNetwork {
+            chain: self.chain,
+            chain_id: self.chain_id,
+        }
+    }
+
+    /// `amount` in smallest units as a decimal number of whole tokens, e.g.
+    /// `"1.5"` for 150000000 sats. Trailing zeros of the fraction are dropped
+    #[must_use]
+    pub fn format_amount(&self, amount: U256) -> String {
+        let decimals = usize::from(self.decimals);
+        if decimals == 0 {
+            return amount.to_string();
+        }
+        let digits = format!("{:0>width$}", amount.to_string(), width = decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        match fraction.trim_end_matches('0') {
+            "" => whole.to_string(),
+            fraction => format!("{whole}.{fraction}"),
+        }
+    }
+
+    /// A decimal number of whole tokens in smallest units, the inverse of
+    /// [`Self::format_amount`]. `None` if it isn't a plain decimal number, has
+    /// more fraction digits than the currency or doesn't fit a `U256`
+    #[must_use]
+    pub fn parse_amount(&self, amount: &str) -> Option<U256> {
+        let decimals = usize::from(self.decimals);
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let is_number = !whole.is_empty()
+            && whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit());
+        if !is_number || fraction.len() > decimals {
+            return None;
+        }
+        U256::from_str_radix(&format!("{whole}{fraction:0<decimals$}"), 10).ok()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Lot {
     pub currency: Currency,
+    #[cfg_attr(feature = "utoipa", schema(value_type = crate::U256Schema))]
     pub amount: U256,
 }
 
+impl Lot {
+    /// The amount in smallest units, `None` if it doesn't fit a `u64`
+    #[must_use]
+    pub fn amount_u64(&self) -> Option<u64> {
+        u64::try_from(self.amount).ok()
+    }
+
+    /// The amount in whole tokens, see [`Currency::format_amount`]
+    #[must_use]
+    pub fn display_amount(&self) -> String {
+        self.currency.format_amount(self.amount)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Quote {
     pub id: Uuid,
 
@@ -43,16 +173,19 @@ pub struct Quote {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub enum QuoteMode {
     ExactInput,
     ExactOutput,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct QuoteRequest {
     pub mode: QuoteMode,
     pub from: Currency,
     pub to: Currency,
+    #[cfg_attr(feature = "utoipa", schema(value_type = crate::U256Schema))]
     pub amount: U256,
 }
 
@@ -60,4 +193,139 @@ impl Quote {
     pub fn hash(&self) -> [u8; 32] {
         keccak256(serde_json::to_string(self).unwrap().as_bytes()).into()
     }
+
+    /// Whether the quote can no longer be used at `now`. It's live up to, but
+    /// not including, `expires_at`
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Whole seconds of validity left at `now`, never negative
+    #[must_use]
+    pub fn expires_in_seconds(&self, now: DateTime<Utc>) -> u64 {
+        seconds_until(self.expires_at, now)
+    }
+}
+
+/// Whole seconds from `now` until `deadline`, zero once it passed
+#[must_use]
+pub fn seconds_until(deadline: DateTime<Utc>, now: DateTime<Utc>) -> u64 {
+    u64::try_from((deadline - now).num_seconds()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CBBTC_ADDRESS;
+
+    fn token_json(address: &str) -> String {
+        format!(r#"{{"type":"Address","data":"{address}"}}"#)
+    }
+
+    #[test]
+    fn test_token_addresses_are_checksummed_whatever_their_case() {
+        let digits = &CBBTC_ADDRESS[2..];
+        for address in [
+            CBBTC_ADDRESS.to_string(),
+            CBBTC_ADDRESS.to_lowercase(),
+            format!("0x{}", digits.to_uppercase()),
+            format!("0X{digits}"),
+            digits.to_lowercase(),
+            format!("  {CBBTC_ADDRESS} "),
+        ] {
+            let expected = TokenIdentifier::Address(CBBTC_ADDRESS.to_string());
+            assert_eq!(TokenIdentifier::address(&address).unwrap(), expected);
+            let deserialized: TokenIdentifier =
+                serde_json::from_str(&token_json(&address)).unwrap();
+            assert_eq!(deserialized, expected, "{address:?}");
+        }
+    }
+
+    #[test]
+    fn test_malformed_token_addresses_are_rejected() {
+        for address in [
+            "",
+            "0x",
+            &CBBTC_ADDRESS[..41],
+            &format!("{CBBTC_ADDRESS}00"),
+            "0xzzb7c0000ab88b473b1f5afd9ef808440eed33bf",
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        ] {
+            assert!(TokenIdentifier::address(address).is_err(), "{address:?}");
+            // Left for validation to reject
+            let deserialized: TokenIdentifier = serde_json::from_str(&token_json(address)).unwrap();
+            assert_eq!(deserialized, TokenIdentifier::Address(address.to_string()));
+        }
+        let native: TokenIdentifier = serde_json::from_str(r#"{"type":"Native"}"#).unwrap();
+        assert_eq!(native, TokenIdentifier::Native);
+    }
+
+    #[test]
+    fn test_same_token_ignores_address_case() {
+        let checksummed = TokenIdentifier::Address(CBBTC_ADDRESS.to_string());
+        let lowercase = TokenIdentifier::Address(CBBTC_ADDRESS.to_lowercase());
+        assert!(checksummed.is_same_token(&lowercase));
+        assert!(!checksummed.is_same_token(&TokenIdentifier::Native));
+        assert!(!checksummed.is_same_token(&TokenIdentifier::Address(
+            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string()
+        )));
+    }
+
+    fn currency(decimals: u8) -> Currency {
+        Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Native,
+            decimals,
+            chain_id: None,
+        }
+    }
+
+    #[test]
+    fn test_amounts_are_displayed_in_whole_tokens() {
+        let sats = currency(8);
+        for (amount, displayed) in [
+            (0u64, "0"),
+            (1, "0.00000001"),
+            (150_000_000, "1.5"),
+            (2_100_000_000_000_000, "21000000"),
+        ] {
+            assert_eq!(sats.format_amount(U256::from(amount)), displayed);
+            assert_eq!(sats.parse_amount(displayed), Some(U256::from(amount)));
+        }
+        assert_eq!(currency(0).format_amount(U256::from(42u64)), "42");
+
+        // Every digit of the largest amount survives, whatever the decimals
+        let max = U256::MAX.to_string();
+        let lot = Lot {
+            currency: sats.clone(),
+            amount: U256::MAX,
+        };
+        assert_eq!(
+            lot.display_amount(),
+            format!("{}.{}", &max[..max.len() - 8], &max[max.len() - 8..])
+        );
+        assert_eq!(sats.parse_amount(&lot.display_amount()), Some(U256::MAX));
+        assert_eq!(lot.amount_u64(), None);
+        let wei = currency(255);
+        assert_eq!(
+            wei.format_amount(U256::MAX),
+            format!("0.{:0>255}", max).trim_end_matches('0')
+        );
+        assert_eq!(
+            wei.parse_amount(&wei.format_amount(U256::MAX)),
+            Some(U256::MAX)
+        );
+    }
+
+    #[test]
+    fn test_malformed_amounts_are_not_parsed() {
+        let sats = currency(8);
+        for amount in ["", ".5", "1.000000001", "1,5", "-1", "1e8", " 1"] {
+            assert_eq!(sats.parse_amount(amount), None, "{amount:?}");
+        }
+        // Past the largest amount
+        let too_large = format!("{}.00000000", U256::MAX);
+        assert_eq!(sats.parse_amount(&too_large), None);
+    }
 }
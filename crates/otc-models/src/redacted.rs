@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroize;
+
+const REDACTED: &str = "[redacted]";
+
+/// A secret that prints as `[redacted]` through `Debug` and `Display`.
+///
+/// Serialization is transparent, so a redacted field keeps its wire format and
+/// still reaches whoever the value is sent to. Only logs and debug output lose it.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Redacted<T>(T);
+
+impl<T> Redacted<T> {
+    #[must_use]
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// The wrapped secret, keep it out of log lines
+    #[must_use]
+    pub const fn expose(&self) -> &T {
+        &self.0
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: FromStr> FromStr for Redacted<T> {
+    type Err = T::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+impl<T: Zeroize> Zeroize for Redacted<T> {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formatting_hides_the_secret() {
+        let secret = Redacted::new("hunter2".to_string());
+
+        assert_eq!(format!("{secret:?}"), "[redacted]");
+        assert_eq!(format!("{secret}"), "[redacted]");
+        assert_eq!(format!("{:?}", Some(secret)), "Some([redacted])");
+    }
+
+    #[test]
+    fn test_serde_passes_the_secret_through() {
+        let secret = Redacted::new("hunter2".to_string());
+
+        let json = serde_json::to_string(&secret).unwrap();
+        assert_eq!(json, "\"hunter2\"");
+        let decoded: Redacted<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, secret);
+    }
+
+    #[test]
+    fn test_zeroize_clears_the_secret() {
+        let mut secret = Redacted::new("hunter2".to_string());
+        secret.zeroize();
+        assert!(secret.expose().is_empty());
+    }
+}
@@ -0,0 +1,30 @@
+//! OpenAPI schemas of foreign types as they appear on the wire
+
+use std::borrow::Cow;
+
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+/// Schema of an `alloy` `U256`, which serde writes as a 0x prefixed hex string.
+/// Use it as `#[schema(value_type = U256Schema)]` on `U256` fields.
+pub struct U256Schema;
+
+impl PartialSchema for U256Schema {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .pattern(Some("^0x[0-9a-fA-F]{1,64}$"))
+            .description(Some(
+                "Unsigned 256-bit integer in the token's smallest unit, as a 0x prefixed hex string",
+            ))
+            .build()
+            .into()
+    }
+}
+
+impl ToSchema for U256Schema {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("U256")
+    }
+}
@@ -7,13 +7,63 @@ use serde::{Deserialize, Serialize};
     feature = "sqlx",
     sqlx(type_name = "swap_status", rename_all = "snake_case")
 )]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub enum SwapStatus {
     WaitingUserDepositInitiated,
     WaitingUserDepositConfirmed,
     WaitingMMDepositInitiated,
     WaitingMMDepositConfirmed,
+    /// The MM's deposit carried the swap nonce but less than the quoted amount
+    MMDepositAmountMismatch,
     Settled,
+    /// The released deposit key doesn't control the deposit address
+    ManualReview,
     RefundingUser,
     RefundingMM,
     Failed,
 }
+
+impl SwapStatus {
+    /// Every status, in lifecycle order
+    pub const ALL: [SwapStatus; 10] = [
+        SwapStatus::WaitingUserDepositInitiated,
+        SwapStatus::WaitingUserDepositConfirmed,
+        SwapStatus::WaitingMMDepositInitiated,
+        SwapStatus::WaitingMMDepositConfirmed,
+        SwapStatus::MMDepositAmountMismatch,
+        SwapStatus::Settled,
+        SwapStatus::ManualReview,
+        SwapStatus::RefundingUser,
+        SwapStatus::RefundingMM,
+        SwapStatus::Failed,
+    ];
+
+    /// What a swap in this status is waiting on, for clients to show
+    #[must_use]
+    pub fn description(&self) -> &'static str {
+        match self {
+            SwapStatus::WaitingUserDepositInitiated => {
+                "Waiting for the user to deposit to the swap's deposit address"
+            }
+            SwapStatus::WaitingUserDepositConfirmed => {
+                "User deposit seen, waiting for its confirmations"
+            }
+            SwapStatus::WaitingMMDepositInitiated => {
+                "User deposit confirmed, waiting for the market maker's payment"
+            }
+            SwapStatus::WaitingMMDepositConfirmed => {
+                "Market maker payment seen, waiting for its confirmations"
+            }
+            SwapStatus::MMDepositAmountMismatch => {
+                "The market maker paid less than quoted, the user deposit will be refunded"
+            }
+            SwapStatus::Settled => {
+                "Both deposits confirmed, the user deposit is released to the market maker"
+            }
+            SwapStatus::ManualReview => "Held for an operator to review after settlement",
+            SwapStatus::RefundingUser => "The user deposit is being refunded",
+            SwapStatus::RefundingMM => "The market maker payment is being refunded",
+            SwapStatus::Failed => "The swap ended without settling",
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Quote, SwapStatus};
+use crate::{Quote, SwapStatus, TxHash};
 use alloy::primitives::{Address, U256};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -14,6 +14,8 @@ pub struct Swap {
     // Salt for deterministic wallet generation when combined with the TEE master key
     pub user_deposit_salt: [u8; 32],
     pub user_deposit_address: String, // cached for convenience, can be derived from the salt and master key
+    // Version of the master key the deposit wallet was derived with, pinned so rotation never moves it
+    pub master_key_version: u32,
 
     // Nonce for the market maker to embed in their payment address
     pub mm_nonce: [u8; 16],
@@ -21,10 +23,16 @@ pub struct Swap {
     // User's addresses
     pub user_destination_address: String,
     pub user_evm_account_address: Address,
+    // Where the user deposit is refunded on the `from` chain, `None` until the user sets it
+    pub user_refund_address: Option<String>,
 
     // Core status
     pub status: SwapStatus,
 
+    // Deposit finality resolved at creation; later policy changes never touch existing swaps
+    pub user_required_confirmations: u64,
+    pub mm_required_confirmations: u64,
+
     // Deposit tracking (JSONB in database)
     pub user_deposit_status: Option<UserDepositStatus>,
     pub mm_deposit_status: Option<MMDepositStatus>,
@@ -38,8 +46,16 @@ pub struct Swap {
 
     // MM coordination
     pub mm_notified_at: Option<DateTime<Utc>>,
+    /// When the MM's deposit was last detected, with `mm_notified_at` its fill latency
+    pub mm_deposit_detected_at: Option<DateTime<Utc>>,
     pub mm_private_key_sent_at: Option<DateTime<Utc>>,
 
+    // Correlation id of the quote request the swap came from, `None` for older swaps
+    pub trace_id: Option<String>,
+
+    // The user's own reference for the swap, opaque to us and never logged in full
+    pub external_reference: Option<String>,
+
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -47,7 +63,7 @@ pub struct Swap {
 // JSONB types for rich deposit/settlement data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserDepositStatus {
-    pub tx_hash: String,
+    pub tx_hash: TxHash,
     pub amount: U256,
     pub detected_at: DateTime<Utc>,
     pub confirmations: u64,
@@ -56,16 +72,48 @@ pub struct UserDepositStatus {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MMDepositStatus {
-    pub tx_hash: String,
+    pub tx_hash: TxHash,
     pub amount: U256,
     pub detected_at: DateTime<Utc>,
     pub confirmations: u64,
     pub last_checked: DateTime<Utc>,
 }
 
+/// What the market maker reported its deposit cost on chain, for reconciling
+/// against the network fee its quote assumed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct FillCost {
+    /// Fee in the paid lot's smallest unit, comparable with the quote's
+    /// `network_fee_sats`. `None` when the MM had no price to convert it with
+    /// or the lot is neither BTC- nor ether-denominated
+    pub fee_in_lot_currency: Option<u64>,
+    pub usage: FillUsage,
+}
+
+/// The fee rate a fill achieved and the block space it took
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(tag = "chain", rename_all = "snake_case")]
+pub enum FillUsage {
+    Bitcoin {
+        sat_per_vb: f64,
+        vsize: u64,
+        inputs: u64,
+        outputs: u64,
+    },
+    Ethereum {
+        /// In wei
+        #[cfg_attr(feature = "utoipa", schema(value_type = crate::U256Schema))]
+        effective_gas_price: U256,
+        /// This fill's share when it was batched with others
+        gas_used: u64,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SettlementStatus {
-    pub tx_hash: String,
+    pub tx_hash: TxHash,
     pub broadcast_at: DateTime<Utc>,
     pub confirmations: u64,
     pub completed_at: Option<DateTime<Utc>>,
@@ -74,8 +122,19 @@ pub struct SettlementStatus {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransferInfo {
-    pub tx_hash: String,
+    pub tx_hash: TxHash,
     pub amount: U256,
     pub detected_at: DateTime<Utc>,
     pub confirmations: u64,
 }
+
+/// A status change recorded when it is persisted, `from_status` is None for swap creation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapEvent {
+    pub swap_id: Uuid,
+    pub from_status: Option<SwapStatus>,
+    pub to_status: SwapStatus,
+    pub occurred_at: DateTime<Utc>,
+    /// Why the status changed when it isn't the usual step, e.g. a reorg rolling it back
+    pub note: Option<String>,
+}
@@ -1,6 +1,10 @@
-use crate::{MMDepositStatus, SettlementStatus, Swap, SwapStatus, UserDepositStatus};
+use std::sync::LazyLock;
+
+use crate::{
+    sanitize_reason, MMDepositStatus, SettlementStatus, Swap, SwapStatus, TxHash, UserDepositStatus,
+};
 use alloy::primitives::U256;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use snafu::{ensure, Snafu};
 
 #[derive(Debug, Snafu)]
@@ -13,25 +17,170 @@ pub enum TransitionError {
 
     #[snafu(display("Swap has already failed: {}", reason))]
     AlreadyFailed { reason: String },
+
+    #[snafu(display("Refund address can no longer be set in status {:?}", status))]
+    RefundAddressLocked { status: SwapStatus },
 }
 
 pub type TransitionResult = Result<(), TransitionError>;
 
+/// The status changes of the swap state machine, each applied by the `Swap`
+/// method of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapTransition {
+    UserDepositDetected,
+    UserDepositConfirmed,
+    UserDepositReorged,
+    MMDepositDetected,
+    MMDepositReorged,
+    MMDepositAmountMismatch,
+    MMDepositConfirmed,
+    InitiateUserRefund,
+    InitiateMMRefund,
+    FlagForManualReview,
+    MarkFailed,
+}
+
+impl SwapTransition {
+    pub const ALL: [SwapTransition; 11] = [
+        SwapTransition::UserDepositDetected,
+        SwapTransition::UserDepositConfirmed,
+        SwapTransition::UserDepositReorged,
+        SwapTransition::MMDepositDetected,
+        SwapTransition::MMDepositReorged,
+        SwapTransition::MMDepositAmountMismatch,
+        SwapTransition::MMDepositConfirmed,
+        SwapTransition::InitiateUserRefund,
+        SwapTransition::InitiateMMRefund,
+        SwapTransition::FlagForManualReview,
+        SwapTransition::MarkFailed,
+    ];
+
+    /// Statuses the transition applies from, and the status it leads to. The
+    /// transition methods and [`SwapStatus::allowed_transitions`] both read this
+    #[must_use]
+    pub fn edges(self) -> (&'static [SwapStatus], SwapStatus) {
+        use SwapStatus::*;
+        match self {
+            Self::UserDepositDetected => {
+                (&[WaitingUserDepositInitiated], WaitingUserDepositConfirmed)
+            }
+            Self::UserDepositConfirmed => {
+                (&[WaitingUserDepositConfirmed], WaitingMMDepositInitiated)
+            }
+            Self::UserDepositReorged => {
+                (&[WaitingUserDepositConfirmed], WaitingUserDepositInitiated)
+            }
+            Self::MMDepositDetected => (&[WaitingMMDepositInitiated], WaitingMMDepositConfirmed),
+            Self::MMDepositReorged => (&[WaitingMMDepositConfirmed], WaitingMMDepositInitiated),
+            Self::MMDepositAmountMismatch => {
+                (&[WaitingMMDepositInitiated], MMDepositAmountMismatch)
+            }
+            Self::MMDepositConfirmed => (&[WaitingMMDepositConfirmed], Settled),
+            Self::InitiateUserRefund => (
+                &[
+                    WaitingUserDepositInitiated,
+                    WaitingUserDepositConfirmed,
+                    WaitingMMDepositInitiated,
+                    MMDepositAmountMismatch,
+                ],
+                RefundingUser,
+            ),
+            Self::InitiateMMRefund => (&[WaitingMMDepositConfirmed, Settled], RefundingMM),
+            Self::FlagForManualReview => (&[Settled], ManualReview),
+            // Failed is the only status a swap never leaves
+            Self::MarkFailed => (
+                &[
+                    WaitingUserDepositInitiated,
+                    WaitingUserDepositConfirmed,
+                    WaitingMMDepositInitiated,
+                    WaitingMMDepositConfirmed,
+                    MMDepositAmountMismatch,
+                    Settled,
+                    ManualReview,
+                    RefundingUser,
+                    RefundingMM,
+                ],
+                Failed,
+            ),
+        }
+    }
+
+    #[must_use]
+    pub fn applies_from(self, status: SwapStatus) -> bool {
+        self.edges().0.contains(&status)
+    }
+
+    #[must_use]
+    pub fn target(self) -> SwapStatus {
+        self.edges().1
+    }
+}
+
+/// Targets reachable from each status in [`SwapStatus::ALL`], in the same order
+static ALLOWED_TRANSITIONS: LazyLock<Vec<Vec<SwapStatus>>> = LazyLock::new(|| {
+    SwapStatus::ALL
+        .into_iter()
+        .map(|from| {
+            let mut targets = Vec::new();
+            for transition in SwapTransition::ALL {
+                if transition.applies_from(from) && !targets.contains(&transition.target()) {
+                    targets.push(transition.target());
+                }
+            }
+            targets
+        })
+        .collect()
+});
+
+impl SwapStatus {
+    /// Statuses a swap in this status may move to
+    #[must_use]
+    pub fn allowed_transitions(&self) -> &'static [SwapStatus] {
+        let index = SwapStatus::ALL
+            .iter()
+            .position(|status| status == self)
+            .expect("SwapStatus::ALL lists every status");
+        &ALLOWED_TRANSITIONS[index]
+    }
+
+    #[must_use]
+    pub fn can_transition_to(&self, to: SwapStatus) -> bool {
+        self.allowed_transitions().contains(&to)
+    }
+}
+
+/// The swap state machine as an adjacency list, every status with the
+/// statuses it may move to
+#[must_use]
+pub fn transition_graph() -> Vec<(SwapStatus, &'static [SwapStatus])> {
+    SwapStatus::ALL
+        .into_iter()
+        .map(|status| (status, status.allowed_transitions()))
+        .collect()
+}
+
 impl Swap {
+    /// Fail unless `transition` applies from the current status
+    fn ensure_transition(&self, transition: SwapTransition) -> TransitionResult {
+        ensure!(
+            transition.applies_from(self.status),
+            InvalidTransitionSnafu {
+                from: self.status,
+                to: transition.target(),
+            }
+        );
+        Ok(())
+    }
+
     /// Transition when user deposit is detected
     pub fn user_deposit_detected(
         &mut self,
-        tx_hash: String,
+        tx_hash: TxHash,
         amount: U256,
         confirmations: u64,
     ) -> TransitionResult {
-        ensure!(
-            self.status == SwapStatus::WaitingUserDepositInitiated,
-            InvalidTransitionSnafu {
-                from: self.status,
-                to: SwapStatus::WaitingUserDepositConfirmed,
-            }
-        );
+        self.ensure_transition(SwapTransition::UserDepositDetected)?;
 
         let now = Utc::now();
         self.user_deposit_status = Some(UserDepositStatus {
@@ -50,13 +199,7 @@ impl Swap {
 
     /// Transition when user deposit is confirmed
     pub fn user_deposit_confirmed(&mut self) -> TransitionResult {
-        ensure!(
-            self.status == SwapStatus::WaitingUserDepositConfirmed,
-            InvalidTransitionSnafu {
-                from: self.status,
-                to: SwapStatus::WaitingMMDepositInitiated,
-            }
-        );
+        self.ensure_transition(SwapTransition::UserDepositConfirmed)?;
 
         ensure!(
             self.user_deposit_status.is_some(),
@@ -71,20 +214,25 @@ impl Swap {
         Ok(())
     }
 
+    /// Roll back to waiting for the user deposit after a reorg dropped it
+    pub fn user_deposit_reorged(&mut self) -> TransitionResult {
+        self.ensure_transition(SwapTransition::UserDepositReorged)?;
+
+        self.user_deposit_status = None;
+        self.status = SwapStatus::WaitingUserDepositInitiated;
+        self.updated_at = Utc::now();
+
+        Ok(())
+    }
+
     /// Transition when MM deposit is detected
     pub fn mm_deposit_detected(
         &mut self,
-        tx_hash: String,
+        tx_hash: TxHash,
         amount: U256,
         confirmations: u64,
     ) -> TransitionResult {
-        ensure!(
-            self.status == SwapStatus::WaitingMMDepositInitiated,
-            InvalidTransitionSnafu {
-                from: self.status,
-                to: SwapStatus::WaitingMMDepositConfirmed,
-            }
-        );
+        self.ensure_transition(SwapTransition::MMDepositDetected)?;
 
         let now = Utc::now();
         self.mm_deposit_status = Some(MMDepositStatus {
@@ -94,6 +242,7 @@ impl Swap {
             confirmations,
             last_checked: now,
         });
+        self.mm_deposit_detected_at = Some(now);
 
         self.status = SwapStatus::WaitingMMDepositConfirmed;
         self.updated_at = now;
@@ -101,6 +250,46 @@ impl Swap {
         Ok(())
     }
 
+    /// Roll back to waiting for the MM deposit after a reorg dropped it
+    pub fn mm_deposit_reorged(&mut self) -> TransitionResult {
+        self.ensure_transition(SwapTransition::MMDepositReorged)?;
+
+        self.mm_deposit_status = None;
+        self.status = SwapStatus::WaitingMMDepositInitiated;
+        self.updated_at = Utc::now();
+
+        Ok(())
+    }
+
+    /// Transition when the MM deposit is detected but pays less than the quote
+    pub fn mm_deposit_amount_mismatch(
+        &mut self,
+        tx_hash: TxHash,
+        amount: U256,
+        confirmations: u64,
+    ) -> TransitionResult {
+        self.ensure_transition(SwapTransition::MMDepositAmountMismatch)?;
+
+        let now = Utc::now();
+        self.failure_reason = Some(sanitize_reason(&format!(
+            "MM deposit {tx_hash} sent {amount}, expected {}",
+            self.quote.to.amount
+        )));
+        self.mm_deposit_status = Some(MMDepositStatus {
+            tx_hash,
+            amount,
+            detected_at: now,
+            confirmations,
+            last_checked: now,
+        });
+        self.mm_deposit_detected_at = Some(now);
+
+        self.status = SwapStatus::MMDepositAmountMismatch;
+        self.updated_at = now;
+
+        Ok(())
+    }
+
     /// Update confirmation count for deposits
     pub fn update_confirmations(
         &mut self,
@@ -128,13 +317,7 @@ impl Swap {
 
     /// Transition when MM deposit is confirmed
     pub fn mm_deposit_confirmed(&mut self) -> TransitionResult {
-        ensure!(
-            self.status == SwapStatus::WaitingMMDepositConfirmed,
-            InvalidTransitionSnafu {
-                from: self.status,
-                to: SwapStatus::Settled,
-            }
-        );
+        self.ensure_transition(SwapTransition::MMDepositConfirmed)?;
 
         ensure!(
             self.mm_deposit_status.is_some(),
@@ -149,10 +332,10 @@ impl Swap {
         Ok(())
     }
 
-    /// Record that MM was notified
-    pub fn mark_mm_notified(&mut self) -> TransitionResult {
-        self.mm_notified_at = Some(Utc::now());
-        self.updated_at = Utc::now();
+    /// Record that MM was notified at `now`
+    pub fn mark_mm_notified(&mut self, now: DateTime<Utc>) -> TransitionResult {
+        self.mm_notified_at = Some(now);
+        self.updated_at = now;
         Ok(())
     }
 
@@ -173,7 +356,7 @@ impl Swap {
     /// Record settlement transaction details
     pub fn record_settlement(
         &mut self,
-        tx_hash: String,
+        tx_hash: TxHash,
         confirmations: u64,
         fee: Option<U256>,
     ) -> TransitionResult {
@@ -213,48 +396,66 @@ impl Swap {
 
     /// Initiate refund to user
     pub fn initiate_user_refund(&mut self, reason: String) -> TransitionResult {
-        ensure!(
-            matches!(
-                self.status,
-                SwapStatus::WaitingUserDepositInitiated
-                    | SwapStatus::WaitingUserDepositConfirmed
-                    | SwapStatus::WaitingMMDepositInitiated
-            ),
-            InvalidTransitionSnafu {
-                from: self.status,
-                to: SwapStatus::RefundingUser,
-            }
-        );
+        self.ensure_transition(SwapTransition::InitiateUserRefund)?;
 
         self.status = SwapStatus::RefundingUser;
-        self.failure_reason = Some(reason);
+        self.failure_reason = Some(sanitize_reason(&reason));
         self.updated_at = Utc::now();
         Ok(())
     }
 
     /// Initiate refund to MM
     pub fn initiate_mm_refund(&mut self, reason: String) -> TransitionResult {
+        self.ensure_transition(SwapTransition::InitiateMMRefund)?;
+
+        self.status = SwapStatus::RefundingMM;
+        self.failure_reason = Some(sanitize_reason(&reason));
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Hold a settled swap for manual review
+    pub fn flag_for_manual_review(&mut self, reason: String) -> TransitionResult {
+        self.ensure_transition(SwapTransition::FlagForManualReview)?;
+
+        self.status = SwapStatus::ManualReview;
+        self.failure_reason = Some(sanitize_reason(&reason));
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Set where the user deposit is refunded
+    ///
+    /// Allowed until the user deposit is confirmed, and afterwards only for a
+    /// user refund still waiting on an address that was never set, so an
+    /// address can't be redirected once the swap relies on it.
+    pub fn set_user_refund_address(&mut self, address: String) -> TransitionResult {
         ensure!(
             matches!(
                 self.status,
-                SwapStatus::WaitingMMDepositConfirmed | SwapStatus::Settled
-            ),
-            InvalidTransitionSnafu {
-                from: self.status,
-                to: SwapStatus::RefundingMM,
+                SwapStatus::WaitingUserDepositInitiated | SwapStatus::WaitingUserDepositConfirmed
+            ) || self.awaiting_refund_address(),
+            RefundAddressLockedSnafu {
+                status: self.status
             }
         );
 
-        self.status = SwapStatus::RefundingMM;
-        self.failure_reason = Some(reason);
+        self.user_refund_address = Some(address);
         self.updated_at = Utc::now();
         Ok(())
     }
 
+    /// Whether a user refund can't run until the user sets a refund address
+    #[must_use]
+    pub fn awaiting_refund_address(&self) -> bool {
+        self.status == SwapStatus::RefundingUser && self.user_refund_address.is_none()
+    }
+
     /// Mark swap as failed
     pub fn mark_failed(&mut self, reason: String) -> TransitionResult {
+        self.ensure_transition(SwapTransition::MarkFailed)?;
         self.status = SwapStatus::Failed;
-        self.failure_reason = Some(reason);
+        self.failure_reason = Some(sanitize_reason(&reason));
         self.updated_at = Utc::now();
         Ok(())
     }
@@ -265,18 +466,22 @@ impl Swap {
         self.failure_at.is_some()
     }
 
-    /// Check if swap is in an active state (not settled or failed)
+    /// Check if swap is in an active state (not settled, held for review or failed)
     #[must_use]
     pub fn is_active(&self) -> bool {
-        !matches!(self.status, SwapStatus::Settled | SwapStatus::Failed)
+        !matches!(
+            self.status,
+            SwapStatus::Settled | SwapStatus::ManualReview | SwapStatus::Failed
+        )
     }
 
-    /// Get required confirmations based on chain and amount
+    /// Get the confirmations required for each deposit, as persisted at creation
     #[must_use]
     pub fn get_required_confirmations(&self) -> (u64, u64) {
-        // TODO: Implement logic based on chain type and amount
-        // For now, return default values
-        (3, 3) // (user_confirmations, mm_confirmations)
+        (
+            self.user_required_confirmations,
+            self.mm_required_confirmations,
+        )
     }
 }
 
@@ -291,6 +496,11 @@ mod tests {
     use chrono::Duration;
     use uuid::Uuid;
 
+    /// An Ethereum tx hash repeating `byte`
+    fn tx_hash(byte: u8) -> TxHash {
+        TxHash::parse(ChainType::Ethereum, &format!("{byte:02x}").repeat(32)).unwrap()
+    }
+
     fn create_test_swap() -> Swap {
         Swap {
             id: Uuid::new_v4(),
@@ -302,6 +512,7 @@ mod tests {
                         chain: ChainType::Ethereum,
                         token: TokenIdentifier::Native,
                         decimals: 18,
+                        chain_id: None,
                     },
                     amount: U256::from(1000000u64),
                 },
@@ -310,6 +521,7 @@ mod tests {
                         chain: ChainType::Bitcoin,
                         token: TokenIdentifier::Native,
                         decimals: 8,
+                        chain_id: None,
                     },
                     amount: U256::from(1000000u64),
                 },
@@ -319,20 +531,27 @@ mod tests {
             market_maker_id: Uuid::new_v4(),
             user_deposit_salt: [0u8; 32],
             user_deposit_address: "0x123".to_string(),
+            master_key_version: 1,
             mm_nonce: [0u8; 16],
             user_destination_address: "0x123".to_string(),
             user_evm_account_address: Address::from_str(
                 "0x1234567890123456789012345678901234567890",
             )
             .unwrap(),
+            user_refund_address: None,
             status: SwapStatus::WaitingUserDepositInitiated,
+            user_required_confirmations: 2,
+            mm_required_confirmations: 4,
             user_deposit_status: None,
             mm_deposit_status: None,
             settlement_status: None,
             failure_reason: None,
             failure_at: None,
             mm_notified_at: None,
+            mm_deposit_detected_at: None,
             mm_private_key_sent_at: None,
+            trace_id: None,
+            external_reference: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -343,18 +562,18 @@ mod tests {
         let mut swap = create_test_swap();
 
         // Valid transition
-        swap.user_deposit_detected("0xabc123".to_string(), U256::from(1000000u64), 1)
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 1)
             .unwrap();
 
         assert_eq!(swap.status, SwapStatus::WaitingUserDepositConfirmed);
         assert!(swap.user_deposit_status.is_some());
         assert_eq!(
             swap.user_deposit_status.as_ref().unwrap().tx_hash,
-            "0xabc123"
+            tx_hash(1)
         );
 
         // Invalid transition - can't deposit again
-        let result = swap.user_deposit_detected("0xdef456".to_string(), U256::from(1000000u64), 1);
+        let result = swap.user_deposit_detected(tx_hash(2), U256::from(1000000u64), 1);
         assert!(result.is_err());
     }
 
@@ -363,7 +582,7 @@ mod tests {
         let mut swap = create_test_swap();
 
         // User deposits
-        swap.user_deposit_detected("0xuser123".to_string(), U256::from(1000000u64), 1)
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 1)
             .unwrap();
         assert_eq!(swap.status, SwapStatus::WaitingUserDepositConfirmed);
 
@@ -372,7 +591,7 @@ mod tests {
         assert_eq!(swap.status, SwapStatus::WaitingMMDepositInitiated);
 
         // MM deposits
-        swap.mm_deposit_detected("0xmm456".to_string(), U256::from(500000u64), 1)
+        swap.mm_deposit_detected(tx_hash(3), U256::from(500000u64), 1)
             .unwrap();
         assert_eq!(swap.status, SwapStatus::WaitingMMDepositConfirmed);
 
@@ -381,11 +600,103 @@ mod tests {
         assert_eq!(swap.status, SwapStatus::Settled);
 
         // Record settlement
-        swap.record_settlement("0xsettle789".to_string(), 6, Some(U256::from(1000u64)))
+        swap.record_settlement(tx_hash(5), 6, Some(U256::from(1000u64)))
             .unwrap();
         assert!(swap.settlement_status.is_some());
     }
 
+    #[test]
+    fn test_reorged_deposits_roll_back() {
+        let mut swap = create_test_swap();
+
+        // Nothing to roll back before a deposit was seen
+        assert!(swap.user_deposit_reorged().is_err());
+
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 1)
+            .unwrap();
+        swap.user_deposit_reorged().unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingUserDepositInitiated);
+        assert!(swap.user_deposit_status.is_none());
+
+        // The deposit can be detected again, e.g. once it's mined in another block
+        swap.user_deposit_detected(tx_hash(2), U256::from(1000000u64), 0)
+            .unwrap();
+        swap.user_deposit_confirmed().unwrap();
+        // A confirmed user deposit is no longer watched
+        assert!(swap.user_deposit_reorged().is_err());
+        assert!(swap.mm_deposit_reorged().is_err());
+
+        swap.mm_deposit_detected(tx_hash(3), U256::from(500000u64), 1)
+            .unwrap();
+        swap.mm_deposit_reorged().unwrap();
+        assert_eq!(swap.status, SwapStatus::WaitingMMDepositInitiated);
+        assert!(swap.mm_deposit_status.is_none());
+        assert_eq!(
+            swap.user_deposit_status.as_ref().unwrap().tx_hash,
+            tx_hash(2)
+        );
+
+        swap.mm_deposit_detected(tx_hash(4), U256::from(500000u64), 1)
+            .unwrap();
+        swap.mm_deposit_confirmed().unwrap();
+        // The deposit key may already be released, a settled swap stays settled
+        assert!(swap.mm_deposit_reorged().is_err());
+        assert_eq!(swap.status, SwapStatus::Settled);
+    }
+
+    #[test]
+    fn test_mm_deposit_amount_mismatch_refunds_user() {
+        let mut swap = create_test_swap();
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 1)
+            .unwrap();
+
+        // Only a swap waiting for the MM deposit can see an underpayment
+        assert!(swap
+            .mm_deposit_amount_mismatch(tx_hash(3), U256::from(1u64), 1)
+            .is_err());
+
+        swap.user_deposit_confirmed().unwrap();
+        swap.mm_deposit_amount_mismatch(tx_hash(3), U256::from(1u64), 1)
+            .unwrap();
+        assert_eq!(swap.status, SwapStatus::MMDepositAmountMismatch);
+        assert_eq!(
+            swap.mm_deposit_status.as_ref().unwrap().amount,
+            U256::from(1u64)
+        );
+        assert_eq!(
+            swap.failure_reason.as_deref(),
+            Some("MM deposit 0xmm456 sent 1, expected 1000000")
+        );
+
+        // The swap can't settle, the user gets their deposit back
+        assert!(swap.mm_deposit_confirmed().is_err());
+        swap.initiate_user_refund("MM deposit amount mismatch".to_string())
+            .unwrap();
+        assert_eq!(swap.status, SwapStatus::RefundingUser);
+    }
+
+    #[test]
+    fn test_deposit_key_mismatch_flags_for_manual_review() {
+        let mut swap = create_test_swap();
+        assert!(swap
+            .flag_for_manual_review("Deposit key mismatch".to_string())
+            .is_err());
+
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 1)
+            .unwrap();
+        swap.user_deposit_confirmed().unwrap();
+        swap.mm_deposit_detected(tx_hash(3), U256::from(500000u64), 1)
+            .unwrap();
+        swap.mm_deposit_confirmed().unwrap();
+
+        swap.flag_for_manual_review("Deposit key mismatch".to_string())
+            .unwrap();
+        assert_eq!(swap.status, SwapStatus::ManualReview);
+        assert_eq!(swap.failure_reason.as_deref(), Some("Deposit key mismatch"));
+        assert!(!swap.is_active());
+        assert!(swap.mark_private_key_sent().is_err());
+    }
+
     #[test]
     fn test_timeout_refund() {
         let mut swap = create_test_swap();
@@ -398,4 +709,129 @@ mod tests {
             .unwrap();
         assert_eq!(swap.status, SwapStatus::RefundingUser);
     }
+
+    #[test]
+    fn test_refund_address_locks_after_deposit_confirmation() {
+        let mut swap = create_test_swap();
+        swap.set_user_refund_address("0xaaa".to_string()).unwrap();
+
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 0)
+            .unwrap();
+        swap.set_user_refund_address("0xbbb".to_string()).unwrap();
+        assert_eq!(swap.user_refund_address.as_deref(), Some("0xbbb"));
+
+        swap.user_deposit_confirmed().unwrap();
+        assert!(matches!(
+            swap.set_user_refund_address("0xccc".to_string()),
+            Err(TransitionError::RefundAddressLocked {
+                status: SwapStatus::WaitingMMDepositInitiated
+            })
+        ));
+
+        // A refund with an address already set can't be redirected
+        swap.initiate_user_refund("Cancelled".to_string()).unwrap();
+        assert!(!swap.awaiting_refund_address());
+        assert!(swap.set_user_refund_address("0xccc".to_string()).is_err());
+        assert_eq!(swap.user_refund_address.as_deref(), Some("0xbbb"));
+    }
+
+    #[test]
+    fn test_refund_waits_for_a_missing_address() {
+        let mut swap = create_test_swap();
+        swap.initiate_user_refund("Cancelled".to_string()).unwrap();
+        assert!(swap.awaiting_refund_address());
+
+        swap.set_user_refund_address("0xaaa".to_string()).unwrap();
+        assert!(!swap.awaiting_refund_address());
+    }
+
+    /// Apply `transition` through its `Swap` method. Exhaustive, so every
+    /// transition in the table has a method
+    fn apply(swap: &mut Swap, transition: SwapTransition) -> TransitionResult {
+        let amount = U256::from(1000000u64);
+        match transition {
+            SwapTransition::UserDepositDetected => {
+                swap.user_deposit_detected(tx_hash(1), amount, 1)
+            }
+            SwapTransition::UserDepositConfirmed => swap.user_deposit_confirmed(),
+            SwapTransition::UserDepositReorged => swap.user_deposit_reorged(),
+            SwapTransition::MMDepositDetected => swap.mm_deposit_detected(tx_hash(3), amount, 1),
+            SwapTransition::MMDepositReorged => swap.mm_deposit_reorged(),
+            SwapTransition::MMDepositAmountMismatch => {
+                swap.mm_deposit_amount_mismatch(tx_hash(3), amount, 1)
+            }
+            SwapTransition::MMDepositConfirmed => swap.mm_deposit_confirmed(),
+            SwapTransition::InitiateUserRefund => swap.initiate_user_refund("test".to_string()),
+            SwapTransition::InitiateMMRefund => swap.initiate_mm_refund("test".to_string()),
+            SwapTransition::FlagForManualReview => swap.flag_for_manual_review("test".to_string()),
+            SwapTransition::MarkFailed => swap.mark_failed("test".to_string()),
+        }
+    }
+
+    /// Swap in `status` with both deposits recorded, so only the status decides
+    /// whether a transition applies
+    fn swap_in(status: SwapStatus) -> Swap {
+        let mut swap = create_test_swap();
+        swap.user_deposit_detected(tx_hash(1), U256::from(1000000u64), 1)
+            .unwrap();
+        swap.user_deposit_confirmed().unwrap();
+        swap.mm_deposit_detected(tx_hash(3), U256::from(1000000u64), 1)
+            .unwrap();
+        swap.status = status;
+        swap
+    }
+
+    #[test]
+    fn test_transition_methods_match_the_table() {
+        for from in SwapStatus::ALL {
+            let mut reached = Vec::new();
+            for transition in SwapTransition::ALL {
+                let mut swap = swap_in(from);
+                let result = apply(&mut swap, transition);
+                assert_eq!(
+                    result.is_ok(),
+                    transition.applies_from(from),
+                    "{transition:?} from {from:?}: {result:?}"
+                );
+                if result.is_ok() {
+                    assert_eq!(swap.status, transition.target(), "{transition:?}");
+                    reached.push(swap.status);
+                } else {
+                    assert_eq!(
+                        swap.status, from,
+                        "{transition:?} failed but moved the swap"
+                    );
+                }
+            }
+
+            // Every edge of the graph is some method succeeding, and the other way round
+            for to in from.allowed_transitions() {
+                assert!(reached.contains(to), "{from:?} -> {to:?} has no method");
+            }
+            for to in &reached {
+                assert!(
+                    from.can_transition_to(*to),
+                    "{from:?} -> {to:?} isn't in the graph"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_transition_graph_covers_every_status_once() {
+        let graph = transition_graph();
+        let statuses: Vec<_> = graph.iter().map(|(status, _)| *status).collect();
+        assert_eq!(statuses, SwapStatus::ALL);
+
+        let (_, from_failed) = graph
+            .iter()
+            .find(|(status, _)| *status == SwapStatus::Failed)
+            .unwrap();
+        assert!(from_failed.is_empty());
+        // A reorg rolls a confirming deposit back, but never a later status
+        assert!(SwapStatus::WaitingUserDepositConfirmed
+            .can_transition_to(SwapStatus::WaitingUserDepositInitiated));
+        assert!(!SwapStatus::WaitingMMDepositConfirmed
+            .can_transition_to(SwapStatus::WaitingUserDepositInitiated));
+    }
 }
@@ -0,0 +1,140 @@
+use crate::{ChainType, Currency, Lot, Quote, Swap, SwapStatus, TokenIdentifier};
+use alloy::primitives::U256;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+/// Builds swaps for tests. Starts from a Bitcoin to Ethereum swap waiting for
+/// the user deposit, quoted now and expiring in an hour
+pub struct SwapBuilder {
+    swap: Swap,
+}
+
+impl Default for SwapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SwapBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        let now = Utc::now();
+        let native = |chain, decimals| Currency {
+            chain,
+            token: TokenIdentifier::Native,
+            decimals,
+            chain_id: None,
+        };
+        let quote = Quote {
+            id: Uuid::new_v4(),
+            from: Lot {
+                currency: native(ChainType::Bitcoin, 8),
+                amount: U256::from(1_000_000u64),
+            },
+            to: Lot {
+                currency: native(ChainType::Ethereum, 18),
+                amount: U256::from(500_000_000_000_000_000u64),
+            },
+            market_maker_id: Uuid::new_v4(),
+            expires_at: now + Duration::hours(1),
+            created_at: now,
+        };
+        Self {
+            swap: Swap {
+                id: Uuid::new_v4(),
+                market_maker_id: quote.market_maker_id,
+                quote,
+                user_deposit_salt: [1u8; 32],
+                user_deposit_address: format!("bcrt1q{}", Uuid::new_v4().simple()),
+                master_key_version: 1,
+                mm_nonce: *Uuid::new_v4().as_bytes(),
+                user_destination_address: "0xabcdef1234567890abcdef1234567890abcdef12".to_string(),
+                user_evm_account_address: "0x1234567890123456789012345678901234567890"
+                    .parse()
+                    .unwrap(),
+                user_refund_address: None,
+                status: SwapStatus::WaitingUserDepositInitiated,
+                user_required_confirmations: 2,
+                mm_required_confirmations: 4,
+                user_deposit_status: None,
+                mm_deposit_status: None,
+                settlement_status: None,
+                failure_reason: None,
+                failure_at: None,
+                mm_notified_at: None,
+                mm_deposit_detected_at: None,
+                mm_private_key_sent_at: None,
+                trace_id: None,
+                external_reference: None,
+                created_at: now,
+                updated_at: now,
+            },
+        }
+    }
+
+    /// Quoted by and assigned to `market_maker_id`
+    #[must_use]
+    pub fn with_market_maker_id(mut self, market_maker_id: Uuid) -> Self {
+        self.swap.market_maker_id = market_maker_id;
+        self.swap.quote.market_maker_id = market_maker_id;
+        self
+    }
+
+    /// Created from `quote`, assigned to its market maker
+    #[must_use]
+    pub fn with_quote(mut self, quote: Quote) -> Self {
+        self.swap.market_maker_id = quote.market_maker_id;
+        self.swap.quote = quote;
+        self
+    }
+
+    /// Pays `from` for `to`
+    #[must_use]
+    pub fn with_lots(mut self, from: Lot, to: Lot) -> Self {
+        self.swap.quote.from = from;
+        self.swap.quote.to = to;
+        self
+    }
+
+    #[must_use]
+    pub fn with_status(mut self, status: SwapStatus) -> Self {
+        self.swap.status = status;
+        self
+    }
+
+    /// Deposit wallet derived from `salt`, at `address`
+    #[must_use]
+    pub fn with_deposit(mut self, salt: [u8; 32], address: impl Into<String>) -> Self {
+        self.swap.user_deposit_salt = salt;
+        self.swap.user_deposit_address = address.into();
+        self
+    }
+
+    #[must_use]
+    pub fn with_destination(mut self, address: impl Into<String>) -> Self {
+        self.swap.user_destination_address = address.into();
+        self
+    }
+
+    #[must_use]
+    pub fn with_confirmations(mut self, user: u64, mm: u64) -> Self {
+        self.swap.user_required_confirmations = user;
+        self.swap.mm_required_confirmations = mm;
+        self
+    }
+
+    /// Quoted and created at `created_at`, untouched since
+    #[must_use]
+    pub fn with_created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.swap.quote.expires_at = created_at + Duration::hours(1);
+        self.swap.quote.created_at = created_at;
+        self.swap.created_at = created_at;
+        self.swap.updated_at = created_at;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> Swap {
+        self.swap
+    }
+}
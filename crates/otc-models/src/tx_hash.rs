@@ -0,0 +1,196 @@
+use crate::ChainType;
+use alloy::primitives::B256;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use snafu::Snafu;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+/// Hex digits in a 32 byte transaction hash
+const TX_HASH_DIGITS: usize = 64;
+
+/// A transaction hash that isn't 32 hex encoded bytes
+#[derive(Debug, Clone, PartialEq, Eq, Snafu)]
+#[snafu(display("Invalid tx hash {tx_hash:?}: {reason}"))]
+pub struct InvalidTxHash {
+    pub tx_hash: String,
+    pub reason: String,
+}
+
+/// A transaction hash in lowercase hex, with `0x` on EVM chains and without
+/// on Bitcoin.
+///
+/// Parsing accepts either form in any case. Two hashes are equal when their
+/// digits are, whichever form they are kept in, so a hash read back from a row
+/// written before canonicalization still matches its chain's form.
+#[derive(Debug, Clone)]
+pub struct TxHash(String);
+
+impl TxHash {
+    /// `tx_hash` in `chain`'s canonical form
+    pub fn parse(chain: ChainType, tx_hash: &str) -> Result<Self, InvalidTxHash> {
+        tx_hash
+            .parse::<Self>()
+            .map(|parsed| parsed.for_chain(chain))
+    }
+
+    /// The same hash in `chain`'s canonical form
+    #[must_use]
+    pub fn for_chain(self, chain: ChainType) -> Self {
+        match chain {
+            ChainType::Bitcoin => Self(self.digits().to_string()),
+            ChainType::Ethereum if self.0.starts_with("0x") => self,
+            ChainType::Ethereum => Self(format!("0x{}", self.0)),
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The 64 hex digits without any prefix
+    #[must_use]
+    pub fn digits(&self) -> &str {
+        self.0.strip_prefix("0x").unwrap_or(&self.0)
+    }
+}
+
+/// Keeps a `0x` prefix if there is one, use [`TxHash::parse`] when the chain is
+/// known
+impl FromStr for TxHash {
+    type Err = InvalidTxHash;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| InvalidTxHashSnafu { tx_hash: s, reason }.build();
+        let trimmed = s.trim();
+        let (prefix, digits) = match trimmed
+            .strip_prefix("0x")
+            .or_else(|| trimmed.strip_prefix("0X"))
+        {
+            Some(digits) => ("0x", digits),
+            None => ("", trimmed),
+        };
+        if digits.len() != TX_HASH_DIGITS {
+            return Err(invalid("must be 64 hex characters"));
+        }
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid("must be hex encoded"));
+        }
+        Ok(Self(format!("{prefix}{}", digits.to_ascii_lowercase())))
+    }
+}
+
+/// An EVM transaction hash, Bitcoin displays its txids byte reversed
+impl From<B256> for TxHash {
+    fn from(hash: B256) -> Self {
+        Self(format!("{hash:#x}"))
+    }
+}
+
+impl fmt::Display for TxHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq for TxHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.digits() == other.digits()
+    }
+}
+
+impl Eq for TxHash {}
+
+impl Hash for TxHash {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.digits().hash(state);
+    }
+}
+
+impl Serialize for TxHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tx_hash = String::deserialize(deserializer)?;
+        tx_hash.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BITCOIN_TXID: &str = "7d865e959b2466918c9863afca942d0fb89d7c9ac0c99bafc3749504ded97730";
+    const ETHEREUM_TX_HASH: &str =
+        "0x88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63";
+
+    #[test]
+    fn test_bitcoin_hashes_round_trip_without_a_prefix() {
+        let canonical = TxHash::parse(ChainType::Bitcoin, BITCOIN_TXID).unwrap();
+        assert_eq!(canonical.as_str(), BITCOIN_TXID);
+
+        let prefixed = format!("0x{}", BITCOIN_TXID.to_uppercase());
+        let parsed = TxHash::parse(ChainType::Bitcoin, &prefixed).unwrap();
+        assert_eq!(parsed.as_str(), BITCOIN_TXID);
+
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert_eq!(json, format!("\"{BITCOIN_TXID}\""));
+        let back: TxHash = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.as_str(), BITCOIN_TXID);
+    }
+
+    #[test]
+    fn test_ethereum_hashes_round_trip_with_a_prefix() {
+        let digits = ETHEREUM_TX_HASH.strip_prefix("0x").unwrap();
+        for input in [
+            ETHEREUM_TX_HASH.to_string(),
+            digits.to_string(),
+            format!("0X{}", digits.to_uppercase()),
+        ] {
+            let parsed = TxHash::parse(ChainType::Ethereum, &input).unwrap();
+            assert_eq!(parsed.as_str(), ETHEREUM_TX_HASH);
+
+            let json = serde_json::to_string(&parsed).unwrap();
+            assert_eq!(json, format!("\"{ETHEREUM_TX_HASH}\""));
+            let back: TxHash = serde_json::from_str(&json).unwrap();
+            assert_eq!(back.as_str(), ETHEREUM_TX_HASH);
+        }
+    }
+
+    #[test]
+    fn test_evm_hashes_convert_to_the_prefixed_form() {
+        let hash: B256 = ETHEREUM_TX_HASH.parse().unwrap();
+        assert_eq!(TxHash::from(hash).as_str(), ETHEREUM_TX_HASH);
+    }
+
+    #[test]
+    fn test_stored_hashes_deserialize_in_either_form() {
+        let digits = ETHEREUM_TX_HASH.strip_prefix("0x").unwrap();
+        let bare: TxHash = serde_json::from_str(&format!("\"{digits}\"")).unwrap();
+        let prefixed: TxHash = serde_json::from_str(&format!("\"{ETHEREUM_TX_HASH}\"")).unwrap();
+
+        assert_eq!(bare.as_str(), digits);
+        assert_eq!(bare, prefixed);
+        assert_eq!(
+            bare.for_chain(ChainType::Ethereum).as_str(),
+            ETHEREUM_TX_HASH
+        );
+    }
+
+    #[test]
+    fn test_rejects_anything_but_32_hex_bytes() {
+        assert!("abcd".parse::<TxHash>().is_err());
+        assert!("".parse::<TxHash>().is_err());
+        assert!(format!("{}zz", &BITCOIN_TXID[2..])
+            .parse::<TxHash>()
+            .is_err());
+        assert!(serde_json::from_str::<TxHash>("\"user-deposit\"").is_err());
+    }
+}
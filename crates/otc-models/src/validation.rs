@@ -0,0 +1,382 @@
+//! Input sanitization for user-supplied strings
+//!
+//! Everything a client sends that ends up persisted or echoed back (addresses,
+//! token identifiers, signatures, free-form text) is trimmed and checked
+//! against per-field length caps and character restrictions here.
+
+use crate::{
+    canonical_token_address, ChainType, Currency, Lot, Quote, QuoteRequest, TokenIdentifier,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Longest valid bech32 string; covers every bitcoin address format
+pub const MAX_BITCOIN_ADDRESS_LEN: usize = 90;
+
+/// `0x` followed by 40 hex characters
+pub const MAX_EVM_ADDRESS_LEN: usize = 42;
+
+/// Upper bound for hex encoded signatures (65 byte signature with `0x` prefix)
+pub const MAX_SIGNATURE_LEN: usize = 132;
+
+/// Upper bound for reasons and messages stored alongside a swap
+pub const MAX_REASON_LEN: usize = 256;
+
+/// Upper bound for the reference a user attaches to their swap
+pub const MAX_EXTERNAL_REFERENCE_LEN: usize = 128;
+
+/// Characters of an external reference kept when it's logged
+const EXTERNAL_REFERENCE_LOG_CHARS: usize = 8;
+
+/// A validation failure for a single request field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Request payloads that must be sanitized before reaching a handler.
+///
+/// Implementations trim fields in place and report every invalid field at once.
+pub trait Validate {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>>;
+}
+
+#[must_use]
+pub const fn max_address_len(chain: ChainType) -> usize {
+    match chain {
+        ChainType::Bitcoin => MAX_BITCOIN_ADDRESS_LEN,
+        ChainType::Ethereum => MAX_EVM_ADDRESS_LEN,
+    }
+}
+
+/// Trim and validate a bounded, printable, single-line text field
+pub fn sanitize_text(field: &str, value: &str, max_len: usize) -> Result<String, FieldError> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Err(FieldError::new(field, "must not be empty"));
+    }
+    if trimmed.chars().count() > max_len {
+        return Err(FieldError::new(
+            field,
+            format!("must be at most {max_len} characters"),
+        ));
+    }
+    if trimmed.chars().any(char::is_control) {
+        return Err(FieldError::new(
+            field,
+            "must not contain control characters",
+        ));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// Trim and validate an address on the given chain.
+///
+/// Every supported address encoding (bech32, base58, hex) is ASCII alphanumeric,
+/// so anything else is rejected outright.
+pub fn sanitize_address(field: &str, value: &str, chain: ChainType) -> Result<String, FieldError> {
+    let address = sanitize_text(field, value, max_address_len(chain))?;
+    if !address.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(FieldError::new(
+            field,
+            "must only contain alphanumeric characters",
+        ));
+    }
+    Ok(address)
+}
+
+/// Trim and validate a hex encoded signature
+pub fn sanitize_signature(field: &str, value: &str) -> Result<String, FieldError> {
+    let signature = sanitize_text(field, value, MAX_SIGNATURE_LEN)?;
+    let digits = signature.strip_prefix("0x").unwrap_or(&signature);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(FieldError::new(field, "must be hex encoded"));
+    }
+    Ok(signature)
+}
+
+/// Trim and validate the reference a user attaches to their swap: ASCII
+/// alphanumeric, spaces and `-`, `_`, `.`, `:`, `/` or `#`, starting with a
+/// letter or digit so it can't be read as a spreadsheet formula in exports
+pub fn sanitize_external_reference(field: &str, value: &str) -> Result<String, FieldError> {
+    let reference = sanitize_text(field, value, MAX_EXTERNAL_REFERENCE_LEN)?;
+    if !reference.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(FieldError::new(field, "must start with a letter or digit"));
+    }
+    if !reference
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | ':' | '/' | '#'))
+    {
+        return Err(FieldError::new(
+            field,
+            "must only contain alphanumeric characters, spaces, '-', '_', '.', ':', '/' or '#'",
+        ));
+    }
+    Ok(reference)
+}
+
+/// An external reference shortened for logs. References are opaque to us and
+/// may identify the user elsewhere, so they're never logged in full
+#[must_use]
+pub fn external_reference_for_log(reference: &str) -> String {
+    if reference.chars().count() <= EXTERNAL_REFERENCE_LOG_CHARS {
+        return reference.to_string();
+    }
+    let prefix: String = reference
+        .chars()
+        .take(EXTERNAL_REFERENCE_LOG_CHARS)
+        .collect();
+    format!("{prefix}...")
+}
+
+/// Make server-generated text safe to persist and echo: control characters
+/// are replaced and the result is capped at [`MAX_REASON_LEN`] characters.
+#[must_use]
+pub fn sanitize_reason(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_REASON_LEN)
+        .collect()
+}
+
+/// Collect the error from a sanitizer, writing the cleaned value back on success
+pub fn apply(
+    errors: &mut Vec<FieldError>,
+    target: &mut String,
+    result: Result<String, FieldError>,
+) {
+    match result {
+        Ok(value) => *target = value,
+        Err(e) => errors.push(e),
+    }
+}
+
+fn validate_token(
+    errors: &mut Vec<FieldError>,
+    field: &str,
+    token: &mut TokenIdentifier,
+    chain: ChainType,
+) {
+    if let TokenIdentifier::Address(address) = token {
+        let result = sanitize_address(field, address, chain).and_then(|address| match chain {
+            ChainType::Ethereum => {
+                canonical_token_address(&address).map_err(|e| FieldError::new(field, e.reason))
+            }
+            ChainType::Bitcoin => Ok(address),
+        });
+        apply(errors, address, result);
+    }
+}
+
+fn validate_currency(errors: &mut Vec<FieldError>, field: &str, currency: &mut Currency) {
+    let chain = currency.chain;
+    validate_token(
+        errors,
+        &format!("{field}.token"),
+        &mut currency.token,
+        chain,
+    );
+    if chain == ChainType::Bitcoin && currency.chain_id.is_some() {
+        errors.push(FieldError::new(
+            format!("{field}.chain_id"),
+            "bitcoin has no chain ids",
+        ));
+    }
+}
+
+fn validate_lot(errors: &mut Vec<FieldError>, field: &str, lot: &mut Lot) {
+    validate_currency(errors, &format!("{field}.currency"), &mut lot.currency);
+}
+
+impl Validate for Quote {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        validate_lot(&mut errors, "quote.from", &mut self.from);
+        validate_lot(&mut errors, "quote.to", &mut self.to);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Validate for QuoteRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        validate_currency(&mut errors, "from", &mut self.from);
+        validate_currency(&mut errors, "to", &mut self.to);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hostile_inputs() -> Vec<String> {
+        vec![
+            String::new(),
+            "   ".to_string(),
+            "a".repeat(1024 * 1024),
+            "bc1q\u{0000}abc".to_string(),
+            "0xabc\ndef".to_string(),
+            "\u{001b}[31mred".to_string(),
+            "tab\there".to_string(),
+            "bc1q abc".to_string(),
+            "0x<script>".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_hostile_addresses_are_rejected() {
+        for chain in [ChainType::Bitcoin, ChainType::Ethereum] {
+            for input in hostile_inputs() {
+                let result = sanitize_address("address", &input, chain);
+                assert!(
+                    result.is_err(),
+                    "{chain:?} accepted {:?}",
+                    &input[..input.len().min(32)]
+                );
+                assert_eq!(result.unwrap_err().field, "address");
+            }
+        }
+    }
+
+    #[test]
+    fn test_addresses_are_trimmed() {
+        let address = "0x742d35Cc6634C0532925a3b844Bc454e4438f44e";
+        assert_eq!(
+            sanitize_address("address", &format!("  {address} "), ChainType::Ethereum).unwrap(),
+            address
+        );
+    }
+
+    #[test]
+    fn test_address_length_is_bounded_per_chain() {
+        let btc = format!("bc1p{}", "q".repeat(58));
+        assert!(sanitize_address("address", &btc, ChainType::Bitcoin).is_ok());
+        assert!(sanitize_address("address", &btc, ChainType::Ethereum).is_err());
+    }
+
+    #[test]
+    fn test_signature_must_be_hex() {
+        assert!(sanitize_signature("signature", "0xdeadBEEF").is_ok());
+        assert!(sanitize_signature("signature", "xyz").is_err());
+        assert!(sanitize_signature("signature", &"a".repeat(MAX_SIGNATURE_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_external_reference_length_and_charset() {
+        assert_eq!(
+            sanitize_external_reference("ref", "  INV-2024/0042 #3 ").unwrap(),
+            "INV-2024/0042 #3"
+        );
+        let longest = "r".repeat(MAX_EXTERNAL_REFERENCE_LEN);
+        assert_eq!(
+            sanitize_external_reference("ref", &longest).unwrap(),
+            longest
+        );
+        assert!(sanitize_external_reference("ref", &format!("{longest}r")).is_err());
+        // Spaces are allowed in references, unlike in addresses
+        let inputs = hostile_inputs()
+            .into_iter()
+            .filter(|input| input != "bc1q abc");
+        for input in inputs.chain([
+            "invoice;drop".to_string(),
+            "-2+3".to_string(),
+            "caf\u{e9}".to_string(),
+            "a\"b".to_string(),
+        ]) {
+            let result = sanitize_external_reference("ref", &input);
+            assert!(
+                result.is_err(),
+                "accepted {:?}",
+                &input[..input.len().min(32)]
+            );
+            assert_eq!(result.unwrap_err().field, "ref");
+        }
+    }
+
+    #[test]
+    fn test_external_references_are_truncated_for_logs() {
+        assert_eq!(external_reference_for_log("INV-42"), "INV-42");
+        assert_eq!(external_reference_for_log("INV-2024-000042"), "INV-2024...");
+    }
+
+    #[test]
+    fn test_sanitize_reason_strips_control_characters_and_truncates() {
+        let reason = sanitize_reason(&format!("bad\u{0000}\nthing{}", "x".repeat(1000)));
+        assert!(!reason.chars().any(char::is_control));
+        assert_eq!(reason.chars().count(), MAX_REASON_LEN);
+        assert!(reason.starts_with("bad  thing"));
+    }
+
+    #[test]
+    fn test_evm_token_addresses_are_checksummed_or_rejected() {
+        let currency = |token: &str| Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(token.to_string()),
+            decimals: 8,
+            chain_id: None,
+        };
+        let mut request = QuoteRequest {
+            mode: crate::QuoteMode::ExactInput,
+            from: currency("cbb7c0000ab88b473b1f5afd9ef808440eed33bf"),
+            to: currency("0xcbb7c0000ab88b473b1f5afd9ef808440eed33b"),
+            amount: alloy::primitives::U256::from(1u64),
+        };
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "to.token");
+        assert_eq!(
+            request.from.token,
+            TokenIdentifier::Address(crate::CBBTC_ADDRESS.to_string())
+        );
+    }
+
+    #[test]
+    fn test_only_evm_currencies_carry_a_chain_id() {
+        let currency = |chain, chain_id| Currency {
+            chain,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id,
+        };
+        let mut request = QuoteRequest {
+            mode: crate::QuoteMode::ExactInput,
+            from: currency(ChainType::Bitcoin, None),
+            to: currency(ChainType::Ethereum, Some(8453)),
+            amount: alloy::primitives::U256::from(1u64),
+        };
+        assert!(request.validate().is_ok());
+
+        request.from.chain_id = Some(1);
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "from.chain_id");
+    }
+}
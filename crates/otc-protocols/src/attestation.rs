@@ -0,0 +1,440 @@
+//! Attestation of the OTC server's TEE
+//!
+//! The OTC server proves which code it runs with an [`AttestationDocument`]: a
+//! statement binding its code measurement to the websocket endpoint (and TLS
+//! key, when it terminates TLS itself) it serves, signed by an attestation key.
+//! Market makers check the signature against a root of trust they configure
+//! and pin the measurement before trusting the server with fills.
+//!
+//! Documents are signed on request, over a nonce the market maker picks for
+//! each connection so a recorded document can't be replayed: served at
+//! [`ATTESTATION_PATH`] and embedded in the `Connected` message for the nonce
+//! sent in [`ATTESTATION_NONCE_HEADER`]. On devnet a [`MockAttestationProvider`]
+//! signing with a local key stands in for the enclave hardware.
+
+use alloy::primitives::{keccak256, Address, Bytes, Signature, B256};
+use alloy::signers::{local::PrivateKeySigner, SignerSync};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::time::Duration;
+
+pub const ATTESTATION_PATH: &str = "/attestation";
+
+/// Hex encoded 32 byte nonce the market maker wants its connection's
+/// attestation signed over
+pub const ATTESTATION_NONCE_HEADER: &str = "x-attestation-nonce";
+
+/// How old an attestation market makers accept by default
+pub const DEFAULT_ATTESTATION_MAX_AGE_SECONDS: u64 = 300;
+
+/// Separates attestation digests from any other message the key might sign
+const DOMAIN_TAG: &[u8] = b"tee-otc/attestation/v1";
+
+#[derive(Debug, Snafu)]
+pub enum AttestationError {
+    #[snafu(display("Attestation signing key is not a valid secp256k1 key"))]
+    InvalidSigningKey,
+
+    #[snafu(display("Failed to sign attestation: {source}"))]
+    Signing { source: alloy::signers::Error },
+
+    #[snafu(display("Server did not present an attestation"))]
+    MissingAttestation,
+
+    #[snafu(display("Attestation signature is malformed: {source}"))]
+    MalformedSignature {
+        source: alloy::primitives::SignatureError,
+    },
+
+    #[snafu(display("Attestation is signed by {signer}, not the root of trust {root_of_trust}"))]
+    UntrustedSigner {
+        signer: Address,
+        root_of_trust: Address,
+    },
+
+    #[snafu(display("Server runs code with measurement {actual}, expected {expected}"))]
+    MeasurementMismatch { expected: B256, actual: B256 },
+
+    #[snafu(display("Attestation was issued for {attested}, not {expected}"))]
+    EndpointMismatch { expected: String, attested: String },
+
+    #[snafu(display("Attestation was not signed over the nonce this connection sent"))]
+    NonceMismatch,
+
+    #[snafu(display(
+        "Attestation was issued at {issued_at}, more than {max_age_seconds}s from now"
+    ))]
+    StaleAttestation {
+        issued_at: DateTime<Utc>,
+        max_age_seconds: u64,
+    },
+}
+
+pub type AttestationResult<T> = Result<T, AttestationError>;
+
+/// What the attestation key vouches for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct AttestationStatement {
+    /// Who produced the document, e.g. `mock` on devnet
+    pub provider: String,
+    /// Hash of the code image running in the enclave
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub measurement: B256,
+    /// Market maker websocket URL the server serves
+    pub endpoint: String,
+    /// DER encoded public key of the server's TLS certificate, when the
+    /// enclave terminates TLS itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub tls_public_key: Option<Bytes>,
+    /// Nonce the requester sent, proving the document was signed for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub nonce: Option<B256>,
+    pub issued_at: DateTime<Utc>,
+}
+
+impl AttestationStatement {
+    /// Digest the attestation key signs
+    ///
+    /// Variable length fields are length prefixed so distinct statements can
+    /// never produce the same encoding.
+    #[must_use]
+    pub fn digest(&self) -> B256 {
+        let mut bytes = Vec::with_capacity(256);
+        bytes.extend_from_slice(DOMAIN_TAG);
+        encode_bytes(&mut bytes, self.provider.as_bytes());
+        bytes.extend_from_slice(self.measurement.as_slice());
+        encode_bytes(&mut bytes, self.endpoint.as_bytes());
+        match &self.tls_public_key {
+            None => bytes.push(0),
+            Some(key) => {
+                bytes.push(1);
+                encode_bytes(&mut bytes, key);
+            }
+        }
+        match &self.nonce {
+            None => bytes.push(0),
+            Some(nonce) => {
+                bytes.push(1);
+                bytes.extend_from_slice(nonce.as_slice());
+            }
+        }
+        bytes.extend_from_slice(&self.issued_at.timestamp_micros().to_be_bytes());
+        keccak256(bytes)
+    }
+}
+
+fn encode_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(value);
+}
+
+/// Query of GET /attestation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "utoipa",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct AttestationQuery {
+    /// Hex encoded 32 byte nonce to sign the document over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "utoipa", param(value_type = Option<String>))]
+    pub nonce: Option<B256>,
+}
+
+/// Response for GET /attestation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct AttestationDocument {
+    #[serde(flatten)]
+    pub statement: AttestationStatement,
+    /// 65 byte recoverable secp256k1 signature over the statement's digest
+    #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+    pub signature: Bytes,
+}
+
+impl AttestationDocument {
+    /// Address of the key that signed the statement
+    pub fn signer(&self) -> AttestationResult<Address> {
+        Signature::try_from(self.signature.as_ref())
+            .and_then(|signature| signature.recover_address_from_prehash(&self.statement.digest()))
+            .context(MalformedSignatureSnafu)
+    }
+}
+
+/// Source of attestation documents for the running server
+pub trait AttestationProvider: Send + Sync {
+    /// Attest that this enclave serves `endpoint`, and `tls_public_key` if set,
+    /// signing over the requester's `nonce`
+    fn attest(
+        &self,
+        endpoint: &str,
+        tls_public_key: Option<Bytes>,
+        nonce: Option<B256>,
+    ) -> AttestationResult<AttestationDocument>;
+}
+
+/// Devnet stand-in for enclave hardware, signs a configured measurement with a
+/// local key. Anyone holding the key can forge documents, never trust it in
+/// production.
+pub struct MockAttestationProvider {
+    signer: PrivateKeySigner,
+    measurement: B256,
+}
+
+impl std::fmt::Debug for MockAttestationProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockAttestationProvider")
+            .field("root_of_trust", &self.root_of_trust())
+            .field("measurement", &self.measurement)
+            .finish()
+    }
+}
+
+impl MockAttestationProvider {
+    pub const PROVIDER: &'static str = "mock";
+
+    pub fn new(signing_key: &B256, measurement: B256) -> AttestationResult<Self> {
+        let signer = PrivateKeySigner::from_bytes(signing_key)
+            .map_err(|_| AttestationError::InvalidSigningKey)?;
+        Ok(Self {
+            signer,
+            measurement,
+        })
+    }
+
+    /// Address market makers must configure as their root of trust
+    #[must_use]
+    pub fn root_of_trust(&self) -> Address {
+        self.signer.address()
+    }
+}
+
+impl AttestationProvider for MockAttestationProvider {
+    fn attest(
+        &self,
+        endpoint: &str,
+        tls_public_key: Option<Bytes>,
+        nonce: Option<B256>,
+    ) -> AttestationResult<AttestationDocument> {
+        let statement = AttestationStatement {
+            provider: Self::PROVIDER.to_string(),
+            measurement: self.measurement,
+            endpoint: endpoint.to_string(),
+            tls_public_key,
+            nonce,
+            issued_at: Utc::now(),
+        };
+        let signature = self
+            .signer
+            .sign_hash_sync(&statement.digest())
+            .context(SigningSnafu)?;
+        Ok(AttestationDocument {
+            statement,
+            signature: Bytes::copy_from_slice(&signature.as_bytes()),
+        })
+    }
+}
+
+/// Checks attestation documents against a root of trust and a pinned measurement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationVerifier {
+    pub root_of_trust: Address,
+    pub expected_measurement: B256,
+    /// Oldest document accepted, and how far ahead of `now` one may be dated
+    pub max_age: Duration,
+}
+
+impl AttestationVerifier {
+    /// Verify that `document` is signed by the root of trust, attests the
+    /// expected measurement, and was issued for `endpoint` over `nonce` within
+    /// `max_age` of `now`
+    pub fn verify(
+        &self,
+        document: Option<&AttestationDocument>,
+        endpoint: &str,
+        nonce: &B256,
+        now: DateTime<Utc>,
+    ) -> AttestationResult<()> {
+        let document = document.context(MissingAttestationSnafu)?;
+
+        let signer = document.signer()?;
+        ensure!(
+            signer == self.root_of_trust,
+            UntrustedSignerSnafu {
+                signer,
+                root_of_trust: self.root_of_trust,
+            }
+        );
+
+        let statement = &document.statement;
+        ensure!(
+            statement.measurement == self.expected_measurement,
+            MeasurementMismatchSnafu {
+                expected: self.expected_measurement,
+                actual: statement.measurement,
+            }
+        );
+        ensure!(
+            statement.endpoint == endpoint,
+            EndpointMismatchSnafu {
+                expected: endpoint,
+                attested: statement.endpoint.clone(),
+            }
+        );
+        ensure!(statement.nonce.as_ref() == Some(nonce), NonceMismatchSnafu);
+
+        let max_age = chrono::Duration::from_std(self.max_age).unwrap_or(chrono::Duration::MAX);
+        let age = now.signed_duration_since(statement.issued_at);
+        ensure!(
+            age.abs() <= max_age,
+            StaleAttestationSnafu {
+                issued_at: statement.issued_at,
+                max_age_seconds: self.max_age.as_secs(),
+            }
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENDPOINT: &str = "ws://127.0.0.1:3000/ws/mm";
+    const NONCE: B256 = B256::repeat_byte(9);
+
+    fn provider() -> MockAttestationProvider {
+        MockAttestationProvider::new(&B256::repeat_byte(7), B256::repeat_byte(1)).unwrap()
+    }
+
+    fn verifier(provider: &MockAttestationProvider) -> AttestationVerifier {
+        AttestationVerifier {
+            root_of_trust: provider.root_of_trust(),
+            expected_measurement: B256::repeat_byte(1),
+            max_age: Duration::from_secs(DEFAULT_ATTESTATION_MAX_AGE_SECONDS),
+        }
+    }
+
+    #[test]
+    fn test_document_round_trip_verifies() {
+        let provider = provider();
+        let document = provider.attest(ENDPOINT, None, Some(NONCE)).unwrap();
+
+        // Documents travel to the market maker as JSON
+        let json = serde_json::to_string(&document).unwrap();
+        let returned: AttestationDocument = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(returned.signer().unwrap(), provider.root_of_trust());
+        verifier(&provider)
+            .verify(Some(&returned), ENDPOINT, &NONCE, Utc::now())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_wrong_measurement_is_rejected() {
+        let provider = provider();
+        let document = provider.attest(ENDPOINT, None, Some(NONCE)).unwrap();
+        let verifier = AttestationVerifier {
+            expected_measurement: B256::repeat_byte(2),
+            ..verifier(&provider)
+        };
+
+        assert!(matches!(
+            verifier.verify(Some(&document), ENDPOINT, &NONCE, Utc::now()),
+            Err(AttestationError::MeasurementMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_untrusted_signer_is_rejected() {
+        let trusted = provider();
+        let other =
+            MockAttestationProvider::new(&B256::repeat_byte(8), B256::repeat_byte(1)).unwrap();
+        let document = other.attest(ENDPOINT, None, Some(NONCE)).unwrap();
+
+        assert!(matches!(
+            verifier(&trusted).verify(Some(&document), ENDPOINT, &NONCE, Utc::now()),
+            Err(AttestationError::UntrustedSigner { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tampered_statement_is_rejected() {
+        let provider = provider();
+        let mut document = provider
+            .attest(ENDPOINT, Some(Bytes::from_static(b"tls key")), Some(NONCE))
+            .unwrap();
+
+        // Rebinding the document to another key changes the recovered signer
+        document.statement.tls_public_key = Some(Bytes::from_static(b"other key"));
+
+        assert!(matches!(
+            verifier(&provider).verify(Some(&document), ENDPOINT, &NONCE, Utc::now()),
+            Err(AttestationError::UntrustedSigner { .. })
+        ));
+    }
+
+    #[test]
+    fn test_other_endpoint_and_missing_document_are_rejected() {
+        let provider = provider();
+        let document = provider.attest(ENDPOINT, None, Some(NONCE)).unwrap();
+        let verifier = verifier(&provider);
+
+        assert!(matches!(
+            verifier.verify(
+                Some(&document),
+                "wss://otc.example.com/ws/mm",
+                &NONCE,
+                Utc::now()
+            ),
+            Err(AttestationError::EndpointMismatch { .. })
+        ));
+        assert!(matches!(
+            verifier.verify(None, ENDPOINT, &NONCE, Utc::now()),
+            Err(AttestationError::MissingAttestation)
+        ));
+    }
+
+    #[test]
+    fn test_replayed_and_stale_documents_are_rejected() {
+        let provider = provider();
+        let verifier = verifier(&provider);
+        let document = provider.attest(ENDPOINT, None, Some(NONCE)).unwrap();
+
+        // Signed for another connection
+        assert!(matches!(
+            verifier.verify(
+                Some(&document),
+                ENDPOINT,
+                &B256::repeat_byte(10),
+                Utc::now()
+            ),
+            Err(AttestationError::NonceMismatch)
+        ));
+        let unbound = provider.attest(ENDPOINT, None, None).unwrap();
+        assert!(matches!(
+            verifier.verify(Some(&unbound), ENDPOINT, &NONCE, Utc::now()),
+            Err(AttestationError::NonceMismatch)
+        ));
+
+        let max_age = chrono::Duration::seconds(DEFAULT_ATTESTATION_MAX_AGE_SECONDS as i64);
+        let issued_at = document.statement.issued_at;
+        verifier
+            .verify(Some(&document), ENDPOINT, &NONCE, issued_at + max_age)
+            .unwrap();
+        for now in [
+            issued_at + max_age + chrono::Duration::seconds(1),
+            issued_at - max_age - chrono::Duration::seconds(1),
+        ] {
+            assert!(matches!(
+                verifier.verify(Some(&document), ENDPOINT, &NONCE, now),
+                Err(AttestationError::StaleAttestation { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Capability descriptor served by the OTC and RFQ servers
+//!
+//! Clients and market makers fetch it from [`CAPABILITIES_PATH`] when they
+//! connect, so they can discover what a deployment supports instead of relying
+//! on out-of-band configuration. Servers build it from their runtime config.
+
+use otc_models::ChainType;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use crate::mm::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+/// HTTP path both servers serve the descriptor on
+pub const CAPABILITIES_PATH: &str = "/api/v1/capabilities";
+
+/// HTTP API versions served by this build
+pub const API_VERSIONS: &[&str] = &["v1"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ServerKind {
+    Otc,
+    Rfq,
+}
+
+/// How a server treats RFQ quote signatures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteSigningMode {
+    /// Every quote carries a signature, unsigned or invalid quotes are rejected
+    #[default]
+    Required,
+    /// Signatures are checked when present, unsigned quotes are accepted
+    Optional,
+    /// Signatures are not checked
+    Off,
+}
+
+impl fmt::Display for QuoteSigningMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            Self::Required => "required",
+            Self::Optional => "optional",
+            Self::Off => "off",
+        };
+        f.write_str(mode)
+    }
+}
+
+impl FromStr for QuoteSigningMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "required" => Ok(Self::Required),
+            "optional" => Ok(Self::Optional),
+            "off" => Ok(Self::Off),
+            _ => Err(format!(
+                "invalid quote signing mode {s:?}, expected required, optional or off"
+            )),
+        }
+    }
+}
+
+/// Where the minimum and maximum swap amounts come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AmountLimitsSource {
+    /// Each market maker decides per quote, the server enforces no bounds
+    MarketMaker,
+    /// The server enforces per-token deposit bounds, listed by GET /api/v1/currencies
+    SupportedCurrencies,
+}
+
+/// Range of MM protocol versions a server speaks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ProtocolVersions {
+    pub version: String,
+    pub min_version: String,
+}
+
+impl Default for ProtocolVersions {
+    fn default() -> Self {
+        Self {
+            version: PROTOCOL_VERSION.to_string(),
+            min_version: MIN_PROTOCOL_VERSION.to_string(),
+        }
+    }
+}
+
+/// Optional features and whether this deployment has them enabled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Features {
+    pub quote_signing: QuoteSigningMode,
+    pub encrypted_key_handoff: bool,
+    pub partial_quotes: bool,
+    pub webhooks: bool,
+    pub announcements: bool,
+    pub admin_api: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Limits {
+    /// Largest request body the server accepts
+    pub max_request_body_bytes: usize,
+
+    /// Longest free-text field (e.g. an override reason) the server keeps,
+    /// `None` when it accepts no free text
+    pub max_metadata_chars: Option<usize>,
+
+    /// Requests per minute per client, `None` when the server doesn't rate limit
+    pub rate_limit_per_minute: Option<u32>,
+
+    /// Longest the server waits for market makers to answer a quote request
+    pub quote_timeout_ms: Option<u64>,
+
+    pub amount_limits_source: AmountLimitsSource,
+
+    /// Bounds on batched quote requests, `None` when the server doesn't take them
+    #[serde(default)]
+    pub quote_batch: Option<QuoteBatchLimits>,
+}
+
+/// Bounds on POST /api/v1/quotes/request-batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteBatchLimits {
+    /// Most quote requests one batch may hold
+    pub max_requests: usize,
+    /// Batches per minute per client
+    pub rate_limit_per_minute: u32,
+}
+
+/// Response for GET /api/v1/capabilities
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Capabilities {
+    pub server: ServerKind,
+    pub server_version: String,
+    pub api_versions: Vec<String>,
+    pub mm_protocol: ProtocolVersions,
+    pub features: Features,
+    pub limits: Limits,
+
+    /// Chains swaps can settle on, any two of them form a supported pair.
+    /// `None` when the server doesn't restrict chains itself (the RFQ server
+    /// forwards every request to the connected market makers).
+    pub chains: Option<Vec<ChainType>>,
+}
+
+impl Capabilities {
+    /// One line summary of the feature set, for logging after negotiation
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let features = &self.features;
+        format!(
+            "{:?} server {} (api {}, mm protocol {}..={}): quote_signing={} encrypted_key_handoff={} partial_quotes={} webhooks={} announcements={} admin_api={}",
+            self.server,
+            self.server_version,
+            self.api_versions.join(","),
+            self.mm_protocol.min_version,
+            self.mm_protocol.version,
+            features.quote_signing,
+            features.encrypted_key_handoff,
+            features.partial_quotes,
+            features.webhooks,
+            features.announcements,
+            features.admin_api,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_signing_mode_round_trips_through_its_display_form() {
+        for mode in [
+            QuoteSigningMode::Required,
+            QuoteSigningMode::Optional,
+            QuoteSigningMode::Off,
+        ] {
+            assert_eq!(mode.to_string().parse::<QuoteSigningMode>(), Ok(mode));
+            assert_eq!(
+                serde_json::to_value(mode).unwrap(),
+                serde_json::Value::String(mode.to_string())
+            );
+        }
+        assert!("sometimes".parse::<QuoteSigningMode>().is_err());
+    }
+}
@@ -1,2 +1,4 @@
+pub mod attestation;
+pub mod capabilities;
 pub mod mm;
 pub mod rfq;
\ No newline at end of file
@@ -1,25 +1,33 @@
 use alloy::primitives::U256;
 use chrono::{DateTime, Utc};
-use otc_models::{ChainType, Lot};
+use otc_models::{ChainType, FillCost, Lot, Redacted, SwapStatus, TxHash};
+
+use crate::attestation::AttestationDocument;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 /// Response from OTC server confirming connection
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Connected {
     pub session_id: Uuid,
     pub server_version: String,
     pub timestamp: DateTime<Utc>,
+    /// Proof of the code the server runs, `None` outside a TEE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationDocument>,
 }
 
 /// Messages sent from OTC server to Market Maker
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MMRequest {
     /// Ask MM if they will fill a specific quote
     ValidateQuote {
         request_id: Uuid,
         quote_id: Uuid,
+        #[cfg_attr(feature = "utoipa", schema(value_type = Vec<u8>))]
         quote_hash: [u8; 32],
         user_destination_address: String,
         timestamp: DateTime<Utc>,
@@ -33,7 +41,8 @@ pub enum MMRequest {
         /// MM's deposit address
         deposit_address: String,
         /// Proof that user is real - their deposit tx hash
-        user_tx_hash: String,
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        user_tx_hash: TxHash,
         timestamp: DateTime<Utc>,
     },
 
@@ -45,9 +54,100 @@ pub enum MMRequest {
         /// User's destination address where MM should send funds
         user_destination_address: String,
         /// The nonce MM must embed in their transaction
+        #[cfg_attr(feature = "utoipa", schema(value_type = Vec<u8>))]
         mm_nonce: [u8; 16],
         /// Expected payment details
         expected_lot: Lot,
+        /// User's deposit address, whose key is released at settlement
+        user_deposit_address: String,
+        /// Chain of the user's deposit address
+        user_deposit_chain: ChainType,
+        /// The user's own reference for the swap, opaque to the MM
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        external_reference: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Ask the MM to pay again because the deposit it reported reverted or
+    /// dropped off the chain. Carries the `UserDepositConfirmed` parameters, the
+    /// MM answers with `DepositInitiated` as it did the first time. Only sent
+    /// to MMs speaking `DEPOSIT_RETRY_VERSION` or newer
+    DepositRetryRequested {
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        /// The reported deposit that failed
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        failed_tx_hash: TxHash,
+        user_destination_address: String,
+        #[cfg_attr(feature = "utoipa", schema(value_type = Vec<u8>))]
+        mm_nonce: [u8; 16],
+        expected_lot: Lot,
+        /// Retries requested for the swap so far, this one included
+        attempt: u32,
+        /// The server gives up on this attempt if no deposit shows up by then
+        deadline: DateTime<Utc>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Notify MM that its deposit was rejected, the user is refunded instead
+    MMDepositRejected {
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        /// The rejected deposit
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        tx_hash: TxHash,
+        error_code: MMErrorCode,
+        /// Amount the quote requires
+        #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+        expected_amount: U256,
+        /// Amount the deposit actually sent
+        #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+        received_amount: U256,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Notify MM that the swap won't settle, so it can release whatever it
+    /// set aside for the quote. Only sent to MMs speaking `SWAP_FAILED_VERSION`
+    /// or newer
+    SwapFailed {
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        reason_code: SwapFailureReason,
+        /// The user's refund, once one has been broadcast
+        refund_tx_hash: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Ask the MM to give up paying a swap still waiting on its deposit, so an
+    /// operator can cancel it and refund the user. An MM that accepts must not
+    /// pay the swap afterwards. Only sent to MMs speaking
+    /// `CANCEL_REQUEST_VERSION` or newer
+    CancelRequested {
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Tell the MM one of its swaps changed status. Informational, the MM
+    /// doesn't answer and may miss some. Only sent to MMs speaking
+    /// `SWAP_STATUS_UPDATE_VERSION` or newer
+    SwapStatusUpdate {
+        request_id: Uuid,
+        swap_id: Uuid,
+        quote_id: Uuid,
+        status: SwapStatus,
+        /// Confirmations of the user's deposit, once it was seen
+        user_deposit_confirmations: Option<u64>,
+        /// Confirmations of the MM's deposit, once it was seen
+        mm_deposit_confirmations: Option<u64>,
+        user_deposit_detected_at: Option<DateTime<Utc>>,
+        mm_deposit_detected_at: Option<DateTime<Utc>>,
+        /// When the swap entered `status`
+        updated_at: DateTime<Utc>,
         timestamp: DateTime<Utc>,
     },
 
@@ -55,14 +155,24 @@ pub enum MMRequest {
     SwapComplete {
         request_id: Uuid,
         swap_id: Uuid,
-        /// Private key for user's deposit wallet
-        user_deposit_private_key: String,
+        /// Private key for user's deposit wallet, sent in full but never logged
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        user_deposit_private_key: Redacted<String>,
         chain: ChainType,
         /// Final settlement details
         user_withdrawal_tx: String,
         timestamp: DateTime<Utc>,
     },
 
+    /// The server is shutting down and closes the connection after this
+    /// message, the MM should reconnect right away to reach a healthy
+    /// instance. Only sent to MMs speaking `GOING_AWAY_VERSION` or newer
+    GoingAway {
+        request_id: Uuid,
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Request MM status/health check
     Ping {
         request_id: Uuid,
@@ -72,6 +182,7 @@ pub enum MMRequest {
 
 /// Messages sent from Market Maker to OTC server
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MMResponse {
     /// Response to `ValidateQuote`
@@ -80,8 +191,8 @@ pub enum MMResponse {
         quote_id: Uuid,
         /// Whether MM will fill this quote
         accepted: bool,
-        /// Optional reason if rejected
-        rejection_reason: Option<String>,
+        /// Why the quote was rejected, if it was
+        rejection_reason: Option<QuoteRejection>,
         timestamp: DateTime<Utc>,
     },
 
@@ -89,10 +200,28 @@ pub enum MMResponse {
     DepositInitiated {
         request_id: Uuid,
         swap_id: Uuid,
-        /// Transaction hash of MM's deposit
-        tx_hash: String,
+        /// Transaction hash of MM's deposit, either form parses but the
+        /// server keeps it in the chain's canonical one
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        tx_hash: TxHash,
         /// Actual amount sent (in case of rounding)
+        #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
         amount_sent: U256,
+        /// Fee the MM paid for the deposit in the chain's native currency, if known
+        #[serde(default)]
+        #[cfg_attr(feature = "utoipa", schema(value_type = Option<otc_models::U256Schema>))]
+        fee: Option<U256>,
+        /// Fee rate and block space the deposit took, if known
+        #[serde(default)]
+        fill_cost: Option<FillCost>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Response to `CancelRequested`, refused when the MM already paid
+    CancelAcknowledged {
+        request_id: Uuid,
+        swap_id: Uuid,
+        accepted: bool,
         timestamp: DateTime<Utc>,
     },
 
@@ -103,6 +232,18 @@ pub enum MMResponse {
         timestamp: DateTime<Utc>,
     },
 
+    /// Acknowledgment of `SwapComplete` after checking the released key
+    /// against the deposit address from `UserDepositConfirmed`
+    VerifyDepositKey {
+        request_id: Uuid,
+        swap_id: Uuid,
+        deposit_address: String,
+        chain: ChainType,
+        /// Whether the key controls `deposit_address`
+        matches: bool,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Response to Ping
     Pong {
         request_id: Uuid,
@@ -124,6 +265,7 @@ pub enum MMResponse {
 
 /// Market Maker operational status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum MMStatus {
     /// Fully operational and accepting quotes
@@ -138,12 +280,17 @@ pub enum MMStatus {
 
 /// Standard error codes for MM protocol
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum MMErrorCode {
     /// Quote not found in MM's system
     QuoteNotFound,
     /// Quote has expired
     QuoteExpired,
+    /// Quote differs from the one the MM issued
+    QuoteMismatch,
+    /// The market moved too far since the quote was issued
+    PriceMoved,
     /// Insufficient liquidity
     InsufficientLiquidity,
     /// Invalid request format
@@ -158,13 +305,271 @@ pub enum MMErrorCode {
     InvalidAmount,
 }
 
+/// Why the server gave up on a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SwapFailureReason {
+    /// The user's deposit didn't arrive or confirm before the swap expired
+    UserDepositTimeout,
+    /// The MM's deposit didn't arrive or confirm before the swap expired
+    MMDepositTimeout,
+    /// The MM's deposit paid less than the quote
+    AmountMismatch,
+    /// An operator cancelled the swap
+    Cancelled,
+}
+
+impl std::fmt::Display for SwapFailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::UserDepositTimeout => "user_deposit_timeout",
+            Self::MMDepositTimeout => "mm_deposit_timeout",
+            Self::AmountMismatch => "amount_mismatch",
+            Self::Cancelled => "cancelled",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Why a market maker won't fill a quote it was asked to validate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteRejection {
+    pub code: MMErrorCode,
+    /// Human readable details
+    pub message: String,
+}
+
+impl QuoteRejection {
+    pub fn new(code: MMErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for QuoteRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
 /// Wrapper for protocol messages with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct ProtocolMessage<T> {
     /// Protocol version
     pub version: String,
-    /// Message sequence number for ordering
+    /// Message sequence number for ordering. The OTC server numbers what it
+    /// sends on a connection from 1 with no gaps, so a jump means a lost message
     pub sequence: u64,
     /// The actual message
     pub payload: T,
+    /// Correlation id of the quote or swap the message is about, for logging
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otc_models::FillUsage;
+
+    #[test]
+    fn swap_failed_round_trips() {
+        let request = MMRequest::SwapFailed {
+            request_id: Uuid::new_v4(),
+            swap_id: Uuid::new_v4(),
+            quote_id: Uuid::new_v4(),
+            reason_code: SwapFailureReason::MMDepositTimeout,
+            refund_tx_hash: Some("abcd".to_string()),
+            timestamp: Utc::now(),
+        };
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["type"], "swap_failed");
+        assert_eq!(json["reason_code"], "mm_deposit_timeout");
+
+        let decoded: MMRequest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+    }
+
+    #[test]
+    fn cancel_request_and_acknowledgment_round_trip() {
+        let swap_id = Uuid::new_v4();
+        let request = MMRequest::CancelRequested {
+            request_id: Uuid::new_v4(),
+            swap_id,
+            quote_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["type"], "cancel_requested");
+        let decoded: MMRequest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+
+        let response = MMResponse::CancelAcknowledged {
+            request_id: Uuid::new_v4(),
+            swap_id,
+            accepted: true,
+            timestamp: Utc::now(),
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["type"], "cancel_acknowledged");
+        assert_eq!(json["accepted"], true);
+        let decoded: MMResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+    }
+
+    #[test]
+    fn swap_status_update_round_trips() {
+        let request = MMRequest::SwapStatusUpdate {
+            request_id: Uuid::new_v4(),
+            swap_id: Uuid::new_v4(),
+            quote_id: Uuid::new_v4(),
+            status: SwapStatus::WaitingMMDepositConfirmed,
+            user_deposit_confirmations: Some(6),
+            mm_deposit_confirmations: Some(0),
+            user_deposit_detected_at: Some(Utc::now()),
+            mm_deposit_detected_at: Some(Utc::now()),
+            updated_at: Utc::now(),
+            timestamp: Utc::now(),
+        };
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["type"], "swap_status_update");
+        assert_eq!(json["status"], "waiting_mm_deposit_confirmed");
+
+        let decoded: MMRequest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+    }
+
+    #[test]
+    fn user_deposit_confirmed_without_external_reference_decodes() {
+        let request = MMRequest::UserDepositConfirmed {
+            request_id: Uuid::new_v4(),
+            swap_id: Uuid::new_v4(),
+            quote_id: Uuid::new_v4(),
+            user_destination_address: "bcrt1qtest".to_string(),
+            mm_nonce: [7; 16],
+            expected_lot: Lot {
+                currency: otc_models::Currency {
+                    chain: ChainType::Bitcoin,
+                    token: otc_models::TokenIdentifier::Native,
+                    decimals: 8,
+                    chain_id: None,
+                },
+                amount: U256::from(1_000u64),
+            },
+            user_deposit_address: "0xdeposit".to_string(),
+            user_deposit_chain: ChainType::Ethereum,
+            external_reference: Some("INV-42".to_string()),
+            timestamp: Utc::now(),
+        };
+        let mut json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["external_reference"], "INV-42");
+
+        json.as_object_mut().unwrap().remove("external_reference");
+        match serde_json::from_value(json).unwrap() {
+            MMRequest::UserDepositConfirmed {
+                external_reference, ..
+            } => assert_eq!(external_reference, None),
+            other => panic!("unexpected request {other:?}"),
+        }
+    }
+
+    #[test]
+    fn swap_complete_hides_the_key_from_debug_output() {
+        let key = "cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy";
+        let request = MMRequest::SwapComplete {
+            request_id: Uuid::new_v4(),
+            swap_id: Uuid::new_v4(),
+            user_deposit_private_key: Redacted::new(key.to_string()),
+            chain: ChainType::Bitcoin,
+            user_withdrawal_tx: "abcd".to_string(),
+            timestamp: Utc::now(),
+        };
+
+        assert!(!format!("{request:?}").contains(key));
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["user_deposit_private_key"], key);
+    }
+
+    fn deposit_initiated(fill_cost: Option<FillCost>) -> MMResponse {
+        MMResponse::DepositInitiated {
+            request_id: Uuid::new_v4(),
+            swap_id: Uuid::new_v4(),
+            tx_hash: "0x88df016429689c079f3b2f6ad39fa052532c56b6a39df8e3c84c03b8346cfc63"
+                .parse()
+                .unwrap(),
+            amount_sent: U256::from(1_000u64),
+            fee: Some(U256::from(250u64)),
+            fill_cost,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn deposit_initiated_without_fee_decodes() {
+        let mut json = serde_json::to_value(deposit_initiated(None)).unwrap();
+        json.as_object_mut().unwrap().remove("fee");
+        json.as_object_mut().unwrap().remove("fill_cost");
+
+        match serde_json::from_value(json).unwrap() {
+            MMResponse::DepositInitiated { fee, fill_cost, .. } => {
+                assert_eq!(fee, None);
+                assert_eq!(fill_cost, None);
+            }
+            other => panic!("unexpected response {other:?}"),
+        }
+    }
+
+    #[test]
+    fn deposit_initiated_fill_cost_round_trips() {
+        for fill_cost in [
+            FillCost {
+                fee_in_lot_currency: Some(1_410),
+                usage: FillUsage::Bitcoin {
+                    sat_per_vb: 6.5,
+                    vsize: 217,
+                    inputs: 1,
+                    outputs: 4,
+                },
+            },
+            FillCost {
+                fee_in_lot_currency: None,
+                usage: FillUsage::Ethereum {
+                    effective_gas_price: U256::from(1_000_000_007u64),
+                    gas_used: 49_361,
+                },
+            },
+        ] {
+            let json = serde_json::to_value(deposit_initiated(Some(fill_cost.clone()))).unwrap();
+            assert!(json["fill_cost"]["usage"]["chain"].is_string());
+
+            match serde_json::from_value(json).unwrap() {
+                MMResponse::DepositInitiated {
+                    fill_cost: decoded, ..
+                } => assert_eq!(decoded, Some(fill_cost)),
+                other => panic!("unexpected response {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn failure_reason_display_matches_serde() {
+        for reason in [
+            SwapFailureReason::UserDepositTimeout,
+            SwapFailureReason::MMDepositTimeout,
+            SwapFailureReason::AmountMismatch,
+            SwapFailureReason::Cancelled,
+        ] {
+            assert_eq!(
+                serde_json::to_value(reason).unwrap(),
+                serde_json::Value::String(reason.to_string())
+            );
+        }
+    }
 }
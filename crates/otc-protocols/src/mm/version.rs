@@ -2,11 +2,30 @@ use super::errors::{ProtocolResult, ProtocolError};
 use serde::{Deserialize, Serialize};
 
 /// Current protocol version
-pub const PROTOCOL_VERSION: &str = "1.0.0";
+pub const PROTOCOL_VERSION: &str = "1.5.0";
 
 /// Minimum supported protocol version
 pub const MIN_PROTOCOL_VERSION: &str = "1.0.0";
 
+/// First protocol version with `MMRequest::SwapFailed`
+pub const SWAP_FAILED_VERSION: &str = "1.1.0";
+
+/// First protocol version with `MMRequest::SwapStatusUpdate`
+pub const SWAP_STATUS_UPDATE_VERSION: &str = "1.2.0";
+
+/// First protocol version with `MMRequest::GoingAway`
+pub const GOING_AWAY_VERSION: &str = "1.3.0";
+
+/// First protocol version with `MMRequest::DepositRetryRequested`
+pub const DEPOSIT_RETRY_VERSION: &str = "1.4.0";
+
+/// First protocol version with `MMRequest::CancelRequested`
+pub const CANCEL_REQUEST_VERSION: &str = "1.5.0";
+
+/// Header a market maker announces its protocol version in when connecting.
+/// Connections without it speak `MIN_PROTOCOL_VERSION`
+pub const PROTOCOL_VERSION_HEADER: &str = "x-protocol-version";
+
 /// Protocol version information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VersionInfo {
@@ -27,6 +46,11 @@ impl Default for VersionInfo {
                 "validate_quote".to_string(),
                 "user_deposit_notification".to_string(),
                 "swap_complete_notification".to_string(),
+                "swap_failed_notification".to_string(),
+                "swap_status_update".to_string(),
+                "going_away".to_string(),
+                "deposit_retry".to_string(),
+                "cancel_request".to_string(),
                 "health_check".to_string(),
             ],
         }
@@ -40,6 +64,19 @@ impl Default for VersionInfo {
     version.starts_with("1.")
 }
 
+/// Whether `version` is `min` or newer, comparing the dot separated numbers.
+/// A version that doesn't parse is never new enough
+#[must_use]
+pub fn is_version_at_least(version: &str, min: &str) -> bool {
+    fn parts(version: &str) -> Option<Vec<u64>> {
+        version.split('.').map(|part| part.parse().ok()).collect()
+    }
+    match (parts(version), parts(min)) {
+        (Some(version), Some(min)) => version >= min,
+        _ => false,
+    }
+}
+
 /// Ensure version compatibility, returning error if incompatible
 pub fn ensure_version_compatible(version: &str) -> ProtocolResult<()> {
     if is_version_compatible(version) {
@@ -50,4 +87,21 @@ pub fn ensure_version_compatible(version: &str) -> ProtocolResult<()> {
             received: version.to_string(),
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(is_version_at_least("1.1.0", SWAP_FAILED_VERSION));
+        assert!(is_version_at_least("1.10.0", "1.2.0"));
+        assert!(!is_version_at_least("1.0.0", SWAP_FAILED_VERSION));
+        assert!(!is_version_at_least(
+            SWAP_FAILED_VERSION,
+            SWAP_STATUS_UPDATE_VERSION
+        ));
+        assert!(!is_version_at_least("1.x", "1.0.0"));
+    }
+}
@@ -1,18 +1,50 @@
+use alloy::primitives::U256;
 use chrono::{DateTime, Utc};
-use otc_models::{Lot, Quote, QuoteRequest};
+use otc_models::{Lot, Quote, QuoteMode, QuoteRequest};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+pub mod signing;
+
+pub use signing::*;
+
+/// Current RFQ protocol version
+pub const PROTOCOL_VERSION: &str = "1.4.0";
+
+/// Minimum supported RFQ protocol version
+pub const MIN_PROTOCOL_VERSION: &str = "1.0.0";
+
+/// First RFQ protocol version with `RFQRequest::QuoteBatchRequested`
+pub const QUOTE_BATCH_VERSION: &str = "1.1.0";
+
+/// First RFQ protocol version with `RFQRequest::GoingAway`
+pub const GOING_AWAY_VERSION: &str = "1.2.0";
+
+/// First RFQ protocol version with `RFQRequest::QuoteLockRequested`
+pub const QUOTE_LOCK_VERSION: &str = "1.3.0";
+
+/// First RFQ protocol version with `RFQRequest::QuoteOutcome`
+pub const QUOTE_OUTCOME_VERSION: &str = "1.4.0";
+
+/// Market makers announce their RFQ protocol version in the same header as on
+/// the OTC server, connections without it speak `MIN_PROTOCOL_VERSION`
+pub use crate::mm::PROTOCOL_VERSION_HEADER;
+
 /// Protocol wrapper for RFQ messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct ProtocolMessage<T> {
     pub version: String,
     pub sequence: u64,
     pub payload: T,
+    /// Correlation id of the quote request the message is about, for logging
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
 }
 
 /// Response from RFQ server confirming connection
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Connected {
     pub session_id: Uuid,
     pub server_version: String,
@@ -21,6 +53,7 @@ pub struct Connected {
 
 /// Messages sent from RFQ server to Market Maker
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum RFQRequest {
     /// Broadcast to all MMs when user requests quotes
@@ -30,6 +63,13 @@ pub enum RFQRequest {
         timestamp: DateTime<Utc>,
     },
 
+    /// Several quote requests at once, answered with a `QuoteBatchResponse`.
+    /// Only sent to MMs speaking `QUOTE_BATCH_VERSION` or newer
+    QuoteBatchRequested {
+        requests: Vec<BatchedQuoteRequest>,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Notify winning MM their quote was selected
     QuoteSelected {
         request_id: Uuid,
@@ -37,6 +77,34 @@ pub enum RFQRequest {
         timestamp: DateTime<Utc>,
     },
 
+    /// How a request this MM quoted was decided, sent to every MM whose quote
+    /// was in the running, the winner included. Only sent to MMs speaking
+    /// `QUOTE_OUTCOME_VERSION` or newer
+    QuoteOutcome {
+        outcome: QuoteOutcome,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// A user wants the selected quote held for them until `lock_expires_at`,
+    /// answered with a `QuoteLockResponse`. Accepting commits to filling the
+    /// quote without being asked to validate it again. Only sent to MMs
+    /// speaking `QUOTE_LOCK_VERSION` or newer
+    QuoteLockRequested {
+        request_id: Uuid,
+        quote_id: Uuid,
+        lock_expires_at: DateTime<Utc>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// The server is shutting down and closes the connection after this
+    /// message, reconnect right away. Only sent to MMs speaking
+    /// `GOING_AWAY_VERSION` or newer
+    GoingAway {
+        request_id: Uuid,
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Ping for keepalive
     Ping {
         request_id: Uuid,
@@ -44,7 +112,34 @@ pub enum RFQRequest {
     },
 }
 
+/// One request of a `QuoteBatchRequested`, answered under its own `request_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct BatchedQuoteRequest {
+    pub request_id: Uuid,
+    pub request: QuoteRequest,
+}
+
+/// How a request a market maker quoted was decided. The winning price is
+/// shared, the market maker that gave it isn't
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QuoteOutcome {
+    /// The request as this market maker was sent it
+    pub request_id: Uuid,
+    /// This market maker's quote
+    pub quote_id: Uuid,
+    pub won: bool,
+    pub mode: QuoteMode,
+    /// Price of the winning quote: what the user receives for exact input,
+    /// what it pays for exact output
+    #[cfg_attr(feature = "utoipa", schema(value_type = otc_models::U256Schema))]
+    pub winning_amount: U256,
+    pub decided_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum RFQResult<T> {
     Success(T),
@@ -55,6 +150,7 @@ pub enum RFQResult<T> {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct FeeSchedule {
     pub network_fee_sats: u64,
     pub liquidity_fee_sats: u64,
@@ -62,13 +158,19 @@ pub struct FeeSchedule {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct QuoteWithFees {
     pub quote: Quote,
     pub fees: FeeSchedule,
+    /// Hex encoded signature over the quote, attached by the RFQ server.
+    /// Must be passed back to the OTC server when creating a swap.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 /// Messages sent from Market Maker to RFQ server
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum RFQResponse {
     /// MM's response with their quote (or None if they can't quote)
@@ -78,6 +180,23 @@ pub enum RFQResponse {
         timestamp: DateTime<Utc>,
     },
 
+    /// MM's quotes for a `QuoteBatchRequested`, requests it can't quote may be left out
+    QuoteBatchResponse {
+        responses: Vec<BatchedQuoteResponse>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// MM's answer to a `QuoteLockRequested`, funds for an accepted lock
+    /// stay reserved until it expires
+    QuoteLockResponse {
+        request_id: Uuid,
+        quote_id: Uuid,
+        accepted: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rejection_reason: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Pong response
     Pong {
         request_id: Uuid,
@@ -93,8 +212,17 @@ pub enum RFQResponse {
     },
 }
 
+/// MM's answer to one request of a `QuoteBatchRequested`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct BatchedQuoteResponse {
+    pub request_id: Uuid,
+    pub quote: RFQResult<QuoteWithFees>,
+}
+
 /// Standard error codes for RFQ protocol
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum RFQErrorCode {
     /// Cannot provide quote for this pair
@@ -0,0 +1,306 @@
+//! Quote signing for quotes issued by the RFQ server
+//!
+//! The RFQ server signs every quote it hands out with a key shared with the
+//! OTC server, so the OTC server can reject quotes it never issued (or that
+//! were modified by the client) before contacting a market maker. Locks a
+//! market maker granted on a quote are signed the same way, so the OTC server
+//! can trust them without asking the market maker again.
+
+use alloy::hex;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use otc_models::{ChainType, Lot, Quote, TokenIdentifier};
+use sha2::Sha256;
+use snafu::prelude::*;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Set on the chain byte of a lot whose currency names its network, which is
+/// then followed by the chain id
+const NETWORK_FLAG: u8 = 0x80;
+
+/// Minimum accepted length of the shared signing key in bytes
+pub const MIN_QUOTE_SIGNING_KEY_LEN: usize = 32;
+
+#[derive(Debug, Snafu)]
+pub enum QuoteSignatureError {
+    #[snafu(display("Quote signing key is not valid hex: {source}"))]
+    InvalidKeyEncoding { source: hex::FromHexError },
+
+    #[snafu(display(
+        "Quote signing key must be at least {MIN_QUOTE_SIGNING_KEY_LEN} bytes, got {len}"
+    ))]
+    KeyTooShort { len: usize },
+
+    #[snafu(display("Quote signature is missing"))]
+    MissingSignature,
+
+    #[snafu(display("Quote signature is not valid hex: {source}"))]
+    MalformedSignature { source: hex::FromHexError },
+
+    #[snafu(display("Quote signature does not match quote contents"))]
+    SignatureMismatch,
+}
+
+/// Prefix of everything signed for a lock, so a lock token is never a valid
+/// quote signature or the other way around
+const LOCK_DOMAIN: &[u8] = b"quote-lock";
+
+pub type QuoteSignatureResult<T> = Result<T, QuoteSignatureError>;
+
+/// Signs and verifies quotes with HMAC-SHA256 over their canonical encoding
+#[derive(Clone)]
+pub struct QuoteSigner {
+    key: Vec<u8>,
+}
+
+impl std::fmt::Debug for QuoteSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuoteSigner")
+            .field("key", &"<redacted>")
+            .finish()
+    }
+}
+
+impl QuoteSigner {
+    pub fn new(key: &[u8]) -> QuoteSignatureResult<Self> {
+        ensure!(
+            key.len() >= MIN_QUOTE_SIGNING_KEY_LEN,
+            KeyTooShortSnafu { len: key.len() }
+        );
+        Ok(Self { key: key.to_vec() })
+    }
+
+    /// Build a signer from a hex encoded key (with or without a 0x prefix)
+    pub fn from_hex(key: &str) -> QuoteSignatureResult<Self> {
+        let key = hex::decode(key).context(InvalidKeyEncodingSnafu)?;
+        Self::new(&key)
+    }
+
+    /// Sign a quote, returning the hex encoded signature
+    #[must_use]
+    pub fn sign(&self, quote: &Quote) -> String {
+        hex::encode(self.mac(quote).finalize().into_bytes())
+    }
+
+    /// Verify a hex encoded signature against the quote in constant time
+    pub fn verify(&self, quote: &Quote, signature: &str) -> QuoteSignatureResult<()> {
+        let signature = hex::decode(signature).context(MalformedSignatureSnafu)?;
+        self.mac(quote)
+            .verify_slice(&signature)
+            .map_err(|_| QuoteSignatureError::SignatureMismatch)
+    }
+
+    /// Sign a lock on `quote` held until `expires_at`, returning the hex encoded token
+    #[must_use]
+    pub fn sign_lock(&self, quote: &Quote, expires_at: DateTime<Utc>) -> String {
+        hex::encode(self.lock_mac(quote, expires_at).finalize().into_bytes())
+    }
+
+    /// Verify a hex encoded lock token against the quote and lock expiry in constant time
+    pub fn verify_lock(
+        &self,
+        quote: &Quote,
+        expires_at: DateTime<Utc>,
+        token: &str,
+    ) -> QuoteSignatureResult<()> {
+        let token = hex::decode(token).context(MalformedSignatureSnafu)?;
+        self.lock_mac(quote, expires_at)
+            .verify_slice(&token)
+            .map_err(|_| QuoteSignatureError::SignatureMismatch)
+    }
+
+    fn mac(&self, quote: &Quote) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(&canonical_quote_bytes(quote));
+        mac
+    }
+
+    fn lock_mac(&self, quote: &Quote, expires_at: DateTime<Utc>) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(LOCK_DOMAIN);
+        mac.update(&canonical_quote_bytes(quote));
+        mac.update(&expires_at.timestamp_micros().to_be_bytes());
+        mac
+    }
+}
+
+/// Deterministic byte encoding of every economically relevant quote field.
+///
+/// Variable length fields are length prefixed so distinct quotes can never
+/// produce the same encoding.
+fn canonical_quote_bytes(quote: &Quote) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(256);
+    bytes.extend_from_slice(quote.id.as_bytes());
+    bytes.extend_from_slice(quote.market_maker_id.as_bytes());
+    encode_lot(&mut bytes, &quote.from);
+    encode_lot(&mut bytes, &quote.to);
+    bytes.extend_from_slice(&quote.expires_at.timestamp_micros().to_be_bytes());
+    bytes.extend_from_slice(&quote.created_at.timestamp_micros().to_be_bytes());
+    bytes
+}
+
+fn encode_lot(bytes: &mut Vec<u8>, lot: &Lot) {
+    let chain = match lot.currency.chain {
+        ChainType::Bitcoin => 0,
+        ChainType::Ethereum => 1,
+    };
+    match lot.currency.chain_id {
+        // Quotes on a primary network encode as they did before networks existed
+        None => bytes.push(chain),
+        Some(chain_id) => {
+            bytes.push(chain | NETWORK_FLAG);
+            bytes.extend_from_slice(&chain_id.to_be_bytes());
+        }
+    }
+    match &lot.currency.token {
+        TokenIdentifier::Native => bytes.push(0),
+        TokenIdentifier::Address(address) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&(address.len() as u64).to_be_bytes());
+            bytes.extend_from_slice(address.as_bytes());
+        }
+    }
+    bytes.push(lot.currency.decimals);
+    bytes.extend_from_slice(&lot.amount.to_be_bytes::<32>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+    use chrono::{Duration, Utc};
+    use otc_models::Currency;
+    use uuid::Uuid;
+
+    fn test_signer() -> QuoteSigner {
+        QuoteSigner::new(&[7u8; 32]).unwrap()
+    }
+
+    fn test_quote() -> Quote {
+        Quote {
+            id: Uuid::new_v4(),
+            market_maker_id: Uuid::new_v4(),
+            from: Lot {
+                currency: Currency {
+                    chain: ChainType::Bitcoin,
+                    token: TokenIdentifier::Native,
+                    decimals: 8,
+                    chain_id: None,
+                },
+                amount: U256::from(10_000_000u64),
+            },
+            to: Lot {
+                currency: Currency {
+                    chain: ChainType::Ethereum,
+                    token: TokenIdentifier::Address(
+                        "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf".to_string(),
+                    ),
+                    decimals: 8,
+                    chain_id: None,
+                },
+                amount: U256::from(9_990_000u64),
+            },
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify_round_trip() {
+        let signer = test_signer();
+        let quote = test_quote();
+        let signature = signer.sign(&quote);
+
+        // Quotes travel to the client and back as JSON
+        let json = serde_json::to_string(&quote).unwrap();
+        let returned: Quote = serde_json::from_str(&json).unwrap();
+
+        assert!(signer.verify(&returned, &signature).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_amount_is_rejected() {
+        let signer = test_signer();
+        let mut quote = test_quote();
+        let signature = signer.sign(&quote);
+
+        quote.to.amount += U256::from(1u64);
+
+        assert!(matches!(
+            signer.verify(&quote, &signature),
+            Err(QuoteSignatureError::SignatureMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_moving_the_quote_to_another_network_is_rejected() {
+        let signer = test_signer();
+        let mut quote = test_quote();
+        let primary_bytes = canonical_quote_bytes(&quote);
+        let signature = signer.sign(&quote);
+
+        quote.to.currency.chain_id = Some(8453);
+
+        assert_ne!(canonical_quote_bytes(&quote), primary_bytes);
+        assert!(matches!(
+            signer.verify(&quote, &signature),
+            Err(QuoteSignatureError::SignatureMismatch)
+        ));
+        let signature = signer.sign(&quote);
+        quote.to.currency.chain_id = Some(42161);
+        assert!(signer.verify(&quote, &signature).is_err());
+    }
+
+    #[test]
+    fn test_signature_from_other_key_is_rejected() {
+        let quote = test_quote();
+        let signature = QuoteSigner::new(&[8u8; 32]).unwrap().sign(&quote);
+
+        assert!(matches!(
+            test_signer().verify(&quote, &signature),
+            Err(QuoteSignatureError::SignatureMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_malformed_signature_is_rejected() {
+        assert!(matches!(
+            test_signer().verify(&test_quote(), "not-hex"),
+            Err(QuoteSignatureError::MalformedSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lock_token_is_bound_to_quote_and_expiry() {
+        let signer = test_signer();
+        let quote = test_quote();
+        let expires_at = Utc::now() + Duration::seconds(60);
+        let token = signer.sign_lock(&quote, expires_at);
+
+        assert!(signer.verify_lock(&quote, expires_at, &token).is_ok());
+        assert!(matches!(
+            signer.verify_lock(&quote, expires_at + Duration::seconds(1), &token),
+            Err(QuoteSignatureError::SignatureMismatch)
+        ));
+        assert!(matches!(
+            signer.verify_lock(&test_quote(), expires_at, &token),
+            Err(QuoteSignatureError::SignatureMismatch)
+        ));
+        // Neither passes for the other
+        assert!(signer.verify(&quote, &token).is_err());
+        assert!(signer
+            .verify_lock(&quote, expires_at, &signer.sign(&quote))
+            .is_err());
+    }
+
+    #[test]
+    fn test_short_key_is_rejected() {
+        assert!(matches!(
+            QuoteSigner::from_hex("deadbeef"),
+            Err(QuoteSignatureError::KeyTooShort { len: 4 })
+        ));
+    }
+}
@@ -1,7 +1,7 @@
 import { db } from "ponder:api";
 import { account, transferEvent } from "ponder:schema";
 import { Hono } from "hono";
-import { eq, desc, count, gte, and } from "ponder";
+import { eq, desc, count, gte, lte, and } from "ponder";
 
 const app = new Hono();
 
@@ -28,12 +28,20 @@ app.get("/transfers/to/:address", async (c) => {
   const limit = 50;
   const offset = (page - 1) * limit;
   const minAmount = c.req.query("amount");
+  const minBlock = c.req.query("min_block");
+  const maxBlock = c.req.query("max_block");
 
   // Build where condition
   const conditions = [eq(transferEvent.to, address)];
   if (minAmount) {
     conditions.push(gte(transferEvent.amount, BigInt(minAmount)));
   }
+  if (minBlock) {
+    conditions.push(gte(transferEvent.blockNumber, BigInt(minBlock)));
+  }
+  if (maxBlock) {
+    conditions.push(lte(transferEvent.blockNumber, BigInt(maxBlock)));
+  }
   const whereCondition =
     conditions.length > 1 ? and(...conditions) : conditions[0];
 
@@ -70,4 +78,16 @@ app.get("/transfers/to/:address", async (c) => {
   });
 });
 
+// Readiness probe for the devnet, answers once the schema is migrated
+app.get("/debug/table-counts", async (c) => {
+  const [accounts] = await db.select({ total: count() }).from(account);
+  const [transfers] = await db.select({ total: count() }).from(transferEvent);
+
+  return c.json({
+    account: accounts?.total ?? 0,
+    transferEvent: transfers?.total ?? 0,
+    timestamp: new Date().toISOString(),
+  });
+});
+
 export default app;
@@ -0,0 +1,113 @@
+use alloy::primitives::B256;
+use chrono::Utc;
+use market_maker::run_market_maker;
+use otc_protocols::attestation::{
+    AttestationDocument, AttestationError, AttestationVerifier, ATTESTATION_PATH,
+};
+use otc_server::server::run_server_with_listener;
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use crate::utils::{
+    bind_free_port, build_mm_test_args, build_otc_server_test_args, test_attestation_verifier,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_otc_server_to_be_ready, TestContext,
+    TEST_ATTESTATION_MEASUREMENT, TEST_MARKET_MAKER_ID,
+};
+
+#[sqlx::test]
+async fn test_market_maker_verifies_otc_server_attestation(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = devnet::MultichainAccount::new(0);
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let mut join_set = JoinSet::new();
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let nonce = B256::repeat_byte(0x42);
+    let response = reqwest::get(format!(
+        "http://127.0.0.1:{otc_port}{ATTESTATION_PATH}?nonce={nonce}"
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let document: AttestationDocument = response.json().await.unwrap();
+    assert_eq!(document.statement.measurement, TEST_ATTESTATION_MEASUREMENT);
+    assert_eq!(document.statement.nonce, Some(nonce));
+
+    let endpoint = format!("ws://127.0.0.1:{otc_port}/ws/mm");
+    test_attestation_verifier()
+        .verify(Some(&document), &endpoint, &nonce, Utc::now())
+        .unwrap();
+
+    // The document doesn't answer anyone else's challenge
+    assert!(matches!(
+        test_attestation_verifier().verify(
+            Some(&document),
+            &endpoint,
+            &B256::repeat_byte(0x43),
+            Utc::now()
+        ),
+        Err(AttestationError::NonceMismatch)
+    ));
+
+    // A market maker pinned to other code refuses the server
+    let pinned_elsewhere = AttestationVerifier {
+        expected_measurement: B256::repeat_byte(0xee),
+        ..test_attestation_verifier()
+    };
+    assert!(matches!(
+        pinned_elsewhere.verify(Some(&document), &endpoint, &nonce, Utc::now()),
+        Err(AttestationError::MeasurementMismatch { .. })
+    ));
+
+    // The market maker checks the attestation in Connected before acting on any request
+    let (_, rfq_port) = bind_free_port().await;
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    // A market maker that failed verification would have dropped the connection by now
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let connected: serde_json::Value = reqwest::get(format!(
+        "http://127.0.0.1:{otc_port}/api/v1/market-makers/connected"
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(
+        connected["market_makers"][0].as_str(),
+        Some(TEST_MARKET_MAKER_ID)
+    );
+}
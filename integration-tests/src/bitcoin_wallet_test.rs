@@ -1,16 +1,27 @@
 use alloy::{hex, primitives::U256};
 use bitcoin::{Network, PrivateKey};
-use bitcoincore_rpc_async::RpcApi;
+use bitcoincore_rpc_async::{Auth, RpcApi};
 use devnet::{MultichainAccount, RiftDevnet};
-use market_maker::{bitcoin_wallet::BitcoinWallet, wallet::Wallet};
-use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_models::{ChainType, Currency, Lot, TokenIdentifier};
+use market_maker::{
+    bitcoin_wallet::{
+        coin_selection::fill_vbytes, BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig,
+        CoinSelectionStrategy, ConsolidationConfig, SignerConfig,
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    },
+    wallet::{Wallet, WalletError},
+};
+use otc_chains::{
+    bitcoin::{BitcoinChain, CoreRpcDataSource, EsploraDataSource},
+    traits::MarketMakerPaymentValidation,
+    ChainOperations,
+};
+use otc_models::{ChainType, Currency, FillUsage, Finality, Lot, TokenIdentifier, TxStatus};
 use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
-use std::{str::FromStr, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 use tokio::task::JoinSet;
 use tracing::info;
 
-use crate::utils::PgConnectOptionsExt;
+use crate::utils::{PgConnectOptionsExt, TestContext};
 
 /// Test that verifies the Bitcoin wallet basic functionality
 #[sqlx::test]
@@ -18,6 +29,7 @@ async fn test_bitcoin_wallet_basic_operations(
     _: PoolOptions<sqlx::Postgres>,
     connect_options: PgConnectOptions,
 ) {
+    let context = TestContext::new();
     // Initialize logging for debugging
     let _ = tracing_subscriber::fmt()
         .with_target(false)
@@ -36,7 +48,8 @@ async fn test_bitcoin_wallet_basic_operations(
     let user_btc_address = user_account.bitcoin_wallet.address.to_string();
 
     // Start the devnet with Esplora enabled
-    let devnet = RiftDevnet::builder()
+    let devnet = context
+        .devnet_builder()
         .using_esplora(true)
         .build()
         .await
@@ -57,15 +70,20 @@ async fn test_bitcoin_wallet_basic_operations(
     info!("Using Esplora at: {}", esplora_url);
 
     // Create a temporary database for the wallet
-    let db_path = format!("/tmp/bitcoin_wallet_test_{}.db", std::process::id());
+    let wallet_dir = context.bitcoin_wallet_dir();
 
     // Create the Bitcoin wallet with transaction broadcaster
     let mut join_set = JoinSet::new();
-    let bitcoin_wallet = BitcoinWallet::new(
-        &db_path,
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
         &market_maker_account.bitcoin_wallet.descriptor(),
+        None,
         Network::Regtest,
         esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
         &mut join_set,
     )
     .await
@@ -73,24 +91,41 @@ async fn test_bitcoin_wallet_basic_operations(
 
     info!("Market maker Bitcoin address: {}", market_maker_btc_address);
 
-    // Test Case 1: Check that wallet is created and can check balance (even if 0)
+    // Test Case 1: Check that wallet is created and can check balance
     info!("Test Case 1: Testing wallet creation and balance checking");
 
+    // ensure the funding tx is detected by esplora
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
     let small_lot = Lot {
         currency: Currency {
             chain: ChainType::Bitcoin,
             token: TokenIdentifier::Native,
             decimals: 8,
+            chain_id: None,
         },
         amount: U256::from(1u64), // 1 satoshi
     };
 
-    // This should return false since wallet has no funds
+    // can_fill syncs on demand, so the funding is visible without waiting for the background sync
     let can_fill_small = bitcoin_wallet.can_fill(&small_lot).await.unwrap();
-    info!("Can fill 1 satoshi (unfunded wallet): {}", can_fill_small);
+    info!("Can fill 1 satoshi (funded wallet): {}", can_fill_small);
     assert!(
-        !can_fill_small,
-        "Unfunded wallet should not be able to fill any amount"
+        can_fill_small,
+        "Funded wallet should be able to fill 1 satoshi"
+    );
+
+    let entire_balance = Lot {
+        amount: U256::from(100_000_000u64),
+        ..small_lot.clone()
+    };
+    assert!(
+        !bitcoin_wallet.can_fill(&entire_balance).await.unwrap(),
+        "Wallet should not be able to fill more than its balance"
     );
 
     // Test Case 2: Test with unsupported currency
@@ -101,6 +136,7 @@ async fn test_bitcoin_wallet_basic_operations(
             chain: ChainType::Ethereum,
             token: TokenIdentifier::Native,
             decimals: 18,
+            chain_id: None,
         },
         amount: U256::from(100_000u64),
     };
@@ -116,6 +152,7 @@ async fn test_bitcoin_wallet_basic_operations(
             chain: ChainType::Bitcoin,
             token: TokenIdentifier::Native,
             decimals: 8,
+            chain_id: None,
         },
         amount: U256::from(100_000u64),
     };
@@ -160,6 +197,7 @@ async fn test_bitcoin_wallet_basic_operations(
             Some(MarketMakerPaymentValidation {
                 embedded_nonce: mm_nonce,
                 fee_amount: U256::from(300),
+                not_before: None,
             }),
         )
         .await;
@@ -168,9 +206,15 @@ async fn test_bitcoin_wallet_basic_operations(
         tx_result1.is_ok() || tx_result2.is_ok(),
         "Should create a transaction {tx_result1:?} or {tx_result2:?}"
     );
-    let txid1 = tx_result1.unwrap();
-    let txid2 = tx_result2.unwrap();
-    let txid3 = tx_result3.unwrap();
+    let txid1 = tx_result1.unwrap().tx_hash;
+    let txid2 = tx_result2.unwrap().tx_hash;
+    let payment3 = tx_result3.unwrap();
+    assert!(
+        payment3.fee.is_some_and(|fee| fee > U256::ZERO),
+        "the fee paid should be reported {payment3:?}"
+    );
+    assert_eq!(payment3.confirmations, 0);
+    let txid3 = payment3.tx_hash.clone();
     info!("Transaction created: {:?}", txid1);
     info!("Transaction created: {:?}", txid2);
     // mine
@@ -208,20 +252,180 @@ async fn test_bitcoin_wallet_basic_operations(
     if !tx3.hex.contains(&hex::encode(mm_nonce)) {
         panic!("tx3 should contain the mm_nonce {tx3:#?}");
     }
+    assert_eq!(
+        payment3.raw,
+        Some(serde_json::Value::String(tx3.hex.clone()))
+    );
+    match &payment3.usage {
+        Some(FillUsage::Bitcoin {
+            vsize,
+            inputs,
+            outputs,
+            ..
+        }) => {
+            assert_eq!(*vsize, tx3.vsize as u64);
+            assert_eq!(*inputs, tx3.vin.len() as u64);
+            assert_eq!(*outputs, tx3.vout.len() as u64);
+        }
+        other => panic!("the fill's block usage should be reported, got {other:?}"),
+    }
 
     // Clean up
     join_set.abort_all();
-    let _ = std::fs::remove_file(&db_path);
+    let _ = std::fs::remove_dir_all(&wallet_dir);
 
     info!("Bitcoin wallet basic operations test completed successfully");
 }
 
+/// Test that MM deposit detection only counts the payment tagged with the swap's
+/// nonce, and that the Core RPC and esplora-only data sources agree on it
+#[sqlx::test]
+async fn test_bitcoin_deposit_detection_matches_nonce(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &market_maker_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(100_000_000),
+        )
+        .await
+        .unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let mut join_set = JoinSet::new();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &market_maker_account.bitcoin_wallet.descriptor(),
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    let core_rpc = CoreRpcDataSource::new(
+        &devnet.bitcoin.rpc_url_with_cookie,
+        Auth::CookieFile(devnet.bitcoin.cookie.clone()),
+        EsploraDataSource::new(esplora_url).unwrap(),
+    )
+    .await
+    .unwrap();
+    let bitcoin_chains = [
+        (
+            "core rpc",
+            BitcoinChain::new(Arc::new(core_rpc), Network::Regtest),
+        ),
+        (
+            "esplora",
+            BitcoinChain::new(
+                Arc::new(EsploraDataSource::new(esplora_url).unwrap()),
+                Network::Regtest,
+            ),
+        ),
+    ];
+
+    let user_address = user_account.bitcoin_wallet.address.to_string();
+    let lot = bitcoin_lot(1_000_000);
+    let mm_payment = MarketMakerPaymentValidation {
+        embedded_nonce: hex!("0123456789abcdef0123456789abcdef"),
+        fee_amount: U256::from(300),
+        not_before: None,
+    };
+
+    // A larger, untagged payment to the same address that is more confirmed
+    // than the MM's, so it would win if the nonce weren't checked
+    devnet
+        .bitcoin
+        .rpc_client
+        .send_to_address(
+            &user_account.bitcoin_wallet.address,
+            bitcoin::Amount::from_sat(2_000_000),
+        )
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(2).await.unwrap();
+
+    let tagged = bitcoin_wallet
+        .create_payment(&lot, &user_address, Some(mm_payment.clone()))
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    // Another swap's nonce matches nothing
+    let other_swap = MarketMakerPaymentValidation {
+        embedded_nonce: [0xee; 16],
+        ..mm_payment.clone()
+    };
+    for (backend, bitcoin_chain) in &bitcoin_chains {
+        let transfer = bitcoin_chain
+            .search_for_transfer(&user_address, &lot, Some(mm_payment.clone()), None)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("{backend}: the tagged payment should be detected"));
+        assert_eq!(transfer.tx_hash, tagged.tx_hash, "{backend}");
+        assert_eq!(transfer.amount, lot.amount, "{backend}");
+        assert_eq!(transfer.confirmations, 0, "{backend}");
+
+        assert!(
+            bitcoin_chain
+                .search_for_transfer(&user_address, &lot, Some(other_swap.clone()), None)
+                .await
+                .unwrap()
+                .is_none(),
+            "{backend}"
+        );
+
+        assert_eq!(
+            bitcoin_chain.get_tx_status(&tagged.tx_hash).await.unwrap(),
+            TxStatus::Included {
+                confirmations: 1,
+                finality: Finality::Unknown,
+            },
+            "{backend}"
+        );
+    }
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+}
+
 /// Test error handling for various failure scenarios
 #[sqlx::test]
 async fn test_bitcoin_wallet_error_handling(
     _: PoolOptions<sqlx::Postgres>,
     connect_options: PgConnectOptions,
 ) {
+    let context = TestContext::new();
     let _ = tracing_subscriber::fmt()
         .with_target(false)
         .with_max_level(tracing::Level::DEBUG)
@@ -231,7 +435,8 @@ async fn test_bitcoin_wallet_error_handling(
 
     let market_maker_account = MultichainAccount::new(1);
 
-    let devnet = RiftDevnet::builder()
+    let devnet = context
+        .devnet_builder()
         .using_esplora(true)
         .build()
         .await
@@ -239,7 +444,7 @@ async fn test_bitcoin_wallet_error_handling(
         .0;
 
     let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
-    let db_path = format!("/tmp/bitcoin_wallet_error_test_{}.db", std::process::id());
+    let wallet_dir = context.bitcoin_wallet_dir();
 
     // Create descriptor from the wallet's private key in WIF format
     // Convert the secret key to WIF for use in descriptor
@@ -250,11 +455,16 @@ async fn test_bitcoin_wallet_error_handling(
     let descriptor = format!("wpkh({})", private_key);
 
     let mut join_set = JoinSet::new();
-    let bitcoin_wallet = BitcoinWallet::new(
-        &db_path,
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
         &descriptor,
+        None,
         Network::Regtest,
         esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
         &mut join_set,
     )
     .await
@@ -266,6 +476,7 @@ async fn test_bitcoin_wallet_error_handling(
             chain: ChainType::Bitcoin,
             token: TokenIdentifier::Native,
             decimals: 8,
+            chain_id: None,
         },
         amount: U256::from(100_000u64),
     };
@@ -282,6 +493,7 @@ async fn test_bitcoin_wallet_error_handling(
             chain: ChainType::Ethereum,
             token: TokenIdentifier::Native,
             decimals: 18,
+            chain_id: None,
         },
         amount: U256::from(100_000u64),
     };
@@ -299,6 +511,7 @@ async fn test_bitcoin_wallet_error_handling(
             chain: ChainType::Bitcoin,
             token: TokenIdentifier::Native,
             decimals: 18, // Bitcoin uses 8, not 18
+            chain_id: None,
         },
         amount: U256::from(100_000u64),
     };
@@ -312,7 +525,744 @@ async fn test_bitcoin_wallet_error_handling(
 
     // Clean up
     join_set.abort_all();
-    let _ = std::fs::remove_file(&db_path);
+    let _ = std::fs::remove_dir_all(&wallet_dir);
 
     info!("Error handling test completed");
 }
+
+/// Test that can_fill syncs before answering instead of trusting a stale balance
+#[sqlx::test]
+async fn test_bitcoin_wallet_can_fill_after_external_spend(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &market_maker_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(100_000_000),
+        )
+        .await
+        .unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let descriptor = market_maker_account.bitcoin_wallet.descriptor();
+    let mut join_set = JoinSet::new();
+
+    // The wallet under test never syncs in the background during the test
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &descriptor,
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig {
+            sync_interval: Duration::from_secs(3600),
+            ..Default::default()
+        },
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    // A second wallet with the same keys stands in for an external spender
+    let external_wallet_dir = context.bitcoin_wallet_dir();
+    let external_wallet = BitcoinWallet::open_or_create_in_dir(
+        &external_wallet_dir,
+        &descriptor,
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    let half_btc = Lot {
+        currency: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        amount: U256::from(50_000_000u64),
+    };
+
+    assert!(
+        bitcoin_wallet.can_fill(&half_btc).await.unwrap(),
+        "Funded wallet should be able to fill 0.5 BTC"
+    );
+    let synced_before_spend = bitcoin_wallet.last_synced_at().await.unwrap();
+
+    // Spend most of the balance behind the wallet's back
+    let spend = Lot {
+        amount: U256::from(90_000_000u64),
+        ..half_btc.clone()
+    };
+    external_wallet
+        .create_payment(
+            &spend,
+            &user_account.bitcoin_wallet.address.to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    assert!(
+        !bitcoin_wallet.can_fill(&half_btc).await.unwrap(),
+        "can_fill should see the external spend after its forced sync"
+    );
+    assert!(
+        bitcoin_wallet.last_synced_at().await.unwrap() > synced_before_spend,
+        "can_fill should have synced the wallet"
+    );
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+    let _ = std::fs::remove_dir_all(&external_wallet_dir);
+}
+
+/// Test that consecutive payouts chain onto their own unconfirmed change
+#[sqlx::test]
+async fn test_bitcoin_wallet_chains_unconfirmed_change(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    // A single funding UTXO forces every payout after the first to spend change
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &market_maker_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(100_000_000),
+        )
+        .await
+        .unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let max_unconfirmed_chain_depth = 3;
+    let mut join_set = JoinSet::new();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &market_maker_account.bitcoin_wallet.descriptor(),
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        max_unconfirmed_chain_depth,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    let payout = Lot {
+        currency: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        amount: U256::from(10_000_000u64), // 0.1 BTC
+    };
+    let recipient = user_account.bitcoin_wallet.address.to_string();
+
+    // Three fills without mining in between
+    let mut txids = Vec::new();
+    for i in 0..max_unconfirmed_chain_depth {
+        assert!(
+            bitcoin_wallet.can_fill(&payout).await.unwrap(),
+            "Own unconfirmed change should count towards fill {i}"
+        );
+        let txid = bitcoin_wallet
+            .create_payment(&payout, &recipient, None)
+            .await
+            .unwrap_or_else(|e| panic!("Fill {i} should broadcast: {e}"));
+        txids.push(txid.tx_hash.parse::<bitcoin::Txid>().unwrap());
+    }
+
+    // Each payout spends the change of the one before it
+    let mut transactions = Vec::new();
+    for txid in &txids {
+        let verbose = devnet
+            .bitcoin
+            .rpc_client
+            .get_raw_transaction_verbose(txid)
+            .await
+            .unwrap();
+        let tx: bitcoin::Transaction =
+            bitcoin::consensus::encode::deserialize_hex(&verbose.hex).unwrap();
+        transactions.push(tx);
+    }
+    for (parent, child) in txids.iter().zip(transactions.iter().skip(1)) {
+        assert!(
+            child
+                .input
+                .iter()
+                .any(|input| input.previous_output.txid == *parent),
+            "Payout should spend the unconfirmed change of {parent}"
+        );
+    }
+
+    // The chain is at the configured depth, so the remaining change can't be extended
+    assert!(
+        !bitcoin_wallet.can_fill(&payout).await.unwrap(),
+        "Change at the maximum unconfirmed depth should not count"
+    );
+    assert!(
+        bitcoin_wallet
+            .create_payment(&payout, &recipient, None)
+            .await
+            .is_err(),
+        "A payout past the maximum unconfirmed depth should be refused"
+    );
+
+    // The whole chain confirms in the same block
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    let mut block_hashes = Vec::new();
+    for txid in &txids {
+        let verbose = devnet
+            .bitcoin
+            .rpc_client
+            .get_raw_transaction_verbose(txid)
+            .await
+            .unwrap();
+        assert_eq!(
+            verbose.confirmations.unwrap_or(0),
+            1,
+            "{txid} should be mined"
+        );
+        block_hashes.push(verbose.blockhash);
+    }
+    assert!(block_hashes.windows(2).all(|w| w[0] == w[1]));
+
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+    assert!(
+        bitcoin_wallet.can_fill(&payout).await.unwrap(),
+        "Confirmed change should be spendable again"
+    );
+
+    // Unconfirmed coins from someone else never count as spendable
+    devnet
+        .bitcoin
+        .rpc_client
+        .send_to_address(
+            &market_maker_account.bitcoin_wallet.address,
+            bitcoin::Amount::from_sat(500_000_000),
+        )
+        .await
+        .unwrap();
+    let beyond_confirmed = Lot {
+        amount: U256::from(100_000_000u64), // more than the ~0.7 BTC confirmed
+        ..payout.clone()
+    };
+    assert!(
+        !bitcoin_wallet.can_fill(&beyond_confirmed).await.unwrap(),
+        "Foreign unconfirmed receipts should not count towards fills"
+    );
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+}
+
+/// Test that payout change goes to the change descriptor, never back to the funding address
+#[sqlx::test]
+async fn test_bitcoin_wallet_sends_change_to_internal_keychain(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+    let change_account = MultichainAccount::new(3);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &market_maker_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(100_000_000),
+        )
+        .await
+        .unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let mut join_set = JoinSet::new();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &market_maker_account.bitcoin_wallet.descriptor(),
+        Some(&change_account.bitcoin_wallet.descriptor()),
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    let funding_script = market_maker_account.bitcoin_wallet.address.script_pubkey();
+    let change_script = change_account.bitcoin_wallet.address.script_pubkey();
+    let recipient = user_account.bitcoin_wallet.address.to_string();
+
+    // The second payout can only be funded from the first one's change, so
+    // change is also signed for with the internal keys
+    let mut txids: Vec<bitcoin::Txid> = Vec::new();
+    for i in 0..2 {
+        let payment = bitcoin_wallet
+            .create_payment(&bitcoin_lot(10_000_000), &recipient, None)
+            .await
+            .unwrap_or_else(|e| panic!("Payout {i} should broadcast: {e}"));
+        let txid = payment.tx_hash.parse::<bitcoin::Txid>().unwrap();
+        let verbose = devnet
+            .bitcoin
+            .rpc_client
+            .get_raw_transaction_verbose(&txid)
+            .await
+            .unwrap();
+        let tx: bitcoin::Transaction =
+            bitcoin::consensus::encode::deserialize_hex(&verbose.hex).unwrap();
+
+        assert!(
+            tx.output
+                .iter()
+                .any(|output| output.script_pubkey == change_script && output.value.to_sat() > 0),
+            "Payout {i} should send its change to the internal keychain"
+        );
+        assert!(
+            tx.output
+                .iter()
+                .all(|output| output.script_pubkey != funding_script),
+            "Payout {i} should not pay back to the funding address"
+        );
+        if let Some(parent) = txids.last() {
+            assert!(
+                tx.input
+                    .iter()
+                    .any(|input| input.previous_output.txid == *parent),
+                "Payout {i} should spend the change of {parent}"
+            );
+        }
+        txids.push(txid);
+    }
+
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+    assert!(
+        bitcoin_wallet
+            .can_fill(&bitcoin_lot(10_000_000))
+            .await
+            .unwrap(),
+        "Confirmed change on the internal keychain should be spendable"
+    );
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+}
+
+/// Test that a prepared fill reserves its funds and pays from the inputs it set aside
+#[sqlx::test]
+async fn test_bitcoin_wallet_prepared_fill(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    // Two UTXOs, 1.3 BTC in total
+    for sats in [100_000_000, 30_000_000] {
+        devnet
+            .bitcoin
+            .deal_bitcoin(
+                &market_maker_account.bitcoin_wallet.address,
+                &bitcoin::Amount::from_sat(sats),
+            )
+            .await
+            .unwrap();
+    }
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let mut join_set = JoinSet::new();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &market_maker_account.bitcoin_wallet.descriptor(),
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    let lot = |sats: u64| Lot {
+        currency: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        amount: U256::from(sats),
+    };
+
+    let preparation = bitcoin_wallet
+        .prepare_fill(&lot(10_000_000), &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        preparation.utxos.len(),
+        1,
+        "The largest UTXO alone covers 0.1 BTC"
+    );
+
+    // 1 BTC fits the balance on its own, but not on top of the prepared 0.1 BTC
+    assert!(bitcoin_wallet.can_fill(&lot(100_000_000)).await.unwrap());
+    assert!(matches!(
+        bitcoin_wallet
+            .prepare_fill(&lot(100_000_000), std::slice::from_ref(&preparation))
+            .await,
+        Err(WalletError::InsufficientBalance { .. })
+    ));
+
+    // The payout spends the input set aside for it
+    let txid = bitcoin_wallet
+        .create_prepared_payment(
+            &lot(10_000_000),
+            &user_account.bitcoin_wallet.address.to_string(),
+            None,
+            &preparation,
+        )
+        .await
+        .unwrap();
+    let verbose = devnet
+        .bitcoin
+        .rpc_client
+        .get_raw_transaction_verbose(&txid.tx_hash.parse::<bitcoin::Txid>().unwrap())
+        .await
+        .unwrap();
+    let tx: bitcoin::Transaction =
+        bitcoin::consensus::encode::deserialize_hex(&verbose.hex).unwrap();
+    assert!(tx
+        .input
+        .iter()
+        .any(|input| input.previous_output == preparation.utxos[0]));
+
+    // Once spent, a stale preparation falls back to a regular payout
+    let txid = bitcoin_wallet
+        .create_prepared_payment(
+            &lot(10_000_000),
+            &user_account.bitcoin_wallet.address.to_string(),
+            None,
+            &preparation,
+        )
+        .await
+        .unwrap();
+    let verbose = devnet
+        .bitcoin
+        .rpc_client
+        .get_raw_transaction_verbose(&txid.tx_hash.parse::<bitcoin::Txid>().unwrap())
+        .await
+        .unwrap();
+    let tx: bitcoin::Transaction =
+        bitcoin::consensus::encode::deserialize_hex(&verbose.hex).unwrap();
+    assert!(tx
+        .input
+        .iter()
+        .all(|input| input.previous_output != preparation.utxos[0]));
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+}
+
+/// Send `count` UTXOs of `sats` each to the market maker and confirm them
+async fn fragment_wallet(
+    devnet: &RiftDevnet,
+    account: &MultichainAccount,
+    count: usize,
+    sats: u64,
+) {
+    for _ in 0..count {
+        devnet
+            .bitcoin
+            .rpc_client
+            .send_to_address(
+                &account.bitcoin_wallet.address,
+                bitcoin::Amount::from_sat(sats),
+            )
+            .await
+            .unwrap();
+    }
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+}
+
+fn bitcoin_lot(sats: u64) -> Lot {
+    Lot {
+        currency: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        amount: U256::from(sats),
+    }
+}
+
+/// Test that a fill never spends more inputs than configured, and that the
+/// balance beyond the cap doesn't count towards fills
+#[sqlx::test]
+async fn test_bitcoin_wallet_caps_fill_inputs(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    // 30 UTXOs of 10k sats, 300k sats in total
+    fragment_wallet(&devnet, &market_maker_account, 30, 10_000).await;
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let max_inputs = 10;
+    let mut join_set = JoinSet::new();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &market_maker_account.bitcoin_wallet.descriptor(),
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig {
+            strategy: CoinSelectionStrategy::LargestFirst,
+            max_inputs,
+            consolidation: None,
+        },
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    let recipient = user_account.bitcoin_wallet.address.to_string();
+    let txid = bitcoin_wallet
+        .create_payment(&bitcoin_lot(50_000), &recipient, None)
+        .await
+        .unwrap();
+    let verbose = devnet
+        .bitcoin
+        .rpc_client
+        .get_raw_transaction_verbose(&txid.tx_hash.parse::<bitcoin::Txid>().unwrap())
+        .await
+        .unwrap();
+    let tx: bitcoin::Transaction =
+        bitcoin::consensus::encode::deserialize_hex(&verbose.hex).unwrap();
+    let inputs = tx.input.len();
+    assert!(
+        (6..=max_inputs).contains(&inputs),
+        "50k sats plus fees takes 6 to {max_inputs} inputs, spent {inputs}"
+    );
+    // The quoter's size estimate covers the fill without padding it by more
+    // than the signature size variance
+    let vsize = tx.vsize() as u64;
+    let estimate = fill_vbytes(inputs);
+    assert!(
+        vsize <= estimate && estimate - vsize <= inputs as u64 + 1,
+        "estimated {estimate} vbytes for a {vsize} vbyte fill"
+    );
+
+    // 150k sats are in the wallet but need more than 10 inputs
+    let over_cap = bitcoin_lot(150_000);
+    assert!(!bitcoin_wallet.can_fill(&over_cap).await.unwrap());
+    assert!(bitcoin_wallet
+        .create_payment(&over_cap, &recipient, None)
+        .await
+        .is_err());
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+}
+
+/// Test that spare small UTXOs are swept together so fills past the input cap
+/// become possible
+#[sqlx::test]
+async fn test_bitcoin_wallet_consolidates_small_utxos(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    fragment_wallet(&devnet, &market_maker_account, 12, 10_000).await;
+
+    let esplora_url = devnet.bitcoin.esplora_url.as_ref().unwrap();
+    let wallet_dir = context.bitcoin_wallet_dir();
+    let max_inputs = 5;
+    let mut join_set = JoinSet::new();
+    let bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &wallet_dir,
+        &market_maker_account.bitcoin_wallet.descriptor(),
+        None,
+        Network::Regtest,
+        esplora_url,
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig {
+            strategy: CoinSelectionStrategy::LargestFirst,
+            max_inputs,
+            consolidation: Some(ConsolidationConfig {
+                max_fee_rate_sat_per_vb: 1_000,
+                small_utxo_sats: 20_000,
+                min_utxos: 5,
+                check_interval: Duration::from_secs(1),
+            }),
+        },
+        SignerConfig::Descriptor,
+        &mut join_set,
+    )
+    .await
+    .unwrap();
+
+    // 80k sats need more than 5 of the 10k UTXOs until they're swept
+    let lot = bitcoin_lot(80_000);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(60);
+    loop {
+        if bitcoin_wallet.can_fill(&lot).await.unwrap() {
+            break;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "Small UTXOs were not consolidated"
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    let txid = bitcoin_wallet
+        .create_payment(&lot, &user_account.bitcoin_wallet.address.to_string(), None)
+        .await
+        .unwrap();
+    let verbose = devnet
+        .bitcoin
+        .rpc_client
+        .get_raw_transaction_verbose(&txid.tx_hash.parse::<bitcoin::Txid>().unwrap())
+        .await
+        .unwrap();
+    let tx: bitcoin::Transaction =
+        bitcoin::consensus::encode::deserialize_hex(&verbose.hex).unwrap();
+    assert!(tx.input.len() <= max_inputs);
+
+    join_set.abort_all();
+    let _ = std::fs::remove_dir_all(&wallet_dir);
+}
@@ -0,0 +1,242 @@
+use alloy::primitives::U256;
+use axum::{
+    body::{to_bytes, Body},
+    http::Request,
+    Router,
+};
+use devnet::MultichainAccount;
+use market_maker::run_market_maker;
+use otc_client::{types::CreateSwapRequest, OtcApiClient, RfqApiClient};
+use otc_models::{ChainType, Currency, QuoteMode, QuoteRequest, TokenIdentifier, MAX_REASON_LEN};
+use otc_protocols::{
+    capabilities::{Capabilities, QuoteSigningMode, ServerKind, CAPABILITIES_PATH},
+    rfq::RFQResult,
+};
+use otc_server::server::{run_server_with_listener, OtcServer};
+use reqwest::StatusCode;
+use rfq_server::server::RfqServer;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+use crate::utils::{
+    bind_free_port, build_mm_test_args, build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, TestContext,
+};
+
+/// GET the capabilities from `router` in process, without a socket
+async fn fetch_capabilities(router: Router) -> Capabilities {
+    let response = router
+        .oneshot(Request::get(CAPABILITIES_PATH).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[sqlx::test]
+async fn test_capabilities_reflect_server_config(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    // Defaults: signatures required, admin API enabled
+    let default_args = build_otc_server_test_args(&context, 0, &devnet, &connect_options).await;
+    let default_server = OtcServer::build(default_args).await.unwrap();
+
+    // Signatures off, no signing key and no admin API
+    let mut unsigned_args =
+        build_otc_server_test_args(&context, 0, &devnet, &connect_options).await;
+    unsigned_args.quote_signature_mode = QuoteSigningMode::Off;
+    unsigned_args.quote_signing_key = None;
+    unsigned_args.admin_api_key = None;
+    let unsigned_server = OtcServer::build(unsigned_args).await.unwrap();
+
+    let mut rfq_args = build_rfq_server_test_args(0);
+    rfq_args.quote_timeout_milliseconds = 1234;
+    let rfq_server = RfqServer::build(rfq_args).await.unwrap();
+
+    let default = fetch_capabilities(default_server.router()).await;
+    assert_eq!(default.server, ServerKind::Otc);
+    assert_eq!(default.api_versions, vec!["v1".to_string()]);
+    assert_eq!(default.features.quote_signing, QuoteSigningMode::Required);
+    assert!(default.features.admin_api);
+    assert!(!default.features.encrypted_key_handoff);
+    assert_eq!(default.limits.max_metadata_chars, Some(MAX_REASON_LEN));
+    assert_eq!(default.limits.quote_timeout_ms, None);
+    assert_eq!(default.limits.quote_batch, None);
+    assert_eq!(
+        default.chains,
+        Some(vec![ChainType::Bitcoin, ChainType::Ethereum])
+    );
+
+    let unsigned = fetch_capabilities(unsigned_server.router()).await;
+    assert_eq!(unsigned.features.quote_signing, QuoteSigningMode::Off);
+    assert!(!unsigned.features.admin_api);
+    assert_eq!(unsigned.chains, default.chains);
+
+    let rfq = fetch_capabilities(rfq_server.router()).await;
+    assert_eq!(rfq.server, ServerKind::Rfq);
+    assert_eq!(rfq.features.quote_signing, QuoteSigningMode::Required);
+    assert_eq!(rfq.limits.quote_timeout_ms, Some(1234));
+    assert_eq!(
+        rfq.limits.quote_batch.map(|batch| batch.max_requests),
+        Some(10)
+    );
+    assert_eq!(rfq.chains, None);
+    assert_eq!(rfq.mm_protocol, default.mm_protocol);
+
+    // Checking signatures without a key to check them with is a startup error
+    let mut keyless_args = build_otc_server_test_args(&context, 0, &devnet, &connect_options).await;
+    keyless_args.quote_signature_mode = QuoteSigningMode::Optional;
+    keyless_args.quote_signing_key = None;
+    assert!(matches!(
+        OtcServer::build(keyless_args).await,
+        Err(otc_server::Error::MissingQuoteSigningKey {
+            mode: QuoteSigningMode::Optional
+        })
+    ));
+}
+
+#[sqlx::test]
+async fn test_market_maker_checks_quote_hash_when_server_skips_signatures(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(5);
+    let user_account = MultichainAccount::new(6);
+
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000),
+        )
+        .await
+        .unwrap();
+
+    let mut join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let mut otc_args =
+        build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    otc_args.quote_signature_mode = QuoteSigningMode::Off;
+    otc_args.quote_signing_key = None;
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    let otc_client = OtcApiClient::new(format!("http://127.0.0.1:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://127.0.0.1:{rfq_port}")).unwrap();
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000),
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let quote = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => quote.quote,
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+
+    // The server no longer checks signatures, so the market maker has to notice
+    // the tampered amount itself
+    let mut tampered_quote = quote.clone();
+    tampered_quote.to.amount *= U256::from(2);
+    let error = otc_client
+        .create_swap(&CreateSwapRequest {
+            quote: tampered_quote,
+            quote_signature: None,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::CONFLICT));
+
+    // The quote as issued is accepted without a signature
+    otc_client
+        .create_swap(&CreateSwapRequest {
+            quote,
+            quote_signature: None,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .unwrap();
+}
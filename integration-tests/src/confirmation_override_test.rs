@@ -0,0 +1,285 @@
+use alloy::primitives::U256;
+use chrono::{Duration as ChronoDuration, Utc};
+use devnet::{MultichainAccount, RiftDevnet};
+use market_maker::run_market_maker;
+use otc_client::{
+    types::{CreateSwapRequest, CreateSwapResponse},
+    OtcApiClient, RfqApiClient,
+};
+use otc_models::{ChainType, Currency, QuoteMode, QuoteRequest, TokenIdentifier};
+use otc_protocols::rfq::RFQResult;
+use otc_server::{api::CurrenciesResponse, server::run_server_with_listener};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_mm_test_args, build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, PgConnectOptionsExt,
+    TestContext, TEST_ADMIN_API_KEY,
+};
+
+async fn create_bitcoin_to_ethereum_swap(
+    otc_client: &OtcApiClient,
+    rfq_client: &RfqApiClient,
+    devnet: &RiftDevnet,
+    user_account: &MultichainAccount,
+) -> CreateSwapResponse {
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let (quote, quote_signature) = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+
+    otc_client
+        .create_swap(&CreateSwapRequest {
+            quote,
+            quote_signature,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .expect("Swap creation should succeed")
+}
+
+async fn get_bitcoin_currency(
+    client: &reqwest::Client,
+    otc_port: u16,
+) -> otc_server::api::ChainCurrencyResponse {
+    let currencies: CurrenciesResponse = client
+        .get(format!("http://localhost:{otc_port}/api/v1/currencies"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    currencies
+        .chains
+        .into_iter()
+        .find(|c| c.chain == ChainType::Bitcoin)
+        .expect("Bitcoin should be listed")
+}
+
+async fn persisted_user_confirmations(pool: &PgPool, swap_id: Uuid) -> i32 {
+    sqlx::query_scalar("SELECT user_required_confirmations FROM swaps WHERE id = $1")
+        .bind(swap_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn test_confirmation_override_applies_only_to_new_swaps(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(9_000_000_000i128), // 90 cbbtc
+        )
+        .await
+        .unwrap();
+
+    let mut join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    let otc_database_url = otc_args.database_url.clone();
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    let client = reqwest::Client::new();
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+    let pool = PgPool::connect(&otc_database_url).await.unwrap();
+    let override_url =
+        format!("http://localhost:{otc_port}/admin/chains/bitcoin/confirmation-override");
+
+    let bitcoin = get_bitcoin_currency(&client, otc_port).await;
+    let baseline = bitcoin.baseline_confirmations;
+    assert_eq!(bitcoin.required_confirmations, baseline);
+    assert!(bitcoin.confirmation_override_expires_at.is_none());
+
+    // A swap created before the override keeps the baseline requirement
+    let pre_existing =
+        create_bitcoin_to_ethereum_swap(&otc_client, &rfq_client, &devnet, &user_account).await;
+    assert_eq!(pre_existing.required_confirmations, baseline);
+
+    // Overrides need the admin key
+    let response = client
+        .post(&override_url)
+        .json(&json!({
+            "multiplier": 3.0,
+            "expires_at": Utc::now() + ChronoDuration::minutes(5),
+            "reason": "fee spike",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Overrides below the baseline are rejected
+    let response = client
+        .post(&override_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .json(&json!({
+            "absolute": baseline - 1,
+            "expires_at": Utc::now() + ChronoDuration::minutes(5),
+            "reason": "fee spike",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Activate an override that expires shortly
+    let expires_at = Utc::now() + ChronoDuration::seconds(20);
+    let response = client
+        .post(&override_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .json(&json!({
+            "multiplier": 3.0,
+            "expires_at": expires_at,
+            "reason": "Bitcoin fee spike with elevated reorg risk",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let overridden = baseline * 3;
+
+    let bitcoin = get_bitcoin_currency(&client, otc_port).await;
+    assert_eq!(bitcoin.required_confirmations, overridden);
+    assert_eq!(bitcoin.baseline_confirmations, baseline);
+    assert!(bitcoin.confirmation_override_expires_at.is_some());
+
+    // New swaps get the higher requirement, and see the longer ETA
+    let during_override =
+        create_bitcoin_to_ethereum_swap(&otc_client, &rfq_client, &devnet, &user_account).await;
+    assert_eq!(during_override.required_confirmations, overridden);
+    assert!(
+        during_override.estimated_confirmation_seconds
+            > pre_existing.estimated_confirmation_seconds
+    );
+    assert_eq!(
+        persisted_user_confirmations(&pool, during_override.swap_id).await,
+        overridden as i32
+    );
+
+    // The pre-existing swap is untouched
+    assert_eq!(
+        persisted_user_confirmations(&pool, pre_existing.swap_id).await,
+        baseline as i32
+    );
+    let swap = otc_client.get_swap(pre_existing.swap_id).await.unwrap();
+    assert_eq!(swap.user_deposit.required_confirmations, baseline);
+
+    // Once expired, new swaps revert to the baseline
+    let remaining = (expires_at - Utc::now()).to_std().unwrap_or_default();
+    tokio::time::sleep(remaining + Duration::from_secs(1)).await;
+
+    let bitcoin = get_bitcoin_currency(&client, otc_port).await;
+    assert_eq!(bitcoin.required_confirmations, baseline);
+    assert!(bitcoin.confirmation_override_expires_at.is_none());
+
+    let after_expiry =
+        create_bitcoin_to_ethereum_swap(&otc_client, &rfq_client, &devnet, &user_account).await;
+    assert_eq!(after_expiry.required_confirmations, baseline);
+    assert_eq!(
+        persisted_user_confirmations(&pool, after_expiry.swap_id).await,
+        baseline as i32
+    );
+    assert_eq!(
+        persisted_user_confirmations(&pool, during_override.swap_id).await,
+        overridden as i32
+    );
+
+    // The override, and only the accepted one, is in the audit trail
+    let reasons: Vec<String> =
+        sqlx::query_scalar("SELECT reason FROM confirmation_overrides WHERE chain = 'bitcoin'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(reasons, vec!["Bitcoin fee spike with elevated reorg risk"]);
+
+    drop(devnet);
+    join_set.shutdown().await;
+}
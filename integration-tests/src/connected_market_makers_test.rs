@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use otc_protocols::rfq::{ProtocolMessage, RFQResponse, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER};
+use serde_json::Value;
+use tokio::task::JoinSet;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+};
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_rfq_server_test_args, wait_for_rfq_server_to_be_ready,
+    INTEGRATION_TEST_TIMEOUT_SECS, TEST_API_KEY, TEST_API_KEY_ID, TEST_MARKET_MAKER_ID,
+};
+
+async fn connected(rfq_port: u16, query: &str) -> Value {
+    reqwest::get(format!(
+        "http://127.0.0.1:{rfq_port}/api/v1/market-makers/connected{query}"
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_connected_market_maker_details_follow_pongs() {
+    let mut join_set = JoinSet::new();
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mut request = format!("ws://127.0.0.1:{rfq_port}/ws/mm")
+        .into_client_request()
+        .unwrap();
+    let headers = request.headers_mut();
+    headers.insert("x-api-key-id", TEST_API_KEY_ID.parse().unwrap());
+    headers.insert("x-api-key", TEST_API_KEY.parse().unwrap());
+    headers.insert(PROTOCOL_VERSION_HEADER, PROTOCOL_VERSION.parse().unwrap());
+    let (mut socket, _) = connect_async(request).await.unwrap();
+    let connected_frame = socket.next().await.unwrap().unwrap();
+    assert!(connected_frame.to_text().unwrap().contains("Connected"));
+
+    // Without `detail` the response keeps its plain id shape
+    let plain = connected(rfq_port, "").await;
+    assert_eq!(plain["market_makers"][0], TEST_MARKET_MAKER_ID);
+    assert_eq!(plain["total"], 1);
+
+    let detailed = connected(rfq_port, "?detail=true").await;
+    let market_maker = &detailed["market_makers"][0];
+    assert_eq!(market_maker["market_maker_id"], TEST_MARKET_MAKER_ID);
+    assert_eq!(market_maker["protocol_version"], PROTOCOL_VERSION);
+    assert_eq!(market_maker["in_flight_requests"], 0);
+    assert!(market_maker["last_pong_at"].is_null());
+
+    let pong = ProtocolMessage {
+        version: PROTOCOL_VERSION.to_string(),
+        sequence: 0,
+        payload: RFQResponse::Pong {
+            request_id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+        },
+        trace_id: None,
+    };
+    socket
+        .send(Message::Text(serde_json::to_string(&pong).unwrap()))
+        .await
+        .unwrap();
+
+    let start_time = std::time::Instant::now();
+    let last_pong_at = loop {
+        assert!(
+            start_time.elapsed() <= Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS),
+            "Timeout waiting for the pong to be recorded"
+        );
+        let detailed = connected(rfq_port, "?detail=true").await;
+        let last_pong_at = &detailed["market_makers"][0]["last_pong_at"];
+        if !last_pong_at.is_null() {
+            break last_pong_at.as_str().unwrap().to_string();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    let last_pong_at: chrono::DateTime<chrono::Utc> = last_pong_at.parse().unwrap();
+    let connected_at: chrono::DateTime<chrono::Utc> = connected(rfq_port, "?detail=true").await
+        ["market_makers"][0]["connected_at"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(last_pong_at >= connected_at);
+
+    // Filters and pages apply to both shapes
+    let filtered = connected(rfq_port, "?protocol_version=0.0.1").await;
+    assert_eq!(filtered["market_makers"], serde_json::json!([]));
+    assert_eq!(filtered["total"], 0);
+    let paged = connected(rfq_port, "?detail=true&offset=1").await;
+    assert_eq!(paged["market_makers"], serde_json::json!([]));
+    assert_eq!(paged["total"], 1);
+
+    join_set.abort_all();
+}
@@ -0,0 +1,190 @@
+use std::{path::Path, str::FromStr, time::Duration};
+
+use alloy::primitives::U256;
+use bitcoincore_rpc_async::Auth;
+use devnet::MultichainAccount;
+use otc_chains::deposit_key;
+use otc_client::types::SwapResponse;
+use otc_models::{ChainType, Lot, QuoteMode, QuoteRequest, SwapStatus};
+use otc_server::recovery::{
+    run_recovery, RecoverArgs, RecoveredDeposit, ReportFormat, SweepTarget,
+};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use uuid::Uuid;
+
+use crate::utils::{SwapTestHarness, SwapTestOptions, TEST_ADMIN_API_KEY};
+
+/// Recovery of the harness's failed swaps, writing a JSON report to `output`
+fn recover_args(
+    harness: &SwapTestHarness,
+    output: &Path,
+    export_keys: bool,
+    sweep_to: Vec<SweepTarget>,
+) -> RecoverArgs {
+    RecoverArgs {
+        database_url: harness.otc_database_url.clone(),
+        log_level: "info".to_string(),
+        settings_file: harness.otc_settings_file.clone(),
+        supported_currencies_file: None,
+        ethereum_mainnet_rpc_url: Some(harness.devnet.ethereum.anvil.endpoint()),
+        ethereum_mainnet_chain_id: harness.devnet.ethereum.anvil.chain_id(),
+        evm_network_rpc_urls: Vec::new(),
+        bitcoin_rpc_url: Some(harness.devnet.bitcoin.rpc_url_with_cookie.clone()),
+        bitcoin_rpc_auth: Auth::CookieFile(harness.devnet.bitcoin.cookie.clone()),
+        esplora_http_server_url: Some(
+            harness
+                .devnet
+                .bitcoin
+                .esplora_url
+                .as_ref()
+                .unwrap()
+                .to_string(),
+        ),
+        bitcoin_network: bitcoin::Network::Regtest,
+        status: vec![SwapStatus::Failed],
+        since: None,
+        until: None,
+        format: ReportFormat::Json,
+        output: Some(output.to_path_buf()),
+        export_keys,
+        sweep_to,
+        yes: true,
+    }
+}
+
+async fn recover(args: RecoverArgs) -> Vec<RecoveredDeposit> {
+    let output = args.output.clone().unwrap();
+    run_recovery(args).await.unwrap();
+    serde_json::from_str(&std::fs::read_to_string(output).unwrap()).unwrap()
+}
+
+#[sqlx::test]
+async fn test_orphaned_deposit_is_recovered_and_swept(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let mut harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+    let user_bitcoin_wallet = harness.user_bitcoin_wallet().await;
+    let esplora = harness.devnet.bitcoin.esplora_client.clone().unwrap();
+
+    // The swap is cancelled, then the user pays into its deposit address anyway
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(10_000_000), // 0.1 BTC
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        })
+        .await;
+    let swap = harness
+        .create_swap(&harness.swap_request(
+            quote,
+            quote_signature,
+            harness.user_account.ethereum_address.to_string(),
+        ))
+        .await;
+    let response = harness
+        .client
+        .post(harness.otc_url(&format!("/admin/swaps/{}/cancel", swap.swap_id)))
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .json(&serde_json::json!({ "reason": "Abandoned" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cancelled: SwapResponse = response.json().await.unwrap();
+    assert_eq!(cancelled.status, "Failed");
+
+    user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: SwapTestHarness::bitcoin(),
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap();
+    harness.devnet.bitcoin.mine_blocks(1).await.unwrap();
+    harness
+        .devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    // The report finds the deposit without writing its key
+    let output = std::env::temp_dir().join(format!("otc_recovery_{}.json", Uuid::new_v4()));
+    let report = recover(recover_args(&harness, &output, false, vec![])).await;
+    assert_eq!(report.len(), 1);
+    let deposit = &report[0];
+    assert_eq!(deposit.swap_id, swap.swap_id);
+    assert_eq!(deposit.chain, ChainType::Bitcoin);
+    assert_eq!(deposit.deposit_address, swap.deposit_address);
+    assert_eq!(deposit.master_key_version, 1);
+    assert_eq!(deposit.balance, Some(swap.expected_amount));
+    assert_eq!(deposit.error, None);
+    assert!(!std::fs::read_to_string(&output)
+        .unwrap()
+        .contains("private_key"));
+
+    // Exported keys control the deposit address
+    let report = recover(recover_args(&harness, &output, true, vec![])).await;
+    let private_key = report[0].private_key.as_deref().unwrap();
+    assert!(deposit_key::private_key_controls_address(
+        ChainType::Bitcoin,
+        private_key,
+        &swap.deposit_address,
+        bitcoin::Network::Regtest,
+    )
+    .unwrap());
+
+    // Sweeping moves everything but the fee to the target
+    let sweep_address = MultichainAccount::new(99)
+        .bitcoin_wallet
+        .address
+        .to_string();
+    let report = recover(recover_args(
+        &harness,
+        &output,
+        false,
+        vec![SweepTarget {
+            chain: ChainType::Bitcoin,
+            address: sweep_address.clone(),
+        }],
+    ))
+    .await;
+    assert!(report[0].sweep_tx_hash.is_some(), "{:?}", report[0].error);
+
+    harness.devnet.bitcoin.mine_blocks(1).await.unwrap();
+    harness
+        .devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+    let swept: u64 = esplora
+        .get_address_utxo(
+            &bitcoin::Address::from_str(&sweep_address)
+                .unwrap()
+                .assume_checked(),
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|utxo| utxo.value)
+        .sum();
+    let deposited = swap.expected_amount.to::<u64>();
+    assert!(
+        swept > 0 && swept < deposited,
+        "swept {swept} of {deposited}"
+    );
+
+    let report = recover(recover_args(&harness, &output, false, vec![])).await;
+    assert_eq!(report[0].balance, Some(U256::ZERO));
+
+    std::fs::remove_file(&output).unwrap();
+    harness.shutdown().await;
+}
@@ -0,0 +1,115 @@
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{Address, U256},
+    providers::{Provider, ProviderBuilder, WsConnect},
+    rpc::types::TransactionRequest,
+};
+use bitcoincore_rpc_async::RpcApi;
+use devnet::{MultichainAccount, RiftDevnet};
+
+/// Confirmed sats held by `address`, read from the node's UTXO set
+async fn confirmed_sats(devnet: &RiftDevnet, address: &bitcoin::Address) -> u64 {
+    let scan: serde_json::Value = devnet
+        .bitcoin
+        .rpc_client
+        .call(
+            "scantxoutset",
+            &[
+                serde_json::json!("start"),
+                serde_json::json!([format!("addr({address})")]),
+            ],
+        )
+        .await
+        .unwrap();
+    bitcoin::Amount::from_btc(scan["total_amount"].as_f64().unwrap())
+        .unwrap()
+        .to_sat()
+}
+
+#[tokio::test]
+async fn test_restore_rolls_back_both_chains() {
+    let account = MultichainAccount::new(40);
+    let devnet = RiftDevnet::builder().build().await.unwrap().0;
+    let bitcoin_address = &account.bitcoin_wallet.address;
+
+    // Funded before the checkpoint, so kept by the restore
+    devnet
+        .ethereum
+        .fund_eth_address(account.ethereum_address, U256::from(10u64.pow(18)))
+        .await
+        .unwrap();
+    devnet
+        .bitcoin
+        .deal_bitcoin(bitcoin_address, &bitcoin::Amount::from_sat(1_000_000))
+        .await
+        .unwrap();
+
+    let provider = ProviderBuilder::new()
+        .wallet(account.ethereum_wallet.clone())
+        .connect_ws(WsConnect::new(devnet.ethereum.anvil.ws_endpoint()))
+        .await
+        .unwrap();
+    let ether_before = provider
+        .get_balance(account.ethereum_address)
+        .await
+        .unwrap();
+    let sats_before = confirmed_sats(&devnet, bitcoin_address).await;
+    let bitcoin_height_before = devnet.bitcoin.rpc_client.get_block_count().await.unwrap();
+    assert_eq!(sats_before, 1_000_000);
+
+    let checkpoint = devnet.checkpoint().await.unwrap();
+
+    // Spend the ether and receive more bitcoin
+    provider
+        .send_transaction(
+            TransactionRequest::default()
+                .with_to(Address::repeat_byte(0x42))
+                .with_value(U256::from(10u64.pow(17))),
+        )
+        .await
+        .unwrap()
+        .get_receipt()
+        .await
+        .unwrap();
+    devnet
+        .bitcoin
+        .deal_bitcoin(bitcoin_address, &bitcoin::Amount::from_sat(500_000))
+        .await
+        .unwrap();
+    assert!(
+        provider
+            .get_balance(account.ethereum_address)
+            .await
+            .unwrap()
+            < ether_before
+    );
+    assert_eq!(confirmed_sats(&devnet, bitcoin_address).await, 1_500_000);
+
+    devnet.restore(checkpoint).await.unwrap();
+
+    assert_eq!(
+        provider
+            .get_balance(account.ethereum_address)
+            .await
+            .unwrap(),
+        ether_before
+    );
+    assert_eq!(confirmed_sats(&devnet, bitcoin_address).await, sats_before);
+    assert_eq!(
+        devnet.bitcoin.rpc_client.get_block_count().await.unwrap(),
+        bitcoin_height_before
+    );
+
+    // The rolled back payment stays out of the blocks mined after the restore
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    assert_eq!(confirmed_sats(&devnet, bitcoin_address).await, sats_before);
+
+    // The devnet takes further checkpoints as usual
+    let checkpoint = devnet.checkpoint().await.unwrap();
+    devnet.bitcoin.mine_blocks(2).await.unwrap();
+    devnet.restore(checkpoint).await.unwrap();
+    assert_eq!(
+        devnet.bitcoin.rpc_client.get_block_count().await.unwrap(),
+        bitcoin_height_before + 1
+    );
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use bitcoincore_rpc_async::bitcoin::Txid;
+use bitcoincore_rpc_async::RpcApi;
+use devnet::{MultichainAccount, RiftDevnet};
+
+/// Confirmations bitcoind counts for `txid`, zero while it is in the mempool
+async fn confirmations(devnet: &RiftDevnet, txid: &Txid) -> u64 {
+    devnet
+        .bitcoin
+        .rpc_client
+        .get_raw_transaction_verbose(txid)
+        .await
+        .unwrap()
+        .confirmations
+        .unwrap_or(0)
+}
+
+async fn send_and_confirm(devnet: &RiftDevnet, address: &bitcoin::Address, sats: u64) -> Txid {
+    let txid = devnet
+        .bitcoin
+        .rpc_client
+        .send_to_address(address, bitcoin::Amount::from_sat(sats))
+        .await
+        .unwrap()
+        .txid()
+        .unwrap();
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    txid
+}
+
+#[tokio::test]
+async fn test_force_reorg_unconfirms_only_the_orphaned_blocks() {
+    let account = MultichainAccount::new(41);
+    let devnet = RiftDevnet::builder().build().await.unwrap().0;
+    let bitcoin_address = &account.bitcoin_wallet.address;
+    devnet.bitcoin.mine_blocks(101).await.unwrap();
+
+    // Two blocks deep, out of reach of a one block reorg
+    let deep = send_and_confirm(&devnet, bitcoin_address, 100_000).await;
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    let shallow = send_and_confirm(&devnet, bitcoin_address, 200_000).await;
+    assert_eq!(confirmations(&devnet, &deep).await, 3);
+    assert_eq!(confirmations(&devnet, &shallow).await, 1);
+    let height = devnet.bitcoin.rpc_client.get_block_count().await.unwrap();
+
+    let reorg = devnet.bitcoin.force_reorg(1).await.unwrap();
+
+    assert_ne!(reorg.old_tip, reorg.new_tip);
+    let rpc_client = &devnet.bitcoin.rpc_client;
+    assert_eq!(
+        rpc_client.get_best_block_hash().await.unwrap(),
+        reorg.new_tip
+    );
+    assert_eq!(rpc_client.get_block_count().await.unwrap(), height + 1);
+    assert_eq!(confirmations(&devnet, &shallow).await, 0);
+    assert!(rpc_client
+        .get_raw_mempool()
+        .await
+        .unwrap()
+        .contains(&shallow));
+    // The dropped block is replaced by two, so the deep one gained one
+    assert_eq!(confirmations(&devnet, &deep).await, 4);
+
+    // Esplora followed the new chain before force_reorg returned
+    let esplora_tip = devnet
+        .bitcoin
+        .esplora_client
+        .as_ref()
+        .unwrap()
+        .get_tip_hash()
+        .await
+        .unwrap();
+    assert_eq!(esplora_tip.to_string(), reorg.new_tip.to_string());
+
+    // The orphaned transaction confirms again with the next block mined
+    devnet.bitcoin.mine_blocks(1).await.unwrap();
+    assert_eq!(confirmations(&devnet, &shallow).await, 1);
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_invalidating_more_blocks_than_the_chain_has_fails() {
+    let devnet = RiftDevnet::builder()
+        .using_esplora(false)
+        .build()
+        .await
+        .unwrap()
+        .0;
+    let height = devnet.bitcoin.rpc_client.get_block_count().await.unwrap();
+
+    assert!(devnet.bitcoin.invalidate_blocks(0).await.is_err());
+    assert!(devnet.bitcoin.invalidate_blocks(height + 1).await.is_err());
+    assert_eq!(
+        devnet.bitcoin.rpc_client.get_block_count().await.unwrap(),
+        height
+    );
+}
@@ -0,0 +1,77 @@
+use std::{str::FromStr, time::Duration};
+
+use bitcoincore_rpc_async::bitcoin::Txid;
+use devnet::{DevnetError, RiftDevnet};
+
+#[tokio::test]
+async fn test_sync_waits_fail_fast_without_esplora_or_indexer() {
+    let devnet = RiftDevnet::builder()
+        .using_esplora(false)
+        .build()
+        .await
+        .unwrap()
+        .0;
+    let txid = Txid::from_str(&"ab".repeat(32)).unwrap();
+
+    assert!(matches!(
+        devnet
+            .bitcoin
+            .wait_for_esplora_sync(Duration::from_secs(5))
+            .await,
+        Err(DevnetError::EsploraDisabled)
+    ));
+    assert!(matches!(
+        devnet
+            .bitcoin
+            .wait_for_tx_indexed(&txid, Duration::from_secs(5))
+            .await,
+        Err(DevnetError::EsploraDisabled)
+    ));
+    assert!(matches!(
+        devnet
+            .ethereum
+            .wait_for_indexer_sync(Duration::from_secs(5))
+            .await,
+        Err(DevnetError::IndexerDisabled)
+    ));
+}
+
+#[tokio::test]
+async fn test_wait_for_tx_indexed_times_out_on_unknown_tx() {
+    let devnet = RiftDevnet::builder().build().await.unwrap().0;
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let txid = Txid::from_str(&"ab".repeat(32)).unwrap();
+    let error = devnet
+        .bitcoin
+        .wait_for_tx_indexed(&txid, Duration::from_millis(200))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, DevnetError::TxIndexTimeout { .. }));
+    assert!(error.to_string().contains(&txid.to_string()));
+}
+
+#[test]
+fn test_sync_timeout_reports_both_heights() {
+    let error = DevnetError::EsploraSyncTimeout {
+        timeout: Duration::from_secs(1),
+        esplora_height: Some(101),
+        bitcoind_height: Some(103),
+    };
+    let message = error.to_string();
+    assert!(message.contains("esplora at 101"), "{message}");
+    assert!(message.contains("bitcoind at 103"), "{message}");
+
+    let error = DevnetError::IndexerSyncTimeout {
+        timeout: Duration::from_secs(1),
+        indexed_block: None,
+        anvil_block: Some(7),
+    };
+    let message = error.to_string();
+    assert!(message.contains("indexer at block unknown"), "{message}");
+    assert!(message.contains("anvil at block 7"), "{message}");
+}
@@ -0,0 +1,182 @@
+use alloy::primitives::U256;
+use devnet::{MultichainAccount, RiftDevnet};
+use otc_chains::{ethereum::EthereumChain, ChainOperations};
+use otc_models::{ChainType, Currency, Lot, SupportedCurrencies, TokenIdentifier};
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use std::time::Duration;
+
+use crate::utils::PgConnectOptionsExt;
+
+fn cbbtc_lot(devnet: &RiftDevnet, amount: u64) -> Lot {
+    Lot {
+        currency: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+        amount: U256::from(amount),
+    }
+}
+
+#[sqlx::test]
+async fn test_search_for_transfer_with_and_without_indexer(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let deposit_account = MultichainAccount::new(3);
+    let untouched_account = MultichainAccount::new(4);
+
+    let devnet = RiftDevnet::builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let deposit_amount = 100_000u64;
+    devnet
+        .ethereum
+        .mint_cbbtc(devnet.ethereum.funded_address, U256::from(1_000_000))
+        .await
+        .unwrap();
+    let receipt = devnet
+        .ethereum
+        .cbbtc_contract
+        .transfer(deposit_account.ethereum_address, U256::from(deposit_amount))
+        .send()
+        .await
+        .unwrap()
+        .get_receipt()
+        .await
+        .unwrap();
+
+    let rpc_url = devnet.ethereum.anvil.endpoint();
+    let chain_id = devnet.ethereum.anvil.chain_id();
+    let indexer_url = devnet
+        .ethereum
+        .token_indexer
+        .as_ref()
+        .expect("Token indexer should be enabled")
+        .api_server_url
+        .clone();
+
+    let supported_currencies = SupportedCurrencies::default();
+    let indexed_chain = EthereumChain::new(
+        &rpc_url,
+        Some(indexer_url.as_str()),
+        chain_id,
+        &supported_currencies,
+    )
+    .await
+    .unwrap();
+    let scanning_chain = EthereumChain::new(&rpc_url, None, chain_id, &supported_currencies)
+        .await
+        .unwrap();
+
+    devnet
+        .ethereum
+        .wait_for_indexer_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    for (mode, chain) in [("indexer", &indexed_chain), ("log scan", &scanning_chain)] {
+        let transfer = chain
+            .search_for_transfer(
+                &deposit_account.ethereum_address.to_string(),
+                &cbbtc_lot(&devnet, deposit_amount),
+                None,
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("{mode}: transfer not found"));
+        assert_eq!(
+            transfer.tx_hash,
+            alloy::hex::encode(receipt.transaction_hash),
+            "{mode}: wrong transaction"
+        );
+        assert_eq!(
+            transfer.amount,
+            U256::from(deposit_amount),
+            "{mode}: wrong amount"
+        );
+
+        // Underpaying deposits don't match
+        assert!(
+            chain
+                .search_for_transfer(
+                    &deposit_account.ethereum_address.to_string(),
+                    &cbbtc_lot(&devnet, deposit_amount + 1),
+                    None,
+                    None,
+                )
+                .await
+                .unwrap()
+                .is_none(),
+            "{mode}: a smaller transfer should not satisfy a larger lot"
+        );
+
+        // Other addresses see nothing
+        assert!(
+            chain
+                .search_for_transfer(
+                    &untouched_account.ethereum_address.to_string(),
+                    &cbbtc_lot(&devnet, 1),
+                    None,
+                    None,
+                )
+                .await
+                .unwrap()
+                .is_none(),
+            "{mode}: unrelated address should have no transfers"
+        );
+
+        // Only the allowed token is supported
+        let native_lot = Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Native,
+                decimals: 18,
+                chain_id: None,
+            },
+            amount: U256::from(1),
+        };
+        assert!(
+            chain
+                .search_for_transfer(
+                    &deposit_account.ethereum_address.to_string(),
+                    &native_lot,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap()
+                .is_none(),
+            "{mode}: native transfers are not tracked"
+        );
+    }
+
+    // Log scanning honours the starting block, transfers before it are ignored
+    let transfer_block = receipt.block_number.unwrap();
+    assert!(scanning_chain
+        .search_for_transfer(
+            &deposit_account.ethereum_address.to_string(),
+            &cbbtc_lot(&devnet, deposit_amount),
+            None,
+            Some(transfer_block + 1),
+        )
+        .await
+        .unwrap()
+        .is_none());
+    assert!(scanning_chain
+        .search_for_transfer(
+            &deposit_account.ethereum_address.to_string(),
+            &cbbtc_lot(&devnet, deposit_amount),
+            None,
+            Some(transfer_block),
+        )
+        .await
+        .unwrap()
+        .is_some());
+}
@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use alloy::primitives::U256;
+use common::EvmNetworkUrl;
+use devnet::{evm_devnet::Mode, EthDevnet, MultichainAccount};
+use market_maker::{
+    bitcoin_wallet::{
+        BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, SignerConfig,
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    },
+    run_market_maker,
+    wallet::Wallet,
+};
+use otc_client::{types::CreateSwapRequest, OtcApiClient, RfqApiClient};
+use otc_models::{
+    ChainType, Currency, Lot, QuoteMode, QuoteRequest, SwapStatus, TokenIdentifier, CBBTC_ADDRESS,
+};
+use otc_protocols::rfq::RFQResult;
+use otc_server::{server::run_server_with_listener, OtcServerArgs};
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use tokio::task::JoinSet;
+
+use crate::utils::{
+    bind_free_port, build_bitcoin_wallet_descriptor, build_mm_test_args,
+    build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, wait_for_swap_status,
+    PgConnectOptionsExt, TestContext,
+};
+
+/// Chain id of the second anvil, standing in for an L2
+const SECOND_NETWORK_CHAIN_ID: u64 = 31338;
+
+/// BTC, and cbBTC on both the devnet's main anvil and the second one
+fn write_supported_currencies_file(context: &TestContext) -> String {
+    let path = context.path().join("supported_currencies.toml");
+    let contents = format!(
+        r#"
+[[currencies]]
+chain = "bitcoin"
+symbol = "BTC"
+decimals = 8
+min_amount = "100000"
+max_amount = "10000000000"
+
+[[currencies]]
+chain = "ethereum"
+address = "{CBBTC_ADDRESS}"
+symbol = "cbBTC"
+decimals = 8
+min_amount = "100000"
+max_amount = "10000000000"
+
+[[currencies]]
+chain = "ethereum"
+chain_id = {SECOND_NETWORK_CHAIN_ID}
+address = "{CBBTC_ADDRESS}"
+symbol = "cbBTC.l2"
+decimals = 8
+min_amount = "100000"
+max_amount = "10000000000"
+"#
+    );
+    std::fs::write(&path, contents).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+#[sqlx::test]
+async fn test_swap_settles_on_a_second_evm_network(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+    let second_network = EthDevnet::setup(
+        Mode::Local,
+        SECOND_NETWORK_CHAIN_ID,
+        None,
+        None,
+        Some(&context.new_dir("second_network")),
+        &mut JoinSet::new(),
+    )
+    .await
+    .unwrap();
+
+    let mut wallet_join_set = JoinSet::new();
+    let user_bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &context.bitcoin_wallet_dir(),
+        &build_bitcoin_wallet_descriptor(&user_account.bitcoin_wallet.private_key),
+        None,
+        bitcoin::Network::Regtest,
+        &devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut wallet_join_set,
+    )
+    .await
+    .unwrap();
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &user_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(500_000_000), // 5 BTC
+        )
+        .await
+        .unwrap();
+    // The market maker only holds cbBTC on the second network
+    for ethereum in [&devnet.ethereum, &second_network] {
+        ethereum
+            .fund_eth_address(
+                market_maker_account.ethereum_address,
+                U256::from(100_000_000_000_000_000_000i128),
+            )
+            .await
+            .unwrap();
+    }
+    second_network
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(9_000_000_000i128), // 90 cbbtc
+        )
+        .await
+        .unwrap();
+
+    let supported_currencies_file = write_supported_currencies_file(&context);
+    let mut service_join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = OtcServerArgs {
+        supported_currencies_file: Some(supported_currencies_file.clone()),
+        evm_network_rpc_urls: vec![EvmNetworkUrl {
+            chain_id: SECOND_NETWORK_CHAIN_ID,
+            url: second_network.anvil.endpoint_url().to_string(),
+        }],
+        ..build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await
+    };
+    service_join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    service_join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mut mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    mm_args.supported_currencies_file = Some(supported_currencies_file);
+    mm_args.ethereum_network_rpc_ws_urls = vec![EvmNetworkUrl {
+        chain_id: SECOND_NETWORK_CHAIN_ID,
+        url: second_network.anvil.ws_endpoint_url().to_string(),
+    }];
+    service_join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(CBBTC_ADDRESS.to_string()),
+            decimals: 8,
+            chain_id: Some(SECOND_NETWORK_CHAIN_ID),
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let (quote, quote_signature) = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+    assert_eq!(quote.to.currency.chain_id, Some(SECOND_NETWORK_CHAIN_ID));
+    let payout = quote.to.amount;
+
+    let swap = otc_client
+        .create_swap(&CreateSwapRequest {
+            quote,
+            quote_signature,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .unwrap();
+
+    user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: Currency {
+                    chain: ChainType::Bitcoin,
+                    token: TokenIdentifier::Native,
+                    decimals: swap.decimals,
+                    chain_id: None,
+                },
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(6).await.unwrap();
+
+    wait_for_swap_status(otc_port, swap.swap_id, SwapStatus::Settled).await;
+
+    // The payout landed on the second network and nothing moved on the main one
+    let user = user_account.ethereum_address;
+    let balance_on = |ethereum: &EthDevnet| {
+        let cbbtc = ethereum.cbbtc_contract.clone();
+        async move { cbbtc.balanceOf(user).call().await.unwrap() }
+    };
+    assert!(balance_on(&second_network).await >= payout);
+    assert_eq!(balance_on(&devnet.ethereum).await, U256::ZERO);
+
+    drop(second_network);
+    drop(devnet);
+    tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
+}
@@ -4,11 +4,11 @@ use alloy::{
 };
 use devnet::{MultichainAccount, RiftDevnet};
 use market_maker::{
-    evm_wallet::{self, EVMWallet},
+    evm_wallet::{self, fill_batcher::FillBatchConfig, EVMWallet},
     wallet::Wallet,
 };
-use otc_chains::traits::MarketMakerPaymentValidation;
-use otc_models::{ChainType, Currency, Lot, TokenIdentifier};
+use otc_chains::{ethereum::EthereumChain, traits::MarketMakerPaymentValidation, ChainOperations};
+use otc_models::{ChainType, Currency, Lot, SupportedCurrencies, TokenIdentifier};
 use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
 use std::{sync::Arc, time::Duration};
 use tokio::task::JoinSet;
@@ -91,6 +91,8 @@ async fn test_evm_wallet_nonce_error_retry(
         provider.clone(),
         eth_rpc_url.to_string(),
         1, // 1 confirmation for testing
+        Arc::new(SupportedCurrencies::default()),
+        None,
         &mut join_set,
     );
 
@@ -108,6 +110,7 @@ async fn test_evm_wallet_nonce_error_retry(
             chain: ChainType::Ethereum,
             token: TokenIdentifier::Address(test_token.to_string()),
             decimals: 18,
+            chain_id: None,
         },
         amount: U256::from(1000) * U256::pow(U256::from(10), U256::from(18)), // 1000 tokens
     };
@@ -186,6 +189,7 @@ async fn test_evm_wallet_nonce_error_retry(
             chain: ChainType::Ethereum,
             token: TokenIdentifier::Address(test_token.to_string()),
             decimals: 18,
+            chain_id: None,
         },
         amount: U256::from(2_000_000) * U256::pow(U256::from(10), U256::from(18)), // 2M tokens (more than funded)
     };
@@ -207,14 +211,16 @@ async fn test_evm_wallet_nonce_error_retry(
             Some(MarketMakerPaymentValidation {
                 embedded_nonce: custom_nonce,
                 fee_amount: U256::from(300),
+                not_before: None,
             }),
         )
-        .await;
-
+        .await
+        .expect("Transaction with custom nonce should succeed");
     assert!(
-        tx_with_nonce.is_ok(),
-        "Transaction with custom nonce should succeed"
+        tx_with_nonce.fee.is_some_and(|fee| fee > U256::ZERO),
+        "the fee paid should be reported"
     );
+    assert!(tx_with_nonce.confirmations >= 1);
 
     // Clean up
     join_set.abort_all();
@@ -286,7 +292,14 @@ async fn test_evm_wallet_gas_price_bumping(
 
     // Create EVM wallet
     let mut join_set = JoinSet::new();
-    let evm_wallet = EVMWallet::new(provider.clone(), eth_rpc_url.to_string(), 1, &mut join_set);
+    let evm_wallet = EVMWallet::new(
+        provider.clone(),
+        eth_rpc_url.to_string(),
+        1,
+        Arc::new(SupportedCurrencies::default()),
+        None,
+        &mut join_set,
+    );
 
     evm_wallet
         .ensure_inf_approval_on_disperse(test_token)
@@ -302,6 +315,7 @@ async fn test_evm_wallet_gas_price_bumping(
             chain: ChainType::Ethereum,
             token: TokenIdentifier::Address(test_token.to_string()),
             decimals: 18,
+            chain_id: None,
         },
         amount: U256::from(100) * U256::pow(U256::from(10), U256::from(18)),
     };
@@ -373,7 +387,14 @@ async fn test_evm_wallet_error_handling(
     );
 
     let mut join_set = JoinSet::new();
-    let evm_wallet = EVMWallet::new(provider.clone(), eth_rpc_url.to_string(), 1, &mut join_set);
+    let evm_wallet = EVMWallet::new(
+        provider.clone(),
+        eth_rpc_url.to_string(),
+        1,
+        Arc::new(SupportedCurrencies::default()),
+        None,
+        &mut join_set,
+    );
 
     // Test 1: Invalid recipient address
     let invalid_lot = Lot {
@@ -383,6 +404,7 @@ async fn test_evm_wallet_error_handling(
                 "0x1234567890123456789012345678901234567890".to_string(),
             ),
             decimals: 18,
+            chain_id: None,
         },
         amount: U256::from(100),
     };
@@ -399,6 +421,7 @@ async fn test_evm_wallet_error_handling(
             chain: ChainType::Bitcoin,
             token: TokenIdentifier::Native,
             decimals: 8,
+            chain_id: None,
         },
         amount: U256::from(100000),
     };
@@ -416,3 +439,153 @@ async fn test_evm_wallet_error_handling(
 
     info!("Error handling test completed");
 }
+
+/// Three fills submitted together go out in one Disperse transaction, and the
+/// otc-server's chain adapter attributes each payout to its own swap
+#[sqlx::test]
+async fn test_evm_wallet_batches_simultaneous_fills(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let market_maker_account = MultichainAccount::new(1);
+    let recipients = [
+        MultichainAccount::new(5).ethereum_address,
+        MultichainAccount::new(6).ethereum_address,
+        MultichainAccount::new(7).ethereum_address,
+    ];
+
+    let devnet = RiftDevnet::builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let eth_rpc_url = devnet.ethereum.anvil.endpoint_url();
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .wallet(market_maker_account.ethereum_wallet.clone())
+            .connect_ws(WsConnect::new(
+                devnet.ethereum.anvil.ws_endpoint_url().to_string(),
+            ))
+            .await
+            .unwrap(),
+    );
+
+    let cbbtc = *devnet.ethereum.cbbtc_contract.address();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(10).pow(U256::from(19)), // 10 ETH
+        )
+        .await
+        .unwrap();
+
+    let mut join_set = JoinSet::new();
+    let evm_wallet = EVMWallet::new(
+        provider.clone(),
+        eth_rpc_url.to_string(),
+        1,
+        Arc::new(SupportedCurrencies::default()),
+        Some(FillBatchConfig {
+            window: Duration::from_secs(2),
+            max_fills: 3,
+        }),
+        &mut join_set,
+    );
+    evm_wallet
+        .ensure_inf_approval_on_disperse(&cbbtc)
+        .await
+        .unwrap();
+
+    let lot = |amount: u64| Lot {
+        currency: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(cbbtc.to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+        amount: U256::from(amount),
+    };
+    let fills: Vec<(Lot, MarketMakerPaymentValidation)> = (0..3u8)
+        .map(|i| {
+            (
+                lot(100_000 * (u64::from(i) + 1)),
+                MarketMakerPaymentValidation {
+                    fee_amount: U256::from(10 * (u64::from(i) + 1)),
+                    embedded_nonce: [i + 1; 16],
+                    not_before: None,
+                },
+            )
+        })
+        .collect();
+
+    let recipient_addresses: Vec<String> = recipients.iter().map(ToString::to_string).collect();
+    let (first, second, third) = tokio::join!(
+        evm_wallet.create_payment(
+            &fills[0].0,
+            &recipient_addresses[0],
+            Some(fills[0].1.clone())
+        ),
+        evm_wallet.create_payment(
+            &fills[1].0,
+            &recipient_addresses[1],
+            Some(fills[1].1.clone())
+        ),
+        evm_wallet.create_payment(
+            &fills[2].0,
+            &recipient_addresses[2],
+            Some(fills[2].1.clone())
+        ),
+    );
+    let (first, second, third) = (first.unwrap(), second.unwrap(), third.unwrap());
+    let tx_hash = first.tx_hash.clone();
+    assert_eq!(second.tx_hash, tx_hash, "fills should share a transaction");
+    assert_eq!(third.tx_hash, tx_hash, "fills should share a transaction");
+    // Each fill reports its share of the one fee
+    let fee = first.fee.expect("the fee paid should be reported");
+    assert!(fee > U256::ZERO);
+    assert_eq!(second.fee, Some(fee));
+    assert_eq!(third.fee, Some(fee));
+
+    let chain = EthereumChain::new(
+        &devnet.ethereum.anvil.endpoint(),
+        None,
+        devnet.ethereum.anvil.chain_id(),
+        &SupportedCurrencies::default(),
+    )
+    .await
+    .unwrap();
+    for ((lot, validation), recipient) in fills.iter().zip(&recipient_addresses) {
+        let transfer = chain
+            .search_for_transfer(recipient, lot, Some(validation.clone()), None)
+            .await
+            .unwrap()
+            .expect("each fill should be attributed to its swap");
+        assert_eq!(format!("0x{}", transfer.tx_hash), tx_hash);
+        assert_eq!(transfer.amount, lot.amount);
+    }
+
+    // A swap's nonce doesn't claim another swap's payout
+    assert!(chain
+        .search_for_transfer(
+            &recipient_addresses[1],
+            &fills[1].0,
+            Some(fills[0].1.clone()),
+            None
+        )
+        .await
+        .unwrap()
+        .is_none());
+
+    join_set.abort_all();
+}
@@ -0,0 +1,84 @@
+//! On an anvil fork of Ethereum mainnet cbBTC is the real token, with its
+//! pausing, blocklist and minter roles. Skipped unless `FORK_RPC_URL` points
+//! at a mainnet RPC.
+
+use alloy::primitives::U256;
+use devnet::{evm_devnet::ForkConfig, MultichainAccount};
+use otc_models::{ChainType, SupportedCurrencies, TokenIdentifier, CBBTC_DECIMALS};
+
+use crate::utils::TestContext;
+
+const FORK_RPC_URL_VAR: &str = "FORK_RPC_URL";
+
+fn fork_config() -> Option<ForkConfig> {
+    let Ok(url) = std::env::var(FORK_RPC_URL_VAR) else {
+        eprintln!("{FORK_RPC_URL_VAR} is not set, skipping forked mainnet test");
+        return None;
+    };
+    Some(ForkConfig {
+        url,
+        block_number: None,
+    })
+}
+
+#[tokio::test]
+async fn test_forked_cbbtc_mints_and_transfers_in_supported_decimals() {
+    let Some(fork_config) = fork_config() else {
+        return;
+    };
+    let context = TestContext::new();
+    let recipient = MultichainAccount::new(5);
+    let (devnet, _) = context
+        .devnet_builder()
+        .fork_config(fork_config)
+        .build()
+        .await
+        .unwrap();
+    let cbbtc = &devnet.ethereum.cbbtc_contract;
+
+    // Quotes are priced with the configured decimals, they must be the token's
+    let decimals = cbbtc.decimals().call().await.unwrap();
+    assert_eq!(decimals, CBBTC_DECIMALS);
+    let supported = SupportedCurrencies::default();
+    let configured = supported
+        .get(
+            ChainType::Ethereum,
+            &TokenIdentifier::Address(cbbtc.address().to_string()),
+        )
+        .expect("cbBTC should be supported by default");
+    assert_eq!(configured.decimals, decimals);
+
+    let funded_address = devnet.ethereum.funded_address;
+    let minted = U256::from(1_000_000u64);
+    devnet
+        .ethereum
+        .mint_cbbtc(funded_address, minted)
+        .await
+        .unwrap();
+    assert_eq!(
+        cbbtc.balanceOf(funded_address).call().await.unwrap(),
+        minted
+    );
+
+    let sent = U256::from(250_000u64);
+    cbbtc
+        .transfer(recipient.ethereum_address, sent)
+        .send()
+        .await
+        .unwrap()
+        .get_receipt()
+        .await
+        .unwrap();
+    assert_eq!(
+        cbbtc
+            .balanceOf(recipient.ethereum_address)
+            .call()
+            .await
+            .unwrap(),
+        sent
+    );
+    assert_eq!(
+        cbbtc.balanceOf(funded_address).call().await.unwrap(),
+        minted - sent
+    );
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use alloy::primitives::U256;
+use market_maker::wallet::Wallet;
+use otc_models::{Lot, QuoteMode, QuoteRequest, SwapStatus};
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use tracing::info;
+
+use crate::utils::{wait_for_swap_status, SwapTestHarness, SwapTestOptions};
+
+/// The test OTC server's `shutdown_drain_timeout_seconds`
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[sqlx::test]
+async fn test_swap_settles_across_an_otc_server_restart(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let mut harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+    let user_bitcoin_wallet = harness.user_bitcoin_wallet().await;
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(10_000_000), // 0.1 BTC
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        })
+        .await;
+    let swap_request = harness.swap_request(
+        quote,
+        quote_signature,
+        harness.user_account.ethereum_address.to_string(),
+    );
+    let swap = harness.create_swap(&swap_request).await;
+
+    let tx_hash = user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: SwapTestHarness::bitcoin(),
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap()
+        .tx_hash;
+    info!("Paid the deposit address with {}", tx_hash);
+    wait_for_swap_status(
+        harness.otc_port,
+        swap.swap_id,
+        SwapStatus::WaitingUserDepositConfirmed,
+    )
+    .await;
+
+    // The market maker hangs up on GoingAway, so the drain doesn't wait out its timeout
+    let drained = harness.stop_otc_server().await;
+    assert!(drained < DRAIN_TIMEOUT, "draining took {drained:?}");
+    assert!(
+        harness
+            .client
+            .get(harness.otc_url("/status"))
+            .send()
+            .await
+            .is_err(),
+        "a stopped server should refuse connections"
+    );
+
+    // The deposit confirms while the server is down
+    harness.devnet.bitcoin.mine_blocks(6).await.unwrap();
+    harness.start_otc_server().await;
+    harness.wait_settled(swap.swap_id).await;
+
+    let pool = PgPool::connect(&harness.otc_database_url).await.unwrap();
+    let transitions: Vec<SwapStatus> =
+        sqlx::query_scalar("SELECT to_status FROM swap_events WHERE swap_id = $1 ORDER BY id")
+            .bind(swap.swap_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        transitions,
+        vec![
+            SwapStatus::WaitingUserDepositInitiated,
+            SwapStatus::WaitingUserDepositConfirmed,
+            SwapStatus::WaitingMMDepositInitiated,
+            SwapStatus::WaitingMMDepositConfirmed,
+            SwapStatus::Settled,
+        ],
+        "every transition should be recorded exactly once"
+    );
+
+    harness.shutdown().await;
+}
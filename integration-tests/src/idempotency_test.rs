@@ -0,0 +1,230 @@
+use alloy::primitives::U256;
+use devnet::{MultichainAccount, RiftDevnet};
+use market_maker::run_market_maker;
+use otc_client::{
+    types::{ApiErrorCode, CreateSwapRequest, CreateSwapResponse, IDEMPOTENCY_KEY_HEADER},
+    OtcApiClient, RfqApiClient,
+};
+use otc_models::{ChainType, Currency, QuoteMode, QuoteRequest, TokenIdentifier};
+use otc_protocols::rfq::RFQResult;
+use otc_server::server::run_server_with_listener;
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_mm_test_args, build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, PgConnectOptionsExt,
+    TestContext,
+};
+
+async fn request_swap_request(
+    rfq_client: &RfqApiClient,
+    devnet: &RiftDevnet,
+    user_account: &MultichainAccount,
+) -> CreateSwapRequest {
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let (quote, quote_signature) = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+
+    CreateSwapRequest {
+        quote,
+        quote_signature,
+        quote_lock: None,
+        user_destination_address: user_account.ethereum_address.to_string(),
+        user_evm_account_address: user_account.ethereum_address,
+        user_refund_address: None,
+        trace_id: None,
+        external_reference: None,
+    }
+}
+
+async fn swaps_for_quote(pool: &PgPool, quote_id: Uuid) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM swaps WHERE quote_id = $1")
+        .bind(quote_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn test_idempotency_key_deduplicates_swap_creation(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(9_000_000_000i128), // 90 cbbtc
+        )
+        .await
+        .unwrap();
+
+    let mut join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    let otc_database_url = otc_args.database_url.clone();
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    let client = reqwest::Client::new();
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+    let pool = PgPool::connect(&otc_database_url).await.unwrap();
+
+    // Replaying the same key and body returns the original swap
+    let request = request_swap_request(&rfq_client, &devnet, &user_account).await;
+    let key = Uuid::new_v4().to_string();
+    let original = otc_client
+        .create_swap_idempotent(&key, &request)
+        .await
+        .unwrap();
+
+    let replayed = otc_client
+        .create_swap_idempotent(&key, &request)
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(&replayed).unwrap(),
+        serde_json::to_value(&original).unwrap()
+    );
+    assert_eq!(swaps_for_quote(&pool, request.quote.id).await, 1);
+    assert_eq!(
+        original.payment_uri,
+        format!("bitcoin:{}?amount=0.1", original.deposit_address)
+    );
+    assert!(original.qr_svg.is_none());
+
+    // The QR code is rendered on request, replays included
+    let response = client
+        .post(format!(
+            "http://localhost:{otc_port}/api/v1/swaps?include_qr=true"
+        ))
+        .header(IDEMPOTENCY_KEY_HEADER, &key)
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let with_qr: CreateSwapResponse = response.json().await.unwrap();
+    assert_eq!(with_qr.swap_id, original.swap_id);
+    assert!(with_qr.qr_svg.unwrap().contains("<svg"));
+
+    // The same key with a different body is rejected
+    let mut conflicting = request.clone();
+    conflicting.user_destination_address = market_maker_account.ethereum_address.to_string();
+    let error = otc_client
+        .create_swap_idempotent(&key, &conflicting)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    assert_eq!(error.code(), Some(ApiErrorCode::IdempotencyKeyReused));
+
+    // Malformed keys are rejected before anything is stored
+    let error = otc_client
+        .create_swap_idempotent("", &request)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+    // Simultaneous duplicates create a single swap, the loser either replays it
+    // or is told the first request is still running
+    let request = request_swap_request(&rfq_client, &devnet, &user_account).await;
+    let key = Uuid::new_v4().to_string();
+    let (first, second) = tokio::join!(
+        otc_client.create_swap_idempotent(&key, &request),
+        otc_client.create_swap_idempotent(&key, &request),
+    );
+    let mut swap_ids = Vec::new();
+    for result in [first, second] {
+        match result {
+            Ok(created) => swap_ids.push(created.swap_id),
+            Err(error) if error.status() == Some(StatusCode::CONFLICT) => {}
+            Err(error) => panic!("Unexpected error for a concurrent duplicate: {error}"),
+        }
+    }
+    assert!(!swap_ids.is_empty(), "One of the requests should succeed");
+    swap_ids.dedup();
+    assert_eq!(swap_ids.len(), 1);
+    assert_eq!(swaps_for_quote(&pool, request.quote.id).await, 1);
+
+    // Once the first request finished, retries replay it
+    let replayed = otc_client
+        .create_swap_idempotent(&key, &request)
+        .await
+        .unwrap();
+    assert_eq!(replayed.swap_id, swap_ids[0]);
+}
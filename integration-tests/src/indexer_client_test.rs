@@ -72,36 +72,16 @@ async fn test_indexer_client(_: PoolOptions<sqlx::Postgres>, connect_options: Pg
     let indexer_client =
         TokenIndexerClient::new(&indexer_url).expect("Failed to create indexer client");
 
-    // Wait for the indexer to process the transfer with retries
     info!("Waiting for token indexer to index the transfer...");
-    let mut transfers = None;
-    let max_retries = 10;
-    let retry_delay = Duration::from_millis(500);
-
-    for i in 0..max_retries {
-        let result = indexer_client
-            .get_transfers_to(to.ethereum_address, Some(1), None)
-            .await;
-
-        if let Ok(transfer_response) = result {
-            if !transfer_response.transfers.is_empty() {
-                info!(
-                    "Found {} transfers after {} retries",
-                    transfer_response.pagination.total, i
-                );
-                transfers = Some(transfer_response);
-                break;
-            }
-        } else {
-            info!("Result: {:?}", result);
-        }
-
-        if i < max_retries - 1 {
-            tokio::time::sleep(retry_delay).await;
-        }
-    }
-
-    let transfers = transfers.expect("Failed to find transfers after retries");
+    devnet
+        .ethereum
+        .wait_for_indexer_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+    let transfers = indexer_client
+        .get_transfers_to(to.ethereum_address, Some(1), None, None, None)
+        .await
+        .expect("Failed to fetch transfers");
 
     // Validate that we have at least one transfer
     assert!(
@@ -142,4 +122,98 @@ async fn test_indexer_client(_: PoolOptions<sqlx::Postgres>, connect_options: Pg
         "  - Block: {} (hash: {:?})",
         latest_transfer.block_number, latest_transfer.block_hash
     );
+    // Block bounds past the transfer exclude it
+    let transfer_block = latest_transfer.block_number().unwrap();
+    let later = indexer_client
+        .get_transfers_to(
+            to.ethereum_address,
+            None,
+            None,
+            Some(transfer_block + 1),
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(later.transfers.is_empty());
+
+    let head_block = devnet
+        .ethereum
+        .funded_provider
+        .get_block_number()
+        .await
+        .unwrap();
+    let confirmed = indexer_client
+        .get_transfers_to_with_confirmations(
+            to.ethereum_address,
+            None,
+            Some(transfer_block),
+            head_block,
+        )
+        .await
+        .unwrap();
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(confirmed[0].confirmations, head_block - transfer_block);
+}
+
+#[sqlx::test]
+async fn test_indexer_is_restarted_after_a_crash(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let to = MultichainAccount::new(2);
+    let devnet = RiftDevnet::builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .build()
+        .await
+        .unwrap()
+        .0;
+    let token_indexer = devnet
+        .ethereum
+        .token_indexer
+        .as_ref()
+        .expect("Token indexer should be enabled");
+
+    token_indexer.kill();
+    let start = tokio::time::Instant::now();
+    while token_indexer.restarts() == 0 {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "Token indexer was not restarted"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    token_indexer
+        .wait_until_ready(Duration::from_secs(120))
+        .await
+        .unwrap();
+    assert!(token_indexer.is_running());
+
+    // Transfers made after the restart are indexed under the same URL
+    devnet
+        .ethereum
+        .mint_cbbtc(devnet.ethereum.funded_address, U256::from(1_000))
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .cbbtc_contract
+        .transfer(to.ethereum_address, U256::from(100))
+        .send()
+        .await
+        .unwrap()
+        .get_receipt()
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .wait_for_indexer_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let indexer_client = TokenIndexerClient::new(&token_indexer.api_server_url).unwrap();
+    let transfers = indexer_client
+        .get_transfers_to(to.ethereum_address, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(transfers.transfers.len(), 1);
 }
@@ -0,0 +1,231 @@
+use alloy::primitives::{Address, U256};
+use chrono::{Duration, Utc};
+use otc_models::{ChainType, Currency, Lot, Quote, QuoteMode, QuoteRequest, TokenIdentifier};
+use otc_server::{api::CreateSwapRequest, server::run_server_with_listener};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, PgConnectOptionsExt,
+    TestContext,
+};
+
+fn hostile_strings() -> Vec<String> {
+    vec![
+        String::new(),
+        "    ".to_string(),
+        "a".repeat(1024 * 1024),
+        "0x1234\u{0000}5678".to_string(),
+        "bc1q\r\ninjected log line".to_string(),
+        "\u{001b}[2J\u{001b}[H".to_string(),
+        "0x<script>alert(1)</script>".to_string(),
+    ]
+}
+
+fn base_quote() -> Quote {
+    Quote {
+        id: Uuid::new_v4(),
+        market_maker_id: Uuid::new_v4(),
+        from: Lot {
+            currency: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(10_000_000u64),
+        },
+        to: Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Address(
+                    "0xcbB7C0000aB88B473b1f5aFd9ef808440eed33Bf".to_string(),
+                ),
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(9_990_000u64),
+        },
+        expires_at: Utc::now() + Duration::minutes(5),
+        created_at: Utc::now(),
+    }
+}
+
+fn base_swap_request() -> CreateSwapRequest {
+    CreateSwapRequest {
+        quote: base_quote(),
+        quote_signature: Some("00".repeat(32)),
+        quote_lock: None,
+        user_destination_address: "0x1234567890123456789012345678901234567890".to_string(),
+        user_evm_account_address: Address::ZERO,
+        user_refund_address: None,
+        trace_id: None,
+        external_reference: None,
+    }
+}
+
+async fn assert_structured_rejection(response: reqwest::Response) -> serde_json::Value {
+    let status = response.status();
+    assert!(
+        status.is_client_error(),
+        "Expected a 4xx rejection, got {status}"
+    );
+    response
+        .json::<serde_json::Value>()
+        .await
+        .expect("Rejection should be a JSON body")
+}
+
+#[sqlx::test]
+async fn test_hostile_inputs_are_rejected(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .using_token_indexer(connect_options.to_database_url())
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let mut join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    let otc_database_url = otc_args.database_url.clone();
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let client = reqwest::Client::new();
+    let swaps_url = format!("http://127.0.0.1:{otc_port}/api/v1/swaps");
+    let quotes_url = format!("http://127.0.0.1:{rfq_port}/api/v1/quotes/request");
+
+    for hostile in hostile_strings() {
+        // POST /api/v1/swaps - every user supplied string field
+        let mut requests = Vec::new();
+
+        let mut request = base_swap_request();
+        request.user_destination_address = hostile.clone();
+        requests.push(("user_destination_address", request));
+
+        let mut request = base_swap_request();
+        request.quote.to.currency.token = TokenIdentifier::Address(hostile.clone());
+        requests.push(("quote.to.currency.token", request));
+
+        let mut request = base_swap_request();
+        request.quote_signature = Some(hostile.clone());
+        requests.push(("quote_signature", request));
+
+        let mut request = base_swap_request();
+        request.external_reference = Some(hostile.clone());
+        requests.push(("external_reference", request));
+
+        for (field, request) in requests {
+            let response = client.post(&swaps_url).json(&request).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = assert_structured_rejection(response).await;
+            assert_eq!(body["error"]["code"], "VALIDATION_FAILED");
+            assert_eq!(body["error"]["fields"][0]["field"], field);
+        }
+
+        // POST /api/v1/quotes/request
+        let quote_request = QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            from: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Address(hostile.clone()),
+                decimals: 8,
+                chain_id: None,
+            },
+            to: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(100_000u64),
+        };
+        let response = client
+            .post(&quotes_url)
+            .json(&quote_request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = assert_structured_rejection(response).await;
+        assert_eq!(body["fields"][0]["field"], "from.token");
+
+        // GET /api/v1/swaps/:id (oversized paths are cut off by hyper before routing)
+        if hostile.len() > 4096 {
+            continue;
+        }
+        let response = client
+            .get(format!("{swaps_url}/{}", urlencode(&hostile)))
+            .send()
+            .await
+            .unwrap();
+        assert!(
+            !response.status().is_server_error(),
+            "Swap lookup should never 500"
+        );
+    }
+
+    // Malformed bodies are rejected with the same structured error shape
+    for body in ["", "{", "\u{0000}", "{\"quote\": null}"] {
+        for url in [&swaps_url, &quotes_url] {
+            let response = client
+                .post(url)
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+                .await
+                .unwrap();
+            assert_structured_rejection(response).await;
+        }
+    }
+
+    // Nothing made it into the database
+    let pool = PgPool::connect(&otc_database_url).await.unwrap();
+    let swaps: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM swaps")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let quotes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM quotes")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(swaps, 0, "No swaps should be persisted");
+    assert_eq!(quotes, 0, "No quotes should be persisted");
+
+    drop(devnet);
+    join_set.shutdown().await;
+}
+
+fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
@@ -26,3 +26,87 @@ mod price_oracle_test;
 
 #[cfg(test)]
 mod quote_storage_test;
+
+#[cfg(test)]
+mod input_sanitization_test;
+
+#[cfg(test)]
+mod confirmation_override_test;
+
+#[cfg(test)]
+mod ethereum_chain_test;
+
+#[cfg(test)]
+mod capabilities_test;
+
+#[cfg(test)]
+mod master_key_rotation_test;
+
+#[cfg(test)]
+mod idempotency_test;
+
+#[cfg(test)]
+mod supported_currencies_test;
+
+#[cfg(test)]
+mod mm_underpayment_test;
+
+#[cfg(test)]
+mod request_tracing_test;
+
+#[cfg(test)]
+mod reorg_test;
+
+#[cfg(test)]
+mod devnet_checkpoint_test;
+
+#[cfg(test)]
+mod devnet_reorg_test;
+
+#[cfg(test)]
+mod swap_cancellation_test;
+
+#[cfg(test)]
+mod attestation_test;
+
+#[cfg(test)]
+mod refund_address_test;
+
+#[cfg(test)]
+mod deposit_recovery_test;
+
+#[cfg(test)]
+mod parallel_harness_test;
+
+#[cfg(test)]
+mod rfq_batch_test;
+
+#[cfg(test)]
+mod forked_mainnet_test;
+
+#[cfg(test)]
+mod graceful_shutdown_test;
+
+#[cfg(test)]
+mod quote_lock_test;
+
+#[cfg(test)]
+mod devnet_sync_test;
+
+#[cfg(test)]
+mod mm_socket_limits_test;
+
+#[cfg(test)]
+mod connected_market_makers_test;
+
+#[cfg(test)]
+mod mm_deposit_retry_test;
+
+#[cfg(test)]
+mod evm_network_test;
+
+#[cfg(test)]
+mod quote_outcome_test;
+
+#[cfg(test)]
+mod transfer_subscription_test;
@@ -1,23 +1,108 @@
-use market_maker::{run_market_maker, MarketMakerArgs};
-use otc_server::{server::run_server, OtcServerArgs};
+use chrono::Utc;
+use market_maker::{
+    exit::{shutdown_reason, ShutdownReason},
+    run_market_maker, MarketMakerArgs,
+};
+use otc_models::{ApiKey, ApiKeyScope};
+use otc_server::{server::run_server_with_listener, OtcServerArgs};
+use reqwest::StatusCode;
 use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
 use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
 use std::time::Duration;
 use tokio::task::JoinSet;
 
 use crate::utils::{
-    build_mm_test_args, build_otc_server_test_args, get_free_port, get_whitelist_file_path,
-    wait_for_otc_server_to_be_ready, PgConnectOptionsExt, INTEGRATION_TEST_TIMEOUT_SECS,
-    TEST_API_KEY, TEST_API_KEY_ID, TEST_MARKET_MAKER_ID,
+    bind_free_port, build_mm_test_args, build_otc_server_test_args, build_rfq_server_test_args,
+    get_whitelist_file_path, wait_for_market_maker_to_connect_to_otc_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, PgConnectOptionsExt,
+    TestContext, INTEGRATION_TEST_TIMEOUT_SECS, TEST_API_KEY, TEST_API_KEY_ID,
+    TEST_MARKET_MAKER_ID,
 };
 
+/// The OTC server's reload interval in tests plus room for the market maker
+/// to notice the close and be turned away on reconnect
+const KEY_INVALIDATION_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Copies the test whitelist into the test's directory, edited by `edit`, so
+/// it can be changed while a server watches it
+fn write_whitelist(path: &Path, edit: impl FnOnce(&mut Vec<ApiKey>)) {
+    let whitelist = std::fs::read_to_string(get_whitelist_file_path()).unwrap();
+    let mut api_keys: Vec<ApiKey> = serde_json::from_str(&whitelist).unwrap();
+    edit(&mut api_keys);
+    std::fs::write(path, serde_json::to_string(&api_keys).unwrap()).unwrap();
+}
+
+fn edit_whitelist(path: &Path, edit: impl FnOnce(&mut ApiKey)) {
+    let whitelist = std::fs::read_to_string(path).unwrap();
+    let mut api_keys: Vec<ApiKey> = serde_json::from_str(&whitelist).unwrap();
+    let api_key = api_keys
+        .iter_mut()
+        .find(|api_key| api_key.id.to_string() == TEST_API_KEY_ID)
+        .expect("Test API key should be in the whitelist");
+    edit(api_key);
+    std::fs::write(path, serde_json::to_string(&api_keys).unwrap()).unwrap();
+}
+
+/// Status of a market maker websocket upgrade as the test key id
+async fn connect_to_otc_server(otc_port: u16, api_key: &str) -> StatusCode {
+    reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{otc_port}/ws/mm"))
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("x-api-key-id", TEST_API_KEY_ID)
+        .header("x-api-key", api_key)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn connected_market_makers(otc_port: u16) -> Vec<String> {
+    let body: serde_json::Value = reqwest::get(format!(
+        "http://127.0.0.1:{otc_port}/api/v1/market-makers/connected"
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    serde_json::from_value(body["market_makers"].clone()).unwrap()
+}
+
+/// Starts an OTC server reading its keys from `whitelist_file`
+async fn start_otc_server(
+    join_set: &mut JoinSet<()>,
+    context: &TestContext,
+    devnet: &devnet::RiftDevnet,
+    connect_options: &PgConnectOptions,
+    whitelist_file: &Path,
+) -> u16 {
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = OtcServerArgs {
+        whitelist_file: whitelist_file.to_string_lossy().to_string(),
+        ..build_otc_server_test_args(context, otc_port, devnet, connect_options).await
+    };
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+    otc_port
+}
+
 #[sqlx::test]
 async fn test_market_maker_otc_auth(
     _: PoolOptions<sqlx::Postgres>,
     connect_options: PgConnectOptions,
 ) {
+    let context = TestContext::new();
     let market_maker_account = devnet::MultichainAccount::new(0);
-    let devnet = devnet::RiftDevnet::builder()
+    let devnet = context
+        .devnet_builder()
         .using_esplora(true)
         .using_token_indexer(connect_options.to_database_url())
         .build()
@@ -26,19 +111,21 @@ async fn test_market_maker_otc_auth(
         .0;
 
     let mut join_set = JoinSet::new();
-    let otc_port = get_free_port().await;
-    let otc_args = build_otc_server_test_args(otc_port, &devnet, &connect_options).await;
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
 
     join_set.spawn(async move {
-        run_server(otc_args)
+        run_server_with_listener(otc_args, otc_listener)
             .await
             .expect("OTC server should not crash");
     });
 
     wait_for_otc_server_to_be_ready(otc_port).await;
 
-    let rfq_port = get_free_port().await; // Get a dummy RFQ port (not used in this test)
+    // Nothing listens on the RFQ port, this test only needs the OTC server
+    let (_, rfq_port) = bind_free_port().await;
     let mm_args = build_mm_test_args(
+        &context,
         otc_port,
         rfq_port,
         &market_maker_account,
@@ -83,3 +170,267 @@ async fn test_market_maker_otc_auth(
         }
     }
 }
+
+#[sqlx::test]
+async fn test_rfq_only_key_is_forbidden_on_otc_server(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .using_token_indexer(connect_options.to_database_url())
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    // The test market maker's key, narrowed to the RFQ pool
+    let whitelist_file = context.path().join("rfq_only_whitelist.json");
+    write_whitelist(&whitelist_file, |api_keys| {
+        for api_key in api_keys {
+            api_key.scopes = vec![ApiKeyScope::Rfq];
+        }
+    });
+
+    let mut join_set = JoinSet::new();
+    let otc_port = start_otc_server(
+        &mut join_set,
+        &context,
+        &devnet,
+        &connect_options,
+        &whitelist_file,
+    )
+    .await;
+
+    // A valid key without the otc scope is forbidden, not unauthorized
+    assert_eq!(
+        connect_to_otc_server(otc_port, TEST_API_KEY).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        connect_to_otc_server(otc_port, "not-the-api-key").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    join_set.abort_all();
+}
+
+#[sqlx::test]
+async fn test_wrong_api_key_stops_the_market_maker_as_auth_rejected(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = devnet::MultichainAccount::new(0);
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let mut join_set = JoinSet::new();
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mm_args = MarketMakerArgs {
+        api_key: "not-the-api-key".to_string().into(),
+        ..build_mm_test_args(
+            &context,
+            otc_port,
+            rfq_port,
+            &market_maker_account,
+            &devnet,
+            &connect_options,
+        )
+        .await
+    };
+
+    // A rejected key isn't retried, so this is well short of the reconnect budget
+    let error = tokio::time::timeout(
+        Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS),
+        run_market_maker(mm_args),
+    )
+    .await
+    .expect("Market maker should stop on a rejected API key")
+    .unwrap_err();
+    let reason = shutdown_reason(&error);
+    assert_eq!(reason, ShutdownReason::AuthRejected, "stopped with {error}");
+    assert_eq!(reason.exit_code(), 77);
+
+    // The key id is valid, the secret isn't, and that's refused before the
+    // upgrade so the market maker never registered
+    assert_eq!(
+        connect_to_otc_server(otc_port, "not-the-api-key").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert!(connected_market_makers(otc_port).await.is_empty());
+
+    join_set.abort_all();
+}
+
+#[sqlx::test]
+async fn test_revoked_api_key_disconnects_the_market_maker(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = devnet::MultichainAccount::new(0);
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let whitelist_file = context.path().join("whitelist.json");
+    write_whitelist(&whitelist_file, |_| {});
+    let mut join_set = JoinSet::new();
+    let otc_port = start_otc_server(
+        &mut join_set,
+        &context,
+        &devnet,
+        &connect_options,
+        &whitelist_file,
+    )
+    .await;
+
+    // Nothing listens on the RFQ port, this test only needs the OTC server
+    let (_, rfq_port) = bind_free_port().await;
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    let market_maker = tokio::spawn(run_market_maker(mm_args));
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    edit_whitelist(&whitelist_file, |api_key| {
+        api_key.revoked_at = Some(Utc::now());
+    });
+
+    let error = tokio::time::timeout(KEY_INVALIDATION_TIMEOUT, market_maker)
+        .await
+        .expect("Market maker should stop once its key is revoked")
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(
+        shutdown_reason(&error),
+        ShutdownReason::AuthRejected,
+        "stopped with {error}"
+    );
+    assert!(connected_market_makers(otc_port).await.is_empty());
+    assert_eq!(
+        connect_to_otc_server(otc_port, TEST_API_KEY).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    join_set.abort_all();
+}
+
+#[sqlx::test]
+async fn test_rotated_api_key_only_accepts_the_new_secret(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = devnet::MultichainAccount::new(0);
+    let devnet = context
+        .devnet_builder()
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let whitelist_file = context.path().join("whitelist.json");
+    write_whitelist(&whitelist_file, |_| {});
+    let mut join_set = JoinSet::new();
+    let otc_port = start_otc_server(
+        &mut join_set,
+        &context,
+        &devnet,
+        &connect_options,
+        &whitelist_file,
+    )
+    .await;
+
+    // Nothing listens on the RFQ port, this test only needs the OTC server
+    let (_, rfq_port) = bind_free_port().await;
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    let market_maker = tokio::spawn(run_market_maker(mm_args));
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    // What api-key-manager rotate does: a new secret under the same key id
+    let new_api_key = otc_auth::generate_api_key_secret();
+    let new_hash = otc_auth::hash_api_key(&new_api_key).unwrap();
+    edit_whitelist(&whitelist_file, |api_key| api_key.hash = new_hash);
+
+    let error = tokio::time::timeout(KEY_INVALIDATION_TIMEOUT, market_maker)
+        .await
+        .expect("Market maker should stop once its key is rotated")
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(
+        shutdown_reason(&error),
+        ShutdownReason::AuthRejected,
+        "stopped with {error}"
+    );
+    assert_eq!(
+        connect_to_otc_server(otc_port, TEST_API_KEY).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Restarted with only the secret changed, the market maker is let back in
+    let mm_args = MarketMakerArgs {
+        api_key: new_api_key.into(),
+        ..build_mm_test_args(
+            &context,
+            otc_port,
+            rfq_port,
+            &market_maker_account,
+            &devnet,
+            &connect_options,
+        )
+        .await
+    };
+    assert_eq!(mm_args.api_key_id, TEST_API_KEY_ID);
+    join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    join_set.abort_all();
+}
@@ -0,0 +1,306 @@
+use alloy::primitives::U256;
+use devnet::{MultichainAccount, RiftDevnet};
+use market_maker::{
+    bitcoin_wallet::{
+        BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, SignerConfig,
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    },
+    run_market_maker,
+    wallet::Wallet,
+};
+use otc_client::{
+    types::{CreateSwapRequest, CreateSwapResponse},
+    OtcApiClient, RfqApiClient,
+};
+use otc_models::{ChainType, Currency, Lot, QuoteMode, QuoteRequest, TokenIdentifier};
+use otc_protocols::rfq::RFQResult;
+use otc_server::{api::MasterKeysResponse, server::run_server_with_listener};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_bitcoin_wallet_descriptor, build_mm_test_args,
+    build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, wait_for_swap_to_be_settled,
+    PgConnectOptionsExt, TestContext, TEST_ADMIN_API_KEY,
+};
+
+async fn create_bitcoin_to_ethereum_swap(
+    otc_client: &OtcApiClient,
+    rfq_client: &RfqApiClient,
+    devnet: &RiftDevnet,
+    user_account: &MultichainAccount,
+) -> CreateSwapResponse {
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let (quote, quote_signature) = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+
+    otc_client
+        .create_swap(&CreateSwapRequest {
+            quote,
+            quote_signature,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .expect("Swap creation should succeed")
+}
+
+async fn master_key_version_of(pool: &PgPool, swap_id: Uuid) -> i32 {
+    sqlx::query_scalar("SELECT master_key_version FROM swaps WHERE id = $1")
+        .bind(swap_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn test_swap_settles_after_master_key_rotation(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let mut wallet_join_set = JoinSet::new();
+    let user_bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &context.bitcoin_wallet_dir(),
+        &build_bitcoin_wallet_descriptor(&user_account.bitcoin_wallet.private_key),
+        None,
+        bitcoin::Network::Regtest,
+        &devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut wallet_join_set,
+    )
+    .await
+    .unwrap();
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &user_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(500_000_000), // 5 BTC
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(9_000_000_000i128), // 90 cbbtc
+        )
+        .await
+        .unwrap();
+
+    let mut service_join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    let otc_database_url = otc_args.database_url.clone();
+    let settings_file = otc_args.settings_file.clone();
+    service_join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    service_join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    service_join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+    let pool = PgPool::connect(&otc_database_url).await.unwrap();
+    let master_keys_url = format!("http://localhost:{otc_port}/admin/master-keys");
+
+    // A fresh server starts on version 1
+    let response = client.get(&master_keys_url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let keys: MasterKeysResponse = client
+        .get(&master_keys_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(keys.current_version, 1);
+    assert_eq!(keys.keys.len(), 1);
+
+    let before_rotation =
+        create_bitcoin_to_ethereum_swap(&otc_client, &rfq_client, &devnet, &user_account).await;
+    assert_eq!(
+        master_key_version_of(&pool, before_rotation.swap_id).await,
+        1
+    );
+
+    // Rotate, the new key is persisted and becomes current
+    let response = client
+        .post(&master_keys_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let keys: MasterKeysResponse = response.json().await.unwrap();
+    assert_eq!(keys.current_version, 2);
+    let versions: Vec<_> = keys
+        .keys
+        .iter()
+        .map(|k| (k.version, k.active_swaps))
+        .collect();
+    assert_eq!(versions, vec![(1, 1), (2, 0)]);
+    let persisted = std::fs::read_to_string(&settings_file).unwrap();
+    assert!(persisted.contains("current_master_key_version = 2"));
+
+    // The in-flight swap keeps its deposit address, new swaps use the new key
+    let swap = otc_client.get_swap(before_rotation.swap_id).await.unwrap();
+    assert_eq!(swap.user_deposit.address, before_rotation.deposit_address);
+
+    let after_rotation =
+        create_bitcoin_to_ethereum_swap(&otc_client, &rfq_client, &devnet, &user_account).await;
+    assert_eq!(
+        master_key_version_of(&pool, after_rotation.swap_id).await,
+        2
+    );
+
+    // Version 1 can't be dropped while a swap still derives from it, the current one never
+    for (version, expected) in [
+        (1, StatusCode::CONFLICT),
+        (2, StatusCode::CONFLICT),
+        (7, StatusCode::NOT_FOUND),
+    ] {
+        let response = client
+            .delete(format!("{master_keys_url}/{version}"))
+            .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected, "removing version {version}");
+    }
+
+    // Settle the swap created under version 1
+    user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: Currency {
+                    chain: ChainType::Bitcoin,
+                    token: TokenIdentifier::Native,
+                    decimals: before_rotation.decimals,
+                    chain_id: None,
+                },
+                amount: before_rotation.expected_amount,
+            },
+            &before_rotation.deposit_address,
+            None,
+        )
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(6).await.unwrap();
+    wait_for_swap_to_be_settled(otc_port, before_rotation.swap_id).await;
+
+    // Once nothing references it, the retired key can go
+    let response = client
+        .delete(format!("{master_keys_url}/1"))
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let keys: MasterKeysResponse = client
+        .get(&master_keys_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let versions: Vec<_> = keys
+        .keys
+        .iter()
+        .map(|k| (k.version, k.active_swaps))
+        .collect();
+    assert_eq!(versions, vec![(2, 1)]);
+
+    drop(devnet);
+    tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
+}
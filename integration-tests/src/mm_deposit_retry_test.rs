@@ -0,0 +1,333 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy::primitives::{Address, U256};
+use alloy::providers::DynProvider;
+use async_trait::async_trait;
+use blockchain_utils::GenericERC20::GenericERC20Instance;
+use devnet::MultichainAccount;
+use market_maker::bitcoin_wallet::{
+    BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, SignerConfig,
+    DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+};
+use market_maker::run_market_maker_with_wallet_layer;
+use market_maker::wallet::{
+    self, FailedPayment, FillPreparation, PaymentStatus, TransactionResult, Wallet, WalletError,
+};
+use otc_chains::traits::MarketMakerPaymentValidation;
+use otc_client::{types::CreateSwapRequest, OtcApiClient, RfqApiClient};
+use otc_models::{
+    ChainType, Currency, Lot, QuoteMode, QuoteRequest, SwapStatus, TokenIdentifier, TxHash,
+};
+use otc_protocols::rfq::RFQResult;
+use otc_server::{server::run_server_with_listener, OtcServerArgs};
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use tokio::task::JoinSet;
+
+use crate::utils::{
+    bind_free_port, build_bitcoin_wallet_descriptor, build_mm_test_args,
+    build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, wait_for_swap_status,
+    PgConnectOptionsExt, TestContext,
+};
+
+/// Gas for the failing payout, set so the send doesn't stop at a failed estimate
+const REVERTING_PAYOUT_GAS: u64 = 100_000;
+
+/// Its first payout is a cbBTC `transferFrom` out of the market maker's account
+/// with no allowance behind it, mined and reverted. Later payouts go through
+struct RevertingOnceWallet {
+    inner: Arc<dyn Wallet>,
+    cbbtc: GenericERC20Instance<DynProvider>,
+    market_maker: Address,
+    reverted: AtomicBool,
+}
+
+impl RevertingOnceWallet {
+    async fn reverting_payout(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+    ) -> wallet::Result<TransactionResult> {
+        let failed = |reason: String| WalletError::TransactionCreationFailed { reason };
+        let to: Address = to_address.parse().map_err(|e| failed(format!("{e}")))?;
+        let receipt = self
+            .cbbtc
+            .transferFrom(self.market_maker, to, lot.amount)
+            .gas(REVERTING_PAYOUT_GAS)
+            .send()
+            .await
+            .map_err(|e| failed(e.to_string()))?
+            .get_receipt()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        assert!(!receipt.status(), "the payout should revert");
+        Ok(TransactionResult {
+            tx_hash: receipt.transaction_hash.into(),
+            fee: None,
+            confirmations: 0,
+            raw: None,
+            usage: None,
+            nonce: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Wallet for RevertingOnceWallet {
+    async fn create_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        if !self.reverted.swap(true, Ordering::SeqCst) {
+            return self.reverting_payout(lot, to_address).await;
+        }
+        self.inner
+            .create_payment(lot, to_address, mm_payment_validation)
+            .await
+    }
+
+    async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
+        self.inner.can_fill(lot).await
+    }
+
+    async fn prepare_fill(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> wallet::Result<FillPreparation> {
+        self.inner.prepare_fill(lot, pending).await
+    }
+
+    async fn create_prepared_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        preparation: &FillPreparation,
+    ) -> wallet::Result<TransactionResult> {
+        if !self.reverted.swap(true, Ordering::SeqCst) {
+            return self.reverting_payout(lot, to_address).await;
+        }
+        self.inner
+            .create_prepared_payment(lot, to_address, mm_payment_validation, preparation)
+            .await
+    }
+
+    async fn balance(&self, currency: &Currency) -> wallet::Result<U256> {
+        self.inner.balance(currency).await
+    }
+
+    async fn payment_status(&self, tx_hash: &TxHash) -> wallet::Result<PaymentStatus> {
+        self.inner.payment_status(tx_hash).await
+    }
+
+    async fn replace_payment(
+        &self,
+        failed: &FailedPayment,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        self.inner
+            .replace_payment(failed, lot, to_address, mm_payment_validation)
+            .await
+    }
+}
+
+#[sqlx::test]
+async fn test_reverted_mm_deposit_is_retried(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let mut wallet_join_set = JoinSet::new();
+    let user_bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &context.bitcoin_wallet_dir(),
+        &build_bitcoin_wallet_descriptor(&user_account.bitcoin_wallet.private_key),
+        None,
+        bitcoin::Network::Regtest,
+        &devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut wallet_join_set,
+    )
+    .await
+    .unwrap();
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &user_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(500_000_000), // 5 BTC
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(9_000_000_000i128), // 90 cbbtc
+        )
+        .await
+        .unwrap();
+
+    let mut service_join_set = JoinSet::new();
+
+    // A short grace so the reverted payout is judged within the test's timeout
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = OtcServerArgs {
+        mm_deposit_retry_grace_seconds: 5,
+        ..build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await
+    };
+    let otc_database_url = otc_args.database_url.clone();
+    service_join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    service_join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    let cbbtc = devnet.ethereum.cbbtc_contract.clone();
+    let market_maker = market_maker_account.ethereum_address;
+    service_join_set.spawn(async move {
+        run_market_maker_with_wallet_layer(mm_args, move |chain, wallet| match chain {
+            ChainType::Ethereum => Arc::new(RevertingOnceWallet {
+                inner: wallet,
+                cbbtc: cbbtc.clone(),
+                market_maker,
+                reverted: AtomicBool::new(false),
+            }),
+            ChainType::Bitcoin => wallet,
+        })
+        .await
+        .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let (quote, quote_signature) = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+
+    let swap = otc_client
+        .create_swap(&CreateSwapRequest {
+            quote,
+            quote_signature,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .unwrap();
+
+    user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: Currency {
+                    chain: ChainType::Bitcoin,
+                    token: TokenIdentifier::Native,
+                    decimals: swap.decimals,
+                    chain_id: None,
+                },
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(6).await.unwrap();
+
+    // The reverted payout is noticed, the market maker pays again and the swap settles
+    wait_for_swap_status(otc_port, swap.swap_id, SwapStatus::Settled).await;
+
+    let pool = PgPool::connect(&otc_database_url).await.unwrap();
+    let (retries, claimed_tx_hash): (i32, Option<String>) =
+        sqlx::query_as("SELECT mm_deposit_retries, mm_claimed_tx_hash FROM swaps WHERE id = $1")
+            .bind(swap.swap_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(retries, 1);
+    let response = otc_client.get_swap(swap.swap_id).await.unwrap();
+    let settled_tx_hash = response.mm_deposit.deposit_tx.unwrap();
+    assert_eq!(claimed_tx_hash, Some(settled_tx_hash));
+
+    drop(devnet);
+    tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
+}
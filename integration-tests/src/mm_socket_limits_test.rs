@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use otc_models::ApiKey;
+use tokio::{net::TcpStream, task::JoinSet};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, protocol::frame::coding::CloseCode, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_rfq_server_test_args, get_whitelist_file_path,
+    wait_for_rfq_server_to_be_ready, TestContext, TEST_API_KEY,
+};
+
+type MmSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const MAX_MESSAGE_BYTES: usize = 1024;
+const MAX_MALFORMED_MESSAGES: u32 = 3;
+
+/// Starts an RFQ server that knows the test key under two market makers,
+/// returning its port and the key ids
+async fn launch_rfq_server(context: &TestContext, join_set: &mut JoinSet<()>) -> (u16, [Uuid; 2]) {
+    let whitelist = std::fs::read_to_string(get_whitelist_file_path()).unwrap();
+    let mut api_keys: Vec<ApiKey> = serde_json::from_str(&whitelist).unwrap();
+    let mut second = api_keys[0].clone();
+    second.id = Uuid::new_v4();
+    second.market_maker = Uuid::new_v4().to_string();
+    let key_ids = [api_keys[0].id, second.id];
+    api_keys.push(second);
+    let whitelist_file = context.path().join("two_market_makers.json");
+    std::fs::write(&whitelist_file, serde_json::to_string(&api_keys).unwrap()).unwrap();
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let mut rfq_args = build_rfq_server_test_args(rfq_port);
+    rfq_args.whitelist_file = whitelist_file.to_string_lossy().to_string();
+    rfq_args.mm_max_message_bytes = MAX_MESSAGE_BYTES;
+    rfq_args.mm_max_malformed_messages = MAX_MALFORMED_MESSAGES;
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    (rfq_port, key_ids)
+}
+
+/// Connects as the market maker holding `key_id`, past the Connected frame
+async fn connect_mm(rfq_port: u16, key_id: Uuid) -> MmSocket {
+    let mut request = format!("ws://127.0.0.1:{rfq_port}/ws/mm")
+        .into_client_request()
+        .unwrap();
+    let headers = request.headers_mut();
+    headers.insert("x-api-key-id", key_id.to_string().parse().unwrap());
+    headers.insert("x-api-key", TEST_API_KEY.parse().unwrap());
+    let (mut socket, _) = connect_async(request).await.unwrap();
+
+    let connected = next_message(&mut socket).await.expect("Connected frame");
+    assert!(connected.to_text().unwrap().contains("Connected"));
+    socket
+}
+
+async fn next_message(socket: &mut MmSocket) -> Option<Message> {
+    tokio::time::timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("Timed out waiting for the RFQ server")
+        .and_then(Result::ok)
+}
+
+async fn expect_close(socket: &mut MmSocket, code: CloseCode) {
+    match next_message(socket).await {
+        Some(Message::Close(Some(frame))) => assert_eq!(frame.code, code),
+        other => panic!("Expected a {code} close, got {other:?}"),
+    }
+}
+
+/// The server still answers `socket`, its connection survived
+async fn expect_alive(socket: &mut MmSocket) {
+    socket
+        .send(Message::Ping(b"still there?".to_vec()))
+        .await
+        .unwrap();
+    match next_message(socket).await {
+        Some(Message::Pong(payload)) => assert_eq!(payload, b"still there?"),
+        other => panic!("Expected a pong, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_message_disconnects_only_the_sender() {
+    let context = TestContext::new();
+    let mut join_set = JoinSet::new();
+    let (rfq_port, [bad_key, good_key]) = launch_rfq_server(&context, &mut join_set).await;
+
+    let mut good = connect_mm(rfq_port, good_key).await;
+    let mut bad = connect_mm(rfq_port, bad_key).await;
+    bad.send(Message::Text("a".repeat(MAX_MESSAGE_BYTES + 1)))
+        .await
+        .unwrap();
+    expect_close(&mut bad, CloseCode::Size).await;
+    expect_alive(&mut good).await;
+
+    // Far past the cap the transport drops the connection without reading it
+    let mut bad = connect_mm(rfq_port, bad_key).await;
+    let _ = bad
+        .send(Message::Text("a".repeat(MAX_MESSAGE_BYTES * 10)))
+        .await;
+    assert!(
+        !matches!(
+            next_message(&mut bad).await,
+            Some(Message::Text(_) | Message::Pong(_))
+        ),
+        "Connection should be closed"
+    );
+    expect_alive(&mut good).await;
+
+    join_set.abort_all();
+}
+
+#[tokio::test]
+async fn test_malformed_burst_disconnects_only_the_sender() {
+    let context = TestContext::new();
+    let mut join_set = JoinSet::new();
+    let (rfq_port, [bad_key, good_key]) = launch_rfq_server(&context, &mut join_set).await;
+
+    let mut good = connect_mm(rfq_port, good_key).await;
+    let mut bad = connect_mm(rfq_port, bad_key).await;
+    // The good market maker slips a bad message in too, one isn't enough
+    good.send(Message::Text("not json".to_string()))
+        .await
+        .unwrap();
+    for _ in 0..MAX_MALFORMED_MESSAGES {
+        bad.send(Message::Text("{\"garbage\":".to_string()))
+            .await
+            .unwrap();
+    }
+    expect_close(&mut bad, CloseCode::Policy).await;
+    expect_alive(&mut good).await;
+
+    join_set.abort_all();
+}
+
+#[tokio::test]
+async fn test_revoked_key_loses_its_socket_and_quote_outcomes() {
+    let context = TestContext::new();
+    let mut join_set = JoinSet::new();
+    let (rfq_port, [revoked_key, good_key]) = launch_rfq_server(&context, &mut join_set).await;
+
+    let mut good = connect_mm(rfq_port, good_key).await;
+    let mut revoked = connect_mm(rfq_port, revoked_key).await;
+
+    let whitelist_file = context.path().join("two_market_makers.json");
+    let whitelist = std::fs::read_to_string(&whitelist_file).unwrap();
+    let mut api_keys: Vec<ApiKey> = serde_json::from_str(&whitelist).unwrap();
+    for api_key in &mut api_keys {
+        if api_key.id == revoked_key {
+            api_key.revoked_at = Some(chrono::Utc::now());
+        }
+    }
+    std::fs::write(&whitelist_file, serde_json::to_string(&api_keys).unwrap()).unwrap();
+
+    // The test args reload the keys every second
+    expect_close(&mut revoked, CloseCode::Policy).await;
+    expect_alive(&mut good).await;
+
+    let quote_outcomes = |key_id: Uuid| {
+        reqwest::Client::new()
+            .get(format!(
+                "http://127.0.0.1:{rfq_port}/api/v1/mm/quote-outcomes"
+            ))
+            .header("x-api-key-id", key_id.to_string())
+            .header("x-api-key", TEST_API_KEY)
+            .send()
+    };
+    assert_eq!(
+        quote_outcomes(revoked_key).await.unwrap().status(),
+        reqwest::StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        quote_outcomes(good_key).await.unwrap().status(),
+        reqwest::StatusCode::OK
+    );
+
+    join_set.abort_all();
+}
@@ -0,0 +1,314 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy::primitives::U256;
+use async_trait::async_trait;
+use devnet::MultichainAccount;
+use market_maker::bitcoin_wallet::{
+    BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, SignerConfig,
+    DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+};
+use market_maker::run_market_maker_with_wallet_layer;
+use market_maker::wallet::{self, FillPreparation, TransactionResult, Wallet};
+use otc_chains::traits::MarketMakerPaymentValidation;
+use otc_client::{types::CreateSwapRequest, OtcApiClient, RfqApiClient};
+use otc_models::{ChainType, Currency, Lot, QuoteMode, QuoteRequest, SwapStatus, TokenIdentifier};
+use otc_protocols::rfq::RFQResult;
+use otc_server::server::run_server_with_listener;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use tokio::task::JoinSet;
+
+use crate::utils::{
+    bind_free_port, build_bitcoin_wallet_descriptor, build_mm_test_args,
+    build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, wait_for_swap_status,
+    PgConnectOptionsExt, TestContext,
+};
+
+/// How much less than quoted the market maker pays
+const SHORTFALL: u64 = 1_000;
+
+/// Pays `SHORTFALL` less than asked, still tagging the payment with the swap nonce
+struct UnderpayingWallet {
+    inner: Arc<dyn Wallet>,
+}
+
+impl UnderpayingWallet {
+    fn short_lot(lot: &Lot) -> Lot {
+        Lot {
+            currency: lot.currency.clone(),
+            amount: lot.amount - U256::from(SHORTFALL),
+        }
+    }
+}
+
+#[async_trait]
+impl Wallet for UnderpayingWallet {
+    async fn create_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+    ) -> wallet::Result<TransactionResult> {
+        self.inner
+            .create_payment(&Self::short_lot(lot), to_address, mm_payment_validation)
+            .await
+    }
+
+    async fn can_fill(&self, lot: &Lot) -> wallet::Result<bool> {
+        self.inner.can_fill(lot).await
+    }
+
+    async fn prepare_fill(
+        &self,
+        lot: &Lot,
+        pending: &[FillPreparation],
+    ) -> wallet::Result<FillPreparation> {
+        self.inner.prepare_fill(lot, pending).await
+    }
+
+    async fn create_prepared_payment(
+        &self,
+        lot: &Lot,
+        to_address: &str,
+        mm_payment_validation: Option<MarketMakerPaymentValidation>,
+        preparation: &FillPreparation,
+    ) -> wallet::Result<TransactionResult> {
+        self.inner
+            .create_prepared_payment(
+                &Self::short_lot(lot),
+                to_address,
+                mm_payment_validation,
+                preparation,
+            )
+            .await
+    }
+
+    async fn balance(&self, currency: &Currency) -> wallet::Result<U256> {
+        self.inner.balance(currency).await
+    }
+}
+
+#[sqlx::test]
+async fn test_underpaying_mm_deposit_refunds_the_user(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let mut wallet_join_set = JoinSet::new();
+    let user_bitcoin_wallet = BitcoinWallet::open_or_create_in_dir(
+        &context.bitcoin_wallet_dir(),
+        &build_bitcoin_wallet_descriptor(&user_account.bitcoin_wallet.private_key),
+        None,
+        bitcoin::Network::Regtest,
+        &devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
+        BitcoinWalletSyncConfig::default(),
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        CoinSelectionConfig::default(),
+        SignerConfig::Descriptor,
+        &mut wallet_join_set,
+    )
+    .await
+    .unwrap();
+
+    devnet
+        .bitcoin
+        .deal_bitcoin(
+            &user_account.bitcoin_wallet.address,
+            &bitcoin::Amount::from_sat(500_000_000), // 5 BTC
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+    devnet
+        .ethereum
+        .mint_cbbtc(
+            market_maker_account.ethereum_address,
+            U256::from(9_000_000_000i128), // 90 cbbtc
+        )
+        .await
+        .unwrap();
+
+    let mut service_join_set = JoinSet::new();
+
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    let otc_database_url = otc_args.database_url.clone();
+    service_join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    service_join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    // The market maker quotes honestly but shorts its cbBTC payouts
+    let mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    service_join_set.spawn(async move {
+        run_market_maker_with_wallet_layer(mm_args, |chain, wallet| match chain {
+            ChainType::Ethereum => Arc::new(UnderpayingWallet { inner: wallet }),
+            ChainType::Bitcoin => wallet,
+        })
+        .await
+        .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    devnet
+        .bitcoin
+        .wait_for_esplora_sync(Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    let quote_response = rfq_client.request_quote(&quote_request).await.unwrap();
+    let (quote, quote_signature) = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+    let quoted_amount = quote.to.amount;
+
+    let swap = otc_client
+        .create_swap(&CreateSwapRequest {
+            quote,
+            quote_signature,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .unwrap();
+
+    user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: Currency {
+                    chain: ChainType::Bitcoin,
+                    token: TokenIdentifier::Native,
+                    decimals: swap.decimals,
+                    chain_id: None,
+                },
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap();
+    devnet.bitcoin.mine_blocks(6).await.unwrap();
+
+    // The short payment is detected and the user is refunded instead of settling
+    wait_for_swap_status(otc_port, swap.swap_id, SwapStatus::RefundingUser).await;
+
+    let pool = PgPool::connect(&otc_database_url).await.unwrap();
+    let events: Vec<(Option<SwapStatus>, SwapStatus)> = sqlx::query_as(
+        "SELECT from_status, to_status FROM swap_events WHERE swap_id = $1 ORDER BY id",
+    )
+    .bind(swap.swap_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        events,
+        vec![
+            (None, SwapStatus::WaitingUserDepositInitiated),
+            (
+                Some(SwapStatus::WaitingUserDepositInitiated),
+                SwapStatus::WaitingUserDepositConfirmed,
+            ),
+            (
+                Some(SwapStatus::WaitingUserDepositConfirmed),
+                SwapStatus::WaitingMMDepositInitiated,
+            ),
+            (
+                Some(SwapStatus::WaitingMMDepositInitiated),
+                SwapStatus::MMDepositAmountMismatch,
+            ),
+            (
+                Some(SwapStatus::MMDepositAmountMismatch),
+                SwapStatus::RefundingUser,
+            ),
+        ]
+    );
+
+    let received_amount = quoted_amount - U256::from(SHORTFALL);
+    let response = otc_client.get_swap(swap.swap_id).await.unwrap();
+    assert_eq!(response.mm_deposit.deposit_amount, Some(received_amount));
+
+    // The user's deposit key was never released
+    let (failure_reason, private_key_sent_at): (
+        Option<String>,
+        Option<chrono::DateTime<chrono::Utc>>,
+    ) = sqlx::query_as("SELECT failure_reason, mm_private_key_sent_at FROM swaps WHERE id = $1")
+        .bind(swap.swap_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(private_key_sent_at.is_none());
+    let failure_reason = failure_reason.unwrap();
+    assert!(
+        failure_reason.ends_with(&format!("sent {received_amount}, expected {quoted_amount}")),
+        "unexpected failure reason: {failure_reason}"
+    );
+
+    drop(devnet);
+    tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
+}
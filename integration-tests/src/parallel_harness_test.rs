@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use tokio::task::JoinSet;
+
+use crate::utils::{SwapTestHarness, SwapTestOptions};
+
+/// Harnesses booting at once, as under a highly parallel test run
+const HARNESSES: usize = 4;
+
+#[sqlx::test]
+async fn test_concurrent_harnesses_get_their_own_ports_and_files(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    // The token indexer would share the test's database between harnesses
+    let options = SwapTestOptions {
+        token_indexer: false,
+        ..SwapTestOptions::default()
+    };
+    let mut launches = JoinSet::new();
+    for _ in 0..HARNESSES {
+        let connect_options = connect_options.clone();
+        launches.spawn(async move { SwapTestHarness::launch(&connect_options, options).await });
+    }
+    let harnesses = launches.join_all().await;
+
+    let ports: HashSet<u16> = harnesses
+        .iter()
+        .flat_map(|harness| [harness.otc_port, harness.rfq_port])
+        .collect();
+    assert_eq!(ports.len(), 2 * HARNESSES);
+    let roots: HashSet<_> = harnesses
+        .iter()
+        .map(|harness| harness.context.path().to_path_buf())
+        .collect();
+    assert_eq!(roots.len(), HARNESSES);
+
+    // Every server answers on the port its harness bound for it
+    for harness in &harnesses {
+        for url in [harness.otc_url("/status"), harness.rfq_url("/status")] {
+            let response = harness.client.get(&url).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{url}");
+        }
+    }
+
+    for harness in harnesses {
+        let root = harness.context.path().to_path_buf();
+        harness.shutdown().await;
+        assert!(!root.exists());
+    }
+}
@@ -0,0 +1,65 @@
+use alloy::primitives::U256;
+use otc_client::RfqApiClient;
+use otc_models::{QuoteMode, QuoteRequest};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use uuid::Uuid;
+
+use crate::utils::{SwapTestHarness, SwapTestOptions};
+
+#[sqlx::test]
+async fn test_locked_quote_creates_a_swap(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(10_000_000), // 0.1 BTC
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        })
+        .await;
+
+    // Locking holds market maker funds, so it takes an API key
+    let anonymous = RfqApiClient::new(format!("http://localhost:{}", harness.rfq_port)).unwrap();
+    let unauthenticated = anonymous.lock_quote(quote.id).await.unwrap_err();
+    assert_eq!(unauthenticated.status(), Some(StatusCode::UNAUTHORIZED));
+
+    let lock = harness
+        .rfq_client
+        .lock_quote(quote.id)
+        .await
+        .expect("Market maker should lock the quote");
+    assert_eq!(lock.quote_id, quote.id);
+    assert!(lock.expires_at <= quote.expires_at);
+
+    let relock = harness.rfq_client.lock_quote(quote.id).await.unwrap_err();
+    assert_eq!(relock.status(), Some(StatusCode::CONFLICT));
+    let unknown = harness
+        .rfq_client
+        .lock_quote(Uuid::new_v4())
+        .await
+        .unwrap_err();
+    assert_eq!(unknown.status(), Some(StatusCode::NOT_FOUND));
+
+    let mut swap_request = harness.swap_request(
+        quote,
+        quote_signature,
+        harness.user_account.ethereum_address.to_string(),
+    );
+
+    // The token only vouches for the expiry the RFQ server granted
+    let mut stretched_lock = lock.clone();
+    stretched_lock.expires_at += chrono::Duration::hours(1);
+    swap_request.quote_lock = Some(stretched_lock);
+    let error = harness.try_create_swap(&swap_request).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+    swap_request.quote_lock = Some(lock);
+    harness.create_swap(&swap_request).await;
+
+    harness.shutdown().await;
+}
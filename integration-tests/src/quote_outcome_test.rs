@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use alloy::primitives::U256;
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use otc_client::{types::QuoteOutcomesResponse, RfqApiClient};
+use otc_models::{
+    ApiKey, ChainType, Currency, Lot, Quote, QuoteMode, QuoteRequest, TokenIdentifier,
+    CBBTC_ADDRESS,
+};
+use otc_protocols::rfq::{
+    FeeSchedule, ProtocolMessage, QuoteOutcome, QuoteWithFees, RFQRequest, RFQResponse, RFQResult,
+    PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+};
+use rfq_server::RfqServerArgs;
+use tokio::{sync::mpsc, task::JoinSet};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+};
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_rfq_server_test_args, get_whitelist_file_path,
+    wait_for_rfq_server_to_be_ready, TestContext, INTEGRATION_TEST_TIMEOUT_SECS, TEST_API_KEY,
+    TEST_API_KEY_ID, TEST_MARKET_MAKER_ID,
+};
+
+/// The second market maker's key, the test key's secret under another id
+const RIVAL_API_KEY_ID: &str = "0b7c3f0e-6a39-4c1e-9f4a-2f4f5d1f2a10";
+const RIVAL_MARKET_MAKER_ID: &str = "4f3e2d1c-0b9a-4c8d-8e7f-6a5b4c3d2e1f";
+
+fn quote_request() -> QuoteRequest {
+    QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000u64),
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(CBBTC_ADDRESS.to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    }
+}
+
+/// Connect as `market_maker_id` and quote `to_amount` for every request,
+/// forwarding the quote outcomes the server pushes
+async fn connect_market_maker(
+    rfq_port: u16,
+    api_key_id: &str,
+    market_maker_id: &str,
+    to_amount: u64,
+    join_set: &mut JoinSet<()>,
+) -> mpsc::UnboundedReceiver<QuoteOutcome> {
+    let mut request = format!("ws://127.0.0.1:{rfq_port}/ws/mm")
+        .into_client_request()
+        .unwrap();
+    let headers = request.headers_mut();
+    headers.insert("x-api-key-id", api_key_id.parse().unwrap());
+    headers.insert("x-api-key", TEST_API_KEY.parse().unwrap());
+    headers.insert(PROTOCOL_VERSION_HEADER, PROTOCOL_VERSION.parse().unwrap());
+    let (mut socket, _) = connect_async(request).await.unwrap();
+    let connected_frame = socket.next().await.unwrap().unwrap();
+    assert!(connected_frame.to_text().unwrap().contains("Connected"));
+
+    let market_maker_id: Uuid = market_maker_id.parse().unwrap();
+    let (outcome_tx, outcome_rx) = mpsc::unbounded_channel();
+    join_set.spawn(async move {
+        while let Some(Ok(Message::Text(text))) = socket.next().await {
+            let message: ProtocolMessage<RFQRequest> = serde_json::from_str(&text).unwrap();
+            match message.payload {
+                RFQRequest::QuoteRequested {
+                    request_id,
+                    request,
+                    ..
+                } => {
+                    let now = Utc::now();
+                    let quote = QuoteWithFees {
+                        quote: Quote {
+                            id: Uuid::new_v4(),
+                            market_maker_id,
+                            from: Lot {
+                                currency: request.from,
+                                amount: request.amount,
+                            },
+                            to: Lot {
+                                currency: request.to,
+                                amount: U256::from(to_amount),
+                            },
+                            expires_at: now + chrono::Duration::minutes(5),
+                            created_at: now,
+                        },
+                        fees: FeeSchedule {
+                            network_fee_sats: 0,
+                            liquidity_fee_sats: 0,
+                            protocol_fee_sats: 0,
+                        },
+                        signature: None,
+                    };
+                    let response = ProtocolMessage {
+                        version: PROTOCOL_VERSION.to_string(),
+                        sequence: message.sequence,
+                        payload: RFQResponse::QuoteResponse {
+                            request_id,
+                            quote: RFQResult::Success(quote),
+                            timestamp: now,
+                        },
+                        trace_id: message.trace_id,
+                    };
+                    socket
+                        .send(Message::Text(serde_json::to_string(&response).unwrap()))
+                        .await
+                        .unwrap();
+                }
+                RFQRequest::QuoteOutcome { outcome, .. } => {
+                    let _ = outcome_tx.send(outcome);
+                }
+                _ => {}
+            }
+        }
+    });
+    outcome_rx
+}
+
+async fn quote_outcomes(rfq_port: u16, api_key_id: &str, since: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!(
+            "http://127.0.0.1:{rfq_port}/api/v1/mm/quote-outcomes{since}"
+        ))
+        .header("x-api-key-id", api_key_id)
+        .header("x-api-key", TEST_API_KEY)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_competing_market_makers_both_learn_the_outcome() {
+    let context = TestContext::new();
+
+    // A second market maker, authenticated with its own key
+    let whitelist = std::fs::read_to_string(get_whitelist_file_path()).unwrap();
+    let mut api_keys: Vec<ApiKey> = serde_json::from_str(&whitelist).unwrap();
+    let mut rival = api_keys[0].clone();
+    rival.id = RIVAL_API_KEY_ID.parse().unwrap();
+    rival.market_maker = RIVAL_MARKET_MAKER_ID.to_string();
+    api_keys.push(rival);
+    let whitelist_file = context.path().join("two_market_makers.json");
+    std::fs::write(&whitelist_file, serde_json::to_string(&api_keys).unwrap()).unwrap();
+
+    let mut join_set = JoinSet::new();
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = RfqServerArgs {
+        whitelist_file: whitelist_file.to_string_lossy().to_string(),
+        ..build_rfq_server_test_args(rfq_port)
+    };
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mut winner_outcomes = connect_market_maker(
+        rfq_port,
+        TEST_API_KEY_ID,
+        TEST_MARKET_MAKER_ID,
+        9_990_000,
+        &mut join_set,
+    )
+    .await;
+    let mut loser_outcomes = connect_market_maker(
+        rfq_port,
+        RIVAL_API_KEY_ID,
+        RIVAL_MARKET_MAKER_ID,
+        9_900_000,
+        &mut join_set,
+    )
+    .await;
+
+    let rfq_client = RfqApiClient::new(format!("http://127.0.0.1:{rfq_port}")).unwrap();
+    let response = rfq_client.request_quote(&quote_request()).await.unwrap();
+    let Some(RFQResult::Success(best)) = response.quote else {
+        panic!("Quote should be a success, got {:?}", response.quote);
+    };
+    assert_eq!(best.quote.market_maker_id.to_string(), TEST_MARKET_MAKER_ID);
+
+    // Both are told over the websocket, the loser only learns the winning price
+    let timeout = Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
+    let won = tokio::time::timeout(timeout, winner_outcomes.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let lost = tokio::time::timeout(timeout, loser_outcomes.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(won.won);
+    assert_eq!(won.quote_id, best.quote.id);
+    assert!(!lost.won);
+    assert_ne!(lost.quote_id, best.quote.id);
+    assert_eq!(lost.winning_amount, U256::from(9_990_000u64));
+
+    // The same outcomes can be fetched later, each market maker only sees its own
+    for (api_key_id, market_maker_id, outcome) in [
+        (TEST_API_KEY_ID, TEST_MARKET_MAKER_ID, &won),
+        (RIVAL_API_KEY_ID, RIVAL_MARKET_MAKER_ID, &lost),
+    ] {
+        let response = quote_outcomes(rfq_port, api_key_id, "").await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let outcomes: QuoteOutcomesResponse = response.json().await.unwrap();
+        assert_eq!(outcomes.market_maker_id.to_string(), market_maker_id);
+        assert_eq!(outcomes.outcomes, vec![outcome.clone()]);
+    }
+    let since = format!("?since={}", lost.decided_at.to_rfc3339()).replace('+', "%2B");
+    let later: QuoteOutcomesResponse = quote_outcomes(rfq_port, RIVAL_API_KEY_ID, &since)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(later.outcomes.is_empty());
+
+    let unauthenticated = reqwest::get(format!(
+        "http://127.0.0.1:{rfq_port}/api/v1/mm/quote-outcomes"
+    ))
+    .await
+    .unwrap();
+    assert_eq!(unauthenticated.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    join_set.abort_all();
+}
@@ -1,22 +1,77 @@
-use alloy::primitives::U256;
-use chrono::{Duration, Utc};
-use market_maker::quote_storage::QuoteStorage;
-use otc_models::{ChainType, Currency, Lot, Quote, TokenIdentifier};
+use alloy::primitives::{B256, U256};
+use chrono::{DateTime, Duration, Utc};
+use common::SystemClock;
+use market_maker::{
+    quote_storage::{
+        QuoteAttempt, QuoteAttemptFilter, QuoteAttemptOutcome, QuoteStorage, QuoteStorageError,
+        QuoteStorageStats,
+    },
+    quotes_cli::QuotesArgs,
+    wallet::FillPreparation,
+    wallet_buckets::{BucketEarmarks, WalletBucket, WalletBucketError, WalletBuckets},
+    wrapped_bitcoin_quoter::PricingInputs,
+};
+use otc_models::{
+    ChainNetwork, ChainType, Currency, Lot, Quote, QuoteMode, QuoteRequest, TokenIdentifier, TxHash,
+};
+use otc_protocols::rfq::{FeeSchedule, QuoteWithFees, RFQResult};
 use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use std::sync::Arc;
 use tokio::task::JoinSet;
 use uuid::Uuid;
 
 use crate::utils::PgConnectOptionsExt;
 
+#[sqlx::test]
+async fn test_pending_migrations_are_counted_without_applying_them(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let database_url = connect_options.to_database_url();
+    let pending = QuoteStorage::pending_migrations(&database_url)
+        .await
+        .expect("Failed to check migrations");
+    assert!(pending > 0);
+    // Checking twice finds the same, nothing was applied
+    assert_eq!(
+        QuoteStorage::pending_migrations(&database_url)
+            .await
+            .unwrap(),
+        pending
+    );
+
+    let mut join_set = JoinSet::new();
+    QuoteStorage::new(
+        &database_url,
+        Duration::hours(24),
+        Arc::new(SystemClock),
+        &mut join_set,
+    )
+    .await
+    .expect("Failed to create storage");
+    assert_eq!(
+        QuoteStorage::pending_migrations(&database_url)
+            .await
+            .unwrap(),
+        0
+    );
+    Ok(())
+}
+
 #[sqlx::test]
 async fn test_quote_storage_round_trip(
     _: PoolOptions<sqlx::Postgres>,
     connect_options: PgConnectOptions,
 ) -> sqlx::Result<()> {
     let mut join_set = JoinSet::new();
-    let storage = QuoteStorage::new(&connect_options.to_database_url(), &mut join_set)
-        .await
-        .expect("Failed to create storage");
+    let storage = QuoteStorage::new(
+        &connect_options.to_database_url(),
+        Duration::hours(24),
+        Arc::new(SystemClock),
+        &mut join_set,
+    )
+    .await
+    .expect("Failed to create storage");
 
     let original_quote = Quote {
         id: Uuid::new_v4(),
@@ -26,6 +81,7 @@ async fn test_quote_storage_round_trip(
                 chain: ChainType::Bitcoin,
                 token: TokenIdentifier::Native,
                 decimals: 8,
+                chain_id: None,
             },
             amount: U256::from(1000000u64),
         },
@@ -34,6 +90,7 @@ async fn test_quote_storage_round_trip(
                 chain: ChainType::Ethereum,
                 token: TokenIdentifier::Native,
                 decimals: 18,
+                chain_id: None,
             },
             amount: U256::from(500000000000000000u64),
         },
@@ -61,3 +118,652 @@ async fn test_quote_storage_round_trip(
 
     Ok(())
 }
+
+#[sqlx::test]
+async fn test_protocol_fee_totals_by_currency(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let mut join_set = JoinSet::new();
+    let storage = QuoteStorage::new(
+        &connect_options.to_database_url(),
+        Duration::hours(24),
+        Arc::new(SystemClock),
+        &mut join_set,
+    )
+    .await
+    .expect("Failed to create storage");
+
+    let now = Utc::now();
+    let to_ether = |fee: u64| (quote_created_at(now, now + Duration::minutes(5)), fee);
+    let to_bitcoin = |fee: u64| {
+        let mut quote = quote_created_at(now, now + Duration::minutes(5));
+        std::mem::swap(&mut quote.from, &mut quote.to);
+        (quote, fee)
+    };
+    let fill_tx = TxHash::from(B256::repeat_byte(0x11));
+    let fills = [
+        to_ether(300),
+        to_ether(1_250),
+        to_bitcoin(300),
+        to_bitcoin(10_000),
+    ];
+    for (quote, fee) in &fills {
+        storage.store_quote(quote).await.unwrap();
+        storage
+            .mark_filled(quote.id, U256::from(*fee), &fill_tx, Some(7))
+            .await
+            .unwrap();
+    }
+    // Quotes that were never paid out owe nothing
+    storage
+        .store_quote(&quote_created_at(now, now + Duration::minutes(5)))
+        .await
+        .unwrap();
+    // A repeated fill report keeps the fee first recorded
+    let retried_fill_tx = TxHash::from(B256::repeat_byte(0x22));
+    storage
+        .mark_filled(
+            fills[0].0.id,
+            U256::from(99_999u64),
+            &retried_fill_tx,
+            Some(8),
+        )
+        .await
+        .unwrap();
+    // while the payout it points at is the latest
+    assert_eq!(
+        storage.fill_tx_hash(fills[0].0.id).await.unwrap(),
+        Some(retried_fill_tx)
+    );
+    assert_eq!(storage.fill_nonce(fills[0].0.id).await.unwrap(), Some(8));
+
+    // The fees left the wallet with the payouts, nothing is set aside for them
+    assert!(storage.bucket_earmarks().await.unwrap().is_empty());
+    assert_eq!(
+        storage.earmarks().get(&fills[0].0.to.currency),
+        BucketEarmarks::default()
+    );
+
+    let mut totals: Vec<(ChainType, U256)> = storage
+        .protocol_fee_totals()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|lot| (lot.currency.chain, lot.amount))
+        .collect();
+    totals.sort();
+    assert_eq!(
+        totals,
+        vec![
+            (ChainType::Bitcoin, U256::from(10_300u64)),
+            (ChainType::Ethereum, U256::from(1_550u64)),
+        ]
+    );
+
+    Ok(())
+}
+
+fn quote_created_at(created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> Quote {
+    Quote {
+        id: Uuid::new_v4(),
+        market_maker_id: Uuid::new_v4(),
+        from: Lot {
+            currency: Currency {
+                chain: ChainType::Bitcoin,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(1000000u64),
+        },
+        to: Lot {
+            currency: Currency {
+                chain: ChainType::Ethereum,
+                token: TokenIdentifier::Native,
+                decimals: 18,
+                chain_id: None,
+            },
+            amount: U256::from(500000000000000000u64),
+        },
+        expires_at,
+        created_at,
+    }
+}
+
+#[sqlx::test]
+async fn test_quote_retention_keeps_referenced_quotes(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let mut join_set = JoinSet::new();
+    // Long enough that the background task never races the explicit cleanup below
+    let storage = QuoteStorage::new(
+        &connect_options.to_database_url(),
+        Duration::days(3650),
+        Arc::new(SystemClock),
+        &mut join_set,
+    )
+    .await
+    .expect("Failed to create storage");
+
+    let now = Utc::now();
+    let two_days_ago = now - Duration::days(2);
+
+    let stale = quote_created_at(two_days_ago, two_days_ago + Duration::minutes(5));
+    let accepted = quote_created_at(two_days_ago, two_days_ago + Duration::minutes(5));
+    let filled = quote_created_at(two_days_ago, two_days_ago + Duration::minutes(5));
+    let recent = quote_created_at(now - Duration::hours(1), now - Duration::minutes(55));
+    let unexpired = quote_created_at(two_days_ago, now + Duration::minutes(5));
+    // Expired from `now` on, whatever the database's clock says
+    let expiring_now = quote_created_at(two_days_ago, now);
+
+    for quote in [
+        &stale,
+        &accepted,
+        &filled,
+        &recent,
+        &unexpired,
+        &expiring_now,
+    ] {
+        storage.store_quote(quote).await.unwrap();
+    }
+    storage.mark_accepted(accepted.id).await.unwrap();
+    storage.mark_accepted(filled.id).await.unwrap();
+    storage
+        .mark_filled(
+            filled.id,
+            U256::from(300u64),
+            &B256::repeat_byte(0x11).into(),
+            None,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        storage.stats().await.unwrap(),
+        QuoteStorageStats {
+            total: 6,
+            active: 1,
+            accepted: 2,
+            filled: 1,
+        }
+    );
+
+    let cutoff = now - Duration::hours(24);
+    let deleted = storage
+        .delete_unreferenced_quotes(now, cutoff)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2);
+
+    for quote in [&stale, &expiring_now] {
+        assert!(storage.get_quote(quote.id).await.is_err());
+    }
+    for quote in [&accepted, &filled, &recent, &unexpired] {
+        assert_eq!(storage.get_quote(quote.id).await.unwrap().id, quote.id);
+    }
+
+    // Running again finds nothing left to delete
+    assert_eq!(
+        storage
+            .delete_unreferenced_quotes(now, cutoff)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(storage.stats().await.unwrap().total, 4);
+
+    Ok(())
+}
+
+fn fill_preparation(chain: ChainType, amount: u64) -> FillPreparation {
+    FillPreparation {
+        lot: Lot {
+            currency: Currency {
+                chain,
+                token: TokenIdentifier::Native,
+                decimals: 8,
+                chain_id: None,
+            },
+            amount: U256::from(amount),
+        },
+        fee_rate: None,
+        utxos: Vec::new(),
+        lookup_duration: std::time::Duration::from_millis(250),
+    }
+}
+
+#[sqlx::test]
+async fn test_fill_preparations_are_taken_once_and_expire(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let mut join_set = JoinSet::new();
+    let storage = QuoteStorage::new(
+        &connect_options.to_database_url(),
+        Duration::hours(24),
+        Arc::new(SystemClock),
+        &mut join_set,
+    )
+    .await
+    .expect("Failed to create storage");
+
+    let ttl = std::time::Duration::from_secs(60);
+    let bitcoin_quote = Uuid::new_v4();
+    let ethereum_quote = Uuid::new_v4();
+    let expiring_quote = Uuid::new_v4();
+    storage.cache_fill_preparation(
+        bitcoin_quote,
+        fill_preparation(ChainType::Bitcoin, 1_000),
+        ttl,
+    );
+    storage.cache_fill_preparation(
+        ethereum_quote,
+        fill_preparation(ChainType::Ethereum, 2_000),
+        ttl,
+    );
+    storage.cache_fill_preparation(
+        expiring_quote,
+        fill_preparation(ChainType::Bitcoin, 3_000),
+        std::time::Duration::from_millis(50),
+    );
+
+    // Pending preparations are what other fills on the chain already claim
+    let pending = storage.pending_fill_preparations(ChainType::Bitcoin);
+    assert_eq!(pending.len(), 2);
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // Expired preparations no longer reserve anything and can't be used
+    let pending = storage.pending_fill_preparations(ChainType::Bitcoin);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].lot.amount, U256::from(1_000));
+    assert!(storage.take_fill_preparation(expiring_quote).is_none());
+
+    // A preparation is used by a single payout
+    let taken = storage.take_fill_preparation(bitcoin_quote).unwrap();
+    assert_eq!(taken.lot.amount, U256::from(1_000));
+    assert!(storage.take_fill_preparation(bitcoin_quote).is_none());
+    assert!(storage
+        .pending_fill_preparations(ChainType::Bitcoin)
+        .is_empty());
+    assert!(storage.take_fill_preparation(Uuid::new_v4()).is_none());
+
+    // The cleanup drops what nobody took before it expired
+    storage.cache_fill_preparation(
+        expiring_quote,
+        fill_preparation(ChainType::Bitcoin, 3_000),
+        std::time::Duration::ZERO,
+    );
+    assert_eq!(storage.expire_fill_preparations(), 1);
+    assert_eq!(
+        storage.pending_fill_preparations(ChainType::Ethereum).len(),
+        1
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_bucket_transfers_persist_and_keep_earmarks_covered(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let mut join_set = JoinSet::new();
+    let storage = std::sync::Arc::new(
+        QuoteStorage::new(
+            &connect_options.to_database_url(),
+            Duration::hours(24),
+            Arc::new(SystemClock),
+            &mut join_set,
+        )
+        .await
+        .expect("Failed to create storage"),
+    );
+    let btc = Currency {
+        chain: ChainType::Bitcoin,
+        token: TokenIdentifier::Native,
+        decimals: 8,
+        chain_id: None,
+    };
+    let earmarks = |protocol_fees: u64, reserved: u64| BucketEarmarks {
+        protocol_fees: U256::from(protocol_fees),
+        reserved: U256::from(reserved),
+    };
+
+    assert!(storage.bucket_earmarks().await.unwrap().is_empty());
+
+    let after = storage
+        .transfer_between_buckets(
+            &btc,
+            WalletBucket::Trading,
+            WalletBucket::ProtocolFees,
+            U256::from(500),
+        )
+        .await
+        .unwrap();
+    assert_eq!(after, earmarks(500, 0));
+    let after = storage
+        .transfer_between_buckets(
+            &btc,
+            WalletBucket::ProtocolFees,
+            WalletBucket::Reserved,
+            U256::from(200),
+        )
+        .await
+        .unwrap();
+    assert_eq!(after, earmarks(300, 200));
+
+    // Buckets in the ledger can't go negative, whatever the wallet holds
+    assert!(matches!(
+        storage
+            .transfer_between_buckets(
+                &btc,
+                WalletBucket::Reserved,
+                WalletBucket::Trading,
+                U256::from(201)
+            )
+            .await,
+        Err(QuoteStorageError::InsufficientBucketBalance {
+            bucket: WalletBucket::Reserved,
+            ..
+        })
+    ));
+    assert!(matches!(
+        storage
+            .transfer_between_buckets(
+                &btc,
+                WalletBucket::Trading,
+                WalletBucket::Trading,
+                U256::from(1)
+            )
+            .await,
+        Err(QuoteStorageError::SameWalletBucket { .. })
+    ));
+
+    let stored = storage.bucket_earmarks().await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].0.chain, ChainType::Bitcoin);
+    assert_eq!(stored[0].1, earmarks(300, 200));
+
+    // A restart picks up where the ledger left off
+    let buckets = WalletBuckets::load(storage.clone()).await.unwrap();
+    assert_eq!(buckets.earmarks().get(&btc), earmarks(300, 200));
+
+    // Moves out of trading are checked against the balance last reconciled
+    assert!(matches!(
+        buckets
+            .transfer(
+                &btc,
+                WalletBucket::Trading,
+                WalletBucket::Reserved,
+                U256::from(1)
+            )
+            .await,
+        Err(WalletBucketError::BalanceNotRecorded { .. })
+    ));
+    storage
+        .record_wallet_balance(&btc, U256::from(1_000u64), Utc::now())
+        .await
+        .unwrap();
+    assert!(matches!(
+        buckets
+            .transfer(
+                &btc,
+                WalletBucket::Trading,
+                WalletBucket::Reserved,
+                U256::from(501)
+            )
+            .await,
+        Err(WalletBucketError::InsufficientTradingBalance { .. })
+    ));
+    assert_eq!(
+        buckets
+            .transfer(
+                &btc,
+                WalletBucket::Trading,
+                WalletBucket::Reserved,
+                U256::from(500)
+            )
+            .await
+            .unwrap(),
+        earmarks(300, 700)
+    );
+    assert_eq!(buckets.earmarks().get(&btc), earmarks(300, 700));
+
+    Ok(())
+}
+
+fn btc_to_eth_request(amount: u64) -> QuoteRequest {
+    QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Native,
+            decimals: 18,
+            chain_id: None,
+        },
+        amount: U256::from(amount),
+    }
+}
+
+fn offered(request: &QuoteRequest, to_amount: u64) -> RFQResult<QuoteWithFees> {
+    RFQResult::Success(QuoteWithFees {
+        quote: Quote {
+            id: Uuid::new_v4(),
+            market_maker_id: Uuid::new_v4(),
+            from: Lot {
+                currency: request.from.clone(),
+                amount: request.amount,
+            },
+            to: Lot {
+                currency: request.to.clone(),
+                amount: U256::from(to_amount),
+            },
+            expires_at: Utc::now() + Duration::minutes(10),
+            created_at: Utc::now(),
+        },
+        fees: FeeSchedule {
+            network_fee_sats: 300,
+            liquidity_fee_sats: 100,
+            protocol_fee_sats: 10,
+        },
+        signature: None,
+    })
+}
+
+fn attempt_at(
+    request: &QuoteRequest,
+    result: &RFQResult<QuoteWithFees>,
+    created_at: DateTime<Utc>,
+) -> QuoteAttempt {
+    QuoteAttempt::new(
+        Uuid::new_v4(),
+        request,
+        result,
+        PricingInputs {
+            sats_per_vbyte: Some(4.5),
+            eth_per_btc: Some(38.25),
+        },
+        std::time::Duration::from_millis(17),
+        created_at,
+    )
+}
+
+#[sqlx::test]
+async fn test_quote_attempts_round_trip_and_record_outcomes(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let storage = QuoteStorage::open(&connect_options.to_database_url())
+        .await
+        .expect("Failed to open storage");
+    let request = btc_to_eth_request(1_000_000);
+    let now = storage.database_now().await.unwrap();
+
+    let quoted = attempt_at(&request, &offered(&request, 380_000_000_000_000_000), now);
+    let declined = attempt_at(
+        &request,
+        &RFQResult::MakerUnavailable("Insufficient balance to fulfill quote".to_string()),
+        now - Duration::seconds(1),
+    );
+    storage.store_quote_attempt(&quoted).await.unwrap();
+    storage.store_quote_attempt(&declined).await.unwrap();
+
+    let attempts = storage
+        .quote_attempts(&QuoteAttemptFilter::default(), 10)
+        .await
+        .unwrap();
+    assert_eq!(attempts.len(), 2);
+
+    // Newest first
+    let stored = &attempts[0];
+    assert_eq!(stored.id, quoted.id);
+    assert_eq!(stored.request_id, quoted.request_id);
+    assert_eq!(stored.request.mode, request.mode);
+    assert_eq!(stored.request.from, request.from);
+    assert_eq!(stored.request.to, request.to);
+    assert_eq!(stored.request.amount, request.amount);
+    assert_eq!(stored.outcome, QuoteAttemptOutcome::Quoted);
+    assert_eq!(stored.message, None);
+    assert_eq!(stored.quote, quoted.quote);
+    assert_eq!(stored.inputs, quoted.inputs);
+    assert_eq!(stored.duration, quoted.duration);
+
+    let stored = &attempts[1];
+    assert_eq!(stored.id, declined.id);
+    assert_eq!(stored.outcome, QuoteAttemptOutcome::MakerUnavailable);
+    assert_eq!(
+        stored.message.as_deref(),
+        Some("Insufficient balance to fulfill quote")
+    );
+    assert_eq!(stored.quote, None);
+
+    // Only the quote itself can win or lose
+    let quote_id = quoted.quote.unwrap().quote_id;
+    storage.record_quote_outcome(quote_id, true).await.unwrap();
+    let won = storage
+        .quote_attempts(
+            &QuoteAttemptFilter {
+                outcome: Some(QuoteAttemptOutcome::Won),
+                ..QuoteAttemptFilter::default()
+            },
+            10,
+        )
+        .await
+        .unwrap();
+    assert_eq!(won.len(), 1);
+    assert_eq!(won[0].id, quoted.id);
+
+    assert_eq!(
+        storage
+            .delete_quote_attempts_before(now - Duration::milliseconds(500))
+            .await
+            .unwrap(),
+        1
+    );
+    let remaining = storage
+        .quote_attempts(&QuoteAttemptFilter::default(), 10)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, quoted.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_quotes_command_filters_attempts(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) -> sqlx::Result<()> {
+    let database_url = connect_options.to_database_url();
+    let storage = QuoteStorage::open(&database_url)
+        .await
+        .expect("Failed to open storage");
+    let now = storage.database_now().await.unwrap();
+
+    let btc_to_eth = btc_to_eth_request(1_000_000);
+    let eth_to_btc = QuoteRequest {
+        from: btc_to_eth.to.clone(),
+        to: btc_to_eth.from.clone(),
+        ..btc_to_eth.clone()
+    };
+    let recent_quote = attempt_at(&btc_to_eth, &offered(&btc_to_eth, 1_000), now);
+    let recent_rejection = attempt_at(
+        &eth_to_btc,
+        &RFQResult::InvalidRequest("Amount below the minimum".to_string()),
+        now - Duration::minutes(5),
+    );
+    let old_rejection = attempt_at(
+        &btc_to_eth,
+        &RFQResult::MakerUnavailable("Still warming up".to_string()),
+        now - Duration::hours(3),
+    );
+    for attempt in [&recent_quote, &recent_rejection, &old_rejection] {
+        storage.store_quote_attempt(attempt).await.unwrap();
+    }
+
+    let listed = |since, pair, outcome, limit| {
+        let storage = &storage;
+        let args = QuotesArgs {
+            database_url: database_url.clone(),
+            since,
+            pair,
+            outcome,
+            limit,
+        };
+        async move {
+            storage
+                .quote_attempts(&args.filter(), args.limit)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|attempt| attempt.id)
+                .collect::<Vec<_>>()
+        }
+    };
+    let btc = ChainNetwork::primary(ChainType::Bitcoin);
+    let eth = ChainNetwork::primary(ChainType::Ethereum);
+
+    assert_eq!(
+        listed(None, None, None, 50).await,
+        vec![recent_quote.id, recent_rejection.id, old_rejection.id]
+    );
+    assert_eq!(
+        listed(Some(now - Duration::hours(1)), None, None, 50).await,
+        vec![recent_quote.id, recent_rejection.id]
+    );
+    assert_eq!(
+        listed(None, Some((btc, eth)), None, 50).await,
+        vec![recent_quote.id, old_rejection.id]
+    );
+    assert_eq!(
+        listed(None, Some((eth, btc)), None, 50).await,
+        vec![recent_rejection.id]
+    );
+    assert_eq!(
+        listed(None, None, Some(QuoteAttemptOutcome::MakerUnavailable), 50).await,
+        vec![old_rejection.id]
+    );
+    assert_eq!(
+        listed(
+            Some(now - Duration::hours(1)),
+            Some((btc, eth)),
+            Some(QuoteAttemptOutcome::MakerUnavailable),
+            50
+        )
+        .await,
+        Vec::<Uuid>::new()
+    );
+    assert_eq!(listed(None, None, None, 1).await, vec![recent_quote.id]);
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use alloy::primitives::U256;
+use alloy::signers::{local::PrivateKeySigner, SignerSync};
+use devnet::MultichainAccount;
+use otc_client::types::{refund_address_message, SetRefundAddressRequest};
+use otc_models::{Lot, QuoteMode, QuoteRequest};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use uuid::Uuid;
+
+use crate::utils::{SwapTestHarness, SwapTestOptions};
+
+/// A request setting `address` as the refund address of `swap_id`, signed by `account`
+fn signed_request(
+    account: &MultichainAccount,
+    swap_id: Uuid,
+    address: &str,
+) -> SetRefundAddressRequest {
+    let signer = PrivateKeySigner::from_bytes(&account.secret_bytes.into()).unwrap();
+    let signature = signer
+        .sign_message_sync(refund_address_message(swap_id, address).as_bytes())
+        .unwrap();
+    SetRefundAddressRequest {
+        user_refund_address: address.to_string(),
+        signature: signature.to_string(),
+    }
+}
+
+#[sqlx::test]
+async fn test_refund_address_can_be_set_until_the_deposit_confirms(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let mut harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+    let user_bitcoin_wallet = harness.user_bitcoin_wallet().await;
+    let refund_address = harness.user_account.bitcoin_wallet.address.to_string();
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(10_000_000), // 0.1 BTC
+        from: SwapTestHarness::bitcoin(),
+        to: harness.cbbtc(),
+    };
+    let user_destination_address = harness.user_account.ethereum_address.to_string();
+
+    // Set when the swap is created, it must be an address on the deposit chain
+    let (quote, quote_signature) = harness.request_signed_quote(&quote_request).await;
+    let mut swap_request =
+        harness.swap_request(quote, quote_signature, user_destination_address.clone());
+    swap_request.user_refund_address = Some(user_destination_address.clone());
+    let error = harness.try_create_swap(&swap_request).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+    swap_request.user_refund_address = Some(refund_address.clone());
+    let swap = harness.create_swap(&swap_request).await;
+    let created = harness.otc_client.get_swap(swap.swap_id).await.unwrap();
+    assert_eq!(created.user_refund_address, Some(refund_address.clone()));
+    assert!(!created.refund_address_required);
+
+    // Set later, only by the swap's user EVM account
+    let (quote, quote_signature) = harness.request_signed_quote(&quote_request).await;
+    let swap = harness
+        .create_swap(&harness.swap_request(quote, quote_signature, user_destination_address))
+        .await;
+    let unset = harness.otc_client.get_swap(swap.swap_id).await.unwrap();
+    assert_eq!(unset.user_refund_address, None);
+
+    let forged = signed_request(&harness.market_maker_account, swap.swap_id, &refund_address);
+    let error = harness
+        .otc_client
+        .set_refund_address(swap.swap_id, &forged)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+
+    // The signature covers the address, it can't be replayed for another one
+    let mut replayed = signed_request(&harness.user_account, swap.swap_id, &refund_address);
+    replayed.user_refund_address = swap.deposit_address.clone();
+    let error = harness
+        .otc_client
+        .set_refund_address(swap.swap_id, &replayed)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+
+    let request = signed_request(&harness.user_account, swap.swap_id, &refund_address);
+    let updated = harness
+        .otc_client
+        .set_refund_address(swap.swap_id, &request)
+        .await
+        .unwrap();
+    assert_eq!(updated.user_refund_address, Some(refund_address.clone()));
+
+    let error = harness
+        .otc_client
+        .set_refund_address(Uuid::new_v4(), &request)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+
+    // Too late once the market maker was asked to pay
+    user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: SwapTestHarness::bitcoin(),
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap();
+    harness.devnet.bitcoin.mine_blocks(6).await.unwrap();
+    harness.wait_settled(swap.swap_id).await;
+
+    let error = harness
+        .otc_client
+        .set_refund_address(swap.swap_id, &request)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::CONFLICT));
+
+    harness.shutdown().await;
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use alloy::primitives::U256;
+use bitcoincore_rpc_async::RpcApi;
+use market_maker::wallet::Wallet;
+use otc_models::{Lot, QuoteMode, QuoteRequest, SwapStatus};
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use uuid::Uuid;
+
+use crate::utils::{SwapTestHarness, SwapTestOptions, INTEGRATION_TEST_TIMEOUT_SECS};
+
+/// Confirmations the OTC server has recorded for the swap's user deposit
+async fn user_deposit_confirmations(pool: &PgPool, swap_id: Uuid) -> Option<u64> {
+    let confirmations: Option<serde_json::Value> =
+        sqlx::query_scalar("SELECT user_deposit_status->'confirmations' FROM swaps WHERE id = $1")
+            .bind(swap_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    confirmations.and_then(|value| value.as_u64())
+}
+
+/// The swap's events that carry a note, oldest first
+async fn noted_events(pool: &PgPool, swap_id: Uuid) -> Vec<(SwapStatus, SwapStatus, String)> {
+    sqlx::query_as(
+        "SELECT from_status, to_status, note FROM swap_events \
+         WHERE swap_id = $1 AND note IS NOT NULL ORDER BY id",
+    )
+    .bind(swap_id)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn test_reorged_user_deposit_rolls_swap_back(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let mut harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+    let user_bitcoin_wallet = harness.user_bitcoin_wallet().await;
+    let pool = PgPool::connect(&harness.otc_database_url).await.unwrap();
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(10_000_000), // 0.1 BTC
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        })
+        .await;
+    let swap_request = harness.swap_request(
+        quote,
+        quote_signature,
+        harness.user_account.ethereum_address.to_string(),
+    );
+    let swap = harness.create_swap(&swap_request).await;
+
+    let tx_hash = user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: SwapTestHarness::bitcoin(),
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap()
+        .tx_hash;
+
+    // One block is short of the two the swap needs, wait for the server to count it
+    let bitcoin = &harness.devnet.bitcoin;
+    bitcoin.mine_blocks(1).await.unwrap();
+    let deadline = Instant::now() + Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
+    while user_deposit_confirmations(&pool, swap.swap_id).await != Some(1) {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for the deposit's first confirmation"
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    // Orphan the block, which puts the deposit back in the mempool
+    let tip = bitcoin.rpc_client.get_best_block_hash().await.unwrap();
+    bitcoin.rpc_client.invalidate_block(&tip).await.unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
+    let events = loop {
+        let events = noted_events(&pool, swap.swap_id).await;
+        if !events.is_empty() {
+            break events;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for the swap to be rolled back"
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    };
+    assert_eq!(
+        events,
+        vec![(
+            SwapStatus::WaitingUserDepositConfirmed,
+            SwapStatus::WaitingUserDepositInitiated,
+            format!("Reorg dropped user deposit {tx_hash} after 1 confirmations"),
+        )]
+    );
+
+    // Mined again on the new chain, the swap goes through as usual
+    bitcoin.mine_blocks(6).await.unwrap();
+    harness.wait_settled(swap.swap_id).await;
+
+    harness.shutdown().await;
+}
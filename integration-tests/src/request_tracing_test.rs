@@ -0,0 +1,72 @@
+use alloy::primitives::U256;
+use otc_client::types::{ApiErrorCode, CreateSwapResponse, TRACE_ID_HEADER};
+use otc_models::{QuoteMode, QuoteRequest};
+use otc_protocols::rfq::RFQResult;
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+
+use crate::utils::{SwapTestHarness, SwapTestOptions};
+
+#[sqlx::test]
+async fn test_quote_trace_id_is_stored_on_the_swap(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+    let trace_id = "wallet-ui:quote-42";
+
+    let quote_response = harness
+        .rfq_client
+        .request_quote_with_trace_id(
+            &QuoteRequest {
+                mode: QuoteMode::ExactInput,
+                amount: U256::from(10_000_000), // 0.1 BTC
+                from: SwapTestHarness::bitcoin(),
+                to: harness.cbbtc(),
+            },
+            trace_id,
+        )
+        .await
+        .unwrap();
+    assert_eq!(quote_response.trace_id, trace_id);
+    let quote = match quote_response.quote {
+        Some(RFQResult::Success(quote)) => quote,
+        other => panic!("Quote should be a success, got {other:?}"),
+    };
+
+    // The swap request carries its own id, the quote's is the one kept
+    let mut swap_request = harness.swap_request(
+        quote.quote,
+        quote.signature,
+        harness.user_account.ethereum_address.to_string(),
+    );
+    swap_request.trace_id = Some(quote_response.trace_id);
+    let response = harness
+        .client
+        .post(harness.otc_url("/api/v1/swaps"))
+        .header(TRACE_ID_HEADER, "swap-request-1")
+        .json(&swap_request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[TRACE_ID_HEADER], "swap-request-1");
+    let swap: CreateSwapResponse = response.json().await.unwrap();
+
+    let pool = PgPool::connect(&harness.otc_database_url).await.unwrap();
+    let (stored_trace_id,): (Option<String>,) =
+        sqlx::query_as("SELECT trace_id FROM swaps WHERE id = $1")
+            .bind(swap.swap_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(stored_trace_id.as_deref(), Some(trace_id));
+
+    // Ids that aren't safe to log are refused rather than stored
+    swap_request.trace_id = Some("has space".to_string());
+    let error = harness.try_create_swap(&swap_request).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+    assert_eq!(error.code(), Some(ApiErrorCode::ValidationFailed));
+
+    harness.shutdown().await;
+}
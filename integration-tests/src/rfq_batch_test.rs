@@ -0,0 +1,238 @@
+use alloy::primitives::U256;
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use otc_client::RfqApiClient;
+use otc_models::{
+    ChainType, Currency, Lot, Quote, QuoteMode, QuoteRequest, TokenIdentifier, CBBTC_ADDRESS,
+};
+use otc_protocols::rfq::{
+    BatchedQuoteResponse, FeeSchedule, ProtocolMessage, QuoteWithFees, RFQRequest, RFQResponse,
+    RFQResult, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use tokio::task::JoinSet;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+};
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_rfq_server_test_args, wait_for_rfq_server_to_be_ready, SwapTestHarness,
+    SwapTestOptions, TEST_API_KEY, TEST_API_KEY_ID, TEST_MARKET_MAKER_ID,
+};
+
+#[sqlx::test]
+async fn test_quote_batch_covers_both_directions(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let options = SwapTestOptions {
+        token_indexer: false,
+        ..SwapTestOptions::default()
+    };
+    let harness = SwapTestHarness::launch(&connect_options, options).await;
+
+    // Both directions of the pair, each at several sizes
+    let mut requests = Vec::new();
+    for sats in [1_000_000u64, 5_000_000, 10_000_000] {
+        requests.push(QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(sats),
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        });
+        requests.push(QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(sats),
+            from: harness.cbbtc(),
+            to: SwapTestHarness::bitcoin(),
+        });
+    }
+
+    let batch = harness
+        .rfq_client
+        .request_quote_batch(requests.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(batch.results.len(), requests.len());
+    for (index, (result, request)) in batch.results.iter().zip(&requests).enumerate() {
+        assert_eq!(result.index, index);
+        assert!(result.error.is_none(), "{:?}", result.error);
+        let response = result.response.as_ref().unwrap();
+        assert_eq!(response.market_makers_contacted, 1);
+        match &response.quote {
+            Some(RFQResult::Success(quote)) => {
+                assert_eq!(quote.quote.from.currency.chain, request.from.chain);
+                assert_eq!(quote.quote.from.amount, request.amount);
+                assert!(quote.signature.is_some());
+            }
+            other => panic!("request {index} should be quoted, got {other:?}"),
+        }
+    }
+
+    harness.shutdown().await;
+}
+
+/// A quote paying `request.amount` back on the other side
+fn quote_for(request: &QuoteRequest) -> QuoteWithFees {
+    let now = Utc::now();
+    QuoteWithFees {
+        quote: Quote {
+            id: Uuid::new_v4(),
+            market_maker_id: TEST_MARKET_MAKER_ID.parse().unwrap(),
+            from: Lot {
+                currency: request.from.clone(),
+                amount: request.amount,
+            },
+            to: Lot {
+                currency: request.to.clone(),
+                amount: request.amount,
+            },
+            expires_at: now + chrono::Duration::minutes(5),
+            created_at: now,
+        },
+        fees: FeeSchedule {
+            network_fee_sats: 0,
+            liquidity_fee_sats: 0,
+            protocol_fee_sats: 0,
+        },
+        signature: None,
+    }
+}
+
+#[tokio::test]
+async fn test_quote_batch_is_one_round_trip_to_the_market_maker() {
+    let mut join_set = JoinSet::new();
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mut request = format!("ws://127.0.0.1:{rfq_port}/ws/mm")
+        .into_client_request()
+        .unwrap();
+    let headers = request.headers_mut();
+    headers.insert("x-api-key-id", TEST_API_KEY_ID.parse().unwrap());
+    headers.insert("x-api-key", TEST_API_KEY.parse().unwrap());
+    headers.insert(PROTOCOL_VERSION_HEADER, PROTOCOL_VERSION.parse().unwrap());
+    let (mut socket, _) = connect_async(request).await.unwrap();
+    let connected_frame = socket.next().await.unwrap().unwrap();
+    assert!(connected_frame.to_text().unwrap().contains("Connected"));
+
+    let bitcoin = Currency {
+        chain: ChainType::Bitcoin,
+        token: TokenIdentifier::Native,
+        decimals: 8,
+        chain_id: None,
+    };
+    let cbbtc = Currency {
+        chain: ChainType::Ethereum,
+        token: TokenIdentifier::Address(CBBTC_ADDRESS.to_string()),
+        decimals: 8,
+        chain_id: None,
+    };
+    let requests: Vec<QuoteRequest> = [1_000_000u64, 5_000_000, 10_000_000]
+        .into_iter()
+        .map(|sats| QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(sats),
+            from: bitcoin.clone(),
+            to: cbbtc.clone(),
+        })
+        .collect();
+    let rfq_client = RfqApiClient::new(format!("http://127.0.0.1:{rfq_port}")).unwrap();
+    let batch = tokio::spawn({
+        let requests = requests.clone();
+        async move { rfq_client.request_quote_batch(requests).await }
+    });
+
+    // Every request arrives in one message
+    let (sequence, batched) = loop {
+        let Some(Ok(Message::Text(text))) = socket.next().await else {
+            panic!("Socket closed before the batch arrived");
+        };
+        let message: ProtocolMessage<RFQRequest> = serde_json::from_str(&text).unwrap();
+        match message.payload {
+            RFQRequest::QuoteBatchRequested { requests, .. } => break (message.sequence, requests),
+            RFQRequest::QuoteRequested { .. } => panic!("Batch was split into single requests"),
+            _ => {}
+        }
+    };
+    assert_eq!(
+        batched.iter().map(|item| &item.request).collect::<Vec<_>>(),
+        requests.iter().collect::<Vec<_>>()
+    );
+
+    // and one message answers them all
+    let response = ProtocolMessage {
+        version: PROTOCOL_VERSION.to_string(),
+        sequence,
+        payload: RFQResponse::QuoteBatchResponse {
+            responses: batched
+                .iter()
+                .map(|item| BatchedQuoteResponse {
+                    request_id: item.request_id,
+                    quote: RFQResult::Success(quote_for(&item.request)),
+                })
+                .collect(),
+            timestamp: Utc::now(),
+        },
+        trace_id: None,
+    };
+    socket
+        .send(Message::Text(serde_json::to_string(&response).unwrap()))
+        .await
+        .unwrap();
+
+    let batch = batch.await.unwrap().unwrap();
+    assert_eq!(batch.results.len(), requests.len());
+    for result in &batch.results {
+        let response = result.response.as_ref().unwrap();
+        assert!(
+            matches!(response.quote, Some(RFQResult::Success(_))),
+            "request {} should be quoted, got {:?}",
+            result.index,
+            response.quote
+        );
+    }
+
+    join_set.abort_all();
+}
+
+#[sqlx::test]
+async fn test_quote_batch_size_is_bounded(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let options = SwapTestOptions {
+        token_indexer: false,
+        ..SwapTestOptions::default()
+    };
+    let harness = SwapTestHarness::launch(&connect_options, options).await;
+    let request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(1_000_000u64),
+        from: SwapTestHarness::bitcoin(),
+        to: harness.cbbtc(),
+    };
+
+    for requests in [Vec::new(), vec![request; 11]] {
+        let response = harness
+            .client
+            .post(harness.rfq_url("/api/v1/quotes/request-batch"))
+            .json(&serde_json::json!({ "requests": requests }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    harness.shutdown().await;
+}
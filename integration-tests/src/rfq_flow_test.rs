@@ -1,138 +1,37 @@
 use alloy::primitives::U256;
-use market_maker::run_market_maker;
-use otc_models::{ChainType, Currency, QuoteRequest, TokenIdentifier};
+use otc_models::{QuoteMode, QuoteRequest};
 use otc_protocols::rfq::RFQResult;
-use rfq_server::server::run_server as run_rfq_server;
 use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
 use std::time::Instant;
-use tokio::task::JoinSet;
 
-use crate::utils::{
-    build_mm_test_args, build_rfq_server_test_args, get_free_port,
-    wait_for_market_maker_to_connect_to_rfq_server, wait_for_rfq_server_to_be_ready,
-};
+use crate::utils::{Funding, SwapTestHarness, SwapTestOptions};
 
 #[sqlx::test]
 async fn test_rfq_flow(_: PoolOptions<sqlx::Postgres>, connect_options: PgConnectOptions) {
-    // Setup market maker account
-    let market_maker_account = devnet::MultichainAccount::new(0);
-    let devnet = devnet::RiftDevnet::builder()
-        .using_esplora(true)
-        .build()
-        .await
-        .unwrap()
-        .0;
-
-    // Don't fund the market maker - we want to test that it correctly rejects quotes
-    // when it has insufficient balance
-    println!("Market maker starting with 0 BTC balance");
-
-    let mut join_set = JoinSet::new();
-
-    // Get free ports for RFQ server and dummy OTC server port
-    let rfq_port = get_free_port().await;
-    let otc_port = get_free_port().await; // Not used but needed for MM args
-
-    tracing::info!("RFQ port: {}", rfq_port);
-    tracing::info!("OTC port: {}", otc_port);
-
-    // Start RFQ server
-    let rfq_args = build_rfq_server_test_args(rfq_port);
-    join_set.spawn(async move {
-        run_rfq_server(rfq_args)
-            .await
-            .expect("RFQ server should not crash");
-    });
-
-    // Wait for RFQ server to be ready
-    wait_for_rfq_server_to_be_ready(rfq_port).await;
-
-    // Start market maker
-    let mm_args = build_mm_test_args(
-        otc_port,
-        rfq_port,
-        &market_maker_account,
-        &devnet,
-        &connect_options,
-    )
-    .await;
-
-    devnet
-        .ethereum
-        .fund_eth_address(
-            market_maker_account.ethereum_address,
-            U256::from(100_000_000_000_000_000_000i128),
-        )
-        .await
-        .unwrap();
-
-    devnet
-        .ethereum
-        .mint_cbbtc(
-            market_maker_account.ethereum_address,
-            U256::from(100_000_000), // 1 bitcoin in sats (cbbtc has 8 decimals)
-        )
-        .await
-        .unwrap();
-
-    join_set.spawn(async move {
-        run_market_maker(mm_args)
-            .await
-            .expect("Market maker should not crash");
-    });
-
-    // Wait for market maker to connect to RFQ server
-    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
-
-    // Test: Request for any amount when MM has no balance - should fail
-    let test_amount = U256::from(1_000_000_000); // 10 BTC in sats
-
-    // Send a quote request (that will fail)
-    let quote_request = QuoteRequest {
-        mode: otc_models::QuoteMode::ExactOutput,
-        amount: test_amount,
-        to: Currency {
-            chain: ChainType::Ethereum,
-            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
-            decimals: 8,
-        },
-        from: Currency {
-            chain: ChainType::Bitcoin,
-            token: TokenIdentifier::Native,
-            decimals: 8,
+    // The market maker only holds 1 cbBTC, so it has to turn down larger quotes
+    let options = SwapTestOptions {
+        market_maker: Funding {
+            bitcoin_sats: 0,
+            cbbtc_sats: 100_000_000,
         },
+        user: Funding::default(),
+        token_indexer: false,
+        ..SwapTestOptions::default()
     };
+    let harness = SwapTestHarness::launch(&connect_options, options).await;
 
-    let quote_request_url = format!("http://127.0.0.1:{rfq_port}/api/v1/quotes/request");
-    let client = reqwest::Client::new();
+    let quote_request = |amount: u64| QuoteRequest {
+        mode: QuoteMode::ExactOutput,
+        amount: U256::from(amount),
+        from: SwapTestHarness::bitcoin(),
+        to: harness.cbbtc(),
+    };
 
-    // Start timing the quote request
+    // 10 cbBTC is far more than the market maker holds
     let start_time = Instant::now();
-
-    let response = client
-        .post(&quote_request_url)
-        .json(&quote_request)
-        .send()
-        .await
-        .expect("Should be able to send quote request");
-
-    // Calculate and record the latency
-    let latency = start_time.elapsed();
+    let quote_response = harness.request_quote(&quote_request(1_000_000_000)).await;
+    tracing::info!("Quote request latency: {:?}", start_time.elapsed());
 
-    assert_eq!(
-        response.status(),
-        200,
-        "Quote request should succeed at HTTP level"
-    );
-
-    let quote_response: rfq_server::server::QuoteResponse = response
-        .json()
-        .await
-        .expect("Should be able to parse quote response");
-
-    println!("quote_response: {quote_response:?}");
-
-    // Verify the response
     assert_eq!(
         quote_response.total_quotes_received, 1,
         "Should receive 1 response from market maker"
@@ -141,131 +40,37 @@ async fn test_rfq_flow(_: PoolOptions<sqlx::Postgres>, connect_options: PgConnec
         quote_response.market_makers_contacted, 1,
         "Should contact 1 market maker"
     );
-
-    // Verify the quote is MakerUnavailable due to insufficient balance
-    let quote = &quote_response.quote;
-    println!("Quote response for 0.1 BTC with 0 balance: {quote:?}");
-
-    assert!(quote.is_some(), "Quote response should be present");
-    match quote.as_ref().unwrap() {
-        RFQResult::MakerUnavailable(reason) => {
-            assert!(
-                reason.contains("Insufficient balance"),
-                "Should indicate insufficient balance, got: {reason}"
-            );
-            println!("✓ Correctly rejected quote due to insufficient balance");
-        }
-        RFQResult::Success(_) => {
-            panic!("Quote should not succeed when market maker has insufficient balance");
-        }
-        RFQResult::InvalidRequest(reason) => {
-            panic!("Quote should not be invalid request, got: {reason}");
+    // The only market maker answered, so the 5s timeout isn't waited out
+    assert_eq!(quote_response.timing.market_makers_responded, 1);
+    assert!(!quote_response.timing.deadline_extended);
+    assert!(quote_response.timing.elapsed_ms < quote_response.timing.deadline_ms);
+    assert_insufficient_balance(quote_response.quote);
+
+    // All of its balance leaves nothing for fees
+    let quote_response = harness.request_quote(&quote_request(100_000_000)).await;
+    assert_insufficient_balance(quote_response.quote);
+
+    // Half of it can be filled
+    let quote_response = harness.request_quote(&quote_request(50_000_000)).await;
+    match quote_response.quote {
+        Some(RFQResult::Success(quote)) => {
+            assert_eq!(quote.quote.to.amount, U256::from(50_000_000));
+            // The market maker's quotes live for 5 minutes
+            let expires_in = quote_response.expires_in_seconds.unwrap();
+            assert!(expires_in > 200 && expires_in <= 300, "{expires_in}");
         }
+        other => panic!("Quote should be a success, got {other:?}"),
     }
 
-    // Output the latency to get the response
-    tracing::info!("Quote request latency: {latency:?}");
-
-    // Now try for a quote that is barely too much
-    let test_amount = U256::from(100_000_000); // 1 BTC in sats
-    let quote_request = QuoteRequest {
-        mode: otc_models::QuoteMode::ExactOutput,
-        amount: test_amount,
-        to: Currency {
-            chain: ChainType::Ethereum,
-            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
-            decimals: 8,
-        },
-        from: Currency {
-            chain: ChainType::Bitcoin,
-            token: TokenIdentifier::Native,
-            decimals: 8,
-        },
-    };
-
-    let quote_request_url = format!("http://127.0.0.1:{rfq_port}/api/v1/quotes/request");
-    let client = reqwest::Client::new();
-
-    let response = client
-        .post(&quote_request_url)
-        .json(&quote_request)
-        .send()
-        .await
-        .expect("Should be able to send quote request");
-
-    let quote_response: rfq_server::server::QuoteResponse = response
-        .json()
-        .await
-        .expect("Should be able to parse quote response");
-
-    // Verify the quote is Success
-    let quote = &quote_response.quote;
-    println!("Quote response for {test_amount} BTC with 1 BTC balance: {quote:?}");
-
-    assert!(quote.is_some(), "Quote response should be present");
-    match quote.as_ref().unwrap() {
-        RFQResult::Success(quote) => {
-            panic!("Quote response for {test_amount} BTC with 1 BTC balance: {quote:?}");
-        }
-        RFQResult::MakerUnavailable(reason) => {
-            assert!(
-                reason.contains("Insufficient balance"),
-                "Should indicate insufficient balance, got: {reason}"
-            );
-            println!("✓ Correctly rejected quote due to insufficient balance");
-        }
-        RFQResult::InvalidRequest(reason) => {
-            panic!("Quote should not be invalid request, got: {reason}");
-        }
-    }
-
-    // finally try for an amount that is valid
-    let test_amount = U256::from(50_000_000); // 1 BTC in sats
-    let quote_request = QuoteRequest {
-        mode: otc_models::QuoteMode::ExactOutput,
-        amount: test_amount,
-        to: Currency {
-            chain: ChainType::Ethereum,
-            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
-            decimals: 8,
-        },
-        from: Currency {
-            chain: ChainType::Bitcoin,
-            token: TokenIdentifier::Native,
-            decimals: 8,
-        },
-    };
-
-    let quote_request_url = format!("http://127.0.0.1:{rfq_port}/api/v1/quotes/request");
-    let client = reqwest::Client::new();
-
-    let response = client
-        .post(&quote_request_url)
-        .json(&quote_request)
-        .send()
-        .await
-        .expect("Should be able to send quote request");
-
-    let quote_response: rfq_server::server::QuoteResponse = response
-        .json()
-        .await
-        .expect("Should be able to parse quote response");
-
-    let quote = &quote_response.quote;
-    println!("Quote response for {test_amount} BTC with 1 BTC balance: {quote:?}");
+    harness.shutdown().await;
+}
 
-    assert!(quote.is_some(), "Quote response should be present");
-    match quote.as_ref().unwrap() {
-        RFQResult::Success(quote) => {
-            println!("Correctly received success quote: {quote:?}");
-        }
-        RFQResult::MakerUnavailable(reason) => {
-            panic!("Quote should not be maker unavailable, got: {reason}");
-        }
-        RFQResult::InvalidRequest(reason) => {
-            panic!("Quote should not be invalid request, got: {reason}");
-        }
+fn assert_insufficient_balance<T: std::fmt::Debug>(quote: Option<RFQResult<T>>) {
+    match quote {
+        Some(RFQResult::MakerUnavailable(reason)) => assert!(
+            reason.contains("Insufficient balance"),
+            "Should indicate insufficient balance, got: {reason}"
+        ),
+        other => panic!("Quote should be turned down for insufficient balance, got {other:?}"),
     }
-
-    println!("RFQ flow test with balance check completed successfully!");
 }
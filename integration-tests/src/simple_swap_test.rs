@@ -1,483 +1,535 @@
-use alloy::primitives::TxHash;
-use alloy::providers::ext::AnvilApi;
-use alloy::{primitives::U256, providers::Provider};
+use std::time::{Duration, Instant};
 
-use bitcoincore_rpc_async::RpcApi;
+use alloy::primitives::U256;
+use alloy::providers::ext::AnvilApi;
 use devnet::bitcoin_devnet::MiningMode;
-use devnet::{MultichainAccount, RiftDevnet};
-use evm_token_indexer_client::TokenIndexerClient;
-use market_maker::evm_wallet::EVMWallet;
 use market_maker::wallet::Wallet;
-use market_maker::{bitcoin_wallet::BitcoinWallet, run_market_maker, MarketMakerArgs};
-use otc_models::{ChainType, Currency, Lot, Quote, QuoteMode, QuoteRequest, TokenIdentifier};
-use otc_protocols::rfq::RFQResult;
-use otc_server::api::SwapResponse;
-use otc_server::{
-    api::{CreateSwapRequest, CreateSwapResponse},
-    server::run_server,
-    OtcServerArgs,
+use otc_client::{
+    types::{ApiErrorCode, CreateSwapRequest, SwapLookup, SwapReceipt, SwapResponse},
+    OtcApiClient,
 };
+use otc_models::{ChainType, FillUsage, Lot, QuoteMode, QuoteRequest, SwapStatus, TxHash};
+use otc_server::{server::run_server_with_listener, ServerMode};
 use reqwest::StatusCode;
-use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
-use std::time::Duration;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
 use tokio::task::JoinSet;
 use tracing::info;
+use uuid::Uuid;
 
 use crate::utils::{
-    build_bitcoin_wallet_descriptor, build_mm_test_args, build_otc_server_test_args,
-    build_rfq_server_test_args, build_test_user_ethereum_wallet, build_tmp_bitcoin_wallet_db_file,
-    get_free_port, wait_for_market_maker_to_connect_to_rfq_server, wait_for_otc_server_to_be_ready,
-    wait_for_rfq_server_to_be_ready, wait_for_swap_to_be_settled, PgConnectOptionsExt,
-    TEST_MARKET_MAKER_ID,
+    bind_free_port, build_otc_replica_test_args, wait_for_otc_server_to_be_ready, SwapTestHarness,
+    SwapTestOptions, TestContext, INTEGRATION_TEST_TIMEOUT_SECS, TEST_ADMIN_API_KEY,
 };
 
-#[sqlx::test]
-async fn test_swap_from_bitcoin_to_ethereum(
-    _: PoolOptions<sqlx::Postgres>,
-    connect_options: PgConnectOptions,
-) {
-    let market_maker_account = MultichainAccount::new(1);
-    let user_account = MultichainAccount::new(2);
-
-    let devnet = RiftDevnet::builder()
-        .using_token_indexer(connect_options.to_database_url())
-        .using_esplora(true)
-        .build()
-        .await
-        .unwrap()
-        .0;
-
-    let mut wallet_join_set = JoinSet::new();
+/// Reference the user tags their swaps with
+const EXTERNAL_REFERENCE: &str = "INV-2024/0042 #1";
 
-    let user_bitcoin_wallet = BitcoinWallet::new(
-        &build_tmp_bitcoin_wallet_db_file(),
-        &build_bitcoin_wallet_descriptor(&user_account.bitcoin_wallet.private_key),
-        bitcoin::Network::Regtest,
-        &devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
-        &mut wallet_join_set,
+/// Status the market maker last heard for `swap_id`, `None` before any update
+async fn mm_swap_status(pool: &PgPool, swap_id: Uuid) -> Option<SwapStatus> {
+    sqlx::query_scalar::<_, serde_json::Value>(
+        "SELECT swap_status FROM mm_quotes WHERE swap_id = $1",
     )
+    .bind(swap_id)
+    .fetch_optional(pool)
     .await
-    .unwrap();
+    .unwrap()
+    .map(|status| serde_json::from_value(status).unwrap())
+}
 
-    // fund all accounts
+/// Wait for the market maker to hear that `swap_id` reached `status`
+async fn wait_for_mm_swap_status(pool: &PgPool, swap_id: Uuid, status: SwapStatus) {
+    let deadline = Instant::now() + Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
+    while mm_swap_status(pool, swap_id).await != Some(status) {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for the market maker to hear the swap is {status:?}"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
 
-    devnet
-        .bitcoin
-        .deal_bitcoin(
-            &user_account.bitcoin_wallet.address,
-            &bitcoin::Amount::from_sat(500_000_000), // 5 BTC
-        )
-        .await
-        .unwrap();
-    devnet
-        .ethereum
-        .fund_eth_address(
-            market_maker_account.ethereum_address,
-            U256::from(100_000_000_000_000_000_000i128),
-        )
+/// Swap status updates the OTC server has sent to market makers so far
+async fn status_updates_sent(client: &reqwest::Client, otc_port: u16) -> u64 {
+    let metrics = client
+        .get(format!("http://localhost:{otc_port}/metrics"))
+        .send()
         .await
-        .unwrap();
-
-    devnet
-        .ethereum
-        .mint_cbbtc(
-            market_maker_account.ethereum_address,
-            U256::from(9_000_000_000i128), // 90 cbbtc
-        )
+        .unwrap()
+        .text()
         .await
         .unwrap();
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("otc_mm_status_updates_sent_total "))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
 
-    let mut service_join_set = JoinSet::new();
-
-    let otc_port = get_free_port().await;
-    let otc_args = build_otc_server_test_args(otc_port, &devnet, &connect_options).await;
-
-    service_join_set.spawn(async move {
-        run_server(otc_args)
-            .await
-            .expect("OTC server should not crash");
-    });
+/// Check that every transition of a settled swap was recorded and that its receipt
+/// reflects them
+async fn assert_settled_swap_receipt(
+    client: &reqwest::Client,
+    otc_client: &OtcApiClient,
+    otc_port: u16,
+    otc_database_url: &str,
+    swap_id: Uuid,
+    user_deposit_tx_hash: &TxHash,
+) {
+    let pool = PgPool::connect(otc_database_url).await.unwrap();
+    let events: Vec<(Option<SwapStatus>, SwapStatus)> = sqlx::query_as(
+        "SELECT from_status, to_status FROM swap_events WHERE swap_id = $1 ORDER BY id",
+    )
+    .bind(swap_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    let expected = vec![
+        (None, SwapStatus::WaitingUserDepositInitiated),
+        (
+            Some(SwapStatus::WaitingUserDepositInitiated),
+            SwapStatus::WaitingUserDepositConfirmed,
+        ),
+        (
+            Some(SwapStatus::WaitingUserDepositConfirmed),
+            SwapStatus::WaitingMMDepositInitiated,
+        ),
+        (
+            Some(SwapStatus::WaitingMMDepositInitiated),
+            SwapStatus::WaitingMMDepositConfirmed,
+        ),
+        (
+            Some(SwapStatus::WaitingMMDepositConfirmed),
+            SwapStatus::Settled,
+        ),
+    ];
+    assert_eq!(events, expected, "Every transition should produce an event");
+
+    // The key release lands just after settling, the receipt doesn't change after it
+    let deadline = Instant::now() + Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
+    while sqlx::query_scalar::<_, Option<chrono::DateTime<chrono::Utc>>>(
+        "SELECT mm_private_key_sent_at FROM swaps WHERE id = $1",
+    )
+    .bind(swap_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap()
+    .is_none()
+    {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for the deposit key to be released"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
 
-    tokio::select! {
-        _ = wait_for_otc_server_to_be_ready(otc_port) => {
-            info!("OTC server is ready");
-        }
-        _ = service_join_set.join_next() => {
-            panic!("OTC server crashed");
-        }
-        _ = wallet_join_set.join_next() => {
-            panic!("Bitcoin wallet crashed");
+    let receipt_url = format!("http://localhost:{otc_port}/api/v1/swaps/{swap_id}/receipt");
+    let response = client.get(&receipt_url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let receipt_body = response.text().await.unwrap();
+    let receipt: SwapReceipt = serde_json::from_str(&receipt_body).unwrap();
+
+    assert_eq!(receipt.swap_id, swap_id);
+    assert_eq!(receipt.status, SwapStatus::Settled);
+    assert_eq!(
+        receipt.external_reference.as_deref(),
+        Some(EXTERNAL_REFERENCE)
+    );
+    assert_eq!(
+        receipt.user_deposit.tx_hash,
+        user_deposit_tx_hash.to_string()
+    );
+    assert!(!receipt.mm_deposit.tx_hash.is_empty());
+    assert!(receipt.user_deposit.confirmations >= receipt.user_deposit.required_confirmations);
+    assert!(receipt.mm_deposit.confirmations >= receipt.mm_deposit.required_confirmations);
+    let fill_cost = receipt
+        .fees
+        .mm_fill_cost
+        .as_ref()
+        .expect("the market maker should report what its fill cost");
+    match (&fill_cost.usage, receipt.mm_deposit.chain) {
+        (
+            FillUsage::Bitcoin {
+                sat_per_vb,
+                vsize,
+                inputs,
+                ..
+            },
+            ChainType::Bitcoin,
+        ) => {
+            assert!(*sat_per_vb > 0.0 && *vsize > 0 && *inputs > 0);
+            assert!(fill_cost.fee_in_lot_currency.is_some());
         }
+        (
+            FillUsage::Ethereum {
+                effective_gas_price,
+                gas_used,
+            },
+            ChainType::Ethereum,
+        ) => assert!(*effective_gas_price > U256::ZERO && *gas_used > 0),
+        (usage, chain) => panic!("{usage:?} was reported for a fill on {chain:?}"),
     }
+    let transitions: Vec<_> = receipt
+        .transitions
+        .iter()
+        .map(|t| (t.from_status, t.to_status))
+        .collect();
+    assert_eq!(transitions, expected);
+    assert_eq!(
+        receipt.settled_at,
+        receipt.transitions.last().unwrap().occurred_at
+    );
 
-    let rfq_port = get_free_port().await;
-    let rfq_args = build_rfq_server_test_args(rfq_port);
-    service_join_set.spawn(async move {
-        rfq_server::server::run_server(rfq_args)
-            .await
-            .expect("RFQ server should not crash");
-    });
-
-    wait_for_rfq_server_to_be_ready(rfq_port).await;
-
-    let mm_args = build_mm_test_args(
-        otc_port,
-        rfq_port,
-        &market_maker_account,
-        &devnet,
-        &connect_options,
-    )
-    .await;
-    service_join_set.spawn(async move {
-        run_market_maker(mm_args)
-            .await
-            .expect("Market maker should not crash");
-    });
+    // The receipt is deterministic once the key is released
+    let again = otc_client.get_swap_receipt(swap_id).await.unwrap();
+    assert_eq!(serde_json::to_string(&again).unwrap(), receipt_body);
+}
 
-    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+/// Check that the settled swap can be looked up by its user deposit tx hash and
+/// deposit address, and by its external reference only with the admin key
+async fn assert_swap_lookup(
+    client: &reqwest::Client,
+    otc_client: &OtcApiClient,
+    otc_port: u16,
+    swap_id: Uuid,
+    deposit_address: &str,
+    user_deposit_tx_hash: &TxHash,
+) {
+    for lookup in [
+        SwapLookup::TxHash(user_deposit_tx_hash.clone()),
+        // Hashes match in either chain's form and any case
+        SwapLookup::TxHash(user_deposit_tx_hash.digits().parse().unwrap()),
+        SwapLookup::TxHash(
+            format!("0x{}", user_deposit_tx_hash.digits().to_uppercase())
+                .parse()
+                .unwrap(),
+        ),
+        SwapLookup::DepositAddress(deposit_address.to_string()),
+    ] {
+        let swaps = otc_client.list_swaps(lookup).await.unwrap();
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].id, swap_id);
+        assert_eq!(swaps[0].status, format!("{:?}", SwapStatus::Settled));
+        assert_eq!(
+            swaps[0].external_reference.as_deref(),
+            Some(EXTERNAL_REFERENCE)
+        );
+    }
 
-    devnet
-        .bitcoin
-        .wait_for_esplora_sync(Duration::from_secs(30))
-        .await
-        .unwrap();
-    // at this point, the user should have a confirmed BTC balance
-    // and our market maker should have plenty of cbbtc to fill their order
-
-    let client = reqwest::Client::new();
-
-    // Request a quote from the RFQ server
-    let quote_request = QuoteRequest {
-        mode: QuoteMode::ExactInput,
-        amount: U256::from(10_000_000), // 0.1 BTC
-        from: Currency {
-            chain: ChainType::Bitcoin,
-            token: TokenIdentifier::Native,
-            decimals: 8,
-        },
-        to: Currency {
-            chain: ChainType::Ethereum,
-            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
-            decimals: 8,
-        },
+    let by_reference = |reference: &str| {
+        client
+            .get(format!("http://localhost:{otc_port}/admin/swaps"))
+            .query(&[("external_reference", reference)])
     };
-
-    let quote_response = client
-        .post(format!("http://localhost:{rfq_port}/api/v1/quotes/request"))
-        .json(&quote_request)
+    let swaps: Vec<SwapResponse> = by_reference(EXTERNAL_REFERENCE)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
         .send()
         .await
-        .unwrap();
-
-    assert_eq!(quote_response.status(), 200, "Quote request should succeed");
-
-    let quote_response: rfq_server::server::QuoteResponse = quote_response
+        .unwrap()
         .json()
         .await
-        .expect("Should be able to parse quote response");
-
-    let quote = quote_response.quote;
-    info!("Received quote: {:?}", quote);
-
-    assert!(quote.is_some(), "Quote should be present");
-    let quote = match quote.as_ref().unwrap() {
-        RFQResult::Success(quote) => quote.quote.clone(),
-        _ => panic!("Quote should be a success"),
-    };
-
-    // create a swap request
-    let swap_request = CreateSwapRequest {
-        quote,
-        user_destination_address: user_account.ethereum_address.to_string(),
-        user_evm_account_address: user_account.ethereum_address,
-    };
-
-    let response = client
-        .post(format!("http://localhost:{otc_port}/api/v1/swaps"))
-        .json(&swap_request)
-        .send()
-        .await
-        .unwrap();
-
-    let response_status = response.status();
-    let response_json = match response_status {
-        StatusCode::OK => {
-            let response_json: CreateSwapResponse = response.json().await.unwrap();
-            response_json
-        }
-        _ => {
-            let response_text = response.text().await;
-            panic!(
-                "Swap request should be successful but got {response_status:#?} {response_text:#?}"
-            );
-            unreachable!()
-        }
-    };
-    let tx_hash = user_bitcoin_wallet
-        .create_payment(
-            &Lot {
-                currency: Currency {
-                    chain: ChainType::Bitcoin,
-                    token: TokenIdentifier::Native,
-                    decimals: response_json.decimals,
-                },
-                amount: response_json.expected_amount,
-            },
-            &response_json.deposit_address,
-            None,
-        )
-        .await
         .unwrap();
-
-    info!(
-        "Broadcasting transaction from user wallet to deposit address: {}",
-        tx_hash
+    assert_eq!(
+        swaps.iter().map(|swap| swap.id).collect::<Vec<_>>(),
+        vec![swap_id]
     );
-    devnet.bitcoin.mine_blocks(6).await.unwrap();
-    info!("Mined block");
-
-    let get_tx_status = devnet
-        .bitcoin
-        .rpc_client
-        .get_raw_transaction_verbose(&tx_hash.parse::<bitcoin::Txid>().unwrap())
-        .await
-        .unwrap();
-
-    info!("Tx status: {:#?}", get_tx_status);
-    wait_for_swap_to_be_settled(otc_port, response_json.swap_id).await;
-
-    drop(devnet);
-    tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
-}
-
-#[sqlx::test]
-async fn test_swap_from_ethereum_to_bitcoin(
-    _: PoolOptions<sqlx::Postgres>,
-    connect_options: PgConnectOptions,
-) {
-    let market_maker_account = MultichainAccount::new(1);
-    let user_account = MultichainAccount::new(2);
-
-    let devnet = RiftDevnet::builder()
-        .using_token_indexer(connect_options.to_database_url())
-        .bitcoin_mining_mode(MiningMode::Interval(2))
-        .using_esplora(true)
-        .build()
+    // References match exactly
+    let swaps: Vec<SwapResponse> = by_reference(&EXTERNAL_REFERENCE.to_lowercase())
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .send()
         .await
         .unwrap()
-        .0;
-
-    let (mut wallet_join_set, user_ethereum_wallet) =
-        build_test_user_ethereum_wallet(&devnet, &user_account).await;
-
-    // fund all accounts
-    devnet
-        .bitcoin
-        .deal_bitcoin(
-            &market_maker_account.bitcoin_wallet.address,
-            &bitcoin::Amount::from_sat(500_000_000), // 5 BTC
-        )
-        .await
-        .unwrap();
-
-    devnet
-        .ethereum
-        .fund_eth_address(
-            user_account.ethereum_address,
-            U256::from(100_000_000_000_000_000_000i128),
-        )
+        .json()
         .await
         .unwrap();
-
-    devnet
-        .ethereum
-        .mint_cbbtc(
-            user_account.ethereum_address,
-            U256::from(9_000_000_000i128), // 90 cbbtc
-        )
+    assert!(swaps.is_empty());
+    // and are guessable, so they aren't served without the admin key
+    let response = by_reference(EXTERNAL_REFERENCE).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client
+        .get(format!("http://localhost:{otc_port}/api/v1/swaps/lookup"))
+        .query(&[("external_reference", EXTERNAL_REFERENCE)])
+        .send()
         .await
         .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
-    devnet
-        .ethereum
-        .fund_eth_address(
-            market_maker_account.ethereum_address,
-            U256::from(100_000_000_000_000_000_000i128),
-        )
+    // No match is an empty list rather than a 404
+    let swap = otc_client
+        .lookup_swap(SwapLookup::TxHash("00".repeat(32).parse().unwrap()))
         .await
         .unwrap();
+    assert!(swap.is_none());
 
-    user_ethereum_wallet
-        .ensure_inf_approval_on_disperse(devnet.ethereum.cbbtc_contract.address())
+    let response = client
+        .get(format!("http://localhost:{otc_port}/api/v1/swaps/lookup"))
+        .send()
         .await
         .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
 
-    let mut service_join_set = JoinSet::new();
-
-    let otc_port = get_free_port().await;
-    let otc_args = build_otc_server_test_args(otc_port, &devnet, &connect_options).await;
-
+/// Check that an API-only replica on the full node's database serves the swap the
+/// full node created, and refuses to create swaps itself
+async fn assert_replica_serves_swap(
+    client: &reqwest::Client,
+    context: &TestContext,
+    service_join_set: &mut JoinSet<()>,
+    otc_port: u16,
+    otc_database_url: &str,
+    swap_id: Uuid,
+    swap_request: &CreateSwapRequest,
+) {
+    let (replica_listener, replica_port) = bind_free_port().await;
+    let replica_args = build_otc_replica_test_args(context, replica_port, otc_database_url);
+    assert_eq!(replica_args.mode, ServerMode::ApiOnly);
     service_join_set.spawn(async move {
-        otc_server::server::run_server(otc_args)
+        run_server_with_listener(replica_args, replica_listener)
             .await
-            .expect("OTC server should not crash");
+            .expect("OTC replica should not crash");
     });
-
-    tokio::select! {
-        _ = wait_for_otc_server_to_be_ready(otc_port) => {
-            info!("OTC server is ready");
+    wait_for_otc_server_to_be_ready(replica_port).await;
+
+    let get_swap = |port: u16| {
+        let request = client.get(format!("http://localhost:{port}/api/v1/swaps/{swap_id}"));
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.json::<serde_json::Value>().await.unwrap()
         }
-        _ = service_join_set.join_next() => {
-            panic!("OTC server crashed");
-        }
-        _ = wallet_join_set.join_next() => {
-            panic!("Bitcoin wallet crashed");
-        }
-    }
-
-    let rfq_port = get_free_port().await;
-    let rfq_args = build_rfq_server_test_args(rfq_port);
-    service_join_set.spawn(async move {
-        rfq_server::server::run_server(rfq_args)
-            .await
-            .expect("RFQ server should not crash");
-    });
-
-    wait_for_rfq_server_to_be_ready(rfq_port).await;
-
-    let mm_args = build_mm_test_args(
-        otc_port,
-        rfq_port,
-        &market_maker_account,
-        &devnet,
-        &connect_options,
-    )
-    .await;
-    service_join_set.spawn(async move {
-        run_market_maker(mm_args)
-            .await
-            .expect("Market maker should not crash");
-    });
-
-    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    };
+    assert_eq!(get_swap(replica_port).await, get_swap(otc_port).await);
 
-    devnet
-        .bitcoin
-        .wait_for_esplora_sync(Duration::from_secs(30))
+    let response = client
+        .get(format!("http://localhost:{replica_port}/health"))
+        .send()
         .await
         .unwrap();
-    // at this point, the user should have a confirmed BTC balance
-    // and our market maker should have plenty of cbbtc to fill their order
-
-    let client = reqwest::Client::new();
-
-    // Request a quote from the RFQ server
-    let quote_request = QuoteRequest {
-        mode: QuoteMode::ExactInput,
-        amount: U256::from(100_000_000i128), // 1 cbbtc
-        from: Currency {
-            chain: ChainType::Ethereum,
-            token: TokenIdentifier::Address(devnet.ethereum.cbbtc_contract.address().to_string()),
-            decimals: 8,
-        },
-        to: Currency {
-            chain: ChainType::Bitcoin,
-            token: TokenIdentifier::Native,
-            decimals: 8,
-        },
-    };
+    assert_eq!(response.status(), StatusCode::OK);
 
-    let quote_response = client
-        .post(format!("http://localhost:{rfq_port}/api/v1/quotes/request"))
-        .json(&quote_request)
+    let replica = OtcApiClient::new(format!("http://localhost:{replica_port}")).unwrap();
+    let error = replica.create_swap(swap_request).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+
+    // Market makers only connect to the full node
+    let response = client
+        .get(format!("http://localhost:{replica_port}/ws/mm"))
         .send()
         .await
         .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
 
-    assert_eq!(quote_response.status(), 200, "Quote request should succeed");
-
-    let quote_response: rfq_server::server::QuoteResponse = quote_response
-        .json()
+/// Bitcoin deposit settled in cbBTC, with the OTC server reading Bitcoin
+/// through Core RPC or esplora alone
+async fn swap_from_bitcoin_to_ethereum(connect_options: PgConnectOptions, bitcoin_core_rpc: bool) {
+    let options = SwapTestOptions {
+        bitcoin_core_rpc,
+        ..SwapTestOptions::default()
+    };
+    let mut harness = SwapTestHarness::launch(&connect_options, options).await;
+    let user_bitcoin_wallet = harness.user_bitcoin_wallet().await;
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(10_000_000), // 0.1 BTC
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        })
+        .await;
+    assert!(
+        quote_signature.is_some(),
+        "Quote should be signed by the RFQ server"
+    );
+    let user_destination_address = harness.user_account.ethereum_address.to_string();
+
+    // a quote with a tampered amount must be rejected before reaching the MM
+    let mut tampered_quote = quote.clone();
+    tampered_quote.to.amount *= U256::from(2);
+    let error = harness
+        .try_create_swap(&harness.swap_request(
+            tampered_quote,
+            quote_signature.clone(),
+            user_destination_address.clone(),
+        ))
         .await
-        .expect("Should be able to parse quote response");
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+    assert_eq!(error.code(), Some(ApiErrorCode::QuoteSignatureInvalid));
+
+    let mut swap_request = harness.swap_request(quote, quote_signature, user_destination_address);
+    swap_request.external_reference = Some(EXTERNAL_REFERENCE.to_string());
+    let swap = harness.create_swap(&swap_request).await;
+
+    // No receipt until the swap settles
+    let error = harness
+        .otc_client
+        .get_swap_receipt(swap.swap_id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::CONFLICT));
 
-    let quote = quote_response.quote;
-    info!("Received quote: {:?}", quote);
+    let tx_hash = user_bitcoin_wallet
+        .create_payment(
+            &Lot {
+                currency: SwapTestHarness::bitcoin(),
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap()
+        .tx_hash;
+    info!("Paid the deposit address with {}", tx_hash);
+
+    // The market maker hears of the deposit before it confirms, then of each
+    // later status
+    let mm_pool = PgPool::connect(&harness.mm_database_url).await.unwrap();
+    wait_for_mm_swap_status(
+        &mm_pool,
+        swap.swap_id,
+        SwapStatus::WaitingUserDepositConfirmed,
+    )
+    .await;
+    harness.devnet.bitcoin.mine_blocks(6).await.unwrap();
+
+    harness.wait_settled(swap.swap_id).await;
+    wait_for_mm_swap_status(&mm_pool, swap.swap_id, SwapStatus::Settled).await;
+    // Deposit detected and confirmed, MM deposit detected, settled
+    assert!(status_updates_sent(&harness.client, harness.otc_port).await >= 4);
+    assert_settled_swap_receipt(
+        &harness.client,
+        &harness.otc_client,
+        harness.otc_port,
+        &harness.otc_database_url,
+        swap.swap_id,
+        &tx_hash,
+    )
+    .await;
+    assert_swap_lookup(
+        &harness.client,
+        &harness.otc_client,
+        harness.otc_port,
+        swap.swap_id,
+        &swap.deposit_address,
+        &tx_hash,
+    )
+    .await;
+    assert_replica_serves_swap(
+        &harness.client,
+        &harness.context,
+        &mut harness.service_join_set,
+        harness.otc_port,
+        &harness.otc_database_url,
+        swap.swap_id,
+        &swap_request,
+    )
+    .await;
 
-    assert!(quote.is_some(), "Quote should be present");
-    let quote = match quote.as_ref().unwrap() {
-        RFQResult::Success(quote) => quote.quote.clone(),
-        _ => panic!("Quote should be a success"),
-    };
+    harness.shutdown().await;
+}
 
-    // create a swap request
-    let swap_request = CreateSwapRequest {
-        quote,
-        user_destination_address: user_account.bitcoin_wallet.address.to_string(),
-        user_evm_account_address: user_account.ethereum_address,
-    };
+#[sqlx::test]
+async fn test_swap_from_bitcoin_to_ethereum(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    swap_from_bitcoin_to_ethereum(connect_options, true).await;
+}
 
-    let response = client
-        .post(format!("http://localhost:{otc_port}/api/v1/swaps"))
-        .json(&swap_request)
-        .send()
-        .await
-        .unwrap();
+#[sqlx::test]
+async fn test_swap_from_bitcoin_to_ethereum_with_esplora_only(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    swap_from_bitcoin_to_ethereum(connect_options, false).await;
+}
 
-    let response_status = response.status();
-    let response_json = match response_status {
-        StatusCode::OK => {
-            let response_json: CreateSwapResponse = response.json().await.unwrap();
-            response_json
-        }
-        _ => {
-            let response_text = response.text().await;
-            panic!(
-                "Swap request should be successful but got {response_status:#?} {response_text:#?}"
-            );
-            unreachable!()
-        }
+/// cbBTC deposit settled in Bitcoin, with the OTC server reading Bitcoin
+/// through Core RPC or esplora alone
+async fn swap_from_ethereum_to_bitcoin(connect_options: PgConnectOptions, bitcoin_core_rpc: bool) {
+    let options = SwapTestOptions {
+        bitcoin_mining_mode: MiningMode::Interval(2),
+        bitcoin_core_rpc,
+        ..SwapTestOptions::default()
     };
+    let mut harness = SwapTestHarness::launch(&connect_options, options).await;
+    let user_ethereum_wallet = harness.user_ethereum_wallet().await;
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(100_000_000), // 1 cbbtc
+            from: harness.cbbtc(),
+            to: SwapTestHarness::bitcoin(),
+        })
+        .await;
+    let mut swap_request = harness.swap_request(
+        quote,
+        quote_signature,
+        harness.user_account.bitcoin_wallet.address.to_string(),
+    );
+    swap_request.external_reference = Some(EXTERNAL_REFERENCE.to_string());
+    let swap = harness.create_swap(&swap_request).await;
+
     let tx_hash = user_ethereum_wallet
         .create_payment(
             &Lot {
-                currency: Currency {
-                    chain: ChainType::Ethereum,
-                    token: TokenIdentifier::Address(
-                        devnet.ethereum.cbbtc_contract.address().to_string(),
-                    ),
-                    decimals: response_json.decimals,
-                },
-                amount: response_json.expected_amount,
+                currency: harness.cbbtc(),
+                amount: swap.expected_amount,
             },
-            &response_json.deposit_address,
+            &swap.deposit_address,
             None,
         )
         .await
-        .unwrap();
-
-    info!(
-        "Broadcasting transaction from user wallet to deposit address: {}",
-        tx_hash
-    );
-    devnet
+        .unwrap()
+        .tx_hash;
+    info!("Paid the deposit address with {}", tx_hash);
+    harness
+        .devnet
         .ethereum
         .funded_provider
         .anvil_mine(Some(2), None)
         .await
         .unwrap();
-    info!("Mined 2 blocks");
 
-    let get_tx_status = devnet
-        .ethereum
-        .funded_provider
-        .get_transaction_receipt(tx_hash.parse::<TxHash>().unwrap())
-        .await
-        .unwrap();
+    harness.wait_settled(swap.swap_id).await;
+    assert_settled_swap_receipt(
+        &harness.client,
+        &harness.otc_client,
+        harness.otc_port,
+        &harness.otc_database_url,
+        swap.swap_id,
+        &tx_hash,
+    )
+    .await;
+    assert_swap_lookup(
+        &harness.client,
+        &harness.otc_client,
+        harness.otc_port,
+        swap.swap_id,
+        &swap.deposit_address,
+        &tx_hash,
+    )
+    .await;
 
-    info!("Tx status: {:#?}", get_tx_status);
-    wait_for_swap_to_be_settled(otc_port, response_json.swap_id).await;
+    harness.shutdown().await;
+}
 
-    drop(devnet);
-    tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
+#[sqlx::test]
+async fn test_swap_from_ethereum_to_bitcoin(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    swap_from_ethereum_to_bitcoin(connect_options, true).await;
+}
+
+#[sqlx::test]
+async fn test_swap_from_ethereum_to_bitcoin_with_esplora_only(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    swap_from_ethereum_to_bitcoin(connect_options, false).await;
 }
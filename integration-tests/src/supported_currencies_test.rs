@@ -0,0 +1,238 @@
+use alloy::primitives::{Address, U256};
+use devnet::MultichainAccount;
+use market_maker::run_market_maker;
+use otc_client::{
+    types::{ApiErrorCode, CreateSwapRequest},
+    OtcApiClient, RfqApiClient,
+};
+use otc_models::{ChainType, Currency, QuoteMode, QuoteRequest, TokenIdentifier};
+use otc_protocols::rfq::{QuoteWithFees, RFQResult};
+use otc_server::{api::CurrenciesResponse, server::run_server_with_listener};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::utils::{
+    bind_free_port, build_mm_test_args, build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, PgConnectOptionsExt,
+    TestContext,
+};
+
+/// Where the fake token is installed on the devnet
+const FAKE_WBTC_ADDRESS: &str = "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599";
+
+fn write_supported_currencies_file(cbbtc_address: &str) -> String {
+    let path = std::env::temp_dir().join(format!("supported_currencies_{}.toml", Uuid::new_v4()));
+    let contents = format!(
+        r#"
+[[currencies]]
+chain = "bitcoin"
+symbol = "BTC"
+decimals = 8
+min_amount = "100000"
+max_amount = "10000000000"
+
+[[currencies]]
+chain = "ethereum"
+address = "{cbbtc_address}"
+symbol = "cbBTC"
+decimals = 8
+min_amount = "100000"
+max_amount = "10000000000"
+
+[[currencies]]
+chain = "ethereum"
+address = "{FAKE_WBTC_ADDRESS}"
+symbol = "WBTC"
+decimals = 8
+min_amount = "100000"
+max_amount = "10000000000"
+"#
+    );
+    std::fs::write(&path, contents).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+async fn request_quote(
+    rfq_client: &RfqApiClient,
+    to_token: &str,
+    amount: u64,
+) -> RFQResult<QuoteWithFees> {
+    let quote_request = QuoteRequest {
+        mode: QuoteMode::ExactInput,
+        amount: U256::from(amount),
+        from: Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        },
+        to: Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(to_token.to_string()),
+            decimals: 8,
+            chain_id: None,
+        },
+    };
+    rfq_client
+        .request_quote(&quote_request)
+        .await
+        .unwrap()
+        .quote
+        .expect("A quote result should be returned")
+}
+
+#[sqlx::test]
+async fn test_configured_token_is_quoted_and_unconfigured_tokens_are_rejected(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let context = TestContext::new();
+    let market_maker_account = MultichainAccount::new(1);
+    let user_account = MultichainAccount::new(2);
+
+    let devnet = context
+        .devnet_builder()
+        .using_token_indexer(connect_options.to_database_url())
+        .using_esplora(true)
+        .build()
+        .await
+        .unwrap()
+        .0;
+
+    let wbtc = devnet
+        .ethereum
+        .deploy_mock_token(FAKE_WBTC_ADDRESS.parse().unwrap(), "WBTC", 8)
+        .await
+        .unwrap();
+    wbtc.mint(
+        market_maker_account.ethereum_address,
+        U256::from(9_000_000_000i128), // 90 WBTC
+    )
+    .send()
+    .await
+    .unwrap()
+    .get_receipt()
+    .await
+    .unwrap();
+    devnet
+        .ethereum
+        .fund_eth_address(
+            market_maker_account.ethereum_address,
+            U256::from(100_000_000_000_000_000_000i128),
+        )
+        .await
+        .unwrap();
+
+    let mut join_set = JoinSet::new();
+
+    // The OTC server keeps the default registry, which doesn't list WBTC
+    let (otc_listener, otc_port) = bind_free_port().await;
+    let otc_args = build_otc_server_test_args(&context, otc_port, &devnet, &connect_options).await;
+    join_set.spawn(async move {
+        run_server_with_listener(otc_args, otc_listener)
+            .await
+            .expect("OTC server should not crash");
+    });
+    wait_for_otc_server_to_be_ready(otc_port).await;
+
+    let (rfq_listener, rfq_port) = bind_free_port().await;
+    let rfq_args = build_rfq_server_test_args(rfq_port);
+    join_set.spawn(async move {
+        rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+            .await
+            .expect("RFQ server should not crash");
+    });
+    wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+    let mut mm_args = build_mm_test_args(
+        &context,
+        otc_port,
+        rfq_port,
+        &market_maker_account,
+        &devnet,
+        &connect_options,
+    )
+    .await;
+    mm_args.supported_currencies_file = Some(write_supported_currencies_file(
+        &devnet.ethereum.cbbtc_contract.address().to_string(),
+    ));
+    join_set.spawn(async move {
+        run_market_maker(mm_args)
+            .await
+            .expect("Market maker should not crash");
+    });
+    wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+    wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+    let client = reqwest::Client::new();
+    let otc_client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let rfq_client = RfqApiClient::new(format!("http://localhost:{rfq_port}")).unwrap();
+
+    // The token added through config is quoted like cbBTC
+    let quote = match request_quote(&rfq_client, FAKE_WBTC_ADDRESS, 10_000_000).await {
+        RFQResult::Success(quote) => quote,
+        other => panic!("WBTC quote should be a success, got {other:?}"),
+    };
+    assert!(matches!(
+        &quote.quote.to.currency.token,
+        TokenIdentifier::Address(address)
+            if address.parse::<Address>().unwrap() == FAKE_WBTC_ADDRESS.parse::<Address>().unwrap()
+    ));
+    assert!(quote.quote.to.amount > U256::ZERO);
+
+    // Tokens missing from the config, and amounts outside its bounds, are not quoted
+    let unconfigured = Address::repeat_byte(0x42).to_string();
+    assert!(matches!(
+        request_quote(&rfq_client, &unconfigured, 10_000_000).await,
+        RFQResult::InvalidRequest(_)
+    ));
+    assert!(matches!(
+        request_quote(&rfq_client, FAKE_WBTC_ADDRESS, 50_000).await,
+        RFQResult::InvalidRequest(_)
+    ));
+
+    // The OTC server only advertises and accepts the tokens it is configured with
+    let currencies: CurrenciesResponse = client
+        .get(format!("http://localhost:{otc_port}/api/v1/currencies"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ethereum = currencies
+        .chains
+        .iter()
+        .find(|c| c.chain == ChainType::Ethereum)
+        .unwrap();
+    assert_eq!(
+        ethereum
+            .tokens
+            .iter()
+            .map(|t| t.symbol.as_str())
+            .collect::<Vec<_>>(),
+        vec!["cbBTC"]
+    );
+
+    let error = otc_client
+        .create_swap(&CreateSwapRequest {
+            quote: quote.quote,
+            quote_signature: quote.signature,
+            quote_lock: None,
+            user_destination_address: user_account.ethereum_address.to_string(),
+            user_evm_account_address: user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+    assert_eq!(error.code(), Some(ApiErrorCode::UnsupportedToken));
+
+    drop(devnet);
+    join_set.shutdown().await;
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use alloy::primitives::U256;
+use otc_client::types::SwapResponse;
+use otc_models::{QuoteMode, QuoteRequest};
+use reqwest::StatusCode;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, PgPool};
+use uuid::Uuid;
+
+use crate::utils::{
+    SwapTestHarness, SwapTestOptions, INTEGRATION_TEST_TIMEOUT_SECS, TEST_ADMIN_API_KEY,
+};
+
+/// Why the market maker recorded the swap for `quote_id` as failed
+async fn mm_failure_reason(pool: &PgPool, quote_id: Uuid) -> Option<String> {
+    sqlx::query_scalar("SELECT failure_reason FROM mm_quotes WHERE id = $1")
+        .bind(quote_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn test_cancelled_swap_is_released_by_the_market_maker(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let harness = SwapTestHarness::launch(&connect_options, SwapTestOptions::default()).await;
+    let mm_pool = PgPool::connect(&harness.mm_database_url).await.unwrap();
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(10_000_000), // 0.1 BTC
+            from: SwapTestHarness::bitcoin(),
+            to: harness.cbbtc(),
+        })
+        .await;
+    let quote_id = quote.id;
+    let swap_request = harness.swap_request(
+        quote,
+        quote_signature,
+        harness.user_account.ethereum_address.to_string(),
+    );
+    let swap = harness.create_swap(&swap_request).await;
+
+    let cancel_url = harness.otc_url(&format!("/admin/swaps/{}/cancel", swap.swap_id));
+    let body = serde_json::json!({ "reason": "User asked to abort" });
+
+    let response = harness
+        .client
+        .post(&cancel_url)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Nothing was deposited, so the swap just fails
+    let response = harness
+        .client
+        .post(&cancel_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cancelled: SwapResponse = response.json().await.unwrap();
+    assert_eq!(cancelled.status, "Failed");
+
+    let response = harness
+        .client
+        .post(&cancel_url)
+        .header("x-admin-api-key", TEST_ADMIN_API_KEY)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let deadline = Instant::now() + Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
+    while mm_failure_reason(&mm_pool, quote_id).await.is_none() {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for the market maker to record the failure"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    assert_eq!(
+        mm_failure_reason(&mm_pool, quote_id).await.as_deref(),
+        Some("cancelled")
+    );
+
+    harness.shutdown().await;
+}
@@ -0,0 +1,66 @@
+use alloy::primitives::U256;
+use market_maker::wallet::Wallet;
+use otc_models::{Lot, QuoteMode, QuoteRequest, SwapStatus};
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions};
+
+use crate::utils::{wait_for_swap_status, SwapTestHarness, SwapTestOptions};
+
+/// Far longer than the test takes, so only the subscription can move the swap
+const ETHEREUM_MONITOR_INTERVAL_SECONDS: u64 = 3600;
+
+#[sqlx::test]
+async fn test_evm_deposit_is_detected_through_the_log_subscription(
+    _: PoolOptions<sqlx::Postgres>,
+    connect_options: PgConnectOptions,
+) {
+    let options = SwapTestOptions {
+        evm_log_subscriptions: true,
+        ethereum_monitor_interval_seconds: Some(ETHEREUM_MONITOR_INTERVAL_SECONDS),
+        ..SwapTestOptions::default()
+    };
+    let mut harness = SwapTestHarness::launch(&connect_options, options).await;
+    let user_ethereum_wallet = harness.user_ethereum_wallet().await;
+
+    let (quote, quote_signature) = harness
+        .request_signed_quote(&QuoteRequest {
+            mode: QuoteMode::ExactInput,
+            amount: U256::from(100_000_000), // 1 cbbtc
+            from: harness.cbbtc(),
+            to: SwapTestHarness::bitcoin(),
+        })
+        .await;
+    let swap = harness
+        .create_swap(&harness.swap_request(
+            quote,
+            quote_signature,
+            harness.user_account.bitcoin_wallet.address.to_string(),
+        ))
+        .await;
+
+    let tx_hash = user_ethereum_wallet
+        .create_payment(
+            &Lot {
+                currency: harness.cbbtc(),
+                amount: swap.expected_amount,
+            },
+            &swap.deposit_address,
+            None,
+        )
+        .await
+        .unwrap()
+        .tx_hash;
+
+    // Well within the polling interval, wait_for_swap_status gives up after a minute
+    let detected = wait_for_swap_status(
+        harness.otc_port,
+        swap.swap_id,
+        SwapStatus::WaitingUserDepositConfirmed,
+    )
+    .await;
+    let deposit_tx = detected.user_deposit.deposit_tx.unwrap();
+    assert!(deposit_tx
+        .trim_start_matches("0x")
+        .eq_ignore_ascii_case(tx_hash.trim_start_matches("0x")));
+
+    harness.shutdown().await;
+}
@@ -0,0 +1,69 @@
+//! Files a test owns alone, so tests running concurrently never share a
+//! wallet database, settings file or devnet data directory
+
+use std::path::{Path, PathBuf};
+
+use devnet::{RiftDevnet, RiftDevnetBuilder};
+use uuid::Uuid;
+
+/// Root directory of one test's files, removed when the test ends
+pub struct TestContext {
+    root: PathBuf,
+}
+
+impl TestContext {
+    #[must_use]
+    pub fn new() -> Self {
+        let root = std::env::temp_dir().join(format!("otc_test_{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&root).unwrap();
+        Self { root }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Fresh directory under the test's root
+    #[must_use]
+    pub fn new_dir(&self, name: &str) -> PathBuf {
+        let dir = self
+            .root
+            .join(format!("{name}_{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Fresh directory for wallet databases, the devnet chain differs per test
+    #[must_use]
+    pub fn bitcoin_wallet_dir(&self) -> PathBuf {
+        self.new_dir("bitcoin_wallet")
+    }
+
+    /// Path for an OTC server's settings, created by the server on first start
+    #[must_use]
+    pub fn otc_settings_file(&self) -> String {
+        self.root
+            .join(format!("otc_server_{}.toml", Uuid::new_v4().simple()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Devnet builder keeping the chains' data directories under the test's root
+    #[must_use]
+    pub fn devnet_builder(&self) -> RiftDevnetBuilder {
+        RiftDevnet::builder().temp_dir_root(self.new_dir("devnet"))
+    }
+}
+
+impl Default for TestContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TestContext {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,443 @@
+//! A devnet with a running OTC server, RFQ server and funded market maker, so
+//! swap tests only have to write the parts they're testing
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use alloy::primitives::U256;
+use blockchain_utils::create_websocket_wallet_provider;
+use common::Shutdown;
+use devnet::{bitcoin_devnet::MiningMode, MultichainAccount, RiftDevnet};
+use market_maker::{
+    bitcoin_wallet::{
+        BitcoinWallet, BitcoinWalletSyncConfig, CoinSelectionConfig, SignerConfig,
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    },
+    evm_wallet::EVMWallet,
+    run_market_maker,
+};
+use otc_client::{
+    types::{CreateSwapRequest, CreateSwapResponse, QuoteResponse},
+    OtcApiClient, RfqApiClient,
+};
+use otc_models::{ChainType, Currency, Quote, QuoteRequest, SupportedCurrencies, TokenIdentifier};
+use otc_protocols::rfq::RFQResult;
+use otc_server::{services::swap_monitoring::ChainMonitorInterval, OtcServerArgs};
+use sqlx::postgres::PgConnectOptions;
+use tokio::{
+    net::TcpListener,
+    task::{JoinHandle, JoinSet},
+};
+use uuid::Uuid;
+
+use super::{
+    bind_free_port, build_bitcoin_wallet_descriptor, build_mm_test_args,
+    build_otc_server_test_args, build_rfq_server_test_args,
+    wait_for_market_maker_to_connect_to_otc_server, wait_for_market_maker_to_connect_to_rfq_server,
+    wait_for_otc_server_to_be_ready, wait_for_rfq_server_to_be_ready, wait_for_swap_to_be_settled,
+    PgConnectOptionsExt, TestContext, TEST_API_KEY, TEST_API_KEY_ID,
+};
+
+/// Gas every account gets, 100 ETH
+const ETH_FUNDING_WEI: u128 = 100_000_000_000_000_000_000;
+
+/// What an account starts with besides gas
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Funding {
+    pub bitcoin_sats: u64,
+    pub cbbtc_sats: u64,
+}
+
+impl Funding {
+    /// 5 BTC and 90 cbBTC
+    pub const PLENTY: Self = Self {
+        bitcoin_sats: 500_000_000,
+        cbbtc_sats: 9_000_000_000,
+    };
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SwapTestOptions {
+    pub market_maker: Funding,
+    pub user: Funding,
+    pub token_indexer: bool,
+    pub bitcoin_mining_mode: MiningMode,
+    /// Whether the OTC server reads Bitcoin through the devnet's Core RPC, or esplora alone
+    pub bitcoin_core_rpc: bool,
+    /// Whether the OTC server also learns of Ethereum deposits from a websocket
+    /// log subscription
+    pub evm_log_subscriptions: bool,
+    /// How often the OTC server polls Ethereum swaps, the test default when unset
+    pub ethereum_monitor_interval_seconds: Option<u64>,
+}
+
+impl Default for SwapTestOptions {
+    fn default() -> Self {
+        Self {
+            market_maker: Funding::PLENTY,
+            user: Funding::PLENTY,
+            token_indexer: true,
+            bitcoin_mining_mode: MiningMode::default(),
+            bitcoin_core_rpc: true,
+            evm_log_subscriptions: false,
+            ethereum_monitor_interval_seconds: None,
+        }
+    }
+}
+
+pub struct SwapTestHarness {
+    pub devnet: RiftDevnet,
+    /// Where the harness keeps its files, dropped after the devnet using them
+    pub context: TestContext,
+    pub market_maker_account: MultichainAccount,
+    pub user_account: MultichainAccount,
+    pub otc_port: u16,
+    pub otc_database_url: String,
+    /// Where the OTC server keeps its master keys
+    pub otc_settings_file: String,
+    pub mm_database_url: String,
+    pub rfq_port: u16,
+    /// For the endpoints the API clients don't cover
+    pub client: reqwest::Client,
+    pub otc_client: OtcApiClient,
+    /// Locks quotes with the test API key
+    pub rfq_client: RfqApiClient,
+    /// RFQ server and the market maker
+    pub service_join_set: JoinSet<()>,
+    /// Sync tasks of the user wallets
+    pub wallet_join_set: JoinSet<market_maker::Result<()>>,
+    /// The OTC server runs apart from the other services so it can be restarted
+    otc_server: OtcServerTask,
+}
+
+/// A running OTC server and what it takes to start it again
+struct OtcServerTask {
+    args: OtcServerArgs,
+    shutdown: Shutdown,
+    handle: JoinHandle<()>,
+}
+
+impl OtcServerTask {
+    fn spawn(args: OtcServerArgs, listener: TcpListener) -> Self {
+        let shutdown = Shutdown::new();
+        let handle = tokio::spawn({
+            let args = args.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                otc_server::server::run_server_until(args, listener, shutdown)
+                    .await
+                    .expect("OTC server should not crash");
+            }
+        });
+        Self {
+            args,
+            shutdown,
+            handle,
+        }
+    }
+}
+
+impl SwapTestHarness {
+    /// Start the devnet, fund both accounts, then run the OTC server, RFQ server
+    /// and market maker until the market maker is connected to both
+    pub async fn launch(connect_options: &PgConnectOptions, options: SwapTestOptions) -> Self {
+        let market_maker_account = MultichainAccount::new(1);
+        let user_account = MultichainAccount::new(2);
+
+        let context = TestContext::new();
+        let mut builder = context
+            .devnet_builder()
+            .bitcoin_mining_mode(options.bitcoin_mining_mode)
+            .using_esplora(true);
+        if options.token_indexer {
+            builder = builder.using_token_indexer(connect_options.to_database_url());
+        }
+        let devnet = builder.build().await.unwrap().0;
+
+        for (account, funding) in [
+            (&market_maker_account, options.market_maker),
+            (&user_account, options.user),
+        ] {
+            fund_account(&devnet, account, funding).await;
+        }
+
+        let mut service_join_set = JoinSet::new();
+
+        let (otc_listener, otc_port) = bind_free_port().await;
+        let mut otc_args =
+            build_otc_server_test_args(&context, otc_port, &devnet, connect_options).await;
+        if !options.bitcoin_core_rpc {
+            otc_args.bitcoin_rpc_url = None;
+        }
+        if options.evm_log_subscriptions {
+            otc_args.ethereum_mainnet_ws_url = Some(devnet.ethereum.anvil.ws_endpoint());
+        }
+        if let Some(seconds) = options.ethereum_monitor_interval_seconds {
+            otc_args
+                .chain_monitor_interval_seconds
+                .push(ChainMonitorInterval {
+                    chain: Some(ChainType::Ethereum),
+                    seconds,
+                });
+        }
+        let otc_database_url = otc_args.database_url.clone();
+        let otc_settings_file = otc_args.settings_file.clone();
+        let mut otc_server = OtcServerTask::spawn(otc_args, otc_listener);
+        tokio::select! {
+            () = wait_for_otc_server_to_be_ready(otc_port) => {}
+            _ = &mut otc_server.handle => panic!("OTC server crashed"),
+        }
+
+        let (rfq_listener, rfq_port) = bind_free_port().await;
+        let rfq_args = build_rfq_server_test_args(rfq_port);
+        service_join_set.spawn(async move {
+            rfq_server::server::run_server_with_listener(rfq_args, rfq_listener)
+                .await
+                .expect("RFQ server should not crash");
+        });
+        wait_for_rfq_server_to_be_ready(rfq_port).await;
+
+        let mm_args = build_mm_test_args(
+            &context,
+            otc_port,
+            rfq_port,
+            &market_maker_account,
+            &devnet,
+            connect_options,
+        )
+        .await;
+        let mm_database_url = mm_args.database_url.clone();
+        service_join_set.spawn(async move {
+            run_market_maker(mm_args)
+                .await
+                .expect("Market maker should not crash");
+        });
+        wait_for_market_maker_to_connect_to_rfq_server(rfq_port).await;
+        wait_for_market_maker_to_connect_to_otc_server(otc_port).await;
+
+        // Balances are only visible to the wallets once esplora has the funding blocks
+        devnet
+            .bitcoin
+            .wait_for_esplora_sync(Duration::from_secs(30))
+            .await
+            .unwrap();
+
+        Self {
+            devnet,
+            context,
+            market_maker_account,
+            user_account,
+            otc_port,
+            otc_database_url,
+            otc_settings_file,
+            mm_database_url,
+            rfq_port,
+            client: reqwest::Client::new(),
+            otc_client: OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap(),
+            rfq_client: RfqApiClient::new(format!("http://localhost:{rfq_port}"))
+                .unwrap()
+                .with_api_key(TEST_API_KEY_ID.parse().unwrap(), TEST_API_KEY),
+            service_join_set,
+            wallet_join_set: JoinSet::new(),
+            otc_server,
+        }
+    }
+
+    /// Stop the OTC server the way SIGTERM does, returning how long it took to drain
+    pub async fn stop_otc_server(&mut self) -> Duration {
+        let started = Instant::now();
+        self.otc_server.shutdown.trigger();
+        (&mut self.otc_server.handle)
+            .await
+            .expect("OTC server should drain without crashing");
+        started.elapsed()
+    }
+
+    /// Start the stopped OTC server again on the same port, database and master
+    /// keys, and wait for the market maker to reconnect
+    pub async fn start_otc_server(&mut self) {
+        let listener = TcpListener::bind(("127.0.0.1", self.otc_port))
+            .await
+            .expect("the stopped OTC server's port should be free");
+        self.otc_server = OtcServerTask::spawn(self.otc_server.args.clone(), listener);
+        wait_for_otc_server_to_be_ready(self.otc_port).await;
+        wait_for_market_maker_to_connect_to_otc_server(self.otc_port).await;
+    }
+
+    #[must_use]
+    pub fn otc_url(&self, path: &str) -> String {
+        format!("http://localhost:{}{path}", self.otc_port)
+    }
+
+    #[must_use]
+    pub fn rfq_url(&self, path: &str) -> String {
+        format!("http://localhost:{}{path}", self.rfq_port)
+    }
+
+    #[must_use]
+    pub fn bitcoin() -> Currency {
+        Currency {
+            chain: ChainType::Bitcoin,
+            token: TokenIdentifier::Native,
+            decimals: 8,
+            chain_id: None,
+        }
+    }
+
+    #[must_use]
+    pub fn cbbtc(&self) -> Currency {
+        Currency {
+            chain: ChainType::Ethereum,
+            token: TokenIdentifier::Address(
+                self.devnet.ethereum.cbbtc_contract.address().to_string(),
+            ),
+            decimals: 8,
+            chain_id: None,
+        }
+    }
+
+    /// The user's bitcoin wallet, synced in the background
+    pub async fn user_bitcoin_wallet(&mut self) -> BitcoinWallet {
+        let wallet = BitcoinWallet::open_or_create_in_dir(
+            &self.context.bitcoin_wallet_dir(),
+            &build_bitcoin_wallet_descriptor(&self.user_account.bitcoin_wallet.private_key),
+            None,
+            bitcoin::Network::Regtest,
+            &self
+                .devnet
+                .bitcoin
+                .esplora_url
+                .as_ref()
+                .unwrap()
+                .to_string(),
+            BitcoinWalletSyncConfig::default(),
+            DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+            CoinSelectionConfig::default(),
+            SignerConfig::Descriptor,
+            &mut self.wallet_join_set,
+        )
+        .await
+        .unwrap();
+        wallet.sync_now().await.unwrap();
+        wallet
+    }
+
+    /// The user's ethereum wallet, approved to pay cbBTC deposits
+    pub async fn user_ethereum_wallet(&mut self) -> EVMWallet {
+        let ws_endpoint = self.devnet.ethereum.anvil.ws_endpoint();
+        let provider =
+            create_websocket_wallet_provider(&ws_endpoint, self.user_account.secret_bytes)
+                .await
+                .unwrap();
+        let wallet = EVMWallet::new(
+            Arc::new(provider),
+            ws_endpoint,
+            1,
+            Arc::new(SupportedCurrencies::default()),
+            None,
+            &mut self.wallet_join_set,
+        );
+        wallet
+            .ensure_inf_approval_on_disperse(self.devnet.ethereum.cbbtc_contract.address())
+            .await
+            .unwrap();
+        wallet
+    }
+
+    /// Ask the RFQ server for a quote, whatever its outcome
+    pub async fn request_quote(&self, request: &QuoteRequest) -> QuoteResponse {
+        self.rfq_client
+            .request_quote(request)
+            .await
+            .expect("Quote request should succeed")
+    }
+
+    /// A quote the market maker agreed to, with the RFQ server's signature
+    pub async fn request_signed_quote(&self, request: &QuoteRequest) -> (Quote, Option<String>) {
+        match self.request_quote(request).await.quote {
+            Some(RFQResult::Success(quote)) => (quote.quote, quote.signature),
+            other => panic!("Quote should be a success, got {other:?}"),
+        }
+    }
+
+    /// A request for a swap of `quote` paying out to `user_destination_address`
+    #[must_use]
+    pub fn swap_request(
+        &self,
+        quote: Quote,
+        quote_signature: Option<String>,
+        user_destination_address: String,
+    ) -> CreateSwapRequest {
+        CreateSwapRequest {
+            quote,
+            quote_signature,
+            quote_lock: None,
+            user_destination_address,
+            user_evm_account_address: self.user_account.ethereum_address,
+            user_refund_address: None,
+            trace_id: None,
+            external_reference: None,
+        }
+    }
+
+    /// Create the swap, whatever its outcome
+    pub async fn try_create_swap(
+        &self,
+        request: &CreateSwapRequest,
+    ) -> otc_client::Result<CreateSwapResponse> {
+        self.otc_client.create_swap(request).await
+    }
+
+    pub async fn create_swap(&self, request: &CreateSwapRequest) -> CreateSwapResponse {
+        self.try_create_swap(request)
+            .await
+            .unwrap_or_else(|e| panic!("Swap request should be successful but got {e}"))
+    }
+
+    pub async fn wait_settled(&self, swap_id: Uuid) {
+        wait_for_swap_to_be_settled(self.otc_port, swap_id).await;
+    }
+
+    /// Stop the devnet and every task the harness or test spawned
+    pub async fn shutdown(self) {
+        let Self {
+            devnet,
+            context,
+            mut service_join_set,
+            mut wallet_join_set,
+            otc_server,
+            ..
+        } = self;
+        otc_server.handle.abort();
+        drop(devnet);
+        tokio::join!(wallet_join_set.shutdown(), service_join_set.shutdown());
+        drop(context);
+    }
+}
+
+async fn fund_account(devnet: &RiftDevnet, account: &MultichainAccount, funding: Funding) {
+    if funding.bitcoin_sats > 0 {
+        devnet
+            .bitcoin
+            .deal_bitcoin(
+                &account.bitcoin_wallet.address,
+                &bitcoin::Amount::from_sat(funding.bitcoin_sats),
+            )
+            .await
+            .unwrap();
+    }
+    devnet
+        .ethereum
+        .fund_eth_address(account.ethereum_address, U256::from(ETH_FUNDING_WEI))
+        .await
+        .unwrap();
+    if funding.cbbtc_sats > 0 {
+        devnet
+            .ethereum
+            .mint_cbbtc(account.ethereum_address, U256::from(funding.cbbtc_sats))
+            .await
+            .unwrap();
+    }
+}
@@ -5,12 +5,31 @@ use std::{
     time::Duration,
 };
 
+use alloy::primitives::{b256, B256, U256};
 use bitcoincore_rpc_async::Auth;
-use blockchain_utils::create_websocket_wallet_provider;
+use blockchain_utils::{create_websocket_wallet_provider, MIN_PROTOCOL_FEE_SATS, PROTOCOL_FEE_BPS};
 use ctor::ctor;
 use devnet::MultichainAccount;
-use market_maker::{evm_wallet::EVMWallet, MarketMakerArgs};
-use otc_server::{api::SwapResponse, OtcServerArgs};
+use market_maker::{
+    bitcoin_wallet::{
+        CoinSelectionStrategy, WalletIdentity, DEFAULT_MAX_FILL_INPUTS,
+        DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+    },
+    evm_wallet::EVMWallet,
+    quote_storage::DEFAULT_QUOTE_RETENTION_HOURS,
+    rfq_placement::DEFAULT_FAILOVER_AFTER,
+    simulation::DEFAULT_SIMULATED_BTC_PER_ETH,
+    MarketMakerArgs,
+};
+use otc_client::{types::SwapResponse, OtcApiClient};
+use otc_models::{SupportedCurrencies, SwapStatus};
+use otc_protocols::{
+    attestation::{
+        AttestationVerifier, MockAttestationProvider, DEFAULT_ATTESTATION_MAX_AGE_SECONDS,
+    },
+    capabilities::QuoteSigningMode,
+};
+use otc_server::{services::swap_monitoring::ChainMonitorInterval, OtcServerArgs, ServerMode};
 use rfq_server::RfqServerArgs;
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
@@ -21,6 +40,12 @@ use tracing::info;
 use tracing_subscriber::EnvFilter;
 use uuid::Uuid;
 
+mod context;
+mod harness;
+
+pub use context::TestContext;
+pub use harness::{Funding, SwapTestHarness, SwapTestOptions};
+
 pub trait PgConnectOptionsExt {
     fn to_database_url(&self) -> String;
 }
@@ -38,15 +63,18 @@ impl PgConnectOptionsExt for PgConnectOptions {
     }
 }
 
-pub async fn get_free_port() -> u16 {
+/// A listener on a free port, for a server to take over as is. Handing over
+/// only the port would free it for a concurrently running test to grab
+pub async fn bind_free_port() -> (TcpListener, u16) {
     let listener = TcpListener::bind(("127.0.0.1", 0))
         .await
         .expect("Should be able to bind to port");
-
-    listener
+    let port = listener
         .local_addr()
         .expect("Should have a local address")
-        .port()
+        .port();
+
+    (listener, port)
 }
 
 pub const TEST_MARKET_MAKER_ID: &str = "550e8400-e29b-41d4-a716-446655440000";
@@ -54,8 +82,28 @@ pub const TEST_API_KEY_ID: &str = "d2e0a695-e3b1-494e-b645-1b41a72d7e75";
 pub const TEST_API_KEY: &str = "7KNJu1t1j9DtVqS0d8FB6pfX0nkqr4TX";
 pub const TEST_MM_WHITELIST_FILE: &str =
     "integration-tests/src/utils/test_whitelisted_market_makers.json";
+pub const TEST_QUOTE_SIGNING_KEY: &str =
+    "3f1c8e2a9b7d4f60a5e1c3b8d2f7a9e04b6c1d8e3f5a7b9c2d4e6f8a0b1c3d5e";
+pub const TEST_ADMIN_API_KEY: &str = "c0ffee5a1e7d4b2f9e8a7c6b5d4e3f21";
+/// Key of the devnet attestation provider, whose address is the MMs' root of trust
+pub const TEST_ATTESTATION_SIGNING_KEY: B256 =
+    b256!("6d9c1f4e2a8b7c3d5e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d");
+pub const TEST_ATTESTATION_MEASUREMENT: B256 =
+    b256!("a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90");
 pub const INTEGRATION_TEST_TIMEOUT_SECS: u64 = 60;
 
+/// Verifier for the attestations of servers built with [`build_otc_server_test_args`]
+pub fn test_attestation_verifier() -> AttestationVerifier {
+    let provider =
+        MockAttestationProvider::new(&TEST_ATTESTATION_SIGNING_KEY, TEST_ATTESTATION_MEASUREMENT)
+            .unwrap();
+    AttestationVerifier {
+        root_of_trust: provider.root_of_trust(),
+        expected_measurement: TEST_ATTESTATION_MEASUREMENT,
+        max_age: Duration::from_secs(DEFAULT_ATTESTATION_MAX_AGE_SECONDS),
+    }
+}
+
 pub fn get_whitelist_file_path() -> String {
     // Convert relative path to absolute path from workspace root
     let mut current_dir = current_dir().expect("Should be able to get current directory");
@@ -119,44 +167,87 @@ pub async fn wait_for_rfq_server_to_be_ready(rfq_port: u16) {
     }
 }
 
-pub async fn wait_for_swap_to_be_settled(otc_port: u16, swap_id: Uuid) {
+pub async fn wait_for_swap_to_be_settled(otc_port: u16, swap_id: Uuid) -> SwapResponse {
+    let swap = wait_for_swap_status(otc_port, swap_id, SwapStatus::Settled).await;
+
+    // Both deposits reached the confirmations the response says they need
+    for (leg, deposit) in [("user", &swap.user_deposit), ("mm", &swap.mm_deposit)] {
+        let detected = deposit
+            .detected
+            .as_ref()
+            .unwrap_or_else(|| panic!("Settled swap {swap_id} should show its {leg} deposit"));
+        assert_eq!(
+            detected.required_confirmations,
+            deposit.required_confirmations
+        );
+        assert!(
+            detected.confirmations >= detected.required_confirmations,
+            "{leg} deposit of settled swap {swap_id} has {} of {} confirmations",
+            detected.confirmations,
+            detected.required_confirmations
+        );
+        assert_eq!(detected.decimals, deposit.decimals);
+        assert_eq!(
+            detected.amount.parse::<U256>().unwrap(),
+            deposit.deposit_amount.unwrap()
+        );
+    }
+    swap
+}
+
+pub async fn wait_for_swap_status(
+    otc_port: u16,
+    swap_id: Uuid,
+    status: SwapStatus,
+) -> SwapResponse {
+    let client = OtcApiClient::new(format!("http://localhost:{otc_port}")).unwrap();
+    let swap = client
+        .wait_for_status(
+            swap_id,
+            status,
+            Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("Swap {swap_id} should reach {status:?}: {e}"));
+    info!("Swap reached {status:?}: {swap:#?}");
+    swap
+}
+
+pub async fn wait_for_market_maker_to_connect_to_rfq_server(rfq_port: u16) {
     let client = reqwest::Client::new();
+    let connected_url = format!("http://127.0.0.1:{rfq_port}/api/v1/market-makers/connected");
 
     let start_time = std::time::Instant::now();
-    let mut last_log_time = std::time::Instant::now();
-    let log_interval = Duration::from_secs(5);
     let timeout = Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
-    // now call the otc-server swap status endpoint until it's detected as complete
+
     loop {
-        let response = client
-            .get(format!(
-                "http://localhost:{otc_port}/api/v1/swaps/{swap_id}"
-            ))
-            .send()
-            .await
-            .unwrap();
-        let response_json: SwapResponse = response.json().await.unwrap();
-        if last_log_time.elapsed() > log_interval {
-            info!("Response from swap status endpoint: {:#?}", response_json);
-            last_log_time = std::time::Instant::now();
-        }
-        if start_time.elapsed() > timeout {
-            info!(
-                "Final response from swap status endpoint: {:#?}",
-                response_json
-            );
-            panic!("Timeout waiting for swap to be settled");
-        }
-        if response_json.status == "Settled" {
-            break;
+        assert!(
+            (start_time.elapsed() <= timeout),
+            "Timeout waiting for market maker to connect to RFQ server"
+        );
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        if let Ok(response) = client.get(&connected_url).send().await {
+            if response.status() == 200 {
+                if let Ok(body) = response.json::<serde_json::Value>().await {
+                    if let Some(market_makers) = body["market_makers"].as_array() {
+                        if market_makers.len() == 1
+                            && market_makers[0].as_str() == Some(TEST_MARKET_MAKER_ID)
+                        {
+                            println!("Market maker is connected to RFQ server!");
+                            break;
+                        }
+                    }
+                }
+            }
         }
-        tokio::time::sleep(Duration::from_secs(1)).await;
     }
 }
 
-pub async fn wait_for_market_maker_to_connect_to_rfq_server(rfq_port: u16) {
+pub async fn wait_for_market_maker_to_connect_to_otc_server(otc_port: u16) {
     let client = reqwest::Client::new();
-    let connected_url = format!("http://127.0.0.1:{rfq_port}/api/v1/market-makers/connected");
+    let connected_url = format!("http://127.0.0.1:{otc_port}/api/v1/market-makers/connected");
 
     let start_time = std::time::Instant::now();
     let timeout = Duration::from_secs(INTEGRATION_TEST_TIMEOUT_SECS);
@@ -164,7 +255,7 @@ pub async fn wait_for_market_maker_to_connect_to_rfq_server(rfq_port: u16) {
     loop {
         assert!(
             (start_time.elapsed() <= timeout),
-            "Timeout waiting for market maker to connect to RFQ server"
+            "Timeout waiting for market maker to connect to OTC server"
         );
 
         tokio::time::sleep(Duration::from_millis(100)).await;
@@ -176,7 +267,7 @@ pub async fn wait_for_market_maker_to_connect_to_rfq_server(rfq_port: u16) {
                         if market_makers.len() == 1
                             && market_makers[0].as_str() == Some(TEST_MARKET_MAKER_ID)
                         {
-                            println!("Market maker is connected to RFQ server!");
+                            println!("Market maker is connected to OTC server!");
                             break;
                         }
                     }
@@ -190,12 +281,8 @@ pub fn build_bitcoin_wallet_descriptor(private_key: &bitcoin::PrivateKey) -> Str
     format!("wpkh({private_key})")
 }
 
-pub fn build_tmp_bitcoin_wallet_db_file() -> String {
-    format!("/tmp/bitcoin_wallet_{}.db", uuid::Uuid::new_v4())
-}
-// TODO:
-
 pub async fn build_mm_test_args(
+    context: &TestContext,
     otc_port: u16,
     rfq_port: u16,
     multichain_account: &MultichainAccount,
@@ -203,25 +290,70 @@ pub async fn build_mm_test_args(
     connect_options: &PgConnectOptions,
 ) -> MarketMakerArgs {
     let db_url = create_test_database(connect_options).await.unwrap();
+    let bitcoin_wallet_descriptor =
+        build_bitcoin_wallet_descriptor(&multichain_account.bitcoin_wallet.private_key);
     MarketMakerArgs {
         market_maker_id: TEST_MARKET_MAKER_ID.to_string(),
         api_key_id: TEST_API_KEY_ID.to_string(),
-        api_key: TEST_API_KEY.to_string(),
+        api_key: TEST_API_KEY.to_string().into(),
         otc_ws_url: format!("ws://127.0.0.1:{otc_port}/ws/mm"),
-        rfq_ws_url: format!("ws://127.0.0.1:{rfq_port}/ws/mm"),
+        rfq_ws_urls: vec![format!("ws://127.0.0.1:{rfq_port}/ws/mm")],
+        rfq_failover_after_seconds: DEFAULT_FAILOVER_AFTER.as_secs(),
+        status_addr: None,
         log_level: "info".to_string(),
-        bitcoin_wallet_db_file: build_tmp_bitcoin_wallet_db_file(),
-        bitcoin_wallet_descriptor: build_bitcoin_wallet_descriptor(
-            &multichain_account.bitcoin_wallet.private_key,
-        ),
+        bitcoin_wallet_db_file: context
+            .bitcoin_wallet_dir()
+            .join(
+                WalletIdentity::new(&bitcoin_wallet_descriptor, bitcoin::Network::Regtest)
+                    .unwrap()
+                    .db_file_name(),
+            )
+            .to_string_lossy()
+            .to_string(),
+        bitcoin_wallet_descriptor: bitcoin_wallet_descriptor.into(),
+        bitcoin_wallet_change_descriptor: None,
         bitcoin_wallet_network: bitcoin::Network::Regtest,
         bitcoin_wallet_esplora_url: devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
-        ethereum_wallet_private_key: multichain_account.secret_bytes,
+        bitcoin_wallet_sync_interval_seconds: 5,
+        bitcoin_wallet_max_sync_staleness_seconds: 120,
+        bitcoin_wallet_max_unconfirmed_chain_depth: DEFAULT_MAX_UNCONFIRMED_CHAIN_DEPTH,
+        bitcoin_wallet_coin_selection: CoinSelectionStrategy::default(),
+        bitcoin_wallet_max_fill_inputs: DEFAULT_MAX_FILL_INPUTS,
+        bitcoin_wallet_consolidation_max_fee_rate: None,
+        bitcoin_wallet_consolidation_small_utxo_sats: 100_000,
+        bitcoin_wallet_consolidation_min_utxos: 10,
+        bitcoin_wallet_consolidation_interval_seconds: 600,
+        bitcoin_wallet_external_signer_url: None,
+        bitcoin_wallet_external_signer_timeout_seconds: 30,
+        bitcoin_wallet_external_signer_allowed_addresses: Vec::new(),
+        ethereum_wallet_private_key: multichain_account.secret_bytes.into(),
         ethereum_confirmations: 1,
         ethereum_rpc_ws_url: devnet.ethereum.anvil.ws_endpoint(),
+        ethereum_chain_id: Some(devnet.ethereum.anvil.chain_id()),
+        ethereum_network_rpc_ws_urls: Vec::new(),
+        ethereum_fill_batch_window_ms: None,
+        ethereum_fill_batch_max_fills: 10,
         trade_spread_bps: 0,
+        allow_extreme_spread: false,
+        pricing_file: None,
+        protocol_fee_bps: PROTOCOL_FEE_BPS,
+        min_protocol_fee_sats: MIN_PROTOCOL_FEE_SATS,
+        blocked_destination_addresses: Vec::new(),
         fee_safety_multiplier: 1.5,
         database_url: db_url,
+        quote_retention_hours: DEFAULT_QUOTE_RETENTION_HOURS,
+        wallet_bucket_reconciliation_interval_seconds: 300,
+        wallet_bucket_drift_tolerance_bps: 10,
+        auto_accept: true,
+        quote_price_tolerance_bps: 50,
+        supported_currencies_file: None,
+        expected_measurement: Some(TEST_ATTESTATION_MEASUREMENT),
+        attestation_root_of_trust: Some(test_attestation_verifier().root_of_trust),
+        attestation_max_age_seconds: DEFAULT_ATTESTATION_MAX_AGE_SECONDS,
+        dry_run: false,
+        // Quotes don't wait on, or get rate limited by, the live price feed
+        simulation_mode: true,
+        simulated_btc_per_eth: DEFAULT_SIMULATED_BTC_PER_ETH,
     }
 }
 
@@ -246,12 +378,33 @@ pub fn build_rfq_server_test_args(rfq_port: u16) -> RfqServerArgs {
         host: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
         log_level: "info".to_string(),
         whitelist_file: get_whitelist_file_path(),
+        whitelist_reload_interval_seconds: 1,
         quote_timeout_milliseconds: 5000,
-        cors_domain: None,
+        quote_timeout_extension_milliseconds: 0,
+        max_quote_timeout_milliseconds: 5000,
+        max_quote_lifetime_seconds: 600,
+        max_quote_clock_skew_seconds: 30,
+        max_quote_batch_size: 10,
+        quote_batch_rate_limit_per_minute: 60,
+        quote_lock_rate_limit_per_minute: 60,
+        trusted_proxy_header: None,
+        cors_domains: Vec::new(),
+        quote_signing_key: TEST_QUOTE_SIGNING_KEY.to_string(),
+        quote_lock_ttl_seconds: 60,
+        max_quote_locks_per_market_maker: 5,
+        quote_outcome_retention_seconds: 86_400,
+        max_quote_outcomes_per_market_maker: 10_000,
+        quote_history_file: None,
+        mm_max_message_bytes: common::DEFAULT_MM_MAX_MESSAGE_BYTES,
+        mm_max_malformed_messages: common::DEFAULT_MM_MAX_MALFORMED_MESSAGES,
+        mm_malformed_window_seconds: common::DEFAULT_MM_MALFORMED_WINDOW.as_secs(),
+        shutdown_drain_timeout_seconds: 10,
+        supported_currencies_file: None,
     }
 }
 
 pub async fn build_otc_server_test_args(
+    context: &TestContext,
     otc_port: u16,
     devnet: &devnet::RiftDevnet,
     connect_options: &PgConnectOptions,
@@ -261,23 +414,113 @@ pub async fn build_otc_server_test_args(
         port: otc_port,
         database_url: db_url,
         whitelist_file: get_whitelist_file_path(),
+        whitelist_reload_interval_seconds: 1,
+        settings_file: context.otc_settings_file(),
+        supported_currencies_file: None,
         host: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
         log_level: "debug".to_string(),
-        ethereum_mainnet_rpc_url: devnet.ethereum.anvil.endpoint(),
+        mode: ServerMode::Full,
+        ethereum_mainnet_rpc_url: Some(devnet.ethereum.anvil.endpoint()),
         ethereum_mainnet_token_indexer_url: devnet
             .ethereum
             .token_indexer
             .as_ref()
-            .unwrap()
-            .api_server_url
-            .clone(),
+            .map(|indexer| indexer.api_server_url.clone()),
         ethereum_mainnet_chain_id: devnet.ethereum.anvil.chain_id(),
-        bitcoin_rpc_url: devnet.bitcoin.rpc_url_with_cookie.clone(),
+        ethereum_mainnet_ws_url: None,
+        evm_network_rpc_urls: Vec::new(),
+        evm_network_token_indexer_urls: Vec::new(),
+        evm_network_ws_urls: Vec::new(),
+        ethereum_ignore_finality_tags: false,
+        bitcoin_rpc_url: Some(devnet.bitcoin.rpc_url_with_cookie.clone()),
         bitcoin_rpc_auth: Auth::CookieFile(devnet.bitcoin.cookie.clone()),
-        esplora_http_server_url: devnet.bitcoin.esplora_url.as_ref().unwrap().to_string(),
+        esplora_http_server_url: Some(devnet.bitcoin.esplora_url.as_ref().unwrap().to_string()),
+        bitcoin_network: bitcoin::network::Network::Regtest,
+        bitcoin_fee_target_blocks: otc_chains::bitcoin::DEFAULT_FEE_TARGET_BLOCKS,
+        chain_monitor_interval_seconds: vec![ChainMonitorInterval {
+            chain: None,
+            seconds: 2,
+        }],
+        swap_monitor_concurrency: 16,
+        mm_deposit_retry_grace_seconds: 120,
+        mm_deposit_max_retries: 2,
+        reconciliation_interval_seconds: 3600,
+        reconciliation_lookback_days: 7,
+        reconciliation_sample_size: 200,
+        swap_lookup_rate_limit_per_minute: 10,
+        trusted_proxy_header: None,
+        cors_domains: Vec::new(),
+        quote_signing_key: Some(TEST_QUOTE_SIGNING_KEY.to_string()),
+        quote_signature_mode: QuoteSigningMode::Required,
+        quote_price_max_deviation_bps: None,
+        min_quote_validity_seconds: 20,
+        admin_api_key: Some(TEST_ADMIN_API_KEY.to_string()),
+        mock_attestation_signing_key: Some(TEST_ATTESTATION_SIGNING_KEY),
+        mock_attestation_measurement: Some(TEST_ATTESTATION_MEASUREMENT),
+        attestation_endpoint: None,
+        mm_max_message_bytes: common::DEFAULT_MM_MAX_MESSAGE_BYTES,
+        mm_max_malformed_messages: common::DEFAULT_MM_MAX_MALFORMED_MESSAGES,
+        mm_malformed_window_seconds: common::DEFAULT_MM_MALFORMED_WINDOW.as_secs(),
+        mm_send_queue_capacity: common::DEFAULT_MM_SEND_QUEUE_CAPACITY,
+        shutdown_drain_timeout_seconds: 10,
+    }
+}
+
+/// Args for an API-only replica reading the database of the full node at `database_url`
+pub fn build_otc_replica_test_args(
+    context: &TestContext,
+    otc_port: u16,
+    database_url: &str,
+) -> OtcServerArgs {
+    OtcServerArgs {
+        port: otc_port,
+        database_url: database_url.to_string(),
+        whitelist_file: get_whitelist_file_path(),
+        whitelist_reload_interval_seconds: 1,
+        settings_file: context.otc_settings_file(),
+        supported_currencies_file: None,
+        host: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        log_level: "debug".to_string(),
+        mode: ServerMode::ApiOnly,
+        ethereum_mainnet_rpc_url: None,
+        ethereum_mainnet_token_indexer_url: None,
+        ethereum_mainnet_chain_id: 1,
+        ethereum_mainnet_ws_url: None,
+        evm_network_rpc_urls: Vec::new(),
+        evm_network_token_indexer_urls: Vec::new(),
+        evm_network_ws_urls: Vec::new(),
+        ethereum_ignore_finality_tags: false,
+        bitcoin_rpc_url: None,
+        bitcoin_rpc_auth: Auth::None,
+        esplora_http_server_url: None,
         bitcoin_network: bitcoin::network::Network::Regtest,
-        chain_monitor_interval_seconds: 2,
-        cors_domain: None,
+        bitcoin_fee_target_blocks: otc_chains::bitcoin::DEFAULT_FEE_TARGET_BLOCKS,
+        chain_monitor_interval_seconds: vec![ChainMonitorInterval {
+            chain: None,
+            seconds: 2,
+        }],
+        swap_monitor_concurrency: 16,
+        mm_deposit_retry_grace_seconds: 120,
+        mm_deposit_max_retries: 2,
+        reconciliation_interval_seconds: 3600,
+        reconciliation_lookback_days: 7,
+        reconciliation_sample_size: 200,
+        swap_lookup_rate_limit_per_minute: 10,
+        trusted_proxy_header: None,
+        cors_domains: Vec::new(),
+        quote_signing_key: None,
+        quote_signature_mode: QuoteSigningMode::Required,
+        quote_price_max_deviation_bps: None,
+        min_quote_validity_seconds: 20,
+        admin_api_key: None,
+        mock_attestation_signing_key: None,
+        mock_attestation_measurement: None,
+        attestation_endpoint: None,
+        mm_max_message_bytes: common::DEFAULT_MM_MAX_MESSAGE_BYTES,
+        mm_max_malformed_messages: common::DEFAULT_MM_MAX_MALFORMED_MESSAGES,
+        mm_malformed_window_seconds: common::DEFAULT_MM_MALFORMED_WINDOW.as_secs(),
+        mm_send_queue_capacity: common::DEFAULT_MM_SEND_QUEUE_CAPACITY,
+        shutdown_drain_timeout_seconds: 10,
     }
 }
 
@@ -295,6 +538,8 @@ pub async fn build_test_user_ethereum_wallet(
         Arc::new(provider),
         devnet.ethereum.anvil.ws_endpoint(),
         1,
+        Arc::new(SupportedCurrencies::default()),
+        None,
         &mut join_set,
     );
     (join_set, wallet)